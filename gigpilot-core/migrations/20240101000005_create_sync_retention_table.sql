@@ -0,0 +1,27 @@
+-- Migration: Create sync_retention table for change-log garbage collection
+-- Records, per user, the horizon before which sync_changes have been pruned.
+-- Devices whose last pull predates this horizon can no longer sync
+-- incrementally and must fetch a full snapshot instead.
+
+CREATE TABLE sync_retention (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Changes with change_timestamp < pruned_before no longer exist
+    pruned_before TIMESTAMPTZ NOT NULL,
+
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE sync_retention ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view their own retention horizon
+CREATE POLICY sync_retention_select_own ON sync_retention
+    FOR SELECT
+    USING (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_sync_retention_updated_at
+    BEFORE UPDATE ON sync_retention
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,28 @@
+-- Migration: Create editing_sessions table for advisory soft-locks
+-- Each row signals that a device is currently editing a record. Rows are
+-- short-lived and refreshed by the client; expired rows are ignored and
+-- cleaned up opportunistically.
+
+CREATE TABLE editing_sessions (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    table_name VARCHAR(100) NOT NULL,
+    record_id UUID NOT NULL,
+    device_id VARCHAR(255) NOT NULL,
+
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    PRIMARY KEY (user_id, table_name, record_id, device_id)
+);
+
+-- Index for looking up active sessions per user
+CREATE INDEX idx_editing_sessions_user_expires ON editing_sessions(user_id, expires_at);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE editing_sessions ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only see and manage their own editing sessions
+CREATE POLICY editing_sessions_all_own ON editing_sessions
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
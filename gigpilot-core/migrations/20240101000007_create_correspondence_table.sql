@@ -0,0 +1,44 @@
+-- Migration: Create correspondence table for chase evidence
+-- Stores every email generated for an invoice, along with delivery receipts
+-- and client replies, so users can export a complete paper trail.
+
+CREATE TABLE correspondence (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+
+    -- 'email' (outbound), 'delivery_receipt', or 'reply' (inbound)
+    kind VARCHAR(50) NOT NULL,
+
+    -- Message details
+    sender VARCHAR(255),
+    recipient VARCHAR(255),
+    subject TEXT,
+    body_text TEXT,
+    body_html TEXT,
+
+    -- Provider tracking
+    provider_message_id VARCHAR(255),
+    delivery_status VARCHAR(50),
+    metadata JSONB,
+
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Index for listing an invoice's correspondence in order
+CREATE INDEX idx_correspondence_invoice ON correspondence(invoice_id, occurred_at);
+CREATE INDEX idx_correspondence_user_id ON correspondence(user_id);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE correspondence ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view their own correspondence
+CREATE POLICY correspondence_select_own ON correspondence
+    FOR SELECT
+    USING (user_id = auth.uid());
+
+-- RLS Policy: Users can insert their own correspondence
+CREATE POLICY correspondence_insert_own ON correspondence
+    FOR INSERT
+    WITH CHECK (user_id = auth.uid());
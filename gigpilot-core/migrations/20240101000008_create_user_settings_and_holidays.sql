@@ -0,0 +1,40 @@
+-- Migration: Create user_settings and public_holidays tables
+-- user_settings holds per-user preferences for the worker and API.
+-- public_holidays supplements the built-in holiday calendars with extra
+-- (regional or one-off) dates per country.
+
+CREATE TABLE user_settings (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Holiday calendar (ISO 3166-1 alpha-2 country code)
+    country_code VARCHAR(2),
+
+    -- Whether scheduling skips weekends and public holidays
+    skip_non_business_days BOOLEAN NOT NULL DEFAULT false,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE user_settings ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own settings
+CREATE POLICY user_settings_all_own ON user_settings
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_user_settings_updated_at
+    BEFORE UPDATE ON user_settings
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TABLE public_holidays (
+    country_code VARCHAR(2) NOT NULL,
+    holiday_date DATE NOT NULL,
+    name VARCHAR(255) NOT NULL,
+
+    PRIMARY KEY (country_code, holiday_date)
+);
@@ -0,0 +1,42 @@
+-- Migration: Add computed totals to invoices and a per-user tax_rates table
+-- Line items carry per-line tax rates; subtotal, tax_total and total are
+-- recomputed by the server whenever line items change.
+
+ALTER TABLE invoices
+    ADD COLUMN subtotal DECIMAL(15, 2) NOT NULL DEFAULT 0,
+    ADD COLUMN tax_total DECIMAL(15, 2) NOT NULL DEFAULT 0,
+    ADD COLUMN total DECIMAL(15, 2) NOT NULL DEFAULT 0;
+
+-- Backfill: invoices without line items are untaxed
+UPDATE invoices SET subtotal = amount, total = amount;
+
+CREATE TABLE tax_rates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    name VARCHAR(100) NOT NULL, -- e.g. 'VAT 20%', 'GST'
+    rate DECIMAL(7, 4) NOT NULL CHECK (rate >= 0 AND rate <= 100), -- percentage
+    is_default BOOLEAN NOT NULL DEFAULT false,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE(user_id, name)
+);
+
+CREATE INDEX idx_tax_rates_user_id ON tax_rates(user_id);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE tax_rates ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own tax rates
+CREATE POLICY tax_rates_all_own ON tax_rates
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_tax_rates_updated_at
+    BEFORE UPDATE ON tax_rates
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,96 @@
+-- Migration: Create expenses and receipts tables
+-- Receipts are uploaded files that a background OCR job reads to suggest an
+-- expense record; users confirm or edit suggestions from their devices.
+
+CREATE TABLE expenses (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Expense fields
+    vendor VARCHAR(255),
+    description TEXT,
+    expense_date DATE,
+    amount DECIMAL(15, 2) NOT NULL,
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    category VARCHAR(100),
+
+    -- 'suggested' (created from OCR, awaiting review) or 'confirmed'
+    status VARCHAR(50) NOT NULL DEFAULT 'confirmed',
+    -- 'manual' or 'ocr'
+    source VARCHAR(50) NOT NULL DEFAULT 'manual',
+    receipt_id UUID,
+
+    -- Sync metadata (CRDT support)
+    last_modified TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    version_vector JSONB,
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_expenses_user_id ON expenses(user_id);
+CREATE INDEX idx_expenses_user_date ON expenses(user_id, expense_date DESC);
+CREATE INDEX idx_expenses_not_deleted ON expenses(user_id, is_deleted) WHERE is_deleted = false;
+
+CREATE TABLE receipts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Uploaded file
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    data BYTEA NOT NULL,
+
+    -- OCR processing: 'pending', 'processing', 'completed', 'failed'
+    ocr_status VARCHAR(50) NOT NULL DEFAULT 'pending',
+    ocr_provider VARCHAR(100),
+    ocr_text TEXT,
+    ocr_error TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+
+    -- Fields extracted from the OCR text
+    extracted JSONB,
+
+    -- Expense suggested from this receipt
+    expense_id UUID REFERENCES expenses(id) ON DELETE SET NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE expenses
+    ADD CONSTRAINT fk_expenses_receipt FOREIGN KEY (receipt_id) REFERENCES receipts(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_receipts_user_id ON receipts(user_id);
+-- Used by the OCR job to claim work in upload order
+CREATE INDEX idx_receipts_pending ON receipts(created_at) WHERE ocr_status = 'pending';
+
+-- Row Level Security: Enable RLS
+ALTER TABLE expenses ENABLE ROW LEVEL SECURITY;
+ALTER TABLE receipts ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own expenses
+CREATE POLICY expenses_all_own ON expenses
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- RLS Policy: Users can only view and manage their own receipts
+CREATE POLICY receipts_all_own ON receipts
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Triggers to auto-update updated_at
+CREATE TRIGGER update_expenses_updated_at
+    BEFORE UPDATE ON expenses
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_receipts_updated_at
+    BEFORE UPDATE ON receipts
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
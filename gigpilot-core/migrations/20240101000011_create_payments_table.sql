@@ -0,0 +1,37 @@
+-- Migration: Create payments table and track amount paid per invoice
+-- Invoices can be settled by several partial payments. amount_paid is kept
+-- equal to the sum of the invoice's payments; the balance due is
+-- total - amount_paid.
+
+CREATE TABLE payments (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+
+    amount DECIMAL(15, 2) NOT NULL CHECK (amount > 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    paid_on DATE NOT NULL DEFAULT CURRENT_DATE,
+    method VARCHAR(50), -- e.g. 'bank_transfer', 'card', 'cash'
+    reference VARCHAR(255), -- bank reference, transaction ID, etc.
+    notes TEXT,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_payments_invoice ON payments(invoice_id, paid_on);
+CREATE INDEX idx_payments_user_id ON payments(user_id);
+
+ALTER TABLE invoices
+    ADD COLUMN amount_paid DECIMAL(15, 2) NOT NULL DEFAULT 0;
+
+-- Backfill: invoices already marked paid are fully paid
+UPDATE invoices SET amount_paid = total WHERE status = 'paid';
+
+-- Row Level Security: Enable RLS
+ALTER TABLE payments ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own payments
+CREATE POLICY payments_all_own ON payments
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
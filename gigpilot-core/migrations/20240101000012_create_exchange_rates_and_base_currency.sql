@@ -0,0 +1,22 @@
+-- Migration: Create exchange_rates table and per-user base currency
+-- Daily rates are fetched once per base currency and reused by every
+-- process; reports convert invoice amounts into the user's base currency.
+
+CREATE TABLE exchange_rates (
+    base_currency VARCHAR(3) NOT NULL,
+    quote_currency VARCHAR(3) NOT NULL,
+    rate_date DATE NOT NULL,
+
+    -- Units of quote currency per one unit of base currency
+    rate DECIMAL(24, 10) NOT NULL CHECK (rate > 0),
+    source VARCHAR(100) NOT NULL,
+
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (base_currency, quote_currency, rate_date)
+);
+
+CREATE INDEX idx_exchange_rates_date ON exchange_rates(rate_date DESC);
+
+ALTER TABLE user_settings
+    ADD COLUMN base_currency VARCHAR(3) NOT NULL DEFAULT 'USD';
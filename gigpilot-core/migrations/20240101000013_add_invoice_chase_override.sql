@@ -0,0 +1,6 @@
+-- Migration: Add per-invoice chase policy override
+-- JSON object with optional fields: not_before (date), skip_level_2 (bool),
+-- level_2_after_days (int) and paused (bool). NULL means the default policy.
+
+ALTER TABLE invoices
+    ADD COLUMN chase_override JSONB;
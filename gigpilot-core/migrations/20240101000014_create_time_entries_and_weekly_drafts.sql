@@ -0,0 +1,116 @@
+-- Migration: Create time_entries and notifications, and weekly invoice drafts
+-- An opt-in worker job drafts one invoice per client every Friday from
+-- unbilled time entries and billable expenses, notifies the user for review
+-- and, if enabled, sends the draft after a grace period.
+
+CREATE TABLE time_entries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Client the work was done for (entries without one are never drafted)
+    client_name VARCHAR(255),
+    client_email VARCHAR(255),
+
+    description TEXT,
+    entry_date DATE NOT NULL,
+    hours DECIMAL(8, 2) NOT NULL CHECK (hours > 0),
+    hourly_rate DECIMAL(15, 2) NOT NULL CHECK (hourly_rate >= 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+
+    -- Invoice the entry was billed on (NULL = unbilled)
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+
+    -- Sync metadata (CRDT support)
+    last_modified TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    version_vector JSONB,
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_time_entries_user_date ON time_entries(user_id, entry_date DESC);
+-- Used by the weekly draft job to find unbilled work
+CREATE INDEX idx_time_entries_unbilled ON time_entries(user_id) WHERE invoice_id IS NULL AND is_deleted = false;
+
+-- Expenses can be re-billed to a client
+ALTER TABLE expenses
+    ADD COLUMN client_name VARCHAR(255),
+    ADD COLUMN client_email VARCHAR(255),
+    ADD COLUMN billable BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_expenses_unbilled ON expenses(user_id) WHERE billable = true AND invoice_id IS NULL AND is_deleted = false;
+
+ALTER TABLE user_settings
+    ADD COLUMN weekly_drafts_enabled BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN weekly_draft_auto_send BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN weekly_draft_grace_hours INTEGER NOT NULL DEFAULT 48
+        CHECK (weekly_draft_grace_hours BETWEEN 1 AND 168);
+
+-- One row per user and week the draft job has run for, so repeated runs on
+-- the same Friday (or by several workers) draft only once
+CREATE TABLE weekly_draft_runs (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    week_start DATE NOT NULL,
+    invoices_created INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, week_start)
+);
+
+CREATE TABLE notifications (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Machine-readable kind (e.g. 'weekly_draft_ready')
+    kind VARCHAR(100) NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    body TEXT,
+
+    -- Kind-specific payload (e.g. the invoice IDs to review)
+    data JSONB,
+
+    read_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_notifications_user_created ON notifications(user_id, created_at DESC);
+CREATE INDEX idx_notifications_unread ON notifications(user_id) WHERE read_at IS NULL;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE time_entries ENABLE ROW LEVEL SECURITY;
+ALTER TABLE weekly_draft_runs ENABLE ROW LEVEL SECURITY;
+ALTER TABLE notifications ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own time entries
+CREATE POLICY time_entries_all_own ON time_entries
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- RLS Policy: Users can only view their own draft runs
+CREATE POLICY weekly_draft_runs_select_own ON weekly_draft_runs
+    FOR SELECT
+    USING (user_id = auth.uid());
+
+-- RLS Policy: Users can only view and manage their own notifications
+CREATE POLICY notifications_all_own ON notifications
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Triggers to auto-update updated_at
+CREATE TRIGGER update_time_entries_updated_at
+    BEFORE UPDATE ON time_entries
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_notifications_updated_at
+    BEFORE UPDATE ON notifications
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
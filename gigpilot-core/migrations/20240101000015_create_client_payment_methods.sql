@@ -0,0 +1,43 @@
+-- Migration: Create client_payment_methods table
+-- Payment methods (bank transfer, Stripe, PayPal) the user accepts, either
+-- per client (matched on client email) or as defaults for every client.
+-- They are rendered as payment instructions on PDFs, the public pay page
+-- and reminder emails.
+
+CREATE TABLE client_payment_methods (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Lower-cased client email; NULL for the user's defaults
+    client_email VARCHAR(255),
+
+    -- 'bank_transfer', 'stripe' or 'paypal'
+    kind VARCHAR(50) NOT NULL,
+    -- Validated, kind-specific details (IBAN, payment link, ...)
+    details JSONB NOT NULL,
+
+    -- Display order, lowest first
+    position INTEGER NOT NULL DEFAULT 0,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One method of each kind per client (and per user for defaults)
+CREATE UNIQUE INDEX idx_client_payment_methods_unique
+    ON client_payment_methods(user_id, COALESCE(client_email, ''), kind);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE client_payment_methods ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own payment methods
+CREATE POLICY client_payment_methods_all_own ON client_payment_methods
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_client_payment_methods_updated_at
+    BEFORE UPDATE ON client_payment_methods
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,57 @@
+-- Migration: Create estimates table
+-- Estimates (quotes) carry line items like invoices and sync the same way;
+-- converting one creates an invoice and links the two.
+
+CREATE TABLE estimates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Estimate fields
+    estimate_number VARCHAR(100) NOT NULL,
+    client_name VARCHAR(255) NOT NULL,
+    client_email VARCHAR(255),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    -- 'draft', 'sent', 'accepted', 'declined', 'expired', 'converted'
+    status VARCHAR(50) NOT NULL DEFAULT 'draft',
+    issue_date DATE NOT NULL DEFAULT CURRENT_DATE,
+    valid_until DATE,
+    description TEXT,
+
+    line_items JSONB NOT NULL DEFAULT '[]'::jsonb,
+    subtotal DECIMAL(15, 2) NOT NULL DEFAULT 0,
+    tax_total DECIMAL(15, 2) NOT NULL DEFAULT 0,
+    total DECIMAL(15, 2) NOT NULL DEFAULT 0,
+
+    -- Invoice created when the estimate was converted
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+
+    -- Sync metadata (CRDT support)
+    last_modified TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    version_vector JSONB,
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE(user_id, estimate_number)
+);
+
+CREATE INDEX idx_estimates_user_id ON estimates(user_id);
+CREATE INDEX idx_estimates_user_status ON estimates(user_id, status) WHERE is_deleted = false;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE estimates ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own estimates
+CREATE POLICY estimates_all_own ON estimates
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_estimates_updated_at
+    BEFORE UPDATE ON estimates
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,20 @@
+-- Migration: Soft-delete aware invoice number uniqueness
+-- Invoice numbers are unique among live invoices only. Whether a deleted
+-- invoice's number may be used again is a per-user policy enforced by the
+-- numbering service and sync push:
+--   'block'   - numbers of deleted invoices are never reused (default)
+--   'recycle' - the lowest number freed by a deletion is handed out again
+
+ALTER TABLE invoices
+    DROP CONSTRAINT invoices_user_id_invoice_number_key;
+
+CREATE UNIQUE INDEX idx_invoices_user_number_live
+    ON invoices(user_id, invoice_number)
+    WHERE is_deleted = false;
+
+-- Still used by the 'block' policy to find numbers held by deleted invoices
+CREATE INDEX idx_invoices_user_number ON invoices(user_id, invoice_number);
+
+ALTER TABLE user_settings
+    ADD COLUMN invoice_number_policy VARCHAR(20) NOT NULL DEFAULT 'block'
+        CHECK (invoice_number_policy IN ('block', 'recycle'));
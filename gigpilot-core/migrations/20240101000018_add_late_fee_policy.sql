@@ -0,0 +1,15 @@
+-- Migration: Add per-user late fee policy
+-- When an invoice escalates to the firm reminder (chasing level 2) and is at
+-- least late_fee_after_days overdue, the chase worker charges a late fee
+-- once: a flat amount in the invoice's currency, or a percentage of the
+-- balance due. The charge is recorded in the invoice's metadata.
+
+ALTER TABLE user_settings
+    ADD COLUMN late_fee_kind VARCHAR(20) NOT NULL DEFAULT 'none'
+        CHECK (late_fee_kind IN ('none', 'flat', 'percentage')),
+    ADD COLUMN late_fee_amount DECIMAL(15, 2) NOT NULL DEFAULT 0
+        CHECK (late_fee_amount >= 0),
+    ADD COLUMN late_fee_after_days INTEGER NOT NULL DEFAULT 0
+        CHECK (late_fee_after_days BETWEEN 0 AND 365),
+    ADD CONSTRAINT user_settings_late_fee_percentage
+        CHECK (late_fee_kind <> 'percentage' OR late_fee_amount <= 100);
@@ -0,0 +1,39 @@
+-- Migration: Create attachments table
+-- Files (contracts, receipts, timesheets) attached to an invoice. The file
+-- contents live in the configured storage backend (local directory or S3)
+-- under storage_key; chase emails link to them through the public pay page.
+
+CREATE TABLE attachments (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+
+    -- Uploaded file
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    size_bytes BIGINT NOT NULL CHECK (size_bytes > 0),
+
+    -- Object key in the storage backend
+    storage_key VARCHAR(512) NOT NULL UNIQUE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_attachments_invoice ON attachments(invoice_id, created_at);
+CREATE INDEX idx_attachments_user_id ON attachments(user_id);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE attachments ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own attachments
+CREATE POLICY attachments_all_own ON attachments
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_attachments_updated_at
+    BEFORE UPDATE ON attachments
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,10 @@
+-- Migration: Add per-invoice exchange rate override
+-- Reports convert each invoice at the exchange rate of its issue date and
+-- each payment at the rate of its payment date; historical rates are
+-- backfilled into exchange_rates by the worker. An invoice can instead fix
+-- the rate (e.g. one agreed in the contract) as a JSON object
+-- {"base_currency": "EUR", "rate": 0.91}: units of base_currency per unit
+-- of the invoice currency. NULL means market rates.
+
+ALTER TABLE invoices
+    ADD COLUMN exchange_rate_override JSONB;
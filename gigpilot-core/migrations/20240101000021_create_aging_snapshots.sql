@@ -0,0 +1,38 @@
+-- Migration: Create aging_snapshots table
+-- Once a day the worker records each user's receivables aging (open
+-- balances per days-overdue bucket) per invoice currency, so reports can
+-- show trends and later jobs have a time series to work from. Users are
+-- snapshotted incrementally: only those without a row for the day are
+-- processed, so a restarted worker picks up where it left off. Users
+-- without open invoices get no rows (all buckets zero).
+
+CREATE TABLE aging_snapshots (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    snapshot_date DATE NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+
+    -- Open invoices and their balance due
+    invoice_count INTEGER NOT NULL DEFAULT 0,
+    outstanding DECIMAL(12, 2) NOT NULL DEFAULT 0,
+
+    -- Balance due by days past the due date
+    current DECIMAL(12, 2) NOT NULL DEFAULT 0,
+    days_1_30 DECIMAL(12, 2) NOT NULL DEFAULT 0,
+    days_31_60 DECIMAL(12, 2) NOT NULL DEFAULT 0,
+    days_61_90 DECIMAL(12, 2) NOT NULL DEFAULT 0,
+    days_over_90 DECIMAL(12, 2) NOT NULL DEFAULT 0,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, snapshot_date, currency)
+);
+
+CREATE INDEX idx_aging_snapshots_date ON aging_snapshots(snapshot_date DESC);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE aging_snapshots ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view their own snapshots (written by the worker)
+CREATE POLICY aging_snapshots_select_own ON aging_snapshots
+    FOR SELECT
+    USING (user_id = auth.uid());
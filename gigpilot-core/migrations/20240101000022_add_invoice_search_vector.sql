@@ -0,0 +1,13 @@
+-- Migration: Add full-text search over invoices
+-- A generated tsvector over the invoice number, client name (weight A) and
+-- description (weight B) backs GET /api/invoices/search. The 'english'
+-- configuration stems words, so "logos" finds "logo".
+
+ALTER TABLE invoices
+    ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', COALESCE(invoice_number, '')), 'A') ||
+        setweight(to_tsvector('english', COALESCE(client_name, '')), 'A') ||
+        setweight(to_tsvector('english', COALESCE(description, '')), 'B')
+    ) STORED;
+
+CREATE INDEX idx_invoices_search_vector ON invoices USING GIN (search_vector);
@@ -0,0 +1,50 @@
+-- Migration: Support several accounts per login
+-- Every row in users is an account that owns data (invoices, settings,
+-- ...). Personal accounts are people who log in; workspace accounts are
+-- extra business lines without a login of their own, used by their members
+-- through an account-switch claim in the JWT. A person is implicitly the
+-- owner of their personal account; account_members lists everyone else's
+-- access. The worker keeps processing data per account, so workspaces are
+-- chased, drafted and reported on separately.
+
+ALTER TABLE users
+    ADD COLUMN kind VARCHAR(20) NOT NULL DEFAULT 'personal'
+        CHECK (kind IN ('personal', 'workspace'));
+
+-- Workspaces cannot log in
+ALTER TABLE users
+    ALTER COLUMN password_hash DROP NOT NULL;
+
+ALTER TABLE users
+    ADD CONSTRAINT users_personal_password
+        CHECK (kind = 'workspace' OR password_hash IS NOT NULL);
+
+CREATE TABLE account_members (
+    account_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    member_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- 'owner' (manages members) or 'member'
+    role VARCHAR(20) NOT NULL CHECK (role IN ('owner', 'member')),
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (account_id, member_id)
+);
+
+-- Accounts a person can switch to
+CREATE INDEX idx_account_members_member ON account_members(member_id);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE account_members ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: People see their own memberships
+CREATE POLICY account_members_select_own ON account_members
+    FOR SELECT
+    USING (member_id = auth.uid() OR account_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_account_members_updated_at
+    BEFORE UPDATE ON account_members
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,38 @@
+-- Migration: Announce changes to what an invoice PDF shows
+-- Cached invoice PDFs live in blob storage. Whenever a change touches the
+-- fields printed on the PDF (whether made by the API, a sync push or the
+-- worker) a domain event is published so every process drops the cached
+-- copy. Bookkeeping-only updates (chase state in metadata, timestamps,
+-- version vectors) do not publish, so reminders keep reusing the cache.
+
+CREATE OR REPLACE FUNCTION notify_invoice_content_changed()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE'
+        OR ROW(
+            OLD.invoice_number, OLD.client_name, OLD.client_email, OLD.amount, OLD.currency,
+            OLD.due_date, OLD.issue_date, OLD.description, OLD.line_items, OLD.subtotal,
+            OLD.tax_total, OLD.total, OLD.amount_paid, OLD.is_deleted
+        ) IS DISTINCT FROM ROW(
+            NEW.invoice_number, NEW.client_name, NEW.client_email, NEW.amount, NEW.currency,
+            NEW.due_date, NEW.issue_date, NEW.description, NEW.line_items, NEW.subtotal,
+            NEW.tax_total, NEW.total, NEW.amount_paid, NEW.is_deleted
+        )
+    THEN
+        PERFORM pg_notify(
+            'gigpilot_domain_events',
+            json_build_object(
+                'type', 'invoice_content_changed',
+                'user_id', OLD.user_id,
+                'invoice_id', OLD.id
+            )::text
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notify_invoices_content_changed
+    AFTER UPDATE OR DELETE ON invoices
+    FOR EACH ROW
+    EXECUTE FUNCTION notify_invoice_content_changed();
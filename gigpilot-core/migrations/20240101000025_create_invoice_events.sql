@@ -0,0 +1,94 @@
+-- Migration: Record a field-level history of every invoice
+-- A trigger writes one invoice_events row per insert, update or delete,
+-- whoever makes the change (API, sync push or worker), listing the fields
+-- that changed with their old and new values. Bookkeeping columns
+-- (timestamps, version vectors, the search vector) are not tracked.
+--
+-- Writers identify themselves for the current transaction with
+-- set_config('gigpilot.actor_id' / 'gigpilot.device_id' /
+-- 'gigpilot.audit_source', ..., true); changes made without doing so are
+-- attributed to the 'system' (background jobs).
+
+CREATE TABLE invoice_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- No foreign key: the history outlives hard-deleted invoices
+    invoice_id UUID NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    action VARCHAR(20) NOT NULL CHECK (action IN ('created', 'updated', 'deleted', 'restored')),
+    -- { "<field>": { "old": ..., "new": ... }, ... }
+    changes JSONB NOT NULL DEFAULT '{}'::jsonb,
+
+    -- Who made the change, from which device, through which path
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    device_id VARCHAR(255),
+    source VARCHAR(20) NOT NULL DEFAULT 'system' CHECK (source IN ('api', 'sync', 'system')),
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_invoice_events_invoice ON invoice_events(user_id, invoice_id, created_at DESC);
+
+ALTER TABLE invoice_events ENABLE ROW LEVEL SECURITY;
+
+CREATE POLICY invoice_events_select_own ON invoice_events
+    FOR SELECT
+    USING (user_id = auth.uid());
+
+CREATE OR REPLACE FUNCTION record_invoice_event()
+RETURNS TRIGGER AS $$
+DECLARE
+    old_row JSONB := CASE WHEN TG_OP = 'INSERT' THEN '{}'::jsonb ELSE to_jsonb(OLD) END;
+    new_row JSONB := CASE WHEN TG_OP = 'DELETE' THEN '{}'::jsonb ELSE to_jsonb(NEW) END;
+    row_changes JSONB;
+    event_action VARCHAR(20);
+BEGIN
+    SELECT COALESCE(
+        jsonb_object_agg(k.key, jsonb_build_object('old', old_row->k.key, 'new', new_row->k.key)),
+        '{}'::jsonb
+    )
+    INTO row_changes
+    FROM jsonb_object_keys(old_row || new_row) AS k(key)
+    WHERE k.key NOT IN (
+            'id', 'user_id', 'created_at', 'updated_at', 'last_modified',
+            'version_vector', 'search_vector'
+        )
+        AND COALESCE(old_row->k.key, 'null'::jsonb) IS DISTINCT FROM COALESCE(new_row->k.key, 'null'::jsonb);
+
+    IF TG_OP = 'INSERT' THEN
+        event_action := 'created';
+    ELSIF TG_OP = 'DELETE' THEN
+        event_action := 'deleted';
+        row_changes := '{}'::jsonb;
+    ELSIF row_changes = '{}'::jsonb THEN
+        RETURN NULL;
+    ELSIF NOT OLD.is_deleted AND NEW.is_deleted THEN
+        event_action := 'deleted';
+    ELSIF OLD.is_deleted AND NOT NEW.is_deleted THEN
+        event_action := 'restored';
+    ELSE
+        event_action := 'updated';
+    END IF;
+
+    INSERT INTO invoice_events (
+        invoice_id, user_id, action, changes, actor_id, device_id, source, created_at
+    ) VALUES (
+        COALESCE(new_row->>'id', old_row->>'id')::uuid,
+        COALESCE(new_row->>'user_id', old_row->>'user_id')::uuid,
+        event_action,
+        row_changes,
+        NULLIF(current_setting('gigpilot.actor_id', true), '')::uuid,
+        NULLIF(current_setting('gigpilot.device_id', true), ''),
+        COALESCE(NULLIF(current_setting('gigpilot.audit_source', true), ''), 'system'),
+        -- Wall-clock time keeps several changes in one transaction ordered
+        clock_timestamp()
+    );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER record_invoices_events
+    AFTER INSERT OR UPDATE OR DELETE ON invoices
+    FOR EACH ROW
+    EXECUTE FUNCTION record_invoice_event();
@@ -0,0 +1,48 @@
+-- Migration: Create statement_schedules table
+-- Monthly statements of account emailed automatically to a client (matched
+-- on client email), listing their open invoices and outstanding balance.
+-- The worker sends every enabled schedule whose next_run_on has come, in
+-- the client's locale and through their preferred channel.
+
+CREATE TABLE statement_schedules (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Lower-cased client email the statement covers and is sent to
+    client_email VARCHAR(255) NOT NULL,
+    -- Name used in the greeting (falls back to the invoices' client name)
+    client_name VARCHAR(255),
+
+    -- Day of the month statements go out (1-28, so every month has it)
+    day_of_month SMALLINT NOT NULL CHECK (day_of_month BETWEEN 1 AND 28),
+    -- Language and number/date formats of the statement: 'en', 'de', 'fr', 'es'
+    locale VARCHAR(10) NOT NULL DEFAULT 'en',
+    -- Delivery channel: 'email'
+    channel VARCHAR(20) NOT NULL DEFAULT 'email',
+    enabled BOOLEAN NOT NULL DEFAULT true,
+
+    next_run_on DATE NOT NULL,
+    last_sent_on DATE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (user_id, client_email)
+);
+
+CREATE INDEX idx_statement_schedules_due ON statement_schedules(next_run_on) WHERE enabled = true;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE statement_schedules ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own schedules
+CREATE POLICY statement_schedules_all_own ON statement_schedules
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_statement_schedules_updated_at
+    BEFORE UPDATE ON statement_schedules
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,10 @@
+-- Migration: Add seller details to user_settings
+-- Structured e-invoices (UBL, Factur-X) must identify the seller by postal
+-- address and, when VAT is charged, VAT identifier. The seller's country is
+-- the existing country_code.
+
+ALTER TABLE user_settings
+    ADD COLUMN vat_id VARCHAR(20),
+    ADD COLUMN address_line VARCHAR(255),
+    ADD COLUMN city VARCHAR(100),
+    ADD COLUMN postal_code VARCHAR(20);
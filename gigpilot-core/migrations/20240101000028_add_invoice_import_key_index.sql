@@ -0,0 +1,8 @@
+-- Migration: Idempotent CSV invoice import
+-- Invoices created by a CSV import keep a key derived from their row under
+-- metadata.import.key. Each key is stored at most once per user (deleted
+-- invoices included), so re-importing a file skips rows already imported.
+
+CREATE UNIQUE INDEX idx_invoices_user_import_key
+    ON invoices(user_id, (metadata #>> '{import,key}'))
+    WHERE metadata #>> '{import,key}' IS NOT NULL;
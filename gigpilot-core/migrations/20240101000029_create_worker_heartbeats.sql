@@ -0,0 +1,21 @@
+-- Migration: Create worker_heartbeats table
+-- Each running worker process upserts its row every heartbeat interval,
+-- together with when it last succeeded and failed to reach the email and
+-- LLM providers (only the worker talks to them). The public status
+-- endpoint reads the rows to report worker and provider health. Rows of
+-- workers silent for a day are removed by the workers themselves.
+
+CREATE TABLE worker_heartbeats (
+    instance_id UUID PRIMARY KEY,
+    hostname VARCHAR(255),
+    started_at TIMESTAMPTZ NOT NULL,
+    last_seen_at TIMESTAMPTZ NOT NULL,
+
+    -- Last successful and failed call to each external provider
+    email_ok_at TIMESTAMPTZ,
+    email_error_at TIMESTAMPTZ,
+    llm_ok_at TIMESTAMPTZ,
+    llm_error_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_worker_heartbeats_last_seen ON worker_heartbeats(last_seen_at DESC);
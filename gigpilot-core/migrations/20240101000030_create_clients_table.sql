@@ -0,0 +1,51 @@
+-- Migration: Create clients table
+-- Clients used to exist only as the client_name/client_email strings on
+-- each invoice. They are now records of their own, synced like invoices;
+-- invoices reference them and keep the name/email they were issued with.
+
+CREATE TABLE clients (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Client fields
+    name VARCHAR(255) NOT NULL,
+    email VARCHAR(255),
+    phone VARCHAR(50),
+    address TEXT,
+    tax_id VARCHAR(100),
+    notes TEXT,
+
+    -- Sync metadata (CRDT support)
+    last_modified TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    version_vector JSONB,
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_clients_user_id ON clients(user_id);
+CREATE INDEX idx_clients_user_name ON clients(user_id, lower(name)) WHERE is_deleted = false;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE clients ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own clients
+CREATE POLICY clients_all_own ON clients
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_clients_updated_at
+    BEFORE UPDATE ON clients
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Invoices optionally belong to a client
+ALTER TABLE invoices
+    ADD COLUMN client_id UUID REFERENCES clients(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_invoices_client_id ON invoices(client_id) WHERE client_id IS NOT NULL;
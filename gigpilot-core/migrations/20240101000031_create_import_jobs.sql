@@ -0,0 +1,50 @@
+-- Migration: Create import_jobs table
+-- Users switching from FreshBooks or Wave upload that tool's client,
+-- invoice and payment exports. The files are kept with the job so the
+-- column mapping can be previewed and adjusted before the worker imports
+-- them; the report holds the per-entity results.
+
+CREATE TABLE import_jobs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- 'freshbooks', 'wave'
+    source VARCHAR(50) NOT NULL,
+    -- 'preview', 'queued', 'running', 'completed', 'failed'
+    status VARCHAR(50) NOT NULL DEFAULT 'preview',
+
+    -- Uploaded exports (any may be missing)
+    clients_csv BYTEA,
+    invoices_csv BYTEA,
+    payments_csv BYTEA,
+
+    options JSONB NOT NULL DEFAULT '{}'::jsonb,
+    preview JSONB,
+    report JSONB,
+    error TEXT,
+
+    attempts INTEGER NOT NULL DEFAULT 0,
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_import_jobs_user_id ON import_jobs(user_id, created_at DESC);
+CREATE INDEX idx_import_jobs_queued ON import_jobs(created_at) WHERE status IN ('queued', 'running');
+
+-- Row Level Security: Enable RLS
+ALTER TABLE import_jobs ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own import jobs
+CREATE POLICY import_jobs_all_own ON import_jobs
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_import_jobs_updated_at
+    BEFORE UPDATE ON import_jobs
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
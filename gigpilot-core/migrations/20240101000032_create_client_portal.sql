@@ -0,0 +1,65 @@
+-- Migration: Create client portal tokens and activity
+-- A portal token lets one client see all of their invoices, download the
+-- PDFs and say when they intend to pay, without a GigPilot account. The
+-- token itself is a signed JWT naming a row here, so it can be revoked
+-- and expires with the row. Every portal visit is recorded as activity.
+
+CREATE TABLE client_portal_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+
+    -- Where the link was shared, e.g. "Accounts payable"
+    label VARCHAR(255),
+
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_client_portal_tokens_client ON client_portal_tokens(user_id, client_id, created_at DESC);
+
+CREATE TABLE client_portal_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    token_id UUID NOT NULL REFERENCES client_portal_tokens(id) ON DELETE CASCADE,
+    invoice_id UUID REFERENCES invoices(id) ON DELETE CASCADE,
+
+    -- 'portal_viewed', 'invoice_viewed', 'pdf_downloaded', 'intent_to_pay'
+    kind VARCHAR(50) NOT NULL,
+    data JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_client_portal_events_client ON client_portal_events(user_id, client_id, created_at DESC);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE client_portal_tokens ENABLE ROW LEVEL SECURITY;
+ALTER TABLE client_portal_events ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own clients' portals
+CREATE POLICY client_portal_tokens_all_own ON client_portal_tokens
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+CREATE POLICY client_portal_events_all_own ON client_portal_events
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_client_portal_tokens_updated_at
+    BEFORE UPDATE ON client_portal_tokens
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Invoice changes made by clients through the portal (payment intents)
+ALTER TABLE invoice_events DROP CONSTRAINT invoice_events_source_check;
+ALTER TABLE invoice_events ADD CONSTRAINT invoice_events_source_check
+    CHECK (source IN ('api', 'sync', 'system', 'portal'));
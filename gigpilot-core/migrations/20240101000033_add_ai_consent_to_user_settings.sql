@@ -0,0 +1,8 @@
+-- Migration: Add AI consent flags to user_settings
+-- Invoice and client text is only sent to external LLM and embedding
+-- providers once the user opts in. Existing users start opted out; chase
+-- emails fall back to templates and the estimator to keyword search.
+
+ALTER TABLE user_settings
+    ADD COLUMN ai_llm_consent BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN ai_embeddings_consent BOOLEAN NOT NULL DEFAULT false;
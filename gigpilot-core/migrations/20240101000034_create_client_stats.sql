@@ -0,0 +1,40 @@
+-- Migration: Create client_stats table
+-- The worker periodically profiles how each client pays: how long their
+-- paid invoices took and how often chasing had to escalate to the firm
+-- level-2 reminder. The chase worker reads the profile to escalate sooner
+-- for slow payers and to go easier on prompt ones. Rows are recomputed
+-- from scratch on every refresh.
+
+CREATE TABLE client_stats (
+    client_id UUID PRIMARY KEY REFERENCES clients(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Invoices past their due date, and those of them that are paid
+    invoices_due INTEGER NOT NULL DEFAULT 0,
+    invoices_paid INTEGER NOT NULL DEFAULT 0,
+
+    -- Averages over paid invoices, in days from the issue and due date
+    average_days_to_pay DECIMAL(8, 2),
+    average_days_late DECIMAL(8, 2),
+
+    -- Due invoices that got a level-2 reminder, and their percentage
+    level_2_chases INTEGER NOT NULL DEFAULT 0,
+    level_2_rate DECIMAL(5, 2),
+
+    refreshed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_client_stats_user_id ON client_stats(user_id);
+
+-- Finds level-2 reminders per invoice
+CREATE INDEX idx_correspondence_chase_state
+    ON correspondence(invoice_id, (metadata->>'chase_state'))
+    WHERE kind = 'email';
+
+-- Row Level Security: Enable RLS
+ALTER TABLE client_stats ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view their own stats (written by the worker)
+CREATE POLICY client_stats_select_own ON client_stats
+    FOR SELECT
+    USING (user_id = auth.uid());
@@ -0,0 +1,35 @@
+-- Migration: Create idempotency_keys table
+-- Clients send an Idempotency-Key header with payments, status changes
+-- and bulk operations. The first request with a key stores its response
+-- here; retries with the same key get that response back instead of
+-- recording the payment (or sending the invoice) a second time. Keys
+-- expire after a day.
+
+CREATE TABLE idempotency_keys (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+
+    -- Fingerprint of the method, path and body of the first request
+    request_hash TEXT NOT NULL,
+
+    -- Stored response; NULL while the first request is still running
+    status_code INTEGER,
+    content_type TEXT,
+    response_body BYTEA,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    PRIMARY KEY (user_id, key)
+);
+
+CREATE INDEX idx_idempotency_keys_created_at ON idempotency_keys(created_at);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE idempotency_keys ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only access their own keys
+CREATE POLICY idempotency_keys_all_own ON idempotency_keys
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
@@ -0,0 +1,53 @@
+-- Migration: Create projects table
+-- Work for a client is grouped into projects with their own rate. Projects
+-- sync like clients; invoices may reference the project they bill, and
+-- the estimator embeds project descriptions (entity_type = 'project').
+
+CREATE TABLE projects (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Client the project is for (NULL for internal work)
+    client_id UUID REFERENCES clients(id) ON DELETE SET NULL,
+
+    -- Project fields
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    rate DECIMAL(15, 2) CHECK (rate >= 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    status VARCHAR(50) NOT NULL DEFAULT 'active',
+
+    -- Sync metadata (CRDT support)
+    last_modified TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    version_vector JSONB,
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_projects_user_id ON projects(user_id);
+CREATE INDEX idx_projects_client_id ON projects(client_id) WHERE client_id IS NOT NULL;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE projects ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own projects
+CREATE POLICY projects_all_own ON projects
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_projects_updated_at
+    BEFORE UPDATE ON projects
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Invoices optionally belong to a project
+ALTER TABLE invoices
+    ADD COLUMN project_id UUID REFERENCES projects(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_invoices_project_id ON invoices(project_id) WHERE project_id IS NOT NULL;
@@ -0,0 +1,52 @@
+-- Migration: Create invoice_disputes table
+-- Clients dispute an invoice from their portal with a reason and an
+-- optional file. The invoice's chase state moves to 'disputed', which
+-- holds automated reminders until the user resolves the dispute; the
+-- state before the dispute is kept here and restored on resolution. The
+-- worker emails the user about new disputes (email_sent_at).
+
+CREATE TABLE invoice_disputes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    token_id UUID REFERENCES client_portal_tokens(id) ON DELETE SET NULL,
+
+    reason TEXT NOT NULL,
+
+    -- Optional file from the client; contents live in blob storage
+    attachment_filename VARCHAR(255),
+    attachment_content_type VARCHAR(255),
+    attachment_size_bytes BIGINT,
+    attachment_storage_key TEXT,
+
+    -- 'open' or 'resolved'
+    status VARCHAR(50) NOT NULL DEFAULT 'open',
+    previous_chase_state VARCHAR(50),
+    resolved_at TIMESTAMPTZ,
+    email_sent_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_invoice_disputes_invoice ON invoice_disputes(invoice_id, created_at DESC);
+CREATE INDEX idx_invoice_disputes_unsent ON invoice_disputes(created_at) WHERE email_sent_at IS NULL;
+
+-- At most one open dispute per invoice
+CREATE UNIQUE INDEX idx_invoice_disputes_open ON invoice_disputes(invoice_id) WHERE status = 'open';
+
+-- Row Level Security: Enable RLS
+ALTER TABLE invoice_disputes ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage disputes of their own invoices
+CREATE POLICY invoice_disputes_all_own ON invoice_disputes
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_invoice_disputes_updated_at
+    BEFORE UPDATE ON invoice_disputes
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
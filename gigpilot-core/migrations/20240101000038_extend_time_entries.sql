@@ -0,0 +1,22 @@
+-- Migration: Extend time_entries for tracking and sync
+-- Devices track time offline and push entries through sync. An entry is
+-- either a tracked interval (started_at/stopped_at, from which hours is
+-- computed) or a plain duration in hours. Entries may belong to a project
+-- and can be marked non-billable; the weekly draft job only bills
+-- billable entries.
+
+ALTER TABLE time_entries
+    ADD COLUMN project_id UUID REFERENCES projects(id) ON DELETE SET NULL,
+    ADD COLUMN started_at TIMESTAMPTZ,
+    ADD COLUMN stopped_at TIMESTAMPTZ,
+    ADD COLUMN billable BOOLEAN NOT NULL DEFAULT true,
+    ADD CONSTRAINT time_entries_interval_check CHECK (
+        (started_at IS NULL AND stopped_at IS NULL)
+        OR (started_at IS NOT NULL AND stopped_at IS NOT NULL AND stopped_at > started_at)
+    );
+
+CREATE INDEX idx_time_entries_project_id ON time_entries(project_id) WHERE project_id IS NOT NULL;
+
+-- Unbilled billable work, for the weekly draft job
+DROP INDEX idx_time_entries_unbilled;
+CREATE INDEX idx_time_entries_unbilled ON time_entries(user_id) WHERE invoice_id IS NULL AND billable = true AND is_deleted = false;
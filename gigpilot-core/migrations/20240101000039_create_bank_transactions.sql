@@ -0,0 +1,50 @@
+-- Migration: Create bank_transactions table
+-- Incoming transfers from imported bank statements (CSV or CAMT.053).
+-- Each is matched to an open invoice by invoice number, amount or payer;
+-- a match records a payment on the invoice (payment_id). Transfers that
+-- could belong to several invoices wait in the review queue with their
+-- candidates until the user picks one or dismisses them. The transaction
+-- key makes importing an overlapping statement again a no-op.
+
+CREATE TABLE bank_transactions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    transaction_key VARCHAR(100) NOT NULL,
+
+    booked_on DATE NOT NULL,
+    amount DECIMAL(15, 2) NOT NULL CHECK (amount > 0),
+    currency VARCHAR(3) NOT NULL,
+    counterparty VARCHAR(255),
+    reference TEXT,
+    description TEXT,
+    bank_reference VARCHAR(255), -- the bank's own ID for the transaction
+
+    -- 'matched', 'review', 'unmatched' or 'dismissed'
+    status VARCHAR(50) NOT NULL,
+    -- 'invoice_number', 'amount' or 'manual' once matched
+    matched_by VARCHAR(50),
+    candidate_invoice_ids UUID[] NOT NULL DEFAULT '{}',
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+    payment_id UUID REFERENCES payments(id) ON DELETE SET NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_bank_transactions_key ON bank_transactions(user_id, transaction_key);
+CREATE INDEX idx_bank_transactions_status ON bank_transactions(user_id, status, booked_on DESC);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE bank_transactions ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own bank transactions
+CREATE POLICY bank_transactions_all_own ON bank_transactions
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_bank_transactions_updated_at
+    BEFORE UPDATE ON bank_transactions
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
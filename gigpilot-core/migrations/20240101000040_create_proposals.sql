@@ -0,0 +1,68 @@
+-- Migration: Create proposals table
+-- A proposal describes the scope of a job with one or more priced options.
+-- The client opens it through a share link and accepts one option, which
+-- can create a project and a deposit invoice.
+
+CREATE TABLE proposals (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Proposal fields
+    client_id UUID REFERENCES clients(id) ON DELETE SET NULL,
+    client_name VARCHAR(255) NOT NULL,
+    client_email VARCHAR(255),
+    title VARCHAR(255) NOT NULL,
+    scope TEXT,
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    -- 'draft', 'sent', 'accepted', 'declined'
+    status VARCHAR(50) NOT NULL DEFAULT 'draft',
+    -- Pricing options: [{ key, title, description, line_items, subtotal, tax_total, total }]
+    options JSONB NOT NULL DEFAULT '[]'::jsonb,
+    expires_on DATE,
+
+    -- What acceptance creates
+    deposit_percent DECIMAL(5, 2) CHECK (deposit_percent > 0 AND deposit_percent <= 100),
+    create_project BOOLEAN NOT NULL DEFAULT true,
+
+    -- Share link (NULL when not shared or revoked)
+    share_token UUID UNIQUE,
+    shared_at TIMESTAMPTZ,
+
+    -- Acceptance tracking
+    first_viewed_at TIMESTAMPTZ,
+    last_viewed_at TIMESTAMPTZ,
+    view_count INTEGER NOT NULL DEFAULT 0,
+    accepted_option VARCHAR(50),
+    accepted_by_name VARCHAR(255),
+    accepted_by_email VARCHAR(255),
+    accepted_at TIMESTAMPTZ,
+    declined_at TIMESTAMPTZ,
+    decline_reason TEXT,
+
+    -- Records created on acceptance
+    project_id UUID REFERENCES projects(id) ON DELETE SET NULL,
+    deposit_invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_proposals_user_status ON proposals(user_id, status) WHERE is_deleted = false;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE proposals ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own proposals
+CREATE POLICY proposals_all_own ON proposals
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_proposals_updated_at
+    BEFORE UPDATE ON proposals
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
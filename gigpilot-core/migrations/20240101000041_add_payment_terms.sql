@@ -0,0 +1,19 @@
+-- Migration: Payment terms and due-date rules
+-- New invoices without a due date get issue date + payment terms: the
+-- client's own terms when set, the user's default otherwise, and never
+-- fewer days than the user's minimum. With roll_due_dates_forward, due
+-- dates on weekends and public holidays (see user_settings.country_code)
+-- move to the next business day. Sync push rejects due dates that break
+-- these rules.
+
+ALTER TABLE user_settings
+    ADD COLUMN payment_terms_days INTEGER NOT NULL DEFAULT 14
+        CHECK (payment_terms_days BETWEEN 0 AND 365),
+    ADD COLUMN min_payment_terms_days INTEGER NOT NULL DEFAULT 0
+        CHECK (min_payment_terms_days BETWEEN 0 AND 365),
+    ADD COLUMN roll_due_dates_forward BOOLEAN NOT NULL DEFAULT false;
+
+-- NULL = use the user's default terms
+ALTER TABLE clients
+    ADD COLUMN payment_terms_days INTEGER
+        CHECK (payment_terms_days BETWEEN 0 AND 365);
@@ -0,0 +1,64 @@
+-- Migration: Create contracts table
+-- Contracts sent to clients for signing, per client and/or project. The
+-- signing status is kept up to date by hand or by an e-sign provider
+-- through the webhook, which finds the contract by provider and document
+-- ID. The signed PDF is kept in blob storage. Chase emails cite the
+-- latest signed contract of the invoice's project or client.
+
+CREATE TABLE contracts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id UUID REFERENCES clients(id) ON DELETE SET NULL,
+    project_id UUID REFERENCES projects(id) ON DELETE SET NULL,
+
+    title VARCHAR(255) NOT NULL,
+    -- 'draft', 'sent', 'signed', 'declined', 'voided'
+    status VARCHAR(50) NOT NULL DEFAULT 'draft',
+
+    -- E-sign provider and its document (envelope) ID
+    provider VARCHAR(50),
+    provider_document_id VARCHAR(255),
+
+    -- Signing progress
+    sent_at TIMESTAMPTZ,
+    last_viewed_at TIMESTAMPTZ,
+    signed_at TIMESTAMPTZ,
+    signed_by_name VARCHAR(255),
+    signed_by_email VARCHAR(255),
+    declined_at TIMESTAMPTZ,
+    voided_at TIMESTAMPTZ,
+
+    -- Signed PDF in blob storage
+    signed_filename VARCHAR(255),
+    signed_size_bytes BIGINT,
+    signed_storage_key TEXT,
+
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_contracts_user_client ON contracts(user_id, client_id) WHERE is_deleted = false;
+CREATE INDEX idx_contracts_user_project ON contracts(user_id, project_id) WHERE is_deleted = false;
+
+-- Webhook lookups; a provider document belongs to one contract
+CREATE UNIQUE INDEX idx_contracts_provider_document
+    ON contracts(provider, provider_document_id)
+    WHERE provider_document_id IS NOT NULL AND is_deleted = false;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE contracts ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own contracts
+CREATE POLICY contracts_all_own ON contracts
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_contracts_updated_at
+    BEFORE UPDATE ON contracts
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,48 @@
+-- Migration: Create client interactions
+-- A timeline of what happened with each client: notes, calls, emails and
+-- meetings the user logs by hand, plus entries recorded automatically for
+-- chase emails and invoice views (portal or pay page). The user reads it
+-- before escalating the tone with a client.
+
+CREATE TABLE client_interactions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+
+    -- Manual: 'note', 'call', 'email', 'meeting'
+    -- Automatic: 'chase_email', 'invoice_viewed'
+    kind VARCHAR(50) NOT NULL,
+    summary VARCHAR(500) NOT NULL,
+    body TEXT,
+
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    metadata JSONB,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Timeline pages, newest first
+CREATE INDEX idx_client_interactions_timeline
+    ON client_interactions(user_id, client_id, occurred_at DESC, id DESC);
+
+-- Recent views of an invoice, to record one view per hour
+CREATE INDEX idx_client_interactions_invoice
+    ON client_interactions(invoice_id, kind, occurred_at DESC)
+    WHERE invoice_id IS NOT NULL;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE client_interactions ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own clients' timelines
+CREATE POLICY client_interactions_all_own ON client_interactions
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_client_interactions_updated_at
+    BEFORE UPDATE ON client_interactions
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
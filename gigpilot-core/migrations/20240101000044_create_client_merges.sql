@@ -0,0 +1,37 @@
+-- Migration: Create client merges
+-- Merging a duplicate client into a canonical one re-points everything that
+-- referenced the duplicate and soft-deletes it. Each merge is recorded so
+-- devices that still hold the duplicate can reconcile it, and so the user
+-- can see what was moved.
+
+CREATE TABLE client_merges (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- The duplicate (soft-deleted by the merge) and the client it became
+    duplicate_client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    canonical_client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+
+    -- What was re-pointed
+    invoices_moved INTEGER NOT NULL DEFAULT 0,
+    payments_moved INTEGER NOT NULL DEFAULT 0,
+    projects_moved INTEGER NOT NULL DEFAULT 0,
+    embeddings_moved INTEGER NOT NULL DEFAULT 0,
+    -- IDs of the re-pointed invoices and projects
+    details JSONB NOT NULL DEFAULT '{}'::jsonb,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A client is merged away at most once
+CREATE UNIQUE INDEX idx_client_merges_duplicate ON client_merges(duplicate_client_id);
+CREATE INDEX idx_client_merges_canonical ON client_merges(user_id, canonical_client_id, created_at DESC);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE client_merges ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own merges
+CREATE POLICY client_merges_all_own ON client_merges
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
@@ -0,0 +1,8 @@
+-- Migration: Add chase_with_statement to user_settings
+-- When enabled, the chase worker sends a client one statement of all their
+-- open invoices (with the PDF attached) whenever any of them is due a
+-- reminder, instead of reminders per invoice. Existing users keep the
+-- per-invoice reminders.
+
+ALTER TABLE user_settings
+    ADD COLUMN chase_with_statement BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,37 @@
+-- Migration: Create sandboxed emails and the per-user sandbox settings
+-- In sandbox mode (globally via EMAIL_SANDBOX, or per user) chase and
+-- invoice emails never reach clients: they are redirected to a sandbox
+-- inbox or held unsent. A copy of each is kept here for previewing, while
+-- chase states, correspondence and sync carry on as if it had been sent.
+
+ALTER TABLE user_settings
+    ADD COLUMN email_sandbox BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN email_sandbox_inbox VARCHAR(255);
+
+CREATE TABLE sandboxed_emails (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- The client the email was addressed to
+    recipient VARCHAR(255) NOT NULL,
+    -- Inbox it was delivered to instead (NULL if held unsent)
+    redirected_to VARCHAR(255),
+
+    subject TEXT NOT NULL,
+    body_text TEXT NOT NULL,
+    -- Names, types and sizes of the attachments (contents are not kept)
+    attachments JSONB NOT NULL DEFAULT '[]'::jsonb,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_sandboxed_emails_user ON sandboxed_emails(user_id, created_at DESC);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE sandboxed_emails ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own sandboxed emails
+CREATE POLICY sandboxed_emails_all_own ON sandboxed_emails
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
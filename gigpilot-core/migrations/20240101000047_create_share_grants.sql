@@ -0,0 +1,41 @@
+-- Migration: Create share grants
+-- An account can share a single project or client with a GigPilot user
+-- outside its organization, read-only or with edit rights. The entity stays
+-- owned by (and synced under) the sharing account; grantees reach it through
+-- their grant, which the service layer and the grantee's sync pulls check.
+
+CREATE TABLE share_grants (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- Account that owns the shared entity
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- User it is shared with
+    grantee_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    entity_type VARCHAR(20) NOT NULL CHECK (entity_type IN ('project', 'client')),
+    entity_id UUID NOT NULL,
+    permission VARCHAR(10) NOT NULL DEFAULT 'read' CHECK (permission IN ('read', 'edit')),
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CHECK (grantee_id <> user_id)
+);
+
+-- An entity is shared with a user at most once
+CREATE UNIQUE INDEX idx_share_grants_entity_grantee ON share_grants(entity_type, entity_id, grantee_id);
+CREATE INDEX idx_share_grants_grantee ON share_grants(grantee_id, user_id);
+CREATE INDEX idx_share_grants_owner ON share_grants(user_id, created_at DESC);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE share_grants ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Owners can view and manage the grants they made
+CREATE POLICY share_grants_all_own ON share_grants
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- RLS Policy: Grantees can view the grants made to them
+CREATE POLICY share_grants_select_grantee ON share_grants
+    FOR SELECT
+    USING (grantee_id = auth.uid());
@@ -0,0 +1,16 @@
+-- Migration: Add budgets to projects
+-- A project can have a budget in hours, in money (in the project's
+-- currency) or both. Time tracked against the project is compared with it,
+-- and with budget_alerts on the worker emails the user when 80% and 100% of
+-- either budget are used. budget_alert_percent is the highest threshold
+-- already emailed, so each is emailed once until usage drops below it again
+-- (e.g. after the budget was raised).
+
+ALTER TABLE projects
+    ADD COLUMN budget_hours DECIMAL(10, 2) CHECK (budget_hours >= 0),
+    ADD COLUMN budget_amount DECIMAL(15, 2) CHECK (budget_amount >= 0),
+    ADD COLUMN budget_alerts BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN budget_alert_percent SMALLINT NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_projects_budget_alerts ON projects(user_id)
+    WHERE budget_alerts = true AND is_deleted = false;
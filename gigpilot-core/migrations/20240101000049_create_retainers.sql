@@ -0,0 +1,87 @@
+-- Migration: Create retainers
+-- A retainer is a monthly agreement with a client: a fixed fee for a number
+-- of hours on a project. Each month (counted from start_date) the worker
+-- opens a period and drafts the fee's invoice; when the period ends, time
+-- tracked on the project during it is drawn down from the included hours
+-- (plus any rolled over), and unused hours roll over to the next period or
+-- expire according to the retainer's policy.
+
+CREATE TABLE retainers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Client invoiced and project whose time is drawn down
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+
+    name VARCHAR(255) NOT NULL,
+    monthly_hours DECIMAL(10, 2) NOT NULL CHECK (monthly_hours >= 0),
+    monthly_amount DECIMAL(15, 2) NOT NULL CHECK (monthly_amount >= 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+
+    -- What happens to unused hours: 'expire' or 'rollover' (capped by
+    -- max_rollover_hours when set)
+    rollover_policy VARCHAR(20) NOT NULL DEFAULT 'expire',
+    max_rollover_hours DECIMAL(10, 2) CHECK (max_rollover_hours >= 0),
+
+    start_date DATE NOT NULL,
+    end_date DATE,
+    status VARCHAR(20) NOT NULL DEFAULT 'active',
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CHECK (end_date IS NULL OR end_date >= start_date)
+);
+
+CREATE INDEX idx_retainers_user_id ON retainers(user_id, created_at DESC);
+CREATE INDEX idx_retainers_active ON retainers(start_date) WHERE status = 'active';
+
+CREATE TABLE retainer_periods (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    retainer_id UUID NOT NULL REFERENCES retainers(id) ON DELETE CASCADE,
+
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+
+    -- Hours available in the period
+    included_hours DECIMAL(10, 2) NOT NULL,
+    rolled_over_hours DECIMAL(10, 2) NOT NULL DEFAULT 0,
+
+    -- Settled when the period is closed
+    used_hours DECIMAL(10, 2) NOT NULL DEFAULT 0,
+    carried_hours DECIMAL(10, 2) NOT NULL DEFAULT 0,
+    expired_hours DECIMAL(10, 2) NOT NULL DEFAULT 0,
+    closed_at TIMESTAMPTZ,
+
+    -- Draft invoice of the period's fee
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A retainer has one period per start date
+CREATE UNIQUE INDEX idx_retainer_periods_start ON retainer_periods(retainer_id, period_start);
+CREATE INDEX idx_retainer_periods_open ON retainer_periods(period_end) WHERE closed_at IS NULL;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE retainers ENABLE ROW LEVEL SECURITY;
+ALTER TABLE retainer_periods ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own retainers
+CREATE POLICY retainers_all_own ON retainers
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+CREATE POLICY retainer_periods_all_own ON retainer_periods
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_retainers_updated_at
+    BEFORE UPDATE ON retainers
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
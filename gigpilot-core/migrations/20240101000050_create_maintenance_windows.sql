@@ -0,0 +1,17 @@
+-- Migration: Create maintenance_windows table
+-- Support staff put the service in maintenance for a limited time before
+-- migrations: while a window is open, mutating API requests and sync
+-- pushes are refused with 503 and the worker stops sending emails. A window
+-- closes on its own at ends_at; ending it early moves ends_at to the end
+-- time. Past windows are kept as a record.
+
+CREATE TABLE maintenance_windows (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    message TEXT,
+    starts_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ends_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX idx_maintenance_windows_ends_at ON maintenance_windows(ends_at DESC);
@@ -0,0 +1,14 @@
+-- Migration: Create tracing_sampling_configs table
+-- Support staff tune which requests are traced at runtime through the admin
+-- API: per-route rates (e.g. every failed push, 1% of health checks) and
+-- users whose requests are always traced while a sync bug is investigated.
+-- The newest row is the configuration in effect; older rows are kept as a
+-- record of changes.
+
+CREATE TABLE tracing_sampling_configs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    config JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_tracing_sampling_configs_created_at ON tracing_sampling_configs(created_at DESC);
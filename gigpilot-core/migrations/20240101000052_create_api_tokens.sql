@@ -0,0 +1,48 @@
+-- Migration: Create personal access tokens
+-- Users create named, long-lived tokens for scripts and spreadsheet
+-- integrations. Unlike login JWTs they are looked up on every request, so
+-- they can be scoped, expire and be revoked. Only a SHA-256 hash of each
+-- token is stored; the token itself is shown once, when it is created.
+
+CREATE TABLE api_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+
+    -- Account the token acts as
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Person who created it (the account itself, or one of its members)
+    created_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    name VARCHAR(100) NOT NULL,
+
+    -- Start of the token, shown to tell tokens apart (e.g. "gp_pat_1a2b3c4d")
+    token_prefix VARCHAR(20) NOT NULL,
+    token_hash BYTEA NOT NULL UNIQUE,
+
+    -- 'read' (safe methods only) or 'write'
+    scope VARCHAR(10) NOT NULL DEFAULT 'read' CHECK (scope IN ('read', 'write')),
+
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_api_tokens_owner ON api_tokens(user_id, created_by, created_at DESC);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE api_tokens ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their account's tokens
+CREATE POLICY api_tokens_all_own ON api_tokens
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_api_tokens_updated_at
+    BEFORE UPDATE ON api_tokens
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,51 @@
+-- Migration: Create pdf_export_jobs table
+-- Users hand a period's invoices to their tax advisor as one ZIP of PDFs.
+-- The worker renders the invoices matching the job's filter, recording
+-- its progress as it goes, and stores the archive in blob storage until
+-- the job expires.
+
+CREATE TABLE pdf_export_jobs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Which invoices to export (JSON `PdfExportFilter`)
+    filter JSONB NOT NULL DEFAULT '{}'::jsonb,
+    -- 'queued', 'running', 'completed', 'failed'
+    status VARCHAR(50) NOT NULL DEFAULT 'queued',
+
+    -- Invoices matching the filter when the job was created, and how many
+    -- have been rendered
+    total INTEGER NOT NULL DEFAULT 0,
+    processed INTEGER NOT NULL DEFAULT 0,
+
+    -- Blob key of the finished archive (cleared once it expired)
+    storage_key VARCHAR(255),
+    error TEXT,
+
+    attempts INTEGER NOT NULL DEFAULT 0,
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_pdf_export_jobs_user_id ON pdf_export_jobs(user_id, created_at DESC);
+CREATE INDEX idx_pdf_export_jobs_queued ON pdf_export_jobs(created_at) WHERE status IN ('queued', 'running');
+CREATE INDEX idx_pdf_export_jobs_expiring ON pdf_export_jobs(expires_at) WHERE storage_key IS NOT NULL;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE pdf_export_jobs ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only view and manage their own export jobs
+CREATE POLICY pdf_export_jobs_all_own ON pdf_export_jobs
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_pdf_export_jobs_updated_at
+    BEFORE UPDATE ON pdf_export_jobs
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
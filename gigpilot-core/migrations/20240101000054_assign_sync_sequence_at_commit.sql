@@ -0,0 +1,41 @@
+-- Migration: Assign sync change sequence numbers in commit order
+-- Sequence numbers used to be taken when a change was inserted, so a
+-- transaction that committed late could make a change visible with a lower
+-- number (and an older change_timestamp) than changes devices had already
+-- pulled. Numbers are now assigned by a deferred trigger while holding a
+-- transaction-level advisory lock, which is released only once the
+-- transaction is visible: every change a reader can see with a number below
+-- N was committed before the change numbered N, so `sequence_number` is a
+-- gap-free pull cursor.
+
+ALTER TABLE sync_changes
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP NOT NULL;
+
+CREATE OR REPLACE FUNCTION assign_sync_change_sequence()
+RETURNS TRIGGER AS $$
+BEGIN
+    -- Serializes the end of transactions that wrote sync changes
+    PERFORM pg_advisory_xact_lock(hashtext('sync_changes.sequence_number'));
+
+    UPDATE sync_changes
+    SET sequence_number = nextval('sync_changes_sequence_number_seq')
+    WHERE id = NEW.id;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Runs at commit, after every other statement of the transaction
+CREATE CONSTRAINT TRIGGER assign_sync_change_sequence
+    AFTER INSERT ON sync_changes
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW
+    EXECUTE FUNCTION assign_sync_change_sequence();
+
+-- Cursor pulls across users (shared records)
+CREATE INDEX idx_sync_changes_sequence_number ON sync_changes(sequence_number);
+
+-- Highest sequence number pruned per user; devices whose cursor is below it
+-- must fetch a full snapshot
+ALTER TABLE sync_retention ADD COLUMN pruned_sequence BIGINT;
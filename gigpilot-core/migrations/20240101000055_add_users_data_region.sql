@@ -0,0 +1,35 @@
+-- Migration: Per-account data residency
+-- An account chooses at signup where its data is kept. NULL keeps it in
+-- the home cluster (DATABASE_URL); a region name keeps it in that
+-- region's cluster (DATABASE_URL_<REGION>), which runs these same
+-- migrations. The home cluster's users table is the directory of every
+-- account and its region; a regional cluster holds a copy of the rows of
+-- its own accounts, without their password, so their data can refer to
+-- them. Data isn't moved between clusters, so the region can't change.
+
+ALTER TABLE users
+    ADD COLUMN data_region VARCHAR(32)
+        CHECK (data_region ~ '^[a-z0-9-]{1,32}$');
+
+-- Copies of personal accounts in a regional cluster have no password
+ALTER TABLE users
+    DROP CONSTRAINT users_personal_password;
+
+ALTER TABLE users
+    ADD CONSTRAINT users_personal_password
+        CHECK (kind = 'workspace' OR password_hash IS NOT NULL OR data_region IS NOT NULL);
+
+CREATE OR REPLACE FUNCTION keep_users_data_region()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.data_region IS DISTINCT FROM OLD.data_region THEN
+        RAISE EXCEPTION 'the data region of account % cannot change', OLD.id;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER keep_users_data_region
+    BEFORE UPDATE OF data_region ON users
+    FOR EACH ROW
+    EXECUTE FUNCTION keep_users_data_region();
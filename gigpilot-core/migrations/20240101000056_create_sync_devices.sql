@@ -0,0 +1,43 @@
+-- Migration: Create sync_devices table for per-device sync checkpoints
+-- Each row records how far a device has pulled, when it last pushed and
+-- which app build it runs. Pruning keeps changes that devices seen within
+-- the retention window haven't pulled yet, and users can spot devices that
+-- have gone stale.
+
+CREATE TABLE sync_devices (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    device_id VARCHAR(255) NOT NULL,
+
+    -- Cursor of the device's last complete pull
+    last_pulled_sequence BIGINT,
+    last_pulled_at TIMESTAMPTZ,
+    last_pushed_at TIMESTAMPTZ,
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Reported by the client, e.g. "2.4.1" and "ios"
+    app_version VARCHAR(64),
+    platform VARCHAR(32),
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, device_id)
+);
+
+-- Index for finding the devices that hold back pruning
+CREATE INDEX idx_sync_devices_user_seen ON sync_devices(user_id, last_seen_at);
+
+-- Trigger: Update updated_at on row update
+CREATE TRIGGER update_sync_devices_updated_at
+    BEFORE UPDATE ON sync_devices
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Row Level Security: Enable RLS
+ALTER TABLE sync_devices ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only see and manage their own devices
+CREATE POLICY sync_devices_all_own ON sync_devices
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
@@ -0,0 +1,41 @@
+-- Migration: Announce new sync changes
+-- Devices connected to the sync WebSocket pull as soon as a change is
+-- logged instead of polling. Every statement inserting sync changes
+-- publishes one domain event per affected account: the owner of the
+-- changed records and the users a changed project or client is shared
+-- with. Notifications are delivered at commit, after sequence numbers have
+-- been assigned, and identical ones in a transaction are sent only once.
+
+CREATE OR REPLACE FUNCTION notify_sync_changes_available()
+RETURNS TRIGGER AS $$
+DECLARE
+    target RECORD;
+BEGIN
+    FOR target IN
+        SELECT user_id, device_id FROM inserted
+        UNION
+        SELECT g.grantee_id, c.device_id
+        FROM inserted c
+        JOIN share_grants g
+            ON g.user_id = c.user_id
+            AND g.entity_id = c.record_id
+            AND c.table_name = CASE g.entity_type WHEN 'project' THEN 'projects' ELSE 'clients' END
+    LOOP
+        PERFORM pg_notify(
+            'gigpilot_domain_events',
+            json_build_object(
+                'type', 'sync_changes_available',
+                'user_id', target.user_id,
+                'device_id', target.device_id
+            )::text
+        );
+    END LOOP;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notify_sync_changes_available
+    AFTER INSERT ON sync_changes
+    REFERENCING NEW TABLE AS inserted
+    FOR EACH STATEMENT
+    EXECUTE FUNCTION notify_sync_changes_available();
@@ -0,0 +1,8 @@
+-- Migration: Track push latency per device
+-- The sync status endpoint reports each device's average push latency, so
+-- the app can show whether syncing is healthy. Pushes are counted with
+-- their total server time rather than stored one by one.
+
+ALTER TABLE sync_devices
+    ADD COLUMN push_count BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN push_duration_ms BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,52 @@
+-- Migration: Create sync_merge_suggestions table for the conflict queue
+-- When a pushed description or note loses a conflict, an optional resolver
+-- asks a language model to merge both texts. The merged text waits here as
+-- a suggestion until the user accepts or dismisses it; it is never applied
+-- on its own.
+
+CREATE TABLE sync_merge_suggestions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- The conflicted field, e.g. invoices/<id>/description
+    table_name VARCHAR(100) NOT NULL,
+    record_id UUID NOT NULL,
+    field VARCHAR(100) NOT NULL,
+
+    -- Device whose text lost the conflict
+    device_id VARCHAR(255) NOT NULL,
+
+    -- Both sides of the conflict and the proposed merge
+    server_value TEXT NOT NULL,
+    client_value TEXT NOT NULL,
+    suggested_value TEXT NOT NULL,
+
+    -- Resolver that produced the suggestion (e.g. "mock")
+    provider VARCHAR(50) NOT NULL,
+
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'accepted', 'dismissed')),
+    resolved_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Index for listing a user's open suggestions
+CREATE INDEX idx_sync_merge_suggestions_user_status
+    ON sync_merge_suggestions(user_id, status, created_at DESC);
+
+-- Trigger: Update updated_at on row update
+CREATE TRIGGER update_sync_merge_suggestions_updated_at
+    BEFORE UPDATE ON sync_merge_suggestions
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Row Level Security: Enable RLS
+ALTER TABLE sync_merge_suggestions ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only see and manage their own suggestions
+CREATE POLICY sync_merge_suggestions_all_own ON sync_merge_suggestions
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
@@ -0,0 +1,12 @@
+-- Migration: Track sync changes folded away by compaction
+-- Successive updates a device made to the same record within one
+-- compaction window are collapsed into the latest of them (see
+-- sync::compaction). The surviving change counts the ones it replaced, so
+-- per-device change counts and conflict rates stay as they were.
+
+ALTER TABLE sync_changes
+    ADD COLUMN compacted_changes INTEGER NOT NULL DEFAULT 0;
+
+-- Runs of updates are found per record in commit order
+CREATE INDEX idx_sync_changes_record_sequence
+    ON sync_changes(user_id, table_name, record_id, sequence_number);
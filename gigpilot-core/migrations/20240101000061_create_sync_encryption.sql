@@ -0,0 +1,66 @@
+-- Migration: Create tables for end-to-end encrypted sync
+-- Users may opt tables into end-to-end encryption. Their devices then push
+-- records of those tables as encrypted payloads, which the server stores
+-- and relays to the user's other devices without being able to read them.
+-- Only structural columns (table, record ID, version, deletion) are kept in
+-- the clear, for ordering and conflict detection.
+
+-- Tables a user syncs encrypted
+CREATE TABLE sync_encrypted_tables (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    table_name VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, table_name)
+);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE sync_encrypted_tables ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only see and manage their own encrypted tables
+CREATE POLICY sync_encrypted_tables_all_own ON sync_encrypted_tables
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
+
+-- Latest encrypted payload of each record
+CREATE TABLE sync_encrypted_records (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    table_name VARCHAR(100) NOT NULL,
+    record_id UUID NOT NULL,
+
+    -- The payload as pushed: algorithm, key ID, nonce and ciphertext
+    payload JSONB NOT NULL,
+
+    -- Incremented on every write; devices send the version they edited
+    version BIGINT NOT NULL DEFAULT 1,
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+
+    -- Device that pushed the latest version
+    device_id VARCHAR(255) NOT NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (user_id, table_name, record_id)
+);
+
+-- Index for streaming a user's live records
+CREATE INDEX idx_sync_encrypted_records_user_live
+    ON sync_encrypted_records(user_id, id)
+    WHERE is_deleted = false;
+
+-- Trigger: Update updated_at on row update
+CREATE TRIGGER update_sync_encrypted_records_updated_at
+    BEFORE UPDATE ON sync_encrypted_records
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Row Level Security: Enable RLS
+ALTER TABLE sync_encrypted_records ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only see and manage their own encrypted records
+CREATE POLICY sync_encrypted_records_all_own ON sync_encrypted_records
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
@@ -0,0 +1,54 @@
+-- Migration: Create jobs table
+-- Durable queue for worker jobs, so several worker replicas can share the
+-- work without doing it twice. A worker claims due jobs with
+-- SELECT ... FOR UPDATE SKIP LOCKED, holding each for a visibility timeout;
+-- a job whose worker died is claimed again once that lapses. Failed jobs
+-- are retried with backoff until they run out of attempts.
+
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+
+    -- What the job does (e.g. 'chase_invoices') and its input
+    kind VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}'::jsonb,
+
+    -- At most one queued or running job per kind and key
+    dedupe_key VARCHAR(255),
+
+    -- 'queued', 'running', 'completed', 'failed'
+    status VARCHAR(50) NOT NULL DEFAULT 'queued',
+
+    -- Times claimed so far, and how many claims it gets
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+
+    -- When the job may next be claimed
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Worker holding a running job, and until when
+    locked_by VARCHAR(255),
+    locked_until TIMESTAMPTZ,
+
+    last_error TEXT,
+    finished_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Index for claiming due jobs and reclaiming lapsed ones
+CREATE INDEX idx_jobs_due ON jobs(kind, run_at) WHERE status IN ('queued', 'running');
+
+-- Enqueueing a job that is already pending is a no-op
+CREATE UNIQUE INDEX idx_jobs_pending_dedupe
+    ON jobs(kind, dedupe_key)
+    WHERE status IN ('queued', 'running');
+
+-- Index for pruning finished jobs
+CREATE INDEX idx_jobs_finished ON jobs(finished_at) WHERE finished_at IS NOT NULL;
+
+-- Trigger to auto-update updated_at
+CREATE TRIGGER update_jobs_updated_at
+    BEFORE UPDATE ON jobs
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,7 @@
+-- Migration: Add final_notice_mentions_collections to user_settings
+-- When enabled, the final notice the chase worker sends (after the level-3
+-- reminder) warns the client that the invoice will be referred to
+-- collections if it stays unpaid. Off by default.
+
+ALTER TABLE user_settings
+    ADD COLUMN final_notice_mentions_collections BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,7 @@
+-- Migration: Add chase pause to invoices
+-- Users can stop reminders for an invoice while negotiating with the
+-- client, without marking it paid. The worker skips the invoice until
+-- chase_paused_until passes; NULL means it isn't paused.
+
+ALTER TABLE invoices
+    ADD COLUMN chase_paused_until TIMESTAMPTZ;
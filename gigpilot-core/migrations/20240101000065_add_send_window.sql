@@ -0,0 +1,16 @@
+-- Migration: Add timezone and send window to user_settings
+-- Once a user sets their timezone (usually where their clients are), the
+-- chase worker only sends reminders between send_window_start_hour and
+-- send_window_end_hour local time, on weekdays unless send_on_weekends is
+-- set; work found outside the window waits for the next opening. Without
+-- a timezone reminders go out whenever the worker runs.
+
+ALTER TABLE user_settings
+    ADD COLUMN timezone VARCHAR(64),
+    ADD COLUMN send_window_start_hour INTEGER NOT NULL DEFAULT 9
+        CHECK (send_window_start_hour BETWEEN 0 AND 23),
+    ADD COLUMN send_window_end_hour INTEGER NOT NULL DEFAULT 12
+        CHECK (send_window_end_hour BETWEEN 1 AND 24),
+    ADD COLUMN send_on_weekends BOOLEAN NOT NULL DEFAULT false,
+    ADD CONSTRAINT user_settings_send_window_order
+        CHECK (send_window_start_hour < send_window_end_hour);
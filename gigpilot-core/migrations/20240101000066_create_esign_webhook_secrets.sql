@@ -0,0 +1,26 @@
+-- Migration: Per-user e-sign webhook secrets
+-- Each user posts e-sign events to their own webhook URL with their own
+-- secret, and the contract is looked up among that user's contracts only:
+-- a document ID is set by the user, so another user could claim the same
+-- one. Only the SHA-256 of each secret is stored.
+
+CREATE TABLE esign_webhook_secrets (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    secret_hash BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A provider document belongs to one contract of each user
+DROP INDEX idx_contracts_provider_document;
+CREATE UNIQUE INDEX idx_contracts_provider_document
+    ON contracts(user_id, provider, provider_document_id)
+    WHERE provider_document_id IS NOT NULL AND is_deleted = false;
+
+-- Row Level Security: Enable RLS
+ALTER TABLE esign_webhook_secrets ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: Users can only manage their own secret
+CREATE POLICY esign_webhook_secrets_all_own ON esign_webhook_secrets
+    FOR ALL
+    USING (user_id = auth.uid())
+    WITH CHECK (user_id = auth.uid());
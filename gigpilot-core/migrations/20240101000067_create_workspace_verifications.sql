@@ -0,0 +1,29 @@
+-- Migration: Verify workspace emails before creating workspaces
+-- A workspace's email becomes an account email that nobody else can sign
+-- up with, so it is only claimed once its owner proves they receive mail
+-- there: creating a workspace records a pending request and emails a
+-- token, and the workspace is created when the requester confirms it.
+-- Only the SHA-256 of each token is stored.
+
+CREATE TABLE workspace_verifications (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    token_hash BYTEA NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Asking again for the same email replaces the pending request
+CREATE UNIQUE INDEX idx_workspace_verifications_owner_email
+    ON workspace_verifications(owner_id, lower(email));
+CREATE UNIQUE INDEX idx_workspace_verifications_token ON workspace_verifications(token_hash);
+
+-- Row Level Security: Enable RLS
+ALTER TABLE workspace_verifications ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policy: People see their own pending workspaces
+CREATE POLICY workspace_verifications_select_own ON workspace_verifications
+    FOR SELECT
+    USING (owner_id = auth.uid());
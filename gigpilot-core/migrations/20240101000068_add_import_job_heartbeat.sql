@@ -0,0 +1,8 @@
+-- Migration: Add heartbeat_at to import_jobs
+-- The worker running an import refreshes heartbeat_at every minute, so only
+-- jobs whose worker stopped (no heartbeat for 30 minutes) are picked up
+-- again, and never while the first run is still going.
+
+ALTER TABLE import_jobs ADD COLUMN heartbeat_at TIMESTAMPTZ;
+
+UPDATE import_jobs SET heartbeat_at = started_at WHERE status = 'running';
@@ -0,0 +1,8 @@
+-- Migration: Add statement_locale to user_settings
+-- Language and number/date formats of the statements the chase worker
+-- sends in place of reminders when chase_with_statement is set. Scheduled
+-- statements keep the locale of their schedule.
+
+ALTER TABLE user_settings
+    ADD COLUMN statement_locale VARCHAR(2) NOT NULL DEFAULT 'en'
+        CHECK (statement_locale IN ('en', 'de', 'fr', 'es'));
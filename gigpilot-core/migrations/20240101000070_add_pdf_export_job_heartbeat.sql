@@ -0,0 +1,8 @@
+-- Migration: Add heartbeat_at to pdf_export_jobs
+-- The worker rendering an export refreshes heartbeat_at every minute, so
+-- only jobs whose worker stopped (no heartbeat for 30 minutes) are picked
+-- up again, and never while the first run is still going.
+
+ALTER TABLE pdf_export_jobs ADD COLUMN heartbeat_at TIMESTAMPTZ;
+
+UPDATE pdf_export_jobs SET heartbeat_at = started_at WHERE status = 'running';
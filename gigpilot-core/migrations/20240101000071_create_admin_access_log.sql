@@ -0,0 +1,16 @@
+-- Migration: Create admin_access_log table
+-- Every admin API request reading or diffing a user's sync data is logged
+-- before it runs: which user, who asked (the optional X-Admin-Actor
+-- header) and what was requested. Entries aren't tied to the user row, so
+-- the log outlives the account.
+
+CREATE TABLE admin_access_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    actor VARCHAR(255),
+    method VARCHAR(10) NOT NULL,
+    path TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_admin_access_log_user_id ON admin_access_log(user_id, created_at DESC);
@@ -0,0 +1,265 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::accounts::{
+    account_role, add_member, create_user, create_workspace, delete_user, list_accounts, remove_member,
+    request_workspace, validate_signup, validate_workspace, verification_email, WorkspaceVerification,
+    VERIFICATION_HOURS,
+};
+use crate::auth::{issue_token, Claims, CurrentIdentity};
+use crate::db::PoolRouter;
+use crate::logging::redact_email;
+use crate::models::account::{AccountRole, AccountSummary, AddAccountMember, CreateWorkspace, VerifyWorkspace};
+use crate::models::user::{CreateUser, UserResponse};
+use crate::worker::services::send_email;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Fails with `403` unless the person owns the account.
+async fn require_owner(pool: &PgPool, account_id: Uuid, person_id: Uuid) -> Result<(), ApiError> {
+    let role = account_role(pool, account_id, person_id).await.map_err(|e| {
+        error!("Failed to load role of {} in account {}: {}", person_id, account_id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to load account")
+    })?;
+
+    match role {
+        Some(AccountRole::Owner) => Ok(()),
+        _ => Err(api_error(StatusCode::FORBIDDEN, "only account owners can manage members")),
+    }
+}
+
+/// Register endpoint handler.
+///
+/// Handles POST requests to `/auth/register` (no login). The account's
+/// data is kept in the chosen `data_region`, one of the configured regions
+/// (the home region when omitted), and it can't be changed later. Returns
+/// 201 with the user, 422 for an invalid request or unknown region and 409
+/// if the email belongs to another account.
+pub async fn register_handler(
+    Extension(router): Extension<PoolRouter>,
+    Json(request): Json<CreateUser>,
+) -> Result<(StatusCode, Json<UserResponse>), ApiError> {
+    let pool = router.home().clone();
+
+    let data_region = validate_signup(&request, router.home_region(), &router.regions())
+        .map_err(|message| api_error(StatusCode::UNPROCESSABLE_ENTITY, &message))?;
+
+    let password = request.password.clone();
+    let password_hash = tokio::task::spawn_blocking(move || bcrypt::hash(password, bcrypt::DEFAULT_COST))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|hash| hash.map_err(anyhow::Error::from))
+        .map_err(|e| {
+            error!("Failed to hash password for {}: {}", redact_email(&request.email), e);
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to create account")
+        })?;
+
+    let user = create_user(&pool, &request, &password_hash, data_region.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to create account for {}: {}", redact_email(&request.email), e);
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to create account")
+        })?
+        .ok_or_else(|| api_error(StatusCode::CONFLICT, "email already belongs to another account"))?;
+
+    // Keep the account's data in its region; an account that can't be
+    // provisioned there is removed so the email can sign up again
+    if let Err(e) = router.provision_account(user.id).await {
+        error!("Failed to provision account {}: {}", user.id, e);
+        if let Err(e) = delete_user(&pool, user.id).await {
+            error!("Failed to remove unprovisioned account {}: {}", user.id, e);
+        }
+        return Err(api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to create account"));
+    }
+
+    Ok((StatusCode::CREATED, Json(UserResponse::from(user))))
+}
+
+/// List accounts endpoint handler.
+///
+/// Handles GET requests to `/api/accounts`, returning every account the
+/// logged-in person can act as (their own first).
+pub async fn list_accounts_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+) -> Result<Json<Vec<AccountSummary>>, StatusCode> {
+    let pool = router.home().clone();
+
+    let accounts = list_accounts(&pool, person_id).await.map_err(|e| {
+        error!("Failed to list accounts for {}: {}", person_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(accounts))
+}
+
+/// Create workspace endpoint handler.
+///
+/// Handles POST requests to `/api/accounts`. Nothing is created yet: a
+/// token is emailed to the workspace's email, to be confirmed with
+/// [`verify_workspace_handler`]. Returns 202.
+pub async fn create_workspace_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    Json(request): Json<CreateWorkspace>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let pool = router.home().clone();
+
+    validate_workspace(&request)
+        .map_err(|message| api_error(StatusCode::UNPROCESSABLE_ENTITY, &message))?;
+
+    let token = request_workspace(&pool, person_id, &request).await.map_err(|e| {
+        error!("Failed to request workspace for {}: {}", person_id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to create workspace")
+    })?;
+    let token = token.ok_or_else(|| api_error(StatusCode::CONFLICT, "email already belongs to another account"))?;
+
+    let email = request.email.trim();
+    let (subject, body) = verification_email(request.name.trim(), &token);
+    send_email(email, &subject, &body).await.map_err(|e| {
+        error!("Failed to send workspace verification to {}: {}", redact_email(&email), e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to send verification email")
+    })?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({ "email": email.to_lowercase(), "expires_in_hours": VERIFICATION_HOURS })),
+    ))
+}
+
+/// Verify workspace endpoint handler.
+///
+/// Handles POST requests to `/api/accounts/verify` with the token emailed
+/// by [`create_workspace_handler`]. The logged-in person becomes the
+/// workspace's owner, and its data is kept in the owner's region. Returns
+/// 201 with the workspace.
+pub async fn verify_workspace_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    Json(request): Json<VerifyWorkspace>,
+) -> Result<(StatusCode, Json<AccountSummary>), ApiError> {
+    let pool = router.home().clone();
+
+    let verification = create_workspace(&pool, person_id, &request.token).await.map_err(|e| {
+        error!("Failed to create workspace for {}: {}", person_id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to create workspace")
+    })?;
+    let account = match verification {
+        WorkspaceVerification::Created(account) => account,
+        WorkspaceVerification::UnknownToken => {
+            return Err(api_error(StatusCode::NOT_FOUND, "unknown or expired verification token"))
+        }
+        WorkspaceVerification::EmailTaken => {
+            return Err(api_error(StatusCode::CONFLICT, "email already belongs to another account"))
+        }
+    };
+
+    // Keep the workspace's data in its owner's region
+    router.provision_account(account.id).await.map_err(|e| {
+        error!("Failed to provision workspace {}: {}", account.id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to create workspace")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(account)))
+}
+
+/// Switch account endpoint handler.
+///
+/// Handles POST requests to `/api/accounts/:id/switch`, returning a token
+/// that acts as the account (same expiry as the current token). Switching
+/// to the person's own id returns a token for their personal account.
+/// Requests made with a personal access token get `403`.
+pub async fn switch_account_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    claims: Option<Extension<Claims>>,
+    Path(account_id): Path<Uuid>,
+) -> Result<Json<Value>, ApiError> {
+    let pool = router.home().clone();
+
+    // Personal access tokens carry no claims and stay in their account
+    let Some(Extension(claims)) = claims else {
+        return Err(api_error(StatusCode::FORBIDDEN, "API tokens can't switch accounts"));
+    };
+
+    let role = account_role(&pool, account_id, person_id).await.map_err(|e| {
+        error!("Failed to load role of {} in account {}: {}", person_id, account_id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to switch account")
+    })?;
+    if role.is_none() {
+        return Err(api_error(StatusCode::FORBIDDEN, "not a member of this account"));
+    }
+
+    let claims = Claims {
+        sub: person_id.to_string(),
+        exp: claims.exp,
+        account: (account_id != person_id).then(|| account_id.to_string()),
+    };
+    let token = issue_token(&claims).map_err(|e| {
+        error!("Failed to issue token for {}: {}", person_id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to switch account")
+    })?;
+
+    Ok(Json(json!({ "token": token, "account": account_id, "role": role })))
+}
+
+/// Add account member endpoint handler.
+///
+/// Handles POST requests to `/api/accounts/:id/members` (owners only).
+/// Adding an existing member changes their role.
+pub async fn add_member_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    Path(account_id): Path<Uuid>,
+    Json(request): Json<AddAccountMember>,
+) -> Result<Json<Value>, ApiError> {
+    let pool = router.home().clone();
+
+    require_owner(&pool, account_id, person_id).await?;
+
+    let role = request.role.unwrap_or(AccountRole::Member);
+    let member_id = add_member(&pool, account_id, &request.email, role).await.map_err(|e| {
+        error!("Failed to add member to account {}: {}", account_id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to add member")
+    })?;
+
+    member_id
+        .map(|member_id| Json(json!({ "account_id": account_id, "member_id": member_id, "role": role })))
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "no person with that email"))
+}
+
+/// Remove account member endpoint handler.
+///
+/// Handles DELETE requests to `/api/accounts/:id/members/:member_id`
+/// (owners only). The last owner cannot be removed.
+pub async fn remove_member_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    Path((account_id, member_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    let pool = router.home().clone();
+
+    require_owner(&pool, account_id, person_id).await?;
+
+    let removed = remove_member(&pool, account_id, member_id).await.map_err(|e| {
+        error!("Failed to remove member {} from account {}: {}", member_id, account_id, e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to remove member")
+    })?;
+
+    match removed {
+        Some(true) => Ok(StatusCode::NO_CONTENT),
+        Some(false) => Err(api_error(StatusCode::CONFLICT, "an account must keep at least one owner")),
+        None => Err(api_error(StatusCode::NOT_FOUND, "not a member of this account")),
+    }
+}
@@ -0,0 +1,539 @@
+//! Accounts and workspaces.
+//!
+//! One login can act as several accounts: the person's own account plus
+//! workspaces they own or belong to (e.g. separate business lines). Each
+//! account owns its data exactly like a user does, so handlers, sync and
+//! the worker stay scoped per account; the JWT's `account` claim selects
+//! which one a request acts as (see `auth::jwt_middleware`). Accounts and
+//! memberships are kept in the home database cluster, whatever region an
+//! account's data lives in (see [`crate::db::residency`]).
+//!
+//! A workspace's email can't be used by anyone else afterwards, so it is
+//! only claimed once verified: [`request_workspace`] emails a token to the
+//! address and [`create_workspace`] creates the workspace when the person
+//! who asked confirms it.
+//!
+//! People sign up with [`create_user`], choosing the region their data is
+//! kept in; it can't be changed afterwards.
+
+pub mod handlers;
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::models::account::{AccountKind, AccountRole, AccountSummary, CreateWorkspace};
+use crate::models::user::{CreateUser, User};
+
+/// Longest workspace name accepted.
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Shortest password accepted at signup.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Longest password accepted at signup (bcrypt only reads 72 bytes).
+const MAX_PASSWORD_BYTES: usize = 72;
+
+/// How long a workspace email verification token stays valid.
+pub const VERIFICATION_HOURS: i64 = 24;
+
+/// Validates a workspace creation request.
+///
+/// # Returns
+///
+/// Returns a user-facing message describing the first problem found.
+pub fn validate_workspace(request: &CreateWorkspace) -> Result<(), String> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("name must be at most {} characters", MAX_NAME_LENGTH));
+    }
+    let email = request.email.trim();
+    if email.len() > 255 || !email.contains('@') || email.starts_with('@') || email.ends_with('@') {
+        return Err("email must be a valid email address".to_string());
+    }
+    Ok(())
+}
+
+/// Validates a signup request.
+///
+/// # Arguments
+///
+/// * `request` - The signup request
+/// * `home_region` - Name of the home region
+/// * `regions` - Regions accounts can choose (see `PoolRouter::regions`)
+///
+/// # Returns
+///
+/// Returns the region to store on the account (`None` for the home
+/// region), or a user-facing message describing the first problem found.
+pub fn validate_signup(request: &CreateUser, home_region: &str, regions: &[&str]) -> Result<Option<String>, String> {
+    let email = request.email.trim();
+    if email.len() > 255 || !email.contains('@') || email.starts_with('@') || email.ends_with('@') {
+        return Err("email must be a valid email address".to_string());
+    }
+    if request.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!("password must be at least {} characters", MIN_PASSWORD_LENGTH));
+    }
+    if request.password.len() > MAX_PASSWORD_BYTES {
+        return Err(format!("password must be at most {} bytes", MAX_PASSWORD_BYTES));
+    }
+    if let Some(name) = &request.full_name {
+        if name.trim().chars().count() > MAX_NAME_LENGTH {
+            return Err(format!("full_name must be at most {} characters", MAX_NAME_LENGTH));
+        }
+    }
+
+    let region = request
+        .data_region
+        .as_deref()
+        .map(|region| region.trim().to_ascii_lowercase())
+        .filter(|region| !region.is_empty());
+    match region {
+        Some(region) if !regions.contains(&region.as_str()) => {
+            Err(format!("data_region must be one of: {}", regions.join(", ")))
+        }
+        Some(region) if region != home_region => Ok(Some(region)),
+        _ => Ok(None),
+    }
+}
+
+/// Whether a member can be removed from an account.
+///
+/// Accounts always keep at least one owner.
+pub fn can_remove_member(role: AccountRole, owner_count: i64) -> bool {
+    role != AccountRole::Owner || owner_count > 1
+}
+
+/// Returns a person's role in an account.
+///
+/// A person is always the owner of their own account.
+///
+/// # Returns
+///
+/// Returns the role, or `None` if the person is not a member.
+pub async fn account_role(
+    pool: &PgPool,
+    account_id: Uuid,
+    person_id: Uuid,
+) -> Result<Option<AccountRole>, anyhow::Error> {
+    if account_id == person_id {
+        return Ok(Some(AccountRole::Owner));
+    }
+
+    let role = sqlx::query_scalar::<_, AccountRole>(
+        r#"
+        SELECT m.role
+        FROM account_members m
+        JOIN users u ON u.id = m.account_id
+        WHERE m.account_id = $1 AND m.member_id = $2 AND u.is_active = true
+        "#,
+    )
+    .bind(account_id)
+    .bind(person_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(role)
+}
+
+/// Lists the accounts a person can act as, their own account first.
+pub async fn list_accounts(pool: &PgPool, person_id: Uuid) -> Result<Vec<AccountSummary>, anyhow::Error> {
+    let accounts = sqlx::query_as::<_, AccountSummary>(
+        r#"
+        SELECT
+            u.id, COALESCE(u.full_name, u.email) AS name, u.email, u.kind,
+            COALESCE(m.role, 'owner') AS role
+        FROM users u
+        LEFT JOIN account_members m ON m.account_id = u.id AND m.member_id = $1
+        WHERE u.id = $1 OR (m.member_id IS NOT NULL AND u.is_active = true)
+        ORDER BY u.id = $1 DESC, name
+        "#,
+    )
+    .bind(person_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(accounts)
+}
+
+/// Creates a personal account.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool (home cluster)
+/// * `request` - The signup request (see [`validate_signup`])
+/// * `password_hash` - Bcrypt hash of `request.password`
+/// * `data_region` - Region to keep the account's data in (`None` for the
+///   home region)
+///
+/// # Returns
+///
+/// Returns the new user, or `None` if the email already belongs to an
+/// account.
+pub async fn create_user(
+    pool: &PgPool,
+    request: &CreateUser,
+    password_hash: &str,
+    data_region: Option<&str>,
+) -> Result<Option<User>, anyhow::Error> {
+    let email = request.email.trim().to_lowercase();
+    let taken = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM users WHERE lower(email) = $1)")
+        .bind(&email)
+        .fetch_one(pool)
+        .await?;
+    if taken {
+        return Ok(None);
+    }
+
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        INSERT INTO users (email, password_hash, full_name, data_region)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(&email)
+    .bind(password_hash)
+    .bind(request.full_name.as_deref().map(str::trim).filter(|name| !name.is_empty()))
+    .bind(data_region)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Deletes an account that was never used, e.g. when signup could not
+/// finish provisioning it.
+pub async fn delete_user(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Generates a verification token: 64 random hex characters.
+fn generate_verification_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// The email asking the owner of a workspace's email to confirm it.
+///
+/// # Returns
+///
+/// Returns the subject and body.
+pub fn verification_email(name: &str, token: &str) -> (String, String) {
+    (
+        format!("Confirm the email of your workspace {}", name),
+        format!(
+            "Someone asked to create the GigPilot workspace \"{}\" with this email address.\n\n\
+             If that was you, confirm it in GigPilot with this code within {} hours:\n\n{}\n\n\
+             If it wasn't, ignore this email; the address won't be used.",
+            name, VERIFICATION_HOURS, token
+        ),
+    )
+}
+
+/// Starts creating a workspace: records the request until its email is
+/// verified.
+///
+/// Asking again for the same email replaces the earlier token.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `owner_id` - ID of the person creating it
+/// * `request` - Name and business email (see [`validate_workspace`])
+///
+/// # Returns
+///
+/// Returns the token to email to `request.email`, or `None` if the email
+/// already belongs to an account.
+pub async fn request_workspace(
+    pool: &PgPool,
+    owner_id: Uuid,
+    request: &CreateWorkspace,
+) -> Result<Option<String>, anyhow::Error> {
+    let email = request.email.trim().to_lowercase();
+    let taken = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM users WHERE lower(email) = $1)")
+        .bind(&email)
+        .fetch_one(pool)
+        .await?;
+    if taken {
+        return Ok(None);
+    }
+
+    let token = generate_verification_token();
+    sqlx::query(
+        r#"
+        INSERT INTO workspace_verifications (owner_id, email, name, token_hash, expires_at)
+        VALUES ($1, $2, $3, sha256(convert_to($4, 'UTF8')), $5)
+        ON CONFLICT (owner_id, lower(email)) DO UPDATE
+            SET name = EXCLUDED.name,
+                token_hash = EXCLUDED.token_hash,
+                expires_at = EXCLUDED.expires_at,
+                created_at = NOW()
+        "#,
+    )
+    .bind(owner_id)
+    .bind(&email)
+    .bind(request.name.trim())
+    .bind(&token)
+    .bind(Utc::now() + Duration::hours(VERIFICATION_HOURS))
+    .execute(pool)
+    .await?;
+
+    Ok(Some(token))
+}
+
+/// Outcome of confirming a workspace's email.
+#[derive(Debug, Clone)]
+pub enum WorkspaceVerification {
+    /// The workspace was created
+    Created(AccountSummary),
+
+    /// No pending request of the person has the token, or it expired
+    UnknownToken,
+
+    /// The email was claimed by another account in the meantime
+    EmailTaken,
+}
+
+/// Creates a workspace once its email is verified, in the owner's data
+/// region.
+///
+/// The token is used up either way.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `owner_id` - ID of the person who requested the workspace
+/// * `token` - The token emailed by [`request_workspace`]
+pub async fn create_workspace(
+    pool: &PgPool,
+    owner_id: Uuid,
+    token: &str,
+) -> Result<WorkspaceVerification, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let pending = sqlx::query_as::<_, (String, String, DateTime<Utc>)>(
+        r#"
+        DELETE FROM workspace_verifications
+        WHERE owner_id = $1 AND token_hash = sha256(convert_to($2, 'UTF8'))
+        RETURNING email, name, expires_at
+        "#,
+    )
+    .bind(owner_id)
+    .bind(token.trim())
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let outcome = match pending {
+        Some((email, name, expires_at)) if expires_at > Utc::now() => {
+            match insert_workspace(&mut tx, owner_id, &name, &email).await? {
+                Some(account) => WorkspaceVerification::Created(account),
+                None => WorkspaceVerification::EmailTaken,
+            }
+        }
+        _ => WorkspaceVerification::UnknownToken,
+    };
+    tx.commit().await?;
+
+    Ok(outcome)
+}
+
+/// Creates a workspace account and makes the person its owner.
+///
+/// # Returns
+///
+/// Returns the new account, or `None` if the email already belongs to
+/// another account.
+async fn insert_workspace(
+    tx: &mut Transaction<'_, Postgres>,
+    owner_id: Uuid,
+    name: &str,
+    email: &str,
+) -> Result<Option<AccountSummary>, anyhow::Error> {
+    let account_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO users (email, full_name, kind, data_region)
+        VALUES ($1, $2, $3, (SELECT data_region FROM users WHERE id = $4))
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(email)
+    .bind(name)
+    .bind(AccountKind::Workspace)
+    .bind(owner_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let Some(account_id) = account_id else {
+        return Ok(None);
+    };
+
+    sqlx::query("INSERT INTO account_members (account_id, member_id, role) VALUES ($1, $2, $3)")
+        .bind(account_id)
+        .bind(owner_id)
+        .bind(AccountRole::Owner)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(Some(AccountSummary {
+        id: account_id,
+        name: name.to_string(),
+        email: email.to_string(),
+        kind: AccountKind::Workspace,
+        role: AccountRole::Owner,
+    }))
+}
+
+/// Grants a person access to an account, or changes their role.
+///
+/// Only personal accounts (people) can become members.
+///
+/// # Returns
+///
+/// Returns the member's ID, or `None` if no person has that email.
+pub async fn add_member(
+    pool: &PgPool,
+    account_id: Uuid,
+    email: &str,
+    role: AccountRole,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let member_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO account_members (account_id, member_id, role)
+        SELECT $1, id, $3
+        FROM users
+        WHERE LOWER(email) = LOWER($2) AND kind = 'personal' AND id <> $1
+        ON CONFLICT (account_id, member_id) DO UPDATE SET role = EXCLUDED.role
+        RETURNING member_id
+        "#,
+    )
+    .bind(account_id)
+    .bind(email.trim())
+    .bind(role)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(member_id)
+}
+
+/// Revokes a person's access to an account.
+///
+/// # Returns
+///
+/// Returns `Ok(Some(true))` if the member was removed, `Ok(Some(false))`
+/// if they are the last owner (and were kept), or `Ok(None)` if they are
+/// not a member.
+pub async fn remove_member(
+    pool: &PgPool,
+    account_id: Uuid,
+    member_id: Uuid,
+) -> Result<Option<bool>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Lock the account's memberships so two owners can't remove each other
+    let roles = sqlx::query_as::<_, (Uuid, AccountRole)>(
+        "SELECT member_id, role FROM account_members WHERE account_id = $1 FOR UPDATE",
+    )
+    .bind(account_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let Some(role) = roles.iter().find(|(id, _)| *id == member_id).map(|(_, role)| *role) else {
+        return Ok(None);
+    };
+    // A personal account's own person is an implicit owner
+    let kind = sqlx::query_scalar::<_, AccountKind>("SELECT kind FROM users WHERE id = $1")
+        .bind(account_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    let owner_count = roles.iter().filter(|(_, role)| *role == AccountRole::Owner).count() as i64
+        + i64::from(kind == AccountKind::Personal);
+    if !can_remove_member(role, owner_count) {
+        return Ok(Some(false));
+    }
+
+    sqlx::query("DELETE FROM account_members WHERE account_id = $1 AND member_id = $2")
+        .bind(account_id)
+        .bind(member_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_workspace() {
+        let valid = CreateWorkspace {
+            name: "Design Studio".to_string(),
+            email: "hello@studio.test".to_string(),
+        };
+        assert!(validate_workspace(&valid).is_ok());
+
+        let unnamed = CreateWorkspace { name: "  ".to_string(), ..valid.clone() };
+        assert!(validate_workspace(&unnamed).is_err());
+
+        let bad_email = CreateWorkspace { email: "studio".to_string(), ..valid };
+        assert!(validate_workspace(&bad_email).is_err());
+    }
+
+    #[test]
+    fn test_validate_signup_keeps_a_configured_region() {
+        let regions = ["us", "eu"];
+        let request = CreateUser {
+            email: "ada@example.test".to_string(),
+            password: "correct horse".to_string(),
+            full_name: None,
+            data_region: Some(" EU ".to_string()),
+        };
+        assert_eq!(validate_signup(&request, "us", &regions), Ok(Some("eu".to_string())));
+
+        let home = CreateUser { data_region: Some("us".to_string()), ..request.clone() };
+        assert_eq!(validate_signup(&home, "us", &regions), Ok(None));
+        let unset = CreateUser { data_region: None, ..request.clone() };
+        assert_eq!(validate_signup(&unset, "us", &regions), Ok(None));
+
+        let unknown = CreateUser { data_region: Some("apac".to_string()), ..request.clone() };
+        assert_eq!(
+            validate_signup(&unknown, "us", &regions),
+            Err("data_region must be one of: us, eu".to_string())
+        );
+
+        let short = CreateUser { password: "secret".to_string(), ..request.clone() };
+        assert!(validate_signup(&short, "us", &regions).is_err());
+        let bad_email = CreateUser { email: "ada".to_string(), ..request };
+        assert!(validate_signup(&bad_email, "us", &regions).is_err());
+    }
+
+    #[test]
+    fn test_verification_email_carries_the_token() {
+        let token = generate_verification_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_verification_token());
+
+        let (subject, body) = verification_email("Design Studio", &token);
+        assert!(subject.contains("Design Studio"));
+        assert!(body.contains(&token));
+    }
+
+    #[test]
+    fn test_last_owner_cannot_be_removed() {
+        assert!(!can_remove_member(AccountRole::Owner, 1));
+        assert!(can_remove_member(AccountRole::Owner, 2));
+        assert!(can_remove_member(AccountRole::Member, 1));
+    }
+}
@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::admin::{
+    change_limit, device_summaries, diff_device_state, pending_conflicts, recent_changes,
+    sequence_gaps, validate_device_state, DeviceState, DeviceSummary, DiffReport, PendingConflict,
+    SequenceGap,
+};
+use crate::models::sync_change::SyncChange;
+use crate::sync::retention::pruned_before;
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+fn internal_error(user_id: Uuid, what: &str, e: anyhow::Error) -> (StatusCode, Json<Value>) {
+    error!("Failed to load {} of user {}: {}", what, user_id, e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to inspect sync state")
+}
+
+/// Sync devices endpoint handler.
+///
+/// Handles GET requests to `/admin/users/:user_id/sync/devices`: each
+/// device's changes still in the log, and the change-log retention horizon
+/// (devices that last pulled before it must resync in full).
+pub async fn devices_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let devices: Vec<DeviceSummary> = device_summaries(&pool, user_id)
+        .await
+        .map_err(|e| internal_error(user_id, "sync devices", e))?;
+    let horizon = pruned_before(&pool, user_id)
+        .await
+        .map_err(|e| internal_error(user_id, "retention horizon", e))?;
+
+    Ok(Json(json!({
+        "devices": devices,
+        "pruned_before": horizon,
+    })))
+}
+
+/// Query parameters for listing changes.
+#[derive(Debug, Deserialize)]
+pub struct ListChangesQuery {
+    /// Only list changes from this device
+    pub device_id: Option<String>,
+
+    /// Only list changes of this table
+    pub table: Option<String>,
+
+    /// Number of changes (default 100, at most 500)
+    pub limit: Option<i64>,
+}
+
+/// Recent sync changes endpoint handler.
+///
+/// Handles GET requests to `/admin/users/:user_id/sync/changes`, newest
+/// first, with the data each change carried.
+pub async fn changes_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<ListChangesQuery>,
+) -> Result<Json<Vec<SyncChange>>, (StatusCode, Json<Value>)> {
+    let changes = recent_changes(
+        &pool,
+        user_id,
+        query.device_id.as_deref(),
+        query.table.as_deref(),
+        change_limit(query.limit),
+    )
+    .await
+    .map_err(|e| internal_error(user_id, "sync changes", e))?;
+
+    Ok(Json(changes))
+}
+
+/// Pending conflicts endpoint handler.
+///
+/// Handles GET requests to `/admin/users/:user_id/sync/conflicts`.
+pub async fn conflicts_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<PendingConflict>>, (StatusCode, Json<Value>)> {
+    let conflicts = pending_conflicts(&pool, user_id)
+        .await
+        .map_err(|e| internal_error(user_id, "sync conflicts", e))?;
+
+    Ok(Json(conflicts))
+}
+
+/// Query parameters for finding sequence gaps.
+#[derive(Debug, Deserialize)]
+pub struct GapsQuery {
+    /// Only look at changes from this time on (default: the last 30 days)
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Sequence gaps endpoint handler.
+///
+/// Handles GET requests to `/admin/users/:user_id/sync/gaps`: changes
+/// committed after a later-timestamped change, which devices pulling in
+/// between skipped.
+pub async fn gaps_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<GapsQuery>,
+) -> Result<Json<Vec<SequenceGap>>, (StatusCode, Json<Value>)> {
+    let since = query.since.unwrap_or_else(|| Utc::now() - Duration::days(30));
+    let gaps = sequence_gaps(&pool, user_id, since)
+        .await
+        .map_err(|e| internal_error(user_id, "sequence gaps", e))?;
+
+    Ok(Json(gaps))
+}
+
+/// Device state diff endpoint handler.
+///
+/// Handles POST requests to `/admin/users/:user_id/sync/diff` with the
+/// records a device holds, `{ "device_id": "...", "records": { "invoices":
+/// [...] } }`, and reports how they differ from the server's.
+pub async fn diff_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(user_id): Path<Uuid>,
+    Json(state): Json<DeviceState>,
+) -> Result<Json<DiffReport>, (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_device_state(&state) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let report = diff_device_state(&pool, user_id, state)
+        .await
+        .map_err(|e| internal_error(user_id, "device diff", e))?;
+
+    Ok(Json(report))
+}
@@ -0,0 +1,686 @@
+//! Admin API for support staff.
+//!
+//! Inspects one user's sync state when they report missing or stale
+//! records: what each device pushed recently, conflicts the server resolved
+//! against a device that hasn't pushed the record since, changes committed
+//! out of order in the change log, and how a device's local records differ
+//! from the server's. Everything here is read-only, and every request for
+//! a user's data is logged first ([`admin_audit_middleware`]).
+//!
+//! Pulls resume from the previous pull's timestamp, so a change whose
+//! timestamp is older than a change logged before it (a transaction that
+//! committed late) is skipped by any device that pulled in between. Such
+//! changes are reported as sequence gaps.
+
+pub mod handlers;
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use axum::extract::Path;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{FromRow, PgPool};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::models::sync_change::{SyncChange, SyncOperation};
+use crate::sync::conflict::current_record;
+use crate::sync::evolution;
+
+/// Record tables devices sync (rows keyed by `id`, soft-deleted); the
+/// user_settings record is synced too but has neither.
+pub const SYNCED_TABLES: &[&str] = &["invoices", "estimates", "clients", "projects", "time_entries"];
+
+/// Optional header naming the support staff member making an admin request.
+pub const ADMIN_ACTOR_HEADER: &str = "x-admin-actor";
+
+/// Longest actor name logged.
+const MAX_ACTOR_LENGTH: usize = 255;
+
+/// Default number of changes listed.
+pub const DEFAULT_CHANGE_LIMIT: i64 = 100;
+
+/// Most changes listed at once.
+pub const MAX_CHANGE_LIMIT: i64 = 500;
+
+/// Most device records compared in one diff.
+pub const MAX_DIFF_RECORDS: usize = 5000;
+
+/// Fields of device records that are not stored on the server
+/// (WatermelonDB's local bookkeeping).
+const DEVICE_ONLY_FIELDS: &[&str] = &["id", "_status", "_changed"];
+
+/// A device's activity in the change log.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DeviceSummary {
+    /// Device identifier (`server` for server-side changes)
+    pub device_id: String,
+
+    /// Changes logged from the device, counting those compacted into
+    /// later ones
+    pub changes: i64,
+
+    /// Changes that conflicted with the server's version
+    pub conflicts: i64,
+
+    /// Oldest change still in the log
+    pub first_change_at: DateTime<Utc>,
+
+    /// Most recent change
+    pub last_change_at: DateTime<Utc>,
+
+    /// Sequence number of the most recent change
+    pub last_sequence_number: Option<i64>,
+}
+
+/// A conflicted change whose device hasn't pushed the record since.
+///
+/// The device still shows its own version until it pulls the server's.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PendingConflict {
+    /// The conflicted change
+    pub change_id: Uuid,
+    pub table_name: String,
+    pub record_id: Uuid,
+    pub device_id: String,
+    pub change_timestamp: DateTime<Utc>,
+
+    /// How the server resolved it (`{ "strategy": "ServerWins" }`)
+    pub conflict_resolution: Option<Value>,
+}
+
+/// A change-log entry, in the order it was logged.
+#[derive(Debug, Clone, FromRow)]
+pub struct LogEntry {
+    pub id: Uuid,
+    pub sequence_number: i64,
+    pub table_name: String,
+    pub record_id: Uuid,
+    pub device_id: String,
+    pub change_timestamp: DateTime<Utc>,
+}
+
+/// A change committed after a later-timestamped change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequenceGap {
+    pub change_id: Uuid,
+    pub sequence_number: i64,
+    pub table_name: String,
+    pub record_id: Uuid,
+    pub device_id: String,
+    pub change_timestamp: DateTime<Utc>,
+
+    /// Latest timestamp logged before the change; devices that pulled
+    /// between the two may have skipped it
+    pub logged_after: DateTime<Utc>,
+}
+
+/// Records a device claims to hold, for [`diff_device_state`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceState {
+    /// Device the records come from
+    pub device_id: Option<String>,
+
+    /// Records by table, as the device stores them (each with its `id`)
+    pub records: HashMap<String, Vec<Value>>,
+}
+
+/// A field whose device value differs from the server's.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub device: Value,
+
+    /// `null` if the server does not have the field
+    pub server: Value,
+}
+
+/// Who last changed a record on the server.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LastChange {
+    pub device_id: String,
+    pub operation: SyncOperation,
+    pub change_timestamp: DateTime<Utc>,
+}
+
+/// A record the device holds in a different version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordDiff {
+    pub id: Uuid,
+    pub fields: Vec<FieldDiff>,
+    pub last_change: Option<LastChange>,
+}
+
+/// How a device's records of one table compare to the server's.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableDiff {
+    pub table: String,
+
+    /// Records identical on both sides
+    pub matching: usize,
+
+    /// Records the device holds in a different version
+    pub differing: Vec<RecordDiff>,
+
+    /// Records the server never received
+    pub missing_on_server: Vec<Uuid>,
+
+    /// Records the device holds that were deleted on the server
+    pub deleted_on_server: Vec<Uuid>,
+
+    /// Live server records the device doesn't hold
+    pub missing_on_device: Vec<Uuid>,
+}
+
+/// Result of [`diff_device_state`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub device_id: Option<String>,
+    pub tables: Vec<TableDiff>,
+}
+
+/// Page size for change listings: the default, capped at the maximum.
+pub fn change_limit(requested: Option<i64>) -> i64 {
+    requested.unwrap_or(DEFAULT_CHANGE_LIMIT).clamp(1, MAX_CHANGE_LIMIT)
+}
+
+/// Validates a device state before it is compared.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the state is valid, or a message describing the
+/// first problem.
+pub fn validate_device_state(state: &DeviceState) -> Result<(), String> {
+    let mut total = 0;
+    for (table, records) in &state.records {
+        if !SYNCED_TABLES.contains(&table.as_str()) {
+            return Err(format!("{} is not a synced table", table));
+        }
+        if records.iter().any(|record| record_id(record).is_none()) {
+            return Err(format!("every {} record needs an id", table));
+        }
+        total += records.len();
+    }
+    if total > MAX_DIFF_RECORDS {
+        return Err(format!("at most {} records can be compared at once", MAX_DIFF_RECORDS));
+    }
+    Ok(())
+}
+
+/// ID of a device record.
+fn record_id(record: &Value) -> Option<Uuid> {
+    record.get("id")?.as_str()?.parse().ok()
+}
+
+/// Summarizes each device's changes still in the user's change log.
+///
+/// # Returns
+///
+/// Returns one summary per device, most recently active first.
+pub async fn device_summaries(pool: &PgPool, user_id: Uuid) -> Result<Vec<DeviceSummary>, anyhow::Error> {
+    let summaries = sqlx::query_as::<_, DeviceSummary>(
+        r#"
+        SELECT
+            device_id,
+            SUM(1 + compacted_changes) AS changes,
+            COUNT(*) FILTER (WHERE is_conflict) AS conflicts,
+            MIN(change_timestamp) AS first_change_at,
+            MAX(change_timestamp) AS last_change_at,
+            MAX(sequence_number) AS last_sequence_number
+        FROM sync_changes
+        WHERE user_id = $1
+        GROUP BY device_id
+        ORDER BY MAX(change_timestamp) DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(summaries)
+}
+
+/// Lists a user's most recent changes, newest first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user being inspected
+/// * `device_id` - Only list changes from this device
+/// * `table` - Only list changes of this table
+/// * `limit` - Number of changes (see [`change_limit`])
+pub async fn recent_changes(
+    pool: &PgPool,
+    user_id: Uuid,
+    device_id: Option<&str>,
+    table: Option<&str>,
+    limit: i64,
+) -> Result<Vec<SyncChange>, anyhow::Error> {
+    let changes = sqlx::query_as::<_, SyncChange>(
+        r#"
+        SELECT
+            id, user_id, table_name, record_id, operation,
+            old_data, new_data, device_id, change_timestamp,
+            vector_clock, is_applied, is_conflict, conflict_resolution,
+            sequence_number, created_at
+        FROM sync_changes
+        WHERE user_id = $1
+            AND ($2::text IS NULL OR device_id = $2)
+            AND ($3::text IS NULL OR table_name = $3)
+        ORDER BY sequence_number DESC
+        LIMIT $4
+        "#,
+    )
+    .bind(user_id)
+    .bind(device_id)
+    .bind(table)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(changes)
+}
+
+/// Lists conflicted changes whose device hasn't pushed the record since.
+///
+/// # Returns
+///
+/// Returns the conflicts, newest first.
+pub async fn pending_conflicts(pool: &PgPool, user_id: Uuid) -> Result<Vec<PendingConflict>, anyhow::Error> {
+    let conflicts = sqlx::query_as::<_, PendingConflict>(
+        r#"
+        SELECT
+            c.id AS change_id, c.table_name, c.record_id, c.device_id,
+            c.change_timestamp, c.conflict_resolution
+        FROM sync_changes c
+        WHERE c.user_id = $1
+            AND c.is_conflict = true
+            AND NOT EXISTS (
+                SELECT 1 FROM sync_changes later
+                WHERE later.user_id = c.user_id
+                    AND later.table_name = c.table_name
+                    AND later.record_id = c.record_id
+                    AND later.device_id = c.device_id
+                    AND later.sequence_number > c.sequence_number
+            )
+        ORDER BY c.sequence_number DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(conflicts)
+}
+
+/// Finds changes logged out of timestamp order since a point in time.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user being inspected
+/// * `since` - Only look at changes from this time on
+pub async fn sequence_gaps(
+    pool: &PgPool,
+    user_id: Uuid,
+    since: DateTime<Utc>,
+) -> Result<Vec<SequenceGap>, anyhow::Error> {
+    let entries = sqlx::query_as::<_, LogEntry>(
+        r#"
+        SELECT id, sequence_number, table_name, record_id, device_id, change_timestamp
+        FROM sync_changes
+        WHERE user_id = $1 AND change_timestamp >= $2 AND is_applied = true
+        ORDER BY sequence_number ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(find_sequence_gaps(&entries))
+}
+
+/// Finds entries whose timestamp is older than an entry logged before them.
+///
+/// # Arguments
+///
+/// * `entries` - Change-log entries ordered by sequence number
+pub fn find_sequence_gaps(entries: &[LogEntry]) -> Vec<SequenceGap> {
+    let mut latest: Option<DateTime<Utc>> = None;
+    let mut gaps = Vec::new();
+
+    for entry in entries {
+        match latest {
+            Some(logged_after) if entry.change_timestamp < logged_after => {
+                gaps.push(SequenceGap {
+                    change_id: entry.id,
+                    sequence_number: entry.sequence_number,
+                    table_name: entry.table_name.clone(),
+                    record_id: entry.record_id,
+                    device_id: entry.device_id.clone(),
+                    change_timestamp: entry.change_timestamp,
+                    logged_after,
+                });
+            }
+            _ => latest = Some(entry.change_timestamp),
+        }
+    }
+
+    gaps
+}
+
+/// Compares the records a device claims to hold with the server's.
+///
+/// Each claimed table is treated as the device's full copy of it, so live
+/// server records the device doesn't list are reported as missing on the
+/// device. Only the fields the device sends are compared.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user being inspected
+/// * `state` - The device's records (see [`validate_device_state`])
+///
+/// # Errors
+///
+/// Returns an error if a query fails.
+pub async fn diff_device_state(
+    pool: &PgPool,
+    user_id: Uuid,
+    state: DeviceState,
+) -> Result<DiffReport, anyhow::Error> {
+    let mut tables = Vec::new();
+    let mut conn = pool.acquire().await?;
+
+    let mut claimed: Vec<_> = state.records.into_iter().collect();
+    claimed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (table, records) in claimed {
+        let mut diff = TableDiff {
+            table: table.clone(),
+            ..TableDiff::default()
+        };
+        let mut held = HashSet::new();
+
+        for device in &records {
+            let Some(id) = record_id(device) else {
+                continue;
+            };
+            held.insert(id);
+
+            let Some(mut server) = current_record(&mut conn, user_id, &table, id).await? else {
+                diff.missing_on_server.push(id);
+                continue;
+            };
+            if server.get("is_deleted").and_then(Value::as_bool) == Some(true) {
+                diff.deleted_on_server.push(id);
+                continue;
+            }
+
+            // Compare under the names the device may still use
+            evolution::dual_write_record(&table, &mut server);
+            let fields = diff_fields(device, &server);
+            if fields.is_empty() {
+                diff.matching += 1;
+            } else {
+                let last_change = last_change(pool, user_id, &table, id).await?;
+                diff.differing.push(RecordDiff { id, fields, last_change });
+            }
+        }
+
+        // The table name was checked against SYNCED_TABLES
+        let live = sqlx::query_scalar::<_, Uuid>(&format!(
+            "SELECT id FROM {} WHERE user_id = $1 AND is_deleted = false ORDER BY id",
+            table
+        ))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+        diff.missing_on_device = live.into_iter().filter(|id| !held.contains(id)).collect();
+
+        tables.push(diff);
+    }
+
+    Ok(DiffReport {
+        device_id: state.device_id,
+        tables,
+    })
+}
+
+/// Loads the latest logged change of a record.
+async fn last_change(
+    pool: &PgPool,
+    user_id: Uuid,
+    table: &str,
+    record_id: Uuid,
+) -> Result<Option<LastChange>, anyhow::Error> {
+    let change = sqlx::query_as::<_, LastChange>(
+        r#"
+        SELECT device_id, operation, change_timestamp
+        FROM sync_changes
+        WHERE user_id = $1 AND table_name = $2 AND record_id = $3
+        ORDER BY sequence_number DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(table)
+    .bind(record_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(change)
+}
+
+/// Lists the fields of a device record that differ from the server's.
+///
+/// Fields only the server has are ignored, as are WatermelonDB's own
+/// bookkeeping fields.
+pub fn diff_fields(device: &Value, server: &Value) -> Vec<FieldDiff> {
+    let Some(fields) = device.as_object() else {
+        return Vec::new();
+    };
+
+    let mut diffs: Vec<FieldDiff> = fields
+        .iter()
+        .filter(|(field, _)| !DEVICE_ONLY_FIELDS.contains(&field.as_str()))
+        .filter_map(|(field, value)| {
+            let server_value = server.get(field).cloned().unwrap_or(Value::Null);
+            (!values_match(value, &server_value)).then(|| FieldDiff {
+                field: field.clone(),
+                device: value.clone(),
+                server: server_value,
+            })
+        })
+        .collect();
+    diffs.sort_by(|a, b| a.field.cmp(&b.field));
+
+    diffs
+}
+
+/// Whether two field values are the same, allowing for decimals sent as
+/// numbers or strings and timestamps in different offsets.
+fn values_match(device: &Value, server: &Value) -> bool {
+    if device == server {
+        return true;
+    }
+    if let (Some(a), Some(b)) = (as_decimal(device), as_decimal(server)) {
+        return a == b;
+    }
+    if let (Some(a), Some(b)) = (as_timestamp(device), as_timestamp(server)) {
+        return a == b;
+    }
+    false
+}
+
+/// Reads a decimal sent as a number or a string.
+fn as_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Number(n) => Decimal::from_str(&n.to_string()).ok(),
+        Value::String(s) => Decimal::from_str(s).ok(),
+        _ => None,
+    }
+}
+
+/// Reads an RFC 3339 timestamp.
+fn as_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.as_str()?)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Logs an admin request for a user's data.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user whose data is requested
+/// * `actor` - Who made the request, if they said
+/// * `method` - HTTP method of the request
+/// * `path` - Path and query of the request
+pub async fn record_admin_access(
+    pool: &PgPool,
+    user_id: Uuid,
+    actor: Option<&str>,
+    method: &str,
+    path: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query("INSERT INTO admin_access_log (user_id, actor, method, path) VALUES ($1, $2, $3, $4)")
+        .bind(user_id)
+        .bind(actor.map(|actor| actor.chars().take(MAX_ACTOR_LENGTH).collect::<String>()))
+        .bind(method)
+        .bind(path)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Middleware logging admin requests for a user's data
+/// (`/admin/users/:user_id/...`) before they run.
+///
+/// Must run after `db::residency::admin_residency_middleware`, so the entry
+/// is written to the user's region. A request that can't be logged is
+/// refused with `500`.
+pub async fn admin_audit_middleware<B>(
+    Path(params): Path<HashMap<String, String>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let user_id = params
+        .get("user_id")
+        .and_then(|id| id.parse::<Uuid>().ok())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let pool = req
+        .extensions()
+        .get::<PgPool>()
+        .cloned()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let actor = req
+        .headers()
+        .get(ADMIN_ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|actor| !actor.is_empty());
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or_else(|| req.uri().path(), |path| path.as_str());
+
+    record_admin_access(&pool, user_id, actor, req.method().as_str(), path)
+        .await
+        .map_err(|e| {
+            error!("Failed to log admin access to user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use serde_json::json;
+
+    fn entry(sequence_number: i64, change_timestamp: DateTime<Utc>) -> LogEntry {
+        LogEntry {
+            id: Uuid::new_v4(),
+            sequence_number,
+            table_name: "invoices".to_string(),
+            record_id: Uuid::new_v4(),
+            device_id: "phone".to_string(),
+            change_timestamp,
+        }
+    }
+
+    #[test]
+    fn test_find_sequence_gaps() {
+        let t0 = Utc.with_ymd_and_hms(2024, 3, 5, 12, 0, 0).unwrap();
+        let entries = vec![
+            entry(1, t0),
+            entry(2, t0 + Duration::seconds(10)),
+            // Committed late: a pull at t0 + 10s resumed after it
+            entry(3, t0 + Duration::seconds(5)),
+            entry(4, t0 + Duration::seconds(10)),
+            entry(5, t0 + Duration::seconds(20)),
+        ];
+
+        let gaps = find_sequence_gaps(&entries);
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].sequence_number, 3);
+        assert_eq!(gaps[0].logged_after, t0 + Duration::seconds(10));
+
+        assert!(find_sequence_gaps(&entries[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_diff_fields() {
+        let server = json!({
+            "id": "a",
+            "amount": "120.50",
+            "status": "sent",
+            "due_date": "2024-03-05",
+            "last_modified": "2024-03-05T12:00:00Z",
+            "version_vector": null,
+        });
+
+        // Same values in other notations, and bookkeeping fields
+        let device = json!({
+            "id": "a",
+            "_status": "synced",
+            "_changed": "",
+            "amount": 120.5,
+            "last_modified": "2024-03-05T13:00:00+01:00",
+        });
+        assert!(diff_fields(&device, &server).is_empty());
+
+        let device = json!({ "amount": "99.00", "status": "sent", "notes": "x" });
+        assert_eq!(
+            diff_fields(&device, &server),
+            vec![
+                FieldDiff { field: "amount".to_string(), device: json!("99.00"), server: json!("120.50") },
+                FieldDiff { field: "notes".to_string(), device: json!("x"), server: Value::Null },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_device_state() {
+        let state = |table: &str, record: Value| DeviceState {
+            device_id: None,
+            records: HashMap::from([(table.to_string(), vec![record])]),
+        };
+
+        assert!(validate_device_state(&state("invoices", json!({ "id": Uuid::new_v4() }))).is_ok());
+        assert!(validate_device_state(&state("users", json!({ "id": Uuid::new_v4() }))).is_err());
+        assert!(validate_device_state(&state("invoices", json!({ "amount": "1" }))).is_err());
+    }
+}
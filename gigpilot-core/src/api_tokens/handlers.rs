@@ -0,0 +1,111 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::api_tokens::{create_token, list_tokens, revoke_token, validate_create_token};
+use crate::auth::{CurrentApiToken, CurrentIdentity, CurrentUser};
+use crate::db::PoolRouter;
+use crate::models::api_token::{ApiToken, CreateApiToken};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Fails with `403` for requests made with a personal access token, so a
+/// leaked token can't be used to mint or keep others.
+fn require_login(api_token: &Option<Extension<CurrentApiToken>>) -> Result<(), (StatusCode, Json<Value>)> {
+    match api_token {
+        Some(_) => Err(error_response(
+            StatusCode::FORBIDDEN,
+            "tokens can only be managed after logging in",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Create API token endpoint handler.
+///
+/// Handles POST requests to `/api/tokens`. Returns the stored token and,
+/// in `token`, the token itself; it is not shown again.
+pub async fn create_token_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    api_token: Option<Extension<CurrentApiToken>>,
+    Json(request): Json<CreateApiToken>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let pool = router.home().clone();
+
+    require_login(&api_token)?;
+    if let Err(message) = validate_create_token(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let (stored, token) = create_token(&pool, user_id, person_id, request).await.map_err(|e| {
+        error!("Failed to create API token for {}: {}", person_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create token")
+    })?;
+    info!(
+        "API token {} ({}) created by {} for account {}",
+        stored.id, stored.token_prefix, person_id, user_id
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "token": token, "api_token": stored })),
+    ))
+}
+
+/// List API tokens endpoint handler.
+///
+/// Handles GET requests to `/api/tokens`: the tokens the person created in
+/// the current account, with when each was last used.
+pub async fn list_tokens_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    api_token: Option<Extension<CurrentApiToken>>,
+) -> Result<Json<Vec<ApiToken>>, (StatusCode, Json<Value>)> {
+    let pool = router.home().clone();
+
+    require_login(&api_token)?;
+
+    let tokens = list_tokens(&pool, user_id, person_id).await.map_err(|e| {
+        error!("Failed to list API tokens for {}: {}", person_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to list tokens")
+    })?;
+
+    Ok(Json(tokens))
+}
+
+/// Revoke API token endpoint handler.
+///
+/// Handles DELETE requests to `/api/tokens/:id`.
+pub async fn revoke_token_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Extension(CurrentIdentity(person_id)): Extension<CurrentIdentity>,
+    api_token: Option<Extension<CurrentApiToken>>,
+    Path(token_id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let pool = router.home().clone();
+
+    require_login(&api_token)?;
+
+    let revoked = revoke_token(&pool, user_id, person_id, token_id).await.map_err(|e| {
+        error!("Failed to revoke API token {}: {}", token_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to revoke token")
+    })?;
+
+    if revoked {
+        info!("API token {} revoked by {}", token_id, person_id);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(error_response(StatusCode::NOT_FOUND, "token not found"))
+    }
+}
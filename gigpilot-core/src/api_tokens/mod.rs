@@ -0,0 +1,241 @@
+//! Personal access tokens.
+//!
+//! Users create named, long-lived tokens for scripts and spreadsheet
+//! integrations and send them as `Authorization: Bearer <token>` like a
+//! login JWT (see `auth::jwt_middleware`). A token acts as the account it
+//! was created in, for the person who created it, within its scope. Only a
+//! SHA-256 hash is stored, so a token is shown once and can't be recovered;
+//! tokens expire and can be revoked at any time. Tokens are kept in the
+//! home database cluster, so they are checked before a request is routed
+//! to its account's region.
+
+pub mod handlers;
+
+use axum::http::Method;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::api_token::{ApiToken, CreateApiToken, TokenScope};
+
+/// Start of every personal access token, telling them apart from JWTs.
+pub const TOKEN_PREFIX: &str = "gp_pat_";
+
+/// Characters after [`TOKEN_PREFIX`] kept to tell tokens apart.
+const DISPLAYED_CHARS: usize = 8;
+
+/// Lifetime of a token when the request doesn't say.
+pub const DEFAULT_TOKEN_DAYS: i64 = 90;
+
+/// Longest lifetime of a token.
+pub const MAX_TOKEN_DAYS: i64 = 365;
+
+/// Longest token name accepted.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Validates a token creation request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create_token(request: &CreateApiToken) -> Result<(), String> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("name must be at most {} characters", MAX_NAME_LENGTH));
+    }
+    if request
+        .expires_in_days
+        .is_some_and(|days| !(1..=MAX_TOKEN_DAYS).contains(&days))
+    {
+        return Err(format!("expires_in_days must be between 1 and {}", MAX_TOKEN_DAYS));
+    }
+    Ok(())
+}
+
+/// Whether a bearer token is a personal access token rather than a JWT.
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Whether a token of `scope` may make a request with `method`.
+///
+/// Read-only tokens are limited to `GET`, `HEAD` and `OPTIONS`.
+pub fn scope_allows(scope: TokenScope, method: &Method) -> bool {
+    match scope {
+        TokenScope::Read => matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS),
+        TokenScope::Write => true,
+    }
+}
+
+/// Generates a new token: the prefix and 64 random hex characters.
+fn generate_token() -> String {
+    format!("{}{}{}", TOKEN_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// The start of a token shown in listings.
+fn displayed_prefix(token: &str) -> &str {
+    &token[..TOKEN_PREFIX.len() + DISPLAYED_CHARS]
+}
+
+/// Creates a personal access token.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the account the token acts as
+/// * `created_by` - ID of the person creating it
+/// * `request` - Name, scope and lifetime (see [`validate_create_token`])
+///
+/// # Returns
+///
+/// Returns the stored token and the token string. The string is not
+/// stored and can't be shown again.
+pub async fn create_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    created_by: Uuid,
+    request: CreateApiToken,
+) -> Result<(ApiToken, String), anyhow::Error> {
+    let days = request.expires_in_days.unwrap_or(DEFAULT_TOKEN_DAYS);
+    let token = generate_token();
+
+    let stored = sqlx::query_as::<_, ApiToken>(
+        r#"
+        INSERT INTO api_tokens (user_id, created_by, name, token_prefix, token_hash, scope, expires_at)
+        VALUES ($1, $2, $3, $4, sha256(convert_to($5, 'UTF8')), $6, $7)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(created_by)
+    .bind(request.name.trim())
+    .bind(displayed_prefix(&token))
+    .bind(&token)
+    .bind(request.scope.unwrap_or_default())
+    .bind(Utc::now() + Duration::days(days))
+    .fetch_one(pool)
+    .await?;
+
+    Ok((stored, token))
+}
+
+/// Lists the tokens a person created in an account, newest first
+/// (revoked and expired ones included).
+pub async fn list_tokens(pool: &PgPool, user_id: Uuid, created_by: Uuid) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let tokens = sqlx::query_as::<_, ApiToken>(
+        r#"
+        SELECT * FROM api_tokens
+        WHERE user_id = $1 AND created_by = $2
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(created_by)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tokens)
+}
+
+/// Revokes a token; requests made with it are rejected from now on.
+///
+/// # Returns
+///
+/// Returns `true` if a token was revoked, `false` if none matched or it
+/// was already revoked.
+pub async fn revoke_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    created_by: Uuid,
+    token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE api_tokens SET revoked_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND created_by = $3 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(token_id)
+    .bind(user_id)
+    .bind(created_by)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Loads the usable token a request was made with and marks it as used.
+///
+/// # Returns
+///
+/// Returns `None` if no token matches, or it was revoked or has expired.
+pub async fn find_active_token(pool: &PgPool, token: &str) -> Result<Option<ApiToken>, anyhow::Error> {
+    let token = sqlx::query_as::<_, ApiToken>(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = NOW()
+        WHERE token_hash = sha256(convert_to($1, 'UTF8'))
+            AND revoked_at IS NULL
+            AND expires_at > NOW()
+        RETURNING *
+        "#,
+    )
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &str, expires_in_days: Option<i64>) -> CreateApiToken {
+        CreateApiToken {
+            name: name.to_string(),
+            scope: None,
+            expires_in_days,
+        }
+    }
+
+    #[test]
+    fn test_validate_create_token() {
+        assert!(validate_create_token(&request("Monthly export", None)).is_ok());
+        assert!(validate_create_token(&request("Monthly export", Some(MAX_TOKEN_DAYS))).is_ok());
+
+        assert!(validate_create_token(&request("  ", None)).is_err());
+        assert!(validate_create_token(&request(&"x".repeat(101), None)).is_err());
+        assert!(validate_create_token(&request("Monthly export", Some(0))).is_err());
+        assert!(validate_create_token(&request("Monthly export", Some(MAX_TOKEN_DAYS + 1))).is_err());
+    }
+
+    #[test]
+    fn test_generated_tokens_are_recognized() {
+        let token = generate_token();
+        assert!(is_api_token(&token));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 64);
+        assert_ne!(token, generate_token());
+
+        assert_eq!(displayed_prefix(&token).len(), TOKEN_PREFIX.len() + DISPLAYED_CHARS);
+        assert!(token.starts_with(displayed_prefix(&token)));
+
+        // Login JWTs start with their base64 header
+        assert!(!is_api_token("eyJhbGciOiJIUzI1NiJ9.e30.sig"));
+    }
+
+    #[test]
+    fn test_read_tokens_only_allow_safe_methods() {
+        assert!(scope_allows(TokenScope::Read, &Method::GET));
+        assert!(scope_allows(TokenScope::Read, &Method::HEAD));
+        assert!(!scope_allows(TokenScope::Read, &Method::POST));
+        assert!(!scope_allows(TokenScope::Read, &Method::DELETE));
+
+        assert!(scope_allows(TokenScope::Write, &Method::POST));
+        assert!(scope_allows(TokenScope::Write, &Method::PUT));
+    }
+}
@@ -0,0 +1,197 @@
+use axum::{
+    extract::{Extension, Multipart, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::attachments::{
+    delete_attachment, find_attachment, find_public_attachment, list_attachments, sanitize_filename,
+    store_attachment, validate_attachment, MAX_ATTACHMENTS_PER_INVOICE,
+};
+use crate::auth::CurrentUser;
+use crate::invoices::find_invoice;
+use crate::models::attachment::Attachment;
+use crate::storage::DynBlobStore;
+
+/// Name of the multipart field carrying the uploaded file.
+const FILE_FIELD: &str = "file";
+
+/// Attachment upload endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/:id/attachments`. The body is
+/// `multipart/form-data` with the file in a `file` field; its file name and
+/// content type are taken from the part headers.
+pub async fn upload_attachment_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<Attachment>), (StatusCode, Json<Value>)> {
+    let invoice = find_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to load invoice" })),
+            )
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "Invoice not found" }))))?;
+
+    let bad_request = |e: axum::extract::multipart::MultipartError| {
+        (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))
+    };
+
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        if field.name() != Some(FILE_FIELD) {
+            continue;
+        }
+        let filename = sanitize_filename(field.file_name().unwrap_or_default());
+        let content_type = field
+            .content_type()
+            .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase())
+            .unwrap_or_default();
+        let data = field.bytes().await.map_err(bad_request)?;
+        upload = Some((filename, content_type, data));
+        break;
+    }
+
+    let (filename, content_type, data) = upload.ok_or_else(|| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": "missing file field" })),
+        )
+    })?;
+
+    validate_attachment(&content_type, data.len())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": e }))))?;
+
+    let attachment = store_attachment(
+        &pool,
+        store.as_ref(),
+        user_id,
+        invoice.id,
+        &filename,
+        &content_type,
+        &data,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to store attachment for invoice {}: {}", invoice_id, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to store attachment" })),
+        )
+    })?
+    .ok_or_else(|| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({
+                "error": format!("invoice already has {} attachments", MAX_ATTACHMENTS_PER_INVOICE)
+            })),
+        )
+    })?;
+
+    Ok((StatusCode::CREATED, Json(attachment)))
+}
+
+/// List attachments endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/attachments`.
+pub async fn list_attachments_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<Vec<Attachment>>, StatusCode> {
+    let attachments = list_attachments(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to list attachments of invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(attachments))
+}
+
+/// Attachment download endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/attachments/:attachment_id`.
+pub async fn download_attachment_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path((invoice_id, attachment_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let attachment = find_attachment(&pool, user_id, invoice_id, attachment_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load attachment {}: {}", attachment_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    serve_attachment(&store, attachment).await
+}
+
+/// Delete attachment endpoint handler.
+///
+/// Handles DELETE requests to `/api/invoices/:id/attachments/:attachment_id`.
+pub async fn delete_attachment_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path((invoice_id, attachment_id)): Path<(Uuid, Uuid)>,
+) -> StatusCode {
+    match delete_attachment(&pool, store.as_ref(), user_id, invoice_id, attachment_id).await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to delete attachment {}: {}", attachment_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Public attachment download handler.
+///
+/// Handles GET requests to `/pay/:id/attachments/:attachment_id` without
+/// authentication. These are the links included in chase emails.
+pub async fn public_attachment_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Path((invoice_id, attachment_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let attachment = find_public_attachment(&pool, invoice_id, attachment_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load attachment {}: {}", attachment_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    serve_attachment(&store, attachment).await
+}
+
+/// Reads an attachment from storage as a download response.
+async fn serve_attachment(store: &DynBlobStore, attachment: Attachment) -> Result<impl IntoResponse, StatusCode> {
+    let data = store.get(&attachment.storage_key).await.map_err(|e| {
+        error!("Failed to read attachment {}: {}", attachment.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let disposition = format!("attachment; filename=\"{}\"", attachment.filename);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        data,
+    ))
+}
@@ -0,0 +1,339 @@
+//! Files attached to invoices.
+//!
+//! Users upload contracts, receipts or timesheets to an invoice. Metadata
+//! is kept in the `attachments` table and the contents in the
+//! [`BlobStore`] (local disk, S3 or memory). Chase emails link to each file through the public
+//! pay page, so clients can download them without logging in.
+
+pub mod handlers;
+
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::models::attachment::{Attachment, ATTACHMENT_COLUMNS};
+use crate::storage::BlobStore;
+
+/// Largest accepted attachment upload (20 MiB).
+pub const MAX_ATTACHMENT_BYTES: usize = 20 * 1024 * 1024;
+
+/// Most attachments kept on one invoice.
+pub const MAX_ATTACHMENTS_PER_INVOICE: i64 = 20;
+
+/// MIME types accepted for attachment uploads.
+pub const ATTACHMENT_CONTENT_TYPES: &[&str] = &[
+    "application/pdf",
+    "image/jpeg",
+    "image/png",
+    "image/heic",
+    "text/plain",
+    "text/csv",
+    "application/msword",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+];
+
+/// Validates an attachment upload before it is stored.
+///
+/// # Returns
+///
+/// Returns a user-facing message describing the first problem found.
+pub fn validate_attachment(content_type: &str, size: usize) -> Result<(), String> {
+    if size == 0 {
+        return Err("attachment file is empty".to_string());
+    }
+    if size > MAX_ATTACHMENT_BYTES {
+        return Err(format!("attachment file exceeds {} bytes", MAX_ATTACHMENT_BYTES));
+    }
+    if !ATTACHMENT_CONTENT_TYPES.contains(&content_type) {
+        return Err(format!("unsupported attachment type: {}", content_type));
+    }
+    Ok(())
+}
+
+/// Cleans an uploaded file name for storage and `Content-Disposition`.
+///
+/// Drops any directory part, control characters and quotes, and caps the
+/// length at 255 characters. Falls back to "attachment" if nothing is left.
+pub fn sanitize_filename(filename: &str) -> String {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect();
+    let cleaned = cleaned.trim();
+
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        "attachment".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Storage key for an attachment's contents.
+///
+/// Built only from IDs, so user-supplied file names never reach the
+/// storage backend.
+pub fn storage_key(user_id: Uuid, invoice_id: Uuid, attachment_id: Uuid) -> String {
+    format!("{}/{}/{}", user_id, invoice_id, attachment_id)
+}
+
+/// Public download link for an attachment, served next to the pay page.
+pub fn attachment_link(base_url: &str, attachment: &Attachment) -> String {
+    format!(
+        "{}/pay/{}/attachments/{}",
+        base_url.trim_end_matches('/'),
+        attachment.invoice_id,
+        attachment.id
+    )
+}
+
+/// Lists attachment download links for chase emails.
+///
+/// # Returns
+///
+/// Returns the text block, or `None` if there are no attachments.
+pub fn attachment_links_text(base_url: &str, attachments: &[Attachment]) -> Option<String> {
+    if attachments.is_empty() {
+        return None;
+    }
+
+    let lines = attachments
+        .iter()
+        .map(|a| format!("- {}: {}", a.filename, attachment_link(base_url, a)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(format!("Supporting documents:\n{}", lines))
+}
+
+/// Stores an uploaded file and attaches it to an invoice.
+///
+/// The row is inserted and the file written in one transaction, so a
+/// failed upload to the storage backend leaves no dangling row.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `store` - Storage backend for the contents
+/// * `user_id` - ID of the user owning the invoice
+/// * `invoice_id` - Invoice to attach the file to (ownership already checked)
+/// * `filename` - Sanitised file name
+/// * `content_type` - MIME type of the file
+/// * `data` - Raw file contents
+///
+/// # Returns
+///
+/// Returns the stored `Attachment`, or `None` if the invoice already has
+/// [`MAX_ATTACHMENTS_PER_INVOICE`] attachments.
+pub async fn store_attachment(
+    pool: &PgPool,
+    store: &dyn BlobStore,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    filename: &str,
+    content_type: &str,
+    data: &[u8],
+) -> Result<Option<Attachment>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Lock the invoice so concurrent uploads can't exceed the limit
+    sqlx::query("SELECT id FROM invoices WHERE id = $1 FOR UPDATE")
+        .bind(invoice_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM attachments WHERE invoice_id = $1")
+        .bind(invoice_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if count >= MAX_ATTACHMENTS_PER_INVOICE {
+        return Ok(None);
+    }
+
+    let attachment_id = Uuid::new_v4();
+    let key = storage_key(user_id, invoice_id, attachment_id);
+
+    let attachment = sqlx::query_as::<_, Attachment>(&format!(
+        r#"
+        INSERT INTO attachments (id, user_id, invoice_id, filename, content_type, size_bytes, storage_key)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING {}
+        "#,
+        ATTACHMENT_COLUMNS
+    ))
+    .bind(attachment_id)
+    .bind(user_id)
+    .bind(invoice_id)
+    .bind(filename)
+    .bind(content_type)
+    .bind(data.len() as i64)
+    .bind(&key)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    store.put(&key, content_type, data).await?;
+    tx.commit().await?;
+
+    Ok(Some(attachment))
+}
+
+/// Lists an invoice's attachments, oldest first.
+pub async fn list_attachments(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+) -> Result<Vec<Attachment>, anyhow::Error> {
+    let attachments = sqlx::query_as::<_, Attachment>(&format!(
+        "SELECT {} FROM attachments WHERE invoice_id = $1 AND user_id = $2 ORDER BY created_at",
+        ATTACHMENT_COLUMNS
+    ))
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(attachments)
+}
+
+/// Loads one attachment of an invoice owned by the given user.
+pub async fn find_attachment(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    attachment_id: Uuid,
+) -> Result<Option<Attachment>, anyhow::Error> {
+    let attachment = sqlx::query_as::<_, Attachment>(&format!(
+        "SELECT {} FROM attachments WHERE id = $1 AND invoice_id = $2 AND user_id = $3",
+        ATTACHMENT_COLUMNS
+    ))
+    .bind(attachment_id)
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(attachment)
+}
+
+/// Loads an attachment that may be downloaded from the public pay page.
+///
+/// Like the pay page itself, attachments of drafts, cancelled and deleted
+/// invoices are never served.
+pub async fn find_public_attachment(
+    pool: &PgPool,
+    invoice_id: Uuid,
+    attachment_id: Uuid,
+) -> Result<Option<Attachment>, anyhow::Error> {
+    let attachment = sqlx::query_as::<_, Attachment>(
+        r#"
+        SELECT
+            a.id, a.user_id, a.invoice_id, a.filename, a.content_type, a.size_bytes,
+            a.storage_key, a.created_at, a.updated_at
+        FROM attachments a
+        JOIN invoices i ON i.id = a.invoice_id
+        WHERE a.id = $1
+            AND a.invoice_id = $2
+            AND i.is_deleted = false
+            AND i.status NOT IN ('draft', 'cancelled')
+        "#,
+    )
+    .bind(attachment_id)
+    .bind(invoice_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(attachment)
+}
+
+/// Deletes an attachment and its stored contents.
+///
+/// The row is removed first; failing to remove the contents afterwards is
+/// only logged.
+///
+/// # Returns
+///
+/// Returns `true` if the attachment existed and was deleted.
+pub async fn delete_attachment(
+    pool: &PgPool,
+    store: &dyn BlobStore,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    attachment_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let key: Option<(String,)> = sqlx::query_as(
+        r#"
+        DELETE FROM attachments
+        WHERE id = $1 AND invoice_id = $2 AND user_id = $3
+        RETURNING storage_key
+        "#,
+    )
+    .bind(attachment_id)
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((key,)) = key else {
+        return Ok(false);
+    };
+
+    tx.commit().await?;
+
+    // An orphaned file is harmless; a row pointing at a missing file is not
+    if let Err(e) = store.delete(&key).await {
+        warn!("Failed to delete stored attachment {}: {}", attachment_id, e);
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("contract.pdf"), "contract.pdf");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("C:\\Users\\me\\Signed \"final\".pdf"), "Signed final.pdf");
+        assert_eq!(sanitize_filename("dir/"), "attachment");
+        assert_eq!(sanitize_filename(".."), "attachment");
+    }
+
+    #[test]
+    fn test_validate_attachment() {
+        assert!(validate_attachment("application/pdf", 1024).is_ok());
+        assert!(validate_attachment("application/pdf", 0).is_err());
+        assert!(validate_attachment("application/pdf", MAX_ATTACHMENT_BYTES + 1).is_err());
+        assert!(validate_attachment("application/x-msdownload", 1024).is_err());
+    }
+
+    #[test]
+    fn test_attachment_links_text() {
+        assert_eq!(attachment_links_text("https://app.test", &[]), None);
+
+        let now = Utc::now();
+        let attachment = Attachment {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            invoice_id: Uuid::new_v4(),
+            filename: "contract.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            size_bytes: 1024,
+            storage_key: String::new(),
+            created_at: now,
+            updated_at: now,
+        };
+        let text = attachment_links_text("https://app.test/", &[attachment.clone()]).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "Supporting documents:\n- contract.pdf: https://app.test/pay/{}/attachments/{}",
+                attachment.invoice_id, attachment.id
+            )
+        );
+    }
+}
@@ -1,49 +1,385 @@
 use axum::http::{Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::Response;
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation, Algorithm};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use std::env;
+use tracing::error;
 use uuid::Uuid;
 
-/// Container for the authenticated user's id stored in request extensions.
+use crate::accounts::account_role;
+use crate::api_tokens::{find_active_token as find_active_api_token, is_api_token, scope_allows};
+use crate::invoices::history::{with_audit_context, AuditContext};
+use crate::models::api_token::ApiToken;
+use crate::models::invoice_event::AuditSource;
+use crate::models::portal::PortalToken;
+use crate::portal::find_active_token;
+use crate::secrets::secrets_match;
+
+/// Optional header naming the device an API request comes from.
+pub const DEVICE_ID_HEADER: &str = "x-device-id";
+
+/// Container for the id of the account a request acts as, stored in
+/// request extensions.
+///
+/// This is the person's own account unless the token switched to another
+/// account they belong to; all data is scoped to it.
 #[derive(Clone, Debug)]
 pub struct CurrentUser(pub Uuid);
 
+/// Container for the id of the person who authenticated, stored in request
+/// extensions next to [`CurrentUser`].
+#[derive(Clone, Debug)]
+pub struct CurrentIdentity(pub Uuid);
+
 /// Claims expected inside the JWT for authenticated users.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     /// Subject - should be the user's UUID as a string.
     pub sub: String,
     pub exp: usize,
+
+    /// Account to act as (a UUID as a string); the subject's own account
+    /// when absent.
+    #[serde(default, skip_serializing_if = Option::is_none)]
+    pub account: Option<String>,
+}
+
+impl Claims {
+    /// Parses the subject and the account the token acts as.
+    ///
+    /// # Returns
+    ///
+    /// Returns `(identity, account)`, or `None` if either is not a UUID.
+    pub fn ids(&self) -> Option<(Uuid, Uuid)> {
+        let identity = Uuid::parse_str(&self.sub).ok()?;
+        let account = match &self.account {
+            Some(account) => Uuid::parse_str(account).ok()?,
+            None => identity,
+        };
+        Some((identity, account))
+    }
+}
+
+/// Subject prefix of client portal tokens.
+const PORTAL_SUBJECT_PREFIX: &str = "portal:";
+
+/// Claims inside a client portal token.
+///
+/// The subject is `portal:<token id>` rather than a user UUID, so portal
+/// tokens are rejected by [`jwt_middleware`] and API tokens by
+/// [`portal_middleware`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortalClaims {
+    pub sub: String,
+    pub exp: usize,
+}
+
+impl PortalClaims {
+    /// Claims for a portal token row, expiring with it.
+    pub fn new(token_id: Uuid, expires_at: DateTime<Utc>) -> Self {
+        PortalClaims {
+            sub: format!("{}{}", PORTAL_SUBJECT_PREFIX, token_id),
+            exp: usize::try_from(expires_at.timestamp()).unwrap_or(0),
+        }
+    }
+
+    /// ID of the portal token row, or `None` for other tokens.
+    pub fn token_id(&self) -> Option<Uuid> {
+        self.sub
+            .strip_prefix(PORTAL_SUBJECT_PREFIX)
+            .and_then(|id| Uuid::parse_str(id).ok())
+    }
+}
+
+/// Container for the portal token a client request was made with, stored
+/// in request extensions.
+#[derive(Clone, Debug)]
+pub struct CurrentPortal(pub PortalToken);
+
+/// Container for the personal access token a request was made with, stored
+/// in request extensions (absent for login JWTs).
+#[derive(Clone, Debug)]
+pub struct CurrentApiToken(pub ApiToken);
+
+/// Secret used to sign and verify tokens.
+fn jwt_secret() -> String {
+    env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string())
+}
+
+/// Signs a token with the server's secret.
+///
+/// # Errors
+///
+/// Returns an error if the token cannot be encoded.
+pub fn issue_token(claims: &Claims) -> Result<String, anyhow::Error> {
+    let key = EncodingKey::from_secret(jwt_secret().as_bytes());
+    Ok(encode(&Header::new(Algorithm::HS256), claims, &key)?)
+}
+
+/// Signs a client portal token.
+///
+/// # Errors
+///
+/// Returns an error if the token cannot be encoded.
+pub fn issue_portal_token(claims: &PortalClaims) -> Result<String, anyhow::Error> {
+    let key = EncodingKey::from_secret(jwt_secret().as_bytes());
+    Ok(encode(&Header::new(Algorithm::HS256), claims, &key)?)
+}
+
+/// Returns the token of a Bearer `Authorization` header.
+fn bearer_token<B>(request: &Request<B>) -> Option<&str> {
+    request
+        .headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "))
+}
+
+/// Returns the `token` query parameter.
+fn query_token<B>(request: &Request<B>) -> Option<&str> {
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .filter(|token| !token.is_empty())
+}
+
+/// Returns the id of the account a request acts as, if it was authenticated.
+pub fn get_current_user_id<B>(request: &Request<B>) -> Option<Uuid> {
+    request.extensions().get::<CurrentUser>().map(|CurrentUser(id)| *id)
+}
+
+/// Returns the subject and account ids of a request's Bearer JWT, if it
+/// carries a valid one.
+///
+/// Unlike [`jwt_middleware`], membership of the account is not checked, so
+/// this is only fit for observability (e.g. trace sampling), never for
+/// authorization.
+pub fn token_ids<B>(request: &Request<B>) -> Option<(Uuid, Uuid)> {
+    let token = bearer_token(request)?;
+    let decoding_key = DecodingKey::from_secret(jwt_secret().as_bytes());
+    decode::<Claims>(token, &decoding_key, &Validation::new(Algorithm::HS256))
+        .ok()?
+        .claims
+        .ids()
 }
 
 /// Middleware to validate a Bearer JWT in the `Authorization` header.
 ///
 /// On success the request is forwarded; on failure a `401` is returned.
+/// Tokens switched to another account (`account` claim) are only accepted
+/// while the subject is a member of it, otherwise `403` is returned.
+///
+/// Personal access tokens (see [`crate::api_tokens`]) are accepted too:
+/// they act as the account they were created in, for the person who
+/// created them, and requests outside their scope get `403`.
 pub async fn jwt_middleware<B>(mut req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
     // Extract token from Authorization header
-    let auth_header = req.headers().get("authorization");
-    let token = match auth_header.and_then(|v| v.to_str().ok()) {
-        Some(s) if s.starts_with("Bearer ") => &s[7..],
-        _ => return Err(StatusCode::UNAUTHORIZED),
+    let token = bearer_token(&req).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let mut claims = None;
+    let mut api_token = None;
+    let (identity, account) = if is_api_token(token) {
+        let pool = req
+            .extensions()
+            .get::<PgPool>()
+            .cloned()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let found = find_active_api_token(&pool, token)
+            .await
+            .map_err(|e| {
+                error!("Failed to check API token: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        if !scope_allows(found.scope, req.method()) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let ids = (found.created_by, found.user_id);
+        api_token = Some(found);
+        ids
+    } else {
+        let secret = jwt_secret();
+        let decoding_key = DecodingKey::from_secret(secret.as_bytes());
+
+        let decoded = match decode::<Claims>(token, &decoding_key, &Validation::new(Algorithm::HS256)) {
+            Ok(c) => c.claims,
+            Err(_) => return Err(StatusCode::UNAUTHORIZED),
+        };
+
+        // Parse subject and account as UUIDs
+        let ids = decoded.ids().ok_or(StatusCode::UNAUTHORIZED)?;
+        claims = Some(decoded);
+        ids
     };
 
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
-    let decoding_key = DecodingKey::from_secret(secret.as_bytes());
+    // Membership is checked on every request so removed members lose
+    // access right away
+    if account != identity {
+        let pool = req
+            .extensions()
+            .get::<PgPool>()
+            .cloned()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let role = account_role(&pool, account, identity).await.map_err(|e| {
+            error!("Failed to check membership of {} in account {}: {}", identity, account, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if role.is_none() {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
 
-    let decoded = match decode::<Claims>(token, &decoding_key, &Validation::new(Algorithm::HS256)) {
-        Ok(c) => c.claims,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    // Invoice changes made while handling the request are attributed to
+    // the person (and their device, when the client says, or else the
+    // API token used)
+    let audit = AuditContext {
+        actor_id: Some(identity),
+        device_id: req
+            .headers()
+            .get(DEVICE_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().chars().take(255).collect::<String>())
+            .filter(|v| !v.is_empty())
+            .or_else(|| api_token.as_ref().map(|token| token.token_prefix.clone())),
+        source: AuditSource::Api,
     };
 
-    // Parse subject as UUID and attach to request extensions for downstream handlers.
-    let user_id = match Uuid::parse_str(&decoded.sub) {
-        Ok(id) => id,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    // Attach both ids to request extensions for downstream handlers.
+    req.extensions_mut().insert(CurrentUser(account));
+    req.extensions_mut().insert(CurrentIdentity(identity));
+    if let Some(claims) = claims {
+        req.extensions_mut().insert(claims);
+    }
+    if let Some(api_token) = api_token {
+        req.extensions_mut().insert(CurrentApiToken(api_token));
+    }
+
+    Ok(with_audit_context(audit, next.run(req)).await)
+}
+
+/// Returns the ID of the portal token a request carries, if its signature
+/// and expiry are valid (the token itself may still be revoked).
+pub fn portal_token_id<B>(request: &Request<B>) -> Option<Uuid> {
+    let token = bearer_token(request).or_else(|| query_token(request))?;
+    let decoding_key = DecodingKey::from_secret(jwt_secret().as_bytes());
+    decode::<PortalClaims>(token, &decoding_key, &Validation::new(Algorithm::HS256))
+        .ok()?
+        .claims
+        .token_id()
+}
+
+/// Middleware to validate a client portal token.
+///
+/// The token is read from a Bearer `Authorization` header or, for links
+/// such as PDF downloads, a `token` query parameter. It must name a portal
+/// token that is neither revoked nor expired and whose client still
+/// exists; otherwise `401` is returned.
+pub async fn portal_middleware<B>(mut req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+    let token_id = portal_token_id(&req).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let pool = req
+        .extensions()
+        .get::<PgPool>()
+        .cloned()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let portal = find_active_token(&pool, token_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to check portal token {}: {}", token_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Invoice changes made from the portal are attributed to it
+    let audit = AuditContext {
+        actor_id: None,
+        device_id: None,
+        source: AuditSource::Portal,
     };
 
-    req.extensions_mut().insert(CurrentUser(user_id));
+    req.extensions_mut().insert(CurrentPortal(portal));
+
+    Ok(with_audit_context(audit, next.run(req)).await)
+}
+
+/// Token support staff send to the admin API, read from `ADMIN_API_TOKEN`.
+/// The admin API is disabled while it is unset.
+fn admin_token() -> Option<String> {
+    env::var("ADMIN_API_TOKEN").ok().filter(|s| !s.is_empty())
+}
+
+/// Middleware to authenticate the admin API.
+///
+/// Requests must send `Authorization: Bearer <ADMIN_API_TOKEN>`; `401` is
+/// returned otherwise, and `404` while no token is configured. Admin
+/// requests act on the user named in their path, not as a user.
+pub async fn admin_middleware<B>(req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+    let expected = admin_token().ok_or(StatusCode::NOT_FOUND)?;
+    let given = bearer_token(&req).ok_or(StatusCode::UNAUTHORIZED)?;
+    if !secrets_match(&expected, given) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
 
     Ok(next.run(req).await)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_defaults_to_subject() {
+        let person = Uuid::new_v4();
+        let workspace = Uuid::new_v4();
+        let mut claims = Claims {
+            sub: person.to_string(),
+            exp: 0,
+            account: None,
+        };
+        assert_eq!(claims.ids(), Some((person, person)));
+
+        claims.account = Some(workspace.to_string());
+        assert_eq!(claims.ids(), Some((person, workspace)));
+
+        claims.account = Some("not-a-uuid".to_string());
+        assert_eq!(claims.ids(), None);
+    }
+
+    #[test]
+    fn test_portal_and_api_tokens_are_not_interchangeable() {
+        let token_id = Uuid::new_v4();
+        let portal = PortalClaims::new(token_id, Utc::now());
+        assert_eq!(portal.token_id(), Some(token_id));
+
+        let as_api = Claims {
+            sub: portal.sub.clone(),
+            exp: portal.exp,
+            account: None,
+        };
+        assert_eq!(as_api.ids(), None);
+
+        let as_portal = PortalClaims {
+            sub: Uuid::new_v4().to_string(),
+            exp: 0,
+        };
+        assert_eq!(as_portal.token_id(), None);
+    }
+
+    #[test]
+    fn test_query_token() {
+        let request = Request::builder()
+            .uri("/portal/invoices/1/pdf?download=1&token=abc.def")
+            .body(())
+            .unwrap();
+        assert_eq!(query_token(&request), Some("abc.def"));
+
+        let request = Request::builder().uri("/portal?token=").body(()).unwrap();
+        assert_eq!(query_token(&request), None);
+    }
+}
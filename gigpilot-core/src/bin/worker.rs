@@ -12,6 +12,11 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilte
 /// - Processes them through the state machine
 /// - Sends chase emails
 /// - Updates invoice states
+/// - Runs OCR on uploaded receipts
+/// - Drafts weekly invoices from unbilled work (opt-in)
+/// - Records daily receivables aging snapshots
+/// - Emails scheduled monthly client statements
+/// - Runs account imports started by users
 /// 
 /// The worker survives server restarts by storing state in the database.
 #[tokio::main]
@@ -31,17 +36,69 @@ async fn main() -> anyhow::Result<()> {
     
     info!("Starting GigPilot Invoice Chasing Worker...");
     
+    if gigpilot_core::logging::pii_logging_enabled() {
+        tracing::warn!("LOG_PII is enabled: client PII will appear verbatim in logs");
+    }
+    
     // Initialize database connection pool
     let db_pool = Database::new().await?;
     
+    // A worker serves one data region, whose cluster is DATABASE_URL
+    info!("Serving data region {}", gigpilot_core::db::residency::home_region());
+    
     // Get poll interval from environment (default: 60 seconds)
     let poll_interval = std::env::var("WORKER_POLL_INTERVAL_SECONDS")
         .ok()
         .and_then(|s| s.parse::<u64>().ok())
         .unwrap_or(60);
     
+    // Forward domain events so cached settings are dropped on change
+    let event_bus = gigpilot_core::events::EventBus::new();
+    gigpilot_core::events::spawn_listener(db_pool.clone(), event_bus.clone());
+    
+    // Pause sending emails while a maintenance window is open
+    gigpilot_core::maintenance::spawn_refresh(db_pool.clone(), &event_bus);
+    
+    // Run receipt OCR in the background
+    gigpilot_core::ocr::spawn_ocr_worker(db_pool.clone(), gigpilot_core::ocr::provider_from_env());
+    
+    // Draft invoices from unbilled work on Fridays and auto-send due drafts
+    gigpilot_core::worker::spawn_weekly_draft_worker(db_pool.clone());
+    
+    // Snapshot receivables aging once a day for trend reports
+    gigpilot_core::worker::spawn_aging_snapshot_worker(db_pool.clone());
+    
+    // Email monthly statements to clients with a schedule
+    gigpilot_core::worker::spawn_statement_worker(db_pool.clone());
+    
+    // Import FreshBooks and Wave exports once users start the job
+    gigpilot_core::worker::spawn_import_worker(db_pool.clone());
+    
+    // Profile how clients pay so chasing can adapt to them
+    gigpilot_core::worker::spawn_client_stats_worker(db_pool.clone());
+    
+    // Email users about invoices their clients disputed in the portal
+    gigpilot_core::worker::spawn_dispute_email_worker(db_pool.clone());
+    
+    // Email users when projects reach 80% and 100% of their budget
+    gigpilot_core::worker::spawn_budget_alert_worker(db_pool.clone());
+    
+    // Open monthly retainer periods, invoice their fee and settle unused hours
+    gigpilot_core::worker::spawn_retainer_worker(db_pool.clone());
+    
+    // Report liveness and provider health for the public status page
+    gigpilot_core::worker::spawn_heartbeat(db_pool.clone());
+    
+    // Reuse invoice PDFs cached by the API for chase email attachments
+    let blob_store = gigpilot_core::storage::store_from_env().await?;
+    gigpilot_core::invoices::pdf::spawn_pdf_cache_invalidation(&event_bus, blob_store.clone());
+    
+    // Render bulk invoice PDF exports
+    gigpilot_core::worker::spawn_pdf_export_worker(db_pool.clone(), blob_store.clone());
+    
     // Create scheduler
-    let mut scheduler = JobScheduler::new(db_pool, Some(poll_interval));
+    let mut scheduler = JobScheduler::new(db_pool, Some(poll_interval)).with_pdf_cache(blob_store);
+    scheduler.settings_cache().spawn_invalidation(&event_bus);
     
     // Handle shutdown signals gracefully (cross-platform)
     let mut ctrl_c = signal::ctrl_c();
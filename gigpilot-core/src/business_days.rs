@@ -0,0 +1,256 @@
+//! Business-day arithmetic with per-country public-holiday calendars.
+//!
+//! Built-in rules cover the fixed and Easter-relative national holidays of a
+//! handful of countries. Additional dates (regional holidays, one-off bank
+//! holidays) can be stored in the `public_holidays` table and are merged in
+//! by [`load_calendar`].
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+/// A public-holiday calendar for a single country.
+#[derive(Debug, Clone, Default)]
+pub struct HolidayCalendar {
+    /// ISO 3166-1 alpha-2 country code (None = weekends only)
+    country_code: Option<String>,
+
+    /// Extra holidays loaded from the database
+    extra_holidays: HashSet<NaiveDate>,
+}
+
+impl HolidayCalendar {
+    /// Creates a calendar using the built-in rules for a country.
+    pub fn new(country_code: Option<&str>) -> Self {
+        Self {
+            country_code: country_code.map(|c| c.to_uppercase()),
+            extra_holidays: HashSet::new(),
+        }
+    }
+
+    /// Adds extra holiday dates on top of the built-in rules.
+    pub fn with_holidays(mut self, dates: impl IntoIterator<Item = NaiveDate>) -> Self {
+        self.extra_holidays.extend(dates);
+        self
+    }
+
+    /// Returns whether the date is a public holiday in this calendar.
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        if self.extra_holidays.contains(&date) {
+            return true;
+        }
+        match self.country_code.as_deref() {
+            Some(country) => builtin_holidays(country, date.year()).contains(&date),
+            None => false,
+        }
+    }
+
+    /// Returns whether the date is a working day (not a weekend or holiday).
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.is_holiday(date)
+    }
+
+    /// Rolls a date forward to the next business day (or keeps it if it is one).
+    pub fn roll_forward(&self, date: NaiveDate) -> NaiveDate {
+        let mut current = date;
+        while !self.is_business_day(current) {
+            current += Duration::days(1);
+        }
+        current
+    }
+
+    /// Adds a number of business days to a date.
+    pub fn add_business_days(&self, date: NaiveDate, days: u32) -> NaiveDate {
+        let mut current = date;
+        let mut remaining = days;
+        while remaining > 0 {
+            current += Duration::days(1);
+            if self.is_business_day(current) {
+                remaining -= 1;
+            }
+        }
+        current
+    }
+
+    /// Counts business days in the half-open range `(start, end]`.
+    ///
+    /// Returns 0 when `end <= start`. Used for days-overdue calculations,
+    /// where the due date itself does not count.
+    pub fn business_days_between(&self, start: NaiveDate, end: NaiveDate) -> i64 {
+        let mut count = 0;
+        let mut current = start;
+        while current < end {
+            current += Duration::days(1);
+            if self.is_business_day(current) {
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// Loads a country's calendar, merging configured extra holidays.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `country_code` - ISO 3166-1 alpha-2 code, or None for weekends only
+///
+/// # Returns
+///
+/// Returns the merged `HolidayCalendar`, or an error if the query fails.
+pub async fn load_calendar(
+    pool: &PgPool,
+    country_code: Option<&str>,
+) -> Result<HolidayCalendar, anyhow::Error> {
+    let calendar = HolidayCalendar::new(country_code);
+    let Some(country) = country_code else {
+        return Ok(calendar);
+    };
+
+    let extra = sqlx::query_scalar::<_, NaiveDate>(
+        "SELECT holiday_date FROM public_holidays WHERE country_code = $1",
+    )
+    .bind(country.to_uppercase())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(calendar.with_holidays(extra))
+}
+
+/// Computes Easter Sunday for a year (anonymous Gregorian algorithm).
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = ((h + l - 7 * m + 114) % 31) + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).expect("valid Easter date")
+}
+
+/// Returns the n-th (1-based) given weekday of a month.
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).expect("valid weekday of month")
+}
+
+/// Returns the last given weekday of a month.
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    let first_of_next = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)
+    }
+    .expect("valid date");
+    let mut date = first_of_next - Duration::days(1);
+    while date.weekday() != weekday {
+        date -= Duration::days(1);
+    }
+    date
+}
+
+/// Built-in national holidays for supported countries.
+///
+/// Weekend substitution ("observed" days) is not applied; configure those
+/// dates in `public_holidays` where they matter.
+fn builtin_holidays(country: &str, year: i32) -> Vec<NaiveDate> {
+    let ymd = |month: u32, day: u32| NaiveDate::from_ymd_opt(year, month, day).expect("valid date");
+    let easter = easter_sunday(year);
+
+    match country {
+        "US" => vec![
+            ymd(1, 1),
+            nth_weekday(year, 1, Weekday::Mon, 3),
+            last_weekday(year, 5, Weekday::Mon),
+            ymd(7, 4),
+            nth_weekday(year, 9, Weekday::Mon, 1),
+            nth_weekday(year, 11, Weekday::Thu, 4),
+            ymd(12, 25),
+        ],
+        "GB" => vec![
+            ymd(1, 1),
+            easter - Duration::days(2),
+            easter + Duration::days(1),
+            nth_weekday(year, 5, Weekday::Mon, 1),
+            last_weekday(year, 5, Weekday::Mon),
+            last_weekday(year, 8, Weekday::Mon),
+            ymd(12, 25),
+            ymd(12, 26),
+        ],
+        "DE" => vec![
+            ymd(1, 1),
+            easter - Duration::days(2),
+            easter + Duration::days(1),
+            ymd(5, 1),
+            easter + Duration::days(39),
+            easter + Duration::days(50),
+            ymd(10, 3),
+            ymd(12, 25),
+            ymd(12, 26),
+        ],
+        "FR" => vec![
+            ymd(1, 1),
+            easter + Duration::days(1),
+            ymd(5, 1),
+            ymd(5, 8),
+            easter + Duration::days(39),
+            easter + Duration::days(50),
+            ymd(7, 14),
+            ymd(8, 15),
+            ymd(11, 1),
+            ymd(11, 11),
+            ymd(12, 25),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+
+    #[test]
+    fn test_easter_dates() {
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+    }
+
+    #[test]
+    fn test_weekends_are_not_business_days() {
+        let calendar = HolidayCalendar::new(None);
+        assert!(!calendar.is_business_day(date(2024, 6, 8)));
+        assert!(calendar.is_business_day(date(2024, 6, 10)));
+    }
+
+    #[test]
+    fn test_roll_forward_past_holiday_weekend() {
+        // Good Friday 2024-03-29, then weekend, then Easter Monday in GB
+        let calendar = HolidayCalendar::new(Some("gb"));
+        assert_eq!(calendar.roll_forward(date(2024, 3, 29)), date(2024, 4, 2));
+    }
+
+    #[test]
+    fn test_us_thanksgiving_and_extra_holidays() {
+        let calendar = HolidayCalendar::new(Some("US")).with_holidays([date(2024, 11, 29)]);
+        assert!(calendar.is_holiday(date(2024, 11, 28)));
+        assert!(calendar.is_holiday(date(2024, 11, 29)));
+        assert_eq!(calendar.add_business_days(date(2024, 11, 27), 1), date(2024, 12, 2));
+    }
+
+    #[test]
+    fn test_business_days_between() {
+        let calendar = HolidayCalendar::new(None);
+        // Fri -> next Fri: Mon..Fri = 5
+        assert_eq!(calendar.business_days_between(date(2024, 6, 7), date(2024, 6, 14)), 5);
+        assert_eq!(calendar.business_days_between(date(2024, 6, 14), date(2024, 6, 7)), 0);
+    }
+}
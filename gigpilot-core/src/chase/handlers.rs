@@ -0,0 +1,181 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::chase::retries::{is_dead_lettered, DeadLetter};
+use crate::chase::{open_invoices, simulate, ChaseSimulation, ProposedPolicy, DEFAULT_SIMULATION_DAYS};
+use crate::models::invoice::InvoiceResponse;
+use crate::repo::DynRepository;
+use crate::settings::SettingsCache;
+
+/// Chase policy simulation endpoint handler.
+///
+/// Handles POST requests to `/api/chase/simulate`, replaying the chase
+/// worker over the user's open invoices under the proposed policy and
+/// returning the emails it would send. Nothing is saved or sent.
+pub async fn simulate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(settings_cache): Extension<SettingsCache>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(policy): Json<ProposedPolicy>,
+) -> Result<Json<ChaseSimulation>, (StatusCode, Json<Value>)> {
+    let invalid = |message: String| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })));
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "failed to simulate chase policy" })),
+        )
+    };
+
+    policy.validate().map_err(invalid)?;
+
+    let saved = settings_cache.user_settings(user_id).await.map_err(|e| {
+        error!("Failed to load settings for user {}: {}", user_id, e);
+        internal_error()
+    })?;
+    let settings = policy.apply_to(saved).map_err(invalid)?;
+
+    let calendar = if settings.skip_non_business_days {
+        let calendar = settings_cache
+            .calendar(settings.country_code.as_deref())
+            .await
+            .map_err(|e| {
+                error!("Failed to load holiday calendar for user {}: {}", user_id, e);
+                internal_error()
+            })?;
+        Some(calendar)
+    } else {
+        None
+    };
+
+    let invoices = open_invoices(&pool, user_id).await.map_err(|e| {
+        error!("Failed to load open invoices for user {}: {}", user_id, e);
+        internal_error()
+    })?;
+
+    let simulation = simulate(
+        &invoices,
+        &settings,
+        calendar.as_ref(),
+        &policy.escalation,
+        Utc::now().date_naive(),
+        policy.days.unwrap_or(DEFAULT_SIMULATION_DAYS),
+    )
+    .map_err(|e| {
+        error!("Failed to simulate chase policy for user {}: {}", user_id, e);
+        internal_error()
+    })?;
+
+    Ok(Json(simulation))
+}
+
+/// List dead letters endpoint handler.
+///
+/// Handles GET requests to `/api/chase/dead-letters`, listing the user's
+/// unpaid invoices whose reminders were given up on after failed sends,
+/// with the last error.
+pub async fn list_dead_letters_handler(
+    Extension(repo): Extension<DynRepository>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<DeadLetter>>, StatusCode> {
+    let invoices = repo.dead_lettered_invoices(user_id).await.map_err(|e| {
+        error!("Failed to list dead-lettered invoices for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(invoices.iter().filter_map(DeadLetter::from_invoice).collect()))
+}
+
+/// Requeue dead letter endpoint handler.
+///
+/// Handles POST requests to `/api/chase/dead-letters/:invoice_id/requeue`,
+/// clearing the invoice's failed sends so the worker chases it on its next
+/// poll. Fix the client's email address first, or it is dead-lettered
+/// again.
+pub async fn requeue_dead_letter_handler(
+    Extension(repo): Extension<DynRepository>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<InvoiceResponse>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to requeue chase of invoice {}: {}", invoice_id, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "failed to requeue invoice" })),
+        )
+    };
+    let not_found = || (StatusCode::NOT_FOUND, Json(json!({ "error": "invoice not found" })));
+
+    let invoice = repo
+        .find_invoice(user_id, invoice_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(not_found)?;
+    if !is_dead_lettered(&invoice) {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "reminders for this invoice were not given up on" })),
+        ));
+    }
+
+    let invoice = repo
+        .requeue_chase(user_id, invoice_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(not_found)?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+
+    use crate::chase::retries::ChaseRetry;
+    use crate::repo::memory::{sample_invoice, InMemoryRepository};
+
+    #[tokio::test]
+    async fn test_requeue_only_dead_lettered_invoices() {
+        let user_id = Uuid::new_v4();
+        let mut invoice = sample_invoice(user_id, Utc::now().date_naive(), Decimal::from(100));
+        let retry = ChaseRetry::dead_lettered(None, "No client email", Utc::now());
+        invoice.metadata = Some(retry.metadata_patch());
+        let invoice_id = invoice.id;
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice));
+        let repo: DynRepository = memory.clone();
+
+        let Json(dead_letters) = list_dead_letters_handler(Extension(repo.clone()), Extension(CurrentUser(user_id)))
+            .await
+            .unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].last_error, "No client email");
+
+        requeue_dead_letter_handler(Extension(repo.clone()), Extension(CurrentUser(user_id)), Path(invoice_id))
+            .await
+            .expect("dead-lettered invoice should be requeued");
+        assert_eq!(memory.sync_changes().len(), 1);
+
+        let (status, _) =
+            requeue_dead_letter_handler(Extension(repo.clone()), Extension(CurrentUser(user_id)), Path(invoice_id))
+                .await
+                .unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let Json(dead_letters) = list_dead_letters_handler(Extension(repo), Extension(CurrentUser(user_id)))
+            .await
+            .unwrap();
+        assert!(dead_letters.is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+//! Grace holds on chasing.
+//!
+//! A client who has just started paying shouldn't get a reminder minutes
+//! later. When they start a card payment from the pay page, or a bank
+//! transfer that may be theirs is waiting for the user's review, the
+//! invoice gets a short hold under `metadata.chase_hold` and the worker
+//! sends nothing while it lasts. Holds lapse on their own, so an invoice
+//! whose payment never arrives is chased as usual afterwards, and a client
+//! can't keep chasing off by starting card payments over and over: a new
+//! card-payment hold is only placed once the last one is
+//! [`PAYMENT_STARTED_RENEWAL_DAYS`] old.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::models::invoice::Invoice;
+
+/// Hours chasing waits after the client started a card payment.
+pub const PAYMENT_STARTED_HOLD_HOURS: i64 = 48;
+
+/// Hours chasing waits while a matching bank transfer is up for review.
+pub const BANK_REVIEW_HOLD_HOURS: i64 = 72;
+
+/// Days after a card-payment hold started before another may be placed.
+pub const PAYMENT_STARTED_RENEWAL_DAYS: i64 = 14;
+
+/// Why an invoice's chasing is on hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldReason {
+    /// The client clicked through to pay by card
+    PaymentStarted,
+
+    /// An imported bank transfer may pay the invoice and awaits review
+    BankMatchReview,
+}
+
+impl HoldReason {
+    /// How long a hold for this reason lasts.
+    pub fn duration(self) -> Duration {
+        match self {
+            HoldReason::PaymentStarted => Duration::hours(PAYMENT_STARTED_HOLD_HOURS),
+            HoldReason::BankMatchReview => Duration::hours(BANK_REVIEW_HOLD_HOURS),
+        }
+    }
+
+    /// How long after a hold for this reason started before another can
+    /// be placed, if that is limited.
+    pub fn renewal_interval(self) -> Option<Duration> {
+        match self {
+            HoldReason::PaymentStarted => Some(Duration::days(PAYMENT_STARTED_RENEWAL_DAYS)),
+            HoldReason::BankMatchReview => None,
+        }
+    }
+
+    /// The latest end of an earlier hold for this reason that still stops
+    /// a new one from being placed at `now`, if renewals are limited.
+    pub fn renewal_cutoff(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.renewal_interval().map(|interval| now - interval + self.duration())
+    }
+}
+
+/// A grace hold, stored as `metadata.chase_hold`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChaseHold {
+    /// Why chasing is held
+    pub reason: HoldReason,
+
+    /// When chasing resumes
+    pub until: DateTime<Utc>,
+}
+
+impl ChaseHold {
+    /// A hold for `reason` starting at `now`.
+    pub fn new(reason: HoldReason, now: DateTime<Utc>) -> Self {
+        Self {
+            reason,
+            until: now + reason.duration(),
+        }
+    }
+}
+
+/// The invoice's hold still in effect at `now`, if any.
+///
+/// Holds that can't be read are ignored, so a bad value never stops
+/// chasing.
+pub fn active_hold(invoice: &Invoice, now: DateTime<Utc>) -> Option<ChaseHold> {
+    let stored = invoice.metadata.as_ref()?.get("chase_hold")?;
+    serde_json::from_value::<ChaseHold>(stored.clone())
+        .ok()
+        .filter(|hold| hold.until > now)
+}
+
+/// Puts invoices' chasing on hold.
+///
+/// Only open invoices are held, and a hold never shortens one that lasts
+/// longer. Invoices whose last hold for the same reason started too
+/// recently (see [`HoldReason::renewal_interval`]) aren't held again. The
+/// invoice's `last_modified` is left alone, so devices editing it don't see
+/// a conflict.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to write with
+/// * `user_id` - ID of the invoices' owner
+/// * `invoice_ids` - The invoices to hold
+/// * `reason` - Why chasing is held
+/// * `now` - Start of the hold
+///
+/// # Returns
+///
+/// Returns the number of invoices put on hold.
+pub async fn place_hold<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    invoice_ids: &[Uuid],
+    reason: HoldReason,
+    now: DateTime<Utc>,
+) -> Result<u64, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let hold = ChaseHold::new(reason, now);
+    let result = sqlx::query(
+        r#"
+        UPDATE invoices
+        SET metadata = jsonb_set(COALESCE(metadata, '{}'::jsonb), '{chase_hold}', $3)
+        WHERE user_id = $1 AND id = ANY($2)
+            AND is_deleted = false
+            AND status NOT IN ('draft', 'paid', 'cancelled')
+            AND (
+                metadata->'chase_hold'->>'until' IS NULL
+                OR (metadata->'chase_hold'->>'until')::timestamptz < $4
+            )
+            AND NOT (
+                $6::timestamptz IS NOT NULL
+                AND metadata->'chase_hold'->'reason' = $5
+                AND (metadata->'chase_hold'->>'until')::timestamptz > $6
+            )
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_ids)
+    .bind(json!(hold))
+    .bind(hold.until)
+    .bind(json!(reason))
+    .bind(reason.renewal_cutoff(now))
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::repo::memory::sample_invoice;
+
+    fn invoice() -> Invoice {
+        sample_invoice(Uuid::new_v4(), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), Decimal::new(10000, 2))
+    }
+
+    #[test]
+    fn test_hold_lasts_for_its_reason() {
+        let now = Utc::now();
+        let held = |hold: &ChaseHold| {
+            let mut invoice = invoice();
+            invoice.metadata = Some(json!({ "chase_state": "chasing_level_1", "chase_hold": hold }));
+            invoice
+        };
+
+        let started = ChaseHold::new(HoldReason::PaymentStarted, now);
+        assert_eq!(active_hold(&held(&started), now + Duration::hours(47)), Some(started.clone()));
+        assert_eq!(active_hold(&held(&started), now + Duration::hours(48)), None);
+
+        let review = ChaseHold::new(HoldReason::BankMatchReview, now);
+        assert!(active_hold(&held(&review), now + Duration::hours(71)).is_some());
+    }
+
+    #[test]
+    fn test_card_payment_holds_are_not_renewed_right_away() {
+        let now = Utc::now();
+        let started = ChaseHold::new(HoldReason::PaymentStarted, now);
+
+        // A hold from the same click, or one a few days later, blocks renewal
+        let cutoff = HoldReason::PaymentStarted.renewal_cutoff(now).unwrap();
+        assert!(started.until > cutoff);
+        let later = HoldReason::PaymentStarted.renewal_cutoff(now + Duration::days(5)).unwrap();
+        assert!(started.until > later);
+
+        // Once the interval has passed, chasing can be held again
+        let renewable = HoldReason::PaymentStarted
+            .renewal_cutoff(now + Duration::days(PAYMENT_STARTED_RENEWAL_DAYS))
+            .unwrap();
+        assert!(started.until <= renewable);
+
+        assert_eq!(HoldReason::BankMatchReview.renewal_cutoff(now), None);
+    }
+
+    #[test]
+    fn test_missing_or_unreadable_hold_is_ignored() {
+        let now = Utc::now();
+        let mut invoice = invoice();
+        assert_eq!(active_hold(&invoice, now), None);
+
+        invoice.metadata = Some(json!({ "chase_hold": { "until": "soon" } }));
+        assert_eq!(active_hold(&invoice, now), None);
+
+        invoice.metadata = Some(json!({ "chase_hold": { "reason": "payment_started", "until": now + Duration::hours(1) } }));
+        assert_eq!(active_hold(&invoice, now).map(|hold| hold.reason), Some(HoldReason::PaymentStarted));
+    }
+}
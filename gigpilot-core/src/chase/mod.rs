@@ -0,0 +1,493 @@
+//! Chase policy previews.
+//!
+//! [`simulate`] replays the chase state machine day by day over a user's
+//! open invoices under a proposed policy, without sending anything, and
+//! returns the emails the worker would send. Users can check the effect of
+//! new escalation or late fee settings before saving them.
+
+pub mod handlers;
+pub mod holds;
+pub mod retries;
+pub mod send_window;
+
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::business_days::HolidayCalendar;
+use crate::chase::holds::active_hold;
+use crate::invoices::late_fees::{has_late_fee, LateFeePolicy};
+use crate::models::chase_override::ChaseOverride;
+use crate::models::invoice::Invoice;
+use crate::models::user_settings::{LateFeeKind, UpdateUserSettings, UserSettings};
+use crate::settings::validate_update;
+use crate::worker::state_machine::{
+    current_chase_state, days_overdue, ChaseAction, ChaseState, ChaseStateMachine, Transition,
+};
+
+/// Days simulated when the request does not say.
+pub const DEFAULT_SIMULATION_DAYS: i64 = 30;
+
+/// Longest simulation allowed.
+pub const MAX_SIMULATION_DAYS: i64 = 180;
+
+/// Most open invoices included in a simulation.
+const MAX_SIMULATED_INVOICES: i64 = 1000;
+
+/// A chase policy to preview.
+///
+/// Settings fields left out keep the user's saved value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProposedPolicy {
+    /// Escalation rules for invoices without their own chase override
+    #[serde(default)]
+    pub escalation: ChaseOverride,
+
+    /// Whether chasing skips weekends and public holidays
+    #[serde(default)]
+    pub skip_non_business_days: Option<bool>,
+
+    /// Country used for the public-holiday calendar
+    #[serde(default)]
+    pub country_code: Option<String>,
+
+    /// How late fees are charged
+    #[serde(default)]
+    pub late_fee_kind: Option<LateFeeKind>,
+
+    /// Flat fee amount, or percentage of the balance due
+    #[serde(default)]
+    pub late_fee_amount: Option<Decimal>,
+
+    /// Days overdue before a late fee is charged
+    #[serde(default)]
+    pub late_fee_after_days: Option<i32>,
+
+    /// Days to simulate, starting today (default 30)
+    #[serde(default)]
+    pub days: Option<i64>,
+}
+
+impl ProposedPolicy {
+    /// The settings part of the policy, as a settings update.
+    fn settings_update(&self) -> UpdateUserSettings {
+        UpdateUserSettings {
+            country_code: self.country_code.clone(),
+            skip_non_business_days: self.skip_non_business_days,
+            late_fee_kind: self.late_fee_kind,
+            late_fee_amount: self.late_fee_amount,
+            late_fee_after_days: self.late_fee_after_days,
+            ..UpdateUserSettings::default()
+        }
+    }
+
+    /// Validates the policy before it is simulated.
+    ///
+    /// # Returns
+    ///
+    /// Returns a user-facing message describing the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(days) = self.days {
+            if !(1..=MAX_SIMULATION_DAYS).contains(&days) {
+                return Err(format!("days must be between 1 and {}", MAX_SIMULATION_DAYS));
+            }
+        }
+        self.escalation.validate()?;
+        validate_update(&self.settings_update())
+    }
+
+    /// Applies the policy's settings over the user's saved settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a user-facing message if the combination is invalid (e.g. a
+    /// saved amount above 100 with a proposed percentage fee).
+    pub fn apply_to(&self, mut settings: UserSettings) -> Result<UserSettings, String> {
+        if let Some(country_code) = &self.country_code {
+            settings.country_code = Some(country_code.trim().to_uppercase());
+        }
+        if let Some(skip) = self.skip_non_business_days {
+            settings.skip_non_business_days = skip;
+        }
+        if let Some(kind) = self.late_fee_kind {
+            settings.late_fee_kind = kind;
+        }
+        if let Some(amount) = self.late_fee_amount {
+            settings.late_fee_amount = amount;
+        }
+        if let Some(days) = self.late_fee_after_days {
+            settings.late_fee_after_days = days;
+        }
+
+        if settings.late_fee_kind == LateFeeKind::Percentage && settings.late_fee_amount > Decimal::ONE_HUNDRED {
+            return Err("late_fee_amount must be at most 100 for percentage fees".to_string());
+        }
+        Ok(settings)
+    }
+}
+
+/// One invoice's part in a projected email.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectedReminder {
+    /// Invoice being chased
+    pub invoice_id: Uuid,
+
+    /// Invoice number
+    pub invoice_number: String,
+
+    /// Email tone ("polite" or "firm")
+    pub tone: String,
+
+    /// Chase state after the email is sent
+    pub chase_state: String,
+
+    /// Days overdue on the day the email is sent
+    pub days_overdue: i64,
+
+    /// Late fee charged with this email, if any
+    pub late_fee: Option<Decimal>,
+}
+
+/// An email the worker would send.
+///
+/// Reminders for several invoices of one client on the same day are
+/// consolidated into one email, as the worker does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectedEmail {
+    /// Day the email would be sent
+    pub date: NaiveDate,
+
+    /// Recipient
+    pub client_email: String,
+
+    /// Client name
+    pub client_name: String,
+
+    /// Invoices covered by the email
+    pub invoices: Vec<ProjectedReminder>,
+}
+
+/// Result of a chase policy simulation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChaseSimulation {
+    /// First simulated day
+    pub from: NaiveDate,
+
+    /// Last simulated day
+    pub to: NaiveDate,
+
+    /// Projected emails in send order
+    pub emails: Vec<ProjectedEmail>,
+
+    /// Invoices that would be chased but have no client email
+    pub missing_email: Vec<Uuid>,
+}
+
+/// An invoice as it moves through the simulation.
+struct SimulatedInvoice<'a> {
+    invoice: &'a Invoice,
+    overrides: ChaseOverride,
+    state: ChaseState,
+    balance_due: Decimal,
+    late_fee_charged: bool,
+}
+
+/// Tone of a reminder action.
+fn tone(action: ChaseAction) -> Option<&'static str> {
+    match action {
+        ChaseAction::SendPoliteReminder => Some("polite"),
+        ChaseAction::SendFirmReminder => Some("firm"),
+        ChaseAction::SendUrgentReminder => Some("urgent"),
+        ChaseAction::SendFinalNotice => Some("final"),
+        ChaseAction::MarkAsPaid | ChaseAction::NoAction => None,
+    }
+}
+
+/// Simulates the chase worker over open invoices.
+///
+/// Each simulated day behaves like that day's worker runs: non-business
+/// days are skipped when a calendar is given, and invoices keep moving
+/// through the state machine until their states settle. Invoices with their
+/// own chase override keep it; the others use `escalation`.
+///
+/// # Arguments
+///
+/// * `invoices` - Open invoices of one user
+/// * `settings` - User settings with the proposed changes applied
+/// * `calendar` - Holiday calendar, if non-business days are skipped
+/// * `escalation` - Default escalation rules to preview
+/// * `from` - First day to simulate (normally today)
+/// * `days` - Number of days to simulate
+///
+/// # Errors
+///
+/// Returns an error if an invoice's chase override is malformed.
+pub fn simulate(
+    invoices: &[Invoice],
+    settings: &UserSettings,
+    calendar: Option<&HolidayCalendar>,
+    escalation: &ChaseOverride,
+    from: NaiveDate,
+    days: i64,
+) -> Result<ChaseSimulation, anyhow::Error> {
+    let late_fees = LateFeePolicy::from_settings(settings);
+
+    let mut simulated = Vec::with_capacity(invoices.len());
+    for invoice in invoices {
+        let own = ChaseOverride::parse(invoice.chase_override.as_ref())?;
+        let mut overrides = if own == ChaseOverride::default() { escalation.clone() } else { own };
+        // Grace holds in effect now delay the first email to the day they end
+        if let Some(hold) = active_hold(invoice, Utc::now()) {
+            overrides.not_before = overrides.not_before.max(Some(hold.until.date_naive()));
+        }
+        simulated.push(SimulatedInvoice {
+            invoice,
+            overrides,
+            state: current_chase_state(invoice, from),
+            balance_due: invoice.balance_due(),
+            late_fee_charged: has_late_fee(invoice),
+        });
+    }
+
+    let mut emails = Vec::new();
+    let mut missing_email = Vec::new();
+
+    for offset in 0..days {
+        let day = from + Duration::days(offset);
+        if calendar.map_or(false, |calendar| !calendar.is_business_day(day)) {
+            continue;
+        }
+
+        // The worker polls every minute, so an invoice keeps moving through
+        // the state machine until it settles; each pass is one worker run
+        loop {
+            let mut run: Vec<ProjectedEmail> = Vec::new();
+            let mut by_recipient: HashMap<String, usize> = HashMap::new();
+            let mut changed = false;
+
+            for entry in simulated.iter_mut() {
+                // The worker only picks up invoices past their due date
+                if entry.invoice.due_date.map_or(true, |due_date| due_date >= day) {
+                    continue;
+                }
+                let Some(client_email) = entry.invoice.client_email.as_deref() else {
+                    if !missing_email.contains(&entry.invoice.id) {
+                        missing_email.push(entry.invoice.id);
+                    }
+                    continue;
+                };
+
+                let overdue = days_overdue(entry.invoice, day, calendar);
+                let (next_state, action) = ChaseStateMachine::transition_with_override(
+                    entry.state,
+                    overdue,
+                    entry.balance_due,
+                    &entry.overrides,
+                    day,
+                );
+                if next_state != entry.state {
+                    entry.state = next_state;
+                    changed = true;
+                }
+
+                let Some(tone) = tone(action) else {
+                    continue;
+                };
+                let late_fee = if action.charges_late_fee() && !entry.late_fee_charged {
+                    late_fees.fee(entry.balance_due, &entry.invoice.currency, overdue)
+                } else {
+                    None
+                };
+                if let Some(fee) = late_fee {
+                    entry.balance_due += fee;
+                    entry.late_fee_charged = true;
+                }
+
+                // Reminders of one run are consolidated per recipient
+                let reminder = ProjectedReminder {
+                    invoice_id: entry.invoice.id,
+                    invoice_number: entry.invoice.invoice_number.clone(),
+                    tone: tone.to_string(),
+                    chase_state: next_state.to_string(),
+                    days_overdue: overdue,
+                    late_fee,
+                };
+                let key = client_email.trim().to_lowercase();
+                match by_recipient.get(&key) {
+                    Some(&i) => run[i].invoices.push(reminder),
+                    None => {
+                        by_recipient.insert(key, run.len());
+                        run.push(ProjectedEmail {
+                            date: day,
+                            client_email: client_email.to_string(),
+                            client_name: entry.invoice.client_name.clone(),
+                            invoices: vec![reminder],
+                        });
+                    }
+                }
+            }
+
+            emails.extend(run);
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    Ok(ChaseSimulation {
+        from,
+        to: from + Duration::days(days - 1),
+        emails,
+        missing_email,
+    })
+}
+
+/// Loads a user's open invoices for a simulation.
+///
+/// Open means sent or overdue, not deleted, and with a balance left.
+pub async fn open_invoices(pool: &PgPool, user_id: Uuid) -> Result<Vec<Invoice>, anyhow::Error> {
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE user_id = $1
+            AND is_deleted = false
+            AND status IN ('sent', 'overdue')
+            AND total > amount_paid
+        ORDER BY due_date ASC NULLS LAST
+        LIMIT $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(MAX_SIMULATED_INVOICES)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(invoices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::memory::sample_invoice;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_projects_polite_then_firm_reminder_with_late_fee() {
+        let user_id = Uuid::new_v4();
+        let invoice = sample_invoice(user_id, date(1), Decimal::from(200));
+        let mut settings = UserSettings::defaults(user_id);
+        settings.late_fee_kind = LateFeeKind::Flat;
+        settings.late_fee_amount = Decimal::from(25);
+        let escalation = ChaseOverride {
+            level_2_after_days: Some(10),
+            ..ChaseOverride::default()
+        };
+
+        let result = simulate(&[invoice.clone()], &settings, None, &escalation, date(4), 14).unwrap();
+
+        assert_eq!(result.to, date(17));
+        assert_eq!(result.emails.len(), 2);
+        assert_eq!(result.emails[0].date, date(4));
+        assert_eq!(result.emails[0].invoices[0].tone, "polite");
+        assert_eq!(result.emails[0].invoices[0].chase_state, "chasing_level_1");
+        assert_eq!(result.emails[1].date, date(11));
+        assert_eq!(result.emails[1].invoices[0].tone, "firm");
+        assert_eq!(result.emails[1].invoices[0].days_overdue, 10);
+        assert_eq!(result.emails[1].invoices[0].late_fee, Some(Decimal::from(25)));
+    }
+
+    #[test]
+    fn test_invoice_override_beats_proposed_escalation() {
+        let user_id = Uuid::new_v4();
+        let mut own = sample_invoice(user_id, date(1), Decimal::from(100));
+        own.client_email = Some("other@client.test".to_string());
+        own.chase_override = Some(serde_json::json!({ "skip_level_2": true }));
+        let default = sample_invoice(user_id, date(1), Decimal::from(100));
+        let escalation = ChaseOverride {
+            level_2_after_days: Some(3),
+            ..ChaseOverride::default()
+        };
+
+        let result = simulate(
+            &[own.clone(), default.clone()],
+            &UserSettings::defaults(user_id),
+            None,
+            &escalation,
+            date(2),
+            7,
+        )
+        .unwrap();
+
+        let firm: Vec<Uuid> = result
+            .emails
+            .iter()
+            .flat_map(|email| &email.invoices)
+            .filter(|reminder| reminder.tone == "firm")
+            .map(|reminder| reminder.invoice_id)
+            .collect();
+        assert_eq!(firm, vec![default.id]);
+    }
+
+    #[test]
+    fn test_same_day_reminders_are_consolidated_and_weekends_skipped() {
+        let user_id = Uuid::new_v4();
+        let first = sample_invoice(user_id, date(1), Decimal::from(100));
+        let second = sample_invoice(user_id, date(1), Decimal::from(50));
+        let mut no_email = sample_invoice(user_id, date(1), Decimal::from(10));
+        no_email.client_email = None;
+        let calendar = HolidayCalendar::new(None);
+
+        // 2024-03-02 is a Saturday: nothing goes out until Monday the 4th
+        let result = simulate(
+            &[first, second, no_email.clone()],
+            &UserSettings::defaults(user_id),
+            Some(&calendar),
+            &ChaseOverride::default(),
+            date(2),
+            3,
+        )
+        .unwrap();
+
+        assert_eq!(result.emails.len(), 1);
+        assert_eq!(result.emails[0].date, date(4));
+        assert_eq!(result.emails[0].invoices.len(), 2);
+        assert_eq!(result.missing_email, vec![no_email.id]);
+    }
+
+    #[test]
+    fn test_validate_policy() {
+        assert!(ProposedPolicy::default().validate().is_ok());
+        assert!(ProposedPolicy { days: Some(0), ..ProposedPolicy::default() }.validate().is_err());
+        assert!(ProposedPolicy {
+            late_fee_amount: Some(Decimal::from(-5)),
+            ..ProposedPolicy::default()
+        }
+        .validate()
+        .is_err());
+
+        let mut saved = UserSettings::defaults(Uuid::new_v4());
+        saved.late_fee_amount = Decimal::from(150);
+        let percentage = ProposedPolicy {
+            late_fee_kind: Some(LateFeeKind::Percentage),
+            ..ProposedPolicy::default()
+        };
+        assert!(percentage.apply_to(saved).is_err());
+
+        // A fee due after the final notice would never be charged
+        assert!(ProposedPolicy { late_fee_after_days: Some(21), ..ProposedPolicy::default() }.validate().is_ok());
+        assert!(ProposedPolicy { late_fee_after_days: Some(30), ..ProposedPolicy::default() }.validate().is_err());
+    }
+}
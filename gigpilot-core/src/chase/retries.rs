@@ -0,0 +1,252 @@
+//! Retries of failed chase emails.
+//!
+//! When a reminder can't be sent (the email provider is down, the client
+//! has no email address, ...), the failure is recorded under
+//! `metadata.chase_retry` with the attempts so far and when to try again.
+//! The worker leaves the invoice alone until then, backing off
+//! exponentially. After [`MAX_SEND_ATTEMPTS`] failures, or right away when
+//! the send can't succeed on retry ([`Undeliverable`]), the invoice is
+//! dead-lettered: the owner is notified and it isn't chased again until
+//! they requeue it or change its chase override. A successful send clears
+//! the record.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::models::invoice::Invoice;
+use crate::models::notification::CreateNotification;
+
+/// Failed sends after which an invoice is dead-lettered.
+pub const MAX_SEND_ATTEMPTS: u32 = 5;
+
+/// Minutes before the first retry.
+const BASE_RETRY_MINUTES: i64 = 5;
+
+/// Longest wait between retries, in hours.
+const MAX_RETRY_HOURS: i64 = 12;
+
+/// Notification kind sent to the owner when an invoice is dead-lettered.
+pub const DEAD_LETTER_NOTIFICATION: &str = "chase_dead_lettered";
+
+/// A send that retrying won't fix, e.g. the client has no email address or
+/// the provider rejected the address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Undeliverable(pub String);
+
+impl fmt::Display for Undeliverable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Undeliverable {}
+
+/// Failed chase emails of an invoice, stored as `metadata.chase_retry`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChaseRetry {
+    /// Failed sends in a row
+    pub attempts: u32,
+
+    /// Why the latest send failed
+    pub last_error: String,
+
+    /// When the latest send failed
+    pub failed_at: DateTime<Utc>,
+
+    /// When to try again; none once dead-lettered
+    pub next_retry_at: Option<DateTime<Utc>>,
+}
+
+impl ChaseRetry {
+    /// The record after another failed send at `now`.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The invoice's record before this failure, if any
+    /// * `error` - Why the send failed
+    /// * `now` - When it failed
+    pub fn after_failure(previous: Option<&ChaseRetry>, error: &str, now: DateTime<Utc>) -> Self {
+        let attempts = previous.map_or(0, |retry| retry.attempts) + 1;
+        Self {
+            attempts,
+            last_error: error.to_string(),
+            failed_at: now,
+            next_retry_at: (attempts < MAX_SEND_ATTEMPTS).then(|| now + retry_delay(attempts)),
+        }
+    }
+
+    /// The record after a failed send at `now` that retrying won't fix.
+    pub fn dead_lettered(previous: Option<&ChaseRetry>, error: &str, now: DateTime<Utc>) -> Self {
+        Self {
+            next_retry_at: None,
+            ..Self::after_failure(previous, error, now)
+        }
+    }
+
+    /// Whether the invoice ran out of attempts.
+    pub fn is_dead_lettered(&self) -> bool {
+        self.next_retry_at.is_none()
+    }
+
+    /// Whether the invoice must not be chased at `now`.
+    pub fn is_waiting(&self, now: DateTime<Utc>) -> bool {
+        self.next_retry_at.is_none_or(|retry_at| retry_at > now)
+    }
+
+    /// The metadata patch storing this record.
+    pub fn metadata_patch(&self) -> Value {
+        json!({ "chase_retry": self })
+    }
+}
+
+/// Delay before retrying after the `attempts`th failed send.
+///
+/// Doubles with every attempt, from 5 minutes up to 12 hours.
+pub fn retry_delay(attempts: u32) -> Duration {
+    let doublings = attempts.clamp(1, 16) - 1;
+    Duration::minutes(BASE_RETRY_MINUTES << doublings).min(Duration::hours(MAX_RETRY_HOURS))
+}
+
+/// The invoice's failed-send record, if any.
+///
+/// Records that can't be read are ignored, so a bad value never stops
+/// chasing.
+pub fn chase_retry(invoice: &Invoice) -> Option<ChaseRetry> {
+    let stored = invoice.metadata.as_ref()?.get("chase_retry")?;
+    serde_json::from_value(stored.clone()).ok()
+}
+
+/// The metadata patch clearing an invoice's failed-send record.
+pub fn clear_patch() -> Value {
+    json!({ "chase_retry": null })
+}
+
+/// Whether a failed send should be retried.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Undeliverable>().is_none()
+}
+
+/// Whether chasing the invoice was given up on.
+pub fn is_dead_lettered(invoice: &Invoice) -> bool {
+    chase_retry(invoice).is_some_and(|retry| retry.is_dead_lettered())
+}
+
+/// An invoice whose reminders were given up on, as listed to its owner.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub invoice_id: Uuid,
+    pub invoice_number: String,
+    pub client_name: String,
+    pub client_email: Option<String>,
+
+    /// Failed sends before it was given up on
+    pub attempts: u32,
+
+    /// Why the last send failed
+    pub last_error: String,
+
+    /// When the last send failed
+    pub failed_at: DateTime<Utc>,
+}
+
+impl DeadLetter {
+    /// The invoice's dead letter, if chasing it was given up on.
+    pub fn from_invoice(invoice: &Invoice) -> Option<Self> {
+        let retry = chase_retry(invoice).filter(ChaseRetry::is_dead_lettered)?;
+        Some(Self {
+            invoice_id: invoice.id,
+            invoice_number: invoice.invoice_number.clone(),
+            client_name: invoice.client_name.clone(),
+            client_email: invoice.client_email.clone(),
+            attempts: retry.attempts,
+            last_error: retry.last_error,
+            failed_at: retry.failed_at,
+        })
+    }
+}
+
+/// The notification telling the owner an invoice was dead-lettered.
+pub fn dead_letter_notification(invoice: &Invoice, retry: &ChaseRetry) -> CreateNotification {
+    CreateNotification {
+        kind: DEAD_LETTER_NOTIFICATION.to_string(),
+        title: format!("Reminders for invoice {} can't be sent", invoice.invoice_number),
+        body: Some(format!(
+            "The last attempt failed: {}. Fix the client's email address, then requeue the invoice to resume chasing.",
+            retry.last_error
+        )),
+        data: Some(json!({
+            "invoice_id": invoice.id,
+            "attempts": retry.attempts,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::repo::memory::sample_invoice;
+
+    #[test]
+    fn test_failures_back_off_then_dead_letter() {
+        let now = Utc::now();
+
+        let first = ChaseRetry::after_failure(None, "provider unavailable", now);
+        assert_eq!(first.attempts, 1);
+        assert_eq!(first.next_retry_at, Some(now + Duration::minutes(5)));
+        assert!(first.is_waiting(now + Duration::minutes(4)));
+        assert!(!first.is_waiting(now + Duration::minutes(5)));
+
+        let second = ChaseRetry::after_failure(Some(&first), "provider unavailable", now);
+        assert_eq!(second.next_retry_at, Some(now + Duration::minutes(10)));
+
+        let mut retry = second;
+        for _ in 2..MAX_SEND_ATTEMPTS {
+            retry = ChaseRetry::after_failure(Some(&retry), "provider unavailable", now);
+        }
+        assert_eq!(retry.attempts, MAX_SEND_ATTEMPTS);
+        assert!(retry.is_dead_lettered());
+        assert!(retry.is_waiting(now + Duration::days(365)));
+        assert_eq!(retry_delay(20), Duration::hours(12));
+    }
+
+    #[test]
+    fn test_undeliverable_send_is_dead_lettered_at_once() {
+        let undeliverable = anyhow::Error::new(Undeliverable("No client email".to_string()));
+        assert!(!is_retryable(&undeliverable));
+        assert!(is_retryable(&anyhow::anyhow!("provider unavailable")));
+
+        let mut invoice = sample_invoice(Uuid::new_v4(), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), Decimal::ONE);
+        let retry = ChaseRetry::dead_lettered(None, &undeliverable.to_string(), Utc::now());
+        assert_eq!(retry.attempts, 1);
+        assert!(retry.is_dead_lettered());
+
+        invoice.metadata = Some(retry.metadata_patch());
+        assert!(is_dead_lettered(&invoice));
+        let letter = DeadLetter::from_invoice(&invoice).unwrap();
+        assert_eq!(letter.last_error, "No client email");
+        assert_eq!(letter.attempts, 1);
+    }
+
+    #[test]
+    fn test_cleared_or_unreadable_record_is_ignored() {
+        let mut invoice = sample_invoice(Uuid::new_v4(), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), Decimal::ONE);
+        assert_eq!(chase_retry(&invoice), None);
+
+        invoice.metadata = Some(clear_patch());
+        assert_eq!(chase_retry(&invoice), None);
+
+        invoice.metadata = Some(json!({ "chase_retry": { "attempts": "many" } }));
+        assert_eq!(chase_retry(&invoice), None);
+
+        let retry = ChaseRetry::after_failure(None, "timeout", Utc::now());
+        invoice.metadata = Some(retry.metadata_patch());
+        assert_eq!(chase_retry(&invoice), Some(retry));
+    }
+}
@@ -0,0 +1,182 @@
+//! Business-hours sending of chase emails.
+//!
+//! Once a user sets their `timezone` (where their clients are), reminders
+//! only go out inside the send window: from `send_window_start_hour` to
+//! `send_window_end_hour` local time, on weekdays unless `send_on_weekends`
+//! is set. Chase work found outside the window is deferred to the next
+//! opening rather than dropped. Users without a timezone are chased
+//! whenever the worker runs.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::models::user_settings::UserSettings;
+
+/// Parses an IANA timezone name (e.g. "Europe/Berlin").
+///
+/// # Errors
+///
+/// Returns a user-facing message if the name is not a known timezone.
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| format!("unknown timezone: {}", name.trim()))
+}
+
+/// Local hours and days a user's reminders may be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendWindow {
+    timezone: Tz,
+
+    /// First local hour of the window
+    start_hour: u32,
+
+    /// Local hour the window closes (exclusive)
+    end_hour: u32,
+
+    /// Whether Saturdays and Sundays are in the window
+    weekends: bool,
+}
+
+impl SendWindow {
+    /// Creates a window.
+    ///
+    /// # Arguments
+    ///
+    /// * `timezone` - Timezone the hours are in
+    /// * `start_hour` - First local hour of the window (0-23)
+    /// * `end_hour` - Local hour the window closes (1-24, after `start_hour`)
+    /// * `weekends` - Whether Saturdays and Sundays are in the window
+    pub fn new(timezone: Tz, start_hour: u32, end_hour: u32, weekends: bool) -> Self {
+        let start_hour = start_hour.min(23);
+        Self {
+            timezone,
+            start_hour,
+            end_hour: end_hour.clamp(start_hour + 1, 24),
+            weekends,
+        }
+    }
+
+    /// The user's send window.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the user hasn't set a timezone, or it can't be
+    /// read, in which case reminders may go out at any time.
+    pub fn from_settings(settings: &UserSettings) -> Option<Self> {
+        let timezone = parse_timezone(settings.timezone.as_deref()?).ok()?;
+        Some(Self::new(
+            timezone,
+            u32::try_from(settings.send_window_start_hour).unwrap_or(0),
+            u32::try_from(settings.send_window_end_hour).unwrap_or(24),
+            settings.send_on_weekends,
+        ))
+    }
+
+    /// The date in the window's timezone at `now`.
+    pub fn local_date(&self, now: DateTime<Utc>) -> NaiveDate {
+        now.with_timezone(&self.timezone).date_naive()
+    }
+
+    /// Whether reminders may be sent at `now`.
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        self.is_sending_day(local.date_naive()) && (self.start_hour..self.end_hour).contains(&local.hour())
+    }
+
+    /// When reminders may next be sent: `now` if inside the window,
+    /// otherwise the next time it opens.
+    pub fn next_opening(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        if self.contains(now) {
+            return now;
+        }
+
+        let today = self.local_date(now);
+        (0..=7)
+            .map(|days| today + Duration::days(days))
+            .filter(|date| self.is_sending_day(*date))
+            .filter_map(|date| self.opening_on(date))
+            .find(|opening| *opening > now)
+            .unwrap_or(now)
+    }
+
+    /// Whether the window opens on the local `date` at all.
+    fn is_sending_day(&self, date: NaiveDate) -> bool {
+        self.weekends || !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+    }
+
+    /// When the window opens on the local `date`.
+    ///
+    /// An opening hour skipped by a daylight-saving change opens an hour
+    /// later.
+    fn opening_on(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        let start = date.and_hms_opt(self.start_hour, 0, 0)?;
+        self.timezone
+            .from_local_datetime(&start)
+            .earliest()
+            .or_else(|| self.timezone.from_local_datetime(&(start + Duration::hours(1))).earliest())
+            .map(|opening| opening.with_timezone(&Utc))
+    }
+}
+
+/// When a user's reminders may next be sent, `now` if at any time.
+pub fn next_send_time(settings: &UserSettings, now: DateTime<Utc>) -> DateTime<Utc> {
+    SendWindow::from_settings(settings).map_or(now, |window| window.next_opening(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    fn berlin_mornings() -> SendWindow {
+        SendWindow::new(parse_timezone("Europe/Berlin").unwrap(), 9, 12, false)
+    }
+
+    #[test]
+    fn test_window_follows_local_time() {
+        let window = berlin_mornings();
+
+        // 08:30 UTC on a Tuesday in summer is 10:30 in Berlin
+        assert!(window.contains(at("2024-06-04T08:30:00Z")));
+        // 10:30 UTC is 12:30, after the window closed
+        assert!(!window.contains(at("2024-06-04T10:30:00Z")));
+        // 22:00 UTC on Monday is already Tuesday 00:00 in Berlin
+        assert_eq!(window.local_date(at("2024-06-03T22:00:00Z")), NaiveDate::from_ymd_opt(2024, 6, 4).unwrap());
+    }
+
+    #[test]
+    fn test_weekend_and_night_sends_wait_for_the_next_weekday_morning() {
+        let window = berlin_mornings();
+
+        // 3am Sunday in Berlin waits until 9am Monday
+        assert_eq!(window.next_opening(at("2024-06-02T01:00:00Z")), at("2024-06-03T07:00:00Z"));
+        // After closing on Friday, it waits until Monday
+        assert_eq!(window.next_opening(at("2024-06-07T15:00:00Z")), at("2024-06-10T07:00:00Z"));
+        // Before opening on a weekday, it waits until the same morning
+        assert_eq!(window.next_opening(at("2024-06-04T05:00:00Z")), at("2024-06-04T07:00:00Z"));
+        // Inside the window, it goes now
+        let now = at("2024-06-04T08:30:00Z");
+        assert_eq!(window.next_opening(now), now);
+
+        let weekends = SendWindow::new(parse_timezone("Europe/Berlin").unwrap(), 9, 12, true);
+        assert_eq!(weekends.next_opening(at("2024-06-02T01:00:00Z")), at("2024-06-02T07:00:00Z"));
+    }
+
+    #[test]
+    fn test_users_without_a_timezone_are_chased_any_time() {
+        let mut settings = UserSettings::defaults(Uuid::new_v4());
+        let now = at("2024-06-02T01:00:00Z");
+        assert_eq!(SendWindow::from_settings(&settings), None);
+        assert_eq!(next_send_time(&settings, now), now);
+
+        settings.timezone = Some("America/New_York".to_string());
+        assert_eq!(next_send_time(&settings, now), at("2024-06-03T13:00:00Z"));
+
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+}
@@ -0,0 +1,444 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::clients::{
+    create_client, delete_client, find_client, link_invoices_by_email, list_clients, update_client,
+    validate_create, validate_update,
+};
+use crate::clients::interactions::{
+    self, create_interaction, delete_interaction, find_interaction, list_interactions, page_size,
+    update_interaction, TimelinePage,
+};
+use crate::clients::merge::{merge_clients, validate_merge};
+use crate::clients::statement::{build_statement, outstanding_invoices, StatementFormat};
+use crate::clients::stats::{find_client_stats, PaymentBehavior};
+use crate::invoices::find_invoice;
+use crate::invoices::pdf::{render_statement_pdf, PdfBranding};
+use crate::models::client::{Client, CreateClient, UpdateClient};
+use crate::models::client_merge::{ClientMerge, MergeClients};
+use crate::models::client_interaction::{
+    ClientInteraction, CreateClientInteraction, InteractionKind, UpdateClientInteraction,
+};
+use crate::models::share_grant::{ShareEntity, SharePermission};
+use crate::payment_methods::{instructions, methods_for_client};
+use crate::sharing::{resolve_access, Access};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// List clients endpoint handler.
+///
+/// Handles GET requests to `/api/clients`.
+pub async fn list_clients_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<Client>>, StatusCode> {
+    let clients = list_clients(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list clients for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(clients))
+}
+
+/// Create client endpoint handler.
+///
+/// Handles POST requests to `/api/clients`.
+pub async fn create_client_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateClient>,
+) -> Result<(StatusCode, Json<Client>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_create(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let client = create_client(&pool, user_id, request).await.map_err(|e| {
+        error!("Failed to create client for user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create client")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(client)))
+}
+
+/// Get client endpoint handler.
+///
+/// Handles GET requests to `/api/clients/:id`, including clients shared
+/// with the user.
+pub async fn get_client_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<Client>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load client {}: {}", client_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let access = resolve_access(&pool, user_id, ShareEntity::Client, client_id, SharePermission::Read)
+        .await
+        .map_err(internal_error)?;
+    let Access::Allowed { owner_id } = access else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let client = find_client(&pool, owner_id, client_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(client))
+}
+
+/// Update client endpoint handler.
+///
+/// Handles PUT requests to `/api/clients/:id`. Clients shared with the
+/// user can be updated with edit permission (403 otherwise); the change is
+/// made to the owner's client.
+pub async fn update_client_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+    Json(update): Json<UpdateClient>,
+) -> Result<Json<Client>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to update client {}: {}", client_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update client")
+    };
+
+    if let Err(message) = validate_update(&update) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    let access = resolve_access(&pool, user_id, ShareEntity::Client, client_id, SharePermission::Edit)
+        .await
+        .map_err(internal_error)?;
+    let Access::Allowed { owner_id } = access else {
+        return Err(error_response(StatusCode::FORBIDDEN, "client is shared with you read-only"));
+    };
+
+    let client = update_client(&pool, owner_id, client_id, update)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "client not found"))?;
+
+    Ok(Json(client))
+}
+
+/// Delete client endpoint handler.
+///
+/// Handles DELETE requests to `/api/clients/:id`. The client's invoices
+/// are kept.
+pub async fn delete_client_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_client(&pool, user_id, client_id).await.map_err(|e| {
+        error!("Failed to delete client {}: {}", client_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Client payment stats endpoint handler.
+///
+/// Handles GET requests to `/api/clients/:id/stats`: the client's latest
+/// payment stats (`null` until the worker has profiled them) and how
+/// chasing adapts to them.
+pub async fn client_stats_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load stats of client {}: {}", client_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    find_client(&pool, user_id, client_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let stats = find_client_stats(&pool, user_id, client_id).await.map_err(internal_error)?;
+    let behavior = PaymentBehavior::from_stats(stats.as_ref());
+
+    Ok(Json(json!({
+        "stats": stats,
+        "behavior": behavior,
+        "level_2_after_days": behavior.level_2_after_days(),
+        "reminder_tone": behavior.reminder_tone(),
+    })))
+}
+
+/// Query parameters for a client statement.
+#[derive(Debug, Default, Deserialize)]
+pub struct StatementQuery {
+    /// `json` (default) or `pdf`
+    #[serde(default)]
+    pub format: StatementFormat,
+
+    /// Statement date (default: today)
+    pub as_of: Option<NaiveDate>,
+}
+
+/// Client statement endpoint handler.
+///
+/// Handles GET requests to `/api/clients/:id/statement?format=json|pdf`:
+/// the client's open invoices with what is left on each and the totals
+/// outstanding and overdue per currency.
+pub async fn client_statement_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+    Query(query): Query<StatementQuery>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to build statement of client {}: {}", client_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to build statement")
+    };
+
+    let client = find_client(&pool, user_id, client_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "client not found"))?;
+    let invoices = outstanding_invoices(&pool, user_id, Some(client.id), client.email.as_deref())
+        .await
+        .map_err(internal_error)?;
+    let as_of = query.as_of.unwrap_or_else(|| Utc::now().date_naive());
+    let statement = build_statement(Some(client.id), &client.name, client.email.as_deref(), as_of, &invoices);
+
+    match query.format {
+        StatementFormat::Json => Ok(Json(statement).into_response()),
+        StatementFormat::Pdf => {
+            let mut branding = PdfBranding::for_user(&pool, user_id).await.map_err(internal_error)?;
+            let methods = methods_for_client(&pool, user_id, client.email.as_deref())
+                .await
+                .map_err(internal_error)?;
+            let references = statement
+                .invoices
+                .iter()
+                .map(|line| line.invoice_number.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            branding.payment_instructions = instructions(&methods, &references);
+
+            let pdf = render_statement_pdf(&statement, &branding).map_err(internal_error)?;
+            let disposition = format!("inline; filename=\"statement-{}.pdf\"", as_of);
+
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "application/pdf".to_string()),
+                    (header::CONTENT_DISPOSITION, disposition),
+                ],
+                pdf,
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Link invoices endpoint handler.
+///
+/// Handles POST requests to `/api/clients/:id/link-invoices`, linking the
+/// invoices without a client that were sent to the client's email.
+pub async fn link_invoices_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let invoice_ids = link_invoices_by_email(&pool, user_id, client_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to link invoices to client {}: {}", client_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({ "linked": invoice_ids.len(), "invoice_ids": invoice_ids })))
+}
+
+/// Merge clients endpoint handler.
+///
+/// Handles POST requests to `/api/clients/merge`: moves everything that
+/// referenced `duplicate_id` to `canonical_id` and deletes the duplicate.
+pub async fn merge_clients_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<MergeClients>,
+) -> Result<Json<ClientMerge>, (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_merge(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let (duplicate_id, canonical_id) = (request.duplicate_id, request.canonical_id);
+    let merge = merge_clients(&pool, user_id, request)
+        .await
+        .map_err(|e| {
+            error!("Failed to merge client {} into {}: {}", duplicate_id, canonical_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to merge clients")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "client not found"))?;
+
+    Ok(Json(merge))
+}
+
+/// Query parameters for a client's timeline.
+#[derive(Debug, Deserialize)]
+pub struct ListInteractionsQuery {
+    /// Only list entries of this kind
+    pub kind: Option<InteractionKind>,
+
+    /// Only list entries older than this one (a page's `next_before`)
+    pub before: Option<Uuid>,
+
+    /// Page size (default 50, at most 200)
+    pub limit: Option<i64>,
+}
+
+/// Checks that the client exists and belongs to the user.
+async fn require_client(pool: &PgPool, user_id: Uuid, client_id: Uuid) -> Result<(), (StatusCode, Json<Value>)> {
+    find_client(pool, user_id, client_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load client {}: {}", client_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load client")
+        })?
+        .map(|_| ())
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "client not found"))
+}
+
+/// Checks that a referenced invoice is the user's.
+async fn require_invoice(pool: &PgPool, user_id: Uuid, invoice_id: Option<Uuid>) -> Result<(), (StatusCode, Json<Value>)> {
+    let Some(invoice_id) = invoice_id else {
+        return Ok(());
+    };
+
+    find_invoice(pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load invoice")
+        })?
+        .map(|_| ())
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "invoice_id must reference one of your invoices"))
+}
+
+/// Client timeline endpoint handler.
+///
+/// Handles GET requests to `/api/clients/:id/interactions`: one page of the
+/// client's notes, calls, emails, meetings, chase emails and invoice views,
+/// newest first. Pass the page's `next_before` as `?before=` for the next.
+pub async fn list_interactions_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+    Query(query): Query<ListInteractionsQuery>,
+) -> Result<Json<TimelinePage>, (StatusCode, Json<Value>)> {
+    require_client(&pool, user_id, client_id).await?;
+
+    let page = list_interactions(&pool, user_id, client_id, query.kind, query.before, page_size(query.limit))
+        .await
+        .map_err(|e| {
+            error!("Failed to list interactions of client {}: {}", client_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to list interactions")
+        })?;
+
+    Ok(Json(page))
+}
+
+/// Log interaction endpoint handler.
+///
+/// Handles POST requests to `/api/clients/:id/interactions`, adding a
+/// note, call, email or meeting to the client's timeline.
+pub async fn create_interaction_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+    Json(request): Json<CreateClientInteraction>,
+) -> Result<(StatusCode, Json<ClientInteraction>), (StatusCode, Json<Value>)> {
+    if let Err(message) = interactions::validate_create(&request, Utc::now()) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    require_client(&pool, user_id, client_id).await?;
+    require_invoice(&pool, user_id, request.invoice_id).await?;
+
+    let interaction = create_interaction(&pool, user_id, client_id, request).await.map_err(|e| {
+        error!("Failed to log interaction with client {}: {}", client_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to log interaction")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(interaction)))
+}
+
+/// Update interaction endpoint handler.
+///
+/// Handles PUT requests to `/api/clients/:id/interactions/:interaction_id`.
+/// Automatic entries can't be changed (422).
+pub async fn update_interaction_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path((client_id, interaction_id)): Path<(Uuid, Uuid)>,
+    Json(update): Json<UpdateClientInteraction>,
+) -> Result<Json<ClientInteraction>, (StatusCode, Json<Value>)> {
+    let not_found = || error_response(StatusCode::NOT_FOUND, "interaction not found");
+    let current = find_interaction(&pool, user_id, client_id, interaction_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load interaction {}: {}", interaction_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load interaction")
+        })?
+        .ok_or_else(not_found)?;
+
+    if let Err(message) = interactions::validate_update(&current, &update, Utc::now()) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    require_invoice(&pool, user_id, update.invoice_id).await?;
+
+    let interaction = update_interaction(&pool, user_id, client_id, interaction_id, update)
+        .await
+        .map_err(|e| {
+            error!("Failed to update interaction {}: {}", interaction_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update interaction")
+        })?
+        .ok_or_else(not_found)?;
+
+    Ok(Json(interaction))
+}
+
+/// Delete interaction endpoint handler.
+///
+/// Handles DELETE requests to `/api/clients/:id/interactions/:interaction_id`.
+/// Only logged entries can be deleted; automatic ones return 404.
+pub async fn delete_interaction_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path((client_id, interaction_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_interaction(&pool, user_id, client_id, interaction_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to delete interaction {}: {}", interaction_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,484 @@
+//! Client interaction timelines.
+//!
+//! Each client has a timeline of what happened with them, newest first:
+//! notes, calls, emails and meetings the user logs by hand, and entries
+//! GigPilot records itself when the worker sends a chase email or the
+//! client opens an invoice in the portal or on its pay page. Automatic
+//! entries find their client through the invoice's `client_id` and can't
+//! be changed or deleted.
+//!
+//! Timelines are paged with a cursor: each page returns the ID to pass as
+//! `before` for the next, older page.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::models::client_interaction::{
+    ClientInteraction, CreateClientInteraction, InteractionKind, UpdateClientInteraction,
+};
+use crate::models::correspondence::Correspondence;
+use crate::models::invoice::Invoice;
+
+/// Longest summary accepted.
+pub const MAX_SUMMARY_LENGTH: usize = 500;
+
+/// Longest body accepted.
+pub const MAX_BODY_LENGTH: usize = 10_000;
+
+/// Entries per page when the request does not say.
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// Most entries per page.
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Views of one invoice within this many minutes are recorded once.
+pub const VIEW_INTERVAL_MINUTES: i64 = 60;
+
+/// How far in the future a logged interaction may be dated (clock skew).
+const FUTURE_TOLERANCE_MINUTES: i64 = 5;
+
+/// Validates the fields of a manual interaction.
+fn validate_fields(
+    kind: InteractionKind,
+    summary: &str,
+    body: Option<&str>,
+    occurred_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    if kind.is_automatic() {
+        return Err("kind must be note, call, email or meeting".to_string());
+    }
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err("summary is required".to_string());
+    }
+    if summary.chars().count() > MAX_SUMMARY_LENGTH {
+        return Err(format!("summary must be at most {} characters", MAX_SUMMARY_LENGTH));
+    }
+    if body.is_some_and(|b| b.chars().count() > MAX_BODY_LENGTH) {
+        return Err(format!("body must be at most {} characters", MAX_BODY_LENGTH));
+    }
+    if occurred_at.is_some_and(|at| at > now + Duration::minutes(FUTURE_TOLERANCE_MINUTES)) {
+        return Err("occurred_at can't be in the future".to_string());
+    }
+    Ok(())
+}
+
+/// Validates a manual interaction creation request.
+///
+/// # Returns
+///
+/// Returns a message describing the first problem found.
+pub fn validate_create(request: &CreateClientInteraction, now: DateTime<Utc>) -> Result<(), String> {
+    validate_fields(
+        request.kind,
+        &request.summary,
+        request.body.as_deref(),
+        request.occurred_at,
+        now,
+    )
+}
+
+/// Validates an update of a logged interaction.
+///
+/// # Returns
+///
+/// Returns a message describing the first problem found, including an
+/// attempt to change an automatic entry.
+pub fn validate_update(
+    current: &ClientInteraction,
+    update: &UpdateClientInteraction,
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    if current.kind.is_automatic() {
+        return Err("automatic entries can't be changed".to_string());
+    }
+    validate_fields(
+        update.kind.unwrap_or(current.kind),
+        update.summary.as_deref().unwrap_or(&current.summary),
+        update.body.as_deref(),
+        update.occurred_at,
+        now,
+    )
+}
+
+/// Page size for a requested limit, clamped to `1..=MAX_PAGE_SIZE`.
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// One page of a client's timeline.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelinePage {
+    /// Entries, newest first
+    pub interactions: Vec<ClientInteraction>,
+
+    /// Pass as `before` to get the next (older) page; `None` on the last page
+    pub next_before: Option<Uuid>,
+}
+
+impl TimelinePage {
+    /// Builds a page from up to `limit + 1` fetched entries; the extra entry
+    /// only tells that another page follows.
+    pub fn new(mut interactions: Vec<ClientInteraction>, limit: i64) -> Self {
+        let limit = usize::try_from(limit).unwrap_or(0);
+        let next_before = if interactions.len() > limit {
+            interactions.truncate(limit);
+            interactions.last().map(|i| i.id)
+        } else {
+            None
+        };
+
+        Self { interactions, next_before }
+    }
+}
+
+/// Lists one page of a client's timeline.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `client_id` - The client
+/// * `kind` - Only list entries of this kind
+/// * `before` - Only list entries older than this entry (the previous
+///   page's `next_before`)
+/// * `limit` - Page size (see [`page_size`])
+pub async fn list_interactions(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    kind: Option<InteractionKind>,
+    before: Option<Uuid>,
+    limit: i64,
+) -> Result<TimelinePage, anyhow::Error> {
+    let interactions = sqlx::query_as::<_, ClientInteraction>(
+        r#"
+        SELECT * FROM client_interactions
+        WHERE user_id = $1 AND client_id = $2
+            AND ($3::varchar IS NULL OR kind = $3)
+            AND ($4::uuid IS NULL OR (occurred_at, id) < (
+                SELECT occurred_at, id FROM client_interactions WHERE id = $4 AND user_id = $1
+            ))
+        ORDER BY occurred_at DESC, id DESC
+        LIMIT $5
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(kind)
+    .bind(before)
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(TimelinePage::new(interactions, limit))
+}
+
+/// Loads one entry of a client's timeline.
+pub async fn find_interaction(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    interaction_id: Uuid,
+) -> Result<Option<ClientInteraction>, anyhow::Error> {
+    let interaction = sqlx::query_as::<_, ClientInteraction>(
+        "SELECT * FROM client_interactions WHERE id = $1 AND user_id = $2 AND client_id = $3",
+    )
+    .bind(interaction_id)
+    .bind(user_id)
+    .bind(client_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(interaction)
+}
+
+/// Logs a manual interaction with a client.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `client_id` - The client (ownership already checked)
+/// * `request` - Validated creation request
+///
+/// # Returns
+///
+/// Returns the new `ClientInteraction`.
+pub async fn create_interaction(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    request: CreateClientInteraction,
+) -> Result<ClientInteraction, anyhow::Error> {
+    let interaction = sqlx::query_as::<_, ClientInteraction>(
+        r#"
+        INSERT INTO client_interactions (user_id, client_id, invoice_id, kind, summary, body, occurred_at, metadata)
+        VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, NOW()), $8)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(request.invoice_id)
+    .bind(request.kind)
+    .bind(request.summary.trim())
+    .bind(request.body.as_deref().map(str::trim).filter(|b| !b.is_empty()))
+    .bind(request.occurred_at)
+    .bind(request.metadata)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(interaction)
+}
+
+/// Updates a logged interaction.
+///
+/// # Returns
+///
+/// Returns the updated `ClientInteraction`, or `None` if no manual entry
+/// matched.
+pub async fn update_interaction(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    interaction_id: Uuid,
+    update: UpdateClientInteraction,
+) -> Result<Option<ClientInteraction>, anyhow::Error> {
+    let interaction = sqlx::query_as::<_, ClientInteraction>(
+        r#"
+        UPDATE client_interactions
+        SET kind = COALESCE($4, kind),
+            summary = COALESCE($5, summary),
+            body = COALESCE($6, body),
+            invoice_id = COALESCE($7, invoice_id),
+            occurred_at = COALESCE($8, occurred_at),
+            metadata = COALESCE($9, metadata)
+        WHERE id = $1 AND user_id = $2 AND client_id = $3
+            AND kind IN ('note', 'call', 'email', 'meeting')
+        RETURNING *
+        "#,
+    )
+    .bind(interaction_id)
+    .bind(user_id)
+    .bind(client_id)
+    .bind(update.kind)
+    .bind(update.summary.as_deref().map(str::trim))
+    .bind(update.body.as_deref().map(str::trim))
+    .bind(update.invoice_id)
+    .bind(update.occurred_at)
+    .bind(update.metadata)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(interaction)
+}
+
+/// Deletes a logged interaction.
+///
+/// # Returns
+///
+/// Returns `true` if an entry was deleted, `false` if no manual entry
+/// matched.
+pub async fn delete_interaction(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    interaction_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM client_interactions
+        WHERE id = $1 AND user_id = $2 AND client_id = $3
+            AND kind IN ('note', 'call', 'email', 'meeting')
+        "#,
+    )
+    .bind(interaction_id)
+    .bind(user_id)
+    .bind(client_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Records an automatic entry on the timeline of an invoice's client.
+///
+/// Nothing is recorded if the invoice has no `client_id`. Views of an invoice already recorded
+/// within [`VIEW_INTERVAL_MINUTES`] are skipped, so reloading a page
+/// doesn't flood the timeline.
+async fn record_invoice_interaction<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    kind: InteractionKind,
+    summary: &str,
+    metadata: Value,
+) -> Result<(), anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO client_interactions (user_id, client_id, invoice_id, kind, summary, metadata)
+        SELECT i.user_id, c.id, i.id, $3, $4, $5
+        FROM invoices i
+        JOIN clients c ON c.id = i.client_id AND c.user_id = i.user_id AND c.is_deleted = false
+        WHERE i.id = $2 AND i.user_id = $1
+            AND NOT ($3 = 'invoice_viewed' AND EXISTS (
+                SELECT 1 FROM client_interactions v
+                WHERE v.invoice_id = i.id AND v.kind = 'invoice_viewed'
+                    AND v.occurred_at > NOW() - make_interval(mins => $6)
+            ))
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_id)
+    .bind(kind)
+    .bind(summary)
+    .bind(metadata)
+    .bind(VIEW_INTERVAL_MINUTES as i32)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a chase email on the timeline of the invoice's client.
+///
+/// # Arguments
+///
+/// * `executor` - Connection or transaction recording the email
+/// * `user_id` - ID of the invoice owner
+/// * `email` - The recorded email
+pub async fn record_chase_email<'e, E>(executor: E, user_id: Uuid, email: &Correspondence) -> Result<(), anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let tone = email.metadata.as_ref().and_then(|m| m.get("tone")).cloned();
+    let summary = match &email.subject {
+        Some(subject) => format!("Chase email sent: {}", subject),
+        None => "Chase email sent".to_string(),
+    };
+
+    record_invoice_interaction(
+        executor,
+        user_id,
+        email.invoice_id,
+        InteractionKind::ChaseEmail,
+        &summary.chars().take(MAX_SUMMARY_LENGTH).collect::<String>(),
+        json!({ "correspondence_id": email.id, "tone": tone }),
+    )
+    .await
+}
+
+/// Where a client opened an invoice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewedVia {
+    /// The client portal
+    Portal,
+
+    /// The public pay page linked from emails
+    PayPage,
+}
+
+/// Records that a client opened an invoice.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `invoice` - The invoice that was opened
+/// * `via` - Where it was opened
+pub async fn record_invoice_view(pool: &PgPool, invoice: &Invoice, via: ViewedVia) -> Result<(), anyhow::Error> {
+    let (summary, via) = match via {
+        ViewedVia::Portal => (format!("Viewed invoice {} in the portal", invoice.invoice_number), "portal"),
+        ViewedVia::PayPage => (format!("Viewed invoice {} on its pay page", invoice.invoice_number), "pay_page"),
+    };
+
+    record_invoice_interaction(
+        pool,
+        invoice.user_id,
+        invoice.id,
+        InteractionKind::InvoiceViewed,
+        &summary,
+        json!({ "via": via }),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(kind: InteractionKind, minutes_ago: i64) -> ClientInteraction {
+        let at = Utc::now() - Duration::minutes(minutes_ago);
+        ClientInteraction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_id: Uuid::new_v4(),
+            invoice_id: None,
+            kind,
+            summary: "Called about INV-0042".to_string(),
+            body: None,
+            occurred_at: at,
+            metadata: None,
+            created_at: at,
+            updated_at: at,
+        }
+    }
+
+    #[test]
+    fn test_validate_create() {
+        let now = Utc::now();
+        let request = CreateClientInteraction {
+            kind: InteractionKind::Call,
+            summary: "Promised to pay on Friday".to_string(),
+            body: None,
+            invoice_id: None,
+            occurred_at: Some(now - Duration::days(1)),
+            metadata: None,
+        };
+        assert!(validate_create(&request, now).is_ok());
+        assert!(validate_create(&CreateClientInteraction { summary: " ".to_string(), ..request.clone() }, now).is_err());
+        assert!(validate_create(
+            &CreateClientInteraction { kind: InteractionKind::ChaseEmail, ..request.clone() },
+            now
+        )
+        .is_err());
+        assert!(validate_create(
+            &CreateClientInteraction { occurred_at: Some(now + Duration::hours(2)), ..request },
+            now
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_automatic_entries_cant_be_updated() {
+        let update = UpdateClientInteraction {
+            summary: Some("Edited".to_string()),
+            ..Default::default()
+        };
+        assert!(validate_update(&interaction(InteractionKind::Note, 5), &update, Utc::now()).is_ok());
+        assert!(validate_update(&interaction(InteractionKind::InvoiceViewed, 5), &update, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_timeline_page() {
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(10_000)), MAX_PAGE_SIZE);
+
+        let fetched: Vec<_> = (0..3).map(|i| interaction(InteractionKind::Note, i)).collect();
+        let page = TimelinePage::new(fetched.clone(), 2);
+        assert_eq!(page.interactions.len(), 2);
+        assert_eq!(page.next_before, Some(fetched[1].id));
+
+        let last = TimelinePage::new(fetched, 3);
+        assert_eq!(last.interactions.len(), 3);
+        assert_eq!(last.next_before, None);
+    }
+}
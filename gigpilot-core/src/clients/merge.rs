@@ -0,0 +1,341 @@
+//! Merging duplicate clients.
+//!
+//! Imports and devices pushing offline can leave the same client on file
+//! twice. A merge moves everything that referenced the duplicate to the
+//! canonical client in one transaction: invoices (and with them their
+//! payments), projects, proposals, contracts, disputes, portal links, the
+//! interaction timeline and the estimator's embeddings. Fields the canonical
+//! client lacks are taken from the duplicate, which is then soft-deleted
+//! with `metadata.merged_into` naming the canonical client.
+//!
+//! Every re-pointed invoice and project, both clients and the merge itself
+//! are recorded for sync, so devices still holding the duplicate can move
+//! their local references before dropping it.
+
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::clients::record_client_change;
+use crate::invoices::history::apply_current_audit_context;
+use crate::models::client::Client;
+use crate::models::client_merge::{ClientMerge, MergeClients};
+use crate::models::invoice::Invoice;
+use crate::models::project::Project;
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+
+/// Synced table the merges are delivered through.
+pub const MERGES_TABLE: &str = "client_merges";
+
+/// Tables whose `client_id` is re-pointed without being synced.
+const UNSYNCED_REFERENCES: &[&str] = &[
+    "proposals",
+    "contracts",
+    "invoice_disputes",
+    "client_portal_tokens",
+    "client_portal_events",
+    "client_interactions",
+    "retainers",
+];
+
+/// Validates a merge request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing why
+/// it is not.
+pub fn validate_merge(request: &MergeClients) -> Result<(), String> {
+    if request.duplicate_id == request.canonical_id {
+        return Err("duplicate_id and canonical_id must be different clients".to_string());
+    }
+    Ok(())
+}
+
+/// Merges a duplicate client into a canonical one.
+///
+/// Both clients are locked for the duration of the merge, so a concurrent
+/// merge or edit of either waits for it.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user owning both clients
+/// * `request` - The clients to merge (see [`validate_merge`])
+///
+/// # Returns
+///
+/// Returns the recorded `ClientMerge`, or `None` if either client does not
+/// exist, is deleted, or belongs to another user.
+///
+/// # Errors
+///
+/// Returns an error if a query fails; nothing is changed then.
+pub async fn merge_clients(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: MergeClients,
+) -> Result<Option<ClientMerge>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    // Lock in a fixed order so two opposite merges can't deadlock
+    let locked = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT id FROM clients
+        WHERE id = ANY($1) AND user_id = $2 AND is_deleted = false
+        ORDER BY id
+        FOR UPDATE
+        "#,
+    )
+    .bind(vec![request.duplicate_id, request.canonical_id])
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if locked.len() != 2 {
+        return Ok(None);
+    }
+
+    let (invoice_ids, payments_moved) = move_invoices(&mut tx, user_id, &request).await?;
+    let project_ids = move_projects(&mut tx, user_id, &request).await?;
+
+    for table in UNSYNCED_REFERENCES {
+        sqlx::query(&format!(
+            "UPDATE {} SET client_id = $3 WHERE client_id = $1 AND user_id = $2",
+            table
+        ))
+        .bind(request.duplicate_id)
+        .bind(user_id)
+        .bind(request.canonical_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let embeddings_moved = sqlx::query(
+        r#"
+        UPDATE embeddings SET entity_id = $3
+        WHERE entity_type = 'client' AND entity_id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(request.duplicate_id)
+    .bind(user_id)
+    .bind(request.canonical_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    // Payment behaviour is recomputed from the moved invoices on the next refresh
+    sqlx::query("DELETE FROM client_stats WHERE client_id = $1")
+        .bind(request.duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+
+    fill_canonical(&mut tx, user_id, &request).await?;
+    retire_duplicate(&mut tx, user_id, &request).await?;
+
+    let merge = sqlx::query_as::<_, ClientMerge>(
+        r#"
+        INSERT INTO client_merges (
+            user_id, duplicate_client_id, canonical_client_id,
+            invoices_moved, payments_moved, projects_moved, embeddings_moved, details
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(request.duplicate_id)
+    .bind(request.canonical_id)
+    .bind(invoice_ids.len() as i32)
+    .bind(payments_moved as i32)
+    .bind(project_ids.len() as i32)
+    .bind(embeddings_moved as i32)
+    .bind(json!({ "invoice_ids": invoice_ids, "project_ids": project_ids }))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        user_id,
+        MERGES_TABLE,
+        merge.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&merge)?,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(merge))
+}
+
+/// Re-points the duplicate's invoices and records them for sync.
+///
+/// Invoices keep the client name and email they were issued with.
+///
+/// # Returns
+///
+/// Returns the IDs of the moved invoices and the number of their payments.
+async fn move_invoices(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    request: &MergeClients,
+) -> Result<(Vec<Uuid>, i64), anyhow::Error> {
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET client_id = $3, last_modified = NOW()
+        WHERE client_id = $1 AND user_id = $2
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(request.duplicate_id)
+    .bind(user_id)
+    .bind(request.canonical_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for invoice in &invoices {
+        record_server_change(
+            &mut **tx,
+            user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(invoice)?,
+        )
+        .await?;
+    }
+
+    let invoice_ids: Vec<Uuid> = invoices.iter().map(|invoice| invoice.id).collect();
+    let payments = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM payments WHERE invoice_id = ANY($1) AND user_id = $2",
+    )
+    .bind(&invoice_ids)
+    .bind(user_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok((invoice_ids, payments))
+}
+
+/// Re-points the duplicate's projects and records them for sync.
+///
+/// # Returns
+///
+/// Returns the IDs of the moved projects.
+async fn move_projects(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    request: &MergeClients,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let projects = sqlx::query_as::<_, Project>(
+        r#"
+        UPDATE projects
+        SET client_id = $3, last_modified = NOW()
+        WHERE client_id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(request.duplicate_id)
+    .bind(user_id)
+    .bind(request.canonical_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for project in &projects {
+        record_server_change(
+            &mut **tx,
+            user_id,
+            "projects",
+            project.id,
+            SyncOperation::Update,
+            &serde_json::to_value(project)?,
+        )
+        .await?;
+    }
+
+    Ok(projects.iter().map(|project| project.id).collect())
+}
+
+/// Fills the canonical client's missing fields from the duplicate.
+async fn fill_canonical(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    request: &MergeClients,
+) -> Result<(), anyhow::Error> {
+    let canonical = sqlx::query_as::<_, Client>(
+        r#"
+        UPDATE clients c
+        SET email = COALESCE(c.email, d.email),
+            phone = COALESCE(c.phone, d.phone),
+            address = COALESCE(c.address, d.address),
+            tax_id = COALESCE(c.tax_id, d.tax_id),
+            notes = COALESCE(c.notes, d.notes),
+            payment_terms_days = COALESCE(c.payment_terms_days, d.payment_terms_days),
+            last_modified = NOW()
+        FROM clients d
+        WHERE c.id = $3 AND c.user_id = $2 AND d.id = $1 AND d.user_id = $2
+        RETURNING c.*
+        "#,
+    )
+    .bind(request.duplicate_id)
+    .bind(user_id)
+    .bind(request.canonical_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_client_change(tx, &canonical, SyncOperation::Update).await
+}
+
+/// Soft-deletes the duplicate, noting which client it was merged into.
+async fn retire_duplicate(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    request: &MergeClients,
+) -> Result<(), anyhow::Error> {
+    let duplicate = sqlx::query_as::<_, Client>(
+        r#"
+        UPDATE clients
+        SET is_deleted = true,
+            metadata = COALESCE(metadata, '{}'::jsonb) || jsonb_build_object('merged_into', $3::text),
+            last_modified = NOW()
+        WHERE id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(request.duplicate_id)
+    .bind(user_id)
+    .bind(request.canonical_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_client_change(tx, &duplicate, SyncOperation::Delete).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_merge() {
+        let client = Uuid::new_v4();
+
+        assert!(validate_merge(&MergeClients {
+            duplicate_id: Uuid::new_v4(),
+            canonical_id: client,
+        })
+        .is_ok());
+        assert!(validate_merge(&MergeClients {
+            duplicate_id: client,
+            canonical_id: client,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,529 @@
+//! Clients the user bills.
+//!
+//! Clients are created through the API or pushed from devices, and every
+//! server-side change is recorded for sync. Invoices link to a client via
+//! `client_id`; deleting a client soft-deletes it and leaves its invoices
+//! untouched, while merging a duplicate into another client moves them
+//! (see [`merge`]). Invoices issued before clients existed carry only the
+//! client's name and email until they are linked with
+//! [`link_invoices_by_email`].
+
+pub mod handlers;
+pub mod interactions;
+pub mod merge;
+pub mod statement;
+pub mod stats;
+
+use serde_json::Value;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::invoices::history::apply_current_audit_context;
+use crate::models::client::{Client, CreateClient, UpdateClient};
+use crate::models::invoice::Invoice;
+use crate::models::sync_change::SyncOperation;
+use crate::portal::INVOICE_COLUMNS;
+use crate::sync::server::record_server_change;
+use crate::text::non_blank;
+
+/// Validates an optional email field.
+fn validate_email(field: &str, email: Option<&str>) -> Result<(), String> {
+    match email {
+        Some(email) if !email.trim().is_empty() && !email.contains('@') => {
+            Err(format!("{} must be an email address", field))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates optional payment terms.
+fn validate_payment_terms(days: Option<i32>) -> Result<(), String> {
+    match days {
+        Some(days) if !(0..=365).contains(&days) => {
+            Err("payment_terms_days must be between 0 and 365".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates a client creation request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create(request: &CreateClient) -> Result<(), String> {
+    if request.name.trim().is_empty() {
+        return Err("name is required".to_string());
+    }
+    validate_email("email", request.email.as_deref())?;
+    validate_payment_terms(request.payment_terms_days)
+}
+
+/// Validates a client update request.
+pub fn validate_update(update: &UpdateClient) -> Result<(), String> {
+    if matches!(&update.name, Some(name) if name.trim().is_empty()) {
+        return Err("name must not be empty".to_string());
+    }
+    validate_email("email", update.email.as_deref())?;
+    validate_payment_terms(update.payment_terms_days)
+}
+
+/// Creates a client and records it for sync.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `request` - The client to create (see [`validate_create`])
+///
+/// # Returns
+///
+/// Returns the stored `Client`, or an error.
+pub async fn create_client(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateClient,
+) -> Result<Client, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    let client = insert_client(&mut tx, user_id, request).await?;
+    tx.commit().await?;
+
+    Ok(client)
+}
+
+/// Inserts a client in the caller's transaction and records it for sync.
+///
+/// Used by [`create_client`] and by account imports.
+pub async fn insert_client(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    request: CreateClient,
+) -> Result<Client, anyhow::Error> {
+    let client = sqlx::query_as::<_, Client>(
+        r#"
+        INSERT INTO clients (
+            user_id, name, email, phone, address, tax_id, notes, payment_terms_days, metadata
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(request.name.trim())
+    .bind(non_blank(request.email))
+    .bind(non_blank(request.phone))
+    .bind(non_blank(request.address))
+    .bind(non_blank(request.tax_id))
+    .bind(request.notes)
+    .bind(request.payment_terms_days)
+    .bind(request.metadata)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_client_change(tx, &client, SyncOperation::Insert).await?;
+
+    Ok(client)
+}
+
+/// Lists a user's live clients by name.
+pub async fn list_clients(pool: &PgPool, user_id: Uuid) -> Result<Vec<Client>, anyhow::Error> {
+    let clients = sqlx::query_as::<_, Client>(
+        r#"
+        SELECT * FROM clients
+        WHERE user_id = $1 AND is_deleted = false
+        ORDER BY lower(name), created_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(clients)
+}
+
+/// Loads a single live client owned by the given user.
+///
+/// # Returns
+///
+/// Returns `Some(Client)` if found, `None` if it does not exist, is
+/// deleted, or belongs to another user.
+pub async fn find_client(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+) -> Result<Option<Client>, anyhow::Error> {
+    let client = sqlx::query_as::<_, Client>(
+        "SELECT * FROM clients WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(client_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(client)
+}
+
+/// Updates a client and records the change for sync.
+///
+/// Fields left as `None` keep their current value. Invoices already issued
+/// to the client keep the name and email they were issued with.
+///
+/// # Returns
+///
+/// Returns the updated `Client`, or `None` if it does not exist.
+pub async fn update_client(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    update: UpdateClient,
+) -> Result<Option<Client>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let client = sqlx::query_as::<_, Client>(
+        r#"
+        UPDATE clients
+        SET name = COALESCE($3, name),
+            email = COALESCE($4, email),
+            phone = COALESCE($5, phone),
+            address = COALESCE($6, address),
+            tax_id = COALESCE($7, tax_id),
+            notes = COALESCE($8, notes),
+            payment_terms_days = COALESCE($9, payment_terms_days),
+            metadata = COALESCE($10, metadata),
+            last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(client_id)
+    .bind(user_id)
+    .bind(update.name.map(|n| n.trim().to_string()))
+    .bind(non_blank(update.email))
+    .bind(non_blank(update.phone))
+    .bind(non_blank(update.address))
+    .bind(non_blank(update.tax_id))
+    .bind(update.notes)
+    .bind(update.payment_terms_days)
+    .bind(update.metadata)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(client) = &client {
+        record_client_change(&mut tx, client, SyncOperation::Update).await?;
+    }
+    tx.commit().await?;
+
+    Ok(client)
+}
+
+/// Soft-deletes a client and records the deletion for sync.
+///
+/// # Returns
+///
+/// Returns `true` if a client was deleted, `false` if none matched.
+pub async fn delete_client(pool: &PgPool, user_id: Uuid, client_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let client = sqlx::query_as::<_, Client>(
+        r#"
+        UPDATE clients SET is_deleted = true, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(client_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(client) = &client {
+        record_client_change(&mut tx, client, SyncOperation::Delete).await?;
+    }
+    tx.commit().await?;
+
+    Ok(client.is_some())
+}
+
+/// Links a client's invoices that were issued before clients existed.
+///
+/// Invoices without a `client_id` that were sent to the client's email
+/// are linked to the client and recorded for sync. Nothing is linked if
+/// the client has no email, or if another live client of the user has the
+/// same email, since the invoices could belong to either.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `client_id` - ID of the client to link invoices to
+///
+/// # Returns
+///
+/// Returns the IDs of the linked invoices, or `None` if the client does
+/// not exist.
+pub async fn link_invoices_by_email(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+) -> Result<Option<Vec<Uuid>>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let client = sqlx::query_as::<_, Client>(
+        "SELECT * FROM clients WHERE id = $1 AND user_id = $2 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(client_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(client) = client else {
+        return Ok(None);
+    };
+
+    let invoices = sqlx::query_as::<_, Invoice>(&format!(
+        r#"
+        UPDATE invoices
+        SET client_id = $1, last_modified = NOW()
+        WHERE user_id = $2
+            AND client_id IS NULL
+            AND lower(client_email) = lower($3)
+            AND NOT EXISTS (
+                SELECT 1 FROM clients other
+                WHERE other.user_id = $2
+                    AND other.id <> $1
+                    AND other.is_deleted = false
+                    AND lower(other.email) = lower($3)
+            )
+        RETURNING {}
+        "#,
+        INVOICE_COLUMNS
+    ))
+    .bind(client.id)
+    .bind(user_id)
+    .bind(client.email.as_deref().filter(|email| !email.trim().is_empty()))
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for invoice in &invoices {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(invoice)?,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Some(invoices.iter().map(|invoice| invoice.id).collect()))
+}
+
+/// Checks that a client referenced by an invoice belongs to the user.
+///
+/// The foreign key alone would accept another user's client.
+///
+/// # Errors
+///
+/// Returns an error if the client does not exist, is deleted, or belongs
+/// to another user.
+pub async fn check_client<'e, E>(executor: E, user_id: Uuid, client_id: Uuid) -> Result<(), anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let exists = sqlx::query_scalar::<_, i32>(
+        "SELECT 1 FROM clients WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(client_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?;
+
+    if exists.is_none() {
+        anyhow::bail!("Client {} not found", client_id);
+    }
+    Ok(())
+}
+
+/// Loads the payment terms of a user's client.
+///
+/// # Returns
+///
+/// Returns the client's terms in days, or `None` if it has none (or does
+/// not exist), in which case the user's default terms apply.
+pub async fn client_payment_terms<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    client_id: Uuid,
+) -> Result<Option<i32>, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let terms = sqlx::query_scalar::<_, Option<i32>>(
+        "SELECT payment_terms_days FROM clients WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(client_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(terms.flatten())
+}
+
+/// Records a server-side client change for sync.
+async fn record_client_change(
+    tx: &mut Transaction<'_, Postgres>,
+    client: &Client,
+    operation: SyncOperation,
+) -> Result<(), anyhow::Error> {
+    record_server_change(
+        &mut **tx,
+        client.user_id,
+        "clients",
+        client.id,
+        operation,
+        &serde_json::to_value(client)?,
+    )
+    .await
+}
+
+/// Reads an optional pushed text field.
+fn pushed_str<'a>(data: &'a Value, field: &str) -> Option<&'a str> {
+    data.get(field).and_then(|v| v.as_str())
+}
+
+/// Reads pushed payment terms (range-checked by the sync schema).
+fn pushed_terms(data: &Value) -> Option<i32> {
+    data.get("payment_terms_days")
+        .and_then(|v| v.as_i64())
+        .and_then(|days| i32::try_from(days).ok())
+}
+
+/// Inserts a client pushed by a device.
+///
+/// # Errors
+///
+/// Returns an error if the name is missing.
+pub async fn apply_pushed_insert(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+    version_vector: Option<&Value>,
+) -> Result<(), anyhow::Error> {
+    let name = pushed_str(data, "name")
+        .ok_or_else(|| anyhow::anyhow!("Missing name"))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO clients (
+            id, user_id, name, email, phone, address, tax_id, notes,
+            payment_terms_days, metadata, last_modified, version_vector
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW(), $11)
+        "#,
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .bind(name)
+    .bind(pushed_str(data, "email"))
+    .bind(pushed_str(data, "phone"))
+    .bind(pushed_str(data, "address"))
+    .bind(pushed_str(data, "tax_id"))
+    .bind(pushed_str(data, "notes"))
+    .bind(pushed_terms(data))
+    .bind(data.get("metadata"))
+    .bind(version_vector)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Updates a client from a device push.
+///
+/// Like invoice pushes, optional fields missing from the payload are
+/// cleared; devices send the whole record.
+pub async fn apply_pushed_update(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+) -> Result<(), anyhow::Error> {
+    sqlx::query(
+        r#"
+        UPDATE clients
+        SET
+            name = COALESCE($3, name),
+            email = $4,
+            phone = $5,
+            address = $6,
+            tax_id = $7,
+            notes = $8,
+            payment_terms_days = $9,
+            metadata = $10,
+            last_modified = NOW(),
+            version_vector = $11
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        "#,
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .bind(pushed_str(data, "name"))
+    .bind(pushed_str(data, "email"))
+    .bind(pushed_str(data, "phone"))
+    .bind(pushed_str(data, "address"))
+    .bind(pushed_str(data, "tax_id"))
+    .bind(pushed_str(data, "notes"))
+    .bind(pushed_terms(data))
+    .bind(data.get("metadata"))
+    .bind(data.get("version_vector"))
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &str, email: Option<&str>) -> CreateClient {
+        CreateClient {
+            name: name.to_string(),
+            email: email.map(str::to_string),
+            phone: None,
+            address: None,
+            tax_id: None,
+            notes: None,
+            payment_terms_days: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_create() {
+        assert!(validate_create(&request("Acme", Some("billing@acme.test"))).is_ok());
+        assert!(validate_create(&request("Acme", None)).is_ok());
+        assert!(validate_create(&request("  ", None)).is_err());
+        assert!(validate_create(&request("Acme", Some("acme.test"))).is_err());
+    }
+
+    #[test]
+    fn test_validate_update() {
+        assert!(validate_update(&UpdateClient::default()).is_ok());
+
+        let blank_name = UpdateClient {
+            name: Some(" ".to_string()),
+            ..Default::default()
+        };
+        assert!(validate_update(&blank_name).is_err());
+
+        let long_terms = UpdateClient {
+            payment_terms_days: Some(400),
+            ..Default::default()
+        };
+        assert!(validate_update(&long_terms).is_err());
+    }
+}
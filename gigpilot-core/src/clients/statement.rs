@@ -0,0 +1,241 @@
+//! Statements of a client's outstanding invoices.
+//!
+//! A statement lists every open invoice of one client (sent or overdue with
+//! a balance left) with what is still owed and how late it is, and the
+//! totals per currency. Invoices belong to the client by `client_id` or,
+//! without one, by email, as for payment stats. Statements are served as
+//! JSON or PDF, and the chase worker sends one in place of per-invoice
+//! reminders for users who enable `chase_with_statement`.
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use crate::models::invoice::Invoice;
+
+/// Format of `GET /api/clients/:id/statement`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    /// The statement as JSON
+    #[default]
+    Json,
+
+    /// A printable PDF
+    Pdf,
+}
+
+/// One open invoice on a statement.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementLine {
+    pub invoice_id: Uuid,
+    pub invoice_number: String,
+    pub issue_date: NaiveDate,
+    pub due_date: Option<NaiveDate>,
+    pub currency: String,
+    pub total: Decimal,
+    pub amount_paid: Decimal,
+
+    /// What is still owed
+    pub balance: Decimal,
+
+    /// Days past the due date on the statement date (0 if not yet due)
+    pub days_overdue: i64,
+}
+
+/// What a client owes in one currency.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementTotal {
+    pub currency: String,
+
+    /// Balance of every listed invoice
+    pub outstanding: Decimal,
+
+    /// Balance of the overdue ones
+    pub overdue: Decimal,
+}
+
+/// Statement of a client's outstanding invoices.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientStatement {
+    /// Client the statement is for (`None` for invoices without a client record)
+    pub client_id: Option<Uuid>,
+    pub client_name: String,
+    pub client_email: Option<String>,
+
+    /// Date the statement is drawn up on
+    pub as_of: NaiveDate,
+
+    /// Open invoices, oldest due date first (invoices due on receipt last)
+    pub invoices: Vec<StatementLine>,
+
+    /// Totals per currency, alphabetically
+    pub totals: Vec<StatementTotal>,
+}
+
+impl ClientStatement {
+    /// Whether anything is outstanding.
+    pub fn is_empty(&self) -> bool {
+        self.invoices.is_empty()
+    }
+}
+
+/// Builds a statement from a client's open invoices.
+///
+/// # Arguments
+///
+/// * `client_id` - Client the statement is for, if it has a record
+/// * `client_name` - Name printed on the statement
+/// * `client_email` - Email printed on the statement
+/// * `as_of` - Statement date; invoices due before it are overdue
+/// * `invoices` - The open invoices (see [`outstanding_invoices`])
+pub fn build_statement(
+    client_id: Option<Uuid>,
+    client_name: &str,
+    client_email: Option<&str>,
+    as_of: NaiveDate,
+    invoices: &[Invoice],
+) -> ClientStatement {
+    let mut lines: Vec<StatementLine> = invoices
+        .iter()
+        .map(|invoice| StatementLine {
+            invoice_id: invoice.id,
+            invoice_number: invoice.invoice_number.clone(),
+            issue_date: invoice.issue_date,
+            due_date: invoice.due_date,
+            currency: invoice.currency.clone(),
+            total: invoice.total,
+            amount_paid: invoice.amount_paid,
+            balance: invoice.balance_due(),
+            days_overdue: invoice
+                .due_date
+                .map(|due| (as_of - due).num_days().max(0))
+                .unwrap_or(0),
+        })
+        .collect();
+    lines.sort_by(|a, b| {
+        (a.due_date.is_none(), a.due_date, &a.invoice_number)
+            .cmp(&(b.due_date.is_none(), b.due_date, &b.invoice_number))
+    });
+
+    let mut totals: Vec<StatementTotal> = Vec::new();
+    for line in &lines {
+        let index = match totals.iter().position(|t| t.currency == line.currency) {
+            Some(index) => index,
+            None => {
+                totals.push(StatementTotal {
+                    currency: line.currency.clone(),
+                    outstanding: Decimal::ZERO,
+                    overdue: Decimal::ZERO,
+                });
+                totals.len() - 1
+            }
+        };
+        totals[index].outstanding += line.balance;
+        if line.days_overdue > 0 {
+            totals[index].overdue += line.balance;
+        }
+    }
+    totals.sort_by(|a, b| a.currency.cmp(&b.currency));
+
+    ClientStatement {
+        client_id,
+        client_name: client_name.to_string(),
+        client_email: client_email.map(str::to_string),
+        as_of,
+        invoices: lines,
+        totals,
+    }
+}
+
+/// Loads a client's open invoices.
+///
+/// An invoice belongs to the client if it names the client, or names no
+/// client but was sent to the client's email.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to read with
+/// * `user_id` - ID of the owning user
+/// * `client_id` - The client's ID, if it has a record
+/// * `client_email` - The client's email, if known
+pub async fn outstanding_invoices<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    client_id: Option<Uuid>,
+    client_email: Option<&str>,
+) -> Result<Vec<Invoice>, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE user_id = $1
+            AND is_deleted = false
+            AND status IN ('sent', 'overdue')
+            AND total > amount_paid
+            AND (
+                client_id = $2
+                OR (client_id IS NULL AND lower(client_email) = lower($3))
+            )
+        ORDER BY due_date ASC NULLS LAST, invoice_number ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(client_email)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(invoices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{date, invoice};
+
+    #[test]
+    fn test_statement_orders_lines_and_totals_balances() {
+        let invoices = vec![
+            invoice("INV-3", "USD", 8000, 0, None),
+            invoice("INV-2", "EUR", 50000, 0, Some(date(2024, 3, 15))),
+            invoice("INV-1", "EUR", 100000, 25000, Some(date(2024, 2, 1))),
+        ];
+
+        let statement = build_statement(None, "Acme", Some("ap@acme.test"), date(2024, 3, 1), &invoices);
+
+        let numbers: Vec<&str> = statement.invoices.iter().map(|l| l.invoice_number.as_str()).collect();
+        assert_eq!(numbers, vec!["INV-1", "INV-2", "INV-3"]);
+        assert_eq!(statement.invoices[0].balance, Decimal::new(75000, 2));
+        assert_eq!(statement.invoices[0].days_overdue, 29);
+        assert_eq!(statement.invoices[1].days_overdue, 0);
+
+        assert_eq!(
+            statement.totals,
+            vec![
+                StatementTotal {
+                    currency: "EUR".to_string(),
+                    outstanding: Decimal::new(125000, 2),
+                    overdue: Decimal::new(75000, 2),
+                },
+                StatementTotal {
+                    currency: "USD".to_string(),
+                    outstanding: Decimal::new(8000, 2),
+                    overdue: Decimal::ZERO,
+                },
+            ]
+        );
+        assert!(!statement.is_empty());
+        assert!(build_statement(None, "Acme", None, date(2024, 3, 1), &[]).is_empty());
+    }
+}
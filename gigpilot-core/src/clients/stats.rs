@@ -0,0 +1,313 @@
+//! Client payment-behavior profiles.
+//!
+//! The worker recomputes `client_stats` from each client's invoices,
+//! payments and chase emails. The chase executor classifies a client as a
+//! prompt, typical or slow payer ([`PaymentBehavior`]) and adapts when it
+//! escalates to the firm reminder and the tone of the first one. Invoices
+//! are matched to clients like in the portal, by `client_id` only.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::chase_override::ChaseOverride;
+use crate::models::client_stats::ClientStats;
+use crate::models::invoice::Invoice;
+use crate::worker::state_machine::LEVEL_2_AFTER_DAYS;
+
+/// Due invoices needed before a client is profiled.
+pub const MIN_DUE_INVOICES: i32 = 3;
+
+/// Level-2 rate (percent) from which a client is a slow payer.
+pub const SLOW_LEVEL_2_RATE: i64 = 50;
+
+/// Average days late from which a client is a slow payer.
+pub const SLOW_DAYS_LATE: i64 = 14;
+
+/// Highest level-2 rate (percent) of a prompt payer.
+pub const PROMPT_LEVEL_2_RATE: i64 = 10;
+
+/// Highest average days late of a prompt payer.
+pub const PROMPT_DAYS_LATE: i64 = 3;
+
+/// Days overdue before a prompt payer gets the level-2 reminder.
+pub const PROMPT_LEVEL_2_AFTER_DAYS: i64 = 14;
+
+/// Days overdue before a slow payer gets the level-2 reminder.
+pub const SLOW_LEVEL_2_AFTER_DAYS: i64 = 3;
+
+/// How a client usually pays, as far as chasing is concerned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentBehavior {
+    /// Pays on time or nearly; rarely needs the firm reminder
+    Prompt,
+
+    /// Not enough history, or nothing stands out
+    #[default]
+    Typical,
+
+    /// Pays late or often needs the firm reminder
+    Slow,
+}
+
+impl PaymentBehavior {
+    /// Classifies a client from their stats.
+    ///
+    /// Clients without stats, or with fewer than [`MIN_DUE_INVOICES`] due
+    /// invoices, are typical.
+    pub fn from_stats(stats: Option<&ClientStats>) -> Self {
+        let Some(stats) = stats.filter(|stats| stats.invoices_due >= MIN_DUE_INVOICES) else {
+            return PaymentBehavior::Typical;
+        };
+        let level_2_rate = stats.level_2_rate.unwrap_or(Decimal::ZERO);
+
+        let slow = level_2_rate >= Decimal::from(SLOW_LEVEL_2_RATE)
+            || stats
+                .average_days_late
+                .is_some_and(|days| days >= Decimal::from(SLOW_DAYS_LATE));
+        let prompt = level_2_rate <= Decimal::from(PROMPT_LEVEL_2_RATE)
+            && stats
+                .average_days_late
+                .is_some_and(|days| days <= Decimal::from(PROMPT_DAYS_LATE));
+
+        if slow {
+            PaymentBehavior::Slow
+        } else if prompt {
+            PaymentBehavior::Prompt
+        } else {
+            PaymentBehavior::Typical
+        }
+    }
+
+    /// Days overdue before the firm level-2 reminder.
+    pub fn level_2_after_days(self) -> i64 {
+        match self {
+            PaymentBehavior::Prompt => PROMPT_LEVEL_2_AFTER_DAYS,
+            PaymentBehavior::Typical => LEVEL_2_AFTER_DAYS,
+            PaymentBehavior::Slow => SLOW_LEVEL_2_AFTER_DAYS,
+        }
+    }
+
+    /// Tone of the first (level-1) reminder.
+    pub fn reminder_tone(self) -> &'static str {
+        match self {
+            PaymentBehavior::Prompt => "gentle",
+            PaymentBehavior::Typical => "polite",
+            PaymentBehavior::Slow => "direct",
+        }
+    }
+
+    /// Applies the behavior to an invoice's chase override.
+    ///
+    /// An override that already decides on level 2 (`skip_level_2` or
+    /// `level_2_after_days`) is kept as it is.
+    pub fn tailor(self, overrides: ChaseOverride) -> ChaseOverride {
+        if overrides.skip_level_2 || overrides.level_2_after_days.is_some() || self == PaymentBehavior::Typical {
+            return overrides;
+        }
+        ChaseOverride {
+            level_2_after_days: Some(self.level_2_after_days()),
+            ..overrides
+        }
+    }
+}
+
+/// Recomputes the stats of every live client.
+///
+/// Invoices count once they are past their due date (drafts, cancelled
+/// and deleted invoices never do). A paid invoice was paid on its latest
+/// payment date, or on its last update when no payment was recorded. An
+/// invoice needed the level-2 reminder if a chase email moved it to
+/// `chasing_level_2`. Stats of deleted clients are removed.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+///
+/// # Returns
+///
+/// Returns the number of clients whose stats were stored.
+///
+/// # Errors
+///
+/// Returns an error if a query fails.
+pub async fn refresh_client_stats(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM client_stats s
+        USING clients c
+        WHERE c.id = s.client_id AND c.is_deleted = true
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO client_stats (
+            client_id, user_id, invoices_due, invoices_paid,
+            average_days_to_pay, average_days_late, level_2_chases, level_2_rate,
+            refreshed_at
+        )
+        SELECT
+            c.id,
+            c.user_id,
+            COUNT(i.id) FILTER (WHERE i.is_due),
+            COUNT(i.id) FILTER (WHERE i.paid_on IS NOT NULL),
+            ROUND(AVG(i.paid_on - i.issue_date), 2),
+            ROUND(AVG(i.paid_on - i.due_date), 2),
+            COUNT(i.id) FILTER (WHERE i.is_due AND i.level_2),
+            ROUND(
+                100.0 * COUNT(i.id) FILTER (WHERE i.is_due AND i.level_2)
+                    / NULLIF(COUNT(i.id) FILTER (WHERE i.is_due), 0),
+                2
+            ),
+            NOW()
+        FROM clients c
+        LEFT JOIN LATERAL (
+            SELECT
+                inv.id,
+                inv.issue_date,
+                inv.due_date,
+                inv.due_date < CURRENT_DATE AS is_due,
+                CASE WHEN inv.status = 'paid' THEN COALESCE(
+                    (SELECT MAX(p.paid_on) FROM payments p WHERE p.invoice_id = inv.id),
+                    inv.updated_at::date
+                ) END AS paid_on,
+                EXISTS (
+                    SELECT 1 FROM correspondence e
+                    WHERE e.invoice_id = inv.id
+                        AND e.kind = 'email'
+                        AND e.metadata->>'chase_state' = 'chasing_level_2'
+                ) AS level_2
+            FROM invoices inv
+            WHERE inv.user_id = c.user_id
+                AND inv.is_deleted = false
+                AND inv.status NOT IN ('draft', 'cancelled')
+                AND inv.client_id = c.id
+        ) i ON true
+        WHERE c.is_deleted = false
+        GROUP BY c.id, c.user_id
+        ON CONFLICT (client_id) DO UPDATE
+            SET invoices_due = EXCLUDED.invoices_due,
+                invoices_paid = EXCLUDED.invoices_paid,
+                average_days_to_pay = EXCLUDED.average_days_to_pay,
+                average_days_late = EXCLUDED.average_days_late,
+                level_2_chases = EXCLUDED.level_2_chases,
+                level_2_rate = EXCLUDED.level_2_rate,
+                refreshed_at = EXCLUDED.refreshed_at
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Loads the stats of a live client owned by the user.
+///
+/// # Returns
+///
+/// Returns `None` if the client does not exist or has not been profiled
+/// yet.
+pub async fn find_client_stats(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+) -> Result<Option<ClientStats>, anyhow::Error> {
+    let stats = sqlx::query_as::<_, ClientStats>(
+        r#"
+        SELECT s.* FROM client_stats s
+        JOIN clients c ON c.id = s.client_id
+        WHERE s.client_id = $1 AND s.user_id = $2 AND c.is_deleted = false
+        "#,
+    )
+    .bind(client_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(stats)
+}
+
+/// Loads the stats of the client an invoice was issued to.
+///
+/// Invoices without a `client_id` have no stats.
+pub async fn stats_for_invoice(pool: &PgPool, invoice: &Invoice) -> Result<Option<ClientStats>, anyhow::Error> {
+    let Some(client_id) = invoice.client_id else {
+        return Ok(None);
+    };
+    let stats = sqlx::query_as::<_, ClientStats>(
+        r#"
+        SELECT s.* FROM client_stats s
+        JOIN clients c ON c.id = s.client_id
+        WHERE s.user_id = $1
+            AND c.is_deleted = false
+            AND c.id = $2
+        "#,
+    )
+    .bind(invoice.user_id)
+    .bind(client_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn stats(invoices_due: i32, level_2_rate: i64, average_days_late: Option<i64>) -> ClientStats {
+        ClientStats {
+            client_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            invoices_due,
+            invoices_paid: invoices_due,
+            average_days_to_pay: average_days_late.map(|days| Decimal::from(days + 30)),
+            average_days_late: average_days_late.map(Decimal::from),
+            level_2_chases: 0,
+            level_2_rate: Some(Decimal::from(level_2_rate)),
+            refreshed_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_classifies_clients_with_enough_history() {
+        assert_eq!(PaymentBehavior::from_stats(None), PaymentBehavior::Typical);
+        assert_eq!(PaymentBehavior::from_stats(Some(&stats(2, 100, Some(30)))), PaymentBehavior::Typical);
+        assert_eq!(PaymentBehavior::from_stats(Some(&stats(5, 0, Some(-2)))), PaymentBehavior::Prompt);
+        assert_eq!(PaymentBehavior::from_stats(Some(&stats(5, 60, Some(1)))), PaymentBehavior::Slow);
+        assert_eq!(PaymentBehavior::from_stats(Some(&stats(5, 0, Some(20)))), PaymentBehavior::Slow);
+        assert_eq!(PaymentBehavior::from_stats(Some(&stats(5, 20, Some(5)))), PaymentBehavior::Typical);
+        // Nothing paid yet: not known to be prompt
+        assert_eq!(PaymentBehavior::from_stats(Some(&stats(5, 0, None))), PaymentBehavior::Typical);
+    }
+
+    #[test]
+    fn test_tailor_keeps_explicit_level_2_choices() {
+        let tailored = PaymentBehavior::Slow.tailor(ChaseOverride::default());
+        assert_eq!(tailored.level_2_after_days, Some(SLOW_LEVEL_2_AFTER_DAYS));
+        assert_eq!(PaymentBehavior::Typical.tailor(ChaseOverride::default()), ChaseOverride::default());
+
+        let skipped = ChaseOverride {
+            skip_level_2: true,
+            ..ChaseOverride::default()
+        };
+        assert_eq!(PaymentBehavior::Slow.tailor(skipped.clone()), skipped);
+
+        let explicit = ChaseOverride {
+            level_2_after_days: Some(10),
+            paused: true,
+            ..ChaseOverride::default()
+        };
+        assert_eq!(PaymentBehavior::Prompt.tailor(explicit.clone()), explicit);
+    }
+}
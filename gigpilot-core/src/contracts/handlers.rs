@@ -0,0 +1,477 @@
+use axum::{
+    extract::{Extension, Multipart, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::attachments::sanitize_filename;
+use crate::auth::CurrentUser;
+use crate::clients::find_client;
+use crate::contracts::{
+    apply_signing_event, create_contract, delete_contract, find_contract, find_provider_document, list_contracts,
+    rotate_webhook_secret, store_signed_document, update_contract, validate_create, validate_signed_document,
+    validate_update, webhook_secret_matches, Signature, SignedDocument,
+};
+use crate::models::contract::{Contract, ContractStatus, CreateContract, SigningEvent, UpdateContract};
+use crate::projects::find_project;
+use crate::storage::DynBlobStore;
+
+/// Name of the multipart field carrying the signed PDF.
+const FILE_FIELD: &str = "file";
+
+/// Name of the webhook multipart field carrying the event JSON.
+const EVENT_FIELD: &str = "event";
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Query parameters for listing contracts.
+#[derive(Debug, Deserialize)]
+pub struct ListContractsQuery {
+    /// Only list contracts with this client
+    pub client_id: Option<Uuid>,
+
+    /// Only list contracts for this project
+    pub project_id: Option<Uuid>,
+
+    /// Only list contracts in this status
+    pub status: Option<ContractStatus>,
+}
+
+/// Response body of the e-sign webhook.
+#[derive(Debug, Serialize)]
+pub struct SigningEventResponse {
+    pub contract_id: Uuid,
+    pub status: ContractStatus,
+
+    /// Whether the event changed the contract
+    pub applied: bool,
+}
+
+/// Checks that the referenced client and project are the user's.
+async fn require_links(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Option<Uuid>,
+    project_id: Option<Uuid>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    if let Some(client_id) = client_id {
+        find_client(pool, user_id, client_id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load client {}: {}", client_id, e);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load client")
+            })?
+            .ok_or_else(|| {
+                error_response(StatusCode::UNPROCESSABLE_ENTITY, "client_id must reference one of your clients")
+            })?;
+    }
+    if let Some(project_id) = project_id {
+        find_project(pool, user_id, project_id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load project {}: {}", project_id, e);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load project")
+            })?
+            .ok_or_else(|| {
+                error_response(StatusCode::UNPROCESSABLE_ENTITY, "project_id must reference one of your projects")
+            })?;
+    }
+    Ok(())
+}
+
+/// Checks that no other live contract of the user uses a provider
+/// document.
+async fn require_unused_document(
+    pool: &PgPool,
+    user_id: Uuid,
+    contract_id: Option<Uuid>,
+    provider: Option<&str>,
+    document_id: Option<&str>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let (Some(provider), Some(document_id)) = (provider, document_id) else {
+        return Ok(());
+    };
+
+    let existing = find_provider_document(pool, user_id, provider, document_id).await.map_err(|e| {
+        error!("Failed to look up provider document {}: {}", document_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load contract")
+    })?;
+    match existing {
+        Some(existing) if Some(existing.id) != contract_id => Err(error_response(
+            StatusCode::CONFLICT,
+            "another contract already uses this provider document",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Loads one of the user's contracts, mapping failures to responses.
+async fn require_contract(
+    pool: &PgPool,
+    user_id: Uuid,
+    contract_id: Uuid,
+) -> Result<Contract, (StatusCode, Json<Value>)> {
+    find_contract(pool, user_id, contract_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load contract {}: {}", contract_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load contract")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "contract not found"))
+}
+
+/// List contracts endpoint handler.
+///
+/// Handles GET requests to `/api/contracts`, optionally filtered with
+/// `?client_id=`, `?project_id=` and `?status=`.
+pub async fn list_contracts_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListContractsQuery>,
+) -> Result<Json<Vec<Contract>>, StatusCode> {
+    let contracts = list_contracts(&pool, user_id, query.client_id, query.project_id, query.status)
+        .await
+        .map_err(|e| {
+            error!("Failed to list contracts for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(contracts))
+}
+
+/// Create contract endpoint handler.
+///
+/// Handles POST requests to `/api/contracts`. Returns 409 if another
+/// contract already uses the provider document.
+pub async fn create_contract_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateContract>,
+) -> Result<(StatusCode, Json<Contract>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_create(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    require_links(&pool, user_id, request.client_id, request.project_id).await?;
+    require_unused_document(
+        &pool,
+        user_id,
+        None,
+        request.provider.as_deref(),
+        request.provider_document_id.as_deref(),
+    )
+    .await?;
+
+    let contract = create_contract(&pool, user_id, request).await.map_err(|e| {
+        error!("Failed to create contract for user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create contract")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(contract)))
+}
+
+/// Get contract endpoint handler.
+///
+/// Handles GET requests to `/api/contracts/:id`.
+pub async fn get_contract_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(contract_id): Path<Uuid>,
+) -> Result<Json<Contract>, (StatusCode, Json<Value>)> {
+    let contract = require_contract(&pool, user_id, contract_id).await?;
+
+    Ok(Json(contract))
+}
+
+/// Update contract endpoint handler.
+///
+/// Handles PUT requests to `/api/contracts/:id`. The status may move to
+/// `sent`, `declined` or `voided` where allowed; signed and voided
+/// contracts keep their status.
+pub async fn update_contract_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(contract_id): Path<Uuid>,
+    Json(update): Json<UpdateContract>,
+) -> Result<Json<Contract>, (StatusCode, Json<Value>)> {
+    let current = require_contract(&pool, user_id, contract_id).await?;
+
+    if let Err(message) = validate_update(&current, &update) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    require_links(&pool, user_id, update.client_id, update.project_id).await?;
+    require_unused_document(
+        &pool,
+        user_id,
+        Some(contract_id),
+        update.provider.as_deref().or(current.provider.as_deref()),
+        update
+            .provider_document_id
+            .as_deref()
+            .or(current.provider_document_id.as_deref()),
+    )
+    .await?;
+
+    let contract = update_contract(&pool, user_id, contract_id, update)
+        .await
+        .map_err(|e| {
+            error!("Failed to update contract {}: {}", contract_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update contract")
+        })?
+        // Deleted or its status changed since it was loaded
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "contract changed; reload and try again"))?;
+
+    Ok(Json(contract))
+}
+
+/// Delete contract endpoint handler.
+///
+/// Handles DELETE requests to `/api/contracts/:id`. Chase emails stop
+/// citing the contract.
+pub async fn delete_contract_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(contract_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_contract(&pool, user_id, contract_id).await.map_err(|e| {
+        error!("Failed to delete contract {}: {}", contract_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Parses an optional RFC 3339 timestamp form field.
+fn parse_signed_at(value: &str) -> Result<Option<DateTime<Utc>>, (StatusCode, Json<Value>)> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| Some(at.with_timezone(&Utc)))
+        .map_err(|_| error_response(StatusCode::UNPROCESSABLE_ENTITY, "signed_at must be an RFC 3339 timestamp"))
+}
+
+/// Reads the signed PDF from a multipart field, validating it.
+async fn read_signed_document(
+    field: axum::extract::multipart::Field<'_>,
+) -> Result<(String, axum::body::Bytes), (StatusCode, Json<Value>)> {
+    let filename = sanitize_filename(field.file_name().unwrap_or("signed.pdf"));
+    let content_type = field
+        .content_type()
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase())
+        .unwrap_or_default();
+    let data = field
+        .bytes()
+        .await
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, &e.to_string()))?;
+
+    validate_signed_document(&content_type, data.len())
+        .map_err(|message| error_response(StatusCode::UNPROCESSABLE_ENTITY, &message))?;
+
+    Ok((filename, data))
+}
+
+/// Signed document upload endpoint handler.
+///
+/// Handles POST requests to `/api/contracts/:id/signed-document`. The body
+/// is `multipart/form-data` with the PDF in a `file` field and optional
+/// `signed_by_name`, `signed_by_email` and `signed_at` (RFC 3339) fields.
+/// Draft and sent contracts become signed; uploading to a signed contract
+/// replaces its document. Returns 409 for declined or voided contracts.
+pub async fn upload_signed_document_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(contract_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Result<Json<Contract>, (StatusCode, Json<Value>)> {
+    let current = require_contract(&pool, user_id, contract_id).await?;
+    if current.status != ContractStatus::Signed && !current.status.can_become(ContractStatus::Signed) {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            &format!("a {} contract can't be signed", current.status.as_str()),
+        ));
+    }
+
+    let bad_request = |e: axum::extract::multipart::MultipartError| error_response(StatusCode::BAD_REQUEST, &e.to_string());
+
+    let mut upload = None;
+    let mut signature = Signature::default();
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        match field.name() {
+            Some(FILE_FIELD) => upload = Some(read_signed_document(field).await?),
+            Some("signed_by_name") => signature.name = Some(field.text().await.map_err(bad_request)?),
+            Some("signed_by_email") => signature.email = Some(field.text().await.map_err(bad_request)?),
+            Some("signed_at") => signature.signed_at = parse_signed_at(&field.text().await.map_err(bad_request)?)?,
+            _ => {}
+        }
+    }
+
+    let (filename, data) =
+        upload.ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "missing file field"))?;
+
+    let contract = store_signed_document(
+        &pool,
+        store.as_ref(),
+        user_id,
+        contract_id,
+        SignedDocument { filename: &filename, data: &data },
+        signature,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to store signed document of contract {}: {}", contract_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to store signed document")
+    })?
+    .ok_or_else(|| error_response(StatusCode::CONFLICT, "contract can no longer be signed"))?;
+
+    Ok(Json(contract))
+}
+
+/// Signed document download endpoint handler.
+///
+/// Handles GET requests to `/api/contracts/:id/signed-document`. Returns
+/// 404 until a signed PDF is stored.
+pub async fn signed_document_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(contract_id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let contract = require_contract(&pool, user_id, contract_id).await?;
+    let key = contract
+        .signed_storage_key
+        .as_deref()
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "contract has no signed document"))?;
+
+    let data = store.get(key).await.map_err(|e| {
+        error!("Failed to read signed document of contract {}: {}", contract_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to read signed document")
+    })?;
+
+    let disposition = format!(
+        "attachment; filename=\"{}\"",
+        contract.signed_filename.as_deref().unwrap_or("signed.pdf")
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        data,
+    ))
+}
+
+/// Rotate e-sign webhook secret endpoint handler.
+///
+/// Handles POST requests to `/api/contracts/webhook-secret`, creating the
+/// user's e-sign webhook secret or replacing it; the previous secret stops
+/// working. Returns 201 with the `webhook_url` to give the provider and the
+/// `secret`, which is not shown again.
+pub async fn rotate_webhook_secret_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let secret = rotate_webhook_secret(&pool, user_id).await.map_err(|e| {
+        error!("Failed to rotate e-sign webhook secret of user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create webhook secret")
+    })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "webhook_url": format!("/webhooks/esign/{}", user_id),
+            "secret": secret,
+        })),
+    ))
+}
+
+/// E-sign provider webhook handler.
+///
+/// Handles POST requests to `/webhooks/esign/:user_id` without a user
+/// login; the provider authenticates with the user's webhook secret in
+/// `Authorization: Bearer <secret>` (see [`rotate_webhook_secret_handler`]).
+/// The body is `multipart/form-data` with the [`SigningEvent`] as JSON in an
+/// `event` field and, for signed events, the signed PDF in an optional
+/// `file` field. Repeated and out-of-order events are acknowledged with
+/// `applied: false`, so providers don't retry them.
+///
+/// Returns 401 for a wrong secret, or if the user has none, and 404 if
+/// none of the user's contracts uses the document.
+pub async fn esign_webhook_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Path(user_id): Path<Uuid>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<SigningEventResponse>, (StatusCode, Json<Value>)> {
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    let authorized = webhook_secret_matches(&pool, user_id, given.trim()).await.map_err(|e| {
+        error!("Failed to check e-sign webhook secret of user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to check webhook secret")
+    })?;
+    if !authorized {
+        return Err(error_response(StatusCode::UNAUTHORIZED, "invalid webhook secret"));
+    }
+
+    let bad_request = |e: axum::extract::multipart::MultipartError| error_response(StatusCode::BAD_REQUEST, &e.to_string());
+
+    let mut event = None;
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        match field.name() {
+            Some(EVENT_FIELD) => {
+                let text = field.text().await.map_err(bad_request)?;
+                let parsed: SigningEvent = serde_json::from_str(&text).map_err(|e| {
+                    error_response(StatusCode::UNPROCESSABLE_ENTITY, &format!("invalid event: {}", e))
+                })?;
+                event = Some(parsed);
+            }
+            Some(FILE_FIELD) => upload = Some(read_signed_document(field).await?),
+            _ => {}
+        }
+    }
+
+    let event = event.ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "missing event field"))?;
+    let document = upload
+        .as_ref()
+        .map(|(filename, data)| SignedDocument { filename, data });
+
+    let outcome = apply_signing_event(&pool, store.as_ref(), user_id, &event, document)
+        .await
+        .map_err(|e| {
+            error!("Failed to apply {} event for document {}: {}", event.provider, event.document_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to apply event")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "no contract uses this document"))?;
+
+    info!(
+        "E-sign event {:?} for contract {} ({})",
+        event.event,
+        outcome.contract.id,
+        if outcome.applied { "applied" } else { "ignored" }
+    );
+
+    Ok(Json(SigningEventResponse {
+        contract_id: outcome.contract.id,
+        status: outcome.contract.status,
+        applied: outcome.applied,
+    }))
+}
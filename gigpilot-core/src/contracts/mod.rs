@@ -0,0 +1,895 @@
+//! Contracts and their signatures.
+//!
+//! A contract is an agreement with a client, optionally for one project,
+//! that is sent for signing. Its status is updated by hand or by an e-sign
+//! provider posting events to the user's webhook URL with the user's
+//! webhook secret; the contract is found among the user's contracts by the
+//! provider's name and document ID. The signed PDF is kept in blob storage.
+//!
+//! Chase emails cite the latest signed contract of the invoice's project
+//! or client (see [`agreement_reference`]), so reminders can point to what
+//! the client agreed to.
+
+pub mod handlers;
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::attachments::MAX_ATTACHMENT_BYTES;
+use crate::clients::check_client;
+use crate::models::contract::{
+    Contract, ContractStatus, CreateContract, SigningEvent, SigningEventKind, UpdateContract,
+};
+use crate::models::invoice::Invoice;
+use crate::models::notification::CreateNotification;
+use crate::notifications::notify;
+use crate::projects::check_project;
+use crate::storage::BlobStore;
+use crate::text::non_blank;
+
+/// Longest contract title accepted.
+pub const MAX_TITLE_LENGTH: usize = 255;
+
+/// Longest provider name accepted.
+pub const MAX_PROVIDER_LENGTH: usize = 50;
+
+/// Longest provider document ID accepted.
+pub const MAX_DOCUMENT_ID_LENGTH: usize = 255;
+
+/// Prefix of e-sign webhook secrets.
+const WEBHOOK_SECRET_PREFIX: &str = "gpw_";
+
+/// Content type of signed documents; only PDFs are accepted.
+pub const SIGNED_DOCUMENT_CONTENT_TYPE: &str = "application/pdf";
+
+/// Normalises a provider name for matching webhook events.
+pub fn normalize_provider(provider: &str) -> String {
+    provider.trim().to_lowercase()
+}
+
+/// Validates a contract title.
+fn validate_title(title: &str) -> Result<(), String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("title is required".to_string());
+    }
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(format!("title must be at most {} characters", MAX_TITLE_LENGTH));
+    }
+    Ok(())
+}
+
+/// Validates the e-sign provider fields of a contract.
+fn validate_provider(provider: Option<&str>, document_id: Option<&str>) -> Result<(), String> {
+    let provider = provider.map(str::trim).filter(|p| !p.is_empty());
+    let document_id = document_id.map(str::trim).filter(|d| !d.is_empty());
+
+    if document_id.is_some() && provider.is_none() {
+        return Err("provider is required with provider_document_id".to_string());
+    }
+    if provider.is_some_and(|p| p.chars().count() > MAX_PROVIDER_LENGTH) {
+        return Err(format!("provider must be at most {} characters", MAX_PROVIDER_LENGTH));
+    }
+    if document_id.is_some_and(|d| d.chars().count() > MAX_DOCUMENT_ID_LENGTH) {
+        return Err(format!(
+            "provider_document_id must be at most {} characters",
+            MAX_DOCUMENT_ID_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Validates a contract creation request.
+///
+/// # Returns
+///
+/// Returns a message describing the first problem found.
+pub fn validate_create(request: &CreateContract) -> Result<(), String> {
+    validate_title(&request.title)?;
+    validate_provider(request.provider.as_deref(), request.provider_document_id.as_deref())?;
+
+    match request.status {
+        None | Some(ContractStatus::Draft) | Some(ContractStatus::Sent) => Ok(()),
+        Some(status) => Err(format!(
+            "new contracts must be draft or sent, not {}",
+            status.as_str()
+        )),
+    }
+}
+
+/// Validates a contract update against the current contract.
+///
+/// Status changes must be allowed by [`ContractStatus::can_become`];
+/// `signed` is only recorded with the signed document or by the provider.
+///
+/// # Returns
+///
+/// Returns a message describing the first problem found.
+pub fn validate_update(current: &Contract, update: &UpdateContract) -> Result<(), String> {
+    if let Some(title) = &update.title {
+        validate_title(title)?;
+    }
+    validate_provider(
+        update.provider.as_deref().or(current.provider.as_deref()),
+        update
+            .provider_document_id
+            .as_deref()
+            .or(current.provider_document_id.as_deref()),
+    )?;
+
+    match update.status {
+        Some(status) if status == current.status => Ok(()),
+        Some(ContractStatus::Signed) => {
+            Err("upload the signed document to mark a contract signed".to_string())
+        }
+        Some(status) if !current.status.can_become(status) => Err(format!(
+            "a {} contract can't become {}",
+            current.status.as_str(),
+            status.as_str()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Validates an uploaded signed document.
+///
+/// # Returns
+///
+/// Returns a user-facing message describing the first problem found.
+pub fn validate_signed_document(content_type: &str, size: usize) -> Result<(), String> {
+    if size == 0 {
+        return Err("signed document is empty".to_string());
+    }
+    if size > MAX_ATTACHMENT_BYTES {
+        return Err(format!("signed document exceeds {} bytes", MAX_ATTACHMENT_BYTES));
+    }
+    if content_type != SIGNED_DOCUMENT_CONTENT_TYPE {
+        return Err(format!("signed document must be a PDF, not {}", content_type));
+    }
+    Ok(())
+}
+
+/// Storage key of a contract's signed document.
+///
+/// Built only from IDs; uploading again replaces the document.
+pub fn storage_key(user_id: Uuid, contract_id: Uuid) -> String {
+    format!("contracts/{}/{}", user_id, contract_id)
+}
+
+/// Generates a new webhook secret: the prefix and 64 random hex characters.
+fn generate_webhook_secret() -> String {
+    format!("{}{}{}", WEBHOOK_SECRET_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Creates the user's e-sign webhook secret, replacing any previous one.
+///
+/// # Returns
+///
+/// Returns the secret. Only its hash is stored, so it can't be shown
+/// again.
+pub async fn rotate_webhook_secret(pool: &PgPool, user_id: Uuid) -> Result<String, anyhow::Error> {
+    let secret = generate_webhook_secret();
+
+    sqlx::query(
+        r#"
+        INSERT INTO esign_webhook_secrets (user_id, secret_hash)
+        VALUES ($1, sha256(convert_to($2, 'UTF8')))
+        ON CONFLICT (user_id) DO UPDATE
+            SET secret_hash = EXCLUDED.secret_hash, created_at = NOW()
+        "#,
+    )
+    .bind(user_id)
+    .bind(&secret)
+    .execute(pool)
+    .await?;
+
+    Ok(secret)
+}
+
+/// Whether a webhook request carries the user's e-sign webhook secret.
+///
+/// # Returns
+///
+/// Returns `false` if the user has no secret.
+pub async fn webhook_secret_matches(pool: &PgPool, user_id: Uuid, secret: &str) -> Result<bool, anyhow::Error> {
+    let matches = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM esign_webhook_secrets
+            WHERE user_id = $1 AND secret_hash = sha256(convert_to($2, 'UTF8'))
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(secret)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(matches)
+}
+
+/// The status a signing event moves a contract to, if it would change it.
+///
+/// Views never change the status. Events repeating the current status or
+/// not allowed by [`ContractStatus::can_become`] (providers may deliver
+/// events late or out of order) are ignored.
+pub fn signing_transition(current: ContractStatus, event: SigningEventKind) -> Option<ContractStatus> {
+    let to = match event {
+        SigningEventKind::Viewed => return None,
+        SigningEventKind::Sent => ContractStatus::Sent,
+        SigningEventKind::Signed => ContractStatus::Signed,
+        SigningEventKind::Declined => ContractStatus::Declined,
+        SigningEventKind::Voided => ContractStatus::Voided,
+    };
+    current.can_become(to).then_some(to)
+}
+
+/// Sentence citing a signed contract in chase emails.
+///
+/// # Returns
+///
+/// Returns e.g. `As per our signed agreement "Website redesign" dated
+/// 5 March 2024.`, or `None` if the contract isn't signed.
+pub fn agreement_reference(contract: &Contract) -> Option<String> {
+    if contract.status != ContractStatus::Signed {
+        return None;
+    }
+    let signed_at = contract.signed_at?;
+
+    Some(format!(
+        "As per our signed agreement \"{}\" dated {}.",
+        contract.title,
+        signed_at.format("%-d %B %Y")
+    ))
+}
+
+/// Who signed a contract and when.
+#[derive(Debug, Clone, Default)]
+pub struct Signature {
+    pub name: Option<String>,
+    pub email: Option<String>,
+
+    /// When it was signed (default: now)
+    pub signed_at: Option<DateTime<Utc>>,
+}
+
+/// A signed PDF to store with a contract.
+#[derive(Debug, Clone, Copy)]
+pub struct SignedDocument<'a> {
+    /// Sanitised file name
+    pub filename: &'a str,
+
+    /// Raw PDF contents
+    pub data: &'a [u8],
+}
+
+/// Creates a contract.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `request` - Validated creation request
+///
+/// # Returns
+///
+/// Returns the new `Contract`.
+///
+/// # Errors
+///
+/// Returns an error if the client or project isn't the user's, or if the
+/// provider document already belongs to another contract.
+pub async fn create_contract(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateContract,
+) -> Result<Contract, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    if let Some(client_id) = request.client_id {
+        check_client(&mut *tx, user_id, client_id).await?;
+    }
+    if let Some(project_id) = request.project_id {
+        check_project(&mut *tx, user_id, project_id).await?;
+    }
+
+    let status = request.status.unwrap_or(ContractStatus::Draft);
+    let contract = sqlx::query_as::<_, Contract>(
+        r#"
+        INSERT INTO contracts (
+            user_id, client_id, project_id, title, status,
+            provider, provider_document_id, sent_at, metadata
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, CASE WHEN $5 = 'sent' THEN NOW() END, $8)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(request.client_id)
+    .bind(request.project_id)
+    .bind(request.title.trim())
+    .bind(status)
+    .bind(request.provider.as_deref().map(normalize_provider).filter(|p| !p.is_empty()))
+    .bind(non_blank(request.provider_document_id.as_deref()))
+    .bind(request.metadata)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(contract)
+}
+
+/// Lists a user's live contracts, newest first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `client_id` - Only list contracts with this client
+/// * `project_id` - Only list contracts for this project
+/// * `status` - Only list contracts in this status
+pub async fn list_contracts(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Option<Uuid>,
+    project_id: Option<Uuid>,
+    status: Option<ContractStatus>,
+) -> Result<Vec<Contract>, anyhow::Error> {
+    let contracts = sqlx::query_as::<_, Contract>(
+        r#"
+        SELECT * FROM contracts
+        WHERE user_id = $1 AND is_deleted = false
+            AND ($2::uuid IS NULL OR client_id = $2)
+            AND ($3::uuid IS NULL OR project_id = $3)
+            AND ($4::varchar IS NULL OR status = $4)
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(project_id)
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(contracts)
+}
+
+/// Loads one of a user's live contracts.
+pub async fn find_contract(
+    pool: &PgPool,
+    user_id: Uuid,
+    contract_id: Uuid,
+) -> Result<Option<Contract>, anyhow::Error> {
+    let contract = sqlx::query_as::<_, Contract>(
+        "SELECT * FROM contracts WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(contract_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(contract)
+}
+
+/// Loads a user's live contract using a provider document.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `provider` - Provider name (normalised here)
+/// * `document_id` - The provider's document ID
+pub async fn find_provider_document(
+    pool: &PgPool,
+    user_id: Uuid,
+    provider: &str,
+    document_id: &str,
+) -> Result<Option<Contract>, anyhow::Error> {
+    let contract = sqlx::query_as::<_, Contract>(
+        r#"
+        SELECT * FROM contracts
+        WHERE user_id = $1 AND provider = $2 AND provider_document_id = $3 AND is_deleted = false
+        "#,
+    )
+    .bind(user_id)
+    .bind(normalize_provider(provider))
+    .bind(document_id.trim())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(contract)
+}
+
+/// Locks one of a user's live contracts for the rest of a transaction.
+async fn lock_contract(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    contract_id: Uuid,
+) -> Result<Option<Contract>, anyhow::Error> {
+    let contract = sqlx::query_as::<_, Contract>(
+        "SELECT * FROM contracts WHERE id = $1 AND user_id = $2 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(contract_id)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(contract)
+}
+
+/// Updates a contract.
+///
+/// The update is validated again under a row lock; a status change stamps
+/// `sent_at`, `declined_at` or `voided_at`.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `contract_id` - Contract to update
+/// * `update` - Fields to change
+///
+/// # Returns
+///
+/// Returns the updated `Contract`, or `None` if it was deleted or its
+/// status changed so that the update is no longer allowed.
+pub async fn update_contract(
+    pool: &PgPool,
+    user_id: Uuid,
+    contract_id: Uuid,
+    update: UpdateContract,
+) -> Result<Option<Contract>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let Some(current) = lock_contract(&mut tx, user_id, contract_id).await? else {
+        return Ok(None);
+    };
+    if validate_update(&current, &update).is_err() {
+        return Ok(None);
+    }
+
+    if let Some(client_id) = update.client_id {
+        check_client(&mut *tx, user_id, client_id).await?;
+    }
+    if let Some(project_id) = update.project_id {
+        check_project(&mut *tx, user_id, project_id).await?;
+    }
+
+    let status = update.status.unwrap_or(current.status);
+    let changed = status != current.status;
+
+    let contract = sqlx::query_as::<_, Contract>(
+        r#"
+        UPDATE contracts
+        SET client_id = $3, project_id = $4, title = $5, status = $6,
+            provider = $7, provider_document_id = $8, metadata = $9,
+            sent_at = CASE WHEN $10 AND $6 = 'sent' THEN NOW() ELSE sent_at END,
+            declined_at = CASE WHEN $10 AND $6 = 'declined' THEN NOW() ELSE declined_at END,
+            voided_at = CASE WHEN $10 AND $6 = 'voided' THEN NOW() ELSE voided_at END
+        WHERE id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(contract_id)
+    .bind(user_id)
+    .bind(update.client_id.or(current.client_id))
+    .bind(update.project_id.or(current.project_id))
+    .bind(update.title.map(|t| t.trim().to_string()).unwrap_or(current.title))
+    .bind(status)
+    .bind(
+        update
+            .provider
+            .as_deref()
+            .map(normalize_provider)
+            .filter(|p| !p.is_empty())
+            .or(current.provider),
+    )
+    .bind(non_blank(update.provider_document_id.as_deref()).or(current.provider_document_id))
+    .bind(update.metadata.or(current.metadata))
+    .bind(changed)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(contract))
+}
+
+/// Soft-deletes a contract. The signed document stays in storage.
+///
+/// # Returns
+///
+/// Returns `true` if a contract was deleted, `false` if none matched.
+pub async fn delete_contract(pool: &PgPool, user_id: Uuid, contract_id: Uuid) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        "UPDATE contracts SET is_deleted = true WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(contract_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Marks a locked contract signed, storing the signed document if given.
+///
+/// An already signed contract keeps its first signature time and signer
+/// unless new ones are given. The document is written before the
+/// transaction commits, so a failed upload leaves the contract unchanged.
+async fn record_signature(
+    tx: &mut Transaction<'_, Postgres>,
+    store: &dyn BlobStore,
+    contract: &Contract,
+    signature: &Signature,
+    document: Option<SignedDocument<'_>>,
+) -> Result<Contract, anyhow::Error> {
+    let key = document.map(|_| storage_key(contract.user_id, contract.id));
+
+    let signed = sqlx::query_as::<_, Contract>(
+        r#"
+        UPDATE contracts
+        SET status = 'signed',
+            signed_at = COALESCE($2, signed_at, NOW()),
+            signed_by_name = COALESCE($3, signed_by_name),
+            signed_by_email = COALESCE($4, signed_by_email),
+            signed_filename = COALESCE($5, signed_filename),
+            signed_size_bytes = COALESCE($6, signed_size_bytes),
+            signed_storage_key = COALESCE($7, signed_storage_key)
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(contract.id)
+    .bind(signature.signed_at)
+    .bind(non_blank(signature.name.as_deref()))
+    .bind(non_blank(signature.email.as_deref()))
+    .bind(document.map(|d| d.filename))
+    .bind(document.map(|d| d.data.len() as i64))
+    .bind(&key)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if let (Some(key), Some(document)) = (key, document) {
+        store.put(&key, SIGNED_DOCUMENT_CONTENT_TYPE, document.data).await?;
+    }
+
+    Ok(signed)
+}
+
+/// Notifies a contract's owner that it was signed or declined.
+async fn notify_decision(tx: &mut Transaction<'_, Postgres>, contract: &Contract) -> Result<(), anyhow::Error> {
+    let (kind, title) = match contract.status {
+        ContractStatus::Signed => ("contract_signed", format!("Contract {} was signed", contract.title)),
+        ContractStatus::Declined => ("contract_declined", format!("Contract {} was declined", contract.title)),
+        _ => return Ok(()),
+    };
+
+    notify(
+        tx,
+        contract.user_id,
+        CreateNotification {
+            kind: kind.to_string(),
+            title,
+            body: contract
+                .signed_by_name
+                .as_ref()
+                .filter(|_| contract.status == ContractStatus::Signed)
+                .map(|name| format!("Signed by {}.", name)),
+            data: Some(json!({ "contract_id": contract.id })),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Stores the signed PDF of a contract and marks it signed.
+///
+/// Draft and sent contracts become signed; a signed contract (e.g. one
+/// reported signed by the provider) gets its document added or replaced.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `store` - Storage backend for the document
+/// * `user_id` - ID of the owning user
+/// * `contract_id` - Contract that was signed
+/// * `document` - The validated signed PDF
+/// * `signature` - Who signed and when, if known
+///
+/// # Returns
+///
+/// Returns the signed `Contract`, or `None` if it was deleted, declined
+/// or voided.
+pub async fn store_signed_document(
+    pool: &PgPool,
+    store: &dyn BlobStore,
+    user_id: Uuid,
+    contract_id: Uuid,
+    document: SignedDocument<'_>,
+    signature: Signature,
+) -> Result<Option<Contract>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let Some(current) = lock_contract(&mut tx, user_id, contract_id).await? else {
+        return Ok(None);
+    };
+    if current.status != ContractStatus::Signed && !current.status.can_become(ContractStatus::Signed) {
+        return Ok(None);
+    }
+
+    let contract = record_signature(&mut tx, store, &current, &signature, Some(document)).await?;
+    tx.commit().await?;
+
+    Ok(Some(contract))
+}
+
+/// Result of a signing event from an e-sign provider.
+#[derive(Debug, Clone)]
+pub struct SigningOutcome {
+    /// The contract after the event
+    pub contract: Contract,
+
+    /// Whether the event changed the contract; repeated and out-of-order
+    /// events are acknowledged without changes
+    pub applied: bool,
+}
+
+/// Applies a signing event posted by an e-sign provider.
+///
+/// Views update `last_viewed_at`; other events move the contract along
+/// (see [`signing_transition`]) and the user is notified when it is signed
+/// or declined. A signed PDF sent with a signed event is stored, also when
+/// the contract was already signed.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `store` - Storage backend for the document
+/// * `user_id` - ID of the user whose webhook received the event
+/// * `event` - The provider's event
+/// * `document` - The signed PDF, if the provider sent it
+///
+/// # Returns
+///
+/// Returns the outcome, or `None` if none of the user's live contracts
+/// uses the document.
+pub async fn apply_signing_event(
+    pool: &PgPool,
+    store: &dyn BlobStore,
+    user_id: Uuid,
+    event: &SigningEvent,
+    document: Option<SignedDocument<'_>>,
+) -> Result<Option<SigningOutcome>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let current = sqlx::query_as::<_, Contract>(
+        r#"
+        SELECT * FROM contracts
+        WHERE user_id = $1 AND provider = $2 AND provider_document_id = $3 AND is_deleted = false
+        FOR UPDATE
+        "#,
+    )
+    .bind(user_id)
+    .bind(normalize_provider(&event.provider))
+    .bind(event.document_id.trim())
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(current) = current else {
+        return Ok(None);
+    };
+
+    let occurred_at = event.occurred_at.unwrap_or_else(Utc::now);
+    let transition = signing_transition(current.status, event.event);
+
+    let contract = match (event.event, transition) {
+        (SigningEventKind::Viewed, _) => {
+            sqlx::query_as::<_, Contract>(
+                r#"
+                UPDATE contracts SET last_viewed_at = GREATEST(last_viewed_at, $2)
+                WHERE id = $1
+                RETURNING *
+                "#,
+            )
+            .bind(current.id)
+            .bind(occurred_at)
+            .fetch_one(&mut *tx)
+            .await?
+        }
+        // A repeated signed event may bring the document
+        (SigningEventKind::Signed, _)
+            if transition.is_some() || (current.status == ContractStatus::Signed && document.is_some()) =>
+        {
+            let signature = Signature {
+                name: event.signer_name.clone(),
+                email: event.signer_email.clone(),
+                signed_at: Some(occurred_at),
+            };
+            let signed = record_signature(&mut tx, store, &current, &signature, document).await?;
+            if transition.is_some() {
+                notify_decision(&mut tx, &signed).await?;
+            }
+            signed
+        }
+        (_, Some(status)) => {
+            let changed = sqlx::query_as::<_, Contract>(
+                r#"
+                UPDATE contracts
+                SET status = $2,
+                    sent_at = CASE WHEN $2 = 'sent' THEN $3 ELSE sent_at END,
+                    declined_at = CASE WHEN $2 = 'declined' THEN $3 ELSE declined_at END,
+                    voided_at = CASE WHEN $2 = 'voided' THEN $3 ELSE voided_at END
+                WHERE id = $1
+                RETURNING *
+                "#,
+            )
+            .bind(current.id)
+            .bind(status)
+            .bind(occurred_at)
+            .fetch_one(&mut *tx)
+            .await?;
+            notify_decision(&mut tx, &changed).await?;
+            changed
+        }
+        _ => return Ok(Some(SigningOutcome { contract: current, applied: false })),
+    };
+
+    tx.commit().await?;
+
+    Ok(Some(SigningOutcome { contract, applied: true }))
+}
+
+/// Finds the signed contract to cite when chasing an invoice.
+///
+/// A contract for the invoice's project wins over one with its client;
+/// among those, the most recently signed.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `invoice` - The invoice being chased
+///
+/// # Returns
+///
+/// Returns the latest signed `Contract`, or `None` if there is none.
+pub async fn signed_agreement_for_invoice(pool: &PgPool, invoice: &Invoice) -> Result<Option<Contract>, anyhow::Error> {
+    if invoice.client_id.is_none() && invoice.project_id.is_none() {
+        return Ok(None);
+    }
+
+    let contract = sqlx::query_as::<_, Contract>(
+        r#"
+        SELECT * FROM contracts
+        WHERE user_id = $1 AND is_deleted = false AND status = 'signed' AND signed_at IS NOT NULL
+            AND (project_id = $2 OR client_id = $3)
+        ORDER BY COALESCE(project_id = $2, false) DESC, signed_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(invoice.user_id)
+    .bind(invoice.project_id)
+    .bind(invoice.client_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(contract)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn contract(status: ContractStatus) -> Contract {
+        Contract {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_id: Some(Uuid::new_v4()),
+            project_id: None,
+            title: "Website redesign".to_string(),
+            status,
+            provider: None,
+            provider_document_id: None,
+            sent_at: None,
+            last_viewed_at: None,
+            signed_at: None,
+            signed_by_name: None,
+            signed_by_email: None,
+            declined_at: None,
+            voided_at: None,
+            signed_filename: None,
+            signed_size_bytes: None,
+            signed_storage_key: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_validate_create() {
+        let request = CreateContract {
+            client_id: None,
+            project_id: None,
+            title: " Retainer ".to_string(),
+            status: None,
+            provider: None,
+            provider_document_id: None,
+            metadata: None,
+        };
+        assert!(validate_create(&request).is_ok());
+        assert!(validate_create(&CreateContract { title: "  ".to_string(), ..request.clone() }).is_err());
+        assert!(validate_create(&CreateContract {
+            status: Some(ContractStatus::Signed),
+            ..request.clone()
+        })
+        .is_err());
+        assert!(validate_create(&CreateContract {
+            provider_document_id: Some("env-1".to_string()),
+            ..request
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_validate_update_status() {
+        let sent = contract(ContractStatus::Sent);
+        let update = |status| UpdateContract { status: Some(status), ..Default::default() };
+
+        assert!(validate_update(&sent, &update(ContractStatus::Voided)).is_ok());
+        assert!(validate_update(&sent, &update(ContractStatus::Sent)).is_ok());
+        assert!(validate_update(&sent, &update(ContractStatus::Draft)).is_err());
+        assert!(validate_update(&sent, &update(ContractStatus::Signed)).is_err());
+        assert!(validate_update(&contract(ContractStatus::Declined), &update(ContractStatus::Sent)).is_ok());
+        assert!(validate_update(&contract(ContractStatus::Voided), &update(ContractStatus::Sent)).is_err());
+    }
+
+    #[test]
+    fn test_signing_transition() {
+        use ContractStatus::*;
+        assert_eq!(signing_transition(Sent, SigningEventKind::Signed), Some(Signed));
+        assert_eq!(signing_transition(Draft, SigningEventKind::Sent), Some(Sent));
+        assert_eq!(signing_transition(Sent, SigningEventKind::Viewed), None);
+        // Repeated and late events are ignored
+        assert_eq!(signing_transition(Signed, SigningEventKind::Signed), None);
+        assert_eq!(signing_transition(Signed, SigningEventKind::Sent), None);
+        assert_eq!(signing_transition(Voided, SigningEventKind::Declined), None);
+    }
+
+    #[test]
+    fn test_validate_signed_document() {
+        assert!(validate_signed_document("application/pdf", 1024).is_ok());
+        assert!(validate_signed_document("application/pdf", 0).is_err());
+        assert!(validate_signed_document("image/png", 1024).is_err());
+        assert!(validate_signed_document("application/pdf", MAX_ATTACHMENT_BYTES + 1).is_err());
+    }
+
+    #[test]
+    fn test_webhook_secrets_are_random() {
+        let secret = generate_webhook_secret();
+        assert!(secret.starts_with(WEBHOOK_SECRET_PREFIX));
+        assert_eq!(secret.len(), WEBHOOK_SECRET_PREFIX.len() + 64);
+        assert_ne!(secret, generate_webhook_secret());
+    }
+
+    #[test]
+    fn test_agreement_reference() {
+        let mut signed = contract(ContractStatus::Signed);
+        assert_eq!(agreement_reference(&signed), None);
+
+        signed.signed_at = Some(Utc.with_ymd_and_hms(2024, 3, 5, 14, 0, 0).unwrap());
+        assert_eq!(
+            agreement_reference(&signed).as_deref(),
+            Some("As per our signed agreement \"Website redesign\" dated 5 March 2024.")
+        );
+
+        let mut voided = signed.clone();
+        voided.status = ContractStatus::Voided;
+        assert_eq!(agreement_reference(&voided), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Backfill of historical exchange rates.
+//!
+//! Reports convert invoices at the rate of their issue date and payments at
+//! the rate of their payment date. This job finds those dates with no stored
+//! rates and fetches them in the background, so reports over past periods
+//! don't wait on the rate provider.
+
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+
+use crate::currency::ExchangeRateService;
+
+/// Currency/date pairs fetched per batch.
+const BACKFILL_BATCH_SIZE: i64 = 100;
+
+/// Finds invoice and payment dates whose rates are not stored yet.
+///
+/// Only currencies that differ from the owning user's base currency are
+/// considered; future dates map to today.
+///
+/// # Returns
+///
+/// Returns up to `limit` (currency, date) pairs, most recent first.
+pub async fn missing_rate_dates(pool: &PgPool, limit: i64) -> Result<Vec<(String, NaiveDate)>, anyhow::Error> {
+    let missing = sqlx::query_as::<_, (String, NaiveDate)>(
+        r#"
+        WITH needed AS (
+            SELECT i.currency, LEAST(i.issue_date, CURRENT_DATE) AS rate_date,
+                COALESCE(s.base_currency, 'USD') AS base_currency
+            FROM invoices i
+            LEFT JOIN user_settings s ON s.user_id = i.user_id
+            WHERE i.is_deleted = false AND i.status NOT IN ('draft', 'cancelled')
+            UNION
+            SELECT p.currency, LEAST(p.paid_on, CURRENT_DATE) AS rate_date,
+                COALESCE(s.base_currency, 'USD') AS base_currency
+            FROM payments p
+            LEFT JOIN user_settings s ON s.user_id = p.user_id
+        )
+        SELECT DISTINCT n.currency, n.rate_date
+        FROM needed n
+        WHERE n.currency <> n.base_currency
+            AND NOT EXISTS (
+                SELECT 1 FROM exchange_rates r
+                WHERE r.base_currency = n.currency AND r.rate_date = n.rate_date
+            )
+        ORDER BY n.rate_date DESC, n.currency
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(missing)
+}
+
+/// Fetches and stores one batch of missing historical rates.
+///
+/// Failures for a single pair are logged and skipped so one unavailable
+/// date doesn't block the rest.
+///
+/// # Returns
+///
+/// Returns the number of (currency, date) pairs looked at, and how many of
+/// them were stored.
+pub async fn backfill_rates(pool: &PgPool, rates: &ExchangeRateService) -> Result<(usize, usize), anyhow::Error> {
+    let missing = missing_rate_dates(pool, BACKFILL_BATCH_SIZE).await?;
+
+    let mut stored = 0;
+    for (currency, date) in &missing {
+        match rates.ensure_rates(currency, *date).await {
+            Ok(_) => stored += 1,
+            Err(e) => warn!("Failed to backfill {} rates for {}: {}", currency, date, e),
+        }
+    }
+
+    Ok((missing.len(), stored))
+}
+
+/// Spawns the historical rate backfill loop.
+///
+/// Runs every `FX_BACKFILL_INTERVAL_SECONDS` (default: 3600 seconds) and
+/// keeps fetching batches until nothing is missing or a batch stores
+/// nothing.
+pub fn spawn_rate_backfill(pool: PgPool, rates: ExchangeRateService) {
+    let seconds = std::env::var("FX_BACKFILL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(3600);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            let mut total = 0;
+            loop {
+                match backfill_rates(&pool, &rates).await {
+                    Ok((seen, stored)) => {
+                        total += stored;
+                        if stored == 0 || seen < BACKFILL_BATCH_SIZE as usize {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Exchange rate backfill failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            if total > 0 {
+                info!("Backfilled exchange rates for {} currency/date pair(s)", total);
+            }
+        }
+    });
+}
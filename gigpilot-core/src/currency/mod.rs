@@ -0,0 +1,100 @@
+//! Currency codes, amount precision and conversion helpers.
+//!
+//! Amounts that carry their currency are [`Money`]; see [`money`].
+//!
+//! Exchange rates come from [`rates::ExchangeRateService`], which caches one
+//! set of daily rates per base currency. Historical rates needed by reports
+//! are fetched ahead of time by [`backfill::spawn_rate_backfill`].
+
+pub mod backfill;
+pub mod money;
+pub mod rates;
+
+pub use backfill::spawn_rate_backfill;
+pub use money::{Currency, Money, MoneyError, Percent};
+pub use rates::{ExchangeRateService, MockRateProvider, RateProvider};
+
+use rust_decimal::Decimal;
+
+/// Supported ISO 4217 currencies and their number of minor units.
+const SUPPORTED_CURRENCIES: &[(&str, u32)] = &[
+    ("AUD", 2),
+    ("BRL", 2),
+    ("CAD", 2),
+    ("CHF", 2),
+    ("CNY", 2),
+    ("CZK", 2),
+    ("DKK", 2),
+    ("EUR", 2),
+    ("GBP", 2),
+    ("HKD", 2),
+    ("INR", 2),
+    ("JPY", 0),
+    ("KRW", 0),
+    ("MXN", 2),
+    ("NOK", 2),
+    ("NZD", 2),
+    ("PLN", 2),
+    ("SEK", 2),
+    ("SGD", 2),
+    ("USD", 2),
+    ("ZAR", 2),
+];
+
+/// Currency used when none is specified.
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Number of minor units (decimal places) for a supported currency.
+pub fn minor_units(currency: &str) -> Option<u32> {
+    SUPPORTED_CURRENCIES
+        .iter()
+        .find(|(code, _)| *code == currency)
+        .map(|(_, units)| *units)
+}
+
+/// Normalizes a currency code to upper case and checks it is supported.
+///
+/// # Errors
+///
+/// Returns an error for unknown or malformed codes.
+pub fn normalize_currency(code: &str) -> Result<String, anyhow::Error> {
+    Ok(Currency::parse(code)?.code().to_string())
+}
+
+/// Validates that an amount is non-negative and representable in a currency.
+///
+/// # Errors
+///
+/// Returns an error if the amount is negative or has more decimal places
+/// than the currency's minor units (e.g. `10.5` JPY).
+pub fn validate_amount(amount: Decimal, currency: &str) -> Result<(), anyhow::Error> {
+    let units = minor_units(currency)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported currency: {}", currency))?;
+    if amount.is_sign_negative() && !amount.is_zero() {
+        anyhow::bail!("Amount must not be negative: {}", amount);
+    }
+    if amount != amount.round_dp(units) {
+        anyhow::bail!("{} amounts allow at most {} decimal places: {}", currency, units, amount);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_currency() {
+        assert_eq!(normalize_currency(" eur ").unwrap(), "EUR");
+        assert!(normalize_currency("XYZ").is_err());
+        assert!(normalize_currency("").is_err());
+    }
+
+    #[test]
+    fn test_validate_amount_precision() {
+        assert!(validate_amount(Decimal::new(1050, 2), "USD").is_ok());
+        assert!(validate_amount(Decimal::new(10505, 3), "USD").is_err());
+        assert!(validate_amount(Decimal::new(105, 1), "JPY").is_err());
+        assert!(validate_amount(Decimal::new(-1, 0), "USD").is_err());
+    }
+}
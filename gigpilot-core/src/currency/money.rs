@@ -0,0 +1,382 @@
+//! Amounts tied to their currency, and percentages.
+//!
+//! A bare `Decimal` next to a currency `String` makes it easy to add a EUR
+//! balance to a USD one. [`Money`] carries its [`Currency`] and only adds
+//! or subtracts amounts in the same one; mixing currencies is an error
+//! rather than a silently wrong total. [`Percent`] marks a value as a
+//! percentage (20 means 20%) so it is not mistaken for an amount.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::SUPPORTED_CURRENCIES;
+
+/// Errors from money arithmetic and parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    /// The currency code is not one of the supported currencies
+    UnsupportedCurrency(String),
+
+    /// Two amounts in different currencies were combined
+    CurrencyMismatch { left: Currency, right: Currency },
+
+    /// The result does not fit in a `Decimal`
+    Overflow,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::UnsupportedCurrency(code) => write!(f, "Unsupported currency: {}", code),
+            MoneyError::CurrencyMismatch { left, right } => {
+                write!(f, "Cannot combine {} and {} amounts", left, right)
+            }
+            MoneyError::Overflow => write!(f, "Amount out of range"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// A supported ISO 4217 currency.
+///
+/// Only codes from the supported list can be constructed, so the minor
+/// units are always known. Serialized as its code (e.g. `"EUR"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency {
+    code: &'static str,
+    minor_units: u32,
+}
+
+impl Currency {
+    /// Parses a currency code, ignoring case and surrounding whitespace.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MoneyError::UnsupportedCurrency` for unknown codes.
+    pub fn parse(code: &str) -> Result<Self, MoneyError> {
+        let normalized = code.trim().to_uppercase();
+        SUPPORTED_CURRENCIES
+            .iter()
+            .find(|(supported, _)| *supported == normalized)
+            .map(|(code, minor_units)| Currency { code, minor_units: *minor_units })
+            .ok_or(MoneyError::UnsupportedCurrency(normalized))
+    }
+
+    /// The upper-case ISO 4217 code.
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// Number of decimal places amounts in this currency use.
+    pub fn minor_units(&self) -> u32 {
+        self.minor_units
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Currency::parse(s)
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Currency::parse(&code).map_err(serde::de::Error::custom)
+    }
+}
+
+/// An amount in a specific currency.
+///
+/// Arithmetic between two amounts is checked: the currencies must match
+/// and the result must fit. Serialized as `{"amount": .., "currency": ".."}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Money {
+    /// The amount, in major units (e.g. 12.50)
+    pub amount: Decimal,
+
+    /// Currency of the amount
+    pub currency: Currency,
+}
+
+impl Money {
+    /// Creates an amount in a currency.
+    pub fn new(amount: Decimal, currency: Currency) -> Self {
+        Money { amount, currency }
+    }
+
+    /// Zero in a currency.
+    pub fn zero(currency: Currency) -> Self {
+        Money::new(Decimal::ZERO, currency)
+    }
+
+    /// Creates an amount from a decimal and a currency code.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MoneyError::UnsupportedCurrency` for unknown codes.
+    pub fn parse(amount: Decimal, currency: &str) -> Result<Self, MoneyError> {
+        Ok(Money::new(amount, Currency::parse(currency)?))
+    }
+
+    /// Sums amounts that must all be in `currency`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any amount is in another currency or the sum
+    /// overflows.
+    pub fn sum<I>(currency: Currency, amounts: I) -> Result<Self, MoneyError>
+    where
+        I: IntoIterator<Item = Money>,
+    {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), |total, amount| total.checked_add(amount))
+    }
+
+    /// Adds an amount in the same currency.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MoneyError::CurrencyMismatch` if the currencies differ, or
+    /// `MoneyError::Overflow` if the sum does not fit.
+    pub fn checked_add(self, other: Money) -> Result<Self, MoneyError> {
+        self.same_currency(&other)?;
+        let amount = self.amount.checked_add(other.amount).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    /// Subtracts an amount in the same currency.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MoneyError::CurrencyMismatch` if the currencies differ, or
+    /// `MoneyError::Overflow` if the difference does not fit.
+    pub fn checked_sub(self, other: Money) -> Result<Self, MoneyError> {
+        self.same_currency(&other)?;
+        let amount = self.amount.checked_sub(other.amount).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch {
+                left: self.currency,
+                right: other.currency,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the amount is zero.
+    pub fn is_zero(&self) -> bool {
+        self.amount.is_zero()
+    }
+
+    /// Whether the amount is greater than zero.
+    pub fn is_positive(&self) -> bool {
+        self.amount > Decimal::ZERO
+    }
+
+    /// The amount, or zero if it is negative.
+    pub fn max_zero(self) -> Self {
+        Money::new(self.amount.max(Decimal::ZERO), self.currency)
+    }
+
+    /// Whether the amount fits the currency's minor units (no `10.5` JPY).
+    pub fn is_representable(&self) -> bool {
+        self.amount == self.amount.round_dp(self.currency.minor_units)
+    }
+
+    /// Rounds to the currency's minor units, halves away from zero.
+    pub fn round(self) -> Self {
+        let amount = self
+            .amount
+            .round_dp_with_strategy(self.currency.minor_units, RoundingStrategy::MidpointAwayFromZero);
+        Money::new(amount, self.currency)
+    }
+
+    /// A percentage of the amount, rounded to the currency's minor units.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MoneyError::Overflow` if the result does not fit.
+    pub fn percent(self, rate: Percent) -> Result<Self, MoneyError> {
+        let amount = self
+            .amount
+            .checked_mul(rate.value())
+            .and_then(|amount| amount.checked_div(Decimal::ONE_HUNDRED))
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency).round())
+    }
+
+    /// Converts into another currency with an exchange rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - Units of `to` per unit of this amount's currency
+    /// * `to` - Target currency; the result is rounded to its minor units
+    ///
+    /// # Errors
+    ///
+    /// Returns `MoneyError::Overflow` if the result does not fit.
+    pub fn convert(self, rate: Decimal, to: Currency) -> Result<Self, MoneyError> {
+        let amount = self.amount.checked_mul(rate).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, to).round())
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:.prec$}",
+            self.currency,
+            self.amount,
+            prec = self.currency.minor_units as usize
+        )
+    }
+}
+
+/// A percentage, where 20 means 20%.
+///
+/// Serialized as the bare number, and stored as `DECIMAL`, so it replaces
+/// a `Decimal` field without changing the JSON or database shape.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(transparent)]
+#[sqlx(transparent)]
+pub struct Percent(Decimal);
+
+impl Percent {
+    /// 0%.
+    pub const ZERO: Percent = Percent(Decimal::ZERO);
+
+    /// 100%.
+    pub const HUNDRED: Percent = Percent(Decimal::ONE_HUNDRED);
+
+    /// Creates a percentage from its value (20 for 20%).
+    pub fn new(value: Decimal) -> Self {
+        Percent(value)
+    }
+
+    /// The percentage value (20 for 20%).
+    pub fn value(&self) -> Decimal {
+        self.0
+    }
+
+    /// Whether the value is a valid rate, between 0% and 100% inclusive.
+    pub fn is_rate(&self) -> bool {
+        *self >= Percent::ZERO && *self <= Percent::HUNDRED
+    }
+
+    /// The percentage of an amount, unrounded.
+    pub fn of(&self, amount: Decimal) -> Decimal {
+        amount * self.0 / Decimal::ONE_HUNDRED
+    }
+}
+
+impl From<Decimal> for Percent {
+    fn from(value: Decimal) -> Self {
+        Percent(value)
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd(amount: Decimal) -> Money {
+        Money::parse(amount, "USD").unwrap()
+    }
+
+    #[test]
+    fn test_currency_parse() {
+        let eur = Currency::parse(" eur ").unwrap();
+        assert_eq!(eur.code(), "EUR");
+        assert_eq!(eur.minor_units(), 2);
+        assert_eq!(Currency::parse("JPY").unwrap().minor_units(), 0);
+        assert_eq!(
+            Currency::parse("xyz"),
+            Err(MoneyError::UnsupportedCurrency("XYZ".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_mixed_currencies_do_not_add() {
+        let eur = Money::parse(Decimal::ONE, "EUR").unwrap();
+        let err = usd(Decimal::ONE).checked_add(eur).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot combine USD and EUR amounts");
+        assert!(Money::sum(eur.currency, [eur, usd(Decimal::ONE)]).is_err());
+
+        let total = Money::sum(eur.currency, [eur, eur]).unwrap();
+        assert_eq!(total.amount, Decimal::TWO);
+        assert_eq!(usd(Decimal::ONE).checked_sub(usd(Decimal::TWO)).unwrap().amount, Decimal::NEGATIVE_ONE);
+        assert_eq!(usd(Decimal::MAX).checked_add(usd(Decimal::ONE)), Err(MoneyError::Overflow));
+    }
+
+    #[test]
+    fn test_rounding_and_display() {
+        let yen = Money::parse(Decimal::new(1005, 1), "JPY").unwrap();
+        assert!(!yen.is_representable());
+        assert_eq!(yen.round().amount, Decimal::from(101));
+        assert_eq!(yen.round().to_string(), "JPY 101");
+        assert_eq!(usd(Decimal::new(5, 0)).to_string(), "USD 5.00");
+
+        // 100.00 USD at 151.237 JPY/USD
+        let converted = usd(Decimal::new(10000, 2)).convert(Decimal::new(151237, 3), yen.currency).unwrap();
+        assert_eq!(converted, Money::parse(Decimal::from(15124), "JPY").unwrap());
+        // 10.00 EUR at 1.08345 USD/EUR
+        let eur = Money::parse(Decimal::new(1000, 2), "EUR").unwrap();
+        let dollars = eur.convert(Decimal::new(108345, 5), Currency::parse("USD").unwrap()).unwrap();
+        assert_eq!(dollars, usd(Decimal::new(1083, 2)));
+        assert_eq!(usd(Decimal::MAX).convert(Decimal::TWO, eur.currency), Err(MoneyError::Overflow));
+    }
+
+    #[test]
+    fn test_percent() {
+        let vat = Percent::new(Decimal::from(19));
+        assert_eq!(usd(Decimal::new(1050, 2)).percent(vat).unwrap().amount, Decimal::new(200, 2));
+        assert_eq!(usd(Decimal::MAX).percent(vat), Err(MoneyError::Overflow));
+        assert_eq!(vat.of(Decimal::from(200)), Decimal::from(38));
+        assert_eq!(vat.to_string(), "19%");
+        assert!(vat.is_rate());
+        assert!(!Percent::new(Decimal::from(101)).is_rate());
+        assert!(!Percent::new(Decimal::NEGATIVE_ONE).is_rate());
+    }
+
+    #[test]
+    fn test_serde() {
+        let money = usd(Decimal::new(1250, 2));
+        let value = serde_json::to_value(money).unwrap();
+        assert_eq!(value["currency"], "USD");
+        assert_eq!(serde_json::from_value::<Money>(value).unwrap(), money);
+        assert!(serde_json::from_value::<Money>(serde_json::json!({ "amount": 1, "currency": "XYZ" })).is_err());
+
+        let rate: Percent = serde_json::from_value(serde_json::json!(20)).unwrap();
+        assert_eq!(rate.value(), Decimal::from(20));
+    }
+}
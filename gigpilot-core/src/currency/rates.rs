@@ -0,0 +1,228 @@
+//! Daily exchange rates with database persistence and an in-memory cache.
+
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// Days of recent rates kept in the in-memory cache.
+const CACHE_DAYS: i64 = 7;
+
+/// A source of daily exchange rates.
+#[async_trait]
+pub trait RateProvider: Send + Sync {
+    /// Short provider name stored alongside rates (e.g. "mock").
+    fn name(&self) -> &'static str;
+
+    /// Fetches the rates for one base currency on a date.
+    ///
+    /// # Returns
+    ///
+    /// Returns a map of quote currency -> units of quote per one unit of base.
+    async fn fetch_rates(
+        &self,
+        base: &str,
+        date: NaiveDate,
+    ) -> Result<HashMap<String, Decimal>, anyhow::Error>;
+}
+
+/// Mock exchange-rate provider.
+///
+/// In production, this would call a rates API (ECB, Open Exchange Rates,
+/// etc.). The mock serves fixed USD-based reference rates and derives
+/// cross rates from them.
+#[derive(Debug, Default)]
+pub struct MockRateProvider;
+
+impl MockRateProvider {
+    /// Units of each currency per one US dollar.
+    const USD_RATES: &'static [(&'static str, i64, u32)] = &[
+        ("USD", 1, 0),
+        ("EUR", 92, 2),
+        ("GBP", 79, 2),
+        ("CAD", 136, 2),
+        ("AUD", 152, 2),
+        ("NZD", 164, 2),
+        ("CHF", 88, 2),
+        ("JPY", 151, 0),
+        ("INR", 8330, 2),
+        ("SEK", 1045, 2),
+        ("NOK", 1060, 2),
+        ("DKK", 686, 2),
+        ("PLN", 398, 2),
+        ("CZK", 2330, 2),
+        ("SGD", 135, 2),
+        ("HKD", 782, 2),
+        ("MXN", 1690, 2),
+        ("BRL", 500, 2),
+        ("ZAR", 1880, 2),
+        ("CNY", 723, 2),
+        ("KRW", 1340, 0),
+    ];
+}
+
+#[async_trait]
+impl RateProvider for MockRateProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn fetch_rates(
+        &self,
+        base: &str,
+        date: NaiveDate,
+    ) -> Result<HashMap<String, Decimal>, anyhow::Error> {
+        info!("Mock rates: Fetching {} rates for {}", base, date);
+
+        let usd: HashMap<&str, Decimal> = Self::USD_RATES
+            .iter()
+            .map(|(code, num, scale)| (*code, Decimal::new(*num, *scale)))
+            .collect();
+        let base_per_usd = usd
+            .get(base)
+            .ok_or_else(|| anyhow::anyhow!("No rates for base currency {}", base))?;
+
+        Ok(usd
+            .iter()
+            .map(|(code, per_usd)| (code.to_string(), (per_usd / base_per_usd).round_dp(10)))
+            .collect())
+    }
+}
+
+/// Exchange-rate service with daily caching.
+///
+/// Rates are looked up in memory, then in the `exchange_rates` table, and
+/// only fetched from the provider once per base currency and day. Cheap to
+/// clone; clones share the cache.
+#[derive(Clone)]
+pub struct ExchangeRateService {
+    /// Database connection pool
+    pool: PgPool,
+
+    /// Upstream rate source
+    provider: Arc<dyn RateProvider>,
+
+    /// (base currency, date) -> quote currency -> rate
+    cache: Arc<RwLock<HashMap<(String, NaiveDate), HashMap<String, Decimal>>>>,
+}
+
+impl ExchangeRateService {
+    /// Creates a service backed by the given provider.
+    pub fn new(pool: PgPool, provider: Arc<dyn RateProvider>) -> Self {
+        Self {
+            pool,
+            provider,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Creates a service using the provider named by `EXCHANGE_RATE_PROVIDER`
+    /// (default: "mock").
+    pub fn from_env(pool: PgPool) -> Self {
+        let provider: Arc<dyn RateProvider> = match std::env::var("EXCHANGE_RATE_PROVIDER").as_deref() {
+            Ok("mock") | Err(_) => Arc::new(MockRateProvider),
+            Ok(other) => {
+                warn!("Unknown exchange rate provider: {}, defaulting to mock", other);
+                Arc::new(MockRateProvider)
+            }
+        };
+        Self::new(pool, provider)
+    }
+
+    /// Returns the rate to convert `from` into `to` on a date.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if rates cannot be loaded or the pair is unknown.
+    pub async fn rate(&self, from: &str, to: &str, date: NaiveDate) -> Result<Decimal, anyhow::Error> {
+        if from == to {
+            return Ok(Decimal::ONE);
+        }
+        let rates = self.rates_for(from, date).await?;
+        rates
+            .get(to)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("No exchange rate from {} to {} on {}", from, to, date))
+    }
+
+    /// Makes sure rates for a base currency on a date are stored, fetching
+    /// them from the provider if needed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of quote currencies available.
+    pub async fn ensure_rates(&self, base: &str, date: NaiveDate) -> Result<usize, anyhow::Error> {
+        Ok(self.rates_for(base, date).await?.len())
+    }
+
+    /// Loads all rates for a base currency on a date, caching the result.
+    async fn rates_for(
+        &self,
+        base: &str,
+        date: NaiveDate,
+    ) -> Result<HashMap<String, Decimal>, anyhow::Error> {
+        let key = (base.to_string(), date);
+        if let Some(rates) = self.cache.read().await.get(&key) {
+            return Ok(rates.clone());
+        }
+
+        let mut rates: HashMap<String, Decimal> = sqlx::query_as::<_, (String, Decimal)>(
+            "SELECT quote_currency, rate FROM exchange_rates WHERE base_currency = $1 AND rate_date = $2",
+        )
+        .bind(base)
+        .bind(date)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        if rates.is_empty() {
+            rates = self.provider.fetch_rates(base, date).await?;
+            self.store_rates(base, date, &rates).await?;
+        }
+
+        // Historical rates (reports, backfill) are read from the database
+        let horizon = Utc::now().date_naive() - Duration::days(CACHE_DAYS);
+        if date >= horizon {
+            let mut cache = self.cache.write().await;
+            cache.retain(|(_, cached_date), _| *cached_date >= horizon);
+            cache.insert(key, rates.clone());
+        }
+
+        Ok(rates)
+    }
+
+    /// Persists fetched rates so other processes reuse them.
+    async fn store_rates(
+        &self,
+        base: &str,
+        date: NaiveDate,
+        rates: &HashMap<String, Decimal>,
+    ) -> Result<(), anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        for (quote, rate) in rates {
+            sqlx::query(
+                r#"
+                INSERT INTO exchange_rates (base_currency, quote_currency, rate_date, rate, source)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (base_currency, quote_currency, rate_date) DO NOTHING
+                "#,
+            )
+            .bind(base)
+            .bind(quote)
+            .bind(date)
+            .bind(rate)
+            .bind(self.provider.name())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        info!("Stored {} {} exchange rates for {}", rates.len(), base, date);
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
+pub mod residency;
+
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 
+pub use residency::{PoolRouter, Regional};
+
 /// Create a Postgres connection pool using `DATABASE_URL` environment variable.
 ///
 /// Returns a `sqlx::PgPool` or an error if the environment is not configured or the pool cannot be created.
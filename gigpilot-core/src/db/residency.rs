@@ -0,0 +1,499 @@
+//! Data residency.
+//!
+//! An account can keep its data in a regional database cluster (e.g. in
+//! the EU), chosen at signup and stored as `users.data_region`. The
+//! cluster at `DATABASE_URL` is the home region: it holds every login,
+//! account membership and API token, and the data of accounts without a
+//! region. Each cluster listed in `DATABASE_REGIONS` runs the full schema
+//! and holds the data of the accounts that chose it, plus a copy of their
+//! `users` row.
+//!
+//! [`PoolRouter`] picks the cluster for an account. Authenticated requests
+//! are routed by [`residency_middleware`], public links by the middleware
+//! for the record they name. Each worker serves the one region it is
+//! deployed for (`DATA_REGION`, with that region's cluster as
+//! `DATABASE_URL`) and uses that region's LLM and email providers.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use sqlx::PgPool;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::db::create_pool;
+use crate::models::account::AccountKind;
+use crate::repo::DynRepository;
+use crate::settings::SettingsCache;
+
+/// Name of the home region when `DATA_REGION` is not set.
+pub const DEFAULT_HOME_REGION: &str = "home";
+
+/// Longest region name accepted.
+const MAX_REGION_LENGTH: usize = 32;
+
+/// A regional database cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionConfig {
+    /// Region name stored in `users.data_region` (e.g. "eu")
+    pub name: String,
+
+    /// PostgreSQL connection string of the region's cluster
+    pub database_url: String,
+}
+
+/// Validates a region name: lowercase letters, digits and hyphens.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the name is valid, or a message describing the
+/// problem.
+pub fn validate_region_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_REGION_LENGTH {
+        return Err(format!("region names must be 1 to {} characters", MAX_REGION_LENGTH));
+    }
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err(format!("{} is not a valid region name", name));
+    }
+    Ok(())
+}
+
+/// Suffix of a region's environment variables (`eu-west` -> `EU_WEST`).
+pub fn env_suffix(region: &str) -> String {
+    region.to_ascii_uppercase().replace('-', "_")
+}
+
+/// Reads the region configuration.
+///
+/// `DATA_REGION` names the home region (default [`DEFAULT_HOME_REGION`]);
+/// `DATABASE_REGIONS` lists the other regions, comma-separated, each with
+/// its cluster in `DATABASE_URL_<REGION>` (e.g. `DATABASE_URL_EU`).
+///
+/// # Arguments
+///
+/// * `lookup` - Reads an environment variable
+///
+/// # Returns
+///
+/// Returns the home region's name and the other regions, or a message
+/// describing the first problem found.
+pub fn parse_regions(lookup: impl Fn(&str) -> Option<String>) -> Result<(String, Vec<RegionConfig>), String> {
+    let home = lookup("DATA_REGION")
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_HOME_REGION.to_string());
+    validate_region_name(&home)?;
+
+    let mut regions: Vec<RegionConfig> = Vec::new();
+    let listed = lookup("DATABASE_REGIONS").unwrap_or_default();
+    for name in listed.split(',').map(|name| name.trim().to_ascii_lowercase()) {
+        if name.is_empty() || name == home || regions.iter().any(|region| region.name == name) {
+            continue;
+        }
+        validate_region_name(&name)?;
+        let variable = format!("DATABASE_URL_{}", env_suffix(&name));
+        let database_url = lookup(&variable)
+            .filter(|url| !url.trim().is_empty())
+            .ok_or_else(|| format!("{} is not set for region {}", variable, name))?;
+        regions.push(RegionConfig { name, database_url });
+    }
+
+    Ok((home, regions))
+}
+
+/// The region this process serves by default, read from `DATA_REGION`.
+pub fn home_region() -> String {
+    parse_regions(|name| std::env::var(name).ok())
+        .map(|(home, _)| home)
+        .unwrap_or_else(|_| DEFAULT_HOME_REGION.to_string())
+}
+
+/// Picks the database cluster of each account.
+///
+/// Cheap to clone; clones share the pools and the cached residency.
+#[derive(Clone)]
+pub struct PoolRouter {
+    /// Name of the home region
+    home_region: String,
+
+    /// region -> pool, the home region included
+    pools: Arc<HashMap<String, PgPool>>,
+
+    /// account_id -> region; an account's region never changes
+    residency: Arc<RwLock<HashMap<Uuid, String>>>,
+}
+
+impl PoolRouter {
+    /// Creates a router over the home cluster and regional clusters.
+    pub fn new(home_region: String, home: PgPool, regions: HashMap<String, PgPool>) -> Self {
+        let mut pools = regions;
+        pools.insert(home_region.clone(), home);
+        Self {
+            home_region,
+            pools: Arc::new(pools),
+            residency: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Connects the regional clusters configured in the environment (see
+    /// [`parse_regions`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid or a cluster can't
+    /// be reached.
+    pub async fn from_env(home: PgPool) -> Result<Self, anyhow::Error> {
+        let (home_region, configs) = parse_regions(|name| std::env::var(name).ok()).map_err(|e| anyhow::anyhow!(e))?;
+
+        let mut regions = HashMap::new();
+        for config in configs {
+            let pool = create_pool(&config.database_url)
+                .await
+                .map_err(|e| anyhow::anyhow!("cannot connect to the {} cluster: {}", config.name, e))?;
+            info!("Connected to the database cluster of region {}", config.name);
+            regions.insert(config.name, pool);
+        }
+
+        Ok(Self::new(home_region, home, regions))
+    }
+
+    /// Name of the home region.
+    pub fn home_region(&self) -> &str {
+        &self.home_region
+    }
+
+    /// The home cluster, which holds logins, memberships and API tokens.
+    pub fn home(&self) -> &PgPool {
+        &self.pools[&self.home_region]
+    }
+
+    /// The cluster of a region, if it is configured.
+    pub fn pool(&self, region: &str) -> Option<&PgPool> {
+        self.pools.get(region)
+    }
+
+    /// Every configured region, the home region first.
+    pub fn regions(&self) -> Vec<&str> {
+        let mut regions: Vec<&str> = self.pools.keys().map(String::as_str).collect();
+        regions.sort_by_key(|region| (*region != self.home_region, *region));
+        regions
+    }
+
+    /// Whether accounts can choose `region`.
+    pub fn has_region(&self, region: &str) -> bool {
+        self.pools.contains_key(region)
+    }
+
+    /// Loads the region of an account.
+    ///
+    /// # Returns
+    ///
+    /// Returns the account's region (the home region when it has none), or
+    /// `None` if no such account exists.
+    pub async fn region_of(&self, account_id: Uuid) -> Result<Option<String>, anyhow::Error> {
+        if let Some(region) = self.residency.read().await.get(&account_id) {
+            return Ok(Some(region.clone()));
+        }
+
+        let stored = sqlx::query_scalar::<_, Option<String>>("SELECT data_region FROM users WHERE id = $1")
+            .bind(account_id)
+            .fetch_optional(self.home())
+            .await?;
+        let Some(stored) = stored else {
+            return Ok(None);
+        };
+
+        let region = stored.unwrap_or_else(|| self.home_region.clone());
+        self.residency.write().await.insert(account_id, region.clone());
+        Ok(Some(region))
+    }
+
+    /// Finds the region holding a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `exists_sql` - Query returning whether the record with ID `$1`
+    ///   exists in a cluster
+    /// * `id` - ID of the record
+    ///
+    /// # Returns
+    ///
+    /// Returns the first region (home first) whose cluster has the record,
+    /// or `None` if none has.
+    pub async fn locate(&self, exists_sql: &str, id: Uuid) -> Result<Option<String>, anyhow::Error> {
+        for region in self.regions() {
+            let exists = sqlx::query_scalar::<_, bool>(exists_sql)
+                .bind(id)
+                .fetch_one(&self.pools[region])
+                .await?;
+            if exists {
+                return Ok(Some(region.to_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Copies an account's `users` row into its region's cluster, so the
+    /// data it creates there can refer to it. Accounts of the home region
+    /// need nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the account doesn't exist, its region is not
+    /// configured, or a query fails.
+    pub async fn provision_account(&self, account_id: Uuid) -> Result<(), anyhow::Error> {
+        let account = sqlx::query_as::<_, (String, Option<String>, AccountKind, Option<String>)>(
+            "SELECT email, full_name, kind, data_region FROM users WHERE id = $1",
+        )
+        .bind(account_id)
+        .fetch_optional(self.home())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("account {} not found", account_id))?;
+
+        let (email, full_name, kind, data_region) = account;
+        let Some(region) = data_region.filter(|region| *region != self.home_region) else {
+            return Ok(());
+        };
+        let pool = self
+            .pool(&region)
+            .ok_or_else(|| anyhow::anyhow!("region {} of account {} is not configured", region, account_id))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, email, full_name, kind, data_region)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        )
+        .bind(account_id)
+        .bind(email)
+        .bind(full_name)
+        .bind(kind)
+        .bind(&region)
+        .execute(pool)
+        .await?;
+
+        info!("Provisioned account {} in region {}", account_id, region);
+        Ok(())
+    }
+}
+
+/// Per-region values built over each region's pool, such as caches and
+/// repositories. Cheap to clone.
+#[derive(Clone)]
+pub struct Regional<T> {
+    values: Arc<HashMap<String, T>>,
+}
+
+impl<T: Clone> Regional<T> {
+    /// Builds a value for every region of `router`.
+    pub fn build(router: &PoolRouter, build: impl Fn(&str, &PgPool) -> T) -> Self {
+        let values = router
+            .pools
+            .iter()
+            .map(|(region, pool)| (region.clone(), build(region, pool)))
+            .collect();
+        Self { values: Arc::new(values) }
+    }
+
+    /// The value of a region.
+    pub fn get(&self, region: &str) -> Option<T> {
+        self.values.get(region).cloned()
+    }
+
+    /// Every region's value.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.values.values()
+    }
+}
+
+/// Points a request's pool, settings cache and repository at `region`.
+///
+/// # Errors
+///
+/// Returns `503` if the region is not configured on this server.
+pub fn enter_region<B>(router: &PoolRouter, req: &mut Request<B>, region: &str) -> Result<(), StatusCode> {
+    let pool = router.pool(region).cloned().ok_or_else(|| {
+        error!("Region {} is not configured on this server", region);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+    let settings_cache = req
+        .extensions()
+        .get::<Regional<SettingsCache>>()
+        .and_then(|caches| caches.get(region));
+    let repository = req
+        .extensions()
+        .get::<Regional<DynRepository>>()
+        .and_then(|repositories| repositories.get(region));
+
+    req.extensions_mut().insert(pool);
+    if let Some(settings_cache) = settings_cache {
+        req.extensions_mut().insert(settings_cache);
+    }
+    if let Some(repository) = repository {
+        req.extensions_mut().insert(repository);
+    }
+    Ok(())
+}
+
+/// Routes a request to the cluster of `account_id`'s region. Unknown
+/// accounts stay in the home region.
+async fn route_to_account<B>(req: Request<B>, next: Next<B>, account_id: Uuid) -> Result<Response, StatusCode> {
+    let mut req = req;
+    let router = req
+        .extensions()
+        .get::<PoolRouter>()
+        .cloned()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let region = router
+        .region_of(account_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load the region of account {}: {}", account_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .unwrap_or_else(|| router.home_region().to_string());
+
+    enter_region(&router, &mut req, &region)?;
+    Ok(next.run(req).await)
+}
+
+/// Routes a public link to the region holding the record it names.
+/// Records found in no region are left to the home region (and its `404`).
+async fn route_to_record<B>(req: Request<B>, next: Next<B>, exists_sql: &str, id: Uuid) -> Result<Response, StatusCode> {
+    let mut req = req;
+    let router = req
+        .extensions()
+        .get::<PoolRouter>()
+        .cloned()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let region = router
+        .locate(exists_sql, id)
+        .await
+        .map_err(|e| {
+            error!("Failed to find the region of record {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .unwrap_or_else(|| router.home_region().to_string());
+
+    enter_region(&router, &mut req, &region)?;
+    Ok(next.run(req).await)
+}
+
+/// The UUID path parameter `name`, if the route has one.
+fn path_id(params: &Option<Path<HashMap<String, String>>>, name: &str) -> Option<Uuid> {
+    params.as_ref()?.get(name)?.parse().ok()
+}
+
+/// Middleware routing authenticated requests to the current account's
+/// region. Must run after `auth::jwt_middleware`.
+pub async fn residency_middleware<B>(req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+    match req.extensions().get::<CurrentUser>().map(|current| current.0) {
+        Some(account_id) => route_to_account(req, next, account_id).await,
+        None => Ok(next.run(req).await),
+    }
+}
+
+/// Middleware routing admin requests to the region of the user named in
+/// their path (`/admin/users/:user_id/...`).
+pub async fn admin_residency_middleware<B>(
+    params: Option<Path<HashMap<String, String>>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    match path_id(&params, "user_id") {
+        Some(user_id) => route_to_account(req, next, user_id).await,
+        None => Ok(next.run(req).await),
+    }
+}
+
+/// Middleware routing pay page links (`/pay/:id/...`) to the region of
+/// the invoice.
+pub async fn pay_link_middleware<B>(
+    params: Option<Path<HashMap<String, String>>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let invoice_id = path_id(&params, "id").ok_or(StatusCode::NOT_FOUND)?;
+    route_to_record(req, next, "SELECT EXISTS (SELECT 1 FROM invoices WHERE id = $1)", invoice_id).await
+}
+
+/// Middleware routing shared proposal links (`/proposals/:token/...`) to
+/// the region of the proposal.
+pub async fn proposal_link_middleware<B>(
+    params: Option<Path<HashMap<String, String>>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let share_token = path_id(&params, "token").ok_or(StatusCode::NOT_FOUND)?;
+    route_to_record(
+        req,
+        next,
+        "SELECT EXISTS (SELECT 1 FROM proposals WHERE share_token = $1)",
+        share_token,
+    )
+    .await
+}
+
+/// Middleware routing client portal requests to the region of the portal
+/// token they carry (see `auth::portal_middleware`, which must run after).
+pub async fn portal_residency_middleware<B>(req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+    match crate::auth::portal_token_id(&req) {
+        Some(token_id) => {
+            route_to_record(
+                req,
+                next,
+                "SELECT EXISTS (SELECT 1 FROM client_portal_tokens WHERE id = $1)",
+                token_id,
+            )
+            .await
+        }
+        // Rejected by the portal middleware
+        None => Ok(next.run(req).await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+    }
+
+    #[test]
+    fn test_parse_regions() {
+        assert_eq!(parse_regions(env(&[])), Ok((DEFAULT_HOME_REGION.to_string(), Vec::new())));
+
+        let (home, regions) = parse_regions(env(&[
+            ("DATA_REGION", "US"),
+            ("DATABASE_REGIONS", "eu, eu-west,,us,eu"),
+            ("DATABASE_URL_EU", "postgres://eu"),
+            ("DATABASE_URL_EU_WEST", "postgres://eu-west"),
+        ]))
+        .unwrap();
+        assert_eq!(home, "us");
+        assert_eq!(
+            regions,
+            vec![
+                RegionConfig { name: "eu".to_string(), database_url: "postgres://eu".to_string() },
+                RegionConfig { name: "eu-west".to_string(), database_url: "postgres://eu-west".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_regions_rejects_incomplete_configuration() {
+        let missing = parse_regions(env(&[("DATABASE_REGIONS", "eu")]));
+        assert_eq!(missing, Err("DATABASE_URL_EU is not set for region eu".to_string()));
+
+        assert!(parse_regions(env(&[("DATABASE_REGIONS", "eu_central"), ("DATABASE_URL_EU_CENTRAL", "x")])).is_err());
+        assert!(parse_regions(env(&[("DATA_REGION", "home region")])).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use axum::{
+    extract::{Extension, Multipart, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::attachments::{sanitize_filename, validate_attachment};
+use crate::auth::{CurrentPortal, CurrentUser};
+use crate::disputes::{find_dispute, list_disputes, resolve_dispute, submit_dispute, validate_dispute};
+use crate::invoices::find_invoice;
+use crate::models::dispute::{Dispute, DisputeAttachment};
+use crate::portal::{find_portal_invoice, PortalInvoice};
+use crate::storage::DynBlobStore;
+
+/// Name of the multipart field carrying the reason.
+const REASON_FIELD: &str = "reason";
+
+/// Name of the multipart field carrying the optional file.
+const FILE_FIELD: &str = "file";
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Dispute submission endpoint handler.
+///
+/// Handles POST requests to `/portal/invoices/:id/dispute` with a portal
+/// token. The body is `multipart/form-data` with a `reason` field and an
+/// optional `file` field. Chasing of the invoice is held and the user is
+/// notified.
+pub async fn submit_dispute_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentPortal(portal)): Extension<CurrentPortal>,
+    Path(invoice_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<PortalInvoice>), (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to record dispute for invoice {}: {}", invoice_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to record dispute")
+    };
+    let bad_request = |e: axum::extract::multipart::MultipartError| error_response(StatusCode::BAD_REQUEST, &e.to_string());
+
+    let invoice = find_portal_invoice(&pool, &portal, invoice_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "invoice not found"))?;
+
+    let mut reason = String::new();
+    let mut attachment = None;
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        match field.name() {
+            Some(REASON_FIELD) => reason = field.text().await.map_err(bad_request)?,
+            Some(FILE_FIELD) => {
+                let filename = sanitize_filename(field.file_name().unwrap_or_default());
+                let content_type = field
+                    .content_type()
+                    .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase())
+                    .unwrap_or_default();
+                let data = field.bytes().await.map_err(bad_request)?;
+                // Browsers send an empty part when no file was picked
+                if !data.is_empty() {
+                    attachment = Some(DisputeAttachment { filename, content_type, data: data.to_vec() });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Err(message) = validate_dispute(&invoice, &reason) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    if let Some(attachment) = &attachment {
+        if let Err(message) = validate_attachment(&attachment.content_type, attachment.data.len()) {
+            return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+        }
+    }
+
+    let (_, updated) = submit_dispute(&pool, store.as_ref(), &portal, &invoice, &reason, attachment)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "invoice is already disputed"))?;
+
+    Ok((StatusCode::CREATED, Json(PortalInvoice::new(&updated, Utc::now().date_naive()))))
+}
+
+/// List disputes endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/disputes`.
+pub async fn list_disputes_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<Vec<Dispute>>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to list disputes of invoice {}: {}", invoice_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    find_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let disputes = list_disputes(&pool, user_id, invoice_id).await.map_err(internal_error)?;
+
+    Ok(Json(disputes))
+}
+
+/// Resolve dispute endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/:id/disputes/:dispute_id/resolve`.
+/// Chasing resumes from where it was before the dispute.
+pub async fn resolve_dispute_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path((invoice_id, dispute_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Dispute>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to resolve dispute {}: {}", dispute_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to resolve dispute")
+    };
+
+    find_dispute(&pool, user_id, invoice_id, dispute_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "dispute not found"))?;
+    let dispute = resolve_dispute(&pool, user_id, invoice_id, dispute_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "dispute is already resolved"))?;
+
+    Ok(Json(dispute))
+}
+
+/// Dispute attachment download endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/disputes/:dispute_id/attachment`.
+pub async fn dispute_attachment_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path((invoice_id, dispute_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let dispute = find_dispute(&pool, user_id, invoice_id, dispute_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load dispute {}: {}", dispute_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let (Some(key), Some(filename), Some(content_type)) = (
+        dispute.attachment_storage_key,
+        dispute.attachment_filename,
+        dispute.attachment_content_type,
+    ) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let data = store.get(&key).await.map_err(|e| {
+        error!("Failed to read attachment of dispute {}: {}", dispute_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let disposition = format!("attachment; filename=\"{}\"", filename);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        data,
+    ))
+}
@@ -0,0 +1,344 @@
+//! Invoice disputes raised by clients.
+//!
+//! A client disputes an invoice from their portal with a reason and,
+//! optionally, a file. The invoice's chase state moves to
+//! [`ChaseState::Disputed`], which holds automated reminders, and the user
+//! is notified in the app right away and by email from the worker (see
+//! `worker::disputes`). Resolving the dispute restores the chase state the
+//! invoice had before it.
+
+pub mod handlers;
+
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::invoices::history::apply_current_audit_context;
+use crate::models::dispute::{Dispute, DisputeAttachment};
+use crate::models::invoice::Invoice;
+use crate::models::notification::CreateNotification;
+use crate::models::portal::{PortalEventKind, PortalToken};
+use crate::models::sync_change::SyncOperation;
+use crate::notifications::notify;
+use crate::portal::{record_event, INVOICE_COLUMNS};
+use crate::storage::BlobStore;
+use crate::sync::server::record_server_change;
+use crate::worker::state_machine::ChaseState;
+
+/// Longest reason accepted with a dispute.
+pub const MAX_REASON_LENGTH: usize = 2000;
+
+/// Validates a dispute against the invoice it is for.
+///
+/// # Returns
+///
+/// Returns a user-facing message describing the first problem found.
+pub fn validate_dispute(invoice: &Invoice, reason: &str) -> Result<(), String> {
+    if invoice.balance_due().is_zero() {
+        return Err("invoice is already paid".to_string());
+    }
+    if reason.trim().is_empty() {
+        return Err("reason is required".to_string());
+    }
+    if reason.trim().chars().count() > MAX_REASON_LENGTH {
+        return Err(format!("reason must be at most {} characters", MAX_REASON_LENGTH));
+    }
+    Ok(())
+}
+
+/// Storage key for a dispute's attachment.
+///
+/// Built only from IDs, so client-supplied file names never reach the
+/// storage backend.
+pub fn storage_key(user_id: Uuid, invoice_id: Uuid, dispute_id: Uuid) -> String {
+    format!("disputes/{}/{}/{}", user_id, invoice_id, dispute_id)
+}
+
+/// Records a client's dispute of an invoice and holds chasing.
+///
+/// The dispute, the invoice's move to the `disputed` chase state (recorded
+/// for sync and attributed to the portal in the invoice history), the
+/// portal activity and the user's notification are written in one
+/// transaction; the attachment is stored before it commits.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `store` - Storage backend for the attachment
+/// * `portal` - Token the client used
+/// * `invoice` - The invoice (from [`crate::portal::find_portal_invoice`])
+/// * `reason` - Why the client disputes it (see [`validate_dispute`])
+/// * `attachment` - Optional file from the client (already validated)
+///
+/// # Returns
+///
+/// Returns the stored `Dispute` and the updated `Invoice`, or `None` if
+/// the invoice already has an open dispute.
+pub async fn submit_dispute(
+    pool: &PgPool,
+    store: &dyn BlobStore,
+    portal: &PortalToken,
+    invoice: &Invoice,
+    reason: &str,
+    attachment: Option<DisputeAttachment>,
+) -> Result<Option<(Dispute, Invoice)>, anyhow::Error> {
+    let reason = reason.trim();
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    // Lock the invoice so concurrent submissions can't both open a dispute
+    let (metadata,): (Option<serde_json::Value>,) =
+        sqlx::query_as("SELECT metadata FROM invoices WHERE id = $1 FOR UPDATE")
+            .bind(invoice.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    let open: Option<(Uuid,)> =
+        sqlx::query_as("SELECT id FROM invoice_disputes WHERE invoice_id = $1 AND status = 'open'")
+            .bind(invoice.id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if open.is_some() {
+        return Ok(None);
+    }
+
+    let previous_state = metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("chase_state"))
+        .and_then(|state| state.as_str())
+        .map(str::to_string);
+
+    let dispute_id = Uuid::new_v4();
+    let key = attachment
+        .as_ref()
+        .map(|_| storage_key(portal.user_id, invoice.id, dispute_id));
+
+    let dispute = sqlx::query_as::<_, Dispute>(
+        r#"
+        INSERT INTO invoice_disputes (
+            id, user_id, invoice_id, client_id, token_id, reason,
+            attachment_filename, attachment_content_type, attachment_size_bytes, attachment_storage_key,
+            previous_chase_state
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING *
+        "#,
+    )
+    .bind(dispute_id)
+    .bind(portal.user_id)
+    .bind(invoice.id)
+    .bind(portal.client_id)
+    .bind(portal.id)
+    .bind(reason)
+    .bind(attachment.as_ref().map(|a| a.filename.as_str()))
+    .bind(attachment.as_ref().map(|a| a.content_type.as_str()))
+    .bind(attachment.as_ref().map(|a| a.data.len() as i64))
+    .bind(&key)
+    .bind(&previous_state)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let updated = sqlx::query_as::<_, Invoice>(&format!(
+        r#"
+        UPDATE invoices
+        SET metadata = jsonb_set(COALESCE(metadata, '{{}}'::jsonb), '{{chase_state}}', to_jsonb($2::text)),
+            last_modified = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        INVOICE_COLUMNS
+    ))
+    .bind(invoice.id)
+    .bind(ChaseState::Disputed.to_string())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        updated.user_id,
+        "invoices",
+        updated.id,
+        SyncOperation::Update,
+        &serde_json::to_value(&updated)?,
+    )
+    .await?;
+    record_event(
+        &mut *tx,
+        portal,
+        Some(invoice.id),
+        PortalEventKind::DisputeSubmitted,
+        Some(json!({ "dispute_id": dispute.id, "reason": reason })),
+    )
+    .await?;
+    notify(
+        &mut tx,
+        portal.user_id,
+        CreateNotification {
+            kind: "invoice_disputed".to_string(),
+            title: format!("{} disputed invoice {}", invoice.client_name, invoice.invoice_number),
+            body: Some(format!("\"{}\" Reminders are on hold until you resolve the dispute.", reason)),
+            data: Some(json!({
+                "invoice_id": invoice.id,
+                "client_id": portal.client_id,
+                "dispute_id": dispute.id,
+            })),
+        },
+    )
+    .await?;
+
+    if let (Some(attachment), Some(key)) = (&attachment, &key) {
+        store.put(key, &attachment.content_type, &attachment.data).await?;
+    }
+    tx.commit().await?;
+
+    Ok(Some((dispute, updated)))
+}
+
+/// Lists an invoice's disputes, newest first.
+pub async fn list_disputes(pool: &PgPool, user_id: Uuid, invoice_id: Uuid) -> Result<Vec<Dispute>, anyhow::Error> {
+    let disputes = sqlx::query_as::<_, Dispute>(
+        r#"
+        SELECT * FROM invoice_disputes
+        WHERE invoice_id = $1 AND user_id = $2
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(disputes)
+}
+
+/// Loads one dispute of an invoice owned by the given user.
+pub async fn find_dispute(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    dispute_id: Uuid,
+) -> Result<Option<Dispute>, anyhow::Error> {
+    let dispute = sqlx::query_as::<_, Dispute>(
+        "SELECT * FROM invoice_disputes WHERE id = $1 AND invoice_id = $2 AND user_id = $3",
+    )
+    .bind(dispute_id)
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(dispute)
+}
+
+/// Resolves an open dispute and resumes chasing.
+///
+/// The invoice gets back the chase state it had before the dispute (none
+/// if it had not been chased yet), unless it has left the `disputed` state
+/// in the meantime (e.g. it was paid); that change is recorded for sync.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user owning the invoice
+/// * `invoice_id` - The disputed invoice
+/// * `dispute_id` - The dispute to resolve
+///
+/// # Returns
+///
+/// Returns the resolved `Dispute`, or `None` if no open dispute matches.
+pub async fn resolve_dispute(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    dispute_id: Uuid,
+) -> Result<Option<Dispute>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let dispute = sqlx::query_as::<_, Dispute>(
+        r#"
+        UPDATE invoice_disputes
+        SET status = 'resolved', resolved_at = NOW()
+        WHERE id = $1 AND invoice_id = $2 AND user_id = $3 AND status = 'open'
+        RETURNING *
+        "#,
+    )
+    .bind(dispute_id)
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(dispute) = dispute else {
+        return Ok(None);
+    };
+
+    let restored = sqlx::query_as::<_, Invoice>(&format!(
+        r#"
+        UPDATE invoices
+        SET metadata = CASE
+                WHEN $3::text IS NULL THEN metadata - 'chase_state'
+                ELSE jsonb_set(metadata, '{{chase_state}}', to_jsonb($3::text))
+            END,
+            last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND metadata->>'chase_state' = $4
+        RETURNING {}
+        "#,
+        INVOICE_COLUMNS
+    ))
+    .bind(invoice_id)
+    .bind(user_id)
+    .bind(&dispute.previous_chase_state)
+    .bind(ChaseState::Disputed.to_string())
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(invoice) = restored {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(&invoice)?,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Some(dispute))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::repo::memory::sample_invoice;
+
+    #[test]
+    fn test_validate_dispute() {
+        let due = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let open = sample_invoice(Uuid::new_v4(), due, Decimal::new(10000, 2));
+
+        assert!(validate_dispute(&open, "Hours on line 2 were not agreed").is_ok());
+        assert_eq!(validate_dispute(&open, "  "), Err("reason is required".to_string()));
+        assert!(validate_dispute(&open, &"x".repeat(MAX_REASON_LENGTH + 1)).is_err());
+
+        let mut paid = open.clone();
+        paid.amount_paid = paid.total;
+        assert_eq!(
+            validate_dispute(&paid, "Wrong amount"),
+            Err("invoice is already paid".to_string())
+        );
+    }
+
+    #[test]
+    fn test_storage_key_uses_ids_only() {
+        let (user_id, invoice_id, dispute_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(
+            storage_key(user_id, invoice_id, dispute_id),
+            format!("disputes/{}/{}/{}", user_id, invoice_id, dispute_id)
+        );
+    }
+}
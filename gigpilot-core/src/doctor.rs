@@ -0,0 +1,479 @@
+//! Startup self-check ("configuration doctor").
+//!
+//! [`run_checks`] verifies the database connection and schema version,
+//! pgvector, the JWT secret, external provider settings and the email
+//! sender. The server runs it before accepting traffic and refuses to start
+//! on errors; `gigpilot-core --check` prints the full report and exits.
+
+use std::fmt;
+
+use sqlx::PgPool;
+
+use crate::db::create_pool;
+use crate::db::residency::parse_regions;
+use crate::storage::StorageConfig;
+
+/// Shortest JWT secret accepted, in bytes (HS256 uses a 256-bit key).
+pub const MIN_JWT_SECRET_BYTES: usize = 32;
+
+/// Well-known placeholder secrets that must never be used.
+const WEAK_JWT_SECRETS: &[&str] = &["secret", "changeme", "change-me", "jwt_secret", "your-secret-key"];
+
+/// Known exchange-rate providers (`EXCHANGE_RATE_PROVIDER`).
+const RATE_PROVIDERS: &[&str] = &["mock"];
+
+/// Known OCR providers (`OCR_PROVIDER`).
+const OCR_PROVIDERS: &[&str] = &["mock"];
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// Everything is in order
+    Ok,
+
+    /// Works, but probably not what production wants
+    Warning,
+
+    /// The server must not start
+    Error,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckStatus::Ok => write!(f, "ok"),
+            CheckStatus::Warning => write!(f, "warn"),
+            CheckStatus::Error => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// Result of one check, with a hint on how to fix problems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// Short name of what was checked (e.g. "jwt secret")
+    pub name: &'static str,
+
+    /// Outcome
+    pub status: CheckStatus,
+
+    /// What was found
+    pub message: String,
+
+    /// What to do about it (warnings and errors only)
+    pub hint: Option<String>,
+}
+
+impl CheckResult {
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warning,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn error(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Error,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Every check result of one run.
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    /// Results in the order the checks ran
+    pub results: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    /// Whether any check failed.
+    pub fn has_errors(&self) -> bool {
+        self.results.iter().any(|r| r.status == CheckStatus::Error)
+    }
+
+    /// Results with the given status.
+    pub fn with_status(&self, status: CheckStatus) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().filter(move |r| r.status == status)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            writeln!(f, "[{:>4}] {}: {}", result.status, result.name, result.message)?;
+            if let Some(hint) = &result.hint {
+                writeln!(f, "       -> {}", hint)?;
+            }
+        }
+
+        let errors = self.with_status(CheckStatus::Error).count();
+        let warnings = self.with_status(CheckStatus::Warning).count();
+        write!(f, "{} error(s), {} warning(s)", errors, warnings)
+    }
+}
+
+/// Checks the JWT signing secret.
+///
+/// # Arguments
+///
+/// * `secret` - Value of `JWT_SECRET`, if set
+pub fn check_jwt_secret(secret: Option<&str>) -> CheckResult {
+    const NAME: &str = "jwt secret";
+    const HINT: &str = "set JWT_SECRET to a random value, e.g. the output of `openssl rand -base64 48`";
+
+    let Some(secret) = secret.filter(|s| !s.is_empty()) else {
+        return CheckResult::error(NAME, "JWT_SECRET is not set", HINT);
+    };
+    if WEAK_JWT_SECRETS.contains(&secret.to_lowercase().as_str()) {
+        return CheckResult::error(NAME, "JWT_SECRET is a well-known placeholder", HINT);
+    }
+    if secret.len() < MIN_JWT_SECRET_BYTES {
+        return CheckResult::error(
+            NAME,
+            format!(
+                "JWT_SECRET is {} bytes, at least {} are required",
+                secret.len(),
+                MIN_JWT_SECRET_BYTES
+            ),
+            HINT,
+        );
+    }
+    if secret.chars().collect::<std::collections::HashSet<_>>().len() < 8 {
+        return CheckResult::error(NAME, "JWT_SECRET has too few distinct characters", HINT);
+    }
+
+    CheckResult::ok(NAME, format!("{} bytes", secret.len()))
+}
+
+/// Checks the data region configuration (see [`crate::db::residency`]).
+///
+/// # Arguments
+///
+/// * `env` - Looks up an environment variable (`std::env::var` in production)
+pub fn check_data_regions(env: &dyn Fn(&str) -> Option<String>) -> CheckResult {
+    const NAME: &str = "data regions";
+
+    match parse_regions(env) {
+        Ok((home, regions)) if regions.is_empty() => CheckResult::ok(NAME, format!("home region {} only", home)),
+        Ok((home, regions)) => {
+            let names: Vec<&str> = regions.iter().map(|region| region.name.as_str()).collect();
+            CheckResult::ok(NAME, format!("home region {}, also {}", home, names.join(", ")))
+        }
+        Err(message) => CheckResult::error(
+            NAME,
+            message,
+            "list regional clusters in DATABASE_REGIONS (e.g. \"eu\") with DATABASE_URL_<REGION> for each",
+        ),
+    }
+}
+
+/// Checks provider selection and credentials.
+///
+/// # Arguments
+///
+/// * `env` - Looks up an environment variable (`std::env::var` in production)
+pub fn check_providers(env: &dyn Fn(&str) -> Option<String>) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+
+    for (name, var, known) in [
+        ("exchange rates", "EXCHANGE_RATE_PROVIDER", RATE_PROVIDERS),
+        ("ocr", "OCR_PROVIDER", OCR_PROVIDERS),
+    ] {
+        let provider = env(var).unwrap_or_else(|| "mock".to_string());
+        results.push(if !known.contains(&provider.as_str()) {
+            CheckResult::warning(
+                name,
+                format!("unknown provider {:?}, the mock is used instead", provider),
+                format!("set {} to one of: {}", var, known.join(", ")),
+            )
+        } else {
+            CheckResult::ok(name, format!("{} provider", provider))
+        });
+    }
+
+    results.push(match env("OPENAI_API_KEY").filter(|k| !k.is_empty()) {
+        None => CheckResult::warning(
+            "embeddings",
+            "OPENAI_API_KEY is not set, mock embeddings are used",
+            "set OPENAI_API_KEY for meaningful estimate suggestions",
+        ),
+        Some(key) if !key.starts_with("sk-") => CheckResult::error(
+            "embeddings",
+            "OPENAI_API_KEY does not look like an OpenAI key",
+            "OpenAI keys start with \"sk-\"; check for stray quotes or whitespace",
+        ),
+        Some(_) => CheckResult::ok("embeddings", "OpenAI API key configured"),
+    });
+
+    let storage = StorageConfig::from_env(env);
+    results.push(match storage.backend.as_str() {
+        "s3" if storage.s3_bucket.is_none() => CheckResult::error(
+            "blob storage",
+            "STORAGE_BACKEND=s3 but STORAGE_S3_BUCKET is not set",
+            "set STORAGE_S3_BUCKET to the bucket holding attachments and cached files",
+        ),
+        "s3" if env("AWS_ACCESS_KEY_ID").is_none()
+            && env("AWS_PROFILE").is_none()
+            && env("AWS_WEB_IDENTITY_TOKEN_FILE").is_none() =>
+        {
+            CheckResult::warning(
+                "blob storage",
+                "no AWS credentials found in the environment",
+                "set AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY or AWS_PROFILE unless an instance role is used",
+            )
+        }
+        "s3" => CheckResult::ok("blob storage", "s3"),
+        "local" => CheckResult::ok("blob storage", format!("local directory {}", storage.dir)),
+        "memory" => CheckResult::warning(
+            "blob storage",
+            "blobs are kept in memory and lost on restart",
+            "set STORAGE_BACKEND to \"local\" or \"s3\" to keep attachments",
+        ),
+        other => CheckResult::warning(
+            "blob storage",
+            format!("unknown storage {:?}, local disk is used instead", other),
+            "set STORAGE_BACKEND to \"local\", \"s3\" or \"memory\"",
+        ),
+    });
+
+    results
+}
+
+/// Checks the address chase emails are sent from.
+///
+/// # Arguments
+///
+/// * `sender` - Value of `EMAIL_FROM`, if set
+pub fn check_email_sender(sender: Option<&str>) -> CheckResult {
+    const NAME: &str = "email sender";
+
+    let Some(sender) = sender.map(str::trim).filter(|s| !s.is_empty()) else {
+        return CheckResult::error(
+            NAME,
+            "EMAIL_FROM is not set",
+            "set EMAIL_FROM to an address verified with your email provider",
+        );
+    };
+
+    // Accept both "billing@example.com" and "Name <billing@example.com>"
+    let address = match (sender.rfind('<'), sender.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &sender[start + 1..end],
+        _ => sender,
+    };
+    let domain = match address.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && domain.contains('.') && !domain.ends_with('.') => domain,
+        _ => {
+            return CheckResult::error(
+                NAME,
+                format!("EMAIL_FROM {:?} is not a valid email address", sender),
+                "use \"billing@yourdomain.com\" or \"Your Business <billing@yourdomain.com>\"",
+            )
+        }
+    };
+
+    let domain = domain.to_lowercase();
+    if ["example.com", "example.org", "localhost", "localdomain"]
+        .iter()
+        .any(|placeholder| domain == *placeholder || domain.ends_with(&format!(".{}", placeholder)))
+    {
+        return CheckResult::warning(
+            NAME,
+            format!("{} uses a placeholder domain", address),
+            "send from a domain verified (SPF/DKIM) with your email provider",
+        );
+    }
+
+    CheckResult::ok(NAME, address.to_string())
+}
+
+/// Compares applied migrations with those embedded in this build.
+///
+/// # Arguments
+///
+/// * `expected` - Versions of the migrations shipped with this build
+/// * `applied` - Versions successfully applied to the database
+pub fn check_schema_version(expected: &[i64], applied: &[i64]) -> CheckResult {
+    const NAME: &str = "schema version";
+
+    let pending: Vec<i64> = expected.iter().copied().filter(|v| !applied.contains(v)).collect();
+    let latest_known = expected.iter().copied().max().unwrap_or_default();
+    let unknown = applied.iter().filter(|v| **v > latest_known).count();
+
+    if !pending.is_empty() {
+        return CheckResult::error(
+            NAME,
+            format!(
+                "{} migration(s) not applied (first pending: {})",
+                pending.len(),
+                pending.iter().min().unwrap_or(&0)
+            ),
+            "run `sqlx migrate run` in gigpilot-core",
+        );
+    }
+    if unknown > 0 {
+        return CheckResult::warning(
+            NAME,
+            format!("database has {} migration(s) newer than this build", unknown),
+            "deploy the latest server build",
+        );
+    }
+
+    CheckResult::ok(NAME, latest_known.to_string())
+}
+
+/// Checks the schema version and pgvector on a connected database.
+async fn check_database(pool: &PgPool) -> Vec<CheckResult> {
+    let mut results = vec![CheckResult::ok("database", "connected")];
+
+    let expected: Vec<i64> = sqlx::migrate!("./migrations")
+        .migrations
+        .iter()
+        .map(|m| m.version)
+        .collect();
+    let applied = sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success = true")
+        .fetch_all(pool)
+        .await;
+    results.push(match applied {
+        Ok(applied) => check_schema_version(&expected, &applied),
+        Err(e) => CheckResult::error(
+            "schema version",
+            format!("cannot read applied migrations: {}", e),
+            "run `sqlx migrate run` in gigpilot-core",
+        ),
+    });
+
+    let installed = sqlx::query_scalar::<_, String>("SELECT extversion FROM pg_extension WHERE extname = 'vector'")
+        .fetch_optional(pool)
+        .await;
+    results.push(match installed {
+        Ok(Some(version)) => CheckResult::ok("pgvector", format!("version {}", version)),
+        Ok(None) => CheckResult::error(
+            "pgvector",
+            "the vector extension is not installed in this database",
+            "install pgvector on the PostgreSQL server (e.g. the pgvector/pgvector image) and run the migrations",
+        ),
+        Err(e) => CheckResult::error(
+            "pgvector",
+            format!("cannot query extensions: {}", e),
+            "check that DATABASE_URL points at PostgreSQL",
+        ),
+    });
+
+    results
+}
+
+/// Runs every check against the environment and database.
+///
+/// # Arguments
+///
+/// * `database_url` - PostgreSQL connection string
+///
+/// # Returns
+///
+/// Returns the report and, if the database was reachable, its pool so the
+/// caller can reuse the connection.
+pub async fn run_checks(database_url: &str) -> (DoctorReport, Option<PgPool>) {
+    let env = |name: &str| std::env::var(name).ok();
+    let mut report = DoctorReport::default();
+
+    let pool = match create_pool(database_url).await {
+        Ok(pool) => {
+            report.results.extend(check_database(&pool).await);
+            Some(pool)
+        }
+        Err(e) => {
+            report.results.push(CheckResult::error(
+                "database",
+                format!("cannot connect: {}", e),
+                "check DATABASE_URL and that PostgreSQL is running (`docker-compose up -d`)",
+            ));
+            None
+        }
+    };
+
+    report.results.push(check_jwt_secret(env("JWT_SECRET").as_deref()));
+    report.results.push(check_data_regions(&env));
+    report.results.extend(check_providers(&env));
+    report.results.push(check_email_sender(env("EMAIL_FROM").as_deref()));
+
+    (report, pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_data_regions() {
+        let vars: HashMap<&str, &str> = HashMap::from([("DATABASE_REGIONS", "eu")]);
+        let env = |name: &str| vars.get(name).map(|v| v.to_string());
+        assert_eq!(check_data_regions(&env).status, CheckStatus::Error);
+
+        let vars: HashMap<&str, &str> = HashMap::from([("DATABASE_REGIONS", "eu"), ("DATABASE_URL_EU", "postgres://eu")]);
+        let env = |name: &str| vars.get(name).map(|v| v.to_string());
+        let result = check_data_regions(&env);
+        assert_eq!(result.status, CheckStatus::Ok);
+        assert_eq!(result.message, "home region home, also eu");
+    }
+
+    #[test]
+    fn test_jwt_secret_strength() {
+        assert_eq!(check_jwt_secret(None).status, CheckStatus::Error);
+        assert_eq!(check_jwt_secret(Some("secret")).status, CheckStatus::Error);
+        assert_eq!(check_jwt_secret(Some("short-but-random-1")).status, CheckStatus::Error);
+        assert_eq!(check_jwt_secret(Some(&"a".repeat(40))).status, CheckStatus::Error);
+        assert_eq!(
+            check_jwt_secret(Some("Zq4v9mT2xL7pR1sW8nK3bH6yC0dF5gJ")).status,
+            CheckStatus::Error
+        );
+        assert_eq!(
+            check_jwt_secret(Some("Zq4v9mT2xL7pR1sW8nK3bH6yC0dF5gJ+")).status,
+            CheckStatus::Ok
+        );
+    }
+
+    #[test]
+    fn test_email_sender() {
+        assert_eq!(check_email_sender(None).status, CheckStatus::Error);
+        assert_eq!(check_email_sender(Some("billing")).status, CheckStatus::Error);
+        assert_eq!(check_email_sender(Some("billing@example.com")).status, CheckStatus::Warning);
+        let result = check_email_sender(Some("Acme Billing <billing@acme.io>"));
+        assert_eq!(result.status, CheckStatus::Ok);
+        assert_eq!(result.message, "billing@acme.io");
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let expected = [1, 2, 3];
+        assert_eq!(check_schema_version(&expected, &[1, 2, 3]).status, CheckStatus::Ok);
+        assert_eq!(check_schema_version(&expected, &[1, 2]).status, CheckStatus::Error);
+        assert_eq!(check_schema_version(&expected, &[1, 2, 3, 4]).status, CheckStatus::Warning);
+    }
+
+    #[test]
+    fn test_s3_storage_requires_bucket() {
+        let vars: HashMap<&str, &str> = [("ATTACHMENT_STORAGE", "s3"), ("OPENAI_API_KEY", "sk-test")].into();
+        let env = |name: &str| vars.get(name).map(|v| v.to_string());
+        let results = check_providers(&env);
+
+        let storage = results.iter().find(|r| r.name == "blob storage").unwrap();
+        assert_eq!(storage.status, CheckStatus::Error);
+        assert!(results.iter().filter(|r| r.name != "blob storage").all(|r| r.status == CheckStatus::Ok));
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::email_sandbox::{delete_sandboxed_email, find_sandboxed_email, list_limit, list_sandboxed_emails};
+use crate::models::sandboxed_email::SandboxedEmail;
+use crate::worker::services::render_email_html;
+
+/// Query parameters for listing sandboxed emails.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListSandboxedEmailsQuery {
+    /// Number of emails (default 50, at most 200)
+    pub limit: Option<i64>,
+}
+
+/// List sandboxed emails endpoint handler.
+///
+/// Handles GET requests to `/api/email-sandbox`, newest first.
+pub async fn list_sandboxed_emails_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListSandboxedEmailsQuery>,
+) -> Result<Json<Vec<SandboxedEmail>>, StatusCode> {
+    let emails = list_sandboxed_emails(&pool, user_id, list_limit(query.limit))
+        .await
+        .map_err(|e| {
+            error!("Failed to list sandboxed emails for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(emails))
+}
+
+/// Sandboxed email preview endpoint handler.
+///
+/// Handles GET requests to `/api/email-sandbox/:id`: the email with its
+/// body rendered as the recipient would have seen it.
+pub async fn get_sandboxed_email_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(email_id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let email = find_sandboxed_email(&pool, user_id, email_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load sandboxed email {}: {}", email_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let body_html = render_email_html(&email.subject, &email.body_text);
+    Ok(Json(json!({ "email": email, "body_html": body_html })))
+}
+
+/// Delete sandboxed email endpoint handler.
+///
+/// Handles DELETE requests to `/api/email-sandbox/:id`.
+pub async fn delete_sandboxed_email_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(email_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_sandboxed_email(&pool, user_id, email_id).await.map_err(|e| {
+        error!("Failed to delete sandboxed email {}: {}", email_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,336 @@
+//! Outbound email sandbox.
+//!
+//! Staging environments and users trying the agent out can keep chase and
+//! invoice emails from reaching clients. The sandbox is on for everyone when
+//! `EMAIL_SANDBOX` is set, or per user with the `email_sandbox` setting.
+//! Sandboxed emails go to a sandbox inbox instead (the user's
+//! `email_sandbox_inbox`, else `EMAIL_SANDBOX_INBOX`), or are held unsent
+//! when there is none. Either way a copy is kept for the preview API, and
+//! the rest of the pipeline (chase states, late fees, correspondence, sync)
+//! runs as if the email had been sent.
+
+pub mod handlers;
+
+use serde_json::json;
+use sqlx::{PgPool, Postgres};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::logging::redact_email;
+use crate::models::sandboxed_email::{CreateSandboxedEmail, SandboxedEmail};
+use crate::models::user_settings::UserSettings;
+use crate::settings::load_user_settings;
+use crate::worker::services::{send_email_with_attachments, EmailAttachment};
+
+/// Default number of sandboxed emails returned by a list request.
+pub const DEFAULT_LIST_LIMIT: i64 = 50;
+
+/// Maximum number of sandboxed emails returned by a list request.
+pub const MAX_LIST_LIMIT: i64 = 200;
+
+/// Whether the sandbox is on for every user.
+///
+/// Controlled by `EMAIL_SANDBOX` (default: false).
+fn global_sandbox() -> bool {
+    std::env::var("EMAIL_SANDBOX")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+}
+
+/// Inbox sandboxed emails go to when the user has none, read from
+/// `EMAIL_SANDBOX_INBOX`.
+fn global_inbox() -> Option<String> {
+    std::env::var("EMAIL_SANDBOX_INBOX")
+        .ok()
+        .map(|inbox| inbox.trim().to_string())
+        .filter(|inbox| !inbox.is_empty())
+}
+
+/// What happens to an outgoing email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    /// Sent to its recipient
+    Send,
+
+    /// Sent to a sandbox inbox instead
+    Redirect(String),
+
+    /// Kept unsent
+    Hold,
+}
+
+impl Delivery {
+    /// Delivery status recorded with the correspondence.
+    pub fn status(&self) -> &'static str {
+        match self {
+            Delivery::Send => "sent",
+            Delivery::Redirect(_) => "sandbox_redirected",
+            Delivery::Hold => "sandbox_held",
+        }
+    }
+}
+
+/// Decides how a user's emails are delivered.
+///
+/// # Arguments
+///
+/// * `settings` - The user's settings
+pub fn delivery_for(settings: &UserSettings) -> Delivery {
+    choose_delivery(global_sandbox(), global_inbox(), settings)
+}
+
+/// Decides delivery from the global flag and inbox and the user's settings.
+fn choose_delivery(global: bool, global_inbox: Option<String>, settings: &UserSettings) -> Delivery {
+    if !global && !settings.email_sandbox {
+        return Delivery::Send;
+    }
+    match settings.email_sandbox_inbox.clone().or(global_inbox) {
+        Some(inbox) => Delivery::Redirect(inbox),
+        None => Delivery::Hold,
+    }
+}
+
+/// Marks a redirected email with the address it was meant for.
+///
+/// # Returns
+///
+/// Returns the subject and body sent to the sandbox inbox.
+pub fn redirected_message(recipient: &str, subject: &str, body: &str) -> (String, String) {
+    (
+        format!("[Sandbox] {}", subject),
+        format!("Sandboxed email for {}, not sent to them.\n\n{}", recipient, body),
+    )
+}
+
+/// Sends an email as decided by [`delivery_for`].
+///
+/// # Returns
+///
+/// Returns the copy to keep in the sandbox, or `None` if the email was
+/// sent to its recipient.
+///
+/// # Errors
+///
+/// Returns an error if sending fails; nothing should be kept then.
+pub async fn send_with(
+    delivery: &Delivery,
+    to: &str,
+    subject: &str,
+    body: &str,
+    attachments: &[EmailAttachment],
+) -> Result<Option<CreateSandboxedEmail>, anyhow::Error> {
+    let redirected_to = match delivery {
+        Delivery::Send => {
+            send_email_with_attachments(to, subject, body, attachments).await?;
+            return Ok(None);
+        }
+        Delivery::Redirect(inbox) => {
+            let (sandbox_subject, sandbox_body) = redirected_message(to, subject, body);
+            send_email_with_attachments(inbox, &sandbox_subject, &sandbox_body, attachments).await?;
+            info!("Sandbox: redirected email for {} to {}", redact_email(&to), redact_email(inbox));
+            Some(inbox.clone())
+        }
+        Delivery::Hold => {
+            info!("Sandbox: held email for {}", redact_email(&to));
+            None
+        }
+    };
+
+    Ok(Some(CreateSandboxedEmail {
+        recipient: to.to_string(),
+        redirected_to,
+        subject: subject.to_string(),
+        body_text: body.to_string(),
+        attachments: json!(attachments
+            .iter()
+            .map(|a| json!({
+                "filename": a.filename,
+                "content_type": a.content_type,
+                "size_bytes": a.data.len(),
+            }))
+            .collect::<Vec<_>>()),
+    }))
+}
+
+/// Sends one of a user's emails through the sandbox.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user the email is sent for
+/// * `to` - Recipient email address
+/// * `subject` - Email subject line
+/// * `body` - Email body content
+/// * `attachments` - Files to attach
+///
+/// # Returns
+///
+/// Returns how the email was delivered.
+pub async fn deliver_email(
+    pool: &PgPool,
+    user_id: Uuid,
+    to: &str,
+    subject: &str,
+    body: &str,
+    attachments: &[EmailAttachment],
+) -> Result<Delivery, anyhow::Error> {
+    let settings = load_user_settings(pool, user_id).await?;
+    let delivery = delivery_for(&settings);
+    if let Some(email) = send_with(&delivery, to, subject, body, attachments).await? {
+        record_sandboxed_email(pool, user_id, email).await?;
+    }
+    Ok(delivery)
+}
+
+/// Keeps a copy of a sandboxed email.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to write with
+/// * `user_id` - ID of the user the email was sent for
+/// * `email` - The email to keep
+pub async fn record_sandboxed_email<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    email: CreateSandboxedEmail,
+) -> Result<SandboxedEmail, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let stored = sqlx::query_as::<_, SandboxedEmail>(
+        r#"
+        INSERT INTO sandboxed_emails (user_id, recipient, redirected_to, subject, body_text, attachments)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(email.recipient)
+    .bind(email.redirected_to)
+    .bind(email.subject)
+    .bind(email.body_text)
+    .bind(email.attachments)
+    .fetch_one(executor)
+    .await?;
+
+    Ok(stored)
+}
+
+/// Clamps a requested page size to `1..=MAX_LIST_LIMIT`.
+pub fn list_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT)
+}
+
+/// Lists a user's sandboxed emails, newest first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `limit` - Number of emails (see [`list_limit`])
+pub async fn list_sandboxed_emails(
+    pool: &PgPool,
+    user_id: Uuid,
+    limit: i64,
+) -> Result<Vec<SandboxedEmail>, anyhow::Error> {
+    let emails = sqlx::query_as::<_, SandboxedEmail>(
+        r#"
+        SELECT * FROM sandboxed_emails
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(emails)
+}
+
+/// Loads one of a user's sandboxed emails.
+///
+/// # Returns
+///
+/// Returns the email, or `None` if it does not exist or belongs to another
+/// user.
+pub async fn find_sandboxed_email(
+    pool: &PgPool,
+    user_id: Uuid,
+    email_id: Uuid,
+) -> Result<Option<SandboxedEmail>, anyhow::Error> {
+    let email = sqlx::query_as::<_, SandboxedEmail>(
+        "SELECT * FROM sandboxed_emails WHERE id = $1 AND user_id = $2",
+    )
+    .bind(email_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(email)
+}
+
+/// Deletes one of a user's sandboxed emails.
+///
+/// # Returns
+///
+/// Returns `true` if the email was deleted, or `false` if it does not exist
+/// or belongs to another user.
+pub async fn delete_sandboxed_email(pool: &PgPool, user_id: Uuid, email_id: Uuid) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM sandboxed_emails WHERE id = $1 AND user_id = $2")
+        .bind(email_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivery_prefers_user_inbox_then_global() {
+        let mut settings = UserSettings::defaults(Uuid::new_v4());
+        let staging = Some("staging@gigpilot.test".to_string());
+
+        assert_eq!(choose_delivery(false, staging.clone(), &settings), Delivery::Send);
+        assert_eq!(
+            choose_delivery(true, staging.clone(), &settings),
+            Delivery::Redirect("staging@gigpilot.test".to_string())
+        );
+        assert_eq!(choose_delivery(true, None, &settings), Delivery::Hold);
+
+        settings.email_sandbox = true;
+        assert_eq!(choose_delivery(false, None, &settings), Delivery::Hold);
+        settings.email_sandbox_inbox = Some("me@freelancer.test".to_string());
+        assert_eq!(
+            choose_delivery(false, staging, &settings),
+            Delivery::Redirect("me@freelancer.test".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_held_email_is_kept_with_attachment_details() {
+        let attachment = EmailAttachment {
+            filename: "INV-1.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            data: vec![0; 42],
+        };
+
+        let kept = send_with(&Delivery::Hold, "ap@client.test", "Invoice INV-1", "Hello", &[attachment])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.recipient, "ap@client.test");
+        assert_eq!(kept.redirected_to, None);
+        assert_eq!(kept.attachments[0]["size_bytes"], 42);
+
+        assert!(send_with(&Delivery::Send, "ap@client.test", "Invoice INV-1", "Hello", &[])
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
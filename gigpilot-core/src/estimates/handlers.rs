@@ -0,0 +1,180 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::estimates::{
+    convert_estimate, create_estimate, delete_estimate, find_estimate, list_estimates,
+    update_estimate, validate_conversion, validate_create, validate_update,
+};
+use crate::models::estimate::{CreateEstimate, Estimate, UpdateEstimate};
+use crate::models::invoice::InvoiceResponse;
+
+/// Response body of a successful conversion.
+#[derive(Debug, Serialize)]
+pub struct ConvertEstimateResponse {
+    /// The estimate, now `converted` and linked to the invoice
+    pub estimate: Estimate,
+
+    /// The new draft invoice
+    pub invoice: InvoiceResponse,
+}
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// List estimates endpoint handler.
+///
+/// Handles GET requests to `/api/estimates`.
+pub async fn list_estimates_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<Estimate>>, StatusCode> {
+    let estimates = list_estimates(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list estimates for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(estimates))
+}
+
+/// Create estimate endpoint handler.
+///
+/// Handles POST requests to `/api/estimates`. The next `EST-` number is
+/// assigned when the request doesn't carry one.
+pub async fn create_estimate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateEstimate>,
+) -> Result<(StatusCode, Json<Estimate>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_create(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let estimate = create_estimate(&pool, user_id, request).await.map_err(|e| {
+        error!("Failed to create estimate for user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create estimate")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(estimate)))
+}
+
+/// Get estimate endpoint handler.
+///
+/// Handles GET requests to `/api/estimates/:id`.
+pub async fn get_estimate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(estimate_id): Path<Uuid>,
+) -> Result<Json<Estimate>, StatusCode> {
+    let estimate = find_estimate(&pool, user_id, estimate_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load estimate {}: {}", estimate_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(estimate))
+}
+
+/// Update estimate endpoint handler.
+///
+/// Handles PUT requests to `/api/estimates/:id`. Converted estimates
+/// can't be changed.
+pub async fn update_estimate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(estimate_id): Path<Uuid>,
+    Json(update): Json<UpdateEstimate>,
+) -> Result<Json<Estimate>, (StatusCode, Json<Value>)> {
+    let current = find_estimate(&pool, user_id, estimate_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load estimate {}: {}", estimate_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load estimate")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "estimate not found"))?;
+
+    if let Err(message) = validate_update(&current, &update) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let estimate = update_estimate(&pool, user_id, estimate_id, update)
+        .await
+        .map_err(|e| {
+            error!("Failed to update estimate {}: {}", estimate_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update estimate")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "estimate not found"))?;
+
+    Ok(Json(estimate))
+}
+
+/// Delete estimate endpoint handler.
+///
+/// Handles DELETE requests to `/api/estimates/:id`.
+pub async fn delete_estimate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(estimate_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_estimate(&pool, user_id, estimate_id).await.map_err(|e| {
+        error!("Failed to delete estimate {}: {}", estimate_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Convert estimate endpoint handler.
+///
+/// Handles POST requests to `/api/estimates/:id/convert`, creating a draft
+/// invoice from the estimate. Returns 409 if the estimate was already
+/// converted or can't be converted in its current status.
+pub async fn convert_estimate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(estimate_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ConvertEstimateResponse>), (StatusCode, Json<Value>)> {
+    let current = find_estimate(&pool, user_id, estimate_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load estimate {}: {}", estimate_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load estimate")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "estimate not found"))?;
+
+    if let Err(message) = validate_conversion(&current) {
+        return Err(error_response(StatusCode::CONFLICT, &message));
+    }
+
+    let (estimate, invoice) = convert_estimate(&pool, user_id, estimate_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to convert estimate {}: {}", estimate_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to convert estimate")
+        })?
+        // Lost a race with another conversion or a status change
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "estimate can no longer be converted"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ConvertEstimateResponse {
+            estimate,
+            invoice: invoice.into(),
+        }),
+    ))
+}
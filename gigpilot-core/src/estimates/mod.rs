@@ -0,0 +1,672 @@
+//! Estimates (quotes) and their conversion into invoices.
+//!
+//! Estimates are created through the API or pushed from devices, and every
+//! server-side change is recorded for sync. Converting an estimate creates
+//! a draft invoice with the same line items and links the two records.
+
+pub mod handlers;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::currency::{normalize_currency, validate_amount, DEFAULT_CURRENCY};
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::numbering::{next_estimate_number, next_invoice_number};
+use crate::invoices::DueDateRules;
+use crate::models::estimate::{CreateEstimate, Estimate, EstimateStatus, UpdateEstimate};
+use crate::models::invoice::Invoice;
+use crate::models::line_item::{InvoiceTotals, LineItem};
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+use crate::taxes::resolve_tax_rates;
+
+/// Parses line items, resolves their tax rates and computes the totals.
+///
+/// # Errors
+///
+/// Returns an error if the line items are malformed or reference an
+/// unknown tax rate.
+async fn prepare_line_items(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    raw: &Value,
+) -> Result<(Value, InvoiceTotals), anyhow::Error> {
+    let mut items = LineItem::parse_list(raw)?;
+    resolve_tax_rates(&mut **tx, user_id, &mut items).await?;
+    let totals = InvoiceTotals::compute(&items);
+    Ok((serde_json::to_value(&items)?, totals))
+}
+
+/// Validates line items sent by a client.
+fn validate_line_items(raw: &Value) -> Result<(), String> {
+    let items = LineItem::parse_list(raw).map_err(|e| e.to_string())?;
+    if items.is_empty() {
+        return Err("line_items must contain at least one item".to_string());
+    }
+    Ok(())
+}
+
+/// Validates an estimate creation request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create(request: &CreateEstimate) -> Result<(), String> {
+    if request.client_name.trim().is_empty() {
+        return Err("client_name is required".to_string());
+    }
+    if matches!(&request.estimate_number, Some(number) if number.trim().is_empty()) {
+        return Err("estimate_number must not be empty".to_string());
+    }
+    if let Some(currency) = &request.currency {
+        normalize_currency(currency).map_err(|e| e.to_string())?;
+    }
+    if request.status == Some(EstimateStatus::Converted) {
+        return Err("estimates are converted with POST /api/estimates/:id/convert".to_string());
+    }
+    if let (Some(issue_date), Some(valid_until)) = (request.issue_date, request.valid_until) {
+        if valid_until < issue_date {
+            return Err("valid_until must not be before issue_date".to_string());
+        }
+    }
+    validate_line_items(&request.line_items)
+}
+
+/// Validates an update against the current estimate.
+///
+/// Converted estimates are frozen: the invoice is the record from then on.
+pub fn validate_update(current: &Estimate, update: &UpdateEstimate) -> Result<(), String> {
+    if current.status == EstimateStatus::Converted {
+        return Err("converted estimates can't be changed".to_string());
+    }
+    if update.status == Some(EstimateStatus::Converted) {
+        return Err("estimates are converted with POST /api/estimates/:id/convert".to_string());
+    }
+    if matches!(&update.client_name, Some(name) if name.trim().is_empty()) {
+        return Err("client_name must not be empty".to_string());
+    }
+    if matches!(&update.estimate_number, Some(number) if number.trim().is_empty()) {
+        return Err("estimate_number must not be empty".to_string());
+    }
+    if let Some(currency) = &update.currency {
+        normalize_currency(currency).map_err(|e| e.to_string())?;
+    }
+    let issue_date = update.issue_date.unwrap_or(current.issue_date);
+    if let Some(valid_until) = update.valid_until.or(current.valid_until) {
+        if valid_until < issue_date {
+            return Err("valid_until must not be before issue_date".to_string());
+        }
+    }
+    if let Some(line_items) = &update.line_items {
+        validate_line_items(line_items)?;
+    }
+    Ok(())
+}
+
+/// Checks that an estimate can be converted into an invoice today.
+pub fn validate_conversion(estimate: &Estimate) -> Result<(), String> {
+    if estimate.status == EstimateStatus::Converted {
+        return Err("estimate has already been converted".to_string());
+    }
+    if !estimate.status.is_convertible() {
+        return Err(format!("{:?} estimates can't be converted", estimate.status).to_lowercase());
+    }
+    if let Some(valid_until) = estimate.valid_until {
+        if valid_until < Utc::now().date_naive() {
+            return Err(format!("estimate expired on {}", valid_until));
+        }
+    }
+    Ok(())
+}
+
+/// Creates an estimate and records it for sync.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `request` - The estimate to create (see [`validate_create`])
+///
+/// # Returns
+///
+/// Returns the stored `Estimate`, or an error.
+pub async fn create_estimate(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateEstimate,
+) -> Result<Estimate, anyhow::Error> {
+    let currency = normalize_currency(request.currency.as_deref().unwrap_or(DEFAULT_CURRENCY))?;
+
+    let mut tx = pool.begin().await?;
+
+    let (line_items, totals) = prepare_line_items(&mut tx, user_id, &request.line_items).await?;
+    validate_amount(totals.total, &currency)?;
+
+    let estimate_number = match request.estimate_number {
+        Some(number) => number.trim().to_string(),
+        None => next_estimate_number(&mut tx, user_id).await?,
+    };
+
+    let estimate = sqlx::query_as::<_, Estimate>(
+        r#"
+        INSERT INTO estimates (
+            user_id, estimate_number, client_name, client_email, currency, status,
+            issue_date, valid_until, description, line_items, subtotal, tax_total, total,
+            metadata
+        ) VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, CURRENT_DATE), $8, $9, $10, $11, $12, $13, $14)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(estimate_number)
+    .bind(request.client_name.trim())
+    .bind(request.client_email)
+    .bind(currency)
+    .bind(request.status.unwrap_or(EstimateStatus::Draft))
+    .bind(request.issue_date)
+    .bind(request.valid_until)
+    .bind(request.description)
+    .bind(line_items)
+    .bind(totals.subtotal)
+    .bind(totals.tax_total)
+    .bind(totals.total)
+    .bind(request.metadata)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_estimate_change(&mut tx, &estimate, SyncOperation::Insert).await?;
+    tx.commit().await?;
+
+    Ok(estimate)
+}
+
+/// Lists a user's live estimates, newest first.
+pub async fn list_estimates(pool: &PgPool, user_id: Uuid) -> Result<Vec<Estimate>, anyhow::Error> {
+    let estimates = sqlx::query_as::<_, Estimate>(
+        r#"
+        SELECT * FROM estimates
+        WHERE user_id = $1 AND is_deleted = false
+        ORDER BY issue_date DESC, created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(estimates)
+}
+
+/// Loads a single live estimate owned by the given user.
+///
+/// # Returns
+///
+/// Returns `Some(Estimate)` if found, `None` if it does not exist, is
+/// deleted, or belongs to another user.
+pub async fn find_estimate(
+    pool: &PgPool,
+    user_id: Uuid,
+    estimate_id: Uuid,
+) -> Result<Option<Estimate>, anyhow::Error> {
+    let estimate = sqlx::query_as::<_, Estimate>(
+        "SELECT * FROM estimates WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(estimate_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(estimate)
+}
+
+/// Updates an estimate and records the change for sync.
+///
+/// Fields left as `None` keep their current value; new line items are
+/// re-totalled.
+///
+/// # Returns
+///
+/// Returns the updated `Estimate`, or `None` if it does not exist.
+pub async fn update_estimate(
+    pool: &PgPool,
+    user_id: Uuid,
+    estimate_id: Uuid,
+    update: UpdateEstimate,
+) -> Result<Option<Estimate>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let current = sqlx::query_as::<_, Estimate>(
+        "SELECT * FROM estimates WHERE id = $1 AND user_id = $2 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(estimate_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(current) = current else {
+        return Ok(None);
+    };
+
+    let currency = match &update.currency {
+        Some(currency) => normalize_currency(currency)?,
+        None => current.currency.clone(),
+    };
+    let (line_items, totals) = match &update.line_items {
+        Some(raw) => {
+            let (items, totals) = prepare_line_items(&mut tx, user_id, raw).await?;
+            (items, totals)
+        }
+        None => (
+            current.line_items.clone(),
+            InvoiceTotals {
+                subtotal: current.subtotal,
+                tax_total: current.tax_total,
+                total: current.total,
+            },
+        ),
+    };
+    validate_amount(totals.total, &currency)?;
+
+    let estimate = sqlx::query_as::<_, Estimate>(
+        r#"
+        UPDATE estimates
+        SET estimate_number = $3, client_name = $4, client_email = $5, currency = $6,
+            status = $7, issue_date = $8, valid_until = $9, description = $10,
+            line_items = $11, subtotal = $12, tax_total = $13, total = $14,
+            metadata = $15, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(estimate_id)
+    .bind(user_id)
+    .bind(update.estimate_number.map(|n| n.trim().to_string()).unwrap_or(current.estimate_number))
+    .bind(update.client_name.map(|n| n.trim().to_string()).unwrap_or(current.client_name))
+    .bind(update.client_email.or(current.client_email))
+    .bind(currency)
+    .bind(update.status.unwrap_or(current.status))
+    .bind(update.issue_date.unwrap_or(current.issue_date))
+    .bind(update.valid_until.or(current.valid_until))
+    .bind(update.description.or(current.description))
+    .bind(line_items)
+    .bind(totals.subtotal)
+    .bind(totals.tax_total)
+    .bind(totals.total)
+    .bind(update.metadata.or(current.metadata))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_estimate_change(&mut tx, &estimate, SyncOperation::Update).await?;
+    tx.commit().await?;
+
+    Ok(Some(estimate))
+}
+
+/// Soft-deletes an estimate and records the deletion for sync.
+///
+/// # Returns
+///
+/// Returns `true` if an estimate was deleted, `false` if none matched.
+pub async fn delete_estimate(pool: &PgPool, user_id: Uuid, estimate_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let estimate = sqlx::query_as::<_, Estimate>(
+        r#"
+        UPDATE estimates SET is_deleted = true, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(estimate_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(estimate) = &estimate {
+        record_estimate_change(&mut tx, estimate, SyncOperation::Delete).await?;
+    }
+    tx.commit().await?;
+
+    Ok(estimate.is_some())
+}
+
+/// Converts an estimate into a draft invoice.
+///
+/// The invoice gets the next invoice number, the estimate's client,
+/// currency and line items, and a due date following the user's payment
+/// terms. The estimate is
+/// marked `converted` and linked to the invoice; both changes are recorded
+/// for sync. The status check is repeated under a row lock, so concurrent
+/// conversions create a single invoice.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `estimate_id` - ID of the estimate (see [`validate_conversion`])
+///
+/// # Returns
+///
+/// Returns the converted estimate and the new invoice, or `None` if the
+/// estimate no longer exists or can no longer be converted.
+pub async fn convert_estimate(
+    pool: &PgPool,
+    user_id: Uuid,
+    estimate_id: Uuid,
+) -> Result<Option<(Estimate, Invoice)>, anyhow::Error> {
+    let due_date_rules = DueDateRules::load(pool, user_id).await?;
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let estimate = sqlx::query_as::<_, Estimate>(
+        r#"
+        SELECT * FROM estimates
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        FOR UPDATE
+        "#,
+    )
+    .bind(estimate_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(estimate) = estimate.filter(|e| validate_conversion(e).is_ok()) else {
+        return Ok(None);
+    };
+
+    let invoice_number = next_invoice_number(&mut tx, user_id).await?;
+    let today = Utc::now().date_naive();
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        INSERT INTO invoices (
+            user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            description, line_items, metadata, subtotal, tax_total, total
+        ) VALUES ($1, $2, $3, $4, $5, $6, 'draft', $7, $8, $9, $10, $11, $12, $13, $14)
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_number)
+    .bind(&estimate.client_name)
+    .bind(&estimate.client_email)
+    .bind(estimate.total)
+    .bind(&estimate.currency)
+    .bind(due_date_rules.default_due_date(today, None))
+    .bind(today)
+    .bind(&estimate.description)
+    .bind(&estimate.line_items)
+    .bind(json!({
+        "estimate_id": estimate.id,
+        "estimate_number": estimate.estimate_number,
+    }))
+    .bind(estimate.subtotal)
+    .bind(estimate.tax_total)
+    .bind(estimate.total)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let estimate = sqlx::query_as::<_, Estimate>(
+        r#"
+        UPDATE estimates SET status = 'converted', invoice_id = $2, last_modified = NOW()
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(estimate.id)
+    .bind(invoice.id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        user_id,
+        "invoices",
+        invoice.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&invoice)?,
+    )
+    .await?;
+    record_estimate_change(&mut tx, &estimate, SyncOperation::Update).await?;
+
+    tx.commit().await?;
+
+    Ok(Some((estimate, invoice)))
+}
+
+/// Records a server-side estimate change for sync.
+async fn record_estimate_change(
+    tx: &mut Transaction<'_, Postgres>,
+    estimate: &Estimate,
+    operation: SyncOperation,
+) -> Result<(), anyhow::Error> {
+    record_server_change(
+        &mut **tx,
+        estimate.user_id,
+        "estimates",
+        estimate.id,
+        operation,
+        &serde_json::to_value(estimate)?,
+    )
+    .await
+}
+
+/// Parses an optional pushed date field (`YYYY-MM-DD`), rejecting
+/// unparsable dates rather than storing `NULL`.
+fn pushed_date(data: &Value, field: &str) -> Result<Option<chrono::NaiveDate>, anyhow::Error> {
+    match data.get(field).and_then(|v| v.as_str()) {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("{} is not a date: {}", field, date)),
+        None => Ok(None),
+    }
+}
+
+/// Parses an optional pushed status field.
+fn pushed_status(data: &Value) -> Result<Option<EstimateStatus>, anyhow::Error> {
+    match data.get("status") {
+        Some(status) if !status.is_null() => Ok(Some(serde_json::from_value(status.clone())?)),
+        _ => Ok(None),
+    }
+}
+
+/// Inserts an estimate pushed by a device.
+///
+/// Line items are re-totalled on the server. Devices can't push converted
+/// estimates or set `invoice_id`; conversion goes through the API.
+///
+/// # Errors
+///
+/// Returns an error if required fields are missing or invalid.
+pub async fn apply_pushed_insert(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+    version_vector: Option<&Value>,
+) -> Result<(), anyhow::Error> {
+    let estimate_number = data.get("estimate_number")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing estimate_number"))?;
+    let client_name = data.get("client_name")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing client_name"))?;
+
+    let status = pushed_status(data)?.unwrap_or(EstimateStatus::Draft);
+    if status == EstimateStatus::Converted {
+        anyhow::bail!("Estimates can only be converted through the API");
+    }
+
+    let currency = normalize_currency(
+        data.get("currency").and_then(|v| v.as_str()).unwrap_or(DEFAULT_CURRENCY),
+    )?;
+    let raw_items = data.get("line_items").cloned().unwrap_or_else(|| json!([]));
+    let (line_items, totals) = prepare_line_items(tx, user_id, &raw_items).await?;
+    validate_amount(totals.total, &currency)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO estimates (
+            id, user_id, estimate_number, client_name, client_email, currency, status,
+            issue_date, valid_until, description, line_items, subtotal, tax_total, total,
+            metadata, last_modified, version_vector
+        ) VALUES (
+            $1, $2, $3, $4, $5, $6, $7, COALESCE($8, CURRENT_DATE), $9, $10, $11, $12, $13, $14,
+            $15, NOW(), $16
+        )
+        "#,
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .bind(estimate_number)
+    .bind(client_name)
+    .bind(data.get("client_email").and_then(|v| v.as_str()))
+    .bind(currency)
+    .bind(status)
+    .bind(pushed_date(data, "issue_date")?)
+    .bind(pushed_date(data, "valid_until")?)
+    .bind(data.get("description").and_then(|v| v.as_str()))
+    .bind(line_items)
+    .bind(totals.subtotal)
+    .bind(totals.tax_total)
+    .bind(totals.total)
+    .bind(data.get("metadata"))
+    .bind(version_vector)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Updates an estimate from a device push.
+///
+/// Converted estimates are frozen, and devices can't mark an estimate as
+/// converted themselves.
+///
+/// # Errors
+///
+/// Returns an error if the change is invalid for the current estimate.
+pub async fn apply_pushed_update(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+) -> Result<(), anyhow::Error> {
+    let current = sqlx::query_as::<_, Estimate>(
+        "SELECT * FROM estimates WHERE id = $1 AND user_id = $2 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("Estimate {} not found", record_id))?;
+
+    let status = pushed_status(data)?;
+    if current.status == EstimateStatus::Converted {
+        // Devices echo back the record; only reject actual changes
+        if status.map_or(false, |s| s != EstimateStatus::Converted) || data.get("line_items").map_or(false, |items| *items != current.line_items) {
+            anyhow::bail!("Converted estimate {} can't be changed", record_id);
+        }
+    } else if status == Some(EstimateStatus::Converted) {
+        anyhow::bail!("Estimates can only be converted through the API");
+    }
+
+    let currency = match data.get("currency").and_then(|v| v.as_str()) {
+        Some(currency) => normalize_currency(currency)?,
+        None => current.currency.clone(),
+    };
+    let totals = match data.get("line_items") {
+        Some(raw) if !raw.is_null() => Some(prepare_line_items(tx, user_id, raw).await?),
+        _ => None,
+    };
+    if let Some((_, totals)) = &totals {
+        validate_amount(totals.total, &currency)?;
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE estimates
+        SET
+            estimate_number = COALESCE($3, estimate_number),
+            client_name = COALESCE($4, client_name),
+            client_email = $5,
+            currency = $6,
+            status = COALESCE($7, status),
+            issue_date = COALESCE($8, issue_date),
+            valid_until = $9,
+            description = $10,
+            line_items = COALESCE($11, line_items),
+            subtotal = COALESCE($12, subtotal),
+            tax_total = COALESCE($13, tax_total),
+            total = COALESCE($14, total),
+            metadata = $15,
+            last_modified = NOW(),
+            version_vector = $16
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .bind(data.get("estimate_number").and_then(|v| v.as_str()))
+    .bind(data.get("client_name").and_then(|v| v.as_str()))
+    .bind(data.get("client_email").and_then(|v| v.as_str()))
+    .bind(currency)
+    .bind(status)
+    .bind(pushed_date(data, "issue_date")?)
+    .bind(pushed_date(data, "valid_until")?)
+    .bind(data.get("description").and_then(|v| v.as_str()))
+    .bind(totals.as_ref().map(|(items, _)| items))
+    .bind(totals.as_ref().map(|(_, t)| t.subtotal))
+    .bind(totals.as_ref().map(|(_, t)| t.tax_total))
+    .bind(totals.as_ref().map(|(_, t)| t.total))
+    .bind(data.get("metadata"))
+    .bind(data.get("version_vector"))
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(line_items: Value) -> CreateEstimate {
+        CreateEstimate {
+            estimate_number: None,
+            client_name: "Acme".to_string(),
+            client_email: None,
+            currency: Some("eur".to_string()),
+            status: None,
+            issue_date: None,
+            valid_until: None,
+            description: None,
+            line_items,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_create() {
+        assert!(validate_create(&request(json!([{ "unit_price": 100 }]))).is_ok());
+        assert!(validate_create(&request(json!([]))).is_err());
+
+        let mut converted = request(json!([{ "unit_price": 100 }]));
+        converted.status = Some(EstimateStatus::Converted);
+        assert!(validate_create(&converted).is_err());
+    }
+
+    #[test]
+    fn test_convertible_statuses() {
+        assert!(EstimateStatus::Accepted.is_convertible());
+        assert!(EstimateStatus::Draft.is_convertible());
+        assert!(!EstimateStatus::Declined.is_convertible());
+        assert!(!EstimateStatus::Converted.is_convertible());
+    }
+}
@@ -0,0 +1,170 @@
+//! Domain events shared between the API server and the worker.
+//!
+//! Events are published with Postgres `NOTIFY` (delivered when the writing
+//! transaction commits), so every process sees them. Each process runs a
+//! listener that re-broadcasts them in-process through an [`EventBus`], where
+//! subscribers such as caches react to them.
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::{Executor, PgPool, Postgres};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Postgres notification channel carrying domain events.
+pub const EVENTS_CHANNEL: &str = "gigpilot_domain_events";
+
+/// In-process buffer per subscriber before lagging ones drop events.
+const BUS_CAPACITY: usize = 256;
+
+/// Something that changed in the domain and that other components may
+/// need to react to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    /// A user's settings were created or updated
+    UserSettingsChanged { user_id: Uuid },
+
+    /// Fields printed on an invoice changed, or it was deleted (published
+    /// by a database trigger, whoever made the change)
+    InvoiceContentChanged { user_id: Uuid, invoice_id: Uuid },
+
+    /// A maintenance window was started or ended
+    MaintenanceChanged,
+
+    /// The trace sampling configuration was replaced
+    TracingSamplingChanged,
+
+    /// Sync changes a user pulls were logged (published by a database
+    /// trigger, once per transaction and device that made them)
+    SyncChangesAvailable { user_id: Uuid, device_id: String },
+}
+
+/// Publishes a domain event to every process.
+///
+/// When `executor` is a transaction, the event is only delivered if the
+/// transaction commits.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to notify through
+/// * `event` - The event to publish
+///
+/// # Returns
+///
+/// Returns `Ok(())` once the notification is queued, or an error.
+pub async fn publish<'e, E>(executor: E, event: &DomainEvent) -> Result<(), anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(EVENTS_CHANNEL)
+        .bind(serde_json::to_string(event)?)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+/// In-process fan-out of domain events.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+}
+
+impl EventBus {
+    /// Creates an empty event bus.
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Subscribes to events emitted after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Delivers an event to all current subscribers.
+    pub fn emit(&self, event: DomainEvent) {
+        // No subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns the listener forwarding Postgres notifications onto the bus.
+///
+/// Reconnects after connection errors; events sent while disconnected are
+/// lost, so subscribers must not rely on events alone (caches also expire).
+pub fn spawn_listener(pool: PgPool, bus: EventBus) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen(&pool, &bus).await {
+                error!("Domain event listener failed: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+}
+
+/// Listens on the events channel until the connection fails.
+async fn listen(pool: &PgPool, bus: &EventBus) -> Result<(), anyhow::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(EVENTS_CHANNEL).await?;
+    info!("Listening for domain events on {}", EVENTS_CHANNEL);
+
+    loop {
+        let notification = listener.recv().await?;
+        match serde_json::from_str::<DomainEvent>(notification.payload()) {
+            Ok(event) => bus.emit(event),
+            Err(e) => warn!("Ignoring malformed domain event: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_wire_format() {
+        let user_id = Uuid::new_v4();
+        let event = DomainEvent::UserSettingsChanged { user_id };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "user_settings_changed");
+        assert_eq!(serde_json::from_value::<DomainEvent>(json).unwrap(), event);
+    }
+
+    #[test]
+    fn test_trigger_payload_parses() {
+        // Shape built by notify_invoice_content_changed()
+        let payload = r#"{"type" : "invoice_content_changed", "user_id" : "6f9619ff-8b86-d011-b42d-00c04fc964ff", "invoice_id" : "7c9e6679-7425-40de-944b-e07fc1f90ae7"}"#;
+        assert!(matches!(
+            serde_json::from_str::<DomainEvent>(payload).unwrap(),
+            DomainEvent::InvoiceContentChanged { .. }
+        ));
+
+        // Shape built by notify_sync_changes_available()
+        let payload = r#"{"type" : "sync_changes_available", "user_id" : "6f9619ff-8b86-d011-b42d-00c04fc964ff", "device_id" : "phone"}"#;
+        assert!(matches!(
+            serde_json::from_str::<DomainEvent>(payload).unwrap(),
+            DomainEvent::SyncChangesAvailable { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bus_delivers_to_subscribers() {
+        let bus = EventBus::new();
+        let mut receiver = bus.subscribe();
+        let event = DomainEvent::UserSettingsChanged { user_id: Uuid::new_v4() };
+        bus.emit(event.clone());
+        assert_eq!(receiver.recv().await.unwrap(), event);
+    }
+}
@@ -0,0 +1,78 @@
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::expenses::{find_receipt, store_receipt, validate_receipt};
+use crate::models::receipt::Receipt;
+
+/// Query parameters for receipt uploads.
+#[derive(Debug, Deserialize)]
+pub struct UploadReceiptParams {
+    /// Original file name (default: "receipt")
+    pub filename: Option<String>,
+}
+
+/// Receipt upload endpoint handler.
+///
+/// Handles POST requests to `/api/receipts`. The request body is the raw
+/// file and `Content-Type` its MIME type. The receipt is queued for OCR and
+/// the suggested expense arrives through sync once processed.
+pub async fn upload_receipt_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(params): Query<UploadReceiptParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Receipt>), (StatusCode, Json<Value>)> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase())
+        .unwrap_or_default();
+
+    validate_receipt(&content_type, body.len())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": e }))))?;
+
+    let filename = params.filename.unwrap_or_else(|| "receipt".to_string());
+
+    let receipt = store_receipt(&pool, user_id, &filename, &content_type, &body)
+        .await
+        .map_err(|e| {
+            error!("Failed to store receipt for user {}: {}", user_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to store receipt" })),
+            )
+        })?;
+
+    Ok((StatusCode::ACCEPTED, Json(receipt)))
+}
+
+/// Receipt status endpoint handler.
+///
+/// Handles GET requests to `/api/receipts/:id`, reporting OCR progress and
+/// extracted fields.
+pub async fn get_receipt_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(receipt_id): Path<Uuid>,
+) -> Result<Json<Receipt>, StatusCode> {
+    let receipt = find_receipt(&pool, user_id, receipt_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load receipt {}: {}", receipt_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(receipt))
+}
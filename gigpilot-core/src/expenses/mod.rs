@@ -0,0 +1,92 @@
+pub mod handlers;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::receipt::{Receipt, RECEIPT_COLUMNS};
+
+/// Largest accepted receipt upload (10 MiB).
+pub const MAX_RECEIPT_BYTES: usize = 10 * 1024 * 1024;
+
+/// MIME types accepted for receipt uploads.
+pub const RECEIPT_CONTENT_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/heic",
+    "application/pdf",
+    "text/plain",
+];
+
+/// Validates a receipt upload before it is stored.
+///
+/// # Returns
+///
+/// Returns a user-facing message describing the first problem found.
+pub fn validate_receipt(content_type: &str, size: usize) -> Result<(), String> {
+    if size == 0 {
+        return Err("receipt file is empty".to_string());
+    }
+    if size > MAX_RECEIPT_BYTES {
+        return Err(format!("receipt file exceeds {} bytes", MAX_RECEIPT_BYTES));
+    }
+    if !RECEIPT_CONTENT_TYPES.contains(&content_type) {
+        return Err(format!("unsupported receipt type: {}", content_type));
+    }
+    Ok(())
+}
+
+/// Stores an uploaded receipt and queues it for OCR.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the uploading user
+/// * `filename` - Original file name
+/// * `content_type` - MIME type of the file
+/// * `data` - Raw file contents
+///
+/// # Returns
+///
+/// Returns the stored `Receipt` (status `pending`), or an error.
+pub async fn store_receipt(
+    pool: &PgPool,
+    user_id: Uuid,
+    filename: &str,
+    content_type: &str,
+    data: &[u8],
+) -> Result<Receipt, anyhow::Error> {
+    let receipt = sqlx::query_as::<_, Receipt>(&format!(
+        r#"
+        INSERT INTO receipts (user_id, filename, content_type, data)
+        VALUES ($1, $2, $3, $4)
+        RETURNING {}
+        "#,
+        RECEIPT_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(filename)
+    .bind(content_type)
+    .bind(data)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(receipt)
+}
+
+/// Loads a receipt owned by the given user (without file contents).
+pub async fn find_receipt(
+    pool: &PgPool,
+    user_id: Uuid,
+    receipt_id: Uuid,
+) -> Result<Option<Receipt>, anyhow::Error> {
+    let receipt = sqlx::query_as::<_, Receipt>(&format!(
+        "SELECT {} FROM receipts WHERE id = $1 AND user_id = $2",
+        RECEIPT_COLUMNS
+    ))
+    .bind(receipt_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(receipt)
+}
@@ -0,0 +1,479 @@
+//! Replay protection for financial mutations.
+//!
+//! Routes guarded by [`idempotency_middleware`] accept an `Idempotency-Key`
+//! header. The first request with a key runs normally and its response is
+//! stored; a retry with the same key (after a network timeout, say) gets
+//! the stored response back, marked with `Idempotent-Replayed: true`,
+//! instead of recording the payment again. Keys are scoped to the account
+//! and kept for [`KEY_TTL_HOURS`] hours.
+//!
+//! A key reused for a different request (another method, path or body) is
+//! rejected with `422`, and a retry that arrives while the first request
+//! is still running gets `409`. A key whose request never finished (the
+//! server restarted mid-request, say) is claimed again after
+//! [`IN_PROGRESS_TIMEOUT_SECONDS`]. Server errors are not stored, so the
+//! request can be retried with the same key. Requests without the header
+//! are not affected.
+
+use axum::body::{boxed, Body, Bytes, Full};
+use axum::extract::OriginalUri;
+use axum::http::{header, Method, Request, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use hyper::body::HttpBody;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::{FromRow, PgPool};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::invoices::import::{stable_hash, stable_hash_bytes};
+
+/// Request header carrying the idempotency key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Response header set on replayed responses.
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Longest accepted key.
+pub const MAX_KEY_LENGTH: usize = 255;
+
+/// Largest request body fingerprinted; above the upload limits of the
+/// guarded routes.
+pub const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Hours a key (and its stored response) is kept.
+pub const KEY_TTL_HOURS: i32 = 24;
+
+/// Seconds after which a key whose request never stored a response is
+/// taken to be abandoned and may be claimed again.
+pub const IN_PROGRESS_TIMEOUT_SECONDS: i32 = 10 * 60;
+
+/// Checks an `Idempotency-Key` header value.
+///
+/// # Errors
+///
+/// Returns a message if the key is empty, longer than [`MAX_KEY_LENGTH`]
+/// or contains anything but printable ASCII.
+pub fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("Idempotency-Key must not be empty".to_string());
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        return Err(format!("Idempotency-Key must be at most {} characters", MAX_KEY_LENGTH));
+    }
+    if !key.chars().all(|c| c.is_ascii_graphic()) {
+        return Err("Idempotency-Key must only contain printable ASCII characters".to_string());
+    }
+    Ok(())
+}
+
+/// Fingerprints a request so a reused key can be told apart from a retry.
+///
+/// Built on [`stable_hash`], so fingerprints stored by one build still
+/// match retries served by the next.
+///
+/// # Arguments
+///
+/// * `method` - Request method
+/// * `uri` - Full request URI (path and query)
+/// * `body` - Request body
+pub fn request_fingerprint(method: &Method, uri: &Uri, body: &[u8]) -> String {
+    let head = stable_hash(&[
+        method.as_str().to_string(),
+        uri.path().to_string(),
+        uri.query().map(|query| format!("?{}", query)).unwrap_or_default(),
+    ]);
+    format!("{:016x}{:016x}", head, stable_hash_bytes(body))
+}
+
+/// Whether a response is stored for replay.
+///
+/// Server errors are not: the request may well succeed when retried.
+pub fn should_store(status: StatusCode) -> bool {
+    !status.is_server_error()
+}
+
+/// A key as stored by an earlier request.
+#[derive(Debug, Clone, FromRow)]
+struct StoredKey {
+    request_hash: String,
+    status_code: Option<i32>,
+    content_type: Option<String>,
+    response_body: Option<Vec<u8>>,
+}
+
+/// What to do with a request carrying a key.
+#[derive(Debug)]
+enum Claim {
+    /// First request with the key: run it and store the response. Holds
+    /// when the key was claimed, so a request whose claim was taken over
+    /// after [`IN_PROGRESS_TIMEOUT_SECONDS`] leaves the new one alone.
+    Acquired(DateTime<Utc>),
+
+    /// Retry of a completed request: return its response
+    Replay(StoredKey),
+
+    /// Retry while the first request is still running
+    InProgress,
+
+    /// The key was used for a different request
+    Mismatch,
+}
+
+impl StoredKey {
+    /// Decides how a request with the given fingerprint is answered.
+    fn claim(self, request_hash: &str) -> Claim {
+        if self.request_hash != request_hash {
+            Claim::Mismatch
+        } else if self.status_code.is_none() {
+            Claim::InProgress
+        } else {
+            Claim::Replay(self)
+        }
+    }
+
+    /// Rebuilds the stored response.
+    fn replay(self) -> Response {
+        let status = self
+            .status_code
+            .and_then(|code| u16::try_from(code).ok())
+            .and_then(|code| StatusCode::from_u16(code).ok())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        let mut builder = Response::builder().status(status).header(REPLAYED_HEADER, "true");
+        if let Some(content_type) = &self.content_type {
+            builder = builder.header(header::CONTENT_TYPE, content_type.as_str());
+        }
+        builder
+            .body(boxed(Full::from(self.response_body.unwrap_or_default())))
+            .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+/// Claims a key for a request, or finds how it was answered before.
+///
+/// Expired keys, and keys left in progress for longer than
+/// [`IN_PROGRESS_TIMEOUT_SECONDS`], are claimed again as if they were new.
+async fn claim_key(pool: &PgPool, user_id: Uuid, key: &str, request_hash: &str) -> Result<Claim, anyhow::Error> {
+    let claimed_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+        r#"
+        INSERT INTO idempotency_keys (user_id, key, request_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, key) DO UPDATE
+            SET request_hash = EXCLUDED.request_hash,
+                status_code = NULL,
+                content_type = NULL,
+                response_body = NULL,
+                created_at = NOW(),
+                completed_at = NULL
+            WHERE idempotency_keys.created_at < NOW() - make_interval(hours => $4)
+                OR (
+                    idempotency_keys.completed_at IS NULL
+                    AND idempotency_keys.created_at < NOW() - make_interval(secs => $5)
+                )
+        RETURNING created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(request_hash)
+    .bind(KEY_TTL_HOURS)
+    .bind(IN_PROGRESS_TIMEOUT_SECONDS)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(claimed_at) = claimed_at {
+        return Ok(Claim::Acquired(claimed_at));
+    }
+
+    let stored = sqlx::query_as::<_, StoredKey>(
+        r#"
+        SELECT request_hash, status_code, content_type, response_body
+        FROM idempotency_keys
+        WHERE user_id = $1 AND key = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+
+    // Gone again: the first request failed and released it just now
+    Ok(stored.map_or(Claim::InProgress, |stored| stored.claim(request_hash)))
+}
+
+/// Stores the response of the request holding a key.
+///
+/// # Returns
+///
+/// Returns `false` if the request no longer holds the key: it ran past
+/// [`IN_PROGRESS_TIMEOUT_SECONDS`] and a retry claimed the key.
+async fn save_response(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &str,
+    claimed_at: DateTime<Utc>,
+    status: StatusCode,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE idempotency_keys
+        SET status_code = $4, content_type = $5, response_body = $6, completed_at = NOW()
+        WHERE user_id = $1 AND key = $2 AND created_at = $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(claimed_at)
+    .bind(i32::from(status.as_u16()))
+    .bind(content_type)
+    .bind(body)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Frees a key whose request failed, so it can be retried.
+///
+/// # Returns
+///
+/// Returns `false` if the request no longer holds the key.
+async fn release_key(pool: &PgPool, user_id: Uuid, key: &str, claimed_at: DateTime<Utc>) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2 AND created_at = $3")
+        .bind(user_id)
+        .bind(key)
+        .bind(claimed_at)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Reads a request body, giving up above [`MAX_BODY_BYTES`].
+///
+/// # Returns
+///
+/// Returns `None` if the body is too large.
+async fn read_body(mut body: Body) -> Result<Option<Bytes>, hyper::Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(Bytes::from(bytes)))
+}
+
+/// Middleware replaying responses to requests with a known
+/// `Idempotency-Key`.
+///
+/// Must run after [`crate::auth::jwt_middleware`]; keys are scoped to the
+/// [`CurrentUser`]. Safe methods and requests without the header are
+/// passed through.
+pub async fn idempotency_middleware(req: Request<Body>, next: Next<Body>) -> Response {
+    if req.method().is_safe() {
+        return next.run(req).await;
+    }
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        None => return next.run(req).await,
+        Some(value) => {
+            let key = value.to_str().unwrap_or_default().trim().to_string();
+            if let Err(message) = validate_key(&key) {
+                return error_response(StatusCode::UNPROCESSABLE_ENTITY, &message);
+            }
+            key
+        }
+    };
+
+    let (parts, body) = req.into_parts();
+    let (Some(pool), Some(CurrentUser(user_id))) = (
+        parts.extensions.get::<PgPool>().cloned(),
+        parts.extensions.get::<CurrentUser>().cloned(),
+    ) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let body = match read_body(body).await {
+        Ok(Some(body)) => body,
+        Ok(None) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "request body is too large"),
+        Err(e) => {
+            error!("Failed to read request body: {}", e);
+            return error_response(StatusCode::BAD_REQUEST, "failed to read request body");
+        }
+    };
+
+    // Nested routers see their own part of the path only
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map(|OriginalUri(uri)| uri.clone())
+        .unwrap_or_else(|| parts.uri.clone());
+    let request_hash = request_fingerprint(&parts.method, &uri, &body);
+
+    let claimed_at = match claim_key(&pool, user_id, &key, &request_hash).await {
+        Ok(Claim::Acquired(claimed_at)) => claimed_at,
+        Ok(Claim::Replay(stored)) => return stored.replay(),
+        Ok(Claim::InProgress) => {
+            return error_response(
+                StatusCode::CONFLICT,
+                "a request with this Idempotency-Key is still in progress",
+            )
+        }
+        Ok(Claim::Mismatch) => {
+            return error_response(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used for a different request",
+            )
+        }
+        Err(e) => {
+            error!("Failed to claim idempotency key for user {}: {}", user_id, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to check idempotency key");
+        }
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    let (parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to read response for idempotency key: {}", e);
+            if let Err(e) = release_key(&pool, user_id, &key, claimed_at).await {
+                error!("Failed to release idempotency key for user {}: {}", user_id, e);
+            }
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let saved = if should_store(parts.status) {
+        let content_type = parts.headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+        save_response(&pool, user_id, &key, claimed_at, parts.status, content_type, &body).await
+    } else {
+        release_key(&pool, user_id, &key, claimed_at).await
+    };
+    // The request itself went through; a retry gets 409 until the key is
+    // taken to be abandoned
+    match saved {
+        Ok(true) => {}
+        Ok(false) => warn!(
+            "Idempotency key of user {} was claimed again while its request was running",
+            user_id
+        ),
+        Err(e) => error!("Failed to store response for idempotency key of user {}: {}", user_id, e),
+    }
+
+    Response::from_parts(parts, boxed(Full::from(body)))
+}
+
+/// Deletes keys older than [`KEY_TTL_HOURS`].
+///
+/// # Returns
+///
+/// Returns the number of deleted keys.
+///
+/// # Errors
+///
+/// Returns an error if the query fails.
+pub async fn prune_expired_keys(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE created_at < NOW() - make_interval(hours => $1)")
+        .bind(KEY_TTL_HOURS)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Spawns a background task deleting expired keys every hour.
+pub fn spawn_pruner(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match prune_expired_keys(&pool).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("Pruned {} expired idempotency keys", deleted),
+                Err(e) => error!("Idempotency key pruning failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(request_hash: &str, status_code: Option<i32>) -> StoredKey {
+        StoredKey {
+            request_hash: request_hash.to_string(),
+            status_code,
+            content_type: Some("application/json".to_string()),
+            response_body: Some(br#"{"id":1}"#.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("3f6c1d2e-payment-42").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("has space").is_err());
+        assert!(validate_key(&"k".repeat(MAX_KEY_LENGTH)).is_ok());
+        assert!(validate_key(&"k".repeat(MAX_KEY_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint_covers_method_path_and_body() {
+        let uri: Uri = "/api/invoices/1/payments".parse().unwrap();
+        let base = request_fingerprint(&Method::POST, &uri, br#"{"amount":"10"}"#);
+
+        assert_eq!(base, request_fingerprint(&Method::POST, &uri, br#"{"amount":"10"}"#));
+        assert_ne!(base, request_fingerprint(&Method::POST, &uri, br#"{"amount":"20"}"#));
+        assert_ne!(base, request_fingerprint(&Method::PUT, &uri, br#"{"amount":"10"}"#));
+        let other: Uri = "/api/invoices/2/payments".parse().unwrap();
+        assert_ne!(base, request_fingerprint(&Method::POST, &other, br#"{"amount":"10"}"#));
+        let query: Uri = "/api/invoices/1/payments?notify=true".parse().unwrap();
+        assert_ne!(base, request_fingerprint(&Method::POST, &query, br#"{"amount":"10"}"#));
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable_across_builds() {
+        // Stored fingerprints must keep matching after an upgrade
+        let uri: Uri = "/api/invoices/1/payments".parse().unwrap();
+        assert_eq!(
+            request_fingerprint(&Method::POST, &uri, br#"{"amount":"10"}"#),
+            "bbafc74e989aac0f4d1ff3fddcd50756"
+        );
+    }
+
+    #[test]
+    fn test_claim_of_stored_key() {
+        assert!(matches!(stored("abc", Some(201)).claim("abc"), Claim::Replay(_)));
+        assert!(matches!(stored("abc", None).claim("abc"), Claim::InProgress));
+        assert!(matches!(stored("abc", Some(201)).claim("def"), Claim::Mismatch));
+    }
+
+    #[test]
+    fn test_server_errors_are_not_stored() {
+        assert!(should_store(StatusCode::CREATED));
+        assert!(should_store(StatusCode::UNPROCESSABLE_ENTITY));
+        assert!(!should_store(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    #[test]
+    fn test_replay_restores_status_and_body() {
+        let response = stored("abc", Some(201)).replay();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[REPLAYED_HEADER], "true");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+}
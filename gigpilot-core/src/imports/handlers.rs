@@ -0,0 +1,226 @@
+use axum::{
+    extract::{Extension, Multipart, Path},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::currency::Currency;
+use crate::imports::{
+    create_job, find_job, list_jobs, load_files, preview_files, save_preview, start_job, FileError,
+    ImportEntity, ImportFiles, ImportPreview, ImportSettings,
+};
+use crate::invoices::import::MAX_IMPORT_BYTES;
+use crate::locale::Locale;
+use crate::models::import_job::{ImportJob, ImportJobStatus, ImportSource};
+use crate::settings::load_user_settings;
+
+/// Largest upload accepted: one file of each kind plus the form fields.
+pub const MAX_UPLOAD_BYTES: usize = 3 * MAX_IMPORT_BYTES + 64 * 1024;
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+fn file_error_response(error: FileError) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(json!({ "error": error.error, "entity": error.entity })),
+    )
+}
+
+/// Previews the files with the user's base currency as the default.
+async fn preview(
+    pool: &PgPool,
+    user_id: Uuid,
+    source: ImportSource,
+    files: &ImportFiles,
+    settings: &ImportSettings,
+) -> Result<ImportPreview, (StatusCode, Json<Value>)> {
+    let internal_error = |message: &str| error_response(StatusCode::INTERNAL_SERVER_ERROR, message);
+    let user_settings = load_user_settings(pool, user_id).await.map_err(|e| {
+        error!("Failed to load settings for user {}: {}", user_id, e);
+        internal_error("Failed to load settings")
+    })?;
+    let currency = Currency::parse(&user_settings.base_currency).map_err(|e| {
+        error!("Invalid base currency for user {}: {}", user_id, e);
+        internal_error("Invalid base currency")
+    })?;
+
+    preview_files(source, files, settings, currency).map_err(file_error_response)
+}
+
+/// Create import job endpoint handler.
+///
+/// Handles POST requests to `/api/imports`. The body is
+/// `multipart/form-data` with a `source` field (`freshbooks` or `wave`),
+/// any of the `clients`, `invoices` and `payments` export files, and
+/// optional `mapping` (JSON object of file to field to header overrides)
+/// and `date_format` and `locale` (e.g. `de-DE`) fields. Nothing is imported yet: the job is returned
+/// with a preview of every file, and a file that can't be read at all is
+/// rejected with its entity.
+pub async fn create_import_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<ImportJob>), (StatusCode, Json<Value>)> {
+    let bad_request = |e: axum::extract::multipart::MultipartError| {
+        error_response(StatusCode::BAD_REQUEST, &e.to_string())
+    };
+    let unprocessable = |message: String| error_response(StatusCode::UNPROCESSABLE_ENTITY, &message);
+
+    let mut source = None;
+    let mut files = ImportFiles::default();
+    let mut settings = ImportSettings::default();
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "source" => {
+                let text = field.text().await.map_err(bad_request)?;
+                source = Some(text.parse::<ImportSource>().map_err(unprocessable)?);
+            }
+            "mapping" => {
+                let text = field.text().await.map_err(bad_request)?;
+                if !text.trim().is_empty() {
+                    settings.mapping = serde_json::from_str(&text).map_err(|e| {
+                        unprocessable(format!("mapping must be a JSON object of file to field to header: {}", e))
+                    })?;
+                }
+            }
+            "date_format" => {
+                let text = field.text().await.map_err(bad_request)?;
+                settings.date_format = Some(text.trim().to_string()).filter(|format| !format.is_empty());
+            }
+            "locale" => {
+                let text = field.text().await.map_err(bad_request)?;
+                if !text.trim().is_empty() {
+                    settings.locale = Some(Locale::parse(&text).map_err(unprocessable)?);
+                }
+            }
+            _ => {
+                if let Ok(entity) = name.parse::<ImportEntity>() {
+                    let data = field.bytes().await.map_err(bad_request)?;
+                    if data.len() > MAX_IMPORT_BYTES {
+                        return Err(unprocessable(format!(
+                            "the {} file must be at most {} bytes",
+                            entity, MAX_IMPORT_BYTES
+                        )));
+                    }
+                    files.set(entity, data.to_vec());
+                }
+            }
+        }
+    }
+    let source = source.ok_or_else(|| unprocessable("missing source field".to_string()))?;
+    if files.is_empty() {
+        return Err(unprocessable("upload at least one of clients, invoices or payments".to_string()));
+    }
+
+    let preview = preview(&pool, user_id, source, &files, &settings).await?;
+    let job = create_job(&pool, user_id, source, &files, &settings, &preview)
+        .await
+        .map_err(|e| {
+            error!("Failed to create import job for user {}: {}", user_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create import job")
+        })?;
+
+    Ok((StatusCode::CREATED, Json(job)))
+}
+
+/// List import jobs endpoint handler.
+///
+/// Handles GET requests to `/api/imports`.
+pub async fn list_imports_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<ImportJob>>, StatusCode> {
+    let jobs = list_jobs(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list import jobs for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(jobs))
+}
+
+/// Get import job endpoint handler.
+///
+/// Handles GET requests to `/api/imports/:id`. Poll it after starting a
+/// job; the report appears once the job has completed.
+pub async fn get_import_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<ImportJob>, StatusCode> {
+    let job = find_job(&pool, user_id, job_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load import job {}: {}", job_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(job))
+}
+
+/// Update import mapping endpoint handler.
+///
+/// Handles PUT requests to `/api/imports/:id` with new `ImportSettings`.
+/// The uploaded files are read again and the preview replaced. Only jobs
+/// that haven't been started can be changed.
+pub async fn update_import_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(job_id): Path<Uuid>,
+    Json(settings): Json<ImportSettings>,
+) -> Result<Json<ImportJob>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to update import job {}: {}", job_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update import job")
+    };
+    let already_started = || error_response(StatusCode::CONFLICT, "import job has already been started");
+
+    let job = find_job(&pool, user_id, job_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "import job not found"))?;
+    if job.status != ImportJobStatus::Preview {
+        return Err(already_started());
+    }
+
+    let files = load_files(&pool, job.id).await.map_err(internal_error)?;
+    let preview = preview(&pool, user_id, job.source, &files, &settings).await?;
+    let job = save_preview(&pool, user_id, job_id, &settings, &preview)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(already_started)?;
+
+    Ok(Json(job))
+}
+
+/// Start import job endpoint handler.
+///
+/// Handles POST requests to `/api/imports/:id/start`. The job is queued
+/// for the worker; poll `GET /api/imports/:id` for the report.
+pub async fn start_import_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(job_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ImportJob>), (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to start import job {}: {}", job_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to start import job")
+    };
+
+    if let Some(job) = start_job(&pool, user_id, job_id).await.map_err(internal_error)? {
+        return Ok((StatusCode::ACCEPTED, Json(job)));
+    }
+
+    match find_job(&pool, user_id, job_id).await.map_err(internal_error)? {
+        Some(_) => Err(error_response(StatusCode::CONFLICT, "import job has already been started")),
+        None => Err(error_response(StatusCode::NOT_FOUND, "import job not found")),
+    }
+}
@@ -0,0 +1,836 @@
+//! Account imports from FreshBooks and Wave.
+//!
+//! A user switching tools uploads any of the old tool's client, invoice and
+//! payment exports. The files are parsed straight away and the job comes
+//! back with a preview of each: the header every field was read from, how
+//! many rows are valid, a few sample rows and the row errors. The column
+//! mapping and date format can be changed until the preview looks right;
+//! starting the job queues it for the worker, which imports clients, then
+//! invoices, then payments and stores a report per entity.
+//!
+//! Imports are idempotent: clients are matched by name, invoices by their
+//! import key and payments by invoice, amount, date and reference, so a job
+//! that stopped part way can simply be uploaded again. Payments are checked
+//! against the balance due like payments recorded by hand.
+
+pub mod handlers;
+pub mod sources;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::clients::insert_client;
+use crate::currency::{Currency, Money};
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::import::{import_invoices, ImportRowError, ParsedImport};
+use crate::invoices::payments::refresh_amount_paid;
+use crate::locale::Locale;
+use crate::models::client::CreateClient;
+use crate::models::import_job::{ImportJob, ImportSource, IMPORT_JOB_COLUMNS};
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::payment::Payment;
+use crate::models::sync_change::SyncOperation;
+use crate::settings::load_user_settings;
+use crate::sync::server::record_server_change;
+use sources::{detect_columns, parse_clients, parse_invoices, parse_payments, ClientRow, ParsedRows, PaymentRow};
+
+pub use sources::ImportEntity;
+
+/// Sample rows shown per file in a preview.
+pub const PREVIEW_ROWS: usize = 5;
+
+/// Row errors shown per file in a preview.
+pub const PREVIEW_ERRORS: usize = 50;
+
+/// How to read a job's files.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportSettings {
+    /// Header overrides per file, e.g. `{"clients": {"name": "Company"}}`
+    #[serde(default)]
+    pub mapping: HashMap<ImportEntity, HashMap<String, String>>,
+
+    /// `chrono` format of the date columns (read tolerantly without one)
+    #[serde(default)]
+    pub date_format: Option<String>,
+
+    /// How numbers and dates are written, e.g. `de-DE`
+    #[serde(default)]
+    pub locale: Option<Locale>,
+}
+
+impl ImportSettings {
+    /// Header overrides for one file.
+    fn overrides(&self, entity: ImportEntity) -> HashMap<String, String> {
+        self.mapping.get(&entity).cloned().unwrap_or_default()
+    }
+}
+
+/// The uploaded exports; any may be missing.
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct ImportFiles {
+    #[sqlx(rename = "clients_csv")]
+    pub clients: Option<Vec<u8>>,
+
+    #[sqlx(rename = "invoices_csv")]
+    pub invoices: Option<Vec<u8>>,
+
+    #[sqlx(rename = "payments_csv")]
+    pub payments: Option<Vec<u8>>,
+}
+
+impl ImportFiles {
+    /// The file uploaded for an entity.
+    pub fn get(&self, entity: ImportEntity) -> Option<&[u8]> {
+        match entity {
+            ImportEntity::Clients => self.clients.as_deref(),
+            ImportEntity::Invoices => self.invoices.as_deref(),
+            ImportEntity::Payments => self.payments.as_deref(),
+        }
+    }
+
+    /// Stores the file uploaded for an entity.
+    pub fn set(&mut self, entity: ImportEntity, data: Vec<u8>) {
+        match entity {
+            ImportEntity::Clients => self.clients = Some(data),
+            ImportEntity::Invoices => self.invoices = Some(data),
+            ImportEntity::Payments => self.payments = Some(data),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        ImportEntity::ALL.iter().all(|entity| self.get(*entity).is_none())
+    }
+}
+
+/// A file that can't be read at all, e.g. because a required column is
+/// missing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileError {
+    pub entity: ImportEntity,
+    pub error: String,
+}
+
+impl FileError {
+    fn new(entity: ImportEntity, error: ImportRowError) -> Self {
+        FileError { entity, error: error.error }
+    }
+}
+
+/// The rows of every uploaded file.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedFiles {
+    pub clients: Option<ParsedRows<ClientRow>>,
+    pub invoices: Option<ParsedImport>,
+    pub payments: Option<ParsedRows<PaymentRow>>,
+}
+
+/// Parses every uploaded file.
+///
+/// # Arguments
+///
+/// * `source` - Tool the files were exported from
+/// * `files` - The uploaded files
+/// * `settings` - Column overrides and date format
+/// * `default_currency` - Currency of invoices without a currency column
+///
+/// # Errors
+///
+/// Returns the first file that can't be read at all. Problems with single
+/// rows are kept with the parsed rows.
+pub fn parse_files(
+    source: ImportSource,
+    files: &ImportFiles,
+    settings: &ImportSettings,
+    default_currency: Currency,
+) -> Result<ParsedFiles, FileError> {
+    let date_format = settings.date_format.as_deref();
+    let locale = settings.locale.as_ref();
+
+    let clients = files
+        .clients
+        .as_deref()
+        .map(|data| parse_clients(source, data, &settings.overrides(ImportEntity::Clients)))
+        .transpose()
+        .map_err(|e| FileError::new(ImportEntity::Clients, e))?;
+    let invoices = files
+        .invoices
+        .as_deref()
+        .map(|data| {
+            let overrides = settings.overrides(ImportEntity::Invoices);
+            parse_invoices(source, data, &overrides, date_format, locale, default_currency)
+        })
+        .transpose()
+        .map_err(|e| FileError::new(ImportEntity::Invoices, e))?;
+    let payments = files
+        .payments
+        .as_deref()
+        .map(|data| parse_payments(source, data, &settings.overrides(ImportEntity::Payments), date_format, locale))
+        .transpose()
+        .map_err(|e| FileError::new(ImportEntity::Payments, e))?;
+
+    Ok(ParsedFiles { clients, invoices, payments })
+}
+
+/// What importing one file would do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityPreview {
+    /// Header each field is read from
+    pub columns: BTreeMap<String, String>,
+
+    /// Rows that will be imported (or skipped if already imported)
+    pub valid: usize,
+
+    /// Rows that will not be imported
+    pub error_count: usize,
+
+    /// The first valid rows, as they will be imported
+    pub sample: Vec<Value>,
+
+    /// The first row errors
+    pub errors: Vec<ImportRowError>,
+}
+
+impl EntityPreview {
+    fn new<T: Serialize>(columns: BTreeMap<String, String>, rows: &[T], errors: &[ImportRowError]) -> Self {
+        EntityPreview {
+            columns,
+            valid: rows.len(),
+            error_count: errors.len(),
+            sample: rows
+                .iter()
+                .take(PREVIEW_ROWS)
+                .filter_map(|row| serde_json::to_value(row).ok())
+                .collect(),
+            errors: errors.iter().take(PREVIEW_ERRORS).cloned().collect(),
+        }
+    }
+}
+
+/// Preview of every uploaded file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportPreview {
+    pub clients: Option<EntityPreview>,
+    pub invoices: Option<EntityPreview>,
+    pub payments: Option<EntityPreview>,
+}
+
+/// Parses the uploaded files and previews what importing them would do.
+///
+/// # Errors
+///
+/// Returns the first file that can't be read at all.
+pub fn preview_files(
+    source: ImportSource,
+    files: &ImportFiles,
+    settings: &ImportSettings,
+    default_currency: Currency,
+) -> Result<ImportPreview, FileError> {
+    let parsed = parse_files(source, files, settings, default_currency)?;
+    let columns = |entity: ImportEntity| {
+        let data = files.get(entity).unwrap_or_default();
+        detect_columns(data, source, entity, &settings.overrides(entity)).map_err(|e| FileError::new(entity, e))
+    };
+
+    let mut preview = ImportPreview::default();
+    if let Some(clients) = &parsed.clients {
+        preview.clients = Some(EntityPreview::new(columns(ImportEntity::Clients)?, &clients.rows, &clients.errors));
+    }
+    if let Some(invoices) = &parsed.invoices {
+        preview.invoices = Some(EntityPreview::new(
+            columns(ImportEntity::Invoices)?,
+            &invoices.rows,
+            &invoices.errors,
+        ));
+    }
+    if let Some(payments) = &parsed.payments {
+        preview.payments = Some(EntityPreview::new(
+            columns(ImportEntity::Payments)?,
+            &payments.rows,
+            &payments.errors,
+        ));
+    }
+
+    Ok(preview)
+}
+
+/// Results of importing one file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityReport {
+    /// Rows stored as new records
+    pub imported: usize,
+
+    /// Rows already imported earlier
+    pub skipped: usize,
+
+    /// Rows that were not imported, and why
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Results of an import job, per file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportJobReport {
+    pub clients: Option<EntityReport>,
+    pub invoices: Option<EntityReport>,
+    pub payments: Option<EntityReport>,
+
+    /// Imported invoices linked to a client with the same name
+    pub linked_invoices: usize,
+}
+
+impl ImportJobReport {
+    /// One-line summary for the completion notification, e.g.
+    /// "Imported 3 clients and 12 invoices; 1 row had errors."
+    pub fn summary(&self) -> String {
+        let entities = [
+            (ImportEntity::Clients, &self.clients),
+            (ImportEntity::Invoices, &self.invoices),
+            (ImportEntity::Payments, &self.payments),
+        ];
+
+        let imported: Vec<String> = entities
+            .iter()
+            .filter_map(|(entity, report)| {
+                report.as_ref().map(|report| match report.imported {
+                    1 => format!("1 {}", entity.as_str().trim_end_matches('s')),
+                    n => format!("{} {}", n, entity),
+                })
+            })
+            .collect();
+        let mut summary = match imported.split_last() {
+            None => "Nothing to import".to_string(),
+            Some((last, [])) => format!("Imported {}", last),
+            Some((last, rest)) => format!("Imported {} and {}", rest.join(", "), last),
+        };
+
+        let errors: usize = entities
+            .iter()
+            .filter_map(|(_, report)| report.as_ref())
+            .map(|report| report.errors.len())
+            .sum();
+        match errors {
+            0 => summary.push('.'),
+            1 => summary.push_str("; 1 row had errors."),
+            n => summary.push_str(&format!("; {} rows had errors.", n)),
+        }
+        summary
+    }
+}
+
+/// Creates an import job in the preview state.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the importing user
+/// * `source` - Tool the files were exported from
+/// * `files` - The uploaded files
+/// * `settings` - How the files were read
+/// * `preview` - Preview from [`preview_files`]
+///
+/// # Returns
+///
+/// Returns the created `ImportJob`, or an error.
+pub async fn create_job(
+    pool: &PgPool,
+    user_id: Uuid,
+    source: ImportSource,
+    files: &ImportFiles,
+    settings: &ImportSettings,
+    preview: &ImportPreview,
+) -> Result<ImportJob, anyhow::Error> {
+    let job = sqlx::query_as::<_, ImportJob>(&format!(
+        r#"
+        INSERT INTO import_jobs (
+            user_id, source, clients_csv, invoices_csv, payments_csv, options, preview
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING {}
+        "#,
+        IMPORT_JOB_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(source)
+    .bind(files.clients.as_deref())
+    .bind(files.invoices.as_deref())
+    .bind(files.payments.as_deref())
+    .bind(serde_json::to_value(settings)?)
+    .bind(serde_json::to_value(preview)?)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Loads the files uploaded with a job.
+pub async fn load_files(pool: &PgPool, job_id: Uuid) -> Result<ImportFiles, anyhow::Error> {
+    let files = sqlx::query_as::<_, ImportFiles>(
+        "SELECT clients_csv, invoices_csv, payments_csv FROM import_jobs WHERE id = $1",
+    )
+    .bind(job_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(files)
+}
+
+/// Loads one of a user's import jobs.
+pub async fn find_job(pool: &PgPool, user_id: Uuid, job_id: Uuid) -> Result<Option<ImportJob>, anyhow::Error> {
+    let job = sqlx::query_as::<_, ImportJob>(&format!(
+        "SELECT {} FROM import_jobs WHERE id = $1 AND user_id = $2",
+        IMPORT_JOB_COLUMNS
+    ))
+    .bind(job_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Lists a user's import jobs, newest first.
+pub async fn list_jobs(pool: &PgPool, user_id: Uuid) -> Result<Vec<ImportJob>, anyhow::Error> {
+    let jobs = sqlx::query_as::<_, ImportJob>(&format!(
+        "SELECT {} FROM import_jobs WHERE user_id = $1 ORDER BY created_at DESC LIMIT 50",
+        IMPORT_JOB_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+/// Stores new settings and their preview on a job that hasn't started.
+///
+/// # Returns
+///
+/// Returns the updated job, or `None` if it has already been started.
+pub async fn save_preview(
+    pool: &PgPool,
+    user_id: Uuid,
+    job_id: Uuid,
+    settings: &ImportSettings,
+    preview: &ImportPreview,
+) -> Result<Option<ImportJob>, anyhow::Error> {
+    let job = sqlx::query_as::<_, ImportJob>(&format!(
+        r#"
+        UPDATE import_jobs SET options = $3, preview = $4
+        WHERE id = $1 AND user_id = $2 AND status = 'preview'
+        RETURNING {}
+        "#,
+        IMPORT_JOB_COLUMNS
+    ))
+    .bind(job_id)
+    .bind(user_id)
+    .bind(serde_json::to_value(settings)?)
+    .bind(serde_json::to_value(preview)?)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Queues a previewed job for the worker.
+///
+/// # Returns
+///
+/// Returns the queued job, or `None` if it has already been started.
+pub async fn start_job(pool: &PgPool, user_id: Uuid, job_id: Uuid) -> Result<Option<ImportJob>, anyhow::Error> {
+    let job = sqlx::query_as::<_, ImportJob>(&format!(
+        r#"
+        UPDATE import_jobs SET status = 'queued'
+        WHERE id = $1 AND user_id = $2 AND status = 'preview'
+        RETURNING {}
+        "#,
+        IMPORT_JOB_COLUMNS
+    ))
+    .bind(job_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Imports a job's files: clients, then invoices, then payments.
+///
+/// Each file is imported in its own transaction, so an error keeps the
+/// files imported before it. Invoices are linked to the client with the
+/// same name, whether imported now or created before.
+///
+/// # Returns
+///
+/// Returns the per-entity results.
+///
+/// # Errors
+///
+/// Returns an error if a file can't be read or a database query fails.
+pub async fn run_job(pool: &PgPool, job: &ImportJob) -> Result<ImportJobReport, anyhow::Error> {
+    let settings: ImportSettings = serde_json::from_value(job.options.clone())?;
+    let user_settings = load_user_settings(pool, job.user_id).await?;
+    let currency = Currency::parse(&user_settings.base_currency)?;
+
+    let files = load_files(pool, job.id).await?;
+    let parsed = parse_files(job.source, &files, &settings, currency)
+        .map_err(|e| anyhow::anyhow!("cannot read the {} file: {}", e.entity, e.error))?;
+
+    let mut report = ImportJobReport::default();
+    if let Some(clients) = parsed.clients {
+        report.clients = Some(import_clients(pool, job.user_id, clients).await?);
+    }
+    if let Some(invoices) = parsed.invoices {
+        let imported = import_invoices(pool, job.user_id, invoices, false).await?;
+        let invoice_ids: Vec<Uuid> = imported
+            .imported
+            .iter()
+            .map(|row| row.invoice_id)
+            .chain(imported.skipped.iter().map(|row| row.invoice_id))
+            .collect();
+        report.linked_invoices = link_invoice_clients(pool, job.user_id, &invoice_ids).await?;
+        report.invoices = Some(EntityReport {
+            imported: imported.imported.len(),
+            skipped: imported.skipped.len(),
+            errors: imported.errors,
+        });
+    }
+    if let Some(payments) = parsed.payments {
+        report.payments = Some(import_payments(pool, job.user_id, payments).await?);
+    }
+
+    Ok(report)
+}
+
+/// Stores imported clients, skipping names the user already has.
+async fn import_clients(
+    pool: &PgPool,
+    user_id: Uuid,
+    parsed: ParsedRows<ClientRow>,
+) -> Result<EntityReport, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(format!("client_import:{}", user_id))
+        .execute(&mut *tx)
+        .await?;
+
+    let mut names: HashSet<String> = sqlx::query_scalar::<_, String>(
+        "SELECT lower(name) FROM clients WHERE user_id = $1 AND is_deleted = false",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+
+    let mut report = EntityReport {
+        errors: parsed.errors,
+        ..EntityReport::default()
+    };
+    for row in parsed.rows {
+        if !names.insert(row.name.to_lowercase()) {
+            report.skipped += 1;
+            continue;
+        }
+
+        let request = CreateClient {
+            name: row.name,
+            email: row.email,
+            phone: row.phone,
+            address: row.address,
+            tax_id: row.tax_id,
+            notes: row.notes,
+            payment_terms_days: None,
+            metadata: Some(json!({ "import": { "line": row.line } })),
+        };
+        insert_client(&mut tx, user_id, request).await?;
+        report.imported += 1;
+    }
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+/// Links imported invoices without a client to the client of that name.
+///
+/// # Returns
+///
+/// Returns the number of invoices linked.
+async fn link_invoice_clients(pool: &PgPool, user_id: Uuid, invoice_ids: &[Uuid]) -> Result<usize, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let linked = sqlx::query_as::<_, Invoice>(
+        r#"
+        WITH matches AS (
+            SELECT DISTINCT ON (i.id) i.id AS invoice_id, c.id AS matched_client_id
+            FROM invoices i
+            JOIN clients c
+                ON c.user_id = i.user_id
+                AND c.is_deleted = false
+                AND lower(c.name) = lower(i.client_name)
+            WHERE i.user_id = $1 AND i.id = ANY($2) AND i.client_id IS NULL
+            ORDER BY i.id, c.created_at
+        )
+        UPDATE invoices
+        SET client_id = matches.matched_client_id, last_modified = NOW()
+        FROM matches
+        WHERE invoices.id = matches.invoice_id
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for invoice in &linked {
+        record_server_change(
+            &mut *tx,
+            invoice.user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(invoice)?,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(linked.len())
+}
+
+/// Stores imported payments against the invoices they name.
+///
+/// A payment identical to one recorded on the invoice before the import
+/// (same amount, date and reference) is skipped, once per recorded
+/// payment, so identical payments in the file are each imported. Payments
+/// that would take an invoice past its total are row errors. The amount
+/// paid of every invoice that received a payment is recomputed afterwards.
+async fn import_payments(
+    pool: &PgPool,
+    user_id: Uuid,
+    parsed: ParsedRows<PaymentRow>,
+) -> Result<EntityReport, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let numbers: Vec<&str> = parsed.rows.iter().map(|row| row.invoice_number.as_str()).collect();
+    let invoices: HashMap<String, (Uuid, String, InvoiceStatus, Decimal)> =
+        sqlx::query_as::<_, (String, Uuid, String, InvoiceStatus, Decimal)>(
+            r#"
+            SELECT invoice_number, id, currency, status, total - amount_paid FROM invoices
+            WHERE user_id = $1 AND is_deleted = false AND invoice_number = ANY($2)
+            ORDER BY id
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .bind(&numbers)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(number, id, currency, status, balance_due)| (number, (id, currency, status, balance_due)))
+        .collect();
+
+    let mut report = EntityReport {
+        errors: parsed.errors,
+        ..EntityReport::default()
+    };
+    let row_error = |line: u64, field: &str, error: String| ImportRowError {
+        line: Some(line),
+        field: Some(field.to_string()),
+        error,
+    };
+    let mut paid_invoices = BTreeSet::new();
+    let mut inserted: Vec<Uuid> = Vec::new();
+    let mut occurrences: HashMap<(Uuid, Decimal, NaiveDate, Option<String>), i64> = HashMap::new();
+    let mut balances: HashMap<Uuid, Decimal> = HashMap::new();
+    for row in parsed.rows {
+        let Some((invoice_id, currency, status, balance_due)) = invoices.get(&row.invoice_number) else {
+            report.errors.push(row_error(
+                row.line,
+                "invoice_number",
+                format!("no invoice numbered {}", row.invoice_number),
+            ));
+            continue;
+        };
+        if *status != InvoiceStatus::Paid && !status.can_transition(InvoiceStatus::Paid) {
+            report.errors.push(row_error(
+                row.line,
+                "invoice_number",
+                format!("cannot record a payment on a {} invoice", status),
+            ));
+            continue;
+        }
+        let amount = Money::new(row.amount, Currency::parse(currency)?);
+        if !amount.is_representable() {
+            report.errors.push(row_error(
+                row.line,
+                "amount",
+                format!("must have at most {} decimal places", amount.currency.minor_units()),
+            ));
+            continue;
+        }
+
+        // Payments stored by this import don't count: the file may list the
+        // same payment twice
+        let occurrence = occurrences
+            .entry((*invoice_id, row.amount, row.paid_on, row.reference.clone()))
+            .or_insert(0);
+        *occurrence += 1;
+        let recorded = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM payments
+            WHERE invoice_id = $1 AND amount = $2 AND paid_on = $3
+                AND reference IS NOT DISTINCT FROM $4
+                AND id <> ALL($5)
+            "#,
+        )
+        .bind(invoice_id)
+        .bind(row.amount)
+        .bind(row.paid_on)
+        .bind(row.reference.as_deref())
+        .bind(&inserted)
+        .fetch_one(&mut *tx)
+        .await?;
+        if *occurrence <= recorded {
+            report.skipped += 1;
+            continue;
+        }
+
+        let balance = balances.entry(*invoice_id).or_insert(*balance_due);
+        if amount.amount > *balance {
+            let balance_due = Money::new((*balance).max(Decimal::ZERO), amount.currency);
+            report.errors.push(row_error(
+                row.line,
+                "amount",
+                format!("amount exceeds balance due ({})", balance_due),
+            ));
+            continue;
+        }
+        *balance -= amount.amount;
+
+        let payment = sqlx::query_as::<_, Payment>(
+            r#"
+            INSERT INTO payments (
+                user_id, invoice_id, amount, currency, paid_on, method, reference, notes
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(invoice_id)
+        .bind(row.amount)
+        .bind(currency)
+        .bind(row.paid_on)
+        .bind(row.method)
+        .bind(row.reference)
+        .bind(row.notes)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "payments",
+            payment.id,
+            SyncOperation::Insert,
+            &serde_json::to_value(&payment)?,
+        )
+        .await?;
+        paid_invoices.insert(*invoice_id);
+        inserted.push(payment.id);
+        report.imported += 1;
+    }
+
+    for invoice_id in paid_invoices {
+        refresh_amount_paid(&mut tx, invoice_id).await?;
+    }
+    report.errors.sort_by_key(|error| error.line);
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(imported: usize, errors: usize) -> Option<EntityReport> {
+        Some(EntityReport {
+            imported,
+            skipped: 0,
+            errors: vec![
+                ImportRowError {
+                    line: Some(2),
+                    field: None,
+                    error: "bad row".to_string(),
+                };
+                errors
+            ],
+        })
+    }
+
+    #[test]
+    fn test_settings_mapping_is_keyed_by_entity() {
+        let settings: ImportSettings = serde_json::from_value(json!({
+            "mapping": { "invoices": { "total": "Grand Total" } }
+        }))
+        .unwrap();
+
+        assert_eq!(settings.overrides(ImportEntity::Invoices).get("total").map(String::as_str), Some("Grand Total"));
+        assert!(settings.overrides(ImportEntity::Clients).is_empty());
+        assert_eq!(settings.date_format, None);
+
+        let defaults: ImportSettings = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(defaults, ImportSettings::default());
+    }
+
+    #[test]
+    fn test_preview_is_truncated() {
+        let rows: Vec<u32> = (0..20).collect();
+        let errors = vec![
+            ImportRowError {
+                line: Some(3),
+                field: Some("total".to_string()),
+                error: "not a number".to_string(),
+            };
+            60
+        ];
+
+        let preview = EntityPreview::new(BTreeMap::new(), &rows, &errors);
+
+        assert_eq!(preview.valid, 20);
+        assert_eq!(preview.sample, vec![json!(0), json!(1), json!(2), json!(3), json!(4)]);
+        assert_eq!(preview.error_count, 60);
+        assert_eq!(preview.errors.len(), PREVIEW_ERRORS);
+    }
+
+    #[test]
+    fn test_report_summary() {
+        let full = ImportJobReport {
+            clients: report(3, 0),
+            invoices: report(12, 1),
+            payments: report(8, 1),
+            linked_invoices: 12,
+        };
+        assert_eq!(full.summary(), "Imported 3 clients, 12 invoices and 8 payments; 2 rows had errors.");
+
+        let invoices_only = ImportJobReport {
+            invoices: report(1, 1),
+            ..ImportJobReport::default()
+        };
+        assert_eq!(invoices_only.summary(), "Imported 1 invoice; 1 row had errors.");
+
+        assert_eq!(ImportJobReport::default().summary(), "Nothing to import.");
+    }
+}
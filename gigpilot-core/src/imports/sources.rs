@@ -0,0 +1,727 @@
+//! Reading FreshBooks and Wave exports.
+//!
+//! Each tool names its columns differently, so every source has a layout
+//! listing the headers a field may appear under. Users can override any
+//! field with their own header when an export was customised. Invoice
+//! exports are translated into the format of the CSV invoice import and
+//! validated by it; clients and payments are parsed here.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use csv::StringRecord;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::invoices::import::{
+    parse_import_date, parse_records, ImportField, ImportOptions, ImportRowError, ParsedImport, MAX_IMPORT_ROWS,
+};
+use crate::locale::{parse_decimal, Locale};
+use crate::models::import_job::ImportSource;
+
+/// Kind of record in an export file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportEntity {
+    Clients,
+    Invoices,
+    Payments,
+}
+
+impl ImportEntity {
+    /// Every entity, in the order they are imported.
+    pub const ALL: [ImportEntity; 3] = [ImportEntity::Clients, ImportEntity::Invoices, ImportEntity::Payments];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportEntity::Clients => "clients",
+            ImportEntity::Invoices => "invoices",
+            ImportEntity::Payments => "payments",
+        }
+    }
+}
+
+impl fmt::Display for ImportEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ImportEntity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ImportEntity::ALL
+            .into_iter()
+            .find(|entity| entity.as_str() == s.trim())
+            .ok_or_else(|| format!("unknown import entity: {}", s))
+    }
+}
+
+/// Column index of each field found in a file.
+type Columns = HashMap<&'static str, usize>;
+
+/// Headers each field may appear under, most likely first.
+type Layout = &'static [(&'static str, &'static [&'static str])];
+
+const FRESHBOOKS_CLIENTS: Layout = &[
+    ("name", &["Organization", "Company Name"]),
+    ("first_name", &["First Name"]),
+    ("last_name", &["Last Name"]),
+    ("email", &["Email", "Email Address"]),
+    ("phone", &["Phone", "Business Phone", "Mobile"]),
+    ("address_line1", &["Street", "Address Line 1", "Address"]),
+    ("address_line2", &["Street2", "Address Line 2"]),
+    ("city", &["City"]),
+    ("region", &["Province/State", "State", "Province"]),
+    ("postal_code", &["Postal Code", "Zip Code", "ZIP"]),
+    ("country", &["Country"]),
+    ("tax_id", &["Tax Number", "VAT Number", "Tax ID"]),
+    ("notes", &["Notes"]),
+];
+
+const WAVE_CLIENTS: Layout = &[
+    ("name", &["Customer Name", "Customer", "Company Name"]),
+    ("first_name", &["Contact First Name", "First Name"]),
+    ("last_name", &["Contact Last Name", "Last Name"]),
+    ("email", &["Email", "Email Address"]),
+    ("phone", &["Phone", "Mobile"]),
+    ("address_line1", &["Address Line 1", "Address 1"]),
+    ("address_line2", &["Address Line 2", "Address 2"]),
+    ("city", &["City"]),
+    ("region", &["Province/State", "State", "Province"]),
+    ("postal_code", &["Postal/Zip Code", "Postal Code", "Zip Code"]),
+    ("country", &["Country"]),
+    ("tax_id", &["Tax Number", "Business Number"]),
+    ("notes", &["Notes", "Memo"]),
+];
+
+const FRESHBOOKS_INVOICES: Layout = &[
+    ("invoice_number", &["Invoice #", "Invoice Number"]),
+    ("client_name", &["Organization", "Client Name", "Client"]),
+    ("client_email", &["Email", "Client Email"]),
+    ("description", &["Description", "Notes"]),
+    ("issue_date", &["Date Issued", "Issue Date", "Invoice Date"]),
+    ("due_date", &["Due Date", "Date Due"]),
+    ("total", &["Invoice Total", "Amount", "Total"]),
+    ("amount_paid", &["Paid", "Amount Paid"]),
+    ("currency", &["Currency"]),
+    ("status", &["Invoice Status", "Status"]),
+];
+
+const WAVE_INVOICES: Layout = &[
+    ("invoice_number", &["Invoice Number", "Invoice #"]),
+    ("client_name", &["Customer", "Customer Name"]),
+    ("client_email", &["Customer Email", "Email"]),
+    ("description", &["Memo", "Notes", "Description"]),
+    ("issue_date", &["Invoice Date", "Date"]),
+    ("due_date", &["Due Date", "Payment Due"]),
+    ("total", &["Invoice Total", "Total"]),
+    ("amount_paid", &["Amount Paid", "Paid"]),
+    ("currency", &["Currency"]),
+    ("status", &["Status"]),
+];
+
+const FRESHBOOKS_PAYMENTS: Layout = &[
+    ("invoice_number", &["Invoice #", "Invoice Number"]),
+    ("paid_on", &["Date", "Payment Date", "Date Paid"]),
+    ("amount", &["Amount", "Payment Amount"]),
+    ("method", &["Payment Type", "Payment Method", "Method"]),
+    ("reference", &["Reference", "Transaction ID"]),
+    ("notes", &["Notes"]),
+];
+
+const WAVE_PAYMENTS: Layout = &[
+    ("invoice_number", &["Invoice Number", "Invoice #"]),
+    ("paid_on", &["Payment Date", "Date"]),
+    ("amount", &["Amount", "Payment Amount"]),
+    ("method", &["Payment Method", "Payment Account"]),
+    ("reference", &["Reference", "Transaction ID"]),
+    ("notes", &["Memo", "Notes"]),
+];
+
+/// Column layout of one of a source's exports.
+fn layout(source: ImportSource, entity: ImportEntity) -> Layout {
+    match (source, entity) {
+        (ImportSource::FreshBooks, ImportEntity::Clients) => FRESHBOOKS_CLIENTS,
+        (ImportSource::FreshBooks, ImportEntity::Invoices) => FRESHBOOKS_INVOICES,
+        (ImportSource::FreshBooks, ImportEntity::Payments) => FRESHBOOKS_PAYMENTS,
+        (ImportSource::Wave, ImportEntity::Clients) => WAVE_CLIENTS,
+        (ImportSource::Wave, ImportEntity::Invoices) => WAVE_INVOICES,
+        (ImportSource::Wave, ImportEntity::Payments) => WAVE_PAYMENTS,
+    }
+}
+
+/// Fields every file of the entity must have a column for.
+///
+/// Clients need a name or a contact name, which is checked per row.
+fn required_fields(entity: ImportEntity) -> &'static [&'static str] {
+    match entity {
+        ImportEntity::Clients => &[],
+        ImportEntity::Invoices => &["client_name", "total"],
+        ImportEntity::Payments => &["invoice_number", "paid_on", "amount"],
+    }
+}
+
+/// Finds the column of each field in an export's header row.
+///
+/// A field listed in `overrides` must be under that header; the others
+/// are looked up in the source's layout.
+///
+/// # Returns
+///
+/// Returns the column index of every field found.
+///
+/// # Errors
+///
+/// Returns an error if an override names an unknown field or a missing
+/// header, or a required field has no column.
+pub fn resolve_columns(
+    source: ImportSource,
+    entity: ImportEntity,
+    headers: &StringRecord,
+    overrides: &HashMap<String, String>,
+) -> Result<Columns, String> {
+    let layout = layout(source, entity);
+    let position = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+    };
+
+    if let Some(field) = overrides.keys().find(|field| !layout.iter().any(|(name, _)| name == field)) {
+        return Err(format!("unknown {} field in mapping: {}", entity, field));
+    }
+
+    let mut columns = HashMap::new();
+    for (field, candidates) in layout {
+        let index = match overrides.get(*field) {
+            Some(header) => Some(position(header).ok_or_else(|| format!("column not found: {}", header))?),
+            None => candidates.iter().find_map(|candidate| position(candidate)),
+        };
+        if let Some(index) = index {
+            columns.insert(*field, index);
+        }
+    }
+    if let Some(field) = required_fields(entity).iter().find(|field| !columns.contains_key(*field)) {
+        return Err(format!("missing column for {}", field));
+    }
+
+    Ok(columns)
+}
+
+/// One data row of an export, read through the resolved columns.
+struct Row<'a> {
+    line: u64,
+    record: &'a StringRecord,
+    columns: &'a Columns,
+}
+
+impl<'a> Row<'a> {
+    /// The non-empty value of a field.
+    fn get(&self, field: &str) -> Option<&'a str> {
+        self.columns
+            .get(field)
+            .and_then(|index| self.record.get(*index))
+            .filter(|value| !value.is_empty())
+    }
+
+    fn error(&self, field: &str, error: impl Into<String>) -> ImportRowError {
+        ImportRowError {
+            line: Some(self.line),
+            field: Some(field.to_string()),
+            error: error.into(),
+        }
+    }
+}
+
+/// Rows read from an export, with the problems found in the others.
+#[derive(Debug, Clone)]
+pub struct ParsedRows<T> {
+    pub rows: Vec<T>,
+    pub errors: Vec<ImportRowError>,
+}
+
+impl<T> Default for ParsedRows<T> {
+    fn default() -> Self {
+        ParsedRows { rows: Vec::new(), errors: Vec::new() }
+    }
+}
+
+fn file_error(error: impl Into<String>) -> ImportRowError {
+    ImportRowError { line: None, field: None, error: error.into() }
+}
+
+/// Opens an export and resolves its columns from the header row.
+fn open<'a>(
+    data: &'a [u8],
+    source: ImportSource,
+    entity: ImportEntity,
+    overrides: &HashMap<String, String>,
+) -> Result<(csv::Reader<&'a [u8]>, StringRecord, Columns), ImportRowError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| file_error(format!("cannot read header row: {}", e)))?
+        .clone();
+    if headers.iter().all(|header| header.is_empty()) {
+        return Err(file_error("the file has no header row"));
+    }
+    let columns = resolve_columns(source, entity, &headers, overrides).map_err(file_error)?;
+
+    Ok((reader, headers, columns))
+}
+
+/// Which header each field of an export will be read from.
+///
+/// Shown in previews, so users can see which fields need an override.
+///
+/// # Errors
+///
+/// Returns an error if the file has no header row or the columns can't be
+/// resolved.
+pub fn detect_columns(
+    data: &[u8],
+    source: ImportSource,
+    entity: ImportEntity,
+    overrides: &HashMap<String, String>,
+) -> Result<BTreeMap<String, String>, ImportRowError> {
+    let (_, headers, columns) = open(data, source, entity, overrides)?;
+
+    Ok(columns
+        .into_iter()
+        .map(|(field, index)| (field.to_string(), headers[index].to_string()))
+        .collect())
+}
+
+/// Reads every data row of an export with `parse`.
+///
+/// # Errors
+///
+/// Returns an error if the file has no header row, the columns can't be
+/// resolved, or it has more than [`MAX_IMPORT_ROWS`] rows.
+fn read_rows<T>(
+    data: &[u8],
+    source: ImportSource,
+    entity: ImportEntity,
+    overrides: &HashMap<String, String>,
+    mut parse: impl FnMut(&Row<'_>) -> Result<T, Vec<ImportRowError>>,
+) -> Result<ParsedRows<T>, ImportRowError> {
+    let (mut reader, _, columns) = open(data, source, entity, overrides)?;
+
+    let mut parsed = ParsedRows::default();
+    let mut count = 0;
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|position| position.line());
+                parsed.errors.push(ImportRowError { line, field: None, error: e.to_string() });
+                continue;
+            }
+        };
+        if record.iter().all(|value| value.is_empty()) {
+            continue;
+        }
+        count += 1;
+        if count > MAX_IMPORT_ROWS {
+            return Err(file_error(format!("files may have at most {} rows", MAX_IMPORT_ROWS)));
+        }
+
+        let row = Row {
+            line: record.position().map_or(0, |position| position.line()),
+            record: &record,
+            columns: &columns,
+        };
+        match parse(&row) {
+            Ok(value) => parsed.rows.push(value),
+            Err(errors) => parsed.errors.extend(errors),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Maps the invoice statuses of other tools onto GigPilot's.
+///
+/// Unknown statuses are passed through and rejected by the invoice import.
+pub fn normalize_status(status: &str) -> String {
+    let status = status.trim().to_lowercase();
+    let normalized = match status.as_str() {
+        "unsent" | "saved" | "draft" => "draft",
+        "sent" | "viewed" | "partial" | "partially paid" | "disputed" | "pending" => "sent",
+        "paid" | "auto-paid" | "auto paid" => "paid",
+        "overdue" | "late" => "overdue",
+        "void" | "voided" | "cancelled" | "canceled" => "cancelled",
+        _ => return status,
+    };
+    normalized.to_string()
+}
+
+/// Drops currency symbols and codes from an exported amount.
+///
+/// `"$1,250.00"` and `"1,250.00 USD"` both become `"1,250.00"`.
+pub fn clean_amount(amount: &str) -> String {
+    amount
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-'))
+        .collect()
+}
+
+/// Reads an invoice export.
+///
+/// Rows are translated into the CSV invoice import's columns (statuses
+/// mapped, amounts cleaned) and validated by it, so they get the same
+/// checks and idempotency keys.
+///
+/// # Arguments
+///
+/// * `source` - Tool the file was exported from
+/// * `data` - The file contents
+/// * `overrides` - Header for any field the layout gets wrong
+/// * `date_format` - `chrono` format of the date columns
+/// * `locale` - How numbers and dates are written
+/// * `default_currency` - Currency of rows without a currency column
+///
+/// # Errors
+///
+/// Returns an error if the file as a whole can't be read.
+pub fn parse_invoices(
+    source: ImportSource,
+    data: &[u8],
+    overrides: &HashMap<String, String>,
+    date_format: Option<&str>,
+    locale: Option<&Locale>,
+    default_currency: Currency,
+) -> Result<ParsedImport, ImportRowError> {
+    let headers = StringRecord::from(ImportField::ALL.iter().map(|field| field.as_str()).collect::<Vec<_>>());
+
+    let translated = read_rows(data, source, ImportEntity::Invoices, overrides, |row| {
+        let mut record: StringRecord = ImportField::ALL
+            .iter()
+            .map(|field| {
+                let value = row.get(field.as_str()).unwrap_or_default();
+                match field {
+                    ImportField::Status => normalize_status(value),
+                    ImportField::Total | ImportField::AmountPaid => clean_amount(value),
+                    _ => value.to_string(),
+                }
+            })
+            .collect();
+        record.set_position(row.record.position().cloned());
+        Ok(record)
+    })?;
+
+    let options = ImportOptions {
+        date_format: date_format.map(str::to_string),
+        locale: locale.cloned(),
+        ..ImportOptions::default()
+    };
+    let mut parsed = parse_records(&headers, translated.rows.into_iter().map(Ok), &options, default_currency)?;
+    parsed.errors.extend(translated.errors);
+    parsed.errors.sort_by_key(|error| error.line);
+
+    Ok(parsed)
+}
+
+/// A client read from an export.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClientRow {
+    /// Line of the row in the file (the header is line 1)
+    pub line: u64,
+
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+
+    /// Street, locality and country lines
+    pub address: Option<String>,
+
+    pub tax_id: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// Whether a value looks like an email address.
+fn is_email(email: &str) -> bool {
+    email
+        .split_once('@')
+        .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'))
+}
+
+/// Joins the non-empty parts with a separator.
+fn join(parts: &[Option<&str>], separator: &str) -> Option<String> {
+    let parts: Vec<&str> = parts.iter().flatten().copied().collect();
+    (!parts.is_empty()).then(|| parts.join(separator))
+}
+
+/// Reads a client export.
+///
+/// Clients without an organisation name are named after their contact.
+/// Address parts are combined into one multi-line address.
+///
+/// # Errors
+///
+/// Returns an error if the file as a whole can't be read.
+pub fn parse_clients(
+    source: ImportSource,
+    data: &[u8],
+    overrides: &HashMap<String, String>,
+) -> Result<ParsedRows<ClientRow>, ImportRowError> {
+    read_rows(data, source, ImportEntity::Clients, overrides, |row| {
+        let mut errors = Vec::new();
+
+        let contact = join(&[row.get("first_name"), row.get("last_name")], " ");
+        let name = row.get("name").map(str::to_string).or(contact);
+        if name.is_none() {
+            errors.push(row.error("name", "is required"));
+        }
+
+        let email = row.get("email").map(str::to_string);
+        if let Some(email) = email.as_deref().filter(|email| !is_email(email)) {
+            errors.push(row.error("email", format!("not a valid email address: {}", email)));
+        }
+
+        let locality = join(&[row.get("city"), row.get("region")], ", ");
+        let locality = join(&[locality.as_deref(), row.get("postal_code")], " ");
+        let address = join(
+            &[row.get("address_line1"), row.get("address_line2"), locality.as_deref(), row.get("country")],
+            "\n",
+        );
+
+        match name {
+            Some(name) if errors.is_empty() => Ok(ClientRow {
+                line: row.line,
+                name,
+                email,
+                phone: row.get("phone").map(str::to_string),
+                address,
+                tax_id: row.get("tax_id").map(str::to_string),
+                notes: row.get("notes").map(str::to_string),
+            }),
+            _ => Err(errors),
+        }
+    })
+}
+
+/// A payment read from an export.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PaymentRow {
+    /// Line of the row in the file (the header is line 1)
+    pub line: u64,
+
+    /// Number of the paid invoice
+    pub invoice_number: String,
+
+    pub paid_on: NaiveDate,
+
+    /// Amount in the invoice currency
+    pub amount: Decimal,
+
+    /// Payment method code (e.g. "bank_transfer")
+    pub method: Option<String>,
+
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// Turns an exported payment method ("Bank Transfer") into a method code.
+pub fn method_code(method: &str) -> String {
+    let code: String = method
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let code: Vec<&str> = code.split('_').filter(|part| !part.is_empty()).collect();
+    code.join("_").chars().take(50).collect()
+}
+
+/// Reads a payment export.
+///
+/// Payments are matched to invoices by number when they are imported.
+/// Dates and amounts are read like those of invoices.
+///
+/// # Errors
+///
+/// Returns an error if the file as a whole can't be read.
+pub fn parse_payments(
+    source: ImportSource,
+    data: &[u8],
+    overrides: &HashMap<String, String>,
+    date_format: Option<&str>,
+    locale: Option<&Locale>,
+) -> Result<ParsedRows<PaymentRow>, ImportRowError> {
+    read_rows(data, source, ImportEntity::Payments, overrides, |row| {
+        let mut errors = Vec::new();
+
+        let invoice_number = row.get("invoice_number");
+        if invoice_number.is_none() {
+            errors.push(row.error("invoice_number", "is required"));
+        }
+
+        let paid_on = match row.get("paid_on") {
+            Some(value) => parse_import_date(value, date_format, locale)
+                .map_err(|message| errors.push(row.error("paid_on", message)))
+                .ok(),
+            None => {
+                errors.push(row.error("paid_on", "is required"));
+                None
+            }
+        };
+
+        let amount = match row.get("amount") {
+            Some(value) => match parse_decimal(&clean_amount(value), locale) {
+                Ok(amount) if amount > Decimal::ZERO => Some(amount),
+                Ok(_) => {
+                    errors.push(row.error("amount", "must be positive"));
+                    None
+                }
+                Err(message) => {
+                    errors.push(row.error("amount", message));
+                    None
+                }
+            },
+            None => {
+                errors.push(row.error("amount", "is required"));
+                None
+            }
+        };
+
+        match (invoice_number, paid_on, amount) {
+            (Some(invoice_number), Some(paid_on), Some(amount)) if errors.is_empty() => Ok(PaymentRow {
+                line: row.line,
+                invoice_number: invoice_number.to_string(),
+                paid_on,
+                amount,
+                method: row.get("method").map(method_code).filter(|code| !code.is_empty()),
+                reference: row.get("reference").map(str::to_string),
+                notes: row.get("notes").map(str::to_string),
+            }),
+            _ => Err(errors),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::invoice::InvoiceStatus;
+
+    fn usd() -> Currency {
+        Currency::parse("USD").unwrap()
+    }
+
+    #[test]
+    fn test_freshbooks_clients() {
+        let csv = "Organization,First Name,Last Name,Email,Street,City,Province/State,Postal Code,Country\n\
+                   Acme Ltd,Ann,Lee,ann@acme.test,1 Main St,Springfield,IL,62701,United States\n\
+                   ,Bob,Stone,bob@example.test,,,,,\n\
+                   ,,,not-an-email,,,,,\n";
+
+        let parsed = parse_clients(ImportSource::FreshBooks, csv.as_bytes(), &HashMap::new()).unwrap();
+
+        assert_eq!(parsed.rows.len(), 2);
+        assert_eq!(parsed.rows[0].name, "Acme Ltd");
+        assert_eq!(
+            parsed.rows[0].address.as_deref(),
+            Some("1 Main St\nSpringfield, IL 62701\nUnited States")
+        );
+        assert_eq!(parsed.rows[1].name, "Bob Stone");
+        assert_eq!(parsed.rows[1].address, None);
+
+        let fields: Vec<Option<&str>> = parsed.errors.iter().map(|e| e.field.as_deref()).collect();
+        assert_eq!(fields, vec![Some("name"), Some("email")]);
+        assert_eq!(parsed.errors[0].line, Some(4));
+    }
+
+    #[test]
+    fn test_wave_invoices_are_translated() {
+        let csv = "Invoice Number,Customer,Invoice Date,Due Date,Invoice Total,Amount Paid,Currency,Status\n\
+                   1001,Acme Ltd,2024-03-01,2024-03-31,\"$1,250.00\",$250.00,USD,Partial\n\
+                   1002,Beta,2024-03-02,,99.90,,EUR,Unsent\n\
+                   1003,Gamma,2024-03-03,,10,,USD,Archived\n";
+
+        let parsed = parse_invoices(ImportSource::Wave, csv.as_bytes(), &HashMap::new(), None, None, usd()).unwrap();
+
+        assert_eq!(parsed.rows.len(), 2);
+        let acme = &parsed.rows[0];
+        assert_eq!(acme.invoice_number.as_deref(), Some("1001"));
+        assert_eq!(acme.status, InvoiceStatus::Sent);
+        assert_eq!(acme.total.amount, Decimal::new(125000, 2));
+        assert_eq!(acme.amount_paid, Decimal::new(25000, 2));
+        assert_eq!(parsed.rows[1].status, InvoiceStatus::Draft);
+
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line, Some(4));
+        assert_eq!(parsed.errors[0].field.as_deref(), Some("status"));
+    }
+
+    #[test]
+    fn test_overrides_and_missing_columns() {
+        let csv = "Invoice #,Client,Grand Total\nINV-1,Acme,10\n";
+        let missing = parse_invoices(ImportSource::FreshBooks, csv.as_bytes(), &HashMap::new(), None, None, usd())
+            .unwrap_err();
+        assert_eq!(missing.error, "missing column for total");
+
+        let overrides = HashMap::from([("total".to_string(), "Grand Total".to_string())]);
+        let parsed = parse_invoices(ImportSource::FreshBooks, csv.as_bytes(), &overrides, None, None, usd()).unwrap();
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.rows[0].client_name, "Acme");
+
+        let unknown = HashMap::from([("amount_due".to_string(), "Grand Total".to_string())]);
+        let error = parse_invoices(ImportSource::FreshBooks, csv.as_bytes(), &unknown, None, None, usd()).unwrap_err();
+        assert_eq!(error.error, "unknown invoices field in mapping: amount_due");
+    }
+
+    #[test]
+    fn test_payments() {
+        let csv = "Date,Invoice #,Amount,Payment Type,Notes\n\
+                   03/15/2024,INV-1,\"$1,000.00\",Bank Transfer,March\n\
+                   03/16/2024,INV-2,0,Cash,\n\
+                   2024-03-17,,5,Cash,\n";
+
+        let parsed = parse_payments(ImportSource::FreshBooks, csv.as_bytes(), &HashMap::new(), Some("%m/%d/%Y"), None).unwrap();
+
+        assert_eq!(parsed.rows.len(), 1);
+        let payment = &parsed.rows[0];
+        assert_eq!(payment.paid_on, NaiveDate::from_ymd_opt(2024, 3, 15).unwrap());
+        assert_eq!(payment.amount, Decimal::new(100000, 2));
+        assert_eq!(payment.method.as_deref(), Some("bank_transfer"));
+
+        let found: Vec<(Option<u64>, Option<&str>)> = parsed
+            .errors
+            .iter()
+            .map(|e| (e.line, e.field.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(Some(3), Some("amount")), (Some(4), Some("invoice_number")), (Some(4), Some("paid_on"))]
+        );
+    }
+
+    #[test]
+    fn test_detect_columns() {
+        let csv = "Customer,Invoice Total,Notes\n";
+        let columns = detect_columns(csv.as_bytes(), ImportSource::Wave, ImportEntity::Invoices, &HashMap::new()).unwrap();
+
+        assert_eq!(columns.get("client_name").map(String::as_str), Some("Customer"));
+        assert_eq!(columns.get("total").map(String::as_str), Some("Invoice Total"));
+        assert!(!columns.contains_key("invoice_number"));
+    }
+
+    #[test]
+    fn test_normalize_status() {
+        assert_eq!(normalize_status("Viewed"), "sent");
+        assert_eq!(normalize_status(" auto-paid "), "paid");
+        assert_eq!(normalize_status("Void"), "cancelled");
+        assert_eq!(normalize_status("Archived"), "archived");
+        assert_eq!(normalize_status(""), "");
+    }
+}
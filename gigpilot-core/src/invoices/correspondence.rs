@@ -0,0 +1,309 @@
+use serde_json::json;
+use sqlx::PgPool;
+use std::io::{Cursor, Write};
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::clients::interactions::record_chase_email;
+use crate::models::correspondence::{Correspondence, CorrespondenceKind, CreateCorrespondence, EmailEvent};
+use crate::models::invoice::Invoice;
+
+/// Records a correspondence entry for an invoice.
+///
+/// Called by the chase executor for every generated email, and by provider
+/// callbacks for delivery receipts and client replies. Generated emails are
+/// also added to the timeline of the invoice's client.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the invoice owner
+/// * `entry` - The message to record
+///
+/// # Returns
+///
+/// Returns the stored `Correspondence` entry, or an error.
+pub async fn record_correspondence(
+    pool: &PgPool,
+    user_id: Uuid,
+    entry: CreateCorrespondence,
+) -> Result<Correspondence, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let stored = sqlx::query_as::<_, Correspondence>(
+        r#"
+        INSERT INTO correspondence (
+            user_id, invoice_id, kind, sender, recipient, subject,
+            body_text, body_html, provider_message_id, delivery_status, metadata, occurred_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, COALESCE($12, NOW()))
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(entry.invoice_id)
+    .bind(entry.kind)
+    .bind(entry.sender)
+    .bind(entry.recipient)
+    .bind(entry.subject)
+    .bind(entry.body_text)
+    .bind(entry.body_html)
+    .bind(entry.provider_message_id)
+    .bind(entry.delivery_status)
+    .bind(entry.metadata)
+    .bind(entry.occurred_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if stored.kind == CorrespondenceKind::Email {
+        record_chase_email(&mut *tx, user_id, &stored).await?;
+    }
+    tx.commit().await?;
+
+    Ok(stored)
+}
+
+/// Secret the email provider sends to the webhook, read from
+/// `EMAIL_WEBHOOK_SECRET`. The webhook is disabled while it is unset.
+pub fn email_webhook_secret() -> Option<String> {
+    std::env::var("EMAIL_WEBHOOK_SECRET").ok().filter(|s| !s.is_empty())
+}
+
+/// Longest subject or body kept from a provider event, in bytes.
+pub const MAX_EVENT_BODY_BYTES: usize = 256 * 1024;
+
+/// Checks an event posted by the email provider.
+///
+/// # Errors
+///
+/// Returns a user-facing message if the event is not a delivery receipt or
+/// reply, a receipt has no `delivery_status`, a reply has no body, or a
+/// body is too large.
+pub fn validate_email_event(event: &EmailEvent) -> Result<(), String> {
+    match event.kind {
+        CorrespondenceKind::Email => return Err("kind must be delivery_receipt or reply".to_string()),
+        CorrespondenceKind::DeliveryReceipt => match event.delivery_status.as_deref() {
+            Some(status) if !status.trim().is_empty() => {}
+            _ => return Err("delivery receipts need a delivery_status".to_string()),
+        },
+        CorrespondenceKind::Reply => {
+            if event.body_text.is_none() && event.body_html.is_none() {
+                return Err("replies need a body_text or body_html".to_string());
+            }
+        }
+    }
+
+    let too_large = [&event.subject, &event.body_text, &event.body_html]
+        .into_iter()
+        .flatten()
+        .any(|text| text.len() > MAX_EVENT_BODY_BYTES);
+    if too_large {
+        return Err(format!("subject and bodies must be at most {} bytes", MAX_EVENT_BODY_BYTES));
+    }
+
+    Ok(())
+}
+
+/// Lists every correspondence entry for an invoice, oldest first.
+pub async fn list_correspondence(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+) -> Result<Vec<Correspondence>, anyhow::Error> {
+    let entries = sqlx::query_as::<_, Correspondence>(
+        r#"
+        SELECT *
+        FROM correspondence
+        WHERE user_id = $1 AND invoice_id = $2
+        ORDER BY occurred_at ASC, created_at ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Folder-name label for an entry kind.
+fn kind_label(kind: CorrespondenceKind) -> &'static str {
+    match kind {
+        CorrespondenceKind::Email => "email",
+        CorrespondenceKind::DeliveryReceipt => "delivery-receipt",
+        CorrespondenceKind::Reply => "reply",
+    }
+}
+
+/// Bundles an invoice's correspondence into a ZIP archive.
+///
+/// Each entry gets its own numbered folder containing `message.txt`,
+/// `message.html` (when available) and `details.json` with headers and
+/// provider data. A top-level `manifest.json` lists the entries in order.
+///
+/// # Arguments
+///
+/// * `invoice` - The invoice the correspondence belongs to
+/// * `entries` - Correspondence entries, oldest first
+///
+/// # Returns
+///
+/// Returns the ZIP archive as bytes.
+pub fn export_correspondence_zip(
+    invoice: &Invoice,
+    entries: &[Correspondence],
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut manifest = Vec::with_capacity(entries.len());
+
+    for (index, entry) in entries.iter().enumerate() {
+        let folder = format!(
+            "{:03}-{}-{}",
+            index + 1,
+            entry.occurred_at.format("%Y-%m-%d"),
+            kind_label(entry.kind)
+        );
+
+        if let Some(body_text) = &entry.body_text {
+            zip.start_file(format!("{}/message.txt", folder), options)?;
+            if let Some(subject) = &entry.subject {
+                writeln!(zip, "Subject: {}\n", subject)?;
+            }
+            zip.write_all(body_text.as_bytes())?;
+        }
+
+        if let Some(body_html) = &entry.body_html {
+            zip.start_file(format!("{}/message.html", folder), options)?;
+            zip.write_all(body_html.as_bytes())?;
+        }
+
+        let details = json!({
+            "id": entry.id,
+            "kind": entry.kind,
+            "occurred_at": entry.occurred_at,
+            "sender": entry.sender,
+            "recipient": entry.recipient,
+            "subject": entry.subject,
+            "provider_message_id": entry.provider_message_id,
+            "delivery_status": entry.delivery_status,
+            "metadata": entry.metadata,
+        });
+        zip.start_file(format!("{}/details.json", folder), options)?;
+        zip.write_all(serde_json::to_string_pretty(&details)?.as_bytes())?;
+
+        manifest.push(json!({ "folder": folder, "details": details }));
+    }
+
+    zip.start_file("manifest.json", options)?;
+    let manifest = json!({
+        "invoice_id": invoice.id,
+        "invoice_number": invoice.invoice_number,
+        "client_name": invoice.client_name,
+        "exported_at": chrono::Utc::now(),
+        "entries": manifest,
+    });
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use rust_decimal::Decimal;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    use crate::repo::memory::sample_invoice;
+
+    fn entry(invoice: &Invoice, kind: CorrespondenceKind, day: u32) -> Correspondence {
+        let occurred_at = Utc.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap();
+        Correspondence {
+            id: Uuid::new_v4(),
+            user_id: invoice.user_id,
+            invoice_id: invoice.id,
+            kind,
+            sender: Some("billing@example.com".to_string()),
+            recipient: Some("client@example.com".to_string()),
+            subject: Some("Invoice reminder".to_string()),
+            body_text: (kind != CorrespondenceKind::DeliveryReceipt).then(|| "Please pay".to_string()),
+            body_html: (kind == CorrespondenceKind::Email).then(|| "<p>Please pay</p>".to_string()),
+            provider_message_id: Some("msg-1".to_string()),
+            delivery_status: (kind == CorrespondenceKind::DeliveryReceipt).then(|| "delivered".to_string()),
+            metadata: None,
+            occurred_at,
+            created_at: occurred_at,
+        }
+    }
+
+    fn read(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut contents = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_zip_has_a_folder_per_entry_and_a_manifest() {
+        let due = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let invoice = sample_invoice(Uuid::new_v4(), due, Decimal::from(100));
+        let entries = vec![
+            entry(&invoice, CorrespondenceKind::Email, 2),
+            entry(&invoice, CorrespondenceKind::DeliveryReceipt, 2),
+            entry(&invoice, CorrespondenceKind::Reply, 4),
+        ];
+
+        let bytes = export_correspondence_zip(&invoice, &entries).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "001-2024-03-02-email/details.json",
+                "001-2024-03-02-email/message.html",
+                "001-2024-03-02-email/message.txt",
+                "002-2024-03-02-delivery-receipt/details.json",
+                "003-2024-03-04-reply/details.json",
+                "003-2024-03-04-reply/message.txt",
+                "manifest.json",
+            ]
+        );
+
+        assert_eq!(
+            read(&mut archive, "001-2024-03-02-email/message.txt"),
+            "Subject: Invoice reminder\n\nPlease pay"
+        );
+        let receipt: serde_json::Value =
+            serde_json::from_str(&read(&mut archive, "002-2024-03-02-delivery-receipt/details.json")).unwrap();
+        assert_eq!(receipt["delivery_status"], "delivered");
+
+        let manifest: serde_json::Value = serde_json::from_str(&read(&mut archive, "manifest.json")).unwrap();
+        assert_eq!(manifest["invoice_number"], invoice.invoice_number);
+        assert_eq!(manifest["entries"].as_array().unwrap().len(), 3);
+        assert_eq!(manifest["entries"][2]["folder"], "003-2024-03-04-reply");
+    }
+
+    #[test]
+    fn test_provider_events_must_be_receipts_or_replies() {
+        let mut event: EmailEvent = serde_json::from_value(json!({
+            "invoice_id": Uuid::new_v4(),
+            "kind": "delivery_receipt",
+        }))
+        .unwrap();
+        assert!(validate_email_event(&event).is_err());
+        event.delivery_status = Some("delivered".to_string());
+        assert!(validate_email_event(&event).is_ok());
+
+        event.kind = CorrespondenceKind::Reply;
+        assert!(validate_email_event(&event).is_err());
+        event.body_text = Some("Paid yesterday".to_string());
+        assert!(validate_email_event(&event).is_ok());
+
+        event.kind = CorrespondenceKind::Email;
+        assert!(validate_email_event(&event).is_err());
+    }
+}
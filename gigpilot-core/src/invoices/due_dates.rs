@@ -0,0 +1,218 @@
+//! Due-date rules for new invoices.
+//!
+//! A user's settings define the default payment terms, a minimum number of
+//! days between issue and due date, and whether due dates are rolled
+//! forward past weekends and public holidays. A client's own payment terms
+//! replace the user's default. The rules fill in the due date of invoices
+//! created by the server and check the due dates devices push.
+
+use chrono::{Duration, NaiveDate};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::business_days::{load_calendar, HolidayCalendar};
+use crate::models::user_settings::UserSettings;
+use crate::settings::load_user_settings;
+
+/// A user's due-date rules.
+#[derive(Debug, Clone)]
+pub struct DueDateRules {
+    /// Days between issue and due date when the client has no terms
+    pub payment_terms_days: i32,
+
+    /// Fewest days allowed between issue and due date
+    pub min_payment_terms_days: i32,
+
+    /// Whether due dates must fall on a business day
+    pub roll_forward: bool,
+
+    /// Calendar deciding which days are business days
+    calendar: HolidayCalendar,
+}
+
+impl DueDateRules {
+    /// Builds the rules from a user's settings and holiday calendar.
+    pub fn new(settings: &UserSettings, calendar: HolidayCalendar) -> Self {
+        Self {
+            payment_terms_days: settings.payment_terms_days,
+            min_payment_terms_days: settings.min_payment_terms_days,
+            roll_forward: settings.roll_due_dates_forward,
+            calendar,
+        }
+    }
+
+    /// Loads a user's rules with the calendar of their country.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - PostgreSQL connection pool
+    /// * `user_id` - ID of the user
+    ///
+    /// # Returns
+    ///
+    /// Returns the user's `DueDateRules`, or an error if a query fails.
+    pub async fn load(pool: &PgPool, user_id: Uuid) -> Result<Self, anyhow::Error> {
+        let settings = load_user_settings(pool, user_id).await?;
+        Self::for_settings(pool, &settings).await
+    }
+
+    /// Loads the calendar for already loaded settings and builds the rules.
+    pub async fn for_settings(pool: &PgPool, settings: &UserSettings) -> Result<Self, anyhow::Error> {
+        let calendar = load_calendar(pool, settings.country_code.as_deref()).await?;
+        Ok(Self::new(settings, calendar))
+    }
+
+    /// The due date of an invoice issued on `issue_date`.
+    ///
+    /// Uses the client's payment terms when it has any, and the user's
+    /// default otherwise, but never fewer days than the minimum. With
+    /// rolling enabled, a due date on a weekend or holiday moves to the
+    /// next business day.
+    pub fn default_due_date(&self, issue_date: NaiveDate, client_terms_days: Option<i32>) -> NaiveDate {
+        let days = client_terms_days
+            .unwrap_or(self.payment_terms_days)
+            .max(self.min_payment_terms_days);
+        self.roll(issue_date + Duration::days(days as i64))
+    }
+
+    /// The earliest due date allowed for an invoice issued on `issue_date`.
+    pub fn earliest_due_date(&self, issue_date: NaiveDate) -> NaiveDate {
+        self.roll(issue_date + Duration::days(self.min_payment_terms_days as i64))
+    }
+
+    /// Checks a due date chosen by the user or a device.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DueDateError` if the due date is earlier than the minimum
+    /// allows, or falls on a weekend or holiday while rolling is enabled.
+    pub fn check(&self, issue_date: NaiveDate, due_date: NaiveDate) -> Result<(), DueDateError> {
+        let earliest = self.earliest_due_date(issue_date);
+        if due_date < issue_date + Duration::days(self.min_payment_terms_days as i64) {
+            return Err(DueDateError::TooEarly { issue_date, due_date, earliest });
+        }
+        if self.roll_forward && !self.calendar.is_business_day(due_date) {
+            return Err(DueDateError::NotBusinessDay {
+                due_date,
+                next_business_day: self.calendar.roll_forward(due_date),
+            });
+        }
+        Ok(())
+    }
+
+    /// Rolls a date forward to a business day when rolling is enabled.
+    fn roll(&self, date: NaiveDate) -> NaiveDate {
+        if self.roll_forward {
+            self.calendar.roll_forward(date)
+        } else {
+            date
+        }
+    }
+}
+
+/// A due date that breaks the user's rules, reported to sync clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DueDateError {
+    /// The due date is closer to the issue date than the minimum allows
+    TooEarly {
+        issue_date: NaiveDate,
+        due_date: NaiveDate,
+        earliest: NaiveDate,
+    },
+
+    /// The due date is a weekend or public holiday
+    NotBusinessDay {
+        due_date: NaiveDate,
+        next_business_day: NaiveDate,
+    },
+}
+
+impl DueDateError {
+    /// Machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DueDateError::TooEarly { .. } => "due_date_too_early",
+            DueDateError::NotBusinessDay { .. } => "due_date_not_business_day",
+        }
+    }
+
+    /// The dates involved, including the date the client could use instead.
+    pub fn details(&self) -> Value {
+        match self {
+            DueDateError::TooEarly { issue_date, due_date, earliest } => json!({
+                "issue_date": issue_date,
+                "due_date": due_date,
+                "earliest": earliest,
+            }),
+            DueDateError::NotBusinessDay { due_date, next_business_day } => json!({
+                "due_date": due_date,
+                "next_business_day": next_business_day,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for DueDateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DueDateError::TooEarly { due_date, earliest, .. } => {
+                write!(f, "due date {} is too early; the earliest allowed is {}", due_date, earliest)
+            }
+            DueDateError::NotBusinessDay { due_date, .. } => {
+                write!(f, "due date {} is not a business day", due_date)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DueDateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+
+    fn rules(terms: i32, min: i32, roll_forward: bool) -> DueDateRules {
+        DueDateRules {
+            payment_terms_days: terms,
+            min_payment_terms_days: min,
+            roll_forward,
+            calendar: HolidayCalendar::new(Some("GB")),
+        }
+    }
+
+    #[test]
+    fn test_default_due_date_uses_client_terms_and_minimum() {
+        let issued = date(2024, 6, 3);
+        assert_eq!(rules(14, 0, false).default_due_date(issued, None), date(2024, 6, 17));
+        assert_eq!(rules(14, 0, false).default_due_date(issued, Some(30)), date(2024, 7, 3));
+        // Client terms below the minimum are raised to it
+        assert_eq!(rules(14, 7, false).default_due_date(issued, Some(0)), date(2024, 6, 10));
+    }
+
+    #[test]
+    fn test_default_due_date_rolls_past_weekends_and_holidays() {
+        // 2024-03-15 + 14 = Good Friday; Easter Monday follows the weekend
+        let issued = date(2024, 3, 15);
+        assert_eq!(rules(14, 0, false).default_due_date(issued, None), date(2024, 3, 29));
+        assert_eq!(rules(14, 0, true).default_due_date(issued, None), date(2024, 4, 2));
+    }
+
+    #[test]
+    fn test_check() {
+        let issued = date(2024, 6, 3);
+        assert!(rules(14, 7, false).check(issued, date(2024, 6, 10)).is_ok());
+
+        let early = rules(14, 7, false).check(issued, date(2024, 5, 1)).unwrap_err();
+        assert_eq!(early.code(), "due_date_too_early");
+        assert_eq!(early.details()["earliest"], "2024-06-10");
+
+        // Saturday is fine unless due dates must be business days
+        assert!(rules(14, 0, false).check(issued, date(2024, 6, 8)).is_ok());
+        let weekend = rules(14, 0, true).check(issued, date(2024, 6, 8)).unwrap_err();
+        assert_eq!(weekend.code(), "due_date_not_business_day");
+        assert_eq!(weekend.details()["next_business_day"], "2024-06-10");
+    }
+}
@@ -0,0 +1,199 @@
+//! Duplicating invoices.
+//!
+//! For repeat work that isn't worth a recurring setup, an invoice can be
+//! copied into a new draft: same client, project, currency, description and
+//! line items, with the next invoice number, issued today and due per the
+//! user's due-date rules. Payments, chase state and per-invoice overrides
+//! stay with the original, and a late fee charged on it isn't copied.
+
+use chrono::Utc;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::invoices::due_dates::DueDateRules;
+use crate::invoices::find_invoice;
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::late_fees::{charged_late_fee, has_late_fee, LATE_FEE_DESCRIPTION};
+use crate::invoices::numbering::next_invoice_number;
+use crate::models::client::Client;
+use crate::models::invoice::Invoice;
+use crate::models::line_item::{InvoiceTotals, LineItem, StoredLineItems};
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+
+/// Line items and totals of a copy of the invoice.
+///
+/// A late fee charged on the invoice is left out: its "Late fee" line, or
+/// the surcharge on invoices without line items.
+pub fn duplicate_contents(invoice: &Invoice) -> (Vec<LineItem>, InvoiceTotals) {
+    let mut items = invoice.items().to_vec();
+    if !items.is_empty() {
+        if has_late_fee(invoice) {
+            if let Some(index) = items.iter().rposition(|item| item.description == LATE_FEE_DESCRIPTION) {
+                items.remove(index);
+            }
+        }
+        let totals = InvoiceTotals::compute(&items);
+        return (items, totals);
+    }
+
+    let surcharge = charged_late_fee(invoice).unwrap_or_default();
+    let totals = InvoiceTotals {
+        subtotal: invoice.subtotal - surcharge,
+        tax_total: invoice.tax_total,
+        total: invoice.total - surcharge,
+    };
+    (items, totals)
+}
+
+/// Copies an invoice into a new draft with the next invoice number.
+///
+/// A copy of an invoice linked to a client is addressed with the client's
+/// current name, email and payment terms; if the client has since been
+/// deleted, the copy keeps the original's name and email, unlinked. The new
+/// invoice is recorded as a server sync change.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice to copy
+///
+/// # Returns
+///
+/// Returns the new draft `Invoice`, or `None` if the original does not
+/// exist.
+pub async fn duplicate_invoice(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let Some(source) = find_invoice(pool, user_id, invoice_id).await? else {
+        return Ok(None);
+    };
+    let due_date_rules = DueDateRules::load(pool, user_id).await?;
+
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    // The copy is addressed to the linked client as it is now (name, email
+    // and payment terms), not as it was when the original was issued
+    let client = match source.client_id {
+        Some(client_id) => sqlx::query_as::<_, Client>(
+            "SELECT * FROM clients WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+        )
+        .bind(client_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?,
+        None => None,
+    };
+    let (client_name, client_email) = match &client {
+        Some(client) => (&client.name, &client.email),
+        None => (&source.client_name, &source.client_email),
+    };
+
+    let (line_items, totals) = duplicate_contents(&source);
+
+    let invoice_number = next_invoice_number(&mut tx, user_id).await?;
+    let today = Utc::now().date_naive();
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        INSERT INTO invoices (
+            user_id, invoice_number, client_name, client_email, client_id, project_id,
+            amount, currency, status, due_date, issue_date,
+            description, line_items, metadata, subtotal, tax_total, total
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'draft', $9, $10, $11, $12, $13, $14, $15, $16)
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_number)
+    .bind(client_name)
+    .bind(client_email)
+    .bind(client.as_ref().map(|client| client.id))
+    .bind(source.project_id)
+    .bind(totals.total)
+    .bind(&source.currency)
+    .bind(due_date_rules.default_due_date(today, client.as_ref().and_then(|client| client.payment_terms_days)))
+    .bind(today)
+    .bind(&source.description)
+    .bind((!line_items.is_empty()).then(|| StoredLineItems(line_items)))
+    .bind(json!({
+        "duplicated_from": source.id,
+        "duplicated_from_number": source.invoice_number,
+    }))
+    .bind(totals.subtotal)
+    .bind(totals.tax_total)
+    .bind(totals.total)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        user_id,
+        "invoices",
+        invoice.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&invoice)?,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(invoice))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::invoices::late_fees::with_late_fee;
+    use crate::repo::memory::sample_invoice;
+
+    fn line(description: &str, unit_price: i64) -> LineItem {
+        LineItem {
+            description: description.to_string(),
+            quantity: Decimal::ONE,
+            unit_price: Decimal::from(unit_price),
+            tax_rate: None,
+            tax_rate_id: None,
+        }
+    }
+
+    #[test]
+    fn test_copy_keeps_line_items_but_not_the_late_fee() {
+        let due = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let mut invoice = sample_invoice(Uuid::new_v4(), due, Decimal::from(150));
+        let items = vec![line("Design", 100), line("Hosting", 50)];
+        invoice.line_items = Some(StoredLineItems(items.clone()));
+
+        let charged = with_late_fee(&invoice, Decimal::from(25), Utc::now());
+        assert_eq!(charged.total, Decimal::from(175));
+
+        let (copied, totals) = duplicate_contents(&charged);
+        assert_eq!(copied, items);
+        assert_eq!(totals.total, Decimal::from(150));
+    }
+
+    #[test]
+    fn test_copy_of_amount_only_invoice_drops_the_surcharge() {
+        let due = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let invoice = sample_invoice(Uuid::new_v4(), due, Decimal::from(200));
+        let charged = with_late_fee(&invoice, Decimal::from(20), Utc::now());
+
+        let (copied, totals) = duplicate_contents(&charged);
+        assert!(copied.is_empty());
+        assert_eq!(totals.subtotal, invoice.subtotal);
+        assert_eq!(totals.total, Decimal::from(200));
+    }
+}
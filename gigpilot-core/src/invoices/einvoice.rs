@@ -0,0 +1,932 @@
+//! Structured e-invoice export (EN 16931).
+//!
+//! An invoice is exported either as UBL 2.1 XML or as Factur-X: the invoice
+//! PDF with the same data embedded as Cross Industry Invoice XML
+//! (`factur-x.xml`, EN 16931 profile). Both carry the EN 16931 core invoice:
+//! seller and buyer, lines, VAT breakdown per category and rate, totals and
+//! the IBAN to pay to.
+//!
+//! The seller comes from the user's branding and settings (`country_code`,
+//! `vat_id`, `address_line`, `city`, `postal_code`); the buyer from the
+//! invoice's client fields and its `metadata.client` object (`country_code`,
+//! `vat_id`, `address_line`, `city`, `postal_code`). Invoices missing data
+//! the standard requires are reported by [`EInvoice::problems`] rather than
+//! exported.
+//!
+//! The Factur-X PDF declares PDF/A-3B and embeds the XML as required, but
+//! uses the standard (non-embedded) PDF fonts, which strict PDF/A validators
+//! flag; receivers read the embedded XML.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use lopdf::{dictionary, Document, Object, Stream};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::invoices::pdf::{pdf_lines, PdfBranding};
+use crate::models::invoice::Invoice;
+use crate::models::user_settings::UserSettings;
+
+/// EN 16931 specification identifier (BT-24).
+pub const EN16931_SPECIFICATION: &str = "urn:cen.eu:en16931:2017";
+
+/// Name of the XML file embedded in Factur-X PDFs.
+pub const FACTUR_X_FILENAME: &str = "factur-x.xml";
+
+/// UNTDID 1001 code of a commercial invoice (BT-3).
+const COMMERCIAL_INVOICE: &str = "380";
+
+/// UN/ECE Rec 20 unit code for "one" (BT-130).
+const UNIT_CODE: &str = "C62";
+
+/// UNTDID 4461 code of a SEPA credit transfer (BT-81).
+const SEPA_CREDIT_TRANSFER: &str = "58";
+
+/// Exemption reason given for invoices outside the scope of VAT (BT-120).
+const NOT_SUBJECT_REASON: &str = "Not subject to VAT";
+
+/// Export format of `GET /api/invoices/:id/export`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EInvoiceFormat {
+    /// UBL 2.1 invoice XML
+    #[default]
+    Ubl,
+
+    /// PDF/A-3 with embedded CII XML
+    #[serde(alias = "factur-x")]
+    Facturx,
+}
+
+/// VAT category of a line (UNTDID 5305).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VatCategory {
+    /// Standard rate (`S`)
+    Standard,
+
+    /// Zero rated (`Z`)
+    ZeroRated,
+
+    /// Not subject to VAT (`O`), for sellers without a VAT identifier
+    NotSubject,
+}
+
+impl VatCategory {
+    /// UNTDID 5305 code.
+    pub fn code(self) -> &'static str {
+        match self {
+            VatCategory::Standard => "S",
+            VatCategory::ZeroRated => "Z",
+            VatCategory::NotSubject => "O",
+        }
+    }
+}
+
+/// Seller or buyer of an e-invoice.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Party {
+    /// Legal name
+    pub name: String,
+
+    /// Email address, used as the electronic address
+    pub email: Option<String>,
+
+    /// VAT identifier, with country prefix
+    pub vat_id: Option<String>,
+
+    /// Street address
+    pub address_line: Option<String>,
+
+    /// City
+    pub city: Option<String>,
+
+    /// Postal code
+    pub postal_code: Option<String>,
+
+    /// ISO 3166-1 alpha-2 country code
+    pub country_code: Option<String>,
+}
+
+impl Party {
+    /// The seller, from the user's branding and settings.
+    pub fn seller(branding: &PdfBranding, settings: &UserSettings) -> Self {
+        Self {
+            name: branding.business_name.clone(),
+            email: branding.business_email.clone(),
+            vat_id: settings.vat_id.clone(),
+            address_line: settings.address_line.clone(),
+            city: settings.city.clone(),
+            postal_code: settings.postal_code.clone(),
+            country_code: settings.country_code.clone(),
+        }
+    }
+
+    /// The buyer, from the invoice's client fields and `metadata.client`.
+    pub fn buyer(invoice: &Invoice) -> Self {
+        let client = invoice.metadata.as_ref().and_then(|metadata| metadata.get("client"));
+        let field = |name: &str| {
+            client
+                .and_then(|client| client.get(name))
+                .and_then(Value::as_str)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Self {
+            name: invoice.client_name.clone(),
+            email: invoice.client_email.clone(),
+            vat_id: field("vat_id").map(|v| v.split_whitespace().collect::<String>().to_uppercase()),
+            address_line: field("address_line"),
+            city: field("city"),
+            postal_code: field("postal_code"),
+            country_code: field("country_code").map(|c| c.to_uppercase()),
+        }
+    }
+}
+
+/// One invoice line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EInvoiceLine {
+    /// Item name
+    pub name: String,
+
+    /// Invoiced quantity
+    pub quantity: Decimal,
+
+    /// Net price per unit
+    pub unit_price: Decimal,
+
+    /// Net line amount
+    pub net: Decimal,
+
+    /// VAT category
+    pub category: VatCategory,
+
+    /// VAT rate in percent
+    pub rate: Decimal,
+}
+
+/// VAT totals of one category and rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VatBreakdown {
+    /// VAT category
+    pub category: VatCategory,
+
+    /// VAT rate in percent
+    pub rate: Decimal,
+
+    /// Sum of the net line amounts
+    pub taxable: Decimal,
+
+    /// VAT due on the taxable amount
+    pub tax: Decimal,
+}
+
+/// An invoice in the EN 16931 model, ready to be serialized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EInvoice {
+    /// Invoice number
+    pub number: String,
+
+    /// Issue date
+    pub issue_date: NaiveDate,
+
+    /// Payment due date (None = due on receipt)
+    pub due_date: Option<NaiveDate>,
+
+    /// ISO 4217 currency code
+    pub currency: String,
+
+    /// Free-text note (the invoice description)
+    pub note: Option<String>,
+
+    /// Seller
+    pub seller: Party,
+
+    /// Buyer
+    pub buyer: Party,
+
+    /// IBAN payments go to, if the seller has one
+    pub iban: Option<String>,
+
+    /// Invoice lines
+    pub lines: Vec<EInvoiceLine>,
+
+    /// VAT breakdown per category and rate
+    pub breakdown: Vec<VatBreakdown>,
+
+    /// Sum of the net line amounts
+    pub line_total: Decimal,
+
+    /// Total VAT
+    pub tax_total: Decimal,
+
+    /// Total with VAT
+    pub grand_total: Decimal,
+
+    /// Amount already paid
+    pub prepaid: Decimal,
+
+    /// Amount due
+    pub payable: Decimal,
+}
+
+impl EInvoice {
+    /// Maps an invoice to the EN 16931 model.
+    ///
+    /// VAT is computed per category and rate from the net line amounts, as
+    /// EN 16931 requires, so it can differ from the sum of per-line
+    /// rounded taxes by a cent.
+    ///
+    /// # Arguments
+    ///
+    /// * `invoice` - The invoice to export
+    /// * `seller` - The seller ([`Party::seller`])
+    /// * `buyer` - The buyer ([`Party::buyer`])
+    /// * `iban` - IBAN payments go to, if any
+    pub fn new(invoice: &Invoice, seller: Party, buyer: Party, iban: Option<String>) -> Self {
+        let items = pdf_lines(invoice);
+
+        // Sellers without a VAT identifier who charge no VAT are outside its scope
+        let not_subject = seller.vat_id.is_none()
+            && items.iter().all(|item| item.tax_rate.unwrap_or_default().value().is_zero());
+
+        let lines: Vec<EInvoiceLine> = items
+            .iter()
+            .map(|item| {
+                let rate = item.tax_rate.unwrap_or_default().value();
+                let category = if not_subject {
+                    VatCategory::NotSubject
+                } else if rate.is_zero() {
+                    VatCategory::ZeroRated
+                } else {
+                    VatCategory::Standard
+                };
+                let name = item.description.trim();
+                EInvoiceLine {
+                    name: if name.is_empty() { format!("Invoice {}", invoice.invoice_number) } else { name.to_string() },
+                    quantity: item.quantity,
+                    unit_price: item.unit_price,
+                    net: item.net(),
+                    category,
+                    rate,
+                }
+            })
+            .collect();
+
+        let mut groups: BTreeMap<(VatCategory, Decimal), Decimal> = BTreeMap::new();
+        for line in &lines {
+            *groups.entry((line.category, line.rate.normalize())).or_default() += line.net;
+        }
+        let breakdown: Vec<VatBreakdown> = groups
+            .into_iter()
+            .map(|((category, rate), taxable)| VatBreakdown {
+                category,
+                rate,
+                taxable,
+                tax: (taxable * rate / Decimal::ONE_HUNDRED)
+                    .round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero),
+            })
+            .collect();
+
+        let line_total: Decimal = lines.iter().map(|line| line.net).sum();
+        let tax_total: Decimal = breakdown.iter().map(|group| group.tax).sum();
+        let grand_total = line_total + tax_total;
+
+        // The buyer's VAT identifier must not appear on invoices outside VAT
+        let mut buyer = buyer;
+        if not_subject {
+            buyer.vat_id = None;
+        }
+
+        Self {
+            number: invoice.invoice_number.clone(),
+            issue_date: invoice.issue_date,
+            due_date: invoice.due_date,
+            currency: invoice.currency.clone(),
+            note: invoice.description.clone().filter(|d| !d.trim().is_empty()),
+            seller,
+            buyer,
+            iban,
+            lines,
+            breakdown,
+            line_total,
+            tax_total,
+            grand_total,
+            prepaid: invoice.amount_paid,
+            payable: grand_total - invoice.amount_paid,
+        }
+    }
+
+    /// Lists the data EN 16931 requires that the invoice lacks.
+    ///
+    /// # Returns
+    ///
+    /// Returns one message per problem; empty if the invoice can be exported.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let valid_country = |code: &Option<String>| {
+            code.as_deref()
+                .map(|c| c.len() == 2 && c.chars().all(|c| c.is_ascii_uppercase()))
+                .unwrap_or(false)
+        };
+
+        if self.seller.name.trim().is_empty() {
+            problems.push("seller name is missing".to_string());
+        }
+        if !valid_country(&self.seller.country_code) {
+            problems.push("seller country is missing: set country_code in settings".to_string());
+        }
+        if self.buyer.name.trim().is_empty() {
+            problems.push("buyer name is missing: set the invoice's client_name".to_string());
+        }
+        if !valid_country(&self.buyer.country_code) {
+            problems.push(
+                "buyer country is missing: set metadata.client.country_code on the invoice".to_string(),
+            );
+        }
+        if self.seller.vat_id.is_none() && self.lines.iter().any(|line| line.category == VatCategory::Standard) {
+            problems.push("seller VAT identifier is required to charge VAT: set vat_id in settings".to_string());
+        }
+        problems
+    }
+}
+
+/// Escapes text for XML content and attribute values.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats an amount with two decimals.
+fn amount(value: Decimal) -> String {
+    format!("{:.2}", value.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero))
+}
+
+/// Minimal indenting XML writer.
+struct XmlWriter {
+    xml: String,
+    depth: usize,
+}
+
+impl XmlWriter {
+    fn new() -> Self {
+        Self {
+            xml: "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string(),
+            depth: 0,
+        }
+    }
+
+    fn start_tag(tag: &str, attributes: &[(&str, &str)]) -> String {
+        let mut start = format!("<{}", tag);
+        for (name, value) in attributes {
+            start.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+        start.push('>');
+        start
+    }
+
+    fn line(&mut self, content: &str) {
+        self.xml.push_str(&"  ".repeat(self.depth));
+        self.xml.push_str(content);
+        self.xml.push('\n');
+    }
+
+    fn open(&mut self, tag: &str, attributes: &[(&str, &str)]) {
+        self.line(&Self::start_tag(tag, attributes));
+        self.depth += 1;
+    }
+
+    fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.line(&format!("</{}>", tag));
+    }
+
+    fn leaf(&mut self, tag: &str, attributes: &[(&str, &str)], value: &str) {
+        let start = Self::start_tag(tag, attributes);
+        self.line(&format!("{}{}</{}>", start, escape(value), tag));
+    }
+
+    fn finish(self) -> String {
+        self.xml
+    }
+}
+
+/// Writes a UBL party (supplier or customer).
+fn ubl_party(xml: &mut XmlWriter, wrapper: &str, party: &Party) {
+    xml.open(wrapper, &[]);
+    xml.open("cac:Party", &[]);
+    if let Some(email) = &party.email {
+        xml.leaf("cbc:EndpointID", &[("schemeID", "EM")], email);
+    }
+    xml.open("cac:PostalAddress", &[]);
+    if let Some(street) = &party.address_line {
+        xml.leaf("cbc:StreetName", &[], street);
+    }
+    if let Some(city) = &party.city {
+        xml.leaf("cbc:CityName", &[], city);
+    }
+    if let Some(postal_code) = &party.postal_code {
+        xml.leaf("cbc:PostalZone", &[], postal_code);
+    }
+    xml.open("cac:Country", &[]);
+    xml.leaf("cbc:IdentificationCode", &[], party.country_code.as_deref().unwrap_or_default());
+    xml.close("cac:Country");
+    xml.close("cac:PostalAddress");
+    if let Some(vat_id) = &party.vat_id {
+        xml.open("cac:PartyTaxScheme", &[]);
+        xml.leaf("cbc:CompanyID", &[], vat_id);
+        xml.open("cac:TaxScheme", &[]);
+        xml.leaf("cbc:ID", &[], "VAT");
+        xml.close("cac:TaxScheme");
+        xml.close("cac:PartyTaxScheme");
+    }
+    xml.open("cac:PartyLegalEntity", &[]);
+    xml.leaf("cbc:RegistrationName", &[], &party.name);
+    xml.close("cac:PartyLegalEntity");
+    xml.close("cac:Party");
+    xml.close(wrapper);
+}
+
+/// Writes a UBL tax category (document level or line level).
+fn ubl_tax_category(xml: &mut XmlWriter, tag: &str, category: VatCategory, rate: Decimal) {
+    xml.open(tag, &[]);
+    xml.leaf("cbc:ID", &[], category.code());
+    if category == VatCategory::NotSubject {
+        if tag == "cac:TaxCategory" {
+            xml.leaf("cbc:TaxExemptionReason", &[], NOT_SUBJECT_REASON);
+        }
+    } else {
+        xml.leaf("cbc:Percent", &[], &rate.normalize().to_string());
+    }
+    xml.open("cac:TaxScheme", &[]);
+    xml.leaf("cbc:ID", &[], "VAT");
+    xml.close("cac:TaxScheme");
+    xml.close(tag);
+}
+
+/// Serializes an invoice as UBL 2.1 invoice XML.
+pub fn to_ubl(invoice: &EInvoice) -> String {
+    let currency = [("currencyID", invoice.currency.as_str())];
+    let mut xml = XmlWriter::new();
+
+    xml.open(
+        "Invoice",
+        &[
+            ("xmlns", "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2"),
+            ("xmlns:cac", "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2"),
+            ("xmlns:cbc", "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2"),
+        ],
+    );
+    xml.leaf("cbc:CustomizationID", &[], EN16931_SPECIFICATION);
+    xml.leaf("cbc:ID", &[], &invoice.number);
+    xml.leaf("cbc:IssueDate", &[], &invoice.issue_date.to_string());
+    if let Some(due_date) = invoice.due_date {
+        xml.leaf("cbc:DueDate", &[], &due_date.to_string());
+    }
+    xml.leaf("cbc:InvoiceTypeCode", &[], COMMERCIAL_INVOICE);
+    if let Some(note) = &invoice.note {
+        xml.leaf("cbc:Note", &[], note);
+    }
+    xml.leaf("cbc:DocumentCurrencyCode", &[], &invoice.currency);
+
+    ubl_party(&mut xml, "cac:AccountingSupplierParty", &invoice.seller);
+    ubl_party(&mut xml, "cac:AccountingCustomerParty", &invoice.buyer);
+
+    if let Some(iban) = &invoice.iban {
+        xml.open("cac:PaymentMeans", &[]);
+        xml.leaf("cbc:PaymentMeansCode", &[], SEPA_CREDIT_TRANSFER);
+        xml.leaf("cbc:PaymentID", &[], &invoice.number);
+        xml.open("cac:PayeeFinancialAccount", &[]);
+        xml.leaf("cbc:ID", &[], iban);
+        xml.close("cac:PayeeFinancialAccount");
+        xml.close("cac:PaymentMeans");
+    }
+    if invoice.due_date.is_none() {
+        xml.open("cac:PaymentTerms", &[]);
+        xml.leaf("cbc:Note", &[], "Due on receipt");
+        xml.close("cac:PaymentTerms");
+    }
+
+    xml.open("cac:TaxTotal", &[]);
+    xml.leaf("cbc:TaxAmount", &currency, &amount(invoice.tax_total));
+    for group in &invoice.breakdown {
+        xml.open("cac:TaxSubtotal", &[]);
+        xml.leaf("cbc:TaxableAmount", &currency, &amount(group.taxable));
+        xml.leaf("cbc:TaxAmount", &currency, &amount(group.tax));
+        ubl_tax_category(&mut xml, "cac:TaxCategory", group.category, group.rate);
+        xml.close("cac:TaxSubtotal");
+    }
+    xml.close("cac:TaxTotal");
+
+    xml.open("cac:LegalMonetaryTotal", &[]);
+    xml.leaf("cbc:LineExtensionAmount", &currency, &amount(invoice.line_total));
+    xml.leaf("cbc:TaxExclusiveAmount", &currency, &amount(invoice.line_total));
+    xml.leaf("cbc:TaxInclusiveAmount", &currency, &amount(invoice.grand_total));
+    if !invoice.prepaid.is_zero() {
+        xml.leaf("cbc:PrepaidAmount", &currency, &amount(invoice.prepaid));
+    }
+    xml.leaf("cbc:PayableAmount", &currency, &amount(invoice.payable));
+    xml.close("cac:LegalMonetaryTotal");
+
+    for (index, line) in invoice.lines.iter().enumerate() {
+        xml.open("cac:InvoiceLine", &[]);
+        xml.leaf("cbc:ID", &[], &(index + 1).to_string());
+        xml.leaf("cbc:InvoicedQuantity", &[("unitCode", UNIT_CODE)], &line.quantity.normalize().to_string());
+        xml.leaf("cbc:LineExtensionAmount", &currency, &amount(line.net));
+        xml.open("cac:Item", &[]);
+        xml.leaf("cbc:Name", &[], &line.name);
+        ubl_tax_category(&mut xml, "cac:ClassifiedTaxCategory", line.category, line.rate);
+        xml.close("cac:Item");
+        xml.open("cac:Price", &[]);
+        xml.leaf("cbc:PriceAmount", &currency, &line.unit_price.normalize().to_string());
+        xml.close("cac:Price");
+        xml.close("cac:InvoiceLine");
+    }
+
+    xml.close("Invoice");
+    xml.finish()
+}
+
+/// Writes a CII trade party (seller or buyer).
+fn cii_party(xml: &mut XmlWriter, tag: &str, party: &Party) {
+    xml.open(tag, &[]);
+    xml.leaf("ram:Name", &[], &party.name);
+    xml.open("ram:PostalTradeAddress", &[]);
+    if let Some(postal_code) = &party.postal_code {
+        xml.leaf("ram:PostcodeCode", &[], postal_code);
+    }
+    if let Some(street) = &party.address_line {
+        xml.leaf("ram:LineOne", &[], street);
+    }
+    if let Some(city) = &party.city {
+        xml.leaf("ram:CityName", &[], city);
+    }
+    xml.leaf("ram:CountryID", &[], party.country_code.as_deref().unwrap_or_default());
+    xml.close("ram:PostalTradeAddress");
+    if let Some(email) = &party.email {
+        xml.open("ram:URIUniversalCommunication", &[]);
+        xml.leaf("ram:URIID", &[("schemeID", "EM")], email);
+        xml.close("ram:URIUniversalCommunication");
+    }
+    if let Some(vat_id) = &party.vat_id {
+        xml.open("ram:SpecifiedTaxRegistration", &[]);
+        xml.leaf("ram:ID", &[("schemeID", "VA")], vat_id);
+        xml.close("ram:SpecifiedTaxRegistration");
+    }
+    xml.close(tag);
+}
+
+/// Writes a CII date (`YYYYMMDD`, format 102).
+fn cii_date(xml: &mut XmlWriter, tag: &str, date: NaiveDate) {
+    xml.open(tag, &[]);
+    xml.leaf("udt:DateTimeString", &[("format", "102")], &date.format("%Y%m%d").to_string());
+    xml.close(tag);
+}
+
+/// Serializes an invoice as Cross Industry Invoice XML (Factur-X EN 16931 profile).
+pub fn to_cii(invoice: &EInvoice) -> String {
+    let currency = [("currencyID", invoice.currency.as_str())];
+    let mut xml = XmlWriter::new();
+
+    xml.open(
+        "rsm:CrossIndustryInvoice",
+        &[
+            ("xmlns:rsm", "urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100"),
+            ("xmlns:ram", "urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100"),
+            ("xmlns:udt", "urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100"),
+        ],
+    );
+
+    xml.open("rsm:ExchangedDocumentContext", &[]);
+    xml.open("ram:GuidelineSpecifiedDocumentContextParameter", &[]);
+    xml.leaf("ram:ID", &[], EN16931_SPECIFICATION);
+    xml.close("ram:GuidelineSpecifiedDocumentContextParameter");
+    xml.close("rsm:ExchangedDocumentContext");
+
+    xml.open("rsm:ExchangedDocument", &[]);
+    xml.leaf("ram:ID", &[], &invoice.number);
+    xml.leaf("ram:TypeCode", &[], COMMERCIAL_INVOICE);
+    cii_date(&mut xml, "ram:IssueDateTime", invoice.issue_date);
+    if let Some(note) = &invoice.note {
+        xml.open("ram:IncludedNote", &[]);
+        xml.leaf("ram:Content", &[], note);
+        xml.close("ram:IncludedNote");
+    }
+    xml.close("rsm:ExchangedDocument");
+
+    xml.open("rsm:SupplyChainTradeTransaction", &[]);
+    for (index, line) in invoice.lines.iter().enumerate() {
+        xml.open("ram:IncludedSupplyChainTradeLineItem", &[]);
+        xml.open("ram:AssociatedDocumentLineDocument", &[]);
+        xml.leaf("ram:LineID", &[], &(index + 1).to_string());
+        xml.close("ram:AssociatedDocumentLineDocument");
+        xml.open("ram:SpecifiedTradeProduct", &[]);
+        xml.leaf("ram:Name", &[], &line.name);
+        xml.close("ram:SpecifiedTradeProduct");
+        xml.open("ram:SpecifiedLineTradeAgreement", &[]);
+        xml.open("ram:NetPriceProductTradePrice", &[]);
+        xml.leaf("ram:ChargeAmount", &[], &line.unit_price.normalize().to_string());
+        xml.close("ram:NetPriceProductTradePrice");
+        xml.close("ram:SpecifiedLineTradeAgreement");
+        xml.open("ram:SpecifiedLineTradeDelivery", &[]);
+        xml.leaf("ram:BilledQuantity", &[("unitCode", UNIT_CODE)], &line.quantity.normalize().to_string());
+        xml.close("ram:SpecifiedLineTradeDelivery");
+        xml.open("ram:SpecifiedLineTradeSettlement", &[]);
+        xml.open("ram:ApplicableTradeTax", &[]);
+        xml.leaf("ram:TypeCode", &[], "VAT");
+        xml.leaf("ram:CategoryCode", &[], line.category.code());
+        if line.category != VatCategory::NotSubject {
+            xml.leaf("ram:RateApplicablePercent", &[], &line.rate.normalize().to_string());
+        }
+        xml.close("ram:ApplicableTradeTax");
+        xml.open("ram:SpecifiedTradeSettlementLineMonetarySummation", &[]);
+        xml.leaf("ram:LineTotalAmount", &[], &amount(line.net));
+        xml.close("ram:SpecifiedTradeSettlementLineMonetarySummation");
+        xml.close("ram:SpecifiedLineTradeSettlement");
+        xml.close("ram:IncludedSupplyChainTradeLineItem");
+    }
+
+    xml.open("ram:ApplicableHeaderTradeAgreement", &[]);
+    cii_party(&mut xml, "ram:SellerTradeParty", &invoice.seller);
+    cii_party(&mut xml, "ram:BuyerTradeParty", &invoice.buyer);
+    xml.close("ram:ApplicableHeaderTradeAgreement");
+    xml.line("<ram:ApplicableHeaderTradeDelivery/>");
+
+    xml.open("ram:ApplicableHeaderTradeSettlement", &[]);
+    xml.leaf("ram:PaymentReference", &[], &invoice.number);
+    xml.leaf("ram:InvoiceCurrencyCode", &[], &invoice.currency);
+    if let Some(iban) = &invoice.iban {
+        xml.open("ram:SpecifiedTradeSettlementPaymentMeans", &[]);
+        xml.leaf("ram:TypeCode", &[], SEPA_CREDIT_TRANSFER);
+        xml.open("ram:PayeePartyCreditorFinancialAccount", &[]);
+        xml.leaf("ram:IBANID", &[], iban);
+        xml.close("ram:PayeePartyCreditorFinancialAccount");
+        xml.close("ram:SpecifiedTradeSettlementPaymentMeans");
+    }
+    for group in &invoice.breakdown {
+        xml.open("ram:ApplicableTradeTax", &[]);
+        xml.leaf("ram:CalculatedAmount", &[], &amount(group.tax));
+        xml.leaf("ram:TypeCode", &[], "VAT");
+        if group.category == VatCategory::NotSubject {
+            xml.leaf("ram:ExemptionReason", &[], NOT_SUBJECT_REASON);
+        }
+        xml.leaf("ram:BasisAmount", &[], &amount(group.taxable));
+        xml.leaf("ram:CategoryCode", &[], group.category.code());
+        if group.category != VatCategory::NotSubject {
+            xml.leaf("ram:RateApplicablePercent", &[], &group.rate.normalize().to_string());
+        }
+        xml.close("ram:ApplicableTradeTax");
+    }
+    xml.open("ram:SpecifiedTradePaymentTerms", &[]);
+    match invoice.due_date {
+        Some(due_date) => cii_date(&mut xml, "ram:DueDateDateTime", due_date),
+        None => xml.leaf("ram:Description", &[], "Due on receipt"),
+    }
+    xml.close("ram:SpecifiedTradePaymentTerms");
+    xml.open("ram:SpecifiedTradeSettlementHeaderMonetarySummation", &[]);
+    xml.leaf("ram:LineTotalAmount", &[], &amount(invoice.line_total));
+    xml.leaf("ram:TaxBasisTotalAmount", &[], &amount(invoice.line_total));
+    xml.leaf("ram:TaxTotalAmount", &currency, &amount(invoice.tax_total));
+    xml.leaf("ram:GrandTotalAmount", &[], &amount(invoice.grand_total));
+    xml.leaf("ram:TotalPrepaidAmount", &[], &amount(invoice.prepaid));
+    xml.leaf("ram:DuePayableAmount", &[], &amount(invoice.payable));
+    xml.close("ram:SpecifiedTradeSettlementHeaderMonetarySummation");
+    xml.close("ram:ApplicableHeaderTradeSettlement");
+    xml.close("rsm:SupplyChainTradeTransaction");
+
+    xml.close("rsm:CrossIndustryInvoice");
+    xml.finish()
+}
+
+/// XMP metadata declaring PDF/A-3B and the Factur-X attachment.
+fn factur_x_xmp(title: &str, now: DateTime<Utc>) -> String {
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/">
+      <pdfaid:part>3</pdfaid:part>
+      <pdfaid:conformance>B</pdfaid:conformance>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+      <dc:title><rdf:Alt><rdf:li xml:lang="x-default">{title}</rdf:li></rdf:Alt></dc:title>
+      <xmp:CreateDate>{date}</xmp:CreateDate>
+      <xmp:ModifyDate>{date}</xmp:ModifyDate>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:fx="urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#">
+      <fx:DocumentType>INVOICE</fx:DocumentType>
+      <fx:DocumentFileName>{file}</fx:DocumentFileName>
+      <fx:Version>1.0</fx:Version>
+      <fx:ConformanceLevel>EN 16931</fx:ConformanceLevel>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:pdfaExtension="http://www.aiim.org/pdfa/ns/extension/" xmlns:pdfaSchema="http://www.aiim.org/pdfa/ns/schema#" xmlns:pdfaProperty="http://www.aiim.org/pdfa/ns/property#">
+      <pdfaExtension:schemas>
+        <rdf:Bag>
+          <rdf:li rdf:parseType="Resource">
+            <pdfaSchema:schema>Factur-X PDFA Extension Schema</pdfaSchema:schema>
+            <pdfaSchema:namespaceURI>urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#</pdfaSchema:namespaceURI>
+            <pdfaSchema:prefix>fx</pdfaSchema:prefix>
+            <pdfaSchema:property>
+              <rdf:Seq>
+                <rdf:li rdf:parseType="Resource"><pdfaProperty:name>DocumentFileName</pdfaProperty:name><pdfaProperty:valueType>Text</pdfaProperty:valueType><pdfaProperty:category>external</pdfaProperty:category><pdfaProperty:description>Name of the embedded XML invoice file</pdfaProperty:description></rdf:li>
+                <rdf:li rdf:parseType="Resource"><pdfaProperty:name>DocumentType</pdfaProperty:name><pdfaProperty:valueType>Text</pdfaProperty:valueType><pdfaProperty:category>external</pdfaProperty:category><pdfaProperty:description>INVOICE</pdfaProperty:description></rdf:li>
+                <rdf:li rdf:parseType="Resource"><pdfaProperty:name>Version</pdfaProperty:name><pdfaProperty:valueType>Text</pdfaProperty:valueType><pdfaProperty:category>external</pdfaProperty:category><pdfaProperty:description>Version of the Factur-X XML schema</pdfaProperty:description></rdf:li>
+                <rdf:li rdf:parseType="Resource"><pdfaProperty:name>ConformanceLevel</pdfaProperty:name><pdfaProperty:valueType>Text</pdfaProperty:valueType><pdfaProperty:category>external</pdfaProperty:category><pdfaProperty:description>Factur-X profile of the embedded XML</pdfaProperty:description></rdf:li>
+              </rdf:Seq>
+            </pdfaSchema:property>
+          </rdf:li>
+        </rdf:Bag>
+      </pdfaExtension:schemas>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#,
+        title = escape(title),
+        date = now.format("%Y-%m-%dT%H:%M:%SZ"),
+        file = FACTUR_X_FILENAME,
+    )
+}
+
+/// Embeds Factur-X XML into an invoice PDF.
+///
+/// The XML is attached as `factur-x.xml` (relationship `Data`) and the
+/// document's XMP metadata is replaced to declare PDF/A-3B and Factur-X.
+///
+/// # Arguments
+///
+/// * `pdf` - The rendered invoice PDF
+/// * `xml` - CII XML from [`to_cii`]
+/// * `title` - Document title for the metadata
+/// * `now` - Modification time of the attachment
+///
+/// # Errors
+///
+/// Returns an error if the PDF cannot be parsed or written.
+pub fn embed_factur_x(pdf: &[u8], xml: &str, title: &str, now: DateTime<Utc>) -> Result<Vec<u8>, anyhow::Error> {
+    let mut doc = Document::load_mem(pdf)?;
+    let modified = Object::string_literal(now.format("D:%Y%m%d%H%M%SZ").to_string());
+
+    let file = doc.add_object(Stream::new(
+        dictionary! {
+            "Type" => "EmbeddedFile",
+            "Subtype" => "text/xml",
+            "Params" => dictionary! {
+                "ModDate" => modified,
+                "Size" => xml.len() as i64,
+            },
+        },
+        xml.as_bytes().to_vec(),
+    ));
+    let filespec = doc.add_object(dictionary! {
+        "Type" => "Filespec",
+        "F" => Object::string_literal(FACTUR_X_FILENAME),
+        "UF" => Object::string_literal(FACTUR_X_FILENAME),
+        "Desc" => Object::string_literal("Factur-X invoice"),
+        "AFRelationship" => "Data",
+        "EF" => dictionary! { "F" => file, "UF" => file },
+    });
+    let metadata = doc.add_object(Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        factur_x_xmp(title, now).into_bytes(),
+    ));
+
+    let root = doc.trailer.get(b"Root")?.as_reference()?;
+    let catalog = doc.get_object_mut(root)?.as_dict_mut()?;
+    catalog.set(
+        "Names",
+        dictionary! {
+            "EmbeddedFiles" => dictionary! {
+                "Names" => vec![Object::string_literal(FACTUR_X_FILENAME), Object::Reference(filespec)],
+            },
+        },
+    );
+    catalog.set("AF", vec![Object::Reference(filespec)]);
+    catalog.set("Metadata", metadata);
+
+    let mut out = Vec::new();
+    doc.save_to(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+    use crate::models::line_item::{LineItem, StoredLineItems};
+    use crate::repo::memory::sample_invoice;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn seller(vat_id: Option<&str>) -> Party {
+        Party {
+            name: "Studio Nord".to_string(),
+            email: Some("hello@studio.example".to_string()),
+            vat_id: vat_id.map(str::to_string),
+            country_code: Some("DE".to_string()),
+            ..Party::default()
+        }
+    }
+
+    fn invoice_with_lines(lines: Value) -> Invoice {
+        let mut invoice = sample_invoice(Uuid::new_v4(), date(2024, 3, 1), Decimal::new(35700, 2));
+        invoice.currency = "EUR".to_string();
+        invoice.line_items = Some(StoredLineItems(LineItem::parse_list(&lines).unwrap()));
+        invoice.metadata = Some(json!({ "client": { "country_code": "fr", "vat_id": "FR 12 345678901" } }));
+        invoice
+    }
+
+    #[test]
+    fn test_vat_is_broken_down_per_rate() {
+        let invoice = invoice_with_lines(json!([
+            { "description": "Design", "quantity": 2, "unit_price": "100.00", "tax_rate": 19 },
+            { "description": "Hosting", "quantity": 1, "unit_price": "50.00", "tax_rate": 19 },
+            { "description": "Book", "quantity": 1, "unit_price": "10.00", "tax_rate": 0 }
+        ]));
+
+        let e = EInvoice::new(&invoice, seller(Some("DE123456789")), Party::buyer(&invoice), None);
+
+        assert_eq!(e.buyer.country_code.as_deref(), Some("FR"));
+        assert_eq!(e.buyer.vat_id.as_deref(), Some("FR12345678901"));
+        assert_eq!(e.breakdown.len(), 2);
+        assert_eq!(e.breakdown[0].category, VatCategory::Standard);
+        assert_eq!(e.breakdown[0].taxable, Decimal::new(25000, 2));
+        assert_eq!(e.breakdown[0].tax, Decimal::new(4750, 2));
+        assert_eq!(e.breakdown[1].category, VatCategory::ZeroRated);
+        assert_eq!(e.grand_total, Decimal::new(30750, 2));
+        assert!(e.problems().is_empty());
+    }
+
+    #[test]
+    fn test_sellers_without_vat_id_are_outside_vat_or_reported() {
+        let untaxed = invoice_with_lines(json!([{ "description": "Design", "unit_price": "100.00" }]));
+        let e = EInvoice::new(&untaxed, seller(None), Party::buyer(&untaxed), None);
+        assert_eq!(e.lines[0].category, VatCategory::NotSubject);
+        assert_eq!(e.buyer.vat_id, None);
+        assert!(e.problems().is_empty());
+
+        let taxed = invoice_with_lines(json!([{ "description": "Design", "unit_price": "100.00", "tax_rate": 19 }]));
+        let mut buyer = Party::buyer(&taxed);
+        buyer.country_code = None;
+        let problems = EInvoice::new(&taxed, seller(None), buyer, None).problems();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("buyer country"));
+        assert!(problems[1].starts_with("seller VAT identifier"));
+    }
+
+    #[test]
+    fn test_ubl_and_cii_carry_totals_and_escape_text() {
+        let mut invoice = invoice_with_lines(json!([
+            { "description": "Design & <layout>", "quantity": 2, "unit_price": "150.00", "tax_rate": 19 }
+        ]));
+        invoice.amount_paid = Decimal::new(5700, 2);
+        let e = EInvoice::new(
+            &invoice,
+            seller(Some("DE123456789")),
+            Party::buyer(&invoice),
+            Some("DE89370400440532013000".to_string()),
+        );
+
+        let ubl = to_ubl(&e);
+        assert!(ubl.contains("<cbc:CustomizationID>urn:cen.eu:en16931:2017</cbc:CustomizationID>"));
+        assert!(ubl.contains("<cbc:Name>Design &amp; &lt;layout&gt;</cbc:Name>"));
+        assert!(ubl.contains("<cbc:TaxAmount currencyID=\"EUR\">57.00</cbc:TaxAmount>"));
+        assert!(ubl.contains("<cbc:PrepaidAmount currencyID=\"EUR\">57.00</cbc:PrepaidAmount>"));
+        assert!(ubl.contains("<cbc:PayableAmount currencyID=\"EUR\">300.00</cbc:PayableAmount>"));
+        assert!(ubl.contains("<cbc:ID>DE89370400440532013000</cbc:ID>"));
+
+        let cii = to_cii(&e);
+        assert!(cii.contains("<udt:DateTimeString format=\"102\">20240301</udt:DateTimeString>"));
+        assert!(cii.contains("<ram:GrandTotalAmount>357.00</ram:GrandTotalAmount>"));
+        assert!(cii.contains("<ram:DuePayableAmount>300.00</ram:DuePayableAmount>"));
+        assert!(cii.contains("<ram:ID schemeID=\"VA\">FR12345678901</ram:ID>"));
+    }
+
+    #[test]
+    fn test_factur_x_embeds_the_xml() {
+        let invoice = invoice_with_lines(json!([{ "description": "Design", "unit_price": "100.00" }]));
+        let pdf = crate::invoices::pdf::render_invoice_pdf(&invoice, &PdfBranding::default()).unwrap();
+        let xml = to_cii(&EInvoice::new(&invoice, seller(None), Party::buyer(&invoice), None));
+
+        let facturx = embed_factur_x(&pdf, &xml, "Invoice INV-00001", Utc::now()).unwrap();
+
+        let doc = Document::load_mem(&facturx).unwrap();
+        let root = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        let catalog = doc.get_object(root).unwrap().as_dict().unwrap();
+        assert!(catalog.get(b"AF").is_ok());
+        assert!(catalog.get(b"Names").is_ok());
+        assert!(facturx.windows(FACTUR_X_FILENAME.len()).any(|w| w == FACTUR_X_FILENAME.as_bytes()));
+    }
+}
@@ -0,0 +1,992 @@
+use axum::{
+    extract::{Extension, Multipart, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Json, Redirect},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::attachments::sanitize_filename;
+use crate::auth::CurrentUser;
+use crate::chase::holds::{place_hold, HoldReason};
+use crate::clients::interactions::{record_invoice_view, ViewedVia};
+use crate::currency::Currency;
+use crate::invoices::correspondence::{
+    email_webhook_secret, export_correspondence_zip, list_correspondence, record_correspondence, validate_email_event,
+};
+use crate::invoices::duplicate::duplicate_invoice;
+use crate::invoices::einvoice::{embed_factur_x, to_cii, to_ubl, EInvoice, EInvoiceFormat, Party};
+use crate::invoices::history::invoice_history;
+use crate::invoices::import::{import_invoices, parse_csv, ImportOptions, ImportReport};
+use crate::invoices::{find_invoice, set_exchange_rate_override, set_invoice_status};
+use crate::invoices::payments::{list_payments, record_payment};
+use crate::invoices::pdf::{cached_invoice_pdf, PdfBranding};
+use crate::invoices::pdf_export::{
+    archive_name, count_matching, create_job, find_job, validate_filter, MAX_EXPORT_INVOICES,
+};
+use crate::invoices::public::{find_public_invoice, render_pay_page};
+use crate::invoices::search::{
+    search_invoices, tsquery, DEFAULT_SEARCH_LIMIT, MAX_QUERY_LENGTH, MAX_SEARCH_LIMIT,
+};
+use crate::locale::Locale;
+use crate::models::chase_override::{ChaseOverride, PauseChase};
+use crate::models::correspondence::{Correspondence, EmailEvent};
+use crate::models::exchange_rate_override::ExchangeRateOverride;
+use crate::models::invoice::{InvoiceResponse, InvoiceStatus, StatusError, UpdateInvoiceStatus};
+use crate::models::invoice_event::InvoiceHistoryEntry;
+use crate::models::payment::{CreatePayment, Payment};
+use crate::models::payment_method::PaymentMethodDetails;
+use crate::models::pdf_export_job::{PdfExportFilter, PdfExportJob, PdfExportStatus};
+use crate::payment_methods::{methods_for_client, stripe_link_for_invoice, stripe_payment_link};
+use crate::repo::DynRepository;
+use crate::secrets::secrets_match;
+use crate::settings::load_user_settings;
+use crate::storage::DynBlobStore;
+use crate::sync::encryption::encrypts_any;
+
+/// Invoice PDF endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/pdf`, rendering the invoice
+/// to a downloadable PDF (cached in blob storage until the invoice or its
+/// branding changes).
+pub async fn invoice_pdf_handler(
+    Extension(repo): Extension<DynRepository>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let invoice = repo
+        .find_invoice(user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let branding = repo
+        .pdf_branding(&invoice)
+        .await
+        .map_err(|e| {
+            error!("Failed to load branding for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let pdf = cached_invoice_pdf(store.as_ref(), &invoice, &branding).await.map_err(|e| {
+        error!("Failed to render PDF for invoice {}: {}", invoice_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let disposition = content_disposition("inline", &format!("{}.pdf", invoice.invoice_number));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        pdf,
+    ))
+}
+
+/// Invoice CSV import endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/import`. The body is
+/// `multipart/form-data` with the CSV in a `file` field, plus optional
+/// `mapping` (JSON object of invoice field to CSV header), `date_format`,
+/// `locale` (e.g. `de-DE`) and `dry_run` fields. Rows without a currency use the user's base
+/// currency. Problems with single rows are listed in the report; only a
+/// file that can't be read at all is rejected.
+pub async fn import_invoices_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    mut multipart: Multipart,
+) -> Result<Json<ImportReport>, (StatusCode, Json<Value>)> {
+    let bad_request = |e: axum::extract::multipart::MultipartError| {
+        (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))
+    };
+    let unprocessable = |message: String| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })));
+
+    let mut options = ImportOptions::default();
+    let mut data = None;
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        match field.name() {
+            Some("file") => data = Some(field.bytes().await.map_err(bad_request)?),
+            Some("mapping") => {
+                let text = field.text().await.map_err(bad_request)?;
+                if !text.trim().is_empty() {
+                    options.mapping = serde_json::from_str(&text).map_err(|e| {
+                        unprocessable(format!("mapping must be a JSON object of field names to CSV headers: {}", e))
+                    })?;
+                }
+            }
+            Some("date_format") => {
+                let text = field.text().await.map_err(bad_request)?;
+                options.date_format = Some(text.trim().to_string()).filter(|format| !format.is_empty());
+            }
+            Some("locale") => {
+                let text = field.text().await.map_err(bad_request)?;
+                if !text.trim().is_empty() {
+                    options.locale = Some(Locale::parse(&text).map_err(unprocessable)?);
+                }
+            }
+            Some("dry_run") => {
+                let text = field.text().await.map_err(bad_request)?;
+                options.dry_run = matches!(text.trim(), "true" | "1");
+            }
+            _ => {}
+        }
+    }
+    let data = data.ok_or_else(|| unprocessable("missing file field".to_string()))?;
+
+    let internal_error = |message: &str| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })));
+    let settings = load_user_settings(&pool, user_id).await.map_err(|e| {
+        error!("Failed to load settings for user {}: {}", user_id, e);
+        internal_error("Failed to load settings")
+    })?;
+    let currency = Currency::parse(&settings.base_currency).map_err(|e| {
+        error!("Invalid base currency for user {}: {}", user_id, e);
+        internal_error("Invalid base currency")
+    })?;
+
+    let parsed = parse_csv(&data, &options, currency).map_err(|e| unprocessable(e.error))?;
+    let report = import_invoices(&pool, user_id, parsed, options.dry_run)
+        .await
+        .map_err(|e| {
+            error!("Failed to import invoices for user {}: {}", user_id, e);
+            internal_error("Failed to import invoices")
+        })?;
+
+    Ok(Json(report))
+}
+
+/// Query parameters for exporting an e-invoice.
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    /// `ubl` (default) or `facturx`
+    #[serde(default)]
+    pub format: EInvoiceFormat,
+}
+
+/// E-invoice export endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/export?format=ubl|facturx`,
+/// returning EN 16931 UBL XML or a Factur-X PDF. Invoices lacking data the
+/// standard requires are rejected with `422 Unprocessable Entity` listing
+/// what is missing.
+pub async fn invoice_export_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let internal = |message: &str| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })));
+
+    let invoice = find_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            internal("failed to load invoice")
+        })?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({ "error": "invoice not found" }))))?;
+
+    let settings = load_user_settings(&pool, user_id).await.map_err(|e| {
+        error!("Failed to load settings for user {}: {}", user_id, e);
+        internal("failed to load settings")
+    })?;
+    let branding = PdfBranding::for_invoice(&pool, &invoice).await.map_err(|e| {
+        error!("Failed to load branding for user {}: {}", user_id, e);
+        internal("failed to load branding")
+    })?;
+    let methods = methods_for_client(&pool, user_id, invoice.client_email.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to load payment methods for invoice {}: {}", invoice_id, e);
+            internal("failed to load payment methods")
+        })?;
+    let iban = methods.iter().find_map(|method| match method.parsed() {
+        Ok(PaymentMethodDetails::BankTransfer { iban, .. }) => iban,
+        _ => None,
+    });
+
+    let einvoice = EInvoice::new(&invoice, Party::seller(&branding, &settings), Party::buyer(&invoice), iban);
+    let problems = einvoice.problems();
+    if !problems.is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": "invoice is missing data required for e-invoicing", "problems": problems })),
+        ));
+    }
+
+    let (content_type, filename, body) = match query.format {
+        EInvoiceFormat::Ubl => (
+            "application/xml",
+            format!("{}.xml", invoice.invoice_number),
+            to_ubl(&einvoice).into_bytes(),
+        ),
+        EInvoiceFormat::Facturx => {
+            let pdf = cached_invoice_pdf(store.as_ref(), &invoice, &branding).await.map_err(|e| {
+                error!("Failed to render PDF for invoice {}: {}", invoice_id, e);
+                internal("failed to render PDF")
+            })?;
+            let title = format!("Invoice {}", invoice.invoice_number);
+            let facturx = embed_factur_x(&pdf, &to_cii(&einvoice), &title, chrono::Utc::now()).map_err(|e| {
+                error!("Failed to build Factur-X PDF for invoice {}: {}", invoice_id, e);
+                internal("failed to build Factur-X PDF")
+            })?;
+            ("application/pdf", format!("{}-facturx.pdf", invoice.invoice_number), facturx)
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, content_disposition("attachment", &filename)),
+        ],
+        body,
+    ))
+}
+
+/// `Content-Disposition` value for a download named after an invoice.
+///
+/// Invoice numbers are user-supplied, so the name is cleaned the same way
+/// as attachment names, with path separators kept as dashes
+/// (`INV/2024/001` downloads as `INV-2024-001.pdf`).
+fn content_disposition(disposition: &str, filename: &str) -> String {
+    let filename = sanitize_filename(&filename.replace(['/', '\\'], "-"));
+    format!("{}; filename=\"{}\"", disposition, filename)
+}
+
+/// Correspondence export endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/correspondence.zip`, bundling
+/// every generated email, delivery receipt and reply for the invoice.
+pub async fn correspondence_export_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let invoice = find_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let entries = list_correspondence(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load correspondence for invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let archive = export_correspondence_zip(&invoice, &entries).map_err(|e| {
+        error!("Failed to build correspondence archive for invoice {}: {}", invoice_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let disposition = content_disposition(
+        "attachment",
+        &format!("{}-correspondence.zip", invoice.invoice_number),
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    ))
+}
+
+/// Email provider webhook handler.
+///
+/// Handles POST requests to `/webhooks/email/:user_id` without a user
+/// login; the provider authenticates with `Authorization: Bearer
+/// <EMAIL_WEBHOOK_SECRET>`. The body is an [`EmailEvent`]: a delivery
+/// receipt or a client reply to an email about one of the user's invoices,
+/// recorded as that invoice's correspondence so it is part of the export.
+///
+/// Returns 201 with the stored entry, 401 for a wrong secret, 404 while no
+/// secret is configured or if the invoice isn't the user's, and 422 for an
+/// invalid event.
+pub async fn email_webhook_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(user_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(event): Json<EmailEvent>,
+) -> Result<(StatusCode, Json<Correspondence>), (StatusCode, Json<Value>)> {
+    let error_response = |status: StatusCode, message: &str| (status, Json(json!({ "error": message })));
+
+    let expected = email_webhook_secret().ok_or_else(|| error_response(StatusCode::NOT_FOUND, "not found"))?;
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !secrets_match(&expected, given.trim()) {
+        return Err(error_response(StatusCode::UNAUTHORIZED, "invalid webhook secret"));
+    }
+
+    validate_email_event(&event).map_err(|message| error_response(StatusCode::UNPROCESSABLE_ENTITY, &message))?;
+
+    let invoice_id = event.invoice_id;
+    find_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load invoice")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "invoice not found"))?;
+
+    let stored = record_correspondence(&pool, user_id, event.into()).await.map_err(|e| {
+        error!("Failed to record email event for invoice {}: {}", invoice_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to record event")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(stored)))
+}
+
+/// Record payment endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/:id/payments`. Payments may be
+/// partial; amounts above the remaining balance are rejected with
+/// `422 Unprocessable Entity`. Responds with the payment and the updated
+/// invoice (including `amount_paid` and `balance_due`).
+pub async fn create_payment_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+    Json(request): Json<CreatePayment>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let invoice = find_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to load invoice" })),
+            )
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "invoice not found" }))))?;
+
+    let (payment, invoice) = record_payment(&pool, &invoice, request)
+        .await
+        .map_err(|e| {
+            error!("Failed to record payment for invoice {}: {}", invoice_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to record payment" })),
+            )
+        })?
+        .map_err(|message| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "payment": payment,
+            "invoice": InvoiceResponse::from(invoice),
+        })),
+    ))
+}
+
+/// Query parameters for invoice search.
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    /// Free-text query
+    pub q: String,
+
+    /// Maximum number of results (default 20)
+    pub limit: Option<i64>,
+}
+
+/// Invoice search endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/search?q=&limit=`, matching the
+/// words of `q` against invoice numbers, client names and descriptions.
+pub async fn search_invoices_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Vec<InvoiceResponse>>, (StatusCode, Json<Value>)> {
+    let invalid = |message: String| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })));
+
+    if params.q.chars().count() > MAX_QUERY_LENGTH {
+        return Err(invalid(format!("q must be at most {} characters", MAX_QUERY_LENGTH)));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if !(1..=MAX_SEARCH_LIMIT).contains(&limit) {
+        return Err(invalid(format!("limit must be between 1 and {}", MAX_SEARCH_LIMIT)));
+    }
+    let Some(query) = tsquery(&params.q) else {
+        return Err(invalid("q must contain at least one word".to_string()));
+    };
+
+    let invoices = search_invoices(&pool, user_id, &query, limit)
+        .await
+        .map_err(|e| {
+            error!("Failed to search invoices for user {}: {}", user_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to search invoices" })),
+            )
+        })?;
+
+    Ok(Json(invoices.into_iter().map(InvoiceResponse::from).collect()))
+}
+
+/// List payments endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/payments`.
+pub async fn list_payments_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<Vec<Payment>>, StatusCode> {
+    find_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let payments = list_payments(&pool, user_id, invoice_id).await.map_err(|e| {
+        error!("Failed to list payments for invoice {}: {}", invoice_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(payments))
+}
+
+/// Invoice history endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/:id/history`, listing every
+/// recorded change (who, when, which fields, from which device), newest
+/// first. Deleted invoices keep their history.
+pub async fn invoice_history_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<Vec<InvoiceHistoryEntry>>, StatusCode> {
+    let history = invoice_history(&pool, user_id, invoice_id).await.map_err(|e| {
+        error!("Failed to load history of invoice {}: {}", invoice_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Invoices created before history was recorded have none yet
+    if history.is_empty() {
+        find_invoice(&pool, user_id, invoice_id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load invoice {}: {}", invoice_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+    }
+
+    Ok(Json(history))
+}
+
+/// Duplicate invoice endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/:id/duplicate`, copying the
+/// invoice's client and line items into a new draft with the next invoice
+/// number. Returns 409 if the user syncs invoices end-to-end encrypted.
+pub async fn duplicate_invoice_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<InvoiceResponse>), StatusCode> {
+    // The server can't write the copy to an end-to-end encrypted table
+    let encrypted = encrypts_any(&pool, user_id, &["invoices"]).await.map_err(|e| {
+        error!("Failed to load encrypted tables of user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if encrypted {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let invoice = duplicate_invoice(&pool, user_id, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to duplicate invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((StatusCode::CREATED, Json(InvoiceResponse::from(invoice))))
+}
+
+/// Set chase override endpoint handler.
+///
+/// Handles PUT requests to `/api/invoices/:id/chase-override`, replacing the
+/// invoice's chase policy override (e.g. `not_before`, `skip_level_2`).
+pub async fn update_chase_override_handler(
+    Extension(repo): Extension<DynRepository>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+    Json(overrides): Json<ChaseOverride>,
+) -> Result<Json<InvoiceResponse>, (StatusCode, Json<Value>)> {
+    overrides
+        .validate()
+        .map_err(|message| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))))?;
+
+    let invoice = repo
+        .set_chase_override(user_id, invoice_id, Some(&overrides))
+        .await
+        .map_err(|e| {
+            error!("Failed to set chase override for invoice {}: {}", invoice_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to update chase override" })),
+            )
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "invoice not found" }))))?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+/// Clear chase override endpoint handler.
+///
+/// Handles DELETE requests to `/api/invoices/:id/chase-override`, restoring
+/// the default chase policy.
+pub async fn clear_chase_override_handler(
+    Extension(repo): Extension<DynRepository>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<InvoiceResponse>, StatusCode> {
+    let invoice = repo
+        .set_chase_override(user_id, invoice_id, None)
+        .await
+        .map_err(|e| {
+            error!("Failed to clear chase override for invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+/// Pause chasing endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/:id/chase/pause`, stopping the
+/// invoice's reminders until `until` (30 days from now if the body is `{}`)
+/// without marking it paid, e.g. while negotiating with the client.
+pub async fn pause_chase_handler(
+    Extension(repo): Extension<DynRepository>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+    Json(pause): Json<PauseChase>,
+) -> Result<Json<InvoiceResponse>, (StatusCode, Json<Value>)> {
+    let until = pause
+        .until(chrono::Utc::now())
+        .map_err(|message| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))))?;
+
+    let invoice = repo
+        .set_chase_pause(user_id, invoice_id, Some(until))
+        .await
+        .map_err(|e| {
+            error!("Failed to pause chasing invoice {}: {}", invoice_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to pause chasing" })),
+            )
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "invoice not found" }))))?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+/// Resume chasing endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/:id/chase/resume`, ending a
+/// pause early so the worker picks the invoice up on its next run.
+pub async fn resume_chase_handler(
+    Extension(repo): Extension<DynRepository>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<InvoiceResponse>, StatusCode> {
+    let invoice = repo
+        .set_chase_pause(user_id, invoice_id, None)
+        .await
+        .map_err(|e| {
+            error!("Failed to resume chasing invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+/// Set exchange rate override endpoint handler.
+///
+/// Handles PUT requests to `/api/invoices/:id/exchange-rate`, fixing the
+/// rate reports use to convert the invoice into the given base currency.
+pub async fn update_exchange_rate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+    Json(mut rate): Json<ExchangeRateOverride>,
+) -> Result<Json<InvoiceResponse>, (StatusCode, Json<Value>)> {
+    rate.validate()
+        .map_err(|message| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))))?;
+
+    let invoice = set_exchange_rate_override(&pool, user_id, invoice_id, Some(&rate))
+        .await
+        .map_err(|e| {
+            error!("Failed to set exchange rate for invoice {}: {}", invoice_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to update exchange rate" })),
+            )
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "invoice not found" }))))?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+/// Clear exchange rate override endpoint handler.
+///
+/// Handles DELETE requests to `/api/invoices/:id/exchange-rate`, so reports
+/// use market rates for the invoice again.
+pub async fn clear_exchange_rate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<InvoiceResponse>, StatusCode> {
+    let invoice = set_exchange_rate_override(&pool, user_id, invoice_id, None)
+        .await
+        .map_err(|e| {
+            error!("Failed to clear exchange rate for invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+/// Invoice status endpoint handler.
+///
+/// Handles PUT requests to `/api/invoices/:id/status`. Unknown statuses and
+/// illegal transitions (e.g. draft to paid) are rejected with
+/// `422 Unprocessable Entity` and a body carrying `code`, `from` and `to`.
+pub async fn update_status_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(invoice_id): Path<Uuid>,
+    Json(request): Json<UpdateInvoiceStatus>,
+) -> Result<Json<InvoiceResponse>, (StatusCode, Json<Value>)> {
+    let status = request
+        .status
+        .parse::<InvoiceStatus>()
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, Json(e.to_json())))?;
+
+    let invoice = set_invoice_status(&pool, user_id, invoice_id, status)
+        .await
+        .map_err(|e| match e.downcast_ref::<StatusError>() {
+            Some(status_error) => (StatusCode::UNPROCESSABLE_ENTITY, Json(status_error.to_json())),
+            None => {
+                error!("Failed to update status of invoice {}: {}", invoice_id, e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "failed to update invoice status" })),
+                )
+            }
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "invoice not found" }))))?;
+
+    Ok(Json(InvoiceResponse::from(invoice)))
+}
+
+/// Public pay page handler.
+///
+/// Handles GET requests to `/pay/:id` without authentication, showing the
+/// balance due and the client's payment instructions. The view is added to
+/// the timeline of the invoice's client.
+pub async fn public_pay_page_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Html<String>, StatusCode> {
+    let invoice = find_public_invoice(&pool, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let branding = PdfBranding::for_invoice(&pool, &invoice)
+        .await
+        .map_err(|e| {
+            error!("Failed to load payment details for invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if let Err(e) = record_invoice_view(&pool, &invoice, ViewedVia::PayPage).await {
+        warn!("Failed to record view of invoice {}: {}", invoice.id, e);
+    }
+
+    Ok(Html(render_pay_page(
+        &invoice,
+        &branding.business_name,
+        &branding.payment_instructions,
+    )))
+}
+
+/// Pay by card endpoint handler.
+///
+/// Handles POST requests to `/pay/:id/card`, sent by the pay page's card
+/// button: puts the invoice's chasing on a grace hold (unless a recent one
+/// rules it out) and redirects to the Stripe payment link, tagged with the
+/// invoice ID. Invoices without a Stripe method are
+/// `404 Not Found`; paid ones go back to the pay page.
+pub async fn start_card_payment_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Redirect, StatusCode> {
+    let invoice = find_public_invoice(&pool, invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.balance_due().is_zero() {
+        return Ok(Redirect::to(&format!("/pay/{}", invoice.id)));
+    }
+
+    let methods = methods_for_client(&pool, invoice.user_id, invoice.client_email.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to load payment methods for invoice {}: {}", invoice_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let payment_link = stripe_payment_link(&methods).ok_or(StatusCode::NOT_FOUND)?;
+
+    // The client still gets to Stripe if the hold can't be stored
+    let now = chrono::Utc::now();
+    if let Err(e) = place_hold(&pool, invoice.user_id, &[invoice.id], HoldReason::PaymentStarted, now).await {
+        warn!("Failed to hold chasing of invoice {}: {}", invoice.id, e);
+    }
+
+    Ok(Redirect::to(&stripe_link_for_invoice(&payment_link, invoice.id)))
+}
+
+/// Bulk PDF export endpoint handler.
+///
+/// Handles POST requests to `/api/invoices/export-pdfs`, queueing a job that
+/// renders every invoice matching the filter. Responds with
+/// `202 Accepted` and the job; poll `/api/invoices/export-pdfs/:id` for
+/// progress. Filters matching no invoices, or more than
+/// `MAX_EXPORT_INVOICES`, are rejected with `422 Unprocessable Entity`.
+pub async fn create_pdf_export_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(filter): Json<PdfExportFilter>,
+) -> Result<(StatusCode, Json<PdfExportJob>), (StatusCode, Json<Value>)> {
+    let invalid = |message: String| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })));
+    let failed = |message: &str| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })));
+
+    validate_filter(&filter).map_err(invalid)?;
+
+    let total = count_matching(&pool, user_id, &filter).await.map_err(|e| {
+        error!("Failed to count invoices to export for user {}: {}", user_id, e);
+        failed("failed to queue export")
+    })?;
+    if total == 0 {
+        return Err(invalid("no invoices match the filter".to_string()));
+    }
+    if total > MAX_EXPORT_INVOICES {
+        return Err(invalid(format!(
+            "{} invoices match the filter; at most {} can be exported at once",
+            total, MAX_EXPORT_INVOICES
+        )));
+    }
+
+    let job = create_job(&pool, user_id, &filter, total).await.map_err(|e| {
+        error!("Failed to queue PDF export for user {}: {}", user_id, e);
+        failed("failed to queue export")
+    })?;
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Loads one of the user's export jobs, mapping failures to a status.
+async fn load_pdf_export(pool: &PgPool, user_id: Uuid, job_id: Uuid) -> Result<PdfExportJob, StatusCode> {
+    find_job(pool, user_id, job_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load PDF export {}: {}", job_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Bulk PDF export status endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/export-pdfs/:id`; `processed`
+/// and `total` report the job's progress.
+pub async fn get_pdf_export_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<PdfExportJob>, StatusCode> {
+    Ok(Json(load_pdf_export(&pool, user_id, job_id).await?))
+}
+
+/// Bulk PDF export download endpoint handler.
+///
+/// Handles GET requests to `/api/invoices/export-pdfs/:id/download`.
+/// Responds with `409 Conflict` while the job hasn't completed and
+/// `410 Gone` once the archive has expired.
+pub async fn download_pdf_export_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(job_id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let job = load_pdf_export(&pool, user_id, job_id).await?;
+    if job.status != PdfExportStatus::Completed {
+        return Err(StatusCode::CONFLICT);
+    }
+    let available = job.expires_at.is_some_and(|expires_at| expires_at > chrono::Utc::now());
+    let key = match &job.storage_key {
+        Some(key) if available => key,
+        _ => return Err(StatusCode::GONE),
+    };
+
+    let archive = store.get(key).await.map_err(|e| {
+        error!("Failed to read PDF export {}: {}", job_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let filter = job.parsed_filter().unwrap_or_default();
+    let disposition = format!("attachment; filename=\"{}\"", archive_name(&filter));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+
+    use crate::repo::memory::{sample_invoice, InMemoryRepository};
+    use crate::repo::InvoiceRepository;
+
+    #[test]
+    fn test_content_disposition_strips_quotes_from_invoice_numbers() {
+        let disposition = content_disposition("attachment", "INV \"1\"; x=\"y/2\r\n.xml");
+
+        assert_eq!(disposition, "attachment; filename=\"INV 1; x=y-2.xml\"");
+        assert!(header::HeaderValue::from_str(&disposition).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_update_chase_override_records_sync_change() {
+        let user_id = Uuid::new_v4();
+        let invoice = sample_invoice(user_id, Utc::now().date_naive(), Decimal::from(100));
+        let invoice_id = invoice.id;
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice));
+        let repo: DynRepository = memory.clone();
+
+        let overrides = ChaseOverride {
+            paused: true,
+            ..ChaseOverride::default()
+        };
+        let Json(response) = update_chase_override_handler(
+            Extension(repo),
+            Extension(CurrentUser(user_id)),
+            Path(invoice_id),
+            Json(overrides.clone()),
+        )
+        .await
+        .expect("override should be saved");
+
+        assert_eq!(response.id, invoice_id);
+        let stored = memory.invoice(invoice_id).unwrap();
+        assert_eq!(ChaseOverride::parse(stored.chase_override.as_ref()).unwrap(), overrides);
+        assert_eq!(memory.sync_changes().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_chase_override_of_other_users_invoice_is_not_found() {
+        let invoice = sample_invoice(Uuid::new_v4(), Utc::now().date_naive(), Decimal::from(100));
+        let invoice_id = invoice.id;
+        let repo: DynRepository = Arc::new(InMemoryRepository::new().with_invoice(invoice));
+
+        let result = clear_chase_override_handler(
+            Extension(repo),
+            Extension(CurrentUser(Uuid::new_v4())),
+            Path(invoice_id),
+        )
+        .await;
+
+        assert_eq!(result.err(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn test_paused_invoice_is_not_chased_until_resumed() {
+        let user_id = Uuid::new_v4();
+        let invoice = sample_invoice(user_id, Utc::now().date_naive() - chrono::Duration::days(10), Decimal::from(100));
+        let invoice_id = invoice.id;
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice));
+        let repo: DynRepository = memory.clone();
+        let today = Utc::now().date_naive();
+
+        let Json(response) = pause_chase_handler(
+            Extension(repo.clone()),
+            Extension(CurrentUser(user_id)),
+            Path(invoice_id),
+            Json(PauseChase::default()),
+        )
+        .await
+        .expect("chasing should be paused");
+
+        let until = response.chase_paused_until.expect("pause should have an end");
+        assert!(until > Utc::now() + chrono::Duration::days(29));
+        assert!(repo.find_overdue_invoices(today, 10).await.unwrap().is_empty());
+
+        resume_chase_handler(Extension(repo.clone()), Extension(CurrentUser(user_id)), Path(invoice_id))
+            .await
+            .expect("chasing should resume");
+
+        assert_eq!(memory.invoice(invoice_id).unwrap().chase_paused_until, None);
+        assert_eq!(repo.find_overdue_invoices(today, 10).await.unwrap().len(), 1);
+        assert_eq!(memory.sync_changes().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pause_ending_in_the_past_is_rejected() {
+        let user_id = Uuid::new_v4();
+        let invoice = sample_invoice(user_id, Utc::now().date_naive(), Decimal::from(100));
+        let invoice_id = invoice.id;
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice));
+        let repo: DynRepository = memory.clone();
+
+        let result = pause_chase_handler(
+            Extension(repo),
+            Extension(CurrentUser(user_id)),
+            Path(invoice_id),
+            Json(PauseChase {
+                until: Some(Utc::now() - chrono::Duration::hours(1)),
+            }),
+        )
+        .await;
+
+        assert_eq!(result.err().map(|(status, _)| status), Some(StatusCode::UNPROCESSABLE_ENTITY));
+        assert!(memory.sync_changes().is_empty());
+    }
+}
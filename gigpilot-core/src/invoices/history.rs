@@ -0,0 +1,136 @@
+//! Invoice change history.
+//!
+//! A database trigger records every change to an invoice in
+//! `invoice_events` (see the `create_invoice_events` migration), so the
+//! history is complete whichever path made the change. Attribution comes
+//! from an [`AuditContext`]: the JWT middleware sets one for the duration
+//! of each API request, and writers copy it into their transaction with
+//! [`apply_audit_context`] before touching invoices. Changes made without
+//! one (background jobs) are attributed to the system.
+
+use std::future::Future;
+
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::models::invoice_event::{AuditSource, InvoiceEvent, InvoiceHistoryEntry};
+
+tokio::task_local! {
+    static AUDIT_CONTEXT: AuditContext;
+}
+
+/// Who is changing invoices, and from where.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditContext {
+    /// Person making the change
+    pub actor_id: Option<Uuid>,
+
+    /// Device the change comes from (sync `device_id`, or the API's
+    /// `X-Device-Id` header)
+    pub device_id: Option<String>,
+
+    /// Path the change comes through
+    pub source: AuditSource,
+}
+
+/// Runs `future` with `context` as the current audit context.
+pub async fn with_audit_context<F: Future>(context: AuditContext, future: F) -> F::Output {
+    AUDIT_CONTEXT.scope(context, future).await
+}
+
+/// Returns the audit context of the current task, if one was set.
+pub fn current_audit_context() -> Option<AuditContext> {
+    AUDIT_CONTEXT.try_with(Clone::clone).ok()
+}
+
+/// Attributes the invoice changes of the current transaction.
+///
+/// Settings are transaction-local, so `executor` must be the transaction
+/// that goes on to change invoices.
+///
+/// # Arguments
+///
+/// * `executor` - Transaction to attribute
+/// * `context` - Who is making the changes
+pub async fn apply_audit_context<'e, E>(executor: E, context: &AuditContext) -> Result<(), anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        r#"
+        SELECT
+            set_config('gigpilot.actor_id', $1, true),
+            set_config('gigpilot.device_id', $2, true),
+            set_config('gigpilot.audit_source', $3, true)
+        "#,
+    )
+    .bind(context.actor_id.map(|id| id.to_string()).unwrap_or_default())
+    .bind(context.device_id.clone().unwrap_or_default())
+    .bind(context.source.as_str())
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Attributes the current transaction to the task's audit context, if any.
+pub async fn apply_current_audit_context<'e, E>(executor: E) -> Result<(), anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    match current_audit_context() {
+        Some(context) => apply_audit_context(executor, &context).await,
+        None => Ok(()),
+    }
+}
+
+/// Loads an invoice's history, newest first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice (deleted invoices keep their history)
+pub async fn invoice_history(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+) -> Result<Vec<InvoiceHistoryEntry>, anyhow::Error> {
+    let events = sqlx::query_as::<_, InvoiceEvent>(
+        r#"
+        SELECT
+            e.id, e.invoice_id, e.action, e.changes, e.actor_id,
+            u.email AS actor_email, e.device_id, e.source, e.created_at
+        FROM invoice_events e
+        LEFT JOIN users u ON u.id = e.actor_id
+        WHERE e.user_id = $1 AND e.invoice_id = $2
+        ORDER BY e.created_at DESC, e.id
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events.into_iter().map(InvoiceHistoryEntry::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_audit_context_is_scoped_to_the_task() {
+        assert_eq!(current_audit_context(), None);
+
+        let context = AuditContext {
+            actor_id: Some(Uuid::new_v4()),
+            device_id: Some("phone".to_string()),
+            source: AuditSource::Api,
+        };
+        let seen = with_audit_context(context.clone(), async { current_audit_context() }).await;
+
+        assert_eq!(seen, Some(context));
+        assert_eq!(current_audit_context(), None);
+    }
+}
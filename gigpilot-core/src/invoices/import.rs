@@ -0,0 +1,786 @@
+//! CSV invoice import.
+//!
+//! Users moving over from spreadsheets upload a CSV file with one invoice
+//! per row. A column mapping names the CSV header that holds each invoice
+//! field; unmapped fields are looked up under their own name (e.g. a
+//! `client_name` column). Each row is validated on its own: valid rows are
+//! imported and invalid ones are reported with their CSV line and field.
+//!
+//! Every imported invoice keeps a key derived from its row under
+//! `metadata.import.key`. Uploading the same file again skips rows that
+//! were already imported, so a file can be fixed and re-uploaded until
+//! every row is in.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::currency::{Currency, Money};
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::numbering::{check_invoice_number, next_invoice_number};
+use crate::locale::{parse_date, parse_decimal, Locale};
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+
+/// Largest CSV file accepted, in bytes.
+pub const MAX_IMPORT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Most data rows imported from one file.
+pub const MAX_IMPORT_ROWS: usize = 1000;
+
+/// An invoice field that can be read from a CSV column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImportField {
+    InvoiceNumber,
+    ClientName,
+    ClientEmail,
+    Description,
+    IssueDate,
+    DueDate,
+    Total,
+    AmountPaid,
+    Currency,
+    Status,
+}
+
+impl ImportField {
+    /// Every importable field.
+    pub const ALL: [ImportField; 10] = [
+        ImportField::InvoiceNumber,
+        ImportField::ClientName,
+        ImportField::ClientEmail,
+        ImportField::Description,
+        ImportField::IssueDate,
+        ImportField::DueDate,
+        ImportField::Total,
+        ImportField::AmountPaid,
+        ImportField::Currency,
+        ImportField::Status,
+    ];
+
+    /// Field name used in mappings and error reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportField::InvoiceNumber => "invoice_number",
+            ImportField::ClientName => "client_name",
+            ImportField::ClientEmail => "client_email",
+            ImportField::Description => "description",
+            ImportField::IssueDate => "issue_date",
+            ImportField::DueDate => "due_date",
+            ImportField::Total => "total",
+            ImportField::AmountPaid => "amount_paid",
+            ImportField::Currency => "currency",
+            ImportField::Status => "status",
+        }
+    }
+
+    /// Whether every file must have a column for the field.
+    fn is_required(&self) -> bool {
+        matches!(self, ImportField::ClientName | ImportField::Total)
+    }
+}
+
+impl fmt::Display for ImportField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ImportField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ImportField::ALL
+            .into_iter()
+            .find(|field| field.as_str() == s.trim())
+            .ok_or_else(|| format!("unknown field in mapping: {}", s))
+    }
+}
+
+/// How to read an uploaded file.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// CSV header for each field name (e.g. `{"client_name": "Customer"}`)
+    pub mapping: HashMap<String, String>,
+
+    /// `chrono` format of the date columns (read tolerantly without one)
+    pub date_format: Option<String>,
+
+    /// How numbers and dates are written, e.g. `de-DE`
+    pub locale: Option<Locale>,
+
+    /// Validate and report without storing anything
+    pub dry_run: bool,
+}
+
+/// A validated CSV row, ready to become an invoice.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportRow {
+    /// Line of the row in the file (the header is line 1)
+    pub line: u64,
+
+    /// Idempotency key derived from the row's values
+    pub key: String,
+
+    pub invoice_number: Option<String>,
+    pub client_name: String,
+    pub client_email: Option<String>,
+    pub description: Option<String>,
+    pub issue_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub total: Money,
+    pub amount_paid: Decimal,
+    pub status: InvoiceStatus,
+}
+
+/// A problem with one row (or with the whole file).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportRowError {
+    /// Line of the row in the file (None for the file itself)
+    pub line: Option<u64>,
+
+    /// Offending field, when known
+    pub field: Option<String>,
+
+    /// What is wrong
+    pub error: String,
+}
+
+impl ImportRowError {
+    fn row(line: u64, field: Option<ImportField>, error: impl Into<String>) -> Self {
+        ImportRowError {
+            line: Some(line),
+            field: field.map(|field| field.to_string()),
+            error: error.into(),
+        }
+    }
+
+    fn file(error: impl Into<String>) -> Self {
+        ImportRowError { line: None, field: None, error: error.into() }
+    }
+}
+
+/// The outcome of parsing a file: valid rows and row-level errors.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedImport {
+    pub rows: Vec<ImportRow>,
+    pub errors: Vec<ImportRowError>,
+}
+
+/// A row stored as a new invoice.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedRow {
+    pub line: u64,
+    pub invoice_id: Uuid,
+    pub invoice_number: String,
+}
+
+/// A row skipped because its invoice was already imported.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedRow {
+    pub line: u64,
+
+    /// The invoice imported from the same row earlier
+    pub invoice_id: Uuid,
+}
+
+/// Validation report returned for an upload.
+///
+/// In a dry run, `imported` lists the invoices that would have been
+/// created; nothing is stored.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported: Vec<ImportedRow>,
+    pub skipped: Vec<SkippedRow>,
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Finds the column index of each field in the header row.
+///
+/// # Errors
+///
+/// Returns an error if the mapping names an unknown field or a missing
+/// header, or a required field (`client_name`, `total`) has no column.
+pub fn resolve_columns(
+    headers: &csv::StringRecord,
+    mapping: &HashMap<String, String>,
+) -> Result<HashMap<ImportField, usize>, String> {
+    let position = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+    };
+
+    let mut columns = HashMap::new();
+    for (field, header) in mapping {
+        let field = ImportField::from_str(field)?;
+        let index = position(header).ok_or_else(|| format!("column not found: {}", header))?;
+        columns.insert(field, index);
+    }
+    for field in ImportField::ALL {
+        if columns.contains_key(&field) {
+            continue;
+        }
+        match position(field.as_str()) {
+            Some(index) => {
+                columns.insert(field, index);
+            }
+            None if field.is_required() => return Err(format!("missing column for {}", field)),
+            None => {}
+        }
+    }
+
+    Ok(columns)
+}
+
+/// Parses and validates an uploaded CSV file.
+///
+/// # Arguments
+///
+/// * `data` - The file contents
+/// * `options` - Column mapping and date format
+/// * `default_currency` - Currency of rows without a currency column
+///
+/// # Returns
+///
+/// Returns the valid rows and every problem found in the others.
+///
+/// # Errors
+///
+/// Returns an error if the file as a whole can't be read: no header row,
+/// an unusable mapping, or more than [`MAX_IMPORT_ROWS`] rows.
+pub fn parse_csv(
+    data: &[u8],
+    options: &ImportOptions,
+    default_currency: Currency,
+) -> Result<ParsedImport, ImportRowError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| ImportRowError::file(format!("cannot read header row: {}", e)))?
+        .clone();
+    if headers.iter().all(|header| header.is_empty()) {
+        return Err(ImportRowError::file("the file has no header row"));
+    }
+
+    parse_records(&headers, reader.records(), options, default_currency)
+}
+
+/// Parses and validates already-read CSV records.
+///
+/// Used by [`parse_csv`] and by importers that translate another tool's
+/// export into this format first. Row numbers in errors come from each
+/// record's position.
+///
+/// # Errors
+///
+/// Returns an error if the mapping is unusable or there are more than
+/// [`MAX_IMPORT_ROWS`] rows.
+pub fn parse_records(
+    headers: &csv::StringRecord,
+    records: impl IntoIterator<Item = csv::Result<csv::StringRecord>>,
+    options: &ImportOptions,
+    default_currency: Currency,
+) -> Result<ParsedImport, ImportRowError> {
+    let columns = resolve_columns(headers, &options.mapping).map_err(ImportRowError::file)?;
+    let date_format = options.date_format.as_deref();
+    let locale = options.locale.as_ref();
+
+    let mut parsed = ParsedImport::default();
+    let mut count = 0;
+    for record in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line());
+                parsed.errors.push(ImportRowError::row(line, None, e.to_string()));
+                continue;
+            }
+        };
+        if record.iter().all(|value| value.is_empty()) {
+            continue;
+        }
+        count += 1;
+        if count > MAX_IMPORT_ROWS {
+            return Err(ImportRowError::file(format!(
+                "files may have at most {} rows",
+                MAX_IMPORT_ROWS
+            )));
+        }
+
+        let line = record.position().map_or(0, |position| position.line());
+        let value = |field: ImportField| {
+            columns
+                .get(&field)
+                .and_then(|index| record.get(*index))
+                .filter(|value| !value.is_empty())
+        };
+        match parse_row(line, value, date_format, locale, default_currency) {
+            Ok(row) => parsed.rows.push(row),
+            Err(errors) => parsed.errors.extend(errors),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Reads a date column.
+///
+/// Dates follow the upload's `chrono` format if it names one; otherwise
+/// they are read tolerantly in the upload's locale (see [`parse_date`]).
+pub fn parse_import_date(value: &str, date_format: Option<&str>, locale: Option<&Locale>) -> Result<NaiveDate, String> {
+    match date_format {
+        Some(format) => NaiveDate::parse_from_str(value, format)
+            .map_err(|_| format!("not a date in the format {}: {}", format, value)),
+        None => parse_date(value, locale),
+    }
+}
+
+/// Validates one row, reporting every problem in it.
+fn parse_row<'a>(
+    line: u64,
+    value: impl Fn(ImportField) -> Option<&'a str>,
+    date_format: Option<&str>,
+    locale: Option<&Locale>,
+    default_currency: Currency,
+) -> Result<ImportRow, Vec<ImportRowError>> {
+    let mut errors = Vec::new();
+    let mut error = |field: ImportField, message: String| {
+        errors.push(ImportRowError::row(line, Some(field), message));
+    };
+
+    let client_name = value(ImportField::ClientName).map(str::to_string);
+    if client_name.is_none() {
+        error(ImportField::ClientName, "is required".to_string());
+    }
+
+    let client_email = value(ImportField::ClientEmail).map(str::to_string);
+    if let Some(email) = &client_email {
+        let valid = email
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'));
+        if !valid {
+            error(ImportField::ClientEmail, format!("not a valid email address: {}", email));
+        }
+    }
+
+    let mut date = |field: ImportField| {
+        let value = value(field)?;
+        parse_import_date(value, date_format, locale)
+            .map_err(|message| error(field, message))
+            .ok()
+    };
+    let issue_date = date(ImportField::IssueDate);
+    let due_date = date(ImportField::DueDate);
+    if let (Some(issue), Some(due)) = (issue_date, due_date) {
+        if due < issue {
+            error(ImportField::DueDate, "must not be before issue_date".to_string());
+        }
+    }
+
+    let currency = match value(ImportField::Currency) {
+        Some(code) => Currency::parse(code)
+            .map_err(|e| error(ImportField::Currency, e.to_string()))
+            .ok(),
+        None => Some(default_currency),
+    };
+
+    let mut amount = |field: ImportField| -> Option<Decimal> {
+        let value = value(field)?;
+        match parse_decimal(value, locale) {
+            Ok(amount) if amount < Decimal::ZERO => {
+                error(field, "must not be negative".to_string());
+                None
+            }
+            Ok(amount) => {
+                if let Some(currency) = currency {
+                    if !Money::new(amount, currency).is_representable() {
+                        error(
+                            field,
+                            format!("{} amounts allow at most {} decimal places", currency, currency.minor_units()),
+                        );
+                    }
+                }
+                Some(amount)
+            }
+            Err(message) => {
+                error(field, message);
+                None
+            }
+        }
+    };
+    let total = amount(ImportField::Total);
+    let amount_paid = amount(ImportField::AmountPaid);
+    if value(ImportField::Total).is_none() {
+        error(ImportField::Total, "is required".to_string());
+    }
+
+    let status = match value(ImportField::Status) {
+        Some(status) => InvoiceStatus::from_str(status)
+            .map_err(|e| error(ImportField::Status, e.to_string()))
+            .ok(),
+        None => Some(InvoiceStatus::Draft),
+    };
+
+    // Paid invoices without an amount paid were paid in full
+    let amount_paid = match (amount_paid, status, total) {
+        (Some(paid), _, _) => paid,
+        (None, Some(InvoiceStatus::Paid), Some(total)) => total,
+        _ => Decimal::ZERO,
+    };
+    if let Some(total) = total {
+        if amount_paid > total {
+            error(ImportField::AmountPaid, "must not exceed total".to_string());
+        }
+    }
+
+    let (Some(client_name), Some(total), Some(currency), Some(status)) = (client_name, total, currency, status) else {
+        return Err(errors);
+    };
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut row = ImportRow {
+        line,
+        key: String::new(),
+        invoice_number: value(ImportField::InvoiceNumber).map(str::to_string),
+        client_name,
+        client_email,
+        description: value(ImportField::Description).map(str::to_string),
+        issue_date,
+        due_date,
+        total: Money::new(total, currency),
+        amount_paid,
+        status,
+    };
+    row.key = row_key(&row);
+    Ok(row)
+}
+
+/// 64-bit FNV-1a hash of a list of values.
+///
+/// Unlike the standard library's hasher it gives the same result across
+/// Rust releases, so it can be stored as an idempotency key.
+pub fn stable_hash(values: &[String]) -> u64 {
+    stable_hash_bytes(values.join("\u{1f}").as_bytes())
+}
+
+/// 64-bit FNV-1a hash of raw bytes, as [`stable_hash`] for data that may not
+/// be text (e.g. request bodies).
+pub fn stable_hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Idempotency key of a row: a stable hash of its values.
+pub fn row_key(row: &ImportRow) -> String {
+    let date = |date: Option<NaiveDate>| date.map(|d| d.to_string()).unwrap_or_default();
+    let values = [
+        row.invoice_number.clone().unwrap_or_default(),
+        row.client_name.clone(),
+        row.client_email.clone().unwrap_or_default().to_lowercase(),
+        row.description.clone().unwrap_or_default(),
+        date(row.issue_date),
+        date(row.due_date),
+        row.total.amount.normalize().to_string(),
+        row.total.currency.to_string(),
+        row.amount_paid.normalize().to_string(),
+        row.status.to_string(),
+    ];
+
+    format!("csv:{:016x}", stable_hash(&values))
+}
+
+/// Imports parsed rows as invoices.
+///
+/// Runs in one transaction under a per-user lock, so concurrent uploads of
+/// the same file can't both import a row. Rows whose key is already stored
+/// (or repeated earlier in the file) are skipped. Rows without an invoice
+/// number get the next one; given numbers are checked against the user's
+/// numbering policy and reported as row errors when taken. In a dry run
+/// the transaction is rolled back.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the importing user
+/// * `parsed` - Rows from [`parse_csv`], with their errors
+/// * `dry_run` - Whether to roll back instead of committing
+///
+/// # Returns
+///
+/// Returns the report of imported, skipped and rejected rows.
+pub async fn import_invoices(
+    pool: &PgPool,
+    user_id: Uuid,
+    parsed: ParsedImport,
+    dry_run: bool,
+) -> Result<ImportReport, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(format!("invoice_import:{}", user_id))
+        .execute(&mut *tx)
+        .await?;
+
+    let keys: Vec<&str> = parsed.rows.iter().map(|row| row.key.as_str()).collect();
+    let mut imported_keys: HashMap<String, Uuid> = sqlx::query_as::<_, (String, Uuid)>(
+        r#"
+        SELECT metadata #>> '{import,key}', id FROM invoices
+        WHERE user_id = $1 AND metadata #>> '{import,key}' = ANY($2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(&keys)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+
+    let mut report = ImportReport {
+        dry_run,
+        errors: parsed.errors,
+        ..ImportReport::default()
+    };
+    for row in parsed.rows {
+        if let Some(invoice_id) = imported_keys.get(&row.key) {
+            report.skipped.push(SkippedRow { line: row.line, invoice_id: *invoice_id });
+            continue;
+        }
+
+        let invoice_id = Uuid::new_v4();
+        let invoice_number = match &row.invoice_number {
+            Some(number) => match check_invoice_number(&mut tx, user_id, invoice_id, number).await {
+                Ok(()) => number.clone(),
+                Err(e) => {
+                    report
+                        .errors
+                        .push(ImportRowError::row(row.line, Some(ImportField::InvoiceNumber), e.to_string()));
+                    continue;
+                }
+            },
+            None => next_invoice_number(&mut tx, user_id).await?,
+        };
+
+        let invoice = insert_row(&mut tx, user_id, invoice_id, &invoice_number, &row).await?;
+        imported_keys.insert(row.key, invoice.id);
+        report.imported.push(ImportedRow {
+            line: row.line,
+            invoice_id: invoice.id,
+            invoice_number: invoice.invoice_number,
+        });
+    }
+    report.errors.sort_by_key(|error| error.line);
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+
+    Ok(report)
+}
+
+/// Inserts one imported row and records it for sync.
+async fn insert_row(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    invoice_number: &str,
+    row: &ImportRow,
+) -> Result<Invoice, anyhow::Error> {
+    let metadata = json!({
+        "import": {
+            "key": row.key,
+            "line": row.line,
+            "imported_at": Utc::now(),
+        }
+    });
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        INSERT INTO invoices (
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            description, metadata, subtotal, tax_total, total, amount_paid
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $6, 0, $6, $13)
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .bind(invoice_number)
+    .bind(&row.client_name)
+    .bind(&row.client_email)
+    .bind(row.total.amount)
+    .bind(row.total.currency.code())
+    .bind(row.status.as_str())
+    .bind(row.due_date)
+    .bind(row.issue_date.unwrap_or_else(|| Utc::now().date_naive()))
+    .bind(&row.description)
+    .bind(metadata)
+    .bind(row.amount_paid)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_server_change(
+        &mut **tx,
+        user_id,
+        "invoices",
+        invoice.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&invoice)?,
+    )
+    .await?;
+
+    Ok(invoice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd() -> Currency {
+        Currency::parse("USD").unwrap()
+    }
+
+    fn parse(csv: &str, options: &ImportOptions) -> ParsedImport {
+        parse_csv(csv.as_bytes(), options, usd()).unwrap()
+    }
+
+    #[test]
+    fn test_mapping_and_defaults() {
+        let options = ImportOptions {
+            mapping: HashMap::from([
+                ("client_name".to_string(), "Customer".to_string()),
+                ("total".to_string(), "Amount".to_string()),
+                ("issue_date".to_string(), "Date".to_string()),
+            ]),
+            date_format: Some("%d/%m/%Y".to_string()),
+            locale: None,
+            dry_run: false,
+        };
+        let parsed = parse(
+            "Customer,Amount,Date,currency,status\nAcme Ltd,\"1,250.50\",05/03/2024,eur,paid\nBeta,100,,,\n",
+            &options,
+        );
+
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let acme = &parsed.rows[0];
+        assert_eq!(acme.line, 2);
+        assert_eq!(acme.client_name, "Acme Ltd");
+        assert_eq!(acme.total, Money::parse(Decimal::new(125050, 2), "EUR").unwrap());
+        assert_eq!(acme.issue_date, NaiveDate::from_ymd_opt(2024, 3, 5));
+        assert_eq!(acme.status, InvoiceStatus::Paid);
+        assert_eq!(acme.amount_paid, acme.total.amount);
+
+        let beta = &parsed.rows[1];
+        assert_eq!(beta.total.currency, usd());
+        assert_eq!(beta.status, InvoiceStatus::Draft);
+        assert_eq!(beta.amount_paid, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_row_errors_are_reported_per_field() {
+        let parsed = parse(
+            "client_name,total,due_date,issue_date,client_email,currency\n\
+             Acme,100,2024-03-01,2024-03-10,billing@acme.test,USD\n\
+             ,abc,,,nobody,JPY\n\
+             Gamma,10.5,,,,JPY\n\
+             Delta,20,,,,USD\n",
+            &ImportOptions::default(),
+        );
+
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.rows[0].line, 5);
+        let found: Vec<(Option<u64>, Option<&str>)> = parsed
+            .errors
+            .iter()
+            .map(|error| (error.line, error.field.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Some(2), Some("due_date")),
+                (Some(3), Some("client_name")),
+                (Some(3), Some("client_email")),
+                (Some(3), Some("total")),
+                (Some(4), Some("total")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_locale_hint_and_ambiguous_values() {
+        let csv = "client_name,total,issue_date\nAcme,\"1.234,50\",05/03/2024\nBeta,\"1,500\",25/03/2024\n";
+
+        let parsed = parse(csv, &ImportOptions::default());
+        let found: Vec<(Option<u64>, Option<&str>)> = parsed
+            .errors
+            .iter()
+            .map(|error| (error.line, error.field.as_deref()))
+            .collect();
+        assert_eq!(found, vec![(Some(2), Some("issue_date")), (Some(3), Some("total"))]);
+        assert!(parsed.errors[0].error.starts_with("ambiguous date"));
+
+        let options = ImportOptions {
+            locale: Some(Locale::parse("de-DE").unwrap()),
+            ..ImportOptions::default()
+        };
+        let parsed = parse(csv, &options);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert_eq!(parsed.rows[0].total.amount, Decimal::new(123450, 2));
+        assert_eq!(parsed.rows[0].issue_date, NaiveDate::from_ymd_opt(2024, 3, 5));
+        assert_eq!(parsed.rows[1].total.amount, Decimal::new(15, 1));
+    }
+
+    #[test]
+    fn test_file_level_errors() {
+        let missing = parse_csv(b"client,amount\nAcme,10\n", &ImportOptions::default(), usd()).unwrap_err();
+        assert_eq!(missing.error, "missing column for client_name");
+
+        let options = ImportOptions {
+            mapping: HashMap::from([("client_name".to_string(), "Customer".to_string())]),
+            ..ImportOptions::default()
+        };
+        let unknown = parse_csv(b"client_name,total\nAcme,10\n", &options, usd()).unwrap_err();
+        assert_eq!(unknown.error, "column not found: Customer");
+    }
+
+    #[test]
+    fn test_row_key_is_stable_and_content_based() {
+        let parsed = parse(
+            "client_name,total\nAcme,100\nAcme,100.00\nAcme,101\n",
+            &ImportOptions::default(),
+        );
+        let keys: Vec<&str> = parsed.rows.iter().map(|row| row.key.as_str()).collect();
+        assert_eq!(keys[0], keys[1]);
+        assert_ne!(keys[0], keys[2]);
+        assert!(keys[0].starts_with("csv:"));
+        assert_eq!(keys[0].len(), 20);
+    }
+}
@@ -0,0 +1,322 @@
+//! Late fees charged when chasing escalates.
+//!
+//! The user's policy (see [`LateFeePolicy`]) is applied once per invoice,
+//! when the chase worker sends the firm reminder or, if the fee wasn't due
+//! yet, a later one. Invoices with line items get a "Late fee" line;
+//! amount-only invoices get a surcharge on their total. Either way the
+//! charge is recorded under `metadata.late_fee`, whose `announced_at` stays
+//! null until a reminder telling the client about it has gone out, so a
+//! fee whose reminder failed is announced by the next.
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde_json::{json, Map, Value};
+use sqlx::PgPool;
+
+use crate::currency::{minor_units, Percent};
+use crate::models::invoice::Invoice;
+use crate::models::line_item::{InvoiceTotals, LineItem, StoredLineItems};
+use crate::models::sync_change::SyncOperation;
+use crate::models::user_settings::{LateFeeKind, UserSettings};
+use crate::sync::server::record_server_change;
+
+/// Description of the line item added for a late fee.
+pub const LATE_FEE_DESCRIPTION: &str = "Late fee";
+
+/// A user's late fee policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LateFeePolicy {
+    /// How the fee is computed
+    pub kind: LateFeeKind,
+
+    /// Flat amount, or percentage of the balance due
+    pub amount: Decimal,
+
+    /// Days overdue before the fee applies
+    pub after_days: i64,
+}
+
+impl LateFeePolicy {
+    /// Extracts the late fee policy from a user's settings.
+    pub fn from_settings(settings: &UserSettings) -> Self {
+        Self {
+            kind: settings.late_fee_kind,
+            amount: settings.late_fee_amount,
+            after_days: i64::from(settings.late_fee_after_days),
+        }
+    }
+
+    /// Computes the fee for an overdue invoice.
+    ///
+    /// # Arguments
+    ///
+    /// * `balance_due` - Amount still owed on the invoice
+    /// * `currency` - Invoice currency, used to round the fee
+    /// * `days_overdue` - Days the invoice is overdue
+    ///
+    /// # Returns
+    ///
+    /// Returns the fee, or `None` if the policy charges nothing (no policy,
+    /// not overdue long enough, or nothing owed).
+    pub fn fee(&self, balance_due: Decimal, currency: &str, days_overdue: i64) -> Option<Decimal> {
+        if days_overdue < self.after_days || balance_due <= Decimal::ZERO {
+            return None;
+        }
+
+        let units = minor_units(currency).unwrap_or(2);
+        let fee = match self.kind {
+            LateFeeKind::None => return None,
+            LateFeeKind::Flat => self.amount,
+            LateFeeKind::Percentage => Percent::new(self.amount).of(balance_due),
+        }
+        .round_dp(units);
+
+        (fee > Decimal::ZERO).then_some(fee)
+    }
+}
+
+/// Whether a late fee was already charged on an invoice.
+pub fn has_late_fee(invoice: &Invoice) -> bool {
+    invoice
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("late_fee"))
+        .map_or(false, |fee| !fee.is_null())
+}
+
+/// The late fee charged on an invoice, if any.
+pub fn charged_late_fee(invoice: &Invoice) -> Option<Decimal> {
+    let amount = invoice.metadata.as_ref()?.get("late_fee")?.get("amount")?;
+    serde_json::from_value(amount.clone()).ok()
+}
+
+/// The late fee charged on an invoice that no reminder has announced yet.
+///
+/// Fees charged before announcements were recorded count as announced.
+pub fn unannounced_late_fee(invoice: &Invoice) -> Option<Decimal> {
+    let late_fee = invoice.metadata.as_ref()?.get("late_fee")?;
+    if !late_fee.get("announced_at").is_some_and(Value::is_null) {
+        return None;
+    }
+    charged_late_fee(invoice)
+}
+
+/// Metadata patch recording that the invoice's late fee was announced.
+///
+/// # Returns
+///
+/// Returns the patch for `metadata`, or `None` if no fee was charged.
+pub fn announced_patch(invoice: &Invoice, now: DateTime<Utc>) -> Option<Value> {
+    let mut late_fee = invoice.metadata.as_ref()?.get("late_fee")?.as_object()?.clone();
+    late_fee.insert("announced_at".to_string(), json!(now));
+    Some(json!({ "late_fee": late_fee }))
+}
+
+/// Sentence telling the client about a late fee, for chase emails.
+pub fn late_fee_notice(invoice: &Invoice, fee: Decimal) -> String {
+    format!(
+        "Please note that a late fee of {} {:.2} has been added to invoice {} because payment is overdue.",
+        invoice.currency, fee, invoice.invoice_number
+    )
+}
+
+/// Returns a copy of the invoice with a late fee charged.
+///
+/// Invoices with line items get a [`LATE_FEE_DESCRIPTION`] line and are
+/// re-totalled; others get the fee added to their totals. The charge is
+/// recorded under `metadata.late_fee`.
+pub fn with_late_fee(invoice: &Invoice, fee: Decimal, now: DateTime<Utc>) -> Invoice {
+    let existing_items = invoice.items().to_vec();
+
+    let mut charged = invoice.clone();
+    let mode = if existing_items.is_empty() {
+        charged.subtotal += fee;
+        charged.total += fee;
+        "surcharge"
+    } else {
+        let mut items = existing_items;
+        items.push(LineItem {
+            description: LATE_FEE_DESCRIPTION.to_string(),
+            quantity: Decimal::ONE,
+            unit_price: fee,
+            tax_rate: None,
+            tax_rate_id: None,
+        });
+        let totals = InvoiceTotals::compute(&items);
+        charged.line_items = Some(StoredLineItems(items));
+        charged.subtotal = totals.subtotal;
+        charged.tax_total = totals.tax_total;
+        charged.total = totals.total;
+        "line_item"
+    };
+    charged.amount = charged.total;
+
+    let mut metadata = match charged.metadata.take() {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    metadata.insert(
+        "late_fee".to_string(),
+        json!({
+            "amount": fee,
+            "currency": invoice.currency,
+            "mode": mode,
+            "applied_at": now,
+            "announced_at": null,
+        }),
+    );
+    charged.metadata = Some(Value::Object(metadata));
+    charged.last_modified = now;
+    charged.updated_at = now;
+
+    charged
+}
+
+/// Charges a late fee on an invoice.
+///
+/// Runs under a row lock and re-checks `metadata.late_fee`, so the fee is
+/// charged at most once even if several workers chase the same invoice.
+/// The updated invoice is recorded as a server sync change.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `invoice` - The invoice to charge
+/// * `fee` - Fee amount in the invoice's currency (see [`LateFeePolicy::fee`])
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or `None` if a late fee was already
+/// charged or the invoice no longer exists.
+pub async fn apply_late_fee(
+    pool: &PgPool,
+    invoice: &Invoice,
+    fee: Decimal,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let current = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE id = $1 AND is_deleted = false
+        FOR UPDATE
+        "#,
+    )
+    .bind(invoice.id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(current) = current.filter(|current| !has_late_fee(current)) else {
+        return Ok(None);
+    };
+
+    let charged = with_late_fee(&current, fee, Utc::now());
+
+    let updated = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET
+            line_items = $2,
+            subtotal = $3,
+            tax_total = $4,
+            total = $5,
+            amount = $5,
+            metadata = $6,
+            updated_at = NOW(),
+            last_modified = NOW()
+        WHERE id = $1
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(current.id)
+    .bind(charged.line_items)
+    .bind(charged.subtotal)
+    .bind(charged.tax_total)
+    .bind(charged.total)
+    .bind(charged.metadata)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        updated.user_id,
+        "invoices",
+        updated.id,
+        SyncOperation::Update,
+        &serde_json::to_value(&updated)?,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn policy(kind: LateFeeKind, amount: &str, after_days: i64) -> LateFeePolicy {
+        LateFeePolicy {
+            kind,
+            amount: Decimal::from_str(amount).unwrap(),
+            after_days,
+        }
+    }
+
+    #[test]
+    fn test_flat_fee_after_days() {
+        let policy = policy(LateFeeKind::Flat, "25", 14);
+        let balance = Decimal::from(400);
+        assert_eq!(policy.fee(balance, "EUR", 13), None);
+        assert_eq!(policy.fee(balance, "EUR", 14), Some(Decimal::from(25)));
+        assert_eq!(policy.fee(Decimal::ZERO, "EUR", 30), None);
+    }
+
+    #[test]
+    fn test_percentage_fee_rounds_to_currency() {
+        let policy = policy(LateFeeKind::Percentage, "1.5", 0);
+        assert_eq!(
+            policy.fee(Decimal::from_str("333.33").unwrap(), "USD", 7),
+            Some(Decimal::from_str("5.00").unwrap())
+        );
+        assert_eq!(policy.fee(Decimal::from(1234), "JPY", 7), Some(Decimal::from(19)));
+    }
+
+    #[test]
+    fn test_no_policy_charges_nothing() {
+        assert_eq!(policy(LateFeeKind::None, "50", 0).fee(Decimal::from(100), "USD", 30), None);
+    }
+
+    #[test]
+    fn test_fee_is_unannounced_until_a_reminder_mentions_it() {
+        let due = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let invoice = crate::repo::memory::sample_invoice(uuid::Uuid::new_v4(), due, Decimal::from(100));
+        assert_eq!(unannounced_late_fee(&invoice), None);
+
+        let mut charged = with_late_fee(&invoice, Decimal::from(25), Utc::now());
+        assert_eq!(unannounced_late_fee(&charged), Some(Decimal::from(25)));
+
+        let patch = announced_patch(&charged, Utc::now()).unwrap();
+        if let (Some(Value::Object(metadata)), Value::Object(patch)) = (charged.metadata.as_mut(), patch) {
+            metadata.extend(patch);
+        }
+        assert_eq!(unannounced_late_fee(&charged), None);
+        assert_eq!(charged_late_fee(&charged), Some(Decimal::from(25)));
+
+        // Charged before announcements were recorded
+        charged.metadata = Some(json!({ "late_fee": { "amount": "25" } }));
+        assert_eq!(unannounced_late_fee(&charged), None);
+    }
+}
@@ -0,0 +1,415 @@
+pub mod correspondence;
+pub mod due_dates;
+pub mod duplicate;
+pub mod einvoice;
+pub mod handlers;
+pub mod history;
+pub mod import;
+pub mod late_fees;
+pub mod numbering;
+pub mod payments;
+pub mod pdf;
+pub mod pdf_export;
+pub mod public;
+pub mod search;
+
+pub use due_dates::DueDateRules;
+pub use pdf::{render_invoice_pdf, PdfBranding};
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::invoices::history::apply_current_audit_context;
+use crate::models::chase_override::ChaseOverride;
+use crate::models::exchange_rate_override::ExchangeRateOverride;
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+
+/// Loads a single live invoice owned by the given user.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice
+///
+/// # Returns
+///
+/// Returns `Some(Invoice)` if found, `None` if it does not exist, is deleted,
+/// or belongs to another user.
+pub async fn find_invoice(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(invoice)
+}
+
+/// Sets or clears an invoice's chase override.
+///
+/// The updated invoice is recorded as a server sync change so other
+/// devices see the new policy. Chasing resumes for an invoice given up on
+/// after failed sends (see [`crate::chase::retries`]).
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice
+/// * `overrides` - New override, or `None` to restore the default policy
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or `None` if it does not exist.
+pub async fn set_chase_override(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    overrides: Option<&ChaseOverride>,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let value = overrides.map(serde_json::to_value).transpose()?;
+
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET chase_override = $3, metadata = metadata - 'chase_retry', last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .bind(value)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(invoice) = &invoice {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(invoice)?,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(invoice)
+}
+
+/// Lists a user's unpaid invoices whose reminders were given up on after
+/// failed sends (see [`crate::chase::retries`]), most recent failure first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+///
+/// # Returns
+///
+/// Returns the dead-lettered invoices, or an error.
+pub async fn dead_lettered_invoices(pool: &PgPool, user_id: Uuid) -> Result<Vec<Invoice>, anyhow::Error> {
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE user_id = $1
+            AND is_deleted = false
+            AND status != 'paid'
+            AND jsonb_typeof(metadata->'chase_retry') = 'object'
+            AND jsonb_typeof(metadata->'chase_retry'->'next_retry_at') = 'null'
+        ORDER BY metadata->'chase_retry'->>'failed_at' DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(invoices)
+}
+
+/// Clears an invoice's failed sends so the worker chases it again.
+///
+/// The updated invoice is recorded as a server sync change.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or `None` if it does not exist.
+pub async fn requeue_chase(pool: &PgPool, user_id: Uuid, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET metadata = metadata - 'chase_retry', last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(invoice) = &invoice {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(invoice)?,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(invoice)
+}
+
+/// Pauses an invoice's reminders until a given time, or resumes them.
+///
+/// The updated invoice is recorded as a server sync change so other
+/// devices see the pause.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice
+/// * `until` - When reminders resume, or `None` to resume them now
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or `None` if it does not exist.
+pub async fn set_chase_pause(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    until: Option<DateTime<Utc>>,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET chase_paused_until = $3, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .bind(until)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(invoice) = &invoice {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(invoice)?,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(invoice)
+}
+
+/// Sets or clears an invoice's exchange rate override.
+///
+/// The updated invoice is recorded as a server sync change so other
+/// devices see the new rate.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice
+/// * `rate` - New fixed rate, or `None` to use market rates again
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or `None` if it does not exist.
+pub async fn set_exchange_rate_override(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    rate: Option<&ExchangeRateOverride>,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let value = rate.map(serde_json::to_value).transpose()?;
+
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET exchange_rate_override = $3, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .bind(value)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(invoice) = &invoice {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "invoices",
+            invoice.id,
+            SyncOperation::Update,
+            &serde_json::to_value(invoice)?,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(invoice)
+}
+
+/// Moves an invoice to a new status.
+///
+/// The invoice is locked while the change is checked against
+/// [`InvoiceStatus::can_transition`], and the updated invoice is recorded
+/// as a server sync change.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `invoice_id` - ID of the invoice
+/// * `status` - Status to move to
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or `None` if it does not exist.
+///
+/// # Errors
+///
+/// Returns a `StatusError` (inside the `anyhow::Error`) if the invoice
+/// cannot move to `status`, or an error if a query fails.
+pub async fn set_invoice_status(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    status: InvoiceStatus,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let current = sqlx::query_scalar::<_, InvoiceStatus>(
+        "SELECT status FROM invoices WHERE id = $1 AND user_id = $2 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(current) = current else {
+        return Ok(None);
+    };
+    current.transition(status)?;
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET status = $3, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(user_id)
+    .bind(status)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        user_id,
+        "invoices",
+        invoice.id,
+        SyncOperation::Update,
+        &serde_json::to_value(&invoice)?,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(invoice))
+}
@@ -0,0 +1,244 @@
+//! Server-side invoice and estimate numbering.
+//!
+//! Devices choose their own numbers when they push; records created by the
+//! server (e.g. weekly drafts, converted estimates) continue the user's
+//! `INV-00042` / `EST-00042` style sequences instead. Whether the numbers of
+//! deleted invoices may be reused is the user's [`InvoiceNumberPolicy`],
+//! applied here and to pushed invoices via [`check_invoice_number`].
+
+use std::collections::HashSet;
+
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use crate::models::user_settings::InvoiceNumberPolicy;
+
+/// Prefix of server-generated invoice numbers.
+pub const INVOICE_NUMBER_PREFIX: &str = "INV-";
+
+/// Prefix of server-generated estimate numbers.
+pub const ESTIMATE_NUMBER_PREFIX: &str = "EST-";
+
+/// Zero-padded width of the numeric part.
+const SEQUENCE_WIDTH: usize = 5;
+
+/// Formats a sequence number with a prefix (e.g. `INV-00042`).
+pub fn format_number(prefix: &str, sequence: i64) -> String {
+    format!("{}{:0width$}", prefix, sequence, width = SEQUENCE_WIDTH)
+}
+
+/// Formats a sequence number as an invoice number (e.g. `INV-00042`).
+pub fn format_invoice_number(sequence: i64) -> String {
+    format_number(INVOICE_NUMBER_PREFIX, sequence)
+}
+
+/// Picks the next sequence number from the ones already used.
+///
+/// # Arguments
+///
+/// * `policy` - How numbers of deleted records are treated
+/// * `used` - Sequence numbers in use, each with whether its record is deleted
+///
+/// # Returns
+///
+/// Under [`InvoiceNumberPolicy::Block`], the number after the highest ever
+/// used. Under [`InvoiceNumberPolicy::Recycle`], the lowest number held only
+/// by deleted records, falling back to the number after the highest.
+pub fn next_sequence(policy: InvoiceNumberPolicy, used: &[(i64, bool)]) -> i64 {
+    let highest = used.iter().map(|(n, _)| *n).max().unwrap_or(0);
+
+    if policy == InvoiceNumberPolicy::Recycle {
+        let live: HashSet<i64> = used
+            .iter()
+            .filter(|(_, deleted)| !deleted)
+            .map(|(n, _)| *n)
+            .collect();
+        let freed = used
+            .iter()
+            .filter(|(n, deleted)| *deleted && !live.contains(n))
+            .map(|(n, _)| *n)
+            .min();
+        if let Some(freed) = freed {
+            return freed;
+        }
+    }
+
+    highest + 1
+}
+
+/// Loads a user's invoice number policy inside a transaction.
+async fn load_policy(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<InvoiceNumberPolicy, anyhow::Error> {
+    let policy = sqlx::query_scalar::<_, InvoiceNumberPolicy>(
+        "SELECT invoice_number_policy FROM user_settings WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(policy.unwrap_or_default())
+}
+
+/// Serializes numbering for a user and sequence until the transaction ends.
+async fn lock_numbering(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    column: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(format!("{}:{}", column, user_id))
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Returns the next free invoice number for a user.
+///
+/// Takes a transaction-scoped advisory lock per user so concurrent callers
+/// get distinct numbers; the number is only reserved once the caller's
+/// invoice is inserted in the same transaction. Numbers of deleted invoices
+/// are reused only under the user's `recycle` policy.
+///
+/// # Arguments
+///
+/// * `tx` - Transaction the invoice will be inserted in
+/// * `user_id` - ID of the invoice owner
+///
+/// # Returns
+///
+/// Returns the next `INV-<n>` number according to [`next_sequence`].
+pub async fn next_invoice_number(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<String, anyhow::Error> {
+    let policy = load_policy(tx, user_id).await?;
+    next_number(tx, user_id, "invoices", "invoice_number", INVOICE_NUMBER_PREFIX, policy).await
+}
+
+/// Returns the next free estimate number for a user.
+///
+/// Works like [`next_invoice_number`] over the `EST-<n>` sequence. Estimate
+/// numbers are never recycled.
+pub async fn next_estimate_number(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<String, anyhow::Error> {
+    next_number(
+        tx,
+        user_id,
+        "estimates",
+        "estimate_number",
+        ESTIMATE_NUMBER_PREFIX,
+        InvoiceNumberPolicy::Block,
+    )
+    .await
+}
+
+/// Returns the next `<prefix><n>` in a table's column under a policy.
+async fn next_number(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    table: &'static str,
+    column: &'static str,
+    prefix: &'static str,
+    policy: InvoiceNumberPolicy,
+) -> Result<String, anyhow::Error> {
+    lock_numbering(tx, user_id, column).await?;
+
+    let used: Vec<(i64, bool)> = sqlx::query_as(&format!(
+        r#"
+        SELECT substring({column} FROM $2)::BIGINT, is_deleted
+        FROM {table}
+        WHERE user_id = $1 AND {column} ~ $2
+        "#,
+        column = column,
+        table = table,
+    ))
+    .bind(user_id)
+    .bind(format!("^{}([0-9]{{1,18}})$", prefix))
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(format_number(prefix, next_sequence(policy, &used)))
+}
+
+/// Checks that an invoice may use a number under the user's policy.
+///
+/// Used for numbers chosen by devices. Takes the same lock as
+/// [`next_invoice_number`], so a pushed number and a server-generated one
+/// can't collide within concurrent transactions.
+///
+/// # Arguments
+///
+/// * `tx` - Transaction the invoice will be written in
+/// * `user_id` - ID of the invoice owner
+/// * `invoice_id` - ID of the invoice taking the number
+/// * `invoice_number` - The requested number
+///
+/// # Errors
+///
+/// Returns an error if another live invoice has the number, or a deleted
+/// invoice had it and the user's policy blocks reuse.
+pub async fn check_invoice_number(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    invoice_number: &str,
+) -> Result<(), anyhow::Error> {
+    lock_numbering(tx, user_id, "invoice_number").await?;
+
+    let holders: Vec<(bool,)> = sqlx::query_as(
+        r#"
+        SELECT is_deleted FROM invoices
+        WHERE user_id = $1 AND invoice_number = $2 AND id <> $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_number)
+    .bind(invoice_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    if holders.iter().any(|(deleted,)| !deleted) {
+        anyhow::bail!("Invoice number {} is already in use", invoice_number);
+    }
+    if !holders.is_empty() && load_policy(tx, user_id).await? == InvoiceNumberPolicy::Block {
+        anyhow::bail!(
+            "Invoice number {} belonged to a deleted invoice and can't be reused",
+            invoice_number
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_invoice_number_pads() {
+        assert_eq!(format_invoice_number(42), "INV-00042");
+        assert_eq!(format_invoice_number(123456), "INV-123456");
+        assert_eq!(format_number(ESTIMATE_NUMBER_PREFIX, 7), "EST-00007");
+    }
+
+    #[test]
+    fn test_next_sequence_block_skips_deleted_numbers() {
+        let used = [(1, false), (2, true), (3, true)];
+        assert_eq!(next_sequence(InvoiceNumberPolicy::Block, &used), 4);
+        assert_eq!(next_sequence(InvoiceNumberPolicy::Block, &[]), 1);
+    }
+
+    #[test]
+    fn test_next_sequence_recycle_fills_lowest_gap() {
+        let used = [(1, false), (2, true), (3, false), (4, true)];
+        assert_eq!(next_sequence(InvoiceNumberPolicy::Recycle, &used), 2);
+
+        // A number reused after deletion is live again
+        let used = [(1, false), (2, true), (2, false)];
+        assert_eq!(next_sequence(InvoiceNumberPolicy::Recycle, &used), 3);
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::anyhow;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::currency::Money;
+use crate::invoices::history::apply_current_audit_context;
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::payment::{CreatePayment, Payment};
+use crate::models::sync_change::SyncOperation;
+use crate::portal::INVOICE_COLUMNS;
+use crate::sync::server::record_server_change;
+
+/// Validates a payment against the invoice it settles.
+///
+/// Only invoices that may become paid accept payments, so drafts and
+/// cancelled invoices are rejected.
+///
+/// # Returns
+///
+/// Returns a user-facing message describing the first problem found.
+pub fn validate_payment(invoice: &Invoice, payment: &CreatePayment) -> Result<(), String> {
+    if !invoice.status.can_transition(InvoiceStatus::Paid) {
+        return Err(format!("cannot record a payment on a {} invoice", invoice.status));
+    }
+    let balance_due = invoice.balance_due_money().map_err(|e| e.to_string())?;
+    let amount = Money::new(payment.amount, balance_due.currency);
+    if !amount.is_positive() {
+        return Err("amount must be positive".to_string());
+    }
+    if !amount.is_representable() {
+        return Err(format!(
+            "amount must have at most {} decimal places",
+            amount.currency.minor_units()
+        ));
+    }
+    if amount.amount > balance_due.amount {
+        return Err(format!("amount exceeds balance due ({})", balance_due));
+    }
+    Ok(())
+}
+
+/// Loads an invoice and locks its row until the transaction ends.
+///
+/// Payments are checked against the locked invoice, so two concurrent
+/// payments can't both fit the same balance.
+///
+/// # Returns
+///
+/// Returns the locked `Invoice`, or `None` if it does not exist.
+pub async fn lock_invoice(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    invoice_id: Uuid,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let query = format!("SELECT {} FROM invoices WHERE id = $1 FOR UPDATE", INVOICE_COLUMNS);
+    let invoice = sqlx::query_as::<_, Invoice>(&query)
+        .bind(invoice_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    Ok(invoice)
+}
+
+/// Records a payment and updates the invoice's amount paid.
+///
+/// The payment is validated against the invoice as it is under the row
+/// lock, not as it was loaded. `amount_paid` is recomputed from all of the
+/// invoice's payments; once the balance reaches zero the invoice is marked
+/// paid and chasing stops. The payment and updated invoice are recorded as
+/// server sync changes.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `invoice` - The invoice being paid
+/// * `payment` - Payment details
+///
+/// # Returns
+///
+/// Returns the stored `Payment` and the updated `Invoice`, or a user-facing
+/// message if the payment doesn't fit the invoice (see
+/// [`validate_payment`]).
+pub async fn record_payment(
+    pool: &PgPool,
+    invoice: &Invoice,
+    payment: CreatePayment,
+) -> Result<Result<(Payment, Invoice), String>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    // Serialize concurrent payments on the same invoice
+    let locked = lock_invoice(&mut tx, invoice.id)
+        .await?
+        .ok_or_else(|| anyhow!("invoice {} not found", invoice.id))?;
+    if let Err(message) = validate_payment(&locked, &payment) {
+        return Ok(Err(message));
+    }
+
+    let stored = sqlx::query_as::<_, Payment>(
+        r#"
+        INSERT INTO payments (
+            user_id, invoice_id, amount, currency, paid_on, method, reference, notes
+        ) VALUES ($1, $2, $3, $4, COALESCE($5, CURRENT_DATE), $6, $7, $8)
+        RETURNING *
+        "#,
+    )
+    .bind(invoice.user_id)
+    .bind(invoice.id)
+    .bind(payment.amount)
+    .bind(&invoice.currency)
+    .bind(payment.paid_on)
+    .bind(payment.method)
+    .bind(payment.reference)
+    .bind(payment.notes)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        stored.user_id,
+        "payments",
+        stored.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&stored)?,
+    )
+    .await?;
+    let updated = refresh_amount_paid(&mut tx, invoice.id).await?;
+
+    tx.commit().await?;
+
+    Ok(Ok((stored, updated)))
+}
+
+/// Recomputes an invoice's amount paid from its payments.
+///
+/// Once the balance reaches zero the invoice is marked paid and chasing
+/// stops. The updated invoice is recorded as a server sync change.
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or an error.
+pub async fn refresh_amount_paid(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    invoice_id: Uuid,
+) -> Result<Invoice, anyhow::Error> {
+    let updated = sqlx::query_as::<_, Invoice>(
+        r#"
+        WITH paid AS (
+            SELECT COALESCE(SUM(amount), 0) AS amount_paid
+            FROM payments
+            WHERE invoice_id = $1
+        )
+        UPDATE invoices
+        SET amount_paid = paid.amount_paid,
+            status = CASE WHEN paid.amount_paid >= total THEN 'paid' ELSE status END,
+            metadata = CASE
+                WHEN paid.amount_paid >= total
+                    THEN jsonb_set(COALESCE(metadata, '{}'::jsonb), '{chase_state}', '"paid"')
+                ELSE metadata
+            END
+        FROM paid
+        WHERE invoices.id = $1
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_server_change(
+        &mut **tx,
+        updated.user_id,
+        "invoices",
+        updated.id,
+        SyncOperation::Update,
+        &serde_json::to_value(&updated)?,
+    )
+    .await?;
+
+    Ok(updated)
+}
+
+/// Lists an invoice's payments, oldest first.
+pub async fn list_payments(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_id: Uuid,
+) -> Result<Vec<Payment>, anyhow::Error> {
+    let payments = sqlx::query_as::<_, Payment>(
+        r#"
+        SELECT *
+        FROM payments
+        WHERE user_id = $1 AND invoice_id = $2
+        ORDER BY paid_on ASC, created_at ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::repo::memory::sample_invoice;
+
+    fn payment(amount: Decimal) -> CreatePayment {
+        CreatePayment { amount, paid_on: None, method: None, reference: None, notes: None }
+    }
+
+    #[test]
+    fn test_validate_payment_uses_currency_precision() {
+        let due = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let mut invoice = sample_invoice(Uuid::new_v4(), due, Decimal::new(10000, 2));
+        invoice.amount_paid = Decimal::from(40);
+
+        assert!(validate_payment(&invoice, &payment(Decimal::new(6000, 2))).is_ok());
+        assert_eq!(
+            validate_payment(&invoice, &payment(Decimal::new(6001, 2))).unwrap_err(),
+            "amount exceeds balance due (USD 60.00)"
+        );
+        assert!(validate_payment(&invoice, &payment(Decimal::new(1005, 3))).is_err());
+        assert!(validate_payment(&invoice, &payment(Decimal::ZERO)).is_err());
+
+        invoice.currency = "JPY".to_string();
+        assert_eq!(
+            validate_payment(&invoice, &payment(Decimal::new(105, 1))).unwrap_err(),
+            "amount must have at most 0 decimal places"
+        );
+    }
+}
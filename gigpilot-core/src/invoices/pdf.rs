@@ -0,0 +1,591 @@
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference};
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::clients::statement::ClientStatement;
+use crate::models::invoice::Invoice;
+use crate::models::line_item::{InvoiceTotals, LineItem};
+use crate::payment_methods::{instructions, methods_for_client};
+use crate::events::{DomainEvent, EventBus};
+use crate::invoices::import::stable_hash;
+use crate::logging::redact_name;
+use crate::storage::{BlobStore, DynBlobStore};
+
+/// A4 page width in millimetres
+const PAGE_WIDTH: f32 = 210.0;
+
+/// A4 page height in millimetres
+const PAGE_HEIGHT: f32 = 297.0;
+
+/// Left margin in millimetres
+const MARGIN: f32 = 20.0;
+
+/// Height of a single table row in millimetres
+const ROW_HEIGHT: f32 = 7.0;
+
+/// Bumped whenever the layout changes, so cached PDFs are rendered again.
+const PDF_CACHE_VERSION: u32 = 1;
+
+/// Branding shown in the invoice header and footer.
+#[derive(Debug, Clone, Default)]
+pub struct PdfBranding {
+    /// Business name shown at the top of the invoice
+    pub business_name: String,
+
+    /// Contact email shown under the business name
+    pub business_email: Option<String>,
+
+    /// Optional footer line (payment terms, registration number, etc.)
+    pub footer: Option<String>,
+
+    /// Payment instructions printed under the totals
+    pub payment_instructions: Vec<String>,
+}
+
+impl PdfBranding {
+    /// Loads branding for a user from their profile.
+    ///
+    /// The `INVOICE_PDF_FOOTER` environment variable can supply a footer line.
+    pub async fn for_user(pool: &PgPool, user_id: Uuid) -> Result<Self, anyhow::Error> {
+        let row = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT email, full_name FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        let (email, full_name) = row.ok_or_else(|| anyhow::anyhow!("User {} not found", user_id))?;
+
+        Ok(Self {
+            business_name: full_name.unwrap_or_else(|| email.clone()),
+            business_email: Some(email),
+            footer: std::env::var("INVOICE_PDF_FOOTER").ok(),
+            payment_instructions: Vec::new(),
+        })
+    }
+
+    /// Loads branding for an invoice, including the payment instructions
+    /// for its client.
+    pub async fn for_invoice(pool: &PgPool, invoice: &Invoice) -> Result<Self, anyhow::Error> {
+        let mut branding = Self::for_user(pool, invoice.user_id).await?;
+        let methods = methods_for_client(pool, invoice.user_id, invoice.client_email.as_deref()).await?;
+        branding.payment_instructions = instructions(&methods, &invoice.invoice_number);
+        Ok(branding)
+    }
+}
+
+/// Returns the invoice's printable line items.
+///
+/// Falls back to a single untaxed line for the invoice amount when no line
+/// items are stored.
+pub fn pdf_lines(invoice: &Invoice) -> Vec<LineItem> {
+    let lines = invoice.items().to_vec();
+
+    if lines.is_empty() {
+        vec![LineItem {
+            description: invoice
+                .description
+                .clone()
+                .unwrap_or_else(|| format!("Invoice {}", invoice.invoice_number)),
+            quantity: Decimal::ONE,
+            unit_price: invoice.amount,
+            tax_rate: None,
+            tax_rate_id: None,
+        }]
+    } else {
+        lines
+    }
+}
+
+/// Writes text onto a layer at the given position.
+fn text(layer: &PdfLayerReference, font: &IndirectFontRef, size: f32, x: f32, y: f32, value: &str) {
+    layer.use_text(value, size, Mm(x), Mm(y), font);
+}
+
+/// Adds a fresh page to the document and returns its layer.
+fn new_page(doc: &PdfDocumentReference) -> PdfLayerReference {
+    let (page, layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+    doc.get_page(page).get_layer(layer)
+}
+
+/// Renders an invoice to a PDF document.
+///
+/// The document contains the branding header, client details, a line-item
+/// table with per-line tax, subtotal/tax/total summary and the client's
+/// payment instructions. Long invoices continue onto additional pages.
+///
+/// # Arguments
+///
+/// * `invoice` - The invoice to render
+/// * `branding` - Business branding for the header and footer
+///
+/// # Returns
+///
+/// Returns the PDF file as bytes.
+///
+/// # Errors
+///
+/// Returns an error if the PDF cannot be assembled.
+pub fn render_invoice_pdf(invoice: &Invoice, branding: &PdfBranding) -> Result<Vec<u8>, anyhow::Error> {
+    let title = format!("Invoice {}", invoice.invoice_number);
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let mut layer = doc.get_page(page).get_layer(layer);
+
+    // Header: branding and invoice details
+    let mut y = PAGE_HEIGHT - MARGIN;
+    text(&layer, &bold, 18.0, MARGIN, y, &branding.business_name);
+    if let Some(email) = &branding.business_email {
+        y -= 6.0;
+        text(&layer, &regular, 10.0, MARGIN, y, email);
+    }
+
+    y -= 14.0;
+    text(&layer, &bold, 14.0, MARGIN, y, &title);
+    y -= 6.0;
+    text(&layer, &regular, 10.0, MARGIN, y, &format!("Issued: {}", invoice.issue_date));
+    if let Some(due_date) = invoice.due_date {
+        y -= 5.0;
+        text(&layer, &regular, 10.0, MARGIN, y, &format!("Due: {}", due_date));
+    }
+
+    // Client details
+    y -= 12.0;
+    text(&layer, &bold, 11.0, MARGIN, y, "Bill to");
+    y -= 6.0;
+    text(&layer, &regular, 10.0, MARGIN, y, &invoice.client_name);
+    if let Some(client_email) = &invoice.client_email {
+        y -= 5.0;
+        text(&layer, &regular, 10.0, MARGIN, y, client_email);
+    }
+
+    // Line item table
+    y -= 14.0;
+    let columns = [MARGIN, 110.0, 130.0, 155.0, 175.0];
+    for (x, heading) in columns.iter().zip(["Description", "Qty", "Unit price", "Tax %", "Amount"]) {
+        text(&layer, &bold, 10.0, *x, y, heading);
+    }
+
+    let lines = pdf_lines(invoice);
+    let totals = InvoiceTotals::compute(&lines);
+
+    for line in &lines {
+        y -= ROW_HEIGHT;
+        if y < MARGIN + 30.0 {
+            layer = new_page(&doc);
+            y = PAGE_HEIGHT - MARGIN;
+        }
+
+        let cells = [
+            line.description.clone(),
+            line.quantity.normalize().to_string(),
+            format!("{:.2}", line.unit_price),
+            line.tax_rate.unwrap_or_default().value().normalize().to_string(),
+            format!("{:.2}", line.net()),
+        ];
+        for (x, cell) in columns.iter().zip(cells.iter()) {
+            text(&layer, &regular, 10.0, *x, y, cell);
+        }
+    }
+
+    // Totals
+    y -= ROW_HEIGHT * 2.0;
+    let mut rows = vec![
+        ("Subtotal", totals.subtotal, &regular),
+        ("Tax", totals.tax_total, &regular),
+        ("Total", totals.total, &bold),
+    ];
+    if invoice.amount_paid > Decimal::ZERO {
+        rows.push(("Paid", invoice.amount_paid, &regular));
+        rows.push(("Balance due", invoice.balance_due(), &bold));
+    }
+    for (label, value, font) in rows {
+        text(&layer, font, 10.0, 140.0, y, label);
+        text(&layer, font, 10.0, 175.0, y, &format!("{} {:.2}", invoice.currency, value));
+        y -= ROW_HEIGHT;
+    }
+
+    if !branding.payment_instructions.is_empty() {
+        y -= ROW_HEIGHT;
+        if y < MARGIN + 10.0 + 5.0 * branding.payment_instructions.len() as f32 {
+            layer = new_page(&doc);
+            y = PAGE_HEIGHT - MARGIN;
+        }
+        text(&layer, &bold, 11.0, MARGIN, y, "How to pay");
+        for instruction in &branding.payment_instructions {
+            y -= 5.0;
+            text(&layer, &regular, 9.0, MARGIN, y, instruction);
+        }
+    }
+
+    if let Some(footer) = &branding.footer {
+        text(&layer, &regular, 8.0, MARGIN, MARGIN / 2.0, footer);
+    }
+
+    let bytes = doc.save_to_bytes()?;
+
+    info!(
+        "Rendered PDF for invoice {} ({} lines, {} bytes)",
+        invoice.invoice_number,
+        lines.len(),
+        bytes.len()
+    );
+
+    Ok(bytes)
+}
+
+/// Renders a client statement to a PDF document.
+///
+/// The document contains the branding header, the client, a table of the
+/// open invoices with what is left on each, the totals per currency and the
+/// client's payment instructions. Long statements continue onto additional
+/// pages.
+///
+/// # Arguments
+///
+/// * `statement` - The statement to render
+/// * `branding` - Business branding for the header and footer
+///
+/// # Returns
+///
+/// Returns the PDF file as bytes.
+///
+/// # Errors
+///
+/// Returns an error if the PDF cannot be assembled.
+pub fn render_statement_pdf(statement: &ClientStatement, branding: &PdfBranding) -> Result<Vec<u8>, anyhow::Error> {
+    let title = format!("Statement for {}", statement.client_name);
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let mut layer = doc.get_page(page).get_layer(layer);
+
+    // Header: branding and statement date
+    let mut y = PAGE_HEIGHT - MARGIN;
+    text(&layer, &bold, 18.0, MARGIN, y, &branding.business_name);
+    if let Some(email) = &branding.business_email {
+        y -= 6.0;
+        text(&layer, &regular, 10.0, MARGIN, y, email);
+    }
+
+    y -= 14.0;
+    text(&layer, &bold, 14.0, MARGIN, y, "Statement of account");
+    y -= 6.0;
+    text(&layer, &regular, 10.0, MARGIN, y, &format!("As of: {}", statement.as_of));
+
+    // Client details
+    y -= 12.0;
+    text(&layer, &bold, 11.0, MARGIN, y, "Client");
+    y -= 6.0;
+    text(&layer, &regular, 10.0, MARGIN, y, &statement.client_name);
+    if let Some(client_email) = &statement.client_email {
+        y -= 5.0;
+        text(&layer, &regular, 10.0, MARGIN, y, client_email);
+    }
+
+    // Invoice table
+    y -= 14.0;
+    let columns = [MARGIN, 60.0, 90.0, 120.0, 150.0, 175.0];
+    for (x, heading) in columns.iter().zip(["Invoice", "Issued", "Due", "Total", "Paid", "Balance"]) {
+        text(&layer, &bold, 10.0, *x, y, heading);
+    }
+
+    for line in &statement.invoices {
+        y -= ROW_HEIGHT;
+        if y < MARGIN + 30.0 {
+            layer = new_page(&doc);
+            y = PAGE_HEIGHT - MARGIN;
+        }
+
+        let due = match line.due_date {
+            Some(due_date) if line.days_overdue > 0 => format!("{} ({}d late)", due_date, line.days_overdue),
+            Some(due_date) => due_date.to_string(),
+            None => "On receipt".to_string(),
+        };
+        let cells = [
+            line.invoice_number.clone(),
+            line.issue_date.to_string(),
+            due,
+            format!("{:.2}", line.total),
+            format!("{:.2}", line.amount_paid),
+            format!("{} {:.2}", line.currency, line.balance),
+        ];
+        for (x, cell) in columns.iter().zip(cells.iter()) {
+            text(&layer, &regular, 10.0, *x, y, cell);
+        }
+    }
+
+    // Totals per currency
+    y -= ROW_HEIGHT;
+    for total in &statement.totals {
+        y -= ROW_HEIGHT;
+        if y < MARGIN + 20.0 {
+            layer = new_page(&doc);
+            y = PAGE_HEIGHT - MARGIN;
+        }
+        text(&layer, &bold, 10.0, 120.0, y, "Outstanding");
+        text(&layer, &bold, 10.0, 175.0, y, &format!("{} {:.2}", total.currency, total.outstanding));
+        if total.overdue > Decimal::ZERO {
+            y -= ROW_HEIGHT;
+            text(&layer, &regular, 10.0, 120.0, y, "of which overdue");
+            text(&layer, &regular, 10.0, 175.0, y, &format!("{} {:.2}", total.currency, total.overdue));
+        }
+    }
+
+    if !branding.payment_instructions.is_empty() {
+        y -= ROW_HEIGHT * 2.0;
+        if y < MARGIN + 10.0 + 5.0 * branding.payment_instructions.len() as f32 {
+            layer = new_page(&doc);
+            y = PAGE_HEIGHT - MARGIN;
+        }
+        text(&layer, &bold, 11.0, MARGIN, y, "How to pay");
+        for instruction in &branding.payment_instructions {
+            y -= 5.0;
+            text(&layer, &regular, 9.0, MARGIN, y, instruction);
+        }
+    }
+
+    if let Some(footer) = &branding.footer {
+        text(&layer, &regular, 8.0, MARGIN, MARGIN / 2.0, footer);
+    }
+
+    let bytes = doc.save_to_bytes()?;
+
+    info!(
+        "Rendered statement for {} ({} invoices, {} bytes)",
+        redact_name(&statement.client_name),
+        statement.invoices.len(),
+        bytes.len()
+    );
+
+    Ok(bytes)
+}
+
+/// Blob key of an invoice's cached PDF.
+pub fn pdf_cache_key(user_id: Uuid, invoice_id: Uuid) -> String {
+    format!("pdf-cache/{}/{}.pdf", user_id, invoice_id)
+}
+
+/// Version of what a rendered PDF shows.
+///
+/// Covers the fields printed on the invoice (the same ones the
+/// `notify_invoice_content_changed` trigger watches), its branding and the
+/// layout. Bookkeeping such as the chase state in `metadata` is left out,
+/// so chasing an invoice does not invalidate its PDF.
+pub fn pdf_version(invoice: &Invoice, branding: &PdfBranding) -> String {
+    let content = serde_json::json!([
+        invoice.invoice_number,
+        invoice.client_name,
+        invoice.client_email,
+        invoice.amount,
+        invoice.currency,
+        invoice.due_date,
+        invoice.issue_date,
+        invoice.description,
+        invoice.line_items,
+        invoice.subtotal,
+        invoice.tax_total,
+        invoice.total,
+        invoice.amount_paid,
+    ]);
+
+    let branding = serde_json::json!([
+        branding.business_name,
+        branding.business_email,
+        branding.footer,
+        branding.payment_instructions,
+    ]);
+
+    // Stable across builds, so cached PDFs survive a deploy
+    let hash = stable_hash(&[PDF_CACHE_VERSION.to_string(), content.to_string(), branding.to_string()]);
+    format!("{:016x}", hash)
+}
+
+/// Renders an invoice to a PDF, reusing the cached copy of this version.
+///
+/// Each invoice has one cache entry holding its version line followed by
+/// the PDF, overwritten when a different version is rendered. The cache is
+/// best effort: storage failures are logged and the PDF is rendered anyway.
+///
+/// # Arguments
+///
+/// * `store` - Blob storage holding the cache
+/// * `invoice` - The invoice to render
+/// * `branding` - Business branding for the header and footer
+///
+/// # Errors
+///
+/// Returns an error if the PDF cannot be assembled.
+pub async fn cached_invoice_pdf(
+    store: &dyn BlobStore,
+    invoice: &Invoice,
+    branding: &PdfBranding,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let key = pdf_cache_key(invoice.user_id, invoice.id);
+    let version = format!("{}\n", pdf_version(invoice, branding));
+
+    if let Ok(cached) = store.get(&key).await {
+        if let Some(pdf) = cached.strip_prefix(version.as_bytes()) {
+            debug!("Serving cached PDF for invoice {}", invoice.id);
+            return Ok(pdf.to_vec());
+        }
+    }
+
+    let pdf = render_invoice_pdf(invoice, branding)?;
+
+    let mut entry = version.into_bytes();
+    entry.extend_from_slice(&pdf);
+    if let Err(e) = store.put(&key, "application/octet-stream", &entry).await {
+        warn!("Failed to cache PDF for invoice {}: {}", invoice.id, e);
+    }
+
+    Ok(pdf)
+}
+
+/// Spawns a task dropping cached PDFs as invoice content changes.
+///
+/// Entries are also checked against the invoice version when read, so a
+/// missed event only leaves an unused entry behind until the next render.
+pub fn spawn_pdf_cache_invalidation(bus: &EventBus, store: DynBlobStore) {
+    let mut receiver = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(DomainEvent::InvoiceContentChanged { user_id, invoice_id }) => {
+                    if let Err(e) = store.delete(&pdf_cache_key(user_id, invoice_id)).await {
+                        warn!("Failed to drop cached PDF for invoice {}: {}", invoice_id, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("PDF cache missed {} events", missed);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+    use serde_json::{json, Value};
+
+    use crate::models::invoice::InvoiceStatus;
+    use crate::models::line_item::StoredLineItems;
+
+    fn sample_invoice(line_items: Option<Value>) -> Invoice {
+        let line_items = line_items.map(|raw| StoredLineItems::from_value(&raw));
+        Invoice {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            invoice_number: "INV-001".to_string(),
+            client_name: "Acme Corp".to_string(),
+            client_email: Some("billing@acme.test".to_string()),
+            client_id: None,
+            project_id: None,
+            amount: Decimal::new(15000, 2),
+            currency: "EUR".to_string(),
+            status: InvoiceStatus::Sent,
+            due_date: NaiveDate::from_ymd_opt(2024, 2, 1),
+            issue_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            description: Some("Logo design".to_string()),
+            line_items,
+            subtotal: Decimal::new(15000, 2),
+            tax_total: Decimal::ZERO,
+            total: Decimal::new(15000, 2),
+            amount_paid: Decimal::ZERO,
+            chase_override: None,
+            exchange_rate_override: None,
+            chase_paused_until: None,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_lines_fall_back_to_invoice_amount() {
+        let lines = pdf_lines(&sample_invoice(None));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].description, "Logo design");
+        assert_eq!(lines[0].net(), Decimal::new(15000, 2));
+    }
+
+    #[test]
+    fn test_lines_compute_tax() {
+        let invoice = sample_invoice(Some(json!([
+            { "description": "Design", "quantity": 2, "unit_price": "50.00", "tax_rate": "20" },
+            { "description": "Hosting", "unit_price": 10 }
+        ])));
+        let lines = pdf_lines(&invoice);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].tax(), Decimal::new(2000, 2));
+        assert_eq!(lines[1].quantity, Decimal::ONE);
+    }
+
+    #[test]
+    fn test_malformed_lines_fall_back_to_amount() {
+        let lines = pdf_lines(&sample_invoice(Some(json!([{ "description": "no price" }]))));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].unit_price, Decimal::new(15000, 2));
+    }
+
+    #[test]
+    fn test_render_produces_pdf() {
+        let branding = PdfBranding {
+            business_name: "Jane Freelancer".to_string(),
+            business_email: Some("jane@example.com".to_string()),
+            footer: None,
+            payment_instructions: vec!["Bank transfer to Jane Freelancer, IBAN GB82 WEST 1234 5698 7654 32".to_string()],
+        };
+        let bytes = render_invoice_pdf(&sample_invoice(None), &branding).expect("Should render");
+        assert!(bytes.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn test_render_statement_produces_pdf() {
+        let invoice = sample_invoice(None);
+        let statement = crate::clients::statement::build_statement(
+            None,
+            &invoice.client_name,
+            invoice.client_email.as_deref(),
+            invoice.issue_date,
+            &[invoice.clone()],
+        );
+        let bytes = render_statement_pdf(&statement, &PdfBranding::default()).expect("Should render");
+        assert!(bytes.starts_with(b"%PDF"));
+    }
+
+    #[tokio::test]
+    async fn test_cached_pdf_follows_invoice_version() {
+        let store = crate::storage::MemoryBlobStore::new();
+        let branding = PdfBranding::default();
+        let mut invoice = sample_invoice(None);
+        let key = pdf_cache_key(invoice.user_id, invoice.id);
+
+        let first = cached_invoice_pdf(&store, &invoice, &branding).await.unwrap();
+        assert!(first.starts_with(b"%PDF"));
+
+        // Chasing only touches metadata: the cached copy is served
+        invoice.metadata = Some(json!({ "chase_state": "reminder_sent" }));
+        let entry = store.get(&key).await.unwrap();
+        assert!(entry.starts_with(pdf_version(&invoice, &branding).as_bytes()));
+        assert_eq!(cached_invoice_pdf(&store, &invoice, &branding).await.unwrap(), first);
+
+        // A new amount paid is printed, so it is rendered again
+        invoice.amount_paid = Decimal::new(5000, 2);
+        let second = cached_invoice_pdf(&store, &invoice, &branding).await.unwrap();
+        let entry = store.get(&key).await.unwrap();
+        assert!(entry.starts_with(pdf_version(&invoice, &branding).as_bytes()));
+        assert!(entry.ends_with(&second));
+    }
+}
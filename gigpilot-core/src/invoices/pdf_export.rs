@@ -0,0 +1,401 @@
+//! Bulk invoice PDF exports.
+//!
+//! Users hand a period's invoices to their tax advisor as one ZIP: a PDF
+//! per invoice plus an `index.csv` listing them. Rendering can take a
+//! while, so the export runs as a job in the worker; clients poll the job
+//! for its progress and download the archive once it is completed. PDFs
+//! come from the same cache as single downloads, so invoices rendered
+//! before are not rendered again.
+
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use tracing::info;
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::invoices::pdf::{cached_invoice_pdf, PdfBranding};
+use crate::models::invoice::Invoice;
+use crate::models::pdf_export_job::{PdfExportFilter, PdfExportJob};
+use crate::portal::INVOICE_COLUMNS;
+use crate::storage::BlobStore;
+
+/// Most invoices one export may contain.
+pub const MAX_EXPORT_INVOICES: i64 = 2000;
+
+/// Days a finished archive stays available.
+pub const EXPORT_RETENTION_DAYS: i64 = 7;
+
+/// Invoices rendered between progress updates.
+const PROGRESS_STEP: usize = 10;
+
+/// Invoices of user `$1` matching a filter (`$2`..`$5`, see [`bind_filter`]).
+const EXPORT_INVOICES: &str = r#"
+    user_id = $1 AND is_deleted = false
+    AND ($2::date IS NULL OR issue_date >= $2)
+    AND ($3::date IS NULL OR issue_date <= $3)
+    AND ((cardinality($4::varchar[]) = 0 AND status <> 'draft') OR status = ANY($4))
+    AND ($5::uuid IS NULL OR client_id = $5)
+"#;
+
+/// Validates an export filter.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the filter is valid, or a message describing the
+/// first problem found.
+pub fn validate_filter(filter: &PdfExportFilter) -> Result<(), String> {
+    if let (Some(from), Some(to)) = (filter.from, filter.to) {
+        if from > to {
+            return Err("from must not be after to".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Binds a filter's parameters after the user ID.
+fn bind_filter<'q, O>(
+    query: sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments>,
+    user_id: Uuid,
+    filter: &PdfExportFilter,
+) -> sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments> {
+    let statuses: Vec<String> = filter.statuses.iter().map(|status| status.as_str().to_string()).collect();
+    query
+        .bind(user_id)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(statuses)
+        .bind(filter.client_id)
+}
+
+/// Counts the invoices an export would contain.
+pub async fn count_matching(pool: &PgPool, user_id: Uuid, filter: &PdfExportFilter) -> Result<i64, anyhow::Error> {
+    let query = format!("SELECT COUNT(*) FROM invoices WHERE {}", EXPORT_INVOICES);
+    let (count,) = bind_filter(sqlx::query_as::<_, (i64,)>(&query), user_id, filter)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Loads the invoices an export contains, oldest first.
+///
+/// At most one more than [`MAX_EXPORT_INVOICES`] are loaded, enough to tell
+/// that the filter matches too many.
+async fn matching_invoices(pool: &PgPool, user_id: Uuid, filter: &PdfExportFilter) -> Result<Vec<Invoice>, anyhow::Error> {
+    let query = format!(
+        "SELECT {} FROM invoices WHERE {} ORDER BY issue_date ASC, invoice_number ASC LIMIT {}",
+        INVOICE_COLUMNS,
+        EXPORT_INVOICES,
+        MAX_EXPORT_INVOICES + 1
+    );
+    let invoices = bind_filter(sqlx::query_as::<_, Invoice>(&query), user_id, filter)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(invoices)
+}
+
+/// Queues an export.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `filter` - Which invoices to export (see [`validate_filter`])
+/// * `total` - Number of matching invoices (see [`count_matching`])
+///
+/// # Returns
+///
+/// Returns the queued job, or an error.
+pub async fn create_job(
+    pool: &PgPool,
+    user_id: Uuid,
+    filter: &PdfExportFilter,
+    total: i64,
+) -> Result<PdfExportJob, anyhow::Error> {
+    let job = sqlx::query_as::<_, PdfExportJob>(
+        r#"
+        INSERT INTO pdf_export_jobs (user_id, filter, total)
+        VALUES ($1, $2, $3)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(serde_json::to_value(filter)?)
+    .bind(i32::try_from(total)?)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Loads one of a user's export jobs.
+pub async fn find_job(pool: &PgPool, user_id: Uuid, job_id: Uuid) -> Result<Option<PdfExportJob>, anyhow::Error> {
+    let job = sqlx::query_as::<_, PdfExportJob>("SELECT * FROM pdf_export_jobs WHERE id = $1 AND user_id = $2")
+        .bind(job_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(job)
+}
+
+/// Blob key of a job's archive.
+pub fn archive_key(user_id: Uuid, job_id: Uuid) -> String {
+    format!("exports/{}/{}.zip", user_id, job_id)
+}
+
+/// File name of a job's archive, e.g. `invoices-2024-01-01-to-2024-12-31.zip`.
+pub fn archive_name(filter: &PdfExportFilter) -> String {
+    match (filter.from, filter.to) {
+        (Some(from), Some(to)) => format!("invoices-{}-to-{}.zip", from, to),
+        (Some(from), None) => format!("invoices-from-{}.zip", from),
+        (None, Some(to)) => format!("invoices-to-{}.zip", to),
+        (None, None) => "invoices.zip".to_string(),
+    }
+}
+
+/// File name of an invoice's PDF inside the archive.
+///
+/// Named by issue date and number so the files sort chronologically;
+/// characters that are unsafe in file names are replaced, and names
+/// already taken get a numeric suffix.
+pub fn pdf_file_name(invoice: &Invoice, taken: &mut HashSet<String>) -> String {
+    let number: String = invoice
+        .invoice_number
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let stem = format!("{}_{}", invoice.issue_date, number);
+
+    let mut name = format!("{}.pdf", stem);
+    let mut suffix = 2;
+    while !taken.insert(name.clone()) {
+        name = format!("{}_{}.pdf", stem, suffix);
+        suffix += 1;
+    }
+    name
+}
+
+/// Builds the archive's `index.csv`: one row per exported invoice.
+///
+/// # Arguments
+///
+/// * `rows` - Each invoice with its file name in the archive
+///
+/// # Returns
+///
+/// Returns the CSV document, or an error if it cannot be written.
+pub fn index_csv(rows: &[(&Invoice, String)]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "file", "invoice_number", "issue_date", "due_date", "client", "status", "currency", "subtotal", "tax_total",
+        "total", "amount_paid",
+    ])?;
+    for (invoice, file) in rows {
+        writer.write_record([
+            file.clone(),
+            invoice.invoice_number.clone(),
+            invoice.issue_date.to_string(),
+            invoice.due_date.map(|date| date.to_string()).unwrap_or_default(),
+            invoice.client_name.clone(),
+            invoice.status.as_str().to_string(),
+            invoice.currency.clone(),
+            invoice.subtotal.to_string(),
+            invoice.tax_total.to_string(),
+            invoice.total.to_string(),
+            invoice.amount_paid.to_string(),
+        ])?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Records how many invoices a job has rendered.
+async fn record_progress(pool: &PgPool, job_id: Uuid, processed: usize) -> Result<(), anyhow::Error> {
+    sqlx::query("UPDATE pdf_export_jobs SET processed = $2 WHERE id = $1")
+        .bind(job_id)
+        .bind(i32::try_from(processed)?)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Renders a job's invoices and stores the archive.
+///
+/// Invoices are selected again when the job runs, so the archive reflects
+/// them as they are then; `total` is corrected if it changed since the job
+/// was created. If more than [`MAX_EXPORT_INVOICES`] match by then, the
+/// job fails rather than leave some out.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `store` - Blob storage for the PDF cache and the archive
+/// * `job` - The job to run
+///
+/// # Returns
+///
+/// Returns the blob key of the stored archive.
+///
+/// # Errors
+///
+/// Returns an error if too many invoices match, a PDF can't be rendered,
+/// the archive can't be stored or a database query fails.
+pub async fn run_job(pool: &PgPool, store: &dyn BlobStore, job: &PdfExportJob) -> Result<String, anyhow::Error> {
+    let filter = job.parsed_filter()?;
+    let invoices = matching_invoices(pool, job.user_id, &filter).await?;
+    if i64::try_from(invoices.len())? > MAX_EXPORT_INVOICES {
+        anyhow::bail!(
+            "more than {} invoices match the filter now; narrow it down to export them",
+            MAX_EXPORT_INVOICES
+        );
+    }
+
+    sqlx::query("UPDATE pdf_export_jobs SET total = $2, processed = 0 WHERE id = $1")
+        .bind(job.id)
+        .bind(i32::try_from(invoices.len())?)
+        .execute(pool)
+        .await?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // PDFs are compressed already
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut taken = HashSet::new();
+    let mut rows = Vec::with_capacity(invoices.len());
+    for (index, invoice) in invoices.iter().enumerate() {
+        let branding = PdfBranding::for_invoice(pool, invoice).await?;
+        let pdf = cached_invoice_pdf(store, invoice, &branding).await?;
+
+        let file = pdf_file_name(invoice, &mut taken);
+        zip.start_file(file.as_str(), stored)?;
+        zip.write_all(&pdf)?;
+        rows.push((invoice, file));
+
+        let processed = index + 1;
+        if processed % PROGRESS_STEP == 0 {
+            record_progress(pool, job.id, processed).await?;
+        }
+    }
+
+    zip.start_file("index.csv", deflated)?;
+    zip.write_all(&index_csv(&rows)?)?;
+    let archive = zip.finish()?.into_inner();
+
+    let key = archive_key(job.user_id, job.id);
+    store.put(&key, "application/zip", &archive).await?;
+    record_progress(pool, job.id, invoices.len()).await?;
+
+    info!(
+        "Exported {} invoice PDFs for user {} ({} bytes)",
+        invoices.len(),
+        job.user_id,
+        archive.len()
+    );
+
+    Ok(key)
+}
+
+/// When a finished archive stops being available.
+pub fn archive_expiry() -> chrono::DateTime<Utc> {
+    Utc::now() + Duration::days(EXPORT_RETENTION_DAYS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::models::invoice::InvoiceStatus;
+    use crate::test_support::date;
+
+    fn invoice(number: &str, issue_date: NaiveDate) -> Invoice {
+        let now = Utc::now();
+        Invoice {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            invoice_number: number.to_string(),
+            client_name: "Acme, Ltd".to_string(),
+            client_email: None,
+            amount: Decimal::new(12000, 2),
+            currency: "EUR".to_string(),
+            status: InvoiceStatus::Paid,
+            due_date: None,
+            issue_date,
+            last_modified: now,
+            version_vector: None,
+            is_deleted: false,
+            description: None,
+            line_items: None,
+            subtotal: Decimal::new(10000, 2),
+            tax_total: Decimal::new(2000, 2),
+            total: Decimal::new(12000, 2),
+            amount_paid: Decimal::new(12000, 2),
+            client_id: None,
+            project_id: None,
+            chase_override: None,
+            exchange_rate_override: None,
+            chase_paused_until: None,
+            metadata: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_validate_filter() {
+        let mut filter = PdfExportFilter {
+            from: Some(date(2024, 1, 1)),
+            to: Some(date(2024, 12, 31)),
+            ..Default::default()
+        };
+        assert!(validate_filter(&filter).is_ok());
+
+        filter.from = Some(date(2025, 1, 1));
+        assert!(validate_filter(&filter).is_err());
+        assert!(validate_filter(&PdfExportFilter::default()).is_ok());
+    }
+
+    #[test]
+    fn test_pdf_file_names_are_safe_and_unique() {
+        let mut taken = HashSet::new();
+        let first = invoice("INV/2024 001", date(2024, 3, 5));
+
+        assert_eq!(pdf_file_name(&first, &mut taken), "2024-03-05_INV_2024_001.pdf");
+        assert_eq!(pdf_file_name(&first, &mut taken), "2024-03-05_INV_2024_001_2.pdf");
+        assert_eq!(pdf_file_name(&invoice("../x", date(2024, 3, 5)), &mut taken), "2024-03-05____x.pdf");
+    }
+
+    #[test]
+    fn test_index_lists_every_invoice() {
+        let invoice = invoice("INV-7", date(2024, 3, 5));
+        let csv = String::from_utf8(index_csv(&[(&invoice, "2024-03-05_INV-7.pdf".to_string())]).unwrap()).unwrap();
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("file,invoice_number,issue_date"));
+        assert_eq!(
+            lines[1],
+            "2024-03-05_INV-7.pdf,INV-7,2024-03-05,,\"Acme, Ltd\",paid,EUR,100.00,20.00,120.00,120.00"
+        );
+    }
+
+    #[test]
+    fn test_archive_name() {
+        let filter = PdfExportFilter {
+            from: Some(date(2024, 1, 1)),
+            to: Some(date(2024, 12, 31)),
+            ..Default::default()
+        };
+        assert_eq!(archive_name(&filter), "invoices-2024-01-01-to-2024-12-31.zip");
+        assert_eq!(archive_name(&PdfExportFilter::default()), "invoices.zip");
+    }
+}
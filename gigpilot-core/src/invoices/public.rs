@@ -0,0 +1,126 @@
+//! Public pay page for invoices.
+//!
+//! The page at `/pay/:id` is linked from invoice and reminder emails and
+//! needs no login; the unguessable invoice ID acts as the access token, so
+//! it only shows what the client already received. Paying by card goes
+//! through a form posting to `/pay/:id/card`, which holds chasing for a
+//! while so the client isn't reminded while the payment is on its way. It
+//! is a POST so that link previews and prefetchers don't start payments.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::invoice::Invoice;
+use crate::payment_methods::is_stripe_link;
+
+/// Loads an invoice that may be shown on the public pay page.
+///
+/// Drafts, cancelled and deleted invoices are never shown.
+///
+/// # Returns
+///
+/// Returns `Some(Invoice)` if the invoice exists and is payable.
+pub async fn find_public_invoice(pool: &PgPool, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error> {
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE id = $1 AND is_deleted = false AND status NOT IN ('draft', 'cancelled')
+        "#,
+    )
+    .bind(invoice_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(invoice)
+}
+
+/// Escapes text for inclusion in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Path the "pay by card" button of an invoice posts to.
+///
+/// It puts chasing on hold before sending the client on to Stripe (see
+/// `chase::holds`).
+pub fn card_payment_path(invoice_id: Uuid) -> String {
+    format!("/pay/{}/card", invoice_id)
+}
+
+/// Wraps `https://` URLs in an instruction line in links; Stripe links
+/// become a button posting to [`card_payment_path`].
+fn linkify(line: &str, invoice_id: Uuid) -> String {
+    line.split(' ')
+        .map(|word| {
+            if is_stripe_link(word) {
+                format!(
+                    "<form method=\"post\" action=\"{}\" style=\"display:inline\"><button type=\"submit\">{}</button></form>",
+                    card_payment_path(invoice_id),
+                    escape(word)
+                )
+            } else if word.starts_with("https://") {
+                format!("<a href=\"{0}\">{0}</a>", escape(word))
+            } else {
+                escape(word)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Renders the public pay page.
+///
+/// # Arguments
+///
+/// * `invoice` - The invoice being paid
+/// * `business_name` - Name of the business being paid
+/// * `instructions` - Payment instruction lines for the client
+///
+/// # Returns
+///
+/// Returns the HTML document as a string.
+pub fn render_pay_page(invoice: &Invoice, business_name: &str, instructions: &[String]) -> String {
+    let balance_due = invoice.balance_due();
+    let summary = if balance_due.is_zero() {
+        "<p><strong>This invoice has been paid. Thank you!</strong></p>\n".to_string()
+    } else {
+        let due = invoice
+            .due_date
+            .map(|d| format!(", due on {}", d))
+            .unwrap_or_default();
+        format!(
+            "<p>Amount due: <strong>{} {:.2}</strong>{}</p>\n",
+            escape(&invoice.currency),
+            balance_due,
+            due
+        )
+    };
+
+    let how_to_pay = if balance_due.is_zero() || instructions.is_empty() {
+        String::new()
+    } else {
+        let items: String = instructions
+            .iter()
+            .map(|line| format!("<li>{}</li>\n", linkify(line, invoice.id)))
+            .collect();
+        format!("<h2>How to pay</h2>\n<ul>\n{}</ul>\n", items)
+    };
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Invoice {0}</title></head>\n<body>\n<h1>Invoice {0}</h1>\n<p>From {1} to {2}</p>\n{3}{4}</body></html>\n",
+        escape(&invoice.invoice_number),
+        escape(business_name),
+        escape(&invoice.client_name),
+        summary,
+        how_to_pay
+    )
+}
@@ -0,0 +1,102 @@
+//! Full-text invoice search.
+//!
+//! Invoices carry a generated `search_vector` over their number, client
+//! name and description. Queries are free text ("that logo invoice for
+//! Acme"): every word is matched as a prefix and results are ranked by how
+//! many words match and where, so loosely worded queries still find the
+//! invoice.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::invoice::Invoice;
+
+/// Results returned when the request does not say.
+pub const DEFAULT_SEARCH_LIMIT: i64 = 20;
+
+/// Most results returned per search.
+pub const MAX_SEARCH_LIMIT: i64 = 100;
+
+/// Longest query accepted.
+pub const MAX_QUERY_LENGTH: usize = 200;
+
+/// Turns free text into a `to_tsquery` expression.
+///
+/// Words are split on anything that is not a letter or digit and matched
+/// as prefixes, any of them being enough for a result. Operators typed by
+/// the user are dropped rather than interpreted.
+///
+/// # Returns
+///
+/// Returns `None` if the text contains no searchable words.
+pub fn tsquery(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect();
+
+    (!words.is_empty()).then(|| words.join(" | "))
+}
+
+/// Searches a user's invoices.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `query` - `to_tsquery` expression built with [`tsquery`]
+/// * `limit` - Maximum number of results
+///
+/// # Returns
+///
+/// Returns matching live invoices, best match first (newest first on ties).
+pub async fn search_invoices(
+    pool: &PgPool,
+    user_id: Uuid,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<Invoice>, anyhow::Error> {
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices, to_tsquery('english', $2) query
+        WHERE user_id = $1 AND is_deleted = false AND search_vector @@ query
+        ORDER BY ts_rank(search_vector, query) DESC, issue_date DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(query)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(invoices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_become_prefix_alternatives() {
+        assert_eq!(
+            tsquery("that logo invoice for Acme").as_deref(),
+            Some("that:* | logo:* | invoice:* | for:* | acme:*")
+        );
+        assert_eq!(tsquery("INV-00012").as_deref(), Some("inv:* | 00012:*"));
+    }
+
+    #[test]
+    fn test_operators_are_not_interpreted() {
+        assert_eq!(tsquery("logo & !(acme | x):*").as_deref(), Some("logo:* | acme:* | x:*"));
+        assert_eq!(tsquery("  & | ! "), None);
+        assert_eq!(tsquery(""), None);
+    }
+}
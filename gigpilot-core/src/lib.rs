@@ -1,6 +1,50 @@
+pub mod accounts;
+pub mod admin;
+pub mod api_tokens;
+pub mod attachments;
 pub mod auth;
+pub mod business_days;
+pub mod chase;
+pub mod clients;
+pub mod contracts;
+pub mod currency;
 pub mod db;
+pub mod disputes;
+pub mod doctor;
+pub mod email_sandbox;
+pub mod estimates;
+pub mod events;
+pub mod expenses;
+pub mod idempotency;
+pub mod imports;
+pub mod invoices;
+pub mod locale;
+pub mod logging;
+pub mod maintenance;
 pub mod models;
+pub mod notifications;
+pub mod ocr;
+pub mod payment_methods;
+pub mod portal;
+pub mod projects;
+pub mod proposals;
+pub mod reconciliation;
+pub mod repo;
 pub mod worker;
 pub mod rag;
+pub mod reports;
+pub mod settings;
+pub mod sharing;
+pub mod retainers;
+pub mod sampling;
+pub mod secrets;
+pub mod statements;
+pub mod status;
+pub mod storage;
+pub mod sync;
+pub mod taxes;
+#[cfg(test)]
+mod test_support;
+pub mod text;
+pub mod time_entries;
 
@@ -0,0 +1,391 @@
+//! Locale-aware parsing of numbers and dates.
+//!
+//! Spreadsheets and devices write numbers and dates the way their locale
+//! does: `1.234,56` in Germany, `05/03/2024` meaning March 5th in the UK
+//! and May 3rd in the US. Callers pass an explicit [`Locale`] hint when
+//! they know the convention; without one, values are read tolerantly and
+//! anything that could mean two different things is rejected with an
+//! error instead of being guessed.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Character separating the integer part from the fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalSeparator {
+    Point,
+    Comma,
+}
+
+impl DecimalSeparator {
+    fn as_char(&self) -> char {
+        match self {
+            DecimalSeparator::Point => '.',
+            DecimalSeparator::Comma => ',',
+        }
+    }
+
+    /// The separator used for thousands alongside this one.
+    fn grouping(&self) -> char {
+        match self {
+            DecimalSeparator::Point => ',',
+            DecimalSeparator::Comma => '.',
+        }
+    }
+}
+
+/// Order of the day, month and year in numeric dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    DayMonthYear,
+    MonthDayYear,
+    YearMonthDay,
+}
+
+/// Languages writing decimals with a comma.
+const COMMA_LANGUAGES: &[&str] = &[
+    "bg", "ca", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu", "id", "it", "lt", "lv",
+    "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv", "tr", "uk", "vi",
+];
+
+/// Languages writing decimals with a point.
+const POINT_LANGUAGES: &[&str] = &["en", "ga", "he", "hi", "ja", "ko", "ms", "mt", "th", "zh"];
+
+/// Regions writing decimals with a point whatever the language.
+const POINT_REGIONS: &[&str] = &["CH", "LI", "MX", "US", "PR"];
+
+/// Languages writing the year first.
+const YEAR_FIRST_LANGUAGES: &[&str] = &["hu", "ja", "ko", "lt", "sv", "zh"];
+
+/// Regions writing the month first.
+const MONTH_FIRST_REGIONS: &[&str] = &["US", "PH", "PR"];
+
+/// Number and date conventions named by a locale tag such as `de-DE`.
+///
+/// Only the language and region of the tag are used; a bare language
+/// takes its most common conventions (`en` reads like `en-US`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Locale {
+    tag: String,
+    decimal_separator: DecimalSeparator,
+    date_order: DateOrder,
+}
+
+impl Locale {
+    /// Parses a locale tag (`de`, `de-DE`, `en_GB`).
+    ///
+    /// # Returns
+    ///
+    /// Returns a message if the tag is malformed or its language is not
+    /// supported.
+    pub fn parse(tag: &str) -> Result<Locale, String> {
+        let mut parts = tag.trim().split(['-', '_']);
+        let language = parts.next().unwrap_or_default().to_lowercase();
+        let region = parts
+            .find(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_alphabetic()))
+            .map(str::to_uppercase);
+        if language.len() < 2 || language.len() > 3 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(format!("not a locale: {}", tag));
+        }
+
+        let region = region.as_deref();
+        let decimal_separator = if region.is_some_and(|region| POINT_REGIONS.contains(&region)) {
+            DecimalSeparator::Point
+        } else if COMMA_LANGUAGES.contains(&language.as_str()) {
+            DecimalSeparator::Comma
+        } else if POINT_LANGUAGES.contains(&language.as_str()) {
+            DecimalSeparator::Point
+        } else {
+            return Err(format!("unsupported locale: {}", tag));
+        };
+
+        let date_order = if YEAR_FIRST_LANGUAGES.contains(&language.as_str()) {
+            DateOrder::YearMonthDay
+        } else if region.map_or(language == "en", |region| MONTH_FIRST_REGIONS.contains(&region)) {
+            DateOrder::MonthDayYear
+        } else {
+            DateOrder::DayMonthYear
+        };
+
+        let tag = match region {
+            Some(region) => format!("{}-{}", language, region),
+            None => language,
+        };
+        Ok(Locale { tag, decimal_separator, date_order })
+    }
+
+    /// The normalized tag, e.g. `de-DE`.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn decimal_separator(&self) -> DecimalSeparator {
+        self.decimal_separator
+    }
+
+    pub fn date_order(&self) -> DateOrder {
+        self.date_order
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.tag)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Locale::parse(s)
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = String;
+
+    fn try_from(tag: String) -> Result<Self, Self::Error> {
+        Locale::parse(&tag)
+    }
+}
+
+impl From<Locale> for String {
+    fn from(locale: Locale) -> Self {
+        locale.tag
+    }
+}
+
+/// Checks that thousands groups after the first have three digits.
+fn check_grouping(integer: &str, grouping: char, value: &str) -> Result<(), String> {
+    let mut groups = integer.split(grouping);
+    let first = groups.next().unwrap_or_default();
+    if first.is_empty() || groups.any(|group| group.len() != 3) {
+        return Err(format!("not a number: {}", value));
+    }
+    Ok(())
+}
+
+/// Parses a number written with the given decimal separator.
+fn parse_with(digits: &str, separator: DecimalSeparator, value: &str) -> Result<Decimal, String> {
+    let (integer, fraction) = match digits.split_once(separator.as_char()) {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits, None),
+    };
+    if fraction.is_some_and(|fraction| fraction.contains(['.', ','])) {
+        return Err(format!("not a number: {}", value));
+    }
+    if integer.contains(separator.grouping()) {
+        check_grouping(integer, separator.grouping(), value)?;
+    }
+
+    let mut normalized = integer.replace(separator.grouping(), "");
+    if let Some(fraction) = fraction {
+        normalized.push('.');
+        normalized.push_str(fraction);
+    }
+    Decimal::from_str_exact(&normalized).map_err(|_| format!("not a number: {}", value))
+}
+
+/// Parses a number such as `1,234.56`, `1.234,56` or `1 234,56`.
+///
+/// Spaces and apostrophes are always read as thousands separators. With a
+/// locale, its decimal separator is used. Without one, the separator is
+/// inferred: the last of `.` and `,` when both appear, a repeated one is
+/// a thousands separator, and a single one is the decimal separator
+/// unless exactly three digits follow it, which could be either
+/// (`1,234`), so the value is rejected.
+///
+/// # Arguments
+///
+/// * `value` - The number as written
+/// * `locale` - Convention the value is known to follow
+///
+/// # Returns
+///
+/// Returns the number, or a message saying why it can't be read.
+pub fn parse_decimal(value: &str, locale: Option<&Locale>) -> Result<Decimal, String> {
+    let digits: String = value
+        .trim()
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '\'' | '\u{2019}'))
+        .collect();
+    if digits.is_empty() {
+        return Err(format!("not a number: {}", value));
+    }
+
+    let separator = match locale {
+        Some(locale) => locale.decimal_separator,
+        None => {
+            let (points, commas) = (digits.matches('.').count(), digits.matches(',').count());
+            match (points, commas) {
+                (0, 0) => DecimalSeparator::Point,
+                (0, 1) | (1, 0) => {
+                    let single = if points == 1 { '.' } else { ',' };
+                    let decimals = digits.rsplit(single).next().unwrap_or_default();
+                    if decimals.len() == 3 && decimals.chars().all(|c| c.is_ascii_digit()) {
+                        return Err(format!(
+                            "ambiguous number: {} (set a locale to say whether {} separates thousands or decimals)",
+                            value, single
+                        ));
+                    }
+                    if single == '.' { DecimalSeparator::Point } else { DecimalSeparator::Comma }
+                }
+                // Only a thousands separator repeats
+                (_, 0) => DecimalSeparator::Comma,
+                (0, _) => DecimalSeparator::Point,
+                _ if digits.rfind('.') > digits.rfind(',') => DecimalSeparator::Point,
+                _ => DecimalSeparator::Comma,
+            }
+        }
+    };
+
+    parse_with(&digits, separator, value)
+}
+
+/// Textual date formats accepted in any locale.
+const TEXT_DATE_FORMATS: &[&str] = &["%d %b %Y", "%d %B %Y", "%b %d, %Y", "%B %d, %Y", "%b %d %Y", "%d-%b-%Y"];
+
+/// Parses a date such as `2024-03-05`, `05/03/2024` or `5 Mar 2024`.
+///
+/// ISO dates (year first) are always accepted. Numeric dates with the
+/// year last follow the locale's order; without a locale, or for locales
+/// writing the year first, they are accepted only if just one reading is
+/// a valid date (`25/03/2024`), and `05/03/2024` is rejected as
+/// ambiguous. Years must have four digits. English month names are
+/// accepted too.
+///
+/// # Arguments
+///
+/// * `value` - The date as written
+/// * `locale` - Convention the value is known to follow
+///
+/// # Returns
+///
+/// Returns the date, or a message saying why it can't be read.
+pub fn parse_date(value: &str, locale: Option<&Locale>) -> Result<NaiveDate, String> {
+    let trimmed = value.trim();
+    let invalid = || format!("not a date: {}", value);
+
+    let parts: Vec<&str> = trimmed.split(['/', '.', '-']).collect();
+    let numeric = parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.len() <= 4 && part.chars().all(|c| c.is_ascii_digit()));
+    if !numeric {
+        return TEXT_DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(trimmed, format).ok())
+            .ok_or_else(invalid);
+    }
+
+    let number = |part: &str| part.parse::<u32>().unwrap_or_default();
+    if parts[0].len() == 4 {
+        return NaiveDate::from_ymd_opt(number(parts[0]) as i32, number(parts[1]), number(parts[2]))
+            .ok_or_else(invalid);
+    }
+    if parts[2].len() != 4 {
+        return Err(format!("not a date: {} (years must have four digits)", value));
+    }
+
+    let year = number(parts[2]) as i32;
+    let (first, second) = (number(parts[0]), number(parts[1]));
+    let day_first = NaiveDate::from_ymd_opt(year, second, first);
+    let month_first = NaiveDate::from_ymd_opt(year, first, second);
+
+    match locale.map(Locale::date_order) {
+        Some(DateOrder::DayMonthYear) => day_first.ok_or_else(invalid),
+        Some(DateOrder::MonthDayYear) => month_first.ok_or_else(invalid),
+        _ => match (day_first, month_first) {
+            (Some(a), Some(b)) if a != b => Err(format!(
+                "ambiguous date: {} could be {} or {} (set a locale or date format)",
+                value, a, b
+            )),
+            (Some(date), _) | (_, Some(date)) => Ok(date),
+            (None, None) => Err(invalid()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).unwrap()
+    }
+
+    fn decimal(value: &str) -> Decimal {
+        Decimal::from_str_exact(value).unwrap()
+    }
+
+    #[test]
+    fn test_locale_conventions() {
+        let de = locale("de_de");
+        assert_eq!(de.tag(), "de-DE");
+        assert_eq!(de.decimal_separator(), DecimalSeparator::Comma);
+        assert_eq!(de.date_order(), DateOrder::DayMonthYear);
+
+        assert_eq!(locale("de-CH").decimal_separator(), DecimalSeparator::Point);
+        assert_eq!(locale("en").date_order(), DateOrder::MonthDayYear);
+        assert_eq!(locale("en-GB").date_order(), DateOrder::DayMonthYear);
+        assert_eq!(locale("sv-SE").date_order(), DateOrder::YearMonthDay);
+
+        assert!(Locale::parse("xx-XX").is_err());
+        assert!(Locale::parse("").is_err());
+        assert_eq!(serde_json::to_value(&de).unwrap(), serde_json::json!("de-DE"));
+    }
+
+    #[test]
+    fn test_parse_decimal_with_locale() {
+        assert_eq!(parse_decimal("1.234,56", Some(&locale("de-DE"))), Ok(decimal("1234.56")));
+        assert_eq!(parse_decimal("1 234,5", Some(&locale("fr"))), Ok(decimal("1234.5")));
+        assert_eq!(parse_decimal("1,234", Some(&locale("en-US"))), Ok(decimal("1234")));
+        assert_eq!(parse_decimal("1,234", Some(&locale("de"))), Ok(decimal("1.234")));
+        assert_eq!(parse_decimal("1'234.50", Some(&locale("de-CH"))), Ok(decimal("1234.50")));
+
+        assert!(parse_decimal("1,23,4.5", Some(&locale("en"))).is_err());
+        assert!(parse_decimal("12,50", Some(&locale("en"))).is_err());
+    }
+
+    #[test]
+    fn test_parse_decimal_without_locale() {
+        assert_eq!(parse_decimal("1,234.56", None), Ok(decimal("1234.56")));
+        assert_eq!(parse_decimal("1.234,56", None), Ok(decimal("1234.56")));
+        assert_eq!(parse_decimal("1.234.567", None), Ok(decimal("1234567")));
+        assert_eq!(parse_decimal("12,5", None), Ok(decimal("12.5")));
+        assert_eq!(parse_decimal("-40.00", None), Ok(decimal("-40.00")));
+
+        let err = parse_decimal("1,234", None).unwrap_err();
+        assert!(err.starts_with("ambiguous number"), "{}", err);
+        assert!(parse_decimal("1.250", None).is_err());
+        assert!(parse_decimal("abc", None).is_err());
+        assert!(parse_decimal("1,2.3,4", None).is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2024-03-05", None), Ok(date(2024, 3, 5)));
+        assert_eq!(parse_date("25/03/2024", None), Ok(date(2024, 3, 25)));
+        assert_eq!(parse_date("03/25/2024", None), Ok(date(2024, 3, 25)));
+        assert_eq!(parse_date("05.05.2024", None), Ok(date(2024, 5, 5)));
+        assert_eq!(parse_date("5 Mar 2024", None), Ok(date(2024, 3, 5)));
+        assert_eq!(parse_date("March 5, 2024", None), Ok(date(2024, 3, 5)));
+
+        let err = parse_date("05/03/2024", None).unwrap_err();
+        assert!(err.contains("2024-03-05") && err.contains("2024-05-03"), "{}", err);
+        assert_eq!(parse_date("05/03/2024", Some(&locale("en-GB"))), Ok(date(2024, 3, 5)));
+        assert_eq!(parse_date("05/03/2024", Some(&locale("en-US"))), Ok(date(2024, 5, 3)));
+
+        assert!(parse_date("13/25/2024", None).is_err());
+        assert!(parse_date("31/02/2024", Some(&locale("de"))).is_err());
+        assert!(parse_date("05/03/24", Some(&locale("de"))).is_err());
+        assert!(parse_date("next friday", None).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Logging policy for personally identifiable information (PII).
+//!
+//! Client emails, names, amounts and free text (email bodies, LLM context,
+//! search queries) must never reach tracing output verbatim by default.
+//! Wrap such values with the helpers in this module before logging them:
+//!
+//! ```rust,ignore
+//! info!("Sending email to {}", redact_email(to));
+//! ```
+//!
+//! Setting `LOG_PII=true` switches to debug mode, where values are logged
+//! as-is. This is intended for local development only.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
+
+/// Kind of PII being logged, which controls how it is redacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PiiKind {
+    /// Email address
+    Email,
+
+    /// Person or company name
+    Name,
+
+    /// Monetary amount
+    Amount,
+
+    /// Free text that may embed any of the above
+    Text,
+}
+
+/// Returns whether PII may be logged verbatim (`LOG_PII=true`).
+///
+/// The setting is read once and cached for the life of the process.
+pub fn pii_logging_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var("LOG_PII")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false)
+    })
+}
+
+/// A value that is redacted when displayed unless PII logging is enabled.
+pub struct Redacted<'a> {
+    kind: PiiKind,
+    value: &'a dyn fmt::Display,
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render(f, self.kind, self.value, pii_logging_enabled())
+    }
+}
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Writes either the raw value or its redacted form.
+///
+/// Redacted emails and names keep a short stable fingerprint so log lines
+/// about the same client can still be correlated.
+fn render(
+    f: &mut fmt::Formatter<'_>,
+    kind: PiiKind,
+    value: &dyn fmt::Display,
+    reveal: bool,
+) -> fmt::Result {
+    if reveal {
+        return write!(f, "{}", value);
+    }
+
+    let raw = value.to_string();
+    match kind {
+        PiiKind::Email => write!(f, "<email:{}>", fingerprint(&raw.to_lowercase())),
+        PiiKind::Name => write!(f, "<name:{}>", fingerprint(&raw)),
+        PiiKind::Amount => write!(f, "<amount>"),
+        PiiKind::Text => write!(f, "<text:{} chars>", raw.chars().count()),
+    }
+}
+
+/// Short, stable fingerprint of a value for log correlation.
+fn fingerprint(value: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("{:06x}", hasher.finish() & 0xff_ffff)
+}
+
+/// Redacts an email address.
+pub fn redact_email<'a>(value: &'a dyn fmt::Display) -> Redacted<'a> {
+    Redacted { kind: PiiKind::Email, value }
+}
+
+/// Redacts a person or company name.
+pub fn redact_name<'a>(value: &'a dyn fmt::Display) -> Redacted<'a> {
+    Redacted { kind: PiiKind::Name, value }
+}
+
+/// Redacts a monetary amount.
+pub fn redact_amount<'a>(value: &'a dyn fmt::Display) -> Redacted<'a> {
+    Redacted { kind: PiiKind::Amount, value }
+}
+
+/// Redacts free text such as email bodies or LLM prompts.
+pub fn redact_text<'a>(value: &'a dyn fmt::Display) -> Redacted<'a> {
+    Redacted { kind: PiiKind::Text, value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Forced<'a>(PiiKind, &'a str, bool);
+
+    impl fmt::Display for Forced<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            render(f, self.0, &self.1, self.2)
+        }
+    }
+
+    #[test]
+    fn test_email_is_fingerprinted() {
+        let redacted = Forced(PiiKind::Email, "Client@Example.com", false).to_string();
+        assert!(redacted.starts_with("<email:"));
+        assert!(!redacted.contains("example"));
+        assert_eq!(redacted, Forced(PiiKind::Email, "client@example.com", false).to_string());
+    }
+
+    #[test]
+    fn test_amount_and_text_are_hidden() {
+        assert_eq!(Forced(PiiKind::Amount, "1500.00", false).to_string(), "<amount>");
+        assert_eq!(Forced(PiiKind::Text, "Invoice for Acme", false).to_string(), "<text:16 chars>");
+    }
+
+    #[test]
+    fn test_debug_mode_reveals_values() {
+        assert_eq!(Forced(PiiKind::Name, "Acme Corp", true).to_string(), "Acme Corp");
+    }
+}
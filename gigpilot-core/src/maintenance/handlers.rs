@@ -0,0 +1,63 @@
+use axum::{extract::Extension, http::StatusCode, response::Json};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::maintenance::{end_maintenance, load_active_window, start_maintenance, validate_start};
+use crate::models::maintenance_window::{MaintenanceWindow, StartMaintenance};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Get maintenance endpoint handler.
+///
+/// Handles GET requests to `/admin/maintenance`: the open maintenance
+/// window, or `null`.
+pub async fn get_maintenance_handler(
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Option<MaintenanceWindow>>, StatusCode> {
+    let window = load_active_window(&pool).await.map_err(|e| {
+        error!("Failed to load maintenance window: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(window))
+}
+
+/// Start maintenance endpoint handler.
+///
+/// Handles PUT requests to `/admin/maintenance`. Maintenance starts
+/// immediately and lasts `minutes`; starting it again replaces the open
+/// window.
+pub async fn start_maintenance_handler(
+    Extension(pool): Extension<PgPool>,
+    Json(request): Json<StartMaintenance>,
+) -> Result<Json<MaintenanceWindow>, (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_start(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let window = start_maintenance(&pool, request).await.map_err(|e| {
+        error!("Failed to start maintenance: {}", e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to start maintenance")
+    })?;
+
+    Ok(Json(window))
+}
+
+/// End maintenance endpoint handler.
+///
+/// Handles DELETE requests to `/admin/maintenance`. Returns 404 if no
+/// window is open.
+pub async fn end_maintenance_handler(Extension(pool): Extension<PgPool>) -> Result<StatusCode, StatusCode> {
+    let ended = end_maintenance(&pool).await.map_err(|e| {
+        error!("Failed to end maintenance: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    match ended {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
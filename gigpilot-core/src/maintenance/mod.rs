@@ -0,0 +1,302 @@
+//! Time-boxed maintenance mode.
+//!
+//! Support staff open a maintenance window before migrations (see
+//! [`handlers`]). While it lasts, [`maintenance_middleware`] answers
+//! mutating requests and sync pushes with `503 Service Unavailable` and a
+//! `Retry-After` header, while reads and sync pulls keep working; the
+//! worker stops sending emails ([`worker_paused`]) and picks the work up
+//! again afterwards. Windows always have an end, so a forgotten one can't
+//! keep the service read-only.
+//!
+//! Every process keeps the current window in memory, refreshed on the
+//! [`DomainEvent::MaintenanceChanged`] event and on an interval in case
+//! an event is missed.
+
+pub mod handlers;
+
+use std::sync::RwLock;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info};
+
+use crate::events::{publish, DomainEvent, EventBus};
+use crate::models::maintenance_window::{MaintenanceWindow, StartMaintenance};
+
+/// Longest maintenance window, in minutes.
+pub const MAX_MINUTES: i64 = 24 * 60;
+
+/// Longest message shown to clients.
+pub const MAX_MESSAGE_LENGTH: usize = 500;
+
+/// Seconds between refreshes of the current window when not configured.
+const DEFAULT_REFRESH_SECONDS: u64 = 15;
+
+/// Requests still accepted during maintenance besides safe methods.
+const ALLOWED_PATHS: &[&str] = &["/sync/pull", "/api/sync/pull"];
+
+/// Window this process last loaded.
+static CURRENT: RwLock<Option<MaintenanceWindow>> = RwLock::new(None);
+
+/// Returns the maintenance window open at `now`, if any.
+pub fn current_window(now: DateTime<Utc>) -> Option<MaintenanceWindow> {
+    CURRENT
+        .read()
+        .ok()
+        .and_then(|current| current.clone())
+        .filter(|window| window.is_active(now))
+}
+
+/// Replaces the window this process knows about.
+fn set_current(window: Option<MaintenanceWindow>) {
+    if let Ok(mut current) = CURRENT.write() {
+        *current = window;
+    }
+}
+
+/// Whether the worker should hold off sending emails.
+pub fn worker_paused() -> bool {
+    current_window(Utc::now()).is_some()
+}
+
+/// Whether a request is refused during maintenance.
+///
+/// Reads (safe methods), sync pulls and the admin API stay available.
+pub fn is_blocked(method: &axum::http::Method, path: &str) -> bool {
+    !method.is_safe() && !ALLOWED_PATHS.contains(&path) && !path.starts_with("/admin/")
+}
+
+/// Seconds until a window ends, rounded up (at least 1).
+pub fn retry_after_seconds(window: &MaintenanceWindow, now: DateTime<Utc>) -> i64 {
+    let milliseconds = (window.ends_at - now).num_milliseconds();
+    ((milliseconds + 999) / 1000).max(1)
+}
+
+/// Validates a maintenance start request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_start(request: &StartMaintenance) -> Result<(), String> {
+    if !(1..=MAX_MINUTES).contains(&request.minutes) {
+        return Err(format!("minutes must be between 1 and {}", MAX_MINUTES));
+    }
+    if request
+        .message
+        .as_deref()
+        .is_some_and(|message| message.chars().count() > MAX_MESSAGE_LENGTH)
+    {
+        return Err(format!("message must be at most {} characters", MAX_MESSAGE_LENGTH));
+    }
+    Ok(())
+}
+
+/// Loads the maintenance window open now, if any.
+pub async fn load_active_window(pool: &PgPool) -> Result<Option<MaintenanceWindow>, anyhow::Error> {
+    let window = sqlx::query_as::<_, MaintenanceWindow>(
+        r#"
+        SELECT * FROM maintenance_windows
+        WHERE starts_at <= NOW() AND ends_at > NOW()
+        ORDER BY ends_at DESC
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(window)
+}
+
+/// Starts maintenance now, replacing any open window.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `request` - How long it lasts and the message (see [`validate_start`])
+///
+/// # Returns
+///
+/// Returns the new window.
+pub async fn start_maintenance(pool: &PgPool, request: StartMaintenance) -> Result<MaintenanceWindow, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE maintenance_windows SET ends_at = NOW() WHERE starts_at <= NOW() AND ends_at > NOW()")
+        .execute(&mut *tx)
+        .await?;
+    let window = sqlx::query_as::<_, MaintenanceWindow>(
+        r#"
+        INSERT INTO maintenance_windows (message, ends_at)
+        VALUES ($1, NOW() + make_interval(mins => $2))
+        RETURNING *
+        "#,
+    )
+    .bind(request.message.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()))
+    .bind(request.minutes as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+    publish(&mut *tx, &DomainEvent::MaintenanceChanged).await?;
+
+    tx.commit().await?;
+
+    info!("Maintenance started until {}", window.ends_at);
+    set_current(Some(window.clone()));
+    Ok(window)
+}
+
+/// Ends the open maintenance window early.
+///
+/// # Returns
+///
+/// Returns the ended window, or `None` if none was open.
+pub async fn end_maintenance(pool: &PgPool) -> Result<Option<MaintenanceWindow>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let window = sqlx::query_as::<_, MaintenanceWindow>(
+        r#"
+        UPDATE maintenance_windows SET ends_at = NOW()
+        WHERE starts_at <= NOW() AND ends_at > NOW()
+        RETURNING *
+        "#,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    publish(&mut *tx, &DomainEvent::MaintenanceChanged).await?;
+
+    tx.commit().await?;
+
+    if window.is_some() {
+        info!("Maintenance ended");
+    }
+    set_current(None);
+    Ok(window)
+}
+
+/// Reloads the current window from the database.
+async fn refresh(pool: &PgPool) {
+    match load_active_window(pool).await {
+        Ok(window) => set_current(window),
+        Err(e) => error!("Failed to load maintenance window: {}", e),
+    }
+}
+
+/// Spawns the tasks keeping this process's window up to date.
+///
+/// Reloads it on [`DomainEvent::MaintenanceChanged`] and every
+/// `MAINTENANCE_REFRESH_SECONDS` (default 15 seconds).
+pub fn spawn_refresh(pool: PgPool, bus: &EventBus) {
+    let seconds = std::env::var("MAINTENANCE_REFRESH_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_REFRESH_SECONDS);
+
+    let event_pool = pool.clone();
+    let mut receiver = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(DomainEvent::MaintenanceChanged) | Err(RecvError::Lagged(_)) => refresh(&event_pool).await,
+                Ok(_) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            refresh(&pool).await;
+        }
+    });
+}
+
+/// Middleware refusing mutating requests during maintenance.
+///
+/// Refused requests get `503` with `Retry-After` (seconds until the window
+/// ends) and `{ "error", "code": "maintenance", "message", "ends_at",
+/// "retry_after" }`.
+pub async fn maintenance_middleware(req: Request<Body>, next: Next<Body>) -> Response {
+    let now = Utc::now();
+    let Some(window) = current_window(now).filter(|_| is_blocked(req.method(), req.uri().path())) else {
+        return next.run(req).await;
+    };
+
+    let retry_after = retry_after_seconds(&window, now);
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        Json(json!({
+            "error": "the service is in maintenance; try again later",
+            "code": "maintenance",
+            "message": window.message,
+            "ends_at": window.ends_at,
+            "retry_after": retry_after,
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Method;
+    use uuid::Uuid;
+
+    fn window(starts_at: DateTime<Utc>, ends_at: DateTime<Utc>) -> MaintenanceWindow {
+        MaintenanceWindow {
+            id: Uuid::new_v4(),
+            message: None,
+            starts_at,
+            ends_at,
+            created_at: starts_at,
+        }
+    }
+
+    #[test]
+    fn test_only_mutations_outside_pulls_and_admin_are_blocked() {
+        assert!(is_blocked(&Method::POST, "/api/invoices/abc/payments"));
+        assert!(is_blocked(&Method::PUT, "/api/settings"));
+        assert!(is_blocked(&Method::DELETE, "/api/clients/abc"));
+        assert!(is_blocked(&Method::POST, "/sync/push"));
+
+        assert!(!is_blocked(&Method::GET, "/api/invoices/abc"));
+        assert!(!is_blocked(&Method::HEAD, "/health"));
+        assert!(!is_blocked(&Method::POST, "/sync/pull"));
+        assert!(!is_blocked(&Method::POST, "/api/sync/pull"));
+        assert!(is_blocked(&Method::POST, "/api/sync/push"));
+        assert!(!is_blocked(&Method::DELETE, "/admin/maintenance"));
+    }
+
+    #[test]
+    fn test_windows_are_time_boxed() {
+        let now = Utc::now();
+        let open = window(now - chrono::Duration::minutes(5), now + chrono::Duration::milliseconds(90_500));
+
+        assert!(open.is_active(now));
+        assert!(!open.is_active(open.ends_at));
+        assert!(!window(now + chrono::Duration::minutes(1), now + chrono::Duration::minutes(2)).is_active(now));
+        assert_eq!(retry_after_seconds(&open, now), 91);
+        assert_eq!(retry_after_seconds(&open, open.ends_at), 1);
+    }
+
+    #[test]
+    fn test_validate_start() {
+        let request = |minutes, message: Option<&str>| StartMaintenance {
+            minutes,
+            message: message.map(str::to_string),
+        };
+
+        assert!(validate_start(&request(30, Some("Database upgrade"))).is_ok());
+        assert!(validate_start(&request(0, None)).is_err());
+        assert!(validate_start(&request(MAX_MINUTES + 1, None)).is_err());
+        assert!(validate_start(&request(30, Some(&"x".repeat(MAX_MESSAGE_LENGTH + 1)))).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Kind of account (a row in `users`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountKind {
+    /// A person who logs in
+    #[default]
+    #[sqlx(rename = "personal")]
+    Personal,
+
+    /// A business line used by its members, without a login of its own
+    #[sqlx(rename = "workspace")]
+    Workspace,
+}
+
+/// A person's role in an account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountRole {
+    /// Manages the account's members
+    #[sqlx(rename = "owner")]
+    Owner,
+
+    /// Works in the account
+    #[sqlx(rename = "member")]
+    Member,
+}
+
+/// An account a person can act as.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AccountSummary {
+    /// Account ID (the `users` row owning the data)
+    pub id: Uuid,
+
+    /// Display name (full name, or the email when unset)
+    pub name: String,
+
+    /// Account email, used as the business email on invoices
+    pub email: String,
+
+    /// Personal account or workspace
+    pub kind: AccountKind,
+
+    /// The person's role in the account
+    pub role: AccountRole,
+}
+
+/// Workspace creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateWorkspace {
+    /// Business name shown on invoices
+    pub name: String,
+
+    /// Business email (must not belong to another account)
+    pub email: String,
+}
+
+/// Workspace email confirmation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyWorkspace {
+    /// Token emailed to the workspace's email
+    pub token: String,
+}
+
+/// Member addition request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddAccountMember {
+    /// Email of the person's personal account
+    pub email: String,
+
+    /// Role to grant (default: member)
+    pub role: Option<AccountRole>,
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// What a personal access token may do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Read-only: `GET`, `HEAD` and `OPTIONS` requests
+    #[default]
+    #[sqlx(rename = "read")]
+    Read,
+
+    /// Any request the user could make, except managing tokens and
+    /// switching accounts
+    #[sqlx(rename = "write")]
+    Write,
+}
+
+/// Personal access token model.
+///
+/// This struct maps to the `api_tokens` table. The token itself is only
+/// returned when it is created; the hash it is looked up by is never
+/// serialized.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    /// Unique identifier for the token
+    pub id: Uuid,
+
+    /// ID of the account the token acts as
+    pub user_id: Uuid,
+
+    /// ID of the person who created the token
+    pub created_by: Uuid,
+
+    /// What the token is for (e.g. "Monthly export sheet")
+    pub name: String,
+
+    /// Start of the token, to tell tokens apart
+    pub token_prefix: String,
+
+    /// SHA-256 hash of the token
+    #[serde(skip)]
+    pub token_hash: Vec<u8>,
+
+    /// What the token may do
+    pub scope: TokenScope,
+
+    /// Timestamp after which the token is rejected
+    pub expires_at: DateTime<Utc>,
+
+    /// Timestamp when the token was revoked
+    pub revoked_at: Option<DateTime<Utc>>,
+
+    /// Timestamp of the last request made with the token
+    pub last_used_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the token was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the token was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Personal access token creation request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateApiToken {
+    pub name: String,
+
+    /// What the token may do (default read-only)
+    pub scope: Option<TokenScope>,
+
+    /// Days until the token expires (default 90, at most 365)
+    pub expires_in_days: Option<i64>,
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A file attached to an invoice.
+///
+/// This struct maps to the `attachments` table. The contents live in the
+/// storage backend under `storage_key`, which is never exposed to clients.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Attachment {
+    /// Unique identifier for the attachment
+    pub id: Uuid,
+
+    /// ID of the user who owns the invoice
+    pub user_id: Uuid,
+
+    /// Invoice the file is attached to
+    pub invoice_id: Uuid,
+
+    /// Original file name
+    pub filename: String,
+
+    /// MIME type of the file
+    pub content_type: String,
+
+    /// File size in bytes
+    pub size_bytes: i64,
+
+    /// Object key in the storage backend
+    #[serde(skip_serializing, default)]
+    pub storage_key: String,
+
+    /// Timestamp when the file was uploaded
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the attachment was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Column list for [`Attachment`] queries.
+pub const ATTACHMENT_COLUMNS: &str = "id, user_id, invoice_id, filename, content_type, size_bytes, \
+    storage_key, created_at, updated_at";
@@ -0,0 +1,123 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Where an imported transfer stands in reconciliation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum BankTransactionStatus {
+    /// Recorded as a payment on an invoice
+    #[sqlx(rename = "matched")]
+    Matched,
+
+    /// Could belong to several invoices; waiting for the user
+    #[sqlx(rename = "review")]
+    Review,
+
+    /// No open invoice fits it
+    #[sqlx(rename = "unmatched")]
+    Unmatched,
+
+    /// Dismissed by the user (not a payment for an invoice)
+    #[sqlx(rename = "dismissed")]
+    Dismissed,
+}
+
+impl BankTransactionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BankTransactionStatus::Matched => "matched",
+            BankTransactionStatus::Review => "review",
+            BankTransactionStatus::Unmatched => "unmatched",
+            BankTransactionStatus::Dismissed => "dismissed",
+        }
+    }
+}
+
+/// How a transfer was matched to its invoice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum MatchedBy {
+    /// Its reference names the invoice number
+    #[sqlx(rename = "invoice_number")]
+    InvoiceNumber,
+
+    /// It is the balance due of a single open invoice
+    #[sqlx(rename = "amount")]
+    Amount,
+
+    /// The user picked the invoice from the review queue
+    #[sqlx(rename = "manual")]
+    Manual,
+}
+
+/// An incoming transfer from an imported bank statement.
+///
+/// This struct maps to the `bank_transactions` table. Matched transfers
+/// link to the payment recorded for them; transfers in review list the
+/// invoices they could pay.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BankTransaction {
+    /// Unique identifier for the transaction
+    pub id: Uuid,
+
+    /// ID of the user who imported the statement
+    pub user_id: Uuid,
+
+    /// Stable key of the transaction, so re-imports skip it
+    #[serde(skip_serializing, default)]
+    pub transaction_key: String,
+
+    /// Date the bank booked the transfer
+    pub booked_on: NaiveDate,
+
+    /// Amount received
+    pub amount: Decimal,
+
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Name of the payer
+    pub counterparty: Option<String>,
+
+    /// Payment reference given by the payer
+    pub reference: Option<String>,
+
+    /// Other text the bank shows for the transfer
+    pub description: Option<String>,
+
+    /// The bank's own ID for the transfer
+    pub bank_reference: Option<String>,
+
+    /// Reconciliation status
+    pub status: BankTransactionStatus,
+
+    /// How the transfer was matched, once it is
+    pub matched_by: Option<MatchedBy>,
+
+    /// Invoices a transfer in review could pay
+    pub candidate_invoice_ids: Vec<Uuid>,
+
+    /// Invoice the transfer paid
+    pub invoice_id: Option<Uuid>,
+
+    /// Payment recorded for the transfer
+    pub payment_id: Option<Uuid>,
+
+    /// Timestamp when the transaction was imported
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the transaction was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request to settle a transfer from the review queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchBankTransaction {
+    /// Invoice the transfer pays
+    pub invoice_id: Uuid,
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Per-invoice override of the default chase policy.
+///
+/// Stored in the invoice's `chase_override` JSONB column. Every field is
+/// optional; an empty override behaves like the default policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChaseOverride {
+    /// Send nothing before this date (e.g. "don't chase before March 1")
+    #[serde(default)]
+    pub not_before: Option<NaiveDate>,
+
+    /// Never escalate to the firm level-2 reminder
+    #[serde(default)]
+    pub skip_level_2: bool,
+
+    /// Days overdue before escalating to level 2 (default policy: 7)
+    #[serde(default)]
+    pub level_2_after_days: Option<i64>,
+
+    /// Stop chasing this invoice until the override is changed
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl ChaseOverride {
+    /// Parses the invoice's `chase_override` column.
+    ///
+    /// Missing or null values yield the default (no override).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stored JSON is not a valid override.
+    pub fn parse(value: Option<&Value>) -> Result<Self, anyhow::Error> {
+        match value {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| anyhow::anyhow!("Invalid chase_override: {}", e)),
+        }
+    }
+
+    /// Validates an override before it is stored.
+    ///
+    /// # Returns
+    ///
+    /// Returns a user-facing message describing the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(days) = self.level_2_after_days {
+            if !(1..=365).contains(&days) {
+                return Err("level_2_after_days must be between 1 and 365".to_string());
+            }
+        }
+        if self.skip_level_2 && self.level_2_after_days.is_some() {
+            return Err("level_2_after_days cannot be set when skip_level_2 is true".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Days a chase pause lasts when no end is given.
+pub const DEFAULT_PAUSE_DAYS: i64 = 30;
+
+/// Longest chase pause, in days.
+pub const MAX_PAUSE_DAYS: i64 = 365;
+
+/// Request to pause an invoice's reminders, e.g. while negotiating with the
+/// client.
+///
+/// Unlike the `paused` override, a pause ends by itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct PauseChase {
+    /// When reminders resume ([`DEFAULT_PAUSE_DAYS`] from now if unset)
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl PauseChase {
+    /// When the pause ends, for a request made at `now`.
+    ///
+    /// # Returns
+    ///
+    /// Returns a user-facing message if the end is not in the future or is
+    /// more than [`MAX_PAUSE_DAYS`] away.
+    pub fn until(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        let until = self.until.unwrap_or(now + Duration::days(DEFAULT_PAUSE_DAYS));
+        if until <= now {
+            return Err("until must be in the future".to_string());
+        }
+        if until > now + Duration::days(MAX_PAUSE_DAYS) {
+            return Err(format!("until must be at most {} days away", MAX_PAUSE_DAYS));
+        }
+        Ok(until)
+    }
+}
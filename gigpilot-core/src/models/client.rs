@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Client model representing someone the user bills.
+///
+/// This struct maps to the `clients` table and includes sync metadata
+/// for offline-first synchronization. Invoices reference a client through
+/// `client_id` and keep their own copy of the name and email they were
+/// issued with.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Client {
+    /// Unique identifier for the client
+    pub id: Uuid,
+
+    /// ID of the user who owns this client
+    pub user_id: Uuid,
+
+    /// Client (person or company) name
+    pub name: String,
+
+    /// Billing email address
+    pub email: Option<String>,
+
+    /// Phone number
+    pub phone: Option<String>,
+
+    /// Postal address (free-form, may span several lines)
+    pub address: Option<String>,
+
+    /// VAT or other tax identifier
+    pub tax_id: Option<String>,
+
+    /// Private notes about the client
+    pub notes: Option<String>,
+
+    /// Days the client has to pay, replacing the user's default terms
+    #[sqlx(default)]
+    pub payment_terms_days: Option<i32>,
+
+    /// Last modification timestamp (for sync)
+    pub last_modified: DateTime<Utc>,
+
+    /// Version vector for CRDT sync (device_id -> timestamp)
+    pub version_vector: Option<Value>,
+
+    /// Soft delete flag (for sync)
+    pub is_deleted: bool,
+
+    /// Additional metadata (flexible JSON)
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the client was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the client was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Client creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateClient {
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub tax_id: Option<String>,
+    pub notes: Option<String>,
+    pub payment_terms_days: Option<i32>,
+    pub metadata: Option<Value>,
+}
+
+/// Client update request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateClient {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub tax_id: Option<String>,
+    pub notes: Option<String>,
+    pub payment_terms_days: Option<i32>,
+    pub metadata: Option<Value>,
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Kind of client interaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionKind {
+    /// Free-form note
+    #[sqlx(rename = "note")]
+    Note,
+
+    /// Phone or video call
+    #[sqlx(rename = "call")]
+    Call,
+
+    /// Email sent or received outside GigPilot
+    #[sqlx(rename = "email")]
+    Email,
+
+    /// In-person meeting
+    #[sqlx(rename = "meeting")]
+    Meeting,
+
+    /// Chase email sent by the worker (automatic)
+    #[sqlx(rename = "chase_email")]
+    ChaseEmail,
+
+    /// The client opened an invoice in the portal or on its pay page
+    /// (automatic)
+    #[sqlx(rename = "invoice_viewed")]
+    InvoiceViewed,
+}
+
+impl InteractionKind {
+    /// Whether entries of this kind are recorded by GigPilot rather than
+    /// logged by the user. Automatic entries can't be changed or deleted.
+    pub fn is_automatic(&self) -> bool {
+        matches!(self, InteractionKind::ChaseEmail | InteractionKind::InvoiceViewed)
+    }
+}
+
+/// Client interaction model, one entry in a client's timeline.
+///
+/// This struct maps to the `client_interactions` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ClientInteraction {
+    /// Unique identifier for the entry
+    pub id: Uuid,
+
+    /// ID of the user whose client it is
+    pub user_id: Uuid,
+
+    /// Client the interaction was with
+    pub client_id: Uuid,
+
+    /// Invoice the interaction was about, if any
+    pub invoice_id: Option<Uuid>,
+
+    /// Kind of interaction
+    pub kind: InteractionKind,
+
+    /// One-line summary (e.g. "Promised to pay on Friday")
+    pub summary: String,
+
+    /// Longer notes
+    pub body: Option<String>,
+
+    /// When the interaction happened
+    pub occurred_at: DateTime<Utc>,
+
+    /// Additional data (e.g. the correspondence entry of a chase email)
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the entry was recorded
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the entry was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Manual interaction creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateClientInteraction {
+    /// `note`, `call`, `email` or `meeting`
+    pub kind: InteractionKind,
+    pub summary: String,
+    pub body: Option<String>,
+    pub invoice_id: Option<Uuid>,
+
+    /// When it happened (default: now)
+    pub occurred_at: Option<DateTime<Utc>>,
+    pub metadata: Option<Value>,
+}
+
+/// Manual interaction update request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateClientInteraction {
+    pub kind: Option<InteractionKind>,
+    pub summary: Option<String>,
+    pub body: Option<String>,
+    pub invoice_id: Option<Uuid>,
+    pub occurred_at: Option<DateTime<Utc>>,
+    pub metadata: Option<Value>,
+}
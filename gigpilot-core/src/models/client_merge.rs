@@ -0,0 +1,51 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Record of a duplicate client merged into a canonical one.
+///
+/// This struct maps to the `client_merges` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ClientMerge {
+    /// Unique identifier for the merge
+    pub id: Uuid,
+
+    /// ID of the user who owns both clients
+    pub user_id: Uuid,
+
+    /// The duplicate, soft-deleted by the merge
+    pub duplicate_client_id: Uuid,
+
+    /// The client everything was moved to
+    pub canonical_client_id: Uuid,
+
+    /// Invoices re-pointed to the canonical client
+    pub invoices_moved: i32,
+
+    /// Payments of those invoices
+    pub payments_moved: i32,
+
+    /// Projects re-pointed to the canonical client
+    pub projects_moved: i32,
+
+    /// Estimator embeddings of the duplicate re-pointed to the canonical client
+    pub embeddings_moved: i32,
+
+    /// IDs of the re-pointed records (`{ "invoice_ids": [...], "project_ids": [...] }`)
+    pub details: Value,
+
+    /// Timestamp of the merge
+    pub created_at: DateTime<Utc>,
+}
+
+/// Client merge request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeClients {
+    /// The client to merge away
+    pub duplicate_id: Uuid,
+
+    /// The client to keep
+    pub canonical_id: Uuid,
+}
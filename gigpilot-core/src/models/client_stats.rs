@@ -0,0 +1,40 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// How a client has paid so far.
+///
+/// This struct maps to the `client_stats` table, which the worker
+/// recomputes periodically (see [`crate::clients::stats`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct ClientStats {
+    /// Client the stats describe
+    pub client_id: Uuid,
+
+    /// ID of the user who owns the client
+    pub user_id: Uuid,
+
+    /// Invoices past their due date (paid or not)
+    pub invoices_due: i32,
+
+    /// Paid invoices
+    pub invoices_paid: i32,
+
+    /// Average days from issue to payment of paid invoices
+    pub average_days_to_pay: Option<Decimal>,
+
+    /// Average days paid invoices were paid after their due date
+    /// (negative when paid early)
+    pub average_days_late: Option<Decimal>,
+
+    /// Due invoices that needed the firm level-2 reminder
+    pub level_2_chases: i32,
+
+    /// Percentage of due invoices that needed the level-2 reminder
+    pub level_2_rate: Option<Decimal>,
+
+    /// Timestamp when the stats were computed
+    pub refreshed_at: DateTime<Utc>,
+}
@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Contract signing status enumeration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    /// Being prepared, not sent for signing yet
+    #[sqlx(rename = "draft")]
+    Draft,
+
+    /// Sent to the client for signing
+    #[sqlx(rename = "sent")]
+    Sent,
+
+    /// Signed by the client
+    #[sqlx(rename = "signed")]
+    Signed,
+
+    /// The client declined to sign
+    #[sqlx(rename = "declined")]
+    Declined,
+
+    /// Withdrawn before it was signed
+    #[sqlx(rename = "voided")]
+    Voided,
+}
+
+impl ContractStatus {
+    /// Returns the database representation of the status.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContractStatus::Draft => "draft",
+            ContractStatus::Sent => "sent",
+            ContractStatus::Signed => "signed",
+            ContractStatus::Declined => "declined",
+            ContractStatus::Voided => "voided",
+        }
+    }
+
+    /// Whether a contract in this status may move to `to`.
+    ///
+    /// Signed and voided contracts are final; a declined contract may be
+    /// sent again.
+    pub fn can_become(&self, to: ContractStatus) -> bool {
+        use ContractStatus::*;
+        matches!(
+            (self, to),
+            (Draft, Sent | Signed | Voided) | (Sent, Signed | Declined | Voided) | (Declined, Sent | Voided)
+        )
+    }
+}
+
+/// Contract model tracking an agreement sent to a client for signing.
+///
+/// This struct maps to the `contracts` table. Signing is tracked manually
+/// or by an e-sign provider through the webhook; the signed PDF lives in
+/// the storage backend under `signed_storage_key`, which is never exposed
+/// to clients.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Contract {
+    /// Unique identifier for the contract
+    pub id: Uuid,
+
+    /// ID of the user who owns this contract
+    pub user_id: Uuid,
+
+    /// Client the contract is with
+    pub client_id: Option<Uuid>,
+
+    /// Project the contract covers
+    pub project_id: Option<Uuid>,
+
+    /// Contract title (e.g. "Website redesign agreement")
+    pub title: String,
+
+    /// Signing status
+    pub status: ContractStatus,
+
+    /// E-sign provider handling the signature (e.g. "docusign")
+    pub provider: Option<String>,
+
+    /// The provider's ID of the document or envelope
+    pub provider_document_id: Option<String>,
+
+    /// Timestamp when the contract was sent for signing
+    pub sent_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the client last opened the contract
+    pub last_viewed_at: Option<DateTime<Utc>>,
+
+    /// Timestamp of the signature
+    pub signed_at: Option<DateTime<Utc>>,
+
+    /// Name of the person who signed
+    pub signed_by_name: Option<String>,
+
+    /// Email of the person who signed
+    pub signed_by_email: Option<String>,
+
+    /// Timestamp when the client declined
+    pub declined_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the contract was voided
+    pub voided_at: Option<DateTime<Utc>>,
+
+    /// File name of the signed PDF
+    pub signed_filename: Option<String>,
+
+    /// Size of the signed PDF in bytes
+    pub signed_size_bytes: Option<i64>,
+
+    /// Object key of the signed PDF in the storage backend
+    #[serde(skip_serializing, default)]
+    pub signed_storage_key: Option<String>,
+
+    /// Soft delete flag
+    pub is_deleted: bool,
+
+    /// Additional metadata (flexible JSON)
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the contract was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the contract was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Contract creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateContract {
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub title: String,
+
+    /// `draft` (default) or `sent`, for contracts already out for signing
+    pub status: Option<ContractStatus>,
+    pub provider: Option<String>,
+    pub provider_document_id: Option<String>,
+    pub metadata: Option<Value>,
+}
+
+/// Contract update request
+///
+/// `signed` can't be set here; it is recorded by uploading the signed PDF
+/// or by the e-sign provider.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateContract {
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub status: Option<ContractStatus>,
+    pub provider: Option<String>,
+    pub provider_document_id: Option<String>,
+    pub metadata: Option<Value>,
+}
+
+/// What an e-sign provider reports about a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningEventKind {
+    Sent,
+    Viewed,
+    Signed,
+    Declined,
+    Voided,
+}
+
+/// Signing event posted by an e-sign provider to the webhook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningEvent {
+    /// Provider name, matching the contract's `provider`
+    pub provider: String,
+
+    /// The provider's ID of the document, matching `provider_document_id`
+    pub document_id: String,
+    pub event: SigningEventKind,
+    pub signer_name: Option<String>,
+    pub signer_email: Option<String>,
+
+    /// When the event happened (default: when it is received)
+    pub occurred_at: Option<DateTime<Utc>>,
+}
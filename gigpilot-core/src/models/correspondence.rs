@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Kind of correspondence entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum CorrespondenceKind {
+    /// Outbound email generated by GigPilot
+    #[sqlx(rename = "email")]
+    Email,
+
+    /// Delivery receipt reported by the email provider
+    #[sqlx(rename = "delivery_receipt")]
+    DeliveryReceipt,
+
+    /// Inbound reply from the client
+    #[sqlx(rename = "reply")]
+    Reply,
+}
+
+/// Correspondence model representing one message about an invoice.
+///
+/// This struct maps to the `correspondence` table and forms the evidence
+/// trail that can be exported per invoice.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Correspondence {
+    /// Unique identifier for the entry
+    pub id: Uuid,
+
+    /// ID of the user who owns the invoice
+    pub user_id: Uuid,
+
+    /// ID of the invoice this message relates to
+    pub invoice_id: Uuid,
+
+    /// Kind of entry (email, delivery receipt, reply)
+    pub kind: CorrespondenceKind,
+
+    /// Sender address
+    pub sender: Option<String>,
+
+    /// Recipient address
+    pub recipient: Option<String>,
+
+    /// Message subject
+    pub subject: Option<String>,
+
+    /// Plain-text body
+    pub body_text: Option<String>,
+
+    /// Rendered HTML body
+    pub body_html: Option<String>,
+
+    /// Message identifier assigned by the email provider
+    pub provider_message_id: Option<String>,
+
+    /// Delivery status reported by the provider
+    pub delivery_status: Option<String>,
+
+    /// Additional provider data (headers, raw receipt, etc.)
+    pub metadata: Option<Value>,
+
+    /// When the message was sent or received
+    pub occurred_at: DateTime<Utc>,
+
+    /// Timestamp when the entry was recorded
+    pub created_at: DateTime<Utc>,
+}
+
+/// Correspondence creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCorrespondence {
+    pub invoice_id: Uuid,
+    pub kind: CorrespondenceKind,
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub subject: Option<String>,
+    pub body_text: Option<String>,
+    pub body_html: Option<String>,
+    pub provider_message_id: Option<String>,
+    pub delivery_status: Option<String>,
+    pub metadata: Option<Value>,
+
+    /// When the message was sent or received (default: now)
+    #[serde(default)]
+    pub occurred_at: Option<DateTime<Utc>>,
+}
+
+/// Delivery receipt or client reply posted by the email provider to the
+/// webhook.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailEvent {
+    /// Invoice the original email was about, passed back by the provider
+    pub invoice_id: Uuid,
+
+    /// `delivery_receipt` or `reply`
+    pub kind: CorrespondenceKind,
+
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub subject: Option<String>,
+    pub body_text: Option<String>,
+    pub body_html: Option<String>,
+    pub provider_message_id: Option<String>,
+
+    /// Delivery status reported for a receipt (e.g. "delivered", "bounced")
+    pub delivery_status: Option<String>,
+
+    /// Raw provider data (headers, receipt payload, etc.)
+    pub metadata: Option<Value>,
+
+    /// When the provider delivered the email or received the reply
+    pub occurred_at: Option<DateTime<Utc>>,
+}
+
+impl From<EmailEvent> for CreateCorrespondence {
+    fn from(event: EmailEvent) -> Self {
+        Self {
+            invoice_id: event.invoice_id,
+            kind: event.kind,
+            sender: event.sender,
+            recipient: event.recipient,
+            subject: event.subject,
+            body_text: event.body_text,
+            body_html: event.body_html,
+            provider_message_id: event.provider_message_id,
+            delivery_status: event.delivery_status,
+            metadata: event.metadata,
+            occurred_at: event.occurred_at,
+        }
+    }
+}
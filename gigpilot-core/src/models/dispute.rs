@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Dispute status enumeration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    /// Waiting for the user; chasing is held
+    #[default]
+    #[sqlx(rename = "open")]
+    Open,
+
+    /// The user resolved the dispute and chasing resumed
+    #[sqlx(rename = "resolved")]
+    Resolved,
+}
+
+/// A client's dispute of an invoice, submitted from the portal.
+///
+/// This struct maps to the `invoice_disputes` table. The attached file, if
+/// any, lives in the storage backend under `attachment_storage_key`, which
+/// is never exposed.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Dispute {
+    /// Unique identifier for the dispute
+    pub id: Uuid,
+
+    /// ID of the user who owns the invoice
+    pub user_id: Uuid,
+
+    /// Disputed invoice
+    pub invoice_id: Uuid,
+
+    /// Client who disputed it
+    pub client_id: Uuid,
+
+    /// Portal token the client used
+    pub token_id: Option<Uuid>,
+
+    /// Why the client disputes the invoice
+    pub reason: String,
+
+    /// File name of the client's attachment
+    pub attachment_filename: Option<String>,
+
+    /// MIME type of the client's attachment
+    pub attachment_content_type: Option<String>,
+
+    /// Size of the client's attachment in bytes
+    pub attachment_size_bytes: Option<i64>,
+
+    /// Object key of the attachment in the storage backend
+    #[serde(skip_serializing, default)]
+    pub attachment_storage_key: Option<String>,
+
+    /// Dispute status
+    pub status: DisputeStatus,
+
+    /// Chase state of the invoice before the dispute, restored on resolution
+    pub previous_chase_state: Option<String>,
+
+    /// Timestamp when the user resolved the dispute
+    pub resolved_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the user was emailed about the dispute
+    pub email_sent_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the client submitted the dispute
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the dispute was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// File uploaded with a dispute.
+#[derive(Debug, Clone)]
+pub struct DisputeAttachment {
+    /// Sanitised file name
+    pub filename: String,
+
+    /// MIME type of the file
+    pub content_type: String,
+
+    /// Raw file contents
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,145 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Estimate status enumeration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum EstimateStatus {
+    /// Being prepared, not shown to the client yet
+    #[sqlx(rename = "draft")]
+    Draft,
+
+    /// Sent to the client, awaiting a decision
+    #[sqlx(rename = "sent")]
+    Sent,
+
+    /// Accepted by the client
+    #[sqlx(rename = "accepted")]
+    Accepted,
+
+    /// Declined by the client
+    #[sqlx(rename = "declined")]
+    Declined,
+
+    /// Past its validity date without a decision
+    #[sqlx(rename = "expired")]
+    Expired,
+
+    /// Turned into an invoice
+    #[sqlx(rename = "converted")]
+    Converted,
+}
+
+impl EstimateStatus {
+    /// Whether an estimate in this status may be converted to an invoice.
+    pub fn is_convertible(&self) -> bool {
+        matches!(self, EstimateStatus::Draft | EstimateStatus::Sent | EstimateStatus::Accepted)
+    }
+}
+
+/// Estimate (quote) model representing proposed work for a client.
+///
+/// This struct maps to the `estimates` table and includes sync metadata
+/// for offline-first synchronization. An accepted estimate can be converted
+/// into an invoice carrying the same line items.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Estimate {
+    /// Unique identifier for the estimate
+    pub id: Uuid,
+
+    /// ID of the user who owns this estimate
+    pub user_id: Uuid,
+
+    /// Estimate number (unique per user)
+    pub estimate_number: String,
+
+    /// Client name
+    pub client_name: String,
+
+    /// Client email address
+    pub client_email: Option<String>,
+
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Estimate status
+    pub status: EstimateStatus,
+
+    /// Date when the estimate was issued
+    pub issue_date: NaiveDate,
+
+    /// Last day the estimate can be accepted
+    pub valid_until: Option<NaiveDate>,
+
+    /// Estimate description
+    pub description: Option<String>,
+
+    /// Line items (JSON array of `LineItem`)
+    pub line_items: Value,
+
+    /// Sum of line item nets, before tax (computed)
+    pub subtotal: Decimal,
+
+    /// Sum of line item taxes (computed)
+    pub tax_total: Decimal,
+
+    /// Subtotal plus tax (computed)
+    pub total: Decimal,
+
+    /// Invoice the estimate was converted into
+    pub invoice_id: Option<Uuid>,
+
+    /// Last modification timestamp (for sync)
+    pub last_modified: DateTime<Utc>,
+
+    /// Version vector for CRDT sync (device_id -> timestamp)
+    pub version_vector: Option<Value>,
+
+    /// Soft delete flag (for sync)
+    pub is_deleted: bool,
+
+    /// Additional metadata (flexible JSON)
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the estimate was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the estimate was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Estimate creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateEstimate {
+    /// Estimate number; generated (`EST-00001`) when omitted
+    pub estimate_number: Option<String>,
+    pub client_name: String,
+    pub client_email: Option<String>,
+    pub currency: Option<String>,
+    pub status: Option<EstimateStatus>,
+    pub issue_date: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+    pub description: Option<String>,
+    pub line_items: Value,
+    pub metadata: Option<Value>,
+}
+
+/// Estimate update request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateEstimate {
+    pub estimate_number: Option<String>,
+    pub client_name: Option<String>,
+    pub client_email: Option<String>,
+    pub currency: Option<String>,
+    pub status: Option<EstimateStatus>,
+    pub issue_date: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+    pub description: Option<String>,
+    pub line_items: Option<Value>,
+    pub metadata: Option<Value>,
+}
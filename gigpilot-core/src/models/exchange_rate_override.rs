@@ -0,0 +1,60 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::currency::normalize_currency;
+
+/// Fixed exchange rate used to convert one invoice in reports.
+///
+/// Stored in the invoice's `exchange_rate_override` JSONB column. Only
+/// applies when reports convert into `base_currency`; otherwise market
+/// rates are used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExchangeRateOverride {
+    /// Currency the rate converts into (the user's base currency)
+    pub base_currency: String,
+
+    /// Units of `base_currency` per one unit of the invoice currency
+    pub rate: Decimal,
+}
+
+impl ExchangeRateOverride {
+    /// Parses the invoice's `exchange_rate_override` column.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` for missing or null values.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stored JSON is not a valid override.
+    pub fn parse(value: Option<&Value>) -> Result<Option<Self>, anyhow::Error> {
+        match value {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("Invalid exchange_rate_override: {}", e)),
+        }
+    }
+
+    /// Validates and normalizes an override before it is stored.
+    ///
+    /// # Returns
+    ///
+    /// Returns a user-facing message describing the first problem found.
+    pub fn validate(&mut self) -> Result<(), String> {
+        self.base_currency = normalize_currency(&self.base_currency).map_err(|e| e.to_string())?;
+        if self.rate <= Decimal::ZERO {
+            return Err("rate must be greater than zero".to_string());
+        }
+        if self.rate != self.rate.round_dp(10) {
+            return Err("rate allows at most 10 decimal places".to_string());
+        }
+        Ok(())
+    }
+
+    /// The fixed rate, if it converts into `base_currency`.
+    pub fn rate_into(&self, base_currency: &str) -> Option<Decimal> {
+        (self.base_currency == base_currency).then_some(self.rate)
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Review status of an expense
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum ExpenseStatus {
+    /// Created automatically (e.g. from OCR), awaiting user review
+    #[sqlx(rename = "suggested")]
+    Suggested,
+
+    /// Entered or confirmed by the user
+    #[sqlx(rename = "confirmed")]
+    Confirmed,
+}
+
+/// Expense model representing money spent by the user.
+///
+/// This struct maps to the `expenses` table and includes sync metadata
+/// for offline-first synchronization.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Expense {
+    /// Unique identifier for the expense
+    pub id: Uuid,
+
+    /// ID of the user who owns this expense
+    pub user_id: Uuid,
+
+    /// Merchant or payee
+    pub vendor: Option<String>,
+
+    /// Free-text description
+    pub description: Option<String>,
+
+    /// Date of the purchase
+    pub expense_date: Option<NaiveDate>,
+
+    /// Amount spent
+    pub amount: Decimal,
+
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// User-defined category
+    pub category: Option<String>,
+
+    /// Review status
+    pub status: ExpenseStatus,
+
+    /// How the expense was created ("manual" or "ocr")
+    pub source: String,
+
+    /// Receipt this expense was extracted from
+    pub receipt_id: Option<Uuid>,
+
+    /// Client the expense is re-billed to
+    #[sqlx(default)]
+    pub client_name: Option<String>,
+
+    /// Email of the client the expense is re-billed to
+    #[sqlx(default)]
+    pub client_email: Option<String>,
+
+    /// Whether the expense should be billed to the client
+    #[sqlx(default)]
+    pub billable: bool,
+
+    /// Invoice the expense was billed on
+    #[sqlx(default)]
+    pub invoice_id: Option<Uuid>,
+
+    /// Last modification timestamp (for sync)
+    pub last_modified: DateTime<Utc>,
+
+    /// Version vector for CRDT sync (device_id -> timestamp)
+    pub version_vector: Option<Value>,
+
+    /// Soft delete flag (for sync)
+    pub is_deleted: bool,
+
+    /// Additional metadata
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the expense was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the expense was last updated
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,124 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Tool an account import was exported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// FreshBooks CSV reports
+    #[sqlx(rename = "freshbooks")]
+    FreshBooks,
+
+    /// Wave CSV exports
+    #[sqlx(rename = "wave")]
+    Wave,
+}
+
+impl ImportSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportSource::FreshBooks => "freshbooks",
+            ImportSource::Wave => "wave",
+        }
+    }
+}
+
+impl fmt::Display for ImportSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ImportSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "freshbooks" => Ok(ImportSource::FreshBooks),
+            "wave" => Ok(ImportSource::Wave),
+            other => Err(format!("unknown import source: {}", other)),
+        }
+    }
+}
+
+/// Import job lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum ImportJobStatus {
+    /// Files uploaded and parsed; the mapping can still be changed
+    #[sqlx(rename = "preview")]
+    Preview,
+
+    /// Confirmed, waiting for the worker
+    #[sqlx(rename = "queued")]
+    Queued,
+
+    /// Being imported by the worker
+    #[sqlx(rename = "running")]
+    Running,
+
+    /// Finished; see the report for per-entity results
+    #[sqlx(rename = "completed")]
+    Completed,
+
+    /// Stopped by an error; rows imported before it are kept
+    #[sqlx(rename = "failed")]
+    Failed,
+}
+
+/// Import job model, one upload of another tool's exports.
+///
+/// This struct maps to the `import_jobs` table. The uploaded files are not
+/// loaded; use [`IMPORT_JOB_COLUMNS`] when selecting jobs.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ImportJob {
+    /// Unique identifier for the job
+    pub id: Uuid,
+
+    /// ID of the importing user
+    pub user_id: Uuid,
+
+    /// Tool the files were exported from
+    pub source: ImportSource,
+
+    /// Job status
+    pub status: ImportJobStatus,
+
+    /// Column mapping and date format (JSON `ImportSettings`)
+    pub options: Value,
+
+    /// Parsed preview of each uploaded file (JSON `ImportPreview`)
+    pub preview: Option<Value>,
+
+    /// Per-entity results once the import ran (JSON `ImportJobReport`)
+    pub report: Option<Value>,
+
+    /// Why the job failed
+    pub error: Option<String>,
+
+    /// Number of times the worker picked the job up
+    pub attempts: i32,
+
+    /// Timestamp when the worker last started the job
+    pub started_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the job completed or failed
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the files were uploaded
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the job was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Columns of [`ImportJob`], excluding the uploaded files.
+pub const IMPORT_JOB_COLUMNS: &str = "id, user_id, source, status, options, preview, report, error, \
+    attempts, started_at, finished_at, created_at, updated_at";
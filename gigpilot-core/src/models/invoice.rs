@@ -1,11 +1,17 @@
+use std::fmt;
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc, NaiveDate};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::currency::{Money, MoneyError};
+use crate::models::line_item::{LineItem, StoredLineItems};
+
 /// Invoice status enumeration
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "varchar")]
 pub enum InvoiceStatus {
     #[sqlx(rename = "draft")]
@@ -20,6 +26,128 @@ pub enum InvoiceStatus {
     Cancelled,
 }
 
+impl InvoiceStatus {
+    /// Status as stored in the database and exchanged with sync clients.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvoiceStatus::Draft => "draft",
+            InvoiceStatus::Sent => "sent",
+            InvoiceStatus::Paid => "paid",
+            InvoiceStatus::Overdue => "overdue",
+            InvoiceStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether an invoice in this status may move to `to`.
+    ///
+    /// Invoices go draft → sent → paid, with sent invoices becoming overdue
+    /// and anything unpaid able to be cancelled. Paid and cancelled are
+    /// final. Keeping the current status is always allowed, since sync
+    /// clients push whole records.
+    pub fn can_transition(self, to: InvoiceStatus) -> bool {
+        use InvoiceStatus::*;
+
+        self == to
+            || matches!(
+                (self, to),
+                (Draft, Sent)
+                    | (Draft, Cancelled)
+                    | (Sent, Paid)
+                    | (Sent, Overdue)
+                    | (Sent, Cancelled)
+                    | (Overdue, Paid)
+                    | (Overdue, Cancelled)
+            )
+    }
+
+    /// Checks a status change, for use before writing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StatusError::InvalidTransition` if the change is not allowed.
+    pub fn transition(self, to: InvoiceStatus) -> Result<InvoiceStatus, StatusError> {
+        if self.can_transition(to) {
+            Ok(to)
+        } else {
+            Err(StatusError::InvalidTransition { from: self, to })
+        }
+    }
+}
+
+impl fmt::Display for InvoiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for InvoiceStatus {
+    type Err = StatusError;
+
+    /// Parses a status case-insensitively ("sent", "Sent").
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "draft" => Ok(InvoiceStatus::Draft),
+            "sent" => Ok(InvoiceStatus::Sent),
+            "paid" => Ok(InvoiceStatus::Paid),
+            "overdue" => Ok(InvoiceStatus::Overdue),
+            "cancelled" => Ok(InvoiceStatus::Cancelled),
+            _ => Err(StatusError::Unknown(s.to_string())),
+        }
+    }
+}
+
+/// A rejected invoice status, reported to REST and sync clients alike.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusError {
+    /// The value is not a known invoice status
+    Unknown(String),
+
+    /// The invoice cannot move from its current status to the new one
+    InvalidTransition { from: InvoiceStatus, to: InvoiceStatus },
+}
+
+impl StatusError {
+    /// Machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            StatusError::Unknown(_) => "unknown_status",
+            StatusError::InvalidTransition { .. } => "invalid_status_transition",
+        }
+    }
+
+    /// The statuses involved: `status`, or `from` and `to`.
+    pub fn details(&self) -> Value {
+        match self {
+            StatusError::Unknown(status) => json!({ "status": status }),
+            StatusError::InvalidTransition { from, to } => json!({
+                "from": from.as_str(),
+                "to": to.as_str(),
+            }),
+        }
+    }
+
+    /// Error body returned to clients: message, code and details.
+    pub fn to_json(&self) -> Value {
+        let mut body = self.details();
+        body["error"] = json!(self.to_string());
+        body["code"] = json!(self.code());
+        body
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusError::Unknown(status) => write!(f, "unknown invoice status: {}", status),
+            StatusError::InvalidTransition { from, to } => {
+                write!(f, "invoice status cannot change from {} to {}", from, to)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StatusError {}
+
 /// Invoice model representing an invoice in the system.
 /// 
 /// This struct maps to the `invoices` table and includes sync metadata
@@ -41,6 +169,14 @@ pub struct Invoice {
     /// Client email address
     pub client_email: Option<String>,
     
+    /// Client record the invoice was issued to
+    #[sqlx(default)]
+    pub client_id: Option<Uuid>,
+    
+    /// Project the invoice bills
+    #[sqlx(default)]
+    pub project_id: Option<Uuid>,
+    
     /// Invoice amount
     pub amount: rust_decimal::Decimal,
     
@@ -68,8 +204,36 @@ pub struct Invoice {
     /// Invoice description
     pub description: Option<String>,
     
-    /// Line items (JSON array)
-    pub line_items: Option<Value>,
+    /// Line items (stored as a JSONB array)
+    pub line_items: Option<StoredLineItems>,
+    
+    /// Sum of line item nets, before tax (computed)
+    #[sqlx(default)]
+    pub subtotal: rust_decimal::Decimal,
+    
+    /// Sum of line item taxes (computed)
+    #[sqlx(default)]
+    pub tax_total: rust_decimal::Decimal,
+    
+    /// Subtotal plus tax (computed)
+    #[sqlx(default)]
+    pub total: rust_decimal::Decimal,
+    
+    /// Sum of recorded payments (computed)
+    #[sqlx(default)]
+    pub amount_paid: rust_decimal::Decimal,
+    
+    /// Per-invoice chase policy override (JSON `ChaseOverride`)
+    #[sqlx(default)]
+    pub chase_override: Option<Value>,
+    
+    /// Fixed exchange rate for reports (JSON `ExchangeRateOverride`)
+    #[sqlx(default)]
+    pub exchange_rate_override: Option<Value>,
+    
+    /// Timestamp until which reminders are paused
+    #[sqlx(default)]
+    pub chase_paused_until: Option<DateTime<Utc>>,
     
     /// Additional metadata (flexible JSON)
     pub metadata: Option<Value>,
@@ -93,7 +257,7 @@ pub struct CreateInvoice {
     pub due_date: Option<NaiveDate>,
     pub issue_date: Option<NaiveDate>,
     pub description: Option<String>,
-    pub line_items: Option<Value>,
+    pub line_items: Option<Vec<LineItem>>,
     pub metadata: Option<Value>,
 }
 
@@ -109,11 +273,18 @@ pub struct UpdateInvoice {
     pub due_date: Option<NaiveDate>,
     pub issue_date: Option<NaiveDate>,
     pub description: Option<String>,
-    pub line_items: Option<Value>,
+    pub line_items: Option<Vec<LineItem>>,
     pub metadata: Option<Value>,
     pub version_vector: Option<Value>,
 }
 
+/// Invoice status change request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateInvoiceStatus {
+    /// New status ("sent", "paid", ...); parsed with `InvoiceStatus::from_str`
+    pub status: String,
+}
+
 /// Invoice response (public representation)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InvoiceResponse {
@@ -122,6 +293,8 @@ pub struct InvoiceResponse {
     pub invoice_number: String,
     pub client_name: String,
     pub client_email: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
     pub amount: rust_decimal::Decimal,
     pub currency: String,
     pub status: InvoiceStatus,
@@ -130,20 +303,67 @@ pub struct InvoiceResponse {
     pub last_modified: DateTime<Utc>,
     pub version_vector: Option<Value>,
     pub description: Option<String>,
-    pub line_items: Option<Value>,
+    pub line_items: Option<Vec<LineItem>>,
+    pub subtotal: rust_decimal::Decimal,
+    pub tax_total: rust_decimal::Decimal,
+    pub total: rust_decimal::Decimal,
+    pub amount_paid: rust_decimal::Decimal,
+    pub balance_due: rust_decimal::Decimal,
+    pub chase_override: Option<Value>,
+    pub exchange_rate_override: Option<Value>,
+    pub chase_paused_until: Option<DateTime<Utc>>,
     pub metadata: Option<Value>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl Invoice {
+    /// The invoice's line items (empty if it has none).
+    pub fn items(&self) -> &[LineItem] {
+        self.line_items.as_ref().map(|items| items.0.as_slice()).unwrap_or(&[])
+    }
+
+    /// Amount still owed: total minus recorded payments, never negative.
+    pub fn balance_due(&self) -> rust_decimal::Decimal {
+        (self.total - self.amount_paid).max(rust_decimal::Decimal::ZERO)
+    }
+
+    /// An amount in the invoice currency.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the invoice currency is not supported.
+    pub fn money(&self, amount: rust_decimal::Decimal) -> Result<Money, MoneyError> {
+        Money::parse(amount, &self.currency)
+    }
+
+    /// [`Invoice::balance_due`] in the invoice currency.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the invoice currency is not supported.
+    pub fn balance_due_money(&self) -> Result<Money, MoneyError> {
+        let paid = self.money(self.amount_paid)?;
+        Ok(self.money(self.total)?.checked_sub(paid)?.max_zero())
+    }
+
+    /// Whether reminders for the invoice are paused at `now`.
+    pub fn is_chase_paused(&self, now: DateTime<Utc>) -> bool {
+        self.chase_paused_until.is_some_and(|until| until > now)
+    }
+}
+
 impl From<Invoice> for InvoiceResponse {
     fn from(invoice: Invoice) -> Self {
+        let balance_due = invoice.balance_due();
         InvoiceResponse {
             id: invoice.id,
             user_id: invoice.user_id,
             invoice_number: invoice.invoice_number,
             client_name: invoice.client_name,
             client_email: invoice.client_email,
+            client_id: invoice.client_id,
+            project_id: invoice.project_id,
             amount: invoice.amount,
             currency: invoice.currency,
             status: invoice.status,
@@ -152,7 +372,15 @@ impl From<Invoice> for InvoiceResponse {
             last_modified: invoice.last_modified,
             version_vector: invoice.version_vector,
             description: invoice.description,
-            line_items: invoice.line_items,
+            line_items: invoice.line_items.map(|items| items.0),
+            subtotal: invoice.subtotal,
+            tax_total: invoice.tax_total,
+            total: invoice.total,
+            amount_paid: invoice.amount_paid,
+            balance_due,
+            chase_override: invoice.chase_override,
+            exchange_rate_override: invoice.exchange_rate_override,
+            chase_paused_until: invoice.chase_paused_until,
             metadata: invoice.metadata,
             created_at: invoice.created_at,
             updated_at: invoice.updated_at,
@@ -160,3 +388,52 @@ impl From<Invoice> for InvoiceResponse {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InvoiceStatus::*;
+
+    #[test]
+    fn test_legal_transitions() {
+        assert!(Draft.can_transition(Sent));
+        assert!(Draft.can_transition(Cancelled));
+        assert!(Sent.can_transition(Paid));
+        assert!(Sent.can_transition(Overdue));
+        assert!(Overdue.can_transition(Paid));
+        assert!(Overdue.can_transition(Cancelled));
+        assert!(Paid.can_transition(Paid));
+    }
+
+    #[test]
+    fn test_invalid_jumps_are_rejected() {
+        assert!(!Draft.can_transition(Paid));
+        assert!(!Draft.can_transition(Overdue));
+        assert!(!Sent.can_transition(Draft));
+        assert!(!Paid.can_transition(Sent));
+        assert!(!Paid.can_transition(Cancelled));
+        assert!(!Cancelled.can_transition(Draft));
+
+        let err = Draft.transition(Paid).unwrap_err();
+        assert_eq!(err, StatusError::InvalidTransition { from: Draft, to: Paid });
+        assert_eq!(
+            err.to_json(),
+            json!({
+                "error": "invoice status cannot change from draft to paid",
+                "code": "invalid_status_transition",
+                "from": "draft",
+                "to": "paid",
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!("sent".parse::<InvoiceStatus>(), Ok(Sent));
+        assert_eq!("Overdue".parse::<InvoiceStatus>(), Ok(Overdue));
+        assert_eq!(
+            "archived".parse::<InvoiceStatus>(),
+            Err(StatusError::Unknown("archived".to_string()))
+        );
+    }
+}
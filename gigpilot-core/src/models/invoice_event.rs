@@ -0,0 +1,195 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// What happened to an invoice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceEventAction {
+    #[sqlx(rename = "created")]
+    Created,
+
+    #[sqlx(rename = "updated")]
+    Updated,
+
+    /// Soft-deleted (`is_deleted` set) or removed for good
+    #[sqlx(rename = "deleted")]
+    Deleted,
+
+    /// A soft delete was undone
+    #[sqlx(rename = "restored")]
+    Restored,
+}
+
+/// Path an invoice change came through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSource {
+    /// A REST endpoint
+    #[sqlx(rename = "api")]
+    Api,
+
+    /// A device's sync push
+    #[sqlx(rename = "sync")]
+    Sync,
+
+    /// The server itself (background jobs)
+    #[default]
+    #[sqlx(rename = "system")]
+    System,
+
+    /// A client using their portal
+    #[sqlx(rename = "portal")]
+    Portal,
+}
+
+impl AuditSource {
+    /// Value stored in `invoice_events.source`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditSource::Api => "api",
+            AuditSource::Sync => "sync",
+            AuditSource::System => "system",
+            AuditSource::Portal => "portal",
+        }
+    }
+}
+
+/// Old and new value of one field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    /// Column name (e.g. "status")
+    pub field: String,
+
+    /// Value before the change (null when the invoice was created)
+    pub old: Value,
+
+    /// Value after the change
+    pub new: Value,
+}
+
+/// Invoice event model, one entry of an invoice's history.
+///
+/// This struct maps to the `invoice_events` table, which a database
+/// trigger fills on every change to `invoices`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InvoiceEvent {
+    /// Unique identifier for the event
+    pub id: Uuid,
+
+    /// ID of the changed invoice
+    pub invoice_id: Uuid,
+
+    /// What happened
+    pub action: InvoiceEventAction,
+
+    /// Changed fields as `{ "<field>": { "old": .., "new": .. } }`
+    pub changes: Json<Value>,
+
+    /// Person who made the change (None for background jobs)
+    pub actor_id: Option<Uuid>,
+
+    /// Email of that person
+    pub actor_email: Option<String>,
+
+    /// Device the change came from, when known
+    pub device_id: Option<String>,
+
+    /// Path the change came through
+    pub source: AuditSource,
+
+    /// When the change was made
+    pub created_at: DateTime<Utc>,
+}
+
+/// An invoice history entry as returned by the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoiceHistoryEntry {
+    /// Unique identifier for the event
+    pub id: Uuid,
+
+    /// What happened
+    pub action: InvoiceEventAction,
+
+    /// Changed fields, in field name order
+    pub changes: Vec<FieldChange>,
+
+    /// Person who made the change (None for background jobs)
+    pub actor_id: Option<Uuid>,
+
+    /// Email of that person
+    pub actor_email: Option<String>,
+
+    /// Device the change came from, when known
+    pub device_id: Option<String>,
+
+    /// Path the change came through
+    pub source: AuditSource,
+
+    /// When the change was made
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<InvoiceEvent> for InvoiceHistoryEntry {
+    fn from(event: InvoiceEvent) -> Self {
+        Self {
+            id: event.id,
+            action: event.action,
+            changes: field_changes(&event.changes),
+            actor_id: event.actor_id,
+            actor_email: event.actor_email,
+            device_id: event.device_id,
+            source: event.source,
+            created_at: event.created_at,
+        }
+    }
+}
+
+/// Lists the field changes stored in an event, in field name order.
+///
+/// Missing `old`/`new` values read as null.
+pub fn field_changes(changes: &Value) -> Vec<FieldChange> {
+    let Some(fields) = changes.as_object() else {
+        return Vec::new();
+    };
+
+    let mut changes: Vec<FieldChange> = fields
+        .iter()
+        .map(|(field, change)| FieldChange {
+            field: field.clone(),
+            old: change.get("old").cloned().unwrap_or(Value::Null),
+            new: change.get("new").cloned().unwrap_or(Value::Null),
+        })
+        .collect();
+    changes.sort_by(|a, b| a.field.cmp(&b.field));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_field_changes_are_sorted() {
+        let changes = json!({
+            "status": { "old": "draft", "new": "sent" },
+            "amount_paid": { "old": "0.00", "new": "50.00" },
+            "description": { "new": "Logo" }
+        });
+
+        let fields = field_changes(&changes);
+
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0].field, "amount_paid");
+        assert_eq!(fields[1].old, Value::Null);
+        assert_eq!(fields[1].new, json!("Logo"));
+        assert_eq!(fields[2].new, json!("sent"));
+        assert!(field_changes(&json!([])).is_empty());
+    }
+}
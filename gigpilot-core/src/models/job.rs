@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Worker job lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting to be claimed once due
+    #[sqlx(rename = "queued")]
+    Queued,
+
+    /// Claimed by a worker until its lock lapses
+    #[sqlx(rename = "running")]
+    Running,
+
+    /// Done
+    #[sqlx(rename = "completed")]
+    Completed,
+
+    /// Out of attempts
+    #[sqlx(rename = "failed")]
+    Failed,
+}
+
+/// Worker job model, one unit of work in the durable queue.
+///
+/// This struct maps to the `jobs` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Job {
+    /// Unique identifier for the job
+    pub id: Uuid,
+
+    /// What the job does
+    pub kind: String,
+
+    /// The job's input, by kind
+    pub payload: Value,
+
+    /// At most one queued or running job of a kind has this key
+    pub dedupe_key: Option<String>,
+
+    /// Job status
+    pub status: JobStatus,
+
+    /// Number of times a worker claimed the job
+    pub attempts: i32,
+
+    /// Number of claims after which a failing job is given up
+    pub max_attempts: i32,
+
+    /// Timestamp from which the job may be claimed
+    pub run_at: DateTime<Utc>,
+
+    /// Worker holding the job while it runs
+    pub locked_by: Option<String>,
+
+    /// Timestamp when the worker's hold lapses and others may claim it
+    pub locked_until: Option<DateTime<Utc>>,
+
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+
+    /// Timestamp when the job completed or failed
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the job was enqueued
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the job was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data for enqueueing a job.
+#[derive(Debug, Clone)]
+pub struct CreateJob {
+    pub kind: String,
+    pub payload: Value,
+    pub dedupe_key: Option<String>,
+    pub max_attempts: i32,
+
+    /// When the job is due (now if unset)
+    pub run_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,322 @@
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef};
+use sqlx::types::Json;
+use sqlx::{Decode, Encode, Postgres, Type};
+use uuid::Uuid;
+
+use crate::currency::Percent;
+
+/// Longest line item description accepted.
+pub const MAX_DESCRIPTION_LENGTH: usize = 500;
+
+/// A single invoice line item.
+///
+/// Stored as an element of the invoice's `line_items` JSONB array.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineItem {
+    /// What was delivered
+    #[serde(default)]
+    pub description: String,
+
+    /// Quantity (hours, units, etc.)
+    #[serde(default = "default_quantity")]
+    pub quantity: Decimal,
+
+    /// Price per unit, before tax
+    pub unit_price: Decimal,
+
+    /// Tax rate as a percentage (e.g. 20 for 20% VAT)
+    #[serde(default)]
+    pub tax_rate: Option<Percent>,
+
+    /// Reference to one of the user's saved tax rates
+    #[serde(default)]
+    pub tax_rate_id: Option<Uuid>,
+}
+
+fn default_quantity() -> Decimal {
+    Decimal::ONE
+}
+
+impl LineItem {
+    /// Net amount of the line (quantity × unit price).
+    pub fn net(&self) -> Decimal {
+        (self.quantity * self.unit_price).round_dp(2)
+    }
+
+    /// Tax due on the line, rounded to cents.
+    pub fn tax(&self) -> Decimal {
+        let rate = self.tax_rate.unwrap_or_default();
+        rate.of(self.net()).round_dp(2)
+    }
+
+    /// Checks the line's values.
+    ///
+    /// # Returns
+    ///
+    /// Returns every problem found as `(field, message)` pairs.
+    pub fn problems(&self) -> Vec<(&'static str, String)> {
+        let mut problems = Vec::new();
+        if self.description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            problems.push((
+                "description",
+                format!("must be at most {} characters", MAX_DESCRIPTION_LENGTH),
+            ));
+        }
+        if self.quantity <= Decimal::ZERO {
+            problems.push(("quantity", "must be greater than 0".to_string()));
+        }
+        if self.unit_price < Decimal::ZERO {
+            problems.push(("unit_price", "must not be negative".to_string()));
+        }
+        if let Some(rate) = self.tax_rate {
+            if !rate.is_rate() {
+                problems.push(("tax_rate", "must be between 0 and 100".to_string()));
+            }
+        }
+        problems
+    }
+
+    /// Parses and validates a `line_items` JSON array.
+    ///
+    /// Every element is checked, so the error lists all malformed lines
+    /// rather than the first one.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LineItemsError` if the value is not an array or any
+    /// element is not a valid line item.
+    pub fn parse_list(value: &Value) -> Result<Vec<LineItem>, LineItemsError> {
+        let Some(elements) = value.as_array() else {
+            return Err(LineItemsError {
+                errors: vec![LineItemError {
+                    index: None,
+                    field: None,
+                    error: "line_items must be an array".to_string(),
+                }],
+            });
+        };
+
+        let mut items = Vec::with_capacity(elements.len());
+        let mut errors = Vec::new();
+        for (index, element) in elements.iter().enumerate() {
+            match serde_json::from_value::<LineItem>(element.clone()) {
+                Ok(item) => {
+                    errors.extend(item.problems().into_iter().map(|(field, error)| LineItemError {
+                        index: Some(index),
+                        field: Some(field.to_string()),
+                        error,
+                    }));
+                    items.push(item);
+                }
+                Err(e) => errors.push(LineItemError {
+                    index: Some(index),
+                    field: None,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(items)
+        } else {
+            Err(LineItemsError { errors })
+        }
+    }
+}
+
+/// An invoice's stored `line_items` column.
+///
+/// Rows written before line items were validated may hold JSON that isn't
+/// a valid list. Those read as no line items, so the invoice falls back to
+/// its amount instead of failing every read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StoredLineItems(pub Vec<LineItem>);
+
+impl StoredLineItems {
+    /// Reads stored `line_items` JSON, leniently.
+    pub fn from_value(value: &Value) -> Self {
+        Self(LineItem::parse_list(value).unwrap_or_default())
+    }
+}
+
+impl Type<Postgres> for StoredLineItems {
+    fn type_info() -> PgTypeInfo {
+        <Json<Value> as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <Json<Value> as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for StoredLineItems {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let Json(raw) = <Json<Value> as Decode<Postgres>>::decode(value)?;
+        Ok(Self::from_value(&raw))
+    }
+}
+
+impl Encode<'_, Postgres> for StoredLineItems {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        Json(&self.0).encode_by_ref(buf)
+    }
+}
+
+/// A problem with one line item (or with the list itself).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineItemError {
+    /// Position of the line in the list (None for the list itself)
+    pub index: Option<usize>,
+
+    /// Offending field, when known
+    pub field: Option<String>,
+
+    /// What is wrong
+    pub error: String,
+}
+
+impl fmt::Display for LineItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.index, &self.field) {
+            (Some(index), Some(field)) => write!(f, "line_items[{}].{}: {}", index, field, self.error),
+            (Some(index), None) => write!(f, "line_items[{}]: {}", index, self.error),
+            _ => write!(f, "{}", self.error),
+        }
+    }
+}
+
+/// Malformed line items.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineItemsError {
+    /// Every problem found
+    pub errors: Vec<LineItemError>,
+}
+
+impl LineItemsError {
+    /// Machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        "invalid_line_items"
+    }
+
+    /// The problems, for API and push responses.
+    pub fn details(&self) -> Value {
+        json!({ "line_items": self.errors })
+    }
+
+    /// Error body for API responses.
+    pub fn to_json(&self) -> Value {
+        let mut body = self.details();
+        body["error"] = json!(self.to_string());
+        body["code"] = json!(self.code());
+        body
+    }
+}
+
+impl fmt::Display for LineItemsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self.errors.iter().map(ToString::to_string).collect();
+        write!(f, "{}", messages.join("; "))
+    }
+}
+
+impl std::error::Error for LineItemsError {}
+
+/// Computed invoice totals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct InvoiceTotals {
+    /// Sum of line nets, before tax
+    pub subtotal: Decimal,
+
+    /// Sum of line taxes
+    pub tax_total: Decimal,
+
+    /// Subtotal plus tax
+    pub total: Decimal,
+}
+
+impl InvoiceTotals {
+    /// Computes totals over a set of line items.
+    pub fn compute(items: &[LineItem]) -> Self {
+        let subtotal: Decimal = items.iter().map(LineItem::net).sum();
+        let tax_total: Decimal = items.iter().map(LineItem::tax).sum();
+        Self {
+            subtotal,
+            tax_total,
+            total: subtotal + tax_total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_accepts_strings_and_numbers() {
+        let items = LineItem::parse_list(&json!([
+            { "description": "Design", "quantity": "2", "unit_price": 50, "tax_rate": "20" },
+            { "description": "Hosting", "unit_price": "9.99" }
+        ]))
+        .expect("Should parse");
+        assert_eq!(items[0].quantity, Decimal::from(2));
+        assert_eq!(items[1].quantity, Decimal::ONE);
+        assert_eq!(items[1].tax_rate, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_non_array() {
+        assert!(LineItem::parse_list(&json!({ "unit_price": 1 })).is_err());
+        assert!(LineItem::parse_list(&json!([{ "description": "no price" }])).is_err());
+    }
+
+    #[test]
+    fn test_malformed_stored_lines_read_as_none() {
+        let stored = StoredLineItems::from_value(&json!([{ "description": "no price" }]));
+        assert!(stored.0.is_empty());
+        let stored = StoredLineItems::from_value(&json!([{ "description": "Design", "unit_price": 50 }]));
+        assert_eq!(stored.0.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_reports_every_invalid_line() {
+        let err = LineItem::parse_list(&json!([
+            { "description": "Design", "quantity": 0, "unit_price": "-5" },
+            { "description": "Hosting", "unit_price": "9.99" },
+            { "description": "Support", "unit_price": 10, "tax_rate": 120 },
+            { "description": "Travel", "unit_price": "abc" }
+        ]))
+        .unwrap_err();
+
+        let messages: Vec<String> = err.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0], "line_items[0].quantity: must be greater than 0");
+        assert_eq!(messages[1], "line_items[0].unit_price: must not be negative");
+        assert_eq!(messages[2], "line_items[2].tax_rate: must be between 0 and 100");
+        assert!(messages[3].starts_with("line_items[3]: "));
+        assert_eq!(err.code(), "invalid_line_items");
+        assert_eq!(err.to_json()["line_items"][0]["field"], "quantity");
+    }
+
+    #[test]
+    fn test_totals_with_mixed_tax_rates() {
+        let items = LineItem::parse_list(&json!([
+            { "quantity": 3, "unit_price": "33.33", "tax_rate": 20 },
+            { "quantity": 1, "unit_price": "100", "tax_rate": "5.5" },
+            { "quantity": 1, "unit_price": "10" }
+        ]))
+        .unwrap();
+        let totals = InvoiceTotals::compute(&items);
+        assert_eq!(totals.subtotal, Decimal::new(20999, 2));
+        assert_eq!(totals.tax_total, Decimal::new(2550, 2));
+        assert_eq!(totals.total, Decimal::new(23549, 2));
+    }
+}
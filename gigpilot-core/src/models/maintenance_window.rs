@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Time during which the service is in maintenance.
+///
+/// This struct maps to the `maintenance_windows` table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct MaintenanceWindow {
+    /// Unique identifier for the window
+    pub id: Uuid,
+
+    /// Message shown to clients while it lasts
+    pub message: Option<String>,
+
+    /// When maintenance started
+    pub starts_at: DateTime<Utc>,
+
+    /// When maintenance ends (moved earlier when ended by hand)
+    pub ends_at: DateTime<Utc>,
+
+    /// Timestamp when the window was created
+    pub created_at: DateTime<Utc>,
+}
+
+impl MaintenanceWindow {
+    /// Whether the window is open at `now`.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+}
+
+/// Maintenance start request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartMaintenance {
+    /// How long maintenance lasts, in minutes
+    pub minutes: i64,
+
+    /// Message shown to clients
+    pub message: Option<String>,
+}
@@ -1,8 +1,77 @@
 pub mod user;
 pub mod invoice;
 pub mod sync_change;
+pub mod correspondence;
+pub mod user_settings;
+pub mod line_item;
+pub mod tax_rate;
+pub mod expense;
+pub mod receipt;
+pub mod payment;
+pub mod chase_override;
+pub mod exchange_rate_override;
+pub mod time_entry;
+pub mod notification;
+pub mod payment_method;
+pub mod estimate;
+pub mod attachment;
+pub mod account;
+pub mod invoice_event;
+pub mod statement_schedule;
+pub mod client;
+pub mod client_interaction;
+pub mod client_merge;
+pub mod import_job;
+pub mod portal;
+pub mod client_stats;
+pub mod project;
+pub mod dispute;
+pub mod bank_transaction;
+pub mod proposal;
+pub mod contract;
+pub mod sandboxed_email;
+pub mod share_grant;
+pub mod retainer;
+pub mod maintenance_window;
+pub mod api_token;
+pub mod pdf_export_job;
+pub mod job;
 
 pub use user::User;
 pub use invoice::Invoice;
 pub use sync_change::SyncChange;
-
+pub use correspondence::Correspondence;
+pub use user_settings::UserSettings;
+pub use line_item::{InvoiceTotals, LineItem};
+pub use tax_rate::TaxRate;
+pub use expense::Expense;
+pub use receipt::Receipt;
+pub use payment::Payment;
+pub use chase_override::ChaseOverride;
+pub use exchange_rate_override::ExchangeRateOverride;
+pub use time_entry::TimeEntry;
+pub use notification::Notification;
+pub use payment_method::{ClientPaymentMethod, PaymentMethodDetails};
+pub use estimate::Estimate;
+pub use attachment::Attachment;
+pub use account::{AccountKind, AccountRole, AccountSummary};
+pub use invoice_event::InvoiceEvent;
+pub use statement_schedule::StatementSchedule;
+pub use client::Client;
+pub use client_interaction::{ClientInteraction, InteractionKind};
+pub use client_merge::ClientMerge;
+pub use import_job::{ImportJob, ImportJobStatus, ImportSource};
+pub use portal::{PortalEvent, PortalToken};
+pub use client_stats::ClientStats;
+pub use project::{Project, ProjectStatus};
+pub use dispute::{Dispute, DisputeStatus};
+pub use bank_transaction::{BankTransaction, BankTransactionStatus};
+pub use proposal::{Proposal, ProposalStatus};
+pub use contract::{Contract, ContractStatus};
+pub use sandboxed_email::SandboxedEmail;
+pub use share_grant::{ShareEntity, ShareGrant, SharePermission};
+pub use retainer::{Retainer, RetainerPeriod, RetainerStatus, RolloverPolicy};
+pub use maintenance_window::MaintenanceWindow;
+pub use api_token::{ApiToken, TokenScope};
+pub use pdf_export_job::{PdfExportFilter, PdfExportJob, PdfExportStatus};
+pub use job::{Job, JobStatus};
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Notification model representing a message for the user in the app.
+///
+/// This struct maps to the `notifications` table. Notifications are
+/// created by the server (e.g. when a weekly draft is ready for review)
+/// and reach devices through sync.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    /// Unique identifier for the notification
+    pub id: Uuid,
+
+    /// ID of the user the notification is for
+    pub user_id: Uuid,
+
+    /// Machine-readable kind (e.g. "weekly_draft_ready")
+    pub kind: String,
+
+    /// Short headline
+    pub title: String,
+
+    /// Longer message
+    pub body: Option<String>,
+
+    /// Kind-specific payload (e.g. invoice IDs)
+    pub data: Option<Value>,
+
+    /// When the user read the notification
+    pub read_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the notification was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the notification was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Notification creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateNotification {
+    pub kind: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub data: Option<Value>,
+}
@@ -0,0 +1,65 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::currency::{Money, MoneyError};
+
+/// Payment model representing money received against an invoice.
+///
+/// This struct maps to the `payments` table. An invoice may have several
+/// partial payments; their sum is the invoice's `amount_paid`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Payment {
+    /// Unique identifier for the payment
+    pub id: Uuid,
+
+    /// ID of the user who owns the invoice
+    pub user_id: Uuid,
+
+    /// ID of the invoice being paid
+    pub invoice_id: Uuid,
+
+    /// Amount received
+    pub amount: Decimal,
+
+    /// Currency code (ISO 4217), always the invoice currency
+    pub currency: String,
+
+    /// Date the payment was received
+    pub paid_on: NaiveDate,
+
+    /// Payment method (e.g. "bank_transfer", "card")
+    pub method: Option<String>,
+
+    /// Bank reference or transaction ID
+    pub reference: Option<String>,
+
+    /// Free-text notes
+    pub notes: Option<String>,
+
+    /// Timestamp when the payment was recorded
+    pub created_at: DateTime<Utc>,
+}
+
+impl Payment {
+    /// The amount received, in the payment currency.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payment currency is not supported.
+    pub fn money(&self) -> Result<Money, MoneyError> {
+        Money::parse(self.amount, &self.currency)
+    }
+}
+
+/// Payment creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatePayment {
+    pub amount: Decimal,
+    pub paid_on: Option<NaiveDate>,
+    pub method: Option<String>,
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+}
@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// How a client can pay, with the details needed to do so.
+///
+/// Stored in the `details` column of `client_payment_methods`, tagged by
+/// `kind`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PaymentMethodDetails {
+    /// Bank transfer to the user's account
+    BankTransfer {
+        /// Name on the receiving account
+        account_name: String,
+
+        /// International Bank Account Number
+        #[serde(default)]
+        iban: Option<String>,
+
+        /// SWIFT/BIC code of the receiving bank
+        #[serde(default)]
+        bic: Option<String>,
+
+        /// Domestic account number (where IBANs are not used)
+        #[serde(default)]
+        account_number: Option<String>,
+
+        /// UK sort code
+        #[serde(default)]
+        sort_code: Option<String>,
+
+        /// US ABA routing number
+        #[serde(default)]
+        routing_number: Option<String>,
+
+        /// Name of the receiving bank
+        #[serde(default)]
+        bank_name: Option<String>,
+    },
+
+    /// Card payment through a Stripe payment link
+    Stripe {
+        /// `https://buy.stripe.com/...` link
+        payment_link: String,
+    },
+
+    /// PayPal payment
+    Paypal {
+        /// PayPal account email
+        #[serde(default)]
+        email: Option<String>,
+
+        /// PayPal.Me link or username
+        #[serde(default)]
+        paypal_me: Option<String>,
+    },
+}
+
+impl PaymentMethodDetails {
+    /// Machine-readable kind, as stored in the `kind` column.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PaymentMethodDetails::BankTransfer { .. } => "bank_transfer",
+            PaymentMethodDetails::Stripe { .. } => "stripe",
+            PaymentMethodDetails::Paypal { .. } => "paypal",
+        }
+    }
+}
+
+/// Payment method accepted from a client.
+///
+/// This struct maps to the `client_payment_methods` table. Methods without
+/// a `client_email` are the user's defaults, used for clients with no
+/// methods of their own.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ClientPaymentMethod {
+    /// Unique identifier for the payment method
+    pub id: Uuid,
+
+    /// ID of the user being paid
+    pub user_id: Uuid,
+
+    /// Client email (lower case), or `None` for the user's defaults
+    pub client_email: Option<String>,
+
+    /// Method kind ("bank_transfer", "stripe", "paypal")
+    pub kind: String,
+
+    /// Method details, see [`PaymentMethodDetails`]
+    pub details: Value,
+
+    /// Display order (lowest first)
+    pub position: i32,
+
+    /// Timestamp when the method was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the method was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ClientPaymentMethod {
+    /// Parses the stored details.
+    pub fn parsed(&self) -> Result<PaymentMethodDetails, anyhow::Error> {
+        serde_json::from_value(self.details.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid payment method {}: {}", self.id, e))
+    }
+}
+
+/// Payment method creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatePaymentMethod {
+    /// Client the method applies to; omit for the user's default
+    pub client_email: Option<String>,
+    pub details: PaymentMethodDetails,
+    pub position: Option<i32>,
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::models::invoice::InvoiceStatus;
+
+/// PDF export job lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum PdfExportStatus {
+    /// Waiting for the worker
+    #[sqlx(rename = "queued")]
+    Queued,
+
+    /// Being rendered by the worker
+    #[sqlx(rename = "running")]
+    Running,
+
+    /// The archive is ready to download
+    #[sqlx(rename = "completed")]
+    Completed,
+
+    /// Stopped by an error
+    #[sqlx(rename = "failed")]
+    Failed,
+}
+
+/// Which invoices a PDF export covers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PdfExportFilter {
+    /// First issue date included
+    pub from: Option<NaiveDate>,
+
+    /// Last issue date included
+    pub to: Option<NaiveDate>,
+
+    /// Statuses included; every status but `draft` when empty
+    #[serde(default)]
+    pub statuses: Vec<InvoiceStatus>,
+
+    /// Only this client's invoices
+    pub client_id: Option<Uuid>,
+}
+
+/// PDF export job model, one ZIP of invoice PDFs.
+///
+/// This struct maps to the `pdf_export_jobs` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PdfExportJob {
+    /// Unique identifier for the job
+    pub id: Uuid,
+
+    /// ID of the exporting user
+    pub user_id: Uuid,
+
+    /// Which invoices to export (JSON `PdfExportFilter`)
+    pub filter: Value,
+
+    /// Job status
+    pub status: PdfExportStatus,
+
+    /// Number of invoices to export
+    pub total: i32,
+
+    /// Number of invoices rendered so far
+    pub processed: i32,
+
+    /// Blob key of the archive, once completed and until it expires
+    #[serde(skip)]
+    pub storage_key: Option<String>,
+
+    /// Why the job failed
+    pub error: Option<String>,
+
+    /// Number of times the worker picked the job up
+    pub attempts: i32,
+
+    /// Timestamp when the worker last started the job
+    pub started_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the job completed or failed
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Timestamp after which the archive is no longer available
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the export was requested
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the job was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PdfExportJob {
+    /// The job's filter.
+    pub fn parsed_filter(&self) -> Result<PdfExportFilter, serde_json::Error> {
+        serde_json::from_value(self.filter.clone())
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Client portal access token model.
+///
+/// This struct maps to the `client_portal_tokens` table. The token handed
+/// to the client is a signed JWT naming the row (see
+/// [`crate::auth::PortalClaims`]).
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PortalToken {
+    /// Unique identifier for the token
+    pub id: Uuid,
+
+    /// ID of the user whose client the token is for
+    pub user_id: Uuid,
+
+    /// Client whose invoices the token shows
+    pub client_id: Uuid,
+
+    /// Where the link was shared
+    pub label: Option<String>,
+
+    /// Timestamp after which the token is rejected
+    pub expires_at: DateTime<Utc>,
+
+    /// Timestamp when the user revoked the token
+    pub revoked_at: Option<DateTime<Utc>>,
+
+    /// Timestamp of the last portal request made with the token
+    pub last_used_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the token was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the token was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Portal token creation request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreatePortalToken {
+    pub label: Option<String>,
+
+    /// Days until the token expires (default 90, at most 365)
+    pub expires_in_days: Option<i64>,
+}
+
+/// What a client did in their portal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum PortalEventKind {
+    /// Opened the invoice list
+    #[sqlx(rename = "portal_viewed")]
+    PortalViewed,
+
+    /// Opened one invoice
+    #[sqlx(rename = "invoice_viewed")]
+    InvoiceViewed,
+
+    /// Downloaded an invoice PDF
+    #[sqlx(rename = "pdf_downloaded")]
+    PdfDownloaded,
+
+    /// Said when they intend to pay an invoice
+    #[sqlx(rename = "intent_to_pay")]
+    IntentToPay,
+
+    /// Disputed an invoice
+    #[sqlx(rename = "dispute_submitted")]
+    DisputeSubmitted,
+}
+
+/// Client portal activity model.
+///
+/// This struct maps to the `client_portal_events` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PortalEvent {
+    /// Unique identifier for the event
+    pub id: Uuid,
+
+    /// ID of the user whose client it was
+    pub user_id: Uuid,
+
+    /// Client who used the portal
+    pub client_id: Uuid,
+
+    /// Token the client used
+    pub token_id: Uuid,
+
+    /// Invoice the event concerns, if any
+    pub invoice_id: Option<Uuid>,
+
+    /// What the client did
+    pub kind: PortalEventKind,
+
+    /// Details, e.g. the payment intent
+    pub data: Option<Value>,
+
+    /// Timestamp of the event
+    pub created_at: DateTime<Utc>,
+}
+
+/// A client's stated plan to pay an invoice.
+///
+/// Stored on the invoice under `metadata.payment_intent`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentIntent {
+    /// Date the client expects to pay
+    pub planned_date: Option<NaiveDate>,
+
+    /// Message from the client
+    pub note: Option<String>,
+
+    /// Timestamp when the client marked the intent
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Payment intent request from the portal
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreatePaymentIntent {
+    pub planned_date: Option<NaiveDate>,
+    pub note: Option<String>,
+}
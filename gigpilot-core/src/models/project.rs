@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Project status enumeration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectStatus {
+    /// Work is ongoing
+    #[default]
+    #[sqlx(rename = "active")]
+    Active,
+
+    /// Paused, expected to resume
+    #[sqlx(rename = "on_hold")]
+    OnHold,
+
+    /// Work is done
+    #[sqlx(rename = "completed")]
+    Completed,
+
+    /// Hidden from everyday lists, kept for history
+    #[sqlx(rename = "archived")]
+    Archived,
+}
+
+/// Project model grouping work done for a client.
+///
+/// This struct maps to the `projects` table and includes sync metadata
+/// for offline-first synchronization. Invoices reference the project they
+/// bill through `project_id`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Project {
+    /// Unique identifier for the project
+    pub id: Uuid,
+
+    /// ID of the user who owns this project
+    pub user_id: Uuid,
+
+    /// Client the project is for
+    pub client_id: Option<Uuid>,
+
+    /// Project name
+    pub name: String,
+
+    /// What the project is about (embedded for the estimator)
+    pub description: Option<String>,
+
+    /// Hourly rate, in `currency`
+    pub rate: Option<Decimal>,
+
+    /// Currency code of the rate (ISO 4217)
+    pub currency: String,
+
+    /// Project status
+    pub status: ProjectStatus,
+
+    /// Budgeted hours
+    #[sqlx(default)]
+    pub budget_hours: Option<Decimal>,
+
+    /// Budgeted amount, in `currency`
+    #[sqlx(default)]
+    pub budget_amount: Option<Decimal>,
+
+    /// Whether the user is emailed when 80% and 100% of the budget are used
+    #[sqlx(default)]
+    pub budget_alerts: bool,
+
+    /// Last modification timestamp (for sync)
+    pub last_modified: DateTime<Utc>,
+
+    /// Version vector for CRDT sync (device_id -> timestamp)
+    pub version_vector: Option<Value>,
+
+    /// Soft delete flag (for sync)
+    pub is_deleted: bool,
+
+    /// Additional metadata (flexible JSON)
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the project was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the project was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Project creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateProject {
+    pub name: String,
+    pub client_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub rate: Option<Decimal>,
+    pub currency: Option<String>,
+    pub status: Option<ProjectStatus>,
+    pub budget_hours: Option<Decimal>,
+    pub budget_amount: Option<Decimal>,
+    pub budget_alerts: Option<bool>,
+    pub metadata: Option<Value>,
+}
+
+/// Project update request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateProject {
+    pub name: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub rate: Option<Decimal>,
+    pub currency: Option<String>,
+    pub status: Option<ProjectStatus>,
+    pub budget_hours: Option<Decimal>,
+    pub budget_amount: Option<Decimal>,
+    pub budget_alerts: Option<bool>,
+    pub metadata: Option<Value>,
+}
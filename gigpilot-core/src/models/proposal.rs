@@ -0,0 +1,231 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::currency::Percent;
+use crate::models::line_item::LineItem;
+
+/// Proposal status enumeration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    /// Being prepared, not shared with the client yet
+    #[sqlx(rename = "draft")]
+    Draft,
+
+    /// Shared with the client, awaiting a decision
+    #[sqlx(rename = "sent")]
+    Sent,
+
+    /// The client accepted one of the options
+    #[sqlx(rename = "accepted")]
+    Accepted,
+
+    /// The client declined the proposal
+    #[sqlx(rename = "declined")]
+    Declined,
+}
+
+impl ProposalStatus {
+    /// Whether the client has decided, freezing the proposal.
+    pub fn is_decided(&self) -> bool {
+        matches!(self, ProposalStatus::Accepted | ProposalStatus::Declined)
+    }
+}
+
+/// One way of pricing the proposed work.
+///
+/// Stored as an element of the proposal's `options` JSONB array; the
+/// client accepts a proposal by picking an option's `key`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProposalOption {
+    /// Identifier of the option within the proposal (e.g. `basic`)
+    pub key: String,
+
+    /// Option name shown to the client
+    pub title: String,
+
+    /// What the option includes
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Priced line items
+    pub line_items: Vec<LineItem>,
+
+    /// Sum of line item nets, before tax (computed)
+    #[serde(default)]
+    pub subtotal: Decimal,
+
+    /// Sum of line item taxes (computed)
+    #[serde(default)]
+    pub tax_total: Decimal,
+
+    /// Subtotal plus tax (computed)
+    #[serde(default)]
+    pub total: Decimal,
+}
+
+/// Proposal model describing a job offered to a client.
+///
+/// This struct maps to the `proposals` table. The client sees it through
+/// its share link and accepts one of its pricing options, which can create
+/// a project and a deposit invoice.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Proposal {
+    /// Unique identifier for the proposal
+    pub id: Uuid,
+
+    /// ID of the user who owns this proposal
+    pub user_id: Uuid,
+
+    /// Client the proposal is for, if saved as a client
+    pub client_id: Option<Uuid>,
+
+    /// Client name
+    pub client_name: String,
+
+    /// Client email address
+    pub client_email: Option<String>,
+
+    /// Proposal title (also the name of the project it creates)
+    pub title: String,
+
+    /// Scope of the work
+    pub scope: Option<String>,
+
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Proposal status
+    pub status: ProposalStatus,
+
+    /// Pricing options (JSON array of `ProposalOption`)
+    pub options: Value,
+
+    /// Last day the proposal can be accepted
+    pub expires_on: Option<NaiveDate>,
+
+    /// Share of the accepted option invoiced as a deposit on acceptance
+    pub deposit_percent: Option<Percent>,
+
+    /// Whether acceptance creates a project
+    pub create_project: bool,
+
+    /// Token of the share link, while the proposal is shared
+    pub share_token: Option<Uuid>,
+
+    /// Timestamp when the proposal was first shared
+    pub shared_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the client first opened the share link
+    pub first_viewed_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the client last opened the share link
+    pub last_viewed_at: Option<DateTime<Utc>>,
+
+    /// Number of times the client opened the share link
+    pub view_count: i32,
+
+    /// Key of the option the client accepted
+    pub accepted_option: Option<String>,
+
+    /// Name the client accepted under
+    pub accepted_by_name: Option<String>,
+
+    /// Email the client accepted under
+    pub accepted_by_email: Option<String>,
+
+    /// Timestamp of the acceptance
+    pub accepted_at: Option<DateTime<Utc>>,
+
+    /// Timestamp when the client declined
+    pub declined_at: Option<DateTime<Utc>>,
+
+    /// Reason the client gave for declining
+    pub decline_reason: Option<String>,
+
+    /// Project created on acceptance
+    pub project_id: Option<Uuid>,
+
+    /// Deposit invoice created on acceptance
+    pub deposit_invoice_id: Option<Uuid>,
+
+    /// Soft delete flag
+    pub is_deleted: bool,
+
+    /// Additional metadata (flexible JSON)
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the proposal was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the proposal was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Proposal {
+    /// The pricing options, or none if the stored JSON is malformed.
+    pub fn pricing_options(&self) -> Vec<ProposalOption> {
+        serde_json::from_value(self.options.clone()).unwrap_or_default()
+    }
+
+    /// Whether the proposal can no longer be accepted because it expired.
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        !self.status.is_decided() && self.expires_on.is_some_and(|expires_on| expires_on < today)
+    }
+}
+
+/// Proposal creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateProposal {
+    pub client_id: Option<Uuid>,
+    pub client_name: String,
+    pub client_email: Option<String>,
+    pub title: String,
+    pub scope: Option<String>,
+    pub currency: Option<String>,
+    pub options: Vec<ProposalOption>,
+    pub expires_on: Option<NaiveDate>,
+    pub deposit_percent: Option<Percent>,
+
+    /// Whether acceptance creates a project (default true)
+    pub create_project: Option<bool>,
+    pub metadata: Option<Value>,
+}
+
+/// Proposal update request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateProposal {
+    pub client_id: Option<Uuid>,
+    pub client_name: Option<String>,
+    pub client_email: Option<String>,
+    pub title: Option<String>,
+    pub scope: Option<String>,
+    pub currency: Option<String>,
+    pub options: Option<Vec<ProposalOption>>,
+    pub expires_on: Option<NaiveDate>,
+    pub deposit_percent: Option<Percent>,
+    pub create_project: Option<bool>,
+    pub metadata: Option<Value>,
+}
+
+/// Acceptance request from the share link
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AcceptProposal {
+    /// Key of the accepted option; may be left out when there is only one
+    pub option: Option<String>,
+
+    /// Name of the person accepting
+    pub name: String,
+    pub email: Option<String>,
+}
+
+/// Decline request from the share link
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeclineProposal {
+    pub reason: Option<String>,
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// OCR processing status of a receipt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum OcrStatus {
+    /// Uploaded, waiting for the OCR job
+    #[sqlx(rename = "pending")]
+    Pending,
+
+    /// Claimed by the OCR job
+    #[sqlx(rename = "processing")]
+    Processing,
+
+    /// Text extracted and expense suggested
+    #[sqlx(rename = "completed")]
+    Completed,
+
+    /// OCR failed after all attempts
+    #[sqlx(rename = "failed")]
+    Failed,
+}
+
+/// Receipt model representing an uploaded receipt file.
+///
+/// This struct maps to the `receipts` table. The file contents are not
+/// loaded; use [`RECEIPT_COLUMNS`] when selecting receipts.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Receipt {
+    /// Unique identifier for the receipt
+    pub id: Uuid,
+
+    /// ID of the user who uploaded the receipt
+    pub user_id: Uuid,
+
+    /// Original file name
+    pub filename: String,
+
+    /// MIME type of the file
+    pub content_type: String,
+
+    /// OCR processing status
+    pub ocr_status: OcrStatus,
+
+    /// Name of the OCR provider that processed the file
+    pub ocr_provider: Option<String>,
+
+    /// Raw text recognised by OCR
+    pub ocr_text: Option<String>,
+
+    /// Last OCR error, if any
+    pub ocr_error: Option<String>,
+
+    /// Number of OCR attempts so far
+    pub attempts: i32,
+
+    /// Fields extracted from the text (vendor, date, amount, currency)
+    pub extracted: Option<Value>,
+
+    /// Expense suggested from this receipt
+    pub expense_id: Option<Uuid>,
+
+    /// Timestamp when the receipt was uploaded
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the receipt was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Column list for [`Receipt`] queries (excludes the file contents).
+pub const RECEIPT_COLUMNS: &str = "id, user_id, filename, content_type, ocr_status, ocr_provider, \
+    ocr_text, ocr_error, attempts, extracted, expense_id, created_at, updated_at";
@@ -0,0 +1,169 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Retainer status enumeration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum RetainerStatus {
+    /// New periods are opened and invoiced every month
+    #[default]
+    #[sqlx(rename = "active")]
+    Active,
+
+    /// No new periods; the current one still closes
+    #[sqlx(rename = "paused")]
+    Paused,
+
+    /// The agreement is over
+    #[sqlx(rename = "ended")]
+    Ended,
+}
+
+/// What happens to hours left unused at the end of a period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum RolloverPolicy {
+    /// They are lost
+    #[default]
+    #[sqlx(rename = "expire")]
+    Expire,
+
+    /// They are added to the next period, up to `max_rollover_hours`
+    #[sqlx(rename = "rollover")]
+    Rollover,
+}
+
+/// Monthly retainer agreement with a client.
+///
+/// This struct maps to the `retainers` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Retainer {
+    /// Unique identifier for the retainer
+    pub id: Uuid,
+
+    /// ID of the user who owns this retainer
+    pub user_id: Uuid,
+
+    /// Client the fee is invoiced to
+    pub client_id: Uuid,
+
+    /// Project whose tracked time is drawn down
+    pub project_id: Uuid,
+
+    /// Name shown on the invoices
+    pub name: String,
+
+    /// Hours included each month
+    pub monthly_hours: Decimal,
+
+    /// Fee invoiced each month, in `currency`
+    pub monthly_amount: Decimal,
+
+    /// Currency code of the fee (ISO 4217)
+    pub currency: String,
+
+    /// What happens to unused hours
+    pub rollover_policy: RolloverPolicy,
+
+    /// Most hours carried into the next period (no limit when `None`)
+    pub max_rollover_hours: Option<Decimal>,
+
+    /// First day of the first period; later periods start on the same day
+    /// of each month
+    pub start_date: NaiveDate,
+
+    /// Last day of the agreement (open-ended when `None`)
+    pub end_date: Option<NaiveDate>,
+
+    /// Retainer status
+    pub status: RetainerStatus,
+
+    /// Timestamp when the retainer was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the retainer was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One month of a retainer.
+///
+/// This struct maps to the `retainer_periods` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RetainerPeriod {
+    /// Unique identifier for the period
+    pub id: Uuid,
+
+    /// ID of the user who owns the retainer
+    pub user_id: Uuid,
+
+    /// The retainer
+    pub retainer_id: Uuid,
+
+    /// First day of the period
+    pub period_start: NaiveDate,
+
+    /// Last day of the period
+    pub period_end: NaiveDate,
+
+    /// Hours included by the retainer
+    pub included_hours: Decimal,
+
+    /// Unused hours carried over from the previous period
+    pub rolled_over_hours: Decimal,
+
+    /// Hours tracked during the period (settled when it closes)
+    pub used_hours: Decimal,
+
+    /// Unused hours carried into the next period
+    pub carried_hours: Decimal,
+
+    /// Unused hours that expired
+    pub expired_hours: Decimal,
+
+    /// When the period was settled (`None` while open)
+    pub closed_at: Option<DateTime<Utc>>,
+
+    /// Draft invoice of the period's fee
+    pub invoice_id: Option<Uuid>,
+
+    /// Timestamp when the period was opened
+    pub created_at: DateTime<Utc>,
+}
+
+/// Retainer creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateRetainer {
+    /// Project whose time is drawn down; its client is invoiced
+    pub project_id: Uuid,
+    pub name: String,
+    pub monthly_hours: Decimal,
+    pub monthly_amount: Decimal,
+
+    /// Currency of the fee (default: the project's)
+    pub currency: Option<String>,
+    pub rollover_policy: Option<RolloverPolicy>,
+    pub max_rollover_hours: Option<Decimal>,
+
+    /// First day of the first period (default: today)
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+}
+
+/// Retainer update request
+///
+/// Changes to hours and fee apply from the next period.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateRetainer {
+    pub name: Option<String>,
+    pub monthly_hours: Option<Decimal>,
+    pub monthly_amount: Option<Decimal>,
+    pub rollover_policy: Option<RolloverPolicy>,
+    pub max_rollover_hours: Option<Decimal>,
+    pub end_date: Option<NaiveDate>,
+    pub status: Option<RetainerStatus>,
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Copy of an email the sandbox kept from its recipient.
+///
+/// This struct maps to the `sandboxed_emails` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SandboxedEmail {
+    /// Unique identifier for the email
+    pub id: Uuid,
+
+    /// ID of the user the email was sent for
+    pub user_id: Uuid,
+
+    /// Address the email was meant for
+    pub recipient: String,
+
+    /// Sandbox inbox it went to instead (`None` if held unsent)
+    pub redirected_to: Option<String>,
+
+    /// Email subject line
+    pub subject: String,
+
+    /// Plain-text body
+    pub body_text: String,
+
+    /// Attachments (`[{ "filename", "content_type", "size_bytes" }]`)
+    pub attachments: Value,
+
+    /// Timestamp when the email would have been sent
+    pub created_at: DateTime<Utc>,
+}
+
+/// Email to keep in the sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSandboxedEmail {
+    pub recipient: String,
+    pub redirected_to: Option<String>,
+    pub subject: String,
+    pub body_text: String,
+    pub attachments: Value,
+}
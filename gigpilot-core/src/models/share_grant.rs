@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Kind of entity that can be shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum ShareEntity {
+    #[sqlx(rename = "project")]
+    Project,
+
+    #[sqlx(rename = "client")]
+    Client,
+}
+
+impl ShareEntity {
+    /// Synced table the entity lives in.
+    pub fn table(self) -> &'static str {
+        match self {
+            ShareEntity::Project => "projects",
+            ShareEntity::Client => "clients",
+        }
+    }
+
+    /// The entity kind stored in a synced table, if it can be shared.
+    pub fn from_table(table: &str) -> Option<Self> {
+        match table {
+            "projects" => Some(ShareEntity::Project),
+            "clients" => Some(ShareEntity::Client),
+            _ => None,
+        }
+    }
+}
+
+/// What a grantee may do with a shared entity.
+///
+/// Ordered so that `Edit` also allows everything `Read` does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum SharePermission {
+    /// View it, and receive its changes in sync
+    #[default]
+    #[sqlx(rename = "read")]
+    Read,
+
+    /// Also change it, through the API or by pushing
+    #[sqlx(rename = "edit")]
+    Edit,
+}
+
+/// A project or client shared with a user outside the owner's organization.
+///
+/// This struct maps to the `share_grants` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ShareGrant {
+    /// Unique identifier for the grant
+    pub id: Uuid,
+
+    /// ID of the account owning the entity
+    pub user_id: Uuid,
+
+    /// ID of the user it is shared with
+    pub grantee_id: Uuid,
+
+    /// Kind of entity shared
+    pub entity_type: ShareEntity,
+
+    /// ID of the shared project or client
+    pub entity_id: Uuid,
+
+    /// What the grantee may do with it
+    pub permission: SharePermission,
+
+    /// Timestamp when the entity was shared
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the permission last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Share request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateShareGrant {
+    pub entity_type: ShareEntity,
+    pub entity_id: Uuid,
+
+    /// Email of the user to share with
+    pub grantee_email: String,
+
+    /// Permission to grant (default: read)
+    pub permission: Option<SharePermission>,
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Language and formats a statement is written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "lowercase")]
+pub enum StatementLocale {
+    /// English (`1,234.56`, `2024-03-01`)
+    #[default]
+    #[sqlx(rename = "en")]
+    En,
+
+    /// German (`1.234,56`, `01.03.2024`)
+    #[sqlx(rename = "de")]
+    De,
+
+    /// French (`1 234,56`, `01/03/2024`)
+    #[sqlx(rename = "fr")]
+    Fr,
+
+    /// Spanish (`1.234,56`, `01/03/2024`)
+    #[sqlx(rename = "es")]
+    Es,
+}
+
+/// How a client wants to receive statements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum StatementChannel {
+    /// Statement in the body of an email to the client email
+    #[default]
+    #[sqlx(rename = "email")]
+    Email,
+}
+
+/// Statement schedule model, one client's monthly statement.
+///
+/// This struct maps to the `statement_schedules` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StatementSchedule {
+    /// Unique identifier for the schedule
+    pub id: Uuid,
+
+    /// ID of the user sending the statements
+    pub user_id: Uuid,
+
+    /// Lower-cased client email the statement covers and is sent to
+    pub client_email: String,
+
+    /// Name used in the greeting
+    pub client_name: Option<String>,
+
+    /// Day of the month statements go out (1-28)
+    pub day_of_month: i16,
+
+    /// Language and formats of the statement
+    pub locale: StatementLocale,
+
+    /// Delivery channel
+    pub channel: StatementChannel,
+
+    /// Whether statements are sent
+    pub enabled: bool,
+
+    /// Next day a statement goes out
+    pub next_run_on: NaiveDate,
+
+    /// Last day a statement went out
+    pub last_sent_on: Option<NaiveDate>,
+
+    /// Timestamp when the schedule was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the schedule was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Statement schedule creation/update request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveStatementSchedule {
+    /// Client email (one schedule per client)
+    pub client_email: String,
+
+    /// Name used in the greeting
+    pub client_name: Option<String>,
+
+    /// Day of the month statements go out (1-28, default 1)
+    pub day_of_month: Option<i16>,
+
+    /// Language and formats (default: en)
+    pub locale: Option<StatementLocale>,
+
+    /// Delivery channel (default: email)
+    pub channel: Option<StatementChannel>,
+
+    /// Whether statements are sent (default: true)
+    pub enabled: Option<bool>,
+}
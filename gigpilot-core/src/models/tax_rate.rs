@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::currency::Percent;
+
+/// Tax rate model representing a user's saved tax rate (VAT, GST, etc.).
+/// 
+/// This struct maps to the `tax_rates` table.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TaxRate {
+    /// Unique identifier for the tax rate
+    pub id: Uuid,
+    
+    /// ID of the user who owns this tax rate
+    pub user_id: Uuid,
+    
+    /// Display name (e.g. "VAT 20%")
+    pub name: String,
+    
+    /// Rate as a percentage (e.g. 20 for 20%)
+    pub rate: Percent,
+    
+    /// Whether this rate is applied to new line items by default
+    pub is_default: bool,
+    
+    /// Timestamp when the tax rate was created
+    pub created_at: DateTime<Utc>,
+    
+    /// Timestamp when the tax rate was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Tax rate creation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateTaxRate {
+    pub name: String,
+    pub rate: Percent,
+    pub is_default: Option<bool>,
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Time entry model representing billable hours worked for a client.
+///
+/// This struct maps to the `time_entries` table and includes sync metadata
+/// for offline-first synchronization. An entry is either a tracked
+/// interval (`started_at` to `stopped_at`, from which `hours` is computed)
+/// or a plain duration. Billable entries with no `invoice_id` are unbilled.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TimeEntry {
+    /// Unique identifier for the entry
+    pub id: Uuid,
+
+    /// ID of the user who owns this entry
+    pub user_id: Uuid,
+
+    /// Client the work was done for
+    pub client_name: Option<String>,
+
+    /// Client email address
+    pub client_email: Option<String>,
+
+    /// Project the work was for
+    pub project_id: Option<Uuid>,
+
+    /// What was worked on
+    pub description: Option<String>,
+
+    /// Day the work was done
+    pub entry_date: NaiveDate,
+
+    /// When tracking started, for tracked intervals
+    pub started_at: Option<DateTime<Utc>>,
+
+    /// When tracking stopped, for tracked intervals
+    pub stopped_at: Option<DateTime<Utc>>,
+
+    /// Hours worked
+    pub hours: Decimal,
+
+    /// Price per hour, before tax
+    pub hourly_rate: Decimal,
+
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Whether the work is billed to the client
+    pub billable: bool,
+
+    /// Invoice the entry was billed on
+    pub invoice_id: Option<Uuid>,
+
+    /// Last modification timestamp (for sync)
+    pub last_modified: DateTime<Utc>,
+
+    /// Version vector for CRDT sync (device_id -> timestamp)
+    pub version_vector: Option<Value>,
+
+    /// Soft delete flag (for sync)
+    pub is_deleted: bool,
+
+    /// Additional metadata
+    pub metadata: Option<Value>,
+
+    /// Timestamp when the entry was created
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the entry was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Time entry creation request
+///
+/// Either `started_at` and `stopped_at` or `hours` must be given. Client,
+/// rate and currency default to the project's.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateTimeEntry {
+    pub project_id: Option<Uuid>,
+    pub client_name: Option<String>,
+    pub client_email: Option<String>,
+    pub description: Option<String>,
+    pub entry_date: Option<NaiveDate>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub hours: Option<Decimal>,
+    pub hourly_rate: Option<Decimal>,
+    pub currency: Option<String>,
+    pub billable: Option<bool>,
+    pub metadata: Option<Value>,
+}
+
+/// Time entry update request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateTimeEntry {
+    pub project_id: Option<Uuid>,
+    pub client_name: Option<String>,
+    pub client_email: Option<String>,
+    pub description: Option<String>,
+    pub entry_date: Option<NaiveDate>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub hours: Option<Decimal>,
+    pub hourly_rate: Option<Decimal>,
+    pub currency: Option<String>,
+    pub billable: Option<bool>,
+    pub metadata: Option<Value>,
+}
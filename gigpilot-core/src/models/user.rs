@@ -3,10 +3,13 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::models::account::AccountKind;
+
 /// User model representing a user in the system.
 /// 
 /// This struct maps to the `users` table in the database and includes
-/// authentication and profile information.
+/// authentication and profile information. Every user is an account that
+/// owns data; see [`AccountKind`].
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     /// Unique identifier for the user
@@ -15,13 +18,22 @@ pub struct User {
     /// User's email address (unique)
     pub email: String,
     
-    /// Bcrypt hashed password
+    /// Bcrypt hashed password (None for workspaces, which cannot log in)
     #[serde(skip_serializing)]
-    pub password_hash: String,
+    pub password_hash: Option<String>,
+    
+    /// Personal account or workspace
+    #[sqlx(default)]
+    pub kind: AccountKind,
     
     /// User's full name
     pub full_name: Option<String>,
     
+    /// Region whose database cluster keeps the account's data (None for
+    /// the home cluster); see [`crate::db::residency`]
+    #[sqlx(default)]
+    pub data_region: Option<String>,
+    
     /// Timestamp when the user was created
     pub created_at: DateTime<Utc>,
     
@@ -41,6 +53,11 @@ pub struct CreateUser {
     pub email: String,
     pub password: String,
     pub full_name: Option<String>,
+    
+    /// Region to keep the account's data in (e.g. "eu"); the home region
+    /// when omitted. It can't be changed later.
+    #[serde(default)]
+    pub data_region: Option<String>,
 }
 
 /// User update request
@@ -56,6 +73,8 @@ pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
     pub full_name: Option<String>,
+    pub kind: AccountKind,
+    pub data_region: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub last_login_at: Option<DateTime<Utc>>,
@@ -68,6 +87,8 @@ impl From<User> for UserResponse {
             id: user.id,
             email: user.email,
             full_name: user.full_name,
+            kind: user.kind,
+            data_region: user.data_region,
             created_at: user.created_at,
             updated_at: user.updated_at,
             last_login_at: user.last_login_at,
@@ -0,0 +1,283 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::models::statement_schedule::StatementLocale;
+
+/// What happens to the numbers of deleted invoices.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceNumberPolicy {
+    /// Numbers of deleted invoices are never used again (gaps stay visible)
+    #[default]
+    #[sqlx(rename = "block")]
+    Block,
+
+    /// The lowest number freed by a deletion is handed out again
+    #[sqlx(rename = "recycle")]
+    Recycle,
+}
+
+/// How late fees are charged on overdue invoices.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar")]
+#[serde(rename_all = "snake_case")]
+pub enum LateFeeKind {
+    /// No late fees
+    #[default]
+    #[sqlx(rename = "none")]
+    None,
+
+    /// Fixed amount in the invoice's currency
+    #[sqlx(rename = "flat")]
+    Flat,
+
+    /// Percentage of the balance due
+    #[sqlx(rename = "percentage")]
+    Percentage,
+}
+
+/// Per-user settings model.
+/// 
+/// This struct maps to the `user_settings` table. Users without a row
+/// get [`UserSettings::defaults`].
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserSettings {
+    /// ID of the user these settings belong to
+    pub user_id: Uuid,
+    
+    /// Country used for the public-holiday calendar (ISO 3166-1 alpha-2)
+    pub country_code: Option<String>,
+    
+    /// Whether scheduling skips weekends and public holidays
+    pub skip_non_business_days: bool,
+    
+    /// Currency reports and dashboards are shown in (ISO 4217)
+    #[sqlx(default)]
+    pub base_currency: String,
+    
+    /// Whether an invoice is drafted every Friday from unbilled work
+    #[sqlx(default)]
+    pub weekly_drafts_enabled: bool,
+    
+    /// Whether weekly drafts are sent automatically after the grace period
+    #[sqlx(default)]
+    pub weekly_draft_auto_send: bool,
+    
+    /// Hours a weekly draft waits for review before it is auto-sent
+    #[sqlx(default)]
+    pub weekly_draft_grace_hours: i32,
+    
+    /// Whether numbers of deleted invoices may be reused
+    #[sqlx(default)]
+    pub invoice_number_policy: InvoiceNumberPolicy,
+    
+    /// How late fees are charged
+    #[sqlx(default)]
+    pub late_fee_kind: LateFeeKind,
+    
+    /// Flat fee amount, or percentage of the balance due
+    #[sqlx(default)]
+    pub late_fee_amount: Decimal,
+    
+    /// Days overdue before a late fee is charged
+    #[sqlx(default)]
+    pub late_fee_after_days: i32,
+    
+    /// Seller VAT identifier printed on e-invoices (e.g. "DE123456789")
+    #[sqlx(default)]
+    pub vat_id: Option<String>,
+    
+    /// Seller street address
+    #[sqlx(default)]
+    pub address_line: Option<String>,
+    
+    /// Seller city
+    #[sqlx(default)]
+    pub city: Option<String>,
+    
+    /// Seller postal code
+    #[sqlx(default)]
+    pub postal_code: Option<String>,
+    
+    /// Days between issue and due date for clients without their own terms
+    #[sqlx(default)]
+    pub payment_terms_days: i32,
+    
+    /// Fewest days allowed between issue and due date
+    #[sqlx(default)]
+    pub min_payment_terms_days: i32,
+    
+    /// Whether due dates falling on weekends and public holidays move to
+    /// the next business day
+    #[sqlx(default)]
+    pub roll_due_dates_forward: bool,
+    
+    /// Whether invoice and client text may be sent to the LLM provider
+    /// that writes chase emails
+    #[sqlx(default)]
+    pub ai_llm_consent: bool,
+    
+    /// Whether invoice and project text may be sent to the embedding
+    /// provider behind the estimator search
+    #[sqlx(default)]
+    pub ai_embeddings_consent: bool,
+    
+    /// Whether clients due a reminder get one statement of all their open
+    /// invoices instead of reminders per invoice
+    #[sqlx(default)]
+    pub chase_with_statement: bool,
+    
+    /// Whether the final notice warns the client that the invoice will be
+    /// referred to collections
+    #[sqlx(default)]
+    pub final_notice_mentions_collections: bool,
+    
+    /// Whether the user's chase and invoice emails are kept from clients
+    /// (see [`crate::email_sandbox`])
+    #[sqlx(default)]
+    pub email_sandbox: bool,
+    
+    /// Inbox sandboxed emails are redirected to (held unsent without one)
+    #[sqlx(default)]
+    pub email_sandbox_inbox: Option<String>,
+    
+    /// IANA timezone reminders are timed in, usually where the user's
+    /// clients are (no send window without one)
+    #[sqlx(default)]
+    pub timezone: Option<String>,
+    
+    /// Local hour from which reminders may be sent
+    #[sqlx(default)]
+    pub send_window_start_hour: i32,
+    
+    /// Local hour after which reminders wait for the next day (exclusive)
+    #[sqlx(default)]
+    pub send_window_end_hour: i32,
+    
+    /// Whether reminders may be sent on Saturdays and Sundays
+    #[sqlx(default)]
+    pub send_on_weekends: bool,
+    
+    /// Language and formats of statements sent in place of reminders
+    #[sqlx(default)]
+    pub statement_locale: StatementLocale,
+    
+    /// Timestamp when the settings were created
+    pub created_at: DateTime<Utc>,
+    
+    /// Timestamp when the settings were last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What user text may be sent to external AI providers.
+/// 
+/// Nothing is sent until the user opts in. Without consent, chase emails
+/// are written from templates and the estimator searches by keyword.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AiConsent {
+    /// Chase emails may be written by the LLM provider
+    pub llm: bool,
+    
+    /// Text may be embedded by the embedding provider
+    pub embeddings: bool,
+}
+
+/// Default review window for weekly drafts.
+pub const DEFAULT_WEEKLY_DRAFT_GRACE_HOURS: i32 = 48;
+
+/// Default payment terms of new invoices, in days.
+pub const DEFAULT_PAYMENT_TERMS_DAYS: i32 = 14;
+
+/// Local hour the reminder send window opens by default.
+pub const DEFAULT_SEND_WINDOW_START_HOUR: i32 = 9;
+
+/// Local hour the reminder send window closes by default.
+pub const DEFAULT_SEND_WINDOW_END_HOUR: i32 = 12;
+
+impl UserSettings {
+    /// Default settings for a user who has not configured anything.
+    pub fn defaults(user_id: Uuid) -> Self {
+        let now = Utc::now();
+        Self {
+            user_id,
+            country_code: None,
+            skip_non_business_days: false,
+            base_currency: crate::currency::DEFAULT_CURRENCY.to_string(),
+            weekly_drafts_enabled: false,
+            weekly_draft_auto_send: false,
+            weekly_draft_grace_hours: DEFAULT_WEEKLY_DRAFT_GRACE_HOURS,
+            invoice_number_policy: InvoiceNumberPolicy::Block,
+            late_fee_kind: LateFeeKind::None,
+            late_fee_amount: Decimal::ZERO,
+            late_fee_after_days: 0,
+            vat_id: None,
+            address_line: None,
+            city: None,
+            postal_code: None,
+            payment_terms_days: DEFAULT_PAYMENT_TERMS_DAYS,
+            min_payment_terms_days: 0,
+            roll_due_dates_forward: false,
+            ai_llm_consent: false,
+            ai_embeddings_consent: false,
+            chase_with_statement: false,
+            final_notice_mentions_collections: false,
+            email_sandbox: false,
+            email_sandbox_inbox: None,
+            timezone: None,
+            send_window_start_hour: DEFAULT_SEND_WINDOW_START_HOUR,
+            send_window_end_hour: DEFAULT_SEND_WINDOW_END_HOUR,
+            send_on_weekends: false,
+            statement_locale: StatementLocale::default(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+    
+    /// The user's consent for external AI providers.
+    pub fn ai_consent(&self) -> AiConsent {
+        AiConsent {
+            llm: self.ai_llm_consent,
+            embeddings: self.ai_embeddings_consent,
+        }
+    }
+}
+
+/// User settings update request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateUserSettings {
+    pub country_code: Option<String>,
+    pub skip_non_business_days: Option<bool>,
+    pub base_currency: Option<String>,
+    pub weekly_drafts_enabled: Option<bool>,
+    pub weekly_draft_auto_send: Option<bool>,
+    pub weekly_draft_grace_hours: Option<i32>,
+    pub invoice_number_policy: Option<InvoiceNumberPolicy>,
+    pub late_fee_kind: Option<LateFeeKind>,
+    pub late_fee_amount: Option<Decimal>,
+    pub late_fee_after_days: Option<i32>,
+    /// Empty strings clear the seller details below
+    pub vat_id: Option<String>,
+    pub address_line: Option<String>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+    pub payment_terms_days: Option<i32>,
+    pub min_payment_terms_days: Option<i32>,
+    pub roll_due_dates_forward: Option<bool>,
+    pub ai_llm_consent: Option<bool>,
+    pub ai_embeddings_consent: Option<bool>,
+    pub chase_with_statement: Option<bool>,
+    pub final_notice_mentions_collections: Option<bool>,
+    pub email_sandbox: Option<bool>,
+    /// Empty string clears the sandbox inbox
+    pub email_sandbox_inbox: Option<String>,
+    /// Empty string clears the timezone and with it the send window
+    pub timezone: Option<String>,
+    pub send_window_start_hour: Option<i32>,
+    pub send_window_end_hour: Option<i32>,
+    pub send_on_weekends: Option<bool>,
+    pub statement_locale: Option<StatementLocale>,
+}
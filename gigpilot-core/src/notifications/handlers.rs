@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::models::notification::Notification;
+use crate::notifications::{list_notifications, mark_read};
+
+/// Query parameters for listing notifications.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListNotificationsQuery {
+    /// Only return notifications that have not been read
+    #[serde(default)]
+    pub unread: bool,
+}
+
+/// List notifications endpoint handler.
+///
+/// Handles GET requests to `/api/notifications` (`?unread=true` to skip
+/// read ones).
+pub async fn list_notifications_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListNotificationsQuery>,
+) -> Result<Json<Vec<Notification>>, StatusCode> {
+    let notifications = list_notifications(&pool, user_id, query.unread).await.map_err(|e| {
+        error!("Failed to list notifications for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(notifications))
+}
+
+/// Mark notification read endpoint handler.
+///
+/// Handles POST requests to `/api/notifications/:id/read`.
+pub async fn mark_notification_read_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Notification>, StatusCode> {
+    let notification = mark_read(&pool, user_id, id).await.map_err(|e| {
+        error!("Failed to mark notification {} read: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    notification.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
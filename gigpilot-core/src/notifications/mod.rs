@@ -0,0 +1,130 @@
+//! In-app notification center.
+//!
+//! Notifications are created by the server and synced to the user's devices;
+//! the API lists them and marks them read.
+
+pub mod handlers;
+
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::models::notification::{CreateNotification, Notification};
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+
+/// Maximum number of notifications returned by a list request.
+const LIST_LIMIT: i64 = 100;
+
+/// Creates a notification for a user.
+///
+/// The notification is also recorded as a server sync change so it reaches
+/// the user's devices on their next pull. Both rows are written in the
+/// caller's transaction, so the notification commits with the change it
+/// reports.
+///
+/// # Arguments
+///
+/// * `tx` - Transaction to write with
+/// * `user_id` - ID of the user to notify
+/// * `notification` - The notification to create
+///
+/// # Returns
+///
+/// Returns the stored `Notification`, or an error.
+pub async fn notify(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    notification: CreateNotification,
+) -> Result<Notification, anyhow::Error> {
+    let stored = sqlx::query_as::<_, Notification>(
+        r#"
+        INSERT INTO notifications (user_id, kind, title, body, data)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(notification.kind)
+    .bind(notification.title)
+    .bind(notification.body)
+    .bind(notification.data)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_server_change(
+        &mut **tx,
+        user_id,
+        "notifications",
+        stored.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&stored)?,
+    )
+    .await?;
+
+    Ok(stored)
+}
+
+/// Lists a user's most recent notifications, newest first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `unread_only` - Whether to skip notifications already read
+///
+/// # Returns
+///
+/// Returns up to 100 notifications, or an error.
+pub async fn list_notifications(
+    pool: &PgPool,
+    user_id: Uuid,
+    unread_only: bool,
+) -> Result<Vec<Notification>, anyhow::Error> {
+    let notifications = sqlx::query_as::<_, Notification>(
+        r#"
+        SELECT * FROM notifications
+        WHERE user_id = $1 AND ($2 = false OR read_at IS NULL)
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(unread_only)
+    .bind(LIST_LIMIT)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(notifications)
+}
+
+/// Marks a notification as read.
+///
+/// Reading an already-read notification keeps its original `read_at`.
+///
+/// # Returns
+///
+/// Returns the updated `Notification`, or `None` if it does not exist or
+/// belongs to another user.
+pub async fn mark_read<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    notification_id: Uuid,
+) -> Result<Option<Notification>, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let notification = sqlx::query_as::<_, Notification>(
+        r#"
+        UPDATE notifications
+        SET read_at = COALESCE(read_at, NOW())
+        WHERE id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(notification_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(notification)
+}
@@ -0,0 +1,155 @@
+//! Heuristic field extraction from OCR'd receipt text.
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Fields extracted from a receipt.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractedReceipt {
+    /// Merchant name (first text line of the receipt)
+    pub vendor: Option<String>,
+
+    /// Purchase date
+    pub date: Option<NaiveDate>,
+
+    /// Total amount paid
+    pub amount: Option<Decimal>,
+
+    /// Currency code (ISO 4217), from a symbol or code on the receipt
+    pub currency: Option<String>,
+}
+
+/// Date formats tried for each token, most specific first.
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d.%m.%Y", "%m/%d/%Y", "%d/%m/%Y", "%d-%m-%Y"];
+
+/// Currency codes recognised when printed on a receipt.
+const CURRENCY_CODES: &[&str] = &["USD", "EUR", "GBP", "CAD", "AUD", "CHF", "INR", "JPY"];
+
+/// Extracts vendor, date, total and currency from receipt text.
+///
+/// The total is taken from the last line mentioning "total" (ignoring
+/// subtotals); if there is none, the largest amount on the receipt is used.
+pub fn extract_receipt_fields(text: &str) -> ExtractedReceipt {
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+
+    let vendor = lines
+        .iter()
+        .find(|line| line.chars().any(char::is_alphabetic) && parse_date(line).is_none())
+        .map(|line| line.to_string());
+
+    let date = lines.iter().find_map(|line| parse_date(line));
+
+    let total_line = lines.iter().rev().find(|line| {
+        let lower = line.to_lowercase();
+        lower.contains("total") && !lower.contains("subtotal") && !lower.contains("sub total")
+    });
+    let amount = total_line
+        .and_then(|line| line_amounts(line).pop())
+        .or_else(|| lines.iter().flat_map(|line| line_amounts(line)).max());
+
+    ExtractedReceipt {
+        vendor,
+        date,
+        amount,
+        currency: detect_currency(text),
+    }
+}
+
+/// Finds the first date token in a line.
+fn parse_date(line: &str) -> Option<NaiveDate> {
+    line.split_whitespace()
+        .map(|token| token.trim_matches(|c: char| c == ',' || c == ';'))
+        .find_map(|token| {
+            DATE_FORMATS
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(token, format).ok())
+        })
+}
+
+/// Parses every money-looking token in a line, in order.
+fn line_amounts(line: &str) -> Vec<Decimal> {
+    line.split_whitespace()
+        // Skip dates and times of day
+        .filter(|token| !token.contains(':') && parse_date(token).is_none())
+        .filter_map(parse_money)
+        .collect()
+}
+
+/// Parses a money token such as `$1,234.56`, `12,50€` or `1.234,56`.
+fn parse_money(token: &str) -> Option<Decimal> {
+    let cleaned: String = token
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    if !cleaned.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let normalized = match (cleaned.rfind('.'), cleaned.rfind(',')) {
+        // Both separators: the last one is the decimal separator
+        (Some(dot), Some(comma)) if comma > dot => cleaned.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => cleaned.replace(',', ""),
+        // Comma only: decimal separator if followed by exactly two digits
+        (None, Some(comma)) if cleaned.len() - comma - 1 == 2 => cleaned.replace(',', "."),
+        (None, Some(_)) => cleaned.replace(',', ""),
+        _ => cleaned,
+    };
+
+    Decimal::from_str(normalized.trim_matches('.')).ok()
+}
+
+/// Detects the receipt currency from a code or symbol.
+fn detect_currency(text: &str) -> Option<String> {
+    let upper = text.to_uppercase();
+    let code = upper
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| CURRENCY_CODES.contains(word));
+    if let Some(code) = code {
+        return Some(code.to_string());
+    }
+
+    [('€', "EUR"), ('£', "GBP"), ('¥', "JPY"), ('₹', "INR"), ('$', "USD")]
+        .iter()
+        .find(|(symbol, _)| text.contains(*symbol))
+        .map(|(_, code)| code.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_us_receipt() {
+        let text = "Corner Coffee Co.\n123 Main St\n03/14/2024 08:15\n\
+                    Latte        4.50\nMuffin       3.25\nSubtotal     7.75\n\
+                    Tax          0.62\nTOTAL       $8.37\n";
+        let fields = extract_receipt_fields(text);
+        assert_eq!(fields.vendor.as_deref(), Some("Corner Coffee Co."));
+        assert_eq!(fields.date, NaiveDate::from_ymd_opt(2024, 3, 14));
+        assert_eq!(fields.amount, Some(Decimal::new(837, 2)));
+        assert_eq!(fields.currency.as_deref(), Some("USD"));
+    }
+
+    #[test]
+    fn test_extracts_european_number_format() {
+        let text = "Bürobedarf GmbH\nDatum: 02.05.2024\nGesamt Total 1.234,50 €\n";
+        let fields = extract_receipt_fields(text);
+        assert_eq!(fields.date, NaiveDate::from_ymd_opt(2024, 5, 2));
+        assert_eq!(fields.amount, Some(Decimal::new(123450, 2)));
+        assert_eq!(fields.currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn test_falls_back_to_largest_amount() {
+        let fields = extract_receipt_fields("Hardware Store\nScrews 2.10\nDrill 89.99\n");
+        assert_eq!(fields.amount, Some(Decimal::new(8999, 2)));
+        assert_eq!(fields.currency, None);
+    }
+
+    #[test]
+    fn test_empty_text_extracts_nothing() {
+        assert_eq!(extract_receipt_fields(""), ExtractedReceipt::default());
+    }
+}
@@ -0,0 +1,242 @@
+//! Background OCR job for uploaded receipts.
+
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::models::expense::Expense;
+use crate::models::receipt::{OcrStatus, Receipt, RECEIPT_COLUMNS};
+use crate::models::sync_change::SyncOperation;
+use crate::ocr::extract::{extract_receipt_fields, ExtractedReceipt};
+use crate::ocr::OcrProvider;
+use crate::sync::server::record_server_change;
+
+/// Receipts claimed per job run.
+const BATCH_SIZE: i64 = 10;
+
+/// Attempts before a receipt is marked as failed.
+const MAX_ATTEMPTS: i32 = 3;
+
+/// Receipts stuck in `processing` longer than this are reclaimed
+/// (e.g. after a worker crash).
+const STALE_PROCESSING_MINUTES: i32 = 10;
+
+/// A receipt claimed for processing, including its file contents.
+#[derive(Debug, FromRow)]
+struct ClaimedReceipt {
+    id: Uuid,
+    user_id: Uuid,
+    content_type: String,
+    data: Vec<u8>,
+    attempts: i32,
+}
+
+/// Runs OCR on a batch of pending receipts.
+///
+/// Each processed receipt yields a `suggested` expense when a total could be
+/// extracted. Both the receipt and the expense are recorded as server sync
+/// changes so the user's devices pick them up on their next pull.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `provider` - OCR backend to use
+///
+/// # Returns
+///
+/// Returns the number of receipts processed successfully.
+pub async fn process_pending_receipts(
+    pool: &PgPool,
+    provider: &dyn OcrProvider,
+) -> Result<usize, anyhow::Error> {
+    let claimed = sqlx::query_as::<_, ClaimedReceipt>(
+        r#"
+        UPDATE receipts
+        SET ocr_status = 'processing', attempts = attempts + 1
+        WHERE id IN (
+            SELECT id FROM receipts
+            WHERE ocr_status = 'pending'
+                OR (ocr_status = 'processing'
+                    AND updated_at < NOW() - make_interval(mins => $2))
+            ORDER BY created_at ASC
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, user_id, content_type, data, attempts
+        "#,
+    )
+    .bind(BATCH_SIZE)
+    .bind(STALE_PROCESSING_MINUTES)
+    .fetch_all(pool)
+    .await?;
+
+    let mut processed = 0;
+    for receipt in claimed {
+        match process_receipt(pool, provider, &receipt).await {
+            Ok(()) => processed += 1,
+            Err(e) => {
+                warn!("OCR failed for receipt {} (attempt {}): {}", receipt.id, receipt.attempts, e);
+                if let Err(e) = record_failure(pool, &receipt, &e.to_string()).await {
+                    error!("Failed to record OCR failure for receipt {}: {}", receipt.id, e);
+                }
+            }
+        }
+    }
+
+    Ok(processed)
+}
+
+/// Recognises one receipt and stores the suggested expense.
+async fn process_receipt(
+    pool: &PgPool,
+    provider: &dyn OcrProvider,
+    receipt: &ClaimedReceipt,
+) -> Result<(), anyhow::Error> {
+    let text = provider.recognize(&receipt.content_type, &receipt.data).await?;
+    let fields = extract_receipt_fields(&text);
+
+    let mut tx = pool.begin().await?;
+
+    let expense = match fields.amount {
+        Some(_) => Some(insert_suggested_expense(&mut tx, receipt, &fields).await?),
+        None => None,
+    };
+
+    let updated = sqlx::query_as::<_, Receipt>(&format!(
+        r#"
+        UPDATE receipts
+        SET ocr_status = $2, ocr_provider = $3, ocr_text = $4, ocr_error = NULL,
+            extracted = $5, expense_id = $6
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        RECEIPT_COLUMNS
+    ))
+    .bind(receipt.id)
+    .bind(OcrStatus::Completed)
+    .bind(provider.name())
+    .bind(&text)
+    .bind(serde_json::to_value(&fields)?)
+    .bind(expense.as_ref().map(|e| e.id))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if let Some(expense) = &expense {
+        record_server_change(
+            &mut *tx,
+            expense.user_id,
+            "expenses",
+            expense.id,
+            SyncOperation::Insert,
+            &serde_json::to_value(expense)?,
+        )
+        .await?;
+    }
+    record_server_change(
+        &mut *tx,
+        updated.user_id,
+        "receipts",
+        updated.id,
+        SyncOperation::Update,
+        &serde_json::to_value(&updated)?,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    info!(
+        "OCR completed for receipt {} ({})",
+        receipt.id,
+        if expense.is_some() { "expense suggested" } else { "no total found" }
+    );
+    Ok(())
+}
+
+/// Inserts a `suggested` expense from extracted receipt fields.
+async fn insert_suggested_expense(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    receipt: &ClaimedReceipt,
+    fields: &ExtractedReceipt,
+) -> Result<Expense, anyhow::Error> {
+    let expense = sqlx::query_as::<_, Expense>(
+        r#"
+        INSERT INTO expenses (
+            user_id, vendor, expense_date, amount, currency,
+            status, source, receipt_id
+        ) VALUES ($1, $2, $3, $4, COALESCE($5, 'USD'), 'suggested', 'ocr', $6)
+        RETURNING *
+        "#,
+    )
+    .bind(receipt.user_id)
+    .bind(&fields.vendor)
+    .bind(fields.date)
+    .bind(fields.amount)
+    .bind(&fields.currency)
+    .bind(receipt.id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(expense)
+}
+
+/// Returns a failed receipt to the queue, or marks it failed for good.
+async fn record_failure(
+    pool: &PgPool,
+    receipt: &ClaimedReceipt,
+    message: &str,
+) -> Result<(), anyhow::Error> {
+    let status = if receipt.attempts >= MAX_ATTEMPTS {
+        OcrStatus::Failed
+    } else {
+        OcrStatus::Pending
+    };
+
+    let updated = sqlx::query_as::<_, Receipt>(&format!(
+        "UPDATE receipts SET ocr_status = $2, ocr_error = $3 WHERE id = $1 RETURNING {}",
+        RECEIPT_COLUMNS
+    ))
+    .bind(receipt.id)
+    .bind(status)
+    .bind(message)
+    .fetch_one(pool)
+    .await?;
+
+    if status == OcrStatus::Failed {
+        record_server_change(
+            pool,
+            updated.user_id,
+            "receipts",
+            updated.id,
+            SyncOperation::Update,
+            &serde_json::to_value(&updated)?,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Spawns the background OCR loop.
+///
+/// Polls for pending receipts every `OCR_POLL_INTERVAL_SECONDS`
+/// (default: 30 seconds).
+pub fn spawn_ocr_worker(pool: PgPool, provider: Arc<dyn OcrProvider>) {
+    let seconds = std::env::var("OCR_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(30);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            match process_pending_receipts(&pool, provider.as_ref()).await {
+                Ok(0) => {}
+                Ok(count) => info!("Processed {} receipt(s) through OCR", count),
+                Err(e) => error!("OCR job failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,75 @@
+//! Receipt OCR pipeline.
+//!
+//! Uploaded receipts are read by a pluggable [`OcrProvider`], the recognised
+//! text is parsed into vendor/date/amount fields by [`extract`], and a
+//! background [`job`] turns the result into a suggested expense that reaches
+//! the user's devices through sync.
+
+pub mod extract;
+pub mod job;
+
+pub use extract::{extract_receipt_fields, ExtractedReceipt};
+pub use job::{process_pending_receipts, spawn_ocr_worker};
+
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// A text-recognition backend for receipt files.
+#[async_trait]
+pub trait OcrProvider: Send + Sync {
+    /// Short provider name stored alongside results (e.g. "mock").
+    fn name(&self) -> &'static str;
+
+    /// Recognises the text in a receipt file.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - MIME type of the file
+    /// * `data` - Raw file contents
+    ///
+    /// # Returns
+    ///
+    /// Returns the recognised text, or an error if recognition failed.
+    async fn recognize(&self, content_type: &str, data: &[u8]) -> Result<String, anyhow::Error>;
+}
+
+/// Mock OCR provider.
+///
+/// In production, this would call an OCR service (Tesseract, AWS Textract,
+/// Google Cloud Vision, etc.). The mock reads `text/*` uploads verbatim so
+/// the rest of the pipeline can be exercised end to end, and recognises no
+/// text in other files.
+#[derive(Debug, Default)]
+pub struct MockOcrProvider;
+
+#[async_trait]
+impl OcrProvider for MockOcrProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn recognize(&self, content_type: &str, data: &[u8]) -> Result<String, anyhow::Error> {
+        info!("Mock OCR: Recognising {} ({} bytes)", content_type, data.len());
+
+        // Simulate async OCR call delay
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        if content_type.starts_with("text/") {
+            Ok(String::from_utf8_lossy(data).into_owned())
+        } else {
+            Ok(String::new())
+        }
+    }
+}
+
+/// Selects the OCR provider from `OCR_PROVIDER` (default: "mock").
+pub fn provider_from_env() -> Arc<dyn OcrProvider> {
+    match std::env::var("OCR_PROVIDER").as_deref() {
+        Ok("mock") | Err(_) => Arc::new(MockOcrProvider),
+        Ok(other) => {
+            warn!("Unknown OCR provider: {}, defaulting to mock", other);
+            Arc::new(MockOcrProvider)
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::models::payment_method::{ClientPaymentMethod, CreatePaymentMethod};
+use crate::payment_methods::{
+    delete_payment_method, list_payment_methods, save_payment_method, validate_details,
+};
+
+/// Query parameters for listing payment methods.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListPaymentMethodsQuery {
+    /// Only return the methods of this client
+    pub client_email: Option<String>,
+}
+
+/// List payment methods endpoint handler.
+///
+/// Handles GET requests to `/api/payment-methods`.
+pub async fn list_payment_methods_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListPaymentMethodsQuery>,
+) -> Result<Json<Vec<ClientPaymentMethod>>, StatusCode> {
+    let methods = list_payment_methods(&pool, user_id, query.client_email.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to list payment methods for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(methods))
+}
+
+/// Save payment method endpoint handler.
+///
+/// Handles POST requests to `/api/payment-methods`. A method of the same
+/// kind already stored for the client is replaced.
+pub async fn save_payment_method_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreatePaymentMethod>,
+) -> Result<(StatusCode, Json<ClientPaymentMethod>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_details(request.details.clone()) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))));
+    }
+
+    let method = save_payment_method(&pool, user_id, request).await.map_err(|e| {
+        error!("Failed to save payment method for user {}: {}", user_id, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "failed to save payment method" })),
+        )
+    })?;
+
+    Ok((StatusCode::CREATED, Json(method)))
+}
+
+/// Delete payment method endpoint handler.
+///
+/// Handles DELETE requests to `/api/payment-methods/:id`.
+pub async fn delete_payment_method_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_payment_method(&pool, user_id, id).await.map_err(|e| {
+        error!("Failed to delete payment method {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
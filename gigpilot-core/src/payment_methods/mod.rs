@@ -0,0 +1,496 @@
+//! Accepted payment methods per client.
+//!
+//! Methods are validated and normalized on entry (IBAN checksum, BIC and
+//! routing number formats, payment link hosts) and rendered as payment
+//! instructions on invoice PDFs, the public pay page and reminder emails.
+
+pub mod handlers;
+
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::models::payment_method::{ClientPaymentMethod, CreatePaymentMethod, PaymentMethodDetails};
+
+/// Removes spaces and dashes and upper-cases an account identifier.
+fn compact(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Validates an IBAN with the ISO 13616 mod-97 checksum.
+///
+/// # Returns
+///
+/// Returns the compact, upper-case IBAN, or a message describing the problem.
+pub fn validate_iban(iban: &str) -> Result<String, String> {
+    let iban = compact(iban);
+    // Checked first: the byte slices below assume one byte per character
+    let valid_format = iban.chars().all(|c| c.is_ascii_alphanumeric())
+        && (15..=34).contains(&iban.len())
+        && iban[..2].chars().all(|c| c.is_ascii_uppercase())
+        && iban[2..4].chars().all(|c| c.is_ascii_digit());
+    if !valid_format {
+        return Err("iban is not a valid IBAN".to_string());
+    }
+
+    // Move the country code and check digits to the end, map letters to
+    // 10..35 and reduce mod 97 digit by digit
+    let rearranged = format!("{}{}", &iban[4..], &iban[..4]);
+    let mut remainder: u32 = 0;
+    for c in rearranged.chars() {
+        let value = c.to_digit(36).expect("alphanumeric");
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+    if remainder != 1 {
+        return Err("iban checksum is invalid".to_string());
+    }
+    Ok(iban)
+}
+
+/// Validates a SWIFT/BIC code (8 or 11 characters).
+pub fn validate_bic(bic: &str) -> Result<String, String> {
+    let bic = compact(bic);
+    let valid = bic.chars().all(|c| c.is_ascii_alphanumeric())
+        && (bic.len() == 8 || bic.len() == 11)
+        && bic[..6].chars().all(|c| c.is_ascii_uppercase());
+    if !valid {
+        return Err("bic is not a valid SWIFT/BIC code".to_string());
+    }
+    Ok(bic)
+}
+
+/// Validates a US ABA routing number, including its checksum digit.
+pub fn validate_routing_number(routing: &str) -> Result<String, String> {
+    let routing = compact(routing);
+    let digits: Vec<u32> = routing.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() != 9 || routing.len() != 9 {
+        return Err("routing_number must have 9 digits".to_string());
+    }
+    let checksum = 3 * (digits[0] + digits[3] + digits[6])
+        + 7 * (digits[1] + digits[4] + digits[7])
+        + (digits[2] + digits[5] + digits[8]);
+    if checksum % 10 != 0 {
+        return Err("routing_number checksum is invalid".to_string());
+    }
+    Ok(routing)
+}
+
+/// Validates a UK sort code, returning it as `12-34-56`.
+pub fn validate_sort_code(sort_code: &str) -> Result<String, String> {
+    let digits = compact(sort_code);
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err("sort_code must have 6 digits".to_string());
+    }
+    Ok(format!("{}-{}-{}", &digits[..2], &digits[2..4], &digits[4..]))
+}
+
+/// Returns the host of an `https://` URL, lower-cased.
+fn https_host(url: &str) -> Option<String> {
+    let rest = url.trim().strip_prefix("https://")?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn host_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// Validates and normalizes payment method details.
+///
+/// # Returns
+///
+/// Returns the normalized details (compact IBANs, upper-case BICs, PayPal.Me
+/// links), or a message describing the first invalid field.
+pub fn validate_details(details: PaymentMethodDetails) -> Result<PaymentMethodDetails, String> {
+    match details {
+        PaymentMethodDetails::BankTransfer {
+            account_name,
+            iban,
+            bic,
+            account_number,
+            sort_code,
+            routing_number,
+            bank_name,
+        } => {
+            if account_name.trim().is_empty() {
+                return Err("account_name is required".to_string());
+            }
+            let iban = iban.as_deref().map(validate_iban).transpose()?;
+            let bic = bic.as_deref().map(validate_bic).transpose()?;
+            let account_number = account_number.map(|n| compact(&n));
+            if let Some(number) = &account_number {
+                if !(4..=17).contains(&number.len()) || !number.chars().all(|c| c.is_ascii_digit()) {
+                    return Err("account_number must have 4 to 17 digits".to_string());
+                }
+            }
+            if iban.is_none() && account_number.is_none() {
+                return Err("bank_transfer requires an iban or account_number".to_string());
+            }
+            Ok(PaymentMethodDetails::BankTransfer {
+                account_name: account_name.trim().to_string(),
+                iban,
+                bic,
+                account_number,
+                sort_code: sort_code.as_deref().map(validate_sort_code).transpose()?,
+                routing_number: routing_number.as_deref().map(validate_routing_number).transpose()?,
+                bank_name: bank_name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+            })
+        }
+        PaymentMethodDetails::Stripe { payment_link } => {
+            match https_host(&payment_link) {
+                Some(host) if host_matches(&host, "stripe.com") => Ok(PaymentMethodDetails::Stripe {
+                    payment_link: payment_link.trim().to_string(),
+                }),
+                _ => Err("payment_link must be an https://stripe.com link".to_string()),
+            }
+        }
+        PaymentMethodDetails::Paypal { email, paypal_me } => {
+            let email = email.map(|e| e.trim().to_lowercase()).filter(|e| !e.is_empty());
+            if let Some(email) = &email {
+                let valid = email
+                    .split_once('@')
+                    .map(|(local, domain)| !local.is_empty() && domain.contains('.'))
+                    .unwrap_or(false);
+                if !valid {
+                    return Err("paypal email is not a valid email address".to_string());
+                }
+            }
+            let paypal_me = match paypal_me.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+                None => None,
+                Some(link) if link.starts_with("https://") => match https_host(link) {
+                    Some(host) if host_matches(&host, "paypal.me") => Some(link.to_string()),
+                    _ => return Err("paypal_me must be an https://paypal.me link".to_string()),
+                },
+                Some(username) => {
+                    if !username.chars().all(|c| c.is_ascii_alphanumeric()) || username.len() > 20 {
+                        return Err("paypal_me username must be up to 20 letters or digits".to_string());
+                    }
+                    Some(format!("https://paypal.me/{}", username))
+                }
+            };
+            if email.is_none() && paypal_me.is_none() {
+                return Err("paypal requires an email or paypal_me".to_string());
+            }
+            Ok(PaymentMethodDetails::Paypal { email, paypal_me })
+        }
+    }
+}
+
+/// Groups an IBAN into blocks of four for display.
+fn format_iban(iban: &str) -> String {
+    iban.chars()
+        .collect::<Vec<_>>()
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Renders one payment instruction line for a method.
+///
+/// # Arguments
+///
+/// * `details` - The payment method
+/// * `reference` - Payment reference to quote (usually the invoice number)
+pub fn instruction(details: &PaymentMethodDetails, reference: &str) -> String {
+    match details {
+        PaymentMethodDetails::BankTransfer {
+            account_name,
+            iban,
+            bic,
+            account_number,
+            sort_code,
+            routing_number,
+            bank_name,
+        } => {
+            let mut parts = vec![format!("Bank transfer to {}", account_name)];
+            if let Some(bank_name) = bank_name {
+                parts.push(bank_name.clone());
+            }
+            if let Some(iban) = iban {
+                parts.push(format!("IBAN {}", format_iban(iban)));
+            }
+            if let Some(bic) = bic {
+                parts.push(format!("BIC {}", bic));
+            }
+            if let Some(sort_code) = sort_code {
+                parts.push(format!("Sort code {}", sort_code));
+            }
+            if let Some(routing_number) = routing_number {
+                parts.push(format!("Routing {}", routing_number));
+            }
+            if let Some(account_number) = account_number {
+                parts.push(format!("Account {}", account_number));
+            }
+            parts.push(format!("Reference {}", reference));
+            parts.join(", ")
+        }
+        PaymentMethodDetails::Stripe { payment_link } => format!("Pay by card: {}", payment_link),
+        PaymentMethodDetails::Paypal { email, paypal_me } => match (paypal_me, email) {
+            (Some(link), _) => format!("PayPal: {} (reference {})", link, reference),
+            (None, Some(email)) => format!("PayPal to {} (reference {})", email, reference),
+            (None, None) => "PayPal".to_string(),
+        },
+    }
+}
+
+/// Renders payment instruction lines for a set of methods.
+///
+/// Methods whose stored details no longer parse are skipped.
+pub fn instructions(methods: &[ClientPaymentMethod], reference: &str) -> Vec<String> {
+    methods
+        .iter()
+        .filter_map(|method| method.parsed().ok())
+        .map(|details| instruction(&details, reference))
+        .collect()
+}
+
+/// Whether a URL is a Stripe link.
+pub fn is_stripe_link(url: &str) -> bool {
+    https_host(url).is_some_and(|host| host_matches(&host, "stripe.com"))
+}
+
+/// The payment link of the first Stripe method among `methods`, if any.
+pub fn stripe_payment_link(methods: &[ClientPaymentMethod]) -> Option<String> {
+    methods.iter().find_map(|method| match method.parsed().ok()? {
+        PaymentMethodDetails::Stripe { payment_link } => Some(payment_link),
+        _ => None,
+    })
+}
+
+/// A Stripe payment link that records the invoice on the payment, as
+/// `client_reference_id`.
+pub fn stripe_link_for_invoice(payment_link: &str, invoice_id: Uuid) -> String {
+    let separator = if payment_link.contains('?') { '&' } else { '?' };
+    format!("{}{}client_reference_id={}", payment_link, separator, invoice_id)
+}
+
+/// Renders a "How to pay" block for plain-text emails, if any methods apply.
+pub fn instructions_text(methods: &[ClientPaymentMethod], reference: &str) -> Option<String> {
+    let lines = instructions(methods, reference);
+    if lines.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "How to pay:\n{}",
+        lines.iter().map(|line| format!("- {}", line)).collect::<Vec<_>>().join("\n")
+    ))
+}
+
+/// Loads the payment methods that apply to a client.
+///
+/// A client's own methods take precedence; clients without any (or without
+/// an email) get the user's defaults.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to read with
+/// * `user_id` - ID of the user being paid
+/// * `client_email` - The client's email, if known
+///
+/// # Returns
+///
+/// Returns the applicable methods in display order.
+pub async fn methods_for_client<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    client_email: Option<&str>,
+) -> Result<Vec<ClientPaymentMethod>, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let client_email = client_email.map(|e| e.trim().to_lowercase());
+
+    // Rank the client's own methods first and keep only the best rank
+    let methods = sqlx::query_as::<_, ClientPaymentMethod>(
+        r#"
+        WITH ranked AS (
+            SELECT *, CASE WHEN client_email IS NULL THEN 1 ELSE 0 END AS rank
+            FROM client_payment_methods
+            WHERE user_id = $1 AND (client_email IS NULL OR client_email = $2)
+        )
+        SELECT id, user_id, client_email, kind, details, position, created_at, updated_at
+        FROM ranked
+        WHERE rank = (SELECT MIN(rank) FROM ranked)
+        ORDER BY position ASC, created_at ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_email)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(methods)
+}
+
+/// Lists a user's payment methods, optionally for one client only.
+pub async fn list_payment_methods(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_email: Option<&str>,
+) -> Result<Vec<ClientPaymentMethod>, anyhow::Error> {
+    let client_email = client_email.map(|e| e.trim().to_lowercase());
+
+    let methods = sqlx::query_as::<_, ClientPaymentMethod>(
+        r#"
+        SELECT * FROM client_payment_methods
+        WHERE user_id = $1 AND ($2::VARCHAR IS NULL OR client_email = $2)
+        ORDER BY client_email ASC NULLS FIRST, position ASC, created_at ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_email)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(methods)
+}
+
+/// Creates a payment method, replacing any of the same kind for the client.
+///
+/// # Errors
+///
+/// Returns an error if the details are invalid or the query fails.
+pub async fn save_payment_method(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreatePaymentMethod,
+) -> Result<ClientPaymentMethod, anyhow::Error> {
+    let details = validate_details(request.details).map_err(|e| anyhow::anyhow!(e))?;
+    let client_email = request
+        .client_email
+        .map(|e| e.trim().to_lowercase())
+        .filter(|e| !e.is_empty());
+
+    let method = sqlx::query_as::<_, ClientPaymentMethod>(
+        r#"
+        INSERT INTO client_payment_methods (user_id, client_email, kind, details, position)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, COALESCE(client_email, ''), kind) DO UPDATE
+            SET details = EXCLUDED.details,
+                position = EXCLUDED.position
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_email)
+    .bind(details.kind())
+    .bind(serde_json::to_value(&details)?)
+    .bind(request.position.unwrap_or(0))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(method)
+}
+
+/// Deletes a payment method.
+///
+/// # Returns
+///
+/// Returns `true` if a method was deleted, `false` if none matched.
+pub async fn delete_payment_method(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM client_payment_methods WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iban_checksum() {
+        assert_eq!(validate_iban("GB82 WEST 1234 5698 7654 32").unwrap(), "GB82WEST12345698765432");
+        assert!(validate_iban("DE89370400440532013000").is_ok());
+        assert!(validate_iban("DE89370400440532013001").is_err());
+        assert!(validate_iban("GB82").is_err());
+    }
+
+    #[test]
+    fn test_bank_codes() {
+        assert_eq!(validate_bic("deutdeff").unwrap(), "DEUTDEFF");
+        assert!(validate_bic("NWBKGB2LXXX").is_ok());
+        assert!(validate_bic("DEUT1EFF").is_err());
+        assert!(validate_routing_number("021000021").is_ok());
+        assert!(validate_routing_number("021000022").is_err());
+        assert_eq!(validate_sort_code("123456").unwrap(), "12-34-56");
+    }
+
+    #[test]
+    fn test_multibyte_input_is_rejected() {
+        assert!(validate_iban("DÉ89370400440532013000").is_err());
+        assert!(validate_iban("GB82WEST1234569876543€").is_err());
+        assert!(validate_bic("DEUTDÉFF").is_err());
+        assert!(validate_bic("DEUTDEFF€€").is_err());
+        assert!(validate_sort_code("1234é").is_err());
+    }
+
+    #[test]
+    fn test_validate_details_normalizes() {
+        let paypal = validate_details(PaymentMethodDetails::Paypal {
+            email: None,
+            paypal_me: Some("janedoe".to_string()),
+        })
+        .unwrap();
+        assert_eq!(
+            paypal,
+            PaymentMethodDetails::Paypal { email: None, paypal_me: Some("https://paypal.me/janedoe".to_string()) }
+        );
+
+        assert!(validate_details(PaymentMethodDetails::Stripe {
+            payment_link: "https://buy.stripe.com/test_123".to_string()
+        })
+        .is_ok());
+        assert!(validate_details(PaymentMethodDetails::Stripe {
+            payment_link: "https://stripe.com.evil.test/pay".to_string()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_bank_transfer_instruction() {
+        let details = validate_details(PaymentMethodDetails::BankTransfer {
+            account_name: "Jane Doe".to_string(),
+            iban: Some("gb82west12345698765432".to_string()),
+            bic: Some("WESTGB2L".to_string()),
+            account_number: None,
+            sort_code: None,
+            routing_number: None,
+            bank_name: None,
+        })
+        .unwrap();
+        assert_eq!(
+            instruction(&details, "INV-00042"),
+            "Bank transfer to Jane Doe, IBAN GB82 WEST 1234 5698 7654 32, BIC WESTGB2L, Reference INV-00042"
+        );
+    }
+
+    #[test]
+    fn test_stripe_links_carry_the_invoice() {
+        let invoice_id = Uuid::new_v4();
+        assert!(is_stripe_link("https://buy.stripe.com/test_123"));
+        assert!(!is_stripe_link("https://stripe.com.evil.test/pay"));
+        assert!(!is_stripe_link("https://paypal.me/acme"));
+
+        assert_eq!(
+            stripe_link_for_invoice("https://buy.stripe.com/test_123", invoice_id),
+            format!("https://buy.stripe.com/test_123?client_reference_id={}", invoice_id)
+        );
+        assert_eq!(
+            stripe_link_for_invoice("https://buy.stripe.com/test_123?locale=de", invoice_id),
+            format!("https://buy.stripe.com/test_123?locale=de&client_reference_id={}", invoice_id)
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use axum::{
+    extract::{Extension, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::auth::{CurrentPortal, CurrentUser};
+use crate::clients::find_client;
+use crate::clients::interactions::{record_invoice_view, ViewedVia};
+use crate::invoices::pdf::{cached_invoice_pdf, PdfBranding};
+use crate::models::portal::{CreatePaymentIntent, CreatePortalToken, PortalEvent, PortalEventKind, PortalToken};
+use crate::portal::{
+    create_token, find_portal_invoice, list_activity, list_tokens, outstanding_by_currency, portal_invoices,
+    record_event, record_payment_intent, revoke_token, validate_create_token, validate_intent, PortalInvoice,
+    PortalOverview,
+};
+use crate::storage::DynBlobStore;
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Records portal activity without failing the request.
+async fn track(pool: &PgPool, portal: &PortalToken, invoice_id: Option<Uuid>, kind: PortalEventKind) {
+    if let Err(e) = record_event(pool, portal, invoice_id, kind, None).await {
+        warn!("Failed to record portal activity for token {}: {}", portal.id, e);
+    }
+}
+
+/// Checks that the client exists and belongs to the user.
+async fn require_client(pool: &PgPool, user_id: Uuid, client_id: Uuid) -> Result<(), (StatusCode, Json<Value>)> {
+    find_client(pool, user_id, client_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load client {}: {}", client_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load client")
+        })?
+        .map(|_| ())
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "client not found"))
+}
+
+/// Create portal token endpoint handler.
+///
+/// Handles POST requests to `/api/clients/:id/portal-tokens`. Returns the
+/// stored token and, in `token`, the string to give the client; it is not
+/// shown again.
+pub async fn create_portal_token_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+    Json(request): Json<CreatePortalToken>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_create_token(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    require_client(&pool, user_id, client_id).await?;
+
+    let (portal_token, token) = create_token(&pool, user_id, client_id, request).await.map_err(|e| {
+        error!("Failed to create portal token for client {}: {}", client_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create portal token")
+    })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "token": token, "portal_token": portal_token })),
+    ))
+}
+
+/// List portal tokens endpoint handler.
+///
+/// Handles GET requests to `/api/clients/:id/portal-tokens`.
+pub async fn list_portal_tokens_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<Vec<PortalToken>>, StatusCode> {
+    let tokens = list_tokens(&pool, user_id, client_id).await.map_err(|e| {
+        error!("Failed to list portal tokens for client {}: {}", client_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(tokens))
+}
+
+/// Revoke portal token endpoint handler.
+///
+/// Handles DELETE requests to `/api/clients/:id/portal-tokens/:token_id`.
+pub async fn revoke_portal_token_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path((client_id, token_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = revoke_token(&pool, user_id, client_id, token_id).await.map_err(|e| {
+        error!("Failed to revoke portal token {}: {}", token_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if revoked {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Portal activity endpoint handler.
+///
+/// Handles GET requests to `/api/clients/:id/portal-activity`: the
+/// client's recent portal views, downloads and payment intents.
+pub async fn portal_activity_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<Vec<PortalEvent>>, StatusCode> {
+    let events = list_activity(&pool, user_id, client_id).await.map_err(|e| {
+        error!("Failed to list portal activity for client {}: {}", client_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(events))
+}
+
+/// Portal overview endpoint handler.
+///
+/// Handles GET requests to `/portal` with a portal token: the client's
+/// invoices and what they owe per currency.
+pub async fn portal_overview_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentPortal(portal)): Extension<CurrentPortal>,
+) -> Result<Json<PortalOverview>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load portal for token {}: {}", portal.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let client = find_client(&pool, portal.user_id, portal.client_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let branding = PdfBranding::for_user(&pool, portal.user_id).await.map_err(internal_error)?;
+
+    let today = Utc::now().date_naive();
+    let invoices: Vec<PortalInvoice> = portal_invoices(&pool, &portal)
+        .await
+        .map_err(internal_error)?
+        .iter()
+        .map(|invoice| PortalInvoice::new(invoice, today))
+        .collect();
+    track(&pool, &portal, None, PortalEventKind::PortalViewed).await;
+
+    Ok(Json(PortalOverview {
+        client_name: client.name,
+        business_name: branding.business_name,
+        outstanding: outstanding_by_currency(&invoices),
+        invoices,
+    }))
+}
+
+/// Portal invoice endpoint handler.
+///
+/// Handles GET requests to `/portal/invoices/:id` with a portal token. The
+/// response includes how to pay.
+pub async fn portal_invoice_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentPortal(portal)): Extension<CurrentPortal>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load portal invoice {}: {}", invoice_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let invoice = find_portal_invoice(&pool, &portal, invoice_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let branding = PdfBranding::for_invoice(&pool, &invoice).await.map_err(internal_error)?;
+    track(&pool, &portal, Some(invoice.id), PortalEventKind::InvoiceViewed).await;
+    if let Err(e) = record_invoice_view(&pool, &invoice, ViewedVia::Portal).await {
+        warn!("Failed to record view of invoice {}: {}", invoice.id, e);
+    }
+
+    Ok(Json(json!({
+        "invoice": PortalInvoice::new(&invoice, Utc::now().date_naive()),
+        "payment_instructions": branding.payment_instructions,
+    })))
+}
+
+/// Portal invoice PDF endpoint handler.
+///
+/// Handles GET requests to `/portal/invoices/:id/pdf` with a portal token
+/// (a `token` query parameter works for download links).
+pub async fn portal_invoice_pdf_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(store): Extension<DynBlobStore>,
+    Extension(CurrentPortal(portal)): Extension<CurrentPortal>,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to render portal PDF for invoice {}: {}", invoice_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let invoice = find_portal_invoice(&pool, &portal, invoice_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let branding = PdfBranding::for_invoice(&pool, &invoice).await.map_err(internal_error)?;
+    let pdf = cached_invoice_pdf(store.as_ref(), &invoice, &branding)
+        .await
+        .map_err(internal_error)?;
+    track(&pool, &portal, Some(invoice.id), PortalEventKind::PdfDownloaded).await;
+
+    let disposition = format!("attachment; filename=\"{}.pdf\"", invoice.invoice_number);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        pdf,
+    ))
+}
+
+/// Payment intent endpoint handler.
+///
+/// Handles POST requests to `/portal/invoices/:id/intent` with a portal
+/// token and `{ "planned_date", "note" }` (both optional). The user is
+/// notified and sees the intent on the invoice.
+pub async fn payment_intent_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentPortal(portal)): Extension<CurrentPortal>,
+    Path(invoice_id): Path<Uuid>,
+    Json(request): Json<CreatePaymentIntent>,
+) -> Result<Json<PortalInvoice>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to record payment intent for invoice {}: {}", invoice_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to record payment intent")
+    };
+
+    let invoice = find_portal_invoice(&pool, &portal, invoice_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "invoice not found"))?;
+    let today = Utc::now().date_naive();
+    if let Err(message) = validate_intent(&invoice, &request, today) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let updated = record_payment_intent(&pool, &portal, &invoice, request)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(PortalInvoice::new(&updated, today)))
+}
@@ -0,0 +1,572 @@
+//! Client portal.
+//!
+//! A user gives a client a portal token; with it the client sees all of
+//! their invoices, downloads the PDFs, marks when they intend to pay and
+//! disputes invoices (see [`crate::disputes`]), without a GigPilot
+//! account. Tokens are scoped to one client, expire and can be revoked.
+//! Everything the client does is recorded as portal activity for the user.
+//!
+//! The portal shows the same invoices as the public pay page: drafts,
+//! cancelled and deleted invoices never appear. Only invoices that
+//! reference the client belong to them: an email address alone proves
+//! nothing, so invoices issued before clients existed appear once linked
+//! (see [`crate::clients::link_invoices_by_email`]).
+
+pub mod handlers;
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::auth::{issue_portal_token, PortalClaims};
+use crate::invoices::history::apply_current_audit_context;
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::line_item::LineItem;
+use crate::models::notification::CreateNotification;
+use crate::models::portal::{
+    CreatePaymentIntent, CreatePortalToken, PaymentIntent, PortalEvent, PortalEventKind, PortalToken,
+};
+use crate::models::sync_change::SyncOperation;
+use crate::notifications::notify;
+use crate::sync::server::record_server_change;
+use crate::worker::state_machine::{current_chase_state, ChaseState};
+
+/// Lifetime of a token when the request doesn't say.
+pub const DEFAULT_TOKEN_DAYS: i64 = 90;
+
+/// Longest lifetime of a token.
+pub const MAX_TOKEN_DAYS: i64 = 365;
+
+/// Longest note accepted with a payment intent.
+pub const MAX_INTENT_NOTE_LENGTH: usize = 1000;
+
+/// Columns of an invoice, for queries returning [`Invoice`].
+pub(crate) const INVOICE_COLUMNS: &str = "id, user_id, invoice_number, client_name, client_email, \
+    amount, currency, status, due_date, issue_date, \
+    last_modified, version_vector, is_deleted, \
+    description, line_items, subtotal, tax_total, total, amount_paid, \
+    client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at";
+
+/// Invoices of user `$1` shown to client `$2`.
+const PORTAL_INVOICES: &str = r#"
+    user_id = $1 AND is_deleted = false AND status NOT IN ('draft', 'cancelled')
+    AND client_id = $2
+"#;
+
+/// Validates a token creation request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create_token(request: &CreatePortalToken) -> Result<(), String> {
+    if request.label.as_deref().is_some_and(|label| label.chars().count() > 255) {
+        return Err("label must be at most 255 characters".to_string());
+    }
+    if request
+        .expires_in_days
+        .is_some_and(|days| !(1..=MAX_TOKEN_DAYS).contains(&days))
+    {
+        return Err(format!("expires_in_days must be between 1 and {}", MAX_TOKEN_DAYS));
+    }
+    Ok(())
+}
+
+/// Validates a payment intent against the invoice it is for.
+///
+/// # Returns
+///
+/// Returns a user-facing message describing the first problem found.
+pub fn validate_intent(invoice: &Invoice, intent: &CreatePaymentIntent, today: NaiveDate) -> Result<(), String> {
+    if invoice.balance_due().is_zero() {
+        return Err("invoice is already paid".to_string());
+    }
+    if intent.planned_date.is_some_and(|date| date < today) {
+        return Err("planned_date must not be in the past".to_string());
+    }
+    if intent
+        .note
+        .as_deref()
+        .is_some_and(|note| note.chars().count() > MAX_INTENT_NOTE_LENGTH)
+    {
+        return Err(format!("note must be at most {} characters", MAX_INTENT_NOTE_LENGTH));
+    }
+    Ok(())
+}
+
+/// An invoice as shown to the client.
+///
+/// Leaves out the user's bookkeeping: chase settings, exchange rates,
+/// metadata and sync fields.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PortalInvoice {
+    pub id: Uuid,
+    pub invoice_number: String,
+    pub description: Option<String>,
+    pub issue_date: NaiveDate,
+    pub due_date: Option<NaiveDate>,
+    pub status: InvoiceStatus,
+    pub currency: String,
+    pub line_items: Vec<LineItem>,
+    pub subtotal: Decimal,
+    pub tax_total: Decimal,
+    pub total: Decimal,
+    pub amount_paid: Decimal,
+    pub balance_due: Decimal,
+
+    /// Whether the due date has passed with a balance left
+    pub overdue: bool,
+
+    /// When the client said they will pay, if they did
+    pub payment_intent: Option<PaymentIntent>,
+
+    /// Whether the client disputed the invoice and the user has not yet
+    /// resolved it
+    pub disputed: bool,
+}
+
+impl PortalInvoice {
+    pub fn new(invoice: &Invoice, today: NaiveDate) -> Self {
+        let balance_due = invoice.balance_due();
+        PortalInvoice {
+            id: invoice.id,
+            invoice_number: invoice.invoice_number.clone(),
+            description: invoice.description.clone(),
+            issue_date: invoice.issue_date,
+            due_date: invoice.due_date,
+            status: invoice.status,
+            currency: invoice.currency.clone(),
+            line_items: invoice.items().to_vec(),
+            subtotal: invoice.subtotal,
+            tax_total: invoice.tax_total,
+            total: invoice.total,
+            amount_paid: invoice.amount_paid,
+            balance_due,
+            overdue: !balance_due.is_zero() && invoice.due_date.is_some_and(|due| due < today),
+            payment_intent: invoice
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get("payment_intent"))
+                .and_then(|intent| serde_json::from_value(intent.clone()).ok()),
+            disputed: current_chase_state(invoice, today) == ChaseState::Disputed,
+        }
+    }
+}
+
+/// What the client sees when opening the portal.
+#[derive(Debug, Clone, Serialize)]
+pub struct PortalOverview {
+    /// Client the portal belongs to
+    pub client_name: String,
+
+    /// Business the client is dealing with
+    pub business_name: String,
+
+    /// The client's invoices, newest first
+    pub invoices: Vec<PortalInvoice>,
+
+    /// Balance due per currency
+    pub outstanding: BTreeMap<String, Decimal>,
+}
+
+/// Sums the balance due of the invoices per currency.
+///
+/// Currencies with nothing outstanding are left out.
+pub fn outstanding_by_currency(invoices: &[PortalInvoice]) -> BTreeMap<String, Decimal> {
+    let mut outstanding = BTreeMap::new();
+    for invoice in invoices.iter().filter(|invoice| !invoice.balance_due.is_zero()) {
+        *outstanding.entry(invoice.currency.clone()).or_insert(Decimal::ZERO) += invoice.balance_due;
+    }
+    outstanding
+}
+
+/// Creates a portal token for a client.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `client_id` - Client the token is for (must belong to the user)
+/// * `request` - Label and lifetime (see [`validate_create_token`])
+///
+/// # Returns
+///
+/// Returns the stored token and the signed token string to give the
+/// client. The string is not stored and can't be shown again.
+pub async fn create_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    request: CreatePortalToken,
+) -> Result<(PortalToken, String), anyhow::Error> {
+    let days = request.expires_in_days.unwrap_or(DEFAULT_TOKEN_DAYS);
+    let label = request
+        .label
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty());
+
+    let token = sqlx::query_as::<_, PortalToken>(
+        r#"
+        INSERT INTO client_portal_tokens (user_id, client_id, label, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(label)
+    .bind(Utc::now() + Duration::days(days))
+    .fetch_one(pool)
+    .await?;
+
+    let signed = issue_portal_token(&PortalClaims::new(token.id, token.expires_at))?;
+
+    Ok((token, signed))
+}
+
+/// Lists a client's portal tokens, newest first.
+pub async fn list_tokens(pool: &PgPool, user_id: Uuid, client_id: Uuid) -> Result<Vec<PortalToken>, anyhow::Error> {
+    let tokens = sqlx::query_as::<_, PortalToken>(
+        r#"
+        SELECT * FROM client_portal_tokens
+        WHERE user_id = $1 AND client_id = $2
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tokens)
+}
+
+/// Revokes a portal token; requests made with it are rejected from now on.
+///
+/// # Returns
+///
+/// Returns `true` if a token was revoked, `false` if none matched or it
+/// was already revoked.
+pub async fn revoke_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE client_portal_tokens SET revoked_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND client_id = $3 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(token_id)
+    .bind(user_id)
+    .bind(client_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Loads a usable portal token and marks it as used.
+///
+/// # Returns
+///
+/// Returns `None` if the token doesn't exist, was revoked, has expired or
+/// its client was deleted.
+pub async fn find_active_token(pool: &PgPool, token_id: Uuid) -> Result<Option<PortalToken>, anyhow::Error> {
+    let token = sqlx::query_as::<_, PortalToken>(
+        r#"
+        UPDATE client_portal_tokens t
+        SET last_used_at = NOW()
+        FROM clients c
+        WHERE t.id = $1
+            AND t.revoked_at IS NULL
+            AND t.expires_at > NOW()
+            AND c.id = t.client_id
+            AND c.is_deleted = false
+        RETURNING t.*
+        "#,
+    )
+    .bind(token_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(token)
+}
+
+/// Lists the invoices the portal shows, newest first.
+pub async fn portal_invoices(pool: &PgPool, portal: &PortalToken) -> Result<Vec<Invoice>, anyhow::Error> {
+    let invoices = sqlx::query_as::<_, Invoice>(&format!(
+        "SELECT {} FROM invoices WHERE {} ORDER BY issue_date DESC, created_at DESC",
+        INVOICE_COLUMNS, PORTAL_INVOICES
+    ))
+    .bind(portal.user_id)
+    .bind(portal.client_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(invoices)
+}
+
+/// Loads one invoice shown by the portal.
+///
+/// # Returns
+///
+/// Returns `None` if the invoice doesn't exist or isn't the client's.
+pub async fn find_portal_invoice(
+    pool: &PgPool,
+    portal: &PortalToken,
+    invoice_id: Uuid,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let invoice = sqlx::query_as::<_, Invoice>(&format!(
+        "SELECT {} FROM invoices WHERE id = $3 AND {}",
+        INVOICE_COLUMNS, PORTAL_INVOICES
+    ))
+    .bind(portal.user_id)
+    .bind(portal.client_id)
+    .bind(invoice_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(invoice)
+}
+
+/// Records what a client did in their portal.
+pub async fn record_event<'e, E>(
+    executor: E,
+    portal: &PortalToken,
+    invoice_id: Option<Uuid>,
+    kind: PortalEventKind,
+    data: Option<serde_json::Value>,
+) -> Result<(), anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO client_portal_events (user_id, client_id, token_id, invoice_id, kind, data)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(portal.user_id)
+    .bind(portal.client_id)
+    .bind(portal.id)
+    .bind(invoice_id)
+    .bind(kind)
+    .bind(data)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Lists a client's recent portal activity, newest first.
+pub async fn list_activity(pool: &PgPool, user_id: Uuid, client_id: Uuid) -> Result<Vec<PortalEvent>, anyhow::Error> {
+    let events = sqlx::query_as::<_, PortalEvent>(
+        r#"
+        SELECT * FROM client_portal_events
+        WHERE user_id = $1 AND client_id = $2
+        ORDER BY created_at DESC
+        LIMIT 200
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Stores a client's intent to pay an invoice and tells the user.
+///
+/// The intent replaces any earlier one under `metadata.payment_intent`;
+/// the invoice change is recorded for sync and attributed to the portal in
+/// the invoice history.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `portal` - Token the client used
+/// * `invoice` - The invoice (from [`find_portal_invoice`])
+/// * `request` - Planned date and note (see [`validate_intent`])
+///
+/// # Returns
+///
+/// Returns the updated `Invoice`, or an error.
+pub async fn record_payment_intent(
+    pool: &PgPool,
+    portal: &PortalToken,
+    invoice: &Invoice,
+    request: CreatePaymentIntent,
+) -> Result<Invoice, anyhow::Error> {
+    let intent = PaymentIntent {
+        planned_date: request.planned_date,
+        note: request
+            .note
+            .map(|note| note.trim().to_string())
+            .filter(|note| !note.is_empty()),
+        recorded_at: Utc::now(),
+    };
+    let intent_json = serde_json::to_value(&intent)?;
+
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let updated = sqlx::query_as::<_, Invoice>(&format!(
+        r#"
+        UPDATE invoices
+        SET metadata = jsonb_set(COALESCE(metadata, '{{}}'::jsonb), '{{payment_intent}}', $2),
+            last_modified = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        INVOICE_COLUMNS
+    ))
+    .bind(invoice.id)
+    .bind(&intent_json)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_server_change(
+        &mut *tx,
+        updated.user_id,
+        "invoices",
+        updated.id,
+        SyncOperation::Update,
+        &serde_json::to_value(&updated)?,
+    )
+    .await?;
+    record_event(&mut *tx, portal, Some(invoice.id), PortalEventKind::IntentToPay, Some(intent_json)).await?;
+
+    let when = intent
+        .planned_date
+        .map(|date| format!("They plan to pay on {}.", date))
+        .unwrap_or_else(|| "They plan to pay soon.".to_string());
+    let body = match &intent.note {
+        Some(note) => format!("{} \"{}\"", when, note),
+        None => when,
+    };
+    notify(
+        &mut tx,
+        portal.user_id,
+        CreateNotification {
+            kind: "payment_intent".to_string(),
+            title: format!("{} intends to pay invoice {}", invoice.client_name, invoice.invoice_number),
+            body: Some(body),
+            data: Some(json!({
+                "invoice_id": invoice.id,
+                "client_id": portal.client_id,
+                "planned_date": intent.planned_date,
+            })),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+    use crate::repo::memory::sample_invoice;
+
+    fn invoice(currency: &str, total: i64, paid: i64, due: Option<NaiveDate>) -> Invoice {
+        let mut invoice = sample_invoice(Uuid::new_v4(), date(2024, 3, 1), Decimal::new(total, 2));
+        invoice.currency = currency.to_string();
+        invoice.amount_paid = Decimal::new(paid, 2);
+        invoice.due_date = due;
+        invoice
+    }
+
+    #[test]
+    fn test_portal_invoice_hides_bookkeeping() {
+        let today = date(2024, 4, 10);
+        let mut overdue = invoice("EUR", 10000, 2500, Some(date(2024, 4, 1)));
+        overdue.metadata = Some(json!({
+            "chase_state": "firm_reminder",
+            "payment_intent": { "planned_date": "2024-04-20", "note": "Next run", "recorded_at": "2024-04-05T10:00:00Z" }
+        }));
+
+        let shown = PortalInvoice::new(&overdue, today);
+
+        assert_eq!(shown.balance_due, Decimal::new(7500, 2));
+        assert!(shown.overdue);
+        let intent = shown.payment_intent.as_ref().unwrap();
+        assert_eq!(intent.planned_date, Some(date(2024, 4, 20)));
+        let value = serde_json::to_value(&shown).unwrap();
+        assert!(value.get("metadata").is_none());
+        assert!(value.get("chase_override").is_none());
+        assert!(!shown.disputed);
+
+        overdue.metadata = Some(json!({ "chase_state": "disputed" }));
+        assert!(PortalInvoice::new(&overdue, today).disputed);
+
+        let paid = invoice("EUR", 10000, 10000, Some(date(2024, 4, 1)));
+        assert!(!PortalInvoice::new(&paid, today).overdue);
+    }
+
+    #[test]
+    fn test_outstanding_by_currency() {
+        let today = date(2024, 4, 10);
+        let invoices: Vec<PortalInvoice> = [
+            invoice("EUR", 10000, 2500, None),
+            invoice("EUR", 5000, 0, None),
+            invoice("USD", 2000, 2000, None),
+        ]
+        .iter()
+        .map(|invoice| PortalInvoice::new(invoice, today))
+        .collect();
+
+        let outstanding = outstanding_by_currency(&invoices);
+
+        assert_eq!(outstanding.len(), 1);
+        assert_eq!(outstanding["EUR"], Decimal::new(12500, 2));
+    }
+
+    #[test]
+    fn test_validate_intent() {
+        let today = date(2024, 4, 10);
+        let open = invoice("EUR", 10000, 0, None);
+        let intent = |planned_date, note: Option<&str>| CreatePaymentIntent {
+            planned_date,
+            note: note.map(str::to_string),
+        };
+
+        assert!(validate_intent(&open, &intent(Some(today), Some("Friday")), today).is_ok());
+        assert!(validate_intent(&open, &intent(None, None), today).is_ok());
+        assert!(validate_intent(&open, &intent(Some(date(2024, 4, 9)), None), today).is_err());
+        assert!(validate_intent(&open, &intent(None, Some(&"x".repeat(1001))), today).is_err());
+
+        let paid = invoice("EUR", 10000, 10000, None);
+        assert_eq!(
+            validate_intent(&paid, &intent(None, None), today),
+            Err("invoice is already paid".to_string())
+        );
+    }
+
+    #[test]
+    fn test_validate_create_token() {
+        assert!(validate_create_token(&CreatePortalToken::default()).is_ok());
+
+        let too_long = CreatePortalToken {
+            expires_in_days: Some(MAX_TOKEN_DAYS + 1),
+            ..Default::default()
+        };
+        assert!(validate_create_token(&too_long).is_err());
+
+        let expired = CreatePortalToken {
+            expires_in_days: Some(0),
+            ..Default::default()
+        };
+        assert!(validate_create_token(&expired).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Project budgets and burn.
+//!
+//! A project can be budgeted in hours, in money (in its currency) or both.
+//! Burn is measured from the time tracked against the project: every live
+//! entry counts towards the hours, and billable entries in the project's
+//! currency count towards the amount (hours times their rate). Entries in
+//! other currencies are left out of the amount rather than converted.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use crate::models::project::Project;
+
+/// Share of a budget used, in percent, at which the user is warned.
+pub const WARNING_PERCENT: i16 = 80;
+
+/// Share of a budget used, in percent, at which it is exhausted.
+pub const EXHAUSTED_PERCENT: i16 = 100;
+
+/// Usage of one budget.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetUsage {
+    /// Budgeted hours or amount
+    pub budget: Decimal,
+
+    /// Hours or amount used so far
+    pub used: Decimal,
+
+    /// Budget left (negative once overrun)
+    pub remaining: Decimal,
+
+    /// Share of the budget used, in percent (`null` for a zero budget)
+    pub percent_used: Option<Decimal>,
+}
+
+/// A project's budget and how much of it is used.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProjectBurn {
+    pub project_id: Uuid,
+
+    /// Currency of the amounts
+    pub currency: String,
+
+    /// Hours tracked against the project
+    pub hours_used: Decimal,
+
+    /// Billable amount tracked against the project
+    pub amount_used: Decimal,
+
+    /// Usage of the hours budget, if the project has one
+    pub hours: Option<BudgetUsage>,
+
+    /// Usage of the amount budget, if the project has one
+    pub amount: Option<BudgetUsage>,
+
+    /// Highest alert threshold reached (0, 80 or 100)
+    pub threshold: i16,
+}
+
+/// Computes the usage of a budget.
+fn usage(budget: Decimal, used: Decimal) -> BudgetUsage {
+    let percent_used = (!budget.is_zero()).then(|| (used * Decimal::ONE_HUNDRED / budget).round_dp(1));
+    BudgetUsage {
+        budget,
+        used,
+        remaining: budget - used,
+        percent_used,
+    }
+}
+
+/// Highest alert threshold a usage has reached.
+///
+/// A zero budget is exhausted as soon as anything is used.
+fn threshold_of(usage: &BudgetUsage) -> i16 {
+    let exhausted = match usage.percent_used {
+        Some(percent) => percent >= Decimal::from(EXHAUSTED_PERCENT),
+        None => usage.used > Decimal::ZERO,
+    };
+    if exhausted {
+        EXHAUSTED_PERCENT
+    } else if usage.percent_used.is_some_and(|percent| percent >= Decimal::from(WARNING_PERCENT)) {
+        WARNING_PERCENT
+    } else {
+        0
+    }
+}
+
+/// Compares a project's budgets with what was tracked.
+///
+/// # Arguments
+///
+/// * `project` - The project with its budgets
+/// * `hours_used` - Hours tracked against it
+/// * `amount_used` - Billable amount tracked against it, in its currency
+pub fn project_burn(project: &Project, hours_used: Decimal, amount_used: Decimal) -> ProjectBurn {
+    let hours = project.budget_hours.map(|budget| usage(budget, hours_used));
+    let amount = project.budget_amount.map(|budget| usage(budget, amount_used));
+    let threshold = hours.iter().chain(amount.iter()).map(threshold_of).max().unwrap_or(0);
+
+    ProjectBurn {
+        project_id: project.id,
+        currency: project.currency.clone(),
+        hours_used,
+        amount_used,
+        hours,
+        amount,
+        threshold,
+    }
+}
+
+/// Sums the time tracked against a project.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to read with
+/// * `project` - The project
+///
+/// # Returns
+///
+/// Returns the hours and the billable amount in the project's currency.
+pub async fn tracked_usage<'e, E>(executor: E, project: &Project) -> Result<(Decimal, Decimal), anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let usage = sqlx::query_as::<_, (Decimal, Decimal)>(
+        r#"
+        SELECT
+            COALESCE(SUM(hours), 0),
+            COALESCE(SUM(hours * hourly_rate) FILTER (WHERE billable AND currency = $3), 0)
+        FROM time_entries
+        WHERE user_id = $1 AND project_id = $2 AND is_deleted = false
+        "#,
+    )
+    .bind(project.user_id)
+    .bind(project.id)
+    .bind(&project.currency)
+    .fetch_one(executor)
+    .await?;
+
+    Ok((usage.0, usage.1.round_dp(2)))
+}
+
+/// Writes the email telling a user a project's budget threshold was crossed.
+///
+/// # Returns
+///
+/// Returns the subject and body.
+pub fn budget_alert_email(project: &Project, burn: &ProjectBurn) -> (String, String) {
+    let subject = if burn.threshold >= EXHAUSTED_PERCENT {
+        format!("Budget of {} is used up", project.name)
+    } else {
+        format!("{}% of the budget of {} is used", burn.threshold, project.name)
+    };
+
+    let mut body = format!("Time tracked on {} has reached {}% of its budget:\n\n", project.name, burn.threshold);
+    if let Some(hours) = &burn.hours {
+        body.push_str(&format!("- Hours: {} of {} used\n", hours.used, hours.budget));
+    }
+    if let Some(amount) = &burn.amount {
+        body.push_str(&format!("- Amount: {} {} of {} {} used\n", amount.used, burn.currency, amount.budget, burn.currency));
+    }
+    body.push_str("\nYou can adjust the budget or turn these emails off on the project in GigPilot.\n");
+
+    (subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    use crate::models::project::ProjectStatus;
+
+    fn project(budget_hours: Option<i64>, budget_amount: Option<i64>) -> Project {
+        Project {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_id: None,
+            name: "Brand refresh".to_string(),
+            description: None,
+            rate: Some(Decimal::from(100)),
+            currency: "EUR".to_string(),
+            status: ProjectStatus::Active,
+            budget_hours: budget_hours.map(Decimal::from),
+            budget_amount: budget_amount.map(Decimal::from),
+            budget_alerts: true,
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_burn_reports_usage_of_each_budget() {
+        let burn = project_burn(&project(Some(40), Some(5000)), Decimal::from(30), Decimal::from(3000));
+
+        let hours = burn.hours.unwrap();
+        assert_eq!(hours.remaining, Decimal::from(10));
+        assert_eq!(hours.percent_used, Some(Decimal::from(75)));
+        assert_eq!(burn.amount.unwrap().percent_used, Some(Decimal::from(60)));
+        assert_eq!(burn.threshold, 0);
+
+        let unbudgeted = project_burn(&project(None, None), Decimal::from(30), Decimal::ZERO);
+        assert!(unbudgeted.hours.is_none() && unbudgeted.amount.is_none());
+        assert_eq!(unbudgeted.threshold, 0);
+    }
+
+    #[test]
+    fn test_threshold_is_the_highest_of_both_budgets() {
+        let budgeted = project(Some(40), Some(5000));
+
+        assert_eq!(project_burn(&budgeted, Decimal::from(32), Decimal::from(1000)).threshold, WARNING_PERCENT);
+        assert_eq!(project_burn(&budgeted, Decimal::from(10), Decimal::from(5200)).threshold, EXHAUSTED_PERCENT);
+        assert_eq!(project_burn(&project(Some(0), None), Decimal::ONE, Decimal::ZERO).threshold, EXHAUSTED_PERCENT);
+        assert_eq!(project_burn(&project(Some(0), None), Decimal::ZERO, Decimal::ZERO).threshold, 0);
+    }
+}
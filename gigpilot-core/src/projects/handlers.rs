@@ -0,0 +1,289 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::clients::find_client;
+use crate::models::invoice::InvoiceResponse;
+use crate::models::project::{CreateProject, Project, ProjectStatus, UpdateProject};
+use crate::models::share_grant::{ShareEntity, SharePermission};
+use crate::models::time_entry::TimeEntry;
+use crate::projects::budget::{project_burn, tracked_usage, ProjectBurn};
+use crate::projects::invoicing::invoice_project_time;
+use crate::projects::{
+    create_project, delete_project, find_project, list_projects, spawn_embedding_refresh, update_project,
+    validate_create, validate_update,
+};
+use crate::sharing::{resolve_access, Access};
+use crate::sync::encryption::encrypts_any;
+
+/// Query parameters for invoicing a project's time.
+#[derive(Debug, Deserialize)]
+pub struct InvoiceProjectQuery {
+    /// Only bill entries on or after this date
+    pub from: Option<NaiveDate>,
+
+    /// Only bill entries on or before this date
+    pub to: Option<NaiveDate>,
+}
+
+/// Response body of a project invoice.
+#[derive(Debug, Serialize)]
+pub struct ProjectInvoiceResponse {
+    /// The new draft invoice
+    pub invoice: InvoiceResponse,
+
+    /// The time entries it bills
+    pub time_entries: Vec<TimeEntry>,
+}
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Query parameters for listing projects.
+#[derive(Debug, Deserialize)]
+pub struct ListProjectsQuery {
+    /// Only list the projects of this client
+    pub client_id: Option<Uuid>,
+
+    /// Only list projects in this status
+    pub status: Option<ProjectStatus>,
+}
+
+/// List projects endpoint handler.
+///
+/// Handles GET requests to `/api/projects`, optionally filtered with
+/// `?client_id=` and `?status=`.
+pub async fn list_projects_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListProjectsQuery>,
+) -> Result<Json<Vec<Project>>, StatusCode> {
+    let projects = list_projects(&pool, user_id, query.client_id, query.status)
+        .await
+        .map_err(|e| {
+            error!("Failed to list projects for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(projects))
+}
+
+/// Create project endpoint handler.
+///
+/// Handles POST requests to `/api/projects`.
+pub async fn create_project_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateProject>,
+) -> Result<(StatusCode, Json<Project>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_create(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    if let Some(client_id) = request.client_id {
+        require_client(&pool, user_id, client_id).await?;
+    }
+
+    let project = create_project(&pool, user_id, request).await.map_err(|e| {
+        error!("Failed to create project for user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create project")
+    })?;
+    spawn_embedding_refresh(pool, user_id, vec![project.id]);
+
+    Ok((StatusCode::CREATED, Json(project)))
+}
+
+/// Get project endpoint handler.
+///
+/// Handles GET requests to `/api/projects/:id`, including projects shared
+/// with the user.
+pub async fn get_project_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(project_id): Path<Uuid>,
+) -> Result<Json<Project>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load project {}: {}", project_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let access = resolve_access(&pool, user_id, ShareEntity::Project, project_id, SharePermission::Read)
+        .await
+        .map_err(internal_error)?;
+    let Access::Allowed { owner_id } = access else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let project = find_project(&pool, owner_id, project_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(project))
+}
+
+/// Update project endpoint handler.
+///
+/// Handles PUT requests to `/api/projects/:id`. Projects shared with the
+/// user can be updated with edit permission (403 otherwise); the change is
+/// made to the owner's project.
+pub async fn update_project_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(project_id): Path<Uuid>,
+    Json(update): Json<UpdateProject>,
+) -> Result<Json<Project>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to update project {}: {}", project_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update project")
+    };
+
+    let access = resolve_access(&pool, user_id, ShareEntity::Project, project_id, SharePermission::Edit)
+        .await
+        .map_err(internal_error)?;
+    let Access::Allowed { owner_id } = access else {
+        return Err(error_response(StatusCode::FORBIDDEN, "project is shared with you read-only"));
+    };
+    let current = find_project(&pool, owner_id, project_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "project not found"))?;
+    if let Err(message) = validate_update(&current, &update) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    if let Some(client_id) = update.client_id {
+        require_client(&pool, owner_id, client_id).await?;
+    }
+
+    let project = update_project(&pool, owner_id, project_id, update)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "project not found"))?;
+    spawn_embedding_refresh(pool, owner_id, vec![project.id]);
+
+    Ok(Json(project))
+}
+
+/// Delete project endpoint handler.
+///
+/// Handles DELETE requests to `/api/projects/:id`. Invoices keep their
+/// link to the project.
+pub async fn delete_project_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(project_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_project(&pool, user_id, project_id).await.map_err(|e| {
+        error!("Failed to delete project {}: {}", project_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        spawn_embedding_refresh(pool, user_id, vec![project_id]);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Invoice project time endpoint handler.
+///
+/// Handles POST requests to `/api/projects/:id/invoice`, optionally
+/// limited with `?from=` and `?to=`. Creates a draft invoice for the
+/// project's client from its unbilled billable time entries and marks them
+/// billed. Returns 422 if the project has no client or no unbilled time,
+/// and 409 if the user syncs invoices or time entries end-to-end encrypted.
+pub async fn invoice_project_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(project_id): Path<Uuid>,
+    Query(query): Query<InvoiceProjectQuery>,
+) -> Result<(StatusCode, Json<ProjectInvoiceResponse>), (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to invoice time of project {}: {}", project_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to invoice project")
+    };
+
+    let project = find_project(&pool, user_id, project_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "project not found"))?;
+    if matches!((query.from, query.to), (Some(from), Some(to)) if from > to) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, "from must not be after to"));
+    }
+    let Some(client_id) = project.client_id else {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, "project has no client to invoice"));
+    };
+    if encrypts_any(&pool, user_id, &["invoices", "time_entries"]).await.map_err(internal_error)? {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            "invoices and time entries are synced end-to-end encrypted; invoice the time on a device",
+        ));
+    }
+    let client = find_client(&pool, user_id, client_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "project has no client to invoice"))?;
+
+    let (invoice, time_entries) = invoice_project_time(&pool, user_id, &project, &client, query.from, query.to)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "project has no unbilled time"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ProjectInvoiceResponse {
+            invoice: invoice.into(),
+            time_entries,
+        }),
+    ))
+}
+
+/// Project budget endpoint handler.
+///
+/// Handles GET requests to `/api/projects/:id/budget`: the hours and
+/// billable amount tracked against the project, compared with its budgets.
+pub async fn project_budget_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(project_id): Path<Uuid>,
+) -> Result<Json<ProjectBurn>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load budget of project {}: {}", project_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let access = resolve_access(&pool, user_id, ShareEntity::Project, project_id, SharePermission::Read)
+        .await
+        .map_err(internal_error)?;
+    let Access::Allowed { owner_id } = access else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let project = find_project(&pool, owner_id, project_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let (hours_used, amount_used) = tracked_usage(&pool, &project).await.map_err(internal_error)?;
+
+    Ok(Json(project_burn(&project, hours_used, amount_used)))
+}
+
+/// Checks that a referenced client exists and belongs to the user.
+async fn require_client(pool: &PgPool, user_id: Uuid, client_id: Uuid) -> Result<(), (StatusCode, Json<Value>)> {
+    find_client(pool, user_id, client_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load client {}: {}", client_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load client")
+        })?
+        .map(|_| ())
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "client_id must reference one of your clients"))
+}
@@ -0,0 +1,435 @@
+//! Invoicing a project's unbilled time.
+//!
+//! A project's unbilled billable time entries become one draft invoice for
+//! the project's client, with one line per task (entries sharing a
+//! description) at the project rate. The entries are linked to the invoice
+//! in the same transaction, so they are not billed twice.
+
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::currency::Percent;
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::numbering::next_invoice_number;
+use crate::invoices::DueDateRules;
+use crate::models::client::Client;
+use crate::models::invoice::Invoice;
+use crate::models::line_item::{InvoiceTotals, LineItem};
+use crate::models::project::Project;
+use crate::models::sync_change::SyncOperation;
+use crate::models::time_entry::TimeEntry;
+use crate::sync::server::record_server_change;
+
+/// Description of time lines for entries without one.
+const DEFAULT_TASK: &str = "Services";
+
+/// Invoice lines for a project's time, and the entries they bill.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectTime {
+    /// One line per task and rate, in order of first entry
+    pub line_items: Vec<LineItem>,
+
+    /// Time entries billed by the lines
+    pub time_entry_ids: Vec<Uuid>,
+}
+
+/// A task's entries while grouping.
+struct TaskLine {
+    description: String,
+    rate: Decimal,
+    hours: Decimal,
+    first: NaiveDate,
+    last: NaiveDate,
+}
+
+impl TaskLine {
+    fn line_description(&self) -> String {
+        if self.first == self.last {
+            format!("{} ({})", self.description, self.first)
+        } else {
+            format!("{} ({} to {})", self.description, self.first, self.last)
+        }
+    }
+}
+
+/// Groups a project's time entries into invoice lines.
+///
+/// Entries are billed at the project rate in the project currency. For
+/// projects without a rate each entry keeps its own rate, and entries in
+/// another currency are left out (they stay unbilled).
+///
+/// # Arguments
+///
+/// * `entries` - The project's unbilled billable entries
+/// * `project` - The project
+/// * `default_tax` - The user's default tax rate (ID and percentage)
+///
+/// # Returns
+///
+/// Returns the lines, one per description and rate, and the billed entries.
+pub fn group_project_time(
+    entries: &[TimeEntry],
+    project: &Project,
+    default_tax: Option<(Uuid, Percent)>,
+) -> ProjectTime {
+    let mut tasks: Vec<TaskLine> = Vec::new();
+    let mut time_entry_ids = Vec::new();
+
+    for entry in entries {
+        let rate = match project.rate {
+            Some(rate) => rate,
+            None if entry.currency == project.currency => entry.hourly_rate,
+            None => continue,
+        };
+        let description = entry
+            .description
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .unwrap_or(DEFAULT_TASK);
+
+        match tasks
+            .iter_mut()
+            .find(|task| task.description == description && task.rate == rate)
+        {
+            Some(task) => {
+                task.hours += entry.hours;
+                task.first = task.first.min(entry.entry_date);
+                task.last = task.last.max(entry.entry_date);
+            }
+            None => tasks.push(TaskLine {
+                description: description.to_string(),
+                rate,
+                hours: entry.hours,
+                first: entry.entry_date,
+                last: entry.entry_date,
+            }),
+        }
+        time_entry_ids.push(entry.id);
+    }
+
+    let line_items = tasks
+        .iter()
+        .map(|task| LineItem {
+            description: task.line_description(),
+            quantity: task.hours,
+            unit_price: task.rate,
+            tax_rate: default_tax.map(|(_, rate)| rate),
+            tax_rate_id: default_tax.map(|(id, _)| id),
+        })
+        .collect();
+
+    ProjectTime { line_items, time_entry_ids }
+}
+
+/// Creates a draft invoice from a project's unbilled time.
+///
+/// Unbilled billable entries of the project dated within the period are
+/// grouped (see [`group_project_time`]) into a draft for the project's
+/// client, with the default tax rate and the client's payment terms.
+/// Entries in a retainer period that is still open are left out until it
+/// is settled, since the retainer's hours may cover them. The
+/// entries are linked to the invoice; the invoice and every entry change
+/// are recorded for sync. The project row is locked, so concurrent
+/// requests for one project create a single invoice.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `project` - The project
+/// * `client` - The project's client
+/// * `from` - Only bill entries on or after this date
+/// * `to` - Only bill entries on or before this date
+///
+/// # Returns
+///
+/// Returns the draft invoice and the billed entries, or `None` if there
+/// was no unbilled time to bill.
+pub async fn invoice_project_time(
+    pool: &PgPool,
+    user_id: Uuid,
+    project: &Project,
+    client: &Client,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<Option<(Invoice, Vec<TimeEntry>)>, anyhow::Error> {
+    let due_date_rules = DueDateRules::load(pool, user_id).await?;
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let locked = sqlx::query_scalar::<_, i32>(
+        "SELECT 1 FROM projects WHERE id = $1 AND user_id = $2 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(project.id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if locked.is_none() {
+        return Ok(None);
+    }
+
+    // Entries billed on a deleted invoice count as unbilled again. Entries
+    // in a retainer period that hasn't been settled yet may still be
+    // covered by the retainer's hours, so they wait for it to close
+    let entries = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        SELECT t.* FROM time_entries t
+        LEFT JOIN invoices i ON i.id = t.invoice_id
+        WHERE t.user_id = $1 AND t.project_id = $2
+            AND t.is_deleted = false AND t.billable = true
+            AND (t.invoice_id IS NULL OR i.is_deleted = true)
+            AND ($3::date IS NULL OR t.entry_date >= $3)
+            AND ($4::date IS NULL OR t.entry_date <= $4)
+            AND NOT EXISTS (
+                SELECT 1 FROM retainer_periods p
+                JOIN retainers r ON r.id = p.retainer_id
+                WHERE r.user_id = t.user_id AND r.project_id = t.project_id
+                    AND p.closed_at IS NULL
+                    AND t.entry_date BETWEEN p.period_start AND p.period_end
+            )
+        ORDER BY t.entry_date ASC, t.started_at ASC NULLS LAST, t.created_at ASC
+        FOR UPDATE OF t
+        "#,
+    )
+    .bind(user_id)
+    .bind(project.id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let default_tax = default_tax_rate(&mut tx, user_id).await?;
+    let time = group_project_time(&entries, project, default_tax);
+    if time.line_items.is_empty() {
+        return Ok(None);
+    }
+
+    let draft = DraftInvoice {
+        client,
+        project_id: Some(project.id),
+        currency: &project.currency,
+        description: &project.name,
+        line_items: &time.line_items,
+    };
+    let invoice = insert_draft_invoice(&mut tx, user_id, draft, &due_date_rules).await?;
+
+    let billed = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        UPDATE time_entries SET invoice_id = $2, last_modified = NOW()
+        WHERE user_id = $1 AND id = ANY($3)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice.id)
+    .bind(&time.time_entry_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for entry in &billed {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "time_entries",
+            entry.id,
+            SyncOperation::Update,
+            &serde_json::to_value(entry)?,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Some((invoice, billed)))
+}
+
+/// Loads the user's default tax rate (ID and percentage), if any.
+pub async fn default_tax_rate(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Option<(Uuid, Percent)>, anyhow::Error> {
+    let default_tax = sqlx::query_as::<_, (Uuid, Percent)>(
+        "SELECT id, rate FROM tax_rates WHERE user_id = $1 AND is_default = true ORDER BY created_at ASC LIMIT 1",
+    )
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(default_tax)
+}
+
+/// A draft invoice to create with [`insert_draft_invoice`].
+#[derive(Debug, Clone)]
+pub struct DraftInvoice<'a> {
+    /// Client invoiced; the due date follows their payment terms
+    pub client: &'a Client,
+
+    /// Project the invoice bills, if any
+    pub project_id: Option<Uuid>,
+
+    pub currency: &'a str,
+    pub description: &'a str,
+    pub line_items: &'a [LineItem],
+}
+
+/// Creates a draft invoice issued today and records it for sync.
+///
+/// # Arguments
+///
+/// * `tx` - Transaction to write with
+/// * `user_id` - ID of the owning user
+/// * `draft` - The invoice to create
+/// * `due_date_rules` - The user's due-date rules
+///
+/// # Returns
+///
+/// Returns the new draft invoice.
+pub async fn insert_draft_invoice(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    draft: DraftInvoice<'_>,
+    due_date_rules: &DueDateRules,
+) -> Result<Invoice, anyhow::Error> {
+    let client = draft.client;
+    let invoice_number = next_invoice_number(tx, user_id).await?;
+    let totals = InvoiceTotals::compute(draft.line_items);
+    let today = Utc::now().date_naive();
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        INSERT INTO invoices (
+            user_id, invoice_number, client_name, client_email, client_id, project_id,
+            amount, currency, status, due_date, issue_date,
+            description, line_items, subtotal, tax_total, total
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'draft', $9, $10, $11, $12, $13, $14, $15)
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_number)
+    .bind(&client.name)
+    .bind(&client.email)
+    .bind(client.id)
+    .bind(draft.project_id)
+    .bind(totals.total)
+    .bind(draft.currency)
+    .bind(due_date_rules.default_due_date(today, client.payment_terms_days))
+    .bind(today)
+    .bind(draft.description)
+    .bind(serde_json::to_value(draft.line_items)?)
+    .bind(totals.subtotal)
+    .bind(totals.tax_total)
+    .bind(totals.total)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_server_change(
+        &mut **tx,
+        user_id,
+        "invoices",
+        invoice.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&invoice)?,
+    )
+    .await?;
+
+    Ok(invoice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::project::ProjectStatus;
+
+    fn project(rate: Option<i64>) -> Project {
+        Project {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_id: Some(Uuid::new_v4()),
+            name: "Brand refresh".to_string(),
+            description: None,
+            rate: rate.map(Decimal::from),
+            currency: "EUR".to_string(),
+            status: ProjectStatus::Active,
+            budget_hours: None,
+            budget_amount: None,
+            budget_alerts: false,
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn entry(description: Option<&str>, day: u32, hours: &str, rate: i64, currency: &str) -> TimeEntry {
+        TimeEntry {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_name: None,
+            client_email: None,
+            project_id: None,
+            description: description.map(str::to_string),
+            entry_date: NaiveDate::from_ymd_opt(2024, 3, day).unwrap(),
+            started_at: None,
+            stopped_at: None,
+            hours: Decimal::from_str_exact(hours).unwrap(),
+            hourly_rate: Decimal::from(rate),
+            currency: currency.to_string(),
+            billable: true,
+            invoice_id: None,
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_group_project_time_at_project_rate() {
+        let entries = vec![
+            entry(Some("Logo design"), 4, "2.5", 50, "EUR"),
+            entry(None, 5, "1", 50, "EUR"),
+            entry(Some(" Logo design "), 6, "1.25", 70, "USD"),
+        ];
+        let tax_id = Uuid::new_v4();
+        let time = group_project_time(&entries, &project(Some(90)), Some((tax_id, Percent::new(Decimal::from(20)))));
+
+        assert_eq!(time.time_entry_ids.len(), 3);
+        assert_eq!(time.line_items.len(), 2);
+        assert_eq!(time.line_items[0].description, "Logo design (2024-03-04 to 2024-03-06)");
+        assert_eq!(time.line_items[0].quantity, Decimal::from_str_exact("3.75").unwrap());
+        assert_eq!(time.line_items[0].unit_price, Decimal::from(90));
+        assert_eq!(time.line_items[0].tax_rate_id, Some(tax_id));
+        assert_eq!(time.line_items[1].description, "Services (2024-03-05)");
+    }
+
+    #[test]
+    fn test_group_project_time_without_project_rate() {
+        let entries = vec![
+            entry(Some("Copywriting"), 4, "2", 50, "EUR"),
+            entry(Some("Copywriting"), 5, "1", 60, "EUR"),
+            entry(Some("Copywriting"), 6, "3", 70, "USD"),
+        ];
+        let time = group_project_time(&entries, &project(None), None);
+
+        // Different rates stay on separate lines; other currencies stay unbilled
+        assert_eq!(time.line_items.len(), 2);
+        assert_eq!(time.line_items[1].unit_price, Decimal::from(60));
+        assert_eq!(time.time_entry_ids, vec![entries[0].id, entries[1].id]);
+        assert_eq!(time.line_items[0].tax_rate, None);
+
+        assert!(group_project_time(&[], &project(Some(90)), None).line_items.is_empty());
+    }
+}
@@ -0,0 +1,674 @@
+//! Projects the user works on for clients.
+//!
+//! Projects are created through the API or pushed from devices, and every
+//! server-side change is recorded for sync. Invoices link to a project via
+//! `project_id`, and a project's unbilled time can be invoiced (see
+//! [`invoicing`]). Each live project's name and description are embedded
+//! (`entity_type = "project"`) so the estimator can suggest it; deleting a
+//! project soft-deletes it and drops its embeddings. Optional budgets in
+//! hours or money are compared with the time tracked (see [`budget`]).
+
+pub mod budget;
+pub mod handlers;
+pub mod invoicing;
+
+use rust_decimal::Decimal;
+use serde_json::Value;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::clients::check_client;
+use crate::currency::{normalize_currency, validate_amount, DEFAULT_CURRENCY};
+use crate::models::project::{CreateProject, Project, ProjectStatus, UpdateProject};
+use crate::models::sync_change::SyncOperation;
+use crate::rag::store_embedding;
+use crate::sync::server::record_server_change;
+use crate::text::non_blank;
+
+/// Embedding entity type of projects.
+pub const PROJECT_ENTITY_TYPE: &str = "project";
+
+/// Validates a rate in its currency.
+fn validate_rate(rate: Option<Decimal>, currency: &str) -> Result<(), String> {
+    match rate {
+        Some(rate) => validate_amount(rate, currency).map_err(|e| format!("rate: {}", e)),
+        None => Ok(()),
+    }
+}
+
+/// Validates budgeted hours.
+fn validate_budget_hours(hours: Option<Decimal>) -> Result<(), String> {
+    match hours {
+        Some(hours) if hours.is_sign_negative() || hours != hours.round_dp(2) => {
+            Err("budget_hours must not be negative and allows at most 2 decimal places".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates a budget in the project's currency.
+fn validate_budget(hours: Option<Decimal>, amount: Option<Decimal>, currency: &str) -> Result<(), String> {
+    validate_budget_hours(hours)?;
+    match amount {
+        Some(amount) => validate_amount(amount, currency).map_err(|e| format!("budget_amount: {}", e)),
+        None => Ok(()),
+    }
+}
+
+/// Validates a project creation request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create(request: &CreateProject) -> Result<(), String> {
+    if request.name.trim().is_empty() {
+        return Err("name is required".to_string());
+    }
+    let currency = normalize_currency(request.currency.as_deref().unwrap_or(DEFAULT_CURRENCY))
+        .map_err(|e| e.to_string())?;
+    validate_rate(request.rate, &currency)?;
+    validate_budget(request.budget_hours, request.budget_amount, &currency)
+}
+
+/// Validates an update against the current project.
+pub fn validate_update(current: &Project, update: &UpdateProject) -> Result<(), String> {
+    if matches!(&update.name, Some(name) if name.trim().is_empty()) {
+        return Err("name must not be empty".to_string());
+    }
+    let currency = match &update.currency {
+        Some(currency) => normalize_currency(currency).map_err(|e| e.to_string())?,
+        None => current.currency.clone(),
+    };
+    validate_rate(update.rate.or(current.rate), &currency)?;
+    validate_budget(update.budget_hours, update.budget_amount.or(current.budget_amount), &currency)
+}
+
+/// Text embedded for a project: its name and description.
+pub fn embedding_text(project: &Project) -> String {
+    match project.description.as_deref().map(str::trim) {
+        Some(description) if !description.is_empty() => format!("{}\n\n{}", project.name, description),
+        _ => project.name.clone(),
+    }
+}
+
+/// Creates a project and records it for sync.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `request` - The project to create (see [`validate_create`])
+///
+/// # Returns
+///
+/// Returns the stored `Project`, or an error.
+///
+/// # Errors
+///
+/// Returns an error if the client does not belong to the user or a query
+/// fails.
+pub async fn create_project(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateProject,
+) -> Result<Project, anyhow::Error> {
+    let currency = normalize_currency(request.currency.as_deref().unwrap_or(DEFAULT_CURRENCY))?;
+
+    let mut tx = pool.begin().await?;
+    if let Some(client_id) = request.client_id {
+        check_client(&mut *tx, user_id, client_id).await?;
+    }
+
+    let project = sqlx::query_as::<_, Project>(
+        r#"
+        INSERT INTO projects (
+            user_id, client_id, name, description, rate, currency, status,
+            budget_hours, budget_amount, budget_alerts, metadata
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(request.client_id)
+    .bind(request.name.trim())
+    .bind(non_blank(request.description))
+    .bind(request.rate)
+    .bind(currency)
+    .bind(request.status.unwrap_or_default())
+    .bind(request.budget_hours)
+    .bind(request.budget_amount)
+    .bind(request.budget_alerts.unwrap_or(false))
+    .bind(request.metadata)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_project_change(&mut tx, &project, SyncOperation::Insert).await?;
+    tx.commit().await?;
+
+    Ok(project)
+}
+
+/// Lists a user's live projects by name.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `client_id` - Only list the projects of this client
+/// * `status` - Only list projects in this status
+pub async fn list_projects(
+    pool: &PgPool,
+    user_id: Uuid,
+    client_id: Option<Uuid>,
+    status: Option<ProjectStatus>,
+) -> Result<Vec<Project>, anyhow::Error> {
+    let projects = sqlx::query_as::<_, Project>(
+        r#"
+        SELECT * FROM projects
+        WHERE user_id = $1
+            AND is_deleted = false
+            AND ($2::uuid IS NULL OR client_id = $2)
+            AND ($3::varchar IS NULL OR status = $3)
+        ORDER BY lower(name), created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(projects)
+}
+
+/// Loads a single live project owned by the given user.
+///
+/// # Returns
+///
+/// Returns `Some(Project)` if found, `None` if it does not exist, is
+/// deleted, or belongs to another user.
+pub async fn find_project(
+    pool: &PgPool,
+    user_id: Uuid,
+    project_id: Uuid,
+) -> Result<Option<Project>, anyhow::Error> {
+    let project = sqlx::query_as::<_, Project>(
+        "SELECT * FROM projects WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(project)
+}
+
+/// Updates a project and records the change for sync.
+///
+/// Fields left as `None` keep their current value.
+///
+/// # Returns
+///
+/// Returns the updated `Project`, or `None` if it does not exist.
+///
+/// # Errors
+///
+/// Returns an error if the new client does not belong to the user or a
+/// query fails.
+pub async fn update_project(
+    pool: &PgPool,
+    user_id: Uuid,
+    project_id: Uuid,
+    update: UpdateProject,
+) -> Result<Option<Project>, anyhow::Error> {
+    let currency = update.currency.as_deref().map(normalize_currency).transpose()?;
+
+    let mut tx = pool.begin().await?;
+    if let Some(client_id) = update.client_id {
+        check_client(&mut *tx, user_id, client_id).await?;
+    }
+
+    let project = sqlx::query_as::<_, Project>(
+        r#"
+        UPDATE projects
+        SET name = COALESCE($3, name),
+            client_id = COALESCE($4, client_id),
+            description = COALESCE($5, description),
+            rate = COALESCE($6, rate),
+            currency = COALESCE($7, currency),
+            status = COALESCE($8, status),
+            metadata = COALESCE($9, metadata),
+            budget_hours = COALESCE($10, budget_hours),
+            budget_amount = COALESCE($11, budget_amount),
+            budget_alerts = COALESCE($12, budget_alerts),
+            last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(update.name.map(|n| n.trim().to_string()))
+    .bind(update.client_id)
+    .bind(non_blank(update.description))
+    .bind(update.rate)
+    .bind(currency)
+    .bind(update.status)
+    .bind(update.metadata)
+    .bind(update.budget_hours)
+    .bind(update.budget_amount)
+    .bind(update.budget_alerts)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(project) = &project {
+        record_project_change(&mut tx, project, SyncOperation::Update).await?;
+    }
+    tx.commit().await?;
+
+    Ok(project)
+}
+
+/// Soft-deletes a project and records the deletion for sync.
+///
+/// Invoices keep their link to the project.
+///
+/// # Returns
+///
+/// Returns `true` if a project was deleted, `false` if none matched.
+pub async fn delete_project(pool: &PgPool, user_id: Uuid, project_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let project = sqlx::query_as::<_, Project>(
+        r#"
+        UPDATE projects SET is_deleted = true, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(project) = &project {
+        record_project_change(&mut tx, project, SyncOperation::Delete).await?;
+    }
+    tx.commit().await?;
+
+    Ok(project.is_some())
+}
+
+/// Checks that a project referenced by an invoice belongs to the user.
+///
+/// The foreign key alone would accept another user's project.
+///
+/// # Errors
+///
+/// Returns an error if the project does not exist, is deleted, or belongs
+/// to another user.
+pub async fn check_project<'e, E>(executor: E, user_id: Uuid, project_id: Uuid) -> Result<(), anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let exists = sqlx::query_scalar::<_, i32>(
+        "SELECT 1 FROM projects WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?;
+
+    if exists.is_none() {
+        anyhow::bail!("Project {} not found", project_id);
+    }
+    Ok(())
+}
+
+/// Records a server-side project change for sync.
+async fn record_project_change(
+    tx: &mut Transaction<'_, Postgres>,
+    project: &Project,
+    operation: SyncOperation,
+) -> Result<(), anyhow::Error> {
+    record_server_change(
+        &mut **tx,
+        project.user_id,
+        "projects",
+        project.id,
+        operation,
+        &serde_json::to_value(project)?,
+    )
+    .await
+}
+
+/// Replaces a project's embeddings with one of its current text.
+///
+/// Deleted projects are left without embeddings. Nothing is embedded
+/// without the user's consent to embeddings.
+///
+/// # Errors
+///
+/// Returns an error if a query or the embedding provider fails.
+pub async fn refresh_project_embedding(pool: &PgPool, user_id: Uuid, project_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query("DELETE FROM embeddings WHERE user_id = $1 AND entity_type = $2 AND entity_id = $3")
+        .bind(user_id)
+        .bind(PROJECT_ENTITY_TYPE)
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+
+    if let Some(project) = find_project(pool, user_id, project_id).await? {
+        store_embedding(pool, user_id, &embedding_text(&project), PROJECT_ENTITY_TYPE, Some(project.id)).await?;
+    }
+    Ok(())
+}
+
+/// Refreshes the embeddings of changed projects in the background.
+///
+/// Embedding calls the provider, so requests don't wait for it; failures
+/// are logged.
+pub fn spawn_embedding_refresh(pool: PgPool, user_id: Uuid, project_ids: Vec<Uuid>) {
+    if project_ids.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        for project_id in project_ids {
+            if let Err(e) = refresh_project_embedding(&pool, user_id, project_id).await {
+                warn!("Failed to refresh embedding of project {}: {}", project_id, e);
+            }
+        }
+    });
+}
+
+/// Reads an optional pushed text field.
+fn pushed_str<'a>(data: &'a Value, field: &str) -> Option<&'a str> {
+    data.get(field).and_then(|v| v.as_str())
+}
+
+/// Reads an optional pushed boolean field.
+fn pushed_bool(data: &Value, field: &str) -> Option<bool> {
+    data.get(field).and_then(|v| v.as_bool())
+}
+
+/// Parses an optional pushed UUID field.
+fn pushed_uuid(data: &Value, field: &str) -> Result<Option<Uuid>, anyhow::Error> {
+    pushed_str(data, field).map(Uuid::parse_str).transpose().map_err(Into::into)
+}
+
+/// Parses an optional pushed rate (a number or a decimal string).
+fn pushed_rate(data: &Value) -> Result<Option<Decimal>, anyhow::Error> {
+    pushed_decimal(data, "rate")
+}
+
+/// Parses an optional pushed decimal field (a number or a decimal string).
+fn pushed_decimal(data: &Value, field: &str) -> Result<Option<Decimal>, anyhow::Error> {
+    match data.get(field) {
+        Some(Value::String(rate)) => Ok(Some(Decimal::from_str_exact(rate)?)),
+        Some(Value::Number(rate)) => Ok(Some(Decimal::try_from(rate.as_f64().unwrap_or_default())?)),
+        _ => Ok(None),
+    }
+}
+
+/// Parses an optional pushed status field.
+fn pushed_status(data: &Value) -> Result<Option<ProjectStatus>, anyhow::Error> {
+    match data.get("status") {
+        Some(status) if !status.is_null() => Ok(Some(serde_json::from_value(status.clone())?)),
+        _ => Ok(None),
+    }
+}
+
+/// Inserts a project pushed by a device.
+///
+/// # Errors
+///
+/// Returns an error if the name is missing, a field is invalid or the
+/// client does not belong to the user.
+pub async fn apply_pushed_insert(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+    version_vector: Option<&Value>,
+) -> Result<(), anyhow::Error> {
+    let name = pushed_str(data, "name")
+        .ok_or_else(|| anyhow::anyhow!("Missing name"))?;
+    let client_id = pushed_uuid(data, "client_id")?;
+    if let Some(client_id) = client_id {
+        check_client(&mut **tx, user_id, client_id).await?;
+    }
+    let currency = normalize_currency(pushed_str(data, "currency").unwrap_or(DEFAULT_CURRENCY))?;
+    let rate = pushed_rate(data)?;
+    if let Some(rate) = rate {
+        validate_amount(rate, &currency)?;
+    }
+    let budget_hours = pushed_decimal(data, "budget_hours")?;
+    let budget_amount = pushed_decimal(data, "budget_amount")?;
+    validate_budget(budget_hours, budget_amount, &currency).map_err(|e| anyhow::anyhow!(e))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO projects (
+            id, user_id, client_id, name, description, rate, currency, status,
+            budget_hours, budget_amount, budget_alerts,
+            metadata, last_modified, version_vector
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, NOW(), $13)
+        "#,
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .bind(client_id)
+    .bind(name)
+    .bind(pushed_str(data, "description"))
+    .bind(rate)
+    .bind(currency)
+    .bind(pushed_status(data)?.unwrap_or_default())
+    .bind(budget_hours)
+    .bind(budget_amount)
+    .bind(pushed_bool(data, "budget_alerts").unwrap_or(false))
+    .bind(data.get("metadata"))
+    .bind(version_vector)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Updates a project from a device push.
+///
+/// Like invoice pushes, optional fields missing from the payload are
+/// cleared; devices send the whole record.
+///
+/// # Errors
+///
+/// Returns an error if a field is invalid or the client does not belong
+/// to the user.
+pub async fn apply_pushed_update(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+) -> Result<(), anyhow::Error> {
+    let client_id = pushed_uuid(data, "client_id")?;
+    if let Some(client_id) = client_id {
+        check_client(&mut **tx, user_id, client_id).await?;
+    }
+    let currency = pushed_str(data, "currency").map(normalize_currency).transpose()?;
+    let rate = pushed_rate(data)?;
+    let budget_hours = pushed_decimal(data, "budget_hours")?;
+    let budget_amount = pushed_decimal(data, "budget_amount")?;
+    if rate.is_some() || budget_amount.is_some() {
+        let effective_currency = match &currency {
+            Some(currency) => currency.clone(),
+            None => sqlx::query_scalar::<_, String>("SELECT currency FROM projects WHERE id = $1 AND user_id = $2")
+                .bind(record_id)
+                .bind(user_id)
+                .fetch_optional(&mut **tx)
+                .await?
+                .unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+        };
+        if let Some(rate) = rate {
+            validate_amount(rate, &effective_currency)?;
+        }
+        if let Some(budget_amount) = budget_amount {
+            validate_amount(budget_amount, &effective_currency)?;
+        }
+    }
+    validate_budget_hours(budget_hours).map_err(|e| anyhow::anyhow!(e))?;
+
+    sqlx::query(
+        r#"
+        UPDATE projects
+        SET
+            name = COALESCE($3, name),
+            client_id = $4,
+            description = $5,
+            rate = $6,
+            currency = COALESCE($7, currency),
+            status = COALESCE($8, status),
+            budget_hours = $9,
+            budget_amount = $10,
+            budget_alerts = COALESCE($11, budget_alerts),
+            metadata = $12,
+            last_modified = NOW(),
+            version_vector = $13
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        "#,
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .bind(pushed_str(data, "name"))
+    .bind(client_id)
+    .bind(pushed_str(data, "description"))
+    .bind(rate)
+    .bind(currency)
+    .bind(pushed_status(data)?)
+    .bind(budget_hours)
+    .bind(budget_amount)
+    .bind(pushed_bool(data, "budget_alerts"))
+    .bind(data.get("metadata"))
+    .bind(data.get("version_vector"))
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn request(name: &str, rate: Option<&str>, currency: Option<&str>) -> CreateProject {
+        CreateProject {
+            name: name.to_string(),
+            client_id: None,
+            description: None,
+            rate: rate.map(|rate| Decimal::from_str_exact(rate).unwrap()),
+            currency: currency.map(str::to_string),
+            status: None,
+            budget_hours: None,
+            budget_amount: None,
+            budget_alerts: None,
+            metadata: None,
+        }
+    }
+
+    fn project(description: Option<&str>) -> Project {
+        Project {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_id: None,
+            name: "Brand refresh".to_string(),
+            description: description.map(str::to_string),
+            rate: Some(Decimal::from(90)),
+            currency: "JPY".to_string(),
+            status: ProjectStatus::Active,
+            budget_hours: None,
+            budget_amount: None,
+            budget_alerts: false,
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_validate_create() {
+        assert!(validate_create(&request("Website", Some("85.50"), None)).is_ok());
+        assert!(validate_create(&request("Website", None, Some("eur"))).is_ok());
+        assert!(validate_create(&request(" ", None, None)).is_err());
+        assert!(validate_create(&request("Website", Some("-1"), None)).is_err());
+        assert!(validate_create(&request("Website", Some("85.5"), Some("JPY"))).is_err());
+        assert!(validate_create(&request("Website", None, Some("XYZ"))).is_err());
+    }
+
+    #[test]
+    fn test_validate_update_checks_rate_in_effective_currency() {
+        let current = project(None);
+        assert!(validate_update(&current, &UpdateProject::default()).is_ok());
+
+        let fractional = UpdateProject {
+            rate: Some(Decimal::from_str_exact("90.5").unwrap()),
+            ..Default::default()
+        };
+        assert!(validate_update(&current, &fractional).is_err());
+
+        let in_usd = UpdateProject {
+            currency: Some("USD".to_string()),
+            ..fractional
+        };
+        assert!(validate_update(&current, &in_usd).is_ok());
+    }
+
+    #[test]
+    fn test_validate_budget() {
+        let budgeted = CreateProject {
+            budget_hours: Some(Decimal::from_str_exact("37.5").unwrap()),
+            budget_amount: Some(Decimal::from(4000)),
+            ..request("Website", None, None)
+        };
+        assert!(validate_create(&budgeted).is_ok());
+
+        let negative_hours = CreateProject {
+            budget_hours: Some(Decimal::from(-1)),
+            ..budgeted.clone()
+        };
+        assert!(validate_create(&negative_hours).is_err());
+
+        let fractional_yen = UpdateProject {
+            budget_amount: Some(Decimal::from_str_exact("4000.50").unwrap()),
+            ..Default::default()
+        };
+        assert!(validate_update(&project(None), &fractional_yen).is_err());
+    }
+
+    #[test]
+    fn test_embedding_text() {
+        assert_eq!(embedding_text(&project(None)), "Brand refresh");
+        assert_eq!(embedding_text(&project(Some("  "))), "Brand refresh");
+        assert_eq!(
+            embedding_text(&project(Some("Logo and colour palette"))),
+            "Brand refresh\n\nLogo and colour palette"
+        );
+    }
+
+    #[test]
+    fn test_pushed_fields() {
+        let data = json!({ "rate": "120.00", "status": "on_hold", "client_id": null });
+        assert_eq!(pushed_rate(&data).unwrap(), Some(Decimal::from_str_exact("120.00").unwrap()));
+        assert_eq!(pushed_status(&data).unwrap(), Some(ProjectStatus::OnHold));
+        assert_eq!(pushed_uuid(&data, "client_id").unwrap(), None);
+
+        assert!(pushed_status(&json!({ "status": "paused" })).is_err());
+        assert!(pushed_uuid(&json!({ "client_id": "acme" }), "client_id").is_err());
+        assert_eq!(pushed_rate(&json!({ "rate": 75 })).unwrap(), Some(Decimal::from(75)));
+    }
+}
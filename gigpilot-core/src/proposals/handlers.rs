@@ -0,0 +1,371 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::clients::find_client;
+use crate::invoices::pdf::PdfBranding;
+use crate::models::proposal::{
+    AcceptProposal, CreateProposal, DeclineProposal, Proposal, ProposalStatus, UpdateProposal,
+};
+use crate::projects::spawn_embedding_refresh;
+use crate::proposals::{
+    accept_proposal, check_open, choose_option, create_proposal, decline_proposal, delete_proposal,
+    find_proposal, find_shared_proposal, list_proposals, share_link, share_proposal, unshare_proposal,
+    update_proposal, validate_create, validate_share, validate_update, view_shared_proposal, PublicProposal,
+    MAX_DECLINE_REASON_LENGTH,
+};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Base URL of public pages, read from `PUBLIC_BASE_URL` (default:
+/// `http://localhost:8080`).
+fn public_base_url() -> String {
+    std::env::var("PUBLIC_BASE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string())
+}
+
+/// Query parameters for listing proposals.
+#[derive(Debug, Deserialize)]
+pub struct ListProposalsQuery {
+    /// Only list proposals in this status
+    pub status: Option<ProposalStatus>,
+}
+
+/// Response body of a shared proposal.
+#[derive(Debug, Serialize)]
+pub struct ShareProposalResponse {
+    pub proposal: Proposal,
+
+    /// Link to give the client
+    pub share_url: String,
+}
+
+/// Response body of an accepted proposal, as seen by the client.
+#[derive(Debug, Serialize)]
+pub struct AcceptProposalResponse {
+    pub proposal: PublicProposal,
+
+    /// Pay page of the deposit invoice, if one was created
+    pub deposit_pay_url: Option<String>,
+}
+
+/// List proposals endpoint handler.
+///
+/// Handles GET requests to `/api/proposals`, optionally filtered with
+/// `?status=`.
+pub async fn list_proposals_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListProposalsQuery>,
+) -> Result<Json<Vec<Proposal>>, StatusCode> {
+    let proposals = list_proposals(&pool, user_id, query.status).await.map_err(|e| {
+        error!("Failed to list proposals for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(proposals))
+}
+
+/// Create proposal endpoint handler.
+///
+/// Handles POST requests to `/api/proposals`. New proposals are drafts
+/// until shared.
+pub async fn create_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateProposal>,
+) -> Result<(StatusCode, Json<Proposal>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_create(&request, Utc::now().date_naive()) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    if let Some(client_id) = request.client_id {
+        require_client(&pool, user_id, client_id).await?;
+    }
+
+    let proposal = create_proposal(&pool, user_id, request).await.map_err(|e| {
+        error!("Failed to create proposal for user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create proposal")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(proposal)))
+}
+
+/// Loads one of the user's proposals, mapping failures to responses.
+async fn require_proposal(
+    pool: &PgPool,
+    user_id: Uuid,
+    proposal_id: Uuid,
+) -> Result<Proposal, (StatusCode, Json<Value>)> {
+    find_proposal(pool, user_id, proposal_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load proposal {}: {}", proposal_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load proposal")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "proposal not found"))
+}
+
+/// Get proposal endpoint handler.
+///
+/// Handles GET requests to `/api/proposals/:id`, including how often the
+/// client opened the share link and what they decided.
+pub async fn get_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(proposal_id): Path<Uuid>,
+) -> Result<Json<Proposal>, (StatusCode, Json<Value>)> {
+    let proposal = require_proposal(&pool, user_id, proposal_id).await?;
+
+    Ok(Json(proposal))
+}
+
+/// Update proposal endpoint handler.
+///
+/// Handles PUT requests to `/api/proposals/:id`. Accepted and declined
+/// proposals can't be changed.
+pub async fn update_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(proposal_id): Path<Uuid>,
+    Json(update): Json<UpdateProposal>,
+) -> Result<Json<Proposal>, (StatusCode, Json<Value>)> {
+    let current = require_proposal(&pool, user_id, proposal_id).await?;
+
+    if let Err(message) = validate_update(&current, &update, Utc::now().date_naive()) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    if let Some(client_id) = update.client_id {
+        require_client(&pool, user_id, client_id).await?;
+    }
+
+    let proposal = update_proposal(&pool, user_id, proposal_id, update)
+        .await
+        .map_err(|e| {
+            error!("Failed to update proposal {}: {}", proposal_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update proposal")
+        })?
+        // Deleted or decided since it was loaded
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "proposal can no longer be changed"))?;
+
+    Ok(Json(proposal))
+}
+
+/// Delete proposal endpoint handler.
+///
+/// Handles DELETE requests to `/api/proposals/:id`. The share link stops
+/// working; a project or deposit invoice created on acceptance is kept.
+pub async fn delete_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(proposal_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_proposal(&pool, user_id, proposal_id).await.map_err(|e| {
+        error!("Failed to delete proposal {}: {}", proposal_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Share proposal endpoint handler.
+///
+/// Handles POST requests to `/api/proposals/:id/share`, marking a draft as
+/// sent and returning the link for the client. Sharing again returns the
+/// same link. Returns 409 if the proposal is decided or expired.
+pub async fn share_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(proposal_id): Path<Uuid>,
+) -> Result<Json<ShareProposalResponse>, (StatusCode, Json<Value>)> {
+    let current = require_proposal(&pool, user_id, proposal_id).await?;
+    if let Err(message) = validate_share(&current, Utc::now().date_naive()) {
+        return Err(error_response(StatusCode::CONFLICT, &message));
+    }
+
+    let proposal = share_proposal(&pool, user_id, proposal_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to share proposal {}: {}", proposal_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to share proposal")
+        })?
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "proposal can no longer be shared"))?;
+    let share_token = proposal
+        .share_token
+        .ok_or_else(|| error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to share proposal"))?;
+
+    Ok(Json(ShareProposalResponse {
+        share_url: share_link(&public_base_url(), share_token),
+        proposal,
+    }))
+}
+
+/// Unshare proposal endpoint handler.
+///
+/// Handles DELETE requests to `/api/proposals/:id/share`, revoking the
+/// link. Sharing the proposal again creates a new one.
+pub async fn unshare_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(proposal_id): Path<Uuid>,
+) -> Result<Json<Proposal>, (StatusCode, Json<Value>)> {
+    let proposal = unshare_proposal(&pool, user_id, proposal_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to unshare proposal {}: {}", proposal_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to unshare proposal")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "proposal not found"))?;
+
+    Ok(Json(proposal))
+}
+
+/// Loads the name of the business making a proposal.
+async fn business_name(pool: &PgPool, proposal: &Proposal) -> Result<String, (StatusCode, Json<Value>)> {
+    let branding = PdfBranding::for_user(pool, proposal.user_id).await.map_err(|e| {
+        error!("Failed to load branding for proposal {}: {}", proposal.id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load proposal")
+    })?;
+
+    Ok(branding.business_name)
+}
+
+/// Loads a shared proposal, mapping failures to responses.
+async fn require_shared(pool: &PgPool, share_token: Uuid) -> Result<Proposal, (StatusCode, Json<Value>)> {
+    find_shared_proposal(pool, share_token)
+        .await
+        .map_err(|e| {
+            error!("Failed to load shared proposal: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load proposal")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "proposal not found"))
+}
+
+/// Public proposal endpoint handler.
+///
+/// Handles GET requests to `/proposals/:token` without authentication,
+/// showing the proposal to the client and recording the view.
+pub async fn public_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(share_token): Path<Uuid>,
+) -> Result<Json<PublicProposal>, (StatusCode, Json<Value>)> {
+    let proposal = view_shared_proposal(&pool, share_token)
+        .await
+        .map_err(|e| {
+            error!("Failed to load shared proposal: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load proposal")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "proposal not found"))?;
+    let business_name = business_name(&pool, &proposal).await?;
+
+    Ok(Json(PublicProposal::new(&proposal, &business_name, Utc::now().date_naive())))
+}
+
+/// Accept proposal endpoint handler.
+///
+/// Handles POST requests to `/proposals/:token/accept` without
+/// authentication, with `{ "option", "name", "email" }`. Creates the
+/// project and deposit invoice the proposal asks for; the response links
+/// to the deposit's pay page. Returns 409 if the proposal is already
+/// decided or expired, 422 if the request doesn't name an option or the
+/// signer.
+pub async fn accept_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(share_token): Path<Uuid>,
+    Json(request): Json<AcceptProposal>,
+) -> Result<(StatusCode, Json<AcceptProposalResponse>), (StatusCode, Json<Value>)> {
+    let current = require_shared(&pool, share_token).await?;
+    let today = Utc::now().date_naive();
+    if let Err(message) = check_open(&current, today) {
+        return Err(error_response(StatusCode::CONFLICT, &message));
+    }
+    if let Err(message) = choose_option(&current, &request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let acceptance = accept_proposal(&pool, share_token, request)
+        .await
+        .map_err(|e| {
+            error!("Failed to accept proposal {}: {}", current.id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to accept proposal")
+        })?
+        // Lost a race with another decision, an edit or the link being revoked
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "proposal can no longer be accepted"))?;
+    if let Some(project) = &acceptance.project {
+        spawn_embedding_refresh(pool.clone(), project.user_id, vec![project.id]);
+    }
+    let business_name = business_name(&pool, &acceptance.proposal).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(AcceptProposalResponse {
+            proposal: PublicProposal::new(&acceptance.proposal, &business_name, today),
+            deposit_pay_url: acceptance
+                .deposit_invoice
+                .map(|invoice| format!("{}/pay/{}", public_base_url().trim_end_matches('/'), invoice.id)),
+        }),
+    ))
+}
+
+/// Decline proposal endpoint handler.
+///
+/// Handles POST requests to `/proposals/:token/decline` without
+/// authentication, with an optional `{ "reason" }`. Returns 409 if the
+/// proposal is already decided or expired.
+pub async fn decline_proposal_handler(
+    Extension(pool): Extension<PgPool>,
+    Path(share_token): Path<Uuid>,
+    Json(request): Json<DeclineProposal>,
+) -> Result<Json<PublicProposal>, (StatusCode, Json<Value>)> {
+    if request
+        .reason
+        .as_deref()
+        .is_some_and(|reason| reason.chars().count() > MAX_DECLINE_REASON_LENGTH)
+    {
+        return Err(error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &format!("reason must be at most {} characters", MAX_DECLINE_REASON_LENGTH),
+        ));
+    }
+    let current = require_shared(&pool, share_token).await?;
+    let today = Utc::now().date_naive();
+    if let Err(message) = check_open(&current, today) {
+        return Err(error_response(StatusCode::CONFLICT, &message));
+    }
+
+    let declined = decline_proposal(&pool, share_token, request)
+        .await
+        .map_err(|e| {
+            error!("Failed to decline proposal {}: {}", current.id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to decline proposal")
+        })?
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "proposal can no longer be declined"))?;
+    let business_name = business_name(&pool, &declined).await?;
+
+    Ok(Json(PublicProposal::new(&declined, &business_name, today)))
+}
+
+/// Checks that a client belongs to the user.
+async fn require_client(pool: &PgPool, user_id: Uuid, client_id: Uuid) -> Result<(), (StatusCode, Json<Value>)> {
+    find_client(pool, user_id, client_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load client {}: {}", client_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load client")
+        })?
+        .map(|_| ())
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "client_id must reference one of your clients"))
+}
@@ -0,0 +1,1048 @@
+//! Proposals and their acceptance through share links.
+//!
+//! A proposal describes the scope of a job and prices it as one or more
+//! options. Sharing it gives the client an unguessable link (see
+//! [`share_link`]) where they can read it without a GigPilot account and
+//! accept one option or decline; views, the acceptance and the decline are
+//! recorded on the proposal and the user is notified of the decision.
+//!
+//! Accepting can create a project named after the proposal and a deposit
+//! invoice for a share of the accepted option, in the same transaction.
+//! Decided proposals are frozen.
+
+pub mod handlers;
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::clients::{check_client, client_payment_terms};
+use crate::currency::{normalize_currency, validate_amount, Currency, Money, MoneyError, Percent, DEFAULT_CURRENCY};
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::numbering::next_invoice_number;
+use crate::invoices::DueDateRules;
+use crate::models::invoice::Invoice;
+use crate::models::line_item::{InvoiceTotals, LineItem};
+use crate::models::notification::CreateNotification;
+use crate::models::project::{Project, ProjectStatus};
+use crate::models::proposal::{
+    AcceptProposal, CreateProposal, DeclineProposal, Proposal, ProposalOption, ProposalStatus, UpdateProposal,
+};
+use crate::models::sync_change::SyncOperation;
+use crate::notifications::notify;
+use crate::portal::INVOICE_COLUMNS;
+use crate::sync::server::record_server_change;
+use crate::taxes::resolve_tax_rates;
+use crate::text::non_blank;
+
+/// Most pricing options a proposal may offer.
+pub const MAX_OPTIONS: usize = 10;
+
+/// Longest option key accepted.
+pub const MAX_OPTION_KEY_LENGTH: usize = 50;
+
+/// Longest reason accepted with a decline.
+pub const MAX_DECLINE_REASON_LENGTH: usize = 1000;
+
+/// Validates an optional email field.
+fn validate_email(field: &str, email: Option<&str>) -> Result<(), String> {
+    match email {
+        Some(email) if !email.trim().is_empty() && !email.contains('@') => {
+            Err(format!("{} must be an email address", field))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates the pricing options of a proposal.
+///
+/// # Returns
+///
+/// Returns a message describing the first invalid option.
+pub fn validate_options(options: &[ProposalOption]) -> Result<(), String> {
+    if options.is_empty() {
+        return Err("options must contain at least one option".to_string());
+    }
+    if options.len() > MAX_OPTIONS {
+        return Err(format!("a proposal can offer at most {} options", MAX_OPTIONS));
+    }
+
+    let mut keys = HashSet::new();
+    for option in options {
+        let key = option.key.trim();
+        if key.is_empty() {
+            return Err("every option needs a key".to_string());
+        }
+        if key.chars().count() > MAX_OPTION_KEY_LENGTH {
+            return Err(format!("option keys must be at most {} characters", MAX_OPTION_KEY_LENGTH));
+        }
+        if !keys.insert(key) {
+            return Err(format!("option key {} is used twice", key));
+        }
+        if option.title.trim().is_empty() {
+            return Err(format!("option {} needs a title", key));
+        }
+        if option.line_items.is_empty() {
+            return Err(format!("option {} must contain at least one line item", key));
+        }
+        if let Some((field, message)) = option.line_items.iter().flat_map(LineItem::problems).next() {
+            return Err(format!("option {}: line item {} {}", key, field, message));
+        }
+    }
+    Ok(())
+}
+
+/// Validates a deposit share.
+fn validate_deposit(deposit_percent: Option<Percent>) -> Result<(), String> {
+    match deposit_percent {
+        Some(percent) if percent <= Percent::ZERO || percent > Percent::HUNDRED => {
+            Err("deposit_percent must be greater than 0 and at most 100".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates a proposal creation request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create(request: &CreateProposal, today: NaiveDate) -> Result<(), String> {
+    if request.title.trim().is_empty() {
+        return Err("title is required".to_string());
+    }
+    if request.client_name.trim().is_empty() {
+        return Err("client_name is required".to_string());
+    }
+    validate_email("client_email", request.client_email.as_deref())?;
+    if let Some(currency) = &request.currency {
+        normalize_currency(currency).map_err(|e| e.to_string())?;
+    }
+    if request.expires_on.is_some_and(|expires_on| expires_on < today) {
+        return Err("expires_on must not be in the past".to_string());
+    }
+    validate_deposit(request.deposit_percent)?;
+    validate_options(&request.options)
+}
+
+/// Validates an update against the current proposal.
+///
+/// Accepted and declined proposals are frozen: they record what the
+/// client decided on.
+pub fn validate_update(current: &Proposal, update: &UpdateProposal, today: NaiveDate) -> Result<(), String> {
+    if current.status.is_decided() {
+        return Err(format!("{:?} proposals can't be changed", current.status).to_lowercase());
+    }
+    if matches!(&update.title, Some(title) if title.trim().is_empty()) {
+        return Err("title must not be empty".to_string());
+    }
+    if matches!(&update.client_name, Some(name) if name.trim().is_empty()) {
+        return Err("client_name must not be empty".to_string());
+    }
+    validate_email("client_email", update.client_email.as_deref())?;
+    if let Some(currency) = &update.currency {
+        normalize_currency(currency).map_err(|e| e.to_string())?;
+    }
+    if update.expires_on.is_some_and(|expires_on| expires_on < today) {
+        return Err("expires_on must not be in the past".to_string());
+    }
+    validate_deposit(update.deposit_percent)?;
+    if let Some(options) = &update.options {
+        validate_options(options)?;
+    }
+    Ok(())
+}
+
+/// Checks that the client can still accept or decline a proposal.
+///
+/// # Returns
+///
+/// Returns a user-facing message if the proposal is decided or expired.
+pub fn check_open(proposal: &Proposal, today: NaiveDate) -> Result<(), String> {
+    match proposal.status {
+        ProposalStatus::Accepted => Err("proposal has already been accepted".to_string()),
+        ProposalStatus::Declined => Err("proposal has already been declined".to_string()),
+        ProposalStatus::Draft => Err("proposal has not been shared".to_string()),
+        ProposalStatus::Sent => match proposal.expires_on {
+            Some(expires_on) if expires_on < today => Err(format!("proposal expired on {}", expires_on)),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Checks that a proposal can be shared today.
+pub fn validate_share(proposal: &Proposal, today: NaiveDate) -> Result<(), String> {
+    if proposal.status.is_decided() {
+        return Err(format!("{:?} proposals can't be shared again", proposal.status).to_lowercase());
+    }
+    if let Some(expires_on) = proposal.expires_on.filter(|expires_on| *expires_on < today) {
+        return Err(format!("proposal expired on {}; move expires_on before sharing it", expires_on));
+    }
+    Ok(())
+}
+
+/// Picks the option the client accepts.
+///
+/// The key may be left out when the proposal offers a single option.
+///
+/// # Returns
+///
+/// Returns the option, or a user-facing message if the request doesn't
+/// name one of the proposal's options or the signer.
+pub fn choose_option(proposal: &Proposal, request: &AcceptProposal) -> Result<ProposalOption, String> {
+    if request.name.trim().is_empty() {
+        return Err("name is required".to_string());
+    }
+    validate_email("email", request.email.as_deref())?;
+
+    let mut options = proposal.pricing_options();
+    match request.option.as_deref().map(str::trim) {
+        Some(key) => {
+            let position = options
+                .iter()
+                .position(|option| option.key == key)
+                .ok_or_else(|| format!("proposal has no option {}", key))?;
+            Ok(options.swap_remove(position))
+        }
+        None if options.len() == 1 => Ok(options.remove(0)),
+        None => Err("option is required; the proposal offers several".to_string()),
+    }
+}
+
+/// Builds the line items of a deposit invoice.
+///
+/// The deposit is a share of the option's net amount, with one line per
+/// tax rate in the option so the deposit is taxed like the work it pays
+/// for. Amounts are rounded to the currency's minor units.
+///
+/// # Arguments
+///
+/// * `option` - The accepted option
+/// * `percent` - Share of the option invoiced
+/// * `currency` - Currency of the proposal
+///
+/// # Errors
+///
+/// Returns `MoneyError::Overflow` if a deposit amount does not fit.
+pub fn deposit_line_items(
+    option: &ProposalOption,
+    percent: Percent,
+    currency: Currency,
+) -> Result<Vec<LineItem>, MoneyError> {
+    let mut groups: BTreeMap<Option<Percent>, (Option<Uuid>, Decimal)> = BTreeMap::new();
+    for item in &option.line_items {
+        let group = groups.entry(item.tax_rate).or_insert((item.tax_rate_id, Decimal::ZERO));
+        group.1 += item.net();
+    }
+
+    let several = groups.len() > 1;
+    groups
+        .into_iter()
+        .filter_map(|(tax_rate, (tax_rate_id, net))| {
+            let amount = match Money::new(net, currency).percent(percent) {
+                Ok(amount) => amount,
+                Err(err) => return Some(Err(err)),
+            };
+            if amount.is_zero() {
+                return None;
+            }
+            let mut description = format!("Deposit ({}) for {}", percent, option.title.trim());
+            if several {
+                match tax_rate {
+                    Some(rate) => description.push_str(&format!(", items taxed at {}", rate)),
+                    None => description.push_str(", untaxed items"),
+                }
+            }
+            Some(Ok(LineItem {
+                description,
+                quantity: Decimal::ONE,
+                unit_price: amount.amount,
+                tax_rate,
+                tax_rate_id,
+            }))
+        })
+        .collect()
+}
+
+/// Link where the client reads and accepts a shared proposal.
+pub fn share_link(base_url: &str, share_token: Uuid) -> String {
+    format!("{}/proposals/{}", base_url.trim_end_matches('/'), share_token)
+}
+
+/// A proposal as shown to the client through its share link.
+///
+/// Leaves out the user's bookkeeping: view tracking, metadata and the
+/// records linked to the proposal other than the deposit invoice, which
+/// the client pays on its pay page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PublicProposal {
+    pub title: String,
+    pub scope: Option<String>,
+    pub client_name: String,
+
+    /// Business making the proposal
+    pub business_name: String,
+    pub currency: String,
+    pub status: ProposalStatus,
+    pub options: Vec<ProposalOption>,
+    pub expires_on: Option<NaiveDate>,
+
+    /// Whether the proposal can no longer be accepted
+    pub expired: bool,
+    pub deposit_percent: Option<Percent>,
+    pub accepted_option: Option<String>,
+    pub accepted_at: Option<chrono::DateTime<Utc>>,
+
+    /// Deposit invoice to pay at `/pay/:id`, once accepted
+    pub deposit_invoice_id: Option<Uuid>,
+}
+
+impl PublicProposal {
+    pub fn new(proposal: &Proposal, business_name: &str, today: NaiveDate) -> Self {
+        PublicProposal {
+            title: proposal.title.clone(),
+            scope: proposal.scope.clone(),
+            client_name: proposal.client_name.clone(),
+            business_name: business_name.to_string(),
+            currency: proposal.currency.clone(),
+            status: proposal.status,
+            options: proposal.pricing_options(),
+            expires_on: proposal.expires_on,
+            expired: proposal.is_expired(today),
+            deposit_percent: proposal.deposit_percent,
+            accepted_option: proposal.accepted_option.clone(),
+            accepted_at: proposal.accepted_at,
+            deposit_invoice_id: proposal.deposit_invoice_id,
+        }
+    }
+}
+
+/// Resolves the options' tax rates and computes their totals.
+///
+/// # Errors
+///
+/// Returns an error if a line item references an unknown tax rate or a
+/// total can't be expressed in the currency.
+async fn prepare_options(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    options: Vec<ProposalOption>,
+    currency: &str,
+) -> Result<Value, anyhow::Error> {
+    let mut prepared = Vec::with_capacity(options.len());
+    for mut option in options {
+        resolve_tax_rates(&mut **tx, user_id, &mut option.line_items).await?;
+        let totals = InvoiceTotals::compute(&option.line_items);
+        validate_amount(totals.total, currency)?;
+        prepared.push(ProposalOption {
+            key: option.key.trim().to_string(),
+            title: option.title.trim().to_string(),
+            description: non_blank(option.description),
+            subtotal: totals.subtotal,
+            tax_total: totals.tax_total,
+            total: totals.total,
+            line_items: option.line_items,
+        });
+    }
+    Ok(serde_json::to_value(prepared)?)
+}
+
+/// Creates a draft proposal.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `request` - The proposal to create (see [`validate_create`])
+///
+/// # Returns
+///
+/// Returns the stored `Proposal`, or an error.
+///
+/// # Errors
+///
+/// Returns an error if the client does not belong to the user or a query
+/// fails.
+pub async fn create_proposal(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateProposal,
+) -> Result<Proposal, anyhow::Error> {
+    let currency = normalize_currency(request.currency.as_deref().unwrap_or(DEFAULT_CURRENCY))?;
+
+    let mut tx = pool.begin().await?;
+    if let Some(client_id) = request.client_id {
+        check_client(&mut *tx, user_id, client_id).await?;
+    }
+    let options = prepare_options(&mut tx, user_id, request.options, &currency).await?;
+
+    let proposal = sqlx::query_as::<_, Proposal>(
+        r#"
+        INSERT INTO proposals (
+            user_id, client_id, client_name, client_email, title, scope, currency,
+            options, expires_on, deposit_percent, create_project, metadata
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(request.client_id)
+    .bind(request.client_name.trim())
+    .bind(non_blank(request.client_email))
+    .bind(request.title.trim())
+    .bind(non_blank(request.scope))
+    .bind(currency)
+    .bind(options)
+    .bind(request.expires_on)
+    .bind(request.deposit_percent)
+    .bind(request.create_project.unwrap_or(true))
+    .bind(request.metadata)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(proposal)
+}
+
+/// Lists a user's live proposals, newest first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `status` - Only list proposals in this status
+pub async fn list_proposals(
+    pool: &PgPool,
+    user_id: Uuid,
+    status: Option<ProposalStatus>,
+) -> Result<Vec<Proposal>, anyhow::Error> {
+    let proposals = sqlx::query_as::<_, Proposal>(
+        r#"
+        SELECT * FROM proposals
+        WHERE user_id = $1 AND is_deleted = false AND ($2::varchar IS NULL OR status = $2)
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(proposals)
+}
+
+/// Loads a single live proposal owned by the given user.
+///
+/// # Returns
+///
+/// Returns `Some(Proposal)` if found, `None` if it does not exist, is
+/// deleted, or belongs to another user.
+pub async fn find_proposal(
+    pool: &PgPool,
+    user_id: Uuid,
+    proposal_id: Uuid,
+) -> Result<Option<Proposal>, anyhow::Error> {
+    let proposal = sqlx::query_as::<_, Proposal>(
+        "SELECT * FROM proposals WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(proposal_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(proposal)
+}
+
+/// Updates a proposal that the client has not decided on.
+///
+/// Fields left as `None` keep their current value; new options are
+/// re-totalled.
+///
+/// # Returns
+///
+/// Returns the updated `Proposal`, or `None` if it does not exist or was
+/// decided in the meantime.
+pub async fn update_proposal(
+    pool: &PgPool,
+    user_id: Uuid,
+    proposal_id: Uuid,
+    update: UpdateProposal,
+) -> Result<Option<Proposal>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let current = sqlx::query_as::<_, Proposal>(
+        r#"
+        SELECT * FROM proposals
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false AND status IN ('draft', 'sent')
+        FOR UPDATE
+        "#,
+    )
+    .bind(proposal_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(current) = current else {
+        return Ok(None);
+    };
+
+    if let Some(client_id) = update.client_id {
+        check_client(&mut *tx, user_id, client_id).await?;
+    }
+    let currency = match &update.currency {
+        Some(currency) => normalize_currency(currency)?,
+        None => current.currency.clone(),
+    };
+    let options = match update.options {
+        Some(options) => prepare_options(&mut tx, user_id, options, &currency).await?,
+        None => {
+            for option in current.pricing_options() {
+                validate_amount(option.total, &currency)?;
+            }
+            current.options
+        }
+    };
+
+    let proposal = sqlx::query_as::<_, Proposal>(
+        r#"
+        UPDATE proposals
+        SET client_id = $3, client_name = $4, client_email = $5, title = $6, scope = $7,
+            currency = $8, options = $9, expires_on = $10, deposit_percent = $11,
+            create_project = $12, metadata = $13
+        WHERE id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(proposal_id)
+    .bind(user_id)
+    .bind(update.client_id.or(current.client_id))
+    .bind(update.client_name.map(|n| n.trim().to_string()).unwrap_or(current.client_name))
+    .bind(non_blank(update.client_email).or(current.client_email))
+    .bind(update.title.map(|t| t.trim().to_string()).unwrap_or(current.title))
+    .bind(non_blank(update.scope).or(current.scope))
+    .bind(currency)
+    .bind(options)
+    .bind(update.expires_on.or(current.expires_on))
+    .bind(update.deposit_percent.or(current.deposit_percent))
+    .bind(update.create_project.unwrap_or(current.create_project))
+    .bind(update.metadata.or(current.metadata))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(proposal))
+}
+
+/// Soft-deletes a proposal; its share link stops working.
+///
+/// # Returns
+///
+/// Returns `true` if a proposal was deleted, `false` if none matched.
+pub async fn delete_proposal(pool: &PgPool, user_id: Uuid, proposal_id: Uuid) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE proposals SET is_deleted = true, share_token = NULL
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        "#,
+    )
+    .bind(proposal_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Shares a proposal, marking a draft as sent.
+///
+/// A proposal that is already shared keeps its link, so sharing again is
+/// safe; a revoked link is replaced by a new one.
+///
+/// # Returns
+///
+/// Returns the shared proposal, or `None` if it does not exist or was
+/// decided in the meantime.
+pub async fn share_proposal(
+    pool: &PgPool,
+    user_id: Uuid,
+    proposal_id: Uuid,
+) -> Result<Option<Proposal>, anyhow::Error> {
+    let proposal = sqlx::query_as::<_, Proposal>(
+        r#"
+        UPDATE proposals
+        SET share_token = COALESCE(share_token, $3),
+            shared_at = COALESCE(shared_at, NOW()),
+            status = 'sent'
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false AND status IN ('draft', 'sent')
+        RETURNING *
+        "#,
+    )
+    .bind(proposal_id)
+    .bind(user_id)
+    .bind(Uuid::new_v4())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(proposal)
+}
+
+/// Revokes a proposal's share link; opening it fails from now on.
+///
+/// # Returns
+///
+/// Returns the proposal, or `None` if it does not exist.
+pub async fn unshare_proposal(
+    pool: &PgPool,
+    user_id: Uuid,
+    proposal_id: Uuid,
+) -> Result<Option<Proposal>, anyhow::Error> {
+    let proposal = sqlx::query_as::<_, Proposal>(
+        r#"
+        UPDATE proposals SET share_token = NULL
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(proposal_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(proposal)
+}
+
+/// Loads a shared proposal for its client and records the view.
+///
+/// # Returns
+///
+/// Returns `None` if no live proposal is shared under the token.
+pub async fn view_shared_proposal(pool: &PgPool, share_token: Uuid) -> Result<Option<Proposal>, anyhow::Error> {
+    let proposal = sqlx::query_as::<_, Proposal>(
+        r#"
+        UPDATE proposals
+        SET first_viewed_at = COALESCE(first_viewed_at, NOW()),
+            last_viewed_at = NOW(),
+            view_count = view_count + 1
+        WHERE share_token = $1 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(share_token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(proposal)
+}
+
+/// Loads a shared proposal without recording a view.
+///
+/// # Returns
+///
+/// Returns `None` if no live proposal is shared under the token.
+pub async fn find_shared_proposal(pool: &PgPool, share_token: Uuid) -> Result<Option<Proposal>, anyhow::Error> {
+    let proposal = sqlx::query_as::<_, Proposal>(
+        "SELECT * FROM proposals WHERE share_token = $1 AND is_deleted = false",
+    )
+    .bind(share_token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(proposal)
+}
+
+/// Locks a shared proposal for a decision.
+async fn lock_shared_proposal(
+    tx: &mut Transaction<'_, Postgres>,
+    share_token: Uuid,
+) -> Result<Option<Proposal>, anyhow::Error> {
+    let proposal = sqlx::query_as::<_, Proposal>(
+        "SELECT * FROM proposals WHERE share_token = $1 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(share_token)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(proposal)
+}
+
+/// What accepting a proposal created.
+#[derive(Debug, Clone)]
+pub struct Acceptance {
+    /// The proposal, now `accepted`
+    pub proposal: Proposal,
+
+    /// Project created for the work, if the proposal asked for one
+    pub project: Option<Project>,
+
+    /// Deposit invoice, if the proposal asked for a deposit
+    pub deposit_invoice: Option<Invoice>,
+}
+
+/// Accepts a shared proposal on behalf of its client.
+///
+/// The checks of [`check_open`] and [`choose_option`] are repeated under a
+/// row lock, so concurrent decisions record a single one. The project and
+/// the deposit invoice the proposal asks for are created, the acceptance
+/// is recorded and the user is notified, all in one transaction. The
+/// deposit invoice is issued (status `sent`) so the client can pay it
+/// straight away; it and the project are recorded for sync.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `share_token` - Token of the share link
+/// * `request` - Chosen option and signer
+///
+/// # Returns
+///
+/// Returns the `Acceptance`, or `None` if no proposal is shared under the
+/// token any more or it can no longer be accepted.
+pub async fn accept_proposal(
+    pool: &PgPool,
+    share_token: Uuid,
+    request: AcceptProposal,
+) -> Result<Option<Acceptance>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let Some(proposal) = lock_shared_proposal(&mut tx, share_token).await? else {
+        return Ok(None);
+    };
+    let today = Utc::now().date_naive();
+    let Ok(option) = check_open(&proposal, today).and_then(|_| choose_option(&proposal, &request)) else {
+        return Ok(None);
+    };
+    let user_id = proposal.user_id;
+
+    let project = if proposal.create_project {
+        let project = sqlx::query_as::<_, Project>(
+            r#"
+            INSERT INTO projects (user_id, client_id, name, description, currency, status, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(proposal.client_id)
+        .bind(&proposal.title)
+        .bind(&proposal.scope)
+        .bind(&proposal.currency)
+        .bind(ProjectStatus::Active)
+        .bind(json!({ "proposal_id": proposal.id, "option": option.key }))
+        .fetch_one(&mut *tx)
+        .await?;
+        record_server_change(
+            &mut *tx,
+            user_id,
+            "projects",
+            project.id,
+            SyncOperation::Insert,
+            &serde_json::to_value(&project)?,
+        )
+        .await?;
+        Some(project)
+    } else {
+        None
+    };
+
+    let deposit_invoice = match proposal.deposit_percent {
+        Some(percent) => {
+            let currency = Currency::parse(&proposal.currency)?;
+            let line_items = deposit_line_items(&option, percent, currency)?;
+            if line_items.is_empty() {
+                None
+            } else {
+                let totals = InvoiceTotals::compute(&line_items);
+                let client_terms = match proposal.client_id {
+                    Some(client_id) => client_payment_terms(&mut *tx, user_id, client_id).await?,
+                    None => None,
+                };
+                let due_date = DueDateRules::load(pool, user_id)
+                    .await?
+                    .default_due_date(today, client_terms);
+                let invoice_number = next_invoice_number(&mut tx, user_id).await?;
+                let invoice = sqlx::query_as::<_, Invoice>(&format!(
+                    r#"
+                    INSERT INTO invoices (
+                        user_id, invoice_number, client_name, client_email, client_id, project_id,
+                        amount, currency, status, due_date, issue_date,
+                        description, line_items, metadata, subtotal, tax_total, total
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'sent', $9, $10, $11, $12, $13, $14, $15, $16)
+                    RETURNING {}
+                    "#,
+                    INVOICE_COLUMNS
+                ))
+                .bind(user_id)
+                .bind(invoice_number)
+                .bind(&proposal.client_name)
+                .bind(&proposal.client_email)
+                .bind(proposal.client_id)
+                .bind(project.as_ref().map(|project| project.id))
+                .bind(totals.total)
+                .bind(&proposal.currency)
+                .bind(due_date)
+                .bind(today)
+                .bind(format!("Deposit for {}", proposal.title))
+                .bind(serde_json::to_value(&line_items)?)
+                .bind(json!({ "proposal_id": proposal.id, "deposit": true }))
+                .bind(totals.subtotal)
+                .bind(totals.tax_total)
+                .bind(totals.total)
+                .fetch_one(&mut *tx)
+                .await?;
+                record_server_change(
+                    &mut *tx,
+                    user_id,
+                    "invoices",
+                    invoice.id,
+                    SyncOperation::Insert,
+                    &serde_json::to_value(&invoice)?,
+                )
+                .await?;
+                Some(invoice)
+            }
+        }
+        None => None,
+    };
+
+    let accepted = sqlx::query_as::<_, Proposal>(
+        r#"
+        UPDATE proposals
+        SET status = 'accepted', accepted_option = $2, accepted_by_name = $3, accepted_by_email = $4,
+            accepted_at = NOW(), project_id = $5, deposit_invoice_id = $6
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(proposal.id)
+    .bind(&option.key)
+    .bind(request.name.trim())
+    .bind(non_blank(request.email))
+    .bind(project.as_ref().map(|project| project.id))
+    .bind(deposit_invoice.as_ref().map(|invoice| invoice.id))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    notify(
+        &mut tx,
+        user_id,
+        CreateNotification {
+            kind: "proposal_accepted".to_string(),
+            title: format!("{} accepted your proposal {}", proposal.client_name, proposal.title),
+            body: Some(format!(
+                "{} accepted the {} option ({} {:.2}).",
+                accepted.accepted_by_name.as_deref().unwrap_or_default(),
+                option.title,
+                proposal.currency,
+                option.total
+            )),
+            data: Some(json!({
+                "proposal_id": proposal.id,
+                "option": option.key,
+                "project_id": accepted.project_id,
+                "deposit_invoice_id": accepted.deposit_invoice_id,
+            })),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Some(Acceptance {
+        proposal: accepted,
+        project,
+        deposit_invoice,
+    }))
+}
+
+/// Declines a shared proposal on behalf of its client and tells the user.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `share_token` - Token of the share link
+/// * `request` - Optional reason (at most [`MAX_DECLINE_REASON_LENGTH`]
+///   characters, checked by the caller)
+///
+/// # Returns
+///
+/// Returns the declined proposal, or `None` if no proposal is shared under
+/// the token any more or it can no longer be declined.
+pub async fn decline_proposal(
+    pool: &PgPool,
+    share_token: Uuid,
+    request: DeclineProposal,
+) -> Result<Option<Proposal>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let proposal = lock_shared_proposal(&mut tx, share_token).await?;
+    let Some(proposal) = proposal.filter(|p| check_open(p, Utc::now().date_naive()).is_ok()) else {
+        return Ok(None);
+    };
+
+    let declined = sqlx::query_as::<_, Proposal>(
+        r#"
+        UPDATE proposals SET status = 'declined', declined_at = NOW(), decline_reason = $2
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(proposal.id)
+    .bind(non_blank(request.reason))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    notify(
+        &mut tx,
+        declined.user_id,
+        CreateNotification {
+            kind: "proposal_declined".to_string(),
+            title: format!("{} declined your proposal {}", declined.client_name, declined.title),
+            body: declined.decline_reason.clone(),
+            data: Some(json!({ "proposal_id": declined.id })),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Some(declined))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+
+    fn item(unit_price: i64, tax_rate: Option<i64>) -> LineItem {
+        LineItem {
+            description: "Design".to_string(),
+            quantity: Decimal::ONE,
+            unit_price: Decimal::new(unit_price, 2),
+            tax_rate: tax_rate.map(|rate| Percent::new(Decimal::from(rate))),
+            tax_rate_id: None,
+        }
+    }
+
+    fn option(key: &str, line_items: Vec<LineItem>) -> ProposalOption {
+        ProposalOption {
+            key: key.to_string(),
+            title: format!("{} package", key),
+            description: None,
+            line_items,
+            subtotal: Decimal::ZERO,
+            tax_total: Decimal::ZERO,
+            total: Decimal::ZERO,
+        }
+    }
+
+    fn proposal(options: Vec<ProposalOption>, status: ProposalStatus, expires_on: Option<NaiveDate>) -> Proposal {
+        Proposal {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_id: None,
+            client_name: "Acme".to_string(),
+            client_email: None,
+            title: "Website redesign".to_string(),
+            scope: None,
+            currency: "EUR".to_string(),
+            status,
+            options: serde_json::to_value(options).unwrap(),
+            expires_on,
+            deposit_percent: None,
+            create_project: true,
+            share_token: None,
+            shared_at: None,
+            first_viewed_at: None,
+            last_viewed_at: None,
+            view_count: 0,
+            accepted_option: None,
+            accepted_by_name: None,
+            accepted_by_email: None,
+            accepted_at: None,
+            declined_at: None,
+            decline_reason: None,
+            project_id: None,
+            deposit_invoice_id: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn acceptance(option: Option<&str>) -> AcceptProposal {
+        AcceptProposal {
+            option: option.map(str::to_string),
+            name: "Jane Doe".to_string(),
+            email: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_options() {
+        assert!(validate_options(&[option("basic", vec![item(10000, None)])]).is_ok());
+        assert!(validate_options(&[]).is_err());
+        assert!(validate_options(&[option("basic", vec![])]).is_err());
+        assert!(validate_options(&[option(" ", vec![item(10000, None)])]).is_err());
+
+        let duplicate = [option("basic", vec![item(10000, None)]), option("basic", vec![item(20000, None)])];
+        assert_eq!(validate_options(&duplicate), Err("option key basic is used twice".to_string()));
+
+        let negative = [option("basic", vec![item(-100, None)])];
+        assert!(validate_options(&negative).is_err());
+    }
+
+    #[test]
+    fn test_choose_option() {
+        let today = date(2024, 5, 1);
+        let single = proposal(vec![option("basic", vec![item(10000, None)])], ProposalStatus::Sent, None);
+        assert_eq!(choose_option(&single, &acceptance(None)).unwrap().key, "basic");
+        assert!(choose_option(&single, &acceptance(Some("premium"))).is_err());
+        assert!(choose_option(&single, &AcceptProposal::default()).is_err());
+
+        let several = proposal(
+            vec![option("basic", vec![item(10000, None)]), option("premium", vec![item(30000, None)])],
+            ProposalStatus::Sent,
+            Some(date(2024, 5, 10)),
+        );
+        assert!(choose_option(&several, &acceptance(None)).is_err());
+        assert_eq!(choose_option(&several, &acceptance(Some("premium"))).unwrap().key, "premium");
+        assert!(check_open(&several, today).is_ok());
+        assert_eq!(
+            check_open(&several, date(2024, 5, 11)),
+            Err("proposal expired on 2024-05-10".to_string())
+        );
+
+        let accepted = proposal(vec![option("basic", vec![item(10000, None)])], ProposalStatus::Accepted, None);
+        assert!(check_open(&accepted, today).is_err());
+        assert!(!accepted.is_expired(date(2030, 1, 1)));
+    }
+
+    #[test]
+    fn test_deposit_line_items_split_by_tax_rate() {
+        let eur = Currency::parse("EUR").unwrap();
+        let mixed = option("basic", vec![item(10000, Some(20)), item(5000, Some(20)), item(3333, None)]);
+
+        let items = deposit_line_items(&mixed, Percent::new(Decimal::from(30)), eur).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].tax_rate, None);
+        assert_eq!(items[0].unit_price, Decimal::new(1000, 2));
+        assert_eq!(items[0].description, "Deposit (30%) for basic package, untaxed items");
+        assert_eq!(items[1].tax_rate, Some(Percent::new(Decimal::from(20))));
+        assert_eq!(items[1].unit_price, Decimal::new(4500, 2));
+
+        let single = option("basic", vec![item(10000, Some(20))]);
+        let items = deposit_line_items(&single, Percent::new(Decimal::from(50)), eur).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].description, "Deposit (50%) for basic package");
+        assert_eq!(InvoiceTotals::compute(&items).total, Decimal::new(6000, 2));
+    }
+}
@@ -4,6 +4,11 @@ use sqlx::FromRow;
 use tracing::{info, instrument, warn};
 use uuid::Uuid;
 
+use crate::logging::redact_text;
+use crate::models::user_settings::AiConsent;
+use crate::rag::provider::{provider_from_env, EmbeddingProvider};
+use crate::settings::load_user_settings;
+
 /// Embedding model representing a stored vector embedding.
 /// 
 /// This struct maps to the `embeddings` table and stores
@@ -39,7 +44,8 @@ pub struct Embedding {
 /// Stores an embedding in the database.
 /// 
 /// This function:
-/// 1. Calls the OpenAI embedding API (mocked) to generate a vector
+/// 1. Calls the embedding provider to generate a vector, if the user
+///    consents to it
 /// 2. Stores the embedding in the database
 /// 
 /// # Arguments
@@ -52,27 +58,47 @@ pub struct Embedding {
 /// 
 /// # Returns
 /// 
-/// Returns the created `Embedding` or an error.
+/// Returns the created `Embedding`, or `None` when the user has not
+/// consented to sending text to the embedding provider.
 /// 
 /// # Errors
 /// 
 /// Returns an error if:
-/// - OpenAI API call fails
+/// - Settings can't be loaded
+/// - The embedding provider fails
 /// - Database insertion fails
-#[instrument(skip(pool))]
 pub async fn store_embedding(
     pool: &sqlx::PgPool,
     user_id: Uuid,
     text: &str,
     entity_type: &str,
     entity_id: Option<Uuid>,
-) -> Result<Embedding, anyhow::Error> {
+) -> Result<Option<Embedding>, anyhow::Error> {
+    store_embedding_with(pool, provider_from_env().as_ref(), user_id, text, entity_type, entity_id).await
+}
+
+/// Stores an embedding made by a given provider.
+///
+/// Behaves like [`store_embedding`], which uses the provider selected by
+/// `EMBEDDING_PROVIDER`.
+#[instrument(skip(pool, provider, text), fields(provider = provider.name()))]
+pub async fn store_embedding_with(
+    pool: &sqlx::PgPool,
+    provider: &dyn EmbeddingProvider,
+    user_id: Uuid,
+    text: &str,
+    entity_type: &str,
+    entity_id: Option<Uuid>,
+) -> Result<Option<Embedding>, anyhow::Error> {
+    let consent = load_user_settings(pool, user_id).await?.ai_consent();
+    
     let start_time = std::time::Instant::now();
     
-    info!("Generating embedding for text: {}...", &text[..text.len().min(50)]);
+    info!("Generating embedding for text: {}", redact_text(&text));
     
-    // Mock OpenAI embedding API call
-    let embedding_vector = generate_embedding_mock(text).await?;
+    let Some(embedding_vector) = embed_text(provider, text, consent).await? else {
+        return Ok(None);
+    };
     
     let llm_latency = start_time.elapsed();
     info!("LLM embedding generation took: {:?}", llm_latency);
@@ -122,71 +148,32 @@ pub async fn store_embedding(
     info!("Database insertion took: {:?}", db_latency);
     info!("Total latency - LLM: {:?}, DB: {:?}", llm_latency, db_latency);
     
-    Ok(embedding)
+    Ok(Some(embedding))
 }
 
-/// Mock function to generate embeddings using OpenAI API.
+/// Embeds text with the embedding provider, if the user allows it.
 /// 
-/// In production, this would make an actual HTTP request to OpenAI's
-/// embedding API endpoint.
+/// Every embedding of user text goes through here, so the provider only
+/// sees text from users who opted in.
 /// 
 /// # Arguments
 /// 
+/// * `provider` - The embedding provider
 /// * `text` - Text to embed
+/// * `consent` - The text owner's consent for external AI providers
 /// 
 /// # Returns
 /// 
-/// Returns a 1536-dimensional vector (OpenAI ada-002 format).
-async fn generate_embedding_mock(text: &str) -> Result<Vec<f32>, anyhow::Error> {
-    // Simulate API call delay
-    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
-    
-    // In production, this would be:
-    // let client = reqwest::Client::new();
-    // let response = client
-    //     .post("https://api.openai.com/v1/embeddings")
-    //     .header("Authorization", format!("Bearer {}", api_key))
-    //     .json(&json!({
-    //         "model": "text-embedding-ada-002",
-    //         "input": text
-    //     }))
-    //     .send()
-    //     .await?;
-    // 
-    // let data: serde_json::Value = response.json().await?;
-    // let embedding = data["data"][0]["embedding"]
-    //     .as_array()
-    //     .ok_or_else(|| anyhow::anyhow!("Invalid embedding response"))?
-    //     .iter()
-    //     .map(|v| v.as_f64().unwrap() as f32)
-    //     .collect();
-    // 
-    // Ok(embedding)
-    
-    // Mock: Generate a deterministic "embedding" based on text hash
-    // In reality, this would be a semantic vector from OpenAI
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    
-    let mut hasher = DefaultHasher::new();
-    text.hash(&mut hasher);
-    let hash = hasher.finish();
-    
-    // Generate 1536-dimensional mock vector
-    let mut embedding = Vec::with_capacity(1536);
-    for i in 0..1536 {
-        let value = ((hash as f64 + i as f64) % 1000.0) / 1000.0 - 0.5;
-        embedding.push(value as f32);
-    }
-    
-    // Normalize the vector
-    let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
-    if norm > 0.0 {
-        for v in &mut embedding {
-            *v /= norm;
-        }
+/// Returns the vector, or `None` without embeddings consent.
+pub async fn embed_text(
+    provider: &dyn EmbeddingProvider,
+    text: &str,
+    consent: AiConsent,
+) -> Result<Option<Vec<f32>>, anyhow::Error> {
+    if !consent.embeddings {
+        info!("Embeddings consent not given, text not sent to the embedding provider");
+        return Ok(None);
     }
     
-    Ok(embedding)
+    provider.embed(text).await.map(Some)
 }
-
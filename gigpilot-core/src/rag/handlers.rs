@@ -0,0 +1,67 @@
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::auth::CurrentUser;
+use crate::rag::search::{search_similar_projects, ProjectMatch};
+
+/// Results returned when the request does not say.
+pub const DEFAULT_SEARCH_LIMIT: i64 = 10;
+
+/// Most results returned per search.
+pub const MAX_SEARCH_LIMIT: i64 = 50;
+
+/// Longest query accepted.
+pub const MAX_QUERY_LENGTH: usize = 1000;
+
+/// Query parameters for searching past projects.
+#[derive(Debug, Deserialize)]
+pub struct ProjectSearchParams {
+    /// Description of the work to price
+    pub q: String,
+
+    /// Maximum number of results (default 10)
+    pub limit: Option<i64>,
+}
+
+/// Similar projects endpoint handler.
+///
+/// Handles GET requests to `/api/estimator/search?q=&limit=`. Each result
+/// explains why it was suggested: the chunk that matched, the query terms
+/// it contains and the vector/keyword score breakdown.
+pub async fn search_projects_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(params): Query<ProjectSearchParams>,
+) -> Result<Json<Vec<ProjectMatch>>, (StatusCode, Json<Value>)> {
+    let invalid = |message: String| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })));
+
+    if params.q.trim().is_empty() {
+        return Err(invalid("q is required".to_string()));
+    }
+    if params.q.chars().count() > MAX_QUERY_LENGTH {
+        return Err(invalid(format!("q must be at most {} characters", MAX_QUERY_LENGTH)));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if !(1..=MAX_SEARCH_LIMIT).contains(&limit) {
+        return Err(invalid(format!("limit must be between 1 and {}", MAX_SEARCH_LIMIT)));
+    }
+
+    let matches = search_similar_projects(&pool, user_id, &params.q, Some(limit))
+        .await
+        .map_err(|e| {
+            error!("Failed to search projects for user {}: {}", user_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to search projects" })),
+            )
+        })?;
+
+    Ok(Json(matches))
+}
@@ -1,5 +1,8 @@
 pub mod embeddings;
+pub mod handlers;
+pub mod provider;
 pub mod search;
 
-pub use embeddings::{store_embedding, Embedding};
-pub use search::search_similar_projects;
+pub use embeddings::{embed_text, store_embedding, store_embedding_with, Embedding};
+pub use provider::{provider_from_env, EmbeddingProvider, HashEmbeddingProvider, MockEmbeddingProvider};
+pub use search::{search_similar_projects, search_similar_projects_with, MatchExplanation, ProjectMatch, ScoreBreakdown};
@@ -0,0 +1,178 @@
+//! Embedding providers.
+//!
+//! Text is embedded by a pluggable [`EmbeddingProvider`], selected with
+//! `EMBEDDING_PROVIDER`. Besides the mock standing in for the OpenAI API,
+//! [`HashEmbeddingProvider`] embeds text offline and gives the same vectors
+//! on every platform and Rust release, so search results can be pinned by
+//! tests.
+
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::invoices::import::stable_hash;
+use crate::rag::search::query_terms;
+
+/// Dimensions of every embedding (OpenAI ada-002 format), matching the
+/// `embeddings.embedding` column.
+pub const EMBEDDING_DIMENSIONS: usize = 1536;
+
+/// A text-embedding backend.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Short provider name (e.g. "mock").
+    fn name(&self) -> &'static str;
+
+    /// Embeds text into a unit vector of [`EMBEDDING_DIMENSIONS`] values.
+    ///
+    /// # Returns
+    ///
+    /// Returns the vector, or an error if the provider failed.
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, anyhow::Error>;
+}
+
+/// Scales a vector to unit length (zero vectors are left as they are).
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm: f32 = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for v in &mut vector {
+            *v /= norm;
+        }
+    }
+    vector
+}
+
+/// Mock embedding provider.
+///
+/// In production, this would call OpenAI's embedding API. The mock derives
+/// a vector from the standard library's hash of the text: equal texts get
+/// equal vectors, but similar texts are not close, and the values may
+/// change between Rust releases.
+#[derive(Debug, Default)]
+pub struct MockEmbeddingProvider;
+
+#[async_trait]
+impl EmbeddingProvider for MockEmbeddingProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, anyhow::Error> {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        // Simulate API call delay
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+        // In production, this would be:
+        // let client = reqwest::Client::new();
+        // let response = client
+        //     .post("https://api.openai.com/v1/embeddings")
+        //     .header("Authorization", format!("Bearer {}", api_key))
+        //     .json(&json!({
+        //         "model": "text-embedding-ada-002",
+        //         "input": text
+        //     }))
+        //     .send()
+        //     .await?;
+
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let embedding = (0..EMBEDDING_DIMENSIONS)
+            .map(|i| (((hash as f64 + i as f64) % 1000.0) / 1000.0 - 0.5) as f32)
+            .collect();
+
+        Ok(normalize(embedding))
+    }
+}
+
+/// Deterministic offline embedding provider.
+///
+/// Each query term of the text (see [`query_terms`]) adds ±1 to the
+/// dimension picked by its FNV-1a hash, and the vector is normalized, so
+/// texts sharing terms are close. The hash is fixed, so vectors never
+/// change between releases. Text without terms embeds to the first unit
+/// vector.
+#[derive(Debug, Default)]
+pub struct HashEmbeddingProvider;
+
+impl HashEmbeddingProvider {
+    /// Embeds text without the async wrapper.
+    pub fn embed_sync(text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0; EMBEDDING_DIMENSIONS];
+        for term in query_terms(text) {
+            let hash = stable_hash(&[term]);
+            let index = (hash % EMBEDDING_DIMENSIONS as u64) as usize;
+            embedding[index] += if hash >> 63 == 1 { -1.0 } else { 1.0 };
+        }
+        if embedding.iter().all(|v| *v == 0.0) {
+            embedding[0] = 1.0;
+        }
+        normalize(embedding)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashEmbeddingProvider {
+    fn name(&self) -> &'static str {
+        "hash"
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, anyhow::Error> {
+        Ok(Self::embed_sync(text))
+    }
+}
+
+/// Selects the embedding provider from `EMBEDDING_PROVIDER` (default:
+/// "mock"; "hash" for [`HashEmbeddingProvider`]).
+///
+/// Changing provider leaves stored embeddings comparable only with the
+/// old one's query vectors; embeddings are refreshed as entities change.
+pub fn provider_from_env() -> Arc<dyn EmbeddingProvider> {
+    match std::env::var("EMBEDDING_PROVIDER").as_deref() {
+        Ok("mock") | Err(_) => Arc::new(MockEmbeddingProvider),
+        Ok("hash") => Arc::new(HashEmbeddingProvider),
+        Ok(other) => {
+            warn!("Unknown embedding provider: {}, defaulting to mock", other);
+            Arc::new(MockEmbeddingProvider)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonzero(vector: &[f32]) -> Vec<(usize, f32)> {
+        vector
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v != 0.0)
+            .map(|(i, v)| (i, *v))
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_embeddings_are_pinned() {
+        // Golden values: a change here changes every stored embedding
+        let component = -std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(
+            nonzero(&HashEmbeddingProvider::embed_sync("Logo design")),
+            vec![(442, component), (1455, component)]
+        );
+        assert_eq!(nonzero(&HashEmbeddingProvider::embed_sync("")), vec![(0, 1.0)]);
+    }
+
+    #[test]
+    fn test_hash_embeddings_follow_terms() {
+        let embed = HashEmbeddingProvider::embed_sync;
+        assert_eq!(embed("Logo design"), embed("logo, DESIGN!"));
+        assert_eq!(embed("Logo design").len(), EMBEDDING_DIMENSIONS);
+
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        let query = embed("logo design");
+        assert!(dot(&query, &embed("logo design for a bakery")) > dot(&query, &embed("bookkeeping for a bakery")));
+    }
+}
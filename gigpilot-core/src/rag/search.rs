@@ -1,15 +1,231 @@
-use sqlx::PgPool;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use tracing::{info, instrument};
 use uuid::Uuid;
 
-use crate::rag::embeddings::{generate_embedding_mock, Embedding};
+use crate::logging::redact_text;
+use crate::rag::embeddings::{embed_text, Embedding};
+use crate::rag::provider::{provider_from_env, EmbeddingProvider};
+use crate::settings::load_user_settings;
 
-/// Search for similar projects/invoices using vector similarity.
+/// Share of the combined score given to vector similarity.
+pub const VECTOR_WEIGHT: f32 = 0.7;
+
+/// Share of the combined score given to keyword overlap.
+pub const KEYWORD_WEIGHT: f32 = 0.3;
+
+/// Nearest neighbours fetched per requested result, re-ranked by the
+/// combined score.
+const CANDIDATE_FACTOR: i64 = 4;
+
+/// How each part of the hybrid score contributed to a match.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScoreBreakdown {
+    /// Cosine similarity between the query and chunk embeddings
+    pub vector: f32,
+
+    /// Share of the query's terms found in the chunk (0 to 1)
+    pub keyword: f32,
+
+    /// `VECTOR_WEIGHT * vector + KEYWORD_WEIGHT * keyword`; results are
+    /// ranked by this
+    pub combined: f32,
+}
+
+impl ScoreBreakdown {
+    /// Combines the two scores with the hybrid weights.
+    pub fn new(vector: f32, keyword: f32) -> Self {
+        ScoreBreakdown {
+            vector,
+            keyword,
+            combined: VECTOR_WEIGHT * vector + KEYWORD_WEIGHT * keyword,
+        }
+    }
+}
+
+/// A query term found in the matched chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Highlight {
+    /// The query term (lowercased)
+    pub term: String,
+
+    /// Start of the matching word in the chunk text, in characters
+    pub start: usize,
+
+    /// End (exclusive) of the matching word, in characters
+    pub end: usize,
+}
+
+/// Why a past project was suggested.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchExplanation {
+    /// ID of the embedded chunk that matched
+    pub chunk_id: Uuid,
+
+    /// Text of that chunk
+    pub chunk_text: String,
+
+    /// Query terms that appear in the chunk, in query order
+    pub matched_terms: Vec<String>,
+
+    /// Every occurrence of a matched term in `chunk_text`
+    pub highlights: Vec<Highlight>,
+
+    /// Contribution of the vector and keyword scores
+    pub scores: ScoreBreakdown,
+}
+
+/// A past project or invoice similar to the query.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectMatch {
+    /// Type of the matched entity ("invoice" or "project")
+    pub entity_type: String,
+
+    /// ID of the matched entity
+    pub entity_id: Option<Uuid>,
+
+    /// Combined score the results are ranked by
+    pub score: f32,
+
+    /// Why it matched
+    pub explanation: MatchExplanation,
+}
+
+/// Splits text into lowercased words with their character offsets.
+///
+/// Words are runs of letters and digits, like in invoice search.
+fn words(text: &str) -> Vec<(String, usize, usize)> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+
+    for (index, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() {
+            if current.is_empty() {
+                start = index;
+            }
+            current.extend(c.to_lowercase());
+        } else if !current.is_empty() {
+            words.push((std::mem::take(&mut current), start, index));
+        }
+    }
+    if !current.is_empty() {
+        let end = text.chars().count();
+        words.push((current, start, end));
+    }
+
+    words
+}
+
+/// Distinct query terms, in query order.
+///
+/// Single characters are dropped; they match almost every chunk.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for (word, _, _) in words(query) {
+        if word.chars().count() > 1 && !terms.contains(&word) {
+            terms.push(word);
+        }
+    }
+    terms
+}
+
+/// Explains how a chunk matches the query terms.
+///
+/// A term matches a word it is a prefix of, so "design" highlights
+/// "designs" and "designer".
+///
+/// # Arguments
+///
+/// * `chunk` - The matched embedding
+/// * `terms` - Query terms from [`query_terms`]
+/// * `vector_similarity` - Cosine similarity of the embeddings
+///
+/// # Returns
+///
+/// Returns the explanation, including the hybrid score.
+pub fn explain_match(chunk: &Embedding, terms: &[String], vector_similarity: f32) -> MatchExplanation {
+    let chunk_words = words(&chunk.text_content);
+
+    let mut matched_terms = Vec::new();
+    let mut highlights = Vec::new();
+    for term in terms {
+        let occurrences: Vec<Highlight> = chunk_words
+            .iter()
+            .filter(|(word, _, _)| word.starts_with(term.as_str()))
+            .map(|(_, start, end)| Highlight {
+                term: term.clone(),
+                start: *start,
+                end: *end,
+            })
+            .collect();
+        if !occurrences.is_empty() {
+            matched_terms.push(term.clone());
+            highlights.extend(occurrences);
+        }
+    }
+    highlights.sort_by_key(|h| h.start);
+
+    let keyword = if terms.is_empty() {
+        0.0
+    } else {
+        matched_terms.len() as f32 / terms.len() as f32
+    };
+
+    MatchExplanation {
+        chunk_id: chunk.id,
+        chunk_text: chunk.text_content.clone(),
+        matched_terms,
+        highlights,
+        scores: ScoreBreakdown::new(vector_similarity, keyword),
+    }
+}
+
+/// Re-ranks vector search candidates by their hybrid score.
+///
+/// # Returns
+///
+/// Returns at most `limit` matches, best first.
+pub fn rank_matches(query: &str, candidates: Vec<(Embedding, f32)>, limit: usize) -> Vec<ProjectMatch> {
+    let terms = query_terms(query);
+
+    let mut matches: Vec<ProjectMatch> = candidates
+        .into_iter()
+        .map(|(chunk, similarity)| {
+            let explanation = explain_match(&chunk, &terms, similarity);
+            ProjectMatch {
+                entity_type: chunk.entity_type,
+                entity_id: chunk.entity_id,
+                score: explanation.scores.combined,
+                explanation,
+            }
+        })
+        .collect();
+
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(limit);
+    matches
+}
+
+/// A nearest chunk with its cosine similarity to the query.
+#[derive(Debug, FromRow)]
+struct SimilarChunk {
+    #[sqlx(flatten)]
+    chunk: Embedding,
+
+    similarity: f32,
+}
+
+/// Search for similar projects/invoices using hybrid search.
 /// 
 /// This function:
 /// 1. Generates an embedding for the query text
-/// 2. Searches for similar embeddings using cosine similarity
-/// 3. Returns the most similar results
+/// 2. Fetches the nearest chunks by cosine similarity
+/// 3. Re-ranks them by vector similarity combined with keyword overlap
+/// 
+/// Without the user's consent to embeddings the query is not embedded;
+/// chunks containing a query term are ranked by keyword overlap alone
+/// (their vector score is 0).
 /// 
 /// # Arguments
 /// 
@@ -20,26 +236,48 @@ use crate::rag::embeddings::{generate_embedding_mock, Embedding};
 /// 
 /// # Returns
 /// 
-/// Returns a vector of `Embedding` results sorted by similarity.
+/// Returns the best matches, each explaining which chunk matched, which
+/// query terms it contains and how its score was computed.
 /// 
 /// # Errors
-/// 
+///
 /// Returns an error if:
+/// - Settings can't be loaded
 /// - Embedding generation fails
 /// - Database query fails
-#[instrument(skip(pool))]
 pub async fn search_similar_projects(
     pool: &sqlx::PgPool,
     user_id: Uuid,
     query: &str,
     limit: Option<i64>,
-) -> Result<Vec<(Embedding, f32)>, anyhow::Error> {
+) -> Result<Vec<ProjectMatch>, anyhow::Error> {
+    search_similar_projects_with(pool, provider_from_env().as_ref(), user_id, query, limit).await
+}
+
+/// Searches with a given embedding provider.
+///
+/// Behaves like [`search_similar_projects`], which uses the provider
+/// selected by `EMBEDDING_PROVIDER`. The query must be embedded by the
+/// provider that embedded the stored chunks.
+#[instrument(skip(pool, provider, query), fields(provider = provider.name()))]
+pub async fn search_similar_projects_with(
+    pool: &sqlx::PgPool,
+    provider: &dyn EmbeddingProvider,
+    user_id: Uuid,
+    query: &str,
+    limit: Option<i64>,
+) -> Result<Vec<ProjectMatch>, anyhow::Error> {
     let start_time = std::time::Instant::now();
     
-    info!("Searching for similar projects with query: {}", query);
+    info!("Searching for similar projects with query: {}", redact_text(&query));
+    
+    let limit = limit.unwrap_or(10);
+    let consent = load_user_settings(pool, user_id).await?.ai_consent();
     
     // Generate embedding for query
-    let query_embedding = generate_embedding_mock(query).await?;
+    let Some(query_embedding) = embed_text(provider, query, consent).await? else {
+        return search_by_keywords(pool, user_id, query, limit).await;
+    };
     
     let llm_latency = start_time.elapsed();
     info!("LLM embedding generation took: {:?}", llm_latency);
@@ -49,26 +287,28 @@ pub async fn search_similar_projects(
     // Convert embedding vector to PostgreSQL vector format
     let embedding_str = format!("[{}]", query_embedding.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(","));
     
-    // Search using cosine similarity
-    let limit = limit.unwrap_or(10);
-    
-    let results = sqlx::query_as::<_, (Embedding, f32)>(
+    // Search using cosine similarity; keyword overlap can lift a
+    // candidate above closer vectors, so fetch more than requested
+    let candidates = sqlx::query_as::<_, SimilarChunk>(
         r#"
         SELECT 
             id, user_id, text_content,
-            embedding::text::float[] as embedding,
+            embedding::text::real[] as embedding,
             entity_type, entity_id, created_at, updated_at,
-            1 - (embedding <=> $2::vector) as similarity
+            (1 - (embedding <=> $2::vector))::real as similarity
         FROM embeddings
         WHERE user_id = $1
             AND entity_type IN ('invoice', 'project')
+            AND (entity_type <> 'project' OR entity_id IN (
+                SELECT p.id FROM projects p WHERE p.user_id = $1 AND p.is_deleted = false
+            ))
         ORDER BY embedding <=> $2::vector
         LIMIT $3
         "#,
     )
     .bind(user_id)
     .bind(embedding_str)
-    .bind(limit)
+    .bind(limit * CANDIDATE_FACTOR)
     .fetch_all(pool)
     .await?;
     
@@ -76,8 +316,220 @@ pub async fn search_similar_projects(
     info!("Database similarity search took: {:?}", db_latency);
     info!("Total latency - LLM: {:?}, DB: {:?}", llm_latency, db_latency);
     
+    let candidates = candidates.into_iter().map(|row| (row.chunk, row.similarity)).collect();
+    let results = rank_matches(query, candidates, usize::try_from(limit).unwrap_or(0));
+    
     info!("Found {} similar results", results.len());
     
     Ok(results)
 }
 
+/// Keyword-only search, used without consent to embeddings.
+///
+/// Fetches recent chunks containing any query term and ranks them by
+/// keyword overlap.
+async fn search_by_keywords(
+    pool: &sqlx::PgPool,
+    user_id: Uuid,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<ProjectMatch>, anyhow::Error> {
+    // Terms are letters and digits only, nothing to escape
+    let patterns: Vec<String> = query_terms(query).iter().map(|term| format!("%{}%", term)).collect();
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let chunks = sqlx::query_as::<_, Embedding>(
+        r#"
+        SELECT
+            id, user_id, text_content,
+            embedding::text::real[] as embedding,
+            entity_type, entity_id, created_at, updated_at
+        FROM embeddings
+        WHERE user_id = $1
+            AND entity_type IN ('invoice', 'project')
+            AND (entity_type <> 'project' OR entity_id IN (
+                SELECT p.id FROM projects p WHERE p.user_id = $1 AND p.is_deleted = false
+            ))
+            AND text_content ILIKE ANY($2)
+        ORDER BY updated_at DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(patterns)
+    .bind(limit * CANDIDATE_FACTOR)
+    .fetch_all(pool)
+    .await?;
+
+    let candidates = chunks.into_iter().map(|chunk| (chunk, 0.0)).collect();
+    let results = rank_matches(query, candidates, usize::try_from(limit).unwrap_or(0));
+
+    info!("Found {} keyword results", results.len());
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    use crate::rag::provider::HashEmbeddingProvider;
+
+    /// Past work the golden searches run against, by entity ID.
+    const FIXTURES: &[(u128, &str)] = &[
+        (1, "Logo design and brand guidelines for a bakery"),
+        (2, "React Native mobile app with login and push notifications"),
+        (3, "Quarterly bookkeeping retainer for a bakery"),
+        (4, "Website redesign with React and a headless CMS"),
+        (5, "Mobile app UI design in Figma"),
+        (6, "Brand identity refresh: logo, colours and typography"),
+    ];
+
+    /// Expected entity IDs, best first, for each query and limit. Limits
+    /// stop before chunks with no score at all, whose order is arbitrary.
+    const GOLDEN: &[(&str, usize, &[u128])] = &[
+        ("logo design", 3, &[1, 5, 6]),
+        ("react native app", 3, &[2, 5, 4]),
+        ("mobile app design", 3, &[5, 2, 1]),
+        ("bakery branding", 2, &[3, 1]),
+        ("React website redesign", 2, &[4, 2]),
+    ];
+
+    fn chunk(text: &str) -> Embedding {
+        Embedding {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            text_content: text.to_string(),
+            embedding: Vec::new(),
+            entity_type: "project".to_string(),
+            entity_id: Some(Uuid::new_v4()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_query_terms() {
+        assert_eq!(
+            query_terms("Build a React Native app, React auth"),
+            vec!["build", "react", "native", "app", "auth"]
+        );
+        assert!(query_terms(" - ").is_empty());
+    }
+
+    #[test]
+    fn test_explanation_highlights_prefix_matches() {
+        let chunk = chunk("Logo designs for Café Ünïcorn; designer: Ana");
+        let terms = query_terms("logo design ünïcorn pricing");
+
+        let explanation = explain_match(&chunk, &terms, 0.8);
+
+        assert_eq!(explanation.matched_terms, vec!["logo", "design", "ünïcorn"]);
+        let spans: Vec<(usize, usize)> = explanation.highlights.iter().map(|h| (h.start, h.end)).collect();
+        assert_eq!(spans, vec![(0, 4), (5, 12), (22, 29), (31, 39)]);
+        assert_eq!(explanation.scores.keyword, 0.75);
+        assert!((explanation.scores.combined - (0.7 * 0.8 + 0.3 * 0.75)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_keyword_overlap_reranks_candidates() {
+        let closer = chunk("Quarterly bookkeeping retainer");
+        let relevant = chunk("React Native app with login");
+
+        let matches = rank_matches(
+            "react native app",
+            vec![(closer, 0.82), (relevant, 0.75)],
+            1,
+        );
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].explanation.chunk_text, "React Native app with login");
+        assert_eq!(matches[0].explanation.scores.vector, 0.75);
+    }
+
+    fn fixture_chunk(entity_id: u128, text: &str) -> Embedding {
+        Embedding {
+            entity_id: Some(Uuid::from_u128(entity_id)),
+            embedding: HashEmbeddingProvider::embed_sync(text),
+            ..chunk(text)
+        }
+    }
+
+    /// Searches the fixtures like [`search_similar_projects`] does: the
+    /// nearest chunks by cosine similarity, re-ranked.
+    fn search_fixtures(query: &str, limit: usize) -> Vec<u128> {
+        let query_embedding = HashEmbeddingProvider::embed_sync(query);
+        let mut candidates: Vec<(Embedding, f32)> = FIXTURES
+            .iter()
+            .map(|(id, text)| {
+                let chunk = fixture_chunk(*id, text);
+                let similarity = chunk.embedding.iter().zip(&query_embedding).map(|(a, b)| a * b).sum();
+                (chunk, similarity)
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.truncate(limit * CANDIDATE_FACTOR as usize);
+
+        rank_matches(query, candidates, limit)
+            .iter()
+            .filter_map(|m| m.entity_id)
+            .map(|id| id.as_u128())
+            .collect()
+    }
+
+    #[test]
+    fn test_golden_search_results() {
+        for (query, limit, expected) in GOLDEN {
+            assert_eq!(search_fixtures(query, *limit), expected.to_vec(), "results for {:?}", query);
+        }
+    }
+
+    /// Runs the golden searches through the database (pgvector ordering
+    /// included).
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_search_similar_projects_golden_results() {
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL not set for tests");
+        // One connection, so the probe setting applies to every query
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&database_url)
+            .await
+            .expect("Failed to connect");
+        sqlx::query("SET ivfflat.probes = 100").execute(&pool).await.unwrap();
+
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO users (email, password_hash) VALUES ($1, 'x') RETURNING id",
+        )
+        .bind(format!("rag-golden-{}@example.com", Uuid::new_v4()))
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO user_settings (user_id, ai_embeddings_consent) VALUES ($1, true)")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let provider = HashEmbeddingProvider;
+        for (id, text) in FIXTURES {
+            crate::rag::store_embedding_with(&pool, &provider, user_id, text, "invoice", Some(Uuid::from_u128(*id)))
+                .await
+                .unwrap()
+                .expect("embeddings consent given");
+        }
+
+        for (query, limit, expected) in GOLDEN {
+            let results = search_similar_projects_with(&pool, &provider, user_id, query, Some(*limit as i64))
+                .await
+                .unwrap();
+            let ids: Vec<u128> = results.iter().filter_map(|m| m.entity_id).map(|id| id.as_u128()).collect();
+            assert_eq!(ids, expected.to_vec(), "results for {:?}", query);
+        }
+
+        sqlx::query("DELETE FROM users WHERE id = $1").bind(user_id).execute(&pool).await.unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use axum::{
+    extract::{Extension, Multipart, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::currency::Currency;
+use crate::invoices::find_invoice;
+use crate::locale::Locale;
+use crate::models::bank_transaction::{BankTransaction, BankTransactionStatus, MatchBankTransaction};
+use crate::models::invoice::InvoiceResponse;
+use crate::reconciliation::statement::{parse_statement, StatementFormat, StatementOptions};
+use crate::reconciliation::{
+    candidate_invoices, dismiss_bank_transaction, find_bank_transaction, import_statement, is_pending,
+    list_bank_transactions, settle_bank_transaction, MatchRefusal, OpenInvoice, StatementReport,
+};
+use crate::settings::load_user_settings;
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Bank statement import endpoint handler.
+///
+/// Handles POST requests to `/api/payments/import-statement`. The body is
+/// `multipart/form-data` with the statement in a `file` field, plus
+/// optional `format` (`csv` or `camt053`, detected from the contents when
+/// missing), `mapping` (JSON object of field to CSV header), `date_format`
+/// and `locale` fields. CSV rows without a currency use the user's base
+/// currency. Matched transfers are recorded as payments straight away;
+/// the report lists them, the transfers left for review and the unreadable
+/// rows.
+pub async fn import_statement_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    mut multipart: Multipart,
+) -> Result<Json<StatementReport>, (StatusCode, Json<Value>)> {
+    let bad_request = |e: axum::extract::multipart::MultipartError| {
+        error_response(StatusCode::BAD_REQUEST, &e.to_string())
+    };
+    let unprocessable = |message: String| error_response(StatusCode::UNPROCESSABLE_ENTITY, &message);
+
+    let mut format = None;
+    let mut options = StatementOptions::default();
+    let mut data = None;
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        match field.name() {
+            Some("file") => data = Some(field.bytes().await.map_err(bad_request)?),
+            Some("format") => {
+                let text = field.text().await.map_err(bad_request)?;
+                if !text.trim().is_empty() {
+                    format = Some(text.parse::<StatementFormat>().map_err(unprocessable)?);
+                }
+            }
+            Some("mapping") => {
+                let text = field.text().await.map_err(bad_request)?;
+                if !text.trim().is_empty() {
+                    options.mapping = serde_json::from_str(&text).map_err(|e| {
+                        unprocessable(format!("mapping must be a JSON object of field names to CSV headers: {}", e))
+                    })?;
+                }
+            }
+            Some("date_format") => {
+                let text = field.text().await.map_err(bad_request)?;
+                options.date_format = Some(text.trim().to_string()).filter(|format| !format.is_empty());
+            }
+            Some("locale") => {
+                let text = field.text().await.map_err(bad_request)?;
+                if !text.trim().is_empty() {
+                    options.locale = Some(Locale::parse(&text).map_err(unprocessable)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    let data = data.ok_or_else(|| unprocessable("missing file field".to_string()))?;
+    let format = format.unwrap_or_else(|| StatementFormat::detect(&data));
+
+    let internal_error = |message: &str| error_response(StatusCode::INTERNAL_SERVER_ERROR, message);
+    let settings = load_user_settings(&pool, user_id).await.map_err(|e| {
+        error!("Failed to load settings for user {}: {}", user_id, e);
+        internal_error("Failed to load settings")
+    })?;
+    let currency = Currency::parse(&settings.base_currency).map_err(|e| {
+        error!("Invalid base currency for user {}: {}", user_id, e);
+        internal_error("Invalid base currency")
+    })?;
+
+    let parsed = parse_statement(&data, format, &options, currency).map_err(|e| unprocessable(e.error))?;
+    let report = import_statement(&pool, user_id, parsed).await.map_err(|e| {
+        error!("Failed to import bank statement for user {}: {}", user_id, e);
+        internal_error("Failed to import bank statement")
+    })?;
+
+    Ok(Json(report))
+}
+
+/// Query parameters for listing imported transfers.
+#[derive(Debug, Deserialize)]
+pub struct ListBankTransactionsQuery {
+    /// Transfers with this status (defaults to the review queue)
+    pub status: Option<BankTransactionStatus>,
+}
+
+/// An imported transfer with the open invoices it could pay.
+#[derive(Debug, Serialize)]
+pub struct BankTransactionResponse {
+    #[serde(flatten)]
+    pub transaction: BankTransaction,
+
+    /// Candidate invoices with their current balance (review only)
+    pub candidates: Vec<OpenInvoice>,
+}
+
+/// List bank transactions endpoint handler.
+///
+/// Handles GET requests to `/api/payments/bank-transactions`, by default
+/// listing the review queue; `?status=unmatched|matched|dismissed` lists
+/// the others.
+pub async fn list_bank_transactions_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListBankTransactionsQuery>,
+) -> Result<Json<Vec<BankTransactionResponse>>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to list bank transactions for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let status = query.status.unwrap_or(BankTransactionStatus::Review);
+    let transactions = list_bank_transactions(&pool, user_id, status).await.map_err(internal_error)?;
+    let candidate_ids: Vec<Uuid> = transactions
+        .iter()
+        .flat_map(|transaction| transaction.candidate_invoice_ids.iter().copied())
+        .collect();
+    let invoices = candidate_invoices(&pool, user_id, &candidate_ids).await.map_err(internal_error)?;
+
+    let response = transactions
+        .into_iter()
+        .map(|transaction| BankTransactionResponse {
+            candidates: invoices
+                .iter()
+                .filter(|invoice| transaction.candidate_invoice_ids.contains(&invoice.id))
+                .cloned()
+                .collect(),
+            transaction,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+/// Loads a transfer that is still waiting to be reconciled.
+async fn require_pending(
+    pool: &PgPool,
+    user_id: Uuid,
+    transaction_id: Uuid,
+) -> Result<BankTransaction, (StatusCode, Json<Value>)> {
+    let transaction = find_bank_transaction(pool, user_id, transaction_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load bank transaction {}: {}", transaction_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load bank transaction")
+        })?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "bank transaction not found"))?;
+    if !is_pending(&transaction) {
+        return Err(already_reconciled());
+    }
+
+    Ok(transaction)
+}
+
+fn already_reconciled() -> (StatusCode, Json<Value>) {
+    error_response(StatusCode::CONFLICT, "bank transaction is already reconciled")
+}
+
+/// Match bank transaction endpoint handler.
+///
+/// Handles POST requests to `/api/payments/bank-transactions/:id/match`
+/// with `{ "invoice_id" }`. The transfer is recorded as a payment on the
+/// invoice, checked like a payment entered by hand (same currency, no more
+/// than the balance due). Only transfers in review or unmatched can be
+/// matched (409 otherwise).
+pub async fn match_bank_transaction_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(transaction_id): Path<Uuid>,
+    Json(request): Json<MatchBankTransaction>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let transaction = require_pending(&pool, user_id, transaction_id).await?;
+
+    let invoice = find_invoice(&pool, user_id, request.invoice_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load invoice {}: {}", request.invoice_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load invoice")
+        })?
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "invoice_id must reference one of your invoices"))?;
+    if !invoice.currency.eq_ignore_ascii_case(&transaction.currency) {
+        return Err(error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &format!("the transfer is in {}, the invoice in {}", transaction.currency, invoice.currency),
+        ));
+    }
+    let (transaction, payment, invoice) = settle_bank_transaction(&pool, &transaction, &invoice)
+        .await
+        .map_err(|e| {
+            error!("Failed to match bank transaction {}: {}", transaction_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to match bank transaction")
+        })?
+        .map_err(|refusal| match refusal {
+            MatchRefusal::Reconciled => already_reconciled(),
+            MatchRefusal::Invalid(message) => error_response(StatusCode::UNPROCESSABLE_ENTITY, &message),
+        })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "transaction": transaction,
+            "payment": payment,
+            "invoice": InvoiceResponse::from(invoice),
+        })),
+    ))
+}
+
+/// Dismiss bank transaction endpoint handler.
+///
+/// Handles POST requests to `/api/payments/bank-transactions/:id/dismiss`
+/// for transfers that don't pay an invoice. Only transfers in review or
+/// unmatched can be dismissed (409 otherwise).
+pub async fn dismiss_bank_transaction_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(transaction_id): Path<Uuid>,
+) -> Result<Json<BankTransaction>, (StatusCode, Json<Value>)> {
+    require_pending(&pool, user_id, transaction_id).await?;
+
+    let dismissed = dismiss_bank_transaction(&pool, user_id, transaction_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to dismiss bank transaction {}: {}", transaction_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to dismiss bank transaction")
+        })?
+        .ok_or_else(already_reconciled)?;
+
+    Ok(Json(dismissed))
+}
@@ -0,0 +1,702 @@
+//! Bank statement reconciliation.
+//!
+//! Imported statements (see [`statement`]) are matched against the user's
+//! open invoices: a transfer whose reference names one invoice number, or
+//! whose amount is the balance due of a single invoice, is recorded as a
+//! payment on it, which marks the invoice paid once its balance reaches
+//! zero. Transfers that could pay several invoices go to a review queue
+//! where the user picks the invoice or dismisses them. Every transfer is
+//! stored with a stable key, so overlapping statements can be imported
+//! again without recording a payment twice.
+
+pub mod handlers;
+pub mod statement;
+
+use std::collections::BTreeSet;
+
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::chase::holds::{place_hold, HoldReason};
+use crate::currency::Money;
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::import::ImportRowError;
+use crate::invoices::payments::{lock_invoice, refresh_amount_paid, validate_payment};
+use crate::models::bank_transaction::{BankTransaction, BankTransactionStatus, MatchedBy};
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::payment::{CreatePayment, Payment};
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+use statement::{ParsedStatement, StatementTransaction};
+
+/// Payment method of payments recorded from bank statements.
+pub const BANK_TRANSFER_METHOD: &str = "bank_transfer";
+
+/// Shortest invoice number looked for in payment references; shorter
+/// ones would match any number in the text.
+const MIN_REFERENCE_LENGTH: usize = 3;
+
+/// An invoice a transfer could pay.
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct OpenInvoice {
+    pub id: Uuid,
+    pub invoice_number: String,
+    pub client_name: String,
+    pub currency: String,
+    pub balance_due: Decimal,
+}
+
+/// What a transfer was matched to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchOutcome {
+    /// It pays this invoice
+    Matched { invoice_id: Uuid, matched_by: MatchedBy },
+
+    /// It could pay any of these invoices
+    Review { candidates: Vec<Uuid> },
+
+    /// No open invoice fits it
+    Unmatched,
+}
+
+/// Uppercase letters and digits of a text, each with whether spacing or
+/// punctuation came before it.
+fn compact(text: &str) -> Vec<(char, bool)> {
+    let mut compacted = Vec::new();
+    let mut separated = false;
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            compacted.push((c.to_ascii_uppercase(), separated));
+            separated = false;
+        } else {
+            separated = true;
+        }
+    }
+    compacted
+}
+
+/// Whether a payment reference names an invoice number.
+///
+/// Punctuation and spacing are ignored (`INV 0042` names `INV-0042`), but
+/// the number may not run on into other digits, so `INV-004` is not found
+/// in `INV-0042` while `1042` is found in `1042/1043`.
+pub fn mentions_invoice_number(text: &str, invoice_number: &str) -> bool {
+    let number: Vec<char> = compact(invoice_number).into_iter().map(|(c, _)| c).collect();
+    if number.len() < MIN_REFERENCE_LENGTH {
+        return false;
+    }
+
+    let text = compact(text);
+    let runs_on = |(c, separated): (char, bool)| c.is_ascii_digit() && !separated;
+    (0..=text.len().saturating_sub(number.len())).any(|start| {
+        let end = start + number.len();
+        end <= text.len()
+            && text[start..end].iter().map(|(c, _)| *c).eq(number.iter().copied())
+            && !(number[0].is_ascii_digit() && start > 0 && text[start - 1].0.is_ascii_digit() && !text[start].1)
+            && !(number[number.len() - 1].is_ascii_digit() && text.get(end).is_some_and(|next| runs_on(*next)))
+    })
+}
+
+/// Whether the payer's name looks like the client's.
+fn same_payer(counterparty: &str, client_name: &str) -> bool {
+    let words = |name: &str| {
+        name.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let (payer, client) = (words(counterparty), words(client_name));
+    !payer.is_empty() && !client.is_empty() && (payer.contains(&client) || client.contains(&payer))
+}
+
+/// Matches a transfer to the open invoice it pays.
+///
+/// Only invoices in the transfer's currency with a balance left count.
+/// A reference naming a single invoice matches it if the amount is no more
+/// than its balance (partial payments are fine). Otherwise a transfer of
+/// exactly one invoice's balance matches it, unless the payer is clearly
+/// someone else than that invoice's client; several invoices with that
+/// balance are told apart by the payer. Everything that still fits more
+/// than one invoice, or only by payer, goes to review.
+///
+/// # Arguments
+///
+/// * `transaction` - The incoming transfer
+/// * `invoices` - The user's open invoices, with their current balances
+///
+/// # Returns
+///
+/// Returns the matched invoice, the candidates to review, or `Unmatched`.
+pub fn match_transaction(transaction: &StatementTransaction, invoices: &[OpenInvoice]) -> MatchOutcome {
+    let amount = transaction.amount.amount;
+    let open: Vec<&OpenInvoice> = invoices
+        .iter()
+        .filter(|invoice| {
+            invoice.currency.eq_ignore_ascii_case(transaction.amount.currency.code())
+                && invoice.balance_due > Decimal::ZERO
+        })
+        .collect();
+    let ids = |invoices: &[&OpenInvoice]| invoices.iter().map(|invoice| invoice.id).collect::<Vec<_>>();
+    let payer_matches = |invoice: &OpenInvoice| {
+        transaction
+            .counterparty
+            .as_deref()
+            .is_some_and(|payer| same_payer(payer, &invoice.client_name))
+    };
+
+    let text = [&transaction.reference, &transaction.description]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let named: Vec<&OpenInvoice> = open
+        .iter()
+        .copied()
+        .filter(|invoice| mentions_invoice_number(&text, &invoice.invoice_number))
+        .collect();
+    match named.as_slice() {
+        [] => {}
+        [invoice] if amount <= invoice.balance_due => {
+            return MatchOutcome::Matched { invoice_id: invoice.id, matched_by: MatchedBy::InvoiceNumber };
+        }
+        _ => return MatchOutcome::Review { candidates: ids(&named) },
+    }
+
+    let exact: Vec<&OpenInvoice> = open.iter().copied().filter(|invoice| invoice.balance_due == amount).collect();
+    let by_payer: Vec<&OpenInvoice> = exact.iter().copied().filter(|invoice| payer_matches(invoice)).collect();
+    match (exact.as_slice(), by_payer.as_slice()) {
+        ([invoice], _) if transaction.counterparty.is_none() || payer_matches(invoice) => {
+            return MatchOutcome::Matched { invoice_id: invoice.id, matched_by: MatchedBy::Amount };
+        }
+        (_, [invoice]) => {
+            return MatchOutcome::Matched { invoice_id: invoice.id, matched_by: MatchedBy::Amount };
+        }
+        ([], _) => {}
+        _ => return MatchOutcome::Review { candidates: ids(&exact) },
+    }
+
+    let from_payer: Vec<&OpenInvoice> = open.iter().copied().filter(|invoice| payer_matches(invoice)).collect();
+    if from_payer.is_empty() {
+        MatchOutcome::Unmatched
+    } else {
+        MatchOutcome::Review { candidates: ids(&from_payer) }
+    }
+}
+
+/// Outcome of importing a statement.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatementReport {
+    /// Transfers recorded as payments
+    pub matched: Vec<BankTransaction>,
+
+    /// Transfers waiting in the review queue
+    pub review: Vec<BankTransaction>,
+
+    /// Transfers no open invoice fits
+    pub unmatched: Vec<BankTransaction>,
+
+    /// Invoices the matched transfers paid in full
+    pub paid_invoice_ids: Vec<Uuid>,
+
+    /// Transfers imported before
+    pub skipped: usize,
+
+    /// Outgoing payments, which are left out
+    pub debits: usize,
+
+    /// Rows that could not be read
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Loads the user's invoices that can still receive payments, locking them.
+async fn lock_open_invoices(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<OpenInvoice>, anyhow::Error> {
+    let invoices = sqlx::query_as::<_, OpenInvoice>(
+        r#"
+        SELECT id, invoice_number, client_name, currency, total - amount_paid AS balance_due
+        FROM invoices
+        WHERE user_id = $1
+            AND is_deleted = false
+            AND status IN ('sent', 'overdue')
+            AND total > amount_paid
+        ORDER BY id
+        FOR UPDATE
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(invoices)
+}
+
+/// Records a payment for a matched transfer and stores it for sync.
+async fn insert_payment(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    currency: &str,
+    transaction: &StatementTransaction,
+) -> Result<Payment, anyhow::Error> {
+    let reference = transaction
+        .reference
+        .as_ref()
+        .or(transaction.bank_reference.as_ref())
+        .map(|reference| reference.chars().take(255).collect::<String>());
+
+    let payment = sqlx::query_as::<_, Payment>(
+        r#"
+        INSERT INTO payments (
+            user_id, invoice_id, amount, currency, paid_on, method, reference, notes
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_id)
+    .bind(transaction.amount.amount)
+    .bind(currency)
+    .bind(transaction.booked_on)
+    .bind(BANK_TRANSFER_METHOD)
+    .bind(reference)
+    .bind(transaction.counterparty.as_ref().map(|payer| format!("Bank transfer from {}", payer)))
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_server_change(
+        &mut **tx,
+        user_id,
+        "payments",
+        payment.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&payment)?,
+    )
+    .await?;
+
+    Ok(payment)
+}
+
+/// Stores an imported transfer with its reconciliation outcome.
+async fn insert_transaction(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    transaction: &StatementTransaction,
+    outcome: &MatchOutcome,
+    payment: Option<&Payment>,
+) -> Result<BankTransaction, anyhow::Error> {
+    let (status, matched_by, candidates) = match outcome {
+        MatchOutcome::Matched { matched_by, .. } => (BankTransactionStatus::Matched, Some(*matched_by), Vec::new()),
+        MatchOutcome::Review { candidates } => (BankTransactionStatus::Review, None, candidates.clone()),
+        MatchOutcome::Unmatched => (BankTransactionStatus::Unmatched, None, Vec::new()),
+    };
+
+    let stored = sqlx::query_as::<_, BankTransaction>(
+        r#"
+        INSERT INTO bank_transactions (
+            user_id, transaction_key, booked_on, amount, currency, counterparty,
+            reference, description, bank_reference, status, matched_by,
+            candidate_invoice_ids, invoice_id, payment_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(&transaction.key)
+    .bind(transaction.booked_on)
+    .bind(transaction.amount.amount)
+    .bind(transaction.amount.currency.code())
+    .bind(&transaction.counterparty)
+    .bind(&transaction.reference)
+    .bind(&transaction.description)
+    .bind(&transaction.bank_reference)
+    .bind(status)
+    .bind(matched_by)
+    .bind(candidates)
+    .bind(payment.map(|payment| payment.invoice_id))
+    .bind(payment.map(|payment| payment.id))
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(stored)
+}
+
+/// Imports a parsed statement and reconciles its transfers.
+///
+/// Runs in one transaction under a per-user lock with the open invoices
+/// locked, so concurrent imports can't pay an invoice twice. Transfers are
+/// matched in statement order against the invoices' running balances (see
+/// [`match_transaction`]); matched transfers are recorded as bank transfer
+/// payments and the amount paid of every paid invoice is recomputed, which
+/// marks fully paid invoices paid and stops chasing them. The candidates of
+/// transfers left for review get a grace hold (see [`crate::chase::holds`]).
+/// Transfers whose key is already stored are skipped.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the importing user
+/// * `parsed` - Transfers from [`statement::parse_statement`], with their errors
+///
+/// # Returns
+///
+/// Returns the matched, review and unmatched transfers and the invoices
+/// that were paid in full.
+pub async fn import_statement(
+    pool: &PgPool,
+    user_id: Uuid,
+    parsed: ParsedStatement,
+) -> Result<StatementReport, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(format!("bank_statement:{}", user_id))
+        .execute(&mut *tx)
+        .await?;
+
+    let keys: Vec<&str> = parsed.transactions.iter().map(|transaction| transaction.key.as_str()).collect();
+    let imported: BTreeSet<String> = sqlx::query_scalar::<_, String>(
+        "SELECT transaction_key FROM bank_transactions WHERE user_id = $1 AND transaction_key = ANY($2)",
+    )
+    .bind(user_id)
+    .bind(&keys)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+    let mut invoices = lock_open_invoices(&mut tx, user_id).await?;
+
+    let mut report = StatementReport {
+        debits: parsed.debits,
+        errors: parsed.errors,
+        ..StatementReport::default()
+    };
+    let mut paid_invoices = BTreeSet::new();
+    for transaction in parsed.transactions {
+        if imported.contains(&transaction.key) {
+            report.skipped += 1;
+            continue;
+        }
+
+        let outcome = match_transaction(&transaction, &invoices);
+        let payment = match &outcome {
+            MatchOutcome::Matched { invoice_id, .. } => {
+                let invoice = invoices
+                    .iter_mut()
+                    .find(|invoice| invoice.id == *invoice_id)
+                    .expect("matched invoices are open");
+                invoice.balance_due -= transaction.amount.amount;
+                paid_invoices.insert(*invoice_id);
+                Some(insert_payment(&mut tx, user_id, *invoice_id, &invoice.currency, &transaction).await?)
+            }
+            _ => None,
+        };
+
+        let stored = insert_transaction(&mut tx, user_id, &transaction, &outcome, payment.as_ref()).await?;
+        // Don't chase invoices this transfer may already have paid
+        if let MatchOutcome::Review { candidates } = &outcome {
+            place_hold(&mut *tx, user_id, candidates, HoldReason::BankMatchReview, Utc::now()).await?;
+        }
+        match stored.status {
+            BankTransactionStatus::Matched => report.matched.push(stored),
+            BankTransactionStatus::Review => report.review.push(stored),
+            _ => report.unmatched.push(stored),
+        }
+    }
+
+    for invoice_id in paid_invoices {
+        let invoice = refresh_amount_paid(&mut tx, invoice_id).await?;
+        if invoice.status == InvoiceStatus::Paid {
+            report.paid_invoice_ids.push(invoice.id);
+        }
+    }
+    report.errors.sort_by_key(|error| error.line);
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+/// Lists imported transfers with a status, newest first.
+pub async fn list_bank_transactions(
+    pool: &PgPool,
+    user_id: Uuid,
+    status: BankTransactionStatus,
+) -> Result<Vec<BankTransaction>, anyhow::Error> {
+    let transactions = sqlx::query_as::<_, BankTransaction>(
+        r#"
+        SELECT * FROM bank_transactions
+        WHERE user_id = $1 AND status = $2
+        ORDER BY booked_on DESC, created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(transactions)
+}
+
+/// Loads the open invoices among the given IDs, for showing review candidates.
+pub async fn candidate_invoices(
+    pool: &PgPool,
+    user_id: Uuid,
+    invoice_ids: &[Uuid],
+) -> Result<Vec<OpenInvoice>, anyhow::Error> {
+    let invoices = sqlx::query_as::<_, OpenInvoice>(
+        r#"
+        SELECT id, invoice_number, client_name, currency, total - amount_paid AS balance_due
+        FROM invoices
+        WHERE user_id = $1 AND id = ANY($2) AND is_deleted = false
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(invoices)
+}
+
+/// Loads one of the user's imported transfers.
+pub async fn find_bank_transaction(
+    pool: &PgPool,
+    user_id: Uuid,
+    transaction_id: Uuid,
+) -> Result<Option<BankTransaction>, anyhow::Error> {
+    let transaction = sqlx::query_as::<_, BankTransaction>(
+        "SELECT * FROM bank_transactions WHERE id = $1 AND user_id = $2",
+    )
+    .bind(transaction_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(transaction)
+}
+
+/// Whether a transfer is still waiting to be reconciled.
+pub fn is_pending(transaction: &BankTransaction) -> bool {
+    matches!(
+        transaction.status,
+        BankTransactionStatus::Review | BankTransactionStatus::Unmatched
+    )
+}
+
+/// Why a transfer from the review queue couldn't be matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchRefusal {
+    /// The transfer was reconciled meanwhile
+    Reconciled,
+
+    /// The transfer doesn't fit the invoice as a payment; a user-facing
+    /// message
+    Invalid(String),
+}
+
+/// The payment a transfer makes.
+pub fn transfer_payment(transaction: &BankTransaction) -> CreatePayment {
+    CreatePayment {
+        amount: transaction.amount,
+        paid_on: Some(transaction.booked_on),
+        method: Some(BANK_TRANSFER_METHOD.to_string()),
+        reference: transaction.reference.clone(),
+        notes: None,
+    }
+}
+
+/// Records a transfer from the review queue as a payment on an invoice.
+///
+/// The payment is checked like one entered by hand (see
+/// `invoices::payments::validate_payment`) against the invoice as it is
+/// under the row lock, and dated on the booking date.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `transaction` - The pending transfer
+/// * `invoice` - The invoice it pays
+///
+/// # Returns
+///
+/// Returns the matched transfer, its payment and the updated invoice, or
+/// why the transfer couldn't be matched.
+pub async fn settle_bank_transaction(
+    pool: &PgPool,
+    transaction: &BankTransaction,
+    invoice: &Invoice,
+) -> Result<Result<(BankTransaction, Payment, Invoice), MatchRefusal>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let pending = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT id FROM bank_transactions
+        WHERE id = $1 AND user_id = $2 AND status IN ('review', 'unmatched')
+        FOR UPDATE
+        "#,
+    )
+    .bind(transaction.id)
+    .bind(transaction.user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if pending.is_none() {
+        return Ok(Err(MatchRefusal::Reconciled));
+    }
+    let Some(locked) = lock_invoice(&mut tx, invoice.id).await? else {
+        return Ok(Err(MatchRefusal::Invalid("invoice_id must reference one of your invoices".to_string())));
+    };
+    if let Err(message) = validate_payment(&locked, &transfer_payment(transaction)) {
+        return Ok(Err(MatchRefusal::Invalid(message)));
+    }
+
+    let statement_transaction = StatementTransaction {
+        line: 0,
+        booked_on: transaction.booked_on,
+        amount: Money::parse(transaction.amount, &transaction.currency)?,
+        counterparty: transaction.counterparty.clone(),
+        reference: transaction.reference.clone(),
+        description: transaction.description.clone(),
+        bank_reference: transaction.bank_reference.clone(),
+        key: transaction.transaction_key.clone(),
+    };
+    let payment = insert_payment(&mut tx, transaction.user_id, invoice.id, &invoice.currency, &statement_transaction).await?;
+
+    let matched = sqlx::query_as::<_, BankTransaction>(
+        r#"
+        UPDATE bank_transactions
+        SET status = 'matched', matched_by = 'manual', invoice_id = $2, payment_id = $3
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(transaction.id)
+    .bind(invoice.id)
+    .bind(payment.id)
+    .fetch_one(&mut *tx)
+    .await?;
+    let updated = refresh_amount_paid(&mut tx, invoice.id).await?;
+
+    tx.commit().await?;
+
+    Ok(Ok((matched, payment, updated)))
+}
+
+/// Dismisses a pending transfer from the review queue.
+///
+/// # Returns
+///
+/// Returns the dismissed transfer, or `None` if it is not pending.
+pub async fn dismiss_bank_transaction(
+    pool: &PgPool,
+    user_id: Uuid,
+    transaction_id: Uuid,
+) -> Result<Option<BankTransaction>, anyhow::Error> {
+    let dismissed = sqlx::query_as::<_, BankTransaction>(
+        r#"
+        UPDATE bank_transactions SET status = 'dismissed'
+        WHERE id = $1 AND user_id = $2 AND status IN ('review', 'unmatched')
+        RETURNING *
+        "#,
+    )
+    .bind(transaction_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(dismissed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    use crate::currency::Currency;
+
+    fn invoice(number: &str, client: &str, balance: i64) -> OpenInvoice {
+        OpenInvoice {
+            id: Uuid::new_v4(),
+            invoice_number: number.to_string(),
+            client_name: client.to_string(),
+            currency: "EUR".to_string(),
+            balance_due: Decimal::from(balance),
+        }
+    }
+
+    fn transfer(amount: i64, payer: Option<&str>, reference: Option<&str>) -> StatementTransaction {
+        StatementTransaction {
+            line: 2,
+            booked_on: NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(),
+            amount: Money::new(Decimal::from(amount), Currency::parse("EUR").unwrap()),
+            counterparty: payer.map(str::to_string),
+            reference: reference.map(str::to_string),
+            description: None,
+            bank_reference: None,
+            key: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_mentions_invoice_number() {
+        assert!(mentions_invoice_number("Payment for inv 0042, thanks", "INV-0042"));
+        assert!(mentions_invoice_number("RE 1042/1043", "1042"));
+        assert!(!mentions_invoice_number("INV-00421", "INV-0042"));
+        assert!(!mentions_invoice_number("INV-0042", "INV-004"));
+        assert!(!mentions_invoice_number("10420", "1042"));
+        assert!(!mentions_invoice_number("Invoice 7", "7"));
+    }
+
+    #[test]
+    fn test_match_by_reference() {
+        let invoices = vec![invoice("INV-0042", "Acme GmbH", 500), invoice("INV-0043", "Beta Ltd", 500)];
+
+        // A partial payment naming one invoice matches it
+        let outcome = match_transaction(&transfer(200, None, Some("Rechnung INV-0042")), &invoices);
+        assert_eq!(
+            outcome,
+            MatchOutcome::Matched { invoice_id: invoices[0].id, matched_by: MatchedBy::InvoiceNumber }
+        );
+
+        // Overpaying or naming several invoices needs review
+        let outcome = match_transaction(&transfer(600, None, Some("INV-0042")), &invoices);
+        assert_eq!(outcome, MatchOutcome::Review { candidates: vec![invoices[0].id] });
+        let outcome = match_transaction(&transfer(1000, None, Some("INV-0042 INV-0043")), &invoices);
+        assert_eq!(outcome, MatchOutcome::Review { candidates: vec![invoices[0].id, invoices[1].id] });
+    }
+
+    #[test]
+    fn test_match_by_amount_and_payer() {
+        let invoices = vec![
+            invoice("INV-0042", "Acme GmbH", 500),
+            invoice("INV-0043", "Beta Ltd", 500),
+            invoice("INV-0044", "Gamma", 250),
+        ];
+
+        let matched = |invoice: &OpenInvoice| MatchOutcome::Matched { invoice_id: invoice.id, matched_by: MatchedBy::Amount };
+        assert_eq!(match_transaction(&transfer(250, None, None), &invoices), matched(&invoices[2]));
+        assert_eq!(match_transaction(&transfer(500, Some("BETA LTD."), None), &invoices), matched(&invoices[1]));
+        assert_eq!(
+            match_transaction(&transfer(500, None, None), &invoices),
+            MatchOutcome::Review { candidates: vec![invoices[0].id, invoices[1].id] }
+        );
+
+        // A unique amount from someone else, or a payer without a matching amount, is reviewed
+        assert_eq!(
+            match_transaction(&transfer(250, Some("Delta AG"), None), &invoices),
+            MatchOutcome::Review { candidates: vec![invoices[2].id] }
+        );
+        assert_eq!(
+            match_transaction(&transfer(120, Some("Acme GmbH"), None), &invoices),
+            MatchOutcome::Review { candidates: vec![invoices[0].id] }
+        );
+        assert_eq!(match_transaction(&transfer(120, Some("Delta AG"), None), &invoices), MatchOutcome::Unmatched);
+    }
+}
@@ -0,0 +1,646 @@
+//! Reading bank statements.
+//!
+//! Statements arrive as a CSV export from online banking or as an ISO
+//! 20022 CAMT.053 file. Both are read into the same transactions; only
+//! incoming transfers (credits) are kept, since only they can pay an
+//! invoice.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use csv::StringRecord;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::currency::{Currency, Money};
+use crate::imports::sources::clean_amount;
+use crate::invoices::import::{parse_import_date, stable_hash, ImportRowError, MAX_IMPORT_ROWS};
+use crate::locale::{parse_decimal, Locale};
+
+/// Longest counterparty name or bank reference kept.
+const MAX_NAME_LENGTH: usize = 255;
+
+/// File format of a bank statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementFormat {
+    /// CSV export with one transaction per row
+    Csv,
+
+    /// ISO 20022 bank-to-customer statement (XML)
+    Camt053,
+}
+
+impl StatementFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatementFormat::Csv => "csv",
+            StatementFormat::Camt053 => "camt053",
+        }
+    }
+
+    /// Guesses the format from the file contents: XML is CAMT.053.
+    pub fn detect(data: &[u8]) -> StatementFormat {
+        let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+        match data.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'<') => StatementFormat::Camt053,
+            _ => StatementFormat::Csv,
+        }
+    }
+}
+
+impl fmt::Display for StatementFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace(['.', '_', '-'], "").as_str() {
+            "csv" => Ok(StatementFormat::Csv),
+            "camt053" | "camt" => Ok(StatementFormat::Camt053),
+            other => Err(format!("unknown statement format: {}", other)),
+        }
+    }
+}
+
+/// How to read a statement.
+#[derive(Debug, Clone, Default)]
+pub struct StatementOptions {
+    /// Header overrides for CSV statements, e.g. `{"reference": "Purpose"}`
+    pub mapping: HashMap<String, String>,
+
+    /// `chrono` format of CSV dates (read tolerantly without one)
+    pub date_format: Option<String>,
+
+    /// How CSV numbers and dates are written, e.g. `de-DE`
+    pub locale: Option<Locale>,
+}
+
+/// An incoming transfer read from a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementTransaction {
+    /// CSV line, or position of the entry in a CAMT.053 file
+    pub line: u64,
+
+    /// Date the bank booked the transfer
+    pub booked_on: NaiveDate,
+
+    /// Amount received
+    pub amount: Money,
+
+    /// Name of the payer
+    pub counterparty: Option<String>,
+
+    /// Payment reference given by the payer
+    pub reference: Option<String>,
+
+    /// Other text the bank shows for the transfer
+    pub description: Option<String>,
+
+    /// The bank's own ID for the transfer
+    pub bank_reference: Option<String>,
+
+    /// Stable key of the transfer (see [`assign_keys`])
+    pub key: String,
+}
+
+/// The transfers of a statement and the problems found in other rows.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedStatement {
+    pub transactions: Vec<StatementTransaction>,
+
+    /// Outgoing payments, which are left out
+    pub debits: usize,
+
+    pub errors: Vec<ImportRowError>,
+}
+
+fn file_error(error: impl Into<String>) -> ImportRowError {
+    ImportRowError { line: None, field: None, error: error.into() }
+}
+
+fn row_error(line: u64, field: &str, error: impl Into<String>) -> ImportRowError {
+    ImportRowError {
+        line: Some(line),
+        field: Some(field.to_string()),
+        error: error.into(),
+    }
+}
+
+/// Trims a text value, dropping it when empty and cutting it to `max` characters.
+fn text(value: &str, max: usize) -> Option<String> {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!value.is_empty()).then(|| value.chars().take(max).collect())
+}
+
+/// Gives every transfer a key that is stable across imports.
+///
+/// Transfers with a bank reference are keyed by it. Others are keyed by
+/// their values and how often the same values appeared before in the
+/// statement, so two identical transfers on one day stay apart while the
+/// same statement imported twice gives the same keys.
+pub fn assign_keys(transactions: &mut [StatementTransaction]) {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    for transaction in transactions {
+        let mut values = vec![
+            transaction.booked_on.to_string(),
+            transaction.amount.amount.normalize().to_string(),
+            transaction.amount.currency.to_string(),
+        ];
+        let prefix = match &transaction.bank_reference {
+            Some(bank_reference) => {
+                values.push(bank_reference.clone());
+                "bank"
+            }
+            None => {
+                values.extend(
+                    [&transaction.counterparty, &transaction.reference, &transaction.description]
+                        .map(|value| value.clone().unwrap_or_default()),
+                );
+                "stmt"
+            }
+        };
+
+        let hash = stable_hash(&values);
+        let occurrence = seen.entry(hash).or_default();
+        *occurrence += 1;
+        transaction.key = format!("{}:{:016x}:{}", prefix, hash, occurrence);
+    }
+}
+
+/// Reads a bank statement.
+///
+/// # Arguments
+///
+/// * `data` - The file contents
+/// * `format` - The file format
+/// * `options` - Column mapping, date format and locale for CSV files
+/// * `default_currency` - Currency of CSV rows without a currency column
+///
+/// # Returns
+///
+/// Returns the incoming transfers, the number of outgoing ones, and every
+/// problem found in the other rows.
+///
+/// # Errors
+///
+/// Returns an error if the file as a whole can't be read, or has more
+/// than [`MAX_IMPORT_ROWS`] transactions.
+pub fn parse_statement(
+    data: &[u8],
+    format: StatementFormat,
+    options: &StatementOptions,
+    default_currency: Currency,
+) -> Result<ParsedStatement, ImportRowError> {
+    let mut parsed = match format {
+        StatementFormat::Csv => parse_csv_statement(data, options, default_currency)?,
+        StatementFormat::Camt053 => parse_camt053(data)?,
+    };
+    assign_keys(&mut parsed.transactions);
+
+    Ok(parsed)
+}
+
+/// Headers each CSV field may appear under, most likely first.
+const CSV_COLUMNS: &[(&str, &[&str])] = &[
+    ("booked_on", &["Booking Date", "Booked On", "Date", "Transaction Date", "Posting Date", "Buchungstag", "Datum", "Value Date"]),
+    ("amount", &["Amount", "Betrag", "Montant", "Importe"]),
+    ("credit", &["Credit", "Paid In", "Money In", "Deposit", "Haben"]),
+    ("debit", &["Debit", "Paid Out", "Money Out", "Withdrawal", "Soll"]),
+    ("currency", &["Currency", "Währung", "Devise"]),
+    ("counterparty", &["Counterparty", "Name", "Payer", "Payee", "Beneficiary", "Auftraggeber", "Sender"]),
+    ("reference", &["Reference", "Payment Reference", "Remittance Information", "Verwendungszweck", "Memo"]),
+    ("description", &["Description", "Details", "Narrative", "Transaction Details", "Buchungstext"]),
+    ("bank_reference", &["Transaction ID", "Bank Reference", "ID"]),
+];
+
+/// Finds the column of each field, applying the user's overrides.
+fn csv_columns(headers: &StringRecord, mapping: &HashMap<String, String>) -> Result<HashMap<&'static str, usize>, String> {
+    let position = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+    };
+
+    if let Some(field) = mapping.keys().find(|field| !CSV_COLUMNS.iter().any(|(name, _)| name == field)) {
+        return Err(format!("unknown statement field: {}", field));
+    }
+    let mut columns = HashMap::new();
+    for (field, candidates) in CSV_COLUMNS {
+        let index = match mapping.get(*field) {
+            Some(header) => Some(position(header).ok_or_else(|| format!("column not found: {}", header))?),
+            None => candidates.iter().find_map(|candidate| position(candidate)),
+        };
+        if let Some(index) = index {
+            columns.insert(*field, index);
+        }
+    }
+
+    if !columns.contains_key("booked_on") {
+        return Err("missing column for booked_on".to_string());
+    }
+    if !columns.contains_key("amount") && !columns.contains_key("credit") {
+        return Err("missing column for amount (or credit)".to_string());
+    }
+    Ok(columns)
+}
+
+/// Reads a CSV statement, separated by commas or semicolons.
+fn parse_csv_statement(
+    data: &[u8],
+    options: &StatementOptions,
+    default_currency: Currency,
+) -> Result<ParsedStatement, ImportRowError> {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let header_line = data.split(|byte| *byte == b'\n').next().unwrap_or_default();
+    let semicolons = header_line.iter().filter(|byte| **byte == b';').count();
+    let commas = header_line.iter().filter(|byte| **byte == b',').count();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(if semicolons > commas { b';' } else { b',' })
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let headers = reader
+        .headers()
+        .map_err(|e| file_error(format!("cannot read header row: {}", e)))?
+        .clone();
+    if headers.iter().all(|header| header.is_empty()) {
+        return Err(file_error("the file has no header row"));
+    }
+    let columns = csv_columns(&headers, &options.mapping).map_err(file_error)?;
+
+    let date_format = options.date_format.as_deref();
+    let locale = options.locale.as_ref();
+    let mut parsed = ParsedStatement::default();
+    for (index, record) in reader.records().enumerate() {
+        if index >= MAX_IMPORT_ROWS {
+            return Err(file_error(format!("a statement may have at most {} rows", MAX_IMPORT_ROWS)));
+        }
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line());
+                parsed.errors.push(ImportRowError { line: Some(line), field: None, error: e.to_string() });
+                continue;
+            }
+        };
+        if record.iter().all(|value| value.is_empty()) {
+            continue;
+        }
+
+        let line = record.position().map_or(0, |position| position.line());
+        let get = |field: &str| {
+            columns
+                .get(field)
+                .and_then(|index| record.get(*index))
+                .filter(|value| !value.is_empty())
+        };
+        let amount = |field: &str| {
+            get(field)
+                .map(|value| parse_decimal(&clean_amount(value), locale).map_err(|message| row_error(line, field, message)))
+                .transpose()
+        };
+
+        // A signed amount column, or separate (unsigned) credit and debit columns
+        let signed = match (amount("amount"), amount("credit"), amount("debit")) {
+            (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => {
+                parsed.errors.push(error);
+                continue;
+            }
+            (Ok(Some(amount)), _, _) => amount,
+            (Ok(None), Ok(Some(credit)), _) if !credit.is_zero() => credit.abs(),
+            (Ok(None), Ok(_), Ok(Some(debit))) => -debit.abs(),
+            (Ok(None), Ok(Some(_)), Ok(None)) => Decimal::ZERO,
+            (Ok(None), Ok(None), Ok(None)) => {
+                parsed.errors.push(row_error(line, "amount", "amount is required"));
+                continue;
+            }
+        };
+        if signed <= Decimal::ZERO {
+            parsed.debits += 1;
+            continue;
+        }
+
+        let booked_on = match get("booked_on").map(|value| parse_import_date(value, date_format, locale)) {
+            Some(Ok(date)) => date,
+            Some(Err(message)) => {
+                parsed.errors.push(row_error(line, "booked_on", message));
+                continue;
+            }
+            None => {
+                parsed.errors.push(row_error(line, "booked_on", "booked_on is required"));
+                continue;
+            }
+        };
+        let currency = match get("currency").map(Currency::parse) {
+            Some(Ok(currency)) => currency,
+            Some(Err(e)) => {
+                parsed.errors.push(row_error(line, "currency", e.to_string()));
+                continue;
+            }
+            None => default_currency,
+        };
+        let amount = Money::new(signed, currency);
+        if !amount.is_representable() {
+            parsed.errors.push(row_error(
+                line,
+                "amount",
+                format!("must have at most {} decimal places", currency.minor_units()),
+            ));
+            continue;
+        }
+
+        parsed.transactions.push(StatementTransaction {
+            line,
+            booked_on,
+            amount,
+            counterparty: get("counterparty").and_then(|value| text(value, MAX_NAME_LENGTH)),
+            reference: get("reference").and_then(|value| text(value, usize::MAX)),
+            description: get("description").and_then(|value| text(value, usize::MAX)),
+            bank_reference: get("bank_reference").and_then(|value| text(value, MAX_NAME_LENGTH)),
+            key: String::new(),
+        });
+    }
+
+    Ok(parsed)
+}
+
+/// The first child element with the given local name.
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Child elements with the given local name.
+fn children<'a, 'input: 'a>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// The element at a path of local names below `node`.
+fn at<'a, 'input>(node: roxmltree::Node<'a, 'input>, path: &[&str]) -> Option<roxmltree::Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, name| child(node, name))
+}
+
+/// The trimmed text of the element at a path, if not empty.
+fn text_at(node: roxmltree::Node, path: &[&str]) -> Option<String> {
+    at(node, path).and_then(|node| node.text()).and_then(|value| text(value, usize::MAX))
+}
+
+/// The date of a `Dt` or `DtTm` element below `node`.
+fn date_at(node: roxmltree::Node, path: &[&str]) -> Option<NaiveDate> {
+    let date = at(node, path)?;
+    let value = text_at(date, &["Dt"]).or_else(|| text_at(date, &["DtTm"]))?;
+    NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// An `Amt` element's amount and currency.
+fn camt_amount(amount: roxmltree::Node) -> Result<Money, String> {
+    let value = amount.text().unwrap_or_default().trim();
+    let currency = Currency::parse(amount.attribute("Ccy").unwrap_or_default()).map_err(|e| e.to_string())?;
+    let amount = Decimal::from_str_exact(value).map_err(|_| format!("not a number: {}", value))?;
+    Ok(Money::new(amount, currency))
+}
+
+/// Reads a CAMT.053 statement.
+///
+/// Every booked credit entry becomes a transfer; batch entries with
+/// several transaction details become one transfer each. The payer comes
+/// from the debtor, the reference from the remittance information.
+fn parse_camt053(data: &[u8]) -> Result<ParsedStatement, ImportRowError> {
+    let xml = std::str::from_utf8(data).map_err(|_| file_error("the statement is not UTF-8"))?;
+    let document = roxmltree::Document::parse(xml).map_err(|e| file_error(format!("cannot read XML: {}", e)))?;
+    let statements = child(document.root_element(), "BkToCstmrStmt")
+        .ok_or_else(|| file_error("not a CAMT.053 statement (missing BkToCstmrStmt)"))?;
+
+    let mut parsed = ParsedStatement::default();
+    let mut line = 0;
+    for entry in children(statements, "Stmt").flat_map(|statement| children(statement, "Ntry")) {
+        line += 1;
+        if line as usize > MAX_IMPORT_ROWS {
+            return Err(file_error(format!("a statement may have at most {} entries", MAX_IMPORT_ROWS)));
+        }
+
+        // Pending entries and reversals are not money received
+        let status = text_at(entry, &["Sts", "Cd"]).or_else(|| text_at(entry, &["Sts"]));
+        let reversal = text_at(entry, &["RvslInd"]).is_some_and(|value| value == "true");
+        if text_at(entry, &["CdtDbtInd"]).as_deref() != Some("CRDT")
+            || reversal
+            || status.is_some_and(|status| status != "BOOK")
+        {
+            parsed.debits += 1;
+            continue;
+        }
+
+        let Some(booked_on) = date_at(entry, &["BookgDt"]).or_else(|| date_at(entry, &["ValDt"])) else {
+            parsed.errors.push(row_error(line, "booked_on", "entry has no booking date"));
+            continue;
+        };
+        let entry_amount = match child(entry, "Amt").map(camt_amount) {
+            Some(Ok(amount)) => amount,
+            Some(Err(message)) => {
+                parsed.errors.push(row_error(line, "amount", message));
+                continue;
+            }
+            None => {
+                parsed.errors.push(row_error(line, "amount", "entry has no amount"));
+                continue;
+            }
+        };
+        let entry_reference = text_at(entry, &["AcctSvcrRef"]);
+        let entry_description = text_at(entry, &["AddtlNtryInf"]);
+
+        let details: Vec<_> = child(entry, "NtryDtls")
+            .map(|details| children(details, "TxDtls").collect())
+            .unwrap_or_default();
+        let batch = details.len() > 1;
+        for detail in details.iter().copied().map(Some).chain((details.is_empty()).then_some(None)) {
+            let amount = match detail.filter(|_| batch).and_then(|detail| at(detail, &["AmtDtls", "TxAmt", "Amt"])) {
+                Some(amount) => match camt_amount(amount) {
+                    Ok(amount) => amount,
+                    Err(message) => {
+                        parsed.errors.push(row_error(line, "amount", message));
+                        continue;
+                    }
+                },
+                None => entry_amount,
+            };
+            if !amount.is_positive() || !amount.is_representable() {
+                parsed.errors.push(row_error(line, "amount", format!("not a valid amount received: {}", amount)));
+                continue;
+            }
+
+            let reference = detail.and_then(|detail| {
+                let remittance = child(detail, "RmtInf")?;
+                let lines: Vec<String> = children(remittance, "Ustrd")
+                    .filter_map(|line| line.text().and_then(|value| text(value, usize::MAX)))
+                    .chain(children(remittance, "Strd").filter_map(|structured| {
+                        text_at(structured, &["CdtrRefInf", "Ref"])
+                    }))
+                    .collect();
+                (!lines.is_empty()).then(|| lines.join(" "))
+            });
+            let counterparty = detail
+                .and_then(|detail| at(detail, &["RltdPties", "Dbtr"]))
+                .and_then(|debtor| debtor.descendants().find(|node| node.tag_name().name() == "Nm"))
+                .and_then(|name| name.text())
+                .and_then(|name| text(name, MAX_NAME_LENGTH));
+            let bank_reference = detail
+                .and_then(|detail| {
+                    text_at(detail, &["Refs", "AcctSvcrRef"]).or_else(|| {
+                        text_at(detail, &["Refs", "EndToEndId"]).filter(|id| id != "NOTPROVIDED")
+                    })
+                })
+                .or_else(|| entry_reference.clone().filter(|_| !batch))
+                .and_then(|reference| text(&reference, MAX_NAME_LENGTH));
+
+            parsed.transactions.push(StatementTransaction {
+                line,
+                booked_on,
+                amount,
+                counterparty,
+                reference,
+                description: detail
+                    .and_then(|detail| text_at(detail, &["AddtlTxInf"]))
+                    .or_else(|| entry_description.clone()),
+                bank_reference,
+                key: String::new(),
+            });
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eur() -> Currency {
+        Currency::parse("EUR").unwrap()
+    }
+
+    #[test]
+    fn test_csv_statement_with_locale() {
+        let csv = "Buchungstag;Auftraggeber;Verwendungszweck;Betrag;Währung\n\
+                   05.03.2024;ACME GmbH;Rechnung INV-0042;1.234,50;EUR\n\
+                   06.03.2024;Office Rent;March;-800,00;EUR\n\
+                   07.03.2024;Beta Ltd;INV-0043;12,5,0;EUR\n\
+                   07.03.2024;ACME GmbH;Rechnung INV-0042;1.234,50;EUR\n";
+        let options = StatementOptions {
+            locale: Some(Locale::parse("de-DE").unwrap()),
+            ..StatementOptions::default()
+        };
+        let parsed = parse_statement(csv.as_bytes(), StatementFormat::Csv, &options, eur()).unwrap();
+
+        assert_eq!(parsed.debits, 1);
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line, Some(4));
+        assert_eq!(parsed.transactions.len(), 2);
+
+        let acme = &parsed.transactions[0];
+        assert_eq!(acme.booked_on, NaiveDate::from_ymd_opt(2024, 3, 5).unwrap());
+        assert_eq!(acme.amount, Money::new(Decimal::new(123450, 2), eur()));
+        assert_eq!(acme.counterparty.as_deref(), Some("ACME GmbH"));
+        assert_eq!(acme.reference.as_deref(), Some("Rechnung INV-0042"));
+        assert_ne!(acme.key, parsed.transactions[1].key);
+    }
+
+    #[test]
+    fn test_csv_statement_keys_are_stable() {
+        let csv = "Date,Description,Credit,Debit\n\
+                   2024-03-05,Transfer,100.00,\n\
+                   2024-03-05,Transfer,100.00,\n\
+                   2024-03-06,Card,,25.00\n";
+        let parse = || parse_statement(csv.as_bytes(), StatementFormat::Csv, &StatementOptions::default(), eur()).unwrap();
+        let (first, second) = (parse(), parse());
+
+        assert_eq!(first.debits, 1);
+        assert_eq!(first.transactions.len(), 2);
+        assert_ne!(first.transactions[0].key, first.transactions[1].key);
+        assert_eq!(
+            first.transactions.iter().map(|t| &t.key).collect::<Vec<_>>(),
+            second.transactions.iter().map(|t| &t.key).collect::<Vec<_>>()
+        );
+
+        let error = parse_statement(b"Reference,Amount\nx,1\n", StatementFormat::Csv, &StatementOptions::default(), eur())
+            .unwrap_err();
+        assert_eq!(error.error, "missing column for booked_on");
+    }
+
+    #[test]
+    fn test_camt053_statement() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Ntry>
+        <Amt Ccy="EUR">250.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-03-05</Dt></BookgDt>
+        <AcctSvcrRef>BANK-1</AcctSvcrRef>
+        <NtryDtls><TxDtls>
+          <RltdPties><Dbtr><Nm>ACME GmbH</Nm></Dbtr></RltdPties>
+          <RmtInf><Ustrd>INV-0042</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">80.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-03-06</Dt></BookgDt>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">300.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><DtTm>2024-03-07T10:00:00</DtTm></BookgDt>
+        <NtryDtls>
+          <TxDtls>
+            <Refs><EndToEndId>E2E-1</EndToEndId></Refs>
+            <AmtDtls><TxAmt><Amt Ccy="EUR">100.00</Amt></TxAmt></AmtDtls>
+            <RltdPties><Dbtr><Pty><Nm>Beta Ltd</Nm></Pty></Dbtr></RltdPties>
+          </TxDtls>
+          <TxDtls>
+            <Refs><EndToEndId>E2E-2</EndToEndId></Refs>
+            <AmtDtls><TxAmt><Amt Ccy="EUR">200.00</Amt></TxAmt></AmtDtls>
+            <RmtInf><Strd><CdtrRefInf><Ref>RF18 INV-0050</Ref></CdtrRefInf></Strd></RmtInf>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+        assert_eq!(StatementFormat::detect(xml.as_bytes()), StatementFormat::Camt053);
+        let parsed = parse_statement(xml.as_bytes(), StatementFormat::Camt053, &StatementOptions::default(), eur())
+            .unwrap();
+
+        assert_eq!(parsed.debits, 1);
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.transactions.len(), 3);
+
+        let first = &parsed.transactions[0];
+        assert_eq!(first.amount, Money::new(Decimal::new(25000, 2), eur()));
+        assert_eq!(first.counterparty.as_deref(), Some("ACME GmbH"));
+        assert_eq!(first.reference.as_deref(), Some("INV-0042"));
+        assert_eq!(first.bank_reference.as_deref(), Some("BANK-1"));
+
+        let batch = &parsed.transactions[1..];
+        assert_eq!(batch[0].amount.amount, Decimal::new(10000, 2));
+        assert_eq!(batch[0].counterparty.as_deref(), Some("Beta Ltd"));
+        assert_eq!(batch[0].booked_on, NaiveDate::from_ymd_opt(2024, 3, 7).unwrap());
+        assert_eq!(batch[1].reference.as_deref(), Some("RF18 INV-0050"));
+        assert_eq!(batch[1].bank_reference.as_deref(), Some("E2E-2"));
+
+        assert!(parse_statement(b"<Invoice/>", StatementFormat::Camt053, &StatementOptions::default(), eur()).is_err());
+    }
+}
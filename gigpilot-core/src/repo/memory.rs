@@ -0,0 +1,753 @@
+//! In-memory repository for unit tests.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::business_days::HolidayCalendar;
+use crate::chase::retries::{chase_retry, is_dead_lettered};
+use crate::invoices::late_fees::{has_late_fee, with_late_fee};
+use crate::invoices::pdf::PdfBranding;
+use crate::models::attachment::Attachment;
+use crate::models::client_stats::ClientStats;
+use crate::models::contract::{Contract, ContractStatus};
+use crate::models::chase_override::ChaseOverride;
+use crate::models::correspondence::{Correspondence, CorrespondenceKind, CreateCorrespondence};
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::job::{CreateJob, Job, JobStatus};
+use crate::models::notification::{CreateNotification, Notification};
+use crate::models::payment_method::ClientPaymentMethod;
+use crate::models::sandboxed_email::{CreateSandboxedEmail, SandboxedEmail};
+use crate::models::sync_change::{SyncChange, SyncOperation};
+use crate::models::user_settings::UserSettings;
+use crate::payment_methods::instructions;
+use crate::repo::{
+    ClientRepository, InvoiceRepository, JobRepository, NotificationRepository, SettingsRepository, SyncRepository,
+};
+use crate::sync::server::SERVER_DEVICE_ID;
+use crate::worker::priority::{priority, round_robin};
+
+#[derive(Default)]
+struct MemoryState {
+    invoices: HashMap<Uuid, Invoice>,
+    attachments: Vec<Attachment>,
+    correspondence: Vec<Correspondence>,
+    sandboxed_emails: Vec<SandboxedEmail>,
+    payment_methods: Vec<ClientPaymentMethod>,
+    client_stats: Vec<ClientStats>,
+    contracts: Vec<Contract>,
+    settings: HashMap<Uuid, UserSettings>,
+    calendars: HashMap<Option<String>, HolidayCalendar>,
+    sync_changes: Vec<SyncChange>,
+    notifications: Vec<Notification>,
+    jobs: Vec<Job>,
+}
+
+impl MemoryState {
+    fn push_change(
+        &mut self,
+        user_id: Uuid,
+        table_name: &str,
+        record_id: Uuid,
+        operation: SyncOperation,
+        data: &Value,
+    ) {
+        let (old_data, new_data) = match operation {
+            SyncOperation::Delete => (Some(data.clone()), None),
+            SyncOperation::Insert | SyncOperation::Update => (None, Some(data.clone())),
+        };
+        let now = Utc::now();
+        let sequence_number = self.sync_changes.len() as i64 + 1;
+
+        self.sync_changes.push(SyncChange {
+            id: Uuid::new_v4(),
+            user_id,
+            table_name: table_name.to_string(),
+            record_id,
+            operation,
+            old_data,
+            new_data,
+            device_id: SERVER_DEVICE_ID.to_string(),
+            change_timestamp: now,
+            vector_clock: None,
+            is_applied: true,
+            is_conflict: false,
+            conflict_resolution: None,
+            sequence_number: Some(sequence_number),
+            created_at: now,
+        });
+    }
+}
+
+/// Repository keeping everything in memory.
+///
+/// Seed it with the `with_*` builders and inspect the outcome with the
+/// accessors after running the code under test.
+#[derive(Default)]
+pub struct InMemoryRepository {
+    state: Mutex<MemoryState>,
+}
+
+impl InMemoryRepository {
+    /// Creates an empty repository.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an invoice.
+    pub fn with_invoice(self, invoice: Invoice) -> Self {
+        self.state.lock().unwrap().invoices.insert(invoice.id, invoice);
+        self
+    }
+
+    /// Adds an invoice attachment.
+    pub fn with_attachment(self, attachment: Attachment) -> Self {
+        self.state.lock().unwrap().attachments.push(attachment);
+        self
+    }
+
+    /// Stores a user's settings.
+    pub fn with_settings(self, settings: UserSettings) -> Self {
+        self.state.lock().unwrap().settings.insert(settings.user_id, settings);
+        self
+    }
+
+    /// Adds a payment method.
+    pub fn with_payment_method(self, method: ClientPaymentMethod) -> Self {
+        self.state.lock().unwrap().payment_methods.push(method);
+        self
+    }
+
+    /// Stores a client's payment stats.
+    pub fn with_client_stats(self, stats: ClientStats) -> Self {
+        self.state.lock().unwrap().client_stats.push(stats);
+        self
+    }
+
+    /// Adds a contract.
+    pub fn with_contract(self, contract: Contract) -> Self {
+        self.state.lock().unwrap().contracts.push(contract);
+        self
+    }
+
+    /// Stores the holiday calendar of a country.
+    pub fn with_calendar(self, country_code: Option<&str>, calendar: HolidayCalendar) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .calendars
+            .insert(country_code.map(str::to_uppercase), calendar);
+        self
+    }
+
+    /// Current state of an invoice.
+    pub fn invoice(&self, invoice_id: Uuid) -> Option<Invoice> {
+        self.state.lock().unwrap().invoices.get(&invoice_id).cloned()
+    }
+
+    /// Every correspondence entry recorded so far.
+    pub fn correspondence(&self) -> Vec<Correspondence> {
+        self.state.lock().unwrap().correspondence.clone()
+    }
+
+    /// Every email the sandbox kept so far.
+    pub fn sandboxed_emails(&self) -> Vec<SandboxedEmail> {
+        self.state.lock().unwrap().sandboxed_emails.clone()
+    }
+
+    /// Every sync change recorded so far.
+    pub fn sync_changes(&self) -> Vec<SyncChange> {
+        self.state.lock().unwrap().sync_changes.clone()
+    }
+
+    /// Every notification sent so far.
+    pub fn notifications(&self) -> Vec<Notification> {
+        self.state.lock().unwrap().notifications.clone()
+    }
+
+    /// Every job enqueued so far.
+    pub fn jobs(&self) -> Vec<Job> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+}
+
+/// Builds an unpaid invoice for tests.
+pub fn sample_invoice(user_id: Uuid, due_date: NaiveDate, total: Decimal) -> Invoice {
+    let now = Utc::now();
+    Invoice {
+        id: Uuid::new_v4(),
+        user_id,
+        invoice_number: "INV-00001".to_string(),
+        client_name: "Acme Ltd".to_string(),
+        client_email: Some("billing@acme.test".to_string()),
+        client_id: None,
+        project_id: None,
+        amount: total,
+        currency: "USD".to_string(),
+        status: InvoiceStatus::Sent,
+        due_date: Some(due_date),
+        issue_date: due_date - chrono::Duration::days(14),
+        last_modified: now,
+        version_vector: None,
+        is_deleted: false,
+        description: None,
+        line_items: None,
+        subtotal: total,
+        tax_total: Decimal::ZERO,
+        total,
+        amount_paid: Decimal::ZERO,
+        chase_override: None,
+        exchange_rate_override: None,
+        chase_paused_until: None,
+        metadata: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+#[async_trait]
+impl InvoiceRepository for InMemoryRepository {
+    async fn find_invoice(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .invoices
+            .get(&invoice_id)
+            .filter(|invoice| invoice.user_id == user_id && !invoice.is_deleted)
+            .cloned())
+    }
+
+    async fn find_overdue_invoices(&self, today: NaiveDate, limit: i64) -> Result<Vec<Invoice>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let invoices: Vec<Invoice> = state
+            .invoices
+            .values()
+            .filter(|invoice| {
+                !invoice.is_deleted
+                    && !matches!(invoice.status, InvoiceStatus::Paid)
+                    && invoice.total > invoice.amount_paid
+                    && invoice.due_date.map_or(false, |due| due < today)
+                    && chase_retry(invoice).is_none_or(|retry| !retry.is_waiting(Utc::now()))
+                    && !invoice.is_chase_paused(Utc::now())
+            })
+            .cloned()
+            .collect();
+        let candidates = invoices
+            .into_iter()
+            .map(|invoice| {
+                let last_action = state
+                    .correspondence
+                    .iter()
+                    .filter(|entry| entry.invoice_id == invoice.id && entry.kind == CorrespondenceKind::Email)
+                    .map(|entry| entry.occurred_at.date_naive())
+                    .max();
+                let score = priority(&invoice, last_action, today);
+                (invoice, score)
+            })
+            .collect();
+        Ok(round_robin(candidates, usize::try_from(limit).unwrap_or(0)))
+    }
+
+    async fn merge_invoice_metadata(&self, invoice_id: Uuid, patch: Value) -> Result<(), anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(invoice) = state.invoices.get_mut(&invoice_id) {
+            let mut metadata = match invoice.metadata.take() {
+                Some(Value::Object(map)) => map,
+                _ => Map::new(),
+            };
+            if let Value::Object(patch) = patch {
+                metadata.extend(patch);
+            }
+            invoice.metadata = Some(Value::Object(metadata));
+            invoice.last_modified = Utc::now();
+        }
+        Ok(())
+    }
+
+    async fn merge_invoices_metadata(&self, patches: Vec<(Uuid, Value)>) -> Result<(), anyhow::Error> {
+        for (invoice_id, patch) in patches {
+            self.merge_invoice_metadata(invoice_id, patch).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_chase_override(
+        &self,
+        user_id: Uuid,
+        invoice_id: Uuid,
+        overrides: Option<&ChaseOverride>,
+    ) -> Result<Option<Invoice>, anyhow::Error> {
+        let value = overrides.map(serde_json::to_value).transpose()?;
+
+        let mut state = self.state.lock().unwrap();
+        let updated = match state.invoices.get_mut(&invoice_id) {
+            Some(invoice) if invoice.user_id == user_id && !invoice.is_deleted => {
+                invoice.chase_override = value;
+                if let Some(Value::Object(metadata)) = invoice.metadata.as_mut() {
+                    metadata.remove("chase_retry");
+                }
+                invoice.last_modified = Utc::now();
+                invoice.clone()
+            }
+            _ => return Ok(None),
+        };
+        state.push_change(
+            user_id,
+            "invoices",
+            invoice_id,
+            SyncOperation::Update,
+            &serde_json::to_value(&updated)?,
+        );
+        Ok(Some(updated))
+    }
+
+    async fn set_chase_pause(
+        &self,
+        user_id: Uuid,
+        invoice_id: Uuid,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Option<Invoice>, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let updated = match state.invoices.get_mut(&invoice_id) {
+            Some(invoice) if invoice.user_id == user_id && !invoice.is_deleted => {
+                invoice.chase_paused_until = until;
+                invoice.last_modified = Utc::now();
+                invoice.clone()
+            }
+            _ => return Ok(None),
+        };
+        state.push_change(
+            user_id,
+            "invoices",
+            invoice_id,
+            SyncOperation::Update,
+            &serde_json::to_value(&updated)?,
+        );
+        Ok(Some(updated))
+    }
+
+    async fn dead_lettered_invoices(&self, user_id: Uuid) -> Result<Vec<Invoice>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let mut invoices: Vec<Invoice> = state
+            .invoices
+            .values()
+            .filter(|invoice| {
+                invoice.user_id == user_id
+                    && !invoice.is_deleted
+                    && !matches!(invoice.status, InvoiceStatus::Paid)
+                    && is_dead_lettered(invoice)
+            })
+            .cloned()
+            .collect();
+        invoices.sort_by_key(|invoice| std::cmp::Reverse(chase_retry(invoice).map(|retry| retry.failed_at)));
+        Ok(invoices)
+    }
+
+    async fn requeue_chase(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let updated = match state.invoices.get_mut(&invoice_id) {
+            Some(invoice) if invoice.user_id == user_id && !invoice.is_deleted => {
+                if let Some(Value::Object(metadata)) = invoice.metadata.as_mut() {
+                    metadata.remove("chase_retry");
+                }
+                invoice.last_modified = Utc::now();
+                invoice.clone()
+            }
+            _ => return Ok(None),
+        };
+        state.push_change(
+            user_id,
+            "invoices",
+            invoice_id,
+            SyncOperation::Update,
+            &serde_json::to_value(&updated)?,
+        );
+        Ok(Some(updated))
+    }
+
+    async fn apply_late_fee(&self, invoice: &Invoice, fee: Decimal) -> Result<Option<Invoice>, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let charged = match state.invoices.get(&invoice.id) {
+            Some(current) if !current.is_deleted && !has_late_fee(current) => {
+                with_late_fee(current, fee, Utc::now())
+            }
+            _ => return Ok(None),
+        };
+        state.invoices.insert(charged.id, charged.clone());
+        state.push_change(
+            charged.user_id,
+            "invoices",
+            charged.id,
+            SyncOperation::Update,
+            &serde_json::to_value(&charged)?,
+        );
+        Ok(Some(charged))
+    }
+
+    async fn record_correspondence(
+        &self,
+        user_id: Uuid,
+        entry: CreateCorrespondence,
+    ) -> Result<Correspondence, anyhow::Error> {
+        let now = Utc::now();
+        let stored = Correspondence {
+            id: Uuid::new_v4(),
+            user_id,
+            invoice_id: entry.invoice_id,
+            kind: entry.kind,
+            sender: entry.sender,
+            recipient: entry.recipient,
+            subject: entry.subject,
+            body_text: entry.body_text,
+            body_html: entry.body_html,
+            provider_message_id: entry.provider_message_id,
+            delivery_status: entry.delivery_status,
+            metadata: entry.metadata,
+            occurred_at: entry.occurred_at.unwrap_or(now),
+            created_at: now,
+        };
+        self.state.lock().unwrap().correspondence.push(stored.clone());
+        Ok(stored)
+    }
+
+    async fn record_sandboxed_email(
+        &self,
+        user_id: Uuid,
+        email: CreateSandboxedEmail,
+    ) -> Result<SandboxedEmail, anyhow::Error> {
+        let stored = SandboxedEmail {
+            id: Uuid::new_v4(),
+            user_id,
+            recipient: email.recipient,
+            redirected_to: email.redirected_to,
+            subject: email.subject,
+            body_text: email.body_text,
+            attachments: email.attachments,
+            created_at: Utc::now(),
+        };
+        self.state.lock().unwrap().sandboxed_emails.push(stored.clone());
+        Ok(stored)
+    }
+
+    async fn list_attachments(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Vec<Attachment>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let mut attachments: Vec<Attachment> = state
+            .attachments
+            .iter()
+            .filter(|a| a.user_id == user_id && a.invoice_id == invoice_id)
+            .cloned()
+            .collect();
+        attachments.sort_by_key(|a| a.created_at);
+        Ok(attachments)
+    }
+}
+
+#[async_trait]
+impl ClientRepository for InMemoryRepository {
+    async fn payment_methods_for_client(
+        &self,
+        user_id: Uuid,
+        client_email: Option<&str>,
+    ) -> Result<Vec<ClientPaymentMethod>, anyhow::Error> {
+        let client_email = client_email.map(|e| e.trim().to_lowercase());
+        let state = self.state.lock().unwrap();
+        let owned = state.payment_methods.iter().filter(|m| m.user_id == user_id);
+
+        // The client's own methods win over the user's defaults
+        let mut methods: Vec<ClientPaymentMethod> = owned
+            .clone()
+            .filter(|m| client_email.is_some() && m.client_email == client_email)
+            .cloned()
+            .collect();
+        if methods.is_empty() {
+            methods = owned.filter(|m| m.client_email.is_none()).cloned().collect();
+        }
+        methods.sort_by_key(|m| (m.position, m.created_at));
+        Ok(methods)
+    }
+
+    async fn pdf_branding(&self, invoice: &Invoice) -> Result<PdfBranding, anyhow::Error> {
+        let methods = self
+            .payment_methods_for_client(invoice.user_id, invoice.client_email.as_deref())
+            .await?;
+        Ok(PdfBranding {
+            business_name: "Test Business".to_string(),
+            business_email: None,
+            footer: None,
+            payment_instructions: instructions(&methods, &invoice.invoice_number),
+        })
+    }
+
+    async fn client_stats(&self, invoice: &Invoice) -> Result<Option<ClientStats>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .client_stats
+            .iter()
+            .find(|stats| stats.user_id == invoice.user_id && Some(stats.client_id) == invoice.client_id)
+            .cloned())
+    }
+
+    async fn signed_agreement(&self, invoice: &Invoice) -> Result<Option<Contract>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let project_match = |c: &Contract| invoice.project_id.is_some() && c.project_id == invoice.project_id;
+
+        // Project contracts win over client contracts, then the latest signature
+        Ok(state
+            .contracts
+            .iter()
+            .filter(|c| {
+                c.user_id == invoice.user_id
+                    && !c.is_deleted
+                    && c.status == ContractStatus::Signed
+                    && c.signed_at.is_some()
+                    && (project_match(c) || (invoice.client_id.is_some() && c.client_id == invoice.client_id))
+            })
+            .max_by_key(|c| (project_match(c), c.signed_at))
+            .cloned())
+    }
+
+    async fn outstanding_invoices(&self, invoice: &Invoice) -> Result<Vec<Invoice>, anyhow::Error> {
+        let client_email = invoice.client_email.as_deref().map(str::to_lowercase);
+        let state = self.state.lock().unwrap();
+        let same_client = |other: &Invoice| match (invoice.client_id, other.client_id) {
+            (Some(client_id), Some(other_id)) => client_id == other_id,
+            (_, None) => client_email.is_some() && other.client_email.as_deref().map(str::to_lowercase) == client_email,
+            (None, Some(_)) => false,
+        };
+
+        let mut invoices: Vec<Invoice> = state
+            .invoices
+            .values()
+            .filter(|other| {
+                other.user_id == invoice.user_id
+                    && !other.is_deleted
+                    && matches!(other.status, InvoiceStatus::Sent | InvoiceStatus::Overdue)
+                    && other.total > other.amount_paid
+                    && same_client(other)
+            })
+            .cloned()
+            .collect();
+        invoices.sort_by(|a, b| {
+            (a.due_date.is_none(), a.due_date, &a.invoice_number)
+                .cmp(&(b.due_date.is_none(), b.due_date, &b.invoice_number))
+        });
+        Ok(invoices)
+    }
+}
+
+#[async_trait]
+impl SettingsRepository for InMemoryRepository {
+    async fn user_settings(&self, user_id: Uuid) -> Result<UserSettings, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .settings
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(|| UserSettings::defaults(user_id)))
+    }
+
+    async fn calendar(&self, country_code: Option<&str>) -> Result<HolidayCalendar, anyhow::Error> {
+        let key = country_code.map(str::to_uppercase);
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .calendars
+            .get(&key)
+            .cloned()
+            .unwrap_or_else(|| HolidayCalendar::new(key.as_deref())))
+    }
+}
+
+#[async_trait]
+impl SyncRepository for InMemoryRepository {
+    async fn record_server_change(
+        &self,
+        user_id: Uuid,
+        table_name: &str,
+        record_id: Uuid,
+        operation: SyncOperation,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .push_change(user_id, table_name, record_id, operation, data);
+        Ok(())
+    }
+
+    async fn changes_since(
+        &self,
+        user_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SyncChange>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .sync_changes
+            .iter()
+            .filter(|c| c.user_id == user_id && c.is_applied)
+            .filter(|c| since.map_or(true, |since| c.change_timestamp > since))
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for InMemoryRepository {
+    async fn notify(&self, user_id: Uuid, notification: CreateNotification) -> Result<Notification, anyhow::Error> {
+        let now = Utc::now();
+        let stored = Notification {
+            id: Uuid::new_v4(),
+            user_id,
+            kind: notification.kind,
+            title: notification.title,
+            body: notification.body,
+            data: notification.data,
+            read_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.push_change(
+            user_id,
+            "notifications",
+            stored.id,
+            SyncOperation::Insert,
+            &serde_json::to_value(&stored)?,
+        );
+        state.notifications.push(stored.clone());
+        Ok(stored)
+    }
+}
+
+#[async_trait]
+impl JobRepository for InMemoryRepository {
+    async fn enqueue_job(&self, job: CreateJob) -> Result<Option<Job>, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let pending = |existing: &Job| {
+            matches!(existing.status, JobStatus::Queued | JobStatus::Running)
+                && existing.kind == job.kind
+                && job.dedupe_key.is_some()
+                && existing.dedupe_key == job.dedupe_key
+        };
+        if state.jobs.iter().any(pending) {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        let job = Job {
+            id: Uuid::new_v4(),
+            kind: job.kind,
+            payload: job.payload,
+            dedupe_key: job.dedupe_key,
+            status: JobStatus::Queued,
+            attempts: 0,
+            max_attempts: job.max_attempts,
+            run_at: job.run_at.unwrap_or(now),
+            locked_by: None,
+            locked_until: None,
+            last_error: None,
+            finished_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        state.jobs.push(job.clone());
+        Ok(Some(job))
+    }
+
+    async fn claim_jobs(
+        &self,
+        kind: &str,
+        worker_id: &str,
+        limit: i64,
+        visibility: Duration,
+    ) -> Result<Vec<Job>, anyhow::Error> {
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap();
+        let lapsed = |job: &Job| job.status == JobStatus::Running && job.locked_until.is_none_or(|until| until < now);
+
+        for job in state.jobs.iter_mut().filter(|job| job.kind == kind) {
+            if lapsed(job) && job.attempts >= job.max_attempts {
+                job.status = JobStatus::Failed;
+                job.last_error = Some("visibility timeout lapsed".to_string());
+                job.locked_by = None;
+                job.locked_until = None;
+                job.finished_at = Some(now);
+            }
+        }
+
+        let mut due: Vec<&mut Job> = state
+            .jobs
+            .iter_mut()
+            .filter(|job| job.kind == kind)
+            .filter(|job| (job.status == JobStatus::Queued && job.run_at <= now) || lapsed(job))
+            .collect();
+        due.sort_by_key(|job| job.run_at);
+
+        let mut claimed = Vec::new();
+        for job in due.into_iter().take(limit.max(0) as usize) {
+            job.status = JobStatus::Running;
+            job.attempts += 1;
+            job.locked_by = Some(worker_id.to_string());
+            job.locked_until = Some(now + visibility);
+            job.updated_at = now;
+            claimed.push(job.clone());
+        }
+        Ok(claimed)
+    }
+
+    async fn complete_job(&self, job_id: Uuid, worker_id: &str) -> Result<bool, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(job) = state.jobs.iter_mut().find(|job| held(job, job_id, worker_id)) else {
+            return Ok(false);
+        };
+        job.status = JobStatus::Completed;
+        job.last_error = None;
+        job.locked_by = None;
+        job.locked_until = None;
+        job.finished_at = Some(Utc::now());
+        Ok(true)
+    }
+
+    async fn fail_job(
+        &self,
+        job_id: Uuid,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(job) = state.jobs.iter_mut().find(|job| held(job, job_id, worker_id)) else {
+            return Ok(false);
+        };
+        match retry_at {
+            Some(run_at) => {
+                job.status = JobStatus::Queued;
+                job.run_at = run_at;
+            }
+            None => {
+                job.status = JobStatus::Failed;
+                job.finished_at = Some(Utc::now());
+            }
+        }
+        job.last_error = Some(error.to_string());
+        job.locked_by = None;
+        job.locked_until = None;
+        Ok(true)
+    }
+
+    async fn prune_finished_jobs(&self, before: DateTime<Utc>) -> Result<u64, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let count = state.jobs.len();
+        state.jobs.retain(|job| job.finished_at.is_none_or(|finished_at| finished_at >= before));
+        Ok((count - state.jobs.len()) as u64)
+    }
+}
+
+/// Whether a worker holds a running job.
+fn held(job: &Job, job_id: Uuid, worker_id: &str) -> bool {
+    job.id == job_id && job.status == JobStatus::Running && job.locked_by.as_deref() == Some(worker_id)
+}
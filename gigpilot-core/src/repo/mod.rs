@@ -0,0 +1,215 @@
+//! Repository traits over invoices, clients, settings, sync, notifications
+//! and worker jobs.
+//!
+//! The chase executor, scheduler and some handlers go through these traits
+//! instead of a `PgPool`, so they can be unit tested against the in-memory
+//! implementation in [`memory`]. Production uses [`PgRepository`], which
+//! delegates to the existing sqlx functions.
+
+#[cfg(test)]
+pub mod memory;
+pub mod postgres;
+
+pub use postgres::PgRepository;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::business_days::HolidayCalendar;
+use crate::invoices::pdf::PdfBranding;
+use crate::models::attachment::Attachment;
+use crate::models::client_stats::ClientStats;
+use crate::models::contract::Contract;
+use crate::models::chase_override::ChaseOverride;
+use crate::models::correspondence::{Correspondence, CreateCorrespondence};
+use crate::models::invoice::Invoice;
+use crate::models::job::{CreateJob, Job};
+use crate::models::notification::{CreateNotification, Notification};
+use crate::models::payment_method::ClientPaymentMethod;
+use crate::models::sandboxed_email::{CreateSandboxedEmail, SandboxedEmail};
+use crate::models::sync_change::{SyncChange, SyncOperation};
+use crate::models::user_settings::UserSettings;
+
+/// Invoice storage.
+#[async_trait]
+pub trait InvoiceRepository: Send + Sync {
+    /// Loads a live invoice owned by the user.
+    async fn find_invoice(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error>;
+
+    /// Loads up to `limit` unpaid invoices due before `today`, most urgent
+    /// first and taking turns between users (see [`crate::worker::priority`]).
+    async fn find_overdue_invoices(&self, today: NaiveDate, limit: i64) -> Result<Vec<Invoice>, anyhow::Error>;
+
+    /// Merges the keys of `patch` into an invoice's metadata.
+    async fn merge_invoice_metadata(&self, invoice_id: Uuid, patch: Value) -> Result<(), anyhow::Error>;
+
+    /// Merges a patch into the metadata of each invoice, all or none.
+    async fn merge_invoices_metadata(&self, patches: Vec<(Uuid, Value)>) -> Result<(), anyhow::Error>;
+
+    /// Sets or clears an invoice's chase override, recording a sync change.
+    async fn set_chase_override(
+        &self,
+        user_id: Uuid,
+        invoice_id: Uuid,
+        overrides: Option<&ChaseOverride>,
+    ) -> Result<Option<Invoice>, anyhow::Error>;
+
+    /// Pauses an invoice's reminders until `until`, or resumes them if
+    /// `None`, recording a sync change.
+    async fn set_chase_pause(
+        &self,
+        user_id: Uuid,
+        invoice_id: Uuid,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Option<Invoice>, anyhow::Error>;
+
+    /// Unpaid invoices whose reminders were given up on after failed sends.
+    async fn dead_lettered_invoices(&self, user_id: Uuid) -> Result<Vec<Invoice>, anyhow::Error>;
+
+    /// Clears an invoice's failed sends so it is chased again, recording a
+    /// sync change.
+    async fn requeue_chase(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error>;
+
+    /// Charges a late fee once, returning the updated invoice.
+    async fn apply_late_fee(&self, invoice: &Invoice, fee: Decimal) -> Result<Option<Invoice>, anyhow::Error>;
+
+    /// Stores a copy of a message exchanged about an invoice.
+    async fn record_correspondence(
+        &self,
+        user_id: Uuid,
+        entry: CreateCorrespondence,
+    ) -> Result<Correspondence, anyhow::Error>;
+
+    /// Keeps a copy of an email the sandbox kept from its recipient.
+    async fn record_sandboxed_email(
+        &self,
+        user_id: Uuid,
+        email: CreateSandboxedEmail,
+    ) -> Result<SandboxedEmail, anyhow::Error>;
+
+    /// Files attached to an invoice, oldest first.
+    async fn list_attachments(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Vec<Attachment>, anyhow::Error>;
+}
+
+/// Per-client data: how a client pays and how their invoices look.
+#[async_trait]
+pub trait ClientRepository: Send + Sync {
+    /// Payment methods for a client, falling back to the user's defaults.
+    async fn payment_methods_for_client(
+        &self,
+        user_id: Uuid,
+        client_email: Option<&str>,
+    ) -> Result<Vec<ClientPaymentMethod>, anyhow::Error>;
+
+    /// PDF branding for an invoice, including its client's payment instructions.
+    async fn pdf_branding(&self, invoice: &Invoice) -> Result<PdfBranding, anyhow::Error>;
+
+    /// Payment stats of the client an invoice was issued to, if profiled.
+    async fn client_stats(&self, invoice: &Invoice) -> Result<Option<ClientStats>, anyhow::Error>;
+
+    /// Latest signed contract covering an invoice's project or client.
+    async fn signed_agreement(&self, invoice: &Invoice) -> Result<Option<Contract>, anyhow::Error>;
+
+    /// Open invoices of the client an invoice was issued to, including
+    /// the invoice itself (see [`crate::clients::statement`]).
+    async fn outstanding_invoices(&self, invoice: &Invoice) -> Result<Vec<Invoice>, anyhow::Error>;
+}
+
+/// User settings and holiday calendars.
+#[async_trait]
+pub trait SettingsRepository: Send + Sync {
+    /// A user's settings, or the defaults.
+    async fn user_settings(&self, user_id: Uuid) -> Result<UserSettings, anyhow::Error>;
+
+    /// Holiday calendar for a country.
+    async fn calendar(&self, country_code: Option<&str>) -> Result<HolidayCalendar, anyhow::Error>;
+}
+
+/// Sync change log.
+#[async_trait]
+pub trait SyncRepository: Send + Sync {
+    /// Records a server-originated change for devices to pull.
+    async fn record_server_change(
+        &self,
+        user_id: Uuid,
+        table_name: &str,
+        record_id: Uuid,
+        operation: SyncOperation,
+        data: &Value,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Applied changes after `since` (all of them when `None`), oldest first.
+    async fn changes_since(
+        &self,
+        user_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SyncChange>, anyhow::Error>;
+}
+
+/// In-app notifications (see [`crate::notifications`]).
+#[async_trait]
+pub trait NotificationRepository: Send + Sync {
+    /// Notifies a user, recording a sync change.
+    async fn notify(&self, user_id: Uuid, notification: CreateNotification) -> Result<Notification, anyhow::Error>;
+}
+
+/// Durable worker job queue (see [`crate::worker::jobs`]).
+#[async_trait]
+pub trait JobRepository: Send + Sync {
+    /// Enqueues a job, unless one of its kind and dedupe key is pending.
+    async fn enqueue_job(&self, job: CreateJob) -> Result<Option<Job>, anyhow::Error>;
+
+    /// Claims up to `limit` due jobs of a kind, holding them for `visibility`.
+    async fn claim_jobs(
+        &self,
+        kind: &str,
+        worker_id: &str,
+        limit: i64,
+        visibility: Duration,
+    ) -> Result<Vec<Job>, anyhow::Error>;
+
+    /// Completes a job the worker holds; `false` if its hold lapsed.
+    async fn complete_job(&self, job_id: Uuid, worker_id: &str) -> Result<bool, anyhow::Error>;
+
+    /// Fails an attempt of a job the worker holds, retrying it at
+    /// `retry_at` or giving up; `false` if its hold lapsed.
+    async fn fail_job(
+        &self,
+        job_id: Uuid,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Deletes jobs that finished before `before`.
+    async fn prune_finished_jobs(&self, before: DateTime<Utc>) -> Result<u64, anyhow::Error>;
+}
+
+/// Every repository the application needs.
+pub trait Repository:
+    InvoiceRepository
+    + ClientRepository
+    + SettingsRepository
+    + SyncRepository
+    + NotificationRepository
+    + JobRepository
+{
+}
+
+impl<T> Repository for T where
+    T: InvoiceRepository
+        + ClientRepository
+        + SettingsRepository
+        + SyncRepository
+        + NotificationRepository
+        + JobRepository
+{
+}
+
+/// Shared repository handle, passed to handlers as an `Extension`.
+pub type DynRepository = Arc<dyn Repository>;
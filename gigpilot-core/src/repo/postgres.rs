@@ -0,0 +1,338 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::attachments::list_attachments;
+use crate::business_days::HolidayCalendar;
+use crate::clients::statement::outstanding_invoices;
+use crate::clients::stats::stats_for_invoice;
+use crate::contracts::signed_agreement_for_invoice;
+use crate::email_sandbox::record_sandboxed_email;
+use crate::invoices::correspondence::record_correspondence;
+use crate::invoices::late_fees::apply_late_fee;
+use crate::invoices::pdf::PdfBranding;
+use crate::invoices::{dead_lettered_invoices, find_invoice, requeue_chase, set_chase_override, set_chase_pause};
+use crate::models::attachment::Attachment;
+use crate::models::client_stats::ClientStats;
+use crate::models::contract::Contract;
+use crate::models::chase_override::ChaseOverride;
+use crate::models::correspondence::{Correspondence, CreateCorrespondence};
+use crate::models::invoice::Invoice;
+use crate::models::job::{CreateJob, Job};
+use crate::models::notification::{CreateNotification, Notification};
+use crate::models::payment_method::ClientPaymentMethod;
+use crate::models::sandboxed_email::{CreateSandboxedEmail, SandboxedEmail};
+use crate::models::sync_change::{SyncChange, SyncOperation};
+use crate::models::user_settings::UserSettings;
+use crate::payment_methods::methods_for_client;
+use crate::notifications::notify;
+use crate::repo::{
+    ClientRepository, InvoiceRepository, JobRepository, NotificationRepository, SettingsRepository, SyncRepository,
+};
+use crate::settings::SettingsCache;
+use crate::sync::pull::changes_since;
+use crate::sync::server::record_server_change;
+use crate::worker::jobs;
+use crate::worker::priority::{REMINDER_POINTS, STALE_DAYS_CAP, STALE_DAY_POINTS};
+
+/// Production repository backed by PostgreSQL.
+///
+/// Settings and calendars are read through the shared [`SettingsCache`].
+#[derive(Clone)]
+pub struct PgRepository {
+    /// Database connection pool
+    pool: PgPool,
+
+    /// Cached user settings and holiday calendars
+    settings_cache: SettingsCache,
+}
+
+impl PgRepository {
+    /// Creates a repository over a pool and settings cache.
+    pub fn new(pool: PgPool, settings_cache: SettingsCache) -> Self {
+        Self { pool, settings_cache }
+    }
+}
+
+#[async_trait]
+impl InvoiceRepository for PgRepository {
+    async fn find_invoice(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error> {
+        find_invoice(&self.pool, user_id, invoice_id).await
+    }
+
+    async fn find_overdue_invoices(&self, today: NaiveDate, limit: i64) -> Result<Vec<Invoice>, anyhow::Error> {
+        // Same priority and round-robin as `worker::priority`
+        let invoices = sqlx::query_as::<_, Invoice>(
+            r#"
+            WITH candidates AS (
+                SELECT
+                    i.id, i.user_id, i.invoice_number, i.client_name, i.client_email,
+                    i.amount, i.currency, i.status, i.due_date, i.issue_date,
+                    i.last_modified, i.version_vector, i.is_deleted,
+                    i.description, i.line_items, i.subtotal, i.tax_total, i.total, i.amount_paid,
+                    i.client_id, i.project_id, i.chase_override, i.exchange_rate_override, i.chase_paused_until, i.metadata,
+                    i.created_at, i.updated_at,
+                    LN(1 + (i.total - i.amount_paid)::float8) / LN(2)
+                    + $3 * CASE
+                        WHEN COALESCE((i.chase_override->>'paused')::boolean, false)
+                            OR (i.chase_override->>'not_before')::date > $1 THEN 0
+                        WHEN i.metadata->>'chase_state' = 'chasing_level_1'
+                            AND COALESCE((i.chase_override->>'skip_level_2')::boolean, false) THEN 2
+                        WHEN i.metadata->>'chase_state' = 'chasing_level_1' THEN 3
+                        WHEN i.metadata->>'chase_state' = 'chasing_level_2' THEN 2
+                        WHEN i.metadata->>'chase_state' = 'chasing_level_3' THEN 1
+                        WHEN i.metadata->>'chase_state' IN ('final_notice', 'paid', 'disputed') THEN 0
+                        ELSE 4
+                    END
+                    + $4 * LEAST(GREATEST($1 - COALESCE(last_email.occurred_at::date, i.due_date), 0), $5)
+                        AS priority
+                FROM invoices i
+                LEFT JOIN LATERAL (
+                    SELECT MAX(c.occurred_at) AS occurred_at
+                    FROM correspondence c
+                    WHERE c.invoice_id = i.id AND c.kind = 'email'
+                ) last_email ON true
+                WHERE i.due_date < $1
+                    AND i.status != 'paid'
+                    AND i.total > i.amount_paid
+                    AND i.is_deleted = false
+                    -- Skip invoices backing off after a failed send, or given up on
+                    AND (jsonb_typeof(i.metadata->'chase_retry') IS DISTINCT FROM 'object'
+                        OR (i.metadata->'chase_retry'->>'next_retry_at')::timestamptz <= NOW())
+                    -- Skip invoices whose reminders the user paused
+                    AND (i.chase_paused_until IS NULL OR i.chase_paused_until <= NOW())
+            ),
+            ranked AS (
+                SELECT
+                    *,
+                    ROW_NUMBER() OVER (PARTITION BY user_id ORDER BY priority DESC, due_date ASC) AS user_rank
+                FROM candidates
+            )
+            SELECT
+                id, user_id, invoice_number, client_name, client_email,
+                amount, currency, status, due_date, issue_date,
+                last_modified, version_vector, is_deleted,
+                description, line_items, subtotal, tax_total, total, amount_paid,
+                client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+            FROM ranked
+            ORDER BY user_rank ASC, priority DESC, due_date ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(today)
+        .bind(limit)
+        .bind(REMINDER_POINTS)
+        .bind(STALE_DAY_POINTS)
+        .bind(STALE_DAYS_CAP as i32)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(invoices)
+    }
+
+    async fn merge_invoice_metadata(&self, invoice_id: Uuid, patch: Value) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            r#"
+            UPDATE invoices
+            SET
+                metadata = COALESCE(metadata, '{}'::jsonb) || $2,
+                updated_at = NOW(),
+                last_modified = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(invoice_id)
+        .bind(patch)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn merge_invoices_metadata(&self, patches: Vec<(Uuid, Value)>) -> Result<(), anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        for (invoice_id, patch) in patches {
+            sqlx::query(
+                r#"
+                UPDATE invoices
+                SET
+                    metadata = COALESCE(metadata, '{}'::jsonb) || $2,
+                    updated_at = NOW(),
+                    last_modified = NOW()
+                WHERE id = $1
+                "#,
+            )
+            .bind(invoice_id)
+            .bind(patch)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn set_chase_override(
+        &self,
+        user_id: Uuid,
+        invoice_id: Uuid,
+        overrides: Option<&ChaseOverride>,
+    ) -> Result<Option<Invoice>, anyhow::Error> {
+        set_chase_override(&self.pool, user_id, invoice_id, overrides).await
+    }
+
+    async fn set_chase_pause(
+        &self,
+        user_id: Uuid,
+        invoice_id: Uuid,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Option<Invoice>, anyhow::Error> {
+        set_chase_pause(&self.pool, user_id, invoice_id, until).await
+    }
+
+    async fn dead_lettered_invoices(&self, user_id: Uuid) -> Result<Vec<Invoice>, anyhow::Error> {
+        dead_lettered_invoices(&self.pool, user_id).await
+    }
+
+    async fn requeue_chase(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Option<Invoice>, anyhow::Error> {
+        requeue_chase(&self.pool, user_id, invoice_id).await
+    }
+
+    async fn apply_late_fee(&self, invoice: &Invoice, fee: Decimal) -> Result<Option<Invoice>, anyhow::Error> {
+        apply_late_fee(&self.pool, invoice, fee).await
+    }
+
+    async fn record_correspondence(
+        &self,
+        user_id: Uuid,
+        entry: CreateCorrespondence,
+    ) -> Result<Correspondence, anyhow::Error> {
+        record_correspondence(&self.pool, user_id, entry).await
+    }
+
+    async fn record_sandboxed_email(
+        &self,
+        user_id: Uuid,
+        email: CreateSandboxedEmail,
+    ) -> Result<SandboxedEmail, anyhow::Error> {
+        record_sandboxed_email(&self.pool, user_id, email).await
+    }
+
+    async fn list_attachments(&self, user_id: Uuid, invoice_id: Uuid) -> Result<Vec<Attachment>, anyhow::Error> {
+        list_attachments(&self.pool, user_id, invoice_id).await
+    }
+}
+
+#[async_trait]
+impl ClientRepository for PgRepository {
+    async fn payment_methods_for_client(
+        &self,
+        user_id: Uuid,
+        client_email: Option<&str>,
+    ) -> Result<Vec<ClientPaymentMethod>, anyhow::Error> {
+        methods_for_client(&self.pool, user_id, client_email).await
+    }
+
+    async fn pdf_branding(&self, invoice: &Invoice) -> Result<PdfBranding, anyhow::Error> {
+        PdfBranding::for_invoice(&self.pool, invoice).await
+    }
+
+    async fn client_stats(&self, invoice: &Invoice) -> Result<Option<ClientStats>, anyhow::Error> {
+        stats_for_invoice(&self.pool, invoice).await
+    }
+
+    async fn signed_agreement(&self, invoice: &Invoice) -> Result<Option<Contract>, anyhow::Error> {
+        signed_agreement_for_invoice(&self.pool, invoice).await
+    }
+
+    async fn outstanding_invoices(&self, invoice: &Invoice) -> Result<Vec<Invoice>, anyhow::Error> {
+        outstanding_invoices(
+            &self.pool,
+            invoice.user_id,
+            invoice.client_id,
+            invoice.client_email.as_deref(),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl SettingsRepository for PgRepository {
+    async fn user_settings(&self, user_id: Uuid) -> Result<UserSettings, anyhow::Error> {
+        self.settings_cache.user_settings(user_id).await
+    }
+
+    async fn calendar(&self, country_code: Option<&str>) -> Result<HolidayCalendar, anyhow::Error> {
+        self.settings_cache.calendar(country_code).await
+    }
+}
+
+#[async_trait]
+impl SyncRepository for PgRepository {
+    async fn record_server_change(
+        &self,
+        user_id: Uuid,
+        table_name: &str,
+        record_id: Uuid,
+        operation: SyncOperation,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        record_server_change(&self.pool, user_id, table_name, record_id, operation, data).await
+    }
+
+    async fn changes_since(
+        &self,
+        user_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SyncChange>, anyhow::Error> {
+        changes_since(&self.pool, user_id, since).await
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for PgRepository {
+    async fn notify(&self, user_id: Uuid, notification: CreateNotification) -> Result<Notification, anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        let stored = notify(&mut tx, user_id, notification).await?;
+        tx.commit().await?;
+        Ok(stored)
+    }
+}
+
+#[async_trait]
+impl JobRepository for PgRepository {
+    async fn enqueue_job(&self, job: CreateJob) -> Result<Option<Job>, anyhow::Error> {
+        jobs::enqueue_job(&self.pool, &job).await
+    }
+
+    async fn claim_jobs(
+        &self,
+        kind: &str,
+        worker_id: &str,
+        limit: i64,
+        visibility: Duration,
+    ) -> Result<Vec<Job>, anyhow::Error> {
+        jobs::claim_jobs(&self.pool, kind, worker_id, limit, visibility).await
+    }
+
+    async fn complete_job(&self, job_id: Uuid, worker_id: &str) -> Result<bool, anyhow::Error> {
+        jobs::complete_job(&self.pool, job_id, worker_id).await
+    }
+
+    async fn fail_job(
+        &self,
+        job_id: Uuid,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, anyhow::Error> {
+        jobs::fail_job(&self.pool, job_id, worker_id, error, retry_at).await
+    }
+
+    async fn prune_finished_jobs(&self, before: DateTime<Utc>) -> Result<u64, anyhow::Error> {
+        jobs::prune_finished_jobs(&self.pool, before).await
+    }
+}
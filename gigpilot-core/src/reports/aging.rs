@@ -0,0 +1,401 @@
+//! Receivables aging.
+//!
+//! [`client_aging`] buckets today's open balances by days overdue for each
+//! client. The worker also records a daily aging snapshot per user and
+//! currency (see `worker::aging_snapshots`); [`aging_trend`] reads them
+//! back as a weekly series, e.g. for "overdue balance over the last 12
+//! weeks".
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// Weeks returned when the request does not say.
+pub const DEFAULT_TREND_WEEKS: u32 = 12;
+
+/// Longest trend allowed.
+pub const MAX_TREND_WEEKS: u32 = 104;
+
+/// Open balance owed by one client in one currency, by days overdue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct ClientAging {
+    /// Client name as written on the invoices
+    pub client_name: String,
+
+    /// Client email (None if the invoices have none)
+    pub client_email: Option<String>,
+
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Number of open invoices
+    pub invoice_count: i64,
+
+    /// Total balance due
+    pub outstanding: Decimal,
+
+    /// Balance past its due date
+    pub overdue: Decimal,
+
+    /// Balance not yet past its due date
+    pub current: Decimal,
+
+    /// Balance 1-30 days overdue
+    pub days_1_30: Decimal,
+
+    /// Balance 31-60 days overdue
+    pub days_31_60: Decimal,
+
+    /// Balance 61-90 days overdue
+    pub days_61_90: Decimal,
+
+    /// Balance more than 90 days overdue
+    pub days_over_90: Decimal,
+}
+
+/// Open balance across all clients in one currency, by days overdue.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgingTotals {
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Number of open invoices
+    pub invoice_count: i64,
+
+    /// Total balance due
+    pub outstanding: Decimal,
+
+    /// Balance past its due date
+    pub overdue: Decimal,
+
+    /// Balance not yet past its due date
+    pub current: Decimal,
+
+    /// Balance 1-30 days overdue
+    pub days_1_30: Decimal,
+
+    /// Balance 31-60 days overdue
+    pub days_31_60: Decimal,
+
+    /// Balance 61-90 days overdue
+    pub days_61_90: Decimal,
+
+    /// Balance more than 90 days overdue
+    pub days_over_90: Decimal,
+}
+
+/// Receivables aging per client on one day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgingReport {
+    /// Day days overdue are counted to
+    pub as_of: NaiveDate,
+
+    /// One row per client and currency, most overdue first
+    pub clients: Vec<ClientAging>,
+
+    /// Sums of `clients` per currency
+    pub totals: Vec<AgingTotals>,
+}
+
+/// Sums client aging rows per currency, ordered by currency.
+pub fn aging_totals(clients: &[ClientAging]) -> Vec<AgingTotals> {
+    let mut totals: BTreeMap<&str, AgingTotals> = BTreeMap::new();
+    for client in clients {
+        let total = totals.entry(client.currency.as_str()).or_insert_with(|| AgingTotals {
+            currency: client.currency.clone(),
+            ..AgingTotals::default()
+        });
+        total.invoice_count += client.invoice_count;
+        total.outstanding += client.outstanding;
+        total.overdue += client.overdue;
+        total.current += client.current;
+        total.days_1_30 += client.days_1_30;
+        total.days_31_60 += client.days_31_60;
+        total.days_61_90 += client.days_61_90;
+        total.days_over_90 += client.days_over_90;
+    }
+    totals.into_values().collect()
+}
+
+/// Buckets a user's open balances per client by days overdue.
+///
+/// Open invoices are sent or overdue, not deleted, with a balance left
+/// (the same ones the worker snapshots). Days overdue are calendar days
+/// from the due date to `as_of`; invoices without a due date are current.
+/// Clients are grouped by name and email.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `as_of` - Day to count days overdue to
+///
+/// # Returns
+///
+/// Returns the per-client rows, most overdue balance first, with totals
+/// per currency.
+pub async fn client_aging(
+    pool: &PgPool,
+    user_id: Uuid,
+    as_of: NaiveDate,
+) -> Result<AgingReport, anyhow::Error> {
+    let clients = sqlx::query_as::<_, ClientAging>(
+        r#"
+        SELECT
+            client_name, client_email, currency, COUNT(*) AS invoice_count,
+            SUM(balance) AS outstanding,
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue > 0), 0) AS overdue,
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue <= 0), 0) AS current,
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue BETWEEN 1 AND 30), 0) AS days_1_30,
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue BETWEEN 31 AND 60), 0) AS days_31_60,
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue BETWEEN 61 AND 90), 0) AS days_61_90,
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue > 90), 0) AS days_over_90
+        FROM (
+            SELECT
+                client_name,
+                client_email,
+                currency,
+                total - amount_paid AS balance,
+                COALESCE($2::date - due_date, 0) AS days_overdue
+            FROM invoices
+            WHERE user_id = $1
+                AND is_deleted = false
+                AND status IN ('sent', 'overdue')
+                AND total > amount_paid
+        ) open_invoices
+        GROUP BY client_name, client_email, currency
+        ORDER BY overdue DESC, outstanding DESC, client_name, currency
+        "#,
+    )
+    .bind(user_id)
+    .bind(as_of)
+    .fetch_all(pool)
+    .await?;
+
+    let totals = aging_totals(&clients);
+    Ok(AgingReport { as_of, clients, totals })
+}
+
+/// A user's receivables aging in one currency on one day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct AgingSnapshot {
+    /// Day the snapshot was taken
+    pub snapshot_date: NaiveDate,
+
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Number of open invoices
+    pub invoice_count: i32,
+
+    /// Total balance due
+    pub outstanding: Decimal,
+
+    /// Balance past its due date
+    pub overdue: Decimal,
+
+    /// Balance not yet past its due date
+    pub current: Decimal,
+
+    /// Balance 1-30 days overdue
+    pub days_1_30: Decimal,
+
+    /// Balance 31-60 days overdue
+    pub days_31_60: Decimal,
+
+    /// Balance 61-90 days overdue
+    pub days_61_90: Decimal,
+
+    /// Balance more than 90 days overdue
+    pub days_over_90: Decimal,
+}
+
+/// Aging at the end of one week.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgingWeek {
+    /// Monday of the week
+    pub week_start: NaiveDate,
+
+    /// Day of the week's latest snapshot (None if none was taken)
+    pub snapshot_date: Option<NaiveDate>,
+
+    /// Aging per currency on that day (empty when nothing was open)
+    pub by_currency: Vec<AgingSnapshot>,
+}
+
+/// Monday of the week containing `date`.
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+}
+
+/// First day covered by a trend of `weeks` weeks ending with this week.
+pub fn trend_start(today: NaiveDate, weeks: u32) -> NaiveDate {
+    week_start(today) - Duration::weeks(i64::from(weeks.max(1)) - 1)
+}
+
+/// Groups daily snapshots into a weekly series.
+///
+/// Each week is represented by its latest snapshot day; every currency
+/// recorded that day is included. Weeks without snapshots are kept so the
+/// series has no gaps.
+///
+/// # Arguments
+///
+/// * `snapshots` - Snapshots from `trend_start(today, weeks)` on, in any order
+/// * `today` - Current date (the last week is the one containing it)
+/// * `weeks` - Number of weeks in the series
+pub fn weekly_trend(snapshots: &[AgingSnapshot], today: NaiveDate, weeks: u32) -> Vec<AgingWeek> {
+    let first = trend_start(today, weeks);
+
+    (0..i64::from(weeks.max(1)))
+        .map(|i| {
+            let start = first + Duration::weeks(i);
+            let end = start + Duration::days(6);
+            let latest = snapshots
+                .iter()
+                .map(|s| s.snapshot_date)
+                .filter(|date| (start..=end).contains(date))
+                .max();
+
+            let mut by_currency: Vec<AgingSnapshot> = latest
+                .map(|date| snapshots.iter().filter(|s| s.snapshot_date == date).cloned().collect())
+                .unwrap_or_default();
+            by_currency.sort_by(|a, b| a.currency.cmp(&b.currency));
+
+            AgingWeek {
+                week_start: start,
+                snapshot_date: latest,
+                by_currency,
+            }
+        })
+        .collect()
+}
+
+/// Loads a user's weekly aging trend.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `today` - Current date
+/// * `weeks` - Number of weeks, ending with the current one
+///
+/// # Returns
+///
+/// Returns one `AgingWeek` per week, oldest first.
+pub async fn aging_trend(
+    pool: &PgPool,
+    user_id: Uuid,
+    today: NaiveDate,
+    weeks: u32,
+) -> Result<Vec<AgingWeek>, anyhow::Error> {
+    let snapshots = sqlx::query_as::<_, AgingSnapshot>(
+        r#"
+        SELECT snapshot_date, currency, invoice_count, outstanding,
+            days_1_30 + days_31_60 + days_61_90 + days_over_90 AS overdue,
+            current, days_1_30, days_31_60, days_61_90, days_over_90
+        FROM aging_snapshots
+        WHERE user_id = $1 AND snapshot_date >= $2 AND snapshot_date <= $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(trend_start(today, weeks))
+    .bind(today)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(weekly_trend(&snapshots, today, weeks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn snapshot(snapshot_date: NaiveDate, currency: &str, overdue: i64) -> AgingSnapshot {
+        AgingSnapshot {
+            snapshot_date,
+            currency: currency.to_string(),
+            invoice_count: 1,
+            outstanding: Decimal::from(overdue + 10),
+            overdue: Decimal::from(overdue),
+            current: Decimal::from(10),
+            days_1_30: Decimal::from(overdue),
+            days_31_60: Decimal::ZERO,
+            days_61_90: Decimal::ZERO,
+            days_over_90: Decimal::ZERO,
+        }
+    }
+
+    fn client(name: &str, currency: &str, current: i64, days_1_30: i64) -> ClientAging {
+        ClientAging {
+            client_name: name.to_string(),
+            client_email: None,
+            currency: currency.to_string(),
+            invoice_count: 1,
+            outstanding: Decimal::from(current + days_1_30),
+            overdue: Decimal::from(days_1_30),
+            current: Decimal::from(current),
+            days_1_30: Decimal::from(days_1_30),
+            days_31_60: Decimal::ZERO,
+            days_61_90: Decimal::ZERO,
+            days_over_90: Decimal::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_totals_are_summed_per_currency() {
+        let clients = vec![
+            client("Acme", "USD", 100, 50),
+            client("Globex", "EUR", 0, 20),
+            client("Initech", "USD", 10, 0),
+        ];
+
+        let totals = aging_totals(&clients);
+
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[0].currency, "EUR");
+        assert_eq!(totals[0].overdue, Decimal::from(20));
+        assert_eq!(totals[1].currency, "USD");
+        assert_eq!(totals[1].invoice_count, 2);
+        assert_eq!(totals[1].outstanding, Decimal::from(160));
+        assert_eq!(totals[1].current, Decimal::from(110));
+        assert_eq!(totals[1].days_1_30, Decimal::from(50));
+    }
+
+    #[test]
+    fn test_trend_start_is_a_monday() {
+        // 2024-03-13 is a Wednesday
+        assert_eq!(trend_start(date(3, 13), 1), date(3, 11));
+        assert_eq!(trend_start(date(3, 13), 3), date(2, 26));
+    }
+
+    #[test]
+    fn test_weeks_use_their_latest_snapshot_and_keep_gaps() {
+        let snapshots = vec![
+            snapshot(date(2, 27), "USD", 5),
+            snapshot(date(3, 1), "USD", 7),
+            snapshot(date(3, 1), "EUR", 3),
+            snapshot(date(3, 12), "USD", 9),
+        ];
+
+        let trend = weekly_trend(&snapshots, date(3, 13), 3);
+
+        assert_eq!(trend.len(), 3);
+        assert_eq!(trend[0].snapshot_date, Some(date(3, 1)));
+        assert_eq!(trend[0].by_currency.len(), 2);
+        assert_eq!(trend[0].by_currency[0].currency, "EUR");
+        assert_eq!(trend[0].by_currency[1].overdue, Decimal::from(7));
+        assert_eq!(trend[1].week_start, date(3, 4));
+        assert_eq!(trend[1].snapshot_date, None);
+        assert!(trend[1].by_currency.is_empty());
+        assert_eq!(trend[2].snapshot_date, Some(date(3, 12)));
+    }
+}
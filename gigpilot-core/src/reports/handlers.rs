@@ -0,0 +1,137 @@
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::auth::CurrentUser;
+use crate::currency::ExchangeRateService;
+use crate::reports::aging::{
+    aging_trend, client_aging, AgingReport, AgingWeek, DEFAULT_TREND_WEEKS, MAX_TREND_WEEKS,
+};
+use crate::reports::{invoice_summary, InvoiceSummary};
+use crate::settings::SettingsCache;
+
+/// Query parameters for the invoice summary.
+#[derive(Debug, Deserialize)]
+pub struct SummaryParams {
+    /// Only include invoices issued on or after this date
+    pub from: Option<NaiveDate>,
+
+    /// Only include invoices issued on or before this date
+    pub to: Option<NaiveDate>,
+}
+
+/// Invoice summary endpoint handler.
+///
+/// Handles GET requests to `/api/reports/summary?from=&to=`, returning
+/// invoiced, paid, outstanding and overdue totals in the user's base
+/// currency, converted at the rates of the invoice and payment dates.
+pub async fn summary_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(rates): Extension<ExchangeRateService>,
+    Extension(settings_cache): Extension<SettingsCache>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(params): Query<SummaryParams>,
+) -> Result<Json<InvoiceSummary>, (StatusCode, Json<Value>)> {
+    if let (Some(from), Some(to)) = (params.from, params.to) {
+        if from > to {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "error": "from must not be after to" })),
+            ));
+        }
+    }
+
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "failed to build summary" })),
+        )
+    };
+
+    let settings = settings_cache.user_settings(user_id).await.map_err(|e| {
+        error!("Failed to load settings for user {}: {}", user_id, e);
+        internal_error()
+    })?;
+
+    let summary = invoice_summary(
+        &pool,
+        &rates,
+        user_id,
+        &settings.base_currency,
+        params.from,
+        params.to,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to build invoice summary for user {}: {}", user_id, e);
+        internal_error()
+    })?;
+
+    Ok(Json(summary))
+}
+
+/// Query parameters for the aging trend.
+#[derive(Debug, Deserialize)]
+pub struct AgingParams {
+    /// Number of weeks, ending with the current one (default 12)
+    pub weeks: Option<u32>,
+}
+
+/// Aging report endpoint handler.
+///
+/// Handles GET requests to `/api/reports/aging`, returning today's open
+/// balances per client bucketed by days overdue.
+pub async fn aging_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<AgingReport>, (StatusCode, Json<Value>)> {
+    let report = client_aging(&pool, user_id, Utc::now().date_naive())
+        .await
+        .map_err(|e| {
+            error!("Failed to build aging report for user {}: {}", user_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to build aging report" })),
+            )
+        })?;
+
+    Ok(Json(report))
+}
+
+/// Aging trend endpoint handler.
+///
+/// Handles GET requests to `/api/reports/aging/trend?weeks=`, returning the
+/// receivables aging per currency at the end of each week, from the daily
+/// snapshots recorded by the worker.
+pub async fn aging_trend_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(params): Query<AgingParams>,
+) -> Result<Json<Vec<AgingWeek>>, (StatusCode, Json<Value>)> {
+    let weeks = params.weeks.unwrap_or(DEFAULT_TREND_WEEKS);
+    if !(1..=MAX_TREND_WEEKS).contains(&weeks) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": format!("weeks must be between 1 and {}", MAX_TREND_WEEKS) })),
+        ));
+    }
+
+    let trend = aging_trend(&pool, user_id, Utc::now().date_naive(), weeks)
+        .await
+        .map_err(|e| {
+            error!("Failed to load aging trend for user {}: {}", user_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to load aging trend" })),
+            )
+        })?;
+
+    Ok(Json(trend))
+}
@@ -0,0 +1,523 @@
+pub mod aging;
+pub mod handlers;
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::currency::{Currency, ExchangeRateService, Money, MoneyError};
+use crate::models::exchange_rate_override::ExchangeRateOverride;
+
+/// Invoice totals in a single currency.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct CurrencyTotals {
+    /// Currency code (ISO 4217)
+    pub currency: String,
+
+    /// Number of invoices in this currency
+    pub invoice_count: i64,
+
+    /// Sum of invoice totals
+    pub invoiced: Decimal,
+
+    /// Sum of recorded payments
+    pub paid: Decimal,
+
+    /// Sum of balances still due
+    pub outstanding: Decimal,
+
+    /// Sum of balances on invoices past their due date
+    pub overdue: Decimal,
+}
+
+/// Invoice summary for reports and dashboards.
+///
+/// Per-currency totals are kept as-is; the top-level figures are converted
+/// into the user's base currency at historical exchange rates: invoice
+/// amounts at the rate of the issue date, payments at the rate of the
+/// payment date, unless the invoice fixes its own rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoiceSummary {
+    /// Currency the converted figures are in
+    pub base_currency: String,
+
+    /// First issue date included, if the report is limited to a period
+    pub from: Option<NaiveDate>,
+
+    /// Last issue date included, if the report is limited to a period
+    pub to: Option<NaiveDate>,
+
+    /// Total invoiced, in the base currency
+    pub invoiced: Decimal,
+
+    /// Total paid, in the base currency
+    pub paid: Decimal,
+
+    /// Total outstanding, in the base currency
+    pub outstanding: Decimal,
+
+    /// Total overdue, in the base currency
+    pub overdue: Decimal,
+
+    /// Unconverted totals per invoice currency
+    pub by_currency: Vec<CurrencyTotals>,
+
+    /// Invoices left out of the totals because their amounts can't be read
+    pub skipped: Vec<SkippedInvoice>,
+}
+
+/// An invoice left out of the summary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedInvoice {
+    /// Invoice ID
+    pub invoice_id: Uuid,
+
+    /// Why it was left out (e.g. "Unsupported currency: XYZ")
+    pub reason: String,
+}
+
+/// Invoice figures needed for the summary.
+#[derive(Debug, Clone, FromRow)]
+pub struct ReportInvoice {
+    /// Invoice ID
+    pub id: Uuid,
+
+    /// Invoice currency
+    pub currency: String,
+
+    /// Date the invoice was issued
+    pub issue_date: NaiveDate,
+
+    /// Due date, if any
+    pub due_date: Option<NaiveDate>,
+
+    /// Invoice total
+    pub total: Decimal,
+
+    /// Sum of recorded payments
+    pub amount_paid: Decimal,
+
+    /// Fixed exchange rate (JSON `ExchangeRateOverride`)
+    pub exchange_rate_override: Option<Value>,
+}
+
+/// Payment figures needed for the summary.
+#[derive(Debug, Clone, FromRow)]
+pub struct ReportPayment {
+    /// Invoice the payment settles
+    pub invoice_id: Uuid,
+
+    /// Amount paid
+    pub amount: Decimal,
+
+    /// Payment currency
+    pub currency: String,
+
+    /// Date the payment was made
+    pub paid_on: NaiveDate,
+}
+
+/// Date whose rate converts an amount dated `date` (future dates use today's).
+fn rate_date(date: NaiveDate, today: NaiveDate) -> NaiveDate {
+    date.min(today)
+}
+
+/// Invoiced, paid, outstanding and overdue amounts in one currency.
+#[derive(Debug, Clone, Copy)]
+struct Amounts {
+    invoiced: Money,
+    paid: Money,
+    outstanding: Money,
+    overdue: Money,
+}
+
+impl Amounts {
+    fn zero(currency: Currency) -> Self {
+        Self {
+            invoiced: Money::zero(currency),
+            paid: Money::zero(currency),
+            outstanding: Money::zero(currency),
+            overdue: Money::zero(currency),
+        }
+    }
+
+    fn checked_add(self, other: Amounts) -> Result<Self, MoneyError> {
+        Ok(Self {
+            invoiced: self.invoiced.checked_add(other.invoiced)?,
+            paid: self.paid.checked_add(other.paid)?,
+            outstanding: self.outstanding.checked_add(other.outstanding)?,
+            overdue: self.overdue.checked_add(other.overdue)?,
+        })
+    }
+}
+
+/// One invoice's amounts, in its own currency and in the base currency.
+///
+/// # Returns
+///
+/// Returns `Err` with the reason if the invoice can't be summarized: its
+/// currency or exchange-rate override can't be read, a payment is in
+/// another currency, or an amount is out of range.
+///
+/// # Errors
+///
+/// Returns an error if a needed rate is missing.
+fn invoice_amounts(
+    invoice: &ReportInvoice,
+    payments: &[ReportPayment],
+    base: Currency,
+    today: NaiveDate,
+    market_rate: &dyn Fn(Currency, NaiveDate) -> Result<Decimal, anyhow::Error>,
+) -> Result<Result<(Amounts, Amounts), String>, anyhow::Error> {
+    let total = match Money::parse(invoice.total, &invoice.currency) {
+        Ok(total) => total,
+        Err(err) => return Ok(Err(err.to_string())),
+    };
+    let fixed = match ExchangeRateOverride::parse(invoice.exchange_rate_override.as_ref()) {
+        Ok(fixed) => fixed.and_then(|o| o.rate_into(base.code())),
+        Err(err) => return Ok(Err(err.to_string())),
+    };
+
+    // Payments are in the invoice currency; one that isn't can't be added up
+    let mut recorded_payments = Vec::new();
+    for payment in payments.iter().filter(|p| p.invoice_id == invoice.id) {
+        let amount = match Money::parse(payment.amount, &payment.currency) {
+            Ok(amount) => amount,
+            Err(err) => return Ok(Err(err.to_string())),
+        };
+        if amount.currency != total.currency {
+            let mismatch = MoneyError::CurrencyMismatch {
+                left: total.currency,
+                right: amount.currency,
+            };
+            return Ok(Err(mismatch.to_string()));
+        }
+        let rate = match fixed {
+            Some(rate) => rate,
+            None => market_rate(amount.currency, payment.paid_on)?,
+        };
+        recorded_payments.push((amount, rate));
+    }
+    let issue_rate = match fixed {
+        Some(rate) => rate,
+        None => market_rate(total.currency, invoice.issue_date)?,
+    };
+
+    let amounts = || -> Result<(Amounts, Amounts), MoneyError> {
+        let amount_paid = Money::new(invoice.amount_paid, total.currency);
+        let outstanding = total.checked_sub(amount_paid)?.max_zero();
+        let overdue = match invoice.due_date {
+            Some(due) if due < today => outstanding,
+            _ => Money::zero(total.currency),
+        };
+
+        // Payments convert at the rate of their own date; any part of
+        // amount_paid without payment records uses the issue-date rate.
+        let mut paid = Money::zero(base);
+        let mut recorded = Money::zero(total.currency);
+        for (amount, rate) in &recorded_payments {
+            paid = paid.checked_add(amount.convert(*rate, base)?)?;
+            recorded = recorded.checked_add(*amount)?;
+        }
+        if amount_paid.amount > recorded.amount {
+            paid = paid.checked_add(amount_paid.checked_sub(recorded)?.convert(issue_rate, base)?)?;
+        }
+
+        let unconverted = Amounts {
+            invoiced: total,
+            paid: amount_paid,
+            outstanding,
+            overdue,
+        };
+        let converted = Amounts {
+            invoiced: total.convert(issue_rate, base)?,
+            paid,
+            outstanding: outstanding.convert(issue_rate, base)?,
+            overdue: overdue.convert(issue_rate, base)?,
+        };
+        Ok((unconverted, converted))
+    };
+    Ok(amounts().map_err(|err| err.to_string()))
+}
+
+/// Builds the summary from already loaded invoices, payments and rates.
+///
+/// Invoices whose amounts can't be read, such as a legacy currency code
+/// or a payment in another currency, are left out of the totals and
+/// listed in `skipped`.
+///
+/// # Arguments
+///
+/// * `base_currency` - Currency to convert into
+/// * `today` - Current date (for overdue balances and future dates)
+/// * `invoices` - Invoices included in the report
+/// * `payments` - Payments recorded against those invoices
+/// * `rates` - Market rates into `base_currency`, keyed by currency and date
+///
+/// # Errors
+///
+/// Returns an error if the base currency is unsupported or a needed rate
+/// is missing.
+pub fn summarize(
+    base_currency: &str,
+    today: NaiveDate,
+    invoices: &[ReportInvoice],
+    payments: &[ReportPayment],
+    rates: &HashMap<(String, NaiveDate), Decimal>,
+) -> Result<InvoiceSummary, anyhow::Error> {
+    let base = Currency::parse(base_currency)?;
+    let market_rate = |currency: Currency, date: NaiveDate| -> Result<Decimal, anyhow::Error> {
+        if currency == base {
+            return Ok(Decimal::ONE);
+        }
+        let date = rate_date(date, today);
+        rates
+            .get(&(currency.code().to_string(), date))
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("No exchange rate from {} to {} on {}", currency, base, date))
+    };
+
+    let mut converted = Amounts::zero(base);
+    let mut by_currency: BTreeMap<Currency, (i64, Amounts)> = BTreeMap::new();
+    let mut skipped = Vec::new();
+
+    for invoice in invoices {
+        let (unconverted, in_base) = match invoice_amounts(invoice, payments, base, today, &market_rate)? {
+            Ok(amounts) => amounts,
+            Err(reason) => {
+                skipped.push(SkippedInvoice {
+                    invoice_id: invoice.id,
+                    reason,
+                });
+                continue;
+            }
+        };
+
+        let currency = unconverted.invoiced.currency;
+        let (count, totals) = by_currency
+            .get(&currency)
+            .copied()
+            .unwrap_or((0, Amounts::zero(currency)));
+        match (totals.checked_add(unconverted), converted.checked_add(in_base)) {
+            (Ok(totals), Ok(in_base)) => {
+                by_currency.insert(currency, (count + 1, totals));
+                converted = in_base;
+            }
+            (Err(err), _) | (_, Err(err)) => skipped.push(SkippedInvoice {
+                invoice_id: invoice.id,
+                reason: err.to_string(),
+            }),
+        }
+    }
+
+    Ok(InvoiceSummary {
+        base_currency: base.code().to_string(),
+        from: None,
+        to: None,
+        invoiced: converted.invoiced.amount,
+        paid: converted.paid.amount,
+        outstanding: converted.outstanding.amount,
+        overdue: converted.overdue.amount,
+        by_currency: by_currency
+            .into_iter()
+            .map(|(currency, (invoice_count, totals))| CurrencyTotals {
+                currency: currency.code().to_string(),
+                invoice_count,
+                invoiced: totals.invoiced.amount,
+                paid: totals.paid.amount,
+                outstanding: totals.outstanding.amount,
+                overdue: totals.overdue.amount,
+            })
+            .collect(),
+        skipped,
+    })
+}
+
+/// Builds the invoice summary for a user in their base currency.
+///
+/// Draft, cancelled and deleted invoices are excluded.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `rates` - Exchange-rate service used for conversion
+/// * `user_id` - ID of the user
+/// * `base_currency` - Currency to convert into
+/// * `from` - Only include invoices issued on or after this date
+/// * `to` - Only include invoices issued on or before this date
+///
+/// # Returns
+///
+/// Returns the `InvoiceSummary`, or an error if a query or rate lookup fails.
+pub async fn invoice_summary(
+    pool: &PgPool,
+    rates: &ExchangeRateService,
+    user_id: Uuid,
+    base_currency: &str,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<InvoiceSummary, anyhow::Error> {
+    let today = Utc::now().date_naive();
+
+    let invoices = sqlx::query_as::<_, ReportInvoice>(
+        r#"
+        SELECT id, currency, issue_date, due_date, total, amount_paid, exchange_rate_override
+        FROM invoices
+        WHERE user_id = $1
+            AND is_deleted = false
+            AND status NOT IN ('draft', 'cancelled')
+            AND ($2::date IS NULL OR issue_date >= $2)
+            AND ($3::date IS NULL OR issue_date <= $3)
+        "#,
+    )
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    let invoice_ids: Vec<Uuid> = invoices.iter().map(|invoice| invoice.id).collect();
+    let payments = sqlx::query_as::<_, ReportPayment>(
+        r#"
+        SELECT invoice_id, amount, currency, paid_on
+        FROM payments
+        WHERE user_id = $1 AND invoice_id = ANY($2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(&invoice_ids)
+    .fetch_all(pool)
+    .await?;
+
+    // Look up every historical rate the report needs (backfilled by the
+    // worker); invoices in an unsupported currency are skipped by summarize
+    let base = Currency::parse(base_currency)?;
+    let needed = invoices
+        .iter()
+        .map(|invoice| (invoice.currency.as_str(), invoice.issue_date))
+        .chain(payments.iter().map(|payment| (payment.currency.as_str(), payment.paid_on)))
+        .filter_map(|(currency, date)| Some((Currency::parse(currency).ok()?, date)));
+    let mut market = HashMap::new();
+    for (currency, date) in needed {
+        let key = (currency.code().to_string(), rate_date(date, today));
+        if currency == base || market.contains_key(&key) {
+            continue;
+        }
+        let rate = rates.rate(currency.code(), base.code(), key.1).await?;
+        market.insert(key, rate);
+    }
+
+    let mut summary = summarize(base_currency, today, &invoices, &payments, &market)?;
+    summary.from = from;
+    summary.to = to;
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn invoice(currency: &str, issue_day: u32, total: i64, amount_paid: i64) -> ReportInvoice {
+        ReportInvoice {
+            id: Uuid::new_v4(),
+            currency: currency.to_string(),
+            issue_date: date(issue_day),
+            due_date: Some(date(issue_day + 14)),
+            total: Decimal::from(total),
+            amount_paid: Decimal::from(amount_paid),
+            exchange_rate_override: None,
+        }
+    }
+
+    #[test]
+    fn test_converts_at_issue_and_payment_date_rates() {
+        let eur = invoice("EUR", 1, 100, 40);
+        let payment = ReportPayment {
+            invoice_id: eur.id,
+            amount: Decimal::from(40),
+            currency: "EUR".to_string(),
+            paid_on: date(10),
+        };
+        let rates = HashMap::from([
+            (("EUR".to_string(), date(1)), Decimal::from_str("1.10").unwrap()),
+            (("EUR".to_string(), date(10)), Decimal::from_str("1.20").unwrap()),
+        ]);
+
+        let summary = summarize("USD", date(31), &[eur], &[payment], &rates).unwrap();
+        assert_eq!(summary.invoiced, Decimal::from(110));
+        assert_eq!(summary.paid, Decimal::from(48));
+        assert_eq!(summary.outstanding, Decimal::from(66));
+        assert_eq!(summary.overdue, Decimal::from(66));
+        assert_eq!(summary.by_currency[0].paid, Decimal::from(40));
+    }
+
+    #[test]
+    fn test_override_rate_wins_for_its_base_currency() {
+        let mut fixed = invoice("GBP", 1, 200, 0);
+        fixed.exchange_rate_override = Some(json!({ "base_currency": "USD", "rate": 1.25 }));
+        let mut other_base = invoice("GBP", 2, 100, 0);
+        other_base.exchange_rate_override = Some(json!({ "base_currency": "EUR", "rate": 1.15 }));
+        let rates = HashMap::from([(("GBP".to_string(), date(2)), Decimal::from_str("1.30").unwrap())]);
+
+        let summary = summarize("USD", date(3), &[fixed, other_base], &[], &rates).unwrap();
+        assert_eq!(summary.invoiced, Decimal::from(380));
+        assert_eq!(summary.overdue, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_invoices_that_cannot_be_read_are_skipped() {
+        let eur = invoice("EUR", 1, 100, 40);
+        let payment = ReportPayment {
+            invoice_id: eur.id,
+            amount: Decimal::from(40),
+            currency: "GBP".to_string(),
+            paid_on: date(10),
+        };
+        let legacy = invoice("XYZ", 1, 500, 0);
+        let mut lowercase = invoice("usd", 2, 80, 0);
+        lowercase.due_date = None;
+        let mut huge = invoice("USD", 3, 0, 0);
+        huge.total = Decimal::MAX;
+        let usd = invoice("USD", 4, 20, 0);
+        let rates = HashMap::from([
+            (("EUR".to_string(), date(1)), Decimal::ONE),
+            (("GBP".to_string(), date(10)), Decimal::ONE),
+        ]);
+
+        let invoices = [eur.clone(), legacy.clone(), lowercase, huge.clone(), usd];
+        let summary = summarize("USD", date(31), &invoices, &[payment], &rates).unwrap();
+        assert_eq!(summary.invoiced, Decimal::from(100));
+        assert_eq!(summary.overdue, Decimal::from(20));
+        assert_eq!(summary.by_currency.len(), 1);
+        assert_eq!(summary.by_currency[0].currency, "USD");
+        assert_eq!(summary.by_currency[0].invoice_count, 2);
+
+        let skipped: Vec<_> = summary.skipped.iter().map(|s| (s.invoice_id, s.reason.as_str())).collect();
+        assert_eq!(
+            skipped,
+            [
+                (eur.id, "Cannot combine EUR and GBP amounts"),
+                (legacy.id, "Unsupported currency: XYZ"),
+                (huge.id, "Amount out of range"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_rate_is_an_error() {
+        let summary = summarize("USD", date(31), &[invoice("JPY", 5, 1000, 0)], &[], &HashMap::new());
+        assert!(summary.is_err());
+    }
+}
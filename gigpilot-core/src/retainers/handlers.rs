@@ -0,0 +1,174 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::models::retainer::{CreateRetainer, Retainer, RetainerPeriod, UpdateRetainer};
+use crate::projects::find_project;
+use crate::retainers::{
+    create_retainer, drawdown, find_retainer, latest_period, list_periods, list_retainers, update_retainer,
+    used_hours, validate_create, validate_update, Drawdown,
+};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// The current period of a retainer and its drawdown so far.
+#[derive(Debug, Serialize)]
+pub struct CurrentPeriod {
+    pub period: RetainerPeriod,
+    pub drawdown: Drawdown,
+}
+
+/// A retainer with its current period.
+#[derive(Debug, Serialize)]
+pub struct RetainerResponse {
+    pub retainer: Retainer,
+
+    /// Latest open period (`null` before the first one opens or once ended)
+    pub current: Option<CurrentPeriod>,
+}
+
+/// List retainers endpoint handler.
+///
+/// Handles GET requests to `/api/retainers`.
+pub async fn list_retainers_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<Retainer>>, StatusCode> {
+    let retainers = list_retainers(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list retainers of user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(retainers))
+}
+
+/// Create retainer endpoint handler.
+///
+/// Handles POST requests to `/api/retainers`. The fee is invoiced to the
+/// project's client, so returns 422 if the project has none. The first
+/// period opens (and is invoiced) on the next worker run from the start
+/// date.
+pub async fn create_retainer_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateRetainer>,
+) -> Result<(StatusCode, Json<Retainer>), (StatusCode, Json<Value>)> {
+    let project_id = request.project_id;
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to create retainer for project {}: {}", project_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create retainer")
+    };
+
+    let project = find_project(&pool, user_id, project_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "project_id must reference one of your projects"))?;
+    let Some(client_id) = project.client_id else {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, "the project has no client to invoice"));
+    };
+    if let Err(message) = validate_create(&request, &project.currency) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let retainer = create_retainer(&pool, user_id, &project, client_id, request)
+        .await
+        .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(retainer)))
+}
+
+/// Get retainer endpoint handler.
+///
+/// Handles GET requests to `/api/retainers/:id`: the retainer and the
+/// drawdown of its current period.
+pub async fn get_retainer_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(retainer_id): Path<Uuid>,
+) -> Result<Json<RetainerResponse>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load retainer {}: {}", retainer_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let retainer = find_retainer(&pool, user_id, retainer_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let current = match latest_period(&pool, retainer.id).await.map_err(internal_error)? {
+        Some(period) if period.closed_at.is_none() => {
+            let used = used_hours(&pool, &retainer, &period).await.map_err(internal_error)?;
+            Some(CurrentPeriod {
+                drawdown: drawdown(&period, used),
+                period,
+            })
+        }
+        _ => None,
+    };
+
+    Ok(Json(RetainerResponse { retainer, current }))
+}
+
+/// Update retainer endpoint handler.
+///
+/// Handles PUT requests to `/api/retainers/:id`. Pausing stops new periods
+/// from opening; ending the retainer can't be undone.
+pub async fn update_retainer_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(retainer_id): Path<Uuid>,
+    Json(update): Json<UpdateRetainer>,
+) -> Result<Json<Retainer>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to update retainer {}: {}", retainer_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update retainer")
+    };
+
+    let current = find_retainer(&pool, user_id, retainer_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "retainer not found"))?;
+    if let Err(message) = validate_update(&current, &update) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let retainer = update_retainer(&pool, user_id, retainer_id, update)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "retainer not found"))?;
+
+    Ok(Json(retainer))
+}
+
+/// List retainer periods endpoint handler.
+///
+/// Handles GET requests to `/api/retainers/:id/periods`: the used, carried
+/// and expired hours of every period, newest first.
+pub async fn list_periods_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(retainer_id): Path<Uuid>,
+) -> Result<Json<Vec<RetainerPeriod>>, StatusCode> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to list periods of retainer {}: {}", retainer_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    find_retainer(&pool, user_id, retainer_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let periods = list_periods(&pool, user_id, retainer_id).await.map_err(internal_error)?;
+
+    Ok(Json(periods))
+}
@@ -0,0 +1,684 @@
+//! Retainer agreements.
+//!
+//! A retainer is a monthly fee for a number of hours on a project. Periods
+//! run monthly from the retainer's start date. The worker opens each period
+//! with the included hours (plus hours rolled over from the previous one)
+//! and drafts the fee's invoice, then settles the period once it is over:
+//! billable time tracked on the project during the period and not billed
+//! otherwise is drawn down from the available hours and linked to the
+//! period's invoice, so invoicing the project's time later only bills the
+//! overage. Unused hours roll over (up to `max_rollover_hours`) or expire,
+//! per the retainer's policy.
+//!
+//! Paused retainers open no periods; periods that would have started while
+//! paused are skipped, not invoiced afterwards.
+
+pub mod handlers;
+
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::currency::{normalize_currency, validate_amount};
+use crate::invoices::history::apply_current_audit_context;
+use crate::invoices::DueDateRules;
+use crate::models::client::Client;
+use crate::models::line_item::LineItem;
+use crate::models::project::Project;
+use crate::models::retainer::{
+    CreateRetainer, Retainer, RetainerPeriod, RetainerStatus, RolloverPolicy, UpdateRetainer,
+};
+use crate::models::sync_change::SyncOperation;
+use crate::models::time_entry::TimeEntry;
+use crate::projects::invoicing::{default_tax_rate, insert_draft_invoice, DraftInvoice};
+use crate::sync::encryption::encrypts_any;
+use crate::sync::server::record_server_change;
+
+/// Longest retainer name accepted.
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Drawdown of a retainer period.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drawdown {
+    /// Included plus rolled-over hours
+    pub available_hours: Decimal,
+
+    /// Billable hours tracked during the period
+    pub used_hours: Decimal,
+
+    /// Hours left (zero once used up)
+    pub remaining_hours: Decimal,
+
+    /// Hours tracked beyond the available ones
+    pub overage_hours: Decimal,
+}
+
+/// Computes a period's drawdown from the hours used.
+pub fn drawdown(period: &RetainerPeriod, used_hours: Decimal) -> Drawdown {
+    let available_hours = period.included_hours + period.rolled_over_hours;
+    Drawdown {
+        available_hours,
+        used_hours,
+        remaining_hours: (available_hours - used_hours).max(Decimal::ZERO),
+        overage_hours: (used_hours - available_hours).max(Decimal::ZERO),
+    }
+}
+
+/// Validates a number of hours.
+fn validate_hours(field: &str, hours: Option<Decimal>) -> Result<(), String> {
+    match hours {
+        Some(hours) if hours.is_sign_negative() || hours != hours.round_dp(2) => {
+            Err(format!("{} must not be negative and allows at most 2 decimal places", field))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates a retainer name.
+fn validate_name(name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("name must be at most {} characters", MAX_NAME_LENGTH));
+    }
+    Ok(())
+}
+
+/// Validates a retainer creation request.
+///
+/// # Arguments
+///
+/// * `request` - The retainer to create
+/// * `project_currency` - Currency of its project, used when none is given
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create(request: &CreateRetainer, project_currency: &str) -> Result<(), String> {
+    validate_name(&request.name)?;
+    validate_hours("monthly_hours", Some(request.monthly_hours))?;
+    validate_hours("max_rollover_hours", request.max_rollover_hours)?;
+    let currency = normalize_currency(request.currency.as_deref().unwrap_or(project_currency))
+        .map_err(|e| e.to_string())?;
+    validate_amount(request.monthly_amount, &currency).map_err(|e| format!("monthly_amount: {}", e))?;
+    let start_date = request.start_date.unwrap_or_else(|| Utc::now().date_naive());
+    if request.end_date.is_some_and(|end_date| end_date < start_date) {
+        return Err("end_date must not be before start_date".to_string());
+    }
+    Ok(())
+}
+
+/// Validates an update against the current retainer.
+pub fn validate_update(current: &Retainer, update: &UpdateRetainer) -> Result<(), String> {
+    if let Some(name) = &update.name {
+        validate_name(name)?;
+    }
+    validate_hours("monthly_hours", update.monthly_hours)?;
+    validate_hours("max_rollover_hours", update.max_rollover_hours)?;
+    if let Some(amount) = update.monthly_amount {
+        validate_amount(amount, &current.currency).map_err(|e| format!("monthly_amount: {}", e))?;
+    }
+    if update.end_date.is_some_and(|end_date| end_date < current.start_date) {
+        return Err("end_date must not be before start_date".to_string());
+    }
+    if current.status == RetainerStatus::Ended && update.status.is_some_and(|s| s != RetainerStatus::Ended) {
+        return Err("an ended retainer can't be resumed".to_string());
+    }
+    Ok(())
+}
+
+/// First and last day of a retainer's period.
+///
+/// Period `index` starts `index` months after the start date (on the same
+/// day, or the month's last day when it is shorter) and ends the day before
+/// the next one starts.
+pub fn period_bounds(start_date: NaiveDate, index: u32) -> Option<(NaiveDate, NaiveDate)> {
+    let from = start_date.checked_add_months(Months::new(index))?;
+    let next = start_date.checked_add_months(Months::new(index + 1))?;
+    Some((from, next.pred_opt()?))
+}
+
+/// Index of the period containing a date, or `None` before the start date.
+pub fn period_index(start_date: NaiveDate, date: NaiveDate) -> Option<u32> {
+    if date < start_date {
+        return None;
+    }
+    let months = (date.year() - start_date.year()) * 12 + date.month() as i32 - start_date.month() as i32;
+    let mut index = u32::try_from(months).ok()?;
+    while index > 0 && period_bounds(start_date, index).is_some_and(|(from, _)| from > date) {
+        index -= 1;
+    }
+    Some(index)
+}
+
+/// Splits a period's unused hours into carried and expired ones.
+///
+/// # Returns
+///
+/// Returns `(carried, expired)`.
+pub fn settle(
+    policy: RolloverPolicy,
+    max_rollover_hours: Option<Decimal>,
+    available_hours: Decimal,
+    used_hours: Decimal,
+) -> (Decimal, Decimal) {
+    let unused = (available_hours - used_hours).max(Decimal::ZERO);
+    match policy {
+        RolloverPolicy::Expire => (Decimal::ZERO, unused),
+        RolloverPolicy::Rollover => {
+            let carried = max_rollover_hours.map_or(unused, |max| unused.min(max));
+            (carried, unused - carried)
+        }
+    }
+}
+
+/// Picks the entries drawn down from the available hours.
+///
+/// Entries are drawn in order while hours are left; the entry that uses the
+/// last of them is drawn whole.
+///
+/// # Arguments
+///
+/// * `entries` - The period's entries (ID and hours), oldest first
+/// * `available_hours` - Hours available in the period
+pub fn drawn_entries(entries: &[(Uuid, Decimal)], available_hours: Decimal) -> Vec<Uuid> {
+    let mut drawn = Vec::new();
+    let mut used = Decimal::ZERO;
+    for (id, hours) in entries {
+        if used >= available_hours {
+            break;
+        }
+        used += *hours;
+        drawn.push(*id);
+    }
+    drawn
+}
+
+/// Creates a retainer for a project's client.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `project` - The project whose time is drawn down
+/// * `client_id` - The project's client
+/// * `request` - The retainer to create (see [`validate_create`])
+pub async fn create_retainer(
+    pool: &PgPool,
+    user_id: Uuid,
+    project: &Project,
+    client_id: Uuid,
+    request: CreateRetainer,
+) -> Result<Retainer, anyhow::Error> {
+    let currency = normalize_currency(request.currency.as_deref().unwrap_or(&project.currency))?;
+
+    let retainer = sqlx::query_as::<_, Retainer>(
+        r#"
+        INSERT INTO retainers (
+            user_id, client_id, project_id, name, monthly_hours, monthly_amount, currency,
+            rollover_policy, max_rollover_hours, start_date, end_date
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(project.id)
+    .bind(request.name.trim())
+    .bind(request.monthly_hours)
+    .bind(request.monthly_amount)
+    .bind(currency)
+    .bind(request.rollover_policy.unwrap_or_default())
+    .bind(request.max_rollover_hours)
+    .bind(request.start_date.unwrap_or_else(|| Utc::now().date_naive()))
+    .bind(request.end_date)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(retainer)
+}
+
+/// Lists a user's retainers, newest first.
+pub async fn list_retainers(pool: &PgPool, user_id: Uuid) -> Result<Vec<Retainer>, anyhow::Error> {
+    let retainers = sqlx::query_as::<_, Retainer>(
+        "SELECT * FROM retainers WHERE user_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(retainers)
+}
+
+/// Loads one of a user's retainers.
+///
+/// # Returns
+///
+/// Returns the retainer, or `None` if it does not exist or belongs to
+/// another user.
+pub async fn find_retainer(pool: &PgPool, user_id: Uuid, retainer_id: Uuid) -> Result<Option<Retainer>, anyhow::Error> {
+    let retainer = sqlx::query_as::<_, Retainer>("SELECT * FROM retainers WHERE id = $1 AND user_id = $2")
+        .bind(retainer_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(retainer)
+}
+
+/// Updates a retainer.
+///
+/// Fields left as `None` keep their current value. Hours and fee changes
+/// apply from the next period.
+///
+/// # Returns
+///
+/// Returns the updated retainer, or `None` if it does not exist.
+pub async fn update_retainer(
+    pool: &PgPool,
+    user_id: Uuid,
+    retainer_id: Uuid,
+    update: UpdateRetainer,
+) -> Result<Option<Retainer>, anyhow::Error> {
+    let retainer = sqlx::query_as::<_, Retainer>(
+        r#"
+        UPDATE retainers
+        SET name = COALESCE($3, name),
+            monthly_hours = COALESCE($4, monthly_hours),
+            monthly_amount = COALESCE($5, monthly_amount),
+            rollover_policy = COALESCE($6, rollover_policy),
+            max_rollover_hours = COALESCE($7, max_rollover_hours),
+            end_date = COALESCE($8, end_date),
+            status = COALESCE($9, status)
+        WHERE id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(retainer_id)
+    .bind(user_id)
+    .bind(update.name.map(|n| n.trim().to_string()))
+    .bind(update.monthly_hours)
+    .bind(update.monthly_amount)
+    .bind(update.rollover_policy)
+    .bind(update.max_rollover_hours)
+    .bind(update.end_date)
+    .bind(update.status)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(retainer)
+}
+
+/// Lists a retainer's periods, newest first.
+pub async fn list_periods(pool: &PgPool, user_id: Uuid, retainer_id: Uuid) -> Result<Vec<RetainerPeriod>, anyhow::Error> {
+    let periods = sqlx::query_as::<_, RetainerPeriod>(
+        r#"
+        SELECT * FROM retainer_periods
+        WHERE retainer_id = $1 AND user_id = $2
+        ORDER BY period_start DESC
+        "#,
+    )
+    .bind(retainer_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(periods)
+}
+
+/// Loads a retainer's latest period.
+pub async fn latest_period<'e, E>(executor: E, retainer_id: Uuid) -> Result<Option<RetainerPeriod>, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let period = sqlx::query_as::<_, RetainerPeriod>(
+        "SELECT * FROM retainer_periods WHERE retainer_id = $1 ORDER BY period_start DESC LIMIT 1",
+    )
+    .bind(retainer_id)
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(period)
+}
+
+/// Loads the billable entries a period draws down: live entries of the
+/// retainer's project dated in the period that no other invoice bills,
+/// oldest first.
+async fn period_entries<'e, E>(
+    executor: E,
+    retainer: &Retainer,
+    period: &RetainerPeriod,
+) -> Result<Vec<TimeEntry>, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let entries = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        SELECT t.* FROM time_entries t
+        LEFT JOIN invoices i ON i.id = t.invoice_id
+        WHERE t.user_id = $1 AND t.project_id = $2
+            AND t.is_deleted = false AND t.billable = true
+            AND t.entry_date BETWEEN $3 AND $4
+            AND (t.invoice_id IS NULL OR i.is_deleted = true OR t.invoice_id = $5)
+        ORDER BY t.entry_date ASC, t.started_at ASC NULLS LAST, t.created_at ASC
+        "#,
+    )
+    .bind(retainer.user_id)
+    .bind(retainer.project_id)
+    .bind(period.period_start)
+    .bind(period.period_end)
+    .bind(period.invoice_id)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Sums the hours an open period has drawn down so far.
+pub async fn used_hours(pool: &PgPool, retainer: &Retainer, period: &RetainerPeriod) -> Result<Decimal, anyhow::Error> {
+    let entries = period_entries(pool, retainer, period).await?;
+    Ok(entries.iter().map(|entry| entry.hours).sum())
+}
+
+/// Settles a period that is over.
+///
+/// Links the drawn-down entries to the period's invoice (recording them for
+/// sync, unless time entries are now synced end-to-end encrypted) and
+/// records the used, carried and expired hours.
+async fn close_period(
+    tx: &mut Transaction<'_, Postgres>,
+    retainer: &Retainer,
+    period: &RetainerPeriod,
+) -> Result<RetainerPeriod, anyhow::Error> {
+    let entries = period_entries(&mut **tx, retainer, period).await?;
+    let used: Decimal = entries.iter().map(|entry| entry.hours).sum();
+    let available = period.included_hours + period.rolled_over_hours;
+    let (carried, expired) = settle(retainer.rollover_policy, retainer.max_rollover_hours, available, used);
+
+    let encrypted = encrypts_any(&mut **tx, retainer.user_id, &["time_entries"]).await?;
+    if let Some(invoice_id) = period.invoice_id.filter(|_| !encrypted) {
+        let hours: Vec<(Uuid, Decimal)> = entries.iter().map(|entry| (entry.id, entry.hours)).collect();
+        let drawn = drawn_entries(&hours, available);
+        // Entries billed on another invoice since they were loaded keep it
+        let billed = sqlx::query_as::<_, TimeEntry>(
+            r#"
+            UPDATE time_entries SET invoice_id = $2, last_modified = NOW()
+            WHERE user_id = $1 AND id = ANY($3)
+                AND (invoice_id IS NULL
+                    OR invoice_id IN (SELECT id FROM invoices WHERE user_id = $1 AND is_deleted = true))
+            RETURNING *
+            "#,
+        )
+        .bind(retainer.user_id)
+        .bind(invoice_id)
+        .bind(&drawn)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        for entry in &billed {
+            record_server_change(
+                &mut **tx,
+                retainer.user_id,
+                "time_entries",
+                entry.id,
+                SyncOperation::Update,
+                &serde_json::to_value(entry)?,
+            )
+            .await?;
+        }
+    }
+
+    let closed = sqlx::query_as::<_, RetainerPeriod>(
+        r#"
+        UPDATE retainer_periods
+        SET used_hours = $2, carried_hours = $3, expired_hours = $4, closed_at = NOW()
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(period.id)
+    .bind(used)
+    .bind(carried)
+    .bind(expired)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(closed)
+}
+
+/// Opens a period and drafts its invoice.
+///
+/// No invoice is drafted for a zero fee, when the client was deleted, or
+/// when the user syncs invoices or time entries end-to-end encrypted (the
+/// server can't write to those tables).
+async fn open_period(
+    tx: &mut Transaction<'_, Postgres>,
+    retainer: &Retainer,
+    (period_start, period_end): (NaiveDate, NaiveDate),
+    rolled_over_hours: Decimal,
+    due_date_rules: &DueDateRules,
+) -> Result<RetainerPeriod, anyhow::Error> {
+    let client = sqlx::query_as::<_, Client>(
+        "SELECT * FROM clients WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(retainer.client_id)
+    .bind(retainer.user_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let encrypted = encrypts_any(&mut **tx, retainer.user_id, &["invoices", "time_entries"]).await?;
+    let invoice_id = match client {
+        Some(client) if !retainer.monthly_amount.is_zero() && !encrypted => {
+            let default_tax = default_tax_rate(tx, retainer.user_id).await?;
+            let line_items = [LineItem {
+                description: format!(
+                    "{}: {} hours ({} to {})",
+                    retainer.name, retainer.monthly_hours, period_start, period_end
+                ),
+                quantity: Decimal::ONE,
+                unit_price: retainer.monthly_amount,
+                tax_rate: default_tax.map(|(_, rate)| rate),
+                tax_rate_id: default_tax.map(|(id, _)| id),
+            }];
+            let draft = DraftInvoice {
+                client: &client,
+                project_id: Some(retainer.project_id),
+                currency: &retainer.currency,
+                description: &retainer.name,
+                line_items: &line_items,
+            };
+            Some(insert_draft_invoice(tx, retainer.user_id, draft, due_date_rules).await?.id)
+        }
+        _ => None,
+    };
+
+    let period = sqlx::query_as::<_, RetainerPeriod>(
+        r#"
+        INSERT INTO retainer_periods (
+            user_id, retainer_id, period_start, period_end, included_hours, rolled_over_hours, invoice_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *
+        "#,
+    )
+    .bind(retainer.user_id)
+    .bind(retainer.id)
+    .bind(period_start)
+    .bind(period_end)
+    .bind(retainer.monthly_hours)
+    .bind(rolled_over_hours)
+    .bind(invoice_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(period)
+}
+
+/// Brings a retainer's periods up to date.
+///
+/// Settles the latest period once it is over, ends retainers past their
+/// end date, and opens (and invoices) the period containing `today` for
+/// active ones. The retainer is locked, so concurrent runs open a period
+/// once.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `retainer_id` - The retainer
+/// * `today` - The current date
+///
+/// # Returns
+///
+/// Returns `true` if a period was opened.
+pub async fn advance_retainer(pool: &PgPool, retainer_id: Uuid, today: NaiveDate) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    apply_current_audit_context(&mut *tx).await?;
+
+    let Some(mut retainer) = sqlx::query_as::<_, Retainer>("SELECT * FROM retainers WHERE id = $1 FOR UPDATE")
+        .bind(retainer_id)
+        .fetch_optional(&mut *tx)
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    let mut latest = latest_period(&mut *tx, retainer.id).await?;
+    if let Some(period) = latest.as_ref().filter(|p| p.closed_at.is_none() && p.period_end < today) {
+        let closed = close_period(&mut tx, &retainer, period).await?;
+        info!(
+            "Closed period {} of retainer {}: {} hours used, {} carried, {} expired",
+            closed.period_start, retainer.id, closed.used_hours, closed.carried_hours, closed.expired_hours
+        );
+        latest = Some(closed);
+    }
+
+    if retainer.status != RetainerStatus::Ended && retainer.end_date.is_some_and(|end_date| end_date < today) {
+        retainer = sqlx::query_as::<_, Retainer>("UPDATE retainers SET status = 'ended' WHERE id = $1 RETURNING *")
+            .bind(retainer.id)
+            .fetch_one(&mut *tx)
+            .await?;
+    }
+
+    let bounds = period_index(retainer.start_date, today)
+        .and_then(|index| period_bounds(retainer.start_date, index))
+        .map(|(from, to)| (from, retainer.end_date.map_or(to, |end_date| to.min(end_date))));
+    let opened = match bounds {
+        Some((from, to))
+            if retainer.status == RetainerStatus::Active && latest.as_ref().is_none_or(|p| p.period_start < from) =>
+        {
+            // Unused hours only carry into the period right after
+            let rolled_over = latest
+                .as_ref()
+                .filter(|p| p.closed_at.is_some() && p.period_end.succ_opt() == Some(from))
+                .map_or(Decimal::ZERO, |p| p.carried_hours);
+            let due_date_rules = DueDateRules::load(pool, retainer.user_id).await?;
+            let period = open_period(&mut tx, &retainer, (from, to), rolled_over, &due_date_rules).await?;
+            info!("Opened period {} of retainer {}", period.period_start, retainer.id);
+            true
+        }
+        _ => false,
+    };
+
+    tx.commit().await?;
+
+    Ok(opened)
+}
+
+/// Brings every retainer with work to do up to date.
+///
+/// # Returns
+///
+/// Returns the number of periods opened.
+pub async fn run_retainers(pool: &PgPool, today: NaiveDate) -> Result<usize, anyhow::Error> {
+    let retainer_ids = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT r.id FROM retainers r
+        WHERE (r.status <> 'ended' AND r.start_date <= $1)
+            OR EXISTS (
+                SELECT 1 FROM retainer_periods p
+                WHERE p.retainer_id = r.id AND p.closed_at IS NULL AND p.period_end < $1
+            )
+        "#,
+    )
+    .bind(today)
+    .fetch_all(pool)
+    .await?;
+
+    let mut opened = 0;
+    for retainer_id in retainer_ids {
+        match advance_retainer(pool, retainer_id, today).await {
+            Ok(true) => opened += 1,
+            Ok(false) => {}
+            Err(e) => tracing::warn!("Failed to advance retainer {}: {}", retainer_id, e),
+        }
+    }
+
+    Ok(opened)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+
+    fn hours(value: &str) -> Decimal {
+        Decimal::from_str_exact(value).unwrap()
+    }
+
+    #[test]
+    fn test_periods_run_monthly_from_the_start_date() {
+        let start = date(2024, 1, 15);
+        assert_eq!(period_bounds(start, 0), Some((date(2024, 1, 15), date(2024, 2, 14))));
+        assert_eq!(period_bounds(start, 1), Some((date(2024, 2, 15), date(2024, 3, 14))));
+
+        assert_eq!(period_index(start, date(2024, 1, 10)), None);
+        assert_eq!(period_index(start, date(2024, 1, 15)), Some(0));
+        assert_eq!(period_index(start, date(2024, 2, 14)), Some(0));
+        assert_eq!(period_index(start, date(2024, 2, 15)), Some(1));
+        assert_eq!(period_index(start, date(2025, 1, 20)), Some(12));
+
+        // Month-end starts stay contiguous through short months
+        let month_end = date(2024, 1, 31);
+        let (_, first_end) = period_bounds(month_end, 0).unwrap();
+        let (second_start, _) = period_bounds(month_end, 1).unwrap();
+        assert_eq!(first_end.succ_opt(), Some(second_start));
+        assert_eq!(period_index(month_end, second_start), Some(1));
+    }
+
+    #[test]
+    fn test_settle_rolls_over_or_expires_unused_hours() {
+        assert_eq!(
+            settle(RolloverPolicy::Expire, None, hours("20"), hours("12.5")),
+            (Decimal::ZERO, hours("7.5"))
+        );
+        assert_eq!(
+            settle(RolloverPolicy::Rollover, None, hours("20"), hours("12.5")),
+            (hours("7.5"), Decimal::ZERO)
+        );
+        assert_eq!(
+            settle(RolloverPolicy::Rollover, Some(hours("5")), hours("20"), hours("12.5")),
+            (hours("5"), hours("2.5"))
+        );
+        assert_eq!(
+            settle(RolloverPolicy::Rollover, None, hours("20"), hours("25")),
+            (Decimal::ZERO, Decimal::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_drawdown_takes_entries_until_hours_run_out() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let entries = [(a, hours("6")), (b, hours("5")), (c, hours("3"))];
+
+        assert_eq!(drawn_entries(&entries, hours("10")), vec![a, b]);
+        assert_eq!(drawn_entries(&entries, hours("20")), vec![a, b, c]);
+        assert!(drawn_entries(&entries, Decimal::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,44 @@
+use axum::{extract::Extension, http::StatusCode, response::Json};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::sampling::{load_config, save_config, validate_config, SamplingConfig};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Get trace sampling endpoint handler.
+///
+/// Handles GET requests to `/admin/tracing/sampling`: the configuration in
+/// effect (the defaults if none was saved).
+pub async fn get_sampling_handler(Extension(pool): Extension<PgPool>) -> Result<Json<SamplingConfig>, StatusCode> {
+    let config = load_config(&pool).await.map_err(|e| {
+        error!("Failed to load trace sampling configuration: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(config))
+}
+
+/// Update trace sampling endpoint handler.
+///
+/// Handles PUT requests to `/admin/tracing/sampling`. The configuration is
+/// replaced as a whole (omitted fields take their defaults) and applies to
+/// every process within seconds.
+pub async fn update_sampling_handler(
+    Extension(pool): Extension<PgPool>,
+    Json(config): Json<SamplingConfig>,
+) -> Result<Json<SamplingConfig>, (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_config(&config) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let config = save_config(&pool, config).await.map_err(|e| {
+        error!("Failed to save trace sampling configuration: {}", e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update trace sampling")
+    })?;
+
+    Ok(Json(config))
+}
@@ -0,0 +1,549 @@
+//! Runtime-configurable sampling of request traces.
+//!
+//! Tracing every request is too expensive in production, but sync bugs
+//! need full traces to debug. [`sampling_middleware`] decides per request
+//! whether it is traced: a sampled request runs inside a `request` span
+//! (so every event the handler logs carries its method, path and user) and
+//! logs its status and latency when it completes. The rest only log their
+//! warnings and errors: [`SampledFilter`] drops their other events before
+//! they are written.
+//!
+//! The share of requests traced is set per route prefix (e.g. every failed
+//! `/sync/push`, 1% of `/health`), with separate rates for failed requests,
+//! and the requests of users under investigation are always traced. The
+//! most verbose level logged is set per target (module path, e.g.
+//! `gigpilot_core::sync` at `debug`, `sqlx` at `warn`). Support
+//! staff replace the configuration through the admin API (see
+//! [`handlers`]); every process keeps it in memory, refreshed on the
+//! [`DomainEvent::TracingSamplingChanged`] event and on an interval in case
+//! an event is missed.
+
+pub mod handlers;
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::level_filters::LevelFilter;
+use tracing::subscriber::Interest;
+use tracing::{error, info, info_span, Instrument, Level, Metadata};
+use tracing_subscriber::layer::{Context, Filter};
+use uuid::Uuid;
+
+use crate::auth::token_ids;
+use crate::events::{publish, DomainEvent, EventBus};
+
+/// Share of requests traced when no rule matches.
+pub const DEFAULT_RATE: f64 = 0.1;
+
+/// Share of failed requests traced when no rule matches.
+pub const DEFAULT_ERROR_RATE: f64 = 1.0;
+
+/// Most route rules in a configuration.
+pub const MAX_RULES: usize = 50;
+
+/// Most users traced at once.
+pub const MAX_TRACED_USERS: usize = 20;
+
+/// Longest route prefix of a rule.
+pub const MAX_PATH_PREFIX_LENGTH: usize = 200;
+
+/// Most per-target log levels in a configuration.
+pub const MAX_LEVELS: usize = 50;
+
+/// Longest target of a log level.
+pub const MAX_TARGET_LENGTH: usize = 200;
+
+/// Seconds between refreshes of the configuration when not configured.
+const DEFAULT_REFRESH_SECONDS: u64 = 60;
+
+/// Sampling rates of the requests under a route prefix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplingRule {
+    /// Paths this rule applies to (e.g. "/sync/push", "/api/invoices");
+    /// the longest matching prefix wins
+    pub path_prefix: String,
+
+    /// Share of requests traced, from 0.0 to 1.0
+    pub rate: f64,
+
+    /// Share of failed requests (4xx and 5xx) traced; the configuration's
+    /// `error_rate` when unset
+    #[serde(default)]
+    pub error_rate: Option<f64>,
+}
+
+/// Most verbose level of events logged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Which requests are traced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    /// Share of requests traced when no rule matches
+    #[serde(default = "default_rate")]
+    pub default_rate: f64,
+
+    /// Share of failed requests traced when no rule matches or the rule
+    /// has no `error_rate`
+    #[serde(default = "default_error_rate")]
+    pub error_rate: f64,
+
+    /// Rates per route prefix
+    #[serde(default)]
+    pub rules: Vec<SamplingRule>,
+
+    /// Users (account or person) whose requests are always traced
+    #[serde(default)]
+    pub traced_users: Vec<Uuid>,
+
+    /// Level logged for targets without one in `levels`
+    #[serde(default)]
+    pub default_level: LogLevel,
+
+    /// Level logged per target (a module path, e.g. "gigpilot_core::sync",
+    /// also covering its submodules); the longest matching target wins
+    #[serde(default)]
+    pub levels: BTreeMap<String, LogLevel>,
+}
+
+fn default_rate() -> f64 {
+    DEFAULT_RATE
+}
+
+fn default_error_rate() -> f64 {
+    DEFAULT_ERROR_RATE
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            default_rate: DEFAULT_RATE,
+            error_rate: DEFAULT_ERROR_RATE,
+            rules: Vec::new(),
+            traced_users: Vec::new(),
+            default_level: LogLevel::default(),
+            levels: BTreeMap::new(),
+        }
+    }
+}
+
+impl SamplingConfig {
+    /// Returns the rule with the longest prefix of `path`, if any.
+    pub fn rule_for(&self, path: &str) -> Option<&SamplingRule> {
+        self.rules
+            .iter()
+            .filter(|rule| path.starts_with(&rule.path_prefix))
+            .max_by_key(|rule| rule.path_prefix.len())
+    }
+
+    /// Share of requests to `path` traced, for failed requests or not.
+    pub fn rate(&self, path: &str, failed: bool) -> f64 {
+        match (self.rule_for(path), failed) {
+            (Some(rule), false) => rule.rate,
+            (Some(rule), true) => rule.error_rate.unwrap_or(self.error_rate),
+            (None, false) => self.default_rate,
+            (None, true) => self.error_rate,
+        }
+    }
+
+    /// Whether requests from the given person or account are always traced.
+    pub fn traces_user(&self, identity: Uuid, account: Uuid) -> bool {
+        self.traced_users.contains(&identity) || self.traced_users.contains(&account)
+    }
+
+    /// Most verbose level logged for events of `target`.
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.levels
+            .iter()
+            .filter(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default_level, |(_, level)| *level)
+            .into()
+    }
+}
+
+/// Validates a sampling configuration.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the configuration is valid, or a message describing
+/// the first invalid field.
+pub fn validate_config(config: &SamplingConfig) -> Result<(), String> {
+    let valid_rate = |rate: f64| (0.0..=1.0).contains(&rate);
+
+    if !valid_rate(config.default_rate) {
+        return Err("default_rate must be between 0 and 1".to_string());
+    }
+    if !valid_rate(config.error_rate) {
+        return Err("error_rate must be between 0 and 1".to_string());
+    }
+    if config.rules.len() > MAX_RULES {
+        return Err(format!("at most {} rules are allowed", MAX_RULES));
+    }
+    for (index, rule) in config.rules.iter().enumerate() {
+        if !rule.path_prefix.starts_with('/') || rule.path_prefix.len() > MAX_PATH_PREFIX_LENGTH {
+            return Err(format!(
+                "rules[{}].path_prefix must start with / and be at most {} characters",
+                index, MAX_PATH_PREFIX_LENGTH
+            ));
+        }
+        if !valid_rate(rule.rate) || !rule.error_rate.is_none_or(valid_rate) {
+            return Err(format!("rules[{}] rates must be between 0 and 1", index));
+        }
+        if config.rules[..index].iter().any(|other| other.path_prefix == rule.path_prefix) {
+            return Err(format!("rules[{}].path_prefix is listed twice", index));
+        }
+    }
+    if config.traced_users.len() > MAX_TRACED_USERS {
+        return Err(format!("at most {} users can be traced", MAX_TRACED_USERS));
+    }
+    if config.levels.len() > MAX_LEVELS {
+        return Err(format!("at most {} levels are allowed", MAX_LEVELS));
+    }
+    let valid_target = |target: &str| {
+        !target.is_empty()
+            && target.len() <= MAX_TARGET_LENGTH
+            && target.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    };
+    if let Some(target) = config.levels.keys().find(|target| !valid_target(target)) {
+        return Err(format!(
+            "levels target {:?} must be a module path of at most {} characters",
+            target, MAX_TARGET_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Configuration this process last loaded (`None` until loaded: defaults).
+static CURRENT: RwLock<Option<SamplingConfig>> = RwLock::new(None);
+
+/// Returns the configuration in effect in this process.
+pub fn current_config() -> SamplingConfig {
+    CURRENT
+        .read()
+        .ok()
+        .and_then(|current| current.clone())
+        .unwrap_or_default()
+}
+
+/// Most verbose level logged for events of `target` in this process.
+fn current_level(target: &str) -> LevelFilter {
+    match CURRENT.read() {
+        Ok(current) => current
+            .as_ref()
+            .map_or_else(|| LogLevel::default().into(), |config| config.level_for(target)),
+        Err(_) => LogLevel::default().into(),
+    }
+}
+
+/// Replaces the configuration this process uses.
+fn set_current(config: SamplingConfig) {
+    if let Ok(mut current) = CURRENT.write() {
+        *current = Some(config);
+    }
+}
+
+/// Loads the configuration in effect (the defaults if none was saved).
+pub async fn load_config(pool: &PgPool) -> Result<SamplingConfig, anyhow::Error> {
+    let config = sqlx::query_scalar::<_, Json<SamplingConfig>>(
+        "SELECT config FROM tracing_sampling_configs ORDER BY created_at DESC LIMIT 1",
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(config.map(|Json(config)| config).unwrap_or_default())
+}
+
+/// Replaces the configuration in every process.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `config` - The new configuration (see [`validate_config`])
+///
+/// # Returns
+///
+/// Returns the saved configuration.
+pub async fn save_config(pool: &PgPool, config: SamplingConfig) -> Result<SamplingConfig, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("INSERT INTO tracing_sampling_configs (config) VALUES ($1)")
+        .bind(Json(&config))
+        .execute(&mut *tx)
+        .await?;
+    publish(&mut *tx, &DomainEvent::TracingSamplingChanged).await?;
+
+    tx.commit().await?;
+
+    info!(
+        "Trace sampling changed: default rate {}, {} rules, {} traced users",
+        config.default_rate,
+        config.rules.len(),
+        config.traced_users.len()
+    );
+    set_current(config.clone());
+    Ok(config)
+}
+
+/// Reloads the configuration from the database.
+async fn refresh(pool: &PgPool) {
+    match load_config(pool).await {
+        Ok(config) => set_current(config),
+        Err(e) => error!("Failed to load trace sampling configuration: {}", e),
+    }
+}
+
+/// Spawns the tasks keeping this process's configuration up to date.
+///
+/// Reloads it on [`DomainEvent::TracingSamplingChanged`] and every
+/// `TRACE_SAMPLING_REFRESH_SECONDS` (default 60 seconds).
+pub fn spawn_refresh(pool: PgPool, bus: &EventBus) {
+    let seconds = std::env::var("TRACE_SAMPLING_REFRESH_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_REFRESH_SECONDS);
+
+    let event_pool = pool.clone();
+    let mut receiver = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(DomainEvent::TracingSamplingChanged) | Err(RecvError::Lagged(_)) => refresh(&event_pool).await,
+                Ok(_) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            refresh(&pool).await;
+        }
+    });
+}
+
+tokio::task_local! {
+    /// Whether the request being handled is traced; unset outside requests
+    /// (startup, background tasks).
+    static REQUEST_TRACED: bool;
+}
+
+/// Filter of the log output by the configuration in effect.
+///
+/// Events are logged up to their target's level (see
+/// [`SamplingConfig::level_for`]); events of requests that weren't sampled
+/// are logged only at `warn` and `error`. Install it on the output layer:
+///
+/// ```ignore
+/// tracing_subscriber::registry()
+///     .with(tracing_subscriber::fmt::layer().with_filter(SampledFilter))
+///     .init();
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampledFilter;
+
+impl<S> Filter<S> for SampledFilter {
+    fn enabled(&self, meta: &Metadata<'_>, _cx: &Context<'_, S>) -> bool {
+        let untraced = REQUEST_TRACED.try_with(|traced| !*traced).unwrap_or(false);
+        if meta.is_event() && untraced && *meta.level() > Level::WARN {
+            return false;
+        }
+        current_level(meta.target()) >= *meta.level()
+    }
+
+    fn callsite_enabled(&self, _meta: &'static Metadata<'static>) -> Interest {
+        // Levels and sampling change at runtime, so every event is asked
+        Interest::sometimes()
+    }
+}
+
+/// Random number in `[0, 1)` deciding whether a request is traced.
+fn roll() -> f64 {
+    // The first 48 bits of a v4 UUID are random
+    (Uuid::new_v4().as_u128() >> 80) as f64 / (1u64 << 48) as f64
+}
+
+/// Whether a response counts as failed for sampling.
+fn is_failure(status: StatusCode) -> bool {
+    status.is_client_error() || status.is_server_error()
+}
+
+/// Middleware tracing a sample of requests.
+///
+/// Requests from traced users, and a share of the others given by their
+/// route's rate, run inside a `request` span. Failed requests are traced at
+/// their route's error rate: one roll decides both, so a failure is traced
+/// at the higher of the two rates (only the completion is logged for
+/// failures the span missed). Only the warnings and errors of the other
+/// requests are logged (see [`SampledFilter`]).
+pub async fn sampling_middleware(req: Request<Body>, next: Next<Body>) -> Response {
+    let config = current_config();
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let traced_user = if config.traced_users.is_empty() {
+        None
+    } else {
+        token_ids(&req)
+            .filter(|(identity, account)| config.traces_user(*identity, *account))
+            .map(|(_, account)| account)
+    };
+    let roll = roll();
+    let started = Instant::now();
+
+    if traced_user.is_some() || roll < config.rate(&path, false) {
+        let span = info_span!("request", %method, %path, user_id = ?traced_user);
+        let response = REQUEST_TRACED.scope(true, next.run(req).instrument(span.clone())).await;
+        span.in_scope(|| {
+            info!(
+                status = response.status().as_u16(),
+                latency_ms = started.elapsed().as_millis() as u64,
+                "Request completed"
+            )
+        });
+        return response;
+    }
+
+    let response = REQUEST_TRACED.scope(false, next.run(req)).await;
+    if is_failure(response.status()) && roll < config.rate(&path, true) {
+        info!(
+            %method,
+            %path,
+            status = response.status().as_u16(),
+            latency_ms = started.elapsed().as_millis() as u64,
+            "Request failed"
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path_prefix: &str, rate: f64, error_rate: Option<f64>) -> SamplingRule {
+        SamplingRule {
+            path_prefix: path_prefix.to_string(),
+            rate,
+            error_rate,
+        }
+    }
+
+    #[test]
+    fn test_longest_prefix_decides_the_rate() {
+        let config = SamplingConfig {
+            rules: vec![
+                rule("/sync", 0.5, None),
+                rule("/sync/push", 0.05, Some(1.0)),
+                rule("/health", 0.01, Some(0.0)),
+            ],
+            ..SamplingConfig::default()
+        };
+
+        assert_eq!(config.rate("/sync/push", false), 0.05);
+        assert_eq!(config.rate("/sync/push", true), 1.0);
+        assert_eq!(config.rate("/sync/pull", false), 0.5);
+        assert_eq!(config.rate("/sync/pull", true), DEFAULT_ERROR_RATE);
+        assert_eq!(config.rate("/health", true), 0.0);
+        assert_eq!(config.rate("/api/invoices", false), DEFAULT_RATE);
+    }
+
+    #[test]
+    fn test_traced_users_match_person_or_account() {
+        let (person, account) = (Uuid::new_v4(), Uuid::new_v4());
+        let config = SamplingConfig {
+            traced_users: vec![account],
+            ..SamplingConfig::default()
+        };
+
+        assert!(config.traces_user(person, account));
+        assert!(config.traces_user(account, account));
+        assert!(!config.traces_user(person, person));
+    }
+
+    #[test]
+    fn test_longest_target_decides_the_level() {
+        let config: SamplingConfig = serde_json::from_str(
+            r#"{ "default_level": "warn", "levels": { "gigpilot_core::sync": "debug", "gigpilot_core::sync::push": "trace", "sqlx": "off" } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.level_for("gigpilot_core::sync::pull"), LevelFilter::DEBUG);
+        assert_eq!(config.level_for("gigpilot_core::sync::push"), LevelFilter::TRACE);
+        assert_eq!(config.level_for("gigpilot_core::sync"), LevelFilter::DEBUG);
+        // A target only matches whole module names
+        assert_eq!(config.level_for("gigpilot_core::syncer"), LevelFilter::WARN);
+        assert_eq!(config.level_for("sqlx::query"), LevelFilter::OFF);
+        assert_eq!(SamplingConfig::default().level_for("gigpilot_core"), LevelFilter::INFO);
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let parsed: SamplingConfig = serde_json::from_str(r#"{ "rules": [{ "path_prefix": "/health", "rate": 0.01 }] }"#).unwrap();
+        assert_eq!(parsed.default_rate, DEFAULT_RATE);
+        assert!(validate_config(&parsed).is_ok());
+
+        let with_rules = |rules| SamplingConfig {
+            rules,
+            ..SamplingConfig::default()
+        };
+        assert!(validate_config(&SamplingConfig { default_rate: 1.5, ..SamplingConfig::default() }).is_err());
+        assert!(validate_config(&with_rules(vec![rule("health", 0.5, None)])).is_err());
+        assert!(validate_config(&with_rules(vec![rule("/health", 0.5, Some(-0.1))])).is_err());
+        assert!(validate_config(&with_rules(vec![rule("/sync", 0.5, None), rule("/sync", 0.1, None)])).is_err());
+        assert!(validate_config(&SamplingConfig {
+            traced_users: vec![Uuid::new_v4(); MAX_TRACED_USERS + 1],
+            ..SamplingConfig::default()
+        })
+        .is_err());
+        assert!(validate_config(&SamplingConfig {
+            levels: BTreeMap::from([("gigpilot_core sync".to_string(), LogLevel::Debug)]),
+            ..SamplingConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_roll_is_a_fraction() {
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&roll()));
+        }
+    }
+}
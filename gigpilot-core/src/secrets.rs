@@ -0,0 +1,27 @@
+//! Helpers for handling secrets: webhook and admin API keys compared
+//! against what a caller presents.
+
+/// Compares a secret without leaking where it differs.
+pub fn secrets_match(expected: &str, given: &str) -> bool {
+    let (expected, given) = (expected.as_bytes(), given.as_bytes());
+    if expected.len() != given.len() {
+        return false;
+    }
+    expected
+        .iter()
+        .zip(given)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_match() {
+        assert!(secrets_match("s3cret", "s3cret"));
+        assert!(!secrets_match("s3cret", "s3cres"));
+        assert!(!secrets_match("s3cret", "s3cret!"));
+    }
+}
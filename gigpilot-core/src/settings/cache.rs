@@ -0,0 +1,187 @@
+//! In-memory cache of per-user settings and holiday calendars.
+//!
+//! The worker consults settings for every invoice in a poll cycle; caching
+//! them avoids repeating the same queries. Entries are dropped when a
+//! [`DomainEvent`] reports a change, and expire after a TTL as a safety net
+//! for events missed while the listener was disconnected.
+
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::business_days::{load_calendar, HolidayCalendar};
+use crate::events::{DomainEvent, EventBus};
+use crate::models::user_settings::UserSettings;
+use crate::settings::load_user_settings;
+
+/// Default time-to-live for cached entries.
+const DEFAULT_TTL_SECONDS: u64 = 300;
+
+/// A map of values that expire after a fixed time-to-live.
+#[derive(Debug)]
+struct TtlMap<K, V> {
+    ttl: Duration,
+    entries: HashMap<K, (V, Instant)>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlMap<K, V> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns a fresh value, if any.
+    fn get(&self, key: &K, now: Instant) -> Option<V> {
+        self.entries
+            .get(key)
+            .filter(|(_, loaded_at)| now.duration_since(*loaded_at) < self.ttl)
+            .map(|(value, _)| value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V, now: Instant) {
+        self.entries.insert(key, (value, now));
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.entries.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Shared cache of user settings and holiday calendars.
+///
+/// Cheap to clone; clones share the cached entries.
+#[derive(Clone)]
+pub struct SettingsCache {
+    /// Database connection pool
+    pool: PgPool,
+
+    /// user_id -> settings
+    settings: Arc<RwLock<TtlMap<Uuid, UserSettings>>>,
+
+    /// country code -> holiday calendar
+    calendars: Arc<RwLock<TtlMap<Option<String>, HolidayCalendar>>>,
+}
+
+impl SettingsCache {
+    /// Creates a cache with the TTL from `SETTINGS_CACHE_TTL_SECONDS`
+    /// (default: 300 seconds).
+    pub fn new(pool: PgPool) -> Self {
+        let ttl = std::env::var("SETTINGS_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TTL_SECONDS);
+        Self::with_ttl(pool, Duration::from_secs(ttl))
+    }
+
+    /// Creates a cache with an explicit TTL.
+    pub fn with_ttl(pool: PgPool, ttl: Duration) -> Self {
+        Self {
+            pool,
+            settings: Arc::new(RwLock::new(TtlMap::new(ttl))),
+            calendars: Arc::new(RwLock::new(TtlMap::new(ttl))),
+        }
+    }
+
+    /// Returns a user's settings, loading them on a miss.
+    pub async fn user_settings(&self, user_id: Uuid) -> Result<UserSettings, anyhow::Error> {
+        if let Some(settings) = self.settings.read().await.get(&user_id, Instant::now()) {
+            return Ok(settings);
+        }
+
+        let settings = load_user_settings(&self.pool, user_id).await?;
+        self.settings
+            .write()
+            .await
+            .insert(user_id, settings.clone(), Instant::now());
+        Ok(settings)
+    }
+
+    /// Returns the holiday calendar for a country, loading it on a miss.
+    pub async fn calendar(&self, country_code: Option<&str>) -> Result<HolidayCalendar, anyhow::Error> {
+        let key = country_code.map(str::to_uppercase);
+        if let Some(calendar) = self.calendars.read().await.get(&key, Instant::now()) {
+            return Ok(calendar);
+        }
+
+        let calendar = load_calendar(&self.pool, key.as_deref()).await?;
+        self.calendars
+            .write()
+            .await
+            .insert(key, calendar.clone(), Instant::now());
+        Ok(calendar)
+    }
+
+    /// Drops entries affected by a domain event.
+    pub async fn invalidate(&self, event: &DomainEvent) {
+        match event {
+            DomainEvent::UserSettingsChanged { user_id } => {
+                debug!("Invalidating cached settings for user {}", user_id);
+                self.settings.write().await.remove(user_id);
+            }
+            DomainEvent::InvoiceContentChanged { .. }
+            | DomainEvent::MaintenanceChanged
+            | DomainEvent::TracingSamplingChanged
+            | DomainEvent::SyncChangesAvailable { .. } => {}
+        }
+    }
+
+    /// Drops every cached entry.
+    pub async fn clear(&self) {
+        self.settings.write().await.clear();
+        self.calendars.write().await.clear();
+    }
+
+    /// Spawns a task invalidating entries as events arrive on the bus.
+    pub fn spawn_invalidation(&self, bus: &EventBus) {
+        let cache = self.clone();
+        let mut receiver = bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => cache.invalidate(&event).await,
+                    Err(RecvError::Lagged(missed)) => {
+                        // Events were dropped; we can't tell which entries are stale
+                        warn!("Settings cache missed {} events, clearing", missed);
+                        cache.clear().await;
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_map_expires_entries() {
+        let mut map = TtlMap::new(Duration::from_secs(60));
+        let start = Instant::now();
+        map.insert("a", 1, start);
+        assert_eq!(map.get(&"a", start + Duration::from_secs(59)), Some(1));
+        assert_eq!(map.get(&"a", start + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_ttl_map_remove() {
+        let mut map = TtlMap::new(Duration::from_secs(60));
+        let now = Instant::now();
+        map.insert("a", 1, now);
+        map.remove(&"a");
+        assert_eq!(map.get(&"a", now), None);
+    }
+}
@@ -0,0 +1,67 @@
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::auth::CurrentUser;
+use crate::events::DomainEvent;
+use crate::models::user_settings::{UpdateUserSettings, UserSettings};
+use crate::settings::{update_user_settings, validate_update, SettingsCache};
+use crate::sync::schema::PayloadError;
+
+/// Get-settings endpoint handler.
+///
+/// Handles GET requests to `/api/settings`.
+pub async fn get_settings_handler(
+    Extension(settings_cache): Extension<SettingsCache>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<UserSettings>, StatusCode> {
+    let settings = settings_cache.user_settings(user_id).await.map_err(|e| {
+        error!("Failed to load settings for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    settings_cache
+        .invalidate(&DomainEvent::UserSettingsChanged { user_id })
+        .await;
+
+    Ok(Json(settings))
+}
+
+/// Update-settings endpoint handler.
+///
+/// Handles PUT requests to `/api/settings`. Invalid values are rejected
+/// with `422 Unprocessable Entity`. This server's cached copy is dropped
+/// before responding, so the change applies without waiting for the
+/// `UserSettingsChanged` event.
+pub async fn update_settings_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(settings_cache): Extension<SettingsCache>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(update): Json<UpdateUserSettings>,
+) -> Result<Json<UserSettings>, (StatusCode, Json<Value>)> {
+    validate_update(&update)
+        .map_err(|message| (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))))?;
+
+    let settings = update_user_settings(&pool, user_id, update).await.map_err(|e| {
+        // Checks against the stored settings (e.g. moving one end of the
+        // send window past the other)
+        if let Some(invalid) = e.downcast_ref::<PayloadError>() {
+            let message = invalid.fields.first().map_or("invalid settings", |field| field.error.as_str());
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })));
+        }
+        error!("Failed to update settings for user {}: {}", user_id, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "failed to update settings" })),
+        )
+    })?;
+    settings_cache
+        .invalidate(&DomainEvent::UserSettingsChanged { user_id })
+        .await;
+
+    Ok(Json(settings))
+}
@@ -0,0 +1,381 @@
+pub mod cache;
+pub mod handlers;
+
+pub use cache::SettingsCache;
+
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::chase::send_window::parse_timezone;
+use crate::currency::normalize_currency;
+use crate::events::{publish, DomainEvent};
+use crate::models::sync_change::SyncOperation;
+use crate::models::user_settings::{LateFeeKind, UpdateUserSettings, UserSettings};
+use crate::sync::schema::{FieldError, PayloadError};
+use crate::sync::server::record_server_change;
+use crate::worker::state_machine::FINAL_NOTICE_AFTER_DAYS;
+
+/// Synced table name of the settings record (see [`sync_record`]).
+pub const SYNC_TABLE: &str = "user_settings";
+
+/// Loads a user's settings, falling back to defaults when none are stored.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to read with
+/// * `user_id` - ID of the user
+///
+/// # Returns
+///
+/// Returns the user's `UserSettings`, or an error if the query fails.
+pub async fn load_user_settings<'e, E>(executor: E, user_id: Uuid) -> Result<UserSettings, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let settings = sqlx::query_as::<_, UserSettings>("SELECT * FROM user_settings WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(settings.unwrap_or_else(|| UserSettings::defaults(user_id)))
+}
+
+/// Validates a settings update before it is applied.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the update is valid, or a message describing the
+/// first invalid field.
+pub fn validate_update(update: &UpdateUserSettings) -> Result<(), String> {
+    if let Some(code) = &update.country_code {
+        let code = code.trim();
+        if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err("country_code must be an ISO 3166-1 alpha-2 code".to_string());
+        }
+    }
+    if let Some(currency) = &update.base_currency {
+        normalize_currency(currency).map_err(|e| e.to_string())?;
+    }
+    if let Some(hours) = update.weekly_draft_grace_hours {
+        if !(1..=168).contains(&hours) {
+            return Err("weekly_draft_grace_hours must be between 1 and 168".to_string());
+        }
+    }
+    if let Some(amount) = update.late_fee_amount {
+        if amount.is_sign_negative() {
+            return Err("late_fee_amount must not be negative".to_string());
+        }
+        if amount != amount.round_dp(2) {
+            return Err("late_fee_amount allows at most 2 decimal places".to_string());
+        }
+        if update.late_fee_kind == Some(LateFeeKind::Percentage) && amount > Decimal::ONE_HUNDRED {
+            return Err("late_fee_amount must be at most 100 for percentage fees".to_string());
+        }
+    }
+    // Fees are charged with a reminder, so one due after the last never is
+    if let Some(days) = update.late_fee_after_days {
+        if !(0..=FINAL_NOTICE_AFTER_DAYS).contains(&i64::from(days)) {
+            return Err(format!(
+                "late_fee_after_days must be between 0 and {}, when the final notice is sent",
+                FINAL_NOTICE_AFTER_DAYS
+            ));
+        }
+    }
+    let terms = [
+        ("payment_terms_days", update.payment_terms_days),
+        ("min_payment_terms_days", update.min_payment_terms_days),
+    ];
+    for (field, days) in terms {
+        if days.map(|d| !(0..=365).contains(&d)).unwrap_or(false) {
+            return Err(format!("{} must be between 0 and 365", field));
+        }
+    }
+    if let Some(vat_id) = update.vat_id.as_deref().map(compact_vat_id).filter(|v| !v.is_empty()) {
+        let valid = (4..=20).contains(&vat_id.len())
+            && vat_id[..2].chars().all(|c| c.is_ascii_alphabetic())
+            && vat_id.chars().all(|c| c.is_ascii_alphanumeric());
+        if !valid {
+            return Err("vat_id must be a country prefix followed by letters or digits".to_string());
+        }
+    }
+    let lengths = [
+        ("address_line", &update.address_line, 255),
+        ("city", &update.city, 100),
+        ("postal_code", &update.postal_code, 20),
+    ];
+    for (field, value, max) in lengths {
+        if value.as_deref().map(|v| v.trim().chars().count() > max).unwrap_or(false) {
+            return Err(format!("{} must be at most {} characters", field, max));
+        }
+    }
+    if let Some(timezone) = update.timezone.as_deref().filter(|v| !v.trim().is_empty()) {
+        parse_timezone(timezone)?;
+    }
+    if let Some(hour) = update.send_window_start_hour {
+        if !(0..=23).contains(&hour) {
+            return Err("send_window_start_hour must be between 0 and 23".to_string());
+        }
+    }
+    if let Some(hour) = update.send_window_end_hour {
+        if !(1..=24).contains(&hour) {
+            return Err("send_window_end_hour must be between 1 and 24".to_string());
+        }
+    }
+    if let (Some(start), Some(end)) = (update.send_window_start_hour, update.send_window_end_hour) {
+        if start >= end {
+            return Err("send_window_start_hour must be before send_window_end_hour".to_string());
+        }
+    }
+    if let Some(inbox) = update.email_sandbox_inbox.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        if inbox.len() > 255 || !inbox.contains('@') || inbox.starts_with('@') || inbox.ends_with('@') {
+            return Err("email_sandbox_inbox must be an email address".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Removes spaces from a VAT identifier and upper-cases it.
+fn compact_vat_id(vat_id: &str) -> String {
+    vat_id.split_whitespace().collect::<String>().to_uppercase()
+}
+
+/// Applies an optional text update: `None` keeps the current value and an
+/// empty string clears it.
+fn updated_text(update: Option<String>, current: Option<String>) -> Option<String> {
+    match update {
+        Some(value) => Some(value.trim().to_string()).filter(|v| !v.is_empty()),
+        None => current,
+    }
+}
+
+/// Creates or updates a user's settings.
+///
+/// Fields left as `None` in the update keep their current value. Publishes
+/// [`DomainEvent::UserSettingsChanged`] so cached copies are dropped, and
+/// records the change so the user's devices pull it.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `update` - Fields to change
+///
+/// # Returns
+///
+/// Returns the stored `UserSettings`, or an error.
+pub async fn update_user_settings(
+    pool: &PgPool,
+    user_id: Uuid,
+    update: UpdateUserSettings,
+) -> Result<UserSettings, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let settings = write_user_settings(&mut tx, user_id, update).await?;
+    record_server_change(
+        &mut *tx,
+        user_id,
+        SYNC_TABLE,
+        user_id,
+        SyncOperation::Update,
+        &sync_record(&settings)?,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(settings)
+}
+
+/// Applies settings pushed by a device.
+///
+/// The settings record's ID is the user's ID. Like the API, fields missing
+/// from the payload (or null) keep their current value.
+///
+/// # Errors
+///
+/// Returns an error if the record ID is not the user's, or a
+/// `PayloadError` if the settings are invalid (e.g. an unknown timezone).
+pub async fn apply_pushed_settings(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+) -> Result<(), anyhow::Error> {
+    if record_id != user_id {
+        anyhow::bail!("user_settings id must be the user's id");
+    }
+
+    let update: UpdateUserSettings = serde_json::from_value(data.clone())?;
+    write_user_settings(tx, user_id, update).await?;
+
+    Ok(())
+}
+
+/// Serializes settings as devices sync them, with the user's ID as the
+/// record ID.
+pub fn sync_record(settings: &UserSettings) -> Result<Value, anyhow::Error> {
+    let mut record = serde_json::to_value(settings)?;
+    if let Some(object) = record.as_object_mut() {
+        object.insert("id".to_string(), json!(settings.user_id));
+    }
+
+    Ok(record)
+}
+
+/// A settings update that can't be applied, reported like a record
+/// rejected by the sync schema.
+///
+/// # Arguments
+///
+/// * `path` - JSON pointer to the offending field (empty for the update
+///   as a whole)
+/// * `message` - What is wrong with it
+fn invalid_settings(path: &str, message: impl Into<String>) -> anyhow::Error {
+    PayloadError {
+        table: SYNC_TABLE.to_string(),
+        fields: vec![FieldError {
+            path: path.to_string(),
+            error: message.into(),
+        }],
+    }
+    .into()
+}
+
+/// Validates and upserts a settings update within a transaction.
+///
+/// Runs [`validate_update`] whichever way the update arrives, so pushed
+/// settings are checked like those sent to the API.
+///
+/// # Errors
+///
+/// Returns a `PayloadError` if the update is invalid.
+async fn write_user_settings(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    update: UpdateUserSettings,
+) -> Result<UserSettings, anyhow::Error> {
+    let current = load_user_settings(&mut **tx, user_id).await?;
+
+    validate_update(&update).map_err(|message| invalid_settings("", message))?;
+
+    let country_code = update
+        .country_code
+        .map(|c| c.trim().to_uppercase())
+        .or(current.country_code);
+
+    let base_currency = match &update.base_currency {
+        Some(currency) => normalize_currency(currency)?,
+        None => current.base_currency,
+    };
+
+    // A percentage can't exceed 100 even when only the kind changes
+    let late_fee_kind = update.late_fee_kind.unwrap_or(current.late_fee_kind);
+    let late_fee_amount = update.late_fee_amount.unwrap_or(current.late_fee_amount);
+    if late_fee_kind == LateFeeKind::Percentage && late_fee_amount > Decimal::ONE_HUNDRED {
+        return Err(invalid_settings(
+            "/late_fee_amount",
+            "late_fee_amount must be at most 100 for percentage fees",
+        ));
+    }
+
+    // The window must stay open for at least an hour when only one end moves
+    let send_window_start_hour = update.send_window_start_hour.unwrap_or(current.send_window_start_hour);
+    let send_window_end_hour = update.send_window_end_hour.unwrap_or(current.send_window_end_hour);
+    if send_window_start_hour >= send_window_end_hour {
+        return Err(invalid_settings(
+            "/send_window_start_hour",
+            "send_window_start_hour must be before send_window_end_hour",
+        ));
+    }
+
+    let settings = sqlx::query_as::<_, UserSettings>(
+        r#"
+        INSERT INTO user_settings (
+            user_id, country_code, skip_non_business_days, base_currency,
+            weekly_drafts_enabled, weekly_draft_auto_send, weekly_draft_grace_hours,
+            invoice_number_policy, late_fee_kind, late_fee_amount, late_fee_after_days,
+            vat_id, address_line, city, postal_code,
+            payment_terms_days, min_payment_terms_days, roll_due_dates_forward,
+            ai_llm_consent, ai_embeddings_consent, chase_with_statement,
+            email_sandbox, email_sandbox_inbox, final_notice_mentions_collections,
+            timezone, send_window_start_hour, send_window_end_hour, send_on_weekends,
+            statement_locale
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20,
+            $21, $22, $23, $24, $25, $26, $27, $28, $29
+        )
+        ON CONFLICT (user_id) DO UPDATE
+            SET country_code = EXCLUDED.country_code,
+                skip_non_business_days = EXCLUDED.skip_non_business_days,
+                base_currency = EXCLUDED.base_currency,
+                weekly_drafts_enabled = EXCLUDED.weekly_drafts_enabled,
+                weekly_draft_auto_send = EXCLUDED.weekly_draft_auto_send,
+                weekly_draft_grace_hours = EXCLUDED.weekly_draft_grace_hours,
+                invoice_number_policy = EXCLUDED.invoice_number_policy,
+                late_fee_kind = EXCLUDED.late_fee_kind,
+                late_fee_amount = EXCLUDED.late_fee_amount,
+                late_fee_after_days = EXCLUDED.late_fee_after_days,
+                vat_id = EXCLUDED.vat_id,
+                address_line = EXCLUDED.address_line,
+                city = EXCLUDED.city,
+                postal_code = EXCLUDED.postal_code,
+                payment_terms_days = EXCLUDED.payment_terms_days,
+                min_payment_terms_days = EXCLUDED.min_payment_terms_days,
+                roll_due_dates_forward = EXCLUDED.roll_due_dates_forward,
+                ai_llm_consent = EXCLUDED.ai_llm_consent,
+                ai_embeddings_consent = EXCLUDED.ai_embeddings_consent,
+                chase_with_statement = EXCLUDED.chase_with_statement,
+                email_sandbox = EXCLUDED.email_sandbox,
+                email_sandbox_inbox = EXCLUDED.email_sandbox_inbox,
+                final_notice_mentions_collections = EXCLUDED.final_notice_mentions_collections,
+                timezone = EXCLUDED.timezone,
+                send_window_start_hour = EXCLUDED.send_window_start_hour,
+                send_window_end_hour = EXCLUDED.send_window_end_hour,
+                send_on_weekends = EXCLUDED.send_on_weekends,
+                statement_locale = EXCLUDED.statement_locale
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(country_code)
+    .bind(update.skip_non_business_days.unwrap_or(current.skip_non_business_days))
+    .bind(base_currency)
+    .bind(update.weekly_drafts_enabled.unwrap_or(current.weekly_drafts_enabled))
+    .bind(update.weekly_draft_auto_send.unwrap_or(current.weekly_draft_auto_send))
+    .bind(update.weekly_draft_grace_hours.unwrap_or(current.weekly_draft_grace_hours))
+    .bind(update.invoice_number_policy.unwrap_or(current.invoice_number_policy))
+    .bind(late_fee_kind)
+    .bind(late_fee_amount)
+    .bind(update.late_fee_after_days.unwrap_or(current.late_fee_after_days))
+    .bind(updated_text(update.vat_id.as_deref().map(compact_vat_id), current.vat_id))
+    .bind(updated_text(update.address_line, current.address_line))
+    .bind(updated_text(update.city, current.city))
+    .bind(updated_text(update.postal_code, current.postal_code))
+    .bind(update.payment_terms_days.unwrap_or(current.payment_terms_days))
+    .bind(update.min_payment_terms_days.unwrap_or(current.min_payment_terms_days))
+    .bind(update.roll_due_dates_forward.unwrap_or(current.roll_due_dates_forward))
+    .bind(update.ai_llm_consent.unwrap_or(current.ai_llm_consent))
+    .bind(update.ai_embeddings_consent.unwrap_or(current.ai_embeddings_consent))
+    .bind(update.chase_with_statement.unwrap_or(current.chase_with_statement))
+    .bind(update.email_sandbox.unwrap_or(current.email_sandbox))
+    .bind(updated_text(update.email_sandbox_inbox, current.email_sandbox_inbox))
+    .bind(
+        update
+            .final_notice_mentions_collections
+            .unwrap_or(current.final_notice_mentions_collections),
+    )
+    .bind(updated_text(update.timezone, current.timezone))
+    .bind(send_window_start_hour)
+    .bind(send_window_end_hour)
+    .bind(update.send_on_weekends.unwrap_or(current.send_on_weekends))
+    .bind(update.statement_locale.unwrap_or(current.statement_locale))
+    .fetch_one(&mut **tx)
+    .await?;
+
+    publish(&mut **tx, &DomainEvent::UserSettingsChanged { user_id }).await?;
+
+    Ok(settings)
+}
@@ -0,0 +1,130 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::accounts::account_role;
+use crate::auth::CurrentUser;
+use crate::db::PoolRouter;
+use crate::models::share_grant::{CreateShareGrant, ShareGrant};
+use crate::sharing::{
+    check_grantee, find_grantee, list_received_shares, list_shares, revoke_share, share_entity, validate_share,
+};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// List shares endpoint handler.
+///
+/// Handles GET requests to `/api/shares`: the projects and clients the
+/// account shared with others.
+pub async fn list_shares_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<ShareGrant>>, StatusCode> {
+    let grants = list_shares(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list shares of user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(grants))
+}
+
+/// List received shares endpoint handler.
+///
+/// Handles GET requests to `/api/shares/received`: the projects and
+/// clients others shared with the user.
+pub async fn list_received_shares_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<ShareGrant>>, StatusCode> {
+    let grants = list_received_shares(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list shares received by user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(grants))
+}
+
+/// Share endpoint handler.
+///
+/// Handles POST requests to `/api/shares`. Sharing an entity with the same
+/// user again changes the permission. Returns 422 if the grantee is not a
+/// GigPilot user, already works in the account or keeps their data in
+/// another region, and 404 if the entity is not one of the account's.
+pub async fn create_share_handler(
+    Extension(router): Extension<PoolRouter>,
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateShareGrant>,
+) -> Result<(StatusCode, Json<ShareGrant>), (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to share {:?} {}: {}", request.entity_type, request.entity_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to share")
+    };
+
+    if let Err(message) = validate_share(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+    // Logins and memberships are kept in the home cluster
+    let grantee_id = find_grantee(router.home(), &request.grantee_email)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "grantee_email is not a GigPilot user"))?;
+    let role = account_role(router.home(), user_id, grantee_id).await.map_err(internal_error)?;
+    if let Err(message) = check_grantee(user_id, grantee_id, role) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    // The shared entity stays in the account's cluster, which the grantee's
+    // requests are never served from
+    let region = router.region_of(user_id).await.map_err(internal_error)?;
+    let grantee_region = router.region_of(grantee_id).await.map_err(internal_error)?;
+    if region != grantee_region {
+        return Err(error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "projects and clients can only be shared with accounts in the same region",
+        ));
+    }
+    router.provision_account(grantee_id).await.map_err(internal_error)?;
+
+    let grant = share_entity(
+        &pool,
+        user_id,
+        grantee_id,
+        request.entity_type,
+        request.entity_id,
+        request.permission.unwrap_or_default(),
+    )
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "entity not found"))?;
+
+    Ok((StatusCode::CREATED, Json(grant)))
+}
+
+/// Revoke share endpoint handler.
+///
+/// Handles DELETE requests to `/api/shares/:id`, by the sharing account or
+/// by the grantee leaving the share.
+pub async fn revoke_share_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(grant_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = revoke_share(&pool, user_id, grant_id).await.map_err(|e| {
+        error!("Failed to revoke share {}: {}", grant_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    match revoked {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
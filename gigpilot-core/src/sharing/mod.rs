@@ -0,0 +1,361 @@
+//! Sharing single projects and clients.
+//!
+//! Accounts group the people who work on all of a business's data. To work
+//! with someone outside the organization on just one project or client, the
+//! owning account shares it with their GigPilot user, read-only or with edit
+//! rights. The entity stays owned by the sharing account: handlers resolve a
+//! grantee's request to the owner's record (see [`resolve_access`]), edits
+//! are recorded under the owner, and the grantee's sync pulls include the
+//! owner's changes to the entities shared with them. Sharing and revoking
+//! record an insert and a delete in the grantee's own change log, so their
+//! devices pick the entity up and drop it again.
+
+pub mod handlers;
+
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::models::account::AccountRole;
+use crate::models::client::Client;
+use crate::models::project::Project;
+use crate::models::share_grant::{CreateShareGrant, ShareEntity, ShareGrant, SharePermission};
+use crate::models::sync_change::SyncOperation;
+use crate::sync::server::record_server_change;
+
+/// How a user may act on a project or client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Act on the record of this account (the user's own unless shared)
+    Allowed { owner_id: Uuid },
+
+    /// It is shared with the user, but not with the permission needed
+    ReadOnly,
+}
+
+/// Validates a share request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_share(request: &CreateShareGrant) -> Result<(), String> {
+    let email = request.grantee_email.trim();
+    if email.len() > 255 || !email.contains('@') || email.starts_with('@') || email.ends_with('@') {
+        return Err("grantee_email must be a valid email address".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that a user can be shared with.
+///
+/// # Arguments
+///
+/// * `owner_id` - ID of the sharing account
+/// * `grantee_id` - ID of the user to share with
+/// * `role` - The grantee's role in the sharing account, if any
+///
+/// # Returns
+///
+/// Returns a user-facing message if the grantee is the account itself or
+/// already works in it.
+pub fn check_grantee(owner_id: Uuid, grantee_id: Uuid, role: Option<AccountRole>) -> Result<(), String> {
+    if owner_id == grantee_id {
+        return Err("you can't share with yourself".to_string());
+    }
+    if role.is_some() {
+        return Err("grantee is already a member of this account".to_string());
+    }
+    Ok(())
+}
+
+/// Decides how a user may act on an entity given the grant made to them.
+///
+/// Without a grant the user acts on their own record, which the caller
+/// still has to find.
+pub fn access_from(user_id: Uuid, grant: Option<&ShareGrant>, needed: SharePermission) -> Access {
+    match grant {
+        None => Access::Allowed { owner_id: user_id },
+        Some(grant) if grant.permission >= needed => Access::Allowed { owner_id: grant.user_id },
+        Some(_) => Access::ReadOnly,
+    }
+}
+
+/// Resolves whose project or client a user's request acts on.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user making the request
+/// * `entity` - Kind of entity
+/// * `entity_id` - ID of the project or client
+/// * `needed` - Permission the request needs
+///
+/// # Returns
+///
+/// Returns the owner to act as (see [`access_from`]).
+pub async fn resolve_access(
+    pool: &PgPool,
+    user_id: Uuid,
+    entity: ShareEntity,
+    entity_id: Uuid,
+    needed: SharePermission,
+) -> Result<Access, anyhow::Error> {
+    let grant = shared_grant(pool, user_id, entity, entity_id).await?;
+    Ok(access_from(user_id, grant.as_ref(), needed))
+}
+
+/// Loads the grant sharing an entity with a user.
+///
+/// # Returns
+///
+/// Returns the grant, or `None` if the entity is not shared with the user.
+pub async fn shared_grant<'e, E>(
+    executor: E,
+    grantee_id: Uuid,
+    entity: ShareEntity,
+    entity_id: Uuid,
+) -> Result<Option<ShareGrant>, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let grant = sqlx::query_as::<_, ShareGrant>(
+        "SELECT * FROM share_grants WHERE grantee_id = $1 AND entity_type = $2 AND entity_id = $3",
+    )
+    .bind(grantee_id)
+    .bind(entity)
+    .bind(entity_id)
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(grant)
+}
+
+/// Finds the active GigPilot user with an email.
+///
+/// Only people can be shared with, not workspaces.
+///
+/// # Returns
+///
+/// Returns the user's ID, or `None` if no active person has that email.
+pub async fn find_grantee(pool: &PgPool, email: &str) -> Result<Option<Uuid>, anyhow::Error> {
+    let grantee_id = sqlx::query_scalar::<_, Uuid>(
+        "SELECT id FROM users WHERE LOWER(email) = LOWER($1) AND kind = 'personal' AND is_active = true",
+    )
+    .bind(email.trim())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(grantee_id)
+}
+
+/// Loads a live entity of the owner as it is synced.
+async fn entity_record(
+    tx: &mut Transaction<'_, Postgres>,
+    owner_id: Uuid,
+    entity: ShareEntity,
+    entity_id: Uuid,
+) -> Result<Option<Value>, anyhow::Error> {
+    let record = match entity {
+        ShareEntity::Project => sqlx::query_as::<_, Project>(
+            "SELECT * FROM projects WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+        )
+        .bind(entity_id)
+        .bind(owner_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .map(|project| serde_json::to_value(&project))
+        .transpose()?,
+        ShareEntity::Client => sqlx::query_as::<_, Client>(
+            "SELECT * FROM clients WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+        )
+        .bind(entity_id)
+        .bind(owner_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .map(|client| serde_json::to_value(&client))
+        .transpose()?,
+    };
+
+    Ok(record)
+}
+
+/// A grant as written by [`share_entity`].
+#[derive(Debug, FromRow)]
+struct UpsertedGrant {
+    #[sqlx(flatten)]
+    grant: ShareGrant,
+
+    /// Whether the row was inserted rather than updated
+    inserted: bool,
+}
+
+/// Shares a project or client with a user, or changes the permission of an
+/// existing share.
+///
+/// A new share records the entity as an insert in the grantee's change log.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `owner_id` - ID of the account owning the entity
+/// * `grantee_id` - ID of the user to share with (see [`check_grantee`])
+/// * `entity` - Kind of entity
+/// * `entity_id` - ID of the project or client
+/// * `permission` - What the grantee may do with it
+///
+/// # Returns
+///
+/// Returns the grant, or `None` if the entity does not exist, is deleted,
+/// or belongs to another account.
+pub async fn share_entity(
+    pool: &PgPool,
+    owner_id: Uuid,
+    grantee_id: Uuid,
+    entity: ShareEntity,
+    entity_id: Uuid,
+    permission: SharePermission,
+) -> Result<Option<ShareGrant>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let Some(record) = entity_record(&mut tx, owner_id, entity, entity_id).await? else {
+        return Ok(None);
+    };
+
+    let UpsertedGrant { grant, inserted } = sqlx::query_as::<_, UpsertedGrant>(
+        r#"
+        INSERT INTO share_grants (user_id, grantee_id, entity_type, entity_id, permission)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (entity_type, entity_id, grantee_id) DO UPDATE
+            SET permission = EXCLUDED.permission,
+                updated_at = NOW()
+        RETURNING *, (xmax = 0) AS inserted
+        "#,
+    )
+    .bind(owner_id)
+    .bind(grantee_id)
+    .bind(entity)
+    .bind(entity_id)
+    .bind(permission)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // The conflict update locks the existing row, setting its xmax; a
+    // freshly inserted row has none
+    if inserted {
+        record_server_change(&mut *tx, grantee_id, entity.table(), entity_id, SyncOperation::Insert, &record).await?;
+    }
+    tx.commit().await?;
+
+    Ok(Some(grant))
+}
+
+/// Revokes a share, either by its owner or by the grantee leaving it.
+///
+/// Records the entity as deleted in the grantee's change log.
+///
+/// # Returns
+///
+/// Returns the revoked grant, or `None` if it does not exist or the user is
+/// neither its owner nor its grantee.
+pub async fn revoke_share(pool: &PgPool, user_id: Uuid, grant_id: Uuid) -> Result<Option<ShareGrant>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let grant = sqlx::query_as::<_, ShareGrant>(
+        "DELETE FROM share_grants WHERE id = $1 AND (user_id = $2 OR grantee_id = $2) RETURNING *",
+    )
+    .bind(grant_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(grant) = &grant {
+        record_server_change(
+            &mut *tx,
+            grant.grantee_id,
+            grant.entity_type.table(),
+            grant.entity_id,
+            SyncOperation::Delete,
+            &json!({ "id": grant.entity_id }),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(grant)
+}
+
+/// Lists the shares an account made, newest first.
+pub async fn list_shares(pool: &PgPool, owner_id: Uuid) -> Result<Vec<ShareGrant>, anyhow::Error> {
+    let grants = sqlx::query_as::<_, ShareGrant>(
+        "SELECT * FROM share_grants WHERE user_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(owner_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(grants)
+}
+
+/// Lists the shares made to a user, newest first.
+pub async fn list_received_shares(pool: &PgPool, grantee_id: Uuid) -> Result<Vec<ShareGrant>, anyhow::Error> {
+    let grants = sqlx::query_as::<_, ShareGrant>(
+        "SELECT * FROM share_grants WHERE grantee_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(grantee_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(grants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn grant(owner_id: Uuid, grantee_id: Uuid, permission: SharePermission) -> ShareGrant {
+        ShareGrant {
+            id: Uuid::new_v4(),
+            user_id: owner_id,
+            grantee_id,
+            entity_type: ShareEntity::Project,
+            entity_id: Uuid::new_v4(),
+            permission,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_grantee_must_be_outside_the_account() {
+        let owner_id = Uuid::new_v4();
+        let outsider = Uuid::new_v4();
+
+        assert!(check_grantee(owner_id, outsider, None).is_ok());
+        assert!(check_grantee(owner_id, owner_id, None).is_err());
+        assert!(check_grantee(owner_id, outsider, Some(AccountRole::Member)).is_err());
+    }
+
+    #[test]
+    fn test_access_follows_the_grant() {
+        let owner_id = Uuid::new_v4();
+        let grantee_id = Uuid::new_v4();
+        let read = grant(owner_id, grantee_id, SharePermission::Read);
+        let edit = grant(owner_id, grantee_id, SharePermission::Edit);
+
+        assert_eq!(
+            access_from(grantee_id, None, SharePermission::Edit),
+            Access::Allowed { owner_id: grantee_id }
+        );
+        assert_eq!(
+            access_from(grantee_id, Some(&read), SharePermission::Read),
+            Access::Allowed { owner_id }
+        );
+        assert_eq!(access_from(grantee_id, Some(&read), SharePermission::Edit), Access::ReadOnly);
+        assert_eq!(
+            access_from(grantee_id, Some(&edit), SharePermission::Edit),
+            Access::Allowed { owner_id }
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::models::statement_schedule::{SaveStatementSchedule, StatementSchedule};
+use crate::statements::{delete_schedule, list_schedules, save_schedule, validate_schedule};
+
+/// List statement schedules endpoint handler.
+///
+/// Handles GET requests to `/api/statement-schedules`.
+pub async fn list_schedules_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<StatementSchedule>>, StatusCode> {
+    let schedules = list_schedules(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list statement schedules for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(schedules))
+}
+
+/// Save statement schedule endpoint handler.
+///
+/// Handles PUT requests to `/api/statement-schedules`. The client's
+/// existing schedule, if any, is replaced.
+pub async fn save_schedule_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<SaveStatementSchedule>,
+) -> Result<Json<StatementSchedule>, (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_schedule(&request) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))));
+    }
+
+    let schedule = save_schedule(&pool, user_id, request, Utc::now().date_naive())
+        .await
+        .map_err(|e| {
+            error!("Failed to save statement schedule for user {}: {}", user_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to save statement schedule" })),
+            )
+        })?;
+
+    Ok(Json(schedule))
+}
+
+/// Delete statement schedule endpoint handler.
+///
+/// Handles DELETE requests to `/api/statement-schedules/:id`.
+pub async fn delete_schedule_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_schedule(&pool, user_id, id).await.map_err(|e| {
+        error!("Failed to delete statement schedule {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,180 @@
+//! Monthly client statements.
+//!
+//! A statement schedule emails one client, on a fixed day each month, the
+//! list of their open invoices and the balance outstanding. Schedules keep
+//! the client's locale and delivery channel; the worker
+//! (`worker::statements`) sends the due ones.
+
+pub mod handlers;
+
+use chrono::{Datelike, NaiveDate};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::statement_schedule::{SaveStatementSchedule, StatementSchedule};
+
+/// Day of the month statements go out when none is given.
+pub const DEFAULT_STATEMENT_DAY: i16 = 1;
+
+/// Validates a statement schedule request.
+///
+/// # Returns
+///
+/// Returns a message describing the first invalid field.
+pub fn validate_schedule(request: &SaveStatementSchedule) -> Result<(), String> {
+    let email = request.client_email.trim();
+    let valid_email = email
+        .split_once('@')
+        .map(|(local, domain)| !local.is_empty() && domain.contains('.'))
+        .unwrap_or(false);
+    if !valid_email {
+        return Err("client_email is not a valid email address".to_string());
+    }
+    if let Some(day) = request.day_of_month {
+        if !(1..=28).contains(&day) {
+            return Err("day_of_month must be between 1 and 28".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// First date on or after `from` falling on `day_of_month`.
+///
+/// # Arguments
+///
+/// * `from` - Earliest acceptable date
+/// * `day_of_month` - Day of the month (1-28, so every month has it)
+pub fn next_statement_date(from: NaiveDate, day_of_month: u32) -> NaiveDate {
+    let day = day_of_month.clamp(1, 28);
+    if from.day() <= day {
+        return from.with_day(day).expect("day 1-28 exists in every month");
+    }
+    let (year, month) = if from.month() == 12 {
+        (from.year() + 1, 1)
+    } else {
+        (from.year(), from.month() + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, day).expect("day 1-28 exists in every month")
+}
+
+/// Lists a user's statement schedules by client email.
+pub async fn list_schedules(pool: &PgPool, user_id: Uuid) -> Result<Vec<StatementSchedule>, anyhow::Error> {
+    let schedules = sqlx::query_as::<_, StatementSchedule>(
+        r#"
+        SELECT * FROM statement_schedules
+        WHERE user_id = $1
+        ORDER BY client_email ASC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(schedules)
+}
+
+/// Creates a client's statement schedule, or replaces the existing one.
+///
+/// The next statement goes out on the first matching day from `today` on.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user sending the statements
+/// * `request` - The schedule (validated with [`validate_schedule`])
+/// * `today` - Current date, the earliest the next statement can go out
+///
+/// # Errors
+///
+/// Returns an error if the request is invalid or the query fails.
+pub async fn save_schedule(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: SaveStatementSchedule,
+    today: NaiveDate,
+) -> Result<StatementSchedule, anyhow::Error> {
+    validate_schedule(&request).map_err(|e| anyhow::anyhow!(e))?;
+    let day_of_month = request.day_of_month.unwrap_or(DEFAULT_STATEMENT_DAY);
+    let client_name = request
+        .client_name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+
+    let schedule = sqlx::query_as::<_, StatementSchedule>(
+        r#"
+        INSERT INTO statement_schedules (
+            user_id, client_email, client_name, day_of_month, locale, channel, enabled, next_run_on
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (user_id, client_email) DO UPDATE
+            SET client_name = EXCLUDED.client_name,
+                day_of_month = EXCLUDED.day_of_month,
+                locale = EXCLUDED.locale,
+                channel = EXCLUDED.channel,
+                enabled = EXCLUDED.enabled,
+                next_run_on = EXCLUDED.next_run_on
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(request.client_email.trim().to_lowercase())
+    .bind(client_name)
+    .bind(day_of_month)
+    .bind(request.locale.unwrap_or_default())
+    .bind(request.channel.unwrap_or_default())
+    .bind(request.enabled.unwrap_or(true))
+    .bind(next_statement_date(today, day_of_month as u32))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(schedule)
+}
+
+/// Deletes a statement schedule.
+///
+/// # Returns
+///
+/// Returns `true` if a schedule was deleted, `false` if none matched.
+pub async fn delete_schedule(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM statement_schedules WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+
+    #[test]
+    fn test_next_statement_date() {
+        assert_eq!(next_statement_date(date(2024, 3, 1), 1), date(2024, 3, 1));
+        assert_eq!(next_statement_date(date(2024, 3, 2), 1), date(2024, 4, 1));
+        assert_eq!(next_statement_date(date(2024, 1, 31), 28), date(2024, 2, 28));
+        assert_eq!(next_statement_date(date(2024, 12, 29), 15), date(2025, 1, 15));
+    }
+
+    #[test]
+    fn test_validate_schedule() {
+        let mut request = SaveStatementSchedule {
+            client_email: "Billing@Acme.com ".to_string(),
+            client_name: None,
+            day_of_month: Some(28),
+            locale: None,
+            channel: None,
+            enabled: None,
+        };
+        assert!(validate_schedule(&request).is_ok());
+
+        request.day_of_month = Some(31);
+        assert!(validate_schedule(&request).is_err());
+
+        request.day_of_month = None;
+        request.client_email = "acme".to_string();
+        assert!(validate_schedule(&request).is_err());
+    }
+}
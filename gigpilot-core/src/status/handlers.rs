@@ -0,0 +1,12 @@
+use axum::{extract::Extension, response::Json};
+use chrono::Utc;
+
+use crate::status::{StatusMonitor, StatusPage};
+
+/// Public status endpoint handler.
+///
+/// Handles GET requests to `/status` (no login). Returns the latest probe
+/// of each component with its uptime counters; see [`StatusMonitor`].
+pub async fn status_handler(Extension(monitor): Extension<StatusMonitor>) -> Json<StatusPage> {
+    Json(monitor.snapshot(Utc::now()))
+}
@@ -0,0 +1,431 @@
+//! Public service status.
+//!
+//! [`StatusMonitor`] probes each component (API, database, worker, email
+//! and LLM providers) on an interval and keeps uptime counters since the
+//! API process started. `GET /status` serves the latest snapshot without
+//! authentication, for public status pages; unlike `/health` it never runs
+//! a check per request and reveals no configuration or error details.
+//!
+//! The worker and provider figures come from `worker_heartbeats`, written
+//! by `worker::heartbeat`: only the worker talks to the providers.
+
+pub mod handlers;
+
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::error;
+
+/// Seconds between worker heartbeats when not configured.
+pub const DEFAULT_HEARTBEAT_INTERVAL_SECONDS: u64 = 30;
+
+/// Seconds between status probes when not configured.
+pub const DEFAULT_STATUS_CHECK_INTERVAL_SECONDS: u64 = 30;
+
+/// Heartbeats a worker may miss before it counts as down.
+const MISSED_HEARTBEATS: i32 = 3;
+
+/// How long a provider failure affects its status, in minutes.
+const PROVIDER_ERROR_WINDOW_MINUTES: i64 = 15;
+
+/// Longest a database probe may take, in seconds.
+const DATABASE_TIMEOUT_SECONDS: u64 = 5;
+
+/// Interval between worker heartbeats (`WORKER_HEARTBEAT_INTERVAL_SECONDS`).
+pub fn heartbeat_interval_seconds() -> u64 {
+    std::env::var("WORKER_HEARTBEAT_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECONDS)
+}
+
+/// A component shown on the status page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    Api,
+    Database,
+    Worker,
+    Email,
+    Llm,
+}
+
+impl Component {
+    /// Every component, in display order.
+    pub const ALL: [Component; 5] = [
+        Component::Api,
+        Component::Database,
+        Component::Worker,
+        Component::Email,
+        Component::Llm,
+    ];
+}
+
+/// Health of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    /// Working normally
+    Operational,
+
+    /// Working, with some failures
+    Degraded,
+
+    /// Not working
+    Outage,
+
+    /// Can't be determined (e.g. the database holding its data is down)
+    Unknown,
+}
+
+impl ComponentStatus {
+    /// Whether the component counts as up for uptime (degraded counts).
+    fn is_up(&self) -> Option<bool> {
+        match self {
+            ComponentStatus::Operational | ComponentStatus::Degraded => Some(true),
+            ComponentStatus::Outage => Some(false),
+            ComponentStatus::Unknown => None,
+        }
+    }
+}
+
+/// What the latest worker heartbeats report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeartbeatSummary {
+    /// Most recent heartbeat of any worker
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub email_ok_at: Option<DateTime<Utc>>,
+    pub email_error_at: Option<DateTime<Utc>>,
+    pub llm_ok_at: Option<DateTime<Utc>>,
+    pub llm_error_at: Option<DateTime<Utc>>,
+}
+
+/// Status of the worker from its latest heartbeat.
+///
+/// # Arguments
+///
+/// * `last_seen_at` - Most recent heartbeat, if any worker ever ran
+/// * `now` - Current time
+/// * `interval_seconds` - Heartbeat interval
+pub fn worker_status(last_seen_at: Option<DateTime<Utc>>, now: DateTime<Utc>, interval_seconds: u64) -> ComponentStatus {
+    let Some(last_seen_at) = last_seen_at else {
+        return ComponentStatus::Unknown;
+    };
+    let allowed = Duration::seconds(interval_seconds as i64) * MISSED_HEARTBEATS;
+    if now - last_seen_at <= allowed {
+        ComponentStatus::Operational
+    } else {
+        ComponentStatus::Outage
+    }
+}
+
+/// Status of a provider from the worker's latest calls.
+///
+/// A provider whose latest call failed within the error window is in an
+/// outage, or degraded if another call succeeded in that window. Without
+/// a live worker nothing is known about it.
+pub fn provider_status(
+    ok_at: Option<DateTime<Utc>>,
+    error_at: Option<DateTime<Utc>>,
+    worker: ComponentStatus,
+    now: DateTime<Utc>,
+) -> ComponentStatus {
+    if worker != ComponentStatus::Operational {
+        return ComponentStatus::Unknown;
+    }
+    let window_start = now - Duration::minutes(PROVIDER_ERROR_WINDOW_MINUTES);
+    match (ok_at, error_at) {
+        (ok_at, Some(error_at)) if error_at >= window_start && ok_at.is_none_or(|ok_at| ok_at < error_at) => {
+            if ok_at.is_some_and(|ok_at| ok_at >= window_start) {
+                ComponentStatus::Degraded
+            } else {
+                ComponentStatus::Outage
+            }
+        }
+        _ => ComponentStatus::Operational,
+    }
+}
+
+/// Overall status: an outage if the API or database is down, degraded if
+/// any other component has problems. Unknown components are ignored.
+pub fn overall_status(components: &[(Component, ComponentStatus)]) -> ComponentStatus {
+    let mut overall = ComponentStatus::Operational;
+    for (component, status) in components {
+        match (component, status) {
+            (Component::Api | Component::Database, ComponentStatus::Outage) => return ComponentStatus::Outage,
+            (_, ComponentStatus::Degraded | ComponentStatus::Outage) => overall = ComponentStatus::Degraded,
+            _ => {}
+        }
+    }
+    overall
+}
+
+/// Probes every component once.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `now` - Current time
+///
+/// # Returns
+///
+/// Returns the status of each component, in [`Component::ALL`] order.
+pub async fn probe(pool: &PgPool, now: DateTime<Utc>) -> Vec<(Component, ComponentStatus)> {
+    let timeout = std::time::Duration::from_secs(DATABASE_TIMEOUT_SECONDS);
+    let heartbeats = tokio::time::timeout(timeout, load_heartbeats(pool)).await;
+
+    let (database, heartbeats) = match heartbeats {
+        Ok(Ok(heartbeats)) => (ComponentStatus::Operational, Some(heartbeats)),
+        Ok(Err(e)) => {
+            error!("Status probe could not query the database: {}", e);
+            (ComponentStatus::Outage, None)
+        }
+        Err(_) => {
+            error!("Status probe timed out querying the database");
+            (ComponentStatus::Outage, None)
+        }
+    };
+
+    let (worker, email, llm) = match heartbeats {
+        Some(h) => {
+            let worker = worker_status(h.last_seen_at, now, heartbeat_interval_seconds());
+            (
+                worker,
+                provider_status(h.email_ok_at, h.email_error_at, worker, now),
+                provider_status(h.llm_ok_at, h.llm_error_at, worker, now),
+            )
+        }
+        None => (ComponentStatus::Unknown, ComponentStatus::Unknown, ComponentStatus::Unknown),
+    };
+
+    vec![
+        (Component::Api, ComponentStatus::Operational),
+        (Component::Database, database),
+        (Component::Worker, worker),
+        (Component::Email, email),
+        (Component::Llm, llm),
+    ]
+}
+
+/// Reads the latest heartbeat and provider outcomes of all workers.
+async fn load_heartbeats(pool: &PgPool) -> Result<HeartbeatSummary, anyhow::Error> {
+    let row = sqlx::query_as::<_, (
+        Option<DateTime<Utc>>,
+        Option<DateTime<Utc>>,
+        Option<DateTime<Utc>>,
+        Option<DateTime<Utc>>,
+        Option<DateTime<Utc>>,
+    )>(
+        r#"
+        SELECT max(last_seen_at), max(email_ok_at), max(email_error_at), max(llm_ok_at), max(llm_error_at)
+        FROM worker_heartbeats
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(HeartbeatSummary {
+        last_seen_at: row.0,
+        email_ok_at: row.1,
+        email_error_at: row.2,
+        llm_ok_at: row.3,
+        llm_error_at: row.4,
+    })
+}
+
+/// Health and uptime counters of one component.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComponentHealth {
+    pub name: Component,
+    pub status: ComponentStatus,
+
+    /// When the component entered its current status
+    pub since: DateTime<Utc>,
+
+    /// Probes that could determine the component's status
+    pub checks: u64,
+
+    /// Probes that found it operational or degraded
+    pub up_checks: u64,
+
+    /// `up_checks` as a percentage of `checks`, if any
+    pub uptime_percent: Option<f64>,
+}
+
+/// The public status document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusPage {
+    /// Worst status across components (see [`overall_status`])
+    pub status: ComponentStatus,
+
+    /// When the components were last probed
+    pub updated_at: Option<DateTime<Utc>>,
+
+    /// When this API process, and its counters, started
+    pub started_at: DateTime<Utc>,
+
+    /// Seconds since `started_at`
+    pub uptime_seconds: i64,
+
+    pub components: Vec<ComponentHealth>,
+}
+
+#[derive(Debug)]
+struct MonitorState {
+    updated_at: Option<DateTime<Utc>>,
+    components: Vec<ComponentHealth>,
+}
+
+/// Periodically probed component health, shared with the status handler.
+#[derive(Debug, Clone)]
+pub struct StatusMonitor {
+    started_at: DateTime<Utc>,
+    state: Arc<RwLock<MonitorState>>,
+}
+
+impl StatusMonitor {
+    /// Creates a monitor with every component unknown.
+    pub fn new(started_at: DateTime<Utc>) -> Self {
+        let components = Component::ALL
+            .into_iter()
+            .map(|name| ComponentHealth {
+                name,
+                status: ComponentStatus::Unknown,
+                since: started_at,
+                checks: 0,
+                up_checks: 0,
+                uptime_percent: None,
+            })
+            .collect();
+
+        Self {
+            started_at,
+            state: Arc::new(RwLock::new(MonitorState { updated_at: None, components })),
+        }
+    }
+
+    /// Records the result of one probe.
+    pub fn record(&self, samples: &[(Component, ComponentStatus)], now: DateTime<Utc>) {
+        let Ok(mut state) = self.state.write() else { return };
+        state.updated_at = Some(now);
+        for (name, status) in samples {
+            let Some(health) = state.components.iter_mut().find(|health| health.name == *name) else {
+                continue;
+            };
+            if health.status != *status {
+                health.status = *status;
+                health.since = now;
+            }
+            if let Some(up) = status.is_up() {
+                health.checks += 1;
+                health.up_checks += u64::from(up);
+                let percent = health.up_checks as f64 * 100.0 / health.checks as f64;
+                health.uptime_percent = Some((percent * 1000.0).round() / 1000.0);
+            }
+        }
+    }
+
+    /// The status document as of the latest probe.
+    pub fn snapshot(&self, now: DateTime<Utc>) -> StatusPage {
+        let (updated_at, components) = match self.state.read() {
+            Ok(state) => (state.updated_at, state.components.clone()),
+            Err(_) => (None, Vec::new()),
+        };
+        let statuses: Vec<(Component, ComponentStatus)> =
+            components.iter().map(|health| (health.name, health.status)).collect();
+
+        StatusPage {
+            status: overall_status(&statuses),
+            updated_at,
+            started_at: self.started_at,
+            uptime_seconds: (now - self.started_at).num_seconds(),
+            components,
+        }
+    }
+
+    /// Spawns the background task probing components.
+    ///
+    /// Probes every `STATUS_CHECK_INTERVAL_SECONDS` (default: 30 seconds),
+    /// starting immediately.
+    pub fn spawn(&self, pool: PgPool) {
+        let seconds = std::env::var("STATUS_CHECK_INTERVAL_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(DEFAULT_STATUS_CHECK_INTERVAL_SECONDS);
+        let monitor = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+            loop {
+                interval.tick().await;
+                let samples = probe(&pool, Utc::now()).await;
+                monitor.record(&samples, Utc::now());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_status_from_heartbeat_age() {
+        let now = Utc::now();
+        assert_eq!(worker_status(None, now, 30), ComponentStatus::Unknown);
+        assert_eq!(worker_status(Some(now - Duration::seconds(80)), now, 30), ComponentStatus::Operational);
+        assert_eq!(worker_status(Some(now - Duration::seconds(100)), now, 30), ComponentStatus::Outage);
+    }
+
+    #[test]
+    fn test_provider_status() {
+        let now = Utc::now();
+        let up = ComponentStatus::Operational;
+        let minutes_ago = |minutes| Some(now - Duration::minutes(minutes));
+
+        assert_eq!(provider_status(None, None, up, now), ComponentStatus::Operational);
+        assert_eq!(provider_status(minutes_ago(1), minutes_ago(5), up, now), ComponentStatus::Operational);
+        assert_eq!(provider_status(minutes_ago(5), minutes_ago(1), up, now), ComponentStatus::Degraded);
+        assert_eq!(provider_status(minutes_ago(60), minutes_ago(1), up, now), ComponentStatus::Outage);
+        assert_eq!(provider_status(None, minutes_ago(1), up, now), ComponentStatus::Outage);
+        assert_eq!(provider_status(None, minutes_ago(30), up, now), ComponentStatus::Operational);
+        assert_eq!(
+            provider_status(None, minutes_ago(1), ComponentStatus::Outage, now),
+            ComponentStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn test_overall_status() {
+        use ComponentStatus::*;
+        assert_eq!(overall_status(&[(Component::Api, Operational), (Component::Llm, Unknown)]), Operational);
+        assert_eq!(overall_status(&[(Component::Api, Operational), (Component::Worker, Outage)]), Degraded);
+        assert_eq!(overall_status(&[(Component::Database, Outage), (Component::Email, Degraded)]), Outage);
+    }
+
+    #[test]
+    fn test_monitor_counts_uptime() {
+        let start = Utc::now();
+        let monitor = StatusMonitor::new(start);
+        let later = start + Duration::seconds(30);
+
+        monitor.record(&[(Component::Database, ComponentStatus::Operational)], start);
+        monitor.record(&[(Component::Database, ComponentStatus::Outage)], later);
+        monitor.record(&[(Component::Database, ComponentStatus::Unknown)], later);
+        monitor.record(&[(Component::Database, ComponentStatus::Degraded)], later);
+
+        let page = monitor.snapshot(start + Duration::seconds(90));
+        let database = &page.components[1];
+        assert_eq!(database.name, Component::Database);
+        assert_eq!((database.checks, database.up_checks), (3, 2));
+        assert_eq!(database.uptime_percent, Some(66.667));
+        assert_eq!(database.since, later);
+        assert_eq!(page.uptime_seconds, 90);
+        assert_eq!(page.status, ComponentStatus::Degraded);
+        assert_eq!(page.components[0].uptime_percent, None);
+    }
+}
@@ -0,0 +1,345 @@
+//! Blob storage backends.
+//!
+//! Binary contents that don't belong in the database (attachments, cached
+//! invoice PDFs) go through a [`BlobStore`], chosen at startup: local disk,
+//! S3, or memory. Callers namespace their keys (e.g. `pdf-cache/...`) so one
+//! store serves them all.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_s3::primitives::ByteStream;
+use tracing::{info, warn};
+
+/// Directory used by the local backend when none is configured.
+pub const DEFAULT_STORAGE_DIR: &str = "./data/attachments";
+
+/// Where binary contents are kept.
+///
+/// Keys are relative, `/`-separated paths.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Short backend name for logs (e.g. "local").
+    fn name(&self) -> &'static str;
+
+    /// Stores a blob under `key`, replacing any existing one.
+    async fn put(&self, key: &str, content_type: &str, data: &[u8]) -> Result<(), anyhow::Error>;
+
+    /// Reads the blob stored under `key`.
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error>;
+
+    /// Removes the blob stored under `key`; missing blobs are not an error.
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error>;
+}
+
+/// Shared storage handle, passed to handlers as an `Extension`.
+pub type DynBlobStore = Arc<dyn BlobStore>;
+
+/// Stores blobs in a directory on the local disk.
+#[derive(Debug, Clone)]
+pub struct LocalBlobStore {
+    /// Directory holding every stored blob
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    /// Creates a store rooted at `root` (created on first write).
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolves a key inside the root, rejecting keys that could escape it.
+    fn path(&self, key: &str) -> Result<PathBuf, anyhow::Error> {
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            anyhow::bail!("Invalid storage key: {}", key);
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, _content_type: &str, data: &[u8]) -> Result<(), anyhow::Error> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, data).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(tokio::fs::read(self.path(key)?).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Stores blobs in an S3 (or S3-compatible) bucket.
+#[derive(Debug, Clone)]
+pub struct S3BlobStore {
+    /// S3 client configured from the environment
+    client: aws_sdk_s3::Client,
+
+    /// Bucket holding every stored blob
+    bucket: String,
+
+    /// Prefix prepended to every key (may be empty)
+    prefix: String,
+}
+
+impl S3BlobStore {
+    /// Creates a store over an existing client.
+    pub fn new(client: aws_sdk_s3::Client, bucket: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Full object key for a storage key.
+    fn object_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix.trim_end_matches('/'), key)
+        }
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put(&self, key: &str, content_type: &str, data: &[u8]) -> Result<(), anyhow::Error> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .content_type(content_type)
+            .body(ByteStream::from(data.to_vec()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        Ok(object.body.collect().await?.into_bytes().to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+/// Keeps blobs in process memory.
+///
+/// Contents are lost on restart and not shared between processes; meant
+/// for tests and throwaway instances.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBlobStore {
+    /// Stored blobs by key
+    blobs: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl MemoryBlobStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl BlobStore for MemoryBlobStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn put(&self, key: &str, _content_type: &str, data: &[u8]) -> Result<(), anyhow::Error> {
+        self.blobs.lock().unwrap().insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No blob stored under {}", key))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error> {
+        self.blobs.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Storage settings read from the environment.
+///
+/// Every setting has a `STORAGE_*` variable and falls back to the
+/// `ATTACHMENT_*` variable used before other features stored blobs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageConfig {
+    /// Backend name: "local", "s3" or "memory" (`STORAGE_BACKEND`)
+    pub backend: String,
+
+    /// Root directory of the local backend (`STORAGE_DIR`)
+    pub dir: String,
+
+    /// Bucket of the S3 backend (`STORAGE_S3_BUCKET`)
+    pub s3_bucket: Option<String>,
+
+    /// Key prefix of the S3 backend (`STORAGE_S3_PREFIX`)
+    pub s3_prefix: String,
+}
+
+impl StorageConfig {
+    /// Reads the settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - Looks up an environment variable (`std::env::var` in production)
+    pub fn from_env(env: &dyn Fn(&str) -> Option<String>) -> Self {
+        let setting = |name: &str, legacy: &str| {
+            env(name)
+                .or_else(|| env(legacy))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Self {
+            backend: setting("STORAGE_BACKEND", "ATTACHMENT_STORAGE").unwrap_or_else(|| "local".to_string()),
+            dir: setting("STORAGE_DIR", "ATTACHMENT_DIR").unwrap_or_else(|| DEFAULT_STORAGE_DIR.to_string()),
+            s3_bucket: setting("STORAGE_S3_BUCKET", "ATTACHMENT_S3_BUCKET"),
+            s3_prefix: setting("STORAGE_S3_PREFIX", "ATTACHMENT_S3_PREFIX").unwrap_or_default(),
+        }
+    }
+}
+
+/// Selects the storage backend from [`StorageConfig`].
+///
+/// - `local` stores blobs under `STORAGE_DIR` (default: `./data/attachments`)
+/// - `s3` stores blobs in `STORAGE_S3_BUCKET` under the optional
+///   `STORAGE_S3_PREFIX`, with credentials and region taken from the
+///   standard AWS environment variables
+/// - `memory` keeps blobs in process memory until restart
+///
+/// # Errors
+///
+/// Returns an error if `s3` is selected without a bucket.
+pub async fn store_from_env() -> Result<DynBlobStore, anyhow::Error> {
+    let config = StorageConfig::from_env(&|name| std::env::var(name).ok());
+    let store: DynBlobStore = match config.backend.as_str() {
+        "s3" => {
+            let bucket = config
+                .s3_bucket
+                .ok_or_else(|| anyhow::anyhow!("STORAGE_S3_BUCKET must be set when STORAGE_BACKEND=s3"))?;
+            let aws = aws_config::load_from_env().await;
+            Arc::new(S3BlobStore::new(aws_sdk_s3::Client::new(&aws), bucket, config.s3_prefix))
+        }
+        "memory" => {
+            warn!("Blobs are kept in memory and will be lost on restart");
+            Arc::new(MemoryBlobStore::new())
+        }
+        other => {
+            if other != "local" {
+                warn!("Unknown storage backend: {}, defaulting to local", other);
+            }
+            Arc::new(LocalBlobStore::new(config.dir))
+        }
+    };
+
+    info!("Storing blobs with the {} backend", store.name());
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_keys_stay_inside_root() {
+        let store = LocalBlobStore::new("/srv/attachments");
+        assert_eq!(
+            store.path("a/b/c").unwrap(),
+            PathBuf::from("/srv/attachments/a/b/c")
+        );
+        assert!(store.path("../etc/passwd").is_err());
+        assert!(store.path("/etc/passwd").is_err());
+        assert!(store.path("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_round_trip() {
+        let root = std::env::temp_dir().join(format!("gigpilot-attachments-{}", uuid::Uuid::new_v4()));
+        let store = LocalBlobStore::new(&root);
+
+        store.put("user/invoice/file", "text/plain", b"hello").await.unwrap();
+        assert_eq!(store.get("user/invoice/file").await.unwrap(), b"hello");
+
+        store.delete("user/invoice/file").await.unwrap();
+        store.delete("user/invoice/file").await.unwrap();
+        assert!(store.get("user/invoice/file").await.is_err());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_memory_round_trip() {
+        let store = MemoryBlobStore::new();
+
+        store.put("a/b", "text/plain", b"hello").await.unwrap();
+        assert_eq!(store.get("a/b").await.unwrap(), b"hello");
+
+        store.delete("a/b").await.unwrap();
+        store.delete("a/b").await.unwrap();
+        assert!(store.get("a/b").await.is_err());
+    }
+
+    #[test]
+    fn test_config_falls_back_to_attachment_variables() {
+        let vars: HashMap<&str, &str> = [
+            ("ATTACHMENT_STORAGE", "s3"),
+            ("ATTACHMENT_S3_BUCKET", "old-bucket"),
+            ("STORAGE_S3_BUCKET", "blobs"),
+            ("STORAGE_S3_PREFIX", " "),
+        ]
+        .into();
+        let config = StorageConfig::from_env(&|name| vars.get(name).map(|v| v.to_string()));
+
+        assert_eq!(config.backend, "s3");
+        assert_eq!(config.s3_bucket.as_deref(), Some("blobs"));
+        assert_eq!(config.s3_prefix, "");
+        assert_eq!(config.dir, DEFAULT_STORAGE_DIR);
+    }
+}
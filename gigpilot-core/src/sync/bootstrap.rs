@@ -0,0 +1,405 @@
+//! Streamed first-sync bootstrap.
+//!
+//! A new device loads the user's current rows instead of replaying the
+//! change log, which pruning would leave incomplete anyway. The rows are
+//! read in one repeatable-read transaction, so they are consistent with
+//! each other and with the returned cursor, and streamed as
+//! newline-delimited JSON one page at a time, so memory use doesn't grow
+//! with the account:
+//!
+//! ```text
+//! {"type":"start","schema_version":1,"timestamp":"..."}
+//! {"type":"record","table":"invoices","record":{...}}
+//! ...
+//! {"type":"end","sequence_number":1042,"timestamp":"...","counts":{"invoices":12,...}}
+//! ```
+//!
+//! Records of tables the user syncs end-to-end encrypted follow the
+//! plaintext ones, as `{"id","version","encrypted"}`. Projects and clients
+//! shared with the user are sent along with their own, as pulls deliver
+//! them.
+//!
+//! The `end` line carries the cursor to resume incremental pulls from; a
+//! stream without it was cut off and must be fetched again.
+//!
+//! Each stream holds a database connection until it ends, so only
+//! `SYNC_MAX_CONCURRENT_BOOTSTRAPS` (default 3) stream at once per process,
+//! and a client that stops reading for [`BOOTSTRAP_SEND_TIMEOUT_SECONDS`]
+//! is cut off.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hyper::body::{Body, Bytes, Sender};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::models::client::Client;
+use crate::models::estimate::Estimate;
+use crate::models::invoice::Invoice;
+use crate::models::project::Project;
+use crate::models::time_entry::TimeEntry;
+use crate::settings::{load_user_settings, sync_record};
+use crate::sync::encryption;
+use crate::sync::evolution;
+use crate::sync::pull::{latest_sequence, pulled_rows};
+use crate::sync::schema::SYNC_SCHEMA_VERSION;
+
+/// Rows read (and sent) at a time per table.
+pub const BOOTSTRAP_PAGE_SIZE: i64 = 500;
+
+/// Bootstraps streamed at once when `SYNC_MAX_CONCURRENT_BOOTSTRAPS` isn't
+/// set.
+pub const DEFAULT_MAX_CONCURRENT_BOOTSTRAPS: usize = 3;
+
+/// Seconds a client may take to read a page before its stream is cut off.
+pub const BOOTSTRAP_SEND_TIMEOUT_SECONDS: u64 = 30;
+
+/// Seconds a client turned away because too many bootstraps are streaming
+/// is asked to wait.
+pub const BOOTSTRAP_RETRY_AFTER_SECONDS: u64 = 10;
+
+/// Permits for the bootstraps streaming in this process.
+fn bootstrap_permits() -> Arc<Semaphore> {
+    static PERMITS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+    PERMITS
+        .get_or_init(|| {
+            let permits = std::env::var("SYNC_MAX_CONCURRENT_BOOTSTRAPS")
+                .ok()
+                .and_then(|s| s.parse::<usize>().ok())
+                .filter(|permits| *permits > 0)
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_BOOTSTRAPS);
+            Arc::new(Semaphore::new(permits))
+        })
+        .clone()
+}
+
+/// Columns of an invoice, as the snapshot reads them.
+const INVOICE_COLUMNS: &str = "id, user_id, invoice_number, client_name, client_email, \
+    amount, currency, status, due_date, issue_date, \
+    last_modified, version_vector, is_deleted, \
+    description, line_items, subtotal, tax_total, total, amount_paid, \
+    client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at";
+
+/// One line of the bootstrap stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BootstrapLine {
+    /// First line
+    Start {
+        /// Sync schema version the server expects pushed records to follow
+        schema_version: u32,
+
+        /// When the snapshot was taken
+        timestamp: DateTime<Utc>,
+    },
+
+    /// A live record, shaped like pulled records
+    Record { table: String, record: Value },
+
+    /// Last line: the snapshot is complete
+    End {
+        /// Cursor for the next pull's `last_sequence_number`
+        sequence_number: i64,
+
+        /// When the snapshot was taken (for older clients' next pull)
+        timestamp: DateTime<Utc>,
+
+        /// Records sent per table
+        counts: BTreeMap<String, usize>,
+    },
+}
+
+impl BootstrapLine {
+    /// The line as sent, newline included.
+    fn to_bytes(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+/// Starts streaming a bootstrap snapshot of the user's rows.
+///
+/// The snapshot is taken before this returns, so a database error is
+/// reported as such; later failures, and clients too slow to read, cut the
+/// stream off before its `end` line.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+///
+/// # Returns
+///
+/// Returns the newline-delimited JSON body (see the module docs), or
+/// `None` if as many bootstraps as allowed are already streaming.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot transaction can't be started.
+pub async fn stream_bootstrap(pool: &PgPool, user_id: Uuid) -> Result<Option<Body>, anyhow::Error> {
+    let Ok(permit) = bootstrap_permits().try_acquire_owned() else {
+        return Ok(None);
+    };
+
+    // Take the timestamp before reading so concurrent writes are picked up
+    // by the next incremental pull rather than lost.
+    let timestamp = Utc::now();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    // The first read fixes the snapshot every later page is read from
+    let sequence_number = latest_sequence(&mut *tx, user_id).await?.unwrap_or(0);
+
+    let (sender, body) = Body::channel();
+    let mut sender = TimedSender { sender, _permit: permit };
+    tokio::spawn(async move {
+        match send_snapshot(&mut tx, &mut sender, user_id, sequence_number, timestamp).await {
+            Ok(counts) => info!("Streamed bootstrap for user {}: {:?}", user_id, counts),
+            Err(e) => {
+                warn!("Bootstrap stream for user {} stopped: {}", user_id, e);
+                sender.sender.abort();
+            }
+        }
+        if let Err(e) = tx.rollback().await {
+            warn!("Failed to end bootstrap snapshot for user {}: {}", user_id, e);
+        }
+    });
+
+    Ok(Some(body))
+}
+
+/// The sending end of a bootstrap stream, holding its permit.
+struct TimedSender {
+    sender: Sender,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl TimedSender {
+    /// Sends a chunk, giving up if the client doesn't take it in time.
+    async fn send_data(&mut self, chunk: Bytes) -> Result<(), anyhow::Error> {
+        tokio::time::timeout(
+            Duration::from_secs(BOOTSTRAP_SEND_TIMEOUT_SECONDS),
+            self.sender.send_data(chunk),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("client stopped reading"))??;
+        Ok(())
+    }
+}
+
+/// Sends every line of the snapshot.
+async fn send_snapshot(
+    tx: &mut Transaction<'static, Postgres>,
+    sender: &mut TimedSender,
+    user_id: Uuid,
+    sequence_number: i64,
+    timestamp: DateTime<Utc>,
+) -> Result<BTreeMap<String, usize>, anyhow::Error> {
+    let start = BootstrapLine::Start {
+        schema_version: SYNC_SCHEMA_VERSION,
+        timestamp,
+    };
+    sender.send_data(Bytes::from(start.to_bytes()?)).await?;
+
+    let mut counts = BTreeMap::new();
+    let sent = send_table::<Invoice>(tx, sender, user_id, "invoices", INVOICE_COLUMNS).await?;
+    counts.insert("invoices".to_string(), sent);
+    let sent = send_table::<Estimate>(tx, sender, user_id, "estimates", "*").await?;
+    counts.insert("estimates".to_string(), sent);
+    let sent = send_table::<Client>(tx, sender, user_id, "clients", "*").await?;
+    counts.insert("clients".to_string(), sent);
+    let sent = send_table::<Project>(tx, sender, user_id, "projects", "*").await?;
+    counts.insert("projects".to_string(), sent);
+    let sent = send_table::<TimeEntry>(tx, sender, user_id, "time_entries", "*").await?;
+    counts.insert("time_entries".to_string(), sent);
+
+    let settings = load_user_settings(&mut **tx, user_id).await?;
+    let line = BootstrapLine::Record {
+        table: "user_settings".to_string(),
+        record: sync_record(&settings)?,
+    };
+    sender.send_data(Bytes::from(line.to_bytes()?)).await?;
+    counts.insert("user_settings".to_string(), 1);
+
+    send_encrypted(tx, sender, user_id, &mut counts).await?;
+
+    let end = BootstrapLine::End {
+        sequence_number,
+        timestamp,
+        counts: counts.clone(),
+    };
+    sender.send_data(Bytes::from(end.to_bytes()?)).await?;
+
+    Ok(counts)
+}
+
+/// Sends a table's live rows the user pulls (see [`pulled_rows`]) a page
+/// at a time, in ID order.
+///
+/// # Returns
+///
+/// Returns the number of records sent.
+async fn send_table<T>(
+    tx: &mut Transaction<'static, Postgres>,
+    sender: &mut TimedSender,
+    user_id: Uuid,
+    table: &str,
+    columns: &str,
+) -> Result<usize, anyhow::Error>
+where
+    T: for<'r> FromRow<'r, PgRow> + Serialize + Send + Unpin + HasId,
+{
+    let query = format!(
+        "SELECT {} FROM {} WHERE {} AND is_deleted = false AND id > $2 ORDER BY id LIMIT $3",
+        columns,
+        table,
+        pulled_rows(table)
+    );
+
+    let mut sent = 0;
+    let mut after = Uuid::nil();
+    loop {
+        let rows = sqlx::query_as::<_, T>(&query)
+            .bind(user_id)
+            .bind(after)
+            .bind(BOOTSTRAP_PAGE_SIZE)
+            .fetch_all(&mut **tx)
+            .await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        after = last.id();
+
+        let mut chunk = Vec::new();
+        for row in &rows {
+            let mut record = serde_json::to_value(row)?;
+            // Keep renamed columns readable for older app versions
+            evolution::dual_write_record(table, &mut record);
+            let line = BootstrapLine::Record {
+                table: table.to_string(),
+                record,
+            };
+            chunk.extend(line.to_bytes()?);
+        }
+        sender.send_data(Bytes::from(chunk)).await?;
+
+        sent += rows.len();
+        if (rows.len() as i64) < BOOTSTRAP_PAGE_SIZE {
+            break;
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Sends the user's live encrypted records a page at a time, as stored,
+/// adding them to the counts of their tables.
+async fn send_encrypted(
+    tx: &mut Transaction<'static, Postgres>,
+    sender: &mut TimedSender,
+    user_id: Uuid,
+    counts: &mut BTreeMap<String, usize>,
+) -> Result<(), anyhow::Error> {
+    let mut after = None;
+    loop {
+        let records = encryption::live_records(tx, user_id, after, Some(BOOTSTRAP_PAGE_SIZE)).await?;
+        let Some(last) = records.last() else {
+            break;
+        };
+        after = Some(last.id);
+
+        let mut chunk = Vec::new();
+        for record in &records {
+            *counts.entry(record.table_name.clone()).or_insert(0) += 1;
+            let line = BootstrapLine::Record {
+                table: record.table_name.clone(),
+                record: record.to_record(),
+            };
+            chunk.extend(line.to_bytes()?);
+        }
+        sender.send_data(Bytes::from(chunk)).await?;
+
+        if (records.len() as i64) < BOOTSTRAP_PAGE_SIZE {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// A row with an ID to page by.
+trait HasId {
+    fn id(&self) -> Uuid;
+}
+
+impl HasId for Invoice {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl HasId for Estimate {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl HasId for Client {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl HasId for Project {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl HasId for TimeEntry {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_line_format() {
+        let timestamp = Utc::now();
+        let record = BootstrapLine::Record {
+            table: "clients".to_string(),
+            record: json!({ "id": Uuid::nil() }),
+        };
+        let bytes = record.to_bytes().unwrap();
+        assert_eq!(bytes.last(), Some(&b'\n'));
+        let line: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(line["type"], "record");
+        assert_eq!(line["table"], "clients");
+
+        let end = BootstrapLine::End {
+            sequence_number: 1042,
+            timestamp,
+            counts: BTreeMap::from([("clients".to_string(), 1)]),
+        };
+        let line: Value = serde_json::from_slice(&end.to_bytes().unwrap()).unwrap();
+        assert_eq!(line["type"], "end");
+        assert_eq!(line["sequence_number"], 1042);
+        assert_eq!(line["counts"]["clients"], 1);
+    }
+}
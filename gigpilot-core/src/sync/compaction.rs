@@ -0,0 +1,182 @@
+//! Compaction of the sync change log.
+//!
+//! A record edited many times produces one change per save, and every one
+//! of them is read (and counted against page limits) by each pull, even
+//! though only the latest state reaches the device. Once a compaction
+//! window has passed, successive updates one device made to the same record
+//! within it are collapsed into the latest of them:
+//!
+//! - the surviving change keeps its sequence number, timestamp and data, so
+//!   cursors and timestamps of devices that pulled some of the run still
+//!   lead to it, and the latest state is unchanged
+//! - its `old_data` becomes that of the first change of the run, so it still
+//!   describes the whole edit
+//! - inserts, deletes and conflicted changes are never removed and end a
+//!   run, so conflict history and the created/updated/deleted envelope stay
+//!   as they were
+//! - `compacted_changes` counts the changes folded into it, for device
+//!   statistics
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+/// Default length of a compaction window in minutes.
+pub const DEFAULT_COMPACTION_WINDOW_MINUTES: i64 = 60;
+
+/// Returns the configured compaction window.
+///
+/// Read from `SYNC_COMPACTION_WINDOW_MINUTES`, falling back to
+/// [`DEFAULT_COMPACTION_WINDOW_MINUTES`].
+pub fn compaction_window() -> Duration {
+    let minutes = std::env::var("SYNC_COMPACTION_WINDOW_MINUTES")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|minutes| *minutes > 0)
+        .unwrap_or(DEFAULT_COMPACTION_WINDOW_MINUTES);
+
+    Duration::minutes(minutes)
+}
+
+/// Computes the start of the window `now` falls in.
+///
+/// Windows are aligned to the Unix epoch, so every run of the compactor
+/// agrees on them. Changes before the horizon belong to windows that have
+/// passed and may be compacted; the current window is left alone.
+pub fn compaction_horizon(now: DateTime<Utc>, window: Duration) -> DateTime<Utc> {
+    let window = window.num_seconds().max(1);
+    let start = now.timestamp().div_euclid(window) * window;
+
+    Utc.timestamp_opt(start, 0).single().unwrap_or(now)
+}
+
+/// Collapses runs of updates made before `horizon`.
+///
+/// A run is a sequence of applied updates to one record by one device,
+/// within one window, with no other change to the record in between.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `horizon` - Changes from this time on are left alone (see
+///   [`compaction_horizon`])
+/// * `window` - Length of a compaction window
+///
+/// # Returns
+///
+/// Returns the number of sync changes removed.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn compact_changes(
+    pool: &PgPool,
+    horizon: DateTime<Utc>,
+    window: Duration,
+) -> Result<u64, anyhow::Error> {
+    let removed = sqlx::query_scalar::<_, i64>(
+        r#"
+        WITH ordered AS (
+            SELECT
+                c.id, c.user_id, c.table_name, c.record_id, c.sequence_number,
+                c.old_data, c.compacted_changes, c.change_timestamp,
+                c.operation = 'UPDATE' AND NOT c.is_conflict AS collapsible,
+                c.operation <> 'UPDATE'
+                    OR c.is_conflict
+                    OR LAG(c.device_id) OVER record IS DISTINCT FROM c.device_id AS starts_run
+            FROM sync_changes c
+            WHERE c.is_applied = true
+                AND c.sequence_number IS NOT NULL
+                AND c.change_timestamp < $1
+            WINDOW record AS (PARTITION BY c.user_id, c.table_name, c.record_id ORDER BY c.sequence_number)
+        ),
+        runs AS (
+            SELECT
+                o.*,
+                COUNT(*) FILTER (WHERE o.starts_run) OVER (
+                    PARTITION BY o.user_id, o.table_name, o.record_id ORDER BY o.sequence_number
+                ) AS run,
+                FLOOR(EXTRACT(EPOCH FROM o.change_timestamp))::bigint / $2 AS bucket
+            FROM ordered o
+        ),
+        collapsed AS (
+            SELECT
+                user_id, table_name, record_id, run, bucket,
+                MAX(sequence_number) AS kept_sequence,
+                (ARRAY_AGG(old_data ORDER BY sequence_number))[1] AS old_data,
+                SUM(1 + compacted_changes) - 1 AS compacted_changes
+            FROM runs
+            WHERE collapsible
+            GROUP BY user_id, table_name, record_id, run, bucket
+            HAVING COUNT(*) > 1
+        ),
+        removed AS (
+            DELETE FROM sync_changes c
+            USING runs r, collapsed k
+            WHERE c.id = r.id
+                AND r.collapsible
+                AND r.user_id = k.user_id
+                AND r.table_name = k.table_name
+                AND r.record_id = k.record_id
+                AND r.run = k.run
+                AND r.bucket = k.bucket
+                AND r.sequence_number < k.kept_sequence
+            RETURNING c.id
+        ),
+        kept AS (
+            UPDATE sync_changes c
+            SET old_data = k.old_data,
+                compacted_changes = k.compacted_changes
+            FROM collapsed k
+            WHERE c.user_id = k.user_id
+                AND c.sequence_number = k.kept_sequence
+        )
+        SELECT COUNT(*) FROM removed
+        "#,
+    )
+    .bind(horizon)
+    .bind(window.num_seconds().max(1))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(removed as u64)
+}
+
+/// Spawns a background task that compacts the change log once per window.
+pub fn spawn_compactor(pool: PgPool) {
+    tokio::spawn(async move {
+        let window = compaction_window();
+        let period = window.to_std().unwrap_or(std::time::Duration::from_secs(60 * 60));
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let horizon = compaction_horizon(Utc::now(), window);
+            match compact_changes(&pool, horizon, window).await {
+                Ok(removed) => info!("Compacted {} sync changes made before {}", removed, horizon),
+                Err(e) => error!("Sync change compaction failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_horizon_is_the_start_of_the_current_window() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 5, 14, 37, 12).unwrap();
+
+        assert_eq!(
+            compaction_horizon(now, Duration::minutes(60)),
+            Utc.with_ymd_and_hms(2024, 3, 5, 14, 0, 0).unwrap()
+        );
+        assert_eq!(
+            compaction_horizon(now, Duration::minutes(15)),
+            Utc.with_ymd_and_hms(2024, 3, 5, 14, 30, 0).unwrap()
+        );
+        // A window boundary belongs to the window it starts
+        let boundary = Utc.with_ymd_and_hms(2024, 3, 5, 14, 0, 0).unwrap();
+        assert_eq!(compaction_horizon(boundary, Duration::minutes(60)), boundary);
+    }
+}
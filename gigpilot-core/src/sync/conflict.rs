@@ -1,10 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde_json::Value;
-use sqlx::{PgPool, Postgres, Transaction};
-use tracing::{error, info, warn};
+use sqlx::PgConnection;
+use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::models::sync_change::SyncOperation;
+use crate::sync::tables::find_table;
 use crate::sync::types::ConflictStrategy;
 
 /// Checks if a conflict exists between client and server versions.
@@ -15,7 +15,7 @@ use crate::sync::types::ConflictStrategy;
 /// 
 /// # Arguments
 /// 
-/// * `executor` - Database executor (pool or transaction)
+/// * `conn` - Database connection (or transaction)
 /// * `user_id` - ID of the user
 /// * `table_name` - Name of the table
 /// * `record_id` - ID of the record
@@ -25,70 +25,79 @@ use crate::sync::types::ConflictStrategy;
 /// # Returns
 /// 
 /// Returns `true` if a conflict exists, `false` otherwise.
-pub async fn has_conflict<'a, E>(
-    executor: E,
+pub async fn has_conflict(
+    conn: &mut PgConnection,
     user_id: Uuid,
     table_name: &str,
     record_id: Uuid,
     client_version_vector: Option<&Value>,
     client_last_modified: Option<DateTime<Utc>>,
-) -> Result<bool, anyhow::Error>
-where
-    E: sqlx::Executor<'a, Database = sqlx::Postgres>,
-{
+) -> Result<bool, anyhow::Error> {
+    let Some(table) = find_table(table_name) else {
+        warn!("Conflict check not implemented for table: {}", table_name);
+        return Ok(false);
+    };
+    
     // Check if record exists and get its current state
-    match table_name {
-        "invoices" => {
-            let result = sqlx::query!(
-                r#"
-                SELECT last_modified, version_vector
-                FROM invoices
-                WHERE id = $1 AND user_id = $2 AND is_deleted = false
-                "#,
-                record_id,
-                user_id
-            )
-            .fetch_optional(executor)
-            .await?;
-            
-            if let Some(row) = result {
-                // Check if server version is newer
-                if let Some(server_last_modified) = row.last_modified {
-                    if let Some(client_modified) = client_last_modified {
-                        if server_last_modified > client_modified {
-                            info!(
-                                "Conflict detected: server version is newer (server: {:?}, client: {:?})",
-                                server_last_modified, client_modified
-                            );
-                            return Ok(true);
-                        }
-                    }
-                }
-                
-                // Check version vectors if provided
-                if let (Some(client_vv), Some(server_vv)) = (client_version_vector, row.version_vector.as_ref()) {
-                    if client_vv != server_vv {
-                        info!("Conflict detected: version vectors differ");
-                        return Ok(true);
-                    }
-                }
+    if let Some((server_last_modified, server_vv)) = table.version(conn, user_id, record_id).await? {
+        // Check if server version is newer
+        if let (Some(server_modified), Some(client_modified)) = (server_last_modified, client_last_modified) {
+            if server_modified > client_modified {
+                info!(
+                    "Conflict detected: server version is newer (server: {:?}, client: {:?})",
+                    server_modified, client_modified
+                );
+                return Ok(true);
             }
         }
-        _ => {
-            warn!("Conflict check not implemented for table: {}", table_name);
+        
+        // Check version vectors if provided
+        if let (Some(client_vv), Some(server_vv)) = (client_version_vector, server_vv.as_ref()) {
+            if client_vv != server_vv {
+                info!("Conflict detected: version vectors differ");
+                return Ok(true);
+            }
         }
     }
     
     Ok(false)
 }
 
+/// Loads the server's current version of a synced record.
+/// 
+/// # Arguments
+/// 
+/// * `conn` - Database connection (or transaction)
+/// * `user_id` - ID of the user
+/// * `table_name` - Name of the table
+/// * `record_id` - ID of the record
+/// 
+/// # Returns
+/// 
+/// Returns the record as JSON (including soft-deleted records), or `None`
+/// if it does not exist or the table is not synced.
+pub async fn current_record(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    table_name: &str,
+    record_id: Uuid,
+) -> Result<Option<Value>, anyhow::Error> {
+    match find_table(table_name) {
+        Some(table) => table.load(conn, user_id, record_id).await,
+        None => {
+            warn!("Record lookup not implemented for table: {}", table_name);
+            Ok(None)
+        }
+    }
+}
+
 /// Resolves a conflict between client and server versions.
 /// 
 /// Uses the specified conflict strategy to determine which version wins.
 /// 
 /// # Arguments
 /// 
-/// * `executor` - Database executor (pool or transaction)
+/// * `conn` - Database connection (or transaction)
 /// * `user_id` - ID of the user
 /// * `table_name` - Name of the table
 /// * `record_id` - ID of the record
@@ -98,70 +107,21 @@ where
 /// # Returns
 /// 
 /// Returns the resolved data (either client or server version).
-pub async fn resolve_conflict<'a, E>(
-    executor: E,
+pub async fn resolve_conflict(
+    conn: &mut PgConnection,
     user_id: Uuid,
     table_name: &str,
     record_id: Uuid,
     client_data: &Value,
     strategy: ConflictStrategy,
-) -> Result<Value, anyhow::Error>
-where
-    E: sqlx::Executor<'a, Database = sqlx::Postgres>,
-{
+) -> Result<Value, anyhow::Error> {
     match strategy {
         ConflictStrategy::ServerWins => {
             info!("Resolving conflict: Server wins for {}:{}", table_name, record_id);
-            // Get server version
-            match table_name {
-                "invoices" => {
-                    let invoice = sqlx::query!(
-                        r#"
-                        SELECT 
-                            id, user_id, invoice_number, client_name, client_email,
-                            amount, currency, status, due_date, issue_date,
-                            last_modified, version_vector, is_deleted,
-                            description, line_items, metadata, created_at, updated_at
-                        FROM invoices
-                        WHERE id = $1 AND user_id = $2
-                        "#,
-                        record_id,
-                        user_id
-                    )
-                    .fetch_optional(executor)
-                    .await?;
-                    
-                    if let Some(inv) = invoice {
-                        Ok(serde_json::json!({
-                            "id": inv.id,
-                            "user_id": inv.user_id,
-                            "invoice_number": inv.invoice_number,
-                            "client_name": inv.client_name,
-                            "client_email": inv.client_email,
-                            "amount": inv.amount.to_string(),
-                            "currency": inv.currency,
-                            "status": inv.status,
-                            "due_date": inv.due_date,
-                            "issue_date": inv.issue_date,
-                            "last_modified": inv.last_modified,
-                            "version_vector": inv.version_vector,
-                            "is_deleted": inv.is_deleted,
-                            "description": inv.description,
-                            "line_items": inv.line_items,
-                            "metadata": inv.metadata,
-                            "created_at": inv.created_at,
-                            "updated_at": inv.updated_at,
-                        }))
-                    } else {
-                        // Record doesn't exist on server, use client version
-                        Ok(client_data.clone())
-                    }
-                }
-                _ => {
-                    warn!("Conflict resolution not implemented for table: {}", table_name);
-                    Ok(client_data.clone())
-                }
-            }
+            // Get server version; if the record doesn't exist on the
+            // server, use the client version
+            let server = current_record(conn, user_id, table_name, record_id).await?;
+            Ok(server.unwrap_or_else(|| client_data.clone()))
         }
         ConflictStrategy::ClientWins => {
             info!("Resolving conflict: Client wins for {}:{}", table_name, record_id);
@@ -175,4 +135,3 @@ where
         }
     }
 }
-
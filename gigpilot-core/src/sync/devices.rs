@@ -0,0 +1,471 @@
+//! Per-device sync checkpoints.
+//!
+//! Every pull and push naming a `device_id` updates the device's row in
+//! `sync_devices`: the cursor of its last complete pull, when it last
+//! pushed, and the app version and platform it reported. Pruning keeps
+//! changes that devices seen within the retention window haven't pulled
+//! yet (see [`prune_expired_changes`](crate::sync::retention::prune_expired_changes)),
+//! users can see which of their devices have gone stale, and the app can
+//! show each device's sync health (see [`sync_status`]).
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::sync::pull::PULLED_CHANGES;
+use crate::sync::retention::{
+    pruned_before, pruned_sequence, requires_full_resync, requires_full_resync_after_sequence,
+    retention_horizon,
+};
+use crate::sync::types::{PullResponse, PullStatus};
+
+/// Longest app version kept for a device.
+pub const MAX_APP_VERSION_LEN: usize = 64;
+
+/// Longest platform name kept for a device.
+pub const MAX_PLATFORM_LEN: usize = 32;
+
+/// What a device says about itself when it syncs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceReport {
+    /// Device ID sent with the pull or push
+    pub device_id: String,
+
+    /// App version the device runs (e.g. "2.4.1")
+    pub app_version: Option<String>,
+
+    /// Platform the device runs on (e.g. "ios")
+    pub platform: Option<String>,
+}
+
+impl DeviceReport {
+    /// Builds a report from a request's fields.
+    ///
+    /// Blank values are dropped and long ones cut to fit.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` for requests that don't name a device.
+    pub fn new(device_id: Option<&str>, app_version: Option<&str>, platform: Option<&str>) -> Option<Self> {
+        let device_id = device_id.map(str::trim).filter(|id| !id.is_empty())?;
+        Some(Self {
+            device_id: device_id.to_string(),
+            app_version: reported(app_version, MAX_APP_VERSION_LEN),
+            platform: reported(platform, MAX_PLATFORM_LEN),
+        })
+    }
+}
+
+/// A reported value, trimmed and cut to `max_len` characters.
+fn reported(value: Option<&str>, max_len: usize) -> Option<String> {
+    let value = value.map(str::trim).filter(|value| !value.is_empty())?;
+    Some(value.chars().take(max_len).collect())
+}
+
+/// A device's sync checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SyncDevice {
+    /// Device ID
+    pub device_id: String,
+
+    /// Cursor of the device's last complete pull
+    pub last_pulled_sequence: Option<i64>,
+
+    /// When the device last completed a pull
+    pub last_pulled_at: Option<DateTime<Utc>>,
+
+    /// When the device last pushed
+    pub last_pushed_at: Option<DateTime<Utc>>,
+
+    /// When the device last pulled or pushed
+    pub last_seen_at: DateTime<Utc>,
+
+    /// App version the device last reported
+    pub app_version: Option<String>,
+
+    /// Platform the device last reported
+    pub platform: Option<String>,
+
+    /// Whether the device must resync in full, or soon will: changes it
+    /// hasn't pulled were pruned, or it hasn't been seen within the
+    /// retention window so pruning no longer waits for it
+    #[sqlx(default)]
+    pub stale: bool,
+}
+
+impl SyncDevice {
+    /// Decides whether the device is stale.
+    ///
+    /// # Arguments
+    ///
+    /// * `pruned_sequence` - Highest sequence number pruned for the user
+    /// * `pruned_before` - The user's pruning horizon
+    /// * `horizon` - Current retention horizon
+    pub fn is_stale(
+        &self,
+        pruned_sequence: Option<i64>,
+        pruned_before: Option<DateTime<Utc>>,
+        horizon: DateTime<Utc>,
+    ) -> bool {
+        let resync = match self.last_pulled_sequence {
+            Some(cursor) => requires_full_resync_after_sequence(cursor, pruned_sequence),
+            None => requires_full_resync(self.last_pulled_at, pruned_before),
+        };
+        resync || self.last_seen_at < horizon
+    }
+}
+
+/// The cursor a pull leaves the device at, if the pull was complete.
+///
+/// Pages before the last one and resync responses don't move the
+/// checkpoint: the device hasn't received everything up to a cursor yet.
+pub fn pull_checkpoint(response: &PullResponse) -> Option<i64> {
+    if response.status != PullStatus::Ok || response.has_more {
+        return None;
+    }
+    response.sequence_number
+}
+
+/// Records a pull in the device's checkpoint.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user who pulled
+/// * `device` - The device that pulled
+/// * `response` - What the pull returned
+///
+/// # Errors
+///
+/// Returns an error if the database write fails.
+pub async fn record_pull(
+    pool: &PgPool,
+    user_id: Uuid,
+    device: &DeviceReport,
+    response: &PullResponse,
+) -> Result<(), anyhow::Error> {
+    let checkpoint = pull_checkpoint(response);
+    sqlx::query(
+        r#"
+        INSERT INTO sync_devices (
+            user_id, device_id, last_pulled_sequence, last_pulled_at, app_version, platform
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (user_id, device_id) DO UPDATE
+            SET last_pulled_sequence = COALESCE(EXCLUDED.last_pulled_sequence, sync_devices.last_pulled_sequence),
+                last_pulled_at = COALESCE(EXCLUDED.last_pulled_at, sync_devices.last_pulled_at),
+                last_seen_at = NOW(),
+                app_version = COALESCE(EXCLUDED.app_version, sync_devices.app_version),
+                platform = COALESCE(EXCLUDED.platform, sync_devices.platform)
+        "#,
+    )
+    .bind(user_id)
+    .bind(&device.device_id)
+    .bind(checkpoint)
+    .bind(checkpoint.map(|_| response.timestamp))
+    .bind(&device.app_version)
+    .bind(&device.platform)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a push in the device's checkpoint.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user who pushed
+/// * `device` - The device that pushed
+/// * `duration` - How long the server took to handle the push
+///
+/// # Errors
+///
+/// Returns an error if the database write fails.
+pub async fn record_push(
+    pool: &PgPool,
+    user_id: Uuid,
+    device: &DeviceReport,
+    duration: Duration,
+) -> Result<(), anyhow::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO sync_devices (
+            user_id, device_id, last_pushed_at, app_version, platform, push_count, push_duration_ms
+        )
+        VALUES ($1, $2, NOW(), $3, $4, 1, $5)
+        ON CONFLICT (user_id, device_id) DO UPDATE
+            SET last_pushed_at = NOW(),
+                last_seen_at = NOW(),
+                app_version = COALESCE(EXCLUDED.app_version, sync_devices.app_version),
+                platform = COALESCE(EXCLUDED.platform, sync_devices.platform),
+                push_count = sync_devices.push_count + 1,
+                push_duration_ms = sync_devices.push_duration_ms + EXCLUDED.push_duration_ms
+        "#,
+    )
+    .bind(user_id)
+    .bind(&device.device_id)
+    .bind(&device.app_version)
+    .bind(&device.platform)
+    .bind(duration.as_millis() as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Lists the user's devices with whether each is stale.
+///
+/// # Returns
+///
+/// Returns the devices, most recently seen first.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn list_devices(pool: &PgPool, user_id: Uuid) -> Result<Vec<SyncDevice>, anyhow::Error> {
+    let mut devices = sqlx::query_as::<_, SyncDevice>(
+        r#"
+        SELECT
+            device_id, last_pulled_sequence, last_pulled_at, last_pushed_at,
+            last_seen_at, app_version, platform
+        FROM sync_devices
+        WHERE user_id = $1
+        ORDER BY last_seen_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let pruned_sequence = pruned_sequence(pool, user_id).await?;
+    let pruned_before = pruned_before(pool, user_id).await?;
+    let horizon = retention_horizon(Utc::now());
+    for device in &mut devices {
+        device.stale = device.is_stale(pruned_sequence, pruned_before, horizon);
+    }
+
+    Ok(devices)
+}
+
+/// A device's sync health.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// Device ID
+    pub device_id: String,
+
+    /// Changes from the user's other devices and the server that the
+    /// device hasn't pulled yet
+    pub pending_changes: i64,
+
+    /// When the device last completed a pull or pushed
+    pub last_synced_at: Option<DateTime<Utc>>,
+
+    /// When the device last completed a pull
+    pub last_pulled_at: Option<DateTime<Utc>>,
+
+    /// When the device last pushed
+    pub last_pushed_at: Option<DateTime<Utc>>,
+
+    /// Changes the device pushed that are still in the change log,
+    /// counting those compacted into later ones
+    pub changes: i64,
+
+    /// How many of those conflicted with the server's version
+    pub conflicts: i64,
+
+    /// Share of those changes that conflicted, from 0 to 1
+    pub conflict_rate: f64,
+
+    /// Average server time of the device's pushes, in milliseconds (absent
+    /// before its first push)
+    pub average_push_ms: Option<i64>,
+}
+
+/// A device's counters, as loaded for its status.
+#[derive(Debug, FromRow)]
+struct StatusRow {
+    device_id: String,
+    pending_changes: i64,
+    last_pulled_at: Option<DateTime<Utc>>,
+    last_pushed_at: Option<DateTime<Utc>>,
+    changes: i64,
+    conflicts: i64,
+    push_count: i64,
+    push_duration_ms: i64,
+}
+
+impl From<StatusRow> for SyncStatus {
+    fn from(row: StatusRow) -> Self {
+        Self {
+            device_id: row.device_id,
+            pending_changes: row.pending_changes,
+            last_synced_at: row.last_pulled_at.max(row.last_pushed_at),
+            last_pulled_at: row.last_pulled_at,
+            last_pushed_at: row.last_pushed_at,
+            changes: row.changes,
+            conflicts: row.conflicts,
+            conflict_rate: conflict_rate(row.changes, row.conflicts),
+            average_push_ms: average_push_ms(row.push_count, row.push_duration_ms),
+        }
+    }
+}
+
+/// Share of `changes` that conflicted (0 without changes).
+pub fn conflict_rate(changes: i64, conflicts: i64) -> f64 {
+    if changes <= 0 {
+        return 0.0;
+    }
+    conflicts as f64 / changes as f64
+}
+
+/// Average push time in milliseconds, if the device has pushed.
+pub fn average_push_ms(push_count: i64, push_duration_ms: i64) -> Option<i64> {
+    (push_count > 0).then(|| push_duration_ms / push_count)
+}
+
+/// Reports the sync health of the user's devices.
+///
+/// Pending changes count what the device's next pull would bring in from
+/// elsewhere: changes after its checkpoint made by other devices or the
+/// server, including changes to records shared with the user. Conflicts
+/// are counted over the change log, so they cover the retention window.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `device_id` - Only report this device
+///
+/// # Returns
+///
+/// Returns one status per device, most recently seen first.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn sync_status(
+    pool: &PgPool,
+    user_id: Uuid,
+    device_id: Option<&str>,
+) -> Result<Vec<SyncStatus>, anyhow::Error> {
+    let rows = sqlx::query_as::<_, StatusRow>(&format!(
+        r#"
+        SELECT
+            d.device_id,
+            (
+                SELECT COUNT(*) FROM sync_changes c
+                WHERE {}
+                    AND c.device_id <> d.device_id
+                    AND (
+                        c.sequence_number > d.last_pulled_sequence
+                        OR (
+                            d.last_pulled_sequence IS NULL
+                            AND (d.last_pulled_at IS NULL OR c.change_timestamp > d.last_pulled_at)
+                        )
+                    )
+            ) AS pending_changes,
+            d.last_pulled_at,
+            d.last_pushed_at,
+            (
+                SELECT COALESCE(SUM(1 + c.compacted_changes), 0) FROM sync_changes c
+                WHERE c.user_id = $1 AND c.device_id = d.device_id
+            ) AS changes,
+            (
+                SELECT COUNT(*) FROM sync_changes c
+                WHERE c.user_id = $1 AND c.device_id = d.device_id AND c.is_conflict
+            ) AS conflicts,
+            d.push_count,
+            d.push_duration_ms
+        FROM sync_devices d
+        WHERE d.user_id = $1
+            AND ($2::varchar IS NULL OR d.device_id = $2)
+        ORDER BY d.last_seen_at DESC
+        "#,
+        PULLED_CHANGES
+    ))
+    .bind(user_id)
+    .bind(device_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(SyncStatus::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use serde_json::json;
+
+    fn device(last_pulled_sequence: Option<i64>, last_pulled_at: Option<DateTime<Utc>>) -> SyncDevice {
+        SyncDevice {
+            device_id: "phone".to_string(),
+            last_pulled_sequence,
+            last_pulled_at,
+            last_pushed_at: None,
+            last_seen_at: Utc::now(),
+            app_version: None,
+            platform: None,
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn test_device_report_needs_a_device() {
+        assert_eq!(DeviceReport::new(None, Some("2.4.1"), Some("ios")), None);
+        assert_eq!(DeviceReport::new(Some("  "), None, None), None);
+
+        let long = "1".repeat(100);
+        let report = DeviceReport::new(Some("phone"), Some(&long), Some(" ")).unwrap();
+        assert_eq!(report.app_version.map(|version| version.len()), Some(MAX_APP_VERSION_LEN));
+        assert_eq!(report.platform, None);
+    }
+
+    #[test]
+    fn test_only_complete_pulls_move_the_checkpoint() {
+        let response = |status: PullStatus, has_more: bool| PullResponse {
+            changes: json!({}),
+            timestamp: Utc::now(),
+            sequence_number: Some(42),
+            has_more,
+            cursor: None,
+            status,
+            snapshot_url: None,
+            editing: Vec::new(),
+            schema_version: 1,
+        };
+
+        assert_eq!(pull_checkpoint(&response(PullStatus::Ok, false)), Some(42));
+        assert_eq!(pull_checkpoint(&response(PullStatus::Ok, true)), None);
+        assert_eq!(pull_checkpoint(&response(PullStatus::ResyncRequired, false)), None);
+    }
+
+    #[test]
+    fn test_status_rates() {
+        assert_eq!(conflict_rate(0, 0), 0.0);
+        assert_eq!(conflict_rate(40, 10), 0.25);
+        assert_eq!(average_push_ms(0, 0), None);
+        assert_eq!(average_push_ms(4, 1000), Some(250));
+    }
+
+    #[test]
+    fn test_stale_devices() {
+        let now = Utc::now();
+        let horizon = now - Duration::days(90);
+
+        assert!(!device(Some(50), None).is_stale(Some(42), Some(horizon), horizon));
+        assert!(device(Some(41), None).is_stale(Some(42), Some(horizon), horizon));
+        // Older clients are judged by the time of their last pull
+        assert!(device(None, Some(horizon - Duration::days(1))).is_stale(None, Some(horizon), horizon));
+        assert!(!device(None, Some(now)).is_stale(None, Some(horizon), horizon));
+
+        let mut away = device(Some(50), None);
+        away.last_seen_at = horizon - Duration::days(1);
+        assert!(away.is_stale(Some(42), Some(horizon), horizon));
+    }
+}
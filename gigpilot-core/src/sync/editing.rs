@@ -0,0 +1,149 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tracing::info;
+use uuid::Uuid;
+
+/// How long an editing signal stays active without being refreshed.
+pub const EDITING_TTL_SECONDS: i64 = 60;
+
+/// Advisory "currently editing" signal for a record.
+///
+/// These are never enforced by the sync engine; they only let other
+/// devices warn the user before creating a conflict.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct EditingIndicator {
+    /// Name of the table being edited
+    pub table_name: String,
+
+    /// ID of the record being edited
+    pub record_id: Uuid,
+
+    /// Device holding the editing signal
+    pub device_id: String,
+
+    /// When the device started editing
+    pub started_at: DateTime<Utc>,
+
+    /// When the signal lapses unless refreshed
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Request to start (or refresh) an editing signal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditingRequest {
+    /// Name of the table being edited
+    pub table: String,
+
+    /// ID of the record being edited
+    pub id: Uuid,
+
+    /// Device that is editing
+    pub device_id: String,
+}
+
+/// Starts or refreshes an editing signal for a device.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `request` - The record and device being edited
+///
+/// # Returns
+///
+/// Returns the active signal with its new expiry, or an error.
+pub async fn start_editing(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: &EditingRequest,
+) -> Result<EditingIndicator, anyhow::Error> {
+    let expires_at = Utc::now() + Duration::seconds(EDITING_TTL_SECONDS);
+
+    let indicator = sqlx::query_as::<_, EditingIndicator>(
+        r#"
+        INSERT INTO editing_sessions (user_id, table_name, record_id, device_id, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, table_name, record_id, device_id) DO UPDATE
+            SET expires_at = EXCLUDED.expires_at,
+                started_at = CASE
+                    WHEN editing_sessions.expires_at < NOW() THEN NOW()
+                    ELSE editing_sessions.started_at
+                END
+        RETURNING table_name, record_id, device_id, started_at, expires_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(&request.table)
+    .bind(request.id)
+    .bind(&request.device_id)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+
+    info!(
+        "Device {} editing {}:{} until {}",
+        request.device_id, request.table, request.id, expires_at
+    );
+
+    Ok(indicator)
+}
+
+/// Releases an editing signal held by a device.
+pub async fn stop_editing(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: &EditingRequest,
+) -> Result<(), anyhow::Error> {
+    sqlx::query(
+        r#"
+        DELETE FROM editing_sessions
+        WHERE user_id = $1 AND table_name = $2 AND record_id = $3 AND device_id = $4
+        "#,
+    )
+    .bind(user_id)
+    .bind(&request.table)
+    .bind(request.id)
+    .bind(&request.device_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Lists active editing signals held by the user's other devices.
+///
+/// Expired rows are deleted as a side effect so the table stays small.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `exclude_device_id` - The requesting device, whose own signals are omitted
+pub async fn active_editing(
+    pool: &PgPool,
+    user_id: Uuid,
+    exclude_device_id: Option<&str>,
+) -> Result<Vec<EditingIndicator>, anyhow::Error> {
+    sqlx::query("DELETE FROM editing_sessions WHERE user_id = $1 AND expires_at < NOW()")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    let indicators = sqlx::query_as::<_, EditingIndicator>(
+        r#"
+        SELECT table_name, record_id, device_id, started_at, expires_at
+        FROM editing_sessions
+        WHERE user_id = $1
+            AND expires_at >= NOW()
+            AND ($2::varchar IS NULL OR device_id <> $2)
+        ORDER BY started_at ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(exclude_device_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(indicators)
+}
@@ -0,0 +1,656 @@
+//! End-to-end encrypted sync.
+//!
+//! Users may opt tables into end-to-end encryption (see
+//! [`set_encrypted_tables`]). Their devices then push records of those
+//! tables as an [`EncryptedPayload`] instead of `data`, encrypted with a key
+//! the server never sees; the server stores the payload as it is and
+//! relays it to the user's other devices through the change log, the
+//! snapshot and the bootstrap stream, as `{ "id", "version", "encrypted" }`.
+//!
+//! The server can't read these records, so:
+//!
+//! - plaintext pushes to an encrypted table are rejected, and the plaintext
+//!   copy of a record is deleted once its encrypted version is pushed
+//! - records that are shared, and invoices and clients with an open
+//!   balance, stay plaintext: grantees and the chasing worker need to read
+//!   them
+//! - the server doesn't create records in an encrypted table itself
+//!   (duplicated invoices, weekly drafts, retainer invoices)
+//! - conflicts are detected on the record's version alone and never merged:
+//!   a push made to an older version than the server's is reported as a
+//!   conflict with the server's payload, and the device merges locally
+//! - server-side features (reminders, reports, search, merge suggestions)
+//!   don't see encrypted records
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Executor, FromRow, PgConnection, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::models::share_grant::ShareEntity;
+use crate::models::sync_change::SyncOperation;
+use crate::sync::tables::find_table;
+use crate::sync::types::PushChange;
+
+/// Tables that can be synced encrypted. Settings stay readable, since the
+/// server acts on them.
+pub const ENCRYPTABLE_TABLES: &[&str] = &["invoices", "estimates", "clients", "projects", "time_entries"];
+
+/// Encryption algorithms devices may name.
+pub const SUPPORTED_ALGORITHMS: &[&str] = &["xchacha20-poly1305", "aes-256-gcm"];
+
+/// Longest ciphertext accepted, in base64 characters (512 KiB).
+pub const MAX_CIPHERTEXT_LEN: usize = 512 * 1024;
+
+/// Longest nonce accepted, in base64 characters.
+const MAX_NONCE_LEN: usize = 64;
+
+/// Longest key ID accepted.
+const MAX_KEY_ID_LEN: usize = 100;
+
+/// An encrypted record as devices push and pull it.
+///
+/// Only the shape is checked; the server can't decrypt it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedPayload {
+    /// Encryption algorithm (see [`SUPPORTED_ALGORITHMS`])
+    pub alg: String,
+
+    /// Identifies the user's key, so devices can rotate keys
+    pub key_id: String,
+
+    /// Base64 nonce
+    pub nonce: String,
+
+    /// Base64 ciphertext of the record
+    pub ciphertext: String,
+
+    /// Version of the server's record the device encrypted its change over
+    /// (none for a new record); pushed only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_version: Option<i64>,
+}
+
+impl EncryptedPayload {
+    /// Checks the payload's shape.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EncryptionError::Invalid`] naming the first malformed
+    /// field.
+    pub fn validate(&self) -> Result<(), EncryptionError> {
+        let invalid = |field, reason| Err(EncryptionError::Invalid { field, reason });
+
+        if !SUPPORTED_ALGORITHMS.contains(&self.alg.as_str()) {
+            return invalid("alg", "unsupported algorithm");
+        }
+        if self.key_id.trim().is_empty() || self.key_id.len() > MAX_KEY_ID_LEN {
+            return invalid("key_id", "must be 1 to 100 characters");
+        }
+        if self.nonce.is_empty() || self.nonce.len() > MAX_NONCE_LEN || !is_base64(&self.nonce) {
+            return invalid("nonce", "must be base64, at most 64 characters");
+        }
+        if self.ciphertext.is_empty() || !is_base64(&self.ciphertext) {
+            return invalid("ciphertext", "must be base64");
+        }
+        if self.ciphertext.len() > MAX_CIPHERTEXT_LEN {
+            return invalid("ciphertext", "is too large");
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `text` is standard, padded base64.
+fn is_base64(text: &str) -> bool {
+    let data = text.trim_end_matches('=');
+    text.len().is_multiple_of(4)
+        && text.len() - data.len() <= 2
+        && data.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
+/// A pushed change that doesn't fit the table's encryption.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptionError {
+    /// The table is synced encrypted, but the change sends plaintext
+    Required { table: String },
+
+    /// The change is encrypted, but the table isn't synced encrypted
+    NotEnabled { table: String },
+
+    /// The payload is malformed
+    Invalid {
+        field: &'static str,
+        reason: &'static str,
+    },
+
+    /// The record is shared, with the user or by them, and stays readable
+    /// to everyone it is shared with
+    Shared { table: String },
+
+    /// The invoice, or an invoice of the client, is still owed, and stays
+    /// readable so it can be chased and paid
+    OpenBalance { table: String },
+}
+
+impl EncryptionError {
+    /// Machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EncryptionError::Required { .. } => "encryption_required",
+            EncryptionError::NotEnabled { .. } => "encryption_not_enabled",
+            EncryptionError::Invalid { .. } => "invalid_encrypted_payload",
+            EncryptionError::Shared { .. } => "encryption_shared",
+            EncryptionError::OpenBalance { .. } => "encryption_open_balance",
+        }
+    }
+
+    /// What was refused, for the push response.
+    pub fn details(&self) -> Value {
+        match self {
+            EncryptionError::Required { table }
+            | EncryptionError::NotEnabled { table }
+            | EncryptionError::Shared { table }
+            | EncryptionError::OpenBalance { table } => json!({ "table": table }),
+            EncryptionError::Invalid { field, .. } => json!({ "field": field }),
+        }
+    }
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::Required { table } => {
+                write!(f, "{} are synced end-to-end encrypted; push an encrypted payload", table.replace('_', " "))
+            }
+            EncryptionError::NotEnabled { table } => {
+                write!(f, "{} are not synced end-to-end encrypted", table.replace('_', " "))
+            }
+            EncryptionError::Invalid { field, reason } => write!(f, "encrypted payload {} {}", field, reason),
+            EncryptionError::Shared { .. } => write!(f, "shared records are synced as plaintext; push the record's data"),
+            EncryptionError::OpenBalance { table } if table == "clients" => {
+                write!(f, "clients with unpaid invoices are synced as plaintext; push the record's data")
+            }
+            EncryptionError::OpenBalance { .. } => {
+                write!(f, "unpaid invoices are synced as plaintext; push the record's data")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+/// A stored encrypted record.
+#[derive(Debug, Clone, FromRow)]
+pub struct EncryptedRecord {
+    /// Row ID, to page by
+    pub id: Uuid,
+    pub table_name: String,
+    pub record_id: Uuid,
+    pub payload: Value,
+    pub version: i64,
+}
+
+impl EncryptedRecord {
+    /// The record as devices pull it.
+    pub fn to_record(&self) -> Value {
+        pulled_record(self.record_id, self.version, &self.payload)
+    }
+}
+
+/// Shapes an encrypted record as devices pull it.
+fn pulled_record(record_id: Uuid, version: i64, payload: &Value) -> Value {
+    json!({ "id": record_id, "version": version, "encrypted": payload })
+}
+
+/// The tables a user syncs encrypted, as the encryption endpoint takes and
+/// returns them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionSettings {
+    pub tables: Vec<String>,
+}
+
+/// Why the tables to encrypt weren't changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TablesRefusal {
+    /// The table can't be synced encrypted
+    NotEncryptable(String),
+
+    /// The table still has encrypted records, which the server couldn't
+    /// turn back into plaintext
+    HasRecords(String),
+}
+
+/// Loads the tables a user syncs encrypted.
+pub async fn encrypted_tables<'e, E>(executor: E, user_id: Uuid) -> Result<Vec<String>, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let tables = sqlx::query_scalar::<_, String>(
+        "SELECT table_name FROM sync_encrypted_tables WHERE user_id = $1 ORDER BY table_name",
+    )
+    .bind(user_id)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(tables)
+}
+
+/// Whether a user syncs any of `tables` encrypted.
+///
+/// The server can't write encrypted records, so work that would create or
+/// change records of these tables on the user's behalf is refused or
+/// skipped instead of sending devices plaintext.
+pub async fn encrypts_any<'e, E>(executor: E, user_id: Uuid, tables: &[&str]) -> Result<bool, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let encrypted = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM sync_encrypted_tables WHERE user_id = $1 AND table_name = ANY($2))",
+    )
+    .bind(user_id)
+    .bind(tables)
+    .fetch_one(executor)
+    .await?;
+
+    Ok(encrypted)
+}
+
+/// Sets the tables a user syncs encrypted.
+///
+/// Existing records are left as they are; devices push them encrypted,
+/// which deletes their plaintext copies. A table can only stop being
+/// encrypted once it has no live encrypted records.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `tables` - Every table to sync encrypted from now on
+///
+/// # Returns
+///
+/// Returns the tables now synced encrypted, or why they weren't changed.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+pub async fn set_encrypted_tables(
+    pool: &PgPool,
+    user_id: Uuid,
+    tables: &[String],
+) -> Result<Result<Vec<String>, TablesRefusal>, anyhow::Error> {
+    let mut wanted: Vec<String> = Vec::new();
+    for table in tables {
+        if !ENCRYPTABLE_TABLES.contains(&table.as_str()) {
+            return Ok(Err(TablesRefusal::NotEncryptable(table.clone())));
+        }
+        if !wanted.contains(table) {
+            wanted.push(table.clone());
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    let dropped = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT t.table_name FROM sync_encrypted_tables t
+        WHERE t.user_id = $1
+            AND t.table_name <> ALL($2)
+            AND EXISTS (
+                SELECT 1 FROM sync_encrypted_records r
+                WHERE r.user_id = t.user_id AND r.table_name = t.table_name AND r.is_deleted = false
+            )
+        ORDER BY t.table_name
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(&wanted)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(table) = dropped {
+        return Ok(Err(TablesRefusal::HasRecords(table)));
+    }
+
+    sqlx::query("DELETE FROM sync_encrypted_tables WHERE user_id = $1 AND table_name <> ALL($2)")
+        .bind(user_id)
+        .bind(&wanted)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO sync_encrypted_tables (user_id, table_name)
+        SELECT $1, unnest($2::varchar[])
+        ON CONFLICT (user_id, table_name) DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(&wanted)
+    .execute(&mut *tx)
+    .await?;
+    let tables = encrypted_tables(&mut *tx, user_id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(tables))
+}
+
+/// Stores a pushed change to an encrypted table.
+///
+/// The change must send an [`EncryptedPayload`] (or be a delete), for a
+/// record that isn't shared and, for invoices and clients, isn't still
+/// owed (see [`keeps_plaintext`]). It applies if the record is new, was
+/// deleted, or is still at the payload's `base_version`; otherwise nothing
+/// is written and the change is a conflict. Applied changes are logged for
+/// the user's other devices.
+///
+/// # Arguments
+///
+/// * `tx` - Database transaction
+/// * `user_id` - ID of the user
+/// * `change` - The pushed change
+/// * `device_id` - Device ID making the change
+///
+/// # Returns
+///
+/// Returns whether the change conflicted, or why it can't be stored.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+pub async fn push_encrypted(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    change: &PushChange,
+    device_id: &str,
+) -> Result<Result<bool, EncryptionError>, anyhow::Error> {
+    if change.deleted {
+        let deleted = sqlx::query_scalar::<_, i64>(
+            r#"
+            UPDATE sync_encrypted_records
+            SET is_deleted = true, version = version + 1, device_id = $4
+            WHERE user_id = $1 AND table_name = $2 AND record_id = $3 AND is_deleted = false
+            RETURNING version
+            "#,
+        )
+        .bind(user_id)
+        .bind(&change.table)
+        .bind(change.id)
+        .bind(device_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        if deleted.is_some() {
+            log_change(tx, user_id, change, SyncOperation::Delete, None, device_id).await?;
+        }
+        return Ok(Ok(false));
+    }
+
+    let payload = match (&change.encrypted, &change.data, &change.patch) {
+        (Some(payload), None, None) => payload,
+        _ => return Ok(Err(EncryptionError::Required { table: change.table.clone() })),
+    };
+    if let Err(e) = payload.validate() {
+        return Ok(Err(e));
+    }
+    if let Some(e) = keeps_plaintext(tx, user_id, &change.table, change.id).await? {
+        return Ok(Err(e));
+    }
+
+    let current = sqlx::query_as::<_, (i64, bool)>(
+        r#"
+        SELECT version, is_deleted FROM sync_encrypted_records
+        WHERE user_id = $1 AND table_name = $2 AND record_id = $3
+        FOR UPDATE
+        "#,
+    )
+    .bind(user_id)
+    .bind(&change.table)
+    .bind(change.id)
+    .fetch_optional(&mut **tx)
+    .await?;
+    // Never merged: the device that edited an older version merges locally
+    let operation = match current {
+        Some((version, false)) if payload.base_version != Some(version) => return Ok(Ok(true)),
+        Some((_, false)) => SyncOperation::Update,
+        Some((_, true)) | None => SyncOperation::Insert,
+    };
+
+    let stored = serde_json::to_value(EncryptedPayload {
+        base_version: None,
+        ..payload.clone()
+    })?;
+    let version = sqlx::query_scalar::<_, i64>(
+        r#"
+        INSERT INTO sync_encrypted_records (user_id, table_name, record_id, payload, device_id)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, table_name, record_id) DO UPDATE
+            SET payload = EXCLUDED.payload,
+                version = sync_encrypted_records.version + 1,
+                is_deleted = false,
+                device_id = EXCLUDED.device_id
+        RETURNING version
+        "#,
+    )
+    .bind(user_id)
+    .bind(&change.table)
+    .bind(change.id)
+    .bind(&stored)
+    .bind(device_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    // The plaintext copy goes once the record is encrypted
+    if let Some(table) = find_table(&change.table) {
+        if table.exists(tx, user_id, change.id).await? {
+            table.delete(tx, user_id, change.id).await?;
+        }
+    }
+
+    let record = pulled_record(change.id, version, &stored);
+    log_change(tx, user_id, change, operation, Some(&record), device_id).await?;
+
+    Ok(Ok(false))
+}
+
+/// Why a record has to stay plaintext, if it does.
+///
+/// Shared projects and clients are read by their grantees, and invoices
+/// with an open balance (or clients with one) by the chasing worker, the
+/// portal and payments; deleting their plaintext copy would silently end
+/// all of that.
+async fn keeps_plaintext(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    table: &str,
+    record_id: Uuid,
+) -> Result<Option<EncryptionError>, anyhow::Error> {
+    if let Some(entity) = ShareEntity::from_table(table) {
+        let shared = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM share_grants
+                WHERE entity_type = $2 AND entity_id = $3 AND (user_id = $1 OR grantee_id = $1)
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(entity)
+        .bind(record_id)
+        .fetch_one(&mut **tx)
+        .await?;
+        if shared {
+            return Ok(Some(EncryptionError::Shared { table: table.to_string() }));
+        }
+    }
+
+    let owed_by = match table {
+        "invoices" => "id",
+        "clients" => "client_id",
+        _ => return Ok(None),
+    };
+    let query = format!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM invoices
+            WHERE user_id = $1 AND {} = $2 AND is_deleted = false
+                AND status IN ('sent', 'overdue') AND amount_paid < total
+        )
+        "#,
+        owed_by
+    );
+    let owed = sqlx::query_scalar::<_, bool>(&query)
+        .bind(user_id)
+        .bind(record_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    Ok(owed.then(|| EncryptionError::OpenBalance { table: table.to_string() }))
+}
+
+/// Logs an applied encrypted change in sync_changes.
+async fn log_change(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    change: &PushChange,
+    operation: SyncOperation,
+    new_data: Option<&Value>,
+    device_id: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO sync_changes (
+            user_id, table_name, record_id, operation, new_data, device_id, is_applied
+        ) VALUES ($1, $2, $3, $4, $5, $6, true)
+        "#,
+    )
+    .bind(user_id)
+    .bind(&change.table)
+    .bind(change.id)
+    .bind(operation)
+    .bind(new_data)
+    .bind(device_id)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Loads the live encrypted version of a record, as devices pull it.
+pub async fn current_record(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    table: &str,
+    record_id: Uuid,
+) -> Result<Option<Value>, anyhow::Error> {
+    let record = sqlx::query_as::<_, EncryptedRecord>(
+        r#"
+        SELECT id, table_name, record_id, payload, version FROM sync_encrypted_records
+        WHERE user_id = $1 AND table_name = $2 AND record_id = $3 AND is_deleted = false
+        "#,
+    )
+    .bind(user_id)
+    .bind(table)
+    .bind(record_id)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(record.as_ref().map(EncryptedRecord::to_record))
+}
+
+/// Loads a user's live encrypted records in row order.
+///
+/// # Arguments
+///
+/// * `conn` - Database connection
+/// * `user_id` - ID of the user
+/// * `after` - Only records after this row ID (the last of the previous page)
+/// * `limit` - Most records to return; `None` returns every record
+pub async fn live_records(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    after: Option<Uuid>,
+    limit: Option<i64>,
+) -> Result<Vec<EncryptedRecord>, anyhow::Error> {
+    let records = sqlx::query_as::<_, EncryptedRecord>(
+        r#"
+        SELECT id, table_name, record_id, payload, version FROM sync_encrypted_records
+        WHERE user_id = $1 AND is_deleted = false AND ($2::uuid IS NULL OR id > $2)
+        ORDER BY id
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(after)
+    .bind(limit)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> EncryptedPayload {
+        EncryptedPayload {
+            alg: "xchacha20-poly1305".to_string(),
+            key_id: "key-2024-01".to_string(),
+            nonce: "bm9uY2Vub25jZW5vbmNlbm9uY2U=".to_string(),
+            ciphertext: "c2VjcmV0IGludm9pY2U=".to_string(),
+            base_version: Some(3),
+        }
+    }
+
+    #[test]
+    fn test_payload_shape_is_checked() {
+        assert_eq!(payload().validate(), Ok(()));
+
+        let cases = [
+            (EncryptedPayload { alg: "rot13".to_string(), ..payload() }, "alg"),
+            (EncryptedPayload { key_id: " ".to_string(), ..payload() }, "key_id"),
+            (EncryptedPayload { nonce: "not base64!".to_string(), ..payload() }, "nonce"),
+            (EncryptedPayload { ciphertext: "abc".to_string(), ..payload() }, "ciphertext"),
+            (EncryptedPayload { ciphertext: "A".repeat(MAX_CIPHERTEXT_LEN + 4), ..payload() }, "ciphertext"),
+        ];
+        for (payload, field) in cases {
+            match payload.validate() {
+                Err(EncryptionError::Invalid { field: failed, .. }) => assert_eq!(failed, field),
+                other => panic!("expected {} to be invalid, got {:?}", field, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_records_kept_plaintext_are_explained() {
+        let shared = EncryptionError::Shared { table: "projects".to_string() };
+        assert_eq!(shared.code(), "encryption_shared");
+        assert_eq!(shared.details(), json!({ "table": "projects" }));
+
+        let client = EncryptionError::OpenBalance { table: "clients".to_string() };
+        assert_eq!(client.code(), "encryption_open_balance");
+        assert!(client.to_string().starts_with("clients with unpaid invoices"));
+        let invoice = EncryptionError::OpenBalance { table: "invoices".to_string() };
+        assert!(invoice.to_string().starts_with("unpaid invoices"));
+    }
+
+    #[test]
+    fn test_records_are_pulled_without_the_base_version() {
+        let stored = serde_json::to_value(EncryptedPayload { base_version: None, ..payload() }).unwrap();
+        let record = EncryptedRecord {
+            id: Uuid::new_v4(),
+            table_name: "invoices".to_string(),
+            record_id: Uuid::new_v4(),
+            payload: stored,
+            version: 4,
+        };
+
+        let pulled = record.to_record();
+
+        assert_eq!(pulled["id"], json!(record.record_id));
+        assert_eq!(pulled["version"], 4);
+        assert_eq!(pulled["encrypted"]["key_id"], "key-2024-01");
+        assert!(pulled["encrypted"].get("base_version").is_none());
+    }
+}
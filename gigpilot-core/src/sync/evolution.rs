@@ -0,0 +1,304 @@
+//! Column changes across sync schema versions.
+//!
+//! Adding or renaming a synced column can't wait for every device to update
+//! the app, so for a while old and new app versions sync side by side
+//! against the migrated server. Each such change is registered in
+//! [`COLUMN_CHANGES`] with the schema version that introduced it, and the
+//! sync layer bridges the gap in both directions:
+//!
+//! - dual-read: records pushed by older versions are upgraded to the current
+//!   columns after validation (old names renamed, new columns defaulted on
+//!   insert), so the apply path only ever sees current names
+//! - dual-write: pulled records carry a renamed column under both names for
+//!   as long as any supported version predates the rename, and change-log
+//!   entries written before the rename are read under the new name too
+//!
+//! Once the last version before a change is dropped from
+//! `SUPPORTED_SCHEMA_VERSIONS` the old name is no longer written; the
+//! registry entry can then be removed.
+
+use serde_json::{Map, Value};
+
+use crate::models::sync_change::SyncOperation;
+use crate::sync::schema::{FieldError, PayloadError, SUPPORTED_SCHEMA_VERSIONS};
+
+/// How a synced column changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnChangeKind {
+    /// The column was added. Records inserted by older versions get
+    /// `default` (a JSON literal, e.g. `"14"` or `"null"`).
+    Added { default: &'static str },
+
+    /// The column was renamed from `from`.
+    Renamed { from: &'static str },
+}
+
+/// A column added or renamed in a sync schema version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnChange {
+    /// Synced table of the column
+    pub table: &'static str,
+
+    /// Current name of the column
+    pub column: &'static str,
+
+    /// First schema version whose clients know the column by this name
+    pub since: u32,
+
+    /// What changed
+    pub kind: ColumnChangeKind,
+}
+
+/// Column changes still bridged for older app versions.
+pub const COLUMN_CHANGES: &[ColumnChange] = &[];
+
+/// Upgrades a pushed record to the current columns.
+///
+/// Applied after the record was validated against the schema of the
+/// version it was pushed with, before any of its fields are read.
+///
+/// # Arguments
+///
+/// * `version` - Sync schema version the client pushed with
+/// * `table` - Table the change targets
+/// * `operation` - Whether the record is inserted or updated
+/// * `data` - The pushed record
+///
+/// # Returns
+///
+/// The record with renamed columns under their current names and added
+/// columns defaulted on insert.
+///
+/// # Errors
+///
+/// Returns a `PayloadError` listing every added column whose registered
+/// default can't be read, rather than storing the record without it.
+pub fn upgrade_push(
+    version: u32,
+    table: &str,
+    operation: SyncOperation,
+    data: Value,
+) -> Result<Value, PayloadError> {
+    upgrade_record(COLUMN_CHANGES, version, table, operation, data)
+}
+
+/// Adds the names older versions read to every record of pulled changes.
+///
+/// # Arguments
+///
+/// * `changes` - Changes grouped by table and operation
+///   (`{ "invoices": { "created": [...], ... }, ... }`)
+pub fn dual_write_changes(changes: &mut Value) {
+    dual_write(COLUMN_CHANGES, oldest_supported(), changes);
+}
+
+/// Adds the names older versions read to a single record.
+///
+/// # Arguments
+///
+/// * `table` - Table of the record
+/// * `record` - The record as sent to clients
+pub fn dual_write_record(table: &str, record: &mut Value) {
+    if let Some(record) = record.as_object_mut() {
+        write_both_names(COLUMN_CHANGES, oldest_supported(), table, record);
+    }
+}
+
+/// Oldest schema version pushes are still accepted for.
+fn oldest_supported() -> u32 {
+    SUPPORTED_SCHEMA_VERSIONS.iter().copied().min().unwrap_or(0)
+}
+
+/// Upgrades a pushed record against a set of column changes.
+fn upgrade_record(
+    changes: &[ColumnChange],
+    version: u32,
+    table: &str,
+    operation: SyncOperation,
+    mut data: Value,
+) -> Result<Value, PayloadError> {
+    let Some(record) = data.as_object_mut() else {
+        return Ok(data);
+    };
+
+    let mut errors = Vec::new();
+
+    for change in changes.iter().filter(|c| c.table == table) {
+        match change.kind {
+            // Accepted from any version so a client updating mid-session
+            // with a stale outbox isn't rejected
+            ColumnChangeKind::Renamed { from } => {
+                if let Some(value) = record.remove(from) {
+                    record.entry(change.column).or_insert(value);
+                }
+            }
+            ColumnChangeKind::Added { default } => {
+                if version < change.since
+                    && matches!(operation, SyncOperation::Insert)
+                    && !record.contains_key(change.column)
+                {
+                    match serde_json::from_str(default) {
+                        Ok(value) => {
+                            record.insert(change.column.to_string(), value);
+                        }
+                        Err(e) => errors.push(FieldError {
+                            path: format!("/{}", change.column),
+                            error: format!("invalid default {:?}: {}", default, e),
+                        }),
+                    }
+                }
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(PayloadError {
+            table: table.to_string(),
+            fields: errors,
+        });
+    }
+
+    Ok(data)
+}
+
+/// Writes renamed columns of pulled records under both names.
+fn dual_write(changes: &[ColumnChange], oldest_supported: u32, pulled: &mut Value) {
+    let Some(tables) = pulled.as_object_mut() else {
+        return;
+    };
+
+    for (table, operations) in tables.iter_mut() {
+        let records = operations
+            .as_object_mut()
+            .into_iter()
+            .flat_map(|operations| operations.values_mut())
+            .filter_map(Value::as_array_mut)
+            .flatten()
+            .filter_map(Value::as_object_mut);
+
+        for record in records {
+            write_both_names(changes, oldest_supported, table, record);
+        }
+    }
+}
+
+/// Writes renamed columns of one pulled record under both names.
+fn write_both_names(
+    changes: &[ColumnChange],
+    oldest_supported: u32,
+    table: &str,
+    record: &mut Map<String, Value>,
+) {
+    for change in changes.iter().filter(|c| c.table == table) {
+        let ColumnChangeKind::Renamed { from } = change.kind else {
+            continue;
+        };
+
+        // Logged before the rename
+        if !record.contains_key(change.column) {
+            if let Some(value) = record.get(from).cloned() {
+                record.insert(change.column.to_string(), value);
+            }
+        }
+
+        if oldest_supported < change.since {
+            if let Some(value) = record.get(change.column).cloned() {
+                record.insert(from.to_string(), value);
+            }
+        } else {
+            record.remove(from);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CHANGES: &[ColumnChange] = &[
+        ColumnChange {
+            table: "invoices",
+            column: "reference",
+            since: 2,
+            kind: ColumnChangeKind::Renamed { from: "invoice_number" },
+        },
+        ColumnChange {
+            table: "invoices",
+            column: "payment_terms_days",
+            since: 2,
+            kind: ColumnChangeKind::Added { default: "14" },
+        },
+    ];
+
+    #[test]
+    fn test_upgrade_renames_and_defaults_old_pushes() {
+        let data = json!({ "invoice_number": "INV-7", "amount": "100.00" });
+
+        let upgraded = upgrade_record(CHANGES, 1, "invoices", SyncOperation::Insert, data.clone()).unwrap();
+        assert_eq!(
+            upgraded,
+            json!({ "reference": "INV-7", "amount": "100.00", "payment_terms_days": 14 })
+        );
+
+        // Updates only touch the fields they send
+        let upgraded = upgrade_record(CHANGES, 1, "invoices", SyncOperation::Update, data).unwrap();
+        assert_eq!(upgraded, json!({ "reference": "INV-7", "amount": "100.00" }));
+
+        // New clients send the new columns themselves
+        let data = json!({ "reference": "INV-8" });
+        let upgraded = upgrade_record(CHANGES, 2, "invoices", SyncOperation::Insert, data.clone()).unwrap();
+        assert_eq!(upgraded, data);
+
+        // Other tables are left alone
+        let data = json!({ "invoice_number": "INV-9" });
+        let upgraded = upgrade_record(CHANGES, 1, "clients", SyncOperation::Insert, data.clone()).unwrap();
+        assert_eq!(upgraded, data);
+    }
+
+    #[test]
+    fn test_unreadable_default_is_a_payload_error() {
+        const BROKEN: &[ColumnChange] = &[ColumnChange {
+            table: "invoices",
+            column: "payment_terms_days",
+            since: 2,
+            kind: ColumnChangeKind::Added { default: "fourteen" },
+        }];
+
+        let data = json!({ "amount": "100.00" });
+        let error = upgrade_record(BROKEN, 1, "invoices", SyncOperation::Insert, data.clone()).unwrap_err();
+        assert_eq!(error.table, "invoices");
+        assert_eq!(error.fields.len(), 1);
+        assert_eq!(error.fields[0].path, "/payment_terms_days");
+
+        // Records the column isn't added to are unaffected
+        let upgraded = upgrade_record(BROKEN, 2, "invoices", SyncOperation::Insert, data.clone()).unwrap();
+        assert_eq!(upgraded, data);
+    }
+
+    #[test]
+    fn test_dual_write_while_old_versions_are_supported() {
+        let mut pulled = json!({
+            "invoices": {
+                "created": [{ "id": "a", "reference": "INV-7" }],
+                "updated": [{ "id": "b", "invoice_number": "INV-1" }],
+                "deleted": [],
+            },
+        });
+
+        dual_write(CHANGES, 1, &mut pulled);
+        assert_eq!(
+            pulled["invoices"]["created"][0],
+            json!({ "id": "a", "reference": "INV-7", "invoice_number": "INV-7" })
+        );
+        assert_eq!(
+            pulled["invoices"]["updated"][0],
+            json!({ "id": "b", "reference": "INV-1", "invoice_number": "INV-1" })
+        );
+
+        // Once version 1 is dropped only the new name is written
+        dual_write(CHANGES, 2, &mut pulled);
+        assert_eq!(pulled["invoices"]["created"][0], json!({ "id": "a", "reference": "INV-7" }));
+        assert_eq!(pulled["invoices"]["updated"][0], json!({ "id": "b", "reference": "INV-1" }));
+    }
+}
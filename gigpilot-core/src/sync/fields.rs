@@ -0,0 +1,176 @@
+//! Which fields of a pushed record are written.
+//!
+//! The fields devices may push are the ones their table's sync schema
+//! lists. Fields the server computes or manages itself (totals, payments,
+//! ownership, timestamps; see
+//! [`SyncableTable::computed_fields`](crate::sync::tables::SyncableTable::computed_fields))
+//! are dropped from pushed records, since clients routinely push back the
+//! records they pulled. Any other field is unknown: with `SYNC_FIELD_MODE`
+//! set to `strict` the change is rejected with the unknown fields listed,
+//! otherwise (`lenient`, the default) they are dropped.
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::sync::evolution::{ColumnChangeKind, COLUMN_CHANGES};
+use crate::sync::schema::{self, FieldError, PayloadError};
+use crate::sync::tables::find_table;
+
+/// How unknown fields of pushed records are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldMode {
+    /// Drop them
+    #[default]
+    Lenient,
+
+    /// Reject the change
+    Strict,
+}
+
+impl FieldMode {
+    /// The mode set with `SYNC_FIELD_MODE` ("lenient" or "strict").
+    pub fn from_env() -> Self {
+        match std::env::var("SYNC_FIELD_MODE").as_deref() {
+            Ok("lenient") | Ok("") | Err(_) => FieldMode::Lenient,
+            Ok("strict") => FieldMode::Strict,
+            Ok(other) => {
+                warn!("Unknown sync field mode: {}, defaulting to lenient", other);
+                FieldMode::Lenient
+            }
+        }
+    }
+}
+
+/// Drops the fields of a pushed record the server doesn't take from
+/// devices.
+///
+/// Tables without a schema for `version`, and records that aren't objects,
+/// are left for validation to deal with.
+///
+/// # Arguments
+///
+/// * `version` - Sync schema version the client pushed with
+/// * `table` - Table the change targets
+/// * `data` - The pushed record, filtered in place
+/// * `mode` - How unknown fields are treated
+///
+/// # Returns
+///
+/// Returns the unknown fields that were dropped (none in strict mode).
+///
+/// # Errors
+///
+/// Returns a `PayloadError` listing the unknown fields in strict mode.
+pub fn filter_fields(version: u32, table: &str, data: &mut Value, mode: FieldMode) -> Result<Vec<String>, PayloadError> {
+    if !schema::has_schema(version, table) {
+        return Ok(Vec::new());
+    }
+    let Some(record) = data.as_object_mut() else {
+        return Ok(Vec::new());
+    };
+    let computed = find_table(table).map(|table| table.computed_fields()).unwrap_or(&[]);
+
+    let mut unknown = Vec::new();
+    record.retain(|field, _| {
+        if schema::has_field(version, table, field) == Some(true) {
+            return true;
+        }
+        if !computed.contains(&field.as_str()) && !is_former_name(table, field) {
+            unknown.push(field.clone());
+        }
+        false
+    });
+
+    if mode == FieldMode::Strict && !unknown.is_empty() {
+        return Err(PayloadError {
+            table: table.to_string(),
+            fields: unknown
+                .into_iter()
+                .map(|field| FieldError {
+                    path: format!("/{}", field),
+                    error: format!("{} is not a field of {} records", field, table),
+                })
+                .collect(),
+        });
+    }
+    if !unknown.is_empty() {
+        debug!("Dropped unknown fields pushed to {}: {:?}", table, unknown);
+    }
+
+    Ok(unknown)
+}
+
+/// Whether `field` is the old name of a renamed column, which pulled
+/// records still carry next to the new one.
+fn is_former_name(table: &str, field: &str) -> bool {
+    COLUMN_CHANGES.iter().any(|change| {
+        change.table == table && matches!(change.kind, ColumnChangeKind::Renamed { from } if from == field)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pulled_invoice() -> Value {
+        json!({
+            "id": "6f1c1c52-2c1e-4f57-9a55-0c8a1e6b7d10",
+            "user_id": "0b6c8f4e-3a52-4c8e-9a4b-2f0e1d7c5a11",
+            "invoice_number": "INV-001",
+            "client_name": "Acme Ltd",
+            "amount": "100.00",
+            "total": "119.00",
+            "amount_paid": "119.00",
+            "is_deleted": false,
+            "created_at": "2024-01-01T09:00:00Z",
+            "metadata": { "source": "mobile" },
+        })
+    }
+
+    #[test]
+    fn test_computed_fields_are_dropped() {
+        let mut invoice = pulled_invoice();
+        let unknown = filter_fields(1, "invoices", &mut invoice, FieldMode::Strict).unwrap();
+
+        assert!(unknown.is_empty());
+        assert_eq!(
+            invoice,
+            json!({
+                "id": "6f1c1c52-2c1e-4f57-9a55-0c8a1e6b7d10",
+                "invoice_number": "INV-001",
+                "client_name": "Acme Ltd",
+                "amount": "100.00",
+                "metadata": { "source": "mobile" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields_follow_the_mode() {
+        let mut invoice = pulled_invoice();
+        invoice["discount"] = json!("10.00");
+        invoice["is_paid"] = json!(true);
+
+        let mut lenient = invoice.clone();
+        let unknown = filter_fields(1, "invoices", &mut lenient, FieldMode::Lenient).unwrap();
+        assert_eq!(unknown, vec!["discount", "is_paid"]);
+        assert!(lenient.get("discount").is_none());
+        assert_eq!(lenient["invoice_number"], "INV-001");
+
+        let err = filter_fields(1, "invoices", &mut invoice, FieldMode::Strict).unwrap_err();
+        let paths: Vec<&str> = err.fields.iter().map(|field| field.path.as_str()).collect();
+        assert_eq!(paths, vec!["/discount", "/is_paid"]);
+    }
+
+    #[test]
+    fn test_tables_without_a_schema_are_left_alone() {
+        let mut record = json!({ "anything": 1 });
+        assert!(filter_fields(1, "users", &mut record, FieldMode::Strict).unwrap().is_empty());
+        assert_eq!(record, json!({ "anything": 1 }));
+
+        let mut settings = json!({ "user_id": "0b6c8f4e-3a52-4c8e-9a4b-2f0e1d7c5a11", "vat_id": "DE123456789" });
+        filter_fields(1, "user_settings", &mut settings, FieldMode::Strict).unwrap();
+        assert_eq!(settings, json!({ "vat_id": "DE123456789" }));
+    }
+}
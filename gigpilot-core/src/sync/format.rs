@@ -0,0 +1,300 @@
+//! Payload formats for pull and push.
+//!
+//! JSON stays the default. Clients can ask for MessagePack instead, which is
+//! smaller and much faster to parse for invoices with long line items on
+//! low-end devices: pull and push responses are MessagePack when `Accept`
+//! prefers `application/msgpack`, and pushes may send a MessagePack body
+//! with a matching `Content-Type`. Both formats carry the same fields;
+//! MessagePack maps use the JSON field names, and IDs, timestamps and dates
+//! are strings as in JSON.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// MIME type of MessagePack payloads.
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// Older MIME type some MessagePack libraries still send.
+const LEGACY_MSGPACK_CONTENT_TYPE: &str = "application/x-msgpack";
+
+/// Encoding of a sync payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncFormat {
+    Json,
+    MessagePack,
+}
+
+impl SyncFormat {
+    /// The format the client wants responses in, from its `Accept` header.
+    ///
+    /// MessagePack is used when it is accepted with at least the quality of
+    /// JSON; anything else (including no `Accept` at all) gets JSON.
+    pub fn accepted(headers: &HeaderMap) -> Self {
+        let mut msgpack = 0.0_f32;
+        let mut json = 0.0_f32;
+        for value in headers.get_all(header::ACCEPT) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for range in value.split(',') {
+                let mut params = range.split(';');
+                let media_type = params.next().unwrap_or_default().trim().to_lowercase();
+                let quality = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                match media_type.as_str() {
+                    MSGPACK_CONTENT_TYPE | LEGACY_MSGPACK_CONTENT_TYPE => msgpack = msgpack.max(quality),
+                    "application/json" | "application/*" | "*/*" => json = json.max(quality),
+                    _ => {}
+                }
+            }
+        }
+
+        if msgpack > 0.0 && msgpack >= json {
+            SyncFormat::MessagePack
+        } else {
+            SyncFormat::Json
+        }
+    }
+
+    /// The format of a request body, from its `Content-Type` header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the body is neither JSON nor MessagePack.
+    pub fn of_body(headers: &HeaderMap) -> Result<Self, String> {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase())
+            .unwrap_or_default();
+
+        match content_type.as_str() {
+            MSGPACK_CONTENT_TYPE | LEGACY_MSGPACK_CONTENT_TYPE => Ok(SyncFormat::MessagePack),
+            "application/json" => Ok(SyncFormat::Json),
+            other if other.starts_with("application/") && other.ends_with("+json") => Ok(SyncFormat::Json),
+            _ => Err(format!(
+                "expected a `Content-Type` of application/json or {}",
+                MSGPACK_CONTENT_TYPE
+            )),
+        }
+    }
+
+    /// Decodes a request body.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the body isn't a valid `T`.
+    pub fn decode<T: DeserializeOwned>(self, body: &[u8]) -> Result<T, String> {
+        match self {
+            SyncFormat::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+            SyncFormat::MessagePack => {
+                let mut deserializer = rmp_serde::Deserializer::new(body).with_human_readable();
+                T::deserialize(&mut deserializer).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Encodes a value as a payload in this format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value can't be serialized.
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            SyncFormat::Json => Ok(serde_json::to_vec(value)?),
+            SyncFormat::MessagePack => {
+                let mut buf = Vec::new();
+                let mut serializer = rmp_serde::Serializer::new(&mut buf)
+                    .with_struct_map()
+                    .with_human_readable();
+                value.serialize(&mut serializer)?;
+                Ok(buf)
+            }
+        }
+    }
+
+    /// A response carrying `value` in this format.
+    ///
+    /// The response varies on `Accept`, so caches keep the formats apart.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value can't be serialized.
+    pub fn respond<T: Serialize>(self, value: &T) -> Result<Response, anyhow::Error> {
+        let mut response = match self {
+            SyncFormat::Json => Json(value).into_response(),
+            SyncFormat::MessagePack => (
+                [(header::CONTENT_TYPE, MSGPACK_CONTENT_TYPE)],
+                self.encode(value)?,
+            )
+                .into_response(),
+        };
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept"));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::types::{PullResponse, PullStatus, PushChange, PushMode, PushRequest};
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn line_items() -> Value {
+        json!([
+            { "description": "Design work", "quantity": 12.5, "unit_price": "85.00", "tax_rate": 0.19 },
+            { "description": "Hosting (März)", "quantity": 1, "unit_price": "20.00", "tax_rate": null },
+        ])
+    }
+
+    /// A value as JSON after a trip through `format`.
+    fn round_trip<T: Serialize + DeserializeOwned>(format: SyncFormat, value: &T) -> Value {
+        let bytes = format.encode(value).unwrap();
+        let decoded: T = format.decode(&bytes).unwrap();
+        serde_json::to_value(decoded).unwrap()
+    }
+
+    #[test]
+    fn test_accept_negotiation() {
+        assert_eq!(SyncFormat::accepted(&HeaderMap::new()), SyncFormat::Json);
+        assert_eq!(
+            SyncFormat::accepted(&headers(header::ACCEPT, "application/json")),
+            SyncFormat::Json
+        );
+        assert_eq!(
+            SyncFormat::accepted(&headers(header::ACCEPT, "application/msgpack")),
+            SyncFormat::MessagePack
+        );
+        assert_eq!(
+            SyncFormat::accepted(&headers(header::ACCEPT, "application/msgpack, application/json;q=0.5")),
+            SyncFormat::MessagePack
+        );
+        assert_eq!(
+            SyncFormat::accepted(&headers(header::ACCEPT, "application/json, application/msgpack;q=0.5")),
+            SyncFormat::Json
+        );
+        assert_eq!(
+            SyncFormat::accepted(&headers(header::ACCEPT, "application/x-msgpack, */*;q=0.1")),
+            SyncFormat::MessagePack
+        );
+        assert_eq!(
+            SyncFormat::accepted(&headers(header::ACCEPT, "application/msgpack;q=0")),
+            SyncFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_body_format() {
+        assert_eq!(
+            SyncFormat::of_body(&headers(header::CONTENT_TYPE, "application/json; charset=utf-8")),
+            Ok(SyncFormat::Json)
+        );
+        assert_eq!(
+            SyncFormat::of_body(&headers(header::CONTENT_TYPE, "application/msgpack")),
+            Ok(SyncFormat::MessagePack)
+        );
+        assert!(SyncFormat::of_body(&headers(header::CONTENT_TYPE, "text/plain")).is_err());
+        assert!(SyncFormat::of_body(&HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_push_request_round_trip() {
+        let request = PushRequest {
+            changes: vec![
+                PushChange {
+                    table: "invoices".to_string(),
+                    id: Uuid::new_v4(),
+                    data: Some(json!({
+                        "invoice_number": "INV-0042",
+                        "amount": 1082.5,
+                        "due_date": "2024-03-31",
+                        "line_items": line_items(),
+                    })),
+                    deleted: false,
+                    device_id: Some("phone".to_string()),
+                    version_vector: Some(json!({ "phone": 3 })),
+                    patch: None,
+                    encrypted: None,
+                },
+                PushChange {
+                    table: "clients".to_string(),
+                    id: Uuid::new_v4(),
+                    data: None,
+                    deleted: true,
+                    device_id: None,
+                    version_vector: None,
+                    patch: None,
+                    encrypted: None,
+                },
+            ],
+            device_id: Some("phone".to_string()),
+            schema_version: Some(1),
+            locale: Some("de-DE".to_string()),
+            mode: PushMode::Atomic,
+            dry_run: false,
+            app_version: Some("2.4.1".to_string()),
+            platform: Some("android".to_string()),
+        };
+
+        let expected = serde_json::to_value(&request).unwrap();
+        assert_eq!(round_trip(SyncFormat::MessagePack, &request), expected);
+        assert_eq!(round_trip(SyncFormat::Json, &request), expected);
+    }
+
+    #[test]
+    fn test_pull_response_round_trip() {
+        let response = PullResponse {
+            changes: json!({
+                "invoices": {
+                    "created": [{
+                        "id": Uuid::new_v4(),
+                        "amount": 1082.5,
+                        "line_items": line_items(),
+                        "is_deleted": false,
+                    }],
+                    "updated": [],
+                    "deleted": [Uuid::new_v4()],
+                },
+            }),
+            timestamp: Utc::now(),
+            sequence_number: Some(1042),
+            has_more: true,
+            cursor: Some("1042".to_string()),
+            status: PullStatus::Ok,
+            snapshot_url: None,
+            editing: Vec::new(),
+            schema_version: 1,
+        };
+
+        let expected = serde_json::to_value(&response).unwrap();
+        assert_eq!(round_trip(SyncFormat::MessagePack, &response), expected);
+    }
+
+    #[test]
+    fn test_msgpack_is_smaller_and_keeps_strings() {
+        let id = Uuid::new_v4();
+        let record = json!({ "id": id, "line_items": line_items() });
+
+        let packed = SyncFormat::MessagePack.encode(&record).unwrap();
+        assert!(packed.len() < SyncFormat::Json.encode(&record).unwrap().len());
+
+        // IDs stay strings for clients decoding into plain maps
+        let decoded: Value = SyncFormat::MessagePack.decode(&packed).unwrap();
+        assert_eq!(decoded["id"], json!(id.to_string()));
+    }
+}
@@ -1,67 +1,438 @@
+use std::time::Instant;
+
 use axum::{
-    extract::{Query, Request, State},
-    http::StatusCode,
-    response::Json,
+    body::Bytes,
+    extract::{ws::WebSocketUpgrade, Extension, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use sqlx::PgPool;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use crate::auth::get_current_user_id;
-use crate::sync::types::{PullRequest, PullResponse, PushRequest, PushResponse};
+use crate::auth::CurrentUser;
+use crate::events::EventBus;
+use crate::sync::bootstrap::{stream_bootstrap, BOOTSTRAP_RETRY_AFTER_SECONDS};
+use crate::sync::devices::{
+    list_devices, record_pull, record_push, sync_status, DeviceReport, SyncDevice, SyncStatus,
+};
+use crate::sync::editing::{start_editing, stop_editing, EditingIndicator, EditingRequest};
+use crate::sync::encryption::{encrypted_tables, set_encrypted_tables, EncryptionSettings, TablesRefusal};
+use crate::sync::format::SyncFormat;
+use crate::sync::notify::serve_socket;
+use crate::sync::pull::{pull_page, pulled_tables};
+use crate::sync::rate_limit::{rate_limited, SyncRateLimiter};
+use crate::sync::snapshot::build_snapshot;
+use crate::sync::suggestions::{
+    self, accept_suggestion, dismiss_suggestion, list_suggestions, pushed_text, spawn_merge_suggestions,
+    text_conflicts, AcceptRefusal, MergeSuggestion,
+};
+use crate::sync::types::{PullRequest, PullResponse, PushRequest};
 use crate::sync::{get_changes, push_changes};
 
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
 /// Pull sync endpoint handler.
 /// 
-/// Handles GET requests to `/sync/pull` for retrieving changes
-/// from the server after a given timestamp.
+/// Handles GET (and, for older clients, POST) requests to `/sync/pull` and
+/// `/api/sync/pull` for retrieving changes from the server after a given
+/// sequence number (or, for older clients, timestamp). `tables` restricts the pull to some tables; naming an
+/// unknown table is rejected with 422, as are an invalid `limit` or
+/// `cursor`. Pulls beyond the device's or user's rate limit get 429. The
+/// response is MessagePack when `Accept` prefers it (see
+/// [`crate::sync::format`]).
 pub async fn pull_handler(
-    State(state): State<super::super::AppState>,
-    request: Request,
+    Extension(pool): Extension<PgPool>,
+    Extension(limiter): Extension<SyncRateLimiter>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
     Query(query): Query<PullRequest>,
-) -> Result<Json<PullResponse>, StatusCode> {
-    let user_id = get_current_user_id(&request)
-        .ok_or_else(|| {
-            error!("No user ID in request extensions");
-            StatusCode::UNAUTHORIZED
-        })?;
-    
+    headers: HeaderMap,
+) -> Result<Response, Response> {
     info!("Pull sync request from user: {}", user_id);
     
-    let response = get_changes(&state.db, user_id, query)
+    limiter
+        .check(user_id, query.device_id.as_deref(), Instant::now())
+        .map_err(rate_limited)?;
+    if let Err(message) = pulled_tables(query.tables.as_deref()) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message).into_response());
+    }
+    if let Err(message) = pull_page(&query) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message).into_response());
+    }
+    
+    let device = DeviceReport::new(
+        query.device_id.as_deref(),
+        query.app_version.as_deref(),
+        query.platform.as_deref(),
+    );
+    let response = get_changes(&pool, user_id, query)
         .await
         .map_err(|e| {
             error!("Pull sync failed: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "pull sync failed").into_response()
         })?;
     
-    Ok(Json(response))
+    // The pull already succeeded; a missed checkpoint only delays pruning
+    if let Some(device) = device {
+        if let Err(e) = record_pull(&pool, user_id, &device, &response).await {
+            warn!("Failed to record pull of device {}: {}", device.device_id, e);
+        }
+    }
+    
+    encode_response(&headers, &response)
 }
 
 /// Push sync endpoint handler.
 /// 
-/// Handles POST requests to `/sync/push` for applying changes
-/// from the client to the server.
+/// Handles POST requests to `/sync/push` and `/api/sync/push` for applying
+/// changes from the client to the server. The body may be JSON or
+/// MessagePack, as its `Content-Type` says (415 otherwise, 422 if it can't
+/// be decoded), and the response follows `Accept` like pulls do. Pushes
+/// beyond the device's or user's rate limit get 429. With a merge resolver
+/// configured, text conflicts are queued for merge suggestions.
 pub async fn push_handler(
-    State(state): State<super::super::AppState>,
-    request: Request,
-    Json(push_request): Json<PushRequest>,
-) -> Result<Json<PushResponse>, StatusCode> {
-    let user_id = get_current_user_id(&request)
+    Extension(pool): Extension<PgPool>,
+    Extension(limiter): Extension<SyncRateLimiter>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, Response> {
+    let format = SyncFormat::of_body(&headers)
+        .map_err(|message| error_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, &message).into_response())?;
+    let push_request: PushRequest = format
+        .decode(&body)
+        .map_err(|message| error_response(StatusCode::UNPROCESSABLE_ENTITY, &message).into_response())?;
+    info!("Push sync request from user: {} with {} changes", user_id, push_request.changes.len());
+    
+    let device = DeviceReport::new(
+        push_request.device_id.as_deref(),
+        push_request.app_version.as_deref(),
+        push_request.platform.as_deref(),
+    );
+    limiter
+        .check(user_id, device.as_ref().map(|device| device.device_id.as_str()), Instant::now())
+        .map_err(rate_limited)?;
+    // A dry run isn't a push the device made
+    let device = device.filter(|_| !push_request.dry_run);
+    let merge_provider = suggestions::provider_from_env().filter(|_| !push_request.dry_run);
+    let pushed = match merge_provider {
+        Some(_) => pushed_text(&push_request),
+        None => Vec::new(),
+    };
+    let device_id = push_request.device_id.clone().unwrap_or_else(|| "unknown".to_string());
+    let started = Instant::now();
+    let response = push_changes(&pool, user_id, push_request)
+        .await
+        .map_err(|e| {
+            error!("Push sync failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+    
+    if let Some(device) = device {
+        if let Err(e) = record_push(&pool, user_id, &device, started.elapsed()).await {
+            warn!("Failed to record push of device {}: {}", device.device_id, e);
+        }
+    }
+    if let Some(provider) = merge_provider {
+        spawn_merge_suggestions(pool, provider, user_id, device_id, text_conflicts(pushed, &response));
+    }
+    
+    encode_response(&headers, &response)
+}
+
+/// Encodes a pull or push response in the format the client accepts.
+fn encode_response<T: Serialize>(headers: &HeaderMap, response: &T) -> Result<Response, Response> {
+    SyncFormat::accepted(headers).respond(response).map_err(|e| {
+        error!("Failed to encode sync response: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+/// Snapshot endpoint handler.
+/// 
+/// Handles GET requests to `/sync/snapshot`, returning every live record
+/// for devices that were told to perform a full resync. Snapshots count
+/// against the user's rate limit.
+pub async fn snapshot_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(limiter): Extension<SyncRateLimiter>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<PullResponse>, Response> {
+    info!("Snapshot sync request from user: {}", user_id);
+    
+    limiter.check(user_id, None, Instant::now()).map_err(rate_limited)?;
+    let response = build_snapshot(&pool, user_id)
+        .await
+        .map_err(|e| {
+            error!("Snapshot sync failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+    
+    Ok(Json(response))
+}
+
+/// Bootstrap endpoint handler.
+/// 
+/// Handles GET requests to `/sync/bootstrap` and `/api/sync/bootstrap` for
+/// a device's first sync: every live record, streamed as newline-delimited
+/// JSON and ending with the sequence number to pull from next (see
+/// [`crate::sync::bootstrap`]). Bootstraps count against the user's rate
+/// limit; while the server is streaming as many as it allows, they are
+/// `503 Service Unavailable` with a `Retry-After` header.
+pub async fn bootstrap_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(limiter): Extension<SyncRateLimiter>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Response, Response> {
+    info!("Bootstrap sync request from user: {}", user_id);
+    
+    limiter.check(user_id, None, Instant::now()).map_err(rate_limited)?;
+    let body = stream_bootstrap(&pool, user_id)
+        .await
+        .map_err(|e| {
+            error!("Bootstrap sync failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?
         .ok_or_else(|| {
-            error!("No user ID in request extensions");
-            StatusCode::UNAUTHORIZED
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, BOOTSTRAP_RETRY_AFTER_SECONDS.to_string())],
+                Json(json!({
+                    "error": "too many bootstraps in progress; try again later",
+                    "code": "bootstrap_busy",
+                    "retry_after": BOOTSTRAP_RETRY_AFTER_SECONDS,
+                })),
+            )
+                .into_response()
         })?;
     
-    info!("Push sync request from user: {} with {} changes", user_id, push_request.changes.len());
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Sync devices endpoint handler.
+/// 
+/// Handles GET requests to `/sync/devices` and `/api/sync/devices`: the
+/// checkpoint of each device that synced the user's data, most recently
+/// seen first, with whether it is stale.
+pub async fn devices_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<SyncDevice>>, StatusCode> {
+    let devices = list_devices(&pool, user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to list sync devices: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
     
-    let response = push_changes(&state.db, user_id, push_request)
+    Ok(Json(devices))
+}
+
+/// Query parameters for the sync status.
+#[derive(Debug, Deserialize)]
+pub struct StatusQuery {
+    /// Only report this device
+    pub device_id: Option<String>,
+}
+
+/// Sync status endpoint handler.
+/// 
+/// Handles GET requests to `/sync/status` and `/api/sync/status`: each
+/// device's pending changes, last sync, conflict rate and average push
+/// latency, for the app's sync health indicator.
+pub async fn status_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<Vec<SyncStatus>>, StatusCode> {
+    let statuses = sync_status(&pool, user_id, query.device_id.as_deref())
         .await
         .map_err(|e| {
-            error!("Push sync failed: {}", e);
+            error!("Failed to load sync status: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
     
-    Ok(Json(response))
+    Ok(Json(statuses))
+}
+
+/// Merge suggestions endpoint handler.
+/// 
+/// Handles GET requests to `/sync/conflicts` and `/api/sync/conflicts`:
+/// the pending merge suggestions for text conflicts, newest first.
+pub async fn suggestions_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<MergeSuggestion>>, StatusCode> {
+    let suggestions = list_suggestions(&pool, user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to list merge suggestions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    
+    Ok(Json(suggestions))
+}
+
+/// Accept merge suggestion endpoint handler.
+/// 
+/// Handles POST requests to `/sync/conflicts/:id/accept`, writing the
+/// suggested text to the record. Returns 404 for unknown or already closed
+/// suggestions and 409 if the record changed since the suggestion was made.
+pub async fn accept_suggestion_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(suggestion_id): Path<Uuid>,
+) -> Result<Json<MergeSuggestion>, (StatusCode, Json<Value>)> {
+    let outcome = accept_suggestion(&pool, user_id, suggestion_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to accept merge suggestion {}: {}", suggestion_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to accept merge suggestion")
+        })?;
+    
+    match outcome {
+        Ok(suggestion) => Ok(Json(suggestion)),
+        Err(AcceptRefusal::NotFound) => Err(error_response(StatusCode::NOT_FOUND, "merge suggestion not found")),
+        Err(AcceptRefusal::Stale) => Err(error_response(
+            StatusCode::CONFLICT,
+            "the record has changed since this suggestion was made",
+        )),
+    }
+}
+
+/// Dismiss merge suggestion endpoint handler.
+/// 
+/// Handles POST requests to `/sync/conflicts/:id/dismiss`, leaving the
+/// record as it is.
+pub async fn dismiss_suggestion_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(suggestion_id): Path<Uuid>,
+) -> Result<Json<MergeSuggestion>, StatusCode> {
+    let dismissed = dismiss_suggestion(&pool, user_id, suggestion_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to dismiss merge suggestion {}: {}", suggestion_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    
+    dismissed.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Encryption settings endpoint handler.
+/// 
+/// Handles GET requests to `/sync/encryption` and `/api/sync/encryption`:
+/// the tables the user syncs end-to-end encrypted.
+pub async fn encryption_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<EncryptionSettings>, StatusCode> {
+    let tables = encrypted_tables(&pool, user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load encrypted tables: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    
+    Ok(Json(EncryptionSettings { tables }))
 }
 
+/// Update encryption settings endpoint handler.
+/// 
+/// Handles PUT requests to `/sync/encryption`, replacing the tables the
+/// user syncs end-to-end encrypted. Returns 422 for tables that can't be
+/// encrypted and 409 when dropping a table that still has encrypted
+/// records.
+pub async fn update_encryption_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(settings): Json<EncryptionSettings>,
+) -> Result<Json<EncryptionSettings>, (StatusCode, Json<Value>)> {
+    let outcome = set_encrypted_tables(&pool, user_id, &settings.tables)
+        .await
+        .map_err(|e| {
+            error!("Failed to update encrypted tables: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update encrypted tables")
+        })?;
+    
+    match outcome {
+        Ok(tables) => {
+            info!("User {} now syncs {:?} end-to-end encrypted", user_id, tables);
+            Ok(Json(EncryptionSettings { tables }))
+        }
+        Err(TablesRefusal::NotEncryptable(table)) => Err(error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &format!("{} can't be synced end-to-end encrypted", table),
+        )),
+        Err(TablesRefusal::HasRecords(table)) => Err(error_response(
+            StatusCode::CONFLICT,
+            &format!("{} still has encrypted records", table),
+        )),
+    }
+}
+
+/// Query parameters for the sync socket.
+#[derive(Debug, Deserialize)]
+pub struct SocketQuery {
+    /// The connecting device, whose own changes don't signal it
+    pub device_id: Option<String>,
+}
+
+/// Sync socket endpoint handler.
+/// 
+/// Handles WebSocket upgrades on `/sync/ws` and `/api/sync/ws`. The
+/// socket sends `{"type":"changes_available"}` when the device should
+/// pull (see [`crate::sync::notify`]).
+pub async fn socket_handler(
+    ws: WebSocketUpgrade,
+    Extension(bus): Extension<EventBus>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<SocketQuery>,
+) -> Response {
+    // Subscribe now so nothing logged during the handshake is missed
+    let events = bus.subscribe();
+    ws.on_upgrade(move |socket| serve_socket(socket, events, user_id, query.device_id))
+}
+
+/// Start-editing endpoint handler.
+/// 
+/// Handles POST requests to `/sync/editing`, creating or refreshing an
+/// advisory editing signal for a record. Clients should refresh the signal
+/// while the editor is open; it lapses automatically otherwise.
+pub async fn start_editing_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<EditingRequest>,
+) -> Result<Json<EditingIndicator>, StatusCode> {
+    let indicator = start_editing(&pool, user_id, &request)
+        .await
+        .map_err(|e| {
+            error!("Failed to start editing signal: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    
+    Ok(Json(indicator))
+}
+
+/// Stop-editing endpoint handler.
+/// 
+/// Handles DELETE requests to `/sync/editing`, releasing the device's
+/// editing signal when the editor is closed.
+pub async fn stop_editing_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<EditingRequest>,
+) -> Result<StatusCode, StatusCode> {
+    stop_editing(&pool, user_id, &request)
+        .await
+        .map_err(|e| {
+            error!("Failed to stop editing signal: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
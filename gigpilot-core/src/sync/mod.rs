@@ -1,8 +1,25 @@
 pub mod pull;
 pub mod push;
 pub mod types;
+pub mod bootstrap;
+pub mod compaction;
 pub mod conflict;
+pub mod devices;
 pub mod handlers;
+pub mod editing;
+pub mod encryption;
+pub mod evolution;
+pub mod fields;
+pub mod format;
+pub mod notify;
+pub mod patch;
+pub mod rate_limit;
+pub mod retention;
+pub mod schema;
+pub mod snapshot;
+pub mod suggestions;
+pub mod server;
+pub mod tables;
 
 #[cfg(test)]
 mod tests;
@@ -10,5 +27,9 @@ mod tests;
 pub use pull::get_changes;
 pub use push::push_changes;
 pub use types::*;
-pub use handlers::{pull_handler, push_handler};
+pub use handlers::{
+    accept_suggestion_handler, bootstrap_handler, devices_handler, dismiss_suggestion_handler, encryption_handler,
+    pull_handler, push_handler, snapshot_handler, socket_handler, start_editing_handler, status_handler,
+    stop_editing_handler, suggestions_handler, update_encryption_handler,
+};
 
@@ -0,0 +1,114 @@
+//! Real-time "changes available" signals.
+//!
+//! Devices can keep a WebSocket open on `/sync/ws` and pull as soon as it
+//! signals, instead of polling. Signals carry no data: they come from
+//! [`DomainEvent::SyncChangesAvailable`], which a database trigger
+//! publishes whenever changes the user pulls are logged, and the device
+//! still fetches the changes with a regular pull. A signal is also sent
+//! right after connecting, covering anything logged while the device was
+//! offline.
+
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::events::DomainEvent;
+
+/// Message sent when the device should pull.
+pub const CHANGES_AVAILABLE: &str = r#"{"type":"changes_available"}"#;
+
+/// Seconds between pings keeping idle connections open through proxies.
+pub const PING_INTERVAL_SECONDS: u64 = 30;
+
+/// Decides whether an event means a device should pull.
+///
+/// # Arguments
+///
+/// * `event` - The domain event
+/// * `user_id` - ID of the user the device syncs
+/// * `device_id` - The device, whose own changes don't signal it
+pub fn signals(event: &DomainEvent, user_id: Uuid, device_id: Option<&str>) -> bool {
+    match event {
+        DomainEvent::SyncChangesAvailable { user_id: target, device_id: source } => {
+            *target == user_id && device_id != Some(source.as_str())
+        }
+        _ => false,
+    }
+}
+
+/// Serves a device's sync socket until it disconnects.
+///
+/// # Arguments
+///
+/// * `socket` - The upgraded connection
+/// * `events` - Domain events, subscribed before the upgrade
+/// * `user_id` - ID of the user the device syncs
+/// * `device_id` - The device, if it said
+pub async fn serve_socket(
+    mut socket: WebSocket,
+    mut events: Receiver<DomainEvent>,
+    user_id: Uuid,
+    device_id: Option<String>,
+) {
+    debug!("Sync socket opened for user {} (device {:?})", user_id, device_id);
+    if socket.send(Message::Text(CHANGES_AVAILABLE.to_string())).await.is_err() {
+        return;
+    }
+
+    let mut ping = tokio::time::interval(Duration::from_secs(PING_INTERVAL_SECONDS));
+    ping.tick().await; // The first tick completes immediately
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let signal = match event {
+                    Ok(event) => signals(&event, user_id, device_id.as_deref()),
+                    // One of the missed events may have been for this user
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Sync socket of user {} missed {} events", user_id, missed);
+                        true
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if signal && socket.send(Message::Text(CHANGES_AVAILABLE.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Devices have nothing to say; pongs and anything else are ignored
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("Sync socket closed for user {} (device {:?})", user_id, device_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signals_other_devices_of_the_user() {
+        let user_id = Uuid::new_v4();
+        let event = DomainEvent::SyncChangesAvailable {
+            user_id,
+            device_id: "laptop".to_string(),
+        };
+
+        assert!(signals(&event, user_id, Some("phone")));
+        assert!(signals(&event, user_id, None));
+        // The device that made the changes already has them
+        assert!(!signals(&event, user_id, Some("laptop")));
+        assert!(!signals(&event, Uuid::new_v4(), Some("phone")));
+        assert!(!signals(&DomainEvent::MaintenanceChanged, user_id, None));
+    }
+}
@@ -0,0 +1,492 @@
+//! JSON Patch (RFC 6902) deltas for synced records.
+//!
+//! Devices may push an update as a patch against the server's record
+//! instead of the whole record (`PushChange.patch`), and may ask pulls to
+//! send updates of records they already have as patches
+//! (`PullRequest.patches`). Records are still stored and pulled in full by
+//! older clients; patches are only a smaller way of sending them.
+//!
+//! A pushed patch is applied to the record as the server has it, and
+//! conflicts are detected per field rather than per record: a failing
+//! `test` operation marks the top-level field it tests as conflicted, and
+//! every operation touching a conflicted field is skipped, so the server
+//! keeps its value there while the rest of the patch applies.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::locale::Locale;
+use crate::sync::schema::{self, PayloadError};
+
+/// One operation of a JSON Patch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    /// Adds a value, replacing an object member or inserting into an array
+    Add { path: String, value: Value },
+
+    /// Removes the value at `path`, which must exist
+    Remove { path: String },
+
+    /// Replaces the value at `path`, which must exist
+    Replace { path: String, value: Value },
+
+    /// Moves the value at `from` to `path`
+    Move { from: String, path: String },
+
+    /// Copies the value at `from` to `path`
+    Copy { from: String, path: String },
+
+    /// Checks that the value at `path` equals `value`
+    Test { path: String, value: Value },
+}
+
+impl PatchOperation {
+    /// Pointers the operation reads or writes.
+    fn pointers(&self) -> Vec<&str> {
+        match self {
+            PatchOperation::Add { path, .. }
+            | PatchOperation::Remove { path }
+            | PatchOperation::Replace { path, .. }
+            | PatchOperation::Test { path, .. } => vec![path],
+            PatchOperation::Move { from, path } | PatchOperation::Copy { from, path } => vec![from, path],
+        }
+    }
+
+    /// The value the operation carries, if any.
+    fn value_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            PatchOperation::Add { value, .. }
+            | PatchOperation::Replace { value, .. }
+            | PatchOperation::Test { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// A pushed patch that can't be applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The change sends both a record and a patch, or patches an insert or
+    /// a delete
+    NotAnUpdate,
+
+    /// The patched record doesn't exist (or was deleted)
+    MissingRecord,
+
+    /// An operation's pointer is malformed or targets the record itself or
+    /// its ID
+    InvalidPath { op: usize, path: String },
+
+    /// An operation's target (or source) doesn't exist
+    PathNotFound { op: usize, path: String },
+}
+
+impl PatchError {
+    /// Machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PatchError::MissingRecord => "patch_target_missing",
+            _ => "invalid_patch",
+        }
+    }
+
+    /// The failing operation, for the push response.
+    pub fn details(&self) -> Value {
+        match self {
+            PatchError::InvalidPath { op, path } | PatchError::PathNotFound { op, path } => {
+                json!({ "op": op, "path": path })
+            }
+            PatchError::NotAnUpdate | PatchError::MissingRecord => json!({}),
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::NotAnUpdate => write!(f, "only updates can be pushed as a patch, without data"),
+            PatchError::MissingRecord => write!(f, "patched record does not exist"),
+            PatchError::InvalidPath { op, path } => write!(f, "operation {} has an invalid path: {:?}", op, path),
+            PatchError::PathNotFound { op, path } => write!(f, "operation {} targets a missing value: {:?}", op, path),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// A patch applied to a record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patched {
+    /// The record with every applicable operation applied
+    pub record: Value,
+
+    /// Top-level fields whose `test` failed, where the server's value was
+    /// kept
+    pub conflicts: Vec<String>,
+}
+
+/// Applies a pushed patch to the server's version of a record.
+///
+/// `test` operations are checked in order against the record as patched so
+/// far; a failing one marks its top-level field conflicted, and the patch
+/// is then applied without any operation touching a conflicted field.
+///
+/// # Arguments
+///
+/// * `record` - The record as the server has it
+/// * `patch` - The pushed operations
+///
+/// # Returns
+///
+/// Returns the patched record and the conflicted fields.
+///
+/// # Errors
+///
+/// Returns a `PatchError` if an operation's path is invalid or targets a
+/// missing value.
+pub fn apply_patch(record: &Value, patch: &[PatchOperation]) -> Result<Patched, PatchError> {
+    let mut fields = Vec::with_capacity(patch.len());
+    for (i, operation) in patch.iter().enumerate() {
+        let mut touched = Vec::new();
+        for path in operation.pointers() {
+            let tokens = parse_pointer(path).ok_or_else(|| invalid_path(i, path))?;
+            match tokens.first() {
+                Some(field) if field != "id" => touched.push(field.clone()),
+                _ => return Err(invalid_path(i, path)),
+            }
+        }
+        fields.push(touched);
+    }
+
+    // Find the conflicted fields on a scratch copy first
+    let mut conflicts = HashSet::new();
+    let mut scratch = record.clone();
+    for (i, operation) in patch.iter().enumerate() {
+        if fields[i].iter().any(|field| conflicts.contains(field)) {
+            continue;
+        }
+        if !apply_operation(&mut scratch, i, operation)? {
+            conflicts.extend(fields[i].iter().cloned());
+        }
+    }
+
+    let mut patched = record.clone();
+    for (i, operation) in patch.iter().enumerate() {
+        if fields[i].iter().any(|field| conflicts.contains(field)) {
+            continue;
+        }
+        apply_operation(&mut patched, i, operation)?;
+    }
+
+    let mut conflicts: Vec<String> = conflicts.into_iter().collect();
+    conflicts.sort();
+    Ok(Patched { record: patched, conflicts })
+}
+
+/// Rewrites localized decimals and dates in a patch's values.
+///
+/// Only values set on top-level fields are read (see
+/// [`schema::localize_change`]); values deeper in a record are left as
+/// they are.
+///
+/// # Errors
+///
+/// Returns a `PayloadError` listing every value that can't be read in the
+/// locale.
+pub fn localize_patch(
+    version: u32,
+    table: &str,
+    patch: &mut [PatchOperation],
+    locale: &Locale,
+) -> Result<(), PayloadError> {
+    for operation in patch.iter_mut() {
+        let field = match operation.pointers().first().and_then(|path| parse_pointer(path)).as_deref() {
+            Some([field]) => field.clone(),
+            _ => continue,
+        };
+        let Some(value) = operation.value_mut() else {
+            continue;
+        };
+
+        let mut partial = Map::new();
+        partial.insert(field.clone(), value.clone());
+        let localized = schema::localize_change(version, table, &Value::Object(partial), locale)?;
+        if let Some(localized) = localized.get(&field) {
+            *value = localized.clone();
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes how a record changed as a patch.
+///
+/// Fields that differ are replaced (or added); fields missing from `new`
+/// are left as they are, since updates may carry a subset of fields.
+///
+/// # Returns
+///
+/// Returns the operations turning `old` into `new`, or `None` if either
+/// isn't an object.
+pub fn diff(old: &Value, new: &Value) -> Option<Vec<PatchOperation>> {
+    let (old, new) = (old.as_object()?, new.as_object()?);
+
+    let patch = new
+        .iter()
+        .filter(|(field, value)| old.get(*field) != Some(value))
+        .map(|(field, value)| {
+            let path = format!("/{}", escape_token(field));
+            let value = value.clone();
+            if old.contains_key(field) {
+                PatchOperation::Replace { path, value }
+            } else {
+                PatchOperation::Add { path, value }
+            }
+        })
+        .collect();
+
+    Some(patch)
+}
+
+/// Describes how some fields of a record changed as a patch.
+///
+/// Unlike [`diff`], every field in `fields` that `new` has is replaced (or
+/// added), even one now equal to its value in `old`: a device that changed
+/// it in between still needs it set back.
+///
+/// # Returns
+///
+/// Returns the operations, or `None` if either record isn't an object.
+pub fn set_fields<'a>(
+    old: &Value,
+    new: &Value,
+    fields: impl IntoIterator<Item = &'a String>,
+) -> Option<Vec<PatchOperation>> {
+    let (old, new) = (old.as_object()?, new.as_object()?);
+
+    let patch = fields
+        .into_iter()
+        .filter_map(|field| {
+            let path = format!("/{}", escape_token(field));
+            let value = new.get(field)?.clone();
+            Some(if old.contains_key(field) {
+                PatchOperation::Replace { path, value }
+            } else {
+                PatchOperation::Add { path, value }
+            })
+        })
+        .collect();
+
+    Some(patch)
+}
+
+fn invalid_path(op: usize, path: &str) -> PatchError {
+    PatchError::InvalidPath { op, path: path.to_string() }
+}
+
+fn not_found(op: usize, path: &str) -> PatchError {
+    PatchError::PathNotFound { op, path: path.to_string() }
+}
+
+/// Splits a JSON Pointer (RFC 6901) into its unescaped tokens.
+fn parse_pointer(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    let rest = pointer.strip_prefix('/')?;
+
+    Some(rest.split('/').map(|token| token.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Applies one operation.
+///
+/// # Returns
+///
+/// Returns `false` for a failing `test`, `true` otherwise.
+fn apply_operation(record: &mut Value, op: usize, operation: &PatchOperation) -> Result<bool, PatchError> {
+    let tokens = |path: &str| parse_pointer(path).ok_or_else(|| invalid_path(op, path));
+
+    match operation {
+        PatchOperation::Add { path, value } => add(record, &tokens(path)?, value.clone()).ok_or_else(|| not_found(op, path))?,
+        PatchOperation::Remove { path } => {
+            remove(record, &tokens(path)?).ok_or_else(|| not_found(op, path))?;
+        }
+        PatchOperation::Replace { path, value } => {
+            let target = get_mut(record, &tokens(path)?).ok_or_else(|| not_found(op, path))?;
+            *target = value.clone();
+        }
+        PatchOperation::Move { from, path } => {
+            let (from_tokens, path_tokens) = (tokens(from)?, tokens(path)?);
+            // A value can't be moved into itself
+            if path_tokens.len() > from_tokens.len() && path_tokens.starts_with(&from_tokens) {
+                return Err(invalid_path(op, path));
+            }
+            let value = remove(record, &from_tokens).ok_or_else(|| not_found(op, from))?;
+            add(record, &path_tokens, value).ok_or_else(|| not_found(op, path))?;
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = get_mut(record, &tokens(from)?).ok_or_else(|| not_found(op, from))?.clone();
+            add(record, &tokens(path)?, value).ok_or_else(|| not_found(op, path))?;
+        }
+        PatchOperation::Test { path, value } => {
+            return Ok(get_mut(record, &tokens(path)?).is_some_and(|current| current == value));
+        }
+    }
+
+    Ok(true)
+}
+
+fn get_mut<'a>(value: &'a mut Value, tokens: &[String]) -> Option<&'a mut Value> {
+    tokens.iter().try_fold(value, |value, token| match value {
+        Value::Object(object) => object.get_mut(token),
+        Value::Array(array) => array_index(token, array.len()).and_then(|i| array.get_mut(i)),
+        _ => None,
+    })
+}
+
+fn add(value: &mut Value, tokens: &[String], new: Value) -> Option<()> {
+    let (last, parent) = tokens.split_last()?;
+    match get_mut(value, parent)? {
+        Value::Object(object) => {
+            object.insert(last.clone(), new);
+        }
+        Value::Array(array) => {
+            let index = if last == "-" { array.len() } else { array_index(last, array.len() + 1)? };
+            array.insert(index, new);
+        }
+        _ => return None,
+    }
+
+    Some(())
+}
+
+fn remove(value: &mut Value, tokens: &[String]) -> Option<Value> {
+    let (last, parent) = tokens.split_last()?;
+    match get_mut(value, parent)? {
+        Value::Object(object) => object.remove(last),
+        Value::Array(array) => {
+            let index = array_index(last, array.len())?;
+            Some(array.remove(index))
+        }
+        _ => None,
+    }
+}
+
+/// Reads an array index below `len` (no leading zeros, as RFC 6901 says).
+fn array_index(token: &str, len: usize) -> Option<usize> {
+    if token.len() > 1 && token.starts_with('0') {
+        return None;
+    }
+    token.parse::<usize>().ok().filter(|i| *i < len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice() -> Value {
+        json!({
+            "id": "6f1c1c52-2c1e-4f57-9a55-0c8a1e6b7d10",
+            "client_name": "Acme Ltd",
+            "description": "Website",
+            "status": "draft",
+            "line_items": [
+                { "description": "Design", "quantity": "1", "unit_price": "500.00" },
+                { "description": "Build", "quantity": "2", "unit_price": "750.00" },
+            ],
+        })
+    }
+
+    fn patch(operations: Value) -> Vec<PatchOperation> {
+        serde_json::from_value(operations).unwrap()
+    }
+
+    #[test]
+    fn test_patch_operations_apply_in_order() {
+        let operations = patch(json!([
+            { "op": "replace", "path": "/description", "value": "Website relaunch" },
+            { "op": "add", "path": "/line_items/-", "value": { "description": "Hosting", "quantity": "1", "unit_price": "20.00" } },
+            { "op": "remove", "path": "/line_items/0" },
+            { "op": "copy", "from": "/client_name", "path": "/notes" },
+            { "op": "move", "from": "/notes", "path": "/client_email" },
+            { "op": "test", "path": "/line_items/1/description", "value": "Hosting" },
+        ]));
+
+        let patched = apply_patch(&invoice(), &operations).unwrap();
+
+        assert!(patched.conflicts.is_empty());
+        assert_eq!(patched.record["description"], "Website relaunch");
+        assert_eq!(patched.record["client_email"], "Acme Ltd");
+        assert!(patched.record.get("notes").is_none());
+        let items: Vec<&str> = patched.record["line_items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["description"].as_str().unwrap())
+            .collect();
+        assert_eq!(items, vec!["Build", "Hosting"]);
+    }
+
+    #[test]
+    fn test_failing_tests_keep_the_servers_field() {
+        // The device last saw the description as "Landing page"
+        let operations = patch(json!([
+            { "op": "test", "path": "/description", "value": "Landing page" },
+            { "op": "replace", "path": "/description", "value": "Landing page v2" },
+            { "op": "test", "path": "/status", "value": "draft" },
+            { "op": "replace", "path": "/status", "value": "sent" },
+        ]));
+
+        let patched = apply_patch(&invoice(), &operations).unwrap();
+
+        assert_eq!(patched.conflicts, vec!["description"]);
+        assert_eq!(patched.record["description"], "Website");
+        assert_eq!(patched.record["status"], "sent");
+    }
+
+    #[test]
+    fn test_invalid_patches_are_rejected() {
+        let missing = patch(json!([{ "op": "replace", "path": "/line_items/5/quantity", "value": "3" }]));
+        assert_eq!(
+            apply_patch(&invoice(), &missing),
+            Err(PatchError::PathNotFound { op: 0, path: "/line_items/5/quantity".to_string() })
+        );
+
+        for path in ["", "description", "/id"] {
+            let operations = patch(json!([{ "op": "replace", "path": path, "value": "x" }]));
+            assert_eq!(
+                apply_patch(&invoice(), &operations),
+                Err(PatchError::InvalidPath { op: 0, path: path.to_string() })
+            );
+        }
+
+        let into_itself = patch(json!([{ "op": "move", "from": "/line_items", "path": "/line_items/0" }]));
+        assert!(apply_patch(&invoice(), &into_itself).is_err());
+    }
+
+    #[test]
+    fn test_diff_round_trips() {
+        let old = invoice();
+        let mut new = invoice();
+        new["status"] = json!("sent");
+        new["due_date"] = json!("2024-04-01");
+        new.as_object_mut().unwrap().remove("client_name");
+
+        let operations = diff(&old, &new).unwrap();
+
+        assert_eq!(operations.len(), 2);
+        assert!(operations.contains(&PatchOperation::Add { path: "/due_date".to_string(), value: json!("2024-04-01") }));
+        assert!(operations.contains(&PatchOperation::Replace { path: "/status".to_string(), value: json!("sent") }));
+        let patched = apply_patch(&old, &operations).unwrap().record;
+        assert_eq!(patched["status"], "sent");
+        assert_eq!(patched["client_name"], "Acme Ltd");
+    }
+}
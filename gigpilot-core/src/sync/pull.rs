@@ -1,141 +1,777 @@
+use std::collections::{BTreeSet, HashMap};
+
 use chrono::{DateTime, Utc};
-use serde_json::{json, Value};
-use sqlx::PgPool;
+use serde_json::{json, Map, Value};
+use sqlx::{Executor, PgPool, Postgres};
 use tracing::{error, info};
 use uuid::Uuid;
 
-use crate::models::sync_change::SyncChange;
-use crate::sync::types::{PullRequest, PullResponse};
+use crate::models::share_grant::ShareEntity;
+use crate::models::sync_change::{SyncChange, SyncOperation};
+use crate::sync::editing::active_editing;
+use crate::sync::evolution;
+use crate::sync::patch;
+use crate::sync::retention::{
+    pruned_before, pruned_sequence, requires_full_resync, requires_full_resync_after_sequence, SNAPSHOT_PATH,
+};
+use crate::sync::schema::SYNC_SCHEMA_VERSION;
+use crate::sync::tables::TABLES;
+use crate::sync::types::{PullRequest, PullResponse, PullStatus};
 
 /// Retrieves changes from the database for pull synchronization.
 /// 
 /// This function implements the "Pull" part of the sync protocol, compatible
 /// with WatermelonDB. It queries the sync_changes table for all changes
-/// that occurred after the last_pulled_at timestamp.
+/// logged after the request's `last_sequence_number` cursor, or, for older
+/// clients that don't send one, after the `last_pulled_at` timestamp.
+/// 
+/// Sequence numbers are assigned in commit order, so a cursor never skips
+/// a change that commits after the pull; a timestamp can, when a
+/// transaction commits after a later one. Every response carries the
+/// cursor for the next pull.
+/// 
+/// With a `limit`, changes come in pages in commit order: while
+/// `has_more` is set, the device pulls again with the returned `cursor`
+/// (and its original cursor or timestamp) until the last page.
 /// 
 /// # Arguments
 /// 
 /// * `pool` - PostgreSQL connection pool
 /// * `user_id` - ID of the user requesting sync
-/// * `request` - Pull request with last_pulled_at timestamp
+/// * `request` - Pull request with the last cursor (or timestamp) and
+///   optional table filter
 /// 
 /// # Returns
 /// 
-/// Returns a `Result<PullResponse>` containing changes grouped by table,
-/// or an error if the query fails.
+/// Returns a `Result<PullResponse>` containing changes grouped by table
+/// (see [`envelope`]), or an error if the query fails.
 /// 
 /// # Errors
 /// 
 /// Returns an error if:
+/// - The table filter names an unknown table (see [`pulled_tables`])
+/// - The page is invalid (see [`pull_page`])
 /// - Database query fails
 /// - JSON serialization fails
+/// 
+/// If changes after the requesting device's last pull have been pruned,
+/// no changes are returned and the response status is `ResyncRequired`
+/// with a link to the full snapshot.
 pub async fn get_changes(
     pool: &PgPool,
     user_id: Uuid,
     request: PullRequest,
 ) -> Result<PullResponse, anyhow::Error> {
     info!(
-        "Pull sync requested for user {} with last_pulled_at: {:?}",
-        user_id, request.last_pulled_at
+        "Pull sync requested for user {} with last_sequence_number: {:?}, last_pulled_at: {:?}",
+        user_id, request.last_sequence_number, request.last_pulled_at
     );
     
-    // Query sync_changes table for changes after last_pulled_at
-    let changes = if let Some(last_pulled) = request.last_pulled_at {
-        // Incremental sync: get changes after last_pulled_at
-        sqlx::query_as::<_, SyncChange>(
-            r#"
-            SELECT 
-                id, user_id, table_name, record_id, operation,
-                old_data, new_data, device_id, change_timestamp,
-                vector_clock, is_applied, is_conflict, conflict_resolution,
-                sequence_number, created_at
-            FROM sync_changes
-            WHERE user_id = $1
-                AND change_timestamp > $2
-                AND is_applied = true
-            ORDER BY change_timestamp ASC, sequence_number ASC
-            "#,
-        )
-        .bind(user_id)
-        .bind(last_pulled)
-        .fetch_all(pool)
-        .await?
-    } else {
-        // Full sync: get all changes (for first sync)
-        sqlx::query_as::<_, SyncChange>(
-            r#"
-            SELECT 
-                id, user_id, table_name, record_id, operation,
-                old_data, new_data, device_id, change_timestamp,
-                vector_clock, is_applied, is_conflict, conflict_resolution,
-                sequence_number, created_at
-            FROM sync_changes
-            WHERE user_id = $1
-                AND is_applied = true
-            ORDER BY change_timestamp ASC, sequence_number ASC
-            "#,
-        )
-        .bind(user_id)
-        .fetch_all(pool)
-        .await?
+    let tables = pulled_tables(request.tables.as_deref()).map_err(|e| anyhow::anyhow!(e))?;
+    let page = pull_page(&request).map_err(|e| anyhow::anyhow!(e))?;
+    
+    // Refuse incremental sync for devices older than the retention horizon
+    let resync = match page.and_then(|page| page.after).or(request.last_sequence_number) {
+        Some(cursor) => requires_full_resync_after_sequence(cursor, pruned_sequence(pool, user_id).await?),
+        None => requires_full_resync(request.last_pulled_at, pruned_before(pool, user_id).await?),
+    };
+    if resync {
+        info!(
+            "Device {:?} of user {} is past the retention horizon, requiring full resync",
+            request.device_id, user_id
+        );
+        return Ok(PullResponse {
+            changes: json!({}),
+            timestamp: Utc::now(),
+            sequence_number: None,
+            has_more: false,
+            cursor: None,
+            status: PullStatus::ResyncRequired,
+            snapshot_url: Some(SNAPSHOT_PATH.to_string()),
+            editing: Vec::new(),
+            schema_version: SYNC_SCHEMA_VERSION,
+        });
+    }
+    
+    // Read the changes and the cursor from the same snapshot
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+    let (changes, has_more, logged) = match page {
+        Some(page) => {
+            let since = match request.last_sequence_number {
+                Some(_) => None,
+                None => request.last_pulled_at,
+            };
+            let after = page.after.or(request.last_sequence_number);
+            let (changes, has_more) = changes_page(&mut *tx, user_id, since, after, page.limit).await?;
+            // The last page of an older client's pull moves it to a cursor
+            let logged = match (has_more, request.last_sequence_number) {
+                (false, None) => latest_sequence(&mut *tx, user_id).await?,
+                _ => after,
+            };
+            (changes, has_more, logged)
+        }
+        None => match request.last_sequence_number {
+            Some(cursor) => (changes_after_sequence(&mut *tx, user_id, cursor).await?, false, Some(cursor)),
+            // Older clients move to a cursor covering everything logged so far
+            None => (
+                changes_since(&mut *tx, user_id, request.last_pulled_at).await?,
+                false,
+                latest_sequence(&mut *tx, user_id).await?,
+            ),
+        },
     };
+    tx.commit().await?;
     
-    info!("Found {} changes for user {}", changes.len(), user_id);
+    info!("Found {} changes for user {} (more: {})", changes.len(), user_id, has_more);
     
-    // Group changes by table name and operation type
-    let mut changes_by_table: std::collections::HashMap<String, std::collections::HashMap<String, Vec<Value>>> = 
-        std::collections::HashMap::new();
+    let sequence_number = next_cursor(&changes, logged);
+    let cursor = has_more.then(|| sequence_number.to_string());
+    let mut changes_json = envelope(&changes, &tables, request.patches);
     
-    for change in changes {
-        let table_name = change.table_name.clone();
-        let operation_type = match change.operation {
-            crate::models::sync_change::SyncOperation::Insert => "created",
-            crate::models::sync_change::SyncOperation::Update => "updated",
-            crate::models::sync_change::SyncOperation::Delete => "deleted",
+    // Keep renamed columns readable for older app versions
+    evolution::dual_write_changes(&mut changes_json);
+    
+    let timestamp = Utc::now();
+    
+    // Surface advisory editing signals from the user's other devices
+    let editing = active_editing(pool, user_id, request.device_id.as_deref()).await?;
+    
+    Ok(PullResponse {
+        changes: changes_json,
+        timestamp,
+        sequence_number: Some(sequence_number),
+        has_more,
+        cursor,
+        status: PullStatus::Ok,
+        snapshot_url: None,
+        editing,
+        schema_version: SYNC_SCHEMA_VERSION,
+    })
+}
+
+/// The cursor a pull returns: the highest sequence number among the pulled
+/// changes, or `logged` (the request's cursor, or the latest number logged
+/// for the user) if it is higher. `0` before anything was logged.
+pub fn next_cursor(changes: &[SyncChange], logged: Option<i64>) -> i64 {
+    changes
+        .iter()
+        .filter_map(|change| change.sequence_number)
+        .chain(logged)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Most changes one page of a pull may return.
+pub const MAX_PULL_LIMIT: i64 = 5000;
+
+/// Where a paged pull resumes and how much it returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PullPage {
+    /// Most changes to return
+    pub limit: i64,
+
+    /// Sequence number the previous page ended at; `None` on the first page
+    pub after: Option<i64>,
+}
+
+/// Resolves the `limit` and `cursor` of a pull.
+///
+/// # Returns
+///
+/// Returns `None` for an unpaged pull (neither is given), the page to
+/// return (a cursor without a limit pages by [`MAX_PULL_LIMIT`]), or a
+/// message describing the invalid field.
+pub fn pull_page(request: &PullRequest) -> Result<Option<PullPage>, String> {
+    if request.limit.is_none() && request.cursor.is_none() {
+        return Ok(None);
+    }
+
+    let limit = request.limit.unwrap_or(MAX_PULL_LIMIT);
+    if !(1..=MAX_PULL_LIMIT).contains(&limit) {
+        return Err(format!("limit must be between 1 and {}", MAX_PULL_LIMIT));
+    }
+    let after = match request.cursor.as_deref() {
+        Some(cursor) => match cursor.parse::<i64>() {
+            Ok(after) if after >= 0 => Some(after),
+            _ => return Err("cursor is not valid".to_string()),
+        },
+        None => None,
+    };
+
+    Ok(Some(PullPage { limit, after }))
+}
+
+/// Tables devices pull but never push; their records are written by the
+/// server (see [`crate::sync::server`]).
+pub const PULL_ONLY_TABLES: &[&str] = &["expenses", "receipts", "payments", "notifications"];
+
+/// Most tables a pull may name.
+const MAX_PULLED_TABLES: usize = 20;
+
+/// Resolves the `tables` filter of a pull.
+///
+/// # Arguments
+///
+/// * `filter` - Comma-separated table names, or `None` for every table
+///
+/// # Returns
+///
+/// Returns the tables to pull (every synced and pull-only table without a
+/// filter), or a message naming the first unknown table.
+pub fn pulled_tables(filter: Option<&str>) -> Result<Vec<&'static str>, String> {
+    let known = TABLES.iter().map(|table| table.name()).chain(PULL_ONLY_TABLES.iter().copied());
+
+    let Some(filter) = filter else {
+        return Ok(known.collect());
+    };
+
+    let known: Vec<&'static str> = known.collect();
+    let mut tables = Vec::new();
+    for name in filter.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let Some(table) = known.iter().copied().find(|table| *table == name) else {
+            return Err(format!("{} is not a synced table", name));
+        };
+        if !tables.contains(&table) {
+            tables.push(table);
+        }
+    }
+    if tables.is_empty() {
+        return Err("tables must name at least one table".to_string());
+    }
+    if tables.len() > MAX_PULLED_TABLES {
+        return Err(format!("at most {} tables can be pulled at once", MAX_PULLED_TABLES));
+    }
+
+    Ok(tables)
+}
+
+/// What a pull reports for one record.
+enum PulledRecord {
+    Created(Value),
+    /// The latest data, and how it changed since the device last pulled it
+    /// if the change log has it
+    Updated(Value, Option<Changed>),
+    Deleted,
+}
+
+/// How an updated record changed since a device last pulled it.
+#[derive(Clone)]
+struct Changed {
+    /// The record as it was before the first change
+    base: Value,
+
+    /// Every field a change in the window set, including the pulling
+    /// device's own changes: the device's copy differs from `base` in
+    /// those, so a field set back to its old value still has to be sent
+    fields: BTreeSet<String>,
+}
+
+impl Changed {
+    /// Adds the fields an update set: those that differ from the record
+    /// before it, or all it sends if the change log doesn't have that.
+    fn extend(mut self, old: Option<&Value>, new: &Value) -> Option<Self> {
+        let fields = match old {
+            Some(old) => changed_fields(old, new)?,
+            None => changed_fields(&json!({}), new)?,
         };
+        self.fields.extend(fields);
+        Some(self)
+    }
+}
+
+/// The fields of `new` that differ from `old`, leaving out the ID.
+fn changed_fields(old: &Value, new: &Value) -> Option<BTreeSet<String>> {
+    let (old, new) = (old.as_object()?, new.as_object()?);
+    Some(
+        new.iter()
+            .filter(|(field, value)| *field != "id" && old.get(*field) != Some(value))
+            .map(|(field, _)| field.clone())
+            .collect(),
+    )
+}
+
+/// Folds change-log entries into the WatermelonDB changes envelope.
+///
+/// Every table in `tables` gets `created`, `updated` and `deleted` lists
+/// (empty if nothing changed); changes to other tables are left out. Each
+/// record appears once, in its latest state: a record inserted and then
+/// updated since the last pull is `created` with its latest data, and a
+/// deleted record is listed by ID only, as WatermelonDB expects.
+///
+/// With `patches`, every table also gets a `patched` list, and updated
+/// records whose previous state the change log has are sent there as
+/// `{ "id", "patch" }` instead of in full. The patch sets every field
+/// changed since that state, not just those that differ from it, since
+/// the window may include the device's own changes.
+///
+/// # Arguments
+///
+/// * `changes` - Change-log entries, oldest first
+/// * `tables` - Tables to include
+/// * `patches` - Whether the device takes patches
+///
+/// # Returns
+///
+/// Returns `{ "<table>": { "created": [...], "updated": [...], "deleted": ["<id>", ...] }, ... }`.
+pub fn envelope(changes: &[SyncChange], tables: &[&str], patches: bool) -> Value {
+    let mut records: Vec<(&str, Uuid, PulledRecord)> = Vec::new();
+    let mut positions: HashMap<(&str, Uuid), usize> = HashMap::new();
+    
+    for change in changes {
+        let table = change.table_name.as_str();
+        if !tables.contains(&table) {
+            continue;
+        }
         
-        // Get the record data (new_data for INSERT/UPDATE, old_data for DELETE)
-        let record_data = match change.operation {
-            crate::models::sync_change::SyncOperation::Insert | 
-            crate::models::sync_change::SyncOperation::Update => {
-                change.new_data.clone()
-            }
-            crate::models::sync_change::SyncOperation::Delete => {
-                change.old_data.clone()
-            }
+        let state = match change.operation {
+            SyncOperation::Delete => Some(PulledRecord::Deleted),
+            SyncOperation::Insert | SyncOperation::Update => change.new_data.clone().map(|mut data| {
+                if let Some(obj) = data.as_object_mut() {
+                    obj.insert("id".to_string(), json!(change.record_id));
+                }
+                // Still new to a device that hasn't seen the insert
+                let previous = positions.get(&(table, change.record_id)).map(|&i| &records[i].2);
+                if matches!(change.operation, SyncOperation::Insert) {
+                    PulledRecord::Created(data)
+                } else {
+                    match previous {
+                        Some(PulledRecord::Created(_)) => PulledRecord::Created(data),
+                        // The device still has the record as before the first update
+                        Some(PulledRecord::Updated(_, changed)) => {
+                            let changed = changed.clone().and_then(|changed| changed.extend(change.old_data.as_ref(), &data));
+                            PulledRecord::Updated(data, changed)
+                        }
+                        Some(PulledRecord::Deleted) => PulledRecord::Updated(data, None),
+                        None => {
+                            let changed = change.old_data.clone().and_then(|base| {
+                                let fields = changed_fields(&base, &data)?;
+                                Some(Changed { base, fields })
+                            });
+                            PulledRecord::Updated(data, changed)
+                        }
+                    }
+                }
+            }),
+        };
+        let Some(state) = state else {
+            continue;
         };
         
-        if let Some(data) = record_data {
-            // Add record ID to the data
-            let mut record = data.clone();
-            if let Some(obj) = record.as_object_mut() {
-                obj.insert("id".to_string(), json!(change.record_id));
+        match positions.get(&(table, change.record_id)) {
+            Some(&i) => records[i].2 = state,
+            None => {
+                positions.insert((table, change.record_id), records.len());
+                records.push((table, change.record_id, state));
             }
-            
-            changes_by_table
-                .entry(table_name)
-                .or_insert_with(std::collections::HashMap::new)
-                .entry(operation_type.to_string())
-                .or_insert_with(Vec::new)
-                .push(record);
         }
     }
     
-    // Convert to WatermelonDB-compatible format
-    let mut changes_json = json!({});
-    for (table, operations) in changes_by_table {
-        let mut table_changes = json!({});
-        for (op_type, records) in operations {
-            table_changes[op_type] = json!(records);
+    let mut envelope = Map::new();
+    for table in tables {
+        let mut lists = json!({ "created": [], "updated": [], "deleted": [] });
+        if patches {
+            lists["patched"] = json!([]);
+        }
+        envelope.insert(table.to_string(), lists);
+    }
+    for (table, record_id, state) in records {
+        let (bucket, record) = match state {
+            PulledRecord::Created(data) => ("created", data),
+            PulledRecord::Updated(data, Some(changed)) if patches => match patch::set_fields(&changed.base, &data, &changed.fields) {
+                Some(operations) => ("patched", json!({ "id": record_id, "patch": operations })),
+                None => ("updated", data),
+            },
+            PulledRecord::Updated(data, _) => ("updated", data),
+            PulledRecord::Deleted => ("deleted", json!(record_id)),
+        };
+        if let Some(list) = envelope[table][bucket].as_array_mut() {
+            list.push(record);
         }
-        changes_json[table] = table_changes;
     }
     
-    let timestamp = Utc::now();
+    Value::Object(envelope)
+}
+
+/// Columns of a `SyncChange` row.
+const CHANGE_COLUMNS: &str = "id, user_id, table_name, record_id, operation, \
+    old_data, new_data, device_id, change_timestamp, \
+    vector_clock, is_applied, is_conflict, conflict_resolution, \
+    sequence_number, created_at";
+
+/// Applied changes user `$1` pulls: their own, and the owners' changes to
+/// projects and clients shared with them, made since they were shared (the
+/// share itself records the record as it was then).
+pub(crate) const PULLED_CHANGES: &str = r#"
+    (
+        c.user_id = $1
+        OR EXISTS (
+            SELECT 1 FROM share_grants g
+            WHERE g.grantee_id = $1
+                AND g.user_id = c.user_id
+                AND g.entity_id = c.record_id
+                AND c.table_name = CASE g.entity_type WHEN 'project' THEN 'projects' ELSE 'clients' END
+                AND c.change_timestamp >= g.created_at
+        )
+    )
+    AND c.is_applied = true
+"#;
+
+/// Rows of a synced table user `$1` pulls: their own, and, for projects
+/// and clients, those shared with them. Snapshots and bootstraps read the
+/// same rows whose changes [`PULLED_CHANGES`] delivers.
+///
+/// # Arguments
+///
+/// * `table` - The synced table, which the condition refers to by name
+///
+/// # Returns
+///
+/// Returns the SQL condition.
+pub(crate) fn pulled_rows(table: &str) -> String {
+    match ShareEntity::from_table(table) {
+        Some(entity) => format!(
+            r#"
+    (
+        {0}.user_id = $1
+        OR EXISTS (
+            SELECT 1 FROM share_grants g
+            WHERE g.grantee_id = $1
+                AND g.user_id = {0}.user_id
+                AND g.entity_id = {0}.id
+                AND g.entity_type = '{1}'
+        )
+    )
+"#,
+            table,
+            match entity {
+                ShareEntity::Project => "project",
+                ShareEntity::Client => "client",
+            }
+        ),
+        None => format!("{}.user_id = $1", table),
+    }
+}
+
+/// Loads a user's applied changes after a timestamp, oldest first.
+/// 
+/// Used for clients that don't send a sequence cursor yet (see
+/// [`get_changes`]).
+/// 
+/// # Arguments
+/// 
+/// * `executor` - Database pool or connection
+/// * `user_id` - ID of the user
+/// * `since` - Only changes after this time; `None` returns every change
+///   (first sync)
+/// 
+/// # Returns
+/// 
+/// Returns the matching `SyncChange` rows, or an error.
+pub async fn changes_since<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<SyncChange>, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let changes = sqlx::query_as::<_, SyncChange>(&format!(
+        r#"
+        SELECT {}
+        FROM sync_changes c
+        WHERE {}
+            AND ($2::timestamptz IS NULL OR c.change_timestamp > $2)
+        ORDER BY c.change_timestamp ASC, c.sequence_number ASC
+        "#,
+        CHANGE_COLUMNS, PULLED_CHANGES
+    ))
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(executor)
+    .await?;
     
-    Ok(PullResponse {
-        changes: changes_json,
-        timestamp,
-    })
+    Ok(changes)
+}
+
+/// Loads a user's applied changes after a sequence number, in commit order.
+/// 
+/// # Arguments
+/// 
+/// * `executor` - Database pool or connection
+/// * `user_id` - ID of the user
+/// * `after` - The cursor returned by the last pull
+/// 
+/// # Returns
+/// 
+/// Returns the matching `SyncChange` rows, or an error.
+pub async fn changes_after_sequence<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    after: i64,
+) -> Result<Vec<SyncChange>, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let changes = sqlx::query_as::<_, SyncChange>(&format!(
+        r#"
+        SELECT {}
+        FROM sync_changes c
+        WHERE {}
+            AND c.sequence_number > $2
+        ORDER BY c.sequence_number ASC
+        "#,
+        CHANGE_COLUMNS, PULLED_CHANGES
+    ))
+    .bind(user_id)
+    .bind(after)
+    .fetch_all(executor)
+    .await?;
+    
+    Ok(changes)
+}
+
+/// Loads one page of a user's applied changes, in commit order.
+/// 
+/// Pages follow sequence numbers, so changes that commit while a device is
+/// paging land on a later page rather than being skipped.
+/// 
+/// # Arguments
+/// 
+/// * `executor` - Database pool or connection
+/// * `user_id` - ID of the user
+/// * `since` - Only changes after this time (older clients' pulls)
+/// * `after` - Only changes after this sequence number
+/// * `limit` - Most changes to return
+/// 
+/// # Returns
+/// 
+/// Returns the page's `SyncChange` rows and whether more follow, or an
+/// error.
+pub async fn changes_page<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    since: Option<DateTime<Utc>>,
+    after: Option<i64>,
+    limit: i64,
+) -> Result<(Vec<SyncChange>, bool), anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let mut changes = sqlx::query_as::<_, SyncChange>(&format!(
+        r#"
+        SELECT {}
+        FROM sync_changes c
+        WHERE {}
+            AND ($2::timestamptz IS NULL OR c.change_timestamp > $2)
+            AND ($3::bigint IS NULL OR c.sequence_number > $3)
+        ORDER BY c.sequence_number ASC
+        LIMIT $4
+        "#,
+        CHANGE_COLUMNS, PULLED_CHANGES
+    ))
+    .bind(user_id)
+    .bind(since)
+    .bind(after)
+    // One more than asked for tells whether another page follows
+    .bind(limit + 1)
+    .fetch_all(executor)
+    .await?;
+    
+    let has_more = changes.len() as i64 > limit;
+    changes.truncate(limit as usize);
+    
+    Ok((changes, has_more))
+}
+
+/// Loads the highest sequence number logged for a user, if any.
+pub async fn latest_sequence<'e, E>(executor: E, user_id: Uuid) -> Result<Option<i64>, anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let latest = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(sequence_number) FROM sync_changes WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(executor)
+    .await?;
+    
+    Ok(latest)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(table: &str, record_id: Uuid, operation: SyncOperation, data: Value) -> SyncChange {
+        let (old_data, new_data) = match operation {
+            SyncOperation::Delete => (Some(data), None),
+            SyncOperation::Insert | SyncOperation::Update => (None, Some(data)),
+        };
+        SyncChange {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            table_name: table.to_string(),
+            record_id,
+            operation,
+            old_data,
+            new_data,
+            device_id: "device-1".to_string(),
+            change_timestamp: Utc::now(),
+            vector_clock: None,
+            is_applied: true,
+            is_conflict: false,
+            conflict_resolution: None,
+            sequence_number: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_envelope_collapses_changes_per_record() {
+        let (created, updated, deleted) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let changes = vec![
+            change("clients", created, SyncOperation::Insert, json!({ "name": "Acme" })),
+            change("clients", created, SyncOperation::Update, json!({ "name": "Acme Ltd" })),
+            change("clients", updated, SyncOperation::Update, json!({ "name": "Globex" })),
+            change("projects", deleted, SyncOperation::Update, json!({ "name": "Site" })),
+            change("projects", deleted, SyncOperation::Delete, json!({ "name": "Site" })),
+        ];
+
+        let envelope = envelope(&changes, &["clients", "projects", "time_entries"], false);
+
+        assert_eq!(envelope["clients"]["created"], json!([{ "id": created, "name": "Acme Ltd" }]));
+        assert_eq!(envelope["clients"]["updated"], json!([{ "id": updated, "name": "Globex" }]));
+        assert_eq!(envelope["clients"]["deleted"], json!([]));
+        assert_eq!(envelope["projects"]["updated"], json!([]));
+        assert_eq!(envelope["projects"]["deleted"], json!([deleted.to_string()]));
+        assert_eq!(envelope["time_entries"], json!({ "created": [], "updated": [], "deleted": [] }));
+    }
+
+    #[test]
+    fn test_envelope_sends_updates_as_patches() {
+        let (patched, unknown_base) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut first = change("clients", patched, SyncOperation::Update, json!({ "name": "Acme", "phone": "555-0100" }));
+        first.old_data = Some(json!({ "id": patched, "name": "Acme", "phone": null }));
+        let changes = vec![
+            first,
+            change("clients", patched, SyncOperation::Update, json!({ "name": "Acme Ltd", "phone": "555-0100" })),
+            change("clients", unknown_base, SyncOperation::Update, json!({ "name": "Globex" })),
+        ];
+
+        let envelope = envelope(&changes, &["clients", "projects"], true);
+
+        assert_eq!(envelope["clients"]["patched"].as_array().map(Vec::len), Some(1));
+        assert_eq!(envelope["clients"]["patched"][0]["id"], json!(patched));
+        let mut operations = envelope["clients"]["patched"][0]["patch"].as_array().unwrap().clone();
+        operations.sort_by_key(|operation| operation["path"].as_str().map(str::to_string));
+        assert_eq!(
+            operations,
+            vec![
+                json!({ "op": "replace", "path": "/name", "value": "Acme Ltd" }),
+                json!({ "op": "replace", "path": "/phone", "value": "555-0100" }),
+            ]
+        );
+        // Without the previous state the record is sent in full
+        assert_eq!(envelope["clients"]["updated"], json!([{ "id": unknown_base, "name": "Globex" }]));
+        assert_eq!(envelope["projects"]["patched"], json!([]));
+    }
+
+    #[test]
+    fn test_patch_sets_fields_changed_back_by_another_device() {
+        // Device A sets the phone from 1 to 2, then device B sets it back to
+        // 1: A's copy has 2, so the patch must set it even though the
+        // record ends as it started
+        let record_id = Uuid::new_v4();
+        let mut first = change("clients", record_id, SyncOperation::Update, json!({ "id": record_id, "phone": "2" }));
+        first.old_data = Some(json!({ "id": record_id, "phone": "1" }));
+        first.device_id = "device-a".to_string();
+        let mut second = change("clients", record_id, SyncOperation::Update, json!({ "id": record_id, "phone": "1" }));
+        second.old_data = Some(json!({ "id": record_id, "phone": "2" }));
+        second.device_id = "device-b".to_string();
+
+        let envelope = envelope(&[first, second], &["clients"], true);
+
+        assert_eq!(
+            envelope["clients"]["patched"],
+            json!([{ "id": record_id, "patch": [{ "op": "replace", "path": "/phone", "value": "1" }] }])
+        );
+    }
+
+    #[test]
+    fn test_envelope_leaves_out_unselected_tables() {
+        let changes = vec![
+            change("invoices", Uuid::new_v4(), SyncOperation::Insert, json!({ "amount": 10 })),
+            change("clients", Uuid::new_v4(), SyncOperation::Insert, json!({ "name": "Acme" })),
+        ];
+
+        let envelope = envelope(&changes, &["clients"], false);
+
+        assert_eq!(envelope.as_object().map(|tables| tables.len()), Some(1));
+        assert_eq!(envelope["clients"]["created"].as_array().map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn test_cursor_is_highest_sequence_number() {
+        let mut first = change("clients", Uuid::new_v4(), SyncOperation::Insert, json!({}));
+        first.sequence_number = Some(12);
+        let mut second = change("clients", Uuid::new_v4(), SyncOperation::Insert, json!({}));
+        second.sequence_number = Some(15);
+
+        assert_eq!(next_cursor(&[first.clone(), second], Some(10)), 15);
+        assert_eq!(next_cursor(&[first], Some(20)), 20);
+        // Nothing new: the cursor stays where it was
+        assert_eq!(next_cursor(&[], Some(7)), 7);
+        assert_eq!(next_cursor(&[], None), 0);
+    }
+
+    #[test]
+    fn test_pull_page() {
+        let request = |limit: Option<i64>, cursor: Option<&str>| PullRequest {
+            last_pulled_at: None,
+            last_sequence_number: None,
+            device_id: None,
+            tables: None,
+            limit,
+            cursor: cursor.map(str::to_string),
+            app_version: None,
+            platform: None,
+            patches: false,
+        };
+
+        assert_eq!(pull_page(&request(None, None)), Ok(None));
+        assert_eq!(
+            pull_page(&request(Some(500), None)),
+            Ok(Some(PullPage { limit: 500, after: None }))
+        );
+        assert_eq!(
+            pull_page(&request(Some(500), Some("1042"))),
+            Ok(Some(PullPage { limit: 500, after: Some(1042) }))
+        );
+        assert_eq!(
+            pull_page(&request(None, Some("1042"))),
+            Ok(Some(PullPage { limit: MAX_PULL_LIMIT, after: Some(1042) }))
+        );
+
+        assert!(pull_page(&request(Some(0), None)).is_err());
+        assert!(pull_page(&request(Some(MAX_PULL_LIMIT + 1), None)).is_err());
+        assert!(pull_page(&request(Some(500), Some("abc"))).is_err());
+        assert!(pull_page(&request(Some(500), Some("-1"))).is_err());
+    }
+
+    #[test]
+    fn test_pulled_tables() {
+        let every = pulled_tables(None).unwrap();
+        assert!(every.contains(&"user_settings"));
+        assert!(every.contains(&"expenses"));
+
+        assert_eq!(
+            pulled_tables(Some("clients, projects,clients,")).unwrap(),
+            vec!["clients", "projects"]
+        );
+        assert!(pulled_tables(Some("users")).is_err());
+        assert!(pulled_tables(Some(" , ")).is_err());
+    }
+}
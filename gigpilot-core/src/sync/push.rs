@@ -1,19 +1,59 @@
 use chrono::Utc;
 use serde_json::Value;
-use sqlx::{PgPool, Postgres, Transaction};
-use std::str::FromStr;
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::invoices::due_dates::{DueDateError, DueDateRules};
+use crate::invoices::history::{apply_audit_context, current_audit_context, AuditContext};
+use crate::locale::Locale;
+use crate::models::invoice::StatusError;
+use crate::models::invoice_event::AuditSource;
+use crate::models::line_item::LineItemsError;
+use crate::models::share_grant::{ShareEntity, SharePermission};
 use crate::models::sync_change::SyncOperation;
-use crate::sync::conflict::{has_conflict, resolve_conflict};
-use crate::sync::types::{ConflictStrategy, PushChange, PushRequest, PushResponse};
+use crate::projects;
+use crate::sharing::shared_grant;
+use crate::sync::conflict::{current_record, has_conflict, resolve_conflict};
+use crate::sync::encryption::{self, EncryptionError};
+use crate::sync::evolution;
+use crate::sync::fields::{self, FieldMode};
+use crate::sync::patch::{self, PatchError, PatchOperation, Patched};
+use crate::sync::schema::{self, PayloadError, SUPPORTED_SCHEMA_VERSIONS, SYNC_SCHEMA_VERSION};
+use crate::sync::tables::{find_table, AccessError, PushContext};
+use crate::sync::types::{
+    ConflictStrategy, ConflictVersion, PushChange, PushMode, PushRequest, PushResponse, RejectedChange,
+};
 
 /// Applies changes from the client to the server (Push synchronization).
 /// 
 /// This function implements the "Push" part of the sync protocol. It applies
 /// changes transactionally, handling conflicts and recording changes in the
-/// sync_changes table.
+/// sync_changes table. Each record is validated against the sync schema of
+/// the client's version first; pushes from unsupported versions are
+/// rejected as a whole. Invoice due dates must follow the user's due-date
+/// rules, and new invoices without one get the default. With a locale, localized decimals and dates are
+/// rewritten to their canonical form before validation; a push naming an
+/// unknown locale is rejected as a whole too.
+/// 
+/// Each change is applied in its own savepoint, so a failing change leaves
+/// nothing behind. In best-effort mode the other changes are still
+/// applied and the failing ones reported in `rejected`; in atomic mode
+/// the first failing change rolls the whole push back and is reported as
+/// the only rejection, with `rolled_back` set.
+/// 
+/// An update may send a JSON Patch instead of the record (see
+/// [`patch`]); it is applied to the current record, and its failing
+/// `test` operations are reported as conflicts.
+/// 
+/// Changes to tables the user syncs end-to-end encrypted are stored as
+/// pushed (see [`encryption`]); they conflict when made to an older
+/// version than the server's, and are never merged.
+/// 
+/// A dry run applies the changes exactly like a real push, then rolls
+/// everything back: the response reports what would be applied, conflict
+/// or be rejected. Every failing change is reported, even in atomic mode,
+/// where `rolled_back` says whether the real push would be rolled back.
 /// 
 /// # Arguments
 /// 
@@ -24,7 +64,8 @@ use crate::sync::types::{ConflictStrategy, PushChange, PushRequest, PushResponse
 /// # Returns
 /// 
 /// Returns a `Result<PushResponse>` containing the number of applied changes
-/// and conflicts, or an error if the operation fails.
+/// and conflicts, the server's version of each conflicted record, and the
+/// changes that were rejected, or an error if the operation fails.
 /// 
 /// # Errors
 /// 
@@ -44,65 +85,459 @@ pub async fn push_changes(
     );
     
     let device_id = request.device_id.unwrap_or_else(|| "unknown".to_string());
+    let schema_version = request.schema_version.unwrap_or(SYNC_SCHEMA_VERSION);
+    
+    if !schema::is_supported(schema_version) {
+        warn!(
+            "Rejecting push from device {} with unsupported schema version {}",
+            device_id, schema_version
+        );
+        return Ok(reject_all(
+            &request,
+            "unsupported_schema_version",
+            format!("sync schema version {} is not supported", schema_version),
+            serde_json::json!({
+                "schema_version": schema_version,
+                "supported": SUPPORTED_SCHEMA_VERSIONS,
+            }),
+        ));
+    }
+    
+    let locale = match request.locale.as_deref().map(Locale::parse).transpose() {
+        Ok(locale) => locale,
+        Err(e) => {
+            warn!("Rejecting push from device {} with unsupported locale: {}", device_id, e);
+            return Ok(reject_all(
+                &request,
+                "unsupported_locale",
+                e,
+                serde_json::json!({ "locale": request.locale }),
+            ));
+        }
+    };
+    
+    let due_date_rules = DueDateRules::load(pool, user_id).await?;
+    let encrypted_tables = encryption::encrypted_tables(pool, user_id).await?;
+    let context = PushContext {
+        due_date_rules: &due_date_rules,
+        field_mode: FieldMode::from_env(),
+        encrypted_tables: &encrypted_tables,
+    };
+    
     let mut applied_count = 0;
     let mut conflict_count = 0;
     let mut conflicted_ids = Vec::new();
+    let mut conflict_versions = Vec::new();
+    let mut rejected = Vec::new();
+    let mut changed_projects = Vec::new();
+    let mut changed_shared_projects = Vec::new();
     
     // Start a transaction for atomicity
     let mut tx = pool.begin().await?;
     
-    for change in request.changes {
-        match apply_change(
-            &mut tx,
+    // Attribute invoice changes in the history to this device
+    let audit = AuditContext {
+        device_id: Some(device_id.clone()),
+        source: AuditSource::Sync,
+        ..current_audit_context().unwrap_or_default()
+    };
+    apply_audit_context(&mut *tx, &audit).await?;
+    
+    let atomic = request.mode == PushMode::Atomic;
+    let dry_run = request.dry_run;
+    for mut change in request.changes {
+        // A failing change is undone on its own, without aborting the push
+        let mut savepoint = tx.begin().await?;
+        let outcome = push_change(
+            &mut savepoint,
             user_id,
-            &change,
+            &mut change,
+            locale.as_ref(),
+            schema_version,
             &device_id,
-            ConflictStrategy::ServerWins, // Default strategy
+            &context,
         )
-        .await
-        {
-            Ok(was_conflict) => {
-                if was_conflict {
-                    conflict_count += 1;
-                    conflicted_ids.push(change.id);
+        .await?;
+        let AppliedChange { owner_id, was_conflict } = match outcome {
+            Ok(applied) => {
+                savepoint.commit().await?;
+                applied
+            }
+            Err(rejection) => {
+                savepoint.rollback().await?;
+                // A dry run goes on to report every change that would fail
+                if atomic && !dry_run {
+                    tx.rollback().await?;
                     warn!(
-                        "Conflict detected and resolved for {}:{}",
-                        change.table, change.id
+                        "Rolled back atomic push from device {}: {}:{} failed ({})",
+                        device_id, rejection.table, rejection.id, rejection.code
                     );
-                } else {
-                    applied_count += 1;
+                    return Ok(rolled_back(rejection));
                 }
+                rejected.push(rejection);
+                continue;
             }
-            Err(e) => {
-                error!(
-                    "Failed to apply change for {}:{}: {}",
-                    change.table, change.id, e
-                );
-                // Continue with other changes (transaction will rollback on final commit if needed)
+        };
+        
+        if change.table == "projects" {
+            if owner_id == user_id {
+                changed_projects.push(change.id);
+            } else {
+                changed_shared_projects.push((owner_id, change.id));
             }
         }
+        if was_conflict {
+            conflict_count += 1;
+            conflicted_ids.push(change.id);
+            warn!(
+                "Conflict detected and resolved for {}:{}",
+                change.table, change.id
+            );
+            
+            // Send back what was kept so the client isn't left
+            // showing data it believes it saved
+            let record = if context.encrypted_tables.contains(&change.table) {
+                encryption::current_record(&mut *tx, owner_id, &change.table, change.id).await?
+            } else {
+                current_record(&mut *tx, owner_id, &change.table, change.id).await?.map(|mut record| {
+                    evolution::dual_write_record(&change.table, &mut record);
+                    record
+                })
+            };
+            if let Some(record) = record {
+                conflict_versions.push(ConflictVersion {
+                    table: change.table.clone(),
+                    id: change.id,
+                    record,
+                });
+            }
+        } else {
+            applied_count += 1;
+        }
+    }
+    
+    if dry_run {
+        // Nothing the push did is kept
+        tx.rollback().await?;
+        info!(
+            "Dry-run push from device {}: {} would apply, {} would conflict, {} would be rejected",
+            device_id, applied_count, conflict_count, rejected.len()
+        );
+        return Ok(PushResponse {
+            applied: applied_count,
+            conflicts: conflict_count,
+            conflicted_ids,
+            conflict_versions,
+            rolled_back: atomic && !rejected.is_empty(),
+            rejected,
+            dry_run: true,
+            timestamp: Utc::now(),
+        });
     }
     
     // Commit the transaction
     tx.commit().await?;
     
+    // Re-embed pushed projects for the estimator
+    projects::spawn_embedding_refresh(pool.clone(), user_id, changed_projects);
+    for (owner_id, project_id) in changed_shared_projects {
+        projects::spawn_embedding_refresh(pool.clone(), owner_id, vec![project_id]);
+    }
+    
     info!(
-        "Push sync completed: {} applied, {} conflicts",
-        applied_count, conflict_count
+        "Push sync completed: {} applied, {} conflicts, {} rejected",
+        applied_count, conflict_count, rejected.len()
     );
     
     Ok(PushResponse {
         applied: applied_count,
         conflicts: conflict_count,
         conflicted_ids,
+        conflict_versions,
+        rejected,
+        rolled_back: false,
+        dry_run: false,
         timestamp: Utc::now(),
     })
 }
 
+/// Rejects every change of a push that can't be applied at all.
+fn reject_all(request: &PushRequest, code: &str, error: String, details: serde_json::Value) -> PushResponse {
+    let rejected = request
+        .changes
+        .iter()
+        .map(|change| RejectedChange {
+            table: change.table.clone(),
+            id: change.id,
+            code: code.to_string(),
+            error: error.clone(),
+            details: Some(details.clone()),
+        })
+        .collect();
+    
+    PushResponse {
+        applied: 0,
+        conflicts: 0,
+        conflicted_ids: Vec::new(),
+        conflict_versions: Vec::new(),
+        rejected,
+        rolled_back: false,
+        dry_run: request.dry_run,
+        timestamp: Utc::now(),
+    }
+}
+
+/// Reports an atomic push rolled back because of `cause`.
+fn rolled_back(cause: RejectedChange) -> PushResponse {
+    PushResponse {
+        applied: 0,
+        conflicts: 0,
+        conflicted_ids: Vec::new(),
+        conflict_versions: Vec::new(),
+        rejected: vec![cause],
+        rolled_back: true,
+        dry_run: false,
+        timestamp: Utc::now(),
+    }
+}
+
+/// A pushed change that was applied.
+struct AppliedChange {
+    /// Account whose data it changed (see [`push_owner`])
+    owner_id: Uuid,
+
+    /// Whether it conflicted with the server's version
+    was_conflict: bool,
+}
+
+/// Applies one pushed change: reads its localized values, resolves whose
+/// data it changes, applies its patch if it sends one and writes it.
+/// 
+/// # Arguments
+/// 
+/// * `tx` - Database transaction (a savepoint, undone if the change fails)
+/// * `user_id` - ID of the user pushing
+/// * `change` - The change; its data is rewritten to canonical values, and
+///   replaced by the patched record for a patch
+/// * `locale` - How the push writes localized values, if it says
+/// * `schema_version` - Sync schema version to validate the data against
+/// * `device_id` - Device ID making the change
+/// * `context` - What the change may depend on besides the record
+/// 
+/// # Returns
+/// 
+/// Returns the applied change, or the rejection to report when it can't
+/// be applied.
+/// 
+/// # Errors
+/// 
+/// Returns an error if resolving the change's owner fails.
+async fn push_change(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    change: &mut PushChange,
+    locale: Option<&Locale>,
+    schema_version: u32,
+    device_id: &str,
+    context: &PushContext<'_>,
+) -> Result<Result<AppliedChange, RejectedChange>, anyhow::Error> {
+    // Encrypted tables keep what devices push, unread and never merged.
+    // They hold only the pusher's own records: another account's record,
+    // shared or not, is never replaced by a private encrypted copy
+    if context.encrypted_tables.contains(&change.table) {
+        if let Some(table) = find_table(&change.table) {
+            if table.owner(tx, change.id).await?.is_some_and(|owner| owner != user_id) {
+                let e = AccessError::Record { table: change.table.clone(), id: change.id };
+                return Ok(Err(rejected_change(change, &anyhow::Error::new(e))));
+            }
+        }
+        return match encryption::push_encrypted(tx, user_id, change, device_id).await? {
+            Ok(was_conflict) => Ok(Ok(AppliedChange { owner_id: user_id, was_conflict })),
+            Err(e) => Ok(Err(rejected_change(change, &anyhow::Error::new(e)))),
+        };
+    }
+    if change.encrypted.is_some() {
+        let e = EncryptionError::NotEnabled { table: change.table.clone() };
+        return Ok(Err(rejected_change(change, &anyhow::Error::new(e))));
+    }
+    
+    if change.patch.is_some() && (change.deleted || change.data.is_some()) {
+        return Ok(Err(rejected_change(change, &anyhow::Error::new(PatchError::NotAnUpdate))));
+    }
+    
+    if let (Some(locale), Some(data)) = (locale, change.data.as_ref()) {
+        match schema::localize_change(schema_version, &change.table, data, locale) {
+            Ok(localized) => change.data = Some(localized),
+            Err(e) => return Ok(Err(rejected_change(change, &anyhow::Error::new(e)))),
+        }
+    }
+    let localized = match (locale, change.patch.as_mut()) {
+        (Some(locale), Some(operations)) => patch::localize_patch(schema_version, &change.table, operations, locale),
+        _ => Ok(()),
+    };
+    if let Err(e) = localized {
+        return Ok(Err(rejected_change(change, &anyhow::Error::new(e))));
+    }
+    
+    // Shared projects and clients are changed in their owner's data
+    let owner_id = match push_owner(tx, user_id, change).await? {
+        Ok(owner_id) => owner_id,
+        Err(rejection) => return Ok(Err(rejection)),
+    };
+    
+    // A patch applies to the record as its owner has it
+    let patch_conflicts = match change.patch.take() {
+        Some(operations) => match patched_record(tx, owner_id, change, &operations).await? {
+            Ok(patched) => {
+                change.data = Some(patched.record);
+                Some(patched.conflicts)
+            }
+            Err(e) => return Ok(Err(rejected_change(change, &anyhow::Error::new(e)))),
+        },
+        None => None,
+    };
+    
+    // Only the fields devices may write reach the table
+    if let (false, Some(data)) = (change.deleted, change.data.as_mut()) {
+        if let Err(e) = fields::filter_fields(schema_version, &change.table, data, context.field_mode) {
+            return Ok(Err(rejected_change(change, &anyhow::Error::new(e))));
+        }
+    }
+    
+    match apply_change(
+        tx,
+        owner_id,
+        change,
+        device_id,
+        schema_version,
+        context,
+        ConflictStrategy::ServerWins, // Default strategy
+        patch_conflicts.as_deref(),
+    )
+    .await
+    {
+        Ok(was_conflict) => Ok(Ok(AppliedChange { owner_id, was_conflict })),
+        Err(e) => {
+            error!(
+                "Failed to apply change for {}:{}: {}",
+                change.table, change.id, e
+            );
+            Ok(Err(rejected_change(change, &e)))
+        }
+    }
+}
+
+/// Applies a pushed patch to the current record.
+/// 
+/// The change's `version_vector`, if it sends one, replaces the record's.
+/// 
+/// # Returns
+/// 
+/// Returns the patched record and its conflicted fields (see
+/// [`patch::apply_patch`]), or why the patch can't be applied.
+async fn patched_record(
+    tx: &mut Transaction<'_, Postgres>,
+    owner_id: Uuid,
+    change: &PushChange,
+    operations: &[PatchOperation],
+) -> Result<Result<Patched, PatchError>, anyhow::Error> {
+    let Some(table) = find_table(&change.table) else {
+        return Ok(Err(PatchError::MissingRecord));
+    };
+    let Some(record) = table.load(&mut **tx, owner_id, change.id).await? else {
+        return Ok(Err(PatchError::MissingRecord));
+    };
+    
+    let mut patched = match patch::apply_patch(&record, operations) {
+        Ok(patched) => patched,
+        Err(e) => return Ok(Err(e)),
+    };
+    if let (Some(version_vector), Some(data)) = (&change.version_vector, patched.record.as_object_mut()) {
+        data.insert("version_vector".to_string(), version_vector.clone());
+    }
+    
+    Ok(Ok(patched))
+}
+
+/// Resolves whose data a pushed change applies to.
+/// 
+/// Projects and clients shared with the user are changed in their owner's
+/// data when the share allows editing. Only owners can delete them.
+/// 
+/// # Returns
+/// 
+/// Returns the ID of the owning account (the user's own unless shared), or
+/// the rejection to report when the share does not allow the change.
+async fn push_owner(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    change: &PushChange,
+) -> Result<Result<Uuid, RejectedChange>, anyhow::Error> {
+    let Some(entity) = ShareEntity::from_table(&change.table) else {
+        return Ok(Ok(user_id));
+    };
+    let Some(grant) = shared_grant(&mut **tx, user_id, entity, change.id).await? else {
+        return Ok(Ok(user_id));
+    };
+    
+    let error = if change.deleted {
+        "only the owner can delete a shared record"
+    } else if grant.permission < SharePermission::Edit {
+        "record is shared with you read-only"
+    } else {
+        return Ok(Ok(grant.user_id));
+    };
+    
+    Ok(Err(RejectedChange {
+        table: change.table.clone(),
+        id: change.id,
+        code: "share_forbidden".to_string(),
+        error: error.to_string(),
+        details: Some(serde_json::json!({ "permission": grant.permission })),
+    }))
+}
+
+/// Describes a change that failed to apply for the push response.
+/// 
+/// Validation errors such as schema violations, malformed line items,
+/// illegal status transitions, due dates breaking the user's rules,
+/// changes reaching another account's data, patches that can't be
+/// applied and changes that don't match the table's encryption are
+/// reported in full; other
+/// failures get a generic reason so internals are not leaked.
+fn rejected_change(change: &PushChange, e: &anyhow::Error) -> RejectedChange {
+    let (code, error, details) = if let Some(payload_error) = e.downcast_ref::<PayloadError>() {
+        (payload_error.code(), payload_error.to_string(), Some(payload_error.details()))
+    } else if let Some(line_items_error) = e.downcast_ref::<LineItemsError>() {
+        (line_items_error.code(), line_items_error.to_string(), Some(line_items_error.details()))
+    } else if let Some(status_error) = e.downcast_ref::<StatusError>() {
+        (status_error.code(), status_error.to_string(), Some(status_error.details()))
+    } else if let Some(due_date_error) = e.downcast_ref::<DueDateError>() {
+        (due_date_error.code(), due_date_error.to_string(), Some(due_date_error.details()))
+    } else if let Some(access_error) = e.downcast_ref::<AccessError>() {
+        (access_error.code(), access_error.to_string(), Some(access_error.details()))
+    } else if let Some(patch_error) = e.downcast_ref::<PatchError>() {
+        (patch_error.code(), patch_error.to_string(), Some(patch_error.details()))
+    } else if let Some(encryption_error) = e.downcast_ref::<EncryptionError>() {
+        (encryption_error.code(), encryption_error.to_string(), Some(encryption_error.details()))
+    } else {
+        ("apply_failed", "change could not be applied".to_string(), None)
+    };
+    
+    RejectedChange {
+        table: change.table.clone(),
+        id: change.id,
+        code: code.to_string(),
+        error,
+        details,
+    }
+}
+
 /// Applies a single change to the database.
 /// 
 /// Handles INSERT, UPDATE, and DELETE operations with conflict detection
-/// and resolution.
+/// and resolution; how a record is written is up to its table (see
+/// [`SyncableTable`](crate::sync::tables::SyncableTable)).
 /// 
 /// # Arguments
 /// 
@@ -110,7 +545,12 @@ pub async fn push_changes(
 /// * `user_id` - ID of the user
 /// * `change` - The change to apply
 /// * `device_id` - Device ID making the change
+/// * `schema_version` - Sync schema version to validate the data against
+/// * `context` - What the change may depend on besides the record
 /// * `strategy` - Conflict resolution strategy
+/// * `patch_conflicts` - For a patch, the fields it conflicted on; the
+///   server already kept its values there, so the record isn't checked
+///   for conflicts again
 /// 
 /// # Returns
 /// 
@@ -121,13 +561,24 @@ async fn apply_change(
     user_id: Uuid,
     change: &PushChange,
     device_id: &str,
+    schema_version: u32,
+    context: &PushContext<'_>,
     strategy: ConflictStrategy,
+    patch_conflicts: Option<&[String]>,
 ) -> Result<bool, anyhow::Error> {
+    let table = find_table(&change.table)
+        .ok_or_else(|| anyhow::anyhow!("Table is not synced: {}", change.table))?;
+    
+    // Nothing is written for a change reaching into another account
+    table
+        .authorize(&mut **tx, user_id, change.id, change.data.as_ref().filter(|_| !change.deleted))
+        .await?;
+    
     let operation = if change.deleted {
         SyncOperation::Delete
     } else if change.data.is_some() {
         // Check if record exists to determine INSERT vs UPDATE
-        let exists = record_exists(tx, &change.table, change.id, user_id).await?;
+        let exists = table.exists(&mut **tx, user_id, change.id).await?;
         if exists {
             SyncOperation::Update
         } else {
@@ -137,6 +588,23 @@ async fn apply_change(
         return Err(anyhow::anyhow!("Change has no data and is not a delete"));
     };
     
+    // Reject malformed records before any of their fields are parsed
+    if let Some(ref data) = change.data {
+        schema::validate_change(schema_version, &change.table, operation, data)?;
+    }
+    
+    // Read columns older app versions still send under their previous names
+    let upgraded = change
+        .data
+        .clone()
+        .map(|data| evolution::upgrade_push(schema_version, &change.table, operation, data))
+        .transpose()?
+        .map(|data| PushChange {
+            data: Some(data),
+            ..change.clone()
+        });
+    let change = upgraded.as_ref().unwrap_or(change);
+    
     // Extract last_modified and version_vector from data if present
     let (client_last_modified, client_version_vector) = if let Some(ref data) = change.data {
         let last_mod = data.get("last_modified")
@@ -151,30 +619,44 @@ async fn apply_change(
     };
     
     // Check for conflicts (only for UPDATE operations)
-    let has_conf = if operation == SyncOperation::Update {
-        has_conflict(
-            tx,
-            user_id,
-            &change.table,
-            change.id,
-            client_version_vector.as_ref(),
-            client_last_modified,
-        )
-        .await?
-    } else {
-        false
+    let has_conf = match patch_conflicts {
+        Some(fields) => !fields.is_empty(),
+        None if operation == SyncOperation::Update => {
+            has_conflict(
+                &mut **tx,
+                user_id,
+                &change.table,
+                change.id,
+                client_version_vector.as_ref(),
+                client_last_modified,
+            )
+            .await?
+        }
+        None => false,
+    };
+    
+    // Keep the record as it was with the change, so both sides of an
+    // update (or the deleted record) can be reviewed later
+    let old_data = match operation {
+        SyncOperation::Insert => None,
+        SyncOperation::Update | SyncOperation::Delete => table.load(&mut **tx, user_id, change.id).await?,
     };
     
     // Apply the change based on operation type
     match operation {
         SyncOperation::Insert => {
-            apply_insert(tx, user_id, change, device_id).await?;
+            let data = change.data.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("INSERT operation requires data")
+            })?;
+            table
+                .insert(tx, context, user_id, change.id, data, change.version_vector.as_ref())
+                .await?;
         }
         SyncOperation::Update => {
-            if has_conf {
+            if has_conf && patch_conflicts.is_none() {
                 // Resolve conflict
                 let resolved_data = resolve_conflict(
-                    tx,
+                    &mut **tx,
                     user_id,
                     &change.table,
                     change.id,
@@ -184,22 +666,16 @@ async fn apply_change(
                 .await?;
                 
                 // Apply resolved data
-                apply_update(tx, user_id, change.id, &change.table, &resolved_data, device_id).await?;
+                table.update(tx, context, user_id, change.id, &resolved_data).await?;
             } else {
                 // No conflict, apply client data
-                apply_update(
-                    tx,
-                    user_id,
-                    change.id,
-                    &change.table,
-                    change.data.as_ref().unwrap(),
-                    device_id,
-                )
-                .await?;
+                table
+                    .update(tx, context, user_id, change.id, change.data.as_ref().unwrap())
+                    .await?;
             }
         }
         SyncOperation::Delete => {
-            apply_delete(tx, user_id, change.id, &change.table, device_id).await?;
+            table.delete(tx, user_id, change.id).await?;
         }
     }
     
@@ -210,226 +686,36 @@ async fn apply_change(
         &change.table,
         change.id,
         operation,
+        old_data.as_ref(),
         change.data.as_ref(),
         device_id,
         change.version_vector.as_ref(),
+        has_conf.then_some(strategy),
     )
     .await?;
     
     Ok(has_conf)
 }
 
-/// Checks if a record exists in the database.
-async fn record_exists(
-    tx: &mut Transaction<'_, Postgres>,
-    table_name: &str,
-    record_id: Uuid,
-    user_id: Uuid,
-) -> Result<bool, anyhow::Error> {
-    match table_name {
-        "invoices" => {
-            let result = sqlx::query!(
-                "SELECT 1 FROM invoices WHERE id = $1 AND user_id = $2 AND is_deleted = false",
-                record_id,
-                user_id
-            )
-            .fetch_optional(&mut **tx)
-            .await?;
-            Ok(result.is_some())
-        }
-        _ => {
-            warn!("Record existence check not implemented for table: {}", table_name);
-            Ok(false)
-        }
-    }
-}
-
-/// Applies an INSERT operation.
-async fn apply_insert(
-    tx: &mut Transaction<'_, Postgres>,
-    user_id: Uuid,
-    change: &PushChange,
-    device_id: &str,
-) -> Result<(), anyhow::Error> {
-    let data = change.data.as_ref().ok_or_else(|| {
-        anyhow::anyhow!("INSERT operation requires data")
-    })?;
-    
-    match change.table.as_str() {
-        "invoices" => {
-            let invoice_number = data.get("invoice_number")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Missing invoice_number"))?;
-            
-            let client_name = data.get("client_name")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Missing client_name"))?;
-            
-            let amount = data.get("amount")
-                .and_then(|v| {
-                    if let Some(s) = v.as_str() {
-                        rust_decimal::Decimal::from_str_exact(s).ok()
-                    } else if let Some(n) = v.as_f64() {
-                        rust_decimal::Decimal::try_from(n).ok()
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow::anyhow!("Invalid amount"))?;
-            
-            let currency = data.get("currency")
-                .and_then(|v| v.as_str())
-                .unwrap_or("USD");
-            
-            let status = data.get("status")
-                .and_then(|v| v.as_str())
-                .unwrap_or("draft");
-            
-            sqlx::query!(
-                r#"
-                INSERT INTO invoices (
-                    id, user_id, invoice_number, client_name, client_email,
-                    amount, currency, status, due_date, issue_date,
-                    description, line_items, metadata, last_modified, version_vector
-                ) VALUES (
-                    $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, NOW(), $14
-                )
-                "#,
-                change.id,
-                user_id,
-                invoice_number,
-                client_name,
-                data.get("client_email").and_then(|v| v.as_str()),
-                amount,
-                currency,
-                status,
-                data.get("due_date").and_then(|v| v.as_str()).and_then(|s| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()),
-                data.get("issue_date").and_then(|v| v.as_str()).and_then(|s| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()).or_else(|| Some(chrono::Utc::now().date_naive())),
-                data.get("description").and_then(|v| v.as_str()),
-                data.get("line_items"),
-                data.get("metadata"),
-                change.version_vector.as_ref(),
-            )
-            .execute(&mut **tx)
-            .await?;
-        }
-        _ => {
-            return Err(anyhow::anyhow!("INSERT not implemented for table: {}", change.table));
-        }
-    }
-    
-    Ok(())
-}
-
-/// Applies an UPDATE operation (upsert logic).
-async fn apply_update(
-    tx: &mut Transaction<'_, Postgres>,
-    user_id: Uuid,
-    record_id: Uuid,
-    table_name: &str,
-    data: &Value,
-    device_id: &str,
-) -> Result<(), anyhow::Error> {
-    match table_name {
-        "invoices" => {
-            let amount = data.get("amount")
-                .and_then(|v| {
-                    if let Some(s) = v.as_str() {
-                        rust_decimal::Decimal::from_str_exact(s).ok()
-                    } else if let Some(n) = v.as_f64() {
-                        rust_decimal::Decimal::try_from(n).ok()
-                    } else {
-                        None
-                    }
-                });
-            
-            sqlx::query!(
-                r#"
-                UPDATE invoices
-                SET
-                    invoice_number = COALESCE($3, invoice_number),
-                    client_name = COALESCE($4, client_name),
-                    client_email = $5,
-                    amount = COALESCE($6, amount),
-                    currency = COALESCE($7, currency),
-                    status = COALESCE($8, status),
-                    due_date = $9,
-                    issue_date = COALESCE($10, issue_date),
-                    description = $11,
-                    line_items = $12,
-                    metadata = $13,
-                    last_modified = NOW(),
-                    version_vector = $14,
-                    updated_at = NOW()
-                WHERE id = $1 AND user_id = $2 AND is_deleted = false
-                "#,
-                record_id,
-                user_id,
-                data.get("invoice_number").and_then(|v| v.as_str()),
-                data.get("client_name").and_then(|v| v.as_str()),
-                data.get("client_email").and_then(|v| v.as_str()),
-                amount,
-                data.get("currency").and_then(|v| v.as_str()),
-                data.get("status").and_then(|v| v.as_str()),
-                data.get("due_date").and_then(|v| v.as_str()).and_then(|s| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()),
-                data.get("issue_date").and_then(|v| v.as_str()).and_then(|s| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()),
-                data.get("description").and_then(|v| v.as_str()),
-                data.get("line_items"),
-                data.get("metadata"),
-                data.get("version_vector"),
-            )
-            .execute(&mut **tx)
-            .await?;
-        }
-        _ => {
-            return Err(anyhow::anyhow!("UPDATE not implemented for table: {}", table_name));
-        }
-    }
-    
-    Ok(())
-}
-
-/// Applies a DELETE operation (soft delete).
-async fn apply_delete(
-    tx: &mut Transaction<'_, Postgres>,
-    user_id: Uuid,
-    record_id: Uuid,
-    table_name: &str,
-    device_id: &str,
-) -> Result<(), anyhow::Error> {
-    match table_name {
-        "invoices" => {
-            sqlx::query!(
-                r#"
-                UPDATE invoices
-                SET is_deleted = true, last_modified = NOW(), updated_at = NOW()
-                WHERE id = $1 AND user_id = $2
-                "#,
-                record_id,
-                user_id
-            )
-            .execute(&mut **tx)
-            .await?;
-        }
-        _ => {
-            return Err(anyhow::anyhow!("DELETE not implemented for table: {}", table_name));
-        }
-    }
-    
-    Ok(())
-}
-
 /// Records a change in the sync_changes table.
+///
+/// Updates and deletes carry the record as it was before the change in
+/// `old_data`. Changes that conflicted are flagged with the strategy that
+/// resolved them, so support can find them later.
 async fn record_sync_change(
     tx: &mut Transaction<'_, Postgres>,
     user_id: Uuid,
     table_name: &str,
     record_id: Uuid,
     operation: SyncOperation,
+    old_data: Option<&Value>,
     new_data: Option<&Value>,
     device_id: &str,
     version_vector: Option<&Value>,
+    conflict: Option<ConflictStrategy>,
 ) -> Result<(), anyhow::Error> {
+    let resolution = conflict.map(|strategy| serde_json::json!({ "strategy": strategy }));
+    
     let operation_str = match operation {
         SyncOperation::Insert => "INSERT",
         SyncOperation::Update => "UPDATE",
@@ -440,18 +726,22 @@ async fn record_sync_change(
         r#"
         INSERT INTO sync_changes (
             user_id, table_name, record_id, operation,
-            new_data, device_id, vector_clock, is_applied
+            old_data, new_data, device_id, vector_clock, is_applied,
+            is_conflict, conflict_resolution
         ) VALUES (
-            $1, $2, $3, $4, $5, $6, $7, true
+            $1, $2, $3, $4, $5, $6, $7, $8, true, $9, $10
         )
         "#,
         user_id,
         table_name,
         record_id,
         operation_str,
+        old_data,
         new_data,
         device_id,
         version_vector,
+        conflict.is_some(),
+        resolution,
     )
     .execute(&mut **tx)
     .await?;
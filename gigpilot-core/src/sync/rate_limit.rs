@@ -0,0 +1,250 @@
+//! Rate limits on sync requests.
+//!
+//! A client stuck in a retry loop can hammer pulls and pushes. Every sync
+//! request takes a token from its user's bucket and, when it names a
+//! device, from that device's bucket too; buckets refill at a steady rate
+//! up to a minute's worth. Requests finding a bucket empty get `429` with
+//! `Retry-After`.
+//!
+//! Buckets live in memory, so each API process limits the requests it
+//! serves on its own.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Default sync requests per minute for one device.
+pub const DEFAULT_DEVICE_REQUESTS_PER_MINUTE: u32 = 60;
+
+/// Default sync requests per minute for one user, across their devices.
+pub const DEFAULT_USER_REQUESTS_PER_MINUTE: u32 = 240;
+
+/// Buckets kept before idle (full) ones are dropped.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// A refilling allowance of requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBucket {
+    /// Requests that can be made right away
+    tokens: f64,
+
+    /// When `tokens` was last brought up to date
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket for `per_minute` requests.
+    pub fn full(per_minute: u32, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(per_minute),
+            updated_at: now,
+        }
+    }
+
+    /// Adds the tokens earned since the last update.
+    fn refill(&mut self, per_minute: u32, now: Instant) {
+        let earned = now.saturating_duration_since(self.updated_at).as_secs_f64() * f64::from(per_minute) / 60.0;
+        self.tokens = (self.tokens + earned).min(f64::from(per_minute));
+        self.updated_at = now;
+    }
+
+    /// How long until a token is available (zero if one is).
+    fn wait(&self, per_minute: u32) -> Duration {
+        if self.tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((1.0 - self.tokens) * 60.0 / f64::from(per_minute))
+    }
+
+    /// Whether the bucket is full again, so forgetting it changes nothing.
+    fn is_full(&self, per_minute: u32, now: Instant) -> bool {
+        let mut refilled = *self;
+        refilled.refill(per_minute, now);
+        refilled.tokens >= f64::from(per_minute)
+    }
+}
+
+/// Whose requests a bucket counts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BucketKey {
+    User(Uuid),
+    Device(Uuid, String),
+}
+
+/// Per-user and per-device limits on sync requests.
+///
+/// Cheap to clone; clones share the buckets.
+#[derive(Debug, Clone)]
+pub struct SyncRateLimiter {
+    /// Requests per minute allowed for one device
+    device_per_minute: u32,
+
+    /// Requests per minute allowed for one user
+    user_per_minute: u32,
+
+    buckets: Arc<Mutex<HashMap<BucketKey, TokenBucket>>>,
+}
+
+impl SyncRateLimiter {
+    /// Creates a limiter with explicit rates (each at least 1).
+    pub fn new(device_per_minute: u32, user_per_minute: u32) -> Self {
+        Self {
+            device_per_minute: device_per_minute.max(1),
+            user_per_minute: user_per_minute.max(1),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Creates a limiter with the rates from `SYNC_DEVICE_REQUESTS_PER_MINUTE`
+    /// and `SYNC_USER_REQUESTS_PER_MINUTE` (defaults
+    /// [`DEFAULT_DEVICE_REQUESTS_PER_MINUTE`] and
+    /// [`DEFAULT_USER_REQUESTS_PER_MINUTE`]).
+    pub fn from_env() -> Self {
+        let rate = |name: &str, default: u32| {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.parse::<u32>().ok())
+                .filter(|rate| *rate > 0)
+                .unwrap_or(default)
+        };
+        Self::new(
+            rate("SYNC_DEVICE_REQUESTS_PER_MINUTE", DEFAULT_DEVICE_REQUESTS_PER_MINUTE),
+            rate("SYNC_USER_REQUESTS_PER_MINUTE", DEFAULT_USER_REQUESTS_PER_MINUTE),
+        )
+    }
+
+    /// Takes a token for a sync request.
+    ///
+    /// The request counts against the user and, when it names one, the
+    /// device; a refused request takes nothing from either.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user making the request
+    /// * `device_id` - The device making it, if it said
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the request may go ahead, or how long to wait
+    /// before retrying.
+    pub fn check(&self, user_id: Uuid, device_id: Option<&str>, now: Instant) -> Result<(), Duration> {
+        let mut keys = vec![(BucketKey::User(user_id), self.user_per_minute)];
+        if let Some(device_id) = device_id {
+            keys.push((BucketKey::Device(user_id, device_id.to_string()), self.device_per_minute));
+        }
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if buckets.len() >= MAX_TRACKED_BUCKETS {
+            let (device_per_minute, user_per_minute) = (self.device_per_minute, self.user_per_minute);
+            buckets.retain(|key, bucket| {
+                let per_minute = match key {
+                    BucketKey::User(_) => user_per_minute,
+                    BucketKey::Device(..) => device_per_minute,
+                };
+                !bucket.is_full(per_minute, now)
+            });
+        }
+
+        let mut wait = Duration::ZERO;
+        for (key, per_minute) in &keys {
+            let bucket = buckets
+                .entry(key.clone())
+                .or_insert_with(|| TokenBucket::full(*per_minute, now));
+            bucket.refill(*per_minute, now);
+            wait = wait.max(bucket.wait(*per_minute));
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        for (key, _) in &keys {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Seconds to send in `Retry-After` (rounded up, at least 1).
+pub fn retry_after_seconds(wait: Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+/// Response refusing a rate-limited sync request.
+///
+/// `429` with `Retry-After` and `{ "error", "code": "rate_limited",
+/// "retry_after" }`.
+pub fn rate_limited(wait: Duration) -> Response {
+    let retry_after = retry_after_seconds(wait).max(1);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        Json(json!({
+            "error": "too many sync requests; try again later",
+            "code": "rate_limited",
+            "retry_after": retry_after,
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_runs_out_and_refills() {
+        let limiter = SyncRateLimiter::new(3, 100);
+        let user_id = Uuid::new_v4();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(user_id, Some("phone"), now), Ok(()));
+        }
+        let wait = limiter.check(user_id, Some("phone"), now).unwrap_err();
+        assert_eq!(retry_after_seconds(wait), 20);
+
+        // Another device of the user has its own allowance
+        assert_eq!(limiter.check(user_id, Some("laptop"), now), Ok(()));
+
+        // A token comes back every 20 seconds at 3 per minute
+        assert_eq!(limiter.check(user_id, Some("phone"), now + Duration::from_secs(20)), Ok(()));
+        assert!(limiter.check(user_id, Some("phone"), now + Duration::from_secs(20)).is_err());
+    }
+
+    #[test]
+    fn test_user_limit_spans_devices() {
+        let limiter = SyncRateLimiter::new(100, 2);
+        let user_id = Uuid::new_v4();
+        let now = Instant::now();
+
+        assert_eq!(limiter.check(user_id, Some("phone"), now), Ok(()));
+        assert_eq!(limiter.check(user_id, Some("laptop"), now), Ok(()));
+        assert!(limiter.check(user_id, Some("tablet"), now).is_err());
+        assert!(limiter.check(user_id, None, now).is_err());
+
+        // Other users are unaffected
+        assert_eq!(limiter.check(Uuid::new_v4(), Some("phone"), now), Ok(()));
+    }
+
+    #[test]
+    fn test_refused_request_takes_nothing() {
+        let limiter = SyncRateLimiter::new(1, 2);
+        let user_id = Uuid::new_v4();
+        let now = Instant::now();
+
+        assert_eq!(limiter.check(user_id, Some("phone"), now), Ok(()));
+        // The device is out; the user's second token is left for others
+        assert!(limiter.check(user_id, Some("phone"), now).is_err());
+        assert_eq!(limiter.check(user_id, Some("laptop"), now), Ok(()));
+    }
+}
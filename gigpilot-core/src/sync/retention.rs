@@ -0,0 +1,188 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// Default number of days sync changes are kept before being pruned.
+pub const DEFAULT_RETENTION_DAYS: i64 = 90;
+
+/// Path clients should fetch a full snapshot from when a resync is required.
+pub const SNAPSHOT_PATH: &str = "/sync/snapshot";
+
+/// Returns the configured change-log retention in days.
+///
+/// Read from `SYNC_RETENTION_DAYS`, falling back to [`DEFAULT_RETENTION_DAYS`].
+pub fn retention_days() -> i64 {
+    std::env::var("SYNC_RETENTION_DAYS")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Computes the retention horizon relative to `now`.
+///
+/// Changes older than the horizon are eligible for pruning.
+pub fn retention_horizon(now: DateTime<Utc>) -> DateTime<Utc> {
+    now - Duration::days(retention_days())
+}
+
+/// Decides whether a device must discard its local state and resync.
+///
+/// # Arguments
+///
+/// * `last_pulled_at` - The device's last successful pull (None for first sync)
+/// * `pruned_before` - The user's pruning horizon (None if nothing was pruned)
+///
+/// # Returns
+///
+/// Returns `true` if incremental changes since `last_pulled_at` are no longer
+/// complete. A first sync is also incomplete once any history has been pruned.
+pub fn requires_full_resync(
+    last_pulled_at: Option<DateTime<Utc>>,
+    pruned_before: Option<DateTime<Utc>>,
+) -> bool {
+    match (last_pulled_at, pruned_before) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(last_pulled), Some(horizon)) => last_pulled < horizon,
+    }
+}
+
+/// Decides whether a device pulling by sequence number must resync.
+///
+/// # Arguments
+///
+/// * `last_sequence_number` - The cursor returned by the device's last pull
+/// * `pruned_sequence` - The highest sequence number pruned for the user
+///   (None if nothing was pruned since sequence numbers were recorded)
+///
+/// # Returns
+///
+/// Returns `true` if changes after the cursor have been pruned.
+pub fn requires_full_resync_after_sequence(last_sequence_number: i64, pruned_sequence: Option<i64>) -> bool {
+    pruned_sequence.is_some_and(|pruned| last_sequence_number < pruned)
+}
+
+/// Loads the pruning horizon for a user, if any changes have been pruned.
+pub async fn pruned_before(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let horizon = sqlx::query_scalar::<_, DateTime<Utc>>(
+        "SELECT pruned_before FROM sync_retention WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(horizon)
+}
+
+/// Loads the highest sequence number pruned for a user, if any.
+pub async fn pruned_sequence(pool: &PgPool, user_id: Uuid) -> Result<Option<i64>, anyhow::Error> {
+    let pruned = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT pruned_sequence FROM sync_retention WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(pruned.flatten())
+}
+
+/// Deletes applied sync changes older than `horizon` and records the new
+/// pruning horizon for every affected user.
+///
+/// Changes that a device seen since `horizon` hasn't pulled yet are kept,
+/// so devices in regular use don't have to resync in full. The recorded
+/// horizon is the newest change actually deleted, so older clients whose
+/// last pull is past it aren't sent to resync either.
+///
+/// # Returns
+///
+/// Returns the number of sync changes deleted.
+pub async fn prune_expired_changes(
+    pool: &PgPool,
+    horizon: DateTime<Utc>,
+) -> Result<u64, anyhow::Error> {
+    let deleted = sqlx::query_scalar::<_, i64>(
+        r#"
+        WITH pruned AS (
+            DELETE FROM sync_changes c
+            WHERE c.change_timestamp < $1
+                AND c.is_applied = true
+                AND NOT EXISTS (
+                    SELECT 1 FROM sync_devices d
+                    WHERE d.user_id = c.user_id
+                        AND d.last_seen_at >= $1
+                        AND (
+                            c.sequence_number > d.last_pulled_sequence
+                            OR (d.last_pulled_sequence IS NULL AND c.change_timestamp > d.last_pulled_at)
+                        )
+                )
+            RETURNING c.user_id, c.change_timestamp, c.sequence_number
+        ),
+        recorded AS (
+            INSERT INTO sync_retention (user_id, pruned_before, pruned_sequence)
+            SELECT user_id, MAX(change_timestamp), MAX(sequence_number)
+            FROM pruned
+            GROUP BY user_id
+            ON CONFLICT (user_id) DO UPDATE
+                SET pruned_before = GREATEST(sync_retention.pruned_before, EXCLUDED.pruned_before),
+                    pruned_sequence = GREATEST(sync_retention.pruned_sequence, EXCLUDED.pruned_sequence)
+        )
+        SELECT COUNT(*) FROM pruned
+        "#,
+    )
+    .bind(horizon)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(deleted as u64)
+}
+
+/// Spawns a background task that prunes expired sync changes once a day.
+pub fn spawn_pruner(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            let horizon = retention_horizon(Utc::now());
+            match prune_expired_changes(&pool, horizon).await {
+                Ok(deleted) => info!("Pruned {} sync changes older than {}", deleted, horizon),
+                Err(e) => error!("Sync change pruning failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_pruning_never_requires_resync() {
+        assert!(!requires_full_resync(None, None));
+        assert!(!requires_full_resync(Some(Utc::now() - Duration::days(365)), None));
+    }
+
+    #[test]
+    fn test_stale_device_requires_resync() {
+        let horizon = Utc::now() - Duration::days(90);
+        assert!(requires_full_resync(Some(horizon - Duration::days(1)), Some(horizon)));
+        assert!(!requires_full_resync(Some(horizon + Duration::days(1)), Some(horizon)));
+    }
+
+    #[test]
+    fn test_first_sync_after_pruning_requires_resync() {
+        assert!(requires_full_resync(None, Some(Utc::now())));
+    }
+
+    #[test]
+    fn test_cursor_before_pruned_sequence_requires_resync() {
+        assert!(!requires_full_resync_after_sequence(0, None));
+        assert!(requires_full_resync_after_sequence(41, Some(42)));
+        assert!(!requires_full_resync_after_sequence(42, Some(42)));
+    }
+}
@@ -0,0 +1,462 @@
+//! JSON Schemas for pushed sync records.
+//!
+//! Every `PushChange.data` is validated against its table's schema before
+//! it is applied, so malformed rows are rejected with field-level errors
+//! instead of being half-parsed (e.g. an unparsable `due_date` silently
+//! becoming `NULL`). Schemas live in `schemas/sync/v<N>/<table>.json` and
+//! are versioned together with the sync protocol: clients send the version
+//! they were built against in `PushRequest.schema_version`.
+//!
+//! Pushes may also name a locale (`PushRequest.locale`); localized decimals
+//! and dates are then rewritten to their canonical form with
+//! [`localize_change`] before validation.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use jsonschema::{Draft, JSONSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::locale::{parse_date, parse_decimal, Locale};
+use crate::models::sync_change::SyncOperation;
+
+/// Current sync schema version.
+pub const SYNC_SCHEMA_VERSION: u32 = 1;
+
+/// Schema versions the server still accepts pushes for.
+pub const SUPPORTED_SCHEMA_VERSIONS: &[u32] = &[1];
+
+/// Raw schema documents of version 1, by table.
+const V1_SCHEMAS: &[(&str, &str)] = &[
+    ("invoices", include_str!("../../schemas/sync/v1/invoices.json")),
+    ("estimates", include_str!("../../schemas/sync/v1/estimates.json")),
+    ("clients", include_str!("../../schemas/sync/v1/clients.json")),
+    ("projects", include_str!("../../schemas/sync/v1/projects.json")),
+    ("time_entries", include_str!("../../schemas/sync/v1/time_entries.json")),
+    ("user_settings", include_str!("../../schemas/sync/v1/user_settings.json")),
+];
+
+/// Compiled schemas for one table.
+struct TableSchema {
+    /// The schema document, walked to find decimal and date fields
+    document: Value,
+
+    /// The full schema, including the fields an insert must carry
+    insert: JSONSchema,
+
+    /// The schema without its top-level `required` and `anyOf`, since
+    /// updates may send any subset of fields
+    update: JSONSchema,
+}
+
+/// A single field that failed validation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// JSON pointer to the offending value (empty for the record itself)
+    pub path: String,
+
+    /// What is wrong with it
+    pub error: String,
+}
+
+/// A pushed record that does not match its table's schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadError {
+    /// Table the record was pushed to
+    pub table: String,
+
+    /// Every field that failed validation
+    pub fields: Vec<FieldError>,
+}
+
+impl PayloadError {
+    /// Machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        "invalid_payload"
+    }
+
+    /// The failing fields, for the push response.
+    pub fn details(&self) -> Value {
+        json!({ "fields": self.fields })
+    }
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} record does not match the sync schema ({} invalid field(s))",
+            self.table,
+            self.fields.len()
+        )
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+/// Whether pushes built against `version` are accepted.
+pub fn is_supported(version: u32) -> bool {
+    SUPPORTED_SCHEMA_VERSIONS.contains(&version)
+}
+
+/// Compiles a schema document, panicking on the (bundled) schema being invalid.
+fn compile(table: &str, schema: &Value) -> JSONSchema {
+    JSONSchema::options()
+        .with_draft(Draft::Draft7)
+        .should_validate_formats(true)
+        .compile(schema)
+        .unwrap_or_else(|e| panic!("invalid sync schema for {}: {}", table, e))
+}
+
+/// Compiles the bundled schemas of a version.
+fn compile_all(raw: &[(&'static str, &str)]) -> HashMap<&'static str, TableSchema> {
+    raw.iter()
+        .map(|(table, source)| {
+            let schema: Value = serde_json::from_str(source)
+                .unwrap_or_else(|e| panic!("invalid sync schema JSON for {}: {}", table, e));
+
+            let mut partial = schema.clone();
+            if let Some(object) = partial.as_object_mut() {
+                object.remove("required");
+                object.remove("anyOf");
+            }
+
+            let compiled = TableSchema {
+                document: schema.clone(),
+                insert: compile(table, &schema),
+                update: compile(table, &partial),
+            };
+            (*table, compiled)
+        })
+        .collect()
+}
+
+/// Compiled schemas of a version, or `None` if it is not supported.
+fn schemas(version: u32) -> Option<&'static HashMap<&'static str, TableSchema>> {
+    static V1: OnceLock<HashMap<&'static str, TableSchema>> = OnceLock::new();
+
+    match version {
+        1 => Some(V1.get_or_init(|| compile_all(V1_SCHEMAS))),
+        _ => None,
+    }
+}
+
+/// Whether `version` has a schema for `table`.
+pub fn has_schema(version: u32, table: &str) -> bool {
+    schemas(version).map(|schemas| schemas.contains_key(table)).unwrap_or(false)
+}
+
+/// Whether `version`'s schema for `table` lists `field` among the fields
+/// devices may push.
+///
+/// # Returns
+///
+/// Returns `None` if the version has no schema for the table.
+pub fn has_field(version: u32, table: &str, field: &str) -> Option<bool> {
+    let schema = schemas(version)?.get(table)?;
+    let properties = schema.document.get("properties").and_then(|p| p.as_object());
+    Some(properties.map(|p| p.contains_key(field)).unwrap_or(false))
+}
+
+/// Validates a pushed record against its table's schema.
+///
+/// Tables without a schema are not checked here; applying them fails later
+/// if the table is not synced at all. Deletes carry no data to validate.
+///
+/// # Arguments
+///
+/// * `version` - Sync schema version the client pushed with (must be supported)
+/// * `table` - Table the change targets
+/// * `operation` - Whether the record is inserted or updated
+/// * `data` - The pushed record
+///
+/// # Errors
+///
+/// Returns a `PayloadError` listing every invalid field.
+pub fn validate_change(
+    version: u32,
+    table: &str,
+    operation: SyncOperation,
+    data: &Value,
+) -> Result<(), PayloadError> {
+    let Some(schema) = schemas(version).and_then(|schemas| schemas.get(table)) else {
+        return Ok(());
+    };
+
+    let compiled = match operation {
+        SyncOperation::Insert => &schema.insert,
+        SyncOperation::Update => &schema.update,
+        SyncOperation::Delete => return Ok(()),
+    };
+
+    compiled.validate(data).map_err(|errors| PayloadError {
+        table: table.to_string(),
+        fields: errors
+            .map(|e| FieldError {
+                path: e.instance_path.to_string(),
+                error: e.to_string(),
+            })
+            .collect(),
+    })
+}
+
+/// Rewrites localized decimals and dates of a pushed record.
+///
+/// String values of fields referencing the schema's `decimal` definition
+/// are read with [`parse_decimal`] and dates (`format: date`) with
+/// [`parse_date`], both following the locale, and replaced by their
+/// canonical form (`1234.56`, `2024-03-05`). Anything else is left as it
+/// is for [`validate_change`] to check.
+///
+/// # Arguments
+///
+/// * `version` - Sync schema version the client pushed with (must be supported)
+/// * `table` - Table the change targets
+/// * `data` - The pushed record
+/// * `locale` - Convention the record's values follow
+///
+/// # Returns
+///
+/// Returns the record with canonical decimals and dates.
+///
+/// # Errors
+///
+/// Returns a `PayloadError` listing every value that can't be read in the
+/// locale.
+pub fn localize_change(version: u32, table: &str, data: &Value, locale: &Locale) -> Result<Value, PayloadError> {
+    let Some(schema) = schemas(version).and_then(|schemas| schemas.get(table)) else {
+        return Ok(data.clone());
+    };
+
+    let mut localized = data.clone();
+    let mut fields = Vec::new();
+    localize_value(&schema.document, &schema.document, &mut localized, "", locale, &mut fields);
+
+    if fields.is_empty() {
+        Ok(localized)
+    } else {
+        Err(PayloadError {
+            table: table.to_string(),
+            fields,
+        })
+    }
+}
+
+/// Localizes a value against a schema node, recording unreadable values.
+fn localize_value(
+    document: &Value,
+    node: &Value,
+    value: &mut Value,
+    path: &str,
+    locale: &Locale,
+    errors: &mut Vec<FieldError>,
+) {
+    if let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+        let Some(name) = reference.strip_prefix("#/definitions/") else {
+            return;
+        };
+        if let (Some(text), "decimal") = (value.as_str(), name) {
+            match parse_decimal(text, Some(locale)) {
+                Ok(amount) => *value = Value::String(amount.to_string()),
+                Err(error) => errors.push(FieldError { path: path.to_string(), error }),
+            }
+            return;
+        }
+        if let Some(definition) = document.get("definitions").and_then(|definitions| definitions.get(name)) {
+            localize_value(document, definition, value, path, locale, errors);
+        }
+        return;
+    }
+
+    if let (Some(text), Some("date")) = (value.as_str(), node.get("format").and_then(Value::as_str)) {
+        match parse_date(text, Some(locale)) {
+            Ok(date) => *value = Value::String(date.to_string()),
+            Err(error) => errors.push(FieldError { path: path.to_string(), error }),
+        }
+        return;
+    }
+
+    // Branches of an `anyOf` only differ in type here, so the one matching
+    // the value is the only one that changes it
+    if let Some(branches) = node.get("anyOf").and_then(Value::as_array) {
+        for branch in branches {
+            let before = errors.len();
+            localize_value(document, branch, value, path, locale, errors);
+            if errors.len() != before {
+                return;
+            }
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(properties) = node.get("properties").and_then(Value::as_object) {
+                for (key, field) in object.iter_mut() {
+                    if let Some(property) = properties.get(key) {
+                        let field_path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                        localize_value(document, property, field, &field_path, locale, errors);
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item) = node.get("items") {
+                for (index, element) in items.iter_mut().enumerate() {
+                    localize_value(document, item, element, &format!("{}/{}", path, index), locale, errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice() -> Value {
+        json!({
+            "invoice_number": "INV-001",
+            "client_name": "Acme Ltd",
+            "client_email": null,
+            "amount": "100.00",
+            "currency": "USD",
+            "status": "draft",
+            "issue_date": "2024-01-01",
+            "due_date": null,
+            "line_items": [{ "description": "Design", "quantity": 2, "unit_price": "50.00" }],
+            "metadata": {},
+        })
+    }
+
+    #[test]
+    fn test_bundled_schemas_compile() {
+        for version in SUPPORTED_SCHEMA_VERSIONS {
+            assert!(schemas(*version).is_some());
+        }
+        assert!(is_supported(SYNC_SCHEMA_VERSION));
+        assert!(!is_supported(SYNC_SCHEMA_VERSION + 1));
+    }
+
+    #[test]
+    fn test_valid_invoice_passes() {
+        assert!(validate_change(1, "invoices", SyncOperation::Insert, &invoice()).is_ok());
+    }
+
+    #[test]
+    fn test_field_errors_are_reported() {
+        let mut data = invoice();
+        data["due_date"] = json!("next friday");
+        data["amount"] = json!("12,50");
+        data["line_items"][0]["unit_price"] = json!(true);
+
+        let err = validate_change(1, "invoices", SyncOperation::Update, &data).unwrap_err();
+        let mut paths: Vec<&str> = err.fields.iter().map(|f| f.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/amount", "/due_date", "/line_items/0/unit_price"]);
+        assert_eq!(err.code(), "invalid_payload");
+    }
+
+    #[test]
+    fn test_required_fields_only_apply_to_inserts() {
+        let partial = json!({ "client_name": "Acme Ltd" });
+        assert!(validate_change(1, "invoices", SyncOperation::Update, &partial).is_ok());
+
+        let err = validate_change(1, "invoices", SyncOperation::Insert, &partial).unwrap_err();
+        assert!(err.fields.iter().any(|f| f.error.contains("invoice_number")));
+    }
+
+    #[test]
+    fn test_client_records_are_checked() {
+        let client = json!({ "name": "Acme Ltd", "email": "billing@acme.test" });
+        assert!(validate_change(1, "clients", SyncOperation::Insert, &client).is_ok());
+
+        let long_terms = json!({ "name": "Acme Ltd", "payment_terms_days": 400 });
+        let err = validate_change(1, "clients", SyncOperation::Insert, &long_terms).unwrap_err();
+        assert_eq!(err.fields[0].path, "/payment_terms_days");
+
+        let mut invoice = invoice();
+        invoice["client_id"] = json!("acme");
+        let err = validate_change(1, "invoices", SyncOperation::Insert, &invoice).unwrap_err();
+        assert_eq!(err.fields[0].path, "/client_id");
+    }
+
+    #[test]
+    fn test_user_settings_records_are_checked() {
+        let settings = json!({ "base_currency": "EUR", "late_fee_kind": "flat", "late_fee_amount": 15 });
+        assert!(validate_change(1, "user_settings", SyncOperation::Update, &settings).is_ok());
+        assert!(validate_change(1, "user_settings", SyncOperation::Insert, &json!({})).is_ok());
+
+        let err = validate_change(1, "user_settings", SyncOperation::Update, &json!({ "weekly_draft_grace_hours": 0 })).unwrap_err();
+        assert_eq!(err.fields[0].path, "/weekly_draft_grace_hours");
+        assert!(has_schema(1, "user_settings"));
+        assert!(!has_schema(1, "users"));
+        assert_eq!(has_field(1, "user_settings", "vat_id"), Some(true));
+        assert_eq!(has_field(1, "user_settings", "user_id"), Some(false));
+        assert_eq!(has_field(1, "users", "id"), None);
+    }
+
+    #[test]
+    fn test_project_records_are_checked() {
+        let project = json!({ "name": "Brand refresh", "rate": "85.00", "status": "on_hold" });
+        assert!(validate_change(1, "projects", SyncOperation::Insert, &project).is_ok());
+
+        let err = validate_change(1, "projects", SyncOperation::Update, &json!({ "status": "paused" })).unwrap_err();
+        assert_eq!(err.fields[0].path, "/status");
+
+        let mut invoice = invoice();
+        invoice["project_id"] = json!("brand-refresh");
+        let err = validate_change(1, "invoices", SyncOperation::Insert, &invoice).unwrap_err();
+        assert_eq!(err.fields[0].path, "/project_id");
+    }
+
+    #[test]
+    fn test_time_entry_records_are_checked() {
+        let tracked = json!({
+            "project_id": "6f1c1c52-2c1e-4f57-9a55-0c8a1e6b7d10",
+            "started_at": "2024-03-04T09:00:00Z",
+            "stopped_at": "2024-03-04T10:30:00Z",
+            "billable": false
+        });
+        assert!(validate_change(1, "time_entries", SyncOperation::Insert, &tracked).is_ok());
+        assert!(validate_change(1, "time_entries", SyncOperation::Insert, &json!({ "hours": "1.5" })).is_ok());
+
+        assert!(validate_change(1, "time_entries", SyncOperation::Insert, &json!({ "description": "Call" })).is_err());
+        assert!(validate_change(1, "time_entries", SyncOperation::Update, &json!({ "description": "Call" })).is_ok());
+
+        let err = validate_change(1, "time_entries", SyncOperation::Update, &json!({ "billable": "yes" })).unwrap_err();
+        assert_eq!(err.fields[0].path, "/billable");
+    }
+
+    #[test]
+    fn test_localized_values_are_rewritten() {
+        let german = Locale::parse("de-DE").unwrap();
+        let mut data = invoice();
+        data["amount"] = json!("1.234,50");
+        data["due_date"] = json!("05.03.2024");
+        data["line_items"][0]["unit_price"] = json!("617,25");
+        data["line_items"][0]["tax_rate"] = json!("19");
+
+        let localized = localize_change(1, "invoices", &data, &german).unwrap();
+        assert_eq!(localized["amount"], json!("1234.50"));
+        assert_eq!(localized["due_date"], json!("2024-03-05"));
+        assert_eq!(localized["issue_date"], json!("2024-01-01"));
+        assert_eq!(localized["line_items"][0]["unit_price"], json!("617.25"));
+        assert_eq!(localized["line_items"][0]["quantity"], json!(2));
+        assert!(validate_change(1, "invoices", SyncOperation::Insert, &localized).is_ok());
+
+        data["due_date"] = json!("31.02.2024");
+        data["amount"] = json!("12,5,0");
+        let err = localize_change(1, "invoices", &data, &german).unwrap_err();
+        let mut paths: Vec<&str> = err.fields.iter().map(|f| f.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/amount", "/due_date"]);
+    }
+
+    #[test]
+    fn test_unknown_tables_are_not_checked() {
+        assert!(validate_change(1, "receipts", SyncOperation::Insert, &json!({})).is_ok());
+    }
+}
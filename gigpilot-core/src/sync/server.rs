@@ -0,0 +1,65 @@
+//! Server-originated sync changes.
+//!
+//! Records produced by background jobs (e.g. OCR-suggested expenses) are not
+//! pushed by any device, so they are written to `sync_changes` here and reach
+//! clients on their next pull.
+
+use serde_json::Value;
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::models::sync_change::SyncOperation;
+
+/// Device identifier used for changes made by the server itself.
+pub const SERVER_DEVICE_ID: &str = "server";
+
+/// Records a server-side change so it is delivered on the next pull.
+///
+/// # Arguments
+///
+/// * `executor` - Pool or transaction to write with
+/// * `user_id` - Owner of the changed record
+/// * `table_name` - Synced table name (e.g. "expenses")
+/// * `record_id` - ID of the changed record
+/// * `operation` - Kind of change
+/// * `data` - Serialized record (new state, or old state for deletes)
+///
+/// # Returns
+///
+/// Returns `Ok(())` once the change is recorded, or an error.
+pub async fn record_server_change<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    table_name: &str,
+    record_id: Uuid,
+    operation: SyncOperation,
+    data: &Value,
+) -> Result<(), anyhow::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let (old_data, new_data) = match operation {
+        SyncOperation::Delete => (Some(data), None),
+        SyncOperation::Insert | SyncOperation::Update => (None, Some(data)),
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO sync_changes (
+            user_id, table_name, record_id, operation,
+            old_data, new_data, device_id, is_applied
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, true)
+        "#,
+    )
+    .bind(user_id)
+    .bind(table_name)
+    .bind(record_id)
+    .bind(operation)
+    .bind(old_data)
+    .bind(new_data)
+    .bind(SERVER_DEVICE_ID)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
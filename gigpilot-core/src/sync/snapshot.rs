@@ -0,0 +1,191 @@
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::models::client::Client;
+use crate::models::estimate::Estimate;
+use crate::models::invoice::Invoice;
+use crate::models::project::Project;
+use crate::models::time_entry::TimeEntry;
+use crate::settings::{load_user_settings, sync_record};
+use crate::sync::encryption;
+use crate::sync::evolution;
+use crate::sync::pull::{latest_sequence, pulled_rows};
+use crate::sync::schema::SYNC_SCHEMA_VERSION;
+use crate::sync::types::{PullResponse, PullStatus};
+
+/// Builds a full snapshot of the user's current rows.
+///
+/// Used by devices that were told to resync because their last pull
+/// predates the change-log retention horizon. Every live record (projects
+/// and clients shared with the user included), and the user's settings
+/// (defaults included), is returned in the `created` bucket of the
+/// WatermelonDB envelope, with records of tables the user syncs
+/// end-to-end encrypted as their stored payloads. The returned `sequence_number` covers every
+/// change the snapshot reflects and is the cursor for subsequent
+/// incremental pulls (older clients use the timestamp as `last_pulled_at`).
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user requesting the snapshot
+///
+/// # Returns
+///
+/// Returns a `PullResponse` containing every live record grouped by table.
+///
+/// # Errors
+///
+/// Returns an error if the database query or serialization fails.
+pub async fn build_snapshot(pool: &PgPool, user_id: Uuid) -> Result<PullResponse, anyhow::Error> {
+    // Take the timestamp before reading so concurrent writes are picked up
+    // by the next incremental pull rather than lost.
+    let timestamp = Utc::now();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE user_id = $1 AND is_deleted = false
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let estimates = sqlx::query_as::<_, Estimate>(
+        "SELECT * FROM estimates WHERE user_id = $1 AND is_deleted = false",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let clients = sqlx::query_as::<_, Client>(&format!(
+        "SELECT * FROM clients WHERE {} AND is_deleted = false",
+        pulled_rows("clients")
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let projects = sqlx::query_as::<_, Project>(&format!(
+        "SELECT * FROM projects WHERE {} AND is_deleted = false",
+        pulled_rows("projects")
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let time_entries = sqlx::query_as::<_, TimeEntry>(
+        "SELECT * FROM time_entries WHERE user_id = $1 AND is_deleted = false",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let settings = load_user_settings(&mut *tx, user_id).await?;
+    let encrypted = encryption::live_records(&mut *tx, user_id, None, None).await?;
+
+    // Read in the same snapshot as the records
+    let sequence_number = latest_sequence(&mut *tx, user_id).await?.unwrap_or(0);
+
+    tx.commit().await?;
+
+    info!(
+        "Built snapshot for user {} with {} invoices, {} estimates, {} clients, {} projects and {} time entries",
+        user_id,
+        invoices.len(),
+        estimates.len(),
+        clients.len(),
+        projects.len(),
+        time_entries.len()
+    );
+
+    let invoice_records = invoices
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<Value>, _>>()?;
+    let estimate_records = estimates
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<Value>, _>>()?;
+    let client_records = clients
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<Value>, _>>()?;
+    let project_records = projects
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<Value>, _>>()?;
+    let time_entry_records = time_entries
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<Value>, _>>()?;
+
+    let mut changes = json!({
+        "invoices": {
+            "created": invoice_records,
+            "updated": [],
+            "deleted": [],
+        },
+        "estimates": {
+            "created": estimate_records,
+            "updated": [],
+            "deleted": [],
+        },
+        "clients": {
+            "created": client_records,
+            "updated": [],
+            "deleted": [],
+        },
+        "projects": {
+            "created": project_records,
+            "updated": [],
+            "deleted": [],
+        },
+        "time_entries": {
+            "created": time_entry_records,
+            "updated": [],
+            "deleted": [],
+        },
+        "user_settings": {
+            "created": [sync_record(&settings)?],
+            "updated": [],
+            "deleted": [],
+        }
+    });
+
+    // Keep renamed columns readable for older app versions
+    evolution::dual_write_changes(&mut changes);
+
+    // Encrypted records are relayed as stored
+    for record in &encrypted {
+        if let Some(created) = changes[record.table_name.as_str()]["created"].as_array_mut() {
+            created.push(record.to_record());
+        }
+    }
+
+    Ok(PullResponse {
+        changes,
+        timestamp,
+        sequence_number: Some(sequence_number),
+        has_more: false,
+        cursor: None,
+        status: PullStatus::Ok,
+        snapshot_url: None,
+        editing: Vec::new(),
+        schema_version: SYNC_SCHEMA_VERSION,
+    })
+}
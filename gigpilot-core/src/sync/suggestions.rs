@@ -0,0 +1,610 @@
+//! Merge suggestions for conflicting text.
+//!
+//! When a pushed update conflicts, the server keeps its own version and a
+//! device's edit to a description or note is lost. With a resolver
+//! configured (`CONFLICT_MERGE_PROVIDER`), the server asks a language model
+//! to merge the two texts after the push and queues the result as a
+//! suggestion, provided the user consented to LLM processing. Suggestions are never applied on their own: the user
+//! accepts one, which updates the record like any server-side change, or
+//! dismisses it.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::invoices::due_dates::DueDateRules;
+use crate::logging::redact_text;
+use crate::models::sync_change::SyncOperation;
+use crate::settings::load_user_settings;
+use crate::sync::fields::FieldMode;
+use crate::sync::server::record_server_change;
+use crate::sync::tables::{find_table, PushContext};
+use crate::sync::types::{PushRequest, PushResponse};
+
+/// Free-text fields whose conflicts get merge suggestions, per table.
+pub const MERGEABLE_FIELDS: &[(&str, &str)] = &[
+    ("invoices", "description"),
+    ("estimates", "description"),
+    ("clients", "notes"),
+    ("projects", "description"),
+    ("time_entries", "description"),
+];
+
+/// Longest text (in characters, per side) sent to the resolver.
+pub const MAX_MERGE_TEXT_CHARS: usize = 4000;
+
+/// Both sides of a conflict in a free-text field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextConflict {
+    pub table_name: String,
+    pub record_id: Uuid,
+    pub field: String,
+
+    /// The text the server kept
+    pub server_value: String,
+
+    /// The text the device pushed
+    pub client_value: String,
+}
+
+/// A free-text field of a pushed change, kept until the push's conflicts
+/// are known.
+#[derive(Debug, Clone)]
+pub struct PushedText {
+    table_name: String,
+    record_id: Uuid,
+    field: &'static str,
+    value: String,
+}
+
+/// A resolver that merges the two sides of a text conflict.
+#[async_trait]
+pub trait MergeProvider: Send + Sync {
+    /// Short provider name stored with suggestions (e.g. "mock").
+    fn name(&self) -> &'static str;
+
+    /// Merges the server's and the device's text into one.
+    ///
+    /// # Returns
+    ///
+    /// Returns the merged text, or an error if the provider failed.
+    async fn merge(&self, conflict: &TextConflict) -> Result<String, anyhow::Error>;
+}
+
+/// The instructions sent to the language model for a conflict.
+pub fn merge_prompt(conflict: &TextConflict) -> String {
+    format!(
+        "The {} of a record in {} was edited on two devices at the same time. \
+         Merge both versions into one text that keeps every fact from each, \
+         drops duplicated sentences and keeps the language and tone of the \
+         originals. Reply with the merged text only.\n\n\
+         Version A:\n{}\n\nVersion B:\n{}",
+        conflict.field.replace('_', " "),
+        conflict.table_name.replace('_', " "),
+        conflict.server_value,
+        conflict.client_value,
+    )
+}
+
+/// Mock merge provider.
+///
+/// In production, this would send [`merge_prompt`] to the LLM provider's
+/// chat completion API. The mock keeps the server's lines and appends the
+/// device's lines that the server's text lacks, so the rest of the flow
+/// can be exercised end to end.
+#[derive(Debug, Default)]
+pub struct MockMergeProvider;
+
+#[async_trait]
+impl MergeProvider for MockMergeProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn merge(&self, conflict: &TextConflict) -> Result<String, anyhow::Error> {
+        // Simulate API call delay
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+        // In production, this would be:
+        // let client = reqwest::Client::new();
+        // let response = client
+        //     .post("https://api.openai.com/v1/chat/completions")
+        //     .header("Authorization", format!("Bearer {}", api_key))
+        //     .json(&json!({
+        //         "model": "gpt-4o-mini",
+        //         "messages": [{ "role": "user", "content": merge_prompt(conflict) }]
+        //     }))
+        //     .send()
+        //     .await?;
+
+        Ok(merge_lines(&conflict.server_value, &conflict.client_value))
+    }
+}
+
+/// The server's lines followed by the device's lines it lacks.
+fn merge_lines(server: &str, client: &str) -> String {
+    let mut merged: Vec<&str> = server.lines().collect();
+    for line in client.lines() {
+        if !line.trim().is_empty() && !merged.iter().any(|kept| kept.trim() == line.trim()) {
+            merged.push(line);
+        }
+    }
+    merged.join("\n")
+}
+
+/// Selects the merge resolver from `CONFLICT_MERGE_PROVIDER` ("mock").
+///
+/// Merge suggestions are optional: without the variable, none are made.
+pub fn provider_from_env() -> Option<Arc<dyn MergeProvider>> {
+    match std::env::var("CONFLICT_MERGE_PROVIDER").as_deref() {
+        Err(_) | Ok("") | Ok("off") => None,
+        Ok("mock") => Some(Arc::new(MockMergeProvider)),
+        Ok(other) => {
+            warn!("Unknown conflict merge provider: {}, merge suggestions are off", other);
+            None
+        }
+    }
+}
+
+/// Collects the free-text fields a push updates.
+pub fn pushed_text(request: &PushRequest) -> Vec<PushedText> {
+    request
+        .changes
+        .iter()
+        .filter(|change| !change.deleted)
+        .filter_map(|change| {
+            let (_, field) = MERGEABLE_FIELDS.iter().find(|(table, _)| *table == change.table)?;
+            let value = change.data.as_ref()?.get(*field)?.as_str()?;
+            Some(PushedText {
+                table_name: change.table.clone(),
+                record_id: change.id,
+                field,
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Finds the pushed texts that lost a conflict to different server text.
+///
+/// Conflicts where either side is empty (or too long to send) are left
+/// out: there is nothing to merge.
+pub fn text_conflicts(pushed: Vec<PushedText>, response: &PushResponse) -> Vec<TextConflict> {
+    pushed
+        .into_iter()
+        .filter_map(|pushed| {
+            let version = response
+                .conflict_versions
+                .iter()
+                .find(|version| version.table == pushed.table_name && version.id == pushed.record_id)?;
+            let server_value = version.record.get(pushed.field)?.as_str()?;
+            let mergeable = |text: &str| !text.trim().is_empty() && text.chars().count() <= MAX_MERGE_TEXT_CHARS;
+            if !mergeable(server_value) || !mergeable(&pushed.value) || server_value.trim() == pushed.value.trim() {
+                return None;
+            }
+            Some(TextConflict {
+                table_name: pushed.table_name,
+                record_id: pushed.record_id,
+                field: pushed.field.to_string(),
+                server_value: server_value.to_string(),
+                client_value: pushed.value,
+            })
+        })
+        .collect()
+}
+
+/// A queued merge suggestion.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MergeSuggestion {
+    pub id: Uuid,
+    pub table_name: String,
+    pub record_id: Uuid,
+    pub field: String,
+
+    /// Device whose text lost the conflict
+    pub device_id: String,
+
+    pub server_value: String,
+    pub client_value: String,
+    pub suggested_value: String,
+
+    /// Resolver that made the suggestion
+    pub provider: String,
+
+    /// "pending", "accepted" or "dismissed"
+    pub status: String,
+
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Columns of a [`MergeSuggestion`].
+const SUGGESTION_COLUMNS: &str = "id, table_name, record_id, field, device_id, \
+    server_value, client_value, suggested_value, provider, status, resolved_at, created_at";
+
+/// Asks the resolver for merges of a push's text conflicts in the
+/// background, queueing each as a suggestion.
+///
+/// Nothing is sent to the resolver unless the user consented to LLM
+/// processing (`ai_llm_consent`).
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `provider` - The merge resolver
+/// * `user_id` - ID of the user who pushed
+/// * `device_id` - Device whose text lost the conflicts
+/// * `conflicts` - The conflicts to merge
+pub fn spawn_merge_suggestions(
+    pool: PgPool,
+    provider: Arc<dyn MergeProvider>,
+    user_id: Uuid,
+    device_id: String,
+    conflicts: Vec<TextConflict>,
+) {
+    if conflicts.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        match load_user_settings(&pool, user_id).await {
+            Ok(settings) if settings.ai_consent().llm => {}
+            Ok(_) => return,
+            Err(e) => {
+                warn!("Failed to load settings of user {} for merge suggestions: {}", user_id, e);
+                return;
+            }
+        }
+        for conflict in conflicts {
+            let suggested = match provider.merge(&conflict).await {
+                Ok(suggested) => suggested,
+                Err(e) => {
+                    // Provider errors may echo the prompt, and with it both texts
+                    warn!(
+                        "Failed to merge {} of {}:{}: {}",
+                        conflict.field,
+                        conflict.table_name,
+                        conflict.record_id,
+                        redact_text(&e)
+                    );
+                    continue;
+                }
+            };
+            // A merge that just keeps the server's text suggests nothing
+            if suggested.trim().is_empty() || suggested.trim() == conflict.server_value.trim() {
+                continue;
+            }
+            if let Err(e) = store_suggestion(&pool, user_id, &device_id, provider.name(), &conflict, &suggested).await {
+                warn!(
+                    "Failed to store merge suggestion for {}:{}: {}",
+                    conflict.table_name, conflict.record_id, e
+                );
+            }
+        }
+    });
+}
+
+/// Queues a merge suggestion, replacing any still pending for the field.
+async fn store_suggestion(
+    pool: &PgPool,
+    user_id: Uuid,
+    device_id: &str,
+    provider: &str,
+    conflict: &TextConflict,
+    suggested: &str,
+) -> Result<(), anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM sync_merge_suggestions
+        WHERE user_id = $1 AND table_name = $2 AND record_id = $3 AND field = $4 AND status = 'pending'
+        "#,
+    )
+    .bind(user_id)
+    .bind(&conflict.table_name)
+    .bind(conflict.record_id)
+    .bind(&conflict.field)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO sync_merge_suggestions (
+            user_id, table_name, record_id, field, device_id,
+            server_value, client_value, suggested_value, provider
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(user_id)
+    .bind(&conflict.table_name)
+    .bind(conflict.record_id)
+    .bind(&conflict.field)
+    .bind(device_id)
+    .bind(&conflict.server_value)
+    .bind(&conflict.client_value)
+    .bind(suggested)
+    .bind(provider)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    info!(
+        "Queued merge suggestion for {} of {}:{}: {}",
+        conflict.field,
+        conflict.table_name,
+        conflict.record_id,
+        redact_text(&suggested)
+    );
+    Ok(())
+}
+
+/// Lists the user's pending merge suggestions.
+///
+/// # Returns
+///
+/// Returns the suggestions, newest first.
+pub async fn list_suggestions(pool: &PgPool, user_id: Uuid) -> Result<Vec<MergeSuggestion>, anyhow::Error> {
+    let query = format!(
+        "SELECT {} FROM sync_merge_suggestions WHERE user_id = $1 AND status = 'pending' ORDER BY created_at DESC",
+        SUGGESTION_COLUMNS
+    );
+    let suggestions = sqlx::query_as::<_, MergeSuggestion>(&query)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(suggestions)
+}
+
+/// Why a merge suggestion couldn't be accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptRefusal {
+    /// No pending suggestion with that ID
+    NotFound,
+
+    /// The record was deleted or its text changed since the suggestion
+    /// was made; it was left alone
+    Stale,
+}
+
+/// Accepts a merge suggestion, writing the suggested text to the record.
+///
+/// The change is logged like other server-side changes, so every device
+/// gets it on its next pull.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the user
+/// * `suggestion_id` - ID of the suggestion
+///
+/// # Returns
+///
+/// Returns the accepted suggestion, or why it couldn't be accepted.
+///
+/// # Errors
+///
+/// Returns an error if a query fails or the record can't take the text.
+pub async fn accept_suggestion(
+    pool: &PgPool,
+    user_id: Uuid,
+    suggestion_id: Uuid,
+) -> Result<Result<MergeSuggestion, AcceptRefusal>, anyhow::Error> {
+    let due_date_rules = DueDateRules::load(pool, user_id).await?;
+    let context = PushContext {
+        due_date_rules: &due_date_rules,
+        field_mode: FieldMode::default(),
+        encrypted_tables: &[],
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let query = format!(
+        "SELECT {} FROM sync_merge_suggestions WHERE id = $1 AND user_id = $2 AND status = 'pending' FOR UPDATE",
+        SUGGESTION_COLUMNS
+    );
+    let Some(suggestion) = sqlx::query_as::<_, MergeSuggestion>(&query)
+        .bind(suggestion_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+    else {
+        return Ok(Err(AcceptRefusal::NotFound));
+    };
+    let Some(table) = find_table(&suggestion.table_name) else {
+        return Ok(Err(AcceptRefusal::NotFound));
+    };
+
+    // Only replace the text the suggestion was made from
+    let record = table.load(&mut tx, user_id, suggestion.record_id).await?;
+    let Some(mut record) = record.filter(|record| {
+        record.get("is_deleted").and_then(|v| v.as_bool()) != Some(true)
+            && record.get(&suggestion.field).and_then(|v| v.as_str()) == Some(suggestion.server_value.as_str())
+    }) else {
+        return Ok(Err(AcceptRefusal::Stale));
+    };
+
+    record[suggestion.field.as_str()] = suggestion.suggested_value.clone().into();
+    table.update(&mut tx, &context, user_id, suggestion.record_id, &record).await?;
+    if let Some(updated) = table.load(&mut tx, user_id, suggestion.record_id).await? {
+        record_server_change(
+            &mut *tx,
+            user_id,
+            &suggestion.table_name,
+            suggestion.record_id,
+            SyncOperation::Update,
+            &updated,
+        )
+        .await?;
+    }
+
+    let accepted = resolve(&mut tx, user_id, suggestion_id, "accepted").await?;
+    tx.commit().await?;
+
+    info!(
+        "Accepted merge suggestion for {} of {}:{}",
+        suggestion.field, suggestion.table_name, suggestion.record_id
+    );
+    Ok(Ok(accepted))
+}
+
+/// Dismisses a merge suggestion, leaving the record as it is.
+///
+/// # Returns
+///
+/// Returns the dismissed suggestion, or `None` if no pending suggestion
+/// has that ID.
+pub async fn dismiss_suggestion(
+    pool: &PgPool,
+    user_id: Uuid,
+    suggestion_id: Uuid,
+) -> Result<Option<MergeSuggestion>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    let exists = sqlx::query_scalar::<_, i32>(
+        "SELECT 1 FROM sync_merge_suggestions WHERE id = $1 AND user_id = $2 AND status = 'pending' FOR UPDATE",
+    )
+    .bind(suggestion_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+
+    let dismissed = resolve(&mut tx, user_id, suggestion_id, "dismissed").await?;
+    tx.commit().await?;
+
+    Ok(Some(dismissed))
+}
+
+/// Closes a pending suggestion with the given status.
+async fn resolve(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+    suggestion_id: Uuid,
+    status: &str,
+) -> Result<MergeSuggestion, anyhow::Error> {
+    let query = format!(
+        "UPDATE sync_merge_suggestions SET status = $3, resolved_at = NOW() \
+         WHERE id = $1 AND user_id = $2 RETURNING {}",
+        SUGGESTION_COLUMNS
+    );
+    let suggestion = sqlx::query_as::<_, MergeSuggestion>(&query)
+        .bind(suggestion_id)
+        .bind(user_id)
+        .bind(status)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    Ok(suggestion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::types::{ConflictVersion, PushChange, PushMode};
+    use serde_json::json;
+
+    fn push_request(changes: Vec<PushChange>) -> PushRequest {
+        PushRequest {
+            changes,
+            device_id: Some("phone".to_string()),
+            schema_version: None,
+            locale: None,
+            mode: PushMode::BestEffort,
+            dry_run: false,
+            app_version: None,
+            platform: None,
+        }
+    }
+
+    fn update(table: &str, id: Uuid, data: serde_json::Value) -> PushChange {
+        PushChange {
+            table: table.to_string(),
+            id,
+            data: Some(data),
+            deleted: false,
+            device_id: Some("phone".to_string()),
+            version_vector: None,
+            patch: None,
+            encrypted: None,
+        }
+    }
+
+    fn response(conflict_versions: Vec<ConflictVersion>) -> PushResponse {
+        PushResponse {
+            applied: 0,
+            conflicts: conflict_versions.len(),
+            conflicted_ids: conflict_versions.iter().map(|version| version.id).collect(),
+            conflict_versions,
+            rejected: Vec::new(),
+            rolled_back: false,
+            dry_run: false,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_text_conflicts_only_cover_differing_text() {
+        let (invoice, client, project) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let request = push_request(vec![
+            update("invoices", invoice, json!({ "description": "Logo design\nTwo revisions" })),
+            update("clients", client, json!({ "notes": "Pays late" })),
+            update("projects", project, json!({ "name": "Rebrand" })),
+        ]);
+        let response = response(vec![
+            ConflictVersion {
+                table: "invoices".to_string(),
+                id: invoice,
+                record: json!({ "description": "Logo design" }),
+            },
+            // Same text on both sides: nothing to merge
+            ConflictVersion {
+                table: "clients".to_string(),
+                id: client,
+                record: json!({ "notes": "Pays late " }),
+            },
+            ConflictVersion {
+                table: "projects".to_string(),
+                id: project,
+                record: json!({ "description": "Full rebrand" }),
+            },
+        ]);
+
+        let conflicts = text_conflicts(pushed_text(&request), &response);
+        assert_eq!(
+            conflicts,
+            vec![TextConflict {
+                table_name: "invoices".to_string(),
+                record_id: invoice,
+                field: "description".to_string(),
+                server_value: "Logo design".to_string(),
+                client_value: "Logo design\nTwo revisions".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_merge_keeps_both_sides() {
+        let conflict = TextConflict {
+            table_name: "time_entries".to_string(),
+            record_id: Uuid::new_v4(),
+            field: "description".to_string(),
+            server_value: "Call with client\nDraft wireframes".to_string(),
+            client_value: "Call with client\nFixed login bug".to_string(),
+        };
+
+        let merged = MockMergeProvider.merge(&conflict).await.unwrap();
+        assert_eq!(merged, "Call with client\nDraft wireframes\nFixed login bug");
+
+        let prompt = merge_prompt(&conflict);
+        assert!(prompt.contains("The description of a record in time entries"));
+        assert!(prompt.contains("Draft wireframes") && prompt.contains("Fixed login bug"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Record-level access checks for pushed changes.
+//!
+//! Writes are scoped to the user by their `WHERE` clauses, but that only
+//! makes a change to someone else's record quietly do nothing, and IDs
+//! inside the record (a `client_id`, a `project_id`) aren't scoped at all.
+//! Every pushed change is checked here first (see
+//! [`SyncableTable::authorize`](super::SyncableTable::authorize)), so a
+//! change touching another account's data is rejected on its own with a
+//! clear reason.
+
+use std::fmt;
+
+use serde_json::{json, Value};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// A field of a synced record holding the ID of another synced record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    /// Field of the pushed record (e.g. "client_id")
+    pub field: &'static str,
+
+    /// Table the ID refers to (e.g. "clients")
+    pub table: &'static str,
+}
+
+/// A pushed change reaching outside the user's data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessError {
+    /// The record ID belongs to another account's record
+    Record { table: String, id: Uuid },
+
+    /// A field refers to a record that isn't one of the user's live
+    /// records (another account's, a deleted one or none at all, which
+    /// are reported alike so other accounts' IDs can't be probed)
+    Reference {
+        field: &'static str,
+        table: &'static str,
+        id: Uuid,
+    },
+}
+
+impl AccessError {
+    /// Machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccessError::Record { .. } => "record_forbidden",
+            AccessError::Reference { .. } => "reference_forbidden",
+        }
+    }
+
+    /// What was refused, for the push response.
+    pub fn details(&self) -> Value {
+        match self {
+            AccessError::Record { id, .. } => json!({ "id": id }),
+            AccessError::Reference { field, table, id } => json!({ "field": field, "table": table, "id": id }),
+        }
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::Record { table, .. } => write!(f, "{} record belongs to another account", table),
+            AccessError::Reference { field, table, .. } => {
+                write!(f, "{} does not refer to one of your {}", field, table.replace('_', " "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+/// Checks that the referenced IDs of a pushed record are the user's live
+/// records.
+///
+/// Only references the push sets or changes are checked: an ID the stored
+/// record already holds stays valid after the record it refers to is
+/// deleted, so a record linked to a since-deleted client can still be
+/// pushed back as pulled. Missing, null and malformed IDs are left to the
+/// table's own validation.
+///
+/// # Arguments
+///
+/// * `conn` - Connection (usually the push's transaction)
+/// * `user_id` - ID of the account the change applies to
+/// * `table` - Table of the pushed record
+/// * `record_id` - ID of the pushed record
+/// * `references` - Fields of the record holding IDs of other records
+/// * `data` - The pushed record
+///
+/// # Errors
+///
+/// Returns an [`AccessError::Reference`] for the first field referring
+/// elsewhere, or an error if a query fails.
+pub async fn check_references(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    table: &str,
+    record_id: Uuid,
+    references: &[Reference],
+    data: &Value,
+) -> Result<(), anyhow::Error> {
+    for reference in references {
+        let Some(id) = data.get(reference.field).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let Ok(id) = Uuid::parse_str(id) else {
+            continue;
+        };
+
+        let query = format!("SELECT {} FROM {} WHERE id = $1 AND user_id = $2", reference.field, table);
+        let stored = sqlx::query_scalar::<_, Option<Uuid>>(&query)
+            .bind(record_id)
+            .bind(user_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .flatten();
+        if stored == Some(id) {
+            continue;
+        }
+
+        let query = format!(
+            "SELECT 1 FROM {} WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+            reference.table
+        );
+        let owned = sqlx::query_scalar::<_, i32>(&query)
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+        if owned.is_none() {
+            return Err(AccessError::Reference {
+                field: reference.field,
+                table: reference.table,
+                id,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_errors_describe_the_refusal() {
+        let id = Uuid::new_v4();
+
+        let record = AccessError::Record {
+            table: "invoices".to_string(),
+            id,
+        };
+        assert_eq!(record.code(), "record_forbidden");
+        assert_eq!(record.to_string(), "invoices record belongs to another account");
+        assert_eq!(record.details(), json!({ "id": id }));
+
+        let reference = AccessError::Reference {
+            field: "project_id",
+            table: "projects",
+            id,
+        };
+        assert_eq!(reference.code(), "reference_forbidden");
+        assert_eq!(reference.to_string(), "project_id does not refer to one of your projects");
+        assert_eq!(reference.details()["field"], "project_id");
+    }
+}
@@ -0,0 +1,705 @@
+//! Synced invoices.
+//!
+//! Unlike the other synced tables, whose push handling lives in their own
+//! modules, pushed invoices are applied here: numbers must follow the
+//! user's numbering rules, line items are re-totalled with the user's tax
+//! rates, statuses must follow the invoice lifecycle and due dates the
+//! user's due-date rules. Pushed metadata is merged around the keys the
+//! server keeps there, and version vectors are merged rather than
+//! replaced.
+
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use sqlx::{PgConnection, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::clients;
+use crate::currency::{normalize_currency, validate_amount, DEFAULT_CURRENCY};
+use crate::invoices::numbering::check_invoice_number;
+use crate::models::invoice::InvoiceStatus;
+use crate::models::line_item::{InvoiceTotals, LineItem};
+use crate::projects;
+use crate::sync::tables::{PushContext, Reference, SyncableTable};
+use crate::taxes::resolve_tax_rates;
+
+/// Parses pushed invoice line items and computes their totals.
+/// 
+/// Referenced tax rates are resolved against the user's saved rates.
+/// 
+/// # Returns
+/// 
+/// Returns the normalized line items and their totals, or `None` if the
+/// change carries no line items.
+/// 
+/// # Errors
+/// 
+/// Returns a `LineItemsError` if the line items are malformed, or an error
+/// if they reference an unknown tax rate.
+async fn invoice_line_items(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    data: &Value,
+) -> Result<Option<(Value, InvoiceTotals)>, anyhow::Error> {
+    let raw = match data.get("line_items") {
+        Some(raw) if !raw.is_null() => raw,
+        _ => return Ok(None),
+    };
+    
+    let mut items = LineItem::parse_list(raw)?;
+    resolve_tax_rates(&mut **tx, user_id, &mut items).await?;
+    let totals = InvoiceTotals::compute(&items);
+    
+    Ok(Some((serde_json::to_value(&items)?, totals)))
+}
+
+/// Parses the client a pushed invoice references.
+/// 
+/// # Returns
+/// 
+/// Returns the client ID, or `None` if the invoice has no client.
+/// 
+/// # Errors
+/// 
+/// Returns an error if the ID is malformed or the client does not belong
+/// to the user.
+async fn pushed_client_id(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    data: &Value,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let client_id = match data.get("client_id").and_then(|v| v.as_str()) {
+        Some(id) => Uuid::parse_str(id)?,
+        None => return Ok(None),
+    };
+    
+    clients::check_client(&mut **tx, user_id, client_id).await?;
+    Ok(Some(client_id))
+}
+
+/// Parses the project a pushed invoice references.
+/// 
+/// # Returns
+/// 
+/// Returns the project ID, or `None` if the invoice has no project.
+/// 
+/// # Errors
+/// 
+/// Returns an error if the ID is malformed or the project does not belong
+/// to the user.
+async fn pushed_project_id(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    data: &Value,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let project_id = match data.get("project_id").and_then(|v| v.as_str()) {
+        Some(id) => Uuid::parse_str(id)?,
+        None => return Ok(None),
+    };
+    
+    projects::check_project(&mut **tx, user_id, project_id).await?;
+    Ok(Some(project_id))
+}
+
+/// Keys of invoice `metadata` the server writes: chase, hold, retry and
+/// late-fee state, payment intents, and where the invoice came from
+/// (including the proposal a deposit invoice was issued for). Devices
+/// can't set or clear them.
+pub const SERVER_METADATA_KEYS: &[&str] = &[
+    "chase_state",
+    "chase_hold",
+    "chase_retry",
+    "late_fee",
+    "payment_intent",
+    "weekly_draft",
+    "import",
+    "duplicated_from",
+    "duplicated_from_number",
+    "estimate_id",
+    "estimate_number",
+    "proposal_id",
+    "deposit",
+];
+
+/// Merges pushed invoice metadata into the stored metadata.
+///
+/// The device's own keys are taken from the push, as a whole; the keys
+/// in [`SERVER_METADATA_KEYS`] keep their stored values.
+///
+/// # Arguments
+///
+/// * `stored` - The invoice's current metadata (`None` for new invoices)
+/// * `pushed` - The pushed metadata (`None` or null to clear the device's
+///   keys)
+///
+/// # Returns
+///
+/// Returns the metadata to store, or `None` if nothing is left.
+pub fn merge_metadata(stored: Option<&Value>, pushed: Option<&Value>) -> Option<Value> {
+    let mut merged: serde_json::Map<String, Value> = pushed
+        .and_then(|pushed| pushed.as_object())
+        .map(|pushed| {
+            pushed
+                .iter()
+                .filter(|(key, _)| !SERVER_METADATA_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(stored) = stored.and_then(|stored| stored.as_object()) {
+        for key in SERVER_METADATA_KEYS {
+            if let Some(value) = stored.get(*key) {
+                merged.insert(key.to_string(), value.clone());
+            }
+        }
+    }
+
+    let pushed_object = pushed.is_some_and(|pushed| pushed.is_object());
+    (pushed_object || !merged.is_empty()).then_some(Value::Object(merged))
+}
+
+/// Merges a pushed version vector into the stored one.
+///
+/// Each device's counter is the higher of the two, so a device can't
+/// wind back the counters of others. Entries that aren't counters are
+/// ignored.
+///
+/// # Returns
+///
+/// Returns the vector to store, or `None` if neither side has one.
+pub fn merge_version_vectors(stored: Option<&Value>, pushed: Option<&Value>) -> Option<Value> {
+    let vectors: Vec<_> = [stored, pushed].into_iter().flatten().filter_map(|vector| vector.as_object()).collect();
+    if vectors.is_empty() {
+        return None;
+    }
+
+    let mut merged = serde_json::Map::new();
+    for vector in vectors {
+        for (device, counter) in vector {
+            let Some(counter) = counter.as_u64() else {
+                continue;
+            };
+            let current = merged.get(device).and_then(|value| value.as_u64()).unwrap_or(0);
+            merged.insert(device.clone(), Value::from(counter.max(current)));
+        }
+    }
+
+    Some(Value::Object(merged))
+}
+
+/// Dates of a pushed invoice update to check against the due-date rules.
+///
+/// Due dates are only checked when the due date or the issue date change,
+/// so invoices predating the rules can still be edited; moving only the
+/// issue date checks the stored due date against it.
+///
+/// # Arguments
+///
+/// * `stored` - The stored issue and due dates
+/// * `issue_date` - The pushed issue date (`None` keeps the stored one)
+/// * `due_date` - The pushed due date (`None` clears it)
+///
+/// # Returns
+///
+/// Returns the issue and due dates to check, or `None` if nothing needs
+/// checking.
+fn changed_due_date(
+    stored: (chrono::NaiveDate, Option<chrono::NaiveDate>),
+    issue_date: Option<chrono::NaiveDate>,
+    due_date: Option<chrono::NaiveDate>,
+) -> Option<(chrono::NaiveDate, chrono::NaiveDate)> {
+    let (stored_issue, stored_due) = stored;
+    let issue_date = issue_date.unwrap_or(stored_issue);
+    let due_date = due_date?;
+    (issue_date != stored_issue || Some(due_date) != stored_due).then_some((issue_date, due_date))
+}
+
+/// Parses an optional pushed date field.
+/// 
+/// # Returns
+/// 
+/// Returns the date, or `None` if the field is missing or null.
+/// 
+/// # Errors
+/// 
+/// Returns an error if the date is not `YYYY-MM-DD`, rather than storing
+/// `NULL` in its place.
+fn pushed_date(data: &Value, field: &str) -> Result<Option<chrono::NaiveDate>, anyhow::Error> {
+    match data.get(field).and_then(|v| v.as_str()) {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("{} is not a date: {}", field, date)),
+        None => Ok(None),
+    }
+}
+
+/// Synced invoices.
+pub struct InvoicesTable;
+
+#[async_trait]
+impl SyncableTable for InvoicesTable {
+    fn name(&self) -> &'static str {
+        "invoices"
+    }
+
+    fn references(&self) -> &'static [Reference] {
+        &[
+            Reference { field: "client_id", table: "clients" },
+            Reference { field: "project_id", table: "projects" },
+        ]
+    }
+
+    /// Totals follow the line items and payments are recorded by the
+    /// server; chase and exchange-rate overrides and chase pauses have their
+    /// own endpoints.
+    fn computed_fields(&self) -> &'static [&'static str] {
+        &[
+            "user_id",
+            "is_deleted",
+            "created_at",
+            "updated_at",
+            "subtotal",
+            "tax_total",
+            "total",
+            "amount_paid",
+            "chase_override",
+            "exchange_rate_override",
+            "chase_paused_until",
+        ]
+    }
+
+    async fn load(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error> {
+        let invoice = sqlx::query!(
+            r#"
+            SELECT 
+                id, user_id, invoice_number, client_name, client_email,
+                amount, currency, status, due_date, issue_date,
+                last_modified, version_vector, is_deleted,
+                description, line_items, subtotal, tax_total, total, amount_paid,
+                client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+            FROM invoices
+            WHERE id = $1 AND user_id = $2
+            "#,
+            record_id,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+        
+        Ok(invoice.map(|inv| {
+            serde_json::json!({
+                "id": inv.id,
+                "user_id": inv.user_id,
+                "invoice_number": inv.invoice_number,
+                "client_name": inv.client_name,
+                "client_email": inv.client_email,
+                "client_id": inv.client_id,
+                "project_id": inv.project_id,
+                "amount": inv.amount.to_string(),
+                "currency": inv.currency,
+                "status": inv.status,
+                "due_date": inv.due_date,
+                "issue_date": inv.issue_date,
+                "last_modified": inv.last_modified,
+                "version_vector": inv.version_vector,
+                "is_deleted": inv.is_deleted,
+                "description": inv.description,
+                "line_items": inv.line_items,
+                "subtotal": inv.subtotal.to_string(),
+                "tax_total": inv.tax_total.to_string(),
+                "total": inv.total.to_string(),
+                "amount_paid": inv.amount_paid.to_string(),
+                "chase_override": inv.chase_override,
+                "exchange_rate_override": inv.exchange_rate_override,
+                "chase_paused_until": inv.chase_paused_until,
+                "metadata": inv.metadata,
+                "created_at": inv.created_at,
+                "updated_at": inv.updated_at,
+            })
+        }))
+    }
+
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+        version_vector: Option<&Value>,
+    ) -> Result<(), anyhow::Error> {
+        let invoice_number = data.get("invoice_number")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing invoice_number"))?;
+        check_invoice_number(tx, user_id, record_id, invoice_number).await?;
+        
+        let client_name = data.get("client_name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing client_name"))?;
+        let client_id = pushed_client_id(tx, user_id, data).await?;
+        let project_id = pushed_project_id(tx, user_id, data).await?;
+        
+        let line_items = invoice_line_items(tx, user_id, data).await?;
+        
+        // With line items the amount is their taxed total; otherwise
+        // the pushed amount is taken as an untaxed total.
+        let totals = match &line_items {
+            Some((_, totals)) => *totals,
+            None => {
+                let amount = data.get("amount")
+                    .and_then(|v| {
+                        if let Some(s) = v.as_str() {
+                            rust_decimal::Decimal::from_str_exact(s).ok()
+                        } else if let Some(n) = v.as_f64() {
+                            rust_decimal::Decimal::try_from(n).ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("Invalid amount"))?;
+                InvoiceTotals {
+                    subtotal: amount,
+                    tax_total: rust_decimal::Decimal::ZERO,
+                    total: amount,
+                }
+            }
+        };
+        
+        let currency = normalize_currency(
+            data.get("currency")
+                .and_then(|v| v.as_str())
+                .unwrap_or(DEFAULT_CURRENCY),
+        )?;
+        validate_amount(totals.total, &currency)?;
+        
+        // A new invoice may start in any status (it may have been
+        // sent while offline), but it must be a known one
+        let status = match data.get("status").and_then(|v| v.as_str()) {
+            Some(status) => InvoiceStatus::from_str(status)?,
+            None => InvoiceStatus::Draft,
+        };
+        
+        // A missing due date follows the payment terms; an explicit
+        // null keeps the invoice due on receipt
+        let issue_date = pushed_date(data, "issue_date")?.unwrap_or_else(|| chrono::Utc::now().date_naive());
+        let due_date = match pushed_date(data, "due_date")? {
+            Some(due_date) => {
+                context.due_date_rules.check(issue_date, due_date)?;
+                Some(due_date)
+            }
+            None if data.get("due_date").is_none() => {
+                let client_terms = match client_id {
+                    Some(client_id) => clients::client_payment_terms(&mut **tx, user_id, client_id).await?,
+                    None => None,
+                };
+                Some(context.due_date_rules.default_due_date(issue_date, client_terms))
+            }
+            None => None,
+        };
+        
+        sqlx::query!(
+            r#"
+            INSERT INTO invoices (
+                id, user_id, invoice_number, client_name, client_email,
+                amount, currency, status, due_date, issue_date,
+                description, line_items, metadata, last_modified, version_vector,
+                subtotal, tax_total, total, client_id, project_id
+            ) VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, NOW(), $14, $15, $16, $17, $18, $19
+            )
+            "#,
+            record_id,
+            user_id,
+            invoice_number,
+            client_name,
+            data.get("client_email").and_then(|v| v.as_str()),
+            totals.total,
+            currency.as_str(),
+            status.as_str(),
+            due_date,
+            Some(issue_date),
+            data.get("description").and_then(|v| v.as_str()),
+            line_items.as_ref().map(|(items, _)| items),
+            merge_metadata(None, data.get("metadata")),
+            merge_version_vectors(None, version_vector),
+            totals.subtotal,
+            totals.tax_total,
+            totals.total,
+            client_id,
+            project_id,
+        )
+        .execute(&mut **tx)
+        .await?;
+        
+        Ok(())
+    }
+
+    async fn update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        // Renumbering is subject to the same rules as new numbers
+        if let Some(invoice_number) = data.get("invoice_number").and_then(|v| v.as_str()) {
+            let current = sqlx::query_scalar::<_, String>(
+                "SELECT invoice_number FROM invoices WHERE id = $1 AND user_id = $2",
+            )
+            .bind(record_id)
+            .bind(user_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+            if current.as_deref() != Some(invoice_number) {
+                check_invoice_number(tx, user_id, record_id, invoice_number).await?;
+            }
+        }
+        
+        let amount = data.get("amount")
+            .and_then(|v| {
+                if let Some(s) = v.as_str() {
+                    rust_decimal::Decimal::from_str_exact(s).ok()
+                } else if let Some(n) = v.as_f64() {
+                    rust_decimal::Decimal::try_from(n).ok()
+                } else {
+                    None
+                }
+            });
+        
+        let client_id = pushed_client_id(tx, user_id, data).await?;
+        let project_id = pushed_project_id(tx, user_id, data).await?;
+        let line_items = invoice_line_items(tx, user_id, data).await?;
+        
+        // Line items determine the amount; a bare amount is untaxed
+        let totals = match &line_items {
+            Some((_, totals)) => Some(*totals),
+            None => amount.map(|amount| InvoiceTotals {
+                subtotal: amount,
+                tax_total: rust_decimal::Decimal::ZERO,
+                total: amount,
+            }),
+        };
+        
+        let currency = data.get("currency")
+            .and_then(|v| v.as_str())
+            .map(normalize_currency)
+            .transpose()?;
+        
+        // Status changes must follow the invoice lifecycle
+        let status = data.get("status")
+            .and_then(|v| v.as_str())
+            .map(InvoiceStatus::from_str)
+            .transpose()?;
+        if let Some(status) = status {
+            let current = sqlx::query_scalar::<_, InvoiceStatus>(
+                "SELECT status FROM invoices WHERE id = $1 AND user_id = $2 FOR UPDATE",
+            )
+            .bind(record_id)
+            .bind(user_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+            if let Some(current) = current {
+                current.transition(status)?;
+            }
+        }
+        
+        // Due dates are checked whenever they or the issue date change
+        let due_date = pushed_date(data, "due_date")?;
+        let issue_date = pushed_date(data, "issue_date")?;
+        let stored_dates = sqlx::query_as::<_, (chrono::NaiveDate, Option<chrono::NaiveDate>)>(
+            "SELECT issue_date, due_date FROM invoices WHERE id = $1 AND user_id = $2",
+        )
+        .bind(record_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let changed = stored_dates.and_then(|stored| changed_due_date(stored, issue_date, due_date));
+        if let Some((check_issue, check_due)) = changed {
+            context.due_date_rules.check(check_issue, check_due)?;
+        }
+        
+        // Server state in the metadata survives the push, and the version
+        // vector only moves forward
+        let (stored_metadata, stored_version_vector) = sqlx::query_as::<_, (Option<Value>, Option<Value>)>(
+            "SELECT metadata, version_vector FROM invoices WHERE id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(record_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .unwrap_or_default();
+        let metadata = match data.get("metadata") {
+            Some(pushed) => merge_metadata(stored_metadata.as_ref(), Some(pushed)),
+            None => stored_metadata,
+        };
+        let version_vector = merge_version_vectors(stored_version_vector.as_ref(), data.get("version_vector"));
+        
+        // Amounts must be representable in the invoice's (new) currency
+        if let Some(totals) = &totals {
+            let effective_currency = match &currency {
+                Some(currency) => currency.clone(),
+                None => sqlx::query_scalar::<_, String>(
+                    "SELECT currency FROM invoices WHERE id = $1 AND user_id = $2",
+                )
+                .bind(record_id)
+                .bind(user_id)
+                .fetch_optional(&mut **tx)
+                .await?
+                .unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+            };
+            validate_amount(totals.total, &effective_currency)?;
+        }
+        
+        sqlx::query!(
+            r#"
+            UPDATE invoices
+            SET
+                invoice_number = COALESCE($3, invoice_number),
+                client_name = COALESCE($4, client_name),
+                client_email = $5,
+                amount = COALESCE($6, amount),
+                currency = COALESCE($7, currency),
+                status = COALESCE($8, status),
+                due_date = $9,
+                issue_date = COALESCE($10, issue_date),
+                description = $11,
+                line_items = $12,
+                metadata = $13,
+                last_modified = NOW(),
+                version_vector = $14,
+                subtotal = COALESCE($15, subtotal),
+                tax_total = COALESCE($16, tax_total),
+                total = COALESCE($17, total),
+                client_id = $18,
+                project_id = $19,
+                updated_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND is_deleted = false
+            "#,
+            record_id,
+            user_id,
+            data.get("invoice_number").and_then(|v| v.as_str()),
+            data.get("client_name").and_then(|v| v.as_str()),
+            data.get("client_email").and_then(|v| v.as_str()),
+            totals.map(|t| t.total),
+            currency.as_deref(),
+            status.map(|s| s.as_str()),
+            due_date,
+            issue_date,
+            data.get("description").and_then(|v| v.as_str()),
+            line_items.as_ref().map(|(items, _)| items),
+            metadata,
+            version_vector,
+            totals.map(|t| t.subtotal),
+            totals.map(|t| t.tax_total),
+            totals.map(|t| t.total),
+            client_id,
+            project_id,
+        )
+        .execute(&mut **tx)
+        .await?;
+        
+        Ok(())
+    }
+
+    async fn delete(&self, tx: &mut Transaction<'_, Postgres>, user_id: Uuid, record_id: Uuid) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE invoices
+            SET is_deleted = true, last_modified = NOW(), updated_at = NOW()
+            WHERE id = $1 AND user_id = $2
+            "#,
+            record_id,
+            user_id
+        )
+        .execute(&mut **tx)
+        .await?;
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pushed_metadata_keeps_server_state() {
+        let stored = json!({
+            "chase_state": "chasing_level_2",
+            "late_fee": { "amount": "25.00" },
+            "client": { "country_code": "DE" },
+            "tag": "old",
+        });
+
+        // A device pushing back stale or missing server keys can't change them
+        let pushed = json!({ "chase_state": "draft", "client": { "country_code": "FR" } });
+        assert_eq!(
+            merge_metadata(Some(&stored), Some(&pushed)),
+            Some(json!({
+                "chase_state": "chasing_level_2",
+                "late_fee": { "amount": "25.00" },
+                "client": { "country_code": "FR" },
+            }))
+        );
+
+        // Clearing the metadata clears only the device's keys
+        assert_eq!(
+            merge_metadata(Some(&stored), Some(&Value::Null)),
+            Some(json!({ "chase_state": "chasing_level_2", "late_fee": { "amount": "25.00" } }))
+        );
+
+        // New invoices can't start with server state
+        assert_eq!(merge_metadata(None, Some(&json!({ "chase_retry": { "attempts": 5 } }))), Some(json!({})));
+        assert_eq!(merge_metadata(None, None), None);
+    }
+
+    #[test]
+    fn test_pushed_metadata_keeps_the_deposit_proposal_link() {
+        let proposal_id = Uuid::new_v4();
+        let stored = json!({ "proposal_id": proposal_id, "deposit": true });
+
+        // A device dropping the link, or pointing it elsewhere, can't change it
+        let pushed = json!({ "note": "paid by wire" });
+        assert_eq!(
+            merge_metadata(Some(&stored), Some(&pushed)),
+            Some(json!({ "proposal_id": proposal_id, "deposit": true, "note": "paid by wire" }))
+        );
+        let pushed = json!({ "proposal_id": Uuid::new_v4(), "deposit": false });
+        assert_eq!(merge_metadata(Some(&stored), Some(&pushed)), Some(stored.clone()));
+        assert_eq!(merge_metadata(Some(&stored), Some(&Value::Null)), Some(stored));
+    }
+
+    #[test]
+    fn test_due_date_is_checked_when_either_date_changes() {
+        let date = |day| chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let stored = (date(1), Some(date(15)));
+
+        // Pushed back unchanged, or with the issue date left out
+        assert_eq!(changed_due_date(stored, Some(date(1)), Some(date(15))), None);
+        assert_eq!(changed_due_date(stored, None, Some(date(15))), None);
+        // Only the issue date moves: the stored due date is checked against it
+        assert_eq!(changed_due_date(stored, Some(date(12)), Some(date(15))), Some((date(12), date(15))));
+        // Only the due date moves
+        assert_eq!(changed_due_date(stored, None, Some(date(20))), Some((date(1), date(20))));
+        // A cleared due date has nothing to check
+        assert_eq!(changed_due_date(stored, Some(date(12)), None), None);
+        // Setting a due date on an invoice without one
+        assert_eq!(changed_due_date((date(1), None), None, Some(date(15))), Some((date(1), date(15))));
+    }
+
+    #[test]
+    fn test_version_vectors_only_move_forward() {
+        let stored = json!({ "phone": 3, "laptop": 5 });
+        let pushed = json!({ "phone": 4, "laptop": 1, "tablet": "x" });
+
+        assert_eq!(
+            merge_version_vectors(Some(&stored), Some(&pushed)),
+            Some(json!({ "phone": 4, "laptop": 5 }))
+        );
+        assert_eq!(merge_version_vectors(Some(&stored), None), Some(stored.clone()));
+        assert_eq!(merge_version_vectors(None, Some(&Value::Null)), None);
+    }
+}
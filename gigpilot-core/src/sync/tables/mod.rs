@@ -0,0 +1,574 @@
+//! Registry of the tables devices sync.
+//!
+//! Each synced table implements [`SyncableTable`]: how pushed records are
+//! inserted, updated and deleted, and how the server's version of a record
+//! is read back for conflict checks and conflict responses. The push and
+//! conflict code look tables up in [`TABLES`] by the name devices send, so
+//! syncing another table takes one implementation registered there (plus
+//! its sync schema). Before a change is applied, its table authorizes it
+//! (see [`access`]).
+
+mod access;
+mod invoices;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, PgConnection, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::clients;
+use crate::estimates;
+use crate::invoices::due_dates::DueDateRules;
+use crate::models::client::Client;
+use crate::models::estimate::Estimate;
+use crate::models::project::Project;
+use crate::models::time_entry::TimeEntry;
+use crate::models::user_settings::UserSettings;
+use crate::projects;
+use crate::settings;
+use crate::sync::fields::FieldMode;
+use crate::time_entries;
+
+pub use access::{AccessError, Reference};
+pub use invoices::InvoicesTable;
+
+/// Server-managed fields every synced record has.
+const RECORD_COMPUTED_FIELDS: &[&str] = &["user_id", "is_deleted", "created_at", "updated_at"];
+
+/// What applying a pushed change may depend on besides the record itself,
+/// loaded once per push.
+pub struct PushContext<'a> {
+    /// The user's due-date rules for invoices
+    pub due_date_rules: &'a DueDateRules,
+
+    /// How unknown fields of pushed records are treated
+    pub field_mode: FieldMode,
+
+    /// Tables the user syncs end-to-end encrypted
+    pub encrypted_tables: &'a [String],
+}
+
+/// A table devices sync.
+///
+/// Table names are interpolated into SQL by the default methods, so they
+/// must come from an implementation, never from the client.
+#[async_trait]
+pub trait SyncableTable: Send + Sync {
+    /// Table name, as sent by devices (e.g. "invoices").
+    fn name(&self) -> &'static str;
+
+    /// Fields of a pushed record that hold IDs of other synced records.
+    fn references(&self) -> &'static [Reference] {
+        &[]
+    }
+
+    /// Fields of pulled records the server computes or manages itself.
+    ///
+    /// Devices push records back as they pulled them, so these are dropped
+    /// from pushed records rather than reported as unknown (see
+    /// [`fields`](crate::sync::fields)).
+    fn computed_fields(&self) -> &'static [&'static str] {
+        RECORD_COMPUTED_FIELDS
+    }
+
+    /// The account a record belongs to, whoever asks.
+    ///
+    /// # Returns
+    ///
+    /// Returns the owner's user ID, or `None` if no record has this ID.
+    async fn owner(&self, conn: &mut PgConnection, record_id: Uuid) -> Result<Option<Uuid>, anyhow::Error> {
+        let query = format!("SELECT user_id FROM {} WHERE id = $1", self.name());
+        let owner = sqlx::query_scalar::<_, Uuid>(&query)
+            .bind(record_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(owner)
+    }
+
+    /// Checks that a pushed change only touches the user's data: the
+    /// record must be new or the user's, and the [`references`] it sets or
+    /// changes must be the user's live records.
+    ///
+    /// `user_id` is the account the change applies to (the owner, for
+    /// records shared with the pusher).
+    ///
+    /// [`references`]: SyncableTable::references
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AccessError`] if the change reaches another account's
+    /// data, or an error if a query fails.
+    async fn authorize(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: Option<&Value>,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(owner) = self.owner(conn, record_id).await? {
+            if owner != user_id {
+                return Err(AccessError::Record {
+                    table: self.name().to_string(),
+                    id: record_id,
+                }
+                .into());
+            }
+        }
+        if let Some(data) = data {
+            access::check_references(conn, user_id, self.name(), record_id, self.references(), data).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the user has a live (not soft-deleted) record with this ID.
+    async fn exists(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<bool, anyhow::Error> {
+        let query = format!(
+            "SELECT 1 FROM {} WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+            self.name()
+        );
+        let result = sqlx::query_scalar::<_, i32>(&query)
+            .bind(record_id)
+            .bind(user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(result.is_some())
+    }
+
+    /// The `last_modified` and `version_vector` of a live record, compared
+    /// with a pushed update's to detect conflicts.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the user has no live record with this ID.
+    async fn version(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        record_id: Uuid,
+    ) -> Result<Option<(Option<DateTime<Utc>>, Option<Value>)>, anyhow::Error> {
+        let query = format!(
+            "SELECT last_modified, version_vector FROM {} WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+            self.name()
+        );
+        let version = sqlx::query_as::<_, (Option<DateTime<Utc>>, Option<Value>)>(&query)
+            .bind(record_id)
+            .bind(user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(version)
+    }
+
+    /// Serializes the server's version of a record, as devices pull it.
+    ///
+    /// # Returns
+    ///
+    /// Returns the record as JSON (including soft-deleted records), or
+    /// `None` if it does not exist.
+    async fn load(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error>;
+
+    /// Inserts a record pushed by a device.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record is invalid; validation errors are
+    /// reported to the device (see `push::rejected_change`).
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+        version_vector: Option<&Value>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Updates a record from a device push (or from the server's version
+    /// after a conflict).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the update is invalid.
+    async fn update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Soft-deletes a record from a device push.
+    async fn delete(&self, tx: &mut Transaction<'_, Postgres>, user_id: Uuid, record_id: Uuid) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "UPDATE {} SET is_deleted = true, last_modified = NOW() WHERE id = $1 AND user_id = $2",
+            self.name()
+        );
+        sqlx::query(&query)
+            .bind(record_id)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Every synced table.
+pub static TABLES: &[&dyn SyncableTable] = &[
+    &InvoicesTable,
+    &EstimatesTable,
+    &ClientsTable,
+    &ProjectsTable,
+    &TimeEntriesTable,
+    &UserSettingsTable,
+];
+
+/// Looks up a synced table by the name devices send.
+///
+/// # Returns
+///
+/// Returns the table, or `None` if it is not synced.
+pub fn find_table(name: &str) -> Option<&'static dyn SyncableTable> {
+    TABLES.iter().copied().find(|table| table.name() == name)
+}
+
+/// Loads a record of a table mapped to a model and serializes it.
+async fn load_as<T>(conn: &mut PgConnection, table: &str, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error>
+where
+    T: for<'r> FromRow<'r, PgRow> + Serialize + Send + Unpin,
+{
+    let query = format!("SELECT * FROM {} WHERE id = $1 AND user_id = $2", table);
+    let record = sqlx::query_as::<_, T>(&query)
+        .bind(record_id)
+        .bind(user_id)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(record.map(serde_json::to_value).transpose()?)
+}
+
+/// Synced estimates (see [`estimates::apply_pushed_insert`]).
+pub struct EstimatesTable;
+
+#[async_trait]
+impl SyncableTable for EstimatesTable {
+    fn name(&self) -> &'static str {
+        "estimates"
+    }
+
+    /// Totals follow the line items; the invoice is set on conversion.
+    fn computed_fields(&self) -> &'static [&'static str] {
+        &["user_id", "is_deleted", "created_at", "updated_at", "subtotal", "tax_total", "total", "invoice_id"]
+    }
+
+    async fn load(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error> {
+        load_as::<Estimate>(conn, self.name(), user_id, record_id).await
+    }
+
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+        version_vector: Option<&Value>,
+    ) -> Result<(), anyhow::Error> {
+        estimates::apply_pushed_insert(tx, user_id, record_id, data, version_vector).await
+    }
+
+    async fn update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        estimates::apply_pushed_update(tx, user_id, record_id, data).await
+    }
+}
+
+/// Synced clients (see [`clients::apply_pushed_insert`]).
+pub struct ClientsTable;
+
+#[async_trait]
+impl SyncableTable for ClientsTable {
+    fn name(&self) -> &'static str {
+        "clients"
+    }
+
+    async fn load(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error> {
+        load_as::<Client>(conn, self.name(), user_id, record_id).await
+    }
+
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+        version_vector: Option<&Value>,
+    ) -> Result<(), anyhow::Error> {
+        clients::apply_pushed_insert(tx, user_id, record_id, data, version_vector).await
+    }
+
+    async fn update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        clients::apply_pushed_update(tx, user_id, record_id, data).await
+    }
+}
+
+/// Synced projects (see [`projects::apply_pushed_insert`]).
+pub struct ProjectsTable;
+
+#[async_trait]
+impl SyncableTable for ProjectsTable {
+    fn name(&self) -> &'static str {
+        "projects"
+    }
+
+    fn references(&self) -> &'static [Reference] {
+        &[Reference { field: "client_id", table: "clients" }]
+    }
+
+    async fn load(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error> {
+        load_as::<Project>(conn, self.name(), user_id, record_id).await
+    }
+
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+        version_vector: Option<&Value>,
+    ) -> Result<(), anyhow::Error> {
+        projects::apply_pushed_insert(tx, user_id, record_id, data, version_vector).await
+    }
+
+    async fn update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        projects::apply_pushed_update(tx, user_id, record_id, data).await
+    }
+}
+
+/// Synced time entries (see [`time_entries::apply_pushed_insert`]).
+pub struct TimeEntriesTable;
+
+#[async_trait]
+impl SyncableTable for TimeEntriesTable {
+    fn name(&self) -> &'static str {
+        "time_entries"
+    }
+
+    fn references(&self) -> &'static [Reference] {
+        &[Reference { field: "project_id", table: "projects" }]
+    }
+
+    /// The invoice is set when the entry is billed.
+    fn computed_fields(&self) -> &'static [&'static str] {
+        &["user_id", "is_deleted", "created_at", "updated_at", "invoice_id"]
+    }
+
+    async fn load(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error> {
+        load_as::<TimeEntry>(conn, self.name(), user_id, record_id).await
+    }
+
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+        version_vector: Option<&Value>,
+    ) -> Result<(), anyhow::Error> {
+        time_entries::apply_pushed_insert(tx, user_id, record_id, data, version_vector).await
+    }
+
+    async fn update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        time_entries::apply_pushed_update(tx, user_id, record_id, data).await
+    }
+
+    /// Billed entries stay on their invoice.
+    async fn delete(&self, tx: &mut Transaction<'_, Postgres>, user_id: Uuid, record_id: Uuid) -> Result<(), anyhow::Error> {
+        time_entries::apply_pushed_delete(tx, user_id, record_id).await
+    }
+}
+
+/// The user's profile settings, synced as a single record whose ID is the
+/// user's ID (see [`settings::apply_pushed_settings`]).
+///
+/// Settings have no soft-delete or version vector; conflicts are detected
+/// on `updated_at`, and they can't be deleted.
+pub struct UserSettingsTable;
+
+#[async_trait]
+impl SyncableTable for UserSettingsTable {
+    fn name(&self) -> &'static str {
+        settings::SYNC_TABLE
+    }
+
+    fn computed_fields(&self) -> &'static [&'static str] {
+        &["user_id", "created_at", "updated_at"]
+    }
+
+    /// The settings record of a user has the user's ID.
+    async fn owner(&self, _conn: &mut PgConnection, record_id: Uuid) -> Result<Option<Uuid>, anyhow::Error> {
+        Ok(Some(record_id))
+    }
+
+    async fn exists(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<bool, anyhow::Error> {
+        Ok(self.version(conn, user_id, record_id).await?.is_some())
+    }
+
+    async fn version(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        record_id: Uuid,
+    ) -> Result<Option<(Option<DateTime<Utc>>, Option<Value>)>, anyhow::Error> {
+        if record_id != user_id {
+            return Ok(None);
+        }
+        let updated_at = sqlx::query_scalar::<_, DateTime<Utc>>("SELECT updated_at FROM user_settings WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(updated_at.map(|updated_at| (Some(updated_at), None)))
+    }
+
+    async fn load(&self, conn: &mut PgConnection, user_id: Uuid, record_id: Uuid) -> Result<Option<Value>, anyhow::Error> {
+        if record_id != user_id {
+            return Ok(None);
+        }
+        let stored = sqlx::query_as::<_, UserSettings>("SELECT * FROM user_settings WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        stored.as_ref().map(settings::sync_record).transpose()
+    }
+
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+        _version_vector: Option<&Value>,
+    ) -> Result<(), anyhow::Error> {
+        settings::apply_pushed_settings(tx, user_id, record_id, data).await
+    }
+
+    async fn update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        _context: &PushContext<'_>,
+        user_id: Uuid,
+        record_id: Uuid,
+        data: &Value,
+    ) -> Result<(), anyhow::Error> {
+        settings::apply_pushed_settings(tx, user_id, record_id, data).await
+    }
+
+    async fn delete(&self, _tx: &mut Transaction<'_, Postgres>, _user_id: Uuid, _record_id: Uuid) -> Result<(), anyhow::Error> {
+        anyhow::bail!("user settings can't be deleted")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::SYNCED_TABLES;
+    use crate::sync::schema::{has_field, has_schema, SYNC_SCHEMA_VERSION};
+
+    #[test]
+    fn test_registry_covers_synced_tables() {
+        // Record tables, plus the settings record
+        for name in SYNCED_TABLES {
+            assert_eq!(find_table(name).map(|table| table.name()), Some(*name));
+        }
+        assert_eq!(TABLES.len(), SYNCED_TABLES.len() + 1);
+        assert_eq!(find_table("user_settings").map(|table| table.name()), Some("user_settings"));
+        assert!(find_table("users").is_none());
+        assert!(find_table("Invoices").is_none());
+    }
+
+    #[test]
+    fn test_every_table_has_a_sync_schema() {
+        for table in TABLES {
+            assert!(has_schema(SYNC_SCHEMA_VERSION, table.name()), "{} has no sync schema", table.name());
+        }
+    }
+
+    #[test]
+    fn test_references_point_at_record_tables() {
+        assert_eq!(
+            find_table("invoices").unwrap().references(),
+            &[
+                Reference { field: "client_id", table: "clients" },
+                Reference { field: "project_id", table: "projects" },
+            ]
+        );
+        // Referenced tables are checked for live records of the user
+        for table in TABLES {
+            for reference in table.references() {
+                assert!(
+                    SYNCED_TABLES.contains(&reference.table),
+                    "{}.{} refers to {}",
+                    table.name(),
+                    reference.field,
+                    reference.table
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_computed_fields_are_not_pushable() {
+        // A field is either taken from devices or computed, never both
+        for table in TABLES {
+            for field in table.computed_fields() {
+                assert_ne!(
+                    has_field(SYNC_SCHEMA_VERSION, table.name(), field),
+                    Some(true),
+                    "{}.{} is computed",
+                    table.name(),
+                    field
+                );
+            }
+        }
+    }
+}
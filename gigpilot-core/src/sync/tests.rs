@@ -1,8 +1,9 @@
 #[cfg(test)]
 mod tests {
+    use crate::sync::editing::{active_editing, start_editing, stop_editing, EditingRequest, EDITING_TTL_SECONDS};
     use crate::sync::push::push_changes;
-    use crate::sync::types::{PushChange, PushRequest};
-    use chrono::Utc;
+    use crate::sync::types::{PushChange, PushMode, PushRequest};
+    use chrono::{Duration, Utc};
     use serde_json::json;
     use sqlx::PgPool;
     use std::str::FromStr;
@@ -20,6 +21,26 @@ mod tests {
         Ok(pool)
     }
 
+    /// Creates a user for tests whose tables reference `users`.
+    async fn create_test_user(pool: &PgPool) -> Uuid {
+        let user_id = Uuid::new_v4();
+        sqlx::query("INSERT INTO users (id, email, password_hash) VALUES ($1, $2, 'x')")
+            .bind(user_id)
+            .bind(format!("{}@example.com", user_id))
+            .execute(pool)
+            .await
+            .expect("User should be created");
+        user_id
+    }
+
+    fn editing_request(record_id: Uuid, device_id: &str) -> EditingRequest {
+        EditingRequest {
+            table: "invoices".to_string(),
+            id: record_id,
+            device_id: device_id.to_string(),
+        }
+    }
+
     /// Test that pushing a change updates the database.
     /// 
     /// This test verifies that:
@@ -56,8 +77,16 @@ mod tests {
                 deleted: false,
                 device_id: Some("test-device".to_string()),
                 version_vector: None,
+                patch: None,
+                encrypted: None,
             }],
             device_id: Some("test-device".to_string()),
+            schema_version: None,
+            locale: None,
+            mode: PushMode::BestEffort,
+            dry_run: false,
+            app_version: None,
+            platform: None,
         };
         
         // Push the change
@@ -145,8 +174,16 @@ mod tests {
                 deleted: false,
                 device_id: Some("test-device".to_string()),
                 version_vector: None,
+                patch: None,
+                encrypted: None,
             }],
             device_id: Some("test-device".to_string()),
+            schema_version: None,
+            locale: None,
+            mode: PushMode::BestEffort,
+            dry_run: false,
+            app_version: None,
+            platform: None,
         };
         
         let response = push_changes(&pool, test_user_id, push_request)
@@ -166,6 +203,385 @@ mod tests {
         .expect("Invoice should exist");
         
         assert_eq!(invoice.client_name, "Updated Client");
+        
+        // The change keeps the invoice as it was before the update
+        let sync_change = sqlx::query!(
+            "SELECT old_data, new_data FROM sync_changes WHERE record_id = $1 AND user_id = $2",
+            invoice_id,
+            test_user_id
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Sync change should be recorded");
+        
+        let old_data = sync_change.old_data.expect("Update should keep the pre-image");
+        assert_eq!(old_data["client_name"], "Original Client");
+        assert_eq!(sync_change.new_data.unwrap()["client_name"], "Updated Client");
     }
-}
 
+    /// Test that a failing change rolls back an atomic push as a whole.
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_atomic_push_rolls_back_on_failure() {
+        let pool = create_test_pool().await.expect("Failed to create test pool");
+        let test_user_id = Uuid::new_v4();
+        let invoice_id = Uuid::new_v4();
+        let unknown_id = Uuid::new_v4();
+        
+        let push_request = PushRequest {
+            changes: vec![
+                PushChange {
+                    table: "invoices".to_string(),
+                    id: invoice_id,
+                    data: Some(json!({
+                        "id": invoice_id,
+                        "invoice_number": "INV-002",
+                        "client_name": "Test Client",
+                        "amount": "100.00",
+                        "currency": "USD",
+                        "status": "draft",
+                        "issue_date": "2024-01-01",
+                        "last_modified": Utc::now().to_rfc3339(),
+                    })),
+                    deleted: false,
+                    device_id: Some("test-device".to_string()),
+                    version_vector: None,
+                    patch: None,
+                    encrypted: None,
+                },
+                PushChange {
+                    table: "unknown_table".to_string(),
+                    id: unknown_id,
+                    data: Some(json!({ "id": unknown_id })),
+                    deleted: false,
+                    device_id: Some("test-device".to_string()),
+                    version_vector: None,
+                    patch: None,
+                    encrypted: None,
+                },
+            ],
+            device_id: Some("test-device".to_string()),
+            schema_version: None,
+            locale: None,
+            mode: PushMode::Atomic,
+            dry_run: false,
+            app_version: None,
+            platform: None,
+        };
+        
+        let response = push_changes(&pool, test_user_id, push_request)
+            .await
+            .expect("Push should succeed");
+        
+        assert!(response.rolled_back);
+        assert_eq!(response.applied, 0);
+        assert_eq!(response.rejected.len(), 1);
+        assert_eq!(response.rejected[0].id, unknown_id);
+        
+        // The valid invoice was rolled back with the rest of the push
+        let invoice = sqlx::query!(
+            "SELECT id FROM invoices WHERE id = $1 AND user_id = $2",
+            invoice_id,
+            test_user_id
+        )
+        .fetch_optional(&pool)
+        .await
+        .expect("Query should succeed");
+        
+        assert!(invoice.is_none(), "Invoice should not be created");
+    }
+
+    /// Test that a dry run reports what the push would do and saves nothing.
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_dry_run_push_saves_nothing() {
+        let pool = create_test_pool().await.expect("Failed to create test pool");
+        let test_user_id = Uuid::new_v4();
+        let invoice_id = Uuid::new_v4();
+        let unknown_id = Uuid::new_v4();
+        
+        let push_request = PushRequest {
+            changes: vec![
+                PushChange {
+                    table: "invoices".to_string(),
+                    id: invoice_id,
+                    data: Some(json!({
+                        "id": invoice_id,
+                        "invoice_number": "INV-003",
+                        "client_name": "Test Client",
+                        "amount": "100.00",
+                        "currency": "USD",
+                        "status": "draft",
+                        "issue_date": "2024-01-01",
+                        "last_modified": Utc::now().to_rfc3339(),
+                    })),
+                    deleted: false,
+                    device_id: Some("test-device".to_string()),
+                    version_vector: None,
+                    patch: None,
+                    encrypted: None,
+                },
+                PushChange {
+                    table: "unknown_table".to_string(),
+                    id: unknown_id,
+                    data: Some(json!({ "id": unknown_id })),
+                    deleted: false,
+                    device_id: Some("test-device".to_string()),
+                    version_vector: None,
+                    patch: None,
+                    encrypted: None,
+                },
+            ],
+            device_id: Some("test-device".to_string()),
+            schema_version: None,
+            locale: None,
+            mode: PushMode::Atomic,
+            dry_run: true,
+            app_version: None,
+            platform: None,
+        };
+        
+        let response = push_changes(&pool, test_user_id, push_request)
+            .await
+            .expect("Push should succeed");
+        
+        // Every change is reported, and the real push would roll back
+        assert!(response.dry_run);
+        assert!(response.rolled_back);
+        assert_eq!(response.applied, 1);
+        assert_eq!(response.rejected.len(), 1);
+        assert_eq!(response.rejected[0].id, unknown_id);
+        
+        let invoice = sqlx::query!(
+            "SELECT id FROM invoices WHERE id = $1 AND user_id = $2",
+            invoice_id,
+            test_user_id
+        )
+        .fetch_optional(&pool)
+        .await
+        .expect("Query should succeed");
+        
+        assert!(invoice.is_none(), "Invoice should not be created");
+    }
+
+    /// Test that a change referring to another account's client is rejected
+    /// on its own.
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_push_rejects_cross_tenant_reference() {
+        let pool = create_test_pool().await.expect("Failed to create test pool");
+        let test_user_id = Uuid::new_v4();
+        let other_user_id = Uuid::new_v4();
+        let other_client_id = Uuid::new_v4();
+        let invoice_id = Uuid::new_v4();
+        
+        sqlx::query("INSERT INTO clients (id, user_id, name) VALUES ($1, $2, 'Other Client')")
+            .bind(other_client_id)
+            .bind(other_user_id)
+            .execute(&pool)
+            .await
+            .expect("Client should be created");
+        
+        let push_request = PushRequest {
+            changes: vec![PushChange {
+                table: "invoices".to_string(),
+                id: invoice_id,
+                data: Some(json!({
+                    "id": invoice_id,
+                    "invoice_number": "INV-004",
+                    "client_name": "Other Client",
+                    "client_id": other_client_id,
+                    "amount": "100.00",
+                    "currency": "USD",
+                    "status": "draft",
+                    "issue_date": "2024-01-01",
+                    "last_modified": Utc::now().to_rfc3339(),
+                })),
+                deleted: false,
+                device_id: Some("test-device".to_string()),
+                version_vector: None,
+                patch: None,
+                encrypted: None,
+            }],
+            device_id: Some("test-device".to_string()),
+            schema_version: None,
+            locale: None,
+            mode: PushMode::BestEffort,
+            dry_run: false,
+            app_version: None,
+            platform: None,
+        };
+        
+        let response = push_changes(&pool, test_user_id, push_request)
+            .await
+            .expect("Push should succeed");
+        
+        assert_eq!(response.applied, 0);
+        assert_eq!(response.rejected.len(), 1);
+        assert_eq!(response.rejected[0].code, "reference_forbidden");
+        assert_eq!(response.rejected[0].details.as_ref().unwrap()["field"], "client_id");
+    }
+
+    /// Test that pushed settings are checked like those sent to the API:
+    /// an unknown timezone, which would silently turn off the send window,
+    /// is rejected as an invalid payload and nothing is stored.
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_push_rejects_unknown_timezone() {
+        let pool = create_test_pool().await.expect("Failed to create test pool");
+        let test_user_id = create_test_user(&pool).await;
+
+        let push_request = PushRequest {
+            changes: vec![PushChange {
+                table: "user_settings".to_string(),
+                id: test_user_id,
+                data: Some(json!({ "id": test_user_id, "timezone": "Not/AZone" })),
+                deleted: false,
+                device_id: Some("test-device".to_string()),
+                version_vector: None,
+                patch: None,
+                encrypted: None,
+            }],
+            device_id: Some("test-device".to_string()),
+            schema_version: None,
+            locale: None,
+            mode: PushMode::BestEffort,
+            dry_run: false,
+            app_version: None,
+            platform: None,
+        };
+
+        let response = push_changes(&pool, test_user_id, push_request)
+            .await
+            .expect("Push should succeed");
+
+        assert_eq!(response.applied, 0);
+        assert_eq!(response.rejected.len(), 1);
+        assert_eq!(response.rejected[0].code, "invalid_payload");
+        assert!(response.rejected[0].details.as_ref().unwrap()["fields"][0]["error"]
+            .as_str()
+            .unwrap()
+            .contains("Not/AZone"));
+
+        let timezone: Option<Option<String>> =
+            sqlx::query_scalar("SELECT timezone FROM user_settings WHERE user_id = $1")
+                .bind(test_user_id)
+                .fetch_optional(&pool)
+                .await
+                .expect("Query should succeed");
+        assert_eq!(timezone.flatten(), None);
+    }
+
+    /// Test that an editing signal is shown to the user's other devices
+    /// only, and that refreshing it extends it without restarting it.
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_editing_signal_is_seen_by_other_devices() {
+        let pool = create_test_pool().await.expect("Failed to create test pool");
+        let test_user_id = create_test_user(&pool).await;
+        let request = editing_request(Uuid::new_v4(), "laptop");
+
+        let started = start_editing(&pool, test_user_id, &request)
+            .await
+            .expect("Editing should start");
+        assert!(started.expires_at > Utc::now() + Duration::seconds(EDITING_TTL_SECONDS - 5));
+
+        let seen = active_editing(&pool, test_user_id, Some("phone"))
+            .await
+            .expect("Query should succeed");
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].record_id, request.id);
+        assert_eq!(seen[0].device_id, "laptop");
+
+        // The editing device doesn't see its own signal
+        let own = active_editing(&pool, test_user_id, Some("laptop"))
+            .await
+            .expect("Query should succeed");
+        assert!(own.is_empty());
+
+        let refreshed = start_editing(&pool, test_user_id, &request)
+            .await
+            .expect("Editing should refresh");
+        assert_eq!(refreshed.started_at, started.started_at);
+        assert!(refreshed.expires_at >= started.expires_at);
+    }
+
+    /// Test that a signal not refreshed within its TTL stops being shown
+    /// and is cleaned up, and that editing again after it lapsed starts a
+    /// new signal.
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_expired_editing_signal_is_dropped() {
+        let pool = create_test_pool().await.expect("Failed to create test pool");
+        let test_user_id = create_test_user(&pool).await;
+        let request = editing_request(Uuid::new_v4(), "laptop");
+        let lapsed_start = Utc::now() - Duration::seconds(EDITING_TTL_SECONDS * 2);
+
+        let expire = |pool: PgPool| async move {
+            sqlx::query("UPDATE editing_sessions SET started_at = $2, expires_at = $3 WHERE user_id = $1")
+                .bind(test_user_id)
+                .bind(lapsed_start)
+                .bind(Utc::now() - Duration::seconds(1))
+                .execute(&pool)
+                .await
+                .expect("Signal should be expired");
+        };
+
+        start_editing(&pool, test_user_id, &request)
+            .await
+            .expect("Editing should start");
+        expire(pool.clone()).await;
+
+        // Editing again restarts the lapsed signal rather than extending it
+        let restarted = start_editing(&pool, test_user_id, &request)
+            .await
+            .expect("Editing should start again");
+        assert!(restarted.started_at > lapsed_start);
+        expire(pool.clone()).await;
+
+        let seen = active_editing(&pool, test_user_id, Some("phone"))
+            .await
+            .expect("Query should succeed");
+        assert!(seen.is_empty());
+
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM editing_sessions WHERE user_id = $1")
+            .bind(test_user_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Query should succeed");
+        assert_eq!(remaining, 0, "Expired signals should be deleted");
+    }
+
+    /// Test that stopping releases only the device's own signal.
+    #[tokio::test]
+    #[ignore] // Requires database setup
+    async fn test_stop_editing_clears_signal() {
+        let pool = create_test_pool().await.expect("Failed to create test pool");
+        let test_user_id = create_test_user(&pool).await;
+        let record_id = Uuid::new_v4();
+        let laptop = editing_request(record_id, "laptop");
+        let tablet = editing_request(record_id, "tablet");
+
+        start_editing(&pool, test_user_id, &laptop)
+            .await
+            .expect("Editing should start");
+        start_editing(&pool, test_user_id, &tablet)
+            .await
+            .expect("Editing should start");
+
+        stop_editing(&pool, test_user_id, &laptop)
+            .await
+            .expect("Editing should stop");
+
+        let seen = active_editing(&pool, test_user_id, Some("phone"))
+            .await
+            .expect("Query should succeed");
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].device_id, "tablet");
+
+        // Stopping again is harmless
+        stop_editing(&pool, test_user_id, &laptop)
+            .await
+            .expect("Stopping twice should succeed");
+    }
+}
@@ -4,31 +4,109 @@ use serde_json::Value;
 use uuid::Uuid;
 
 use crate::models::sync_change::SyncOperation;
+use crate::sync::editing::EditingIndicator;
+use crate::sync::encryption::EncryptedPayload;
+use crate::sync::patch::PatchOperation;
 
 /// Pull sync request from client.
 /// 
-/// WatermelonDB-compatible pull request that includes the last
-/// synchronization timestamp to fetch incremental changes.
+/// WatermelonDB-compatible pull request that includes the cursor of the
+/// last pull to fetch incremental changes. Older clients send the last
+/// synchronization timestamp instead.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PullRequest {
-    /// Timestamp of the last successful pull (None for first sync)
+    /// Timestamp of the last successful pull (None for first sync); only
+    /// used without `last_sequence_number`
     pub last_pulled_at: Option<DateTime<Utc>>,
     
+    /// `sequence_number` returned by the last successful pull or snapshot
+    #[serde(default)]
+    pub last_sequence_number: Option<i64>,
+    
     /// Optional device ID for tracking
     pub device_id: Option<String>,
+    
+    /// Comma-separated tables to pull (e.g. "clients,projects"); every
+    /// table when omitted
+    #[serde(default)]
+    pub tables: Option<String>,
+    
+    /// Most changes to return; every change when omitted
+    #[serde(default)]
+    pub limit: Option<i64>,
+    
+    /// `cursor` returned by the previous page of this pull
+    #[serde(default)]
+    pub cursor: Option<String>,
+    
+    /// App version of the device (e.g. "2.4.1"), kept with its checkpoint
+    #[serde(default)]
+    pub app_version: Option<String>,
+    
+    /// Platform of the device (e.g. "ios"), kept with its checkpoint
+    #[serde(default)]
+    pub platform: Option<String>,
+    
+    /// Send updates of records the device already has as JSON patches
+    /// (in each table's `patched` list) where the server can
+    #[serde(default)]
+    pub patches: bool,
 }
 
 /// Pull sync response to client.
 /// 
-/// Returns all changes that occurred after the last_pulled_at timestamp,
-/// organized by table name for WatermelonDB compatibility.
+/// Returns all changes that occurred after the request's cursor, organized
+/// by table name for WatermelonDB compatibility.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PullResponse {
     /// Changes grouped by table name
     pub changes: Value, // { "invoices": { "created": [...], "updated": [...], "deleted": [...] } }
     
-    /// Timestamp of this pull (for next sync)
+    /// Timestamp of this pull (for older clients' next sync)
     pub timestamp: DateTime<Utc>,
+    
+    /// Cursor for the next pull's `last_sequence_number`; every change up
+    /// to it has been returned (absent when a resync is required)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_number: Option<i64>,
+    
+    /// Whether more changes are waiting; pull again with `cursor` to get
+    /// the next page
+    #[serde(default)]
+    pub has_more: bool,
+    
+    /// Cursor for the next page, when `has_more` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    
+    /// Whether the incremental changes are complete for this device
+    #[serde(default)]
+    pub status: PullStatus,
+    
+    /// Where to fetch a full snapshot when `status` is `ResyncRequired`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_url: Option<String>,
+    
+    /// Records currently being edited on the user's other devices
+    #[serde(default)]
+    pub editing: Vec<EditingIndicator>,
+    
+    /// Sync schema version the server expects pushed records to follow
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+/// Outcome of a pull request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullStatus {
+    /// Incremental changes are complete
+    #[default]
+    Ok,
+    
+    /// The device's last pull predates the change-log retention horizon.
+    /// The client must discard local state and load a full snapshot.
+    ResyncRequired,
 }
 
 /// Single change record for push operations.
@@ -53,6 +131,16 @@ pub struct PushChange {
     
     /// Optional version vector for conflict detection
     pub version_vector: Option<Value>,
+    
+    /// JSON Patch (RFC 6902) to apply to the server's record instead of
+    /// sending `data`, for updates (see [`crate::sync::patch`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<Vec<PatchOperation>>,
+
+    /// End-to-end encrypted record, sent instead of `data` to tables the
+    /// user syncs encrypted (see [`crate::sync::encryption`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<EncryptedPayload>,
 }
 
 /// Push sync request from client.
@@ -65,6 +153,47 @@ pub struct PushRequest {
     
     /// Optional device ID
     pub device_id: Option<String>,
+    
+    /// Sync schema version the client was built against (defaults to the
+    /// current version)
+    #[serde(default)]
+    pub schema_version: Option<u32>,
+
+    /// How localized numbers and dates in the changes are written, e.g.
+    /// `de-DE` (only ISO dates and plain decimals are read without one)
+    #[serde(default)]
+    pub locale: Option<String>,
+    
+    /// Whether a failing change undoes the whole push (`atomic`) or only
+    /// itself (`best_effort`, the default)
+    #[serde(default)]
+    pub mode: PushMode,
+    
+    /// Check the changes without saving them: the response reports what
+    /// the push would do, and nothing is written
+    #[serde(default)]
+    pub dry_run: bool,
+    
+    /// App version of the device (e.g. "2.4.1"), kept with its checkpoint
+    #[serde(default)]
+    pub app_version: Option<String>,
+    
+    /// Platform of the device (e.g. "ios"), kept with its checkpoint
+    #[serde(default)]
+    pub platform: Option<String>,
+}
+
+/// How a push treats changes that fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PushMode {
+    /// Apply every change that can be applied and report the others
+    #[default]
+    BestEffort,
+    
+    /// Apply all changes or none: the first failing change rolls the
+    /// whole push back
+    Atomic,
 }
 
 /// Push sync response to client.
@@ -81,10 +210,67 @@ pub struct PushResponse {
     /// Array of conflicted change IDs
     pub conflicted_ids: Vec<Uuid>,
     
+    /// The version the server kept for each conflicted change, so the
+    /// client can replace its local copy without waiting for a pull
+    #[serde(default)]
+    pub conflict_versions: Vec<ConflictVersion>,
+    
+    /// Changes that were not applied, with the reason; for a rolled-back
+    /// atomic push, the change that caused it
+    #[serde(default)]
+    pub rejected: Vec<RejectedChange>,
+    
+    /// Whether an atomic push was rolled back, leaving nothing applied
+    /// (for a dry run, whether it would be)
+    #[serde(default)]
+    pub rolled_back: bool,
+    
+    /// Whether this was a dry run: the counts and lists say what the push
+    /// would do, and nothing was saved
+    #[serde(default)]
+    pub dry_run: bool,
+    
     /// Timestamp of this push
     pub timestamp: DateTime<Utc>,
 }
 
+/// The server's version of a record after a conflict was resolved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictVersion {
+    /// Name of the table
+    pub table: String,
+    
+    /// ID of the record
+    pub id: Uuid,
+    
+    /// The record as now stored on the server, in the same shape as pulled
+    /// records
+    pub record: Value,
+}
+
+/// A pushed change the server refused to apply.
+/// 
+/// The client should keep its local copy and show `error` to the user,
+/// or drop the change if it cannot be fixed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedChange {
+    /// Name of the table the change targeted
+    pub table: String,
+    
+    /// ID of the record
+    pub id: Uuid,
+    
+    /// Machine-readable reason (e.g. "invalid_status_transition")
+    pub code: String,
+    
+    /// Human-readable reason
+    pub error: String,
+    
+    /// Extra context for the reason (e.g. `from` and `to` statuses)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
 /// Conflict resolution strategy.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum ConflictStrategy {
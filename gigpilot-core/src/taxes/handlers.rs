@@ -0,0 +1,76 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::models::tax_rate::{CreateTaxRate, TaxRate};
+use crate::taxes::{create_tax_rate, delete_tax_rate, list_tax_rates, validate_create_tax_rate};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// List tax rates endpoint handler.
+///
+/// Handles GET requests to `/api/tax-rates`.
+pub async fn list_tax_rates_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+) -> Result<Json<Vec<TaxRate>>, StatusCode> {
+    let rates = list_tax_rates(&pool, user_id).await.map_err(|e| {
+        error!("Failed to list tax rates for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rates))
+}
+
+/// Create tax rate endpoint handler.
+///
+/// Handles POST requests to `/api/tax-rates`. Invalid requests get `422`
+/// and a name the user already has `409`.
+pub async fn create_tax_rate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateTaxRate>,
+) -> Result<(StatusCode, Json<TaxRate>), (StatusCode, Json<Value>)> {
+    if let Err(message) = validate_create_tax_rate(&request) {
+        return Err(error_response(StatusCode::UNPROCESSABLE_ENTITY, &message));
+    }
+
+    let rate = create_tax_rate(&pool, user_id, request)
+        .await
+        .map_err(|e| {
+            error!("Failed to create tax rate for user {}: {}", user_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create tax rate")
+        })?
+        .ok_or_else(|| error_response(StatusCode::CONFLICT, "a tax rate with this name already exists"))?;
+
+    Ok((StatusCode::CREATED, Json(rate)))
+}
+
+/// Delete tax rate endpoint handler.
+///
+/// Handles DELETE requests to `/api/tax-rates/:id`.
+pub async fn delete_tax_rate_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = delete_tax_rate(&pool, user_id, id).await.map_err(|e| {
+        error!("Failed to delete tax rate {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
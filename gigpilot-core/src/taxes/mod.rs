@@ -0,0 +1,184 @@
+pub mod handlers;
+
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::currency::Percent;
+use crate::models::line_item::LineItem;
+use crate::models::tax_rate::{CreateTaxRate, TaxRate};
+
+/// Lists a user's saved tax rates.
+pub async fn list_tax_rates(pool: &PgPool, user_id: Uuid) -> Result<Vec<TaxRate>, anyhow::Error> {
+    let rates = sqlx::query_as::<_, TaxRate>(
+        "SELECT * FROM tax_rates WHERE user_id = $1 ORDER BY name ASC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rates)
+}
+
+/// Longest tax rate name accepted.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Validates a tax rate creation request.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the request is valid, or a message describing the
+/// first invalid field.
+pub fn validate_create_tax_rate(request: &CreateTaxRate) -> Result<(), String> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("name must be at most {} characters", MAX_NAME_LENGTH));
+    }
+    if !request.rate.is_rate() {
+        return Err("rate must be a percentage between 0 and 100".to_string());
+    }
+    Ok(())
+}
+
+/// Creates a tax rate for a user.
+///
+/// Marking a rate as default clears the flag on the user's other rates.
+/// The request must have passed [`validate_create_tax_rate`].
+///
+/// # Returns
+///
+/// Returns the new `TaxRate`, or `None` if the user already has a rate
+/// with that name (nothing is changed then).
+pub async fn create_tax_rate(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateTaxRate,
+) -> Result<Option<TaxRate>, anyhow::Error> {
+    let is_default = request.is_default.unwrap_or(false);
+    let mut tx = pool.begin().await?;
+
+    if is_default {
+        sqlx::query("UPDATE tax_rates SET is_default = false WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let rate = sqlx::query_as::<_, TaxRate>(
+        r#"
+        INSERT INTO tax_rates (user_id, name, rate, is_default)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id, name) DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(request.name.trim())
+    .bind(request.rate)
+    .bind(is_default)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    // Leaves the other rates' default flag alone
+    let Some(rate) = rate else {
+        return Ok(None);
+    };
+
+    tx.commit().await?;
+    Ok(Some(rate))
+}
+
+/// Deletes one of a user's tax rates.
+///
+/// # Returns
+///
+/// Returns `true` if a rate was deleted.
+pub async fn delete_tax_rate(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM tax_rates WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Fills in `tax_rate` for line items that reference a saved tax rate.
+///
+/// Explicit `tax_rate` values on a line take precedence over the reference.
+///
+/// # Arguments
+///
+/// * `executor` - Database executor (pool or transaction)
+/// * `user_id` - ID of the user owning the tax rates
+/// * `items` - Line items to resolve in place
+///
+/// # Errors
+///
+/// Returns an error if a line references a tax rate the user does not own.
+pub async fn resolve_tax_rates<'a, E>(
+    executor: E,
+    user_id: Uuid,
+    items: &mut [LineItem],
+) -> Result<(), anyhow::Error>
+where
+    E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+{
+    let ids: Vec<Uuid> = items
+        .iter()
+        .filter(|item| item.tax_rate.is_none())
+        .filter_map(|item| item.tax_rate_id)
+        .collect();
+
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let rates: HashMap<Uuid, Percent> = sqlx::query_as::<_, (Uuid, Percent)>(
+        "SELECT id, rate FROM tax_rates WHERE user_id = $1 AND id = ANY($2)",
+    )
+    .bind(user_id)
+    .bind(&ids)
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .collect();
+
+    for item in items.iter_mut().filter(|item| item.tax_rate.is_none()) {
+        if let Some(id) = item.tax_rate_id {
+            let rate = rates
+                .get(&id)
+                .ok_or_else(|| anyhow::anyhow!("Unknown tax_rate_id: {}", id))?;
+            item.tax_rate = Some(*rate);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    fn request(name: &str, rate: i64) -> CreateTaxRate {
+        CreateTaxRate {
+            name: name.to_string(),
+            rate: Percent::from(Decimal::from(rate)),
+            is_default: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_create_tax_rate() {
+        assert!(validate_create_tax_rate(&request("VAT 20%", 20)).is_ok());
+        assert!(validate_create_tax_rate(&request("Exempt", 0)).is_ok());
+        assert!(validate_create_tax_rate(&request("  ", 20)).is_err());
+        assert!(validate_create_tax_rate(&request(&"x".repeat(101), 20)).is_err());
+        assert!(validate_create_tax_rate(&request("Too much", 101)).is_err());
+        assert!(validate_create_tax_rate(&request("Negative", -5)).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//! Fixtures shared by unit tests.
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::models::invoice::Invoice;
+use crate::repo::memory::sample_invoice;
+
+/// The date `y`-`m`-`d`, which must exist.
+pub fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+/// An unpaid-or-part-paid invoice with amounts in cents.
+///
+/// # Arguments
+///
+/// * `number` - Invoice number
+/// * `currency` - Currency code
+/// * `total` - Total in cents
+/// * `paid` - Amount paid in cents
+/// * `due` - Due date, if any
+pub fn invoice(number: &str, currency: &str, total: i64, paid: i64, due: Option<NaiveDate>) -> Invoice {
+    let mut invoice = sample_invoice(Uuid::new_v4(), date(2024, 2, 1), Decimal::new(total, 2));
+    invoice.invoice_number = number.to_string();
+    invoice.currency = currency.to_string();
+    invoice.amount_paid = Decimal::new(paid, 2);
+    invoice.due_date = due;
+    invoice
+}
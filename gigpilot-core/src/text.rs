@@ -0,0 +1,23 @@
+//! Helpers for text fields entered by users.
+
+/// Trims an optional text field, treating blank values as absent.
+pub(crate) fn non_blank<S: AsRef<str>>(value: Option<S>) -> Option<String> {
+    value
+        .as_ref()
+        .map(|v| v.as_ref().trim())
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_blank() {
+        assert_eq!(non_blank(Some("  x ".to_string())), Some("x".to_string()));
+        assert_eq!(non_blank(Some("   ".to_string())), None);
+        assert_eq!(non_blank(Some(" y")), Some("y".to_string()));
+        assert_eq!(non_blank(None::<String>), None);
+    }
+}
@@ -0,0 +1,195 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::models::time_entry::{CreateTimeEntry, TimeEntry, UpdateTimeEntry};
+use crate::time_entries::{
+    create_time_entry, delete_time_entry, find_time_entry, is_billed, list_time_entries, merge_update,
+    project_defaults, resolve_entry, update_time_entry, ProjectDefaults, ResolvedEntry,
+};
+
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// Query parameters for listing time entries.
+#[derive(Debug, Deserialize)]
+pub struct ListTimeEntriesQuery {
+    /// Only list the entries of this project
+    pub project_id: Option<Uuid>,
+
+    /// Only list entries on or after this date
+    pub from: Option<NaiveDate>,
+
+    /// Only list entries on or before this date
+    pub to: Option<NaiveDate>,
+
+    /// Only list billable (or non-billable) entries
+    pub billable: Option<bool>,
+}
+
+/// List time entries endpoint handler.
+///
+/// Handles GET requests to `/api/time-entries`, optionally filtered with
+/// `?project_id=`, `?from=`, `?to=` and `?billable=`.
+pub async fn list_time_entries_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Query(query): Query<ListTimeEntriesQuery>,
+) -> Result<Json<Vec<TimeEntry>>, StatusCode> {
+    let entries = list_time_entries(&pool, user_id, query.project_id, query.from, query.to, query.billable)
+        .await
+        .map_err(|e| {
+            error!("Failed to list time entries for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(entries))
+}
+
+/// Create time entry endpoint handler.
+///
+/// Handles POST requests to `/api/time-entries`.
+pub async fn create_time_entry_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Json(request): Json<CreateTimeEntry>,
+) -> Result<(StatusCode, Json<TimeEntry>), (StatusCode, Json<Value>)> {
+    let entry = resolve(&pool, user_id, request).await?;
+
+    let stored = create_time_entry(&pool, user_id, entry).await.map_err(|e| {
+        error!("Failed to create time entry for user {}: {}", user_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create time entry")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(stored)))
+}
+
+/// Get time entry endpoint handler.
+///
+/// Handles GET requests to `/api/time-entries/:id`.
+pub async fn get_time_entry_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(entry_id): Path<Uuid>,
+) -> Result<Json<TimeEntry>, StatusCode> {
+    let entry = find_time_entry(&pool, user_id, entry_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load time entry {}: {}", entry_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(entry))
+}
+
+/// Update time entry endpoint handler.
+///
+/// Handles PUT requests to `/api/time-entries/:id`. Billed entries can't
+/// be changed.
+pub async fn update_time_entry_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(entry_id): Path<Uuid>,
+    Json(update): Json<UpdateTimeEntry>,
+) -> Result<Json<TimeEntry>, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to update time entry {}: {}", entry_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update time entry")
+    };
+
+    let current = require_unbilled(&pool, user_id, entry_id).await?;
+    let entry = resolve(&pool, user_id, merge_update(&current, update)).await?;
+
+    let updated = update_time_entry(&pool, user_id, entry_id, entry)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "time entry not found"))?;
+
+    Ok(Json(updated))
+}
+
+/// Delete time entry endpoint handler.
+///
+/// Handles DELETE requests to `/api/time-entries/:id`. Billed entries
+/// can't be deleted.
+pub async fn delete_time_entry_handler(
+    Extension(pool): Extension<PgPool>,
+    Extension(CurrentUser(user_id)): Extension<CurrentUser>,
+    Path(entry_id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    require_unbilled(&pool, user_id, entry_id).await?;
+
+    let deleted = delete_time_entry(&pool, user_id, entry_id).await.map_err(|e| {
+        error!("Failed to delete time entry {}: {}", entry_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to delete time entry")
+    })?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(error_response(StatusCode::NOT_FOUND, "time entry not found"))
+    }
+}
+
+/// Validates an entry and applies its project's defaults.
+async fn resolve(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateTimeEntry,
+) -> Result<ResolvedEntry, (StatusCode, Json<Value>)> {
+    let project = match request.project_id {
+        Some(project_id) => Some(require_project(pool, user_id, project_id).await?),
+        None => None,
+    };
+
+    resolve_entry(request, project.as_ref(), Utc::now().date_naive())
+        .map_err(|message| error_response(StatusCode::UNPROCESSABLE_ENTITY, &message))
+}
+
+/// Loads the defaults of a referenced project owned by the user.
+async fn require_project(
+    pool: &PgPool,
+    user_id: Uuid,
+    project_id: Uuid,
+) -> Result<ProjectDefaults, (StatusCode, Json<Value>)> {
+    project_defaults(pool, user_id, project_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load project {}: {}", project_id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load project")
+        })?
+        .ok_or_else(|| error_response(StatusCode::UNPROCESSABLE_ENTITY, "project_id must reference one of your projects"))
+}
+
+/// Loads an entry that has not been billed yet.
+async fn require_unbilled(
+    pool: &PgPool,
+    user_id: Uuid,
+    entry_id: Uuid,
+) -> Result<TimeEntry, (StatusCode, Json<Value>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to load time entry {}: {}", entry_id, e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load time entry")
+    };
+
+    let entry = find_time_entry(pool, user_id, entry_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "time entry not found"))?;
+    if is_billed(pool, &entry).await.map_err(internal_error)? {
+        return Err(error_response(StatusCode::CONFLICT, "time entry is already billed"));
+    }
+
+    Ok(entry)
+}
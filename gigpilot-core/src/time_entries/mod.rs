@@ -0,0 +1,790 @@
+//! Time tracked by the user.
+//!
+//! Entries are created through the API or tracked offline on a device and
+//! pushed through sync; every server-side change is recorded for sync. An
+//! entry is either a tracked interval (`started_at` to `stopped_at`, from
+//! which `hours` is computed) or a plain duration in `hours`. Entries of a
+//! project take its client, rate and currency unless they set their own.
+//! The weekly draft job bills unbilled billable entries; billed entries
+//! can no longer be changed or deleted.
+
+pub mod handlers;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde_json::Value;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::currency::{normalize_currency, validate_amount, DEFAULT_CURRENCY};
+use crate::models::sync_change::SyncOperation;
+use crate::models::time_entry::{CreateTimeEntry, TimeEntry, UpdateTimeEntry};
+use crate::sync::server::record_server_change;
+use crate::text::non_blank;
+
+/// What a time entry inherits from its project.
+#[derive(Debug, Clone, Default, PartialEq, FromRow)]
+pub struct ProjectDefaults {
+    /// The project's hourly rate
+    pub rate: Option<Decimal>,
+
+    /// Currency of the rate
+    pub currency: String,
+
+    /// Name of the project's client
+    pub client_name: Option<String>,
+
+    /// Email of the project's client
+    pub client_email: Option<String>,
+}
+
+/// A time entry's fields with defaults applied and hours computed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedEntry {
+    pub project_id: Option<Uuid>,
+    pub client_name: Option<String>,
+    pub client_email: Option<String>,
+    pub description: Option<String>,
+    pub entry_date: NaiveDate,
+    pub started_at: Option<DateTime<Utc>>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub hours: Decimal,
+    pub hourly_rate: Decimal,
+    pub currency: String,
+    pub billable: bool,
+    pub metadata: Option<Value>,
+}
+
+/// Hours between two instants, rounded to the nearest hundredth.
+///
+/// # Returns
+///
+/// Returns a message if the interval is reversed or rounds to zero.
+pub fn tracked_hours(started_at: DateTime<Utc>, stopped_at: DateTime<Utc>) -> Result<Decimal, String> {
+    if stopped_at <= started_at {
+        return Err("stopped_at must be after started_at".to_string());
+    }
+    let seconds = (stopped_at - started_at).num_seconds();
+    let hours = (Decimal::from(seconds) / Decimal::from(3600))
+        .round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+    if hours.is_zero() {
+        return Err("tracked time must be at least 0.01 hours".to_string());
+    }
+    Ok(hours)
+}
+
+/// Applies project defaults to an entry and computes its hours.
+///
+/// A tracked interval takes precedence over `hours`. Without an
+/// `entry_date` the entry is dated on the day tracking started, or
+/// `today`.
+///
+/// # Arguments
+///
+/// * `request` - The entry as sent by the user or device
+/// * `project` - Defaults of the entry's project, if it has one
+/// * `today` - Current date
+///
+/// # Returns
+///
+/// Returns the resolved entry, or a message describing the first invalid
+/// field.
+pub fn resolve_entry(
+    request: CreateTimeEntry,
+    project: Option<&ProjectDefaults>,
+    today: NaiveDate,
+) -> Result<ResolvedEntry, String> {
+    let hours = match (request.started_at, request.stopped_at) {
+        (Some(started_at), Some(stopped_at)) => tracked_hours(started_at, stopped_at)?,
+        (None, None) => {
+            let hours = request
+                .hours
+                .ok_or_else(|| "hours, or started_at and stopped_at, are required".to_string())?;
+            if hours <= Decimal::ZERO {
+                return Err("hours must be positive".to_string());
+            }
+            if hours.normalize().scale() > 2 {
+                return Err("hours must have at most 2 decimal places".to_string());
+            }
+            hours
+        }
+        _ => return Err("started_at and stopped_at must be given together".to_string()),
+    };
+
+    let currency = match (request.currency.as_deref(), project) {
+        (Some(currency), _) => normalize_currency(currency).map_err(|e| e.to_string())?,
+        (None, Some(project)) => project.currency.clone(),
+        (None, None) => DEFAULT_CURRENCY.to_string(),
+    };
+    let hourly_rate = request
+        .hourly_rate
+        .or_else(|| project.and_then(|project| project.rate))
+        .unwrap_or(Decimal::ZERO);
+    validate_amount(hourly_rate, &currency).map_err(|e| format!("hourly_rate: {}", e))?;
+
+    let client_name = non_blank(request.client_name);
+    let client_email = non_blank(request.client_email);
+    let (client_name, client_email) = match (client_name, project) {
+        (None, Some(project)) => (
+            project.client_name.clone(),
+            client_email.or_else(|| project.client_email.clone()),
+        ),
+        (client_name, _) => (client_name, client_email),
+    };
+
+    Ok(ResolvedEntry {
+        project_id: request.project_id,
+        client_name,
+        client_email,
+        description: non_blank(request.description),
+        entry_date: request
+            .entry_date
+            .or_else(|| request.started_at.map(|started_at| started_at.date_naive()))
+            .unwrap_or(today),
+        started_at: request.started_at,
+        stopped_at: request.stopped_at,
+        hours,
+        hourly_rate,
+        currency,
+        billable: request.billable.unwrap_or(true),
+        metadata: request.metadata,
+    })
+}
+
+/// Applies an update to an entry, as a request to resolve again.
+///
+/// Fields left as `None` keep their current value. Setting `hours` alone
+/// turns a tracked interval into a plain duration; setting either end of
+/// the interval recomputes the hours.
+pub fn merge_update(current: &TimeEntry, update: UpdateTimeEntry) -> CreateTimeEntry {
+    let (started_at, stopped_at, hours) = if update.started_at.is_some() || update.stopped_at.is_some() {
+        (
+            update.started_at.or(current.started_at),
+            update.stopped_at.or(current.stopped_at),
+            None,
+        )
+    } else if update.hours.is_some() {
+        (None, None, update.hours)
+    } else {
+        (current.started_at, current.stopped_at, Some(current.hours))
+    };
+
+    CreateTimeEntry {
+        project_id: update.project_id.or(current.project_id),
+        client_name: update.client_name.or_else(|| current.client_name.clone()),
+        client_email: update.client_email.or_else(|| current.client_email.clone()),
+        description: update.description.or_else(|| current.description.clone()),
+        entry_date: update.entry_date.or(Some(current.entry_date)),
+        started_at,
+        stopped_at,
+        hours,
+        hourly_rate: update.hourly_rate.or(Some(current.hourly_rate)),
+        currency: update.currency.or_else(|| Some(current.currency.clone())),
+        billable: update.billable.or(Some(current.billable)),
+        metadata: update.metadata.or_else(|| current.metadata.clone()),
+    }
+}
+
+/// Loads what an entry inherits from a project owned by the user.
+///
+/// # Returns
+///
+/// Returns `None` if the project does not exist, is deleted, or belongs
+/// to another user.
+pub async fn project_defaults<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    project_id: Uuid,
+) -> Result<Option<ProjectDefaults>, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let defaults = sqlx::query_as::<_, ProjectDefaults>(
+        r#"
+        SELECT p.rate, p.currency, c.name AS client_name, c.email AS client_email
+        FROM projects p
+        LEFT JOIN clients c ON c.id = p.client_id AND c.is_deleted = false
+        WHERE p.id = $1 AND p.user_id = $2 AND p.is_deleted = false
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(defaults)
+}
+
+/// Loads the project defaults for a device push.
+///
+/// # Errors
+///
+/// Returns an error if the project does not belong to the user.
+async fn pushed_project_defaults(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    project_id: Option<Uuid>,
+) -> Result<Option<ProjectDefaults>, anyhow::Error> {
+    match project_id {
+        Some(project_id) => project_defaults(&mut **tx, user_id, project_id)
+            .await?
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("Project {} not found", project_id)),
+        None => Ok(None),
+    }
+}
+
+/// Creates a time entry and records it for sync.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `entry` - The entry (see [`resolve_entry`])
+///
+/// # Returns
+///
+/// Returns the stored `TimeEntry`, or an error.
+pub async fn create_time_entry(
+    pool: &PgPool,
+    user_id: Uuid,
+    entry: ResolvedEntry,
+) -> Result<TimeEntry, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let stored = insert_entry(&mut tx, user_id, Uuid::new_v4(), &entry, None).await?;
+    record_time_entry_change(&mut tx, &stored, SyncOperation::Insert).await?;
+    tx.commit().await?;
+
+    Ok(stored)
+}
+
+/// Inserts a resolved entry.
+async fn insert_entry(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    entry_id: Uuid,
+    entry: &ResolvedEntry,
+    version_vector: Option<&Value>,
+) -> Result<TimeEntry, anyhow::Error> {
+    let stored = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        INSERT INTO time_entries (
+            id, user_id, project_id, client_name, client_email, description, entry_date,
+            started_at, stopped_at, hours, hourly_rate, currency, billable,
+            metadata, last_modified, version_vector
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, NOW(), $15)
+        RETURNING *
+        "#,
+    )
+    .bind(entry_id)
+    .bind(user_id)
+    .bind(entry.project_id)
+    .bind(&entry.client_name)
+    .bind(&entry.client_email)
+    .bind(&entry.description)
+    .bind(entry.entry_date)
+    .bind(entry.started_at)
+    .bind(entry.stopped_at)
+    .bind(entry.hours)
+    .bind(entry.hourly_rate)
+    .bind(&entry.currency)
+    .bind(entry.billable)
+    .bind(&entry.metadata)
+    .bind(version_vector)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(stored)
+}
+
+/// Lists a user's live time entries, newest first.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `project_id` - Only list the entries of this project
+/// * `from` - Only list entries on or after this date
+/// * `to` - Only list entries on or before this date
+/// * `billable` - Only list billable (or non-billable) entries
+pub async fn list_time_entries(
+    pool: &PgPool,
+    user_id: Uuid,
+    project_id: Option<Uuid>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    billable: Option<bool>,
+) -> Result<Vec<TimeEntry>, anyhow::Error> {
+    let entries = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        SELECT * FROM time_entries
+        WHERE user_id = $1
+            AND is_deleted = false
+            AND ($2::uuid IS NULL OR project_id = $2)
+            AND ($3::date IS NULL OR entry_date >= $3)
+            AND ($4::date IS NULL OR entry_date <= $4)
+            AND ($5::boolean IS NULL OR billable = $5)
+        ORDER BY entry_date DESC, started_at DESC NULLS LAST, created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(project_id)
+    .bind(from)
+    .bind(to)
+    .bind(billable)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Loads a single live time entry owned by the given user.
+///
+/// # Returns
+///
+/// Returns `Some(TimeEntry)` if found, `None` if it does not exist, is
+/// deleted, or belongs to another user.
+pub async fn find_time_entry(
+    pool: &PgPool,
+    user_id: Uuid,
+    entry_id: Uuid,
+) -> Result<Option<TimeEntry>, anyhow::Error> {
+    let entry = sqlx::query_as::<_, TimeEntry>(
+        "SELECT * FROM time_entries WHERE id = $1 AND user_id = $2 AND is_deleted = false",
+    )
+    .bind(entry_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(entry)
+}
+
+/// Whether an entry is billed on an invoice that still exists.
+///
+/// Entries billed on a deleted draft count as unbilled again.
+pub async fn is_billed<'e, E>(executor: E, entry: &TimeEntry) -> Result<bool, anyhow::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let Some(invoice_id) = entry.invoice_id else {
+        return Ok(false);
+    };
+    let live = sqlx::query_scalar::<_, i32>("SELECT 1 FROM invoices WHERE id = $1 AND is_deleted = false")
+        .bind(invoice_id)
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(live.is_some())
+}
+
+/// Replaces an entry's fields and records the change for sync.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `user_id` - ID of the owning user
+/// * `entry_id` - The entry to update
+/// * `entry` - Its new fields (see [`merge_update`] and [`resolve_entry`])
+///
+/// # Returns
+///
+/// Returns the updated `TimeEntry`, or `None` if it does not exist.
+pub async fn update_time_entry(
+    pool: &PgPool,
+    user_id: Uuid,
+    entry_id: Uuid,
+    entry: ResolvedEntry,
+) -> Result<Option<TimeEntry>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let updated = update_entry(&mut tx, user_id, entry_id, &entry, None).await?;
+    if let Some(updated) = &updated {
+        record_time_entry_change(&mut tx, updated, SyncOperation::Update).await?;
+    }
+    tx.commit().await?;
+
+    Ok(updated)
+}
+
+/// Writes a resolved entry over an existing one.
+async fn update_entry(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    entry_id: Uuid,
+    entry: &ResolvedEntry,
+    version_vector: Option<&Value>,
+) -> Result<Option<TimeEntry>, anyhow::Error> {
+    let updated = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        UPDATE time_entries
+        SET project_id = $3,
+            client_name = $4,
+            client_email = $5,
+            description = $6,
+            entry_date = $7,
+            started_at = $8,
+            stopped_at = $9,
+            hours = $10,
+            hourly_rate = $11,
+            currency = $12,
+            billable = $13,
+            metadata = $14,
+            last_modified = NOW(),
+            version_vector = COALESCE($15, version_vector)
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(entry_id)
+    .bind(user_id)
+    .bind(entry.project_id)
+    .bind(&entry.client_name)
+    .bind(&entry.client_email)
+    .bind(&entry.description)
+    .bind(entry.entry_date)
+    .bind(entry.started_at)
+    .bind(entry.stopped_at)
+    .bind(entry.hours)
+    .bind(entry.hourly_rate)
+    .bind(&entry.currency)
+    .bind(entry.billable)
+    .bind(&entry.metadata)
+    .bind(version_vector)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(updated)
+}
+
+/// Soft-deletes a time entry and records the deletion for sync.
+///
+/// # Returns
+///
+/// Returns `true` if an entry was deleted, `false` if none matched.
+pub async fn delete_time_entry(pool: &PgPool, user_id: Uuid, entry_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let entry = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        UPDATE time_entries SET is_deleted = true, last_modified = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING *
+        "#,
+    )
+    .bind(entry_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(entry) = &entry {
+        record_time_entry_change(&mut tx, entry, SyncOperation::Delete).await?;
+    }
+    tx.commit().await?;
+
+    Ok(entry.is_some())
+}
+
+/// Records a server-side time entry change for sync.
+async fn record_time_entry_change(
+    tx: &mut Transaction<'_, Postgres>,
+    entry: &TimeEntry,
+    operation: SyncOperation,
+) -> Result<(), anyhow::Error> {
+    record_server_change(
+        &mut **tx,
+        entry.user_id,
+        "time_entries",
+        entry.id,
+        operation,
+        &serde_json::to_value(entry)?,
+    )
+    .await
+}
+
+/// Reads an optional pushed text field.
+fn pushed_str<'a>(data: &'a Value, field: &str) -> Option<&'a str> {
+    data.get(field).and_then(|v| v.as_str())
+}
+
+/// Parses an optional pushed field with serde, treating `null` as absent.
+fn pushed_field<T: serde::de::DeserializeOwned>(data: &Value, field: &str) -> Result<Option<T>, anyhow::Error> {
+    match data.get(field) {
+        Some(value) if !value.is_null() => Ok(Some(serde_json::from_value(value.clone())?)),
+        _ => Ok(None),
+    }
+}
+
+/// Parses an optional pushed decimal (a number or a decimal string).
+fn pushed_decimal(data: &Value, field: &str) -> Result<Option<Decimal>, anyhow::Error> {
+    match data.get(field) {
+        Some(Value::String(value)) => Ok(Some(Decimal::from_str_exact(value)?)),
+        Some(Value::Number(value)) => Ok(Some(Decimal::try_from(value.as_f64().unwrap_or_default())?)),
+        _ => Ok(None),
+    }
+}
+
+/// Parses a pushed time entry.
+fn pushed_entry(data: &Value) -> Result<CreateTimeEntry, anyhow::Error> {
+    Ok(CreateTimeEntry {
+        project_id: pushed_field(data, "project_id")?,
+        client_name: pushed_str(data, "client_name").map(str::to_string),
+        client_email: pushed_str(data, "client_email").map(str::to_string),
+        description: pushed_str(data, "description").map(str::to_string),
+        entry_date: pushed_field(data, "entry_date")?,
+        started_at: pushed_field(data, "started_at")?,
+        stopped_at: pushed_field(data, "stopped_at")?,
+        hours: pushed_decimal(data, "hours")?,
+        hourly_rate: pushed_decimal(data, "hourly_rate")?,
+        currency: pushed_str(data, "currency").map(str::to_string),
+        billable: data.get("billable").and_then(|v| v.as_bool()),
+        metadata: data.get("metadata").filter(|v| !v.is_null()).cloned(),
+    })
+}
+
+/// Loads a pushed entry's current row, refusing billed entries.
+///
+/// # Errors
+///
+/// Returns an error if the entry is billed.
+async fn unbilled_entry(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+) -> Result<Option<TimeEntry>, anyhow::Error> {
+    let entry = sqlx::query_as::<_, TimeEntry>(
+        "SELECT * FROM time_entries WHERE id = $1 AND user_id = $2 AND is_deleted = false FOR UPDATE",
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if let Some(entry) = &entry {
+        if is_billed(&mut **tx, entry).await? {
+            anyhow::bail!("Time entry {} is already billed", record_id);
+        }
+    }
+    Ok(entry)
+}
+
+/// Inserts a time entry pushed by a device.
+///
+/// # Errors
+///
+/// Returns an error if a field is invalid or the project does not belong
+/// to the user.
+pub async fn apply_pushed_insert(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+    version_vector: Option<&Value>,
+) -> Result<(), anyhow::Error> {
+    let request = pushed_entry(data)?;
+    let project = pushed_project_defaults(tx, user_id, request.project_id).await?;
+    let entry = resolve_entry(request, project.as_ref(), Utc::now().date_naive()).map_err(anyhow::Error::msg)?;
+
+    insert_entry(tx, user_id, record_id, &entry, version_vector).await?;
+
+    Ok(())
+}
+
+/// Updates a time entry from a device push.
+///
+/// Like invoice pushes, optional fields missing from the payload are
+/// cleared; devices send the whole record. The date, rate, currency,
+/// billable flag and hours keep their value when missing.
+///
+/// # Errors
+///
+/// Returns an error if a field is invalid, the project does not belong to
+/// the user or the entry is already billed.
+pub async fn apply_pushed_update(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+    data: &Value,
+) -> Result<(), anyhow::Error> {
+    let Some(current) = unbilled_entry(tx, user_id, record_id).await? else {
+        return Ok(());
+    };
+    let mut request = pushed_entry(data)?;
+    request.entry_date = request.entry_date.or(Some(current.entry_date));
+    request.hourly_rate = request.hourly_rate.or(Some(current.hourly_rate));
+    request.currency = request.currency.or(Some(current.currency));
+    request.billable = request.billable.or(Some(current.billable));
+    if request.started_at.is_none() && request.stopped_at.is_none() {
+        request.hours = request.hours.or(Some(current.hours));
+    }
+
+    let project = pushed_project_defaults(tx, user_id, request.project_id).await?;
+    let entry = resolve_entry(request, project.as_ref(), Utc::now().date_naive()).map_err(anyhow::Error::msg)?;
+
+    update_entry(tx, user_id, record_id, &entry, data.get("version_vector")).await?;
+
+    Ok(())
+}
+
+/// Soft-deletes a time entry from a device push.
+///
+/// # Errors
+///
+/// Returns an error if the entry is already billed.
+pub async fn apply_pushed_delete(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    record_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    if unbilled_entry(tx, user_id, record_id).await?.is_none() {
+        return Ok(());
+    }
+
+    sqlx::query("UPDATE time_entries SET is_deleted = true, last_modified = NOW() WHERE id = $1 AND user_id = $2")
+        .bind(record_id)
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 4, h, m, s).unwrap()
+    }
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 8).unwrap()
+    }
+
+    fn project() -> ProjectDefaults {
+        ProjectDefaults {
+            rate: Some(Decimal::from(90)),
+            currency: "EUR".to_string(),
+            client_name: Some("Acme Ltd".to_string()),
+            client_email: Some("billing@acme.test".to_string()),
+        }
+    }
+
+    fn stored(entry: ResolvedEntry) -> TimeEntry {
+        TimeEntry {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            client_name: entry.client_name,
+            client_email: entry.client_email,
+            project_id: entry.project_id,
+            description: entry.description,
+            entry_date: entry.entry_date,
+            started_at: entry.started_at,
+            stopped_at: entry.stopped_at,
+            hours: entry.hours,
+            hourly_rate: entry.hourly_rate,
+            currency: entry.currency,
+            billable: entry.billable,
+            invoice_id: None,
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            metadata: entry.metadata,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_tracked_hours() {
+        assert_eq!(tracked_hours(at(9, 0, 0), at(10, 30, 0)), Ok(Decimal::from_str_exact("1.50").unwrap()));
+        // 20 minutes is a third of an hour
+        assert_eq!(tracked_hours(at(9, 0, 0), at(9, 20, 0)), Ok(Decimal::from_str_exact("0.33").unwrap()));
+        assert!(tracked_hours(at(9, 0, 0), at(9, 0, 10)).is_err());
+        assert!(tracked_hours(at(10, 0, 0), at(9, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_resolve_entry_requires_a_duration() {
+        let interval = CreateTimeEntry {
+            started_at: Some(at(9, 0, 0)),
+            stopped_at: Some(at(11, 15, 0)),
+            hours: Some(Decimal::from(8)),
+            ..Default::default()
+        };
+        let entry = resolve_entry(interval, None, today()).unwrap();
+        assert_eq!(entry.hours, Decimal::from_str_exact("2.25").unwrap());
+        assert_eq!(entry.entry_date, at(9, 0, 0).date_naive());
+        assert_eq!(entry.currency, "USD");
+        assert!(entry.billable);
+
+        let duration = CreateTimeEntry { hours: Some(Decimal::from(3)), ..Default::default() };
+        assert_eq!(resolve_entry(duration, None, today()).unwrap().entry_date, today());
+
+        assert!(resolve_entry(CreateTimeEntry::default(), None, today()).is_err());
+        let half_open = CreateTimeEntry { started_at: Some(at(9, 0, 0)), ..Default::default() };
+        assert!(resolve_entry(half_open, None, today()).is_err());
+        let precise = CreateTimeEntry { hours: Some(Decimal::from_str_exact("1.005").unwrap()), ..Default::default() };
+        assert!(resolve_entry(precise, None, today()).is_err());
+    }
+
+    #[test]
+    fn test_resolve_entry_uses_project_defaults() {
+        let request = CreateTimeEntry {
+            project_id: Some(Uuid::new_v4()),
+            hours: Some(Decimal::from(2)),
+            ..Default::default()
+        };
+        let entry = resolve_entry(request.clone(), Some(&project()), today()).unwrap();
+        assert_eq!(entry.hourly_rate, Decimal::from(90));
+        assert_eq!(entry.currency, "EUR");
+        assert_eq!(entry.client_name.as_deref(), Some("Acme Ltd"));
+        assert_eq!(entry.client_email.as_deref(), Some("billing@acme.test"));
+
+        let own_client = CreateTimeEntry {
+            client_name: Some("Globex".to_string()),
+            hourly_rate: Some(Decimal::from(50)),
+            currency: Some("gbp".to_string()),
+            ..request.clone()
+        };
+        let entry = resolve_entry(own_client, Some(&project()), today()).unwrap();
+        assert_eq!(entry.client_name.as_deref(), Some("Globex"));
+        assert_eq!(entry.client_email, None);
+        assert_eq!(entry.hourly_rate, Decimal::from(50));
+        assert_eq!(entry.currency, "GBP");
+
+        let fractional_yen = CreateTimeEntry {
+            hourly_rate: Some(Decimal::from_str_exact("10.5").unwrap()),
+            currency: Some("JPY".to_string()),
+            ..request
+        };
+        assert!(resolve_entry(fractional_yen, Some(&project()), today()).is_err());
+    }
+
+    #[test]
+    fn test_merge_update_switches_between_interval_and_duration() {
+        let tracked = resolve_entry(
+            CreateTimeEntry { started_at: Some(at(9, 0, 0)), stopped_at: Some(at(10, 0, 0)), ..Default::default() },
+            None,
+            today(),
+        )
+        .unwrap();
+        let current = stored(tracked);
+
+        let later_stop = UpdateTimeEntry { stopped_at: Some(at(12, 0, 0)), ..Default::default() };
+        let entry = resolve_entry(merge_update(&current, later_stop), None, today()).unwrap();
+        assert_eq!(entry.hours, Decimal::from(3));
+        assert_eq!(entry.started_at, Some(at(9, 0, 0)));
+
+        let duration = UpdateTimeEntry { hours: Some(Decimal::from(5)), ..Default::default() };
+        let entry = resolve_entry(merge_update(&current, duration), None, today()).unwrap();
+        assert_eq!(entry.hours, Decimal::from(5));
+        assert_eq!(entry.started_at, None);
+
+        let unchanged = resolve_entry(merge_update(&current, UpdateTimeEntry::default()), None, today()).unwrap();
+        assert_eq!(unchanged.hours, current.hours);
+        assert_eq!(unchanged.entry_date, current.entry_date);
+    }
+}
@@ -0,0 +1,178 @@
+//! Daily receivables aging snapshots.
+//!
+//! Once a day every user's open balances are bucketed by days overdue and
+//! stored per currency in `aging_snapshots` (read by `reports::aging`).
+//! Snapshots are incremental: each run only covers users who have no
+//! snapshot for the day yet, so frequent polling is cheap. Each day's
+//! snapshot is taken by a job in the durable job queue (see
+//! [`crate::worker::jobs`]), so one worker replica takes it and a failed
+//! run is retried with backoff.
+
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+
+use crate::maintenance::worker_paused;
+use crate::models::job::CreateJob;
+use crate::worker::jobs::{
+    claim_jobs, complete_job, enqueue_job, fail_job, retry_at, visibility_timeout, worker_id, DEFAULT_MAX_ATTEMPTS,
+};
+
+/// Kind of the jobs recording a day's aging snapshots.
+pub const AGING_SNAPSHOT_JOB: &str = "aging_snapshot";
+
+/// Snapshot jobs claimed per run.
+const AGING_SNAPSHOT_BATCH_SIZE: usize = 7;
+
+/// Input of a snapshot job: the day to snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct AgingSnapshotPayload {
+    date: NaiveDate,
+}
+
+/// Records the aging snapshot of `date` for every user who lacks one.
+///
+/// Open invoices are sent or overdue, not deleted, with a balance left.
+/// Days overdue are calendar days from the due date to `date`.
+///
+/// # Returns
+///
+/// Returns the number of snapshot rows inserted.
+pub async fn record_aging_snapshots(pool: &PgPool, date: NaiveDate) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO aging_snapshots (
+            user_id, snapshot_date, currency, invoice_count, outstanding,
+            current, days_1_30, days_31_60, days_61_90, days_over_90
+        )
+        SELECT
+            user_id, $1::date, currency, COUNT(*), SUM(balance),
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue <= 0), 0),
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue BETWEEN 1 AND 30), 0),
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue BETWEEN 31 AND 60), 0),
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue BETWEEN 61 AND 90), 0),
+            COALESCE(SUM(balance) FILTER (WHERE days_overdue > 90), 0)
+        FROM (
+            SELECT
+                i.user_id,
+                i.currency,
+                i.total - i.amount_paid AS balance,
+                COALESCE($1::date - i.due_date, 0) AS days_overdue
+            FROM invoices i
+            WHERE i.is_deleted = false
+                AND i.status IN ('sent', 'overdue')
+                AND i.total > i.amount_paid
+                AND NOT EXISTS (
+                    SELECT 1 FROM aging_snapshots s
+                    WHERE s.user_id = i.user_id AND s.snapshot_date = $1
+                )
+        ) open_invoices
+        GROUP BY user_id, currency
+        ON CONFLICT (user_id, snapshot_date, currency) DO NOTHING
+        "#,
+    )
+    .bind(date)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Queues the snapshot job of `date`.
+///
+/// # Returns
+///
+/// Returns `false` if that day's job is already queued or running.
+pub async fn enqueue_aging_snapshot(pool: &PgPool, date: NaiveDate) -> Result<bool, anyhow::Error> {
+    let job = CreateJob {
+        kind: AGING_SNAPSHOT_JOB.to_string(),
+        payload: json!(AgingSnapshotPayload { date }),
+        dedupe_key: Some(date.to_string()),
+        max_attempts: DEFAULT_MAX_ATTEMPTS,
+        run_at: None,
+    };
+
+    Ok(enqueue_job(pool, &job).await?.is_some())
+}
+
+/// Runs the snapshot jobs this worker claims.
+///
+/// A failed job is retried with backoff until it runs out of attempts.
+///
+/// # Returns
+///
+/// Returns the number of snapshot rows inserted.
+pub async fn run_aging_snapshot_jobs(
+    pool: &PgPool,
+    worker_id: &str,
+    visibility: chrono::Duration,
+) -> Result<u64, anyhow::Error> {
+    let mut inserted = 0;
+    for _ in 0..AGING_SNAPSHOT_BATCH_SIZE {
+        let claimed = claim_jobs(pool, AGING_SNAPSHOT_JOB, worker_id, 1, visibility).await?;
+        let Some(job) = claimed.into_iter().next() else {
+            break;
+        };
+
+        let outcome = match serde_json::from_value::<AgingSnapshotPayload>(job.payload.clone()) {
+            Ok(payload) => record_aging_snapshots(pool, payload.date).await,
+            Err(e) => Err(e.into()),
+        };
+        match outcome {
+            Ok(rows) => {
+                inserted += rows;
+                if !complete_job(pool, job.id, worker_id).await? {
+                    warn!("Aging snapshot job {} was reclaimed before it completed", job.id);
+                }
+            }
+            Err(e) => {
+                let retry = retry_at(&job, Utc::now());
+                error!(
+                    "Aging snapshot job {} failed (attempt {}), {}: {}",
+                    job.id,
+                    job.attempts,
+                    if retry.is_some() { "retrying" } else { "giving up" },
+                    e
+                );
+                fail_job(pool, job.id, worker_id, &e.to_string(), retry).await?;
+            }
+        }
+    }
+
+    Ok(inserted)
+}
+
+/// Spawns the background aging snapshot loop.
+///
+/// Polls every `AGING_SNAPSHOT_POLL_INTERVAL_SECONDS` (default: 3600
+/// seconds), queueing the snapshot job of the current day (UTC) and
+/// running the snapshot jobs it claims.
+pub fn spawn_aging_snapshot_worker(pool: PgPool) {
+    let seconds = std::env::var("AGING_SNAPSHOT_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(3600);
+    let worker_id = worker_id();
+    let visibility = visibility_timeout();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if worker_paused() {
+                continue;
+            }
+            if let Err(e) = enqueue_aging_snapshot(&pool, Utc::now().date_naive()).await {
+                error!("Failed to queue the aging snapshot: {}", e);
+            }
+            match run_aging_snapshot_jobs(&pool, &worker_id, visibility).await {
+                Ok(0) => {}
+                Ok(count) => info!("Recorded {} aging snapshot row(s)", count),
+                Err(e) => error!("Aging snapshot jobs failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,115 @@
+//! Project budget alerts.
+//!
+//! Emails users when the time tracked on a project with `budget_alerts`
+//! reaches 80% and 100% of its budget (see [`crate::projects::budget`]).
+//! Each project remembers the highest threshold emailed; it is claimed
+//! before sending and released if sending fails, so the email is tried again
+//! on the next run. When usage drops below a threshold (the budget was
+//! raised or time was removed) the project is quietly re-armed.
+
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::logging::redact_email;
+use crate::maintenance::worker_paused;
+use crate::projects::budget::{budget_alert_email, project_burn, tracked_usage};
+use crate::projects::find_project;
+use crate::worker::services::send_email;
+
+/// Updates a project's emailed threshold if it still has the expected one.
+///
+/// # Returns
+///
+/// Returns `true` if the threshold was changed.
+async fn swap_alert_percent(pool: &PgPool, project_id: Uuid, from: i16, to: i16) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        "UPDATE projects SET budget_alert_percent = $3 WHERE id = $1 AND budget_alert_percent = $2",
+    )
+    .bind(project_id)
+    .bind(from)
+    .bind(to)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Emails users about projects that crossed a budget threshold.
+///
+/// # Returns
+///
+/// Returns the number of emails sent.
+pub async fn send_budget_alerts(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let candidates = sqlx::query_as::<_, (Uuid, Uuid, i16, String)>(
+        r#"
+        SELECT p.id, p.user_id, p.budget_alert_percent, u.email
+        FROM projects p
+        JOIN users u ON u.id = p.user_id
+        WHERE p.budget_alerts = true
+            AND p.is_deleted = false
+            AND (p.budget_hours IS NOT NULL OR p.budget_amount IS NOT NULL OR p.budget_alert_percent > 0)
+            AND u.is_active = true
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut sent = 0;
+    for (project_id, user_id, alerted, email) in candidates {
+        let Some(project) = find_project(pool, user_id, project_id).await? else {
+            continue;
+        };
+        let (hours_used, amount_used) = tracked_usage(pool, &project).await?;
+        let burn = project_burn(&project, hours_used, amount_used);
+
+        if burn.threshold < alerted {
+            swap_alert_percent(pool, project_id, alerted, burn.threshold).await?;
+            continue;
+        }
+        if burn.threshold == alerted || !swap_alert_percent(pool, project_id, alerted, burn.threshold).await? {
+            continue;
+        }
+
+        let (subject, body) = budget_alert_email(&project, &burn);
+        match send_email(&email, &subject, &body).await {
+            Ok(()) => {
+                info!("Emailed {} about {}% of project {}'s budget", redact_email(&email), burn.threshold, project_id);
+                sent += 1;
+            }
+            Err(e) => {
+                warn!("Failed to email budget alert of project {}: {}", project_id, e);
+                if let Err(e) = swap_alert_percent(pool, project_id, burn.threshold, alerted).await {
+                    error!("Failed to release budget alert of project {} for a retry: {}", project_id, e);
+                }
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Spawns the budget alert worker.
+///
+/// Runs every `BUDGET_ALERT_POLL_INTERVAL_SECONDS` (default 900 seconds).
+pub fn spawn_budget_alert_worker(pool: PgPool) {
+    let seconds = std::env::var("BUDGET_ALERT_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(900);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if worker_paused() {
+                continue;
+            }
+            match send_budget_alerts(&pool).await {
+                Ok(0) => {}
+                Ok(count) => info!("Sent {} budget alert(s)", count),
+                Err(e) => error!("Budget alerts failed: {}", e),
+            }
+        }
+    });
+}
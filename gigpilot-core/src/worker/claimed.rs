@@ -0,0 +1,236 @@
+//! Jobs run while their worker holds a heartbeat on them.
+//!
+//! Long-running user jobs (imports, PDF exports) live in their own tables
+//! with `status`, `attempts` and `heartbeat_at` columns. A worker claims
+//! queued jobs by moving them to `running` and bumping `attempts`, and
+//! refreshes the heartbeat while it runs one. A job whose heartbeat stopped
+//! because the worker died is picked up again after
+//! [`STALE_RUNNING_MINUTES`], up to the runner's `MAX_ATTEMPTS`, and failed
+//! after that. A worker holds a job only while it is running with the
+//! attempt it claimed: one that lost its job to another stops running it
+//! and can't record a result.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::postgres::PgRow;
+use sqlx::{Executor, FromRow, PgPool, Postgres};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// Jobs running without a heartbeat for longer than this are reclaimed.
+pub const STALE_RUNNING_MINUTES: i32 = 30;
+
+/// How often a running job's heartbeat is refreshed.
+pub const HEARTBEAT_SECONDS: u64 = 60;
+
+/// Seconds between runs of a worker when not configured.
+const DEFAULT_POLL_SECONDS: u64 = 15;
+
+/// A job row a worker can claim.
+pub trait ClaimedJob: for<'r> FromRow<'r, PgRow> + Send + Sync + Unpin {
+    /// ID of the job
+    fn id(&self) -> Uuid;
+
+    /// Attempt the job is on (the one that claimed it, once claimed)
+    fn attempts(&self) -> i32;
+}
+
+/// A kind of job run by [`process_claimed_jobs`].
+#[async_trait]
+pub trait ClaimedJobRunner: Send + Sync {
+    /// The job rows
+    type Job: ClaimedJob;
+
+    /// What a successful run produces
+    type Output: Send;
+
+    /// Table the jobs are stored in
+    const TABLE: &'static str;
+
+    /// Columns selected for a job
+    const COLUMNS: &'static str;
+
+    /// What the jobs are called in logs and errors (e.g. "import")
+    const NAME: &'static str;
+
+    /// Jobs claimed per run
+    const BATCH_SIZE: i64;
+
+    /// Times a job is picked up before it is given up
+    const MAX_ATTEMPTS: i32;
+
+    /// Runs a claimed job.
+    ///
+    /// Dropped without finishing if the worker loses the job.
+    async fn run(&self, pool: &PgPool, job: &Self::Job) -> Result<Self::Output, anyhow::Error>;
+
+    /// Records a job's result and notifies the user, unless the job has
+    /// since been picked up again (see [`fail_claimed_job`]).
+    async fn finish(
+        &self,
+        pool: &PgPool,
+        job: &Self::Job,
+        result: Result<Self::Output, anyhow::Error>,
+    ) -> Result<(), anyhow::Error>;
+}
+
+/// Runs a batch of queued jobs of one kind.
+///
+/// Jobs whose worker stopped on their last attempt are failed first.
+///
+/// # Returns
+///
+/// Returns the number of jobs that completed.
+pub async fn process_claimed_jobs<R: ClaimedJobRunner>(pool: &PgPool, runner: &R) -> Result<usize, anyhow::Error> {
+    let abandoned = sqlx::query_as::<_, R::Job>(&format!(
+        r#"
+        SELECT {} FROM {}
+        WHERE status = 'running'
+            AND heartbeat_at < NOW() - make_interval(mins => $1)
+            AND attempts >= $2
+        "#,
+        R::COLUMNS,
+        R::TABLE
+    ))
+    .bind(STALE_RUNNING_MINUTES)
+    .bind(R::MAX_ATTEMPTS)
+    .fetch_all(pool)
+    .await?;
+    for job in abandoned {
+        let stopped = anyhow::anyhow!("the {} stopped {} times before finishing", R::NAME, job.attempts());
+        if let Err(e) = runner.finish(pool, &job, Err(stopped)).await {
+            error!("Failed to give up {} job {}: {}", R::NAME, job.id(), e);
+        }
+    }
+
+    let claimed = sqlx::query_as::<_, R::Job>(&format!(
+        r#"
+        UPDATE {table}
+        SET status = 'running', attempts = attempts + 1, started_at = NOW(), heartbeat_at = NOW()
+        WHERE id IN (
+            SELECT id FROM {table}
+            WHERE status = 'queued'
+                OR (status = 'running'
+                    AND heartbeat_at < NOW() - make_interval(mins => $2)
+                    AND attempts < $3)
+            ORDER BY created_at ASC
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING {columns}
+        "#,
+        table = R::TABLE,
+        columns = R::COLUMNS
+    ))
+    .bind(R::BATCH_SIZE)
+    .bind(STALE_RUNNING_MINUTES)
+    .bind(R::MAX_ATTEMPTS)
+    .fetch_all(pool)
+    .await?;
+
+    let mut completed = 0;
+    for job in claimed {
+        let result = tokio::select! {
+            result = runner.run(pool, &job) => result,
+            lost = keep_claimed::<R>(pool, &job) => {
+                warn!("{} job {} stopped: {}", R::NAME, job.id(), lost);
+                continue;
+            }
+        };
+        if let Err(e) = &result {
+            warn!("{} job {} failed (attempt {}): {}", R::NAME, job.id(), job.attempts(), e);
+        } else {
+            completed += 1;
+        }
+        if let Err(e) = runner.finish(pool, &job, result).await {
+            error!("Failed to record the result of {} job {}: {}", R::NAME, job.id(), e);
+        }
+    }
+
+    Ok(completed)
+}
+
+/// Refreshes a running job's heartbeat until the worker no longer holds it.
+///
+/// A failed refresh is retried on the next beat.
+///
+/// # Returns
+///
+/// Returns why the worker lost the job.
+async fn keep_claimed<R: ClaimedJobRunner>(pool: &PgPool, job: &R::Job) -> anyhow::Error {
+    let mut interval = tokio::time::interval(Duration::from_secs(HEARTBEAT_SECONDS));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let refreshed = sqlx::query(&format!(
+            r#"
+            UPDATE {} SET heartbeat_at = NOW()
+            WHERE id = $1 AND status = 'running' AND attempts = $2
+            "#,
+            R::TABLE
+        ))
+        .bind(job.id())
+        .bind(job.attempts())
+        .execute(pool)
+        .await;
+        match refreshed {
+            Ok(result) if result.rows_affected() == 0 => {
+                return anyhow::anyhow!("attempt {} no longer holds the job", job.attempts());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to refresh the heartbeat of {} job {}: {}", R::NAME, job.id(), e),
+        }
+    }
+}
+
+/// Marks a job the worker still holds as failed.
+///
+/// # Arguments
+///
+/// * `executor` - Database executor (pool or transaction)
+/// * `table` - Table the job is stored in
+/// * `job` - The claimed job
+/// * `error` - Why it failed
+///
+/// # Returns
+///
+/// Returns `false` if the job has since been picked up again, in which
+/// case nothing is recorded.
+pub async fn fail_claimed_job<'a, E>(
+    executor: E,
+    table: &str,
+    job: &impl ClaimedJob,
+    error: &str,
+) -> Result<bool, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let updated = sqlx::query(&format!(
+        r#"
+        UPDATE {}
+        SET status = 'failed', error = $2, finished_at = NOW()
+        WHERE id = $1 AND status = 'running' AND attempts = $3
+        "#,
+        table
+    ))
+    .bind(job.id())
+    .bind(error)
+    .bind(job.attempts())
+    .execute(executor)
+    .await?;
+
+    Ok(updated.rows_affected() > 0)
+}
+
+/// Interval between runs of a worker, read from the environment variable
+/// `var` (default 15 seconds).
+pub fn poll_interval(var: &str) -> Duration {
+    let seconds = std::env::var(var)
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_POLL_SECONDS);
+
+    Duration::from_secs(seconds)
+}
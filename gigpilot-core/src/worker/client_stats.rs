@@ -0,0 +1,32 @@
+//! Client payment-behavior profiling.
+//!
+//! Recomputes every client's payment stats (see [`crate::clients::stats`])
+//! on an interval. The chase worker reads the latest stats, so a refresh
+//! an hour old is fine.
+
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::clients::stats::refresh_client_stats;
+
+/// Spawns the background client stats loop.
+///
+/// Refreshes every `CLIENT_STATS_POLL_INTERVAL_SECONDS` (default: 3600
+/// seconds).
+pub fn spawn_client_stats_worker(pool: PgPool) {
+    let seconds = std::env::var("CLIENT_STATS_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(3600);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            match refresh_client_stats(&pool).await {
+                Ok(count) => info!("Refreshed payment stats of {} client(s)", count),
+                Err(e) => error!("Client stats job failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,176 @@
+//! Dispute emails.
+//!
+//! Clients dispute invoices from the portal, which only notifies the user
+//! in the app (the API process never talks to the email provider). This
+//! worker emails the user about each new dispute. A dispute is claimed by
+//! setting `email_sent_at`; the claim is released if sending fails, so the
+//! email is tried again on the next run.
+
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+
+use crate::logging::redact_email;
+use crate::maintenance::worker_paused;
+use crate::models::dispute::Dispute;
+use crate::worker::services::send_email;
+
+/// Disputes emailed per run.
+const DISPUTE_BATCH_SIZE: i64 = 20;
+
+/// Writes the email telling a user about a dispute.
+///
+/// # Arguments
+///
+/// * `dispute` - The dispute
+/// * `invoice_number` - Number of the disputed invoice
+/// * `client_name` - Name of the client on the invoice
+///
+/// # Returns
+///
+/// Returns the subject and body.
+pub fn dispute_email(dispute: &Dispute, invoice_number: &str, client_name: &str) -> (String, String) {
+    let subject = format!("{} disputed invoice {}", client_name, invoice_number);
+    let mut body = format!(
+        "{} disputed invoice {} from your client portal:\n\n\"{}\"\n",
+        client_name, invoice_number, dispute.reason
+    );
+    if let Some(filename) = &dispute.attachment_filename {
+        body.push_str(&format!("\nThey attached {}, which you can download from the invoice in GigPilot.\n", filename));
+    }
+    body.push_str("\nReminders for this invoice are on hold until you resolve the dispute in GigPilot.\n");
+
+    (subject, body)
+}
+
+/// Emails users about disputes they have not been emailed about yet.
+///
+/// # Returns
+///
+/// Returns the number of emails sent.
+pub async fn send_dispute_emails(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let claimed = sqlx::query_as::<_, Dispute>(
+        r#"
+        UPDATE invoice_disputes
+        SET email_sent_at = NOW()
+        WHERE id IN (
+            SELECT id FROM invoice_disputes
+            WHERE email_sent_at IS NULL
+            ORDER BY created_at ASC
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+    )
+    .bind(DISPUTE_BATCH_SIZE)
+    .fetch_all(pool)
+    .await?;
+
+    let mut sent = 0;
+    for dispute in claimed {
+        match send_dispute_email(pool, &dispute).await {
+            Ok(()) => sent += 1,
+            Err(e) => {
+                warn!("Failed to email user about dispute {}: {}", dispute.id, e);
+                if let Err(e) = sqlx::query("UPDATE invoice_disputes SET email_sent_at = NULL WHERE id = $1")
+                    .bind(dispute.id)
+                    .execute(pool)
+                    .await
+                {
+                    error!("Failed to release dispute {} for a retry: {}", dispute.id, e);
+                }
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Emails the invoice owner about one dispute.
+async fn send_dispute_email(pool: &PgPool, dispute: &Dispute) -> Result<(), anyhow::Error> {
+    let (email, invoice_number, client_name): (String, String, String) = sqlx::query_as(
+        r#"
+        SELECT u.email, i.invoice_number, i.client_name
+        FROM invoices i
+        JOIN users u ON u.id = i.user_id
+        WHERE i.id = $1
+        "#,
+    )
+    .bind(dispute.invoice_id)
+    .fetch_one(pool)
+    .await?;
+
+    let (subject, body) = dispute_email(dispute, &invoice_number, &client_name);
+    send_email(&email, &subject, &body).await?;
+    info!("Emailed {} about dispute {}", redact_email(&email), dispute.id);
+
+    Ok(())
+}
+
+/// Spawns the dispute email worker.
+///
+/// Runs every `DISPUTE_POLL_INTERVAL_SECONDS` (default 30 seconds).
+pub fn spawn_dispute_email_worker(pool: PgPool) {
+    let seconds = std::env::var("DISPUTE_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(30);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if worker_paused() {
+                continue;
+            }
+            match send_dispute_emails(&pool).await {
+                Ok(0) => {}
+                Ok(count) => info!("Sent {} dispute email(s)", count),
+                Err(e) => error!("Dispute emails failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use crate::models::dispute::DisputeStatus;
+
+    fn dispute(attachment_filename: Option<&str>) -> Dispute {
+        Dispute {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            invoice_id: Uuid::new_v4(),
+            client_id: Uuid::new_v4(),
+            token_id: None,
+            reason: "We agreed on 10 hours, not 12".to_string(),
+            attachment_filename: attachment_filename.map(str::to_string),
+            attachment_content_type: None,
+            attachment_size_bytes: None,
+            attachment_storage_key: None,
+            status: DisputeStatus::Open,
+            previous_chase_state: None,
+            resolved_at: None,
+            email_sent_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_dispute_email() {
+        let (subject, body) = dispute_email(&dispute(None), "INV-042", "Acme Ltd");
+
+        assert_eq!(subject, "Acme Ltd disputed invoice INV-042");
+        assert!(body.contains("\"We agreed on 10 hours, not 12\""));
+        assert!(body.contains("on hold"));
+        assert!(!body.contains("attached"));
+
+        let (_, body) = dispute_email(&dispute(Some("timesheet.pdf")), "INV-042", "Acme Ltd");
+        assert!(body.contains("They attached timesheet.pdf"));
+    }
+}
@@ -1,19 +1,142 @@
+use std::sync::Arc;
+
 use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
 use sqlx::PgPool;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::attachments::attachment_links_text;
+use crate::business_days::HolidayCalendar;
+use crate::chase::holds::active_hold;
+use crate::chase::retries::{
+    chase_retry, clear_patch, dead_letter_notification, is_retryable, ChaseRetry, Undeliverable,
+};
+use crate::chase::send_window::SendWindow;
+use crate::clients::statement::build_statement;
+use crate::clients::stats::PaymentBehavior;
+use crate::contracts::agreement_reference;
+use crate::email_sandbox::{delivery_for, send_with, Delivery};
+use crate::invoices::late_fees::{
+    announced_patch, charged_late_fee, has_late_fee, late_fee_notice, unannounced_late_fee, LateFeePolicy,
+};
+use crate::invoices::pdf::{cached_invoice_pdf, render_invoice_pdf, render_statement_pdf};
+use crate::logging::redact_email;
+use crate::models::chase_override::ChaseOverride;
+use crate::models::correspondence::{CorrespondenceKind, CreateCorrespondence};
 use crate::models::invoice::Invoice;
-use crate::worker::services::{generate_email, send_email};
-use crate::worker::state_machine::{ChaseAction, ChaseState, ChaseStateMachine, Transition};
+use crate::payment_methods::{instructions, instructions_text};
+use crate::repo::{DynRepository, PgRepository};
+use crate::settings::SettingsCache;
+use crate::storage::DynBlobStore;
+use crate::worker::services::{
+    email_sender, generate_email, render_email_html, EmailAttachment,
+};
+use crate::worker::statements::render_statement;
+use crate::worker::state_machine::{
+    current_chase_state, days_overdue, ChaseAction, ChaseState, ChaseStateMachine, Transition,
+    ESCALATION_INTERVAL_DAYS,
+};
+
+/// Whether chase emails should carry the invoice PDF.
+/// 
+/// Controlled by `CHASE_ATTACH_INVOICE_PDF` (default: true).
+fn attach_invoice_pdf() -> bool {
+    std::env::var("CHASE_ATTACH_INVOICE_PDF")
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(true)
+}
+
+/// Chase decision for a single invoice, computed before any action runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChasePlan {
+    /// State before this run
+    pub current_state: ChaseState,
+    
+    /// State after the action is executed
+    pub next_state: ChaseState,
+    
+    /// Action to take
+    pub action: ChaseAction,
+    
+    /// Late fee to charge before the reminder is sent
+    pub late_fee: Option<Decimal>,
+    
+    /// How the client usually pays
+    pub behavior: PaymentBehavior,
+}
+
+impl ChasePlan {
+    /// Email tone for the plan's action, if it sends an email.
+    /// 
+    /// The first reminder's tone depends on how the client usually pays.
+    fn tone(&self) -> Option<&'static str> {
+        match self.action {
+            ChaseAction::SendPoliteReminder => Some(self.behavior.reminder_tone()),
+            ChaseAction::SendFirmReminder => Some("firm"),
+            ChaseAction::SendUrgentReminder => Some("urgent"),
+            ChaseAction::SendFinalNotice => Some("final"),
+            ChaseAction::MarkAsPaid | ChaseAction::NoAction => None,
+        }
+    }
+}
+
+/// Base URL of public pages linked from emails.
+/// 
+/// Read from `PUBLIC_BASE_URL` (default: `http://localhost:8080`).
+fn public_base_url() -> String {
+    std::env::var("PUBLIC_BASE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string())
+}
+
+/// Public link where the client can pay an invoice.
+/// 
+/// Built from `PUBLIC_BASE_URL` (default: `http://localhost:8080`).
+pub fn pay_link(invoice: &Invoice) -> String {
+    format!("{}/pay/{}", public_base_url().trim_end_matches('/'), invoice.id)
+}
+
+/// Describes what the client owes on an invoice for chase emails.
+/// 
+/// Partially paid invoices mention both the outstanding balance and the
+/// original total.
+fn amount_owed(invoice: &Invoice) -> String {
+    if invoice.amount_paid > rust_decimal::Decimal::ZERO {
+        format!(
+            "{} {:.2} outstanding of {} {:.2}",
+            invoice.currency,
+            invoice.balance_due(),
+            invoice.currency,
+            invoice.total
+        )
+    } else {
+        format!("{} {:.2}", invoice.currency, invoice.total)
+    }
+}
+
+/// Sentence warning the client of collections, for final notices. The
+/// deadline is one more escalation interval ([`ESCALATION_INTERVAL_DAYS`])
+/// after the notice.
+fn collections_notice(invoice: &Invoice) -> String {
+    let days = match ESCALATION_INTERVAL_DAYS {
+        1 => "1 day".to_string(),
+        days => format!("{} days", days),
+    };
+    format!(
+        "If invoice {} remains unpaid {} from now, it will be referred to a collections agency.",
+        invoice.invoice_number, days
+    )
+}
 
 /// Executor for processing invoice chase actions.
 /// 
 /// Handles the execution of chase actions determined by the state machine,
 /// including generating emails, sending them, and updating invoice state.
 pub struct ChaseExecutor {
-    /// Database connection pool
-    pool: PgPool,
+    /// Invoices, client payment details, settings and sync
+    repo: DynRepository,
+
+    /// Blob storage caching rendered invoice PDFs (None renders every time)
+    pdf_cache: Option<DynBlobStore>,
 }
 
 impl ChaseExecutor {
@@ -22,20 +145,34 @@ impl ChaseExecutor {
     /// # Arguments
     /// 
     /// * `pool` - PostgreSQL connection pool
+    /// * `settings_cache` - Shared settings cache
     /// 
     /// # Returns
     /// 
     /// Returns a new `ChaseExecutor` instance.
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, settings_cache: SettingsCache) -> Self {
+        Self::with_repository(Arc::new(PgRepository::new(pool, settings_cache)))
+    }
+
+    /// Creates a chase executor over any repository (e.g. in-memory in tests).
+    pub fn with_repository(repo: DynRepository) -> Self {
+        Self { repo, pdf_cache: None }
+    }
+
+    /// Reuses invoice PDFs cached in blob storage for email attachments.
+    pub fn with_pdf_cache(mut self, store: DynBlobStore) -> Self {
+        self.pdf_cache = Some(store);
+        self
     }
 
     /// Processes an invoice through the chasing state machine.
     /// 
     /// This function:
     /// 1. Determines the current chase state (from metadata or defaults to Pending)
-    /// 2. Calculates days overdue
-    /// 3. Transitions to next state using the state machine
+    /// 2. Calculates days overdue (in business days if the user skips
+    ///    weekends and holidays, in which case nothing is sent on those days)
+    /// 3. Transitions to next state using the state machine, honouring the
+    ///    invoice's chase override, grace hold and remaining balance
     /// 4. Executes the required action (send email, etc.)
     /// 5. Updates the invoice state in the database
     /// 
@@ -47,35 +184,210 @@ impl ChaseExecutor {
     /// 
     /// Returns `Ok(())` if processing succeeded, or an error.
     pub async fn process_invoice(&self, invoice: &Invoice) -> Result<(), anyhow::Error> {
+        match self.plan_invoice(invoice).await? {
+            Some(plan) => self.execute_plan(invoice, plan).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Processes all overdue invoices of a single client together.
+    /// 
+    /// When two or more of the client's invoices are due a reminder in this
+    /// run, one consolidated email listing all of them is sent instead of
+    /// separate emails. Users who chase with statements instead send one
+    /// statement of all the client's open invoices as soon as any of them
+    /// is due a reminder. Invoices with other actions are processed as usual.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `invoices` - Invoices of one user sharing the same client email
+    /// 
+    /// # Returns
+    /// 
+    /// Returns the number of invoices processed successfully.
+    pub async fn process_client_invoices(&self, invoices: &[Invoice]) -> Result<usize, anyhow::Error> {
+        let mut reminders = Vec::new();
+        let mut processed = 0;
+        
+        for invoice in invoices {
+            match self.plan_invoice(invoice).await {
+                Ok(Some(plan)) if plan.tone().is_some() => reminders.push((invoice, plan)),
+                Ok(Some(plan)) => match self.execute_plan(invoice, plan).await {
+                    Ok(()) => processed += 1,
+                    Err(e) => error!("Failed to process invoice {}: {}", invoice.invoice_number, e),
+                },
+                Ok(None) => processed += 1,
+                Err(e) => error!("Failed to plan invoice {}: {}", invoice.invoice_number, e),
+            }
+        }
+        
+        let chase_with_statement = match reminders.first() {
+            Some((invoice, _)) => self.repo.user_settings(invoice.user_id).await?.chase_with_statement,
+            None => false,
+        };
+        
+        let reminded: Vec<&Invoice> = reminders.iter().map(|(invoice, _)| *invoice).collect();
+        if chase_with_statement {
+            let sent = self.send_statement_email(&reminders).await;
+            self.record_send(&reminded, sent).await?;
+            processed += reminders.len();
+        } else if reminders.len() < 2 {
+            for (invoice, plan) in reminders {
+                match self.execute_plan(invoice, plan).await {
+                    Ok(()) => processed += 1,
+                    Err(e) => error!("Failed to process invoice {}: {}", invoice.invoice_number, e),
+                }
+            }
+        } else {
+            let sent = self.send_consolidated_email(&reminders).await;
+            self.record_send(&reminded, sent).await?;
+            processed += reminders.len();
+        }
+        
+        Ok(processed)
+    }
+
+    /// Determines the chase plan for an invoice without executing it.
+    /// 
+    /// # Returns
+    /// 
+    /// Returns `Some(plan)`, or `None` if chasing is deferred for this run
+    /// (e.g. today is not a business day for the user).
+    pub async fn plan_invoice(&self, invoice: &Invoice) -> Result<Option<ChasePlan>, anyhow::Error> {
         info!(
             "Processing invoice {} for chasing",
             invoice.invoice_number
         );
         
+        // Leave the invoice alone while the user paused its reminders
+        if invoice.is_chase_paused(Utc::now()) {
+            info!(
+                "Skipping invoice {}: reminders paused until {:?}",
+                invoice.invoice_number, invoice.chase_paused_until
+            );
+            return Ok(None);
+        }
+        
+        // Wait out the backoff after a failed send
+        if let Some(retry) = chase_retry(invoice).filter(|retry| retry.is_waiting(Utc::now())) {
+            info!(
+                "Deferring invoice {}: {} failed sends, retrying at {:?}",
+                invoice.invoice_number, retry.attempts, retry.next_retry_at
+            );
+            return Ok(None);
+        }
+        
         // Get current chase state from metadata or default to Pending
         let current_state = self.get_chase_state(invoice)?;
         
+        // Load the holiday calendar if the user skips non-business days
+        let settings = self.repo.user_settings(invoice.user_id).await?;
+        let calendar = if settings.skip_non_business_days {
+            Some(self.repo.calendar(settings.country_code.as_deref()).await?)
+        } else {
+            None
+        };
+        
+        // Business days and the send window follow the user's timezone
+        let window = SendWindow::from_settings(&settings);
+        
+        // Defer all chase activity on weekends and holidays
+        if let Some(calendar) = &calendar {
+            let now = Utc::now();
+            let today = window.map_or(now.date_naive(), |window| window.local_date(now));
+            if !calendar.is_business_day(today) {
+                info!(
+                    "Deferring invoice {}: {} is not a business day",
+                    invoice.invoice_number, today
+                );
+                return Ok(None);
+            }
+        }
+        
         // Calculate days overdue
-        let days_overdue = self.calculate_days_overdue(invoice)?;
+        let days_overdue = self.calculate_days_overdue(invoice, calendar.as_ref())?;
         
-        // Determine next state and action
-        let (next_state, action) = ChaseStateMachine::transition(current_state, days_overdue);
+        // Determine next state and action, honouring any per-invoice override;
+        // otherwise slow payers are escalated sooner and prompt ones later
+        let stats = self.repo.client_stats(invoice).await?;
+        let behavior = PaymentBehavior::from_stats(stats.as_ref());
+        let mut overrides = behavior.tailor(ChaseOverride::parse(invoice.chase_override.as_ref())?);
+        
+        // Give a payment the client just started time to arrive
+        if let Some(hold) = active_hold(invoice, Utc::now()) {
+            info!(
+                "Holding invoice {} until {} ({:?})",
+                invoice.invoice_number, hold.until, hold.reason
+            );
+            overrides.paused = true;
+        }
+        
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            current_state,
+            days_overdue,
+            invoice.balance_due(),
+            &overrides,
+            Utc::now().date_naive(),
+        );
         
         info!(
-            "Invoice {}: {} -> {} (action: {})",
+            "Invoice {}: {} -> {} (action: {}, client: {:?})",
             invoice.invoice_number,
             current_state,
             next_state,
-            action
+            action,
+            behavior
         );
         
+        // Hold emails until the send window opens; the state is left as is,
+        // so the same reminder goes out then
+        if let Some(window) = window.filter(|window| action.escalation() > 0 && !window.contains(Utc::now())) {
+            info!(
+                "Deferring invoice {}: outside the send window until {}",
+                invoice.invoice_number,
+                window.next_opening(Utc::now())
+            );
+            return Ok(None);
+        }
+        
+        // Late fees are charged once, on escalation to the firm reminder or,
+        // if not due yet then, a later one
+        let late_fee = if action.charges_late_fee() && !has_late_fee(invoice) {
+            LateFeePolicy::from_settings(&settings).fee(
+                invoice.balance_due(),
+                &invoice.currency,
+                days_overdue,
+            )
+        } else {
+            None
+        };
+        
+        Ok(Some(ChasePlan {
+            current_state,
+            next_state,
+            action,
+            late_fee,
+            behavior,
+        }))
+    }
+
+    /// Executes a previously computed chase plan for an invoice.
+    async fn execute_plan(&self, invoice: &Invoice, plan: ChasePlan) -> Result<(), anyhow::Error> {
+        let ChasePlan { current_state, next_state, action, .. } = plan;
+        
         // Execute the action
         match action {
             ChaseAction::SendPoliteReminder => {
-                self.send_chase_email(invoice, "polite", &next_state).await?;
+                let sent = self.send_chase_email(invoice, plan.behavior.reminder_tone(), &next_state, None).await;
+                self.record_send(&[invoice], sent).await?;
             }
-            ChaseAction::SendFirmReminder => {
-                self.send_chase_email(invoice, "firm", &next_state).await?;
+            ChaseAction::SendFirmReminder | ChaseAction::SendUrgentReminder | ChaseAction::SendFinalNotice => {
+                let tone = plan.tone().unwrap_or("firm");
+                let sent = match self.charge_late_fee(invoice, &plan).await {
+                    Ok((charged, late_fee)) => self.send_chase_email(&charged, tone, &next_state, late_fee).await,
+                    Err(e) => Err(e),
+                };
+                self.record_send(&[invoice], sent).await?;
             }
             ChaseAction::MarkAsPaid => {
                 // Invoice was marked as paid, update state
@@ -93,6 +405,35 @@ impl ChaseExecutor {
         Ok(())
     }
 
+    /// Charges the plan's late fee, if any.
+    /// 
+    /// # Returns
+    /// 
+    /// Returns the invoice to chase (updated if a fee was charged) and the
+    /// fee to announce: the one charged in this run, or one an earlier run
+    /// charged but whose reminder didn't go out.
+    async fn charge_late_fee(
+        &self,
+        invoice: &Invoice,
+        plan: &ChasePlan,
+    ) -> Result<(Invoice, Option<Decimal>), anyhow::Error> {
+        let Some(fee) = plan.late_fee else {
+            return Ok((invoice.clone(), unannounced_late_fee(invoice)));
+        };
+        
+        match self.repo.apply_late_fee(invoice, fee).await? {
+            Some(updated) => {
+                info!(
+                    "Charged late fee of {} {:.2} on invoice {}",
+                    updated.currency, fee, updated.invoice_number
+                );
+                Ok((updated, Some(fee)))
+            }
+            // Charged concurrently by another run
+            None => Ok((invoice.clone(), None)),
+        }
+    }
+
     /// Gets the current chase state from invoice metadata.
     /// 
     /// # Arguments
@@ -103,35 +444,7 @@ impl ChaseExecutor {
     /// 
     /// Returns the current chase state, or Pending if not set.
     fn get_chase_state(&self, invoice: &Invoice) -> Result<ChaseState, anyhow::Error> {
-        // Try to get chase_state from metadata
-        if let Some(metadata) = &invoice.metadata {
-            if let Some(chase_state_str) = metadata.get("chase_state").and_then(|v| v.as_str()) {
-                match chase_state_str {
-                    "pending" => return Ok(ChaseState::Pending),
-                    "overdue" => return Ok(ChaseState::Overdue),
-                    "chasing_level_1" => return Ok(ChaseState::ChasingLevel1),
-                    "chasing_level_2" => return Ok(ChaseState::ChasingLevel2),
-                    "paid" => return Ok(ChaseState::Paid),
-                    _ => {
-                        warn!("Unknown chase_state in metadata: {}", chase_state_str);
-                    }
-                }
-            }
-        }
-        
-        // Default based on invoice status and due date
-        if invoice.status == crate::models::invoice::InvoiceStatus::Paid {
-            Ok(ChaseState::Paid)
-        } else if let Some(due_date) = invoice.due_date {
-            let today = Utc::now().date_naive();
-            if due_date < today {
-                Ok(ChaseState::Overdue)
-            } else {
-                Ok(ChaseState::Pending)
-            }
-        } else {
-            Ok(ChaseState::Pending)
-        }
+        Ok(current_chase_state(invoice, Utc::now().date_naive()))
     }
 
     /// Calculates the number of days an invoice is overdue.
@@ -139,23 +452,17 @@ impl ChaseExecutor {
     /// # Arguments
     /// 
     /// * `invoice` - The invoice
+    /// * `calendar` - Holiday calendar; when set, only business days count
     /// 
     /// # Returns
     /// 
     /// Returns the number of days overdue, or 0 if not overdue.
-    fn calculate_days_overdue(&self, invoice: &Invoice) -> Result<i64, anyhow::Error> {
-        let today = Utc::now().date_naive();
-        
-        if let Some(due_date) = invoice.due_date {
-            if due_date < today {
-                let days = (today - due_date).num_days();
-                Ok(days)
-            } else {
-                Ok(0)
-            }
-        } else {
-            Ok(0)
-        }
+    fn calculate_days_overdue(
+        &self,
+        invoice: &Invoice,
+        calendar: Option<&HolidayCalendar>,
+    ) -> Result<i64, anyhow::Error> {
+        Ok(days_overdue(invoice, Utc::now().date_naive(), calendar))
     }
 
     /// Sends a chase email for an invoice.
@@ -163,8 +470,9 @@ impl ChaseExecutor {
     /// # Arguments
     /// 
     /// * `invoice` - The invoice to chase
-    /// * `tone` - Email tone ("polite" or "firm")
+    /// * `tone` - Email tone ("gentle", "polite", "direct" or "firm")
     /// * `new_state` - The new chase state after sending
+    /// * `late_fee` - Late fee to announce (see [`Self::charge_late_fee`])
     /// 
     /// # Returns
     /// 
@@ -174,38 +482,562 @@ impl ChaseExecutor {
         invoice: &Invoice,
         tone: &str,
         new_state: &ChaseState,
+        late_fee: Option<Decimal>,
     ) -> Result<(), anyhow::Error> {
         // Get client email
         let client_email = invoice.client_email.as_ref().ok_or_else(|| {
-            anyhow::anyhow!("No client email for invoice {}", invoice.invoice_number)
+            Undeliverable(format!("No client email for invoice {}", invoice.invoice_number))
         })?;
         
         // Build context string for LLM
-        let context = format!(
-            "Invoice {} for {} {:.2} (Due: {:?})",
+        let mut context = format!(
+            "Invoice {} for {} (Due: {:?})",
             invoice.invoice_number,
-            invoice.currency,
-            invoice.amount,
+            amount_owed(invoice),
             invoice.due_date
         );
+        let settings = self.repo.user_settings(invoice.user_id).await?;
         
-        // Generate email content using LLM
-        let (subject, body) = generate_email(tone, &context).await?;
+        // Late fees are announced when charged and again in the last reminders
+        let restated = matches!(new_state, ChaseState::ChasingLevel3 | ChaseState::FinalNotice);
+        let notice = late_fee
+            .or(if restated { charged_late_fee(invoice) } else { None })
+            .map(|fee| late_fee_notice(invoice, fee));
+        if let Some(notice) = &notice {
+            context = format!("{}\n{}", context, notice);
+        }
+        let collections = (*new_state == ChaseState::FinalNotice && settings.final_notice_mentions_collections)
+            .then(|| collections_notice(invoice));
+        if let Some(collections) = &collections {
+            context = format!("{}\n{}", context, collections);
+        }
+        let agreement = self.repo.signed_agreement(invoice).await?;
+        let reference = agreement.as_ref().and_then(agreement_reference);
+        if let Some(reference) = &reference {
+            context = format!("{}\n{}", context, reference);
+        }
         
-        // Send email
-        send_email(client_email, &subject, &body).await?;
+        // Generate email content using LLM, if the user allows it
+        let (subject, mut body) = generate_email(tone, &context, settings.ai_consent()).await?;
+        
+        // State the late fee and collections warning verbatim rather than
+        // relying on the LLM
+        if let Some(notice) = &notice {
+            body = format!("{}\n\n{}", body, notice);
+        }
+        if let Some(collections) = &collections {
+            body = format!("{}\n\n{}", body, collections);
+        }
+        
+        // Cite the signed contract the work was done under
+        if let Some(reference) = &reference {
+            body = format!("{}\n\n{}", body, reference);
+        }
+        
+        // Tell the client how they can pay
+        let methods = self.repo.payment_methods_for_client(invoice.user_id, Some(client_email)).await?;
+        if let Some(how_to_pay) = instructions_text(&methods, &invoice.invoice_number) {
+            body = format!("{}\n\n{}", body, how_to_pay);
+        }
+        
+        // Link the files attached to the invoice (contracts, receipts, ...)
+        let linked = self.repo.list_attachments(invoice.user_id, invoice.id).await?;
+        if let Some(links) = attachment_links_text(&public_base_url(), &linked) {
+            body = format!("{}\n\n{}", body, links);
+        }
+        
+        // Attach the invoice PDF unless disabled
+        let attachments = if attach_invoice_pdf() {
+            vec![self.invoice_pdf_attachment(invoice).await?]
+        } else {
+            Vec::new()
+        };
+        
+        // Send email (or keep it from the client in sandbox mode)
+        let delivery = self.deliver(invoice.user_id, client_email, &subject, &body, &attachments).await?;
+        
+        // Keep a copy of the email as evidence for correspondence exports
+        self.repo.record_correspondence(
+            invoice.user_id,
+            CreateCorrespondence {
+                invoice_id: invoice.id,
+                kind: CorrespondenceKind::Email,
+                sender: Some(email_sender()),
+                recipient: Some(client_email.clone()),
+                subject: Some(subject.clone()),
+                body_html: Some(render_email_html(&subject, &body)),
+                body_text: Some(body),
+                provider_message_id: None,
+                delivery_status: Some(delivery.status().to_string()),
+                metadata: Some(serde_json::json!({
+                    "tone": tone,
+                    "chase_state": new_state.to_string(),
+                    "attachments": attachments.iter().map(|a| &a.filename).collect::<Vec<_>>(),
+                    "linked_attachments": linked.iter().map(|a| a.id).collect::<Vec<_>>(),
+                    "signed_agreement": agreement.as_ref().map(|c| c.id),
+                    "late_fee": late_fee,
+                })),
+                occurred_at: None,
+            },
+        )
+        .await?;
         
         // Update invoice state
+        if late_fee.is_some() {
+            self.record_late_fee_announced(invoice).await?;
+        }
         self.update_chase_state(invoice.id, *new_state).await?;
         
         info!(
             "Sent {} chase email for invoice {} to {}",
-            tone, invoice.invoice_number, client_email
+            tone, invoice.invoice_number, redact_email(client_email)
+        );
+        
+        Ok(())
+    }
+
+    /// Sends one consolidated reminder covering several invoices of a client.
+    /// 
+    /// The email lists every invoice with its amount, due date and pay link,
+    /// plus the combined total per currency. The firmest tone among the
+    /// invoices is used, and each invoice advances to its own next state.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `reminders` - Invoices (of one client) with their send plans
+    /// 
+    /// # Returns
+    /// 
+    /// Returns `Ok(())` once the email was sent (see
+    /// [`record_reminder_sent`](Self::record_reminder_sent)), or an error if
+    /// it wasn't.
+    async fn send_consolidated_email(&self, reminders: &[(&Invoice, ChasePlan)]) -> Result<(), anyhow::Error> {
+        let (first, _) = reminders[0];
+        let client_email = first.client_email.as_ref().ok_or_else(|| {
+            Undeliverable(format!("No client email for invoice {}", first.invoice_number))
+        })?;
+        
+        // Charge late fees first so the listed amounts include them
+        let mut charged = Vec::with_capacity(reminders.len());
+        for (invoice, plan) in reminders {
+            let (invoice, late_fee) = self.charge_late_fee(invoice, plan).await?;
+            charged.push((invoice, *plan, late_fee));
+        }
+        
+        // All invoices belong to the same client, so share the firmest tone
+        let tone = reminders
+            .iter()
+            .map(|(_, plan)| plan)
+            .max_by_key(|plan| plan.action.escalation())
+            .and_then(ChasePlan::tone)
+            .unwrap_or("polite");
+        
+        // Combined total per currency, in first-seen order
+        let mut totals: Vec<(String, rust_decimal::Decimal)> = Vec::new();
+        let mut lines = Vec::with_capacity(charged.len());
+        let mut notices = Vec::new();
+        for (invoice, _, late_fee) in &charged {
+            lines.push(format!(
+                "- Invoice {}: {} (Due: {:?}) - pay at {}",
+                invoice.invoice_number,
+                amount_owed(invoice),
+                invoice.due_date,
+                pay_link(invoice)
+            ));
+            if let Some(fee) = late_fee {
+                notices.push(late_fee_notice(invoice, *fee));
+            }
+            let balance_due = invoice.balance_due();
+            match totals.iter_mut().find(|(currency, _)| *currency == invoice.currency) {
+                Some((_, total)) => *total += balance_due,
+                None => totals.push((invoice.currency.clone(), balance_due)),
+            }
+        }
+        let combined = totals
+            .iter()
+            .map(|(currency, total)| format!("{} {:.2}", currency, total))
+            .collect::<Vec<_>>()
+            .join(" + ");
+        
+        let mut context = format!(
+            "the following {} invoices:\n{}\nCombined total: {}",
+            charged.len(),
+            lines.join("\n"),
+            combined
+        );
+        if !notices.is_empty() {
+            context = format!("{}\n{}", context, notices.join("\n"));
+        }
+        
+        // One citation per contract, even if it covers several invoices
+        let mut agreements: Vec<(Uuid, Uuid)> = Vec::new();
+        let mut references = Vec::new();
+        for (invoice, _, _) in &charged {
+            let Some(contract) = self.repo.signed_agreement(invoice).await? else {
+                continue;
+            };
+            if !agreements.iter().any(|(_, id)| *id == contract.id) {
+                references.extend(agreement_reference(&contract));
+            }
+            agreements.push((invoice.id, contract.id));
+        }
+        if !references.is_empty() {
+            context = format!("{}\n{}", context, references.join("\n"));
+        }
+        
+        let consent = self.repo.user_settings(first.user_id).await?.ai_consent();
+        let (subject, mut body) = generate_email(tone, &context, consent).await?;
+        
+        if !notices.is_empty() {
+            body = format!("{}\n\n{}", body, notices.join("\n"));
+        }
+        if !references.is_empty() {
+            body = format!("{}\n\n{}", body, references.join("\n"));
+        }
+        
+        let invoice_numbers = charged
+            .iter()
+            .map(|(invoice, _, _)| invoice.invoice_number.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let methods = self.repo.payment_methods_for_client(first.user_id, Some(client_email)).await?;
+        if let Some(how_to_pay) = instructions_text(&methods, &invoice_numbers) {
+            body = format!("{}\n\n{}", body, how_to_pay);
+        }
+        
+        let mut linked = Vec::new();
+        for (invoice, _, _) in &charged {
+            linked.extend(self.repo.list_attachments(invoice.user_id, invoice.id).await?);
+        }
+        if let Some(links) = attachment_links_text(&public_base_url(), &linked) {
+            body = format!("{}\n\n{}", body, links);
+        }
+        
+        let mut attachments = Vec::new();
+        if attach_invoice_pdf() {
+            for (invoice, _, _) in &charged {
+                attachments.push(self.invoice_pdf_attachment(invoice).await?);
+            }
+        }
+        
+        let delivery = self.deliver(first.user_id, client_email, &subject, &body, &attachments).await?;
+        
+        let invoice_ids: Vec<Uuid> = charged.iter().map(|(invoice, _, _)| invoice.id).collect();
+        let body_html = render_email_html(&subject, &body);
+        
+        let mut sent = Vec::with_capacity(charged.len());
+        for (invoice, plan, late_fee) in &charged {
+            sent.push((
+                invoice,
+                plan.next_state,
+                CreateCorrespondence {
+                    invoice_id: invoice.id,
+                    kind: CorrespondenceKind::Email,
+                    sender: Some(email_sender()),
+                    recipient: Some(client_email.clone()),
+                    subject: Some(subject.clone()),
+                    body_text: Some(body.clone()),
+                    body_html: Some(body_html.clone()),
+                    provider_message_id: None,
+                    delivery_status: Some(delivery.status().to_string()),
+                    metadata: Some(serde_json::json!({
+                        "tone": tone,
+                        "chase_state": plan.next_state.to_string(),
+                        "consolidated_with": invoice_ids,
+                        "linked_attachments": linked
+                            .iter()
+                            .filter(|a| a.invoice_id == invoice.id)
+                            .map(|a| a.id)
+                            .collect::<Vec<_>>(),
+                        "signed_agreement": agreements
+                            .iter()
+                            .find(|(invoice_id, _)| *invoice_id == invoice.id)
+                            .map(|(_, contract_id)| contract_id),
+                        "late_fee": late_fee,
+                    })),
+                    occurred_at: None,
+                },
+            ));
+        }
+        self.record_reminder_sent(sent).await;
+        
+        info!(
+            "Sent consolidated {} chase email covering {} invoices to {}",
+            tone,
+            reminders.len(),
+            redact_email(client_email)
+        );
+        
+        Ok(())
+    }
+
+    /// Sends one statement of all a client's open invoices in place of
+    /// reminders.
+    /// 
+    /// The statement lists every open invoice of the client, not only the
+    /// ones due a reminder, with the totals outstanding per currency, and
+    /// carries the statement PDF. Late fees are charged first so they are
+    /// included, and each reminded invoice advances to its own next state.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `reminders` - Invoices (of one client) with their send plans
+    /// 
+    /// # Returns
+    /// 
+    /// Returns `Ok(())` once the statement was sent (see
+    /// [`record_reminder_sent`](Self::record_reminder_sent)), or an error if
+    /// it wasn't.
+    async fn send_statement_email(&self, reminders: &[(&Invoice, ChasePlan)]) -> Result<(), anyhow::Error> {
+        let (first, _) = reminders[0];
+        let client_email = first.client_email.as_ref().ok_or_else(|| {
+            Undeliverable(format!("No client email for invoice {}", first.invoice_number))
+        })?;
+        
+        let mut charged = Vec::with_capacity(reminders.len());
+        let mut notices = Vec::new();
+        for (invoice, plan) in reminders {
+            let (invoice, late_fee) = self.charge_late_fee(invoice, plan).await?;
+            if let Some(fee) = late_fee {
+                notices.push(late_fee_notice(&invoice, fee));
+            }
+            charged.push((invoice, *plan, late_fee));
+        }
+        
+        let today = Utc::now().date_naive();
+        let open = self.repo.outstanding_invoices(first).await?;
+        let statement = build_statement(first.client_id, &first.client_name, Some(client_email), today, &open);
+        
+        let references = open
+            .iter()
+            .map(|invoice| invoice.invoice_number.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let methods = self.repo.payment_methods_for_client(first.user_id, Some(client_email)).await?;
+        let how_to_pay = instructions_text(&methods, &references);
+        let locale = self.repo.user_settings(first.user_id).await?.statement_locale;
+        let (subject, mut body) = render_statement(
+            locale,
+            &first.client_name,
+            today,
+            &open,
+            how_to_pay.as_deref(),
+        );
+        if !notices.is_empty() {
+            body = format!("{}\n\n{}", body, notices.join("\n"));
+        }
+        
+        let mut branding = self.repo.pdf_branding(first).await?;
+        branding.payment_instructions = instructions(&methods, &references);
+        let attachments = vec![EmailAttachment {
+            filename: format!("statement-{}.pdf", today),
+            content_type: "application/pdf".to_string(),
+            data: render_statement_pdf(&statement, &branding)?,
+        }];
+        
+        let delivery = self.deliver(first.user_id, client_email, &subject, &body, &attachments).await?;
+        
+        let statement_of: Vec<Uuid> = open.iter().map(|invoice| invoice.id).collect();
+        let body_html = render_email_html(&subject, &body);
+        
+        let mut sent = Vec::with_capacity(charged.len());
+        for (invoice, plan, late_fee) in &charged {
+            sent.push((
+                invoice,
+                plan.next_state,
+                CreateCorrespondence {
+                    invoice_id: invoice.id,
+                    kind: CorrespondenceKind::Email,
+                    sender: Some(email_sender()),
+                    recipient: Some(client_email.clone()),
+                    subject: Some(subject.clone()),
+                    body_text: Some(body.clone()),
+                    body_html: Some(body_html.clone()),
+                    provider_message_id: None,
+                    delivery_status: Some(delivery.status().to_string()),
+                    metadata: Some(serde_json::json!({
+                        "tone": plan.tone(),
+                        "chase_state": plan.next_state.to_string(),
+                        "statement_of": statement_of,
+                        "late_fee": late_fee,
+                    })),
+                    occurred_at: None,
+                },
+            ));
+        }
+        self.record_reminder_sent(sent).await;
+        
+        info!(
+            "Sent statement of {} open invoices in place of {} reminders to {}",
+            open.len(),
+            reminders.len(),
+            redact_email(client_email)
         );
         
         Ok(())
     }
 
+    /// Sends an email for a user, through the sandbox if it is on for them.
+    /// 
+    /// # Returns
+    /// 
+    /// Returns how the email was delivered.
+    async fn deliver(
+        &self,
+        user_id: Uuid,
+        to: &str,
+        subject: &str,
+        body: &str,
+        attachments: &[EmailAttachment],
+    ) -> Result<Delivery, anyhow::Error> {
+        let delivery = delivery_for(&self.repo.user_settings(user_id).await?);
+        if let Some(email) = send_with(&delivery, to, subject, body, attachments).await? {
+            self.repo.record_sandboxed_email(user_id, email).await?;
+        }
+        Ok(delivery)
+    }
+
+    /// Renders the invoice PDF as an email attachment (from the PDF cache
+    /// when one is configured and holds the current version).
+    /// 
+    /// # Arguments
+    /// 
+    /// * `invoice` - The invoice to render
+    /// 
+    /// # Returns
+    /// 
+    /// Returns the PDF attachment, or an error if rendering fails.
+    async fn invoice_pdf_attachment(&self, invoice: &Invoice) -> Result<EmailAttachment, anyhow::Error> {
+        let branding = self.repo.pdf_branding(invoice).await?;
+        let data = match &self.pdf_cache {
+            Some(store) => cached_invoice_pdf(store.as_ref(), invoice, &branding).await?,
+            None => render_invoice_pdf(invoice, &branding)?,
+        };
+        
+        Ok(EmailAttachment {
+            filename: format!("{}.pdf", invoice.invoice_number),
+            content_type: "application/pdf".to_string(),
+            data,
+        })
+    }
+
+    /// Records the outcome of sending a reminder covering `invoices`.
+    /// 
+    /// A failure is recorded on each invoice so it is retried with backoff,
+    /// or dead-lettered once out of attempts or when retrying won't help, in
+    /// which case the owner is notified (see [`crate::chase::retries`]). A
+    /// success clears earlier failures.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `invoices` - The invoices the reminder covers, as loaded for this run
+    /// * `sent` - The outcome of sending it
+    /// 
+    /// # Returns
+    /// 
+    /// Returns `sent`, or an error if clearing earlier failures fails.
+    async fn record_send(&self, invoices: &[&Invoice], sent: Result<(), anyhow::Error>) -> Result<(), anyhow::Error> {
+        let Err(e) = sent else {
+            for invoice in invoices.iter().filter(|invoice| chase_retry(invoice).is_some()) {
+                self.repo.merge_invoice_metadata(invoice.id, clear_patch()).await?;
+            }
+            return Ok(());
+        };
+        
+        let now = Utc::now();
+        for invoice in invoices {
+            let previous = chase_retry(invoice);
+            let retry = if is_retryable(&e) {
+                ChaseRetry::after_failure(previous.as_ref(), &e.to_string(), now)
+            } else {
+                ChaseRetry::dead_lettered(previous.as_ref(), &e.to_string(), now)
+            };
+            if retry.is_dead_lettered() {
+                error!(
+                    "Giving up chasing invoice {} after {} failed sends: {}",
+                    invoice.invoice_number, retry.attempts, e
+                );
+            } else {
+                warn!(
+                    "Send {} for invoice {} failed, retrying at {:?}: {}",
+                    retry.attempts, invoice.invoice_number, retry.next_retry_at, e
+                );
+            }
+            
+            if let Err(record_error) = self.repo.merge_invoice_metadata(invoice.id, retry.metadata_patch()).await {
+                error!(
+                    "Failed to record failed send for invoice {}: {}",
+                    invoice.invoice_number, record_error
+                );
+                continue;
+            }
+            
+            if retry.is_dead_lettered() {
+                let notification = dead_letter_notification(invoice, &retry);
+                if let Err(notify_error) = self.repo.notify(invoice.user_id, notification).await {
+                    error!(
+                        "Failed to notify owner of dead-lettered invoice {}: {}",
+                        invoice.invoice_number, notify_error
+                    );
+                }
+            }
+        }
+        
+        Err(e)
+    }
+
+    /// Records a reminder that went out covering several invoices.
+    /// 
+    /// Every invoice's next chase state (and the announcement of a late fee
+    /// it charged) is written at once, so the send is recorded for all of
+    /// them or none, and writing it again is harmless. Then each invoice
+    /// gets its correspondence entry. The email is already out, so failures
+    /// are logged rather than returned: a failed send would be retried and
+    /// email the client again.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `sent` - Each invoice (as charged), its next state and its
+    ///   correspondence entry
+    async fn record_reminder_sent(&self, sent: Vec<(&Invoice, ChaseState, CreateCorrespondence)>) {
+        let now = Utc::now();
+        let patches = sent
+            .iter()
+            .map(|(invoice, state, _)| {
+                let mut patch = announced_patch(invoice, now).unwrap_or_else(|| serde_json::json!({}));
+                patch["chase_state"] = serde_json::json!(state.to_string());
+                (invoice.id, patch)
+            })
+            .collect();
+        match self.repo.merge_invoices_metadata(patches).await {
+            Ok(()) => {
+                for (invoice, state, _) in &sent {
+                    info!("Updated chase state for invoice {} to {}", invoice.id, state);
+                }
+            }
+            Err(e) => error!(
+                "Reminder covering {} invoices was sent but their chase states could not be updated: {}",
+                sent.len(),
+                e
+            ),
+        }
+        
+        for (invoice, _, entry) in sent {
+            if let Err(e) = self.repo.record_correspondence(invoice.user_id, entry).await {
+                error!("Failed to record the reminder sent for invoice {}: {}", invoice.invoice_number, e);
+            }
+        }
+    }
+
+    /// Records that a reminder announcing the invoice's late fee went out,
+    /// so later runs don't announce it as new.
+    async fn record_late_fee_announced(&self, invoice: &Invoice) -> Result<(), anyhow::Error> {
+        if let Some(patch) = announced_patch(invoice, Utc::now()) {
+            self.repo.merge_invoice_metadata(invoice.id, patch).await?;
+        }
+        Ok(())
+    }
+
     /// Updates the chase state in the invoice metadata.
     /// 
     /// # Arguments
@@ -221,25 +1053,439 @@ impl ChaseExecutor {
         invoice_id: Uuid,
         state: ChaseState,
     ) -> Result<(), anyhow::Error> {
-        let state_str = state.to_string();
-        
-        sqlx::query!(
-            r#"
-            UPDATE invoices
-            SET 
-                metadata = COALESCE(metadata, '{}'::jsonb) || jsonb_build_object('chase_state', $2),
-                updated_at = NOW(),
-                last_modified = NOW()
-            WHERE id = $1
-            "#,
-            invoice_id,
-            state_str
-        )
-        .execute(&self.pool)
-        .await?;
+        self.repo
+            .merge_invoice_metadata(invoice_id, serde_json::json!({ "chase_state": state.to_string() }))
+            .await?;
         
         info!("Updated chase state for invoice {} to {}", invoice_id, state);
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Timelike};
+    use serde_json::json;
+
+    use crate::attachments::attachment_link;
+    use crate::chase::holds::{ChaseHold, HoldReason};
+    use crate::chase::retries::DEAD_LETTER_NOTIFICATION;
+    use crate::models::attachment::Attachment;
+    use crate::models::client_stats::ClientStats;
+    use crate::models::contract::{Contract, ContractStatus};
+    use crate::models::statement_schedule::StatementLocale;
+    use crate::models::user_settings::{LateFeeKind, UserSettings};
+    use crate::repo::memory::{sample_invoice, InMemoryRepository};
+    use crate::repo::InvoiceRepository;
+
+    #[tokio::test]
+    async fn test_escalation_charges_late_fee_and_mentions_it() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(10);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(200));
+        invoice.metadata = Some(json!({ "chase_state": "chasing_level_1" }));
+
+        let mut settings = UserSettings::defaults(user_id);
+        settings.late_fee_kind = LateFeeKind::Flat;
+        settings.late_fee_amount = Decimal::from(25);
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(invoice.clone())
+                .with_settings(settings),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+
+        let stored = memory.invoice(invoice.id).unwrap();
+        assert_eq!(stored.total, Decimal::from(225));
+        assert_eq!(stored.metadata.unwrap()["chase_state"], "chasing_level_2");
+
+        let sent = memory.correspondence();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0]
+            .body_text
+            .as_deref()
+            .unwrap()
+            .contains("late fee of USD 25.00"));
+    }
+
+    #[tokio::test]
+    async fn test_final_notice_restates_late_fee_and_warns_of_collections() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(21);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(200));
+        invoice.metadata = Some(json!({
+            "chase_state": "chasing_level_3",
+            "late_fee": { "amount": "25", "currency": "USD" },
+        }));
+
+        let mut settings = UserSettings::defaults(user_id);
+        settings.final_notice_mentions_collections = true;
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(invoice.clone())
+                .with_settings(settings),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+
+        let stored = memory.invoice(invoice.id).unwrap();
+        assert_eq!(stored.metadata.unwrap()["chase_state"], "final_notice");
+
+        let sent = memory.correspondence();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].subject.as_deref().unwrap().starts_with("Final Notice"));
+        let body = sent[0].body_text.as_deref().unwrap();
+        assert!(body.contains("late fee of USD 25.00"));
+        assert!(body.contains(&format!(
+            "If invoice {} remains unpaid {} days from now, it will be referred to a collections agency.",
+            invoice.invoice_number, ESCALATION_INTERVAL_DAYS
+        )));
+
+        // Nothing more is sent after the final notice
+        let stored = memory.invoice(invoice.id).unwrap();
+        executor.process_invoice(&stored).await.unwrap();
+        assert_eq!(memory.correspondence().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reminder_links_invoice_attachments() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(2);
+        let invoice = sample_invoice(user_id, due_date, Decimal::from(120));
+        let now = Utc::now();
+        let contract = Attachment {
+            id: Uuid::new_v4(),
+            user_id,
+            invoice_id: invoice.id,
+            filename: "contract.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            size_bytes: 2048,
+            storage_key: "key".to_string(),
+            created_at: now,
+            updated_at: now,
+        };
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(invoice.clone())
+                .with_attachment(contract.clone()),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+
+        let sent = memory.correspondence();
+        assert_eq!(sent.len(), 1);
+        let body = sent[0].body_text.as_deref().unwrap();
+        assert!(body.contains(&format!("- contract.pdf: {}", attachment_link(&public_base_url(), &contract))));
+        assert_eq!(
+            sent[0].metadata.as_ref().unwrap()["linked_attachments"],
+            json!([contract.id])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reminder_cites_signed_agreement() {
+        use chrono::TimeZone;
+
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(2);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(120));
+        invoice.client_id = Some(Uuid::new_v4());
+        invoice.project_id = Some(Uuid::new_v4());
+
+        let signed = |title: &str, project_id: Option<Uuid>, day: u32| Contract {
+            id: Uuid::new_v4(),
+            user_id,
+            client_id: invoice.client_id,
+            project_id,
+            title: title.to_string(),
+            status: ContractStatus::Signed,
+            provider: None,
+            provider_document_id: None,
+            sent_at: None,
+            last_viewed_at: None,
+            signed_at: Some(Utc.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap()),
+            signed_by_name: None,
+            signed_by_email: None,
+            declined_at: None,
+            voided_at: None,
+            signed_filename: None,
+            signed_size_bytes: None,
+            signed_storage_key: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        // The project's contract wins over a later client-wide one
+        let project_contract = signed("Brand refresh", invoice.project_id, 5);
+        let client_contract = signed("Master services agreement", None, 20);
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(invoice.clone())
+                .with_contract(client_contract)
+                .with_contract(project_contract.clone()),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+
+        let sent = memory.correspondence();
+        assert_eq!(sent.len(), 1);
+        let body = sent[0].body_text.as_deref().unwrap();
+        assert!(body.contains("As per our signed agreement \"Brand refresh\" dated 5 March 2024."));
+        assert!(!body.contains("Master services agreement"));
+        assert_eq!(
+            sent[0].metadata.as_ref().unwrap()["signed_agreement"],
+            json!(project_contract.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_payer_is_escalated_sooner() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(4);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(150));
+        invoice.metadata = Some(json!({ "chase_state": "chasing_level_1" }));
+        let stats = ClientStats {
+            client_id: Uuid::new_v4(),
+            user_id,
+            invoices_due: 6,
+            invoices_paid: 5,
+            average_days_to_pay: Some(Decimal::from(45)),
+            average_days_late: Some(Decimal::from(15)),
+            level_2_chases: 4,
+            level_2_rate: Some(Decimal::new(6667, 2)),
+            refreshed_at: Utc::now(),
+        };
+        invoice.client_id = Some(stats.client_id);
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(invoice.clone())
+                .with_client_stats(stats),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        let plan = executor.plan_invoice(&invoice).await.unwrap().unwrap();
+        assert_eq!(plan.behavior, PaymentBehavior::Slow);
+        executor.process_invoice(&invoice).await.unwrap();
+
+        // Four days overdue is before the default level-2 threshold of 7
+        let stored = memory.invoice(invoice.id).unwrap();
+        assert_eq!(stored.metadata.unwrap()["chase_state"], "chasing_level_2");
+        let sent = memory.correspondence();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].metadata.as_ref().unwrap()["tone"], "firm");
+    }
+
+    #[tokio::test]
+    async fn test_settled_invoice_is_marked_paid_without_email() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(3);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(80));
+        invoice.amount_paid = invoice.total;
+
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice.clone()));
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+
+        let stored = memory.invoice(invoice.id).unwrap();
+        assert_eq!(stored.metadata.unwrap()["chase_state"], "paid");
+        assert!(memory.correspondence().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invoice_on_grace_hold_is_not_chased() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(3);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(80));
+        let hold = ChaseHold::new(HoldReason::PaymentStarted, Utc::now() - Duration::hours(1));
+        invoice.metadata = Some(json!({ "chase_state": "overdue", "chase_hold": hold }));
+
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice.clone()));
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+        assert!(memory.correspondence().is_empty());
+
+        // Once the hold lapses the reminder goes out
+        let lapsed = ChaseHold::new(HoldReason::PaymentStarted, Utc::now() - Duration::days(3));
+        invoice.metadata = Some(json!({ "chase_state": "overdue", "chase_hold": lapsed }));
+        executor.process_invoice(&invoice).await.unwrap();
+        assert_eq!(memory.correspondence().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reminder_outside_send_window_is_deferred() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(3);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(80));
+        invoice.metadata = Some(json!({ "chase_state": "overdue" }));
+
+        // A window that has just closed for the day
+        let hour = Utc::now().hour() as i32;
+        let mut settings = UserSettings::defaults(user_id);
+        settings.timezone = Some("UTC".to_string());
+        settings.send_on_weekends = true;
+        (settings.send_window_start_hour, settings.send_window_end_hour) = if hour < 23 { (hour + 1, 24) } else { (0, 23) };
+
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice.clone()).with_settings(settings.clone()));
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+        assert!(memory.correspondence().is_empty());
+        assert_eq!(executor.get_chase_state(&memory.invoice(invoice.id).unwrap()).unwrap(), ChaseState::Overdue);
+
+        // Once the window opens the same reminder goes out
+        settings.send_window_start_hour = 0;
+        settings.send_window_end_hour = 24;
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice.clone()).with_settings(settings));
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+        assert_eq!(memory.correspondence().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_is_retried_after_backoff() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(3);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(80));
+        let retry = ChaseRetry::after_failure(None, "provider unavailable", Utc::now());
+        invoice.metadata = Some(json!({ "chase_state": "overdue", "chase_retry": retry }));
+
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice.clone()));
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        assert!(memory.find_overdue_invoices(Utc::now().date_naive(), 10).await.unwrap().is_empty());
+
+        // Not retried before the backoff lapses
+        executor.process_invoice(&invoice).await.unwrap();
+        assert!(memory.correspondence().is_empty());
+
+        // Once it lapses a sent reminder clears the failures
+        let mut lapsed = retry;
+        lapsed.next_retry_at = Some(Utc::now() - Duration::minutes(1));
+        invoice.metadata = Some(json!({ "chase_state": "overdue", "chase_retry": lapsed }));
+        executor.process_invoice(&invoice).await.unwrap();
+        assert_eq!(memory.correspondence().len(), 1);
+        assert_eq!(chase_retry(&memory.invoice(invoice.id).unwrap()), None);
+    }
+
+    #[tokio::test]
+    async fn test_undeliverable_reminder_is_dead_lettered_and_owner_notified() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(3);
+        let mut invoice = sample_invoice(user_id, due_date, Decimal::from(80));
+        invoice.client_email = None;
+        invoice.metadata = Some(json!({ "chase_state": "overdue" }));
+
+        let memory = Arc::new(InMemoryRepository::new().with_invoice(invoice.clone()));
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        assert!(executor.process_invoice(&invoice).await.is_err());
+
+        // No point retrying without an address
+        let retry = chase_retry(&memory.invoice(invoice.id).unwrap()).unwrap();
+        assert_eq!(retry.attempts, 1);
+        assert!(retry.is_dead_lettered());
+        assert!(memory.find_overdue_invoices(Utc::now().date_naive(), 10).await.unwrap().is_empty());
+
+        let notifications = memory.notifications();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].kind, DEAD_LETTER_NOTIFICATION);
+        assert_eq!(notifications[0].data.as_ref().unwrap()["invoice_id"], json!(invoice.id));
+
+        let dead_letters = memory.dead_lettered_invoices(user_id).await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+
+        // Requeueing resumes chasing
+        let requeued = memory.requeue_chase(user_id, invoice.id).await.unwrap().unwrap();
+        assert_eq!(chase_retry(&requeued), None);
+        assert!(memory.dead_lettered_invoices(user_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_statement_replaces_reminders() {
+        let user_id = Uuid::new_v4();
+        let today = Utc::now().date_naive();
+        let overdue = sample_invoice(user_id, today - Duration::days(3), Decimal::from(120));
+        let mut upcoming = sample_invoice(user_id, today + Duration::days(10), Decimal::from(80));
+        upcoming.invoice_number = "INV-00002".to_string();
+
+        let mut settings = UserSettings::defaults(user_id);
+        settings.chase_with_statement = true;
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(overdue.clone())
+                .with_invoice(upcoming.clone())
+                .with_settings(settings),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        let processed = executor.process_client_invoices(&[overdue.clone()]).await.unwrap();
+        assert_eq!(processed, 1);
+
+        // One statement covering the invoice not yet due as well
+        let sent = memory.correspondence();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].invoice_id, overdue.id);
+        let body = sent[0].body_text.as_deref().unwrap();
+        assert!(body.contains("INV-00001") && body.contains("INV-00002"));
+        assert_eq!(
+            sent[0].metadata.as_ref().unwrap()["statement_of"],
+            json!([overdue.id, upcoming.id])
+        );
+        // Only the reminded invoice advances
+        assert_eq!(memory.invoice(overdue.id).unwrap().metadata.unwrap()["chase_state"], "chasing_level_1");
+        assert!(memory.invoice(upcoming.id).unwrap().metadata.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_statement_uses_the_users_locale() {
+        let user_id = Uuid::new_v4();
+        let overdue = sample_invoice(user_id, Utc::now().date_naive() - Duration::days(3), Decimal::from(1200));
+
+        let mut settings = UserSettings::defaults(user_id);
+        settings.chase_with_statement = true;
+        settings.statement_locale = StatementLocale::De;
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(overdue.clone())
+                .with_settings(settings),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_client_invoices(&[overdue]).await.unwrap();
+
+        let sent = memory.correspondence();
+        assert!(sent[0].subject.as_deref().unwrap().contains("Kontoauszug"));
+        assert!(sent[0].body_text.as_deref().unwrap().contains("1.200,00"));
+    }
+
+    #[tokio::test]
+    async fn test_sandboxed_reminder_is_held_but_chasing_continues() {
+        let user_id = Uuid::new_v4();
+        let due_date = Utc::now().date_naive() - Duration::days(2);
+        let invoice = sample_invoice(user_id, due_date, Decimal::from(120));
+
+        let mut settings = UserSettings::defaults(user_id);
+        settings.email_sandbox = true;
+
+        let memory = Arc::new(
+            InMemoryRepository::new()
+                .with_invoice(invoice.clone())
+                .with_settings(settings),
+        );
+        let executor = ChaseExecutor::with_repository(memory.clone());
+        executor.process_invoice(&invoice).await.unwrap();
+
+        let held = memory.sandboxed_emails();
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].recipient, "billing@acme.test");
+        assert_eq!(held[0].redirected_to, None);
+
+        let sent = memory.correspondence();
+        assert_eq!(sent[0].delivery_status.as_deref(), Some("sandbox_held"));
+        assert_eq!(memory.invoice(invoice.id).unwrap().metadata.unwrap()["chase_state"], "chasing_level_1");
+    }
+}
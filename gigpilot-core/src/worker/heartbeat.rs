@@ -0,0 +1,148 @@
+//! Worker heartbeat.
+//!
+//! Every worker process records that it is alive in `worker_heartbeats`,
+//! along with when it last succeeded and failed to reach the email and
+//! LLM providers. The public status endpoint ([`crate::status`]) reads the
+//! rows; the API process never talks to those providers itself.
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::status::heartbeat_interval_seconds;
+
+/// External provider whose calls are tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    /// Email delivery
+    Email,
+
+    /// LLM used to write chase emails
+    Llm,
+}
+
+/// Latest call outcomes per provider in this process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProviderOutcomes {
+    pub email_ok_at: Option<DateTime<Utc>>,
+    pub email_error_at: Option<DateTime<Utc>>,
+    pub llm_ok_at: Option<DateTime<Utc>>,
+    pub llm_error_at: Option<DateTime<Utc>>,
+}
+
+impl ProviderOutcomes {
+    /// Records the outcome of one call.
+    pub fn record(&mut self, provider: Provider, ok: bool, at: DateTime<Utc>) {
+        let slot = match (provider, ok) {
+            (Provider::Email, true) => &mut self.email_ok_at,
+            (Provider::Email, false) => &mut self.email_error_at,
+            (Provider::Llm, true) => &mut self.llm_ok_at,
+            (Provider::Llm, false) => &mut self.llm_error_at,
+        };
+        *slot = Some(at);
+    }
+}
+
+static OUTCOMES: Mutex<ProviderOutcomes> = Mutex::new(ProviderOutcomes {
+    email_ok_at: None,
+    email_error_at: None,
+    llm_ok_at: None,
+    llm_error_at: None,
+});
+
+/// Records whether a call to a provider succeeded.
+///
+/// Reported with the next heartbeat.
+pub fn record_provider_outcome(provider: Provider, ok: bool) {
+    if let Ok(mut outcomes) = OUTCOMES.lock() {
+        outcomes.record(provider, ok, Utc::now());
+    }
+}
+
+fn provider_outcomes() -> ProviderOutcomes {
+    OUTCOMES.lock().map(|outcomes| *outcomes).unwrap_or_default()
+}
+
+/// Upserts this process's heartbeat and removes rows of long-gone workers.
+async fn beat(
+    pool: &PgPool,
+    instance_id: Uuid,
+    hostname: Option<&str>,
+    started_at: DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    let outcomes = provider_outcomes();
+    sqlx::query(
+        r#"
+        INSERT INTO worker_heartbeats (
+            instance_id, hostname, started_at, last_seen_at,
+            email_ok_at, email_error_at, llm_ok_at, llm_error_at
+        )
+        VALUES ($1, $2, $3, NOW(), $4, $5, $6, $7)
+        ON CONFLICT (instance_id) DO UPDATE
+            SET last_seen_at = NOW(),
+                email_ok_at = EXCLUDED.email_ok_at,
+                email_error_at = EXCLUDED.email_error_at,
+                llm_ok_at = EXCLUDED.llm_ok_at,
+                llm_error_at = EXCLUDED.llm_error_at
+        "#,
+    )
+    .bind(instance_id)
+    .bind(hostname)
+    .bind(started_at)
+    .bind(outcomes.email_ok_at)
+    .bind(outcomes.email_error_at)
+    .bind(outcomes.llm_ok_at)
+    .bind(outcomes.llm_error_at)
+    .execute(pool)
+    .await?;
+
+    sqlx::query("DELETE FROM worker_heartbeats WHERE last_seen_at < NOW() - INTERVAL '1 day'")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Spawns the background task recording this worker's heartbeat.
+///
+/// Beats every `WORKER_HEARTBEAT_INTERVAL_SECONDS` (default: 30 seconds).
+pub fn spawn_heartbeat(pool: PgPool) {
+    let instance_id = Uuid::new_v4();
+    let hostname = std::env::var("HOSTNAME").ok();
+    let started_at = Utc::now();
+    let seconds = heartbeat_interval_seconds();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if let Err(e) = beat(&pool, instance_id, hostname.as_deref(), started_at).await {
+                error!("Worker heartbeat failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_keeps_latest_outcome_per_provider() {
+        let mut outcomes = ProviderOutcomes::default();
+        let earlier = Utc::now() - chrono::Duration::minutes(5);
+        let now = Utc::now();
+
+        outcomes.record(Provider::Email, true, earlier);
+        outcomes.record(Provider::Email, false, now);
+        outcomes.record(Provider::Llm, true, now);
+
+        assert_eq!(outcomes.email_ok_at, Some(earlier));
+        assert_eq!(outcomes.email_error_at, Some(now));
+        assert_eq!(outcomes.llm_ok_at, Some(now));
+        assert_eq!(outcomes.llm_error_at, None);
+    }
+}
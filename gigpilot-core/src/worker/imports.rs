@@ -0,0 +1,150 @@
+//! Account import jobs.
+//!
+//! Runs import jobs the user has started, one at a time per job, holding a
+//! heartbeat on each (see [`crate::worker::claimed`]); a job whose worker
+//! died is picked up again after a while, up to [`MAX_IMPORT_ATTEMPTS`]
+//! times. Imports are idempotent, so rows stored by
+//! an earlier attempt are skipped. A worker that lost its job to another
+//! stops importing and can't record a result. The user is notified when a
+//! job completes or fails.
+
+use async_trait::async_trait;
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::imports::{run_job, ImportJobReport};
+use crate::models::import_job::{ImportJob, IMPORT_JOB_COLUMNS};
+use crate::models::notification::CreateNotification;
+use crate::notifications::notify;
+use crate::worker::claimed::{fail_claimed_job, poll_interval, process_claimed_jobs, ClaimedJob, ClaimedJobRunner};
+
+/// Jobs claimed per run.
+const IMPORT_BATCH_SIZE: i64 = 5;
+
+/// Times a job is picked up before it is given up.
+pub const MAX_IMPORT_ATTEMPTS: i32 = 3;
+
+impl ClaimedJob for ImportJob {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn attempts(&self) -> i32 {
+        self.attempts
+    }
+}
+
+/// Runs import jobs (see [`process_claimed_jobs`]).
+struct ImportRunner;
+
+#[async_trait]
+impl ClaimedJobRunner for ImportRunner {
+    type Job = ImportJob;
+    type Output = ImportJobReport;
+
+    const TABLE: &'static str = "import_jobs";
+    const COLUMNS: &'static str = IMPORT_JOB_COLUMNS;
+    const NAME: &'static str = "import";
+    const BATCH_SIZE: i64 = IMPORT_BATCH_SIZE;
+    const MAX_ATTEMPTS: i32 = MAX_IMPORT_ATTEMPTS;
+
+    // Dropping the run when the job is lost rolls back the file being imported
+    async fn run(&self, pool: &PgPool, job: &ImportJob) -> Result<ImportJobReport, anyhow::Error> {
+        run_job(pool, job).await
+    }
+
+    async fn finish(
+        &self,
+        pool: &PgPool,
+        job: &ImportJob,
+        result: Result<ImportJobReport, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        finish_job(pool, job, result).await
+    }
+}
+
+/// Runs queued import jobs.
+///
+/// Jobs whose worker stopped on their last attempt are failed first.
+///
+/// # Returns
+///
+/// Returns the number of jobs that completed.
+pub async fn process_queued_imports(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    process_claimed_jobs(pool, &ImportRunner).await
+}
+
+/// Stores a job's report or error and notifies the user.
+///
+/// Nothing is recorded if the job has since been picked up again.
+async fn finish_job(
+    pool: &PgPool,
+    job: &ImportJob,
+    result: Result<ImportJobReport, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let notification = match result {
+        Ok(report) => {
+            let updated = sqlx::query(
+                r#"
+                UPDATE import_jobs
+                SET status = 'completed', report = $2, error = NULL, finished_at = NOW()
+                WHERE id = $1 AND status = 'running' AND attempts = $3
+                "#,
+            )
+            .bind(job.id)
+            .bind(serde_json::to_value(&report)?)
+            .bind(job.attempts)
+            .execute(&mut *tx)
+            .await?;
+            if updated.rows_affected() == 0 {
+                return Ok(());
+            }
+
+            CreateNotification {
+                kind: "import_finished".to_string(),
+                title: format!("Your {} import is complete", job.source),
+                body: Some(report.summary()),
+                data: Some(json!({ "import_job_id": job.id, "status": "completed" })),
+            }
+        }
+        Err(e) => {
+            if !fail_claimed_job(&mut *tx, ImportRunner::TABLE, job, &e.to_string()).await? {
+                return Ok(());
+            }
+
+            CreateNotification {
+                kind: "import_finished".to_string(),
+                title: format!("Your {} import failed", job.source),
+                body: Some(e.to_string()),
+                data: Some(json!({ "import_job_id": job.id, "status": "failed" })),
+            }
+        }
+    };
+    notify(&mut tx, job.user_id, notification).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Spawns the import worker.
+///
+/// Runs every `IMPORT_POLL_INTERVAL_SECONDS` (default 15 seconds).
+pub fn spawn_import_worker(pool: PgPool) {
+    let period = poll_interval("IMPORT_POLL_INTERVAL_SECONDS");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match process_queued_imports(&pool).await {
+                Ok(0) => {}
+                Ok(count) => info!("Completed {} import job(s)", count),
+                Err(e) => error!("Import job failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,300 @@
+//! Durable job queue shared by worker replicas.
+//!
+//! Jobs live in the `jobs` table. A worker claims due jobs with
+//! `SELECT ... FOR UPDATE SKIP LOCKED`, so replicas polling at the same time
+//! never claim the same job, and holds each for a visibility timeout. A job
+//! whose worker died before finishing it is claimed again once that timeout
+//! lapses; a worker whose hold lapsed can no longer complete or fail the
+//! job. Failed jobs are retried with exponential backoff until they run out
+//! of attempts.
+//!
+//! Enqueueing with a dedupe key is a no-op while a job of the same kind and
+//! key is queued or running, so every replica can enqueue the work it finds
+//! without it being done twice.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::job::{CreateJob, Job};
+
+/// Claims a job gets by default before it is given up.
+pub const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+
+/// Default visibility timeout in seconds.
+pub const DEFAULT_VISIBILITY_TIMEOUT_SECONDS: i64 = 300;
+
+/// Delay before the first retry of a failed job.
+const BASE_RETRY_SECONDS: i64 = 30;
+
+/// Longest delay between retries.
+const MAX_RETRY_SECONDS: i64 = 60 * 60;
+
+/// How long finished jobs are kept.
+pub const FINISHED_JOB_RETENTION_HOURS: i64 = 24;
+
+/// Returns how long a claimed job is held.
+///
+/// Read from `JOB_VISIBILITY_TIMEOUT_SECONDS`, falling back to
+/// [`DEFAULT_VISIBILITY_TIMEOUT_SECONDS`].
+pub fn visibility_timeout() -> Duration {
+    let seconds = std::env::var("JOB_VISIBILITY_TIMEOUT_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_VISIBILITY_TIMEOUT_SECONDS);
+
+    Duration::seconds(seconds)
+}
+
+/// Identifies this worker process in the jobs it holds.
+///
+/// The host name alone isn't enough: containers of one deployment may
+/// share it.
+pub fn worker_id() -> String {
+    let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string());
+    format!("{}/{}", hostname, Uuid::new_v4())
+}
+
+/// Delay before retrying a job that failed its `attempts`th claim.
+///
+/// Doubles with every attempt, from 30 seconds up to an hour.
+pub fn retry_delay(attempts: i32) -> Duration {
+    let doublings = attempts.clamp(1, 16) as u32 - 1;
+    Duration::seconds((BASE_RETRY_SECONDS << doublings).min(MAX_RETRY_SECONDS))
+}
+
+/// When a job that just failed should run again.
+///
+/// # Returns
+///
+/// Returns `None` if the job is out of attempts.
+pub fn retry_at(job: &Job, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    (job.attempts < job.max_attempts).then(|| now + retry_delay(job.attempts))
+}
+
+/// Enqueues a job.
+///
+/// # Returns
+///
+/// Returns the new job, or `None` if a job of the same kind and dedupe key
+/// is already queued or running.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn enqueue_job(pool: &PgPool, job: &CreateJob) -> Result<Option<Job>, anyhow::Error> {
+    let job = sqlx::query_as::<_, Job>(
+        r#"
+        INSERT INTO jobs (kind, payload, dedupe_key, max_attempts, run_at)
+        VALUES ($1, $2, $3, $4, COALESCE($5, NOW()))
+        ON CONFLICT (kind, dedupe_key) WHERE status IN ('queued', 'running') DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(&job.kind)
+    .bind(&job.payload)
+    .bind(&job.dedupe_key)
+    .bind(job.max_attempts)
+    .bind(job.run_at)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Claims due jobs of a kind for a worker.
+///
+/// Claims queued jobs that are due and running jobs whose hold lapsed,
+/// oldest first, skipping jobs another worker is claiming. Lapsed jobs
+/// that are out of attempts are failed instead.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `kind` - Kind of jobs to claim
+/// * `worker_id` - The claiming worker (see [`worker_id`])
+/// * `limit` - Most jobs to claim
+/// * `visibility` - How long the worker holds the jobs
+///
+/// # Returns
+///
+/// Returns the claimed jobs, their attempts counting this claim.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn claim_jobs(
+    pool: &PgPool,
+    kind: &str,
+    worker_id: &str,
+    limit: i64,
+    visibility: Duration,
+) -> Result<Vec<Job>, anyhow::Error> {
+    let jobs = sqlx::query_as::<_, Job>(
+        r#"
+        WITH abandoned AS (
+            UPDATE jobs
+            SET status = 'failed', last_error = 'visibility timeout lapsed',
+                locked_by = NULL, locked_until = NULL, finished_at = NOW()
+            WHERE kind = $1
+                AND status = 'running'
+                AND locked_until < NOW()
+                AND attempts >= max_attempts
+        )
+        UPDATE jobs
+        SET status = 'running', attempts = attempts + 1,
+            locked_by = $3, locked_until = NOW() + make_interval(secs => $4)
+        WHERE id IN (
+            SELECT id FROM jobs
+            WHERE kind = $1
+                AND ((status = 'queued' AND run_at <= NOW())
+                    OR (status = 'running' AND locked_until < NOW() AND attempts < max_attempts))
+            ORDER BY run_at ASC
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+    )
+    .bind(kind)
+    .bind(limit)
+    .bind(worker_id)
+    .bind(visibility.num_milliseconds() as f64 / 1000.0)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+/// Marks a job the worker holds as completed.
+///
+/// # Returns
+///
+/// Returns `false` if the worker no longer holds the job.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn complete_job(pool: &PgPool, job_id: Uuid, worker_id: &str) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE jobs
+        SET status = 'completed', last_error = NULL,
+            locked_by = NULL, locked_until = NULL, finished_at = NOW()
+        WHERE id = $1 AND status = 'running' AND locked_by = $2
+        "#,
+    )
+    .bind(job_id)
+    .bind(worker_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Records a failed attempt of a job the worker holds.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `job_id` - The failed job
+/// * `worker_id` - The worker holding it
+/// * `error` - Why it failed
+/// * `retry_at` - When to run it again, or `None` to give up (see
+///   [`retry_at`])
+///
+/// # Returns
+///
+/// Returns `false` if the worker no longer holds the job.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn fail_job(
+    pool: &PgPool,
+    job_id: Uuid,
+    worker_id: &str,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE jobs
+        SET status = CASE WHEN $4::timestamptz IS NULL THEN 'failed' ELSE 'queued' END,
+            run_at = COALESCE($4, run_at),
+            finished_at = CASE WHEN $4::timestamptz IS NULL THEN NOW() END,
+            last_error = $3, locked_by = NULL, locked_until = NULL
+        WHERE id = $1 AND status = 'running' AND locked_by = $2
+        "#,
+    )
+    .bind(job_id)
+    .bind(worker_id)
+    .bind(error)
+    .bind(retry_at)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes jobs that finished before `before`.
+///
+/// # Returns
+///
+/// Returns the number of jobs deleted.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub async fn prune_finished_jobs(pool: &PgPool, before: DateTime<Utc>) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query("DELETE FROM jobs WHERE finished_at < $1")
+        .bind(before)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job::JobStatus;
+    use serde_json::json;
+
+    fn job(attempts: i32) -> Job {
+        let now = Utc::now();
+        Job {
+            id: Uuid::new_v4(),
+            kind: "chase_invoices".to_string(),
+            payload: json!({}),
+            dedupe_key: None,
+            status: JobStatus::Running,
+            attempts,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            run_at: now,
+            locked_by: Some("worker/1".to_string()),
+            locked_until: Some(now),
+            last_error: None,
+            finished_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::seconds(30));
+        assert_eq!(retry_delay(2), Duration::seconds(60));
+        assert_eq!(retry_delay(4), Duration::seconds(240));
+        assert_eq!(retry_delay(20), Duration::hours(1));
+    }
+
+    #[test]
+    fn test_jobs_out_of_attempts_are_given_up() {
+        let now = Utc::now();
+
+        assert_eq!(retry_at(&job(1), now), Some(now + Duration::seconds(30)));
+        assert_eq!(retry_at(&job(DEFAULT_MAX_ATTEMPTS), now), None);
+    }
+}
@@ -1,10 +1,35 @@
 pub mod scheduler;
+pub mod priority;
 pub mod state_machine;
 pub mod services;
 pub mod executor;
+pub mod weekly_drafts;
+pub mod aging_snapshots;
+pub mod statements;
+pub mod heartbeat;
+pub mod claimed;
+pub mod imports;
+pub mod client_stats;
+pub mod disputes;
+pub mod budgets;
+pub mod retainers;
+pub mod pdf_exports;
+pub mod jobs;
 
 pub use scheduler::JobScheduler;
 pub use state_machine::{ChaseState, Transition};
-pub use services::{generate_email, send_email};
+pub use services::{
+    email_sender, generate_email, render_email_html, send_email, send_email_with_attachments, EmailAttachment,
+};
 pub use executor::ChaseExecutor;
+pub use weekly_drafts::spawn_weekly_draft_worker;
+pub use aging_snapshots::spawn_aging_snapshot_worker;
+pub use statements::spawn_statement_worker;
+pub use heartbeat::spawn_heartbeat;
+pub use imports::spawn_import_worker;
+pub use client_stats::spawn_client_stats_worker;
+pub use disputes::spawn_dispute_email_worker;
+pub use budgets::spawn_budget_alert_worker;
+pub use retainers::spawn_retainer_worker;
+pub use pdf_exports::spawn_pdf_export_worker;
 
@@ -0,0 +1,192 @@
+//! Bulk invoice PDF export jobs.
+//!
+//! Renders queued exports (see `invoices::pdf_export`) and notifies the
+//! user when the archive is ready, holding a heartbeat on each job (see
+//! [`crate::worker::claimed`]); a job whose worker died is picked up again
+//! after a while and starts over, up to [`MAX_EXPORT_ATTEMPTS`] times. A worker that lost its job to another
+//! stops rendering and can't record a result. Archives are deleted once
+//! they expire.
+
+use async_trait::async_trait;
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::invoices::pdf_export::{archive_expiry, run_job};
+use crate::models::notification::CreateNotification;
+use crate::models::pdf_export_job::PdfExportJob;
+use crate::notifications::notify;
+use crate::storage::{BlobStore, DynBlobStore};
+use crate::worker::claimed::{fail_claimed_job, poll_interval, process_claimed_jobs, ClaimedJob, ClaimedJobRunner};
+
+/// Jobs claimed per run.
+const EXPORT_BATCH_SIZE: i64 = 2;
+
+/// Times a job is picked up before it is given up.
+pub const MAX_EXPORT_ATTEMPTS: i32 = 3;
+
+impl ClaimedJob for PdfExportJob {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn attempts(&self) -> i32 {
+        self.attempts
+    }
+}
+
+/// Runs PDF export jobs (see [`process_claimed_jobs`]).
+struct ExportRunner<'a> {
+    /// Where archives are stored
+    store: &'a dyn BlobStore,
+}
+
+#[async_trait]
+impl ClaimedJobRunner for ExportRunner<'_> {
+    type Job = PdfExportJob;
+    type Output = String;
+
+    const TABLE: &'static str = "pdf_export_jobs";
+    const COLUMNS: &'static str = "*";
+    const NAME: &'static str = "PDF export";
+    const BATCH_SIZE: i64 = EXPORT_BATCH_SIZE;
+    const MAX_ATTEMPTS: i32 = MAX_EXPORT_ATTEMPTS;
+
+    async fn run(&self, pool: &PgPool, job: &PdfExportJob) -> Result<String, anyhow::Error> {
+        run_job(pool, self.store, job).await
+    }
+
+    async fn finish(
+        &self,
+        pool: &PgPool,
+        job: &PdfExportJob,
+        result: Result<String, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        finish_job(pool, job, result).await
+    }
+}
+
+/// Runs queued PDF export jobs.
+///
+/// Jobs whose worker stopped on their last attempt are failed first.
+///
+/// # Returns
+///
+/// Returns the number of jobs that completed.
+pub async fn process_queued_exports(pool: &PgPool, store: &dyn BlobStore) -> Result<usize, anyhow::Error> {
+    process_claimed_jobs(pool, &ExportRunner { store }).await
+}
+
+/// Stores a job's archive key or error and notifies the user.
+///
+/// The notification counts the invoices the run exported, which may differ
+/// from the count when the job was queued. Nothing is recorded if the job
+/// has since been picked up again.
+async fn finish_job(pool: &PgPool, job: &PdfExportJob, result: Result<String, anyhow::Error>) -> Result<(), anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let notification = match result {
+        Ok(key) => {
+            let exported = sqlx::query_scalar::<_, i32>(
+                r#"
+                UPDATE pdf_export_jobs
+                SET status = 'completed', storage_key = $2, expires_at = $3, error = NULL, finished_at = NOW()
+                WHERE id = $1 AND status = 'running' AND attempts = $4
+                RETURNING total
+                "#,
+            )
+            .bind(job.id)
+            .bind(key)
+            .bind(archive_expiry())
+            .bind(job.attempts)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some(exported) = exported else {
+                return Ok(());
+            };
+
+            CreateNotification {
+                kind: "pdf_export_finished".to_string(),
+                title: "Your invoice PDFs are ready to download".to_string(),
+                body: Some(format!("{} invoice(s) exported", exported)),
+                data: Some(json!({ "pdf_export_job_id": job.id, "status": "completed" })),
+            }
+        }
+        Err(e) => {
+            if !fail_claimed_job(&mut *tx, ExportRunner::TABLE, job, &e.to_string()).await? {
+                return Ok(());
+            }
+
+            CreateNotification {
+                kind: "pdf_export_finished".to_string(),
+                title: "Your invoice PDF export failed".to_string(),
+                body: Some(e.to_string()),
+                data: Some(json!({ "pdf_export_job_id": job.id, "status": "failed" })),
+            }
+        }
+    };
+    notify(&mut tx, job.user_id, notification).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Deletes the archives of expired exports.
+///
+/// A job keeps its archive's key until the archive is deleted, so an
+/// archive that fails to delete is tried again on the next run.
+///
+/// # Returns
+///
+/// Returns the number of archives deleted.
+pub async fn prune_expired_exports(pool: &PgPool, store: &dyn BlobStore) -> Result<usize, anyhow::Error> {
+    let expired = sqlx::query_as::<_, (Uuid, String)>(
+        r#"
+        SELECT id, storage_key FROM pdf_export_jobs
+        WHERE storage_key IS NOT NULL AND expires_at <= NOW()
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut deleted = 0;
+    for (job_id, key) in &expired {
+        if let Err(e) = store.delete(key).await {
+            warn!("Failed to delete the archive of PDF export job {}: {}", job_id, e);
+            continue;
+        }
+        sqlx::query("UPDATE pdf_export_jobs SET storage_key = NULL WHERE id = $1 AND storage_key = $2")
+            .bind(job_id)
+            .bind(key)
+            .execute(pool)
+            .await?;
+        deleted += 1;
+    }
+
+    Ok(deleted)
+}
+
+/// Spawns the PDF export worker.
+///
+/// Runs every `PDF_EXPORT_POLL_INTERVAL_SECONDS` (default 15 seconds).
+pub fn spawn_pdf_export_worker(pool: PgPool, store: DynBlobStore) {
+    let period = poll_interval("PDF_EXPORT_POLL_INTERVAL_SECONDS");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match process_queued_exports(&pool, store.as_ref()).await {
+                Ok(0) => {}
+                Ok(count) => info!("Completed {} PDF export job(s)", count),
+                Err(e) => error!("PDF export job failed: {}", e),
+            }
+            match prune_expired_exports(&pool, store.as_ref()).await {
+                Ok(0) => {}
+                Ok(count) => info!("Deleted {} expired PDF export(s)", count),
+                Err(e) => error!("Failed to delete expired PDF exports: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,182 @@
+//! Order in which the scheduler chases overdue invoices.
+//!
+//! Each poll handles at most a fixed number of invoices. They are picked
+//! by priority, taking turns between users: every user's most urgent
+//! invoice comes before anyone's second, so one user with hundreds of
+//! overdue invoices cannot crowd out everyone else's reminders.
+//!
+//! An invoice's priority adds up:
+//! - the amount at risk: `log2(1 + balance due)`, so larger balances
+//!   count for more without drowning out everything else (currencies are
+//!   not converted);
+//! - [`REMINDER_POINTS`] per reminder still to send: invoices awaiting
+//!   their first reminder come first, fully escalated or held invoices
+//!   only wait for payment;
+//! - [`STALE_DAY_POINTS`] per day since the last chase email (or the due
+//!   date), up to [`STALE_DAYS_CAP`] days.
+//!
+//! The PostgreSQL repository computes the same score in SQL.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rust_decimal::prelude::ToPrimitive;
+use uuid::Uuid;
+
+use crate::models::chase_override::ChaseOverride;
+use crate::models::invoice::Invoice;
+use crate::worker::state_machine::{current_chase_state, ChaseState};
+
+/// Points per reminder still to send.
+pub const REMINDER_POINTS: f64 = 10.0;
+
+/// Points per day since the last action.
+pub const STALE_DAY_POINTS: f64 = 0.5;
+
+/// Days since the last action that still add points.
+pub const STALE_DAYS_CAP: i64 = 30;
+
+/// Reminders the chase worker may still send for an invoice.
+///
+/// Four before the first reminder, one fewer at each level after it (two
+/// after the first if level 2 is skipped), none after the final notice,
+/// while disputed or while chasing is paused or deferred.
+pub fn reminders_left(invoice: &Invoice, today: NaiveDate) -> i64 {
+    // An unreadable override is reported when the invoice is processed
+    let overrides = ChaseOverride::parse(invoice.chase_override.as_ref()).unwrap_or_default();
+    if overrides.paused || overrides.not_before.is_some_and(|date| today < date) {
+        return 0;
+    }
+
+    match current_chase_state(invoice, today) {
+        ChaseState::Pending | ChaseState::Overdue => 4,
+        ChaseState::ChasingLevel1 if overrides.skip_level_2 => 2,
+        ChaseState::ChasingLevel1 => 3,
+        ChaseState::ChasingLevel2 => 2,
+        ChaseState::ChasingLevel3 => 1,
+        ChaseState::FinalNotice | ChaseState::Paid | ChaseState::Disputed => 0,
+    }
+}
+
+/// Chase priority of an overdue invoice; higher goes first.
+///
+/// # Arguments
+///
+/// * `invoice` - The invoice
+/// * `last_action` - Date of the last chase email, if any
+/// * `today` - Current date
+pub fn priority(invoice: &Invoice, last_action: Option<NaiveDate>, today: NaiveDate) -> f64 {
+    let balance = invoice.balance_due().to_f64().unwrap_or(0.0).max(0.0);
+    let since = last_action.or(invoice.due_date).unwrap_or(today);
+    let stale_days = (today - since).num_days().clamp(0, STALE_DAYS_CAP);
+
+    (1.0 + balance).log2() + REMINDER_POINTS * reminders_left(invoice, today) as f64 + STALE_DAY_POINTS * stale_days as f64
+}
+
+/// Picks up to `limit` invoices, taking turns between users.
+///
+/// Each user's invoices are ranked by priority (earlier due date first on
+/// ties). Every user's first-ranked invoice comes before any second-ranked
+/// one, and so on; within a rank the higher priority goes first.
+///
+/// # Arguments
+///
+/// * `candidates` - Overdue invoices with their priority
+/// * `limit` - Maximum number of invoices to return
+pub fn round_robin(candidates: Vec<(Invoice, f64)>, limit: usize) -> Vec<Invoice> {
+    let by_priority = |a: &(Invoice, f64), b: &(Invoice, f64)| {
+        b.1.total_cmp(&a.1).then(a.0.due_date.cmp(&b.0.due_date))
+    };
+
+    let mut per_user: HashMap<Uuid, Vec<(Invoice, f64)>> = HashMap::new();
+    for candidate in candidates {
+        per_user.entry(candidate.0.user_id).or_default().push(candidate);
+    }
+
+    let mut ranked: Vec<(usize, (Invoice, f64))> = Vec::new();
+    for mut invoices in per_user.into_values() {
+        invoices.sort_by(by_priority);
+        ranked.extend(invoices.into_iter().enumerate());
+    }
+    ranked.sort_by(|(rank_a, a), (rank_b, b)| rank_a.cmp(rank_b).then(by_priority(a, b)));
+
+    ranked.into_iter().take(limit).map(|(_, (invoice, _))| invoice).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal::Decimal;
+    use serde_json::json;
+
+    use crate::repo::memory::sample_invoice;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    #[test]
+    fn test_reminders_left() {
+        let mut invoice = sample_invoice(Uuid::new_v4(), today() - Duration::days(3), Decimal::from(100));
+        assert_eq!(reminders_left(&invoice, today()), 4);
+
+        invoice.metadata = Some(json!({ "chase_state": "chasing_level_1" }));
+        assert_eq!(reminders_left(&invoice, today()), 3);
+
+        invoice.chase_override = Some(json!({ "skip_level_2": true }));
+        assert_eq!(reminders_left(&invoice, today()), 2);
+
+        invoice.chase_override = Some(json!({ "not_before": "2024-03-05" }));
+        assert_eq!(reminders_left(&invoice, today()), 0);
+
+        invoice.chase_override = None;
+        invoice.metadata = Some(json!({ "chase_state": "chasing_level_2" }));
+        assert_eq!(reminders_left(&invoice, today()), 2);
+
+        invoice.metadata = Some(json!({ "chase_state": "final_notice" }));
+        assert_eq!(reminders_left(&invoice, today()), 0);
+
+        invoice.metadata = Some(json!({ "chase_state": "disputed" }));
+        assert_eq!(reminders_left(&invoice, today()), 0);
+    }
+
+    #[test]
+    fn test_priority_weighs_reminders_amount_and_staleness() {
+        let due = today() - Duration::days(10);
+        let first_reminder = sample_invoice(Uuid::new_v4(), due, Decimal::from(50));
+        let mut escalated = sample_invoice(Uuid::new_v4(), due, Decimal::from(50_000));
+        escalated.metadata = Some(json!({ "chase_state": "final_notice" }));
+        assert!(priority(&first_reminder, None, today()) > priority(&escalated, None, today()));
+
+        let small = sample_invoice(Uuid::new_v4(), due, Decimal::from(100));
+        let large = sample_invoice(Uuid::new_v4(), due, Decimal::from(10_000));
+        assert!(priority(&large, None, today()) > priority(&small, None, today()));
+
+        let chased_today = priority(&small, Some(today()), today());
+        let chased_long_ago = priority(&small, Some(today() - Duration::days(60)), today());
+        assert_eq!(chased_long_ago - chased_today, STALE_DAY_POINTS * STALE_DAYS_CAP as f64);
+    }
+
+    #[test]
+    fn test_round_robin_takes_turns_between_users() {
+        let busy = Uuid::new_v4();
+        let quiet = Uuid::new_v4();
+        let due = today() - Duration::days(5);
+        let invoice = |user_id| sample_invoice(user_id, due, Decimal::from(100));
+
+        let busy_invoices: Vec<Invoice> = (0..5).map(|_| invoice(busy)).collect();
+        let quiet_invoice = invoice(quiet);
+        let mut candidates: Vec<(Invoice, f64)> = busy_invoices
+            .iter()
+            .enumerate()
+            .map(|(i, invoice)| (invoice.clone(), 50.0 - i as f64))
+            .collect();
+        candidates.push((quiet_invoice.clone(), 1.0));
+
+        let picked = round_robin(candidates, 3);
+
+        let ids: Vec<Uuid> = picked.iter().map(|invoice| invoice.id).collect();
+        assert_eq!(ids, vec![busy_invoices[0].id, quiet_invoice.id, busy_invoices[1].id]);
+    }
+}
@@ -0,0 +1,35 @@
+//! Retainer periods.
+//!
+//! Opens each retainer's monthly period (drafting the fee's invoice) and
+//! settles the previous one, rolling over or expiring its unused hours
+//! (see [`crate::retainers`]).
+
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::retainers::run_retainers;
+
+/// Spawns the retainer worker.
+///
+/// Runs every `RETAINER_POLL_INTERVAL_SECONDS` (default 3600 seconds; 0
+/// falls back to the default).
+pub fn spawn_retainer_worker(pool: PgPool) {
+    let seconds = std::env::var("RETAINER_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(3600);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            match run_retainers(&pool, Utc::now().date_naive()).await {
+                Ok(0) => {}
+                Ok(count) => info!("Opened {} retainer period(s)", count),
+                Err(e) => error!("Retainer run failed: {}", e),
+            }
+        }
+    });
+}
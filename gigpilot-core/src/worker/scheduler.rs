@@ -1,5 +1,8 @@
-use chrono::{NaiveDate, Utc};
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::PgPool;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
@@ -7,20 +10,57 @@ use tokio::time::sleep;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::chase::send_window::next_send_time;
+use crate::maintenance::worker_paused;
 use crate::models::invoice::Invoice;
+use crate::models::job::{CreateJob, Job};
+use crate::repo::{DynRepository, PgRepository};
+use crate::settings::SettingsCache;
+use crate::storage::DynBlobStore;
 use crate::worker::executor::ChaseExecutor;
+use crate::worker::jobs::{self, retry_at, DEFAULT_MAX_ATTEMPTS, FINISHED_JOB_RETENTION_HOURS};
+
+/// Maximum number of overdue invoices queued per poll, shared fairly
+/// between users, and of chase jobs claimed per poll.
+const OVERDUE_BATCH_SIZE: i64 = 100;
+
+/// Kind of the jobs chasing a client's overdue invoices.
+pub const CHASE_JOB: &str = "chase_invoices";
+
+/// Input of a chase job: overdue invoices of one client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChasePayload {
+    user_id: Uuid,
+    invoice_ids: Vec<Uuid>,
+}
 
 /// Job scheduler for processing overdue invoices.
 /// 
 /// Polls the database at regular intervals to find invoices that need
-/// chasing and processes them through the state machine.
+/// chasing, queues a chase job per client in the durable job queue (see
+/// [`crate::worker::jobs`]) and processes the jobs it claims through the
+/// state machine. Any number of worker replicas can run side by side: a
+/// client's invoices are queued once at a time and each job is claimed by
+/// one worker, so no reminder is sent twice.
 pub struct JobScheduler {
-    /// Database connection pool
-    pool: PgPool,
+    /// Repository the scheduler and its executors work against
+    repo: DynRepository,
     
     /// Polling interval in seconds
     poll_interval_seconds: u64,
     
+    /// Settings cache shared by all executors across poll cycles
+    settings_cache: SettingsCache,
+
+    /// Blob storage caching invoice PDFs attached to chase emails
+    pdf_cache: Option<DynBlobStore>,
+
+    /// Identifies this worker in the jobs it claims
+    worker_id: String,
+
+    /// How long claimed jobs are held
+    visibility_timeout: ChronoDuration,
+    
     /// Whether the scheduler is running (wrapped in Arc for sharing)
     running: Arc<RwLock<bool>>,
 }
@@ -37,13 +77,39 @@ impl JobScheduler {
     /// 
     /// Returns a new `JobScheduler` instance.
     pub fn new(pool: PgPool, poll_interval_seconds: Option<u64>) -> Self {
+        let settings_cache = SettingsCache::new(pool.clone());
         Self {
-            pool,
+            repo: Arc::new(PgRepository::new(pool, settings_cache.clone())),
+            settings_cache,
+            pdf_cache: None,
+            worker_id: jobs::worker_id(),
+            visibility_timeout: jobs::visibility_timeout(),
             poll_interval_seconds: poll_interval_seconds.unwrap_or(60),
             running: Arc::new(RwLock::new(false)),
         }
     }
 
+    /// Attaches cached invoice PDFs to chase emails instead of rendering
+    /// them every time.
+    pub fn with_pdf_cache(mut self, store: DynBlobStore) -> Self {
+        self.pdf_cache = Some(store);
+        self
+    }
+
+    /// Creates an executor sharing the scheduler's repository and PDF cache.
+    fn executor(&self) -> ChaseExecutor {
+        let executor = ChaseExecutor::with_repository(self.repo.clone());
+        match &self.pdf_cache {
+            Some(store) => executor.with_pdf_cache(store.clone()),
+            None => executor,
+        }
+    }
+
+    /// Returns the scheduler's settings cache (e.g. to wire up invalidation).
+    pub fn settings_cache(&self) -> &SettingsCache {
+        &self.settings_cache
+    }
+
     /// Starts the scheduler loop.
     /// 
     /// This function runs indefinitely, polling for overdue invoices
@@ -61,6 +127,11 @@ impl JobScheduler {
         );
         
         while *self.running.read().await {
+            // Overdue invoices are chased once maintenance is over
+            if worker_paused() {
+                sleep(Duration::from_secs(self.poll_interval_seconds)).await;
+                continue;
+            }
             match self.poll_and_process().await {
                 Ok(count) => {
                     if count > 0 {
@@ -90,76 +161,138 @@ impl JobScheduler {
         *self.running.write().await = false;
     }
 
-    /// Polls the database for overdue invoices and processes them.
+    /// Queues chase jobs for overdue invoices and processes the jobs this
+    /// worker claims.
     /// 
-    /// Finds all invoices where:
+    /// Overdue invoices are those where:
     /// - due_date < current date
     /// - status != 'paid'
+    /// - a balance remains after recorded payments
     /// - is_deleted = false
     /// 
+    /// A failed job is retried with backoff until it runs out of attempts.
+    /// 
     /// # Returns
     /// 
     /// Returns the number of invoices processed, or an error.
     async fn poll_and_process(&self) -> Result<usize, anyhow::Error> {
-        let overdue_invoices = self.find_overdue_invoices().await?;
-        
-        if overdue_invoices.is_empty() {
-            return Ok(0);
+        let queued = self.enqueue_overdue().await?;
+        if queued > 0 {
+            info!("Queued {} chase job(s)", queued);
         }
         
-        info!("Found {} overdue invoice(s) to process", overdue_invoices.len());
-        
+        // Jobs are claimed one at a time, so each gets the whole visibility
+        // timeout: jobs claimed together would lapse while the ones ahead of
+        // them send, and another replica would chase the same clients again
         let mut processed = 0;
-        for invoice in overdue_invoices {
-            match self.process_invoice(&invoice).await {
-                Ok(_) => {
-                    processed += 1;
-                    info!("Successfully processed invoice: {}", invoice.invoice_number);
+        for _ in 0..OVERDUE_BATCH_SIZE {
+            let claimed = self
+                .repo
+                .claim_jobs(CHASE_JOB, &self.worker_id, 1, self.visibility_timeout)
+                .await?;
+            let Some(job) = claimed.into_iter().next() else {
+                break;
+            };
+            match self.run_chase_job(&job).await {
+                Ok(count) => {
+                    processed += count;
+                    match self.repo.complete_job(job.id, &self.worker_id).await {
+                        Ok(true) => {}
+                        Ok(false) => warn!("Chase job {} was reclaimed before it completed", job.id),
+                        Err(e) => error!("Failed to complete chase job {}: {}", job.id, e),
+                    }
                 }
                 Err(e) => {
+                    let retry = retry_at(&job, Utc::now());
                     error!(
-                        "Failed to process invoice {}: {}",
-                        invoice.invoice_number, e
+                        "Chase job {} failed (attempt {}), {}: {}",
+                        job.id,
+                        job.attempts,
+                        if retry.is_some() { "retrying" } else { "giving up" },
+                        e
                     );
-                    // Continue with other invoices
+                    // Continue with other jobs
+                    if let Err(e) = self.repo.fail_job(job.id, &self.worker_id, &e.to_string(), retry).await {
+                        error!("Failed to record the failure of chase job {}: {}", job.id, e);
+                    }
                 }
             }
         }
         
+        let retention = Utc::now() - ChronoDuration::hours(FINISHED_JOB_RETENTION_HOURS);
+        if let Err(e) = self.repo.prune_finished_jobs(retention).await {
+            warn!("Failed to prune finished jobs: {}", e);
+        }
+        
         Ok(processed)
     }
 
-    /// Finds all overdue invoices that need chasing.
+    /// Queues a chase job for each client with overdue invoices.
+    /// 
+    /// Clients whose job is still queued or running are skipped. Outside
+    /// the user's send window the job is due when the window next opens
+    /// (see [`crate::chase::send_window`]).
+    /// 
+    /// # Returns
+    /// 
+    /// Returns the number of jobs queued.
+    async fn enqueue_overdue(&self) -> Result<usize, anyhow::Error> {
+        let overdue_invoices = self.find_overdue_invoices().await?;
+        
+        let mut queued = 0;
+        for group in group_by_client(overdue_invoices) {
+            let now = Utc::now();
+            let settings = self.repo.user_settings(group[0].user_id).await?;
+            let run_at = Some(next_send_time(&settings, now)).filter(|run_at| *run_at > now);
+            if self.repo.enqueue_job(chase_job(&group, run_at)).await?.is_some() {
+                queued += 1;
+            }
+        }
+        
+        Ok(queued)
+    }
+
+    /// Chases the invoices of a claimed job, as they are now.
+    /// 
+    /// # Returns
+    /// 
+    /// Returns the number of invoices processed, or an error.
+    async fn run_chase_job(&self, job: &Job) -> Result<usize, anyhow::Error> {
+        let payload: ChasePayload = serde_json::from_value(job.payload.clone())?;
+        
+        // Invoices deleted since they were queued are left out
+        let mut group = Vec::new();
+        for invoice_id in payload.invoice_ids {
+            if let Some(invoice) = self.repo.find_invoice(payload.user_id, invoice_id).await? {
+                group.push(invoice);
+            }
+        }
+        
+        match group.as_slice() {
+            [] => Ok(0),
+            [invoice] => {
+                self.process_invoice(invoice).await?;
+                info!("Successfully processed invoice: {}", invoice.invoice_number);
+                Ok(1)
+            }
+            // Several invoices for the same client: let the executor
+            // consolidate reminders into a single email
+            _ => self.executor().process_client_invoices(&group).await,
+        }
+    }
+
+    /// Finds the overdue invoices to chase in this poll.
     /// 
     /// Queries the database for invoices where the due date has passed
-    /// and the invoice is not yet paid.
+    /// and the invoice is not yet paid, in priority order with users
+    /// taking turns (see [`crate::worker::priority`]).
     /// 
     /// # Returns
     /// 
     /// Returns a vector of `Invoice` structs, or an error.
     async fn find_overdue_invoices(&self) -> Result<Vec<Invoice>, anyhow::Error> {
         let today = Utc::now().date_naive();
-        
-        let invoices = sqlx::query_as::<_, Invoice>(
-            r#"
-            SELECT 
-                id, user_id, invoice_number, client_name, client_email,
-                amount, currency, status, due_date, issue_date,
-                last_modified, version_vector, is_deleted,
-                description, line_items, metadata, created_at, updated_at
-            FROM invoices
-            WHERE due_date < $1
-                AND status != 'paid'
-                AND is_deleted = false
-            ORDER BY due_date ASC
-            LIMIT 100
-            "#,
-        )
-        .bind(today)
-        .fetch_all(&self.pool)
-        .await?;
-        
-        Ok(invoices)
+        self.repo.find_overdue_invoices(today, OVERDUE_BATCH_SIZE).await
     }
 
     /// Processes a single invoice through the chasing state machine.
@@ -174,8 +307,100 @@ impl JobScheduler {
     /// 
     /// Returns `Ok(())` if processing succeeded, or an error.
     async fn process_invoice(&self, invoice: &Invoice) -> Result<(), anyhow::Error> {
-        let executor = ChaseExecutor::new(self.pool.clone());
-        executor.process_invoice(invoice).await
+        self.executor().process_invoice(invoice).await
     }
 }
 
+/// Builds the chase job for a group of a client's invoices (see
+/// [`group_by_client`]), due at `run_at` (now if unset).
+/// 
+/// Jobs are deduplicated per client, or per invoice without a client
+/// email, so a client is chased by one job at a time.
+fn chase_job(group: &[Invoice], run_at: Option<DateTime<Utc>>) -> CreateJob {
+    let first = &group[0];
+    let client = match &first.client_email {
+        Some(email) => email.trim().to_lowercase(),
+        None => first.id.to_string(),
+    };
+    let payload = ChasePayload {
+        user_id: first.user_id,
+        invoice_ids: group.iter().map(|invoice| invoice.id).collect(),
+    };
+    
+    CreateJob {
+        kind: CHASE_JOB.to_string(),
+        payload: json!(payload),
+        dedupe_key: Some(format!("{}:{}", first.user_id, client)),
+        max_attempts: DEFAULT_MAX_ATTEMPTS,
+        run_at,
+    }
+}
+
+/// Groups invoices by owner and client email, preserving query order.
+/// 
+/// Invoices without a client email are kept in groups of their own.
+fn group_by_client(invoices: Vec<Invoice>) -> Vec<Vec<Invoice>> {
+    let mut groups: Vec<Vec<Invoice>> = Vec::new();
+    let mut index: HashMap<(Uuid, String), usize> = HashMap::new();
+    
+    for invoice in invoices {
+        let key = invoice
+            .client_email
+            .as_ref()
+            .map(|email| (invoice.user_id, email.trim().to_lowercase()));
+        
+        match key {
+            Some(key) => match index.get(&key) {
+                Some(&i) => groups[i].push(invoice),
+                None => {
+                    index.insert(key, groups.len());
+                    groups.push(vec![invoice]);
+                }
+            },
+            None => groups.push(vec![invoice]),
+        }
+    }
+    
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::memory::{sample_invoice, InMemoryRepository};
+    use crate::repo::JobRepository;
+    use rust_decimal::Decimal;
+
+    #[tokio::test]
+    async fn test_replicas_chase_a_client_once() {
+        let due = Utc::now().date_naive() - ChronoDuration::days(10);
+        let user_id = Uuid::new_v4();
+        let group = vec![
+            sample_invoice(user_id, due, Decimal::new(100, 0)),
+            sample_invoice(user_id, due, Decimal::new(250, 0)),
+        ];
+        let repo = InMemoryRepository::new();
+
+        // Both replicas find the same overdue invoices
+        assert!(repo.enqueue_job(chase_job(&group, None)).await.unwrap().is_some());
+        assert!(repo.enqueue_job(chase_job(&group, None)).await.unwrap().is_none());
+
+        // The first worker's hold has lapsed as soon as it is taken
+        let first = repo.claim_jobs(CHASE_JOB, "a", 10, ChronoDuration::seconds(-1)).await.unwrap();
+        assert_eq!(first.len(), 1);
+        let payload: ChasePayload = serde_json::from_value(first[0].payload.clone()).unwrap();
+        assert_eq!(payload.invoice_ids, vec![group[0].id, group[1].id]);
+
+        // So the job is claimed again, and the first worker can no longer
+        // complete it
+        let second = repo.claim_jobs(CHASE_JOB, "b", 10, ChronoDuration::minutes(5)).await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].attempts, 2);
+        assert!(repo.claim_jobs(CHASE_JOB, "a", 10, ChronoDuration::minutes(5)).await.unwrap().is_empty());
+        assert!(!repo.complete_job(first[0].id, "a").await.unwrap());
+        assert!(repo.complete_job(second[0].id, "b").await.unwrap());
+
+        // A finished job no longer blocks the client's next one
+        assert!(repo.enqueue_job(chase_job(&group, None)).await.unwrap().is_some());
+    }
+}
@@ -1,14 +1,23 @@
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
+
+use crate::db::residency::{env_suffix, home_region};
+use crate::logging::{redact_email, redact_text};
+use crate::models::user_settings::AiConsent;
+use crate::worker::heartbeat::{record_provider_outcome, Provider};
 
 /// Mock LLM service for generating email content.
 /// 
 /// In production, this would call an actual LLM API (OpenAI, Anthropic, etc.)
 /// to generate personalized email content based on the tone and context.
+/// This is the only place chase text is sent to the LLM: without the
+/// user's consent the email is written from [`template_email`] instead.
 /// 
 /// # Arguments
 /// 
-/// * `tone` - The tone of the email ("polite" or "firm")
+/// * `tone` - The tone of the email ("gentle", "polite", "direct", "firm",
+///   "urgent" or "final")
 /// * `context` - Context about the invoice (client name, amount, due date, etc.)
+/// * `consent` - The invoice owner's consent for external AI providers
 /// 
 /// # Returns
 /// 
@@ -17,15 +26,99 @@ use tracing::{info, warn};
 /// # Example
 /// 
 /// ```rust
-/// let (subject, body) = generate_email("polite", "Invoice INV-001 for $100.00");
+/// let (subject, body) = generate_email("polite", "Invoice INV-001 for $100.00", consent).await?;
 /// ```
-pub async fn generate_email(tone: &str, context: &str) -> Result<(String, String), anyhow::Error> {
-    info!("Mock LLM: Generating {} email with context: {}", tone, context);
+pub async fn generate_email(
+    tone: &str,
+    context: &str,
+    consent: AiConsent,
+) -> Result<(String, String), anyhow::Error> {
+    if !consent.llm {
+        debug!("LLM consent not given, writing {} email from template", tone);
+        return Ok(template_email(tone, context));
+    }
+    
+    let result = mock_llm_email(tone, context).await;
+    record_provider_outcome(Provider::Llm, result.is_ok());
+    result
+}
+
+/// Endpoint of a provider in a region.
+/// 
+/// Read from `LLM_API_URL_<REGION>` or `EMAIL_API_URL_<REGION>` (e.g.
+/// `EMAIL_API_URL_EU`), falling back to `LLM_API_URL` or `EMAIL_API_URL`.
+/// 
+/// # Arguments
+/// 
+/// * `provider` - The provider
+/// * `region` - Region whose data the calls carry
+/// * `lookup` - Reads an environment variable
+/// 
+/// # Returns
+/// 
+/// Returns the endpoint URL, or `None` for the provider's default
+/// endpoint.
+pub fn provider_endpoint(provider: Provider, region: &str, lookup: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let variable = match provider {
+        Provider::Llm => "LLM_API_URL",
+        Provider::Email => "EMAIL_API_URL",
+    };
+    lookup(&format!("{}_{}", variable, env_suffix(region)))
+        .or_else(|| lookup(variable))
+        .filter(|url| !url.trim().is_empty())
+}
+
+/// Endpoint of a provider in the region this worker serves (`DATA_REGION`).
+fn regional_endpoint(provider: Provider) -> String {
+    provider_endpoint(provider, &home_region(), |name| std::env::var(name).ok())
+        .unwrap_or_else(|| "default endpoint".to_string())
+}
+
+/// Mock LLM call behind [`generate_email`].
+async fn mock_llm_email(tone: &str, context: &str) -> Result<(String, String), anyhow::Error> {
+    info!(
+        "Mock LLM ({}): Generating {} email with context: {}",
+        regional_endpoint(Provider::Llm),
+        tone,
+        redact_text(&context)
+    );
     
     // Simulate async LLM call delay
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     
-    let (subject, body) = match tone {
+    // The mock answers with the template text
+    let (subject, body) = template_email(tone, context);
+    
+    info!("Mock LLM: Generated email subject: {}", subject);
+    Ok((subject, body))
+}
+
+/// Writes a chase email from a fixed template, without any AI provider.
+/// 
+/// Unknown tones get the polite template.
+/// 
+/// # Arguments
+/// 
+/// * `tone` - The tone of the email ("gentle", "polite", "direct", "firm",
+///   "urgent" or "final")
+/// * `context` - Context about the invoice, quoted in the body
+/// 
+/// # Returns
+/// 
+/// Returns the email subject and body.
+pub fn template_email(tone: &str, context: &str) -> (String, String) {
+    match tone {
+        "gentle" => (
+            "Quick Note: Payment Reminder".to_string(),
+            format!(
+                "Dear Client,\n\nJust a quick note regarding {}. \
+                It has probably slipped through the cracks, which is \
+                understandable.\n\n\
+                Whenever you have a moment, we would appreciate payment.\n\n\
+                Thank you for always paying so reliably!\n\nBest regards,\nGigPilot",
+                context
+            ),
+        ),
         "polite" => (
             "Friendly Reminder: Payment Due".to_string(),
             format!(
@@ -37,6 +130,16 @@ pub async fn generate_email(tone: &str, context: &str) -> Result<(String, String
                 context
             ),
         ),
+        "direct" => (
+            "Payment Due: Please Pay Now".to_string(),
+            format!(
+                "Dear Client,\n\nPayment is now due regarding {}.\n\n\
+                Please arrange payment today, or reply with the date on \
+                which we can expect it.\n\n\
+                Thank you,\nGigPilot",
+                context
+            ),
+        ),
         "firm" => (
             "Urgent: Payment Required".to_string(),
             format!(
@@ -50,14 +153,88 @@ pub async fn generate_email(tone: &str, context: &str) -> Result<(String, String
                 context
             ),
         ),
+        "urgent" => (
+            "Overdue: Payment Still Outstanding".to_string(),
+            format!(
+                "Dear Client,\n\nDespite our earlier reminders, payment \
+                regarding {} is still outstanding.\n\n\
+                Please pay within the next 7 days, or contact us today if \
+                there is a problem with the invoice.\n\n\
+                Best regards,\nGigPilot",
+                context
+            ),
+        ),
+        "final" => (
+            "Final Notice: Payment Required".to_string(),
+            format!(
+                "Dear Client,\n\nThis is our final notice regarding {}. \
+                We have sent several reminders without receiving payment.\n\n\
+                This is the last reminder we will send. Unless payment is \
+                received promptly, we will take further steps to recover \
+                the amount owed.\n\n\
+                Best regards,\nGigPilot",
+                context
+            ),
+        ),
         _ => {
             warn!("Unknown tone: {}, defaulting to polite", tone);
-            generate_email("polite", context).await?
+            template_email("polite", context)
         }
-    };
+    }
+}
+
+/// Renders a plain-text email as a minimal HTML document.
+/// 
+/// The body is HTML-escaped and each blank-line separated block becomes
+/// a paragraph.
+/// 
+/// # Arguments
+/// 
+/// * `subject` - Email subject line (used as the document title)
+/// * `body` - Plain-text email body
+/// 
+/// # Returns
+/// 
+/// Returns the HTML document as a string.
+pub fn render_email_html(subject: &str, body: &str) -> String {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
     
-    info!("Mock LLM: Generated email subject: {}", subject);
-    Ok((subject, body))
+    let paragraphs: String = body
+        .split("\n\n")
+        .map(|p| format!("<p>{}</p>\n", escape(p).replace('\n', "<br>")))
+        .collect();
+    
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>\n{}</body></html>\n",
+        escape(subject),
+        paragraphs
+    )
+}
+
+/// Address emails are sent from, read from `EMAIL_FROM`.
+/// 
+/// The startup self-check refuses to start the server without it; the
+/// fallback only keeps the worker and tests running.
+pub fn email_sender() -> String {
+    std::env::var("EMAIL_FROM").unwrap_or_else(|_| "GigPilot <noreply@gigpilot.local>".to_string())
+}
+
+/// File attached to an outgoing email.
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    /// File name shown to the recipient
+    pub filename: String,
+    
+    /// MIME type of the attachment (e.g. "application/pdf")
+    pub content_type: String,
+    
+    /// Raw file contents
+    pub data: Vec<u8>,
 }
 
 /// Mock email sending service.
@@ -81,9 +258,58 @@ pub async fn generate_email(tone: &str, context: &str) -> Result<(String, String
 /// send_email("client@example.com", "Reminder", "Please pay...").await?;
 /// ```
 pub async fn send_email(to: &str, subject: &str, body: &str) -> Result<(), anyhow::Error> {
-    info!("Mock Email Service: Sending email to {}", to);
+    send_email_with_attachments(to, subject, body, &[]).await
+}
+
+/// Mock email sending service with file attachments.
+/// 
+/// Behaves like [`send_email`] but also attaches the given files
+/// (e.g. the invoice PDF) to the message.
+/// 
+/// # Arguments
+/// 
+/// * `to` - Recipient email address
+/// * `subject` - Email subject line
+/// * `body` - Email body content
+/// * `attachments` - Files to attach
+/// 
+/// # Returns
+/// 
+/// Returns `Ok(())` if the email was sent successfully, or an error.
+pub async fn send_email_with_attachments(
+    to: &str,
+    subject: &str,
+    body: &str,
+    attachments: &[EmailAttachment],
+) -> Result<(), anyhow::Error> {
+    let result = mock_deliver_email(to, subject, body, attachments).await;
+    record_provider_outcome(Provider::Email, result.is_ok());
+    result
+}
+
+/// Mock email delivery behind [`send_email_with_attachments`].
+async fn mock_deliver_email(
+    to: &str,
+    subject: &str,
+    body: &str,
+    attachments: &[EmailAttachment],
+) -> Result<(), anyhow::Error> {
+    info!(
+        "Mock Email Service ({}): Sending email from {} to {}",
+        regional_endpoint(Provider::Email),
+        email_sender(),
+        redact_email(&to)
+    );
     info!("Subject: {}", subject);
-    info!("Body preview: {}...", &body[..body.len().min(100)]);
+    info!("Body preview: {}", redact_text(&body));
+    for attachment in attachments {
+        info!(
+            "Attachment: {} ({}, {} bytes)",
+            attachment.filename,
+            attachment.content_type,
+            attachment.data.len()
+        );
+    }
     
     // Simulate async email sending delay
     tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
@@ -96,7 +322,7 @@ pub async fn send_email(to: &str, subject: &str, body: &str) -> Result<(), anyho
     //     body: body.to_string(),
     // }).await?;
     
-    info!("Mock Email Service: Email sent successfully to {}", to);
+    info!("Mock Email Service: Email sent successfully to {}", redact_email(&to));
     Ok(())
 }
 
@@ -104,9 +330,11 @@ pub async fn send_email(to: &str, subject: &str, body: &str) -> Result<(), anyho
 mod tests {
     use super::*;
 
+    const LLM_CONSENT: AiConsent = AiConsent { llm: true, embeddings: false };
+
     #[tokio::test]
     async fn test_generate_polite_email() {
-        let (subject, body) = generate_email("polite", "Invoice INV-001")
+        let (subject, body) = generate_email("polite", "Invoice INV-001", LLM_CONSENT)
             .await
             .expect("Should generate email");
         
@@ -116,7 +344,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_firm_email() {
-        let (subject, body) = generate_email("firm", "Invoice INV-001")
+        let (subject, body) = generate_email("firm", "Invoice INV-001", LLM_CONSENT)
             .await
             .expect("Should generate email");
         
@@ -124,6 +352,40 @@ mod tests {
         assert!(body.contains("overdue"));
     }
 
+    #[tokio::test]
+    async fn test_generate_email_without_consent_uses_template() {
+        let generated = generate_email("firm", "Invoice INV-001", AiConsent::default())
+            .await
+            .expect("Should generate email");
+        
+        assert_eq!(generated, template_email("firm", "Invoice INV-001"));
+        assert!(template_email("final", "Invoice INV-001").0.starts_with("Final Notice"));
+        assert_eq!(template_email("casual", "Invoice INV-001"), template_email("polite", "Invoice INV-001"));
+    }
+
+    #[test]
+    fn test_render_email_html_escapes_body() {
+        let html = render_email_html("Reminder", "Dear <Client>,\n\nPlease pay & thanks");
+        assert!(html.contains("<p>Dear &lt;Client&gt;,</p>"));
+        assert!(html.contains("<p>Please pay &amp; thanks</p>"));
+    }
+
+    #[test]
+    fn test_provider_endpoint_prefers_the_region() {
+        let vars = [("EMAIL_API_URL", "https://mail.example.com"), ("EMAIL_API_URL_EU_WEST", "https://eu.mail.example.com")];
+        let lookup = |name: &str| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string());
+
+        assert_eq!(
+            provider_endpoint(Provider::Email, "eu-west", lookup).as_deref(),
+            Some("https://eu.mail.example.com")
+        );
+        assert_eq!(
+            provider_endpoint(Provider::Email, "us", lookup).as_deref(),
+            Some("https://mail.example.com")
+        );
+        assert_eq!(provider_endpoint(Provider::Llm, "eu-west", lookup), None);
+    }
+
     #[tokio::test]
     async fn test_send_email() {
         let result = send_email(
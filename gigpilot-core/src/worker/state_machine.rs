@@ -1,5 +1,22 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use tracing::warn;
+
+use crate::business_days::HolidayCalendar;
+use crate::models::chase_override::ChaseOverride;
+use crate::models::invoice::{Invoice, InvoiceStatus};
+
+/// Days overdue before escalating from level 1 to level 2 (default policy).
+pub const LEVEL_2_AFTER_DAYS: i64 = 7;
+
+/// Days between the level-2 reminder, the level-3 one and the final notice.
+pub const ESCALATION_INTERVAL_DAYS: i64 = 7;
+
+/// Days overdue the final notice is sent after (default policy); no
+/// reminder, and so no late fee, follows it.
+pub const FINAL_NOTICE_AFTER_DAYS: i64 = LEVEL_2_AFTER_DAYS + 2 * ESCALATION_INTERVAL_DAYS;
 
 /// Chase state enumeration representing the stages of invoice chasing.
 /// 
@@ -8,7 +25,12 @@ use std::fmt;
 /// - Overdue: Invoice due date has passed
 /// - ChasingLevel1: First chase (polite reminder)
 /// - ChasingLevel2: Second chase (firm reminder)
+/// - ChasingLevel3: Third chase (urgent reminder)
+/// - FinalNotice: Last chase before the user takes other steps; no more
+///   reminders are sent
 /// - Paid: Invoice has been paid (terminal state)
+/// - Disputed: The client disputed the invoice; chasing is held until the
+///   user resolves the dispute
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "varchar")]
 pub enum ChaseState {
@@ -24,8 +46,17 @@ pub enum ChaseState {
     #[sqlx(rename = "chasing_level_2")]
     ChasingLevel2,
     
+    #[sqlx(rename = "chasing_level_3")]
+    ChasingLevel3,
+    
+    #[sqlx(rename = "final_notice")]
+    FinalNotice,
+    
     #[sqlx(rename = "paid")]
     Paid,
+    
+    #[sqlx(rename = "disputed")]
+    Disputed,
 }
 
 impl fmt::Display for ChaseState {
@@ -35,7 +66,10 @@ impl fmt::Display for ChaseState {
             ChaseState::Overdue => write!(f, "overdue"),
             ChaseState::ChasingLevel1 => write!(f, "chasing_level_1"),
             ChaseState::ChasingLevel2 => write!(f, "chasing_level_2"),
+            ChaseState::ChasingLevel3 => write!(f, "chasing_level_3"),
+            ChaseState::FinalNotice => write!(f, "final_notice"),
             ChaseState::Paid => write!(f, "paid"),
+            ChaseState::Disputed => write!(f, "disputed"),
         }
     }
 }
@@ -49,6 +83,12 @@ pub enum ChaseAction {
     /// Send a firm reminder email
     SendFirmReminder,
     
+    /// Send an urgent reminder email
+    SendUrgentReminder,
+    
+    /// Send the final notice email
+    SendFinalNotice,
+    
     /// Mark as paid (no action needed)
     MarkAsPaid,
     
@@ -61,12 +101,53 @@ impl fmt::Display for ChaseAction {
         match self {
             ChaseAction::SendPoliteReminder => write!(f, "send_polite_reminder"),
             ChaseAction::SendFirmReminder => write!(f, "send_firm_reminder"),
+            ChaseAction::SendUrgentReminder => write!(f, "send_urgent_reminder"),
+            ChaseAction::SendFinalNotice => write!(f, "send_final_notice"),
             ChaseAction::MarkAsPaid => write!(f, "mark_as_paid"),
             ChaseAction::NoAction => write!(f, "no_action"),
         }
     }
 }
 
+impl ChaseAction {
+    /// How escalated the email this action sends is, from 1 (polite) to 4
+    /// (final notice); 0 if it sends none.
+    pub fn escalation(self) -> u8 {
+        match self {
+            ChaseAction::MarkAsPaid | ChaseAction::NoAction => 0,
+            ChaseAction::SendPoliteReminder => 1,
+            ChaseAction::SendFirmReminder => 2,
+            ChaseAction::SendUrgentReminder => 3,
+            ChaseAction::SendFinalNotice => 4,
+        }
+    }
+
+    /// Whether a late fee not yet charged is charged with this action: the
+    /// firm reminder or any later one, so a fee that only became due after
+    /// the firm reminder went out is charged with the next.
+    pub fn charges_late_fee(self) -> bool {
+        self.escalation() >= ChaseAction::SendFirmReminder.escalation()
+    }
+}
+
+/// Escalation past level 2, once `days_overdue` reaches the thresholds
+/// that follow a level-2 threshold of `level_2_after_days`.
+fn escalate_past_level_2(
+    current_state: ChaseState,
+    days_overdue: i64,
+    level_2_after_days: i64,
+) -> (ChaseState, ChaseAction) {
+    match current_state {
+        ChaseState::ChasingLevel2 if days_overdue >= level_2_after_days + ESCALATION_INTERVAL_DAYS => {
+            (ChaseState::ChasingLevel3, ChaseAction::SendUrgentReminder)
+        }
+        ChaseState::ChasingLevel3 if days_overdue >= level_2_after_days + 2 * ESCALATION_INTERVAL_DAYS => {
+            (ChaseState::FinalNotice, ChaseAction::SendFinalNotice)
+        }
+        _ => (current_state, ChaseAction::NoAction),
+    }
+}
+
 /// Trait for state transitions in the invoice chasing state machine.
 /// 
 /// Defines the logic for determining the next state and action
@@ -84,6 +165,102 @@ pub trait Transition {
     /// Returns a tuple of (next_state, action_to_take).
     fn transition(current_state: ChaseState, days_overdue: i64) -> (ChaseState, ChaseAction);
     
+    /// Determines the next state and action, considering the remaining balance.
+    /// 
+    /// Partial payments do not stop chasing; once nothing is left to pay the
+    /// invoice moves to `Paid` from any state. Otherwise the regular
+    /// [`Transition::transition`] applies.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `current_state` - The current chase state
+    /// * `days_overdue` - Number of days the invoice is overdue
+    /// * `balance_due` - Amount still owed on the invoice
+    /// 
+    /// # Returns
+    /// 
+    /// Returns a tuple of (next_state, action_to_take).
+    fn transition_with_balance(
+        current_state: ChaseState,
+        days_overdue: i64,
+        balance_due: Decimal,
+    ) -> (ChaseState, ChaseAction) {
+        if balance_due <= Decimal::ZERO {
+            let action = if current_state == ChaseState::Paid {
+                ChaseAction::NoAction
+            } else {
+                ChaseAction::MarkAsPaid
+            };
+            (ChaseState::Paid, action)
+        } else {
+            Self::transition(current_state, days_overdue)
+        }
+    }
+    
+    /// Determines the next state and action under a per-invoice override.
+    /// 
+    /// A zero balance always moves the invoice to `Paid`. Otherwise, a paused
+    /// invoice or one whose `not_before` date has not been reached stays in
+    /// its current state; `level_2_after_days` changes when the firm reminder
+    /// is sent, and the level-3 reminder and final notice keep following it a
+    /// week apart. With `skip_level_2` no firm reminder is sent: the level-3
+    /// reminder follows the first one when it would have followed the firm
+    /// reminder.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `current_state` - The current chase state
+    /// * `days_overdue` - Number of days the invoice is overdue
+    /// * `balance_due` - Amount still owed on the invoice
+    /// * `overrides` - The invoice's chase override
+    /// * `today` - Current date
+    /// 
+    /// # Returns
+    /// 
+    /// Returns a tuple of (next_state, action_to_take).
+    fn transition_with_override(
+        current_state: ChaseState,
+        days_overdue: i64,
+        balance_due: Decimal,
+        overrides: &ChaseOverride,
+        today: NaiveDate,
+    ) -> (ChaseState, ChaseAction) {
+        if balance_due <= Decimal::ZERO {
+            return Self::transition_with_balance(current_state, days_overdue, balance_due);
+        }
+        
+        let held = overrides.paused || overrides.not_before.map_or(false, |date| today < date);
+        if held {
+            return (current_state, ChaseAction::NoAction);
+        }
+        
+        match current_state {
+            ChaseState::ChasingLevel1 if overrides.skip_level_2 => {
+                // The urgent reminder comes when it would have followed the
+                // firm one
+                if days_overdue >= LEVEL_2_AFTER_DAYS + ESCALATION_INTERVAL_DAYS {
+                    (ChaseState::ChasingLevel3, ChaseAction::SendUrgentReminder)
+                } else {
+                    (ChaseState::ChasingLevel1, ChaseAction::NoAction)
+                }
+            }
+            ChaseState::ChasingLevel1 => {
+                let threshold = overrides.level_2_after_days.unwrap_or(LEVEL_2_AFTER_DAYS);
+                if days_overdue >= threshold {
+                    (ChaseState::ChasingLevel2, ChaseAction::SendFirmReminder)
+                } else {
+                    (ChaseState::ChasingLevel1, ChaseAction::NoAction)
+                }
+            }
+            ChaseState::ChasingLevel2 | ChaseState::ChasingLevel3 => escalate_past_level_2(
+                current_state,
+                days_overdue,
+                overrides.level_2_after_days.unwrap_or(LEVEL_2_AFTER_DAYS),
+            ),
+            _ => Self::transition(current_state, days_overdue),
+        }
+    }
+    
     /// Gets the initial state for a new invoice.
     /// 
     /// # Returns
@@ -94,13 +271,68 @@ pub trait Transition {
     }
 }
 
+/// Reads an invoice's chase state as of `today`.
+/// 
+/// Uses `metadata.chase_state` when set; otherwise paid invoices are
+/// `Paid`, invoices past their due date `Overdue` and the rest `Pending`.
+pub fn current_chase_state(invoice: &Invoice, today: NaiveDate) -> ChaseState {
+    let stored = invoice
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("chase_state"))
+        .and_then(|v| v.as_str());
+    
+    if let Some(chase_state_str) = stored {
+        match chase_state_str {
+            "pending" => return ChaseState::Pending,
+            "overdue" => return ChaseState::Overdue,
+            "chasing_level_1" => return ChaseState::ChasingLevel1,
+            "chasing_level_2" => return ChaseState::ChasingLevel2,
+            "chasing_level_3" => return ChaseState::ChasingLevel3,
+            "final_notice" => return ChaseState::FinalNotice,
+            "paid" => return ChaseState::Paid,
+            "disputed" => return ChaseState::Disputed,
+            _ => {
+                warn!("Unknown chase_state in metadata: {}", chase_state_str);
+            }
+        }
+    }
+    
+    // Default based on invoice status and due date
+    if invoice.status == InvoiceStatus::Paid {
+        ChaseState::Paid
+    } else if invoice.due_date.map_or(false, |due_date| due_date < today) {
+        ChaseState::Overdue
+    } else {
+        ChaseState::Pending
+    }
+}
+
+/// Counts the days an invoice is overdue as of `today`.
+/// 
+/// With a calendar only business days count. Returns 0 if the invoice has
+/// no due date or is not overdue.
+pub fn days_overdue(invoice: &Invoice, today: NaiveDate, calendar: Option<&HolidayCalendar>) -> i64 {
+    match invoice.due_date {
+        Some(due_date) if due_date < today => match calendar {
+            Some(calendar) => calendar.business_days_between(due_date, today),
+            None => (today - due_date).num_days(),
+        },
+        _ => 0,
+    }
+}
+
 /// Default implementation of the Transition trait for invoice chasing.
 /// 
 /// Implements the state machine logic:
 /// - Pending -> Overdue (when due_date passes)
 /// - Overdue -> ChasingLevel1 (after 0 days overdue, send polite reminder)
 /// - ChasingLevel1 -> ChasingLevel2 (after 7 days, send firm reminder)
-/// - Any state -> Paid (if invoice is marked as paid)
+/// - ChasingLevel2 -> ChasingLevel3 (after 14 days, send urgent reminder)
+/// - ChasingLevel3 -> FinalNotice (after 21 days, send final notice)
+/// - FinalNotice stays FinalNotice (no further reminders)
+/// - Disputed stays Disputed (held until the dispute is resolved)
+/// - Any state -> Paid (once the remaining balance reaches zero)
 pub struct ChaseStateMachine;
 
 impl Transition for ChaseStateMachine {
@@ -119,20 +351,28 @@ impl Transition for ChaseStateMachine {
             }
             ChaseState::ChasingLevel1 => {
                 // After 7 days of first chase, escalate to firm reminder
-                if days_overdue >= 7 {
+                if days_overdue >= LEVEL_2_AFTER_DAYS {
                     (ChaseState::ChasingLevel2, ChaseAction::SendFirmReminder)
                 } else {
                     (ChaseState::ChasingLevel1, ChaseAction::NoAction)
                 }
             }
-            ChaseState::ChasingLevel2 => {
+            ChaseState::ChasingLevel2 | ChaseState::ChasingLevel3 => {
+                // A week after the firm reminder, then a week after that
+                escalate_past_level_2(current_state, days_overdue, LEVEL_2_AFTER_DAYS)
+            }
+            ChaseState::FinalNotice => {
                 // Already at maximum chase level, no further action
-                (ChaseState::ChasingLevel2, ChaseAction::NoAction)
+                (ChaseState::FinalNotice, ChaseAction::NoAction)
             }
             ChaseState::Paid => {
                 // Terminal state, no transitions
                 (ChaseState::Paid, ChaseAction::NoAction)
             }
+            ChaseState::Disputed => {
+                // Held until the user resolves the dispute
+                (ChaseState::Disputed, ChaseAction::NoAction)
+            }
         }
     }
 }
@@ -140,6 +380,7 @@ impl Transition for ChaseStateMachine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::date;
 
     #[test]
     fn test_pending_to_overdue_transition() {
@@ -162,11 +403,140 @@ mod tests {
         assert_eq!(action, ChaseAction::SendFirmReminder);
     }
 
+    #[test]
+    fn test_escalation_past_level_2_to_final_notice() {
+        let (next_state, action) = ChaseStateMachine::transition(ChaseState::ChasingLevel2, 13);
+        assert_eq!(next_state, ChaseState::ChasingLevel2);
+        assert_eq!(action, ChaseAction::NoAction);
+
+        let (next_state, action) = ChaseStateMachine::transition(ChaseState::ChasingLevel2, 14);
+        assert_eq!(next_state, ChaseState::ChasingLevel3);
+        assert_eq!(action, ChaseAction::SendUrgentReminder);
+
+        let (next_state, action) = ChaseStateMachine::transition(ChaseState::ChasingLevel3, 21);
+        assert_eq!(next_state, ChaseState::FinalNotice);
+        assert_eq!(action, ChaseAction::SendFinalNotice);
+
+        let (next_state, action) = ChaseStateMachine::transition(ChaseState::FinalNotice, 90);
+        assert_eq!(next_state, ChaseState::FinalNotice);
+        assert_eq!(action, ChaseAction::NoAction);
+    }
+
+    #[test]
+    fn test_partial_payment_keeps_chasing() {
+        let (next_state, action) =
+            ChaseStateMachine::transition_with_balance(ChaseState::ChasingLevel1, 7, Decimal::new(5000, 2));
+        assert_eq!(next_state, ChaseState::ChasingLevel2);
+        assert_eq!(action, ChaseAction::SendFirmReminder);
+    }
+
+    #[test]
+    fn test_zero_balance_marks_paid() {
+        let (next_state, action) =
+            ChaseStateMachine::transition_with_balance(ChaseState::ChasingLevel2, 30, Decimal::ZERO);
+        assert_eq!(next_state, ChaseState::Paid);
+        assert_eq!(action, ChaseAction::MarkAsPaid);
+        
+        let (next_state, action) =
+            ChaseStateMachine::transition_with_balance(ChaseState::Paid, 30, Decimal::ZERO);
+        assert_eq!(next_state, ChaseState::Paid);
+        assert_eq!(action, ChaseAction::NoAction);
+    }
+
+    #[test]
+    fn test_override_not_before_holds_state() {
+        let overrides = ChaseOverride {
+            not_before: Some(date(2024, 3, 1)),
+            ..Default::default()
+        };
+        let balance = Decimal::from(100);
+        
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::Pending, 10, balance, &overrides, date(2024, 2, 20),
+        );
+        assert_eq!(next_state, ChaseState::Pending);
+        assert_eq!(action, ChaseAction::NoAction);
+        
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::Pending, 10, balance, &overrides, date(2024, 3, 1),
+        );
+        assert_eq!(next_state, ChaseState::Overdue);
+        assert_eq!(action, ChaseAction::SendPoliteReminder);
+    }
+
+    #[test]
+    fn test_override_skip_and_delay_level_2() {
+        let balance = Decimal::from(100);
+        let today = date(2024, 3, 1);
+        
+        let skip = ChaseOverride { skip_level_2: true, ..Default::default() };
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel1, 10, balance, &skip, today,
+        );
+        assert_eq!(next_state, ChaseState::ChasingLevel1);
+        assert_eq!(action, ChaseAction::NoAction);
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel1, 14, balance, &skip, today,
+        );
+        assert_eq!(next_state, ChaseState::ChasingLevel3);
+        assert_eq!(action, ChaseAction::SendUrgentReminder);
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel3, 21, balance, &skip, today,
+        );
+        assert_eq!(next_state, ChaseState::FinalNotice);
+        assert_eq!(action, ChaseAction::SendFinalNotice);
+        
+        let delay = ChaseOverride { level_2_after_days: Some(14), ..Default::default() };
+        let (next_state, _) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel1, 10, balance, &delay, today,
+        );
+        assert_eq!(next_state, ChaseState::ChasingLevel1);
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel1, 14, balance, &delay, today,
+        );
+        assert_eq!(next_state, ChaseState::ChasingLevel2);
+        assert_eq!(action, ChaseAction::SendFirmReminder);
+        
+        // Later escalation keeps following the delayed firm reminder
+        let (next_state, _) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel2, 20, balance, &delay, today,
+        );
+        assert_eq!(next_state, ChaseState::ChasingLevel2);
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel2, 21, balance, &delay, today,
+        );
+        assert_eq!(next_state, ChaseState::ChasingLevel3);
+        assert_eq!(action, ChaseAction::SendUrgentReminder);
+    }
+
+    #[test]
+    fn test_paused_override_still_marks_paid() {
+        let paused = ChaseOverride { paused: true, ..Default::default() };
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::ChasingLevel1, 30, Decimal::ZERO, &paused, date(2024, 3, 1),
+        );
+        assert_eq!(next_state, ChaseState::Paid);
+        assert_eq!(action, ChaseAction::MarkAsPaid);
+    }
+
     #[test]
     fn test_paid_state_no_transition() {
         let (next_state, action) = ChaseStateMachine::transition(ChaseState::Paid, 100);
         assert_eq!(next_state, ChaseState::Paid);
         assert_eq!(action, ChaseAction::NoAction);
     }
-}
 
+    #[test]
+    fn test_disputed_state_holds_until_paid() {
+        let (next_state, action) = ChaseStateMachine::transition_with_override(
+            ChaseState::Disputed, 30, Decimal::new(5000, 2), &ChaseOverride::default(), date(2024, 3, 1),
+        );
+        assert_eq!(next_state, ChaseState::Disputed);
+        assert_eq!(action, ChaseAction::NoAction);
+
+        let (next_state, action) =
+            ChaseStateMachine::transition_with_balance(ChaseState::Disputed, 30, Decimal::ZERO);
+        assert_eq!(next_state, ChaseState::Paid);
+        assert_eq!(action, ChaseAction::MarkAsPaid);
+    }
+}
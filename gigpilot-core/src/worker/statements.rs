@@ -0,0 +1,512 @@
+//! Monthly client statement delivery.
+//!
+//! Sends every enabled statement schedule whose day has come: the client's
+//! open invoices with their balances, the total outstanding per currency
+//! and how to pay, written in the schedule's locale and delivered through
+//! its channel. Clients with nothing outstanding get no statement that
+//! month. Each due statement is sent by a job in the durable job queue
+//! (see [`crate::worker::jobs`]), so one worker replica sends it and a
+//! failed send is retried with backoff.
+
+use chrono::{Duration, NaiveDate, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::email_sandbox::deliver_email;
+use crate::invoices::pdf::PdfBranding;
+use crate::logging::redact_email;
+use crate::maintenance::worker_paused;
+use crate::models::invoice::Invoice;
+use crate::models::job::CreateJob;
+use crate::models::statement_schedule::{StatementChannel, StatementLocale, StatementSchedule};
+use crate::payment_methods::{instructions_text, methods_for_client};
+use crate::statements::next_statement_date;
+use crate::worker::executor::pay_link;
+use crate::worker::jobs::{
+    claim_jobs, complete_job, enqueue_job, fail_job, retry_at, visibility_timeout, worker_id, DEFAULT_MAX_ATTEMPTS,
+};
+
+/// Statements queued, and statement jobs claimed, per run.
+const STATEMENT_BATCH_SIZE: i64 = 50;
+
+/// Kind of the jobs sending a client statement.
+pub const STATEMENT_JOB: &str = "send_statement";
+
+/// Input of a statement job: the schedule and the date of its statement.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct StatementPayload {
+    schedule_id: Uuid,
+    as_of: NaiveDate,
+}
+
+/// Fixed wording of a statement in one locale.
+struct StatementText {
+    subject: &'static str,
+    greeting: &'static str,
+    intro: &'static str,
+    invoice: &'static str,
+    due: &'static str,
+    on_receipt: &'static str,
+    overdue: &'static str,
+    pay_online: &'static str,
+    total: &'static str,
+    closing: &'static str,
+}
+
+fn text(locale: StatementLocale) -> StatementText {
+    match locale {
+        StatementLocale::En => StatementText {
+            subject: "Statement of account",
+            greeting: "Hello",
+            intro: "Here is your statement of open invoices as of",
+            invoice: "Invoice",
+            due: "due",
+            on_receipt: "due on receipt",
+            overdue: "OVERDUE",
+            pay_online: "Pay online",
+            total: "Total outstanding",
+            closing: "Thank you for your business.",
+        },
+        StatementLocale::De => StatementText {
+            subject: "Kontoauszug",
+            greeting: "Guten Tag",
+            intro: "anbei Ihr Kontoauszug der offenen Rechnungen zum",
+            invoice: "Rechnung",
+            due: "fällig am",
+            on_receipt: "fällig bei Erhalt",
+            overdue: "ÜBERFÄLLIG",
+            pay_online: "Online bezahlen",
+            total: "Offener Gesamtbetrag",
+            closing: "Vielen Dank für Ihren Auftrag.",
+        },
+        StatementLocale::Fr => StatementText {
+            subject: "Relevé de compte",
+            greeting: "Bonjour",
+            intro: "voici votre relevé des factures ouvertes au",
+            invoice: "Facture",
+            due: "échéance",
+            on_receipt: "payable à réception",
+            overdue: "EN RETARD",
+            pay_online: "Payer en ligne",
+            total: "Total dû",
+            closing: "Merci de votre confiance.",
+        },
+        StatementLocale::Es => StatementText {
+            subject: "Estado de cuenta",
+            greeting: "Hola",
+            intro: "este es su estado de cuenta de facturas pendientes a",
+            invoice: "Factura",
+            due: "vence",
+            on_receipt: "pagadera al recibir",
+            overdue: "VENCIDA",
+            pay_online: "Pagar en línea",
+            total: "Total pendiente",
+            closing: "Gracias por su confianza.",
+        },
+    }
+}
+
+/// Formats a date the way the locale writes it.
+pub fn format_date(locale: StatementLocale, date: NaiveDate) -> String {
+    match locale {
+        StatementLocale::En => date.format("%Y-%m-%d").to_string(),
+        StatementLocale::De => date.format("%d.%m.%Y").to_string(),
+        StatementLocale::Fr | StatementLocale::Es => date.format("%d/%m/%Y").to_string(),
+    }
+}
+
+/// Formats an amount with two decimals and the locale's separators.
+pub fn format_amount(locale: StatementLocale, amount: Decimal) -> String {
+    let (thousands, decimal) = match locale {
+        StatementLocale::En => (',', '.'),
+        StatementLocale::De | StatementLocale::Es => ('.', ','),
+        StatementLocale::Fr => (' ', ','),
+    };
+
+    let rounded = amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+    let plain = format!("{:.2}", rounded.abs());
+    let (whole, cents) = plain.split_once('.').unwrap_or((&plain, "00"));
+
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(thousands);
+        }
+        grouped.push(digit);
+    }
+
+    let sign = if rounded.is_sign_negative() && !rounded.is_zero() { "-" } else { "" };
+    format!("{}{}{}{}", sign, grouped, decimal, cents)
+}
+
+/// Renders a statement email.
+///
+/// # Arguments
+///
+/// * `locale` - Language and formats to use
+/// * `client_name` - Name used in the greeting
+/// * `as_of` - Date of the statement (overdue means due before it)
+/// * `invoices` - The client's open invoices
+/// * `how_to_pay` - Payment instructions block, if any
+///
+/// # Returns
+///
+/// Returns the subject and plain-text body.
+pub fn render_statement(
+    locale: StatementLocale,
+    client_name: &str,
+    as_of: NaiveDate,
+    invoices: &[Invoice],
+    how_to_pay: Option<&str>,
+) -> (String, String) {
+    let text = text(locale);
+    let mut totals: BTreeMap<&str, Decimal> = BTreeMap::new();
+    let mut lines = Vec::new();
+
+    for invoice in invoices {
+        let balance = invoice.total - invoice.amount_paid;
+        *totals.entry(invoice.currency.as_str()).or_default() += balance;
+
+        let due = invoice
+            .due_date
+            .map(|d| format!("{} {}", text.due, format_date(locale, d)))
+            .unwrap_or_else(|| text.on_receipt.to_string());
+        let overdue = match invoice.due_date {
+            Some(due_date) if due_date < as_of => format!(" — {}", text.overdue),
+            _ => String::new(),
+        };
+        lines.push(format!(
+            "- {} {}: {} {} ({}){}\n  {}: {}",
+            text.invoice,
+            invoice.invoice_number,
+            invoice.currency,
+            format_amount(locale, balance),
+            due,
+            overdue,
+            text.pay_online,
+            pay_link(invoice),
+        ));
+    }
+
+    let totals = totals
+        .iter()
+        .map(|(currency, amount)| format!("{}: {} {}", text.total, currency, format_amount(locale, *amount)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let subject = format!("{} {}", text.subject, format_date(locale, as_of));
+    let mut body = format!(
+        "{} {},\n\n{} {}:\n\n{}\n\n{}",
+        text.greeting,
+        client_name,
+        text.intro,
+        format_date(locale, as_of),
+        lines.join("\n"),
+        totals,
+    );
+    if let Some(how_to_pay) = how_to_pay {
+        body = format!("{}\n\n{}", body, how_to_pay);
+    }
+    body = format!("{}\n\n{}", body, text.closing);
+
+    (subject, body)
+}
+
+/// Queues a statement job for each schedule due on `today`.
+///
+/// Schedules whose job is still queued or running are skipped.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `today` - Current date; schedules due on or before it are queued
+///
+/// # Returns
+///
+/// Returns the number of jobs queued.
+pub async fn enqueue_due_statements(pool: &PgPool, today: NaiveDate) -> Result<usize, anyhow::Error> {
+    let due: Vec<(Uuid,)> = sqlx::query_as(
+        r#"
+        SELECT id FROM statement_schedules
+        WHERE enabled = true AND next_run_on <= $1
+        ORDER BY next_run_on ASC
+        LIMIT $2
+        "#,
+    )
+    .bind(today)
+    .bind(STATEMENT_BATCH_SIZE)
+    .fetch_all(pool)
+    .await?;
+
+    let mut queued = 0;
+    for (schedule_id,) in due {
+        let job = CreateJob {
+            kind: STATEMENT_JOB.to_string(),
+            payload: json!(StatementPayload { schedule_id, as_of: today }),
+            dedupe_key: Some(schedule_id.to_string()),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            run_at: None,
+        };
+        if enqueue_job(pool, &job).await?.is_some() {
+            queued += 1;
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Runs the statement jobs this worker claims.
+///
+/// A failed job is retried with backoff until it runs out of attempts.
+///
+/// # Returns
+///
+/// Returns the number of statements sent.
+pub async fn run_statement_jobs(
+    pool: &PgPool,
+    worker_id: &str,
+    visibility: chrono::Duration,
+) -> Result<usize, anyhow::Error> {
+    // Jobs are claimed one at a time so each gets the whole visibility
+    // timeout, as chase jobs are
+    let mut sent = 0;
+    for _ in 0..STATEMENT_BATCH_SIZE {
+        let claimed = claim_jobs(pool, STATEMENT_JOB, worker_id, 1, visibility).await?;
+        let Some(job) = claimed.into_iter().next() else {
+            break;
+        };
+
+        let outcome = match serde_json::from_value::<StatementPayload>(job.payload.clone()) {
+            Ok(payload) => send_statement(pool, payload.schedule_id, payload.as_of).await,
+            Err(e) => Err(e.into()),
+        };
+        match outcome {
+            Ok(statement_sent) => {
+                sent += usize::from(statement_sent);
+                if !complete_job(pool, job.id, worker_id).await? {
+                    warn!("Statement job {} was reclaimed before it completed", job.id);
+                }
+            }
+            Err(e) => {
+                let retry = retry_at(&job, Utc::now());
+                error!(
+                    "Statement job {} failed (attempt {}), {}: {}",
+                    job.id,
+                    job.attempts,
+                    if retry.is_some() { "retrying" } else { "giving up" },
+                    e
+                );
+                fail_job(pool, job.id, worker_id, &e.to_string(), retry).await?;
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Sends the statement of a schedule for `as_of` and moves the schedule to
+/// the next month.
+///
+/// The schedule is moved on and the statement marked sent in a committed
+/// transaction before the email goes out, so no lock is held while it is
+/// sent and a failed commit can't send it twice. A failed send marks it
+/// unsent again and fails the job, which retries it; a worker that dies
+/// while sending leaves it marked sent, so the client isn't emailed twice.
+///
+/// # Returns
+///
+/// Returns `false` if the statement was already sent, the schedule is
+/// disabled or gone, or the client owes nothing.
+async fn send_statement(pool: &PgPool, schedule_id: Uuid, as_of: NaiveDate) -> Result<bool, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let schedule = sqlx::query_as::<_, StatementSchedule>(
+        "SELECT * FROM statement_schedules WHERE id = $1 AND enabled = true FOR UPDATE",
+    )
+    .bind(schedule_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(schedule) = schedule else {
+        return Ok(false);
+    };
+    if schedule.last_sent_on.is_some_and(|sent_on| sent_on >= as_of) {
+        return Ok(false);
+    }
+
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        FROM invoices
+        WHERE user_id = $1 AND LOWER(client_email) = $2
+            AND is_deleted = false
+            AND status IN ('sent', 'overdue')
+            AND total > amount_paid
+        ORDER BY due_date ASC NULLS LAST, invoice_number ASC
+        "#,
+    )
+    .bind(schedule.user_id)
+    .bind(&schedule.client_email)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let statement_sent = !invoices.is_empty();
+    sqlx::query(
+        r#"
+        UPDATE statement_schedules
+        SET next_run_on = GREATEST(next_run_on, $2),
+            last_sent_on = CASE WHEN $3 THEN $4 ELSE last_sent_on END
+        WHERE id = $1
+        "#,
+    )
+    .bind(schedule.id)
+    .bind(next_statement_date(as_of + Duration::days(1), schedule.day_of_month as u32))
+    .bind(statement_sent)
+    .bind(as_of)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if !statement_sent {
+        return Ok(false);
+    }
+
+    if let Err(e) = deliver_statement(pool, &schedule, as_of, &invoices).await {
+        // Nothing went out: mark it unsent so the job's retry sends it
+        sqlx::query("UPDATE statement_schedules SET last_sent_on = $3 WHERE id = $1 AND last_sent_on = $2")
+            .bind(schedule.id)
+            .bind(as_of)
+            .bind(schedule.last_sent_on)
+            .execute(pool)
+            .await?;
+        return Err(e);
+    }
+
+    Ok(true)
+}
+
+/// Renders a statement and sends it through the schedule's channel.
+async fn deliver_statement(
+    pool: &PgPool,
+    schedule: &StatementSchedule,
+    as_of: NaiveDate,
+    invoices: &[Invoice],
+) -> Result<(), anyhow::Error> {
+    let client_name = schedule
+        .client_name
+        .clone()
+        .unwrap_or_else(|| invoices[0].client_name.clone());
+    let references = invoices
+        .iter()
+        .map(|invoice| invoice.invoice_number.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let methods = methods_for_client(pool, schedule.user_id, Some(&schedule.client_email)).await?;
+    let how_to_pay = instructions_text(&methods, &references);
+    let (subject, mut body) = render_statement(schedule.locale, &client_name, as_of, invoices, how_to_pay.as_deref());
+
+    let branding = PdfBranding::for_user(pool, schedule.user_id).await?;
+    body = format!("{}\n{}", body, branding.business_name);
+
+    match schedule.channel {
+        StatementChannel::Email => {
+            deliver_email(pool, schedule.user_id, &schedule.client_email, &subject, &body, &[]).await?;
+        }
+    }
+    info!(
+        "Sent statement of {} open invoice(s) to {}",
+        invoices.len(),
+        redact_email(&schedule.client_email)
+    );
+    Ok(())
+}
+
+/// Spawns the background statement loop.
+///
+/// Polls every `STATEMENT_POLL_INTERVAL_SECONDS` (default: 3600 seconds),
+/// queueing a job for each statement due on the current day (UTC) and
+/// running the statement jobs this worker claims.
+pub fn spawn_statement_worker(pool: PgPool) {
+    let seconds = std::env::var("STATEMENT_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(3600);
+    let worker_id = worker_id();
+    let visibility = visibility_timeout();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if worker_paused() {
+                continue;
+            }
+            match enqueue_due_statements(&pool, Utc::now().date_naive()).await {
+                Ok(0) => {}
+                Ok(count) => info!("Queued {} client statement(s)", count),
+                Err(e) => error!("Failed to queue client statements: {}", e),
+            }
+            match run_statement_jobs(&pool, &worker_id, visibility).await {
+                Ok(0) => {}
+                Ok(count) => info!("Sent {} client statement(s)", count),
+                Err(e) => error!("Statement jobs failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{date, invoice};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_amounts_use_locale_separators() {
+        let amount = Decimal::from_str("1234567.891").unwrap();
+        assert_eq!(format_amount(StatementLocale::En, amount), "1,234,567.89");
+        assert_eq!(format_amount(StatementLocale::De, amount), "1.234.567,89");
+        assert_eq!(format_amount(StatementLocale::Fr, amount), "1 234 567,89");
+        assert_eq!(format_amount(StatementLocale::En, Decimal::from_str("-12.5").unwrap()), "-12.50");
+        assert_eq!(format_amount(StatementLocale::Es, Decimal::from_str("999").unwrap()), "999,00");
+    }
+
+    #[test]
+    fn test_dates_use_locale_order() {
+        assert_eq!(format_date(StatementLocale::En, date(2024, 3, 1)), "2024-03-01");
+        assert_eq!(format_date(StatementLocale::De, date(2024, 3, 1)), "01.03.2024");
+        assert_eq!(format_date(StatementLocale::Fr, date(2024, 3, 1)), "01/03/2024");
+    }
+
+    #[test]
+    fn test_statement_lists_balances_and_totals_per_currency() {
+        let invoices = vec![
+            invoice("INV-1", "EUR", 100000, 25000, Some(date(2024, 2, 1))),
+            invoice("INV-2", "EUR", 50000, 0, Some(date(2024, 3, 15))),
+            invoice("INV-3", "USD", 8000, 0, None),
+        ];
+
+        let (subject, body) =
+            render_statement(StatementLocale::De, "Acme", date(2024, 3, 1), &invoices, Some("How to pay:"));
+
+        assert_eq!(subject, "Kontoauszug 01.03.2024");
+        assert!(body.starts_with("Guten Tag Acme,"));
+        assert!(body.contains("Rechnung INV-1: EUR 750,00 (fällig am 01.02.2024) — ÜBERFÄLLIG"));
+        assert!(body.contains("Rechnung INV-2: EUR 500,00 (fällig am 15.03.2024)\n"));
+        assert!(body.contains("Rechnung INV-3: USD 80,00 (fällig bei Erhalt)\n"));
+        assert!(body.contains("Offener Gesamtbetrag: EUR 1.250,00\nOffener Gesamtbetrag: USD 80,00"));
+        assert!(body.contains("How to pay:"));
+    }
+}
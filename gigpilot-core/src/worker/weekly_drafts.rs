@@ -0,0 +1,834 @@
+//! Weekly automatic invoice drafts from unbilled work.
+//!
+//! Every Friday, users who opted in get one draft invoice per client (and
+//! currency) covering their unbilled billable time entries and billable
+//! expenses. The user is notified to review the drafts; if auto-send is
+//! enabled, a draft still untouched after the grace period is sent to the
+//! client.
+//! Changing a draft's status or deleting it cancels the auto-send.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::BTreeMap;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::currency::Percent;
+use crate::email_sandbox::deliver_email;
+use crate::invoices::correspondence::record_correspondence;
+use crate::invoices::numbering::next_invoice_number;
+use crate::invoices::DueDateRules;
+use crate::invoices::pdf::{render_invoice_pdf, PdfBranding};
+use crate::logging::redact_email;
+use crate::maintenance::worker_paused;
+use crate::models::correspondence::{CorrespondenceKind, CreateCorrespondence};
+use crate::models::expense::Expense;
+use crate::models::invoice::{Invoice, InvoiceStatus};
+use crate::models::line_item::{InvoiceTotals, LineItem};
+use crate::models::notification::CreateNotification;
+use crate::models::sync_change::SyncOperation;
+use crate::models::time_entry::TimeEntry;
+use crate::models::user_settings::UserSettings;
+use crate::notifications::notify;
+use crate::payment_methods::{instructions_text, methods_for_client};
+use crate::sync::encryption::encrypts_any;
+use crate::sync::server::record_server_change;
+use crate::worker::executor::pay_link;
+use crate::worker::services::{email_sender, render_email_html, EmailAttachment};
+
+/// Drafts auto-sent per run.
+const AUTO_SEND_BATCH_SIZE: i64 = 50;
+
+/// Whether weekly drafts are created on this day.
+pub fn is_draft_day(date: NaiveDate) -> bool {
+    date.weekday() == Weekday::Fri
+}
+
+/// Monday of the week containing `date`.
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// Unbilled work for one client in one currency, to become one draft.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DraftGroup {
+    /// Client name as first seen on the grouped items
+    pub client_name: String,
+
+    /// Client email as first seen on the grouped items
+    pub client_email: Option<String>,
+
+    /// Currency shared by every item
+    pub currency: String,
+
+    /// Invoice lines, time entries first
+    pub line_items: Vec<LineItem>,
+
+    /// Time entries billed by the draft
+    pub time_entry_ids: Vec<Uuid>,
+
+    /// Expenses billed by the draft
+    pub expense_ids: Vec<Uuid>,
+}
+
+impl DraftGroup {
+    fn new(client_name: &str, client_email: Option<&str>, currency: &str) -> Self {
+        Self {
+            client_name: client_name.to_string(),
+            client_email: client_email.map(str::to_string),
+            currency: currency.to_string(),
+            line_items: Vec::new(),
+            time_entry_ids: Vec::new(),
+            expense_ids: Vec::new(),
+        }
+    }
+}
+
+/// Identifies a client: by email when known, otherwise by name.
+fn client_key(name: &str, email: Option<&str>) -> String {
+    match email.map(str::trim).filter(|e| !e.is_empty()) {
+        Some(email) => email.to_lowercase(),
+        None => name.trim().to_lowercase(),
+    }
+}
+
+/// Groups unbilled time entries and expenses into drafts per client and
+/// currency.
+///
+/// Items without a client are left out. The default tax rate, if any, is
+/// applied to time lines only; expenses are re-billed at cost as recorded
+/// (their tax is already part of the receipt amount).
+///
+/// # Arguments
+///
+/// * `entries` - Unbilled time entries
+/// * `expenses` - Unbilled billable expenses
+/// * `default_tax` - The user's default tax rate (ID and percentage)
+///
+/// # Returns
+///
+/// Returns the groups ordered by client and currency.
+pub fn group_unbilled(
+    entries: &[TimeEntry],
+    expenses: &[Expense],
+    default_tax: Option<(Uuid, Percent)>,
+) -> Vec<DraftGroup> {
+    let mut groups: BTreeMap<(String, String), DraftGroup> = BTreeMap::new();
+
+    for entry in entries {
+        let Some(client_name) = entry.client_name.as_deref() else { continue };
+        let key = (client_key(client_name, entry.client_email.as_deref()), entry.currency.clone());
+        let group = groups
+            .entry(key)
+            .or_insert_with(|| DraftGroup::new(client_name, entry.client_email.as_deref(), &entry.currency));
+
+        let description = entry.description.as_deref().unwrap_or("Services");
+        group.line_items.push(LineItem {
+            description: format!("{} ({})", description, entry.entry_date),
+            quantity: entry.hours,
+            unit_price: entry.hourly_rate,
+            tax_rate: default_tax.map(|(_, rate)| rate),
+            tax_rate_id: default_tax.map(|(id, _)| id),
+        });
+        group.time_entry_ids.push(entry.id);
+    }
+
+    for expense in expenses {
+        let Some(client_name) = expense.client_name.as_deref() else { continue };
+        let key = (client_key(client_name, expense.client_email.as_deref()), expense.currency.clone());
+        let group = groups
+            .entry(key)
+            .or_insert_with(|| DraftGroup::new(client_name, expense.client_email.as_deref(), &expense.currency));
+
+        let label = [expense.vendor.as_deref(), expense.description.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" - ");
+        let mut description = format!("Expense: {}", if label.is_empty() { "Unspecified" } else { &label });
+        if let Some(date) = expense.expense_date {
+            description.push_str(&format!(" ({})", date));
+        }
+        group.line_items.push(LineItem {
+            description,
+            quantity: Decimal::ONE,
+            unit_price: expense.amount,
+            tax_rate: None,
+            tax_rate_id: None,
+        });
+        group.expense_ids.push(expense.id);
+    }
+
+    groups.into_values().collect()
+}
+
+/// Drafts the weekly invoices for every opted-in user, if today is a draft
+/// day.
+///
+/// Each user is drafted at most once per week: the run is claimed in
+/// `weekly_draft_runs` in the same transaction that creates the drafts, so
+/// repeated polls and concurrent workers do not duplicate them, and a
+/// failed run is retried on the next poll.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+/// * `now` - Current time
+///
+/// # Returns
+///
+/// Returns the number of draft invoices created.
+pub async fn run_weekly_drafts(pool: &PgPool, now: DateTime<Utc>) -> Result<usize, anyhow::Error> {
+    let today = now.date_naive();
+    if !is_draft_day(today) {
+        return Ok(0);
+    }
+
+    let users = sqlx::query_as::<_, UserSettings>(
+        "SELECT * FROM user_settings WHERE weekly_drafts_enabled = true",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut created = 0;
+    for settings in users {
+        match draft_for_user(pool, &settings, now).await {
+            Ok(count) => created += count,
+            Err(e) => error!("Failed to draft weekly invoices for user {}: {}", settings.user_id, e),
+        }
+    }
+
+    Ok(created)
+}
+
+/// Creates one user's weekly drafts and notifies them.
+async fn draft_for_user(
+    pool: &PgPool,
+    settings: &UserSettings,
+    now: DateTime<Utc>,
+) -> Result<usize, anyhow::Error> {
+    let user_id = settings.user_id;
+    // The server can't write drafts to end-to-end encrypted tables
+    if encrypts_any(pool, user_id, &["invoices", "time_entries"]).await? {
+        return Ok(0);
+    }
+    let today = now.date_naive();
+    let week = week_start(today);
+    let due_date = DueDateRules::for_settings(pool, settings)
+        .await?
+        .default_due_date(today, None);
+
+    let mut tx = pool.begin().await?;
+
+    let claimed = sqlx::query(
+        r#"
+        INSERT INTO weekly_draft_runs (user_id, week_start)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id, week_start) DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(week)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if claimed == 0 {
+        return Ok(0);
+    }
+
+    // Items billed on a draft that was later deleted count as unbilled again
+    let entries = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        SELECT t.* FROM time_entries t
+        LEFT JOIN invoices i ON i.id = t.invoice_id
+        WHERE t.user_id = $1 AND t.is_deleted = false AND t.billable = true
+            AND t.client_name IS NOT NULL AND t.entry_date <= $2
+            AND (t.invoice_id IS NULL OR i.is_deleted = true)
+        ORDER BY t.entry_date ASC, t.created_at ASC
+        FOR UPDATE OF t
+        "#,
+    )
+    .bind(user_id)
+    .bind(today)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let expenses = sqlx::query_as::<_, Expense>(
+        r#"
+        SELECT e.* FROM expenses e
+        LEFT JOIN invoices i ON i.id = e.invoice_id
+        WHERE e.user_id = $1 AND e.is_deleted = false
+            AND e.billable = true AND e.status = 'confirmed'
+            AND e.client_name IS NOT NULL
+            AND (e.expense_date IS NULL OR e.expense_date <= $2)
+            AND (e.invoice_id IS NULL OR i.is_deleted = true)
+        ORDER BY e.expense_date ASC NULLS LAST, e.created_at ASC
+        FOR UPDATE OF e
+        "#,
+    )
+    .bind(user_id)
+    .bind(today)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let default_tax = sqlx::query_as::<_, (Uuid, Percent)>(
+        "SELECT id, rate FROM tax_rates WHERE user_id = $1 AND is_default = true ORDER BY created_at ASC LIMIT 1",
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let auto_send_at = settings
+        .weekly_draft_auto_send
+        .then(|| now + Duration::hours(settings.weekly_draft_grace_hours as i64));
+
+    let mut drafts = Vec::new();
+    for group in group_unbilled(&entries, &expenses, default_tax) {
+        let invoice = insert_draft(&mut tx, user_id, &group, today, due_date, week, auto_send_at).await?;
+        mark_billed(&mut tx, user_id, invoice.id, &group).await?;
+        drafts.push(invoice);
+    }
+
+    sqlx::query("UPDATE weekly_draft_runs SET invoices_created = $3 WHERE user_id = $1 AND week_start = $2")
+        .bind(user_id)
+        .bind(week)
+        .bind(drafts.len() as i32)
+        .execute(&mut *tx)
+        .await?;
+
+    if !drafts.is_empty() {
+        notify(&mut tx, user_id, draft_ready_notification(&drafts, week, auto_send_at)).await?;
+    }
+
+    tx.commit().await?;
+
+    if !drafts.is_empty() {
+        info!("Drafted {} weekly invoice(s) for user {}", drafts.len(), user_id);
+    }
+    Ok(drafts.len())
+}
+
+/// Inserts a draft invoice for a group and records it for sync.
+async fn insert_draft(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    group: &DraftGroup,
+    today: NaiveDate,
+    due_date: NaiveDate,
+    week: NaiveDate,
+    auto_send_at: Option<DateTime<Utc>>,
+) -> Result<Invoice, anyhow::Error> {
+    let invoice_number = next_invoice_number(tx, user_id).await?;
+    let totals = InvoiceTotals::compute(&group.line_items);
+
+    // Drafts without a client email can't be sent automatically
+    let auto_send_at = auto_send_at.filter(|_| group.client_email.is_some());
+    let metadata = json!({
+        "weekly_draft": {
+            "week_start": week,
+            "auto_send_at": auto_send_at,
+        }
+    });
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        INSERT INTO invoices (
+            user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            description, line_items, metadata, subtotal, tax_total, total
+        ) VALUES ($1, $2, $3, $4, $5, $6, 'draft', $7, $8, $9, $10, $11, $12, $13, $14)
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(&invoice_number)
+    .bind(&group.client_name)
+    .bind(&group.client_email)
+    .bind(totals.total)
+    .bind(&group.currency)
+    .bind(due_date)
+    .bind(today)
+    .bind(format!("Work for the week of {}", week))
+    .bind(serde_json::to_value(&group.line_items)?)
+    .bind(metadata)
+    .bind(totals.subtotal)
+    .bind(totals.tax_total)
+    .bind(totals.total)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    record_server_change(
+        &mut **tx,
+        user_id,
+        "invoices",
+        invoice.id,
+        SyncOperation::Insert,
+        &serde_json::to_value(&invoice)?,
+    )
+    .await?;
+
+    Ok(invoice)
+}
+
+/// Links a group's time entries and expenses to their draft invoice.
+async fn mark_billed(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    invoice_id: Uuid,
+    group: &DraftGroup,
+) -> Result<(), anyhow::Error> {
+    let entries = sqlx::query_as::<_, TimeEntry>(
+        r#"
+        UPDATE time_entries SET invoice_id = $2, last_modified = NOW()
+        WHERE user_id = $1 AND id = ANY($3)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_id)
+    .bind(&group.time_entry_ids)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for entry in &entries {
+        record_server_change(
+            &mut **tx,
+            user_id,
+            "time_entries",
+            entry.id,
+            SyncOperation::Update,
+            &serde_json::to_value(entry)?,
+        )
+        .await?;
+    }
+
+    let expenses = sqlx::query_as::<_, Expense>(
+        r#"
+        UPDATE expenses SET invoice_id = $2, last_modified = NOW()
+        WHERE user_id = $1 AND id = ANY($3)
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(invoice_id)
+    .bind(&group.expense_ids)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for expense in &expenses {
+        record_server_change(
+            &mut **tx,
+            user_id,
+            "expenses",
+            expense.id,
+            SyncOperation::Update,
+            &serde_json::to_value(expense)?,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Builds the "drafts ready for review" notification.
+fn draft_ready_notification(
+    drafts: &[Invoice],
+    week: NaiveDate,
+    auto_send_at: Option<DateTime<Utc>>,
+) -> CreateNotification {
+    let mut lines: Vec<String> = drafts
+        .iter()
+        .map(|invoice| {
+            format!(
+                "{} for {}: {} {:.2}",
+                invoice.invoice_number, invoice.client_name, invoice.currency, invoice.total
+            )
+        })
+        .collect();
+    if let Some(at) = auto_send_at {
+        lines.push(format!(
+            "Drafts with a client email will be sent automatically at {} unless you change or delete them.",
+            at.format("%Y-%m-%d %H:%M UTC")
+        ));
+    }
+
+    CreateNotification {
+        kind: "weekly_draft_ready".to_string(),
+        title: format!("{} invoice draft(s) ready for review", drafts.len()),
+        body: Some(lines.join("\n")),
+        data: Some(json!({
+            "week_start": week,
+            "invoice_ids": drafts.iter().map(|i| i.id).collect::<Vec<_>>(),
+            "auto_send_at": auto_send_at,
+        })),
+    }
+}
+
+/// Sends weekly drafts whose grace period has passed.
+///
+/// Only drafts still in `draft` status are sent, and only while the user
+/// keeps auto-send enabled. Sending moves the issue date to today (keeping
+/// the payment terms), marks the invoice `sent` and notifies the user.
+///
+/// # Arguments
+///
+/// * `pool` - PostgreSQL connection pool
+///
+/// # Returns
+///
+/// Returns the number of drafts sent.
+pub async fn auto_send_due_drafts(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let due: Vec<(Uuid,)> = sqlx::query_as(
+        r#"
+        SELECT i.id FROM invoices i
+        JOIN user_settings s ON s.user_id = i.user_id AND s.weekly_draft_auto_send = true
+        WHERE i.status = 'draft' AND i.is_deleted = false AND i.client_email IS NOT NULL
+            AND (i.metadata->'weekly_draft'->>'auto_send_at')::timestamptz <= NOW()
+        ORDER BY i.created_at ASC
+        LIMIT $1
+        "#,
+    )
+    .bind(AUTO_SEND_BATCH_SIZE)
+    .fetch_all(pool)
+    .await?;
+
+    let mut sent = 0;
+    for (invoice_id,) in due {
+        match send_draft(pool, invoice_id).await {
+            Ok(true) => sent += 1,
+            Ok(false) => {}
+            Err(e) => error!("Failed to auto-send draft invoice {}: {}", invoice_id, e),
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Sends one due draft, returning `false` if it was already handled.
+///
+/// The draft is first claimed and committed on its own (re-checking under
+/// the row lock that it is still a due draft and the user still has
+/// auto-send enabled), so no lock is held while the email is sent. A failed
+/// send releases the claim for the next run; once the email is out, the
+/// claim is never released, so a failure to mark the invoice sent leaves
+/// it a draft rather than emailing the client twice.
+async fn send_draft(pool: &PgPool, invoice_id: Uuid) -> Result<bool, anyhow::Error> {
+    // The claimed draft comes back with the dates it is sent with: issued
+    // today, keeping its payment terms
+    let claimed = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices i
+        SET metadata = jsonb_set(i.metadata, '{weekly_draft,sending_at}', to_jsonb(NOW()))
+        FROM user_settings s
+        WHERE i.id = $1 AND i.status = 'draft' AND i.is_deleted = false AND i.client_email IS NOT NULL
+            AND s.user_id = i.user_id AND s.weekly_draft_auto_send = true
+            AND (i.metadata->'weekly_draft'->>'auto_send_at')::timestamptz <= NOW()
+            AND i.metadata->'weekly_draft'->'sending_at' IS NULL
+        RETURNING
+            i.id, i.user_id, i.invoice_number, i.client_name, i.client_email,
+            i.amount, i.currency, i.status,
+            CURRENT_DATE + (COALESCE(i.due_date, i.issue_date) - i.issue_date) AS due_date,
+            CURRENT_DATE AS issue_date,
+            i.last_modified, i.version_vector, i.is_deleted,
+            i.description, i.line_items, i.subtotal, i.tax_total, i.total, i.amount_paid,
+            i.client_id, i.project_id, i.chase_override, i.exchange_rate_override, i.chase_paused_until,
+            i.metadata, i.created_at, i.updated_at
+        "#,
+    )
+    .bind(invoice_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(mut invoice) = claimed else {
+        return Ok(false);
+    };
+    let Some(client_email) = invoice.client_email.clone() else {
+        return Ok(false);
+    };
+    invoice.status = InvoiceStatus::Sent;
+
+    match email_draft(pool, &invoice, &client_email).await {
+        Ok(correspondence) => {
+            if let Err(e) = mark_draft_sent(pool, &invoice).await {
+                error!(
+                    "Weekly draft {} was emailed but could not be marked sent, leaving it a draft: {}",
+                    invoice.invoice_number, e
+                );
+            }
+            record_correspondence(pool, invoice.user_id, correspondence).await?;
+            Ok(true)
+        }
+        Err(e) => {
+            // Nothing went out: release the claim so the next run retries
+            sqlx::query("UPDATE invoices SET metadata = metadata #- '{weekly_draft,sending_at}' WHERE id = $1")
+                .bind(invoice.id)
+                .execute(pool)
+                .await?;
+            Err(e)
+        }
+    }
+}
+
+/// Emails a claimed draft to its client.
+///
+/// # Returns
+///
+/// Returns the correspondence to record.
+async fn email_draft(
+    pool: &PgPool,
+    invoice: &Invoice,
+    client_email: &str,
+) -> Result<CreateCorrespondence, anyhow::Error> {
+    let subject = format!("Invoice {}", invoice.invoice_number);
+    let mut body = format!(
+        "Hello {},\n\nPlease find attached invoice {} for {} {:.2}, due on {}.\n\nYou can pay online at {}",
+        invoice.client_name,
+        invoice.invoice_number,
+        invoice.currency,
+        invoice.total,
+        invoice.due_date.map(|d| d.to_string()).unwrap_or_else(|| "receipt".to_string()),
+        pay_link(invoice),
+    );
+    let methods = methods_for_client(pool, invoice.user_id, Some(client_email)).await?;
+    if let Some(how_to_pay) = instructions_text(&methods, &invoice.invoice_number) {
+        body = format!("{}\n\n{}", body, how_to_pay);
+    }
+    body.push_str("\n\nThank you for your business.");
+
+    let branding = PdfBranding::for_invoice(pool, invoice).await?;
+    let attachment = EmailAttachment {
+        filename: format!("{}.pdf", invoice.invoice_number),
+        content_type: "application/pdf".to_string(),
+        data: render_invoice_pdf(invoice, &branding)?,
+    };
+
+    let delivery = deliver_email(
+        pool,
+        invoice.user_id,
+        client_email,
+        &subject,
+        &body,
+        std::slice::from_ref(&attachment),
+    )
+    .await?;
+
+    info!(
+        "Auto-sent weekly draft {} to {}",
+        invoice.invoice_number,
+        redact_email(&client_email)
+    );
+    Ok(CreateCorrespondence {
+        invoice_id: invoice.id,
+        kind: CorrespondenceKind::Email,
+        sender: Some(email_sender()),
+        recipient: Some(client_email.to_string()),
+        subject: Some(subject.clone()),
+        body_html: Some(render_email_html(&subject, &body)),
+        body_text: Some(body),
+        provider_message_id: None,
+        delivery_status: Some(delivery.status().to_string()),
+        metadata: Some(json!({
+            "weekly_draft": true,
+            "attachments": [attachment.filename],
+        })),
+        occurred_at: None,
+    })
+}
+
+/// Marks an emailed draft sent with the dates it was sent with, and
+/// notifies the user.
+async fn mark_draft_sent(pool: &PgPool, sent: &Invoice) -> Result<(), anyhow::Error> {
+    let mut tx = pool.begin().await?;
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        UPDATE invoices
+        SET status = 'sent',
+            issue_date = $2,
+            due_date = $3,
+            metadata = metadata #- '{weekly_draft,auto_send_at}' #- '{weekly_draft,sending_at}',
+            last_modified = NOW()
+        WHERE id = $1 AND status = 'draft' AND is_deleted = false
+        RETURNING
+            id, user_id, invoice_number, client_name, client_email,
+            amount, currency, status, due_date, issue_date,
+            last_modified, version_vector, is_deleted,
+            description, line_items, subtotal, tax_total, total, amount_paid,
+            client_id, project_id, chase_override, exchange_rate_override, chase_paused_until, metadata, created_at, updated_at
+        "#,
+    )
+    .bind(sent.id)
+    .bind(sent.issue_date)
+    .bind(sent.due_date)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("the draft was changed or deleted while it was sent"))?;
+
+    record_server_change(
+        &mut *tx,
+        invoice.user_id,
+        "invoices",
+        invoice.id,
+        SyncOperation::Update,
+        &serde_json::to_value(&invoice)?,
+    )
+    .await?;
+    notify(
+        &mut tx,
+        invoice.user_id,
+        CreateNotification {
+            kind: "weekly_draft_sent".to_string(),
+            title: format!("Invoice {} sent to {}", invoice.invoice_number, invoice.client_name),
+            body: Some(format!(
+                "The weekly draft was sent automatically after its review period ({} {:.2}).",
+                invoice.currency, invoice.total
+            )),
+            data: Some(json!({ "invoice_id": invoice.id })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Spawns the background weekly draft loop.
+///
+/// Polls every `WEEKLY_DRAFT_POLL_INTERVAL_SECONDS` (default: 900 seconds),
+/// drafting on Fridays (UTC) and auto-sending drafts whose grace period has
+/// passed.
+pub fn spawn_weekly_draft_worker(pool: PgPool) {
+    let seconds = std::env::var("WEEKLY_DRAFT_POLL_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(900);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            match run_weekly_drafts(&pool, Utc::now()).await {
+                Ok(0) => {}
+                Ok(count) => info!("Created {} weekly invoice draft(s)", count),
+                Err(e) => error!("Weekly draft job failed: {}", e),
+            }
+            // Due drafts are sent once maintenance is over
+            if worker_paused() {
+                continue;
+            }
+            match auto_send_due_drafts(&pool).await {
+                Ok(0) => {}
+                Ok(count) => info!("Auto-sent {} weekly invoice draft(s)", count),
+                Err(e) => error!("Weekly draft auto-send failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::date;
+
+    fn entry(client: Option<&str>, email: Option<&str>, currency: &str, hours: i64, rate: i64) -> TimeEntry {
+        TimeEntry {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            client_name: client.map(str::to_string),
+            client_email: email.map(str::to_string),
+            project_id: None,
+            description: Some("Development".to_string()),
+            entry_date: date(2024, 3, 4),
+            started_at: None,
+            stopped_at: None,
+            hours: Decimal::from(hours),
+            hourly_rate: Decimal::from(rate),
+            currency: currency.to_string(),
+            billable: true,
+            invoice_id: None,
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn expense(client: &str, email: Option<&str>, amount: i64) -> Expense {
+        Expense {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            vendor: Some("Rail Co".to_string()),
+            description: Some("Train ticket".to_string()),
+            expense_date: Some(date(2024, 3, 5)),
+            amount: Decimal::from(amount),
+            currency: "USD".to_string(),
+            category: None,
+            status: crate::models::expense::ExpenseStatus::Confirmed,
+            source: "manual".to_string(),
+            receipt_id: None,
+            client_name: Some(client.to_string()),
+            client_email: email.map(str::to_string),
+            billable: true,
+            invoice_id: None,
+            last_modified: Utc::now(),
+            version_vector: None,
+            is_deleted: false,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_draft_day_and_week_start() {
+        assert!(is_draft_day(date(2024, 3, 8)));
+        assert!(!is_draft_day(date(2024, 3, 9)));
+        assert_eq!(week_start(date(2024, 3, 8)), date(2024, 3, 4));
+        assert_eq!(week_start(date(2024, 3, 4)), date(2024, 3, 4));
+    }
+
+    #[test]
+    fn test_groups_by_client_email_and_currency() {
+        let entries = vec![
+            entry(Some("Acme"), Some("billing@acme.test"), "USD", 2, 100),
+            entry(Some("ACME Inc"), Some("Billing@Acme.test"), "USD", 1, 100),
+            entry(Some("Acme"), Some("billing@acme.test"), "EUR", 3, 90),
+            entry(None, None, "USD", 5, 100),
+        ];
+        let expenses = vec![expense("Acme", Some("billing@acme.test"), 40)];
+
+        let groups = group_unbilled(&entries, &expenses, None);
+        assert_eq!(groups.len(), 2);
+
+        let usd = groups.iter().find(|g| g.currency == "USD").unwrap();
+        assert_eq!(usd.client_name, "Acme");
+        assert_eq!(usd.time_entry_ids.len(), 2);
+        assert_eq!(usd.expense_ids, vec![expenses[0].id]);
+        assert_eq!(InvoiceTotals::compute(&usd.line_items).total, Decimal::from(340));
+        assert_eq!(usd.line_items[2].description, "Expense: Rail Co - Train ticket (2024-03-05)");
+    }
+
+    #[test]
+    fn test_default_tax_applies_to_time_only() {
+        let tax_id = Uuid::new_v4();
+        let groups = group_unbilled(
+            &[entry(Some("Acme"), None, "USD", 1, 100)],
+            &[expense("acme", None, 50)],
+            Some((tax_id, Percent::new(Decimal::from(20)))),
+        );
+        assert_eq!(groups.len(), 1);
+        let totals = InvoiceTotals::compute(&groups[0].line_items);
+        assert_eq!(totals.tax_total, Decimal::from(20));
+        assert_eq!(totals.total, Decimal::from(170));
+        assert_eq!(groups[0].line_items[0].tax_rate_id, Some(tax_id));
+    }
+}